@@ -75,6 +75,27 @@ pub struct HttpCompressionConfig {
     pub accepted_compression_algorithms: Vec<HttpCompressionAlgorithm>,
 }
 
+/// Identities that may use an instance. Only identities verified by the
+/// tokens of `experimental_auth` are checked; the identity clients send in
+/// `experimental_identity_header` is not, as any client can send any
+/// identity in it. Without `experimental_auth` on the listener all clients
+/// have the empty identity, so only lists with the empty identity let them
+/// in.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct InstanceAccessConfig {
+    /// Identities that may read from the instance.
+    /// Default: None (anyone may read)
+    #[serde(default)]
+    pub read_identities: Option<Vec<String>>,
+
+    /// Identities that may write to the instance, or execute actions for
+    /// execution instances.
+    /// Default: None (anyone may write)
+    #[serde(default)]
+    pub write_identities: Option<Vec<String>>,
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct AcStoreConfig {
@@ -87,6 +108,11 @@ pub struct AcStoreConfig {
     /// it is only possible to read from the Action Cache.
     #[serde(default)]
     pub read_only: bool,
+
+    /// Identities that may read and update action results.
+    /// Default: {see `InstanceAccessConfig`}
+    #[serde(default)]
+    pub access: InstanceAccessConfig,
 }
 
 #[derive(Deserialize, Debug)]
@@ -96,6 +122,20 @@ pub struct CasStoreConfig {
     /// This store name referenced here may be reused multiple times.
     #[serde(deserialize_with = "convert_string_with_shellexpand")]
     pub cas_store: StoreRefName,
+
+    /// Identities that may read and upload blobs.
+    /// Default: {see `InstanceAccessConfig`}
+    #[serde(default)]
+    pub access: InstanceAccessConfig,
+
+    /// Maximum total size of the blobs in a `BatchReadBlobs` or
+    /// `BatchUpdateBlobs` request. Larger requests are rejected. The
+    /// capabilities service reports this limit to clients, which use
+    /// ByteStream for larger blobs.
+    ///
+    /// Default: 4194304 (4MiB)
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub max_batch_total_size_bytes: usize,
 }
 
 #[derive(Deserialize, Debug, Default)]
@@ -113,6 +153,27 @@ pub struct CapabilitiesConfig {
     /// If not set the capabilities service will inform the client that remote
     /// execution is not supported.
     pub remote_execution: Option<CapabilitiesRemoteExecutionConfig>,
+
+    /// Digest functions clients of this instance may use. Requests with
+    /// other digest functions are rejected by every service.
+    ///
+    /// Default: [sha256, blake3, sha384, sha512]
+    #[serde(default)]
+    pub digest_functions: Vec<ConfigDigestHashFunction>,
+
+    /// Digest function used for requests of this instance that don't name
+    /// one. It must be one of `digest_functions`.
+    ///
+    /// Default: `default_digest_hash_function` of the global config
+    #[serde(default)]
+    pub default_digest_function: Option<ConfigDigestHashFunction>,
+
+    /// Whether clients may upload directories and action results with
+    /// symlinks to absolute paths.
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub allow_absolute_symlinks: bool,
 }
 
 #[derive(Deserialize, Debug)]
@@ -127,6 +188,28 @@ pub struct ExecutionConfig {
     /// The scheduler name referenced in the `schedulers` map in the main config.
     #[serde(deserialize_with = "convert_string_with_shellexpand")]
     pub scheduler: SchedulerRefName,
+
+    /// Identities that may execute and cancel actions (`write_identities`)
+    /// and wait for and list them (`read_identities`).
+    /// Default: {see `InstanceAccessConfig`}
+    #[serde(default)]
+    pub access: InstanceAccessConfig,
+
+    /// How long clients are asked to wait before retrying an execution
+    /// the scheduler rejected because its queue is full. It is sent as
+    /// the `RetryInfo` of the `RESOURCE_EXHAUSTED` error.
+    /// Default: 5 (seconds)
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub queue_full_retry_delay_s: u64,
+
+    /// Whether the updates of executing actions name their live stdout and
+    /// stderr streams, which clients can read through the `ByteStream`
+    /// service while the actions run. The workers must have
+    /// `stream_action_output` set and use the same `ByteStream` service as
+    /// the clients for the streams to have data.
+    /// Default: false
+    #[serde(default)]
+    pub stream_action_output: bool,
 }
 
 #[derive(Deserialize, Debug, Default)]
@@ -135,6 +218,12 @@ pub struct ByteStreamConfig {
     /// Name of the store in the "stores" configuration.
     pub cas_stores: HashMap<InstanceName, StoreRefName>,
 
+    /// Identities that may read and upload blobs of the instances in
+    /// `cas_stores`.
+    /// Default: empty (anyone may use any instance)
+    #[serde(default)]
+    pub access: HashMap<InstanceName, InstanceAccessConfig>,
+
     /// Max number of bytes to send on each grpc stream chunk.
     /// According to <https://github.com/grpc/grpc.github.io/issues/371>
     /// 16KiB - 64KiB is optimal.
@@ -157,6 +246,48 @@ pub struct ByteStreamConfig {
     /// Default: 10 (seconds)
     #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
     pub persist_stream_on_disconnect_timeout: usize,
+
+    /// Experimental - Keep the data of uploads on disk until they finish,
+    /// so clients can resume them with the offset of `QueryWriteStatus`
+    /// after a disconnect or a restart of the server. Replaces the
+    /// in-memory streams of `persist_stream_on_disconnect_timeout`.
+    /// Default: None (uploads are kept in memory)
+    #[serde(default)]
+    pub experimental_persistent_uploads: Option<PersistentUploadsConfig>,
+
+    /// The maximum number of bytes kept in memory of each live stdout and
+    /// stderr stream of a running action. Older bytes are dropped as the
+    /// action writes more, and clients that did not read them yet fail to
+    /// read the stream.
+    /// Default: 1 MiB
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub max_action_output_stream_size: usize,
+
+    /// The identities that may write the live stdout and stderr streams of
+    /// running actions, which should only be the identities of the workers
+    /// with `stream_action_output` set, as a stream replaces the one of an
+    /// earlier execution of the action. Without `experimental_auth` all
+    /// clients have the empty identity, so "" must be listed for the
+    /// workers to write streams.
+    /// Default: [] (action output streams can not be written)
+    #[serde(default)]
+    pub action_output_writer_identities: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct PersistentUploadsConfig {
+    /// Directory the data of unfinished uploads is written to. Uploads
+    /// found in it on startup can be resumed.
+    #[serde(deserialize_with = "convert_string_with_shellexpand")]
+    pub path: String,
+
+    /// Uploads that were not written to for this many seconds are removed,
+    /// and their clients have to start over.
+    ///
+    /// Default: 3600 (1 hour)
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub idle_timeout_s: u64,
 }
 
 #[derive(Deserialize, Debug)]
@@ -167,6 +298,11 @@ pub struct WorkerApiConfig {
     pub scheduler: SchedulerRefName,
 }
 
+/// Besides the metrics of the components, the endpoint exports the
+/// histograms `nativelink_store_operation_duration_seconds` and
+/// `nativelink_store_operation_bytes`, labeled with the name of each store
+/// in `stores`, and `nativelink_rpc_duration_seconds`, labeled with the gRPC
+/// service, method, instance name and code.
 #[derive(Deserialize, Debug, Default)]
 #[serde(deny_unknown_fields)]
 pub struct PrometheusConfig {
@@ -179,6 +315,24 @@ pub struct PrometheusConfig {
     pub path: String,
 }
 
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct PprofConfig {
+    /// Path to register the profiling endpoints. If path is "/debug/pprof",
+    /// and your domain is "example.com", you can reach the endpoints with:
+    /// <http://example.com/debug/pprof/profile>.
+    ///
+    /// Default: "/debug/pprof"
+    #[serde(default)]
+    pub path: String,
+
+    /// Longest CPU profile a request may ask for.
+    ///
+    /// Default: 300 (seconds)
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub max_profile_duration_s: u64,
+}
+
 #[derive(Deserialize, Debug, Default)]
 #[serde(deny_unknown_fields)]
 pub struct AdminConfig {
@@ -197,10 +351,154 @@ pub struct HealthConfig {
     /// Path to register the health status check. If path is "/status", and your
     /// domain is "example.com", you can reach the endpoint with:
     /// <http://example.com/status>.
+    /// It reports the status of every store and scheduler as JSON, with how
+    /// long checking it took, and fails if any of them failed.
     ///
     /// Default: "/status"
     #[serde(default)]
     pub path: String,
+
+    /// Path to register the liveness check, which succeeds as long as the
+    /// process serves requests.
+    ///
+    /// Default: "/livez"
+    #[serde(default)]
+    pub liveness_path: String,
+
+    /// Path to register the readiness check. It reports the same as `path`,
+    /// but also fails while any store or scheduler is still initializing.
+    /// Stores load their index before the listeners start, so the process
+    /// is not reachable until then.
+    ///
+    /// Default: "/readyz"
+    #[serde(default)]
+    pub readiness_path: String,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct BlobRedirectConfig {
+    /// Path to register the blob download endpoint. If path is "/blobs",
+    /// and your domain is "example.com", blobs can be downloaded with
+    /// `GET` from <http://example.com/blobs/{instance_name}/{hash}/{size}>,
+    /// or from <http://example.com/blobs/{hash}/{size}> for the empty
+    /// instance name.
+    ///
+    /// Default: "/blobs"
+    #[serde(default)]
+    pub path: String,
+
+    /// The CAS stores to download blobs from. The key is the
+    /// `instance_name` and the value is the store name referenced in the
+    /// `stores` map in the main config. Blobs in S3 stores that are at
+    /// least `presigned_url_threshold` bytes are served with a redirect to
+    /// a presigned URL, all other blobs are streamed through this server.
+    pub cas_stores: HashMap<InstanceName, StoreRefName>,
+
+    /// Identities that may download blobs of the instances in
+    /// `cas_stores`. Only `read_identities` is used.
+    /// Default: empty (anyone may use any instance)
+    #[serde(default)]
+    pub access: HashMap<InstanceName, InstanceAccessConfig>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct HttpCacheConfig {
+    /// Path to register the HTTP cache endpoint. If path is "/cache", and
+    /// your domain is "example.com", Bazel can use it with
+    /// `--remote_cache=http://example.com/cache`. Objects are read with
+    /// `GET` and `HEAD` and written with `PUT` at
+    /// <http://example.com/cache/ac/{hash}> and
+    /// <http://example.com/cache/cas/{hash}>.
+    ///
+    /// Default: "/cache"
+    #[serde(default)]
+    pub path: String,
+
+    /// The CAS store to serve `/cas/{hash}` from. The store name
+    /// referenced in the `stores` map in the main config.
+    ///
+    /// Warning: The protocol does not send the size of objects that are
+    /// read, so it is looked up by listing the keys with the hash. The
+    /// store must be able to list them without walking all its keys, which
+    /// only a memory or filesystem store can. Wrapping stores, like
+    /// `fast_slow`, `verify` or `compression`, and S3 or redis stores are
+    /// not supported, and the server refuses to start with them. Point the
+    /// HTTP cache at the memory or filesystem store inside them, or serve
+    /// it from a server of its own.
+    #[serde(deserialize_with = "convert_string_with_shellexpand")]
+    pub cas_store: StoreRefName,
+
+    /// The AC store to serve `/ac/{hash}` from. The store name referenced
+    /// in the `stores` map in the main config. The same restrictions as
+    /// for `cas_store` apply.
+    ///
+    /// It may be shared with the gRPC `ActionCache` service. ActionResults
+    /// are written under the digest of their Action, which is taken from
+    /// an existing entry or the Action in `cas_store`. If neither exists,
+    /// the size of the ActionResult is used and gRPC clients do not find
+    /// the entry.
+    #[serde(deserialize_with = "convert_string_with_shellexpand")]
+    pub ac_store: StoreRefName,
+
+    /// Identities that may read objects with `GET` and `HEAD` and write
+    /// them with `PUT`. The protocol has no instance name, so this is the
+    /// access of the empty instance name.
+    /// Default: {see `InstanceAccessConfig`}
+    #[serde(default)]
+    pub access: InstanceAccessConfig,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RemoteAssetConfig {
+    /// The CAS store that fetched content is uploaded to and that pushed
+    /// content must be in. The store name referenced in the `stores` map
+    /// in the main config.
+    #[serde(deserialize_with = "convert_string_with_shellexpand")]
+    pub cas_store: StoreRefName,
+
+    /// The store that remembers which digest a URI and its qualifiers
+    /// resolved to. The store name referenced in the `stores` map in the
+    /// main config. This store should not be content addressed, like the
+    /// store of the AC.
+    #[serde(deserialize_with = "convert_string_with_shellexpand")]
+    pub asset_store: StoreRefName,
+
+    /// How long a fetched URI is returned from the `asset_store` before it
+    /// is fetched again. Pushed content uses its `expire_at` instead, if
+    /// the client set it.
+    ///
+    /// Default: 86400 (seconds)
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub cache_ttl_s: u64,
+
+    /// Timeout for fetching a URI if the client does not send one.
+    ///
+    /// Default: 600 (seconds)
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub fetch_timeout_s: u64,
+
+    /// Content larger than this is not fetched. Fetched content is held in
+    /// memory until it is verified and uploaded to the `cas_store`.
+    ///
+    /// Default: 1GiB
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub max_fetch_size: u64,
+
+    /// Whether clients may associate URIs with content using the Push
+    /// service. Clients that can push can make any URI resolve to any
+    /// content, so only enable this for trusted clients.
+    #[serde(default)]
+    pub allow_push: bool,
+
+    /// Identities that may fetch content (`read_identities`) and push it
+    /// (`write_identities`). Fetching content that is not cached yet writes
+    /// it to the `cas_store`, so it needs both.
+    /// Default: {see `InstanceAccessConfig`}
+    #[serde(default)]
+    pub access: InstanceAccessConfig,
 }
 
 #[derive(Deserialize, Debug)]
@@ -209,6 +507,20 @@ pub struct BepConfig {
     /// The store name referenced in the `stores` map in the main config.
     #[serde(deserialize_with = "convert_string_with_shellexpand")]
     pub store: StoreRefName,
+
+    /// The CAS store that Bazel uploads outputs to. If set, files that are
+    /// referenced by build events and are found in this store are recorded
+    /// in `store` under `BepFile:<build_id>:<invocation_id>:<digest>`.
+    /// Default: None (files are not linked)
+    #[serde(default, deserialize_with = "convert_optional_string_with_shellexpand")]
+    pub cas_store: Option<StoreRefName>,
+
+    /// Identities that may publish build events. Only `write_identities`
+    /// is used, of the empty instance name, as build events can not be
+    /// read through this service.
+    /// Default: {see `InstanceAccessConfig`}
+    #[serde(default)]
+    pub access: InstanceAccessConfig,
 }
 
 #[derive(Deserialize, Clone, Debug, Default)]
@@ -223,6 +535,75 @@ pub struct IdentityHeaderSpec {
     pub required: bool,
 }
 
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AuthTokenConfig {
+    /// The token clients send. Use environment variables to keep it out of
+    /// the config file, like `"${CI_TOKEN}"`.
+    #[serde(deserialize_with = "convert_string_with_shellexpand")]
+    pub token: String,
+
+    /// The identity of clients that send this token. It is used in place
+    /// of the identity header, e.g. for `experimental_identity_header`
+    /// events and per instance authorization.
+    #[serde(deserialize_with = "convert_string_with_shellexpand")]
+    pub identity: String,
+
+    /// If clients with this token may only read from the cache.
+    /// Default: false
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct JwtAuthConfig {
+    /// The issuer that JWTs must be issued by, the `iss` claim.
+    #[serde(deserialize_with = "convert_string_with_shellexpand")]
+    pub issuer: String,
+
+    /// The audience that JWTs must be issued for, the `aud` claim.
+    /// Default: None (the audience is not checked)
+    #[serde(default, deserialize_with = "convert_optional_string_with_shellexpand")]
+    pub audience: Option<String>,
+
+    /// Path to a JSON Web Key Set with the keys that JWTs are signed with,
+    /// like the one published at the `jwks_uri` of an OIDC issuer.
+    #[serde(deserialize_with = "convert_string_with_shellexpand")]
+    pub jwks_file: String,
+
+    /// The claim that holds the identity of the client.
+    /// Default: "sub"
+    #[serde(default, deserialize_with = "convert_string_with_shellexpand")]
+    pub identity_claim: String,
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct AuthConfig {
+    /// The header clients send their token in. A `Bearer ` prefix is
+    /// removed from the value.
+    /// Default: "authorization"
+    #[serde(default, deserialize_with = "convert_string_with_shellexpand")]
+    pub header_name: String,
+
+    /// Static tokens that are accepted.
+    /// Default: empty
+    #[serde(default)]
+    pub tokens: Vec<AuthTokenConfig>,
+
+    /// Accept JWTs signed by an issuer.
+    /// Default: None (JWTs are not accepted)
+    #[serde(default)]
+    pub jwt: Option<JwtAuthConfig>,
+
+    /// If clients without a token may read from the cache. Requests that
+    /// write or execute always need a token.
+    /// Default: false
+    #[serde(default)]
+    pub allow_anonymous_read: bool,
+}
+
 #[derive(Deserialize, Clone, Debug)]
 pub struct OriginEventsPublisherSpec {
     /// The store to publish nativelink events to.
@@ -245,7 +626,65 @@ pub struct OriginEventsSpec {
     pub max_event_queue_size: usize,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AuditLogFileSpec {
+    /// The file the audit log is appended to. Rotated files are named
+    /// after it with a suffix: `<path>.1` is the most recent one.
+    #[serde(deserialize_with = "convert_string_with_shellexpand")]
+    pub path: String,
+
+    /// The size of the file after which it is rotated.
+    ///
+    /// Default: 104857600 (100MiB, zero defaults to this)
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub max_bytes: u64,
+
+    /// The number of rotated files to keep. Older files are deleted.
+    ///
+    /// Default: 10 (zero defaults to this)
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub max_files: usize,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AuditLogStoreSpec {
+    /// The store to upload the audit log to, in batches of records under
+    /// the key `AuditLog:<uuid>`.
+    /// The store name referenced in the `stores` map in the main config.
+    #[serde(deserialize_with = "convert_string_with_shellexpand")]
+    pub store: StoreRefName,
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Deserialize, Clone, Debug)]
+pub enum AuditLogWriterSpec {
+    /// Append the audit log to a local file, rotated by size.
+    file(AuditLogFileSpec),
+
+    /// Upload the audit log to a store.
+    store(AuditLogStoreSpec),
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AuditLogSpec {
+    /// Where the audit log is written. Every record is a JSON line with
+    /// the request id, the identity of the client, the operation, the
+    /// instance name, the digests read or written, the size of uploads
+    /// and the command line of executions.
+    pub writer: AuditLogWriterSpec,
+
+    /// The maximum number of records to queue before applying back pressure.
+    /// IMPORTANT: Backpressure causes all clients to slow down significantly.
+    ///
+    /// Default: 65536 (zero defaults to this)
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub max_queue_size: usize,
+}
+
+#[derive(Deserialize, Debug, Default)]
 #[serde(deny_unknown_fields)]
 pub struct ServicesConfig {
     /// The Content Addressable Storage (CAS) backend config.
@@ -266,6 +705,8 @@ pub struct ServicesConfig {
     /// The remote execution service configuration.
     /// NOTE: This service is under development and is currently just a
     /// place holder.
+    /// The `google.longrunning.Operations` service is served alongside it to
+    /// list, query and cancel in-flight executions.
     pub execution: Option<HashMap<InstanceName, ExecutionConfig>>,
 
     /// This is the service used to stream data to and from the CAS.
@@ -292,11 +733,63 @@ pub struct ServicesConfig {
     pub experimental_prometheus: Option<PrometheusConfig>,
 
     /// This is the service for any administrative tasks.
-    /// It provides a REST API endpoint for administrative purposes.
+    /// It provides a REST API endpoint for administrative purposes:
+    /// - `POST {path}/scheduler/{scheduler}/set_drain_worker/{worker_id}/{0|1}`
+    ///   drains or undrains a worker. Draining workers finish the actions
+    ///   they are running but are not sent new ones.
+    /// - `GET {path}/scheduler/{scheduler}/worker_status/{worker_id}` tells
+    ///   if the worker is draining and how many actions it is running as
+    ///   JSON, so it can be shut down once it is drained.
+    /// - `GET {path}/scheduler/{scheduler}/queue` lists the operations that
+    ///   are not finished as JSON, in the order they are dispatched in, with
+    ///   their stage, priority, platform properties, time queued and worker.
+    /// - `POST {path}/stores/{store}/rebalance_shards/{0|1}` moves objects of
+    ///   a shard store to the shard they belong to.
+    /// - `POST {path}/stores/{store}/evict/{bytes}` evicts the expired objects
+    ///   of a memory or filesystem store and at least `bytes` more.
+    /// - `POST {path}/stores/{store}/flush_write_back` copies the objects a
+    ///   write back fast slow store did not copy to its slow store yet.
+    /// - `POST {path}/log_filter` sets the log filter to the request body,
+    ///   using the syntax of `RUST_LOG`.
+    ///
+    /// As these change the state of the process, this service should be put
+    /// on a listener of its own that is not reachable by clients.
     pub admin: Option<AdminConfig>,
 
     /// This is the service for health status check.
     pub health: Option<HealthConfig>,
+
+    /// Experimental - HTTP endpoint to download blobs from the CAS. Large
+    /// blobs in S3 stores are served with a redirect, so clients download
+    /// them directly from S3 instead of through this server.
+    pub experimental_blob_redirect: Option<BlobRedirectConfig>,
+
+    /// Experimental - HTTP/1.1 cache endpoint compatible with the protocol
+    /// of bazel-remote, for Bazel's `--remote_cache=http://...` and other
+    /// tools that only speak the HTTP cache protocol. Only memory and
+    /// filesystem stores can be served, see `HttpCacheConfig`.
+    pub experimental_http_cache: Option<HttpCacheConfig>,
+
+    /// Experimental - Remote Asset API (Fetch and Push services), used by
+    /// Bazel's `--experimental_remote_downloader` to download external
+    /// dependencies through this server. The key is the `instance_name`
+    /// used in the protocol.
+    pub experimental_remote_asset: Option<HashMap<InstanceName, RemoteAssetConfig>>,
+
+    /// Experimental - Profiling endpoints to investigate the CPU and memory
+    /// usage of the process in production:
+    /// - `GET {path}/profile?seconds=30&frequency=99` samples the CPU for
+    ///   `seconds` at `frequency` Hz and returns the profile in the pprof
+    ///   format, to be opened with `go tool pprof` or `pprof`. Only one
+    ///   profile is taken at a time.
+    /// - `GET {path}/heap` returns a summary of the memory of the process as
+    ///   JSON: the bytes held in memory stores and in-flight uploads against
+    ///   `max_process_bytes`, and the resident and virtual memory sizes.
+    ///
+    /// Profiling slows down the process and the profiles reveal details of
+    /// its internals, so this service should be put on a listener of its
+    /// own that is not reachable by clients.
+    pub experimental_pprof: Option<PprofConfig>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -319,6 +812,19 @@ pub struct TlsConfig {
     /// authentication is required for this endpoint.
     #[serde(default, deserialize_with = "convert_optional_string_with_shellexpand")]
     pub client_crl_file: Option<String>,
+
+    /// If set, only clients whose certificate has one of these common names
+    /// in its subject may connect. Requires `client_ca_file`.
+    /// Default: empty (any certificate signed by `client_ca_file` is accepted)
+    #[serde(default)]
+    pub allowed_client_common_names: Vec<String>,
+
+    /// How often `cert_file` and `key_file` are checked for changes. A
+    /// changed certificate is used for new connections, so it can be
+    /// rotated without a restart.
+    /// Default: 0 (the certificate is only loaded at startup)
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub cert_reload_interval_s: u64,
 }
 
 /// Advanced Http configurations. These are generally should not be set.
@@ -423,6 +929,13 @@ pub struct HttpListener {
     /// Default: None
     #[serde(default)]
     pub tls: Option<TlsConfig>,
+
+    /// Serve the gRPC server reflection service (`v1` and `v1alpha`), so
+    /// tools like `grpcurl` and `evans` can be used without the protos.
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub enable_grpc_reflection: bool,
 }
 
 #[derive(Deserialize, Debug)]
@@ -445,6 +958,61 @@ pub struct ServerConfig {
     /// Default: {see `IdentityHeaderSpec`}
     #[serde(default)]
     pub experimental_identity_header: IdentityHeaderSpec,
+
+    /// Experimental - Authenticate the requests to the gRPC services of
+    /// this server with tokens or JWTs. Unauthenticated requests are
+    /// rejected with `UNAUTHENTICATED`. The admin, pprof, HTTP cache and
+    /// blob redirect services of the server need the same credentials;
+    /// read-only clients may only send `GET` and `HEAD` requests to the
+    /// HTTP cache and blob redirect services. The health and prometheus
+    /// endpoints stay open for probes and scrapers.
+    /// Default: None (requests are not authenticated)
+    #[serde(default)]
+    pub experimental_auth: Option<AuthConfig>,
+
+    /// Experimental - Limits of the gRPC requests of each client of this
+    /// server, so one client can not starve the others. Requests over a
+    /// limit are rejected with `RESOURCE_EXHAUSTED`. The limits are only
+    /// checked when a request starts, and only apply to the gRPC services:
+    /// the HTTP cache and blob redirect services of the server are not
+    /// limited.
+    /// Default: None (requests are not limited)
+    #[serde(default)]
+    pub experimental_rate_limits: Option<RateLimitConfig>,
+}
+
+/// Limits of the requests of a single client. Clients are told apart by
+/// their identity, see `experimental_auth` and `experimental_identity_header`,
+/// and by their IP address if they have none.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// Maximum number of `ByteStream.Write` requests of a client in flight.
+    ///
+    /// Default: 0 (unlimited)
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub max_concurrent_bytestream_writes: usize,
+
+    /// Maximum number of `Execute` requests of a client in flight. An
+    /// `Execute` request is in flight until its action completed or the
+    /// client stopped waiting on it.
+    ///
+    /// Default: 0 (unlimited)
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub max_concurrent_executes: usize,
+
+    /// Maximum number of bytes per second a client may upload, counting
+    /// the bodies of all its requests. This only limits admission: the
+    /// bytes of a request in flight are counted, but the request is never
+    /// slowed down or cut, so a single long `ByteStream.Write` may upload
+    /// at any rate. New requests of the client are rejected until the
+    /// average is below the limit again. Bursts of up to one second of the
+    /// limit are allowed. Downloads, like `ByteStream.Read`, are not
+    /// counted.
+    ///
+    /// Default: 0 (unlimited)
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub max_bytes_per_second: u64,
 }
 
 #[allow(non_camel_case_types)]
@@ -601,6 +1169,238 @@ pub struct UploadActionResultConfig {
     pub failure_message_template: String,
 }
 
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ContainerRunnerConfig {
+    /// The docker compatible command line tool used to run the containers,
+    /// like `docker`, `podman` or `nerdctl` for containerd.
+    /// Default: docker
+    #[serde(default, deserialize_with = "convert_string_with_shellexpand")]
+    pub program: String,
+
+    /// The platform property of the action that holds the image to run it
+    /// in. A `docker://` prefix of the value is removed.
+    /// Default: container-image
+    #[serde(default)]
+    pub image_property: String,
+
+    /// The image actions without the image property run in. If not set,
+    /// such actions fail.
+    /// Default: "" (actions must set the image property)
+    #[serde(default, deserialize_with = "convert_string_with_shellexpand")]
+    pub default_image: String,
+
+    /// If set, actions may only run in images that start with one of these
+    /// prefixes, like `ghcr.io/my-org/`. Images are compared after the
+    /// `docker://` prefix is removed. Images that start with `-` are always
+    /// rejected.
+    /// Default: [] (any image)
+    #[serde(default)]
+    pub allowed_image_prefixes: Vec<String>,
+
+    /// Additional arguments passed to the `run` command of `program` before
+    /// the image, like `--network=none` or `--user=1000:1000`.
+    /// Default: []
+    #[serde(default)]
+    pub additional_args: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct BubblewrapRunnerConfig {
+    /// The bubblewrap executable. It is looked up in the `PATH` of the
+    /// action, so it may need to be an absolute path.
+    /// Default: bwrap
+    #[serde(default, deserialize_with = "convert_string_with_shellexpand")]
+    pub program: String,
+
+    /// If set, actions share the network of the worker. Otherwise they only
+    /// see a loopback interface of their own.
+    /// Default: false
+    #[serde(default)]
+    pub allow_network: bool,
+
+    /// Additional arguments passed to bubblewrap before the command, like
+    /// `--bind /var/cache/toolchains /var/cache/toolchains` to make a path
+    /// writable.
+    /// Default: []
+    #[serde(default, deserialize_with = "convert_vec_string_with_shellexpand")]
+    pub additional_args: Vec<String>,
+}
+
+/// How the worker runs the commands of actions.
+#[allow(non_camel_case_types)]
+#[derive(Deserialize, Debug, Default, Clone)]
+pub enum ActionRunner {
+    /// Runs the command directly on the worker host.
+    #[default]
+    local,
+
+    /// Runs the command in a container created from the image in the
+    /// platform properties of the action. The action directory is mounted
+    /// at the same path in the container, so the input root and the outputs
+    /// are where they would be without a container.
+    ///
+    /// The environment variables of the action are passed to the container,
+    /// and `entrypoint` wraps the container command line, not the command
+    /// in the container. The `work_directory` of the worker must be an
+    /// absolute path, and the image property is best made a `priority`
+    /// property of the scheduler so workers do not have to list the images.
+    container(ContainerRunnerConfig),
+
+    /// Runs the command in a bubblewrap sandbox with namespaces of its own.
+    /// The file system of the worker is read only in the sandbox, `/tmp` is
+    /// an empty tmpfs and the directories of the other actions are hidden,
+    /// so the action can only write to its own action directory.
+    ///
+    /// Needs unprivileged user namespaces on the worker host or a setuid
+    /// bubblewrap. The `work_directory` of the worker must be an absolute
+    /// path.
+    bubblewrap(BubblewrapRunnerConfig),
+}
+
+/// Runs the actions of tools that speak the Bazel persistent worker protocol,
+/// like `javac` or `tsc`, in processes that are kept alive between actions,
+/// so they do not start up for every action. Bazel marks these actions with
+/// the `persistentWorkerKey` platform property when it is run with
+/// `--experimental_remote_mark_tool_inputs`.
+///
+/// The processes are kept per key, the arguments they start with, their
+/// working directory and their environment variables. They exchange
+/// protobuf `WorkRequest`s and `WorkResponse`s over stdin and stdout, and
+/// run directly on the worker host outside of a cgroup, so they can only be
+/// used with the `local` runner and without `resource_limits`.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PersistentWorkersConfig {
+    /// The platform property with the key of the persistent worker of an
+    /// action, which Bazel derives from the tool inputs of the action.
+    /// Default: persistentWorkerKey
+    #[serde(default)]
+    pub key_property: String,
+
+    /// The maximum number of idle processes kept for each key. Processes
+    /// that finish an action when this many are idle are stopped.
+    /// Default: 4
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub max_idle_workers_per_key: usize,
+
+    /// The maximum size of a `WorkResponse` of a process. Actions whose
+    /// process sends a larger response fail and the process is stopped.
+    /// Default: 16777216 (16MiB)
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub max_work_response_size: usize,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct OverlayfsInputRootConfig {
+    /// The maximum number of input roots kept in the shared content
+    /// directory. The least recently used input roots no action is using
+    /// are removed when there are more.
+    /// Default: 32
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub max_input_roots: usize,
+}
+
+/// How the worker lays out the input root of an action in its directory.
+#[allow(non_camel_case_types)]
+#[derive(Deserialize, Debug, Default, Clone)]
+pub enum InputRootStrategy {
+    /// Hardlinks the files of the input root from the `FilesystemStore`,
+    /// which must be on the same filesystem as the `work_directory`.
+    #[default]
+    hardlink,
+
+    /// Copies the files of the input root from the `FilesystemStore` with
+    /// `copy_file_range`, so they share their blocks with the store on
+    /// filesystems with reflinks, like Btrfs and XFS. Elsewhere the files
+    /// are copied in full, which is slower but works where hardlinks do
+    /// not, and actions can not change the files in the store.
+    reflink,
+
+    /// Mounts an overlayfs with a writable layer of its own for each
+    /// action on top of a directory with the input root, which is shared
+    /// by the actions with the same input root and made only once. The
+    /// shared directories are kept in the `work_directory`, so with the
+    /// scheduler preferring workers that recently had the input root of an
+    /// action, laying out the inputs mostly takes a mount.
+    ///
+    /// The worker must be allowed to mount filesystems, the shared
+    /// directories are made with hardlinks like `hardlink`, and outputs
+    /// can not be moved out of the overlay, so this can not be used
+    /// together with `persistent_workers`.
+    overlayfs(OverlayfsInputRootConfig),
+}
+
+/// How the worker uploads the output files of actions to the CAS. Files are
+/// uploaded as soon as they are hashed, while the other outputs are still
+/// being looked at, except for small files, which are sent together.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct OutputUploadConfig {
+    /// The maximum number of uploads of an action at once, counting a
+    /// batch of small files as one.
+    /// Default: 64
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub max_concurrent_uploads: usize,
+
+    /// Output files up to this size are read into memory and uploaded in
+    /// batches with the other small files of the action, which the `grpc`
+    /// store sends as `BatchUpdateBlobs` requests instead of one stream per
+    /// file.
+    /// Default: 65536 (64KiB)
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub max_batch_file_size_bytes: u64,
+
+    /// A batch is uploaded once its files add up to this size, and the
+    /// rest when all outputs are looked at.
+    /// Default: 3145728 (3MiB)
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub max_batch_size_bytes: u64,
+}
+
+/// Limits of the CPU and memory of each action, enforced with a cgroup v2 of
+/// its own. Actions killed for using more memory than their limit fail with
+/// a `ResourceExhausted` error, which clients may retry, and have an
+/// `OomKillMetadata` in the `auxiliary_metadata` of their result.
+///
+/// They can not be used with the `container` runner, as the container tools
+/// start the actions in cgroups of their own where the limits would not
+/// apply.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ResourceLimitsConfig {
+    /// The cgroup v2 directory the cgroups of the actions are created in,
+    /// like `/sys/fs/cgroup/nativelink`. The worker must be allowed to
+    /// create cgroups in it, and the `cpu` and `memory` controllers must be
+    /// enabled in its `cgroup.subtree_control`.
+    #[serde(deserialize_with = "convert_string_with_shellexpand")]
+    pub cgroup_directory: String,
+
+    /// The platform property with the CPU shares of an action, relative to
+    /// the 1024 shares of actions without a limit, like the `--cpu-shares`
+    /// option of docker.
+    /// Default: cpu-shares
+    #[serde(default)]
+    pub cpu_shares_property: String,
+
+    /// The CPU shares of actions without the CPU shares property.
+    /// Default: 0 (unlimited)
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub default_cpu_shares: u64,
+
+    /// The platform property with the memory limit of an action in bytes.
+    /// Default: memory-limit-bytes
+    #[serde(default)]
+    pub memory_limit_property: String,
+
+    /// The memory limit of actions without the memory limit property.
+    /// Default: 0 (unlimited)
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub default_memory_limit_bytes: u64,
+}
+
 #[derive(Deserialize, Debug, Default)]
 #[serde(deny_unknown_fields)]
 pub struct LocalWorkerConfig {
@@ -690,6 +1490,50 @@ pub struct LocalWorkerConfig {
     /// of the environment variable being the value of the property of the
     /// action being executed of that name or the fixed value.
     pub additional_environment: Option<HashMap<String, EnvironmentSource>>,
+
+    /// If set, the worker asks the scheduler to drain it when the process
+    /// is shut down. It is then not sent new actions, finishes the actions
+    /// it is running and reports their results before it goes away.
+    /// Otherwise the scheduler runs the actions of the worker again on
+    /// other workers as soon as it shuts down.
+    /// Default: false
+    #[serde(default)]
+    pub drain_on_shutdown: bool,
+
+    /// How the commands of actions are run, directly on the worker host, in
+    /// a container or in a sandbox.
+    /// Default: local
+    #[serde(default)]
+    pub runner: ActionRunner,
+
+    /// If set, the CPU and memory of each action is limited.
+    /// Default: None (no limits)
+    #[serde(default)]
+    pub resource_limits: Option<ResourceLimitsConfig>,
+
+    /// If set, the actions of tools that support it run in persistent
+    /// workers.
+    /// Default: None (actions never run in persistent workers)
+    #[serde(default)]
+    pub persistent_workers: Option<PersistentWorkersConfig>,
+
+    /// How the input roots of actions are laid out in their directories.
+    /// Default: hardlink
+    #[serde(default)]
+    pub input_root_strategy: InputRootStrategy,
+
+    /// How the output files of actions are uploaded.
+    #[serde(default)]
+    pub output_upload: OutputUploadConfig,
+
+    /// Whether the stdout and stderr of actions are written to the
+    /// `ByteStream` service of the slow store of `cas_fast_slow_store`
+    /// while they run, so clients can follow them. The slow store must be a
+    /// grpc store, and the identity of the worker must be in the
+    /// `action_output_writer_identities` of the `ByteStream` service.
+    /// Default: false
+    #[serde(default)]
+    pub stream_action_output: bool,
 }
 
 #[allow(non_camel_case_types)]
@@ -699,7 +1543,7 @@ pub enum WorkerConfig {
     local(LocalWorkerConfig),
 }
 
-#[derive(Deserialize, Debug, Clone, Copy)]
+#[derive(Deserialize, Debug, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct GlobalConfig {
     /// Maximum number of open files that can be opened at one time.
@@ -757,6 +1601,127 @@ pub struct GlobalConfig {
     /// Default: 1024*1024 (1MiB)
     #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
     pub default_digest_size_health_check: usize,
+
+    /// Maximum number of bytes all memory stores together may hold,
+    /// including an estimate of the per entry overhead and the buffers of
+    /// uploads in flight. Once exceeded, every memory store evicts a share
+    /// proportional to its size, regardless of its own `max_bytes`. Set
+    /// this below the memory limit of the process, so memory is freed
+    /// before the process is killed.
+    ///
+    /// Default: 0. Zero means no process wide limit.
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub max_process_bytes: u64,
+
+    /// How long to wait on SIGTERM before exiting. Servers stop accepting
+    /// connections and ask clients to not send new requests on existing
+    /// ones, schedulers stop dispatching actions to workers and workers
+    /// finish the actions they are running. The process exits once all of
+    /// this is done or the grace period elapsed, whichever is first.
+    /// Note: Long lived streams, like workers connected to a scheduler or
+    /// clients waiting on a queued action, hold the process until the
+    /// grace period elapsed.
+    ///
+    /// Default: 30 (seconds)
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub shutdown_grace_period_s: u64,
+
+    /// Filter of the logs emitted, using the syntax of the `RUST_LOG`
+    /// environment variable, like "warn,nativelink_store=debug".
+    /// It is applied again when the config is reloaded on SIGHUP, so the
+    /// log level can be changed without a restart.
+    ///
+    /// Default: <The filter of `RUST_LOG`, or "warn" if not set>
+    #[serde(default, deserialize_with = "convert_optional_string_with_shellexpand")]
+    pub log_filter: Option<String>,
+
+    /// Periodically push the metrics to a collector, for processes that
+    /// can not be scraped by prometheus, like workers behind a NAT. The
+    /// metrics are the ones of the `experimental_prometheus` service,
+    /// without the latency and size histograms.
+    ///
+    /// Default: None (metrics are not pushed)
+    #[serde(default)]
+    pub metrics_push: Option<MetricsPushConfig>,
+
+    /// Log the store operations and gRPC requests that take longer than a
+    /// threshold, and count them in `nativelink_slow_operations_total`.
+    ///
+    /// Default: None (slow operations are not logged)
+    #[serde(default)]
+    pub slow_operation_log: Option<SlowOperationLogConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct SlowOperationLogConfig {
+    /// Store operations taking longer than this many milliseconds are
+    /// logged with the name of the store, the key and the size of the data.
+    /// This applies to the stores of the `stores` list, not to the stores
+    /// nested in their specs.
+    ///
+    /// Default: 1000 (1 second)
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub store_operation_threshold_ms: u64,
+
+    /// gRPC requests taking longer than this many milliseconds to be
+    /// answered are logged with the request. For streaming responses this
+    /// is the time until the stream was returned, not until it was
+    /// consumed.
+    ///
+    /// Default: 5000 (5 seconds)
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub rpc_threshold_ms: u64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MetricsPushConfig {
+    /// Where to push the metrics.
+    pub exporter: MetricsPushExporter,
+
+    /// Number of seconds between two pushes.
+    ///
+    /// Default: 60 (seconds)
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub interval_s: u64,
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Deserialize, Debug, Clone)]
+pub enum MetricsPushExporter {
+    /// OTLP over HTTP, to an OpenTelemetry collector. The resource of the
+    /// metrics can be set with the `OTEL_RESOURCE_ATTRIBUTES` environment
+    /// variable.
+    otlp(OtlpMetricsPushExporter),
+
+    /// Statsd over UDP with Datadog style tags, to a statsd server or the
+    /// Datadog agent. The values are sent as gauges.
+    statsd(StatsdMetricsPushExporter),
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct OtlpMetricsPushExporter {
+    /// URL of the OTLP/HTTP metrics endpoint of the collector.
+    /// Example: "http://otel-collector:4318/v1/metrics"
+    #[serde(deserialize_with = "convert_string_with_shellexpand")]
+    pub endpoint: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct StatsdMetricsPushExporter {
+    /// Address of the statsd server. Example: "127.0.0.1:8125"
+    #[serde(deserialize_with = "convert_string_with_shellexpand")]
+    pub address: String,
+
+    /// Tags added to every metric, like the name of the host, so the
+    /// metrics of different processes can be told apart.
+    ///
+    /// Default: {} (no tags)
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -782,6 +1747,10 @@ pub struct CasConfig {
     /// external service.
     pub experimental_origin_events: Option<OriginEventsSpec>,
 
+    /// Experimental - Audit log of who read and wrote which entries of the
+    /// Action Cache and CAS, and which actions they executed.
+    pub experimental_audit_log: Option<AuditLogSpec>,
+
     /// Any global configurations that apply to all modules live here.
     pub global: Option<GlobalConfig>,
 }
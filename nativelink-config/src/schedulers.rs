@@ -16,7 +16,10 @@ use std::collections::HashMap;
 
 use serde::Deserialize;
 
-use crate::serde_utils::{convert_duration_with_shellexpand, convert_numeric_with_shellexpand};
+use crate::serde_utils::{
+    convert_duration_with_shellexpand, convert_numeric_with_shellexpand,
+    convert_optional_numeric_with_shellexpand, convert_string_with_shellexpand,
+};
 use crate::stores::{GrpcEndpoint, Retry, StoreRefName};
 
 #[allow(non_camel_case_types)]
@@ -26,6 +29,7 @@ pub enum SchedulerSpec {
     grpc(GrpcSpec),
     cache_lookup(CacheLookupSpec),
     property_modifier(PropertyModifierSpec),
+    federated(FederatedSpec),
 }
 
 /// When the scheduler matches tasks to workers that are capable of running
@@ -50,6 +54,18 @@ pub enum PropertyType {
     /// to cause the scheduler to prefer certain workers over others, but not
     /// restrict them based on these values.
     priority,
+
+    /// Requires workers to set the platform property to a u64. Tasks set it
+    /// to a comparison the value of the worker must satisfy, one of `>=32`,
+    /// `>32`, `<=32`, `<32` or `=32`. A number alone is the same as `>=`.
+    /// Unlike `minimum`, the value is not reserved on the worker while the
+    /// task runs.
+    range,
+
+    /// Tasks set the platform property to a set of values, like
+    /// `{a100,h100}` or `a100,h100`, and run on workers that have any of
+    /// them. Workers may set more than one value the same way.
+    one_of,
 }
 
 /// When a worker is being searched for to run a job, this will be used
@@ -65,6 +81,70 @@ pub enum WorkerAllocationStrategy {
     most_recently_used,
 }
 
+/// A kind of failure of a job on a worker.
+#[allow(non_camel_case_types)]
+#[derive(Deserialize, Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum JobFailureKind {
+    /// The worker disconnected, timed out or stopped sending updates for
+    /// the job while running it.
+    worker_lost,
+
+    /// The worker reported an error that is likely caused by the worker or
+    /// the infrastructure, like an internal error or a store that is
+    /// unavailable. These are errors with the codes `Internal`,
+    /// `Unavailable`, `Unknown`, `Aborted`, `Cancelled`, `DataLoss` and
+    /// `DeadlineExceeded`.
+    infrastructure_error,
+
+    /// The worker reported an error that a retry is unlikely to fix, like
+    /// missing inputs or an invalid action. These are errors with any other
+    /// code.
+    action_error,
+}
+
+/// Maps the priorities requested by clients to the priorities actions are
+/// queued with. The client priority is inverted first (if enabled) and
+/// then clamped to `min_priority` and `max_priority`.
+///
+/// **Example JSON Config:**
+/// ```json
+/// "priority_mapping": {
+///   "invert": true,
+///   "min_priority": -10,
+///   "max_priority": 10
+/// }
+/// ```
+#[derive(Deserialize, Debug, Default, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct PriorityMappingSpec {
+    /// The remote execution API defines a lower priority value as more
+    /// urgent, while this scheduler dispatches higher values first. If set,
+    /// the priority of the client is negated, so clients following the API
+    /// definition are dispatched in the order they expect.
+    /// Default: false
+    #[serde(default)]
+    pub invert: bool,
+
+    /// The lowest priority an action may be queued with. Lower priorities
+    /// are raised to this value.
+    /// Default: no limit
+    #[serde(
+        default,
+        deserialize_with = "convert_optional_numeric_with_shellexpand"
+    )]
+    pub min_priority: Option<i32>,
+
+    /// The highest priority an action may be queued with. Higher priorities
+    /// are lowered to this value, which stops clients from starving everyone
+    /// else by asking for a very high priority.
+    /// Default: no limit
+    #[serde(
+        default,
+        deserialize_with = "convert_optional_numeric_with_shellexpand"
+    )]
+    pub max_priority: Option<i32>,
+}
+
 #[derive(Deserialize, Debug, Default)]
 #[serde(deny_unknown_fields)]
 pub struct SimpleSpec {
@@ -122,15 +202,275 @@ pub struct SimpleSpec {
     #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
     pub max_job_retries: usize,
 
+    /// The kinds of failures of a job on a worker that are retried, up to
+    /// `max_job_retries` times. Other failures are returned to the client
+    /// right away. A job that ran and exited with a non-zero exit code or
+    /// exceeded its timeout is complete and is never retried, while a job
+    /// rejected by a busy worker is always retried. The number of
+    /// retries is returned to the client in the `auxiliary_metadata` of the
+    /// `ExecutedActionMetadata`.
+    /// Default: ["worker_lost", "infrastructure_error", "action_error"]
+    #[serde(default)]
+    pub retry_on_failures: Vec<JobFailureKind>,
+
     /// The strategy used to assign workers jobs.
     #[serde(default)]
     pub allocation_strategy: WorkerAllocationStrategy,
 
+    /// How the priority clients set in the `ExecutionPolicy` of their
+    /// requests is turned into the priority actions are queued with.
+    /// Queued actions with a higher priority are dispatched first, and
+    /// actions with the same priority in the order they were queued.
+    /// Default: the priority of the request is used as is.
+    #[serde(default)]
+    pub priority_mapping: PriorityMappingSpec,
+
+    /// Raises the priority queued actions are dispatched with by one for
+    /// every this many seconds they have been queued, so actions with a low
+    /// priority still run while actions with a higher one keep coming in.
+    /// The priority the action was queued with is still reported.
+    /// Default: 0 (actions are dispatched with the priority they were
+    /// queued with)
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub priority_aging_interval_s: u64,
+
+    /// The maximum number of actions that may be queued at once. New
+    /// actions are rejected with `RESOURCE_EXHAUSTED` while the queue is
+    /// full, so clients back off instead of queuing forever. Executions
+    /// that join an action that is already queued or running are not
+    /// rejected. Actions queued at the same time may exceed the limit by
+    /// a few.
+    /// Default: 0 (unlimited)
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub max_queued_actions: usize,
+
+    /// Like `max_queued_actions`, but for each pool of actions with the
+    /// same platform properties. This keeps a backlog of actions no worker
+    /// can run from taking the whole queue.
+    /// Default: 0 (unlimited)
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub max_queued_actions_per_pool: usize,
+
+    /// Splits the queued actions into sub-queues by some of their platform
+    /// properties. Each sub-queue is matched to workers by its own loop, so
+    /// a flood of actions for one pool of workers does not hold up matching
+    /// the actions of another.
+    /// Default: all actions are in one queue
+    pub sub_queues: Option<SubQueuesSpec>,
+
+    /// The timeout of actions that do not set one, in seconds.
+    /// Default: 0 (`max_action_timeout_s` if set, otherwise the worker
+    /// decides)
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub default_action_timeout_s: u64,
+
+    /// The maximum timeout of actions, in seconds. Actions asking for a
+    /// longer one get this one instead. The scheduler completes executing
+    /// actions that ran for longer than their timeout plus
+    /// `worker_timeout_s` with a `DEADLINE_EXCEEDED` error, even if their
+    /// worker stopped responding.
+    /// Default: 0 (unlimited)
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub max_action_timeout_s: u64,
+
+    /// Shares the workers between clients in proportion to configured
+    /// weights, instead of dispatching queued actions only by priority.
+    /// Without it, a client queuing thousands of actions at once keeps
+    /// every worker busy until its actions are done.
+    /// Default: actions are dispatched only by priority
+    pub experimental_fair_share: Option<FairShareSpec>,
+
+    /// Runs a second copy of actions that take much longer than they
+    /// usually do on another worker, and uses the result of whichever copy
+    /// finishes first. A slow or flaky worker then no longer holds up the
+    /// whole build.
+    /// Default: actions are not hedged
+    pub experimental_hedging: Option<HedgingSpec>,
+
+    /// Exports signals an autoscaler can scale the workers of each pool
+    /// on, like the number of queued actions and the number of workers
+    /// the pool needs.
+    /// Default: no autoscaling signals are exported
+    pub autoscaling: Option<AutoscalingSpec>,
+
     /// The storage backend to use for the scheduler.
     /// Default: memory
     pub experimental_backend: Option<ExperimentalSimpleSchedulerBackend>,
 }
 
+/// What the actions are grouped by when sharing the workers between
+/// clients.
+#[allow(non_camel_case_types)]
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FairShareGroupBy {
+    /// Group actions by the identity of the client that queued them.
+    #[default]
+    identity,
+    /// Group actions by the instance name they were queued for.
+    instance_name,
+}
+
+/// Dispatches queued actions so the number of actions each group runs is
+/// proportional to its weight. When a worker is free, it takes the next
+/// action of the group with the fewest running actions for its weight.
+/// Within a group, actions are still dispatched by priority. Actions that
+/// join an action queued by another client count towards the group of the
+/// client that queued it first.
+///
+/// **Example JSON Config:**
+/// ```json
+/// "experimental_fair_share": {
+///   "group_by": "identity",
+///   "weights": {
+///     "ci": 1,
+///     "developers": 4
+///   }
+/// }
+/// ```
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct FairShareSpec {
+    /// What the actions are grouped by.
+    /// Default: identity
+    #[serde(default)]
+    pub group_by: FairShareGroupBy,
+
+    /// The weight of each group. A group with twice the weight of another
+    /// runs twice as many actions when both have actions queued.
+    /// Default: {} (every group has the `default_weight`)
+    #[serde(default)]
+    pub weights: HashMap<String, u32>,
+
+    /// The weight of groups not in `weights`.
+    /// Default: 1
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub default_weight: u32,
+}
+
+/// Sub-queues of the queued actions. The actions with the same values of
+/// `properties` share a sub-queue, which has its own matching loop, limit
+/// and metrics. A sub-queue is added the first time one of its actions is
+/// queued.
+///
+/// **Example JSON Config:**
+/// ```json
+/// "sub_queues": {
+///   "properties": ["pool"],
+///   "max_queued_actions": 10000
+/// }
+/// ```
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SubQueuesSpec {
+    /// The platform properties whose values tell the sub-queue of an action.
+    /// Actions without one of them are queued with an empty value for it.
+    pub properties: Vec<String>,
+
+    /// Like `max_queued_actions`, but for each sub-queue.
+    /// Default: 0 (unlimited)
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub max_queued_actions: usize,
+}
+
+/// Hedges actions that run far longer than they usually do. How long an
+/// action usually takes is the 95th percentile of its past durations.
+/// Actions are told apart by their command, so an action keeps its past
+/// durations when its inputs change. When the second copy succeeds first,
+/// the original is completed with its result and killed. When the original
+/// finishes first, the second copy is cancelled.
+///
+/// **Example JSON Config:**
+/// ```json
+/// "experimental_hedging": {
+///   "p95_multiplier": 3,
+///   "min_samples": 10
+/// }
+/// ```
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct HedgingSpec {
+    /// An action is hedged once it has run for this many times its 95th
+    /// percentile duration.
+    /// Default: 2
+    #[serde(default)]
+    pub p95_multiplier: f32,
+
+    /// The number of past durations an action needs before it is hedged.
+    /// Default: 5
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub min_samples: usize,
+
+    /// The number of past durations kept for each action. Older ones are
+    /// forgotten.
+    /// Default: 20
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub max_samples: usize,
+
+    /// The number of actions past durations are kept for. The durations of
+    /// the actions that ran least recently are forgotten first.
+    /// Default: 10000
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub max_actions: usize,
+
+    /// How often running actions are checked for stragglers, in seconds.
+    /// Default: 10
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub check_interval_s: u64,
+}
+
+/// Signals for autoscalers, like KEDA or the autoscaling groups of cloud
+/// providers, so they can scale the workers without scraping logs. The
+/// signals of each pool are exported as metrics of the scheduler and can
+/// be pushed to a webhook.
+///
+/// **Example JSON Config:**
+/// ```json
+/// "autoscaling": {
+///   "pool_properties": ["pool"],
+///   "actions_per_worker": 4,
+///   "max_workers": 100,
+///   "webhook_url": "https://autoscaler.example.com/nativelink"
+/// }
+/// ```
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct AutoscalingSpec {
+    /// The platform properties that split actions and workers into pools.
+    /// Actions and workers with the same values of these properties are
+    /// in the same pool.
+    /// Default: [] (all actions and workers are in one pool)
+    #[serde(default)]
+    pub pool_properties: Vec<String>,
+
+    /// The number of actions a worker runs at the same time. The desired
+    /// number of workers of a pool is its number of queued and executing
+    /// actions divided by this.
+    /// Default: 1
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub actions_per_worker: u64,
+
+    /// The desired number of workers of a pool is never below this.
+    /// Default: 0
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub min_workers: u64,
+
+    /// The desired number of workers of a pool is never above this.
+    /// Default: 0 (unlimited)
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub max_workers: u64,
+
+    /// How often the signals are updated, in seconds.
+    /// Default: 10
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub interval_s: u64,
+
+    /// If set, the signals of all pools are sent as JSON in a POST request
+    /// to this URL every time they are updated.
+    /// Default: "" (signals are only exported as metrics)
+    #[serde(default, deserialize_with = "convert_string_with_shellexpand")]
+    pub webhook_url: String,
+}
+
 #[allow(non_camel_case_types)]
 #[derive(Deserialize, Debug)]
 pub enum ExperimentalSimpleSchedulerBackend {
@@ -140,11 +480,36 @@ pub enum ExperimentalSimpleSchedulerBackend {
     redis(ExperimentalRedisSchedulerBackend),
 }
 
+/// Keeps the queued actions, operation state and the worker each action
+/// is assigned to in redis instead of in memory. The queue survives a
+/// scheduler restart, and multiple scheduler replicas pointing at the
+/// same redis store can run behind a load balancer. All updates are
+/// versioned, so replicas racing to assign the same action to a worker
+/// is safe, and replicas are notified of each other's changes through the
+/// store's pubsub channel.
+///
+/// Workers stay connected to a single replica, which only assigns work
+/// to its own workers. If a replica goes away, the actions it assigned
+/// are retried once their worker stops sending keep alives. The same
+/// happens to the running actions a scheduler finds after a restart if
+/// their worker does not connect to it again, even if no client is
+/// waiting for them anymore.
+///
+/// **Example JSON Config:**
+/// ```json
+/// "experimental_backend": {
+///   "redis": {
+///     "redis_store": "SCHEDULER_REDIS_STORE"
+///   }
+/// }
+/// ```
 #[derive(Deserialize, Debug, Default)]
 #[serde(deny_unknown_fields)]
 pub struct ExperimentalRedisSchedulerBackend {
     /// A reference to the redis store to use for the scheduler.
-    /// Note: This MUST resolve to a `RedisSpec`.
+    /// Note: This MUST resolve to a `RedisSpec` with
+    /// `experimental_pub_sub_channel` set, and every replica must use the
+    /// same addresses, `key_prefix` and channel.
     pub redis_store: StoreRefName,
 }
 
@@ -195,6 +560,32 @@ pub struct PlatformPropertyAddition {
     pub value: String,
 }
 
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PlatformPropertyReplacement {
+    /// The name of the property whose value is rewritten.
+    pub name: String,
+    /// The regex matched against the value of the property. Every match is
+    /// replaced.
+    pub pattern: String,
+    /// The text each match is replaced with. It may refer to the groups of
+    /// `pattern`, like `$1` or `${name}`.
+    pub replacement: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PlatformPropertyCondition {
+    /// The name of the property to check. Actions without the property
+    /// never match.
+    pub name: String,
+    /// The regex the value of the property must match. Use `^` and `$` to
+    /// match the whole value.
+    pub pattern: String,
+    /// The modifications to perform if the value matches, in order.
+    pub modifications: Vec<PropertyModification>,
+}
+
 #[allow(non_camel_case_types)]
 #[derive(Deserialize, Debug, Clone)]
 pub enum PropertyModification {
@@ -202,6 +593,13 @@ pub enum PropertyModification {
     add(PlatformPropertyAddition),
     /// Remove a named property from the action.
     remove(String),
+    /// Rewrite the value of a property with a regex. Nothing is done if
+    /// the action does not have the property.
+    replace(PlatformPropertyReplacement),
+    /// Perform modifications only if the value of a property matches a
+    /// regex, like adding `pool=gpu` if `container-image` matches
+    /// `^docker://gpu/`.
+    conditional(PlatformPropertyCondition),
 }
 
 #[derive(Deserialize, Debug)]
@@ -217,3 +615,25 @@ pub struct PropertyModifierSpec {
     /// The nested scheduler to use after modifying the properties.
     pub scheduler: Box<SchedulerSpec>,
 }
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct FederatedSpec {
+    /// The scheduler actions are sent to first, usually a `simple` scheduler
+    /// with the local workers.
+    pub scheduler: Box<SchedulerSpec>,
+
+    /// The schedulers actions are forwarded to, usually `grpc` schedulers of
+    /// other clusters. An action is forwarded to the first one that knows
+    /// all of its platform properties.
+    pub remote_schedulers: Vec<SchedulerSpec>,
+
+    /// Forward actions that are still waiting for a local worker after this
+    /// many seconds. Forwarded actions are cancelled on the local scheduler.
+    /// Actions with platform properties the local scheduler does not know,
+    /// or that are rejected because its queue is full, are always forwarded
+    /// right away.
+    /// Default: 0 (actions are only forwarded for their platform properties)
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub forward_after_queued_s: u64,
+}
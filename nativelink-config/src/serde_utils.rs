@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fmt;
 use std::marker::PhantomData;
 
@@ -154,6 +155,21 @@ pub fn convert_vec_string_with_shellexpand<'de, D: Deserializer<'de>>(
         .collect()
 }
 
+/// Same as `convert_string_with_shellexpand`, but expands the values of a
+/// `HashMap<String, String>`.
+pub fn convert_hashmap_string_with_shellexpand<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<String, String>, D::Error> {
+    let map = HashMap::<String, String>::deserialize(deserializer)?;
+    map.into_iter()
+        .map(|(k, v)| {
+            shellexpand::env(&v)
+                .map_err(de::Error::custom)
+                .map(|v| (k, v.into_owned()))
+        })
+        .collect()
+}
+
 /// Same as `convert_string_with_shellexpand`, but supports `Option<String>`.
 pub fn convert_optional_string_with_shellexpand<'de, D: Deserializer<'de>>(
     deserializer: D,
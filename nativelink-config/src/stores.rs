@@ -12,12 +12,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 
 use crate::serde_utils::{
     convert_data_size_with_shellexpand, convert_duration_with_shellexpand,
-    convert_numeric_with_shellexpand, convert_optional_string_with_shellexpand,
-    convert_string_with_shellexpand, convert_vec_string_with_shellexpand,
+    convert_hashmap_string_with_shellexpand, convert_numeric_with_shellexpand,
+    convert_optional_string_with_shellexpand, convert_string_with_shellexpand,
+    convert_vec_string_with_shellexpand,
 };
 
 /// Name of the store. This type will be used when referencing a store
@@ -25,7 +28,7 @@ use crate::serde_utils::{
 pub type StoreRefName = String;
 
 #[allow(non_camel_case_types)]
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ConfigDigestHashFunction {
     /// Use the sha256 hash function.
     /// <https://en.wikipedia.org/wiki/SHA-2>
@@ -34,6 +37,14 @@ pub enum ConfigDigestHashFunction {
     /// Use the blake3 hash function.
     /// <https://en.wikipedia.org/wiki/BLAKE_(hash_function)>
     blake3,
+
+    /// Use the sha384 hash function.
+    /// <https://en.wikipedia.org/wiki/SHA-2>
+    sha384,
+
+    /// Use the sha512 hash function.
+    /// <https://en.wikipedia.org/wiki/SHA-2>
+    sha512,
 }
 
 #[allow(non_camel_case_types)]
@@ -78,6 +89,85 @@ pub enum StoreSpec {
     ///
     experimental_s3_store(S3Spec),
 
+    /// GCS store will use Google Cloud Storage as a backend to store
+    /// the files. This configuration can be used to share files
+    /// across multiple instances.
+    ///
+    /// Credentials are taken from `credentials_file`, then from the
+    /// service account key file in `GOOGLE_APPLICATION_CREDENTIALS`, and
+    /// finally from the GCE/GKE metadata server (ie: workload identity).
+    ///
+    /// This configuration will never delete files, so you are
+    /// responsible for purging old files in other ways.
+    ///
+    /// **Example JSON Config:**
+    /// ```json
+    /// "experimental_gcs_store": {
+    ///   "bucket": "nativelink-cas-bucket",
+    ///   "key_prefix": "test-prefix-index/",
+    ///   "retry": {
+    ///     "max_retries": 6,
+    ///     "delay": 0.3,
+    ///     "jitter": 0.5
+    ///   },
+    ///   "resumable_chunk_size": "8mb"
+    /// }
+    /// ```
+    ///
+    experimental_gcs_store(GcsSpec),
+
+    /// Azure Blob store will use Azure Blob Storage as a backend to store
+    /// the files. This configuration can be used to share files
+    /// across multiple instances.
+    ///
+    /// Requests are authorized with `sas_token` if set, otherwise with
+    /// access tokens of the managed identity of the machine or pod.
+    ///
+    /// This configuration will never delete files, so you are
+    /// responsible for purging old files in other ways.
+    ///
+    /// **Example JSON Config:**
+    /// ```json
+    /// "experimental_azure_blob_store": {
+    ///   "account_name": "nativelinkaccount",
+    ///   "container": "nativelink-cas",
+    ///   "key_prefix": "test-prefix-index/",
+    ///   "retry": {
+    ///     "max_retries": 6,
+    ///     "delay": 0.3,
+    ///     "jitter": 0.5
+    ///   },
+    ///   "block_size": "8mb"
+    /// }
+    /// ```
+    ///
+    experimental_azure_blob_store(AzureBlobSpec),
+
+    /// HTTP store will use any HTTP server that supports `GET` (with
+    /// `Range` headers), `HEAD` and `PUT` as a backend to store the
+    /// files, ie: nginx with WebDAV or an artifact proxy. Each key is
+    /// mapped to the URL `<endpoint>/<key_prefix><key>`.
+    ///
+    /// This configuration will never delete files, so you are
+    /// responsible for purging old files in other ways.
+    ///
+    /// **Example JSON Config:**
+    /// ```json
+    /// "experimental_http_store": {
+    ///   "endpoint": "https://cache.example.com/cas",
+    ///   "headers": {
+    ///     "Authorization": "Bearer ${CACHE_TOKEN}"
+    ///   },
+    ///   "retry": {
+    ///     "max_retries": 6,
+    ///     "delay": 0.3,
+    ///     "jitter": 0.5
+    ///   }
+    /// }
+    /// ```
+    ///
+    experimental_http_store(HttpSpec),
+
     /// Verify store is used to apply verifications to an underlying
     /// store implementation. It is strongly encouraged to validate
     /// as much data as you can before accepting data from a client,
@@ -130,6 +220,35 @@ pub enum StoreSpec {
     ///
     completeness_checking(Box<CompletenessCheckingSpec>),
 
+    /// Retention store expires the ActionResults in its `backend` after a
+    /// fixed age, and optionally once they have not been read for a number
+    /// of sweeps, independently of the size based eviction of `backend`.
+    /// Expired ActionResults are removed and reported as not found.
+    /// Note: This store should only be used on AC stores.
+    ///
+    /// **Example JSON Config:**
+    /// ```json
+    /// "ac_retention": {
+    ///   "backend": {
+    ///     "filesystem": {
+    ///       "content_path": "~/.cache/nativelink/content_path-ac",
+    ///       "temp_path": "~/.cache/nativelink/tmp_path-ac",
+    ///       "eviction_policy": {
+    ///         // 500mb.
+    ///         "max_bytes": 500000000,
+    ///       }
+    ///     }
+    ///   },
+    ///   // 7 days.
+    ///   "max_age_s": 604800,
+    ///   // 1 hour.
+    ///   "sweep_interval_s": 3600,
+    ///   "max_sweeps_without_hit": 24
+    /// }
+    /// ```
+    ///
+    ac_retention(Box<AcRetentionSpec>),
+
     /// A compression store that will compress the data inbound and
     /// outbound. There will be a non-trivial cost to compress and
     /// decompress the data, but in many cases if the final store is
@@ -158,6 +277,39 @@ pub enum StoreSpec {
     ///
     compression(Box<CompressionSpec>),
 
+    /// An encryption store will encrypt the data with AES-256-GCM before
+    /// forwarding it to the backend and decrypt it when it is read, so
+    /// the backend never sees the contents. Every object is encrypted
+    /// with its own random data key, which is stored next to the data
+    /// wrapped (encrypted) by the configured key.
+    ///
+    /// The wrapped data key is bound to the key the object is stored under,
+    /// so objects copied to another key in the backend fail to decrypt.
+    ///
+    /// The key should be provided through the environment or a file
+    /// rather than written into the config. Keys can be rotated by moving
+    /// the old key to `decryption_keys`. Wrapping the data keys with a key
+    /// management service is not supported.
+    ///
+    /// Note: Objects in the backend are larger than the original data,
+    /// and the sizes reported by the backend are adjusted accordingly.
+    ///
+    /// **Example JSON Config:**
+    /// ```json
+    /// "encryption": {
+    ///   "key": "${NATIVELINK_ENCRYPTION_KEY}",
+    ///   "backend": {
+    ///     "experimental_s3_store": {
+    ///       "region": "eu-north-1",
+    ///       "bucket": "crossplane-bucket-af79aeca9",
+    ///       "key_prefix": "test-prefix-index/"
+    ///     }
+    ///   }
+    /// }
+    /// ```
+    ///
+    encryption(Box<EncryptionSpec>),
+
     /// A dedup store will take the inputs and run a rolling hash
     /// algorithm on them to slice the input into smaller parts then
     /// run a sha256 algorithm on the slice and if the object doesn't
@@ -226,7 +378,8 @@ pub enum StoreSpec {
     /// Existence store will wrap around another store and cache calls
     /// to has so that subsequent `has_with_results` calls will be
     /// faster. This is useful for cases when you have a store that
-    /// is slow to respond to has calls.
+    /// is slow to respond to has calls. Digests that are missing can
+    /// also be cached by setting `negative_cache_eviction_policy`.
     /// Note: This store should only be used on CAS stores.
     ///
     /// **Example JSON Config:**
@@ -250,6 +403,78 @@ pub enum StoreSpec {
     ///
     existence_cache(Box<ExistenceCacheSpec>),
 
+    /// Rate limit store limits the bytes per second and/or the requests per
+    /// second that are sent to the underlying store. This is useful when
+    /// the backend (ie: Redis or S3) is shared with other services and
+    /// should not be saturated by a burst of builds. Requests that exceed
+    /// the budget are delayed, not rejected.
+    ///
+    /// **Example JSON Config:**
+    /// ```json
+    /// "rate_limit": {
+    ///     "backend": {
+    ///       "redis_store": {
+    ///         "addresses": ["redis://127.0.0.1:6379/"]
+    ///       }
+    ///     },
+    ///     "max_bytes_per_second": "100mb",
+    ///     "max_ops_per_second": 1000
+    ///   }
+    /// ```
+    ///
+    rate_limit(Box<RateLimitSpec>),
+
+    /// Concurrency limit store bounds the number of `update` and `get_part`
+    /// requests that are in flight to the underlying store at the same
+    /// time. Requests over the limit wait in a queue until a slot is free.
+    /// This protects stores like the filesystem store from running out of
+    /// file descriptors under a burst of batch reads.
+    ///
+    /// **Example JSON Config:**
+    /// ```json
+    /// "concurrency_limit": {
+    ///     "backend": {
+    ///       "filesystem": {
+    ///         "content_path": "~/.cache/nativelink/content_path-cas",
+    ///         "temp_path": "~/.cache/nativelink/tmp_path-cas"
+    ///       }
+    ///     },
+    ///     "max_concurrent_requests": 512,
+    ///     "queue_timeout_ms": 30000
+    ///   }
+    /// ```
+    ///
+    concurrency_limit(Box<ConcurrencyLimitSpec>),
+
+    /// Quota store tracks the bytes written through it per instance name
+    /// and rejects or throttles the writes of an instance name once it
+    /// used up its budget. This is useful when one deployment is shared by
+    /// several teams that use different instance names. The usage of every
+    /// instance name is exported through the metrics.
+    ///
+    /// Note: The HTTP cache protocol has no instance name, so writes
+    /// through `experimental_http_cache` are counted against the quota of
+    /// the empty instance name.
+    ///
+    /// **Example JSON Config:**
+    /// ```json
+    /// "quota": {
+    ///     "backend": {
+    ///       "ref_store": {
+    ///         "name": "CAS_MAIN_STORE"
+    ///       }
+    ///     },
+    ///     "instance_quotas": {
+    ///       "team_a": { "max_bytes": "500gb" },
+    ///       "team_b": { "max_bytes": "100gb", "throttle_bytes_per_second": "1mb" }
+    ///     },
+    ///     "default_quota": { "max_bytes": "10gb" },
+    ///     "reset_interval_s": 86400
+    ///   }
+    /// ```
+    ///
+    quota(Box<QuotaSpec>),
+
     /// `FastSlow` store will first try to fetch the data from the `fast`
     /// store and then if it does not exist try the `slow` store.
     /// When the object does exist in the `slow` store, it will copy
@@ -293,6 +518,65 @@ pub enum StoreSpec {
     ///
     fast_slow(Box<FastSlowSpec>),
 
+    /// Failover store sends all requests to the `primary` store and falls
+    /// back to the `secondary` store on read misses and while the primary
+    /// is failing, ie: S3 buckets in two regions. After `failure_threshold`
+    /// consecutive failed requests the primary is skipped for
+    /// `recovery_interval_s`. Objects only found in the secondary are
+    /// copied back into the primary in the background.
+    ///
+    /// Writes only go to the secondary while the primary is skipped, so
+    /// the secondary should be kept in sync by other means (ie: S3
+    /// cross-region replication).
+    ///
+    /// **Example JSON Config:**
+    /// ```json
+    /// "failover": {
+    ///   "primary": {
+    ///     "experimental_s3_store": {
+    ///       "region": "us-east-1",
+    ///       "bucket": "nativelink-cas-us-east-1"
+    ///     }
+    ///   },
+    ///   "secondary": {
+    ///     "experimental_s3_store": {
+    ///       "region": "us-west-2",
+    ///       "bucket": "nativelink-cas-us-west-2"
+    ///     }
+    ///   },
+    ///   "failure_threshold": 5,
+    ///   "recovery_interval_s": 30
+    /// }
+    /// ```
+    ///
+    failover(Box<FailoverSpec>),
+
+    /// Mirror store will write every object to all of its stores and read
+    /// from the first store that has it. Objects that are found to be
+    /// missing from a store when they are read are copied to it in the
+    /// background. This is useful for keeping redundant copies of the
+    /// data, ie: on filesystems on different disks.
+    ///
+    /// **Example JSON Config:**
+    /// ```json
+    /// "mirror": {
+    ///   "stores": [{
+    ///     "filesystem": {
+    ///       "content_path": "/mnt/disk1/nativelink/content_path-cas",
+    ///       "temp_path": "/mnt/disk1/nativelink/tmp_path-cas"
+    ///     }
+    ///   }, {
+    ///     "filesystem": {
+    ///       "content_path": "/mnt/disk2/nativelink/content_path-cas",
+    ///       "temp_path": "/mnt/disk2/nativelink/tmp_path-cas"
+    ///     }
+    ///   }],
+    ///   "write_quorum": 1
+    /// }
+    /// ```
+    ///
+    mirror(MirrorSpec),
+
     /// Shards the data to multiple stores. This is useful for cases
     /// when you want to distribute the load across multiple stores.
     /// The digest hash is used to determine which store to send the
@@ -452,6 +736,36 @@ pub struct ShardConfig {
 pub struct ShardSpec {
     /// Stores to shard the data to.
     pub stores: Vec<ShardConfig>,
+
+    /// How keys are distributed over the stores.
+    ///
+    /// Default: weighted_range
+    #[serde(default)]
+    pub algorithm: ShardAlgorithm,
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ShardAlgorithm {
+    /// Splits the key space into one contiguous range per store, sized by
+    /// the weights. Adding or removing a store moves most keys to a
+    /// different store.
+    #[default]
+    weighted_range,
+
+    /// Places each store on a hash ring a number of times proportional to
+    /// its weight and sends a key to the store that follows it on the ring.
+    /// Adding a store only moves the keys the new store takes over, which
+    /// is roughly its share of the total weight. New stores must be added
+    /// to the end of `stores`, because the position of a store in the list
+    /// determines where it is placed on the ring.
+    ///
+    /// Keys stored before a change can be moved to their new store through
+    /// the admin API with a POST to
+    /// `/admin/stores/{store_name}/rebalance_shards/{remove_moved}`, where
+    /// `remove_moved` is 1 to remove the moved keys from their old store
+    /// and 0 to keep them.
+    consistent_hash,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -512,6 +826,71 @@ pub struct FilesystemSpec {
     /// Default: 4096
     #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
     pub block_size: u64,
+
+    /// Read and write content files through io_uring, which needs far
+    /// fewer syscalls than the default thread pool based file I/O. This
+    /// only has an effect if nativelink was built with the `io_uring`
+    /// feature and runs on a Linux kernel that allows io_uring, otherwise
+    /// regular file I/O is used.
+    /// Default: false
+    #[serde(default)]
+    pub use_io_uring: bool,
+
+    /// Read and write content files with `O_DIRECT` and aligned buffers,
+    /// so they bypass the page cache. Useful when the store has a dedicated
+    /// disk and the page cache should be left to the other processes on the
+    /// host, like co-located workers. Filesystems that do not support
+    /// `O_DIRECT` keep using the page cache. Can not be combined with
+    /// `use_io_uring`.
+    /// Default: false
+    #[serde(default)]
+    pub use_direct_io: bool,
+
+    /// Integrity check of the content files that is run on startup, before
+    /// the store serves any requests. Corrupt entries, for example files
+    /// that were truncated by a crash, are removed from the store.
+    /// Default: none
+    #[serde(default)]
+    pub fsck_on_startup: FsckMode,
+
+    /// If set, corrupt entries found by the integrity check are kept in
+    /// this directory for inspection instead of only being deleted. It must
+    /// be on the same filesystem as `content_path`.
+    /// Default: "" (corrupt entries are deleted)
+    #[serde(default, deserialize_with = "convert_string_with_shellexpand")]
+    pub fsck_quarantine_path: String,
+
+    /// If set, the eviction index, which holds the access order and size of
+    /// every entry, is written to this file every
+    /// `index_snapshot_interval_s` and read back on startup. This keeps the
+    /// LRU order across restarts and makes startup fast, because only the
+    /// names of the files have to be listed instead of reading the metadata
+    /// of every file. It must not be inside `content_path` or `temp_path`.
+    /// Default: "" (the index is rebuilt from the files on startup)
+    #[serde(default, deserialize_with = "convert_string_with_shellexpand")]
+    pub index_snapshot_path: String,
+
+    /// How often the eviction index is written to `index_snapshot_path`.
+    /// Default: 300
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub index_snapshot_interval_s: u64,
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FsckMode {
+    /// Entries are not checked.
+    #[default]
+    none,
+
+    /// The size of every file is compared to the size in the digest of its
+    /// name. Entries with string keys can not be checked.
+    size,
+
+    /// Like `size`, and the content of every file is hashed and compared to
+    /// the hash in its name. This reads every file, so it can take a long
+    /// time on large stores.
+    hash,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -524,6 +903,154 @@ pub struct FastSlowSpec {
     /// If the object does not exist in the `fast` store it will try to
     /// get it from this store.
     pub slow: StoreSpec,
+
+    /// When objects that are read from the `slow` store are also written
+    /// to the `fast` store. Objects requested by workers to be in the
+    /// `fast` store are always written to it.
+    ///
+    /// Default: always
+    #[serde(default)]
+    pub populate_on_read: PopulateOnReadPolicy,
+
+    /// If set, updates complete as soon as the object is written to the
+    /// `fast` store, and it is copied from the `fast` store to the `slow`
+    /// store in the background. Until the copy is done the object only
+    /// exists in the `fast` store, so it is lost if it is evicted or the
+    /// process stops before then.
+    ///
+    /// Default: None, updates are written to both stores before they
+    /// complete.
+    #[serde(default)]
+    pub write_back: Option<FastSlowWriteBackConfig>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct FastSlowWriteBackConfig {
+    /// Maximum number of objects waiting to be copied to the `slow` store.
+    /// Updates that find the queue full write to the `slow` store before
+    /// they complete.
+    ///
+    /// Default: 1024
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub max_queued_uploads: usize,
+
+    /// Maximum number of objects copied to the `slow` store at the same
+    /// time.
+    ///
+    /// Default: 16
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub max_concurrent_uploads: usize,
+
+    /// Retry configuration for copies to the `slow` store that fail.
+    #[serde(default)]
+    pub retry: Retry,
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+pub enum PopulateOnReadPolicy {
+    /// Every object read from the `slow` store is written to the `fast`
+    /// store.
+    #[default]
+    always,
+
+    /// Objects read from the `slow` store are never written to the `fast`
+    /// store.
+    never,
+
+    /// Only objects smaller than this many bytes are written to the `fast`
+    /// store, so large one-off objects don't evict the small ones that are
+    /// read often.
+    ///
+    /// Example:
+    /// ```json
+    /// "populate_on_read": { "only_if_smaller_than": "10mb" }
+    /// ```
+    #[serde(deserialize_with = "convert_data_size_with_shellexpand")]
+    only_if_smaller_than(u64),
+
+    /// Each object read from the `slow` store is written to the `fast`
+    /// store with this probability, between 0.0 and 1.0. Objects that are
+    /// read often end up in the `fast` store after a few reads, while most
+    /// objects that are read once never do.
+    ///
+    /// Example:
+    /// ```json
+    /// "populate_on_read": { "probabilistic": 0.1 }
+    /// ```
+    probabilistic(f32),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct FailoverSpec {
+    /// Store that receives all requests while it is healthy.
+    pub primary: StoreSpec,
+
+    /// Store used for read misses of the primary and for all requests
+    /// while the primary is failing.
+    pub secondary: StoreSpec,
+
+    /// Number of consecutive failed requests to the primary after which
+    /// requests are sent to the secondary instead. Not found errors
+    /// do not count as failures.
+    ///
+    /// Default: 5
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub failure_threshold: u32,
+
+    /// Number of seconds the primary is skipped after reaching
+    /// `failure_threshold`. The next request after that goes to the
+    /// primary again, and switches back to the secondary if it fails.
+    ///
+    /// Default: 30
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub recovery_interval_s: u32,
+
+    /// Don't copy objects that are missing in the primary but exist in the
+    /// secondary back into the primary when they are read.
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub disable_backfill: bool,
+
+    /// Maximum number of objects copied into the primary at the same time.
+    /// Reads that would start more backfills skip them.
+    ///
+    /// Default: 16
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub max_concurrent_backfills: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MirrorSpec {
+    /// Stores that receive every object. Reads are served by the first
+    /// store in this list that has the object.
+    pub stores: Vec<StoreSpec>,
+
+    /// Number of stores that must accept a write for it to succeed. Zero
+    /// means all stores. Writes are sent to all stores regardless, but a
+    /// write only fails if fewer than this many stores accepted it.
+    ///
+    /// Default: 0
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub write_quorum: usize,
+
+    /// Don't copy objects to the stores that are missing them when they
+    /// are read.
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub disable_repair: bool,
+
+    /// Maximum number of objects copied to other stores at the same time.
+    /// Reads that would start more repairs skip them.
+    ///
+    /// Default: 16
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub max_concurrent_repairs: usize,
 }
 
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
@@ -533,6 +1060,26 @@ pub struct MemorySpec {
     /// value will cause items to never be removed from the store causing
     /// infinite memory usage.
     pub eviction_policy: Option<EvictionPolicy>,
+
+    /// Number of shards the entries are split into by key, each behind its
+    /// own lock, so requests for different keys don't contend. The limits
+    /// of `eviction_policy` are split evenly between the shards and each
+    /// shard evicts on its own, so with more than one shard the evicted
+    /// entries are only approximately the least recently used ones.
+    ///
+    /// Default: 1
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub shard_count: usize,
+
+    /// If set, entries evicted from memory are written to this on-disk
+    /// area instead of being dropped, and are moved back into memory when
+    /// they are read again. The size of the area is bounded by its own
+    /// `eviction_policy`. This gives a single store the behavior of a
+    /// `fast_slow` store with a filesystem store as the slow store.
+    ///
+    /// Default: None
+    #[serde(default)]
+    pub spill: Option<FilesystemSpec>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -565,6 +1112,8 @@ pub struct DedupSpec {
     /// value will be about `normal_size * 1.3` due to implementation
     /// details.
     ///
+    /// With the `fixed_size` chunker this is the size of every chunk.
+    ///
     /// Default: 262144 (256k)
     #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
     pub normal_size: u32,
@@ -588,6 +1137,42 @@ pub struct DedupSpec {
     /// Default: 10
     #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
     pub max_concurrent_fetch_per_get: u32,
+
+    /// The algorithm used to split content into chunks.
+    ///
+    /// Default: fastcdc
+    #[serde(default)]
+    pub chunker: DedupChunker,
+
+    /// How often chunks in the `content_store` that are not referenced by
+    /// any index in the `index_store` anymore are removed. Chunks are left
+    /// behind when the `index_store` evicts an index. Collecting them
+    /// requires both stores to support listing their keys and the
+    /// `content_store` to support removing keys.
+    ///
+    /// Only uploads through this instance are protected from a collection
+    /// that runs at the same time, so it should be enabled on only one
+    /// instance if several instances share the stores.
+    ///
+    /// Default: 0 (chunks are never collected)
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub gc_interval_s: u64,
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DedupChunker {
+    /// Content defined chunking with FastCDC. Chunks are between
+    /// `min_size` and `max_size` bytes and about `normal_size` bytes on
+    /// average. Inserting or removing bytes only changes the chunks around
+    /// the change, so it works well for most build outputs.
+    #[default]
+    fastcdc,
+
+    /// Chunks of exactly `normal_size` bytes, except for the last one.
+    /// This is cheaper than FastCDC, but only dedups content whose changes
+    /// don't move the rest of the data, ie: disk images.
+    fixed_size,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -604,15 +1189,121 @@ pub struct ExistenceCacheSpec {
     /// value will cause items to never be removed from the store causing
     /// infinite memory usage.
     pub eviction_policy: Option<EvictionPolicy>,
+
+    /// If set, digests that the backend reported as missing are remembered
+    /// too, so repeated lookups for the same missing digests (which are
+    /// common in `FindMissingBlobs` calls of large builds) do not reach the
+    /// backend. An object uploaded through this store is removed from the
+    /// negative cache right away, but an object uploaded to the backend by
+    /// someone else is reported as missing until its entry expires, so
+    /// `max_seconds` should be kept short. Each entry counts as one byte
+    /// towards `max_bytes`.
+    /// Default: None. Missing digests are not cached.
+    #[serde(default)]
+    pub negative_cache_eviction_policy: Option<EvictionPolicy>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(deny_unknown_fields)]
-pub struct VerifySpec {
-    /// The underlying store wrap around. All content will first flow
-    /// through self before forwarding to backend. In the event there
-    /// is an error detected in self, the connection to the backend
-    /// will be terminated, and early termination should always cause
+pub struct RateLimitSpec {
+    /// The underlying store that the requests are forwarded to.
+    pub backend: StoreSpec,
+
+    /// Maximum number of bytes per second that are uploaded to and
+    /// downloaded from the backend combined.
+    ///
+    /// Default: 0. Bytes are not limited.
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub max_bytes_per_second: u64,
+
+    /// Number of bytes that can be transferred at once before
+    /// `max_bytes_per_second` applies, ie: after the store was idle.
+    ///
+    /// Default: `max_bytes_per_second`.
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub max_bytes_burst: u64,
+
+    /// Maximum number of requests per second sent to the backend. Every
+    /// digest of a `has` call counts as one request.
+    ///
+    /// Default: 0. Requests are not limited.
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub max_ops_per_second: u64,
+
+    /// Number of requests that can be sent at once before
+    /// `max_ops_per_second` applies.
+    ///
+    /// Default: `max_ops_per_second`.
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub max_ops_burst: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ConcurrencyLimitSpec {
+    /// The underlying store that the requests are forwarded to.
+    pub backend: StoreSpec,
+
+    /// Maximum number of `update` and `get_part` requests that are sent to
+    /// the backend at the same time. `has` requests are not limited.
+    #[serde(deserialize_with = "convert_numeric_with_shellexpand")]
+    pub max_concurrent_requests: usize,
+
+    /// How long a request may wait for a free slot before it fails with
+    /// `ResourceExhausted`.
+    ///
+    /// Default: 0. Requests wait until a slot is free.
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub queue_timeout_ms: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct QuotaSpec {
+    /// The underlying store that the requests are forwarded to.
+    pub backend: StoreSpec,
+
+    /// The quota of each instance name.
+    ///
+    /// Default: {}
+    #[serde(default)]
+    pub instance_quotas: HashMap<String, QuotaLimitSpec>,
+
+    /// The quota of the instance names that are not in `instance_quotas`.
+    ///
+    /// Default: None. Other instance names are not limited.
+    #[serde(default)]
+    pub default_quota: Option<QuotaLimitSpec>,
+
+    /// How often the usage of every instance name is reset.
+    ///
+    /// Default: 86400 (1 day).
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub reset_interval_s: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct QuotaLimitSpec {
+    /// Number of bytes the instance name may write per `reset_interval_s`.
+    #[serde(deserialize_with = "convert_data_size_with_shellexpand")]
+    pub max_bytes: u64,
+
+    /// Once `max_bytes` is used up, writes are throttled to this many bytes
+    /// per second instead of being rejected.
+    ///
+    /// Default: 0. Writes over the quota are rejected.
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub throttle_bytes_per_second: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct VerifySpec {
+    /// The underlying store wrap around. All content will first flow
+    /// through self before forwarding to backend. In the event there
+    /// is an error detected in self, the connection to the backend
+    /// will be terminated, and early termination should always cause
     /// updates to fail on the backend.
     pub backend: StoreSpec,
 
@@ -641,6 +1332,64 @@ pub struct CompletenessCheckingSpec {
     /// When a request is made, the results are decoded and all output digests/files are verified
     /// to exist in this CAS store before returning success.
     pub cas_store: StoreSpec,
+
+    /// Number of seconds between garbage collections of `cas_store`. A
+    /// garbage collection reads every ActionResult in `backend` and marks
+    /// the objects they reference in `cas_store` as recently used, so the
+    /// size based eviction of `cas_store` evicts objects that no
+    /// ActionResult references first. Both stores must support listing
+    /// their keys. Zero disables garbage collection.
+    ///
+    /// Default: 0
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub gc_interval_s: u64,
+
+    /// Also remove objects from `cas_store` that no ActionResult referenced
+    /// in two garbage collections in a row. Objects that are uploaded but
+    /// not referenced by an ActionResult yet, like the inputs of actions,
+    /// are removed too if they stay unreferenced for that long, so
+    /// `gc_interval_s` should be well above the duration of a build.
+    /// `cas_store` must support removing objects.
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub gc_remove_unreachable: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct AcRetentionSpec {
+    /// The underlying AC store whose ActionResults are expired.
+    pub backend: StoreSpec,
+
+    /// Maximum age in seconds of an ActionResult, measured from the
+    /// `worker_completed_timestamp` in its execution metadata. Older
+    /// ActionResults are reported as not found and removed from `backend`.
+    /// ActionResults without that timestamp never expire by age. Zero
+    /// disables expiry by age.
+    ///
+    /// Default: 0
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub max_age_s: u64,
+
+    /// Number of seconds between sweeps of `backend`. A sweep lists every
+    /// ActionResult in `backend` and removes the ones that expired, so they
+    /// free up space before they are read again. `backend` must support
+    /// listing its keys. Zero disables sweeps, expired ActionResults are
+    /// then only removed when they are read.
+    ///
+    /// Default: 0
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub sweep_interval_s: u64,
+
+    /// Remove ActionResults that were not read or written during this many
+    /// sweeps in a row. Reads are only tracked in memory, so the count
+    /// starts over when the process restarts. Requires `sweep_interval_s`.
+    /// Zero disables expiry of unused ActionResults.
+    ///
+    /// Default: 0
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub max_sweeps_without_hit: u32,
 }
 
 #[derive(Serialize, Deserialize, Debug, Default, PartialEq, Clone, Copy)]
@@ -666,6 +1415,60 @@ pub struct Lz4Config {
     pub max_decode_block_size: u32,
 }
 
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ZstdConfig {
+    /// Size of the blocks to compress.
+    ///
+    /// Default: 65536 (64k).
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub block_size: u32,
+
+    /// Maximum size allowed to attempt to deserialize data into.
+    /// See `Lz4Config::max_decode_block_size`.
+    ///
+    /// Default: value in `block_size`.
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub max_decode_block_size: u32,
+
+    /// Compression level from 1 (fastest) to 22 (smallest).
+    ///
+    /// Default: 3
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub level: i32,
+
+    /// Path to a zstd dictionary, ie: one made with `zstd --train`. New
+    /// blobs are compressed with it and the id of the dictionary is
+    /// recorded in every blob, so it is also used to decompress them.
+    /// If the file does not exist and `dictionary_training_samples_path`
+    /// is set, a dictionary is trained and written to this path.
+    ///
+    /// Default: "" (no dictionary)
+    #[serde(default, deserialize_with = "convert_string_with_shellexpand")]
+    pub dictionary_path: String,
+
+    /// Directory of sample files, ie: typical source files or protobuf
+    /// blobs, used to train a dictionary when there is no file at
+    /// `dictionary_path` yet.
+    ///
+    /// Default: "" (no training)
+    #[serde(default, deserialize_with = "convert_string_with_shellexpand")]
+    pub dictionary_training_samples_path: String,
+
+    /// Maximum size of a trained dictionary.
+    ///
+    /// Default: 112640 (110k).
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub dictionary_max_size: u32,
+
+    /// Paths to dictionaries that are only used to decompress blobs, ie:
+    /// dictionaries that were used before `dictionary_path` was changed.
+    ///
+    /// Default: []
+    #[serde(default, deserialize_with = "convert_vec_string_with_shellexpand")]
+    pub decompression_dictionary_paths: Vec<String>,
+}
+
 #[allow(non_camel_case_types)]
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub enum CompressionAlgorithm {
@@ -677,6 +1480,13 @@ pub enum CompressionAlgorithm {
     ///
     /// see: <https://lz4.github.io/lz4/>
     lz4(Lz4Config),
+
+    /// Zstandard compresses much better than lz4 at a higher cost in CPU.
+    /// With a dictionary it also compresses small blobs, like source files
+    /// and protobuf messages, well.
+    ///
+    /// see: <https://facebook.github.io/zstd/>
+    zstd(ZstdConfig),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -691,12 +1501,92 @@ pub struct CompressionSpec {
 
     /// The compression algorithm to use.
     pub compression_algorithm: CompressionAlgorithm,
+
+    /// Uploads smaller than this are stored without compression, because
+    /// compressing them saves little.
+    ///
+    /// Default: 0 (everything is compressed)
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub min_compression_size: u64,
+
+    /// Uploads larger than this are stored without compression, ie: to
+    /// not spend CPU on large objects that are already compressed.
+    ///
+    /// Default: 0 (no limit)
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub max_compression_size: u64,
+
+    /// Use a different compression algorithm for uploads up to a size,
+    /// ie: zstd with a high level for small and medium objects and lz4 for
+    /// the rest. The band with the smallest `max_size` the upload fits in
+    /// is used. Uploads larger than every band use `compression_algorithm`.
+    /// Data is always decompressed with the algorithm it was compressed
+    /// with, so bands can be changed at any time.
+    ///
+    /// Default: []
+    #[serde(default)]
+    pub size_bands: Vec<CompressionSizeBand>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct CompressionSizeBand {
+    /// The largest upload size that this band is used for.
+    #[serde(deserialize_with = "convert_data_size_with_shellexpand")]
+    pub max_size: u64,
+
+    /// The compression algorithm to use for uploads in this band.
+    pub compression_algorithm: CompressionAlgorithm,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct EncryptionSpec {
+    /// The underlying store that will receive the encrypted data.
+    pub backend: StoreSpec,
+
+    /// Base64 encoded 256 bit key used to encrypt new data, ie: generated
+    /// with `openssl rand -base64 32`. Exactly one of `key` and `key_file`
+    /// must be set.
+    #[serde(default, deserialize_with = "convert_optional_string_with_shellexpand")]
+    pub key: Option<String>,
+
+    /// Path to a file containing the base64 encoded key.
+    #[serde(default, deserialize_with = "convert_optional_string_with_shellexpand")]
+    pub key_file: Option<String>,
+
+    /// Base64 encoded keys that are no longer used to encrypt new data,
+    /// but are still needed to decrypt data written with them.
+    ///
+    /// Default: []
+    #[serde(default, deserialize_with = "convert_vec_string_with_shellexpand")]
+    pub decryption_keys: Vec<String>,
+
+    /// Size of the blocks that are encrypted individually. Smaller blocks
+    /// allow reading part of an object with less overhead, but add 16
+    /// bytes for every block. The sizes reported by the backend are
+    /// converted using this value, so it should not be changed once
+    /// data was written.
+    ///
+    /// Default: 65536 (64k).
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub block_size: u32,
+
+    /// Maximum block size allowed when decrypting data. The block size is
+    /// part of the stored data and authenticated by the key, but this
+    /// still limits how much memory a single read may allocate.
+    ///
+    /// Default: 4MiB.
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub max_decode_block_size: u32,
 }
 
 /// Eviction policy always works on LRU (Least Recently Used). Any time an entry
 /// is touched it updates the timestamp. Inserts and updates will execute the
 /// eviction policy removing any expired entries and/or the oldest entries
-/// until the store size becomes smaller than `max_bytes`.
+/// until the store size becomes smaller than `max_bytes`. If
+/// `high_watermark_bytes` is set, the oldest entries are evicted by a
+/// background task instead, before the store reaches `max_bytes`.
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct EvictionPolicy {
@@ -722,6 +1612,62 @@ pub struct EvictionPolicy {
     /// Default: 0. Zero means never evict based on count.
     #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
     pub max_count: u64,
+
+    /// Once the store grows past this many bytes, a background task starts
+    /// evicting the oldest entries, so inserts only have to evict inline
+    /// when `max_bytes` is reached. Should be smaller than `max_bytes`.
+    /// Default: 0. Zero means eviction only happens inline.
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub high_watermark_bytes: usize,
+
+    /// The background eviction task keeps evicting until the store is
+    /// smaller than this many bytes.
+    /// Default: 0. Zero means `high_watermark_bytes - evict_bytes`.
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub low_watermark_bytes: usize,
+
+    /// Maximum number of bytes the background eviction task evicts per
+    /// second, so a large eviction does not starve the store of its lock.
+    /// Default: 0. Zero means no pacing.
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub eviction_bytes_per_second: usize,
+
+    /// The algorithm used to pick the entry to evict.
+    /// Default: lru
+    #[serde(default)]
+    pub algorithm: EvictionAlgorithm,
+
+    /// Only used by `segmented_lru`. Percentage of `max_bytes` reserved for
+    /// the protected segment.
+    /// Default: 80
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub protected_percent: u32,
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionAlgorithm {
+    /// Evict the entry that was accessed least recently.
+    #[default]
+    lru,
+
+    /// Evict the entry that was accessed the least number of times. Ties
+    /// are broken by evicting the entry that was accessed least recently.
+    /// New entries start with the access count of the least frequently used
+    /// entry, so they are not evicted right after they are inserted.
+    lfu,
+
+    /// Evict the entry that was inserted first. Accessing an entry does not
+    /// extend its life, so `max_seconds` is the time to live of an entry
+    /// since it was inserted.
+    ttl,
+
+    /// New entries go into a probationary segment and are moved to a
+    /// protected segment when they are accessed again, for example when an
+    /// action cache entry is looked up and verified. Entries are evicted
+    /// from the probationary segment first, so a burst of blobs that are
+    /// only written once does not push out entries that are in use.
+    segmented_lru,
 }
 
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
@@ -770,6 +1716,65 @@ pub struct S3Spec {
     /// Default: 10.
     pub multipart_max_concurrent_uploads: Option<usize>,
 
+    /// Resume incomplete multipart uploads of the same key instead of
+    /// starting over. Parts that were already uploaded with the same
+    /// content, which is checked with the MD5 entity tag S3 returns for
+    /// each part, are skipped, and failed multipart uploads are kept around
+    /// so a retry by the client can reuse them. Parts encrypted with
+    /// SSE-KMS do not have MD5 entity tags and are always uploaded again.
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub resume_multipart_uploads: bool,
+
+    /// Abort incomplete multipart uploads under `key_prefix` that were
+    /// started more than this many seconds ago when the store starts.
+    /// Parts of incomplete uploads are billed until they are aborted,
+    /// so this should be set when `resume_multipart_uploads` is enabled
+    /// unless the bucket has a lifecycle rule that does the same. Zero
+    /// disables the cleanup.
+    ///
+    /// Default: 0
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub abort_incomplete_multipart_uploads_after_s: u32,
+
+    /// Server-side encryption to request for uploaded objects. If None,
+    /// the default encryption settings of the bucket apply.
+    ///
+    /// Default: None
+    #[serde(default)]
+    pub sse: Option<S3ServerSideEncryption>,
+
+    /// Storage class of uploaded objects, ie: `STANDARD_IA` or
+    /// `INTELLIGENT_TIERING`. If None, the default storage class of the
+    /// bucket is used.
+    ///
+    /// Default: None
+    #[serde(default, deserialize_with = "convert_optional_string_with_shellexpand")]
+    pub storage_class: Option<String>,
+
+    /// Tags to set on every uploaded object, ie: so objects can be matched
+    /// by lifecycle rules of the bucket. Values support shell expansion.
+    ///
+    /// Default: {}
+    #[serde(default, deserialize_with = "convert_hashmap_string_with_shellexpand")]
+    pub tags: HashMap<String, String>,
+
+    /// Objects of at least this many bytes are not proxied through the
+    /// `experimental_blob_redirect` service, instead the client is
+    /// redirected to a presigned URL to download the object directly from
+    /// S3. Zero disables redirects.
+    ///
+    /// Default: 0
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub presigned_url_threshold: u64,
+
+    /// Number of seconds a presigned URL stays valid.
+    ///
+    /// Default: 900 (zero defaults to this)
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub presigned_url_expiration_s: u32,
+
     /// Allow unencrypted HTTP connections. Only use this for local testing.
     ///
     /// Default: false
@@ -787,6 +1792,222 @@ pub struct S3Spec {
     pub disable_http2: bool,
 }
 
+#[allow(non_camel_case_types)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum S3ServerSideEncryptionAlgorithm {
+    /// Encrypt with keys managed by S3 (SSE-S3).
+    #[serde(rename = "AES256")]
+    aes256,
+    /// Encrypt with a key managed by AWS KMS (SSE-KMS).
+    #[serde(rename = "aws:kms")]
+    aws_kms,
+}
+
+/// Server-side encryption settings of the S3 store.
+///
+/// **Example JSON Config:**
+/// ```json
+/// "sse": {
+///   "algorithm": "aws:kms",
+///   "kms_key_id": "arn:aws:kms:us-east-1:111122223333:key/1234abcd-12ab-34cd-56ef-1234567890ab",
+///   "bucket_key_enabled": true
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct S3ServerSideEncryption {
+    /// The encryption algorithm to use.
+    pub algorithm: S3ServerSideEncryptionAlgorithm,
+
+    /// Id or ARN of the KMS key to encrypt with. Only valid with `aws:kms`.
+    /// If None, the AWS managed key of S3 (`aws/s3`) is used.
+    #[serde(default, deserialize_with = "convert_optional_string_with_shellexpand")]
+    pub kms_key_id: Option<String>,
+
+    /// Use an S3 Bucket Key to reduce the number of requests to KMS. Only
+    /// valid with `aws:kms`. If None, the setting of the bucket applies.
+    #[serde(default)]
+    pub bucket_key_enabled: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct GcsSpec {
+    /// Bucket name to use as the backend.
+    #[serde(default, deserialize_with = "convert_string_with_shellexpand")]
+    pub bucket: String,
+
+    /// If you wish to prefix the location in the bucket. If None, no prefix
+    /// will be used.
+    #[serde(default)]
+    pub key_prefix: Option<String>,
+
+    /// Retry configuration to use when a network request fails.
+    #[serde(default)]
+    pub retry: Retry,
+
+    /// If the number of seconds since the last modification of the object
+    /// is greater than this value, the object will not be considered
+    /// "existing". See `S3Spec::consider_expired_after_s`.
+    ///
+    /// Default: 0. Zero means never consider an object expired.
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub consider_expired_after_s: u32,
+
+    /// Path to a service account key file (JSON). If not set, the file in
+    /// the `GOOGLE_APPLICATION_CREDENTIALS` environment variable is used,
+    /// and if that is not set either, access tokens are requested from the
+    /// metadata server of the machine or pod (workload identity).
+    #[serde(default, deserialize_with = "convert_optional_string_with_shellexpand")]
+    pub credentials_file: Option<String>,
+
+    /// Uploads larger than this, or of unknown size, use a resumable upload
+    /// session and are sent in parts of this size. A failed part is resumed
+    /// from the last byte GCS acknowledged instead of restarting the upload.
+    /// Must be a multiple of 256KiB. At most one part per upload is buffered
+    /// in memory.
+    ///
+    /// Default: 8MiB
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub resumable_chunk_size: usize,
+
+    /// The GCS endpoint to talk to. Only change this for emulators or
+    /// private service connect endpoints.
+    ///
+    /// Default: <https://storage.googleapis.com>
+    #[serde(default, deserialize_with = "convert_optional_string_with_shellexpand")]
+    pub endpoint: Option<String>,
+
+    /// Allow unencrypted HTTP connections. Only use this for local testing.
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub insecure_allow_http: bool,
+
+    /// Disable http/2 connections and only use http/1.1.
+    /// See `S3Spec::disable_http2`.
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub disable_http2: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct AzureBlobSpec {
+    /// Name of the storage account.
+    #[serde(default, deserialize_with = "convert_string_with_shellexpand")]
+    pub account_name: String,
+
+    /// Name of the container in the storage account to use as the backend.
+    #[serde(default, deserialize_with = "convert_string_with_shellexpand")]
+    pub container: String,
+
+    /// If you wish to prefix the location in the container. If None, no
+    /// prefix will be used.
+    #[serde(default)]
+    pub key_prefix: Option<String>,
+
+    /// Retry configuration to use when a network request fails.
+    #[serde(default)]
+    pub retry: Retry,
+
+    /// If the number of seconds since the last modification of the blob
+    /// is greater than this value, the blob will not be considered
+    /// "existing". See `S3Spec::consider_expired_after_s`.
+    ///
+    /// Default: 0. Zero means never consider a blob expired.
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub consider_expired_after_s: u32,
+
+    /// Shared access signature appended to every request, with or without
+    /// the leading `?`. It needs read, write and create permissions on the
+    /// container. If not set, the managed identity of the machine or pod
+    /// is used instead.
+    #[serde(default, deserialize_with = "convert_optional_string_with_shellexpand")]
+    pub sas_token: Option<String>,
+
+    /// Client id of the user assigned managed identity to use. Only needed
+    /// if `sas_token` is not set and more than one identity is assigned.
+    #[serde(default, deserialize_with = "convert_optional_string_with_shellexpand")]
+    pub managed_identity_client_id: Option<String>,
+
+    /// Uploads larger than this, or of unknown size, are staged as blocks
+    /// of this size and then committed as a block list. A failed block is
+    /// retried on its own instead of restarting the upload. At most one
+    /// block per upload is buffered in memory.
+    ///
+    /// Default: 8MiB
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub block_size: usize,
+
+    /// The blob service endpoint to talk to. Only change this for emulators
+    /// (ie: azurite) or custom domains.
+    ///
+    /// Default: <https://{account_name}.blob.core.windows.net>
+    #[serde(default, deserialize_with = "convert_optional_string_with_shellexpand")]
+    pub endpoint: Option<String>,
+
+    /// Allow unencrypted HTTP connections. Only use this for local testing.
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub insecure_allow_http: bool,
+
+    /// Disable http/2 connections and only use http/1.1.
+    /// See `S3Spec::disable_http2`.
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub disable_http2: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct HttpSpec {
+    /// Base URL the keys are appended to, ie: `https://cache.example.com/cas`.
+    /// WebDAV servers must create missing collections on `PUT` if keys
+    /// contain a `/` (ie: `create_full_put_path` in nginx).
+    #[serde(deserialize_with = "convert_string_with_shellexpand")]
+    pub endpoint: String,
+
+    /// If you wish to prefix the path of every key. If None, no prefix
+    /// will be used.
+    #[serde(default)]
+    pub key_prefix: Option<String>,
+
+    /// Only use the hash of digests in URLs (ie: `<endpoint>/<hash>`), as
+    /// done by the HTTP remote cache protocol of Bazel. By default the
+    /// size is included as well (ie: `<endpoint>/<hash>-<size>`).
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub hash_only_paths: bool,
+
+    /// Headers sent with every request, ie: for authentication. Values
+    /// support shell expansion, so secrets can be read from the
+    /// environment.
+    #[serde(default, deserialize_with = "convert_hashmap_string_with_shellexpand")]
+    pub headers: HashMap<String, String>,
+
+    /// Retry configuration to use when a network request fails.
+    #[serde(default)]
+    pub retry: Retry,
+
+    /// The maximum buffer size to retain in case of a retryable error
+    /// during upload. See `S3Spec::max_retry_buffer_per_request`.
+    ///
+    /// Default: 5MB.
+    pub max_retry_buffer_per_request: Option<usize>,
+
+    /// Disable http/2 connections and only use http/1.1.
+    /// See `S3Spec::disable_http2`.
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub disable_http2: bool,
+}
+
 #[allow(non_camel_case_types)]
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 pub enum StoreType {
@@ -925,6 +2146,60 @@ pub struct RedisSpec {
     #[serde(default)]
     pub mode: RedisMode,
 
+    /// The name of the master set monitored by the sentinels. Only used
+    /// when `mode` is "sentinel".
+    ///
+    /// When set, every entry in `addresses` is treated as a sentinel
+    /// endpoint (ex: `["redis://sentinel-1:26379", "redis://sentinel-2:26379"]`)
+    /// and the current master is discovered through them. On failover the
+    /// store reconnects to the newly promoted master.
+    ///
+    /// If unset, a single address with a `sentinelServiceName` query
+    /// parameter must be provided instead.
+    ///
+    /// Default: (Empty String / Use URL)
+    #[serde(default, deserialize_with = "convert_optional_string_with_shellexpand")]
+    pub sentinel_master_name: Option<String>,
+
+    /// Connect to the redis server(s) over TLS. This is implied if the
+    /// address uses the `rediss://` scheme.
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub tls: bool,
+
+    /// Path to a PEM encoded certificate authority used to verify the
+    /// redis server. Setting this implies `tls`. If unset and TLS is in
+    /// use, the system root certificates are used.
+    ///
+    /// Default: (Empty String / System Roots)
+    #[serde(default, deserialize_with = "convert_optional_string_with_shellexpand")]
+    pub ca_cert: Option<String>,
+
+    /// The ACL username to authenticate with. Overrides any username
+    /// given in the address URL.
+    ///
+    /// Default: (Empty String / Use URL)
+    #[serde(default, deserialize_with = "convert_optional_string_with_shellexpand")]
+    pub username: Option<String>,
+
+    /// The password to authenticate with. Overrides any password given
+    /// in the address URL. To keep the secret out of the config file,
+    /// reference an environment variable (ie: `"${REDIS_PASSWORD}"`) or
+    /// use `password_file`.
+    ///
+    /// Default: (Empty String / Use URL)
+    #[serde(default, deserialize_with = "convert_optional_string_with_shellexpand")]
+    pub password: Option<String>,
+
+    /// Path to a file containing the password to authenticate with.
+    /// Leading and trailing whitespace is trimmed. Must not be set
+    /// together with `password`.
+    ///
+    /// Default: (Empty String / Use `password`)
+    #[serde(default, deserialize_with = "convert_optional_string_with_shellexpand")]
+    pub password_file: Option<String>,
+
     /// When using pubsub interface, this is the maximum number of items to keep
     /// queued up before dropping old items.
     ///
@@ -961,12 +2236,39 @@ pub struct RedisSpec {
     #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
     pub read_chunk_size: usize,
 
+    /// The maximum number of `read_chunk_size` reads to keep outstanding
+    /// per get. Reads are pipelined so large objects are not bound by the
+    /// round trip latency of every chunk. The length of an object is not
+    /// known before it is read, so up to `max_chunk_reads_per_get - 1`
+    /// reads may be issued past its end and thrown away.
+    ///
+    /// Default: 4
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub max_chunk_reads_per_get: usize,
+
     /// The number of connections to keep open to the redis server(s).
     ///
     /// Default: 3
     #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
     pub connection_pool_size: usize,
 
+    /// The maximum number of store operations allowed in flight on a single
+    /// pooled connection. Operations are spread over connections that have
+    /// spare capacity, and once every connection is at the limit new
+    /// operations wait for up to `connection_acquire_timeout_ms`.
+    ///
+    /// Default: 0. Zero means unlimited.
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub max_in_flight_per_connection: usize,
+
+    /// The amount of time in milliseconds to wait for a pooled connection
+    /// with spare capacity before failing the operation with
+    /// `ResourceExhausted`. Only used if `max_in_flight_per_connection` is set.
+    ///
+    /// Default: 10000 (10 seconds)
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub connection_acquire_timeout_ms: u64,
+
     /// The maximum number of upload chunks to allow per update.
     /// This is used to limit the amount of memory used when uploading
     /// large objects to the redis server. A good rule of thumb is to
@@ -978,6 +2280,74 @@ pub struct RedisSpec {
     #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
     pub max_chunk_uploads_per_update: usize,
 
+    /// The maximum amount of data to send to the redis server in a single
+    /// write command during an upload. Larger incoming chunks are split,
+    /// so at most `upload_chunk_size * max_chunk_uploads_per_update` bytes
+    /// of an upload are buffered in memory at once.
+    ///
+    /// Default: 1MiB
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub upload_chunk_size: usize,
+
+    /// If set, blobs are split across multiple redis keys of at most this
+    /// size instead of being stored in a single string. The key of the
+    /// blob then only holds a small metadata record pointing to its chunks.
+    /// This allows storing blobs larger than the 512MiB redis string limit
+    /// and spreads large blobs across the memory of a single node more
+    /// evenly. Must not be larger than 512MiB.
+    ///
+    /// The chunk size is saved in the metadata record of every blob, so
+    /// blobs written with a different non-zero value stay readable.
+    ///
+    /// Note: The layout can not be switched on a store that holds data.
+    /// Once this is enabled, blobs in the unchunked layout fail to read
+    /// with a `DataLoss` error, and once it is disabled, blobs in the
+    /// chunked layout are read back as their metadata record.
+    ///
+    /// Default: 0 (disabled)
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub storage_chunk_size: usize,
+
+    /// If set, up to this many small values read from redis are cached
+    /// in-process and served without a round trip to redis. The cache is
+    /// kept coherent using RESP3 client-side caching: redis notifies the
+    /// store when a cached key is modified, expires or is evicted, and the
+    /// entry is dropped. This is intended for read heavy action cache
+    /// traffic and requires redis 6 or newer.
+    ///
+    /// Note: Values served from the cache do not refresh `key_ttl_seconds`.
+    /// Values stored in the chunked layout (see `storage_chunk_size`) are
+    /// never cached.
+    ///
+    /// Default: 0 (disabled)
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub client_side_cache_max_entries: usize,
+
+    /// The largest value that is kept in the client-side cache.
+    ///
+    /// Default: 64KiB
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub client_side_cache_max_value_size: usize,
+
+    /// If set, an expiry of this many seconds is set on every key written
+    /// to the store and refreshed every time the key is read or checked
+    /// for existence. This gives the store LRU-like eviction without
+    /// relying on the redis `maxmemory-policy`.
+    ///
+    /// Default: 0. Zero means keys never expire.
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub key_ttl_seconds: u32,
+
+    /// If set, the temporary keys used while an upload is in progress are
+    /// created with an expiry of this many seconds, so uploads that never
+    /// complete are cleaned up by redis. This should be comfortably larger
+    /// than the longest expected upload, and is usually much shorter than
+    /// `key_ttl_seconds`.
+    ///
+    /// Default: 0. Zero means temporary keys never expire.
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub temp_key_ttl_seconds: u32,
+
     /// Retry configuration to use when a network request fails.
     /// See the `Retry` struct for more information.
     ///
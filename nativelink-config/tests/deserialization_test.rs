@@ -14,7 +14,8 @@
 
 use nativelink_config::serde_utils::{
     convert_data_size_with_shellexpand, convert_duration_with_shellexpand,
-    convert_optional_numeric_with_shellexpand, convert_optional_string_with_shellexpand,
+    convert_hashmap_string_with_shellexpand, convert_optional_numeric_with_shellexpand,
+    convert_optional_string_with_shellexpand,
 };
 use serde::Deserialize;
 
@@ -45,6 +46,12 @@ struct OptionalStringEntity {
     value: Option<String>,
 }
 
+#[derive(Deserialize, Debug)]
+struct HashMapStringEntity {
+    #[serde(default, deserialize_with = "convert_hashmap_string_with_shellexpand")]
+    value: std::collections::HashMap<String, String>,
+}
+
 mod duration_tests {
     use super::*;
 
@@ -358,6 +365,16 @@ mod shellexpand_tests {
             serde_json5::from_str::<OptionalStringEntity>(r#"{"value": "${EMPTY_VAR}"}"#).unwrap();
         assert_eq!(empty_string_result.value, Some(String::new()));
 
+        // Test map values with environment variable
+        let map_result = serde_json5::from_str::<HashMapStringEntity>(
+            r#"{"value": {"Authorization": "Bearer ${TEST_VAR}"}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            map_result.value.get("Authorization"),
+            Some(&"Bearer test_value".to_string())
+        );
+
         // Test undefined environment variable
         let undefined_result =
             serde_json5::from_str::<OptionalNumericEntity>(r#"{"value": "${UNDEFINED_VAR}"}"#);
@@ -0,0 +1,88 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+
+use crate::metrics_collection::{
+    CollectedMetricChildren, CollectedMetricPrimitiveValue, CollectedMetrics,
+    RootMetricCollectedMetrics,
+};
+
+/// Encodes the collected metrics as statsd lines, with `tags` in the
+/// Datadog syntax. The values are totals, not increments since the last
+/// push, so they are sent as gauges.
+pub fn statsd_export(
+    mut root_prefix: String,
+    tags: &BTreeMap<String, String>,
+    root_collected_metrics: &RootMetricCollectedMetrics,
+) -> Vec<String> {
+    if !root_prefix.is_empty() {
+        root_prefix.push('_');
+    }
+    let tags_suffix = if tags.is_empty() {
+        String::new()
+    } else {
+        let tags: Vec<String> = tags
+            .iter()
+            .map(|(key, value)| format!("{}:{}", sanitize(key), sanitize(value)))
+            .collect();
+        format!("|#{}", tags.join(","))
+    };
+    let mut lines = Vec::new();
+    process_children(
+        &mut root_prefix,
+        &tags_suffix,
+        root_collected_metrics,
+        &mut lines,
+    );
+    lines
+}
+
+/// Replaces the characters statsd servers do not accept in names and tags.
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn process_children(
+    prefix: &mut String,
+    tags_suffix: &str,
+    children: &CollectedMetricChildren,
+    lines: &mut Vec<String>,
+) {
+    for (name, child) in children {
+        prefix.push_str(name);
+        let mut added_prefix_len = name.len();
+        match child {
+            CollectedMetrics::Primitive(primitive) => {
+                if let Some(CollectedMetricPrimitiveValue::Counter(value)) = &primitive.value {
+                    lines.push(format!("{}:{value}|g{tags_suffix}", sanitize(prefix)));
+                }
+            }
+            CollectedMetrics::Component(component) => {
+                prefix.push('_');
+                added_prefix_len += 1;
+                process_children(prefix, tags_suffix, component, lines);
+            }
+        }
+        prefix.truncate(prefix.len() - added_prefix_len);
+    }
+}
@@ -12,14 +12,14 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Debug;
 use std::io::{BufRead, Cursor};
 use std::marker::PhantomData;
 use std::str::from_utf8;
 
 use nativelink_metric::{MetricFieldData, MetricKind, MetricsComponent};
-use nativelink_metric_collector::{otel_export, MetricsCollectorLayer};
+use nativelink_metric_collector::{otel_export, statsd_export, MetricsCollectorLayer};
 use opentelemetry::metrics::MeterProvider;
 use opentelemetry_sdk::metrics::SdkMeterProvider;
 use prometheus::{Encoder, TextEncoder};
@@ -188,3 +188,53 @@ target_info{service_name="unknown_service",telemetry_sdk_language="rust",telemet
 
     assert_eq!(output, expected_output);
 }
+
+// Note: Special case to not use nativelink-test macro. We want this test
+// to be very lightweight and not depend on other crates.
+#[test]
+fn test_statsd_exporter() {
+    let multi_struct = MultiStruct {
+        pub_u64: 1,
+        str: "str_data".to_string(),
+        _no_metric_str: "no_metric_str".to_string(),
+        _no_metric_u64: 2,
+        sub_struct_group: Foo {
+            custom_handler_num_str: 3,
+            custom_handler_num_counter: 4,
+            _bar: &PhantomData,
+        },
+        sub_struct: Foo {
+            custom_handler_num_str: 5,
+            custom_handler_num_counter: 6,
+            _bar: &PhantomData,
+        },
+    };
+    let (layer, output_metrics) = MetricsCollectorLayer::new();
+    let subscriber = tracing_subscriber::registry().with(layer);
+
+    tracing::subscriber::with_default(subscriber, || {
+        MetricsComponent::publish(
+            &multi_struct,
+            MetricKind::Component,
+            MetricFieldData::default(),
+        )
+        .unwrap();
+    });
+
+    let tags = BTreeMap::from([
+        ("host".to_string(), "worker-1".to_string()),
+        ("pool".to_string(), "linux x86".to_string()),
+    ]);
+    let mut output = statsd_export("nativelink".to_string(), &tags, &output_metrics.lock());
+    let mut expected_output = vec![
+        "nativelink_custom_handler_num_counter:6|g|#host:worker-1,pool:linux_x86",
+        "nativelink_foo_custom_handler_num_counter:4|g|#host:worker-1,pool:linux_x86",
+        "nativelink_pub_u64:1|g|#host:worker-1,pool:linux_x86",
+    ];
+
+    // We need to sort because the output order is non-deterministic.
+    output.sort();
+    expected_output.sort();
+
+    assert_eq!(output, expected_output);
+}
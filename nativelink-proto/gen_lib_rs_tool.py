@@ -70,8 +70,12 @@ def main():
     print(_HEADER)
 
     tree_root = { "children": {}, "filename": None }
+    descriptor_set = None
     for filepath in args.files:
         filepath = os.path.relpath(os.path.normpath(filepath), args.rootdir)
+        if filepath.endswith('.bin'):
+            descriptor_set = filepath
+            continue
         assert filepath.endswith('.pb.rs'), "Expected " + filepath + " to end in '.pb.rs'"
         package_parts = filepath.split('.')[:-2]  # Remove `.pb.rs'.
         assert '.' not in package_parts and '..' not in package_parts, \
@@ -84,6 +88,11 @@ def main():
         assert not cur_node["filename"], "Duplicate package '%s'" % ('.'.join(package_parts), )
         cur_node["filename"] = '.'.join(package_parts) + '.pb.rs'
 
+    if descriptor_set is not None:
+        print("/// Encoded `FileDescriptorSet` of all protos, used by gRPC reflection.")
+        print('pub const FILE_DESCRIPTOR_SET: &[u8] = include_bytes!("%s");' % (descriptor_set, ))
+        print()
+
     print_package_part_to_mod(tree_root)
 
 
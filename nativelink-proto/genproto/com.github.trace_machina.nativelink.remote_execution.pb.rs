@@ -19,6 +19,14 @@ pub struct KeepAliveRequest {
     /// / ID of the worker making the request.
     #[prost(string, tag = "1")]
     pub worker_id: ::prost::alloc::string::String,
+    /// / The input roots of the last actions the worker ran, most recent
+    /// / first. Their files are likely still in the local cache of the
+    /// / worker, so the scheduler prefers the worker for actions with the
+    /// / same input root.
+    #[prost(message, repeated, tag = "2")]
+    pub cached_input_roots: ::prost::alloc::vec::Vec<
+        super::super::super::super::super::build::bazel::remote::execution::v2::Digest,
+    >,
 }
 /// / Request object for going away requests.
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -26,6 +34,11 @@ pub struct GoingAwayRequest {
     /// / ID of the worker making the request.
     #[prost(string, tag = "1")]
     pub worker_id: ::prost::alloc::string::String,
+    /// / If set, the scheduler stops sending the worker new actions, but it
+    /// / keeps the worker so it can finish the actions it is running. The
+    /// / worker sends another request without it once it is done.
+    #[prost(bool, tag = "2")]
+    pub drain: bool,
 }
 /// / Represents the initial request sent to the scheduler informing the
 /// / scheduler about this worker's capabilities.
@@ -152,6 +165,24 @@ pub struct StartExecute {
     /// / The ID of the worker that is executing the action.
     #[prost(string, tag = "6")]
     pub worker_id: ::prost::alloc::string::String,
+    /// / The W3C trace context of the scheduler span that dispatched the
+    /// / action, so the spans of the worker continue the trace of the client.
+    /// / Empty if the scheduler does not export spans.
+    #[prost(map = "string, string", tag = "7")]
+    pub trace_context: ::std::collections::HashMap<
+        ::prost::alloc::string::String,
+        ::prost::alloc::string::String,
+    >,
+    /// / The id of the request that queued the action, so the logs of the
+    /// / worker can be correlated with the logs of the scheduler.
+    /// / Empty if not known.
+    #[prost(string, tag = "8")]
+    pub request_id: ::prost::alloc::string::String,
+    /// / The timeout the scheduler enforces for the action, which the worker
+    /// / uses instead of the timeout of the action, as the scheduler may fill
+    /// / in a default or lower it. Not set if the action has no timeout.
+    #[prost(message, optional, tag = "9")]
+    pub timeout: ::core::option::Option<::prost_types::Duration>,
 }
 /// / This is a special message used to save actions into the CAS that can be used
 /// / by programs like bb_browswer to inspect the history of a build.
@@ -168,6 +199,24 @@ pub struct HistoricalExecuteResponse {
         super::super::super::super::super::build::bazel::remote::execution::v2::ExecuteResponse,
     >,
 }
+/// / Added by the scheduler to the `auxiliary_metadata` of the
+/// / `ExecutedActionMetadata` of actions that failed on a worker and were
+/// / retried before they completed.
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct RetryMetadata {
+    /// / Number of times the action was retried.
+    #[prost(uint32, tag = "1")]
+    pub retries: u32,
+}
+/// / Added by the worker to the `auxiliary_metadata` of the
+/// / `ExecutedActionMetadata` of actions it killed because they used more
+/// / memory than their limit.
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct OomKillMetadata {
+    /// / The memory limit of the action in bytes.
+    #[prost(uint64, tag = "1")]
+    pub memory_limit_bytes: u64,
+}
 /// Generated client implementations.
 pub mod worker_api_client {
     #![allow(
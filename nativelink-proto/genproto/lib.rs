@@ -25,9 +25,17 @@
     rustdoc::invalid_html_tags
 )]
 
+/// Encoded `FileDescriptorSet` of all protos, used by gRPC reflection.
+pub const FILE_DESCRIPTOR_SET: &[u8] = include_bytes!("nativelink_descriptor_set.bin");
+
 pub mod build {
     pub mod bazel {
         pub mod remote {
+            pub mod asset {
+                pub mod v1 {
+                    include!("build.bazel.remote.asset.v1.pb.rs");
+                }
+            }
             pub mod execution {
                 pub mod v2 {
                     include!("build.bazel.remote.execution.v2.pb.rs");
@@ -99,6 +107,9 @@ pub mod blaze {
     pub mod strategy_policy {
         include!("blaze.strategy_policy.pb.rs");
     }
+    pub mod worker {
+        include!("blaze.worker.pb.rs");
+    }
 }
 pub mod options {
     include!("options.pb.rs");
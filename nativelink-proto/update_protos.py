@@ -42,6 +42,10 @@ _RUST_LICENSE = """\
 """
 
 
+# Encoded `FileDescriptorSet` of all protos, served by gRPC reflection.
+_DESCRIPTOR_SET = "nativelink_descriptor_set.bin"
+
+
 def expected_contents(pkg):
     src = runfiles_file_path(pkg)
     with open(src, "rb") as infile:
@@ -61,6 +65,10 @@ def update(proto_packages):
     for pkg in proto_packages:
         with open(repo_file_path(pkg), "wb") as outfile:
             outfile.write(expected_contents(pkg))
+    shutil.copyfile(
+        os.path.join(_BAZEL_DIR, _DESCRIPTOR_SET),
+        os.path.join(_REPO_DIR, _DESCRIPTOR_SET),
+    )
     with open(_REPO_DIR + "/lib.rs", "wb") as outfile:
         with open(_BAZEL_DIR + "/lib.rs", "rb") as infile:
             outfile.write(infile.read())
@@ -85,6 +93,22 @@ def check(proto_packages):
             print("%s out of date" % dst)
             failed = True
 
+    # Check the descriptor set, which is binary so it is compared exactly.
+    dst = os.path.join(_REPO_DIR, _DESCRIPTOR_SET)
+    try:
+        with open(os.path.join(_BAZEL_DIR, _DESCRIPTOR_SET), "rb") as infile:
+            expected = infile.read()
+        with open(dst, "rb") as infile:
+            actual = infile.read()
+        if expected == actual:
+            print("%s OK" % dst)
+        else:
+            print("%s out of date" % dst)
+            failed = True
+    except OSError as e:
+        failed = True
+        print("Could not read %s: %s" % (_DESCRIPTOR_SET, e))
+
     # Now check the lib.rs file.
     dst = _REPO_DIR + "/lib.rs"
     try:
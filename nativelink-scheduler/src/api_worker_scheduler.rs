@@ -12,8 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
+use std::time::SystemTime;
 
 use async_lock::Mutex;
 use lru::LruCache;
@@ -23,7 +26,9 @@ use nativelink_metric::{
     group, MetricFieldData, MetricKind, MetricPublishKnownKindData, MetricsComponent,
     RootMetricsComponent,
 };
-use nativelink_util::action_messages::{OperationId, WorkerId};
+use nativelink_util::action_messages::{ActionInfo, ActionStage, OperationId, WorkerId};
+use nativelink_util::common::DigestInfo;
+use nativelink_util::health_utils::{HealthRegistryBuilder, HealthStatus, HealthStatusIndicator};
 use nativelink_util::operation_state_manager::{UpdateOperationType, WorkerStateManager};
 use nativelink_util::platform_properties::PlatformProperties;
 use nativelink_util::spawn;
@@ -33,9 +38,22 @@ use tokio::sync::Notify;
 use tonic::async_trait;
 use tracing::{event, Level};
 
+use crate::autoscaling::{Autoscaling, PoolSignals};
+use crate::hedging::Hedging;
 use crate::platform_property_manager::PlatformPropertyManager;
 use crate::worker::{ActionInfoWithProps, Worker, WorkerTimestamp, WorkerUpdate};
-use crate::worker_scheduler::WorkerScheduler;
+use crate::worker_scheduler::{WorkerScheduler, WorkerStatus};
+
+/// Tells the operators that a draining worker finished its actions.
+fn log_if_drained(worker: &Worker) {
+    if worker.is_draining && !worker.has_actions() {
+        event!(
+            Level::INFO,
+            worker_id = ?worker.id,
+            "Draining worker is idle and can be shut down"
+        );
+    }
+}
 
 struct Workers(LruCache<WorkerId, Worker>);
 
@@ -86,6 +104,13 @@ struct ApiWorkerSchedulerImpl {
     worker_change_notify: Arc<Notify>,
     /// A channel to notify that an operation is still alive.
     operation_keep_alive_tx: UnboundedSender<(OperationId, WorkerId)>,
+    /// If set, no worker is found for actions, so they remain queued.
+    dispatch_paused: bool,
+    /// The workers that reported each input root to be in their cache.
+    workers_by_cached_input_root: HashMap<DigestInfo, HashSet<WorkerId>>,
+    /// If set, the durations of the actions that ran successfully are
+    /// recorded to tell stragglers apart.
+    maybe_hedging: Option<Arc<Hedging>>,
 }
 
 impl ApiWorkerSchedulerImpl {
@@ -109,6 +134,9 @@ impl ApiWorkerSchedulerImpl {
         );
         worker.last_update_timestamp = timestamp;
         for operation_id in worker.running_action_infos.keys() {
+            if worker.killed_operation_ids.contains(operation_id) {
+                continue;
+            }
             if self
                 .operation_keep_alive_tx
                 .send((operation_id.clone(), *worker_id))
@@ -155,10 +183,50 @@ impl ApiWorkerSchedulerImpl {
     /// running.
     fn remove_worker(&mut self, worker_id: &WorkerId) -> Option<Worker> {
         let result = self.workers.pop(worker_id);
+        if let Some(worker) = &result {
+            for input_root_digest in &worker.cached_input_roots {
+                self.forget_cached_input_root(worker_id, input_root_digest);
+            }
+        }
         self.worker_change_notify.notify_one();
         result
     }
 
+    /// Sets the input roots the worker likely has in its local cache.
+    fn set_worker_cached_input_roots(
+        &mut self,
+        worker_id: &WorkerId,
+        cached_input_roots: HashSet<DigestInfo>,
+    ) -> Result<(), Error> {
+        let worker = self.workers.0.peek_mut(worker_id).ok_or_else(|| {
+            make_input_err!(
+                "Worker not found in worker map in set_worker_cached_input_roots() {}",
+                worker_id
+            )
+        })?;
+        let old_cached_input_roots =
+            std::mem::replace(&mut worker.cached_input_roots, cached_input_roots.clone());
+        for input_root_digest in old_cached_input_roots.difference(&cached_input_roots) {
+            self.forget_cached_input_root(worker_id, input_root_digest);
+        }
+        for input_root_digest in cached_input_roots.difference(&old_cached_input_roots) {
+            self.workers_by_cached_input_root
+                .entry(*input_root_digest)
+                .or_default()
+                .insert(*worker_id);
+        }
+        Ok(())
+    }
+
+    fn forget_cached_input_root(&mut self, worker_id: &WorkerId, input_root_digest: &DigestInfo) {
+        if let Some(worker_ids) = self.workers_by_cached_input_root.get_mut(input_root_digest) {
+            worker_ids.remove(worker_id);
+            if worker_ids.is_empty() {
+                self.workers_by_cached_input_root.remove(input_root_digest);
+            }
+        }
+    }
+
     /// Sets if the worker is draining or not.
     async fn set_drain_worker(
         &mut self,
@@ -170,24 +238,62 @@ impl ApiWorkerSchedulerImpl {
             .get_mut(worker_id)
             .err_tip(|| format!("Worker {worker_id} doesn't exist in the pool"))?;
         worker.is_draining = is_draining;
+        log_if_drained(worker);
         self.worker_change_notify.notify_one();
         Ok(())
     }
 
+    fn get_worker_status(&self, worker_id: &WorkerId) -> Result<WorkerStatus, Error> {
+        let worker = self.workers.peek(worker_id).ok_or_else(|| {
+            make_err!(
+                Code::NotFound,
+                "Worker {worker_id} doesn't exist in the pool"
+            )
+        })?;
+        Ok(WorkerStatus {
+            is_draining: worker.is_draining,
+            running_actions: worker.running_action_infos.len(),
+        })
+    }
+
     fn inner_find_worker_for_action(
         &self,
         platform_properties: &PlatformProperties,
+        input_root_digest: &DigestInfo,
+        excluded_worker_id: Option<&WorkerId>,
     ) -> Option<WorkerId> {
+        if self.dispatch_paused {
+            return None;
+        }
+        let can_run_action = |w: &Worker| {
+            w.can_accept_work()
+                && excluded_worker_id != Some(&w.id)
+                && platform_properties.is_satisfied_by(&w.platform_properties)
+        };
+        // Workers that have the inputs of the action in their cache do not
+        // need to download them, so they are preferred over the allocation
+        // strategy.
+        let maybe_cached_worker_id = self
+            .workers_by_cached_input_root
+            .get(input_root_digest)
+            .and_then(|worker_ids| {
+                worker_ids
+                    .iter()
+                    .find(|worker_id| self.workers.peek(*worker_id).is_some_and(can_run_action))
+            });
+        if let Some(worker_id) = maybe_cached_worker_id {
+            return Some(*worker_id);
+        }
         let mut workers_iter = self.workers.iter();
         let workers_iter = match self.allocation_strategy {
             // Use rfind to get the least recently used that satisfies the properties.
-            WorkerAllocationStrategy::least_recently_used => workers_iter.rfind(|(_, w)| {
-                w.can_accept_work() && platform_properties.is_satisfied_by(&w.platform_properties)
-            }),
+            WorkerAllocationStrategy::least_recently_used => {
+                workers_iter.rfind(|(_, w)| can_run_action(w))
+            }
             // Use find to get the most recently used that satisfies the properties.
-            WorkerAllocationStrategy::most_recently_used => workers_iter.find(|(_, w)| {
-                w.can_accept_work() && platform_properties.is_satisfied_by(&w.platform_properties)
-            }),
+            WorkerAllocationStrategy::most_recently_used => {
+                workers_iter.find(|(_, w)| can_run_action(w))
+            }
         };
         workers_iter.map(|(_, w)| &w.id).copied()
     }
@@ -220,7 +326,25 @@ impl ApiWorkerSchedulerImpl {
             UpdateOperationType::UpdateWithError(err) => {
                 (true, err.code == Code::ResourceExhausted)
             }
+            UpdateOperationType::UpdateWithWorkerLost(_) => (true, false),
         };
+        let succeeded = matches!(
+            &update,
+            UpdateOperationType::UpdateWithActionStage(ActionStage::Completed(action_result))
+                if action_result.exit_code == 0 && action_result.error.is_none()
+        );
+
+        // The operation was already completed when it was cancelled, so we
+        // only wait for the worker to be done with it to free its resources.
+        if worker.killed_operation_ids.contains(operation_id) {
+            if !is_finished {
+                return Ok(());
+            }
+            let complete_action_res = worker.complete_action(operation_id).await;
+            log_if_drained(worker);
+            self.worker_change_notify.notify_one();
+            return complete_action_res;
+        }
 
         // Update the operation in the worker state manager.
         {
@@ -245,6 +369,20 @@ impl ApiWorkerSchedulerImpl {
             return Ok(());
         }
 
+        // Only successful runs tell how long an action usually takes.
+        let maybe_pending_action_info = worker.running_action_infos.get(operation_id);
+        if let (Some(hedging), Some(pending_action_info), true) =
+            (&self.maybe_hedging, maybe_pending_action_info, succeeded)
+        {
+            hedging.record_duration(
+                &pending_action_info.action_info.inner,
+                pending_action_info
+                    .start_timestamp
+                    .elapsed()
+                    .unwrap_or_default(),
+            );
+        }
+
         // Clear this action from the current worker if finished.
         let complete_action_res = {
             let was_paused = !worker.can_accept_work();
@@ -256,6 +394,7 @@ impl ApiWorkerSchedulerImpl {
             if (was_paused || due_to_backpressure) && worker.has_actions() {
                 worker.is_paused = true;
             }
+            log_if_drained(worker);
             complete_action_res
         };
 
@@ -306,6 +445,27 @@ impl ApiWorkerSchedulerImpl {
         Ok(())
     }
 
+    /// Asks the worker running the operation to kill it and evicts the
+    /// worker if it could not be notified.
+    async fn kill_operation(
+        &mut self,
+        worker_id: &WorkerId,
+        operation_id: &OperationId,
+    ) -> Result<(), Error> {
+        let Some(worker) = self.workers.peek_mut(worker_id) else {
+            // The worker is gone, so it is no longer running the operation.
+            return Ok(());
+        };
+        if !worker.running_action_infos.contains_key(operation_id) {
+            return Ok(());
+        }
+        if let Err(err) = worker.kill_operation(operation_id) {
+            return Result::<(), _>::Err(err.clone())
+                .merge(self.immediate_evict_worker(worker_id, err).await);
+        }
+        Ok(())
+    }
+
     /// Evicts the worker from the pool and puts items back into the queue if anything was being executed on it.
     async fn immediate_evict_worker(
         &mut self,
@@ -317,12 +477,15 @@ impl ApiWorkerSchedulerImpl {
             // We don't care if we fail to send message to worker, this is only a best attempt.
             let _ = worker.notify_update(WorkerUpdate::Disconnect).await;
             for (operation_id, _) in worker.running_action_infos.drain() {
+                if worker.killed_operation_ids.contains(&operation_id) {
+                    continue;
+                }
                 result = result.merge(
                     self.worker_state_manager
                         .update_operation(
                             &operation_id,
                             worker_id,
-                            UpdateOperationType::UpdateWithError(err.clone()),
+                            UpdateOperationType::UpdateWithWorkerLost(err.clone()),
                         )
                         .await,
                 );
@@ -335,6 +498,16 @@ impl ApiWorkerSchedulerImpl {
     }
 }
 
+/// An operation running on a worker.
+#[derive(Debug, Clone)]
+pub struct RunningAction {
+    pub worker_id: WorkerId,
+    pub operation_id: OperationId,
+    pub action_info: Arc<ActionInfo>,
+    /// When the action was sent to the worker.
+    pub start_timestamp: SystemTime,
+}
+
 #[derive(MetricsComponent)]
 pub struct ApiWorkerScheduler {
     #[metric]
@@ -356,6 +529,7 @@ impl ApiWorkerScheduler {
         allocation_strategy: WorkerAllocationStrategy,
         worker_change_notify: Arc<Notify>,
         worker_timeout_s: u64,
+        maybe_hedging: Option<Arc<Hedging>>,
     ) -> Arc<Self> {
         let (operation_keep_alive_tx, mut operation_keep_alive_rx) = mpsc::unbounded_channel();
         Arc::new(Self {
@@ -365,6 +539,9 @@ impl ApiWorkerScheduler {
                 allocation_strategy,
                 worker_change_notify,
                 operation_keep_alive_tx,
+                dispatch_paused: false,
+                workers_by_cached_input_root: HashMap::new(),
+                maybe_hedging,
             }),
             platform_property_manager,
             worker_timeout_s,
@@ -411,6 +588,16 @@ impl ApiWorkerScheduler {
             .await
     }
 
+    /// Asks the worker to kill an operation it is running.
+    pub async fn kill_operation(
+        &self,
+        worker_id: &WorkerId,
+        operation_id: &OperationId,
+    ) -> Result<(), Error> {
+        let mut inner = self.inner.lock().await;
+        inner.kill_operation(worker_id, operation_id).await
+    }
+
     /// Attempts to find a worker that is capable of running this action.
     // TODO(blaise.bruer) This algorithm is not very efficient. Simple testing using a tree-like
     // structure showed worse performance on a 10_000 worker * 7 properties * 1000 queued tasks
@@ -418,9 +605,62 @@ impl ApiWorkerScheduler {
     pub async fn find_worker_for_action(
         &self,
         platform_properties: &PlatformProperties,
+        input_root_digest: &DigestInfo,
+        excluded_worker_id: Option<&WorkerId>,
     ) -> Option<WorkerId> {
         let inner = self.inner.lock().await;
-        inner.inner_find_worker_for_action(platform_properties)
+        inner.inner_find_worker_for_action(
+            platform_properties,
+            input_root_digest,
+            excluded_worker_id,
+        )
+    }
+
+    /// Returns the operations running on the workers, except the ones the
+    /// workers were asked to kill.
+    pub async fn running_actions(&self) -> Vec<RunningAction> {
+        let inner = self.inner.lock().await;
+        inner
+            .workers
+            .iter()
+            .flat_map(|(worker_id, worker)| {
+                worker
+                    .running_action_infos
+                    .iter()
+                    .filter(|(operation_id, _)| {
+                        !worker.killed_operation_ids.contains(*operation_id)
+                    })
+                    .map(|(operation_id, pending_action_info)| RunningAction {
+                        worker_id: *worker_id,
+                        operation_id: operation_id.clone(),
+                        action_info: pending_action_info.action_info.inner.clone(),
+                        start_timestamp: pending_action_info.start_timestamp,
+                    })
+            })
+            .collect()
+    }
+
+    /// Counts the busy and idle workers of each autoscaling pool. Draining
+    /// workers are not counted, they are going away.
+    pub async fn count_workers_per_pool(
+        &self,
+        autoscaling: &Autoscaling,
+        pools: &mut BTreeMap<String, PoolSignals>,
+    ) {
+        let inner = self.inner.lock().await;
+        for (_worker_id, worker) in inner.workers.iter() {
+            if worker.is_draining {
+                continue;
+            }
+            let signals = pools
+                .entry(autoscaling.pool_of_worker(&worker.platform_properties))
+                .or_default();
+            if worker.has_actions() {
+                signals.busy_workers += 1;
+            } else {
+                signals.idle_workers += 1;
+            }
+        }
     }
 
     /// Checks to see if the worker exists in the worker pool. Should only be used in unit tests.
@@ -483,6 +723,17 @@ impl WorkerScheduler for ApiWorkerScheduler {
             .err_tip(|| "Error refreshing lifetime in worker_keep_alive_received()")
     }
 
+    async fn set_worker_cached_input_roots(
+        &self,
+        worker_id: &WorkerId,
+        cached_input_roots: HashSet<DigestInfo>,
+    ) -> Result<(), Error> {
+        let mut inner = self.inner.lock().await;
+        inner
+            .set_worker_cached_input_roots(worker_id, cached_input_roots)
+            .err_tip(|| "Error setting cached input roots in set_worker_cached_input_roots()")
+    }
+
     async fn remove_worker(&self, worker_id: &WorkerId) -> Result<(), Error> {
         let mut inner = self.inner.lock().await;
         inner
@@ -537,6 +788,45 @@ impl WorkerScheduler for ApiWorkerScheduler {
         let mut inner = self.inner.lock().await;
         inner.set_drain_worker(worker_id, is_draining).await
     }
+
+    async fn get_worker_status(&self, worker_id: &WorkerId) -> Result<WorkerStatus, Error> {
+        let inner = self.inner.lock().await;
+        inner.get_worker_status(worker_id)
+    }
+
+    async fn set_dispatch_paused(&self, is_paused: bool) -> Result<(), Error> {
+        let mut inner = self.inner.lock().await;
+        inner.dispatch_paused = is_paused;
+        // Queued actions are matched again once dispatch is resumed.
+        inner.worker_change_notify.notify_one();
+        Ok(())
+    }
+
+    fn register_health(self: Arc<Self>, registry: &mut HealthRegistryBuilder) {
+        registry.register_indicator(self);
+    }
 }
 
 impl RootMetricsComponent for ApiWorkerScheduler {}
+
+#[async_trait]
+impl HealthStatusIndicator for ApiWorkerScheduler {
+    fn get_name(&self) -> &'static str {
+        "ApiWorkerScheduler"
+    }
+
+    async fn check_health(&self, _namespace: Cow<'static, str>) -> HealthStatus {
+        let inner = self.inner.lock().await;
+        if inner.dispatch_paused {
+            return HealthStatus::new_warning(self, "Dispatching actions is paused".into());
+        }
+        // Actions only queue up without workers, so this is not a failure.
+        if inner.workers.is_empty() {
+            return HealthStatus::new_warning(self, "No workers are connected".into());
+        }
+        HealthStatus::new_ok(
+            self,
+            format!("{} workers are connected", inner.workers.len()).into(),
+        )
+    }
+}
@@ -0,0 +1,183 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Request};
+use nativelink_config::schedulers::AutoscalingSpec;
+use nativelink_error::{make_err, make_input_err, Code, Error};
+use nativelink_metric::MetricsComponent;
+use nativelink_store::http_store::HttpClient;
+use nativelink_util::platform_properties::PlatformProperties;
+use parking_lot::Mutex;
+use serde::Serialize;
+
+/// Default of how often the autoscaling signals are updated.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_INTERVAL_S: u64 = 10;
+
+/// Names a pool by the values of its properties, like `pool=gpu`.
+pub(crate) fn pool_name(
+    pool_properties: &[String],
+    get_value: impl Fn(&str) -> Option<String>,
+) -> String {
+    pool_properties
+        .iter()
+        .map(|name| format!("{name}={}", get_value(name).unwrap_or_default()))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// What an autoscaler needs to know to scale the workers of a pool.
+#[derive(MetricsComponent, Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct PoolSignals {
+    #[metric(help = "The number of actions queued in the pool")]
+    pub queued_actions: u64,
+    #[metric(help = "The time in seconds the oldest action of the pool has been queued for")]
+    pub oldest_queued_action_age_s: u64,
+    #[metric(help = "The number of actions executing in the pool")]
+    pub executing_actions: u64,
+    #[metric(help = "The number of workers of the pool running actions")]
+    pub busy_workers: u64,
+    #[metric(help = "The number of workers of the pool not running any action")]
+    pub idle_workers: u64,
+    #[metric(help = "The number of workers the pool needs to run its actions")]
+    pub desired_workers: u64,
+}
+
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    timestamp_s: u64,
+    pools: &'a BTreeMap<String, PoolSignals>,
+}
+
+/// Splits actions and workers into pools and keeps the last signals of
+/// each pool, so they are exported as metrics and sent to the webhook.
+#[derive(MetricsComponent)]
+pub struct Autoscaling {
+    pool_properties: Vec<String>,
+    actions_per_worker: u64,
+    min_workers: u64,
+    max_workers: u64,
+    interval: Duration,
+    maybe_webhook: Option<(String, Arc<dyn HttpClient>)>,
+    #[metric(group = "pools")]
+    pools: Mutex<BTreeMap<String, PoolSignals>>,
+}
+
+impl Autoscaling {
+    pub fn new(spec: &AutoscalingSpec) -> Self {
+        let connector = hyper_rustls::HttpsConnectorBuilder::new()
+            .with_webpki_roots()
+            .https_or_http()
+            .enable_http1()
+            .build();
+        Self::new_with_client(
+            spec,
+            Arc::new(hyper::Client::builder().build::<_, Body>(connector)),
+        )
+    }
+
+    pub fn new_with_client(spec: &AutoscalingSpec, http_client: Arc<dyn HttpClient>) -> Self {
+        let mut interval_s = spec.interval_s;
+        if interval_s == 0 {
+            interval_s = DEFAULT_INTERVAL_S;
+        }
+        Self {
+            pool_properties: spec.pool_properties.clone(),
+            actions_per_worker: spec.actions_per_worker.max(1),
+            min_workers: spec.min_workers,
+            max_workers: spec.max_workers,
+            interval: Duration::from_secs(interval_s),
+            maybe_webhook: (!spec.webhook_url.is_empty())
+                .then(|| (spec.webhook_url.clone(), http_client)),
+            pools: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// How often the signals are updated.
+    pub const fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Returns the name of the pool of an action with these platform
+    /// properties.
+    pub fn pool_of_action(&self, platform_properties: &HashMap<String, String>) -> String {
+        pool_name(&self.pool_properties, |name| {
+            platform_properties.get(name).cloned()
+        })
+    }
+
+    /// Returns the name of the pool of a worker with these platform
+    /// properties.
+    pub fn pool_of_worker(&self, platform_properties: &PlatformProperties) -> String {
+        pool_name(&self.pool_properties, |name| {
+            platform_properties
+                .properties
+                .get(name)
+                .map(|value| value.as_str().into_owned())
+        })
+    }
+
+    /// Returns the number of workers a pool needs to run its actions.
+    pub fn desired_workers(&self, signals: &PoolSignals) -> u64 {
+        let actions = signals.queued_actions + signals.executing_actions;
+        let mut desired_workers = actions
+            .div_ceil(self.actions_per_worker)
+            .max(self.min_workers);
+        if self.max_workers != 0 {
+            desired_workers = desired_workers.min(self.max_workers);
+        }
+        desired_workers
+    }
+
+    /// Returns the signals of each pool from the last update.
+    pub fn pools(&self) -> BTreeMap<String, PoolSignals> {
+        self.pools.lock().clone()
+    }
+
+    /// Replaces the signals of the pools and sends them to the webhook if
+    /// there is one.
+    pub async fn report(&self, pools: BTreeMap<String, PoolSignals>) -> Result<(), Error> {
+        let Some((webhook_url, http_client)) = &self.maybe_webhook else {
+            *self.pools.lock() = pools;
+            return Ok(());
+        };
+        let payload = serde_json::to_vec(&WebhookPayload {
+            timestamp_s: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            pools: &pools,
+        })
+        .map_err(|e| make_err!(Code::Internal, "Failed to encode autoscaling signals: {e}"))?;
+        *self.pools.lock() = pools;
+        let request = Request::post(webhook_url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(payload))
+            .map_err(|e| make_input_err!("Failed to build autoscaling webhook request: {e}"))?;
+        let response = http_client.send(request).await?;
+        if !response.status().is_success() {
+            return Err(make_err!(
+                Code::Unavailable,
+                "Autoscaling webhook {webhook_url} responded with status {}",
+                response.status()
+            ));
+        }
+        Ok(())
+    }
+}
@@ -22,8 +22,10 @@ use nativelink_metric::{
 use nativelink_util::action_messages::{
     ActionInfo, ActionStage, ActionState, OperationId, WorkerId,
 };
-use nativelink_util::origin_context::ActiveOriginContext;
+use nativelink_util::instance_access::active_identity;
+use nativelink_util::origin_context::{ActiveOriginContext, REQUEST_ID};
 use nativelink_util::origin_event::{OriginMetadata, ORIGIN_EVENT_COLLECTOR};
+use nativelink_util::telemetry::current_trace_context;
 use serde::{Deserialize, Serialize};
 use static_assertions::{assert_eq_size, const_assert, const_assert_eq};
 
@@ -72,6 +74,12 @@ pub struct AwaitedAction {
     #[metric(help = "The last time the client sent a keepalive message")]
     last_client_keepalive_timestamp: SystemTime,
 
+    /// The time the current worker started executing the action, None if
+    /// the action is not executing.
+    #[serde(default)]
+    #[metric(help = "The time the worker started executing the AwaitedAction")]
+    execution_started_timestamp: Option<SystemTime>,
+
     /// Worker that is currently running this action, None if unassigned.
     #[metric(help = "The worker id of the AwaitedAction")]
     worker_id: Option<WorkerId>,
@@ -109,6 +117,33 @@ impl AwaitedAction {
             .ok()
             .flatten()
             .map(|v| v.metadata.clone());
+        // The request id and trace context are kept even if origin events
+        // are disabled, so the logs and spans of the worker running the
+        // action can be correlated with the request. The identity is kept
+        // so the workers can be shared fairly between clients.
+        let request_id = ActiveOriginContext::get_value(&REQUEST_ID)
+            .ok()
+            .flatten()
+            .map(|request_id| request_id.as_ref().clone())
+            .unwrap_or_default();
+        let trace_context = current_trace_context();
+        let identity = active_identity();
+        let maybe_origin_metadata = match maybe_origin_metadata {
+            Some(origin_metadata) => Some(OriginMetadata {
+                request_id,
+                trace_context,
+                ..origin_metadata
+            }),
+            None if !identity.is_empty() || !request_id.is_empty() || !trace_context.is_empty() => {
+                Some(OriginMetadata {
+                    identity,
+                    request_id,
+                    trace_context,
+                    ..Default::default()
+                })
+            }
+            None => None,
+        };
 
         Self {
             version: AwaitedActionVersion(0),
@@ -118,6 +153,7 @@ impl AwaitedAction {
             attempts: 0,
             last_worker_updated_timestamp: now,
             last_client_keepalive_timestamp: now,
+            execution_started_timestamp: None,
             maybe_origin_metadata,
             worker_id: None,
             state,
@@ -148,6 +184,23 @@ impl AwaitedAction {
         self.sort_key
     }
 
+    pub(crate) fn set_sort_key(&mut self, sort_key: AwaitedActionSortKey) {
+        self.sort_key = sort_key;
+    }
+
+    /// Raises the priority of the action to `priority` if it is higher than
+    /// its current one. The position of the action among actions of the
+    /// same priority is kept. Returns true if the priority was changed.
+    pub(crate) fn upgrade_priority(&mut self, priority: i32) -> bool {
+        if priority <= self.action_info.priority {
+            return false;
+        }
+        Arc::make_mut(&mut self.action_info).priority = priority;
+        self.sort_key =
+            AwaitedActionSortKey::new_with_unique_key(priority, &self.action_info.insert_timestamp);
+        true
+    }
+
     pub fn state(&self) -> &Arc<ActionState> {
         &self.state
     }
@@ -164,6 +217,10 @@ impl AwaitedAction {
         self.last_worker_updated_timestamp
     }
 
+    pub(crate) fn execution_started_timestamp(&self) -> Option<SystemTime> {
+        self.execution_started_timestamp
+    }
+
     pub(crate) fn worker_keep_alive(&mut self, now: SystemTime) {
         self.last_worker_updated_timestamp = now;
     }
@@ -189,6 +246,11 @@ impl AwaitedAction {
 
     /// Sets the current state of the action and updates the last worker updated timestamp.
     pub fn worker_set_state(&mut self, mut state: Arc<ActionState>, now: SystemTime) {
+        if state.stage != ActionStage::Executing {
+            self.execution_started_timestamp = None;
+        } else if self.state.stage != ActionStage::Executing {
+            self.execution_started_timestamp = Some(now);
+        }
         std::mem::swap(&mut self.state, &mut state);
         self.worker_keep_alive(now);
     }
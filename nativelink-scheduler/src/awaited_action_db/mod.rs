@@ -17,10 +17,13 @@ use std::ops::Bound;
 use std::sync::Arc;
 
 pub use awaited_action::{AwaitedAction, AwaitedActionSortKey};
-use futures::{Future, Stream};
+use futures::{Future, Stream, TryStreamExt};
 use nativelink_error::{make_input_err, Error, ResultExt};
 use nativelink_metric::MetricsComponent;
-use nativelink_util::action_messages::{ActionInfo, ActionStage, OperationId};
+use nativelink_util::action_messages::{
+    ActionInfo, ActionStage, ActionUniqueKey, ActionUniqueQualifier, OperationId,
+};
+use nativelink_util::operation_state_manager::QueuedActionCounts;
 use serde::{Deserialize, Serialize};
 
 mod awaited_action;
@@ -183,4 +186,86 @@ pub trait AwaitedActionDb: Send + Sync + MetricsComponent + Unpin + 'static {
         client_operation_id: OperationId,
         action_info: Arc<ActionInfo>,
     ) -> impl Future<Output = Result<Self::Subscriber, Error>> + Send;
+
+    /// Detaches the client from its operation if other clients joined the
+    /// operation too, so it keeps running for them. Returns false if the
+    /// client is the only one waiting for the operation.
+    fn detach_client(
+        &self,
+        client_operation_id: &OperationId,
+    ) -> impl Future<Output = Result<bool, Error>> + Send;
+
+    /// Returns the number of queued actions of each set of platform
+    /// properties. This goes through all queued actions, so databases that
+    /// can should keep counts instead.
+    fn queued_action_counts(
+        &self,
+    ) -> impl Future<Output = Result<QueuedActionCounts, Error>> + Send {
+        async move {
+            let mut counts = QueuedActionCounts::new();
+            let stream = self
+                .get_range_of_actions(
+                    SortedAwaitedActionState::Queued,
+                    Bound::Unbounded,
+                    Bound::Unbounded,
+                    false,
+                )
+                .await
+                .err_tip(|| "In AwaitedActionDb::queued_action_counts")?;
+            tokio::pin!(stream);
+            while let Some(subscriber) = stream
+                .try_next()
+                .await
+                .err_tip(|| "In AwaitedActionDb::queued_action_counts")?
+            {
+                let awaited_action = subscriber
+                    .borrow()
+                    .await
+                    .err_tip(|| "In AwaitedActionDb::queued_action_counts")?;
+                let platform_properties = awaited_action
+                    .action_info()
+                    .platform_properties
+                    .clone()
+                    .into_iter()
+                    .collect();
+                *counts.entry(platform_properties).or_default() += 1;
+            }
+            Ok(counts)
+        }
+    }
+
+    /// Returns whether an operation of the action with `unique_key` is not
+    /// finished yet. This goes through all actions, so databases that can
+    /// should look the action up instead.
+    fn has_unfinished_action(
+        &self,
+        unique_key: &ActionUniqueKey,
+    ) -> impl Future<Output = Result<bool, Error>> + Send {
+        async move {
+            let stream = self
+                .get_all_awaited_actions()
+                .await
+                .err_tip(|| "In AwaitedActionDb::has_unfinished_action")?;
+            tokio::pin!(stream);
+            while let Some(subscriber) = stream
+                .try_next()
+                .await
+                .err_tip(|| "In AwaitedActionDb::has_unfinished_action")?
+            {
+                let awaited_action = subscriber
+                    .borrow()
+                    .await
+                    .err_tip(|| "In AwaitedActionDb::has_unfinished_action")?;
+                if !awaited_action.state().stage.is_finished()
+                    && matches!(
+                        &awaited_action.action_info().unique_qualifier,
+                        ActionUniqueQualifier::Cachable(key) if key == unique_key
+                    )
+                {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+    }
 }
@@ -16,7 +16,8 @@ use std::collections::HashMap;
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use nativelink_error::{make_err, Code, Error, ResultExt};
+use nativelink_config::schedulers::SchedulerSpec;
+use nativelink_error::{make_err, make_input_err, Code, Error, ResultExt};
 use nativelink_metric::{MetricsComponent, RootMetricsComponent};
 use nativelink_proto::build::bazel::remote::execution::v2::{
     ActionResult as ProtoActionResult, GetActionResultRequest,
@@ -355,6 +356,25 @@ impl ClientStateManager for CacheLookupScheduler {
         self.inner_filter_operations(filter).await
     }
 
+    async fn cancel_operation(&self, client_operation_id: &OperationId) -> Result<(), Error> {
+        self.action_scheduler
+            .cancel_operation(client_operation_id)
+            .await
+            .err_tip(|| "In CacheLookupScheduler::cancel_operation")
+    }
+
+    async fn reconfigure(&self, spec: &SchedulerSpec, check_only: bool) -> Result<(), Error> {
+        let SchedulerSpec::cache_lookup(spec) = spec else {
+            return Err(make_input_err!(
+                "CacheLookupScheduler can only be reconfigured with a cache_lookup spec"
+            ));
+        };
+        self.action_scheduler
+            .reconfigure(&spec.scheduler, check_only)
+            .await
+            .err_tip(|| "In CacheLookupScheduler::reconfigure")
+    }
+
     fn as_known_platform_property_provider(&self) -> Option<&dyn KnownPlatformPropertyProvider> {
         self.action_scheduler.as_known_platform_property_provider()
     }
@@ -13,7 +13,7 @@
 // limitations under the License.
 
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 use nativelink_config::schedulers::{
     ExperimentalSimpleSchedulerBackend, SchedulerSpec, SimpleSpec,
@@ -28,6 +28,7 @@ use nativelink_util::operation_state_manager::ClientStateManager;
 use tokio::sync::{mpsc, Notify};
 
 use crate::cache_lookup_scheduler::CacheLookupScheduler;
+use crate::federated_scheduler::FederatedScheduler;
 use crate::grpc_scheduler::GrpcScheduler;
 use crate::memory_awaited_action_db::MemoryAwaitedActionDb;
 use crate::property_modifier_scheduler::PropertyModifierScheduler;
@@ -82,9 +83,33 @@ fn inner_scheduler_factory(
             let property_modifier_scheduler = Arc::new(PropertyModifierScheduler::new(
                 spec,
                 action_scheduler.err_tip(|| "Nested scheduler is not an action scheduler")?,
-            ));
+            )?);
             (Some(property_modifier_scheduler), worker_scheduler)
         }
+        SchedulerSpec::federated(spec) => {
+            let (action_scheduler, worker_scheduler) =
+                inner_scheduler_factory(&spec.scheduler, store_manager, maybe_origin_event_tx)
+                    .err_tip(|| "In nested FederatedScheduler construction")?;
+            let remote_schedulers = spec
+                .remote_schedulers
+                .iter()
+                .map(|remote_spec| {
+                    let (remote_action_scheduler, _remote_worker_scheduler) =
+                        inner_scheduler_factory(remote_spec, store_manager, maybe_origin_event_tx)
+                            .err_tip(|| "In remote FederatedScheduler construction")?;
+                    remote_action_scheduler
+                        .err_tip(|| "Remote scheduler is not an action scheduler")
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            let maybe_forward_after_queued = (spec.forward_after_queued_s != 0)
+                .then(|| Duration::from_secs(spec.forward_after_queued_s));
+            let federated_scheduler = Arc::new(FederatedScheduler::new(
+                action_scheduler.err_tip(|| "Nested scheduler is not an action scheduler")?,
+                remote_schedulers,
+                maybe_forward_after_queued,
+            ));
+            (Some(federated_scheduler), worker_scheduler)
+        }
     };
 
     Ok(scheduler)
@@ -0,0 +1,135 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{HashMap, VecDeque};
+
+use nativelink_config::schedulers::{FairShareGroupBy, FairShareSpec};
+use nativelink_util::action_messages::ActionInfo;
+use nativelink_util::origin_event::OriginMetadata;
+
+/// Default weight of groups that are not configured.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_WEIGHT: u32 = 1;
+
+/// Groups actions and knows the share of the workers each group gets.
+#[derive(Debug)]
+pub struct FairShare {
+    group_by: FairShareGroupBy,
+    weights: HashMap<String, u32>,
+    default_weight: u32,
+}
+
+impl FairShare {
+    pub fn new(spec: &FairShareSpec) -> Self {
+        let mut default_weight = spec.default_weight;
+        if default_weight == 0 {
+            default_weight = DEFAULT_WEIGHT;
+        }
+        Self {
+            group_by: spec.group_by,
+            weights: spec.weights.clone(),
+            default_weight,
+        }
+    }
+
+    /// Returns the group the action is shared in.
+    pub fn group_of(
+        &self,
+        action_info: &ActionInfo,
+        maybe_origin_metadata: Option<&OriginMetadata>,
+    ) -> String {
+        match self.group_by {
+            FairShareGroupBy::identity => maybe_origin_metadata
+                .map(|origin_metadata| origin_metadata.identity.clone())
+                .unwrap_or_default(),
+            FairShareGroupBy::instance_name => action_info.instance_name().clone(),
+        }
+    }
+
+    fn weight(&self, group: &str) -> u64 {
+        u64::from(
+            self.weights
+                .get(group)
+                .copied()
+                .unwrap_or(self.default_weight)
+                .max(1),
+        )
+    }
+}
+
+/// The queued actions of all groups, in the order they should be offered to
+/// workers for the groups to keep their share.
+pub struct FairShareQueue<'a, T> {
+    fair_share: &'a FairShare,
+    /// Number of running actions of each group.
+    running: HashMap<String, usize>,
+    /// Queued actions of each group along with their position in the
+    /// priority order of all queued actions.
+    queued: HashMap<String, VecDeque<(usize, T)>>,
+    next_position: usize,
+}
+
+impl<'a, T> FairShareQueue<'a, T> {
+    pub fn new(fair_share: &'a FairShare, running: HashMap<String, usize>) -> Self {
+        Self {
+            fair_share,
+            running,
+            queued: HashMap::new(),
+            next_position: 0,
+        }
+    }
+
+    /// Adds a queued action. Actions must be pushed in priority order.
+    pub fn push(&mut self, group: String, item: T) {
+        let position = self.next_position;
+        self.next_position += 1;
+        self.queued
+            .entry(group)
+            .or_default()
+            .push_back((position, item));
+    }
+
+    /// Takes the next action of the group with the fewest running actions
+    /// for its weight. If groups are tied, the action with the higher
+    /// priority is taken.
+    pub fn pop(&mut self) -> Option<(String, T)> {
+        let (group, _) = self
+            .queued
+            .iter()
+            .filter_map(|(group, items)| {
+                let (position, _) = items.front()?;
+                let running = self.running.get(group).copied().unwrap_or_default() as u64;
+                Some((group, (running, self.fair_share.weight(group), *position)))
+            })
+            .min_by(
+                |(_, (running_a, weight_a, position_a)), (_, (running_b, weight_b, position_b))| {
+                    (running_a * weight_b)
+                        .cmp(&(running_b * weight_a))
+                        .then(position_a.cmp(position_b))
+                },
+            )?;
+        let group = group.clone();
+        let items = self.queued.get_mut(&group)?;
+        let (_, item) = items.pop_front()?;
+        if items.is_empty() {
+            self.queued.remove(&group);
+        }
+        Some((group, item))
+    }
+
+    /// Records that an action of `group` was dispatched to a worker.
+    pub fn record_dispatch(&mut self, group: &str) {
+        *self.running.entry(group.to_string()).or_default() += 1;
+    }
+}
@@ -0,0 +1,473 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use lru::LruCache;
+use nativelink_config::schedulers::SchedulerSpec;
+use nativelink_error::{make_input_err, Code, Error, ResultExt};
+use nativelink_metric::{MetricsComponent, RootMetricsComponent};
+use nativelink_util::action_messages::{
+    ActionInfo, ActionStage, ActionState, OperationId, WorkerId,
+};
+use nativelink_util::known_platform_property_provider::KnownPlatformPropertyProvider;
+use nativelink_util::operation_state_manager::{
+    ActionStateResult, ActionStateResultStream, ClientStateManager, OperationFilter,
+};
+use nativelink_util::origin_event::OriginMetadata;
+use parking_lot::Mutex;
+use tokio::select;
+use tokio::time::{sleep_until, Instant};
+use tracing::{event, Level};
+
+/// Number of forwarded operations remembered, so they can be found by
+/// `WaitExecution`. The oldest ones are forgotten first.
+const MAX_FORWARDED_OPERATIONS: usize = 10_000;
+
+/// An operation that runs on a remote scheduler.
+#[derive(Clone)]
+struct ForwardedOperation {
+    /// The index of the scheduler in `remote_schedulers`.
+    remote_index: usize,
+    /// The id of the operation on the remote scheduler.
+    remote_operation_id: OperationId,
+}
+
+#[derive(MetricsComponent)]
+struct Schedulers {
+    #[metric(group = "scheduler")]
+    local_scheduler: Arc<dyn ClientStateManager>,
+    #[metric(group = "remote_schedulers")]
+    remote_schedulers: Vec<Arc<dyn ClientStateManager>>,
+    /// The forwarded operations by their client operation id.
+    forwarded_operations: Mutex<LruCache<OperationId, ForwardedOperation>>,
+    #[metric(help = "Number of actions forwarded to remote schedulers")]
+    forwarded_actions: AtomicU64,
+}
+
+impl Schedulers {
+    /// Adds the action to the first remote scheduler that knows all of its
+    /// platform properties. Returns `None` if there is none.
+    async fn add_remote_action(
+        &self,
+        client_operation_id: &OperationId,
+        action_info: &Arc<ActionInfo>,
+    ) -> Result<Option<Box<dyn ActionStateResult>>, Error> {
+        for (remote_index, remote_scheduler) in self.remote_schedulers.iter().enumerate() {
+            match knows_properties(remote_scheduler.as_ref(), action_info).await {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(err) => {
+                    event!(
+                        Level::WARN,
+                        ?err,
+                        remote_index,
+                        "Could not get the known properties of a remote scheduler in FederatedScheduler"
+                    );
+                    continue;
+                }
+            }
+            let action_state_result = remote_scheduler
+                .add_action(client_operation_id.clone(), action_info.clone())
+                .await
+                .err_tip(|| "In FederatedScheduler::add_remote_action")?;
+            let (action_state, _maybe_origin_metadata) = action_state_result
+                .as_state()
+                .await
+                .err_tip(|| "In FederatedScheduler::add_remote_action")?;
+            self.forwarded_operations.lock().put(
+                client_operation_id.clone(),
+                ForwardedOperation {
+                    remote_index,
+                    remote_operation_id: action_state.client_operation_id.clone(),
+                },
+            );
+            self.forwarded_actions.fetch_add(1, Ordering::Relaxed);
+            return Ok(Some(action_state_result));
+        }
+        Ok(None)
+    }
+
+    fn get_forwarded_operation(
+        &self,
+        client_operation_id: &OperationId,
+    ) -> Option<ForwardedOperation> {
+        self.forwarded_operations
+            .lock()
+            .get(client_operation_id)
+            .cloned()
+    }
+}
+
+/// Returns if the scheduler knows all the platform properties of the action.
+async fn knows_properties(
+    scheduler: &dyn ClientStateManager,
+    action_info: &ActionInfo,
+) -> Result<bool, Error> {
+    if action_info.platform_properties.is_empty() {
+        return Ok(true);
+    }
+    let known_platform_property_provider = scheduler
+        .as_known_platform_property_provider()
+        .err_tip(|| {
+            "Scheduler does not implement KnownPlatformPropertyProvider for FederatedScheduler"
+        })?;
+    let known_properties = known_platform_property_provider
+        .get_known_properties(action_info.instance_name())
+        .await?;
+    Ok(action_info
+        .platform_properties
+        .keys()
+        .all(|name| known_properties.contains(name)))
+}
+
+const fn is_waiting_for_worker(stage: &ActionStage) -> bool {
+    matches!(stage, ActionStage::CacheCheck | ActionStage::Queued)
+}
+
+struct FederatedActionStateResult {
+    client_operation_id: OperationId,
+    schedulers: Arc<Schedulers>,
+    action_state_result: Box<dyn ActionStateResult>,
+    /// When the action is forwarded if it is still waiting for a local
+    /// worker. `None` if it is not forwarded anymore.
+    maybe_forward_at: Option<(Instant, Arc<ActionInfo>)>,
+    is_forwarded: bool,
+}
+
+impl FederatedActionStateResult {
+    fn with_client_operation_id(
+        &self,
+        (mut action_state, maybe_origin_metadata): (Arc<ActionState>, Option<OriginMetadata>),
+    ) -> (Arc<ActionState>, Option<OriginMetadata>) {
+        if self.is_forwarded {
+            // The remote scheduler has its own operation ids, but the client
+            // keeps using the one it was given.
+            Arc::make_mut(&mut action_state).client_operation_id = self.client_operation_id.clone();
+        }
+        (action_state, maybe_origin_metadata)
+    }
+
+    /// Forwards the action to a remote scheduler and cancels it on the local
+    /// one. Returns false if the action can not be forwarded.
+    async fn forward(&mut self, action_info: &Arc<ActionInfo>) -> Result<bool, Error> {
+        let (action_state, _maybe_origin_metadata) = self
+            .action_state_result
+            .as_state()
+            .await
+            .err_tip(|| "In FederatedActionStateResult::forward")?;
+        if !is_waiting_for_worker(&action_state.stage) {
+            return Ok(false);
+        }
+        let Some(action_state_result) = self
+            .schedulers
+            .add_remote_action(&self.client_operation_id, action_info)
+            .await?
+        else {
+            return Ok(false);
+        };
+        if let Err(err) = self
+            .schedulers
+            .local_scheduler
+            .cancel_operation(&self.client_operation_id)
+            .await
+        {
+            event!(
+                Level::WARN,
+                ?err,
+                client_operation_id = ?self.client_operation_id,
+                "Could not cancel forwarded action on the local scheduler"
+            );
+        }
+        self.action_state_result = action_state_result;
+        self.is_forwarded = true;
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl ActionStateResult for FederatedActionStateResult {
+    async fn as_state(&self) -> Result<(Arc<ActionState>, Option<OriginMetadata>), Error> {
+        let action_state = self.action_state_result.as_state().await?;
+        Ok(self.with_client_operation_id(action_state))
+    }
+
+    async fn changed(&mut self) -> Result<(Arc<ActionState>, Option<OriginMetadata>), Error> {
+        while let Some((forward_at, action_info)) = self.maybe_forward_at.clone() {
+            select! {
+                result = self.action_state_result.changed() => {
+                    let (action_state, maybe_origin_metadata) = result?;
+                    if !is_waiting_for_worker(&action_state.stage) {
+                        self.maybe_forward_at = None;
+                    }
+                    return Ok((action_state, maybe_origin_metadata));
+                }
+                () = sleep_until(forward_at) => {
+                    self.maybe_forward_at = None;
+                    match self.forward(&action_info).await {
+                        Ok(true) => return self.as_state().await,
+                        Ok(false) => {}
+                        Err(err) => event!(
+                            Level::WARN,
+                            ?err,
+                            client_operation_id = ?self.client_operation_id,
+                            "Could not forward action to a remote scheduler"
+                        ),
+                    }
+                }
+            }
+        }
+        let action_state = self.action_state_result.changed().await?;
+        Ok(self.with_client_operation_id(action_state))
+    }
+
+    async fn as_action_info(&self) -> Result<(Arc<ActionInfo>, Option<OriginMetadata>), Error> {
+        self.action_state_result.as_action_info().await
+    }
+
+    async fn as_worker_id(&self) -> Result<Option<WorkerId>, Error> {
+        self.action_state_result.as_worker_id().await
+    }
+}
+
+/// Sends actions to the local scheduler and forwards them to remote
+/// schedulers if the local scheduler does not know their platform
+/// properties, its queue is full or they wait too long for a local worker.
+#[derive(MetricsComponent)]
+pub struct FederatedScheduler {
+    #[metric]
+    schedulers: Arc<Schedulers>,
+    maybe_forward_after_queued: Option<Duration>,
+}
+
+impl FederatedScheduler {
+    pub fn new(
+        local_scheduler: Arc<dyn ClientStateManager>,
+        remote_schedulers: Vec<Arc<dyn ClientStateManager>>,
+        maybe_forward_after_queued: Option<Duration>,
+    ) -> Self {
+        Self {
+            schedulers: Arc::new(Schedulers {
+                local_scheduler,
+                remote_schedulers,
+                forwarded_operations: Mutex::new(LruCache::new(
+                    NonZeroUsize::new(MAX_FORWARDED_OPERATIONS).unwrap(),
+                )),
+                forwarded_actions: AtomicU64::new(0),
+            }),
+            maybe_forward_after_queued,
+        }
+    }
+
+    /// Returns the number of actions forwarded to remote schedulers.
+    pub fn forwarded_actions(&self) -> u64 {
+        self.schedulers.forwarded_actions.load(Ordering::Relaxed)
+    }
+
+    fn make_result(
+        &self,
+        client_operation_id: OperationId,
+        action_info: Arc<ActionInfo>,
+        action_state_result: Box<dyn ActionStateResult>,
+        is_forwarded: bool,
+    ) -> Box<dyn ActionStateResult> {
+        let maybe_forward_at = if is_forwarded || self.schedulers.remote_schedulers.is_empty() {
+            None
+        } else {
+            self.maybe_forward_after_queued
+                .map(|forward_after_queued| (Instant::now() + forward_after_queued, action_info))
+        };
+        Box::new(FederatedActionStateResult {
+            client_operation_id,
+            schedulers: self.schedulers.clone(),
+            action_state_result,
+            maybe_forward_at,
+            is_forwarded,
+        })
+    }
+
+    async fn inner_add_action(
+        &self,
+        client_operation_id: OperationId,
+        action_info: Arc<ActionInfo>,
+    ) -> Result<Box<dyn ActionStateResult>, Error> {
+        let is_known_locally =
+            knows_properties(self.schedulers.local_scheduler.as_ref(), &action_info)
+                .await
+                .err_tip(|| "In FederatedScheduler::add_action")?;
+        if !is_known_locally {
+            if let Some(action_state_result) = self
+                .schedulers
+                .add_remote_action(&client_operation_id, &action_info)
+                .await?
+            {
+                return Ok(self.make_result(
+                    client_operation_id,
+                    action_info,
+                    action_state_result,
+                    true,
+                ));
+            }
+        }
+        let result = self
+            .local_scheduler
+            .add_action(client_operation_id.clone(), action_info.clone())
+            .await;
+        match result {
+            Ok(action_state_result) => {
+                Ok(self.make_result(client_operation_id, action_info, action_state_result, false))
+            }
+            Err(err) if err.code == Code::ResourceExhausted => {
+                // The local queue is full, so burst to a remote scheduler.
+                let Some(action_state_result) = self
+                    .schedulers
+                    .add_remote_action(&client_operation_id, &action_info)
+                    .await?
+                else {
+                    return Err(err);
+                };
+                Ok(self.make_result(client_operation_id, action_info, action_state_result, true))
+            }
+            Err(err) => Err(err).err_tip(|| "In FederatedScheduler::add_action"),
+        }
+    }
+
+    async fn inner_filter_operations(
+        &self,
+        filter: OperationFilter,
+    ) -> Result<ActionStateResultStream, Error> {
+        let maybe_forwarded_operation =
+            filter
+                .client_operation_id
+                .as_ref()
+                .and_then(|client_operation_id| {
+                    self.schedulers
+                        .get_forwarded_operation(client_operation_id)
+                        .map(|forwarded_operation| {
+                            (client_operation_id.clone(), forwarded_operation)
+                        })
+                });
+        let Some((client_operation_id, forwarded_operation)) = maybe_forwarded_operation else {
+            return self
+                .schedulers
+                .local_scheduler
+                .filter_operations(filter)
+                .await;
+        };
+        let remote_filter = OperationFilter {
+            client_operation_id: Some(forwarded_operation.remote_operation_id),
+            ..Default::default()
+        };
+        let schedulers = self.schedulers.clone();
+        let stream = self.schedulers.remote_schedulers[forwarded_operation.remote_index]
+            .filter_operations(remote_filter)
+            .await
+            .err_tip(|| "In FederatedScheduler::filter_operations")?
+            .map(move |action_state_result| -> Box<dyn ActionStateResult> {
+                Box::new(FederatedActionStateResult {
+                    client_operation_id: client_operation_id.clone(),
+                    schedulers: schedulers.clone(),
+                    action_state_result,
+                    maybe_forward_at: None,
+                    is_forwarded: true,
+                })
+            });
+        Ok(Box::pin(stream))
+    }
+
+    async fn inner_cancel_operation(&self, client_operation_id: &OperationId) -> Result<(), Error> {
+        let Some(forwarded_operation) =
+            self.schedulers.get_forwarded_operation(client_operation_id)
+        else {
+            return self
+                .local_scheduler
+                .cancel_operation(client_operation_id)
+                .await;
+        };
+        self.schedulers.remote_schedulers[forwarded_operation.remote_index]
+            .cancel_operation(&forwarded_operation.remote_operation_id)
+            .await
+            .err_tip(|| "In FederatedScheduler::cancel_operation")
+    }
+}
+
+#[async_trait]
+impl KnownPlatformPropertyProvider for FederatedScheduler {
+    async fn get_known_properties(&self, instance_name: &str) -> Result<Vec<String>, Error> {
+        self.schedulers.local_scheduler
+            .as_known_platform_property_provider()
+            .err_tip(|| "Local scheduler does not implement KnownPlatformPropertyProvider for FederatedScheduler")?
+            .get_known_properties(instance_name)
+            .await
+    }
+}
+
+#[async_trait]
+impl ClientStateManager for FederatedScheduler {
+    async fn add_action(
+        &self,
+        client_operation_id: OperationId,
+        action_info: Arc<ActionInfo>,
+    ) -> Result<Box<dyn ActionStateResult>, Error> {
+        self.inner_add_action(client_operation_id, action_info)
+            .await
+    }
+
+    async fn filter_operations<'a>(
+        &'a self,
+        filter: OperationFilter,
+    ) -> Result<ActionStateResultStream<'a>, Error> {
+        self.inner_filter_operations(filter).await
+    }
+
+    async fn cancel_operation(&self, client_operation_id: &OperationId) -> Result<(), Error> {
+        self.inner_cancel_operation(client_operation_id).await
+    }
+
+    async fn reconfigure(&self, spec: &SchedulerSpec, check_only: bool) -> Result<(), Error> {
+        let SchedulerSpec::federated(spec) = spec else {
+            return Err(make_input_err!(
+                "FederatedScheduler can only be reconfigured with a federated spec"
+            ));
+        };
+        self.schedulers
+            .local_scheduler
+            .reconfigure(&spec.scheduler, check_only)
+            .await
+            .err_tip(|| "In FederatedScheduler::reconfigure")?;
+        for (remote_scheduler, remote_spec) in self
+            .schedulers
+            .remote_schedulers
+            .iter()
+            .zip(&spec.remote_schedulers)
+        {
+            remote_scheduler
+                .reconfigure(remote_spec, check_only)
+                .await
+                .err_tip(|| "In FederatedScheduler::reconfigure")?;
+        }
+        Ok(())
+    }
+
+    fn as_known_platform_property_provider(&self) -> Option<&dyn KnownPlatformPropertyProvider> {
+        Some(self)
+    }
+}
+
+impl RootMetricsComponent for FederatedScheduler {}
@@ -0,0 +1,213 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{HashMap, VecDeque};
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use lru::LruCache;
+use nativelink_config::schedulers::HedgingSpec;
+use nativelink_metric::MetricsComponent;
+use nativelink_util::action_messages::{ActionInfo, ActionUniqueQualifier, OperationId, WorkerId};
+use nativelink_util::common::DigestInfo;
+use parking_lot::Mutex;
+
+/// Default of how many times its p95 duration an action runs before it is
+/// hedged.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_P95_MULTIPLIER: f32 = 2.;
+
+/// Default number of past durations an action needs before it is hedged.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_MIN_SAMPLES: usize = 5;
+
+/// Default number of past durations kept for each action.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_MAX_SAMPLES: usize = 20;
+
+/// Default number of actions past durations are kept for.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_MAX_ACTIONS: usize = 10_000;
+
+/// Default of how often running actions are checked for stragglers.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_CHECK_INTERVAL_S: u64 = 10;
+
+/// A second copy of a straggling action.
+#[derive(Debug, Clone)]
+pub struct Hedge {
+    /// The digest of the hedged action.
+    pub action_digest: DigestInfo,
+    /// The worker running the original action.
+    pub worker_id: WorkerId,
+    /// The client operation id the copy was queued with.
+    pub hedge_operation_id: OperationId,
+    /// Whether the copy finished.
+    pub is_finished: bool,
+}
+
+/// Keeps how long actions took in the past and the copies started for the
+/// ones running far longer than that. The durations are kept per command,
+/// so they still apply when the inputs of an action change.
+#[derive(MetricsComponent)]
+pub struct Hedging {
+    p95_multiplier: f32,
+    #[metric(help = "The number of past durations an action needs before it is hedged")]
+    min_samples: usize,
+    max_samples: usize,
+    check_interval: Duration,
+    durations: Mutex<LruCache<DigestInfo, VecDeque<Duration>>>,
+    /// The copies of the running actions, by the operation id of the
+    /// original.
+    hedges: Mutex<HashMap<OperationId, Hedge>>,
+    #[metric(help = "The number of actions a second copy was started for")]
+    hedged_actions: AtomicU64,
+    #[metric(help = "The number of hedged actions the second copy finished first")]
+    won_hedges: AtomicU64,
+}
+
+impl Hedging {
+    pub fn new(spec: &HedgingSpec) -> Self {
+        let mut p95_multiplier = spec.p95_multiplier;
+        if p95_multiplier <= 0. {
+            p95_multiplier = DEFAULT_P95_MULTIPLIER;
+        }
+        let mut min_samples = spec.min_samples;
+        if min_samples == 0 {
+            min_samples = DEFAULT_MIN_SAMPLES;
+        }
+        let mut max_samples = spec.max_samples;
+        if max_samples == 0 {
+            max_samples = DEFAULT_MAX_SAMPLES;
+        }
+        let mut check_interval_s = spec.check_interval_s;
+        if check_interval_s == 0 {
+            check_interval_s = DEFAULT_CHECK_INTERVAL_S;
+        }
+        let mut max_actions = spec.max_actions;
+        if max_actions == 0 {
+            max_actions = DEFAULT_MAX_ACTIONS;
+        }
+        Self {
+            p95_multiplier,
+            min_samples,
+            max_samples: max_samples.max(min_samples),
+            check_interval: Duration::from_secs(check_interval_s),
+            durations: Mutex::new(LruCache::new(NonZeroUsize::new(max_actions).unwrap())),
+            hedges: Mutex::new(HashMap::new()),
+            hedged_actions: AtomicU64::new(0),
+            won_hedges: AtomicU64::new(0),
+        }
+    }
+
+    /// How often running actions are checked for stragglers.
+    pub const fn check_interval(&self) -> Duration {
+        self.check_interval
+    }
+
+    /// Remembers how long an action took to run successfully.
+    pub fn record_duration(&self, action_info: &ActionInfo, duration: Duration) {
+        let mut durations = self.durations.lock();
+        let durations = durations.get_or_insert_mut(action_info.command_digest, VecDeque::new);
+        if durations.len() >= self.max_samples {
+            durations.pop_front();
+        }
+        durations.push_back(duration);
+    }
+
+    /// Returns how long an action may run before it is hedged, or `None` if
+    /// it did not run often enough to tell.
+    pub fn hedge_after(&self, action_info: &ActionInfo) -> Option<Duration> {
+        let durations = self.durations.lock();
+        let durations = durations.peek(&action_info.command_digest)?;
+        if durations.len() < self.min_samples {
+            return None;
+        }
+        let mut sorted_durations: Vec<Duration> = durations.iter().copied().collect();
+        sorted_durations.sort_unstable();
+        // Nearest-rank percentile, so a few samples use the longest one.
+        let rank = (sorted_durations.len() * 95).div_ceil(100);
+        Some(sorted_durations[rank.saturating_sub(1)].mul_f32(self.p95_multiplier))
+    }
+
+    /// Returns whether a copy of the action is already running.
+    pub fn is_hedged(&self, action_digest: &DigestInfo) -> bool {
+        self.hedges
+            .lock()
+            .values()
+            .any(|hedge| hedge.action_digest == *action_digest)
+    }
+
+    /// Remembers the copy of the original operation.
+    pub fn add_hedge(&self, original_operation_id: OperationId, hedge: Hedge) {
+        self.hedges.lock().insert(original_operation_id, hedge);
+        self.hedged_actions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Forgets the copy of the original operation.
+    pub fn remove_hedge(&self, original_operation_id: &OperationId) -> Option<Hedge> {
+        self.hedges.lock().remove(original_operation_id)
+    }
+
+    /// Marks the copy of the original operation finished. Returns `false`
+    /// if there is no such copy anymore, so the original already stopped.
+    pub fn finish_hedge(&self, original_operation_id: &OperationId) -> bool {
+        let mut hedges = self.hedges.lock();
+        let Some(hedge) = hedges.get_mut(original_operation_id) else {
+            return false;
+        };
+        hedge.is_finished = true;
+        true
+    }
+
+    /// Counts a copy that finished before the original.
+    pub fn record_won_hedge(&self) {
+        self.won_hedges.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Forgets the copies of the originals `is_running` no longer holds for
+    /// and returns them.
+    pub fn remove_stopped_hedges(
+        &self,
+        is_running: impl Fn(&OperationId, &WorkerId) -> bool,
+    ) -> Vec<Hedge> {
+        let mut stopped_hedges = Vec::new();
+        self.hedges.lock().retain(|original_operation_id, hedge| {
+            if is_running(original_operation_id, &hedge.worker_id) {
+                return true;
+            }
+            stopped_hedges.push(hedge.clone());
+            false
+        });
+        stopped_hedges
+    }
+
+    /// Returns the worker running the original of an action if the action
+    /// is a copy of it, as the copy must run somewhere else.
+    pub fn excluded_worker(&self, action_info: &ActionInfo) -> Option<WorkerId> {
+        if !matches!(
+            action_info.unique_qualifier,
+            ActionUniqueQualifier::Uncachable(_)
+        ) {
+            return None;
+        }
+        let action_digest = action_info.digest();
+        self.hedges
+            .lock()
+            .values()
+            .find(|hedge| hedge.action_digest == action_digest)
+            .map(|hedge| hedge.worker_id)
+    }
+}
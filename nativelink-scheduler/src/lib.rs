@@ -13,15 +13,20 @@
 // limitations under the License.
 
 pub mod api_worker_scheduler;
+pub mod autoscaling;
 pub mod awaited_action_db;
 pub mod cache_lookup_scheduler;
 pub mod default_scheduler_factory;
+pub mod fair_share;
+pub mod federated_scheduler;
 pub mod grpc_scheduler;
+pub mod hedging;
 pub mod memory_awaited_action_db;
 pub mod platform_property_manager;
 pub mod property_modifier_scheduler;
 pub mod simple_scheduler;
 mod simple_scheduler_state_manager;
 pub mod store_awaited_action_db;
+pub mod sub_queues;
 pub mod worker;
 pub mod worker_scheduler;
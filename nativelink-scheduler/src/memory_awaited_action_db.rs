@@ -14,6 +14,7 @@
 
 use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::ops::{Bound, RangeBounds};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -28,6 +29,7 @@ use nativelink_util::action_messages::{
 use nativelink_util::chunked_stream::ChunkedStream;
 use nativelink_util::evicting_map::{EvictingMap, LenEntry};
 use nativelink_util::instant_wrapper::InstantWrapper;
+use nativelink_util::operation_state_manager::QueuedActionCounts;
 use nativelink_util::spawn;
 use nativelink_util::task::JoinHandleDropGuard;
 use tokio::sync::{mpsc, watch, Notify};
@@ -54,6 +56,10 @@ struct ClientAwaitedAction {
 
     /// The sender to notify of this struct being dropped.
     event_tx: mpsc::UnboundedSender<ActionEvent>,
+
+    /// Set if the client was detached from the operation, which already
+    /// stopped counting it as connected.
+    is_detached: AtomicBool,
 }
 
 impl ClientAwaitedAction {
@@ -61,6 +67,7 @@ impl ClientAwaitedAction {
         Self {
             operation_id,
             event_tx,
+            is_detached: AtomicBool::new(false),
         }
     }
 
@@ -71,6 +78,9 @@ impl ClientAwaitedAction {
 
 impl Drop for ClientAwaitedAction {
     fn drop(&mut self) {
+        if self.is_detached.load(Ordering::Acquire) {
+            return;
+        }
         // If we failed to send it means noone is listening.
         let _ = self.event_tx.send(ActionEvent::ClientDroppedOperation(
             self.operation_id.clone(),
@@ -230,9 +240,27 @@ struct SortedAwaitedActions {
     executing: BTreeSet<SortedAwaitedAction>,
     #[metric(group = "completed")]
     completed: BTreeSet<SortedAwaitedAction>,
+    /// The number of actions in `queued` of each set of platform
+    /// properties, which the scheduler checks its queue limits with.
+    queued_counts: QueuedActionCounts,
 }
 
 impl SortedAwaitedActions {
+    fn count_queued(&mut self, platform_properties: &HashMap<String, String>) {
+        let key = platform_properties.clone().into_iter().collect();
+        *self.queued_counts.entry(key).or_default() += 1;
+    }
+
+    fn uncount_queued(&mut self, platform_properties: &HashMap<String, String>) {
+        let key: BTreeMap<String, String> = platform_properties.clone().into_iter().collect();
+        if let Some(count) = self.queued_counts.get_mut(&key) {
+            *count -= 1;
+            if *count == 0 {
+                self.queued_counts.remove(&key);
+            }
+        }
+    }
+
     fn btree_for_state(&mut self, state: &ActionStage) -> &mut BTreeSet<SortedAwaitedAction> {
         match state {
             ActionStage::Unknown => &mut self.unknown,
@@ -289,8 +317,15 @@ impl SortedAwaitedActions {
             ));
         };
 
+        if old_awaited_action.state().stage == ActionStage::Queued {
+            self.uncount_queued(&old_awaited_action.action_info().platform_properties);
+        }
+
         self.insert_sort_map_for_stage(&new_awaited_action.state().stage, &sorted_awaited_action)
             .err_tip(|| "In AwaitedActionDb::update_awaited_action")?;
+        if new_awaited_action.state().stage == ActionStage::Queued {
+            self.count_queued(&new_awaited_action.action_info().platform_properties);
+        }
         Ok(())
     }
 }
@@ -449,6 +484,9 @@ impl<I: InstantWrapper, NowFn: Fn() -> I + Clone + Send + Sync> AwaitedActionDbI
                             ?sort_key,
                             "Expected maybe_sorted_awaited_action to have {sort_key:?}",
                         );
+                    } else if awaited_action.state().stage == ActionStage::Queued {
+                        self.sorted_action_info_hash_keys
+                            .uncount_queued(&awaited_action.action_info().platform_properties);
                     }
                 }
                 ActionEvent::ClientKeepAlive(client_id) => {
@@ -605,6 +643,9 @@ impl<I: InstantWrapper, NowFn: Fn() -> I + Clone + Send + Sync> AwaitedActionDbI
                 ));
             }
             new_awaited_action.increment_version();
+            // The priority of the action may have been upgraded since the
+            // update was based on it, so keep the sort key of the database.
+            new_awaited_action.set_sort_key(old_awaited_action.sort_key());
 
             error_if!(
                 old_awaited_action.action_info().unique_qualifier
@@ -685,6 +726,7 @@ impl<I: InstantWrapper, NowFn: Fn() -> I + Clone + Send + Sync> AwaitedActionDbI
             ActionUniqueQualifier::Uncachable(_unique_key) => None,
         };
         let operation_id = OperationId::default();
+        let platform_properties = action_info.platform_properties.clone();
         let awaited_action =
             AwaitedAction::new(operation_id.clone(), action_info, (self.now_fn)().now());
         debug_assert!(
@@ -732,6 +774,8 @@ impl<I: InstantWrapper, NowFn: Fn() -> I + Clone + Send + Sync> AwaitedActionDbI
                 },
             )
             .err_tip(|| "In AwaitedActionDb::subscribe_or_add_action")?;
+        self.sorted_action_info_hash_keys
+            .count_queued(&platform_properties);
 
         Ok(MemoryAwaitedActionSubscriber::new_with_client(
             rx,
@@ -741,14 +785,40 @@ impl<I: InstantWrapper, NowFn: Fn() -> I + Clone + Send + Sync> AwaitedActionDbI
         ))
     }
 
+    async fn detach_client(&mut self, client_operation_id: &OperationId) -> Result<bool, Error> {
+        let Some(client_awaited_action) = self
+            .client_operation_to_awaited_action
+            .get(client_operation_id)
+            .await
+        else {
+            return Ok(false);
+        };
+        let Some(connected_clients) = self
+            .connected_clients_for_operation_id
+            .get_mut(client_awaited_action.operation_id())
+        else {
+            return Ok(false);
+        };
+        if *connected_clients <= 1 {
+            return Ok(false);
+        }
+        // The client is not counted anymore right away, so if the other
+        // clients also cancel the operation, the last one cancels it.
+        *connected_clients -= 1;
+        client_awaited_action
+            .is_detached
+            .store(true, Ordering::Release);
+        self.client_operation_to_awaited_action
+            .remove(client_operation_id)
+            .await;
+        Ok(true)
+    }
+
     async fn try_subscribe(
         &mut self,
         client_operation_id: &OperationId,
         unique_qualifier: &ActionUniqueQualifier,
-        // TODO(allada) To simplify the scheduler 2024 refactor, we
-        // removed the ability to upgrade priorities of actions.
-        // we should add priority upgrades back in.
-        _priority: i32,
+        priority: i32,
     ) -> Result<Option<MemoryAwaitedActionSubscriber<I, NowFn>>, Error> {
         let unique_key = match unique_qualifier {
             ActionUniqueQualifier::Cachable(unique_key) => unique_key,
@@ -792,6 +862,36 @@ impl<I: InstantWrapper, NowFn: Fn() -> I + Clone + Send + Sync> AwaitedActionDbI
             awaited_action.update_client_keep_alive((self.now_fn)().now());
             false
         });
+        // If a client asks for a queued action with a higher priority,
+        // move the action up the queue, so it is not stuck behind the
+        // lower priority requests that asked for it before.
+        tx.send_if_modified(|awaited_action| {
+            if awaited_action.state().stage != ActionStage::Queued {
+                return false;
+            }
+            let old_sort_key = awaited_action.sort_key();
+            if !awaited_action.upgrade_priority(priority) {
+                return false;
+            }
+            let queued = &mut self.sorted_action_info_hash_keys.queued;
+            if queued.remove(&SortedAwaitedAction {
+                sort_key: old_sort_key,
+                operation_id: operation_id.clone(),
+            }) {
+                queued.insert(SortedAwaitedAction {
+                    sort_key: awaited_action.sort_key(),
+                    operation_id: operation_id.clone(),
+                });
+            } else {
+                event!(
+                    Level::ERROR,
+                    ?operation_id,
+                    ?old_sort_key,
+                    "Expected queued sorted_action_info_hash_keys to have {old_sort_key:?}",
+                );
+            }
+            false
+        });
         let subscription = tx.subscribe();
 
         self.client_operation_to_awaited_action
@@ -979,4 +1079,32 @@ impl<I: InstantWrapper, NowFn: Fn() -> I + Clone + Send + Sync + 'static> Awaite
         self.tasks_change_notify.notify_one();
         Ok(subscriber)
     }
+
+    async fn detach_client(&self, client_operation_id: &OperationId) -> Result<bool, Error> {
+        self.inner
+            .lock()
+            .await
+            .detach_client(client_operation_id)
+            .await
+    }
+
+    async fn queued_action_counts(&self) -> Result<QueuedActionCounts, Error> {
+        Ok(self
+            .inner
+            .lock()
+            .await
+            .sorted_action_info_hash_keys
+            .queued_counts
+            .clone())
+    }
+
+    async fn has_unfinished_action(&self, unique_key: &ActionUniqueKey) -> Result<bool, Error> {
+        // Finished actions are removed from the map.
+        Ok(self
+            .inner
+            .lock()
+            .await
+            .action_info_hash_key_to_awaited_action
+            .contains_key(unique_key))
+    }
 }
@@ -19,7 +19,9 @@ use nativelink_error::{make_input_err, Code, Error, ResultExt};
 use nativelink_metric::{
     group, MetricFieldData, MetricKind, MetricPublishKnownKindData, MetricsComponent,
 };
-use nativelink_util::platform_properties::{PlatformProperties, PlatformPropertyValue};
+use nativelink_util::platform_properties::{
+    PlatformProperties, PlatformPropertyOneOf, PlatformPropertyRange, PlatformPropertyValue,
+};
 
 /// Helps manage known properties and conversion into `PlatformPropertyValue`.
 pub struct PlatformPropertyManager {
@@ -87,6 +89,13 @@ impl PlatformPropertyManager {
                 )),
                 PropertyType::exact => Ok(PlatformPropertyValue::Exact(value.to_string())),
                 PropertyType::priority => Ok(PlatformPropertyValue::Priority(value.to_string())),
+                PropertyType::range => Ok(PlatformPropertyValue::Range(
+                    PlatformPropertyRange::parse(value)
+                        .err_tip(|| format!("For platform property '{key}'"))?,
+                )),
+                PropertyType::one_of => Ok(PlatformPropertyValue::OneOf(
+                    PlatformPropertyOneOf::parse(value),
+                )),
             };
         }
         Err(make_input_err!("Unknown platform property '{}'", key))
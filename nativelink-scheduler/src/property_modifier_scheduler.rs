@@ -16,8 +16,10 @@ use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use nativelink_config::schedulers::{PropertyModification, PropertyModifierSpec};
-use nativelink_error::{Error, ResultExt};
+use nativelink_config::schedulers::{
+    PlatformPropertyAddition, PropertyModification, PropertyModifierSpec, SchedulerSpec,
+};
+use nativelink_error::{make_input_err, Error, ResultExt};
 use nativelink_metric::{MetricsComponent, RootMetricsComponent};
 use nativelink_util::action_messages::{ActionInfo, OperationId};
 use nativelink_util::known_platform_property_provider::KnownPlatformPropertyProvider;
@@ -25,10 +27,110 @@ use nativelink_util::operation_state_manager::{
     ActionStateResult, ActionStateResultStream, ClientStateManager, OperationFilter,
 };
 use parking_lot::Mutex;
+use regex::Regex;
+
+/// A `PropertyModification` with its regexes compiled.
+enum Modification {
+    Add(PlatformPropertyAddition),
+    Remove(String),
+    Replace {
+        name: String,
+        pattern: Regex,
+        replacement: String,
+    },
+    Conditional {
+        name: String,
+        pattern: Regex,
+        modifications: Vec<Modification>,
+    },
+}
+
+impl Modification {
+    fn compile_all(modifications: &[PropertyModification]) -> Result<Vec<Self>, Error> {
+        modifications.iter().map(Self::compile).collect()
+    }
+
+    fn compile(modification: &PropertyModification) -> Result<Self, Error> {
+        let compile_pattern = |pattern: &str| {
+            Regex::new(pattern)
+                .map_err(|e| make_input_err!("Invalid property modifier pattern {pattern:?}: {e}"))
+        };
+        Ok(match modification {
+            PropertyModification::add(addition) => Self::Add(addition.clone()),
+            PropertyModification::remove(name) => Self::Remove(name.clone()),
+            PropertyModification::replace(replacement) => Self::Replace {
+                name: replacement.name.clone(),
+                pattern: compile_pattern(&replacement.pattern)?,
+                replacement: replacement.replacement.clone(),
+            },
+            PropertyModification::conditional(condition) => Self::Conditional {
+                name: condition.name.clone(),
+                pattern: compile_pattern(&condition.pattern)?,
+                modifications: Self::compile_all(&condition.modifications)?,
+            },
+        })
+    }
+
+    fn apply_all(modifications: &[Self], platform_properties: &mut HashMap<String, String>) {
+        for modification in modifications {
+            modification.apply(platform_properties);
+        }
+    }
+
+    fn apply(&self, platform_properties: &mut HashMap<String, String>) {
+        match self {
+            Self::Add(addition) => {
+                platform_properties.insert(addition.name.clone(), addition.value.clone());
+            }
+            Self::Remove(name) => {
+                platform_properties.remove(name);
+            }
+            Self::Replace {
+                name,
+                pattern,
+                replacement,
+            } => {
+                if let Some(value) = platform_properties.get_mut(name) {
+                    *value = pattern
+                        .replace_all(value, replacement.as_str())
+                        .into_owned();
+                }
+            }
+            Self::Conditional {
+                name,
+                pattern,
+                modifications,
+            } => {
+                let is_match = platform_properties
+                    .get(name)
+                    .is_some_and(|value| pattern.is_match(value));
+                if is_match {
+                    Self::apply_all(modifications, platform_properties);
+                }
+            }
+        }
+    }
+
+    /// Adds the properties removed by `modifications` to `known_properties`,
+    /// as clients may send them.
+    fn add_removed_properties(modifications: &[Self], known_properties: &mut HashSet<String>) {
+        for modification in modifications {
+            match modification {
+                Self::Remove(name) => {
+                    known_properties.insert(name.clone());
+                }
+                Self::Conditional { modifications, .. } => {
+                    Self::add_removed_properties(modifications, known_properties);
+                }
+                Self::Add(_) | Self::Replace { .. } => (),
+            }
+        }
+    }
+}
 
 #[derive(MetricsComponent)]
 pub struct PropertyModifierScheduler {
-    modifications: Vec<PropertyModification>,
+    modifications: Mutex<Arc<Vec<Modification>>>,
     #[metric(group = "scheduler")]
     scheduler: Arc<dyn ClientStateManager>,
     #[metric(group = "property_manager")]
@@ -36,12 +138,18 @@ pub struct PropertyModifierScheduler {
 }
 
 impl PropertyModifierScheduler {
-    pub fn new(spec: &PropertyModifierSpec, scheduler: Arc<dyn ClientStateManager>) -> Self {
-        Self {
-            modifications: spec.modifications.clone(),
+    pub fn new(
+        spec: &PropertyModifierSpec,
+        scheduler: Arc<dyn ClientStateManager>,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            modifications: Mutex::new(Arc::new(
+                Modification::compile_all(&spec.modifications)
+                    .err_tip(|| "In PropertyModifierScheduler::new")?,
+            )),
             scheduler,
             known_properties: Mutex::new(HashMap::new()),
-        }
+        })
     }
 
     async fn inner_get_known_properties(&self, instance_name: &str) -> Result<Vec<String>, Error> {
@@ -60,14 +168,8 @@ impl PropertyModifierScheduler {
                 .get_known_properties(instance_name)
                 .await?,
         );
-        for modification in &self.modifications {
-            match modification {
-                PropertyModification::remove(name) => {
-                    known_properties.insert(name.clone());
-                }
-                PropertyModification::add(_) => (),
-            }
-        }
+        let modifications = self.modifications.lock().clone();
+        Modification::add_removed_properties(&modifications, &mut known_properties);
         let final_known_properties: Vec<String> = known_properties.into_iter().collect();
         self.known_properties
             .lock()
@@ -82,16 +184,8 @@ impl PropertyModifierScheduler {
         mut action_info: Arc<ActionInfo>,
     ) -> Result<Box<dyn ActionStateResult>, Error> {
         let action_info_mut = Arc::make_mut(&mut action_info);
-        for modification in &self.modifications {
-            match modification {
-                PropertyModification::add(addition) => action_info_mut
-                    .platform_properties
-                    .insert(addition.name.clone(), addition.value.clone()),
-                PropertyModification::remove(name) => {
-                    action_info_mut.platform_properties.remove(name)
-                }
-            };
-        }
+        let modifications = self.modifications.lock().clone();
+        Modification::apply_all(&modifications, &mut action_info_mut.platform_properties);
         self.scheduler
             .add_action(client_operation_id, action_info)
             .await
@@ -130,6 +224,31 @@ impl ClientStateManager for PropertyModifierScheduler {
         self.inner_filter_operations(filter).await
     }
 
+    async fn cancel_operation(&self, client_operation_id: &OperationId) -> Result<(), Error> {
+        self.scheduler.cancel_operation(client_operation_id).await
+    }
+
+    async fn reconfigure(&self, spec: &SchedulerSpec, check_only: bool) -> Result<(), Error> {
+        let SchedulerSpec::property_modifier(spec) = spec else {
+            return Err(make_input_err!(
+                "PropertyModifierScheduler can only be reconfigured with a property_modifier spec"
+            ));
+        };
+        let modifications = Modification::compile_all(&spec.modifications)
+            .err_tip(|| "In PropertyModifierScheduler::reconfigure")?;
+        self.scheduler
+            .reconfigure(&spec.scheduler, check_only)
+            .await
+            .err_tip(|| "In PropertyModifierScheduler::reconfigure")?;
+        if !check_only {
+            *self.modifications.lock() = Arc::new(modifications);
+            // Known properties include the removed properties, so they have
+            // to be looked up again.
+            self.known_properties.lock().clear();
+        }
+        Ok(())
+    }
+
     fn as_known_platform_property_provider(&self) -> Option<&dyn KnownPlatformPropertyProvider> {
         Some(self)
     }
@@ -12,37 +12,48 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::SystemTime;
 
 use async_trait::async_trait;
 use futures::{Future, FutureExt};
-use nativelink_config::schedulers::SimpleSpec;
-use nativelink_error::{Code, Error, ResultExt};
+use nativelink_config::schedulers::{JobFailureKind, PriorityMappingSpec, SimpleSpec};
+use nativelink_error::{make_err, Code, Error, ResultExt};
 use nativelink_metric::{MetricsComponent, RootMetricsComponent};
 use nativelink_proto::com::github::trace_machina::nativelink::events::OriginEvent;
-use nativelink_util::action_messages::{ActionInfo, ActionState, OperationId, WorkerId};
+use nativelink_util::action_messages::{
+    ActionInfo, ActionStage, ActionState, ActionUniqueQualifier, OperationId, WorkerId,
+};
+use nativelink_util::common::DigestInfo;
+use nativelink_util::health_utils::HealthRegistryBuilder;
 use nativelink_util::instant_wrapper::InstantWrapper;
 use nativelink_util::known_platform_property_provider::KnownPlatformPropertyProvider;
 use nativelink_util::operation_state_manager::{
     ActionStateResult, ActionStateResultStream, ClientStateManager, MatchingEngineStateManager,
-    OperationFilter, OperationStageFlags, OrderDirection, UpdateOperationType,
+    OperationFilter, OperationStageFlags, OrderDirection, UpdateOperationType, WorkerStateManager,
 };
-use nativelink_util::origin_context::ActiveOriginContext;
+use nativelink_util::origin_context::{ActiveOriginContext, REQUEST_ID};
 use nativelink_util::origin_event::{OriginEventCollector, OriginMetadata, ORIGIN_EVENT_COLLECTOR};
-use nativelink_util::spawn;
 use nativelink_util::task::JoinHandleDropGuard;
+use nativelink_util::telemetry::set_trace_parent;
+use nativelink_util::{background_spawn, spawn};
 use tokio::sync::{mpsc, Notify};
 use tokio::time::Duration;
 use tokio_stream::StreamExt;
-use tracing::{event, info_span, Level};
+use tracing::{error_span, event, info_span, Instrument, Level};
 
-use crate::api_worker_scheduler::ApiWorkerScheduler;
+use crate::api_worker_scheduler::{ApiWorkerScheduler, RunningAction};
+use crate::autoscaling::{Autoscaling, PoolSignals};
 use crate::awaited_action_db::AwaitedActionDb;
+use crate::fair_share::{FairShare, FairShareQueue};
+use crate::hedging::{Hedge, Hedging};
 use crate::platform_property_manager::PlatformPropertyManager;
 use crate::simple_scheduler_state_manager::SimpleSchedulerStateManager;
+use crate::sub_queues::{SubQueue, SubQueues};
 use crate::worker::{ActionInfoWithProps, Worker, WorkerTimestamp};
-use crate::worker_scheduler::WorkerScheduler;
+use crate::worker_scheduler::{WorkerScheduler, WorkerStatus};
 
 /// Default timeout for workers in seconds.
 /// If this changes, remember to change the documentation in the config.
@@ -57,6 +68,48 @@ const DEFAULT_CLIENT_ACTION_TIMEOUT_S: u64 = 60;
 /// If this changes, remember to change the documentation in the config.
 const DEFAULT_MAX_JOB_RETRIES: usize = 3;
 
+/// Default kinds of job failures that are retried.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_RETRY_ON_FAILURES: [JobFailureKind; 3] = [
+    JobFailureKind::worker_lost,
+    JobFailureKind::infrastructure_error,
+    JobFailureKind::action_error,
+];
+
+/// Maps the priority requested by the client to the priority the action is
+/// queued with.
+fn map_priority(priority_mapping: &PriorityMappingSpec, priority: i32) -> i32 {
+    let mut priority = if priority_mapping.invert {
+        priority.saturating_neg()
+    } else {
+        priority
+    };
+    if let Some(min_priority) = priority_mapping.min_priority {
+        priority = priority.max(min_priority);
+    }
+    if let Some(max_priority) = priority_mapping.max_priority {
+        priority = priority.min(max_priority);
+    }
+    priority
+}
+
+/// Returns the priority an action queued with `priority` is dispatched with
+/// after it has been queued for `queued_for`.
+fn aged_priority(priority: i32, queued_for: Duration, priority_aging_interval: Duration) -> i32 {
+    if priority_aging_interval.is_zero() {
+        return priority;
+    }
+    let boost = queued_for.as_secs() / priority_aging_interval.as_secs().max(1);
+    priority.saturating_add(i32::try_from(boost).unwrap_or(i32::MAX))
+}
+
+/// A queued action along with its action info.
+struct QueuedAction {
+    action_state_result: Box<dyn ActionStateResult>,
+    action_info: Arc<ActionInfo>,
+    maybe_origin_metadata: Option<OriginMetadata>,
+}
+
 struct SimpleSchedulerActionStateResult {
     client_operation_id: OperationId,
     action_state_result: Box<dyn ActionStateResult>,
@@ -106,6 +159,13 @@ impl ActionStateResult for SimpleSchedulerActionStateResult {
             .await
             .err_tip(|| "In SimpleSchedulerActionStateResult")
     }
+
+    async fn as_worker_id(&self) -> Result<Option<WorkerId>, Error> {
+        self.action_state_result
+            .as_worker_id()
+            .await
+            .err_tip(|| "In SimpleSchedulerActionStateResult")
+    }
 }
 
 /// Engine used to manage the queued/running tasks and relationship with
@@ -121,6 +181,10 @@ pub struct SimpleScheduler {
     #[metric(group = "client_state_manager")]
     client_state_manager: Arc<dyn ClientStateManager>,
 
+    /// Manager for worker state of this scheduler, used to complete the
+    /// original of a hedged action with the result of its copy.
+    worker_state_manager: Arc<dyn WorkerStateManager>,
+
     /// Manager for platform of this scheduler.
     #[metric(group = "platform_properties")]
     platform_property_manager: Arc<PlatformPropertyManager>,
@@ -130,12 +194,69 @@ pub struct SimpleScheduler {
     #[metric(group = "worker_scheduler")]
     worker_scheduler: Arc<ApiWorkerScheduler>,
 
+    /// How client priorities are mapped to queue priorities.
+    priority_mapping: PriorityMappingSpec,
+
+    /// Queued actions are dispatched with a priority raised by one for
+    /// every interval they have been queued, or zero if they do not age.
+    priority_aging_interval: Duration,
+
+    /// How long the oldest queued action had been queued for the last time
+    /// the scheduler tried to match actions to workers.
+    #[metric(help = "The time in seconds the oldest queued action has been queued for")]
+    max_queue_age_s: AtomicU64,
+
+    /// Shares the workers between groups of clients if configured.
+    maybe_fair_share: Option<FairShare>,
+
+    /// Maximum number of queued actions, or 0 if unlimited.
+    max_queued_actions: usize,
+
+    /// Maximum number of queued actions with the same platform properties,
+    /// or 0 if unlimited.
+    max_queued_actions_per_pool: usize,
+
+    /// Splits the queued actions into sub-queues with their own matching
+    /// loops if configured.
+    #[metric(group = "sub_queues")]
+    maybe_sub_queues: Option<SubQueues>,
+
+    /// Timeout of actions that do not set one, or zero if the worker decides.
+    default_action_timeout: Duration,
+
+    /// Maximum timeout of actions, or zero if unlimited.
+    max_action_timeout: Duration,
+
+    /// Computes and exports the autoscaling signals of each pool if
+    /// configured.
+    #[metric(group = "autoscaling")]
+    maybe_autoscaling: Option<Arc<Autoscaling>>,
+
+    /// Starts copies of straggling actions on other workers if configured.
+    #[metric(group = "hedging")]
+    maybe_hedging: Option<Arc<Hedging>>,
+
     /// The sender to send origin events to the origin events.
     maybe_origin_event_tx: Option<mpsc::Sender<OriginEvent>>,
 
     /// Background task that tries to match actions to workers. If this struct
     /// is dropped the spawn will be cancelled as well.
     _task_worker_matching_spawn: JoinHandleDropGuard<()>,
+
+    /// Background task that queues actions again if their worker stopped
+    /// updating them and completes actions that exceeded their timeout. If
+    /// this struct is dropped the spawn will be cancelled as well.
+    _stale_operations_spawn: JoinHandleDropGuard<()>,
+
+    /// Background task that updates the autoscaling signals if autoscaling
+    /// is configured. If this struct is dropped the spawn will be cancelled
+    /// as well.
+    _autoscaling_spawn: Option<JoinHandleDropGuard<()>>,
+
+    /// Background task that hedges straggling actions if hedging is
+    /// configured. If this struct is dropped the spawn will be cancelled as
+    /// well.
+    _hedging_spawn: Option<JoinHandleDropGuard<()>>,
 }
 
 impl SimpleScheduler {
@@ -149,8 +270,19 @@ impl SimpleScheduler {
     async fn inner_add_action(
         &self,
         client_operation_id: OperationId,
-        action_info: Arc<ActionInfo>,
+        mut action_info: Arc<ActionInfo>,
     ) -> Result<Box<dyn ActionStateResult>, Error> {
+        let priority = map_priority(&self.priority_mapping, action_info.priority);
+        if priority != action_info.priority {
+            Arc::make_mut(&mut action_info).priority = priority;
+        }
+        let timeout = self.action_timeout(action_info.timeout);
+        if timeout != action_info.timeout {
+            Arc::make_mut(&mut action_info).timeout = timeout;
+        }
+        self.check_queue_limits(&action_info)
+            .await
+            .err_tip(|| "In SimpleScheduler::add_action")?;
         let action_state_result = self
             .client_state_manager
             .add_action(client_operation_id.clone(), action_info)
@@ -162,6 +294,108 @@ impl SimpleScheduler {
         )))
     }
 
+    /// Returns the timeout an action asking for `timeout` runs with.
+    fn action_timeout(&self, timeout: Duration) -> Duration {
+        let mut timeout = timeout;
+        if timeout.is_zero() {
+            timeout = self.default_action_timeout;
+        }
+        if timeout.is_zero() {
+            timeout = self.max_action_timeout;
+        }
+        if !self.max_action_timeout.is_zero() && timeout > self.max_action_timeout {
+            timeout = self.max_action_timeout;
+        }
+        timeout
+    }
+
+    /// Returns a `ResourceExhausted` error if `action_info` would be queued
+    /// while the queue, the pool of its platform properties or its
+    /// sub-queue is full. The queued actions are counted by the state
+    /// manager, so this does not go through the queue.
+    async fn check_queue_limits(&self, action_info: &ActionInfo) -> Result<(), Error> {
+        let maybe_sub_queue_limit = self
+            .maybe_sub_queues
+            .as_ref()
+            .filter(|sub_queues| sub_queues.max_queued_actions() != 0)
+            .map(|sub_queues| {
+                (
+                    sub_queues,
+                    sub_queues.queue_of(&action_info.platform_properties),
+                )
+            });
+        if self.max_queued_actions == 0
+            && self.max_queued_actions_per_pool == 0
+            && maybe_sub_queue_limit.is_none()
+        {
+            return Ok(());
+        }
+        let queued_action_counts = self
+            .client_state_manager
+            .queued_action_counts()
+            .await
+            .err_tip(|| "In SimpleScheduler::check_queue_limits")?;
+        let pool: BTreeMap<String, String> = action_info
+            .platform_properties
+            .clone()
+            .into_iter()
+            .collect();
+        let queued: usize = queued_action_counts.values().sum();
+        let queued_in_pool = queued_action_counts.get(&pool).copied().unwrap_or(0);
+        // Sub-queues are made of the pools whose sub-queue properties match.
+        let queued_in_sub_queue: usize =
+            maybe_sub_queue_limit
+                .as_ref()
+                .map_or(0, |(sub_queues, sub_queue_name)| {
+                    queued_action_counts
+                        .iter()
+                        .filter(|(platform_properties, _)| {
+                            let platform_properties: HashMap<String, String> =
+                                (*platform_properties).clone().into_iter().collect();
+                            sub_queues.queue_of(&platform_properties) == *sub_queue_name
+                        })
+                        .map(|(_, count)| count)
+                        .sum()
+                });
+        let is_queue_full = self.max_queued_actions != 0 && queued >= self.max_queued_actions;
+        let is_pool_full = self.max_queued_actions_per_pool != 0
+            && queued_in_pool >= self.max_queued_actions_per_pool;
+        let is_sub_queue_full = maybe_sub_queue_limit
+            .as_ref()
+            .is_some_and(|(sub_queues, _)| queued_in_sub_queue >= sub_queues.max_queued_actions());
+        if !is_queue_full && !is_pool_full && !is_sub_queue_full {
+            return Ok(());
+        }
+        if let ActionUniqueQualifier::Cachable(unique_key) = &action_info.unique_qualifier {
+            let has_unfinished_operation = self
+                .client_state_manager
+                .has_unfinished_operation(unique_key)
+                .await
+                .err_tip(|| "In SimpleScheduler::check_queue_limits")?;
+            if has_unfinished_operation {
+                // The action joins the queued or running one.
+                return Ok(());
+            }
+        }
+        if is_queue_full {
+            return Err(make_err!(
+                Code::ResourceExhausted,
+                "Scheduler queue is full with {queued} queued actions, try again later"
+            ));
+        }
+        if let (true, Some((_, sub_queue_name))) = (is_sub_queue_full, &maybe_sub_queue_limit) {
+            return Err(make_err!(
+                Code::ResourceExhausted,
+                "Scheduler sub-queue {sub_queue_name} is full with {queued_in_sub_queue} queued actions, try again later"
+            ));
+        }
+        Err(make_err!(
+            Code::ResourceExhausted,
+            "Scheduler queue is full with {queued_in_pool} queued actions with the platform properties {:?}, try again later",
+            action_info.platform_properties
+        ))
+    }
+
     async fn inner_filter_operations(
         &self,
         filter: OperationFilter,
@@ -172,6 +406,21 @@ impl SimpleScheduler {
             .err_tip(|| "In SimpleScheduler::find_by_client_operation_id getting filter result")
     }
 
+    async fn inner_cancel_operation(&self, client_operation_id: &OperationId) -> Result<(), Error> {
+        let maybe_running_on = self
+            .matching_engine_state_manager
+            .cancel_operation(client_operation_id)
+            .await
+            .err_tip(|| "In SimpleScheduler::cancel_operation")?;
+        let Some((operation_id, worker_id)) = maybe_running_on else {
+            return Ok(());
+        };
+        self.worker_scheduler
+            .kill_operation(&worker_id, &operation_id)
+            .await
+            .err_tip(|| "In SimpleScheduler::cancel_operation")
+    }
+
     async fn get_queued_operations(&self) -> Result<ActionStateResultStream, Error> {
         let filter = OperationFilter {
             stages: OperationStageFlags::Queued,
@@ -188,22 +437,63 @@ impl SimpleScheduler {
         self.do_try_match().await
     }
 
+    pub async fn do_try_match_sub_queue_for_test(&self, name: &str) -> Result<(), Error> {
+        self.do_try_match_queue(Some(name)).await
+    }
+
+    async fn do_try_match(&self) -> Result<(), Error> {
+        let Some(sub_queues) = &self.maybe_sub_queues else {
+            return self.do_try_match_queue(None).await;
+        };
+        // The matching loop of each sub-queue matches its actions, so this
+        // only adds the sub-queues of new actions and wakes all of them.
+        let mut result = Ok(());
+        let mut stream = self
+            .get_queued_operations()
+            .await
+            .err_tip(|| "Failed to get queued operations in do_try_match")?;
+        let mut names = HashSet::new();
+        let mut max_queue_age = Duration::ZERO;
+        while let Some(action_state_result) = stream.next().await {
+            match action_state_result.as_action_info().await {
+                Ok((action_info, _maybe_origin_metadata)) => {
+                    let queued_for = action_info.insert_timestamp.elapsed().unwrap_or_default();
+                    max_queue_age = max_queue_age.max(queued_for);
+                    names.insert(sub_queues.queue_of(&action_info.platform_properties));
+                }
+                Err(err) => {
+                    result =
+                        result.merge(Err(err).err_tip(|| {
+                            "Failed to get action_info from as_action_info_result stream"
+                        }));
+                }
+            }
+        }
+        self.max_queue_age_s
+            .store(max_queue_age.as_secs(), Ordering::Relaxed);
+        sub_queues.notify_all(names);
+        result
+    }
+
+    /// Matches the queued actions of the named sub-queue to workers, or all
+    /// queued actions if there is no name.
     // TODO(blaise.bruer) This is an O(n*m) (aka n^2) algorithm. In theory we
     // can create a map of capabilities of each worker and then try and match
     // the actions to the worker using the map lookup (ie. map reduce).
-    async fn do_try_match(&self) -> Result<(), Error> {
+    async fn do_try_match_queue(&self, maybe_sub_queue_name: Option<&str>) -> Result<(), Error> {
         async fn match_action_to_worker(
-            action_state_result: &dyn ActionStateResult,
+            queued_action: QueuedAction,
             workers: &ApiWorkerScheduler,
             matching_engine_state_manager: &dyn MatchingEngineStateManager,
             platform_property_manager: &PlatformPropertyManager,
+            maybe_hedging: Option<&Hedging>,
             maybe_origin_event_tx: Option<&mpsc::Sender<OriginEvent>>,
-        ) -> Result<(), Error> {
-            let (action_info, maybe_origin_metadata) =
-                action_state_result
-                    .as_action_info()
-                    .await
-                    .err_tip(|| "Failed to get action_info from as_action_info_result stream")?;
+        ) -> Result<bool, Error> {
+            let QueuedAction {
+                action_state_result,
+                action_info,
+                maybe_origin_metadata,
+            } = queued_action;
 
             // TODO(allada) We should not compute this every time and instead store
             // it with the ActionInfo when we receive it.
@@ -213,6 +503,10 @@ impl SimpleScheduler {
                     "Failed to make platform properties in SimpleScheduler::do_try_match"
                 })?;
 
+            // A copy of a straggler must not run on the same worker.
+            let maybe_excluded_worker_id =
+                maybe_hedging.and_then(|hedging| hedging.excluded_worker(&action_info));
+
             let action_info = ActionInfoWithProps {
                 inner: action_info,
                 platform_properties,
@@ -221,16 +515,40 @@ impl SimpleScheduler {
             // Try to find a worker for the action.
             let worker_id = {
                 match workers
-                    .find_worker_for_action(&action_info.platform_properties)
+                    .find_worker_for_action(
+                        &action_info.platform_properties,
+                        &action_info.inner.input_root_digest,
+                        maybe_excluded_worker_id.as_ref(),
+                    )
                     .await
                 {
                     Some(worker_id) => worker_id,
                     // If we could not find a worker for the action,
                     // we have nothing to do.
-                    None => return Ok(()),
+                    None => return Ok(false),
                 }
             };
 
+            let maybe_request_id = maybe_origin_metadata
+                .as_ref()
+                .map(|origin_metadata| origin_metadata.request_id.clone())
+                .filter(|request_id| !request_id.is_empty());
+            // Continue the trace of the client that queued the action, so it
+            // covers the worker running it too.
+            let dispatch_span = error_span!(
+                "dispatch_action",
+                ?worker_id,
+                request_id = maybe_request_id.as_deref().unwrap_or_default(),
+                queued_for = ?action_info
+                    .inner
+                    .insert_timestamp
+                    .elapsed()
+                    .unwrap_or_default(),
+            );
+            if let Some(origin_metadata) = &maybe_origin_metadata {
+                set_trace_parent(&dispatch_span, &origin_metadata.trace_context);
+            }
+
             let attach_operation_fut = async move {
                 // Extract the operation_id from the action_state.
                 let operation_id = {
@@ -250,7 +568,7 @@ impl SimpleScheduler {
                     if err.code == Code::Aborted {
                         // If the operation was aborted, it means that the operation was
                         // cancelled due to another operation being assigned to the worker.
-                        return Ok(());
+                        return Ok(false);
                     }
                     // Any other error is a real error.
                     return Err(err);
@@ -261,55 +579,445 @@ impl SimpleScheduler {
                     .await
                     .err_tip(|| {
                         "Failed to run worker_notify_run_action in SimpleScheduler::do_try_match"
-                    })
-            };
+                    })?;
+                Ok(true)
+            }
+            .instrument(dispatch_span);
             tokio::pin!(attach_operation_fut);
 
-            let attach_operation_fut = if let Some(origin_event_tx) = maybe_origin_event_tx {
-                let mut ctx = ActiveOriginContext::fork().unwrap_or_default();
-
-                // Populate our origin event collector with the origin metadata
-                // associated with the action.
-                ctx.replace_value(&ORIGIN_EVENT_COLLECTOR, move |maybe_old_collector| {
-                    let origin_metadata = maybe_origin_metadata.unwrap_or_default();
-                    let Some(old_collector) = maybe_old_collector else {
-                        return Some(Arc::new(OriginEventCollector::new(
-                            origin_event_tx.clone(),
-                            origin_metadata,
-                        )));
-                    };
-                    Some(Arc::new(old_collector.clone_with_metadata(origin_metadata)))
-                });
-                Arc::new(ctx)
-                    .wrap_async(info_span!("do_try_match"), attach_operation_fut)
-                    .left_future()
-            } else {
-                attach_operation_fut.right_future()
-            };
+            let attach_operation_fut =
+                if maybe_origin_event_tx.is_some() || maybe_request_id.is_some() {
+                    let mut ctx = ActiveOriginContext::fork().unwrap_or_default();
+
+                    // The worker is sent the id of the request that queued the
+                    // action, so it can be found in its logs.
+                    if let Some(request_id) = maybe_request_id {
+                        ctx.set_value(&REQUEST_ID, Arc::new(request_id));
+                    }
+                    // Populate our origin event collector with the origin metadata
+                    // associated with the action.
+                    if let Some(origin_event_tx) = maybe_origin_event_tx {
+                        ctx.replace_value(&ORIGIN_EVENT_COLLECTOR, move |maybe_old_collector| {
+                            let origin_metadata = maybe_origin_metadata.unwrap_or_default();
+                            let Some(old_collector) = maybe_old_collector else {
+                                return Some(Arc::new(OriginEventCollector::new(
+                                    origin_event_tx.clone(),
+                                    origin_metadata,
+                                )));
+                            };
+                            Some(Arc::new(old_collector.clone_with_metadata(origin_metadata)))
+                        });
+                    }
+                    Arc::new(ctx)
+                        .wrap_async(info_span!("do_try_match"), attach_operation_fut)
+                        .left_future()
+                } else {
+                    attach_operation_fut.right_future()
+                };
             attach_operation_fut.await
         }
 
         let mut result = Ok(());
 
+        let maybe_sub_queue = match (maybe_sub_queue_name, &self.maybe_sub_queues) {
+            (Some(name), Some(sub_queues)) => {
+                let Some(sub_queue) = sub_queues.get(name) else {
+                    return Ok(());
+                };
+                Some((name, sub_queues, sub_queue))
+            }
+            _ => None,
+        };
         let mut stream = self
             .get_queued_operations()
             .await
             .err_tip(|| "Failed to get queued operations in do_try_match")?;
-
+        let mut queued_actions = Vec::new();
+        let mut max_queue_age = Duration::ZERO;
         while let Some(action_state_result) = stream.next().await {
-            result = result.merge(
-                match_action_to_worker(
-                    action_state_result.as_ref(),
+            match action_state_result.as_action_info().await {
+                Ok((action_info, maybe_origin_metadata)) => {
+                    if let Some((name, sub_queues, _)) = &maybe_sub_queue {
+                        if sub_queues.queue_of(&action_info.platform_properties) != *name {
+                            continue;
+                        }
+                    }
+                    let queued_for = action_info.insert_timestamp.elapsed().unwrap_or_default();
+                    max_queue_age = max_queue_age.max(queued_for);
+                    queued_actions.push((
+                        aged_priority(
+                            action_info.priority,
+                            queued_for,
+                            self.priority_aging_interval,
+                        ),
+                        QueuedAction {
+                            action_state_result,
+                            action_info,
+                            maybe_origin_metadata,
+                        },
+                    ));
+                }
+                Err(err) => {
+                    result =
+                        result.merge(Err(err).err_tip(|| {
+                            "Failed to get action_info from as_action_info_result stream"
+                        }));
+                }
+            }
+        }
+        let maybe_sub_queue = maybe_sub_queue.map(|(_, _, sub_queue)| sub_queue);
+        match &maybe_sub_queue {
+            Some(sub_queue) => sub_queue.record_match(queued_actions.len() as u64, max_queue_age),
+            None => self
+                .max_queue_age_s
+                .store(max_queue_age.as_secs(), Ordering::Relaxed),
+        }
+        // The stream is in priority order, so the sort keeps actions with the
+        // same aged priority in that order.
+        if !self.priority_aging_interval.is_zero() {
+            queued_actions.sort_by_key(|(priority, _)| std::cmp::Reverse(*priority));
+        }
+        let queued_actions = queued_actions
+            .into_iter()
+            .map(|(_, queued_action)| queued_action);
+
+        let Some(fair_share) = &self.maybe_fair_share else {
+            for queued_action in queued_actions {
+                let match_result = match_action_to_worker(
+                    queued_action,
                     self.worker_scheduler.as_ref(),
                     self.matching_engine_state_manager.as_ref(),
                     self.platform_property_manager.as_ref(),
+                    self.maybe_hedging.as_deref(),
                     self.maybe_origin_event_tx.as_ref(),
                 )
-                .await,
+                .await;
+                if let (Ok(true), Some(sub_queue)) = (&match_result, &maybe_sub_queue) {
+                    sub_queue.record_dispatch();
+                }
+                result = result.merge(match_result.map(|_| ()));
+            }
+            return result;
+        };
+
+        let mut queue = FairShareQueue::new(
+            fair_share,
+            self.get_running_actions_per_group(fair_share)
+                .await
+                .err_tip(|| "Failed to count running actions in do_try_match")?,
+        );
+        for queued_action in queued_actions {
+            queue.push(
+                fair_share.group_of(
+                    &queued_action.action_info,
+                    queued_action.maybe_origin_metadata.as_ref(),
+                ),
+                queued_action,
             );
         }
+        while let Some((group, queued_action)) = queue.pop() {
+            let match_result = match_action_to_worker(
+                queued_action,
+                self.worker_scheduler.as_ref(),
+                self.matching_engine_state_manager.as_ref(),
+                self.platform_property_manager.as_ref(),
+                self.maybe_hedging.as_deref(),
+                self.maybe_origin_event_tx.as_ref(),
+            )
+            .await;
+            if let Ok(true) = match_result {
+                queue.record_dispatch(&group);
+                if let Some(sub_queue) = &maybe_sub_queue {
+                    sub_queue.record_dispatch();
+                }
+            }
+            result = result.merge(match_result.map(|_| ()));
+        }
+        result
+    }
+
+    /// Returns how long the oldest queued action had been queued for the
+    /// last time the scheduler tried to match actions to workers.
+    pub fn max_queue_age(&self) -> Duration {
+        Duration::from_secs(self.max_queue_age_s.load(Ordering::Relaxed))
+    }
+
+    /// Counts the running actions of each fair share group.
+    async fn get_running_actions_per_group(
+        &self,
+        fair_share: &FairShare,
+    ) -> Result<HashMap<String, usize>, Error> {
+        let filter = OperationFilter {
+            stages: OperationStageFlags::Executing,
+            ..Default::default()
+        };
+        let mut stream = self
+            .matching_engine_state_manager
+            .filter_operations(filter)
+            .await
+            .err_tip(|| "In SimpleScheduler::get_running_actions_per_group")?;
+        let mut running = HashMap::new();
+        while let Some(action_state_result) = stream.next().await {
+            let (action_info, maybe_origin_metadata) =
+                action_state_result
+                    .as_action_info()
+                    .await
+                    .err_tip(|| "In SimpleScheduler::get_running_actions_per_group")?;
+            *running
+                .entry(fair_share.group_of(&action_info, maybe_origin_metadata.as_ref()))
+                .or_default() += 1;
+        }
+        Ok(running)
+    }
+
+    /// Returns the names of the sub-queues, or none if there are no
+    /// sub-queues.
+    pub fn sub_queue_names(&self) -> Vec<String> {
+        self.maybe_sub_queues
+            .as_ref()
+            .map(SubQueues::names)
+            .unwrap_or_default()
+    }
+
+    /// Returns the sub-queue with this name if it was added.
+    pub fn sub_queue(&self, name: &str) -> Option<Arc<SubQueue>> {
+        self.maybe_sub_queues
+            .as_ref()
+            .and_then(|sub_queues| sub_queues.get(name))
+    }
+
+    /// Returns the autoscaling signals of each pool from their last update,
+    /// or none if autoscaling is not configured.
+    pub fn autoscaling_signals(&self) -> BTreeMap<String, PoolSignals> {
+        self.maybe_autoscaling
+            .as_ref()
+            .map(|autoscaling| autoscaling.pools())
+            .unwrap_or_default()
+    }
+
+    pub async fn update_autoscaling_signals_for_test(&self) -> Result<(), Error> {
+        self.update_autoscaling_signals().await
+    }
+
+    /// Counts the actions and workers of each pool and reports them to the
+    /// autoscaler.
+    async fn update_autoscaling_signals(&self) -> Result<(), Error> {
+        let Some(autoscaling) = &self.maybe_autoscaling else {
+            return Ok(());
+        };
+        let mut pools = BTreeMap::<String, PoolSignals>::new();
+        let mut stream = self
+            .get_queued_operations()
+            .await
+            .err_tip(|| "In SimpleScheduler::update_autoscaling_signals")?;
+        while let Some(action_state_result) = stream.next().await {
+            let (action_info, _maybe_origin_metadata) = action_state_result
+                .as_action_info()
+                .await
+                .err_tip(|| "In SimpleScheduler::update_autoscaling_signals")?;
+            let signals = pools
+                .entry(autoscaling.pool_of_action(&action_info.platform_properties))
+                .or_default();
+            signals.queued_actions += 1;
+            let queued_for = action_info.insert_timestamp.elapsed().unwrap_or_default();
+            signals.oldest_queued_action_age_s =
+                signals.oldest_queued_action_age_s.max(queued_for.as_secs());
+        }
+        let filter = OperationFilter {
+            stages: OperationStageFlags::Executing,
+            ..Default::default()
+        };
+        let mut stream = self
+            .matching_engine_state_manager
+            .filter_operations(filter)
+            .await
+            .err_tip(|| "In SimpleScheduler::update_autoscaling_signals")?;
+        while let Some(action_state_result) = stream.next().await {
+            let (action_info, _maybe_origin_metadata) = action_state_result
+                .as_action_info()
+                .await
+                .err_tip(|| "In SimpleScheduler::update_autoscaling_signals")?;
+            pools
+                .entry(autoscaling.pool_of_action(&action_info.platform_properties))
+                .or_default()
+                .executing_actions += 1;
+        }
+        self.worker_scheduler
+            .count_workers_per_pool(autoscaling, &mut pools)
+            .await;
+        for signals in pools.values_mut() {
+            signals.desired_workers = autoscaling.desired_workers(signals);
+        }
+        autoscaling
+            .report(pools)
+            .await
+            .err_tip(|| "In SimpleScheduler::update_autoscaling_signals")
+    }
+
+    pub async fn hedge_stragglers_for_test(self: &Arc<Self>) -> Result<(), Error> {
+        self.hedge_stragglers().await
+    }
+
+    /// Starts a copy of each action that has run far longer than it usually
+    /// takes, and cancels the copies of actions that are no longer running.
+    async fn hedge_stragglers(self: &Arc<Self>) -> Result<(), Error> {
+        let Some(hedging) = &self.maybe_hedging else {
+            return Ok(());
+        };
+        let running_actions = self.worker_scheduler.running_actions().await;
+        let mut result = Ok(());
+        let stopped_hedges = hedging.remove_stopped_hedges(|operation_id, worker_id| {
+            running_actions.iter().any(|running_action| {
+                running_action.operation_id == *operation_id
+                    && running_action.worker_id == *worker_id
+            })
+        });
+        for hedge in stopped_hedges {
+            if !hedge.is_finished {
+                result = result.merge(
+                    self.inner_cancel_operation(&hedge.hedge_operation_id)
+                        .await
+                        .err_tip(|| "In SimpleScheduler::hedge_stragglers"),
+                );
+            }
+        }
+        for running_action in running_actions {
+            if hedging.is_hedged(&running_action.action_info.digest()) {
+                continue;
+            }
+            let Some(hedge_after) = hedging.hedge_after(&running_action.action_info) else {
+                continue;
+            };
+            let running_for = running_action.start_timestamp.elapsed().unwrap_or_default();
+            if running_for <= hedge_after {
+                continue;
+            }
+            result = result.merge(self.start_hedge(hedging, running_action).await);
+        }
         result
     }
+
+    /// Queues a copy of the running action and completes the original with
+    /// the result of the copy if the copy finishes first.
+    async fn start_hedge(
+        self: &Arc<Self>,
+        hedging: &Hedging,
+        running_action: RunningAction,
+    ) -> Result<(), Error> {
+        let RunningAction {
+            worker_id,
+            operation_id,
+            action_info,
+            start_timestamp: _,
+        } = running_action;
+        let (ActionUniqueQualifier::Cachable(unique_key)
+        | ActionUniqueQualifier::Uncachable(unique_key)) = &action_info.unique_qualifier;
+        // The copy must not be joined with the original.
+        let hedge_action_info = Arc::new(ActionInfo {
+            unique_qualifier: ActionUniqueQualifier::Uncachable(unique_key.clone()),
+            ..action_info.as_ref().clone()
+        });
+        let hedge_operation_id = OperationId::default();
+        // The copy is added before it is queued, so it is never matched
+        // with the worker running the original.
+        hedging.add_hedge(
+            operation_id.clone(),
+            Hedge {
+                action_digest: action_info.digest(),
+                worker_id,
+                hedge_operation_id: hedge_operation_id.clone(),
+                is_finished: false,
+            },
+        );
+        let mut hedge_state_result = match self
+            .client_state_manager
+            .add_action(hedge_operation_id, hedge_action_info)
+            .await
+        {
+            Ok(hedge_state_result) => hedge_state_result,
+            Err(err) => {
+                hedging.remove_hedge(&operation_id);
+                return Err(err).err_tip(|| "In SimpleScheduler::start_hedge");
+            }
+        };
+        event!(
+            Level::INFO,
+            ?operation_id,
+            ?worker_id,
+            "Hedging straggling action on another worker"
+        );
+        let weak_self = Arc::downgrade(self);
+        background_spawn!("simple_scheduler_hedge", async move {
+            let hedge_state = loop {
+                match hedge_state_result.changed().await {
+                    Ok((action_state, _)) if action_state.stage.is_finished() => {
+                        break action_state;
+                    }
+                    Ok(_) => {}
+                    Err(err) => {
+                        event!(Level::WARN, ?err, "Failed to wait for hedged action");
+                        return;
+                    }
+                }
+            };
+            let Some(scheduler) = weak_self.upgrade() else {
+                // The scheduler is shutting down.
+                return;
+            };
+            if let Err(err) = scheduler
+                .finish_hedge(&operation_id, &worker_id, &hedge_state)
+                .await
+            {
+                event!(
+                    Level::WARN,
+                    ?err,
+                    ?operation_id,
+                    ?worker_id,
+                    "Failed to finish hedged action"
+                );
+            }
+        });
+        Ok(())
+    }
+
+    /// Completes the original of a hedged action and kills it if its copy
+    /// finished first and succeeded. A failed copy leaves the original
+    /// running.
+    async fn finish_hedge(
+        &self,
+        operation_id: &OperationId,
+        worker_id: &WorkerId,
+        hedge_state: &ActionState,
+    ) -> Result<(), Error> {
+        let Some(hedging) = &self.maybe_hedging else {
+            return Ok(());
+        };
+        if !hedging.finish_hedge(operation_id) {
+            // The original finished first.
+            return Ok(());
+        }
+        let ActionStage::Completed(action_result) = &hedge_state.stage else {
+            return Ok(());
+        };
+        if action_result.exit_code != 0 || action_result.error.is_some() {
+            return Ok(());
+        }
+        self.worker_state_manager
+            .update_operation(
+                operation_id,
+                worker_id,
+                UpdateOperationType::UpdateWithActionStage(ActionStage::Completed(
+                    action_result.clone(),
+                )),
+            )
+            .await
+            .err_tip(|| "In SimpleScheduler::finish_hedge")?;
+        hedging.record_won_hedge();
+        self.worker_scheduler
+            .kill_operation(worker_id, operation_id)
+            .await
+            .err_tip(|| "In SimpleScheduler::finish_hedge")
+    }
 }
 
 impl SimpleScheduler {
@@ -374,13 +1082,25 @@ impl SimpleScheduler {
             max_job_retries = DEFAULT_MAX_JOB_RETRIES;
         }
 
+        let mut retry_on_failures = spec.retry_on_failures.clone();
+        if retry_on_failures.is_empty() {
+            retry_on_failures = DEFAULT_RETRY_ON_FAILURES.to_vec();
+        }
+
+        let maybe_hedging = spec
+            .experimental_hedging
+            .as_ref()
+            .map(|hedging_spec| Arc::new(Hedging::new(hedging_spec)));
+        let hedging_now_fn = now_fn.clone();
+
         let worker_change_notify = Arc::new(Notify::new());
         let state_manager = SimpleSchedulerStateManager::new(
             max_job_retries,
+            retry_on_failures,
             Duration::from_secs(worker_timeout_s),
             Duration::from_secs(client_action_timeout_s),
             awaited_action_db,
-            now_fn,
+            now_fn.clone(),
         );
 
         let worker_scheduler = ApiWorkerScheduler::new(
@@ -389,8 +1109,64 @@ impl SimpleScheduler {
             spec.allocation_strategy,
             worker_change_notify.clone(),
             worker_timeout_s,
+            maybe_hedging.clone(),
         );
 
+        let maybe_autoscaling = spec
+            .autoscaling
+            .as_ref()
+            .map(|autoscaling_spec| Arc::new(Autoscaling::new(autoscaling_spec)));
+        let autoscaling_now_fn = now_fn.clone();
+
+        let weak_state_manager = Arc::downgrade(&state_manager);
+        let weak_worker_scheduler = Arc::downgrade(&worker_scheduler);
+        let stale_operations_spawn = spawn!("simple_scheduler_stale_operations", async move {
+            loop {
+                (now_fn)()
+                    .sleep(Duration::from_secs(worker_timeout_s))
+                    .await;
+                let (Some(state_manager), Some(worker_scheduler)) = (
+                    weak_state_manager.upgrade(),
+                    weak_worker_scheduler.upgrade(),
+                ) else {
+                    // The scheduler is shutting down.
+                    return;
+                };
+                // Actions that ran out of time are completed before stale
+                // ones are queued again, so they are not retried.
+                match state_manager.timeout_expired_operations().await {
+                    Ok(expired_operations) => {
+                        for (operation_id, worker_id) in expired_operations {
+                            if let Err(err) = worker_scheduler
+                                .kill_operation(&worker_id, &operation_id)
+                                .await
+                            {
+                                event!(
+                                    Level::WARN,
+                                    ?err,
+                                    ?operation_id,
+                                    ?worker_id,
+                                    "Failed to kill operation that exceeded its timeout"
+                                );
+                            }
+                        }
+                    }
+                    Err(err) => event!(
+                        Level::ERROR,
+                        ?err,
+                        "Error while timing out expired operations"
+                    ),
+                }
+                if let Err(err) = state_manager.timeout_stale_operations().await {
+                    event!(
+                        Level::ERROR,
+                        ?err,
+                        "Error while timing out stale operations"
+                    );
+                }
+            }
+        });
+
         let worker_scheduler_clone = worker_scheduler.clone();
 
         let action_scheduler = Arc::new_cyclic(move |weak_self| -> Self {
@@ -419,13 +1195,90 @@ impl SimpleScheduler {
                     }
                     // Unreachable.
                 });
+            let autoscaling_spawn = maybe_autoscaling.as_ref().map(|autoscaling| {
+                let weak_inner = weak_self.clone();
+                let interval = autoscaling.interval();
+                spawn!("simple_scheduler_autoscaling", async move {
+                    loop {
+                        (autoscaling_now_fn)().sleep(interval).await;
+                        let Some(scheduler) = weak_inner.upgrade() else {
+                            // The scheduler is shutting down.
+                            return;
+                        };
+                        if let Err(err) = scheduler.update_autoscaling_signals().await {
+                            event!(
+                                Level::WARN,
+                                ?err,
+                                "Error while updating autoscaling signals"
+                            );
+                        }
+                    }
+                })
+            });
+            let maybe_sub_queues = spec.sub_queues.as_ref().map(|sub_queues_spec| {
+                let weak_inner = weak_self.clone();
+                SubQueues::new(
+                    sub_queues_spec,
+                    Box::new(move |name: String, notify: Arc<Notify>| {
+                        let weak_inner = weak_inner.clone();
+                        spawn!("simple_scheduler_sub_queue_matching", async move {
+                            loop {
+                                notify.notified().await;
+                                let Some(scheduler) = weak_inner.upgrade() else {
+                                    // The scheduler is shutting down.
+                                    return;
+                                };
+                                if let Err(err) = scheduler.do_try_match_queue(Some(&name)).await {
+                                    event!(
+                                        Level::ERROR,
+                                        ?err,
+                                        sub_queue = %name,
+                                        "Error while running do_try_match for sub-queue"
+                                    );
+                                }
+                            }
+                        })
+                    }),
+                )
+            });
+            let hedging_spawn = maybe_hedging.as_ref().map(|hedging| {
+                let weak_inner = weak_self.clone();
+                let interval = hedging.check_interval();
+                spawn!("simple_scheduler_hedging", async move {
+                    loop {
+                        (hedging_now_fn)().sleep(interval).await;
+                        let Some(scheduler) = weak_inner.upgrade() else {
+                            // The scheduler is shutting down.
+                            return;
+                        };
+                        if let Err(err) = scheduler.hedge_stragglers().await {
+                            event!(Level::WARN, ?err, "Error while hedging stragglers");
+                        }
+                    }
+                })
+            });
             SimpleScheduler {
                 matching_engine_state_manager: state_manager.clone(),
                 client_state_manager: state_manager.clone(),
+                worker_state_manager: state_manager.clone(),
                 worker_scheduler,
                 platform_property_manager,
+                priority_mapping: spec.priority_mapping,
+                priority_aging_interval: Duration::from_secs(spec.priority_aging_interval_s),
+                max_queue_age_s: AtomicU64::new(0),
+                maybe_fair_share: spec.experimental_fair_share.as_ref().map(FairShare::new),
+                max_queued_actions: spec.max_queued_actions,
+                max_queued_actions_per_pool: spec.max_queued_actions_per_pool,
+                maybe_sub_queues,
+                default_action_timeout: Duration::from_secs(spec.default_action_timeout_s),
+                max_action_timeout: Duration::from_secs(spec.max_action_timeout_s),
+                maybe_autoscaling,
+                maybe_hedging,
                 maybe_origin_event_tx,
                 _task_worker_matching_spawn: task_worker_matching_spawn,
+                _stale_operations_spawn: stale_operations_spawn,
+                _autoscaling_spawn: autoscaling_spawn,
+                _hedging_spawn: hedging_spawn,
             }
         });
         (action_scheduler, worker_scheduler_clone)
@@ -450,6 +1303,10 @@ impl ClientStateManager for SimpleScheduler {
         self.inner_filter_operations(filter).await
     }
 
+    async fn cancel_operation(&self, client_operation_id: &OperationId) -> Result<(), Error> {
+        self.inner_cancel_operation(client_operation_id).await
+    }
+
     fn as_known_platform_property_provider(&self) -> Option<&dyn KnownPlatformPropertyProvider> {
         Some(self)
     }
@@ -499,6 +1356,16 @@ impl WorkerScheduler for SimpleScheduler {
             .await
     }
 
+    async fn set_worker_cached_input_roots(
+        &self,
+        worker_id: &WorkerId,
+        cached_input_roots: HashSet<DigestInfo>,
+    ) -> Result<(), Error> {
+        self.worker_scheduler
+            .set_worker_cached_input_roots(worker_id, cached_input_roots)
+            .await
+    }
+
     async fn remove_worker(&self, worker_id: &WorkerId) -> Result<(), Error> {
         self.worker_scheduler.remove_worker(worker_id).await
     }
@@ -514,6 +1381,18 @@ impl WorkerScheduler for SimpleScheduler {
             .set_drain_worker(worker_id, is_draining)
             .await
     }
+
+    async fn get_worker_status(&self, worker_id: &WorkerId) -> Result<WorkerStatus, Error> {
+        self.worker_scheduler.get_worker_status(worker_id).await
+    }
+
+    async fn set_dispatch_paused(&self, is_paused: bool) -> Result<(), Error> {
+        self.worker_scheduler.set_dispatch_paused(is_paused).await
+    }
+
+    fn register_health(self: Arc<Self>, registry: &mut HealthRegistryBuilder) {
+        self.worker_scheduler.clone().register_health(registry);
+    }
 }
 
 impl RootMetricsComponent for SimpleScheduler {}
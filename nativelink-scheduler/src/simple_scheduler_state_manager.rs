@@ -20,17 +20,19 @@ use std::time::{Duration, SystemTime};
 use async_lock::Mutex;
 use async_trait::async_trait;
 use futures::{stream, StreamExt, TryStreamExt};
+use nativelink_config::schedulers::JobFailureKind;
 use nativelink_error::{make_err, Code, Error, ResultExt};
 use nativelink_metric::MetricsComponent;
 use nativelink_util::action_messages::{
-    ActionInfo, ActionResult, ActionStage, ActionState, ActionUniqueQualifier, ExecutionMetadata,
-    OperationId, WorkerId,
+    ActionInfo, ActionResult, ActionStage, ActionState, ActionUniqueKey, ActionUniqueQualifier,
+    ExecutionMetadata, OperationId, WorkerId,
 };
 use nativelink_util::instant_wrapper::InstantWrapper;
 use nativelink_util::known_platform_property_provider::KnownPlatformPropertyProvider;
 use nativelink_util::operation_state_manager::{
     ActionStateResult, ActionStateResultStream, ClientStateManager, MatchingEngineStateManager,
-    OperationFilter, OperationStageFlags, OrderDirection, UpdateOperationType, WorkerStateManager,
+    OperationFilter, OperationStageFlags, OrderDirection, QueuedActionCounts, UpdateOperationType,
+    WorkerStateManager,
 };
 use nativelink_util::origin_event::OriginMetadata;
 use tracing::{event, Level};
@@ -43,6 +45,21 @@ use super::awaited_action_db::{
 /// can fail before giving up.
 const MAX_UPDATE_RETRIES: usize = 5;
 
+/// Classifies an error reported by a worker for a job.
+fn failure_kind_for_code(code: Code) -> JobFailureKind {
+    match code {
+        Code::Internal
+        | Code::Unavailable
+        | Code::Unknown
+        | Code::Aborted
+        | Code::Cancelled
+        | Code::DataLoss
+        | Code::DeadlineExceeded
+        | Code::ResourceExhausted => JobFailureKind::infrastructure_error,
+        _ => JobFailureKind::action_error,
+    }
+}
+
 /// Simple struct that implements the `ActionStateResult` trait and always returns an error.
 struct ErrorActionStateResult(Error);
 
@@ -114,6 +131,10 @@ where
     async fn as_action_info(&self) -> Result<(Arc<ActionInfo>, Option<OriginMetadata>), Error> {
         self.inner.as_action_info().await
     }
+
+    async fn as_worker_id(&self) -> Result<Option<WorkerId>, Error> {
+        self.inner.as_worker_id().await
+    }
 }
 
 struct MatchingEngineActionStateResult<U, T, I, NowFn>
@@ -239,6 +260,15 @@ where
             awaited_action.maybe_origin_metadata().cloned(),
         ))
     }
+
+    async fn as_worker_id(&self) -> Result<Option<WorkerId>, Error> {
+        let awaited_action = self
+            .awaited_action_sub
+            .borrow()
+            .await
+            .err_tip(|| "In MatchingEngineActionStateResult::as_worker_id")?;
+        Ok(awaited_action.worker_id())
+    }
 }
 
 /// `SimpleSchedulerStateManager` is responsible for maintaining the state of the scheduler.
@@ -262,6 +292,9 @@ where
     #[metric(help = "Maximum number of times a job can be retried")]
     max_job_retries: usize,
 
+    /// The kinds of failures of a job that are retried.
+    retry_on_failures: Vec<JobFailureKind>,
+
     /// Duration after which an action is considered to be timed out if
     /// no event is received.
     #[metric(
@@ -296,6 +329,7 @@ where
 {
     pub fn new(
         max_job_retries: usize,
+        retry_on_failures: Vec<JobFailureKind>,
         no_event_action_timeout: Duration,
         client_action_timeout: Duration,
         action_db: T,
@@ -304,6 +338,7 @@ where
         Arc::new_cyclic(|weak_self| Self {
             action_db,
             max_job_retries,
+            retry_on_failures,
             no_event_action_timeout,
             client_action_timeout,
             timeout_operation_mux: Mutex::new(()),
@@ -470,6 +505,154 @@ where
         .await
     }
 
+    /// Times out the executing operations whose worker has not updated them
+    /// within the worker timeout, so they are queued again. Clients waiting
+    /// for an operation do this on their own, but no client may be waiting
+    /// for the operations a scheduler recovers from its backend after a
+    /// restart, and their workers may never connect again.
+    pub(crate) async fn timeout_stale_operations(&self) -> Result<(), Error> {
+        let Some(worker_update_before) = (self.now_fn)()
+            .now()
+            .checked_sub(self.no_event_action_timeout)
+        else {
+            // No operation can be older than the timeout yet.
+            return Ok(());
+        };
+        let filter = OperationFilter {
+            stages: OperationStageFlags::Executing,
+            worker_update_before: Some(worker_update_before),
+            ..Default::default()
+        };
+        let stale_operation_ids: Vec<OperationId> =
+            MatchingEngineStateManager::filter_operations(self, filter)
+                .await
+                .err_tip(|| "In SimpleSchedulerStateManager::timeout_stale_operations")?
+                .then(|action_state_result| async move {
+                    action_state_result
+                        .as_state()
+                        .await
+                        .map(|(action_state, _origin_metadata)| {
+                            action_state.client_operation_id.clone()
+                        })
+                })
+                .try_collect()
+                .await
+                .err_tip(|| "In SimpleSchedulerStateManager::timeout_stale_operations")?;
+
+        let mut result = Ok(());
+        for operation_id in &stale_operation_ids {
+            event!(
+                Level::WARN,
+                ?operation_id,
+                "Worker of operation did not update it in {} seconds, issuing a retry",
+                self.no_event_action_timeout.as_secs_f32(),
+            );
+            result = result.merge(
+                self.timeout_operation_id(operation_id)
+                    .await
+                    .err_tip(|| "In SimpleSchedulerStateManager::timeout_stale_operations"),
+            );
+        }
+        result
+    }
+
+    /// Completes the executing operations that ran for longer than their
+    /// timeout plus the worker timeout with a `DeadlineExceeded` error, so
+    /// clients are not left waiting on a worker that stopped enforcing it.
+    /// Returns the operations along with the worker that was running them.
+    pub(crate) async fn timeout_expired_operations(
+        &self,
+    ) -> Result<Vec<(OperationId, WorkerId)>, Error> {
+        let now = (self.now_fn)().now();
+        let expired_operations: Vec<(OperationId, WorkerId, Duration)> = self
+            .action_db
+            .get_range_of_actions(
+                SortedAwaitedActionState::Executing,
+                Bound::Unbounded,
+                Bound::Unbounded,
+                false,
+            )
+            .await
+            .err_tip(|| "In SimpleSchedulerStateManager::timeout_expired_operations")?
+            .and_then(|awaited_action_subscriber| async move {
+                awaited_action_subscriber
+                    .borrow()
+                    .await
+                    .err_tip(|| "In SimpleSchedulerStateManager::timeout_expired_operations")
+            })
+            .try_filter_map(|awaited_action| async move {
+                let timeout = awaited_action.action_info().timeout;
+                let (Some(started), Some(worker_id)) = (
+                    awaited_action.execution_started_timestamp(),
+                    awaited_action.worker_id(),
+                ) else {
+                    return Ok(None);
+                };
+                if timeout.is_zero()
+                    || !matches!(awaited_action.state().stage, ActionStage::Executing)
+                {
+                    return Ok(None);
+                }
+                let expired = started
+                    .checked_add(timeout)
+                    .and_then(|deadline| deadline.checked_add(self.no_event_action_timeout))
+                    .is_some_and(|deadline| deadline < now);
+                if !expired {
+                    return Ok(None);
+                }
+                Ok(Some((
+                    awaited_action.operation_id().clone(),
+                    worker_id,
+                    timeout,
+                )))
+            })
+            .try_collect()
+            .await
+            .err_tip(|| "In SimpleSchedulerStateManager::timeout_expired_operations")?;
+
+        let mut timed_out_operations = Vec::with_capacity(expired_operations.len());
+        for (operation_id, worker_id, timeout) in expired_operations {
+            event!(
+                Level::WARN,
+                ?operation_id,
+                ?worker_id,
+                "Operation exceeded its timeout of {} seconds, completing it",
+                timeout.as_secs_f32(),
+            );
+            let update_result = self
+                .inner_update_operation(
+                    &operation_id,
+                    Some(&worker_id),
+                    UpdateOperationType::UpdateWithActionStage(ActionStage::Completed(
+                        ActionResult {
+                            execution_metadata: ExecutionMetadata {
+                                worker: worker_id.to_string(),
+                                ..ExecutionMetadata::default()
+                            },
+                            error: Some(make_err!(
+                                Code::DeadlineExceeded,
+                                "Operation exceeded its timeout of {} seconds",
+                                timeout.as_secs_f32(),
+                            )),
+                            ..ActionResult::default()
+                        },
+                    )),
+                )
+                .await;
+            match update_result {
+                Ok(()) => timed_out_operations.push((operation_id, worker_id)),
+                // The worker may have finished the operation in the meantime.
+                Err(err) => event!(
+                    Level::WARN,
+                    ?operation_id,
+                    ?err,
+                    "Failed to complete operation that exceeded its timeout"
+                ),
+            }
+        }
+        Ok(timed_out_operations)
+    }
+
     async fn inner_update_operation(
         &self,
         operation_id: &OperationId,
@@ -541,31 +724,61 @@ where
                         .await
                         .err_tip(|| "Failed to send KeepAlive in SimpleSchedulerStateManager::update_operation");
                 }
-                UpdateOperationType::UpdateWithActionStage(stage) => stage.clone(),
-                UpdateOperationType::UpdateWithError(err) => {
+                UpdateOperationType::UpdateWithActionStage(stage) => {
+                    let mut stage = stage.clone();
+                    if let ActionStage::Completed(action_result) = &mut stage {
+                        action_result.execution_metadata.retries =
+                            u32::try_from(awaited_action.attempts).unwrap_or(u32::MAX);
+                    }
+                    stage
+                }
+                UpdateOperationType::UpdateWithError(err)
+                | UpdateOperationType::UpdateWithWorkerLost(err) => {
                     // Don't count a backpressure failure as an attempt for an action.
                     let due_to_backpressure = err.code == Code::ResourceExhausted;
                     if !due_to_backpressure {
                         awaited_action.attempts += 1;
                     }
+                    let failure_kind =
+                        if matches!(update, UpdateOperationType::UpdateWithWorkerLost(_)) {
+                            JobFailureKind::worker_lost
+                        } else {
+                            failure_kind_for_code(err.code)
+                        };
+
+                    let maybe_final_error = if due_to_backpressure {
+                        None
+                    } else if !self.retry_on_failures.contains(&failure_kind) {
+                        Some(err.clone().merge(make_err!(
+                            Code::Internal,
+                            "Job failed with a {failure_kind:?} failure that is not retried {}",
+                            format!("for operation_id: {operation_id}, maybe_worker_id: {maybe_worker_id:?}"),
+                        )))
+                    } else if awaited_action.attempts > self.max_job_retries {
+                        Some(err.clone().merge(make_err!(
+                            Code::Internal,
+                            "Job cancelled because it attempted to execute too many times {} > {} times {}",
+                            awaited_action.attempts,
+                            self.max_job_retries,
+                            format!("for operation_id: {operation_id}, maybe_worker_id: {maybe_worker_id:?}"),
+                        )))
+                    } else {
+                        None
+                    };
 
-                    if awaited_action.attempts > self.max_job_retries {
-                        ActionStage::Completed(ActionResult {
+                    match maybe_final_error {
+                        Some(error) => ActionStage::Completed(ActionResult {
                             execution_metadata: ExecutionMetadata {
-                                worker: maybe_worker_id.map_or_else(String::default, ToString::to_string),
+                                worker: maybe_worker_id
+                                    .map_or_else(String::default, ToString::to_string),
+                                retries: u32::try_from(awaited_action.attempts - 1)
+                                    .unwrap_or(u32::MAX),
                                 ..ExecutionMetadata::default()
                             },
-                            error: Some(err.clone().merge(make_err!(
-                                Code::Internal,
-                                "Job cancelled because it attempted to execute too many times {} > {} times {}",
-                                awaited_action.attempts,
-                                self.max_job_retries,
-                                format!("for operation_id: {operation_id}, maybe_worker_id: {maybe_worker_id:?}"),
-                            ))),
+                            error: Some(error),
                             ..ActionResult::default()
-                        })
-                    } else {
-                        ActionStage::Queued
+                        }),
+                        None => ActionStage::Queued,
                     }
                 }
             };
@@ -616,6 +829,79 @@ where
         }
     }
 
+    async fn inner_cancel_operation(
+        &self,
+        client_operation_id: &OperationId,
+    ) -> Result<Option<(OperationId, WorkerId)>, Error> {
+        // Listing operations reports them by their operation id, so we
+        // accept both here.
+        let (maybe_awaited_action_subscriber, is_client_operation_id) = match self
+            .action_db
+            .get_awaited_action_by_id(client_operation_id)
+            .await
+            .err_tip(|| "In SimpleSchedulerStateManager::cancel_operation")?
+        {
+            Some(awaited_action_subscriber) => (Some(awaited_action_subscriber), true),
+            None => (
+                self.action_db
+                    .get_by_operation_id(client_operation_id)
+                    .await
+                    .err_tip(|| "In SimpleSchedulerStateManager::cancel_operation")?,
+                false,
+            ),
+        };
+        let awaited_action = maybe_awaited_action_subscriber
+            .ok_or_else(|| {
+                make_err!(
+                    Code::NotFound,
+                    "Operation {client_operation_id} does not exist in SimpleSchedulerStateManager::cancel_operation"
+                )
+            })?
+            .borrow()
+            .await
+            .err_tip(|| "In SimpleSchedulerStateManager::cancel_operation")?;
+
+        // Nothing to cancel if the operation already finished.
+        if awaited_action.state().stage.is_finished() {
+            return Ok(None);
+        }
+
+        // Other clients may have joined the operation, in which case only
+        // the client that cancelled it stops waiting for it.
+        if is_client_operation_id
+            && self
+                .action_db
+                .detach_client(client_operation_id)
+                .await
+                .err_tip(|| "In SimpleSchedulerStateManager::cancel_operation")?
+        {
+            return Ok(None);
+        }
+
+        let operation_id = awaited_action.operation_id().clone();
+        let maybe_worker_id = awaited_action.worker_id();
+        self.inner_update_operation(
+            &operation_id,
+            maybe_worker_id.as_ref(),
+            UpdateOperationType::UpdateWithActionStage(ActionStage::Completed(ActionResult {
+                execution_metadata: ExecutionMetadata {
+                    worker: maybe_worker_id
+                        .as_ref()
+                        .map_or_else(String::default, ToString::to_string),
+                    ..ExecutionMetadata::default()
+                },
+                error: Some(make_err!(
+                    Code::Cancelled,
+                    "Operation {client_operation_id} was cancelled"
+                )),
+                ..ActionResult::default()
+            })),
+        )
+        .await
+        .err_tip(|| "In SimpleSchedulerStateManager::cancel_operation")?;
+        Ok(maybe_worker_id.map(|worker_id| (operation_id, worker_id)))
+    }
+
     async fn inner_add_operation(
         &self,
         new_client_operation_id: OperationId,
@@ -808,6 +1094,20 @@ where
         .await
     }
 
+    async fn queued_action_counts(&self) -> Result<QueuedActionCounts, Error> {
+        self.action_db
+            .queued_action_counts()
+            .await
+            .err_tip(|| "In SimpleSchedulerStateManager::queued_action_counts")
+    }
+
+    async fn has_unfinished_operation(&self, unique_key: &ActionUniqueKey) -> Result<bool, Error> {
+        self.action_db
+            .has_unfinished_action(unique_key)
+            .await
+            .err_tip(|| "In SimpleSchedulerStateManager::has_unfinished_operation")
+    }
+
     fn as_known_platform_property_provider(&self) -> Option<&dyn KnownPlatformPropertyProvider> {
         None
     }
@@ -863,9 +1163,16 @@ where
                 Some(worker_id),
                 UpdateOperationType::UpdateWithActionStage(ActionStage::Executing),
             ),
-            Err(err) => (None, UpdateOperationType::UpdateWithError(err)),
+            Err(err) => (None, UpdateOperationType::UpdateWithWorkerLost(err)),
         };
         self.inner_update_operation(operation_id, maybe_worker_id, update)
             .await
     }
+
+    async fn cancel_operation(
+        &self,
+        client_operation_id: &OperationId,
+    ) -> Result<Option<(OperationId, WorkerId)>, Error> {
+        self.inner_cancel_operation(client_operation_id).await
+    }
 }
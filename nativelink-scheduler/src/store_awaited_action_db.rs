@@ -23,7 +23,7 @@ use futures::{Stream, TryStreamExt};
 use nativelink_error::{make_err, make_input_err, Code, Error, ResultExt};
 use nativelink_metric::MetricsComponent;
 use nativelink_util::action_messages::{
-    ActionInfo, ActionStage, ActionUniqueQualifier, OperationId,
+    ActionInfo, ActionStage, ActionUniqueKey, ActionUniqueQualifier, OperationId,
 };
 use nativelink_util::instant_wrapper::InstantWrapper;
 use nativelink_util::spawn;
@@ -511,6 +511,15 @@ where
                     );
                     return Ok(None);
                 }
+                // The client has to find the operation it joined, like
+                // clients that add a new one.
+                self.store
+                    .update_data(UpdateClientIdToOperationId {
+                        client_operation_id: client_operation_id.clone(),
+                        operation_id: operation_id.clone(),
+                    })
+                    .await
+                    .err_tip(|| "In RedisAwaitedActionDb::try_subscribe")?;
 
                 Ok(Some(OperationSubscriber::new(
                     Some(client_operation_id.clone()),
@@ -629,6 +638,32 @@ where
         ))
     }
 
+    async fn detach_client(&self, _client_operation_id: &ClientOperationId) -> Result<bool, Error> {
+        // The clients of an operation are not counted in the store, so the
+        // operation is cancelled for all of them.
+        Ok(false)
+    }
+
+    async fn has_unfinished_action(&self, unique_key: &ActionUniqueKey) -> Result<bool, Error> {
+        let unique_qualifier = ActionUniqueQualifier::Cachable(unique_key.clone());
+        let stream = self
+            .store
+            .search_by_index_prefix(SearchUniqueQualifierToAwaitedAction(&unique_qualifier))
+            .await
+            .err_tip(|| "In RedisAwaitedActionDb::has_unfinished_action")?;
+        tokio::pin!(stream);
+        while let Some(awaited_action) = stream
+            .try_next()
+            .await
+            .err_tip(|| "In RedisAwaitedActionDb::has_unfinished_action")?
+        {
+            if !awaited_action.state().stage.is_finished() {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
     async fn get_range_of_actions(
         &self,
         state: SortedAwaitedActionState,
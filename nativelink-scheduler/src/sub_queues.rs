@@ -0,0 +1,144 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use nativelink_config::schedulers::SubQueuesSpec;
+use nativelink_metric::MetricsComponent;
+use nativelink_util::task::JoinHandleDropGuard;
+use parking_lot::Mutex;
+use tokio::sync::Notify;
+
+use crate::autoscaling::pool_name;
+
+/// Spawns the matching loop of the named sub-queue, which matches its
+/// actions every time it is notified.
+pub type SpawnMatchingLoop =
+    Box<dyn Fn(String, Arc<Notify>) -> JoinHandleDropGuard<()> + Send + Sync>;
+
+/// The queued actions with the same values of the sub-queue properties.
+#[derive(MetricsComponent)]
+pub struct SubQueue {
+    #[metric(help = "The number of actions queued in the sub-queue")]
+    queued_actions: AtomicU64,
+    #[metric(help = "The time in seconds the oldest action of the sub-queue has been queued for")]
+    max_queue_age_s: AtomicU64,
+    #[metric(help = "The number of actions of the sub-queue dispatched to workers")]
+    dispatched_actions: AtomicU64,
+    notify: Arc<Notify>,
+    _matching_spawn: JoinHandleDropGuard<()>,
+}
+
+impl SubQueue {
+    /// Returns the number of actions in the sub-queue the last time it was
+    /// matched.
+    pub fn queued_actions(&self) -> u64 {
+        self.queued_actions.load(Ordering::Relaxed)
+    }
+
+    /// Returns how long the oldest action of the sub-queue had been queued
+    /// for the last time it was matched.
+    pub fn max_queue_age(&self) -> Duration {
+        Duration::from_secs(self.max_queue_age_s.load(Ordering::Relaxed))
+    }
+
+    /// Returns the number of actions of the sub-queue dispatched to workers.
+    pub fn dispatched_actions(&self) -> u64 {
+        self.dispatched_actions.load(Ordering::Relaxed)
+    }
+
+    /// Records what the sub-queue held when it was matched.
+    pub(crate) fn record_match(&self, queued_actions: u64, max_queue_age: Duration) {
+        self.queued_actions.store(queued_actions, Ordering::Relaxed);
+        self.max_queue_age_s
+            .store(max_queue_age.as_secs(), Ordering::Relaxed);
+    }
+
+    /// Counts an action of the sub-queue dispatched to a worker.
+    pub(crate) fn record_dispatch(&self) {
+        self.dispatched_actions.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Splits the queued actions into sub-queues by some of their platform
+/// properties, each with its own matching loop. Sub-queues are named like
+/// autoscaling pools, for example `pool=mac`.
+#[derive(MetricsComponent)]
+pub struct SubQueues {
+    properties: Vec<String>,
+    #[metric(help = "The maximum number of actions each sub-queue may hold, or 0 if unlimited")]
+    max_queued_actions: usize,
+    #[metric(group = "queues")]
+    queues: Mutex<BTreeMap<String, Arc<SubQueue>>>,
+    spawn_matching_loop: SpawnMatchingLoop,
+}
+
+impl SubQueues {
+    pub fn new(spec: &SubQueuesSpec, spawn_matching_loop: SpawnMatchingLoop) -> Self {
+        Self {
+            properties: spec.properties.clone(),
+            max_queued_actions: spec.max_queued_actions,
+            queues: Mutex::new(BTreeMap::new()),
+            spawn_matching_loop,
+        }
+    }
+
+    /// The maximum number of actions each sub-queue may hold, or 0 if
+    /// unlimited.
+    pub const fn max_queued_actions(&self) -> usize {
+        self.max_queued_actions
+    }
+
+    /// Returns the name of the sub-queue of an action with these platform
+    /// properties.
+    pub fn queue_of(&self, platform_properties: &HashMap<String, String>) -> String {
+        pool_name(&self.properties, |name| {
+            platform_properties.get(name).cloned()
+        })
+    }
+
+    /// Returns the sub-queue with this name if it was added.
+    pub fn get(&self, name: &str) -> Option<Arc<SubQueue>> {
+        self.queues.lock().get(name).cloned()
+    }
+
+    /// Returns the names of the sub-queues.
+    pub fn names(&self) -> Vec<String> {
+        self.queues.lock().keys().cloned().collect()
+    }
+
+    /// Adds the sub-queues that are missing and wakes the matching loops of
+    /// all sub-queues.
+    pub fn notify_all(&self, names: impl IntoIterator<Item = String>) {
+        let mut queues = self.queues.lock();
+        for name in names {
+            queues.entry(name).or_insert_with_key(|name| {
+                let notify = Arc::new(Notify::new());
+                Arc::new(SubQueue {
+                    queued_actions: AtomicU64::new(0),
+                    max_queue_age_s: AtomicU64::new(0),
+                    dispatched_actions: AtomicU64::new(0),
+                    notify: notify.clone(),
+                    _matching_spawn: (self.spawn_matching_loop)(name.clone(), notify),
+                })
+            });
+        }
+        for queue in queues.values() {
+            queue.notify.notify_one();
+        }
+    }
+}
@@ -12,20 +12,23 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::hash::{Hash, Hasher};
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use nativelink_error::{make_err, Code, Error, ResultExt};
+use nativelink_error::{error_if, make_err, Code, Error, ResultExt};
 use nativelink_metric::MetricsComponent;
 use nativelink_proto::com::github::trace_machina::nativelink::remote_execution::{
-    update_for_worker, ConnectionResult, StartExecute, UpdateForWorker,
+    update_for_worker, ConnectionResult, KillOperationRequest, StartExecute, UpdateForWorker,
 };
 use nativelink_util::action_messages::{ActionInfo, OperationId, WorkerId};
+use nativelink_util::common::DigestInfo;
 use nativelink_util::metrics_utils::{AsyncCounterWrapper, CounterWithTime, FuncCounterWrapper};
+use nativelink_util::origin_context::{ActiveOriginContext, REQUEST_ID};
 use nativelink_util::origin_event::OriginEventContext;
 use nativelink_util::platform_properties::{PlatformProperties, PlatformPropertyValue};
+use nativelink_util::telemetry::current_trace_context;
 use tokio::sync::mpsc::UnboundedSender;
 
 pub type WorkerTimestamp = u64;
@@ -57,6 +60,8 @@ pub enum WorkerUpdate {
 pub struct PendingActionInfoData {
     #[metric]
     pub action_info: ActionInfoWithProps,
+    #[metric(help = "When the action was sent to the worker.")]
+    pub start_timestamp: SystemTime,
     ctx: OriginEventContext<StartExecute>,
 }
 
@@ -79,6 +84,11 @@ pub struct Worker {
     #[metric(group = "running_action_infos")]
     pub running_action_infos: HashMap<OperationId, PendingActionInfoData>,
 
+    /// Running operations the worker was asked to kill. Updates about them
+    /// are no longer forwarded, but they keep their resources until the
+    /// worker reports them finished.
+    pub killed_operation_ids: HashSet<OperationId>,
+
     /// Timestamp of last time this worker had been communicated with.
     // Warning: Do not update this timestamp without updating the placement of the worker in
     // the LRUCache in the Workers struct.
@@ -93,6 +103,9 @@ pub struct Worker {
     #[metric(help = "If the worker is draining.")]
     pub is_draining: bool,
 
+    /// Input roots the worker reported to likely have in its local cache.
+    pub cached_input_roots: HashSet<DigestInfo>,
+
     /// Stats about the worker.
     #[metric]
     metrics: Arc<Metrics>,
@@ -138,9 +151,11 @@ impl Worker {
             platform_properties,
             tx,
             running_action_infos: HashMap::new(),
+            killed_operation_ids: HashSet::new(),
             last_update_timestamp: timestamp,
             is_paused: false,
             is_draining: false,
+            cached_input_roots: HashSet::new(),
             metrics: Arc::new(Metrics {
                 connected_timestamp: SystemTime::now()
                     .duration_since(UNIX_EPOCH)
@@ -188,6 +203,29 @@ impl Worker {
         })
     }
 
+    /// Asks the worker to kill an operation it is running.
+    pub fn kill_operation(&mut self, operation_id: &OperationId) -> Result<(), Error> {
+        error_if!(
+            !self.running_action_infos.contains_key(operation_id),
+            "Worker {} tried to kill operation {} that was not running",
+            self.id,
+            operation_id
+        );
+        self.killed_operation_ids.insert(operation_id.clone());
+        send_msg_to_worker(
+            &mut self.tx,
+            update_for_worker::Update::KillOperationRequest(KillOperationRequest {
+                operation_id: operation_id.to_string(),
+            }),
+        )
+        .err_tip(|| {
+            format!(
+                "Failed to send KillOperationRequest to worker : {}",
+                self.id
+            )
+        })
+    }
+
     async fn run_action(
         &mut self,
         operation_id: OperationId,
@@ -208,6 +246,18 @@ impl Worker {
                     queued_timestamp: Some(action_info.inner.insert_timestamp.into()),
                     platform: Some((&action_info.platform_properties).into()),
                     worker_id,
+                    trace_context: current_trace_context(),
+                    request_id: ActiveOriginContext::get_value(&REQUEST_ID)
+                        .ok()
+                        .flatten()
+                        .map(|request_id| request_id.as_ref().clone())
+                        .unwrap_or_default(),
+                    // Timeouts too large for the proto are not enforced.
+                    timeout: if action_info.inner.timeout.is_zero() {
+                        None
+                    } else {
+                        action_info.inner.timeout.try_into().ok()
+                    },
                 };
                 reduce_platform_properties(
                     worker_platform_properties,
@@ -215,8 +265,14 @@ impl Worker {
                 );
 
                 let ctx = OriginEventContext::new(|| &start_execute).await;
-                running_action_infos
-                    .insert(operation_id, PendingActionInfoData { action_info, ctx });
+                running_action_infos.insert(
+                    operation_id,
+                    PendingActionInfoData {
+                        action_info,
+                        start_timestamp: SystemTime::now(),
+                        ctx,
+                    },
+                );
 
                 send_msg_to_worker(tx, update_for_worker::Update::StartAction(start_execute))
             })
@@ -227,6 +283,7 @@ impl Worker {
         &mut self,
         operation_id: &OperationId,
     ) -> Result<(), Error> {
+        self.killed_operation_ids.remove(operation_id);
         let pending_action_info = self.running_action_infos.remove(operation_id).err_tip(|| {
             format!(
                 "Worker {} tried to complete operation {} that was not running",
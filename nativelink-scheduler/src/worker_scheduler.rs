@@ -12,15 +12,36 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashSet;
+use std::sync::Arc;
+
 use async_trait::async_trait;
 use nativelink_error::Error;
 use nativelink_metric::RootMetricsComponent;
 use nativelink_util::action_messages::{OperationId, WorkerId};
+use nativelink_util::common::DigestInfo;
+use nativelink_util::health_utils::HealthRegistryBuilder;
 use nativelink_util::operation_state_manager::UpdateOperationType;
 
 use crate::platform_property_manager::PlatformPropertyManager;
 use crate::worker::{Worker, WorkerTimestamp};
 
+/// The state of a worker that tells if it can be shut down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorkerStatus {
+    /// Whether the worker is draining, so it is not sent new actions.
+    pub is_draining: bool,
+    /// Number of actions the worker is running.
+    pub running_actions: usize,
+}
+
+impl WorkerStatus {
+    /// Returns true if the worker is draining and finished all its actions.
+    pub const fn is_drained(&self) -> bool {
+        self.is_draining && self.running_actions == 0
+    }
+}
+
 /// WorkerScheduler interface is responsible for interactions between the scheduler
 /// and worker related operations.
 #[async_trait]
@@ -46,6 +67,14 @@ pub trait WorkerScheduler: Sync + Send + Unpin + RootMetricsComponent + 'static
         timestamp: WorkerTimestamp,
     ) -> Result<(), Error>;
 
+    /// Sets the input roots the worker likely has in its local cache. Actions
+    /// with one of these input roots prefer the worker.
+    async fn set_worker_cached_input_roots(
+        &self,
+        worker_id: &WorkerId,
+        cached_input_roots: HashSet<DigestInfo>,
+    ) -> Result<(), Error>;
+
     /// Removes worker from pool and reschedule any tasks that might be running on it.
     async fn remove_worker(&self, worker_id: &WorkerId) -> Result<(), Error>;
 
@@ -55,4 +84,16 @@ pub trait WorkerScheduler: Sync + Send + Unpin + RootMetricsComponent + 'static
 
     /// Sets if the worker is draining or not.
     async fn set_drain_worker(&self, worker_id: &WorkerId, is_draining: bool) -> Result<(), Error>;
+
+    /// Returns if the worker is draining and how many actions it is still
+    /// running, so it can be shut down once it is drained.
+    async fn get_worker_status(&self, worker_id: &WorkerId) -> Result<WorkerStatus, Error>;
+
+    /// Sets if actions are dispatched to workers or not. Actions that are
+    /// already running are not affected, queued actions wait until dispatch
+    /// is resumed.
+    async fn set_dispatch_paused(&self, is_paused: bool) -> Result<(), Error>;
+
+    /// Registers the health status indicators of the scheduler.
+    fn register_health(self: Arc<Self>, _registry: &mut HealthRegistryBuilder) {}
 }
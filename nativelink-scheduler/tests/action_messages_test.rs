@@ -17,7 +17,9 @@ use std::time::SystemTime;
 
 use nativelink_error::Error;
 use nativelink_macro::nativelink_test;
-use nativelink_proto::build::bazel::remote::execution::v2::ExecuteResponse;
+use nativelink_proto::build::bazel::remote::execution::v2::{
+    ExecuteOperationMetadata, ExecuteResponse, ExecutedActionMetadata,
+};
 use nativelink_proto::google::longrunning::{operation, Operation};
 use nativelink_proto::google::rpc::Status;
 use nativelink_util::action_messages::{
@@ -27,6 +29,7 @@ use nativelink_util::action_messages::{
 use nativelink_util::common::DigestInfo;
 use nativelink_util::digest_hasher::DigestHasherFunc;
 use pretty_assertions::assert_eq;
+use prost::Message;
 
 #[nativelink_test]
 async fn action_state_any_url_test() -> Result<(), Error> {
@@ -60,6 +63,39 @@ async fn action_state_any_url_test() -> Result<(), Error> {
     Ok(())
 }
 
+#[nativelink_test]
+async fn output_stream_names_only_while_executing_test() -> Result<(), Error> {
+    let action_digest = DigestInfo::new([1u8; 32], 5);
+    let stream_names = |stage| {
+        let operation = ActionState {
+            client_operation_id: OperationId::default(),
+            stage,
+            action_digest,
+        }
+        .as_operation_with_output_streams(OperationId::default(), "foo_instance");
+        let metadata =
+            ExecuteOperationMetadata::decode(operation.metadata.unwrap().value.as_slice()).unwrap();
+        (metadata.stdout_stream_name, metadata.stderr_stream_name)
+    };
+    let hash = "0101010101010101010101010101010101010101010101010101010101010101";
+    assert_eq!(
+        stream_names(ActionStage::Executing),
+        (
+            format!("foo_instance/action-outputs/{hash}/5/stdout"),
+            format!("foo_instance/action-outputs/{hash}/5/stderr"),
+        )
+    );
+    assert_eq!(
+        stream_names(ActionStage::Queued),
+        (String::new(), String::new())
+    );
+    assert_eq!(
+        stream_names(ActionStage::Completed(ActionResult::default())),
+        (String::new(), String::new())
+    );
+    Ok(())
+}
+
 #[nativelink_test]
 async fn execute_response_status_message_is_some_on_success_test() -> Result<(), Error> {
     let execute_response: ExecuteResponse = ActionStage::Completed(ActionResult {
@@ -81,6 +117,8 @@ async fn execute_response_status_message_is_some_on_success_test() -> Result<(),
             execution_completed_timestamp: SystemTime::UNIX_EPOCH,
             output_upload_start_timestamp: SystemTime::UNIX_EPOCH,
             output_upload_completed_timestamp: SystemTime::UNIX_EPOCH,
+            retries: 0,
+            oom_kill_memory_limit_bytes: None,
         },
         server_logs: HashMap::default(),
         error: None,
@@ -93,3 +131,49 @@ async fn execute_response_status_message_is_some_on_success_test() -> Result<(),
 
     Ok(())
 }
+
+#[nativelink_test]
+async fn execution_metadata_retries_round_trip_test() -> Result<(), Error> {
+    let execution_metadata = ExecutionMetadata {
+        worker: "foo_worker_id".to_string(),
+        retries: 2,
+        ..ExecutionMetadata::default()
+    };
+    let executed_action_metadata: ExecutedActionMetadata = execution_metadata.clone().into();
+    assert_eq!(executed_action_metadata.auxiliary_metadata.len(), 1);
+    assert_eq!(
+        executed_action_metadata.auxiliary_metadata[0].type_url,
+        "type.googleapis.com/com.github.trace_machina.nativelink.remote_execution.RetryMetadata"
+    );
+    assert_eq!(
+        ExecutionMetadata::try_from(executed_action_metadata)?,
+        execution_metadata
+    );
+
+    // Actions that were not retried have no auxiliary metadata.
+    let executed_action_metadata: ExecutedActionMetadata = ExecutionMetadata::default().into();
+    assert!(executed_action_metadata.auxiliary_metadata.is_empty());
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn execution_metadata_oom_kill_round_trip_test() -> Result<(), Error> {
+    let execution_metadata = ExecutionMetadata {
+        worker: "foo_worker_id".to_string(),
+        oom_kill_memory_limit_bytes: Some(1 << 30),
+        ..ExecutionMetadata::default()
+    };
+    let executed_action_metadata: ExecutedActionMetadata = execution_metadata.clone().into();
+    assert_eq!(executed_action_metadata.auxiliary_metadata.len(), 1);
+    assert_eq!(
+        executed_action_metadata.auxiliary_metadata[0].type_url,
+        "type.googleapis.com/com.github.trace_machina.nativelink.remote_execution.OomKillMetadata"
+    );
+    assert_eq!(
+        ExecutionMetadata::try_from(executed_action_metadata)?,
+        execution_metadata
+    );
+
+    Ok(())
+}
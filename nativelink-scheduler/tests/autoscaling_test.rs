@@ -0,0 +1,182 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use hyper::{Body, Request, Response, StatusCode};
+use nativelink_config::schedulers::AutoscalingSpec;
+use nativelink_error::{Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_scheduler::autoscaling::{Autoscaling, PoolSignals};
+use nativelink_store::http_store::HttpClient;
+use nativelink_util::platform_properties::{PlatformProperties, PlatformPropertyValue};
+use pretty_assertions::assert_eq;
+
+const WEBHOOK_URL: &str = "http://autoscaler.example.com/nativelink";
+
+/// Records the bodies of the requests it is sent and responds with `status`.
+struct MockHttpClient {
+    status: StatusCode,
+    requests: Mutex<Vec<(String, serde_json::Value)>>,
+}
+
+#[async_trait]
+impl HttpClient for MockHttpClient {
+    async fn send(&self, request: Request<Body>) -> Result<Response<Body>, Error> {
+        assert_eq!(request.method(), "POST");
+        assert_eq!(request.headers()["content-type"], "application/json");
+        let uri = request.uri().to_string();
+        let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+        self.requests
+            .lock()
+            .unwrap()
+            .push((uri, serde_json::from_slice(&body).unwrap()));
+        Ok(Response::builder()
+            .status(self.status)
+            .body(Body::empty())
+            .unwrap())
+    }
+}
+
+fn make_autoscaling(
+    spec: &AutoscalingSpec,
+    status: StatusCode,
+) -> (Autoscaling, Arc<MockHttpClient>) {
+    let http_client = Arc::new(MockHttpClient {
+        status,
+        requests: Mutex::new(Vec::new()),
+    });
+    (
+        Autoscaling::new_with_client(spec, http_client.clone()),
+        http_client,
+    )
+}
+
+#[nativelink_test]
+async fn desired_workers_are_clamped_test() -> Result<(), Error> {
+    let (autoscaling, _http_client) = make_autoscaling(
+        &AutoscalingSpec {
+            actions_per_worker: 4,
+            min_workers: 2,
+            max_workers: 10,
+            ..Default::default()
+        },
+        StatusCode::OK,
+    );
+    let desired_workers = |queued_actions, executing_actions| {
+        autoscaling.desired_workers(&PoolSignals {
+            queued_actions,
+            executing_actions,
+            ..Default::default()
+        })
+    };
+    assert_eq!(desired_workers(0, 0), 2);
+    // Partly used workers are still needed.
+    assert_eq!(desired_workers(9, 8), 5);
+    assert_eq!(desired_workers(100, 0), 10);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn actions_and_workers_share_pools_test() -> Result<(), Error> {
+    let (autoscaling, _http_client) = make_autoscaling(
+        &AutoscalingSpec {
+            pool_properties: vec!["pool".to_string(), "os".to_string()],
+            ..Default::default()
+        },
+        StatusCode::OK,
+    );
+    let action_pool = autoscaling.pool_of_action(&HashMap::from([
+        ("os".to_string(), "linux".to_string()),
+        ("pool".to_string(), "gpu".to_string()),
+        ("cpu_count".to_string(), "4".to_string()),
+    ]));
+    assert_eq!(action_pool, "pool=gpu,os=linux");
+    let worker_pool = autoscaling.pool_of_worker(&PlatformProperties::new(HashMap::from([
+        (
+            "os".to_string(),
+            PlatformPropertyValue::Exact("linux".to_string()),
+        ),
+        (
+            "pool".to_string(),
+            PlatformPropertyValue::Exact("gpu".to_string()),
+        ),
+        ("cpu_count".to_string(), PlatformPropertyValue::Minimum(16)),
+    ])));
+    assert_eq!(worker_pool, action_pool);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn report_pushes_signals_to_webhook_test() -> Result<(), Error> {
+    let (autoscaling, http_client) = make_autoscaling(
+        &AutoscalingSpec {
+            webhook_url: WEBHOOK_URL.to_string(),
+            ..Default::default()
+        },
+        StatusCode::OK,
+    );
+    let pools = BTreeMap::from([(
+        "pool=gpu".to_string(),
+        PoolSignals {
+            queued_actions: 3,
+            oldest_queued_action_age_s: 42,
+            executing_actions: 1,
+            busy_workers: 1,
+            idle_workers: 0,
+            desired_workers: 4,
+        },
+    )]);
+    autoscaling.report(pools.clone()).await?;
+    assert_eq!(autoscaling.pools(), pools);
+
+    let requests = http_client.requests.lock().unwrap().clone();
+    assert_eq!(requests.len(), 1);
+    let (uri, body) = &requests[0];
+    assert_eq!(uri, WEBHOOK_URL);
+    assert!(body["timestamp_s"].is_u64(), "{body}");
+    assert_eq!(
+        body["pools"],
+        serde_json::json!({
+            "pool=gpu": {
+                "queued_actions": 3,
+                "oldest_queued_action_age_s": 42,
+                "executing_actions": 1,
+                "busy_workers": 1,
+                "idle_workers": 0,
+                "desired_workers": 4,
+            },
+        })
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn report_fails_when_webhook_fails_test() -> Result<(), Error> {
+    let (autoscaling, _http_client) = make_autoscaling(
+        &AutoscalingSpec {
+            webhook_url: WEBHOOK_URL.to_string(),
+            ..Default::default()
+        },
+        StatusCode::INTERNAL_SERVER_ERROR,
+    );
+    let pools = BTreeMap::from([(String::new(), PoolSignals::default())]);
+    let err = autoscaling.report(pools.clone()).await.unwrap_err();
+    assert_eq!(err.code, Code::Unavailable, "{err:?}");
+    // The signals are still exported as metrics.
+    assert_eq!(autoscaling.pools(), pools);
+    Ok(())
+}
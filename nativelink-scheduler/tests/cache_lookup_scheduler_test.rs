@@ -95,6 +95,67 @@ async fn add_action_handles_skip_cache() -> Result<(), Error> {
     Ok(())
 }
 
+#[nativelink_test]
+async fn skip_cache_executes_action_with_cached_result_test() -> Result<(), Error> {
+    let context = make_cache_scheduler()?;
+    let action_info = make_base_action_info(UNIX_EPOCH, DigestInfo::zero_digest());
+    let action_result = ProtoActionResult::from(ActionResult::default());
+    context
+        .ac_store
+        .update_oneshot(action_info.digest(), action_result.encode_to_vec().into())
+        .await?;
+
+    // Without skip_cache_lookup the cached result is returned.
+    let cached_action_state = context
+        .cache_scheduler
+        .add_action(OperationId::default(), action_info.clone())
+        .await?
+        .as_state()
+        .await?
+        .0;
+    assert!(
+        matches!(
+            cached_action_state.stage,
+            ActionStage::CompletedFromCache(_)
+        ),
+        "{cached_action_state:?}"
+    );
+
+    // With it the action runs again, even though its result is cached.
+    let (_forward_watch_channel_tx, forward_watch_channel_rx) =
+        watch::channel(Arc::new(ActionState {
+            client_operation_id: OperationId::default(),
+            stage: ActionStage::Queued,
+            action_digest: action_info.unique_qualifier.digest(),
+        }));
+    let ActionUniqueQualifier::Cachable(action_key) = action_info.unique_qualifier.clone() else {
+        panic!("This test should be testing when item was cached first");
+    };
+    let mut skip_cache_action = action_info.as_ref().clone();
+    skip_cache_action.unique_qualifier = ActionUniqueQualifier::Uncachable(action_key);
+    let client_operation_id = OperationId::default();
+    let (action_state_result, (forwarded_operation_id, forwarded_action_info)) = join!(
+        context
+            .cache_scheduler
+            .add_action(client_operation_id.clone(), Arc::new(skip_cache_action)),
+        context
+            .mock_scheduler
+            .expect_add_action(Ok(Box::new(TokioWatchActionStateResult::new(
+                client_operation_id.clone(),
+                action_info,
+                forward_watch_channel_rx
+            ))))
+    );
+    assert_eq!(forwarded_operation_id, client_operation_id);
+    assert!(matches!(
+        forwarded_action_info.unique_qualifier,
+        ActionUniqueQualifier::Uncachable(_)
+    ));
+    let action_state = action_state_result?.as_state().await?.0;
+    assert_eq!(action_state.stage, ActionStage::Queued);
+    Ok(())
+}
+
 #[nativelink_test]
 async fn find_by_client_operation_id_call_passed() -> Result<(), Error> {
     let context = make_cache_scheduler()?;
@@ -0,0 +1,104 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::time::UNIX_EPOCH;
+
+mod utils {
+    pub(crate) mod scheduler_utils;
+}
+
+use nativelink_config::schedulers::{FairShareGroupBy, FairShareSpec};
+use nativelink_error::Error;
+use nativelink_macro::nativelink_test;
+use nativelink_scheduler::fair_share::{FairShare, FairShareQueue};
+use nativelink_util::common::DigestInfo;
+use nativelink_util::origin_event::OriginMetadata;
+use pretty_assertions::assert_eq;
+use utils::scheduler_utils::{make_base_action_info, INSTANCE_NAME};
+
+/// Pops all queued actions, dispatching each of them.
+fn dispatch_all(queue: &mut FairShareQueue<'_, u32>) -> Vec<u32> {
+    let mut dispatched = Vec::new();
+    while let Some((group, item)) = queue.pop() {
+        queue.record_dispatch(&group);
+        dispatched.push(item);
+    }
+    dispatched
+}
+
+#[nativelink_test]
+async fn interleaves_groups_by_weight_test() -> Result<(), Error> {
+    let fair_share = FairShare::new(&FairShareSpec {
+        weights: HashMap::from([("dev".to_string(), 2)]),
+        ..Default::default()
+    });
+    let mut queue = FairShareQueue::new(&fair_share, HashMap::new());
+    // CI queues its fan-out first, so it would get every worker if actions
+    // were only dispatched in the order they were queued.
+    for item in 0..6 {
+        queue.push("ci".to_string(), item);
+    }
+    for item in 10..14 {
+        queue.push("dev".to_string(), item);
+    }
+    assert_eq!(
+        dispatch_all(&mut queue),
+        vec![0, 10, 11, 1, 12, 13, 2, 3, 4, 5]
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn accounts_for_running_actions_test() -> Result<(), Error> {
+    let fair_share = FairShare::new(&FairShareSpec::default());
+    let mut queue = FairShareQueue::new(&fair_share, HashMap::from([("ci".to_string(), 2)]));
+    queue.push("ci".to_string(), 0);
+    queue.push("ci".to_string(), 1);
+    queue.push("dev".to_string(), 10);
+    queue.push("dev".to_string(), 11);
+    queue.push("dev".to_string(), 12);
+    // Not every popped action finds a worker, so only dispatched actions
+    // count towards the share of their group.
+    let (group, item) = queue.pop().unwrap();
+    assert_eq!((group.as_str(), item), ("dev", 10));
+    assert_eq!(dispatch_all(&mut queue), vec![11, 12, 0, 1]);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn groups_actions_test() -> Result<(), Error> {
+    let action_info = make_base_action_info(UNIX_EPOCH, DigestInfo::zero_digest());
+    let origin_metadata = OriginMetadata {
+        identity: "ci".to_string(),
+        ..Default::default()
+    };
+
+    let by_identity = FairShare::new(&FairShareSpec::default());
+    assert_eq!(
+        by_identity.group_of(&action_info, Some(&origin_metadata)),
+        "ci"
+    );
+    assert_eq!(by_identity.group_of(&action_info, None), "");
+
+    let by_instance_name = FairShare::new(&FairShareSpec {
+        group_by: FairShareGroupBy::instance_name,
+        ..Default::default()
+    });
+    assert_eq!(
+        by_instance_name.group_of(&action_info, Some(&origin_metadata)),
+        INSTANCE_NAME
+    );
+    Ok(())
+}
@@ -0,0 +1,202 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+mod utils {
+    pub(crate) mod mock_scheduler;
+    pub(crate) mod scheduler_utils;
+}
+
+use futures::{join, StreamExt};
+use nativelink_error::{make_err, Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_scheduler::federated_scheduler::FederatedScheduler;
+use nativelink_util::action_messages::{ActionInfo, ActionStage, ActionState, OperationId};
+use nativelink_util::common::DigestInfo;
+use nativelink_util::operation_state_manager::{ClientStateManager, OperationFilter};
+use pretty_assertions::assert_eq;
+use tokio::sync::watch;
+use utils::mock_scheduler::MockActionScheduler;
+use utils::scheduler_utils::{make_base_action_info, TokioWatchActionStateResult};
+
+const REMOTE_OPERATION_ID: &str = "remote-operation";
+
+struct TestContext {
+    local_scheduler: Arc<MockActionScheduler>,
+    remote_scheduler: Arc<MockActionScheduler>,
+    federated_scheduler: FederatedScheduler,
+}
+
+fn make_federated_scheduler(maybe_forward_after_queued: Option<Duration>) -> TestContext {
+    let local_scheduler = Arc::new(MockActionScheduler::new());
+    let remote_scheduler = Arc::new(MockActionScheduler::new());
+    let federated_scheduler = FederatedScheduler::new(
+        local_scheduler.clone(),
+        vec![remote_scheduler.clone()],
+        maybe_forward_after_queued,
+    );
+    TestContext {
+        local_scheduler,
+        remote_scheduler,
+        federated_scheduler,
+    }
+}
+
+fn make_action_state_result(
+    client_operation_id: OperationId,
+    action_info: Arc<ActionInfo>,
+    stage: ActionStage,
+) -> (watch::Sender<Arc<ActionState>>, TokioWatchActionStateResult) {
+    let (tx, rx) = watch::channel(Arc::new(ActionState {
+        client_operation_id: client_operation_id.clone(),
+        stage,
+        action_digest: action_info.unique_qualifier.digest(),
+    }));
+    (
+        tx,
+        TokioWatchActionStateResult::new(client_operation_id, action_info, rx),
+    )
+}
+
+#[nativelink_test]
+async fn forwards_actions_with_unknown_properties_test() -> Result<(), Error> {
+    let context = make_federated_scheduler(None);
+    let mut action_info = make_base_action_info(UNIX_EPOCH, DigestInfo::zero_digest())
+        .as_ref()
+        .clone();
+    action_info
+        .platform_properties
+        .insert("gpu".to_string(), "a100".to_string());
+    let action_info = Arc::new(action_info);
+    let client_operation_id = OperationId::from("client-operation");
+    let (_remote_tx, remote_result) = make_action_state_result(
+        OperationId::from(REMOTE_OPERATION_ID),
+        action_info.clone(),
+        ActionStage::Queued,
+    );
+
+    let (action_state_result, ()) = join!(
+        context
+            .federated_scheduler
+            .add_action(client_operation_id.clone(), action_info),
+        async {
+            context
+                .local_scheduler
+                .expect_get_known_properties(Ok(vec![]))
+                .await;
+            context
+                .remote_scheduler
+                .expect_get_known_properties(Ok(vec!["gpu".to_string()]))
+                .await;
+            let (forwarded_operation_id, _) = context
+                .remote_scheduler
+                .expect_add_action(Ok(Box::new(remote_result)))
+                .await;
+            assert_eq!(forwarded_operation_id, client_operation_id);
+        },
+    );
+    // The client keeps its own operation id.
+    let (action_state, _) = action_state_result?.as_state().await?;
+    assert_eq!(action_state.client_operation_id, client_operation_id);
+    assert_eq!(context.federated_scheduler.forwarded_actions(), 1);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn forwards_actions_queued_too_long_test() -> Result<(), Error> {
+    let context = make_federated_scheduler(Some(Duration::from_millis(10)));
+    let action_info = make_base_action_info(UNIX_EPOCH, DigestInfo::zero_digest());
+    let client_operation_id = OperationId::from("client-operation");
+    let (_local_tx, local_result) = make_action_state_result(
+        client_operation_id.clone(),
+        action_info.clone(),
+        ActionStage::Queued,
+    );
+    let (_remote_tx, remote_result) = make_action_state_result(
+        OperationId::from(REMOTE_OPERATION_ID),
+        action_info.clone(),
+        ActionStage::Executing,
+    );
+
+    let (action_state_result, _) = join!(
+        context
+            .federated_scheduler
+            .add_action(client_operation_id.clone(), action_info),
+        context
+            .local_scheduler
+            .expect_add_action(Ok(Box::new(local_result))),
+    );
+    let mut action_state_result = action_state_result?;
+    let (changed, _) = join!(
+        action_state_result.changed(),
+        context
+            .remote_scheduler
+            .expect_add_action(Ok(Box::new(remote_result))),
+    );
+    let (action_state, _) = changed?;
+    assert_eq!(action_state.stage, ActionStage::Executing);
+    assert_eq!(action_state.client_operation_id, client_operation_id);
+
+    // Lookups of the operation go to the remote scheduler.
+    let (stream, filter) = join!(
+        context
+            .federated_scheduler
+            .filter_operations(OperationFilter {
+                client_operation_id: Some(client_operation_id.clone()),
+                ..Default::default()
+            }),
+        context
+            .remote_scheduler
+            .expect_filter_operations(Ok(futures::stream::empty().boxed())),
+    );
+    assert_eq!(
+        filter.client_operation_id,
+        Some(OperationId::from(REMOTE_OPERATION_ID))
+    );
+    assert!(stream?.next().await.is_none());
+    Ok(())
+}
+
+#[nativelink_test]
+async fn forwards_actions_when_local_queue_is_full_test() -> Result<(), Error> {
+    let context = make_federated_scheduler(None);
+    let action_info = make_base_action_info(UNIX_EPOCH, DigestInfo::zero_digest());
+    let client_operation_id = OperationId::from("client-operation");
+    let (_remote_tx, remote_result) = make_action_state_result(
+        OperationId::from(REMOTE_OPERATION_ID),
+        action_info.clone(),
+        ActionStage::Queued,
+    );
+
+    let (action_state_result, ()) = join!(
+        context
+            .federated_scheduler
+            .add_action(client_operation_id.clone(), action_info),
+        async {
+            context
+                .local_scheduler
+                .expect_add_action(Err(make_err!(Code::ResourceExhausted, "Queue is full")))
+                .await;
+            context
+                .remote_scheduler
+                .expect_add_action(Ok(Box::new(remote_result)))
+                .await;
+        },
+    );
+    assert!(action_state_result.is_ok());
+    assert_eq!(context.federated_scheduler.forwarded_actions(), 1);
+    Ok(())
+}
@@ -0,0 +1,117 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::{Duration, UNIX_EPOCH};
+
+mod utils {
+    pub(crate) mod scheduler_utils;
+}
+
+use nativelink_config::schedulers::HedgingSpec;
+use nativelink_error::Error;
+use nativelink_macro::nativelink_test;
+use nativelink_scheduler::hedging::{Hedge, Hedging};
+use nativelink_util::action_messages::{ActionInfo, ActionUniqueQualifier, OperationId, WorkerId};
+use nativelink_util::common::DigestInfo;
+use pretty_assertions::assert_eq;
+use utils::scheduler_utils::make_base_action_info;
+use uuid::Uuid;
+
+#[nativelink_test]
+async fn hedge_after_needs_min_samples_test() -> Result<(), Error> {
+    let hedging = Hedging::new(&HedgingSpec {
+        min_samples: 3,
+        ..Default::default()
+    });
+    let action_info = make_base_action_info(UNIX_EPOCH, DigestInfo::new([1u8; 32], 1));
+    hedging.record_duration(&action_info, Duration::from_secs(3));
+    hedging.record_duration(&action_info, Duration::from_secs(1));
+    assert_eq!(hedging.hedge_after(&action_info), None);
+
+    hedging.record_duration(&action_info, Duration::from_secs(2));
+    // The longest of a few durations is their p95, doubled by default.
+    assert_eq!(
+        hedging.hedge_after(&action_info),
+        Some(Duration::from_secs(6))
+    );
+
+    // Durations are kept per command, so other inputs share them.
+    let other_action_info = make_base_action_info(UNIX_EPOCH, DigestInfo::new([2u8; 32], 1));
+    assert_eq!(
+        hedging.hedge_after(&other_action_info),
+        Some(Duration::from_secs(6))
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn old_durations_are_forgotten_test() -> Result<(), Error> {
+    let hedging = Hedging::new(&HedgingSpec {
+        p95_multiplier: 1.,
+        min_samples: 1,
+        max_samples: 2,
+        ..Default::default()
+    });
+    let action_info = make_base_action_info(UNIX_EPOCH, DigestInfo::new([1u8; 32], 1));
+    hedging.record_duration(&action_info, Duration::from_secs(10));
+    hedging.record_duration(&action_info, Duration::from_secs(1));
+    hedging.record_duration(&action_info, Duration::from_secs(2));
+    assert_eq!(
+        hedging.hedge_after(&action_info),
+        Some(Duration::from_secs(2))
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn copies_do_not_run_on_worker_of_original_test() -> Result<(), Error> {
+    let hedging = Hedging::new(&HedgingSpec::default());
+    let action_info = make_base_action_info(UNIX_EPOCH, DigestInfo::new([1u8; 32], 1));
+    let ActionUniqueQualifier::Cachable(unique_key) = &action_info.unique_qualifier else {
+        panic!("Expected a cachable action");
+    };
+    let hedge_action_info = ActionInfo {
+        unique_qualifier: ActionUniqueQualifier::Uncachable(unique_key.clone()),
+        ..action_info.as_ref().clone()
+    };
+    let worker_id = WorkerId(Uuid::new_v4());
+    let original_operation_id = OperationId::default();
+    assert!(!hedging.is_hedged(&action_info.digest()));
+
+    hedging.add_hedge(
+        original_operation_id.clone(),
+        Hedge {
+            action_digest: action_info.digest(),
+            worker_id,
+            hedge_operation_id: OperationId::default(),
+            is_finished: false,
+        },
+    );
+    assert!(hedging.is_hedged(&action_info.digest()));
+    // Only the copy is kept off the worker, the original can be retried there.
+    assert_eq!(hedging.excluded_worker(&action_info), None);
+    assert_eq!(hedging.excluded_worker(&hedge_action_info), Some(worker_id));
+
+    // The original still runs, so its copy is kept.
+    assert!(hedging
+        .remove_stopped_hedges(|operation_id, running_worker_id| {
+            *operation_id == original_operation_id && *running_worker_id == worker_id
+        })
+        .is_empty());
+    let stopped_hedges = hedging.remove_stopped_hedges(|_, _| false);
+    assert_eq!(stopped_hedges.len(), 1);
+    assert!(!stopped_hedges[0].is_finished);
+    assert_eq!(hedging.excluded_worker(&hedge_action_info), None);
+    Ok(())
+}
@@ -0,0 +1,78 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use nativelink_config::schedulers::PropertyType;
+use nativelink_error::{Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_scheduler::platform_property_manager::PlatformPropertyManager;
+use pretty_assertions::assert_eq;
+
+fn make_manager() -> PlatformPropertyManager {
+    PlatformPropertyManager::new(HashMap::from([
+        ("mem_gb".to_string(), PropertyType::range),
+        ("gpu".to_string(), PropertyType::one_of),
+    ]))
+}
+
+fn is_satisfied_by(
+    manager: &PlatformPropertyManager,
+    key: &str,
+    action_value: &str,
+    worker_value: &str,
+) -> Result<bool, Error> {
+    Ok(manager
+        .make_prop_value(key, action_value)?
+        .is_satisfied_by(&manager.make_prop_value(key, worker_value)?))
+}
+
+#[nativelink_test]
+async fn range_property_compares_worker_value_test() -> Result<(), Error> {
+    let manager = make_manager();
+    assert!(is_satisfied_by(&manager, "mem_gb", ">=32", "64")?);
+    assert!(is_satisfied_by(&manager, "mem_gb", "32", "32")?);
+    assert!(!is_satisfied_by(&manager, "mem_gb", ">64", "64")?);
+    assert!(is_satisfied_by(&manager, "mem_gb", "<= 64", "64")?);
+    assert!(!is_satisfied_by(&manager, "mem_gb", "<8", "<8")?);
+    assert!(is_satisfied_by(&manager, "mem_gb", "=16", "16")?);
+    assert!(!is_satisfied_by(&manager, "mem_gb", "=16", "32")?);
+
+    // The expression is passed on to the worker as it was requested.
+    assert_eq!(manager.make_prop_value("mem_gb", ">=32")?.as_str(), ">=32");
+    Ok(())
+}
+
+#[nativelink_test]
+async fn one_of_property_matches_any_value_test() -> Result<(), Error> {
+    let manager = make_manager();
+    assert!(is_satisfied_by(&manager, "gpu", "{a100,h100}", "h100")?);
+    assert!(is_satisfied_by(&manager, "gpu", "a100, h100", "a100")?);
+    assert!(!is_satisfied_by(&manager, "gpu", "{a100,h100}", "t4")?);
+    assert!(is_satisfied_by(&manager, "gpu", "h100", "{t4,h100}")?);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn invalid_range_property_is_rejected_test() -> Result<(), Error> {
+    let manager = make_manager();
+    assert_eq!(
+        manager
+            .make_prop_value("mem_gb", ">=lots")
+            .unwrap_err()
+            .code,
+        Code::InvalidArgument
+    );
+    Ok(())
+}
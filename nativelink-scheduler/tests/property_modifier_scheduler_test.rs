@@ -23,9 +23,10 @@ mod utils {
 
 use futures::{join, StreamExt};
 use nativelink_config::schedulers::{
-    PlatformPropertyAddition, PropertyModification, PropertyModifierSpec, SchedulerSpec, SimpleSpec,
+    PlatformPropertyAddition, PlatformPropertyCondition, PlatformPropertyReplacement,
+    PropertyModification, PropertyModifierSpec, SchedulerSpec, SimpleSpec,
 };
-use nativelink_error::Error;
+use nativelink_error::{Code, Error};
 use nativelink_macro::nativelink_test;
 use nativelink_scheduler::property_modifier_scheduler::PropertyModifierScheduler;
 use nativelink_util::action_messages::{ActionStage, ActionState, OperationId};
@@ -48,7 +49,8 @@ fn make_modifier_scheduler(modifications: Vec<PropertyModification>) -> TestCont
         modifications,
         scheduler: Box::new(SchedulerSpec::simple(SimpleSpec::default())),
     };
-    let modifier_scheduler = PropertyModifierScheduler::new(&config, mock_scheduler.clone());
+    let modifier_scheduler =
+        PropertyModifierScheduler::new(&config, mock_scheduler.clone()).unwrap();
     TestContext {
         mock_scheduler,
         modifier_scheduler,
@@ -246,6 +248,127 @@ async fn add_action_property_remove() -> Result<(), Error> {
     Ok(())
 }
 
+#[nativelink_test]
+async fn add_action_replaces_property_value() -> Result<(), Error> {
+    let context = make_modifier_scheduler(vec![PropertyModification::replace(
+        PlatformPropertyReplacement {
+            name: "container-image".to_string(),
+            pattern: "^docker://old-registry/(.*)$".to_string(),
+            replacement: "docker://new-registry/$1".to_string(),
+        },
+    )]);
+    let mut action_info = make_base_action_info(UNIX_EPOCH, DigestInfo::zero_digest())
+        .as_ref()
+        .clone();
+    action_info.platform_properties.insert(
+        "container-image".to_string(),
+        "docker://old-registry/ubuntu:22.04".to_string(),
+    );
+    let action_info = Arc::new(action_info);
+    let (_forward_watch_channel_tx, forward_watch_channel_rx) =
+        watch::channel(Arc::new(ActionState {
+            client_operation_id: OperationId::default(),
+            stage: ActionStage::Queued,
+            action_digest: action_info.unique_qualifier.digest(),
+        }));
+    let client_operation_id = OperationId::default();
+    let (_, (_, action_info)) = join!(
+        context
+            .modifier_scheduler
+            .add_action(client_operation_id.clone(), action_info.clone()),
+        context
+            .mock_scheduler
+            .expect_add_action(Ok(Box::new(TokioWatchActionStateResult::new(
+                client_operation_id.clone(),
+                action_info,
+                forward_watch_channel_rx
+            )))),
+    );
+    assert_eq!(
+        HashMap::from([(
+            "container-image".to_string(),
+            "docker://new-registry/ubuntu:22.04".to_string()
+        )]),
+        action_info.platform_properties
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn add_action_applies_conditional_modifications() -> Result<(), Error> {
+    let context = make_modifier_scheduler(vec![PropertyModification::conditional(
+        PlatformPropertyCondition {
+            name: "container-image".to_string(),
+            pattern: "^docker://gpu/".to_string(),
+            modifications: vec![
+                PropertyModification::add(PlatformPropertyAddition {
+                    name: "pool".to_string(),
+                    value: "gpu".to_string(),
+                }),
+                PropertyModification::remove("legacy-pool".to_string()),
+            ],
+        },
+    )]);
+    let mut action_info = make_base_action_info(UNIX_EPOCH, DigestInfo::zero_digest())
+        .as_ref()
+        .clone();
+    action_info.platform_properties.insert(
+        "container-image".to_string(),
+        "docker://gpu/cuda:12".to_string(),
+    );
+    action_info
+        .platform_properties
+        .insert("legacy-pool".to_string(), "old".to_string());
+    let action_info = Arc::new(action_info);
+    let (_forward_watch_channel_tx, forward_watch_channel_rx) =
+        watch::channel(Arc::new(ActionState {
+            client_operation_id: OperationId::default(),
+            stage: ActionStage::Queued,
+            action_digest: action_info.unique_qualifier.digest(),
+        }));
+    let client_operation_id = OperationId::default();
+    let (_, (_, action_info)) = join!(
+        context
+            .modifier_scheduler
+            .add_action(client_operation_id.clone(), action_info.clone()),
+        context
+            .mock_scheduler
+            .expect_add_action(Ok(Box::new(TokioWatchActionStateResult::new(
+                client_operation_id.clone(),
+                action_info,
+                forward_watch_channel_rx
+            )))),
+    );
+    assert_eq!(
+        HashMap::from([
+            (
+                "container-image".to_string(),
+                "docker://gpu/cuda:12".to_string()
+            ),
+            ("pool".to_string(), "gpu".to_string()),
+        ]),
+        action_info.platform_properties
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn invalid_pattern_is_rejected() -> Result<(), Error> {
+    let config = PropertyModifierSpec {
+        modifications: vec![PropertyModification::replace(PlatformPropertyReplacement {
+            name: "name".to_string(),
+            pattern: "(unclosed".to_string(),
+            replacement: String::new(),
+        })],
+        scheduler: Box::new(SchedulerSpec::simple(SimpleSpec::default())),
+    };
+    let err = PropertyModifierScheduler::new(&config, Arc::new(MockActionScheduler::new()))
+        .err()
+        .unwrap();
+    assert_eq!(err.code, Code::InvalidArgument, "{err:?}");
+    Ok(())
+}
+
 #[nativelink_test]
 async fn find_by_client_operation_id_call_passed() -> Result<(), Error> {
     let context = make_modifier_scheduler(vec![]);
@@ -304,3 +427,60 @@ async fn remove_retains_type_in_underlying_manager() -> Result<(), Error> {
     assert_eq!(Ok(vec![name]), known_props);
     Ok(())
 }
+
+#[nativelink_test]
+async fn reconfigure_replaces_modifications() -> Result<(), Error> {
+    let name = "name".to_string();
+    let reconfigured_value = "reconfigured".to_string();
+    let context =
+        make_modifier_scheduler(vec![PropertyModification::add(PlatformPropertyAddition {
+            name: name.clone(),
+            value: "value".to_string(),
+        })]);
+    context
+        .modifier_scheduler
+        .reconfigure(
+            &SchedulerSpec::property_modifier(PropertyModifierSpec {
+                modifications: vec![PropertyModification::add(PlatformPropertyAddition {
+                    name: name.clone(),
+                    value: reconfigured_value.clone(),
+                })],
+                scheduler: Box::new(SchedulerSpec::simple(SimpleSpec::default())),
+            }),
+            false,
+        )
+        .await?;
+
+    let action_info = make_base_action_info(UNIX_EPOCH, DigestInfo::zero_digest());
+    let (_forward_watch_channel_tx, forward_watch_channel_rx) =
+        watch::channel(Arc::new(ActionState {
+            client_operation_id: OperationId::default(),
+            stage: ActionStage::Queued,
+            action_digest: action_info.unique_qualifier.digest(),
+        }));
+    let client_operation_id = OperationId::default();
+    let (_, (_passed_client_operation_id, action_info)) = join!(
+        context
+            .modifier_scheduler
+            .add_action(client_operation_id.clone(), action_info.clone()),
+        context
+            .mock_scheduler
+            .expect_add_action(Ok(Box::new(TokioWatchActionStateResult::new(
+                client_operation_id.clone(),
+                action_info,
+                forward_watch_channel_rx
+            )))),
+    );
+    assert_eq!(
+        HashMap::from([(name, reconfigured_value)]),
+        action_info.platform_properties
+    );
+
+    let err = context
+        .modifier_scheduler
+        .reconfigure(&SchedulerSpec::simple(SimpleSpec::default()), false)
+        .await
+        .unwrap_err();
+    assert_eq!(err.code, Code::InvalidArgument, "{err:?}");
+    Ok(())
+}
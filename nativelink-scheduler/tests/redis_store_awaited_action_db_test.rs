@@ -50,6 +50,14 @@ const TEMP_UUID: &str = "550e8400-e29b-41d4-a716-446655440000";
 const SCRIPT_VERSION: &str = "3e762c15";
 const VERSION_SCRIPT_HASH: &str = "fdf1152fd21705c8763752809b86b55c5d4511ce";
 const MAX_CHUNK_UPLOADS_PER_UPDATE: usize = 10;
+// Only one read is outstanding at a time, so the order of commands is deterministic.
+const MAX_CHUNK_READS_PER_GET: usize = 1;
+const UPLOAD_CHUNK_SIZE: usize = 1024 * 1024;
+const STORAGE_CHUNK_SIZE: usize = 0;
+const MAX_IN_FLIGHT_PER_CONNECTION: usize = 0;
+const CONNECTION_ACQUIRE_TIMEOUT: Duration = Duration::from_secs(10);
+const KEY_TTL_S: i64 = 0;
+const TEMP_KEY_TTL_S: i64 = 0;
 
 fn mock_uuid_generator() -> String {
     uuid::Uuid::parse_str(TEMP_UUID).unwrap().to_string()
@@ -417,7 +425,14 @@ async fn add_action_smoke_test() -> Result<(), Error> {
                 mock_uuid_generator,
                 String::new(),
                 4064,
+                MAX_CHUNK_READS_PER_GET,
                 MAX_CHUNK_UPLOADS_PER_UPDATE,
+                UPLOAD_CHUNK_SIZE,
+                STORAGE_CHUNK_SIZE,
+                MAX_IN_FLIGHT_PER_CONNECTION,
+                CONNECTION_ACQUIRE_TIMEOUT,
+                KEY_TTL_S,
+                TEMP_KEY_TTL_S,
             )
             .unwrap(),
         )
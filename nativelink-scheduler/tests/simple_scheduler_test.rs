@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::future::Future;
 use std::ops::Bound;
 use std::pin::Pin;
@@ -24,7 +24,10 @@ use async_lock::Mutex;
 use futures::task::Poll;
 use futures::{poll, Stream, StreamExt};
 use mock_instant::thread_local::{MockClock, SystemTime as MockSystemTime};
-use nativelink_config::schedulers::{PropertyType, SimpleSpec};
+use nativelink_config::schedulers::{
+    AutoscalingSpec, HedgingSpec, JobFailureKind, PriorityMappingSpec, PropertyType, SimpleSpec,
+    SubQueuesSpec,
+};
 use nativelink_error::{make_err, Code, Error, ResultExt};
 use nativelink_macro::nativelink_test;
 use nativelink_metric::MetricsComponent;
@@ -32,8 +35,9 @@ use nativelink_proto::build::bazel::remote::execution::v2::{
     digest_function, ExecuteRequest, Platform,
 };
 use nativelink_proto::com::github::trace_machina::nativelink::remote_execution::{
-    update_for_worker, ConnectionResult, StartExecute, UpdateForWorker,
+    update_for_worker, ConnectionResult, KillOperationRequest, StartExecute, UpdateForWorker,
 };
+use nativelink_scheduler::autoscaling::PoolSignals;
 use nativelink_scheduler::awaited_action_db::{
     AwaitedAction, AwaitedActionDb, AwaitedActionSubscriber, SortedAwaitedAction,
     SortedAwaitedActionState,
@@ -197,6 +201,9 @@ async fn basic_add_action_with_one_worker_test() -> Result<(), Error> {
                 queued_timestamp: Some(insert_timestamp.into()),
                 platform: Some(Platform::default()),
                 worker_id: worker_id.to_string(),
+                trace_context: HashMap::new(),
+                request_id: String::new(),
+                timeout: None,
             })),
         };
         let msg_for_worker = rx_from_worker.recv().await.unwrap();
@@ -351,6 +358,9 @@ async fn find_executing_action() -> Result<(), Error> {
                 queued_timestamp: Some(insert_timestamp.into()),
                 platform: Some(Platform::default()),
                 worker_id: worker_id.to_string(),
+                trace_context: HashMap::new(),
+                request_id: String::new(),
+                timeout: None,
             })),
         };
         let msg_for_worker = rx_from_worker.recv().await.unwrap();
@@ -425,6 +435,9 @@ async fn remove_worker_reschedules_multiple_running_job_test() -> Result<(), Err
         queued_timestamp: Some(insert_timestamp1.into()),
         platform: Some(Platform::default()),
         worker_id: worker_id1.to_string(),
+        trace_context: HashMap::new(),
+        request_id: String::new(),
+        timeout: None,
     };
 
     let mut expected_start_execute_for_worker2 = StartExecute {
@@ -438,6 +451,9 @@ async fn remove_worker_reschedules_multiple_running_job_test() -> Result<(), Err
         queued_timestamp: Some(insert_timestamp2.into()),
         platform: Some(Platform::default()),
         worker_id: worker_id1.to_string(),
+        trace_context: HashMap::new(),
+        request_id: String::new(),
+        timeout: None,
     };
     let operation_id1 = {
         // Worker1 should now see first execution request.
@@ -642,6 +658,101 @@ async fn set_drain_worker_pauses_and_resumes_worker_test() -> Result<(), Error>
     Ok(())
 }
 
+#[nativelink_test]
+async fn set_dispatch_paused_keeps_actions_queued_test() -> Result<(), Error> {
+    let worker_id: WorkerId = WorkerId(Uuid::new_v4());
+
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec::default(),
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+    let action_digest = DigestInfo::new([99u8; 32], 512);
+
+    let mut rx_from_worker =
+        setup_new_worker(&scheduler, worker_id, PlatformProperties::default()).await?;
+    scheduler.set_dispatch_paused(true).await?;
+
+    let insert_timestamp = make_system_time(1);
+    let mut action_listener =
+        setup_action(&scheduler, action_digest, HashMap::new(), insert_timestamp).await?;
+    assert_eq!(
+        action_listener.changed().await?.0.stage,
+        ActionStage::Queued
+    );
+    tokio::task::yield_now().await;
+    assert!(
+        rx_from_worker.try_recv().is_err(),
+        "Worker should not receive actions while dispatch is paused"
+    );
+
+    scheduler.set_dispatch_paused(false).await?;
+    match rx_from_worker.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(_)) => {}
+        v => panic!("Expected StartAction, got : {v:?}"),
+    }
+    assert_eq!(
+        action_listener.changed().await?.0.stage,
+        ActionStage::Executing
+    );
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn prefers_worker_with_cached_input_root_test() -> Result<(), Error> {
+    let worker_id1: WorkerId = WorkerId(Uuid::new_v4());
+    let worker_id2: WorkerId = WorkerId(Uuid::new_v4());
+
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec::default(),
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+    let action_info = make_base_action_info(make_system_time(1), DigestInfo::zero_digest());
+
+    // The least recently used worker would get the action, but only the
+    // other worker has its inputs in its cache.
+    let mut rx_from_worker1 =
+        setup_new_worker(&scheduler, worker_id1, PlatformProperties::default()).await?;
+    let mut rx_from_worker2 =
+        setup_new_worker(&scheduler, worker_id2, PlatformProperties::default()).await?;
+    scheduler
+        .set_worker_cached_input_roots(&worker_id2, HashSet::from([action_info.input_root_digest]))
+        .await?;
+
+    let mut action_listener = scheduler
+        .add_action(OperationId::default(), action_info)
+        .await?;
+    match rx_from_worker2.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(_)) => { /* Success */ }
+        v => panic!("Expected StartAction, got : {v:?}"),
+    }
+    assert_eq!(
+        action_listener.changed().await.unwrap().0.stage,
+        ActionStage::Executing
+    );
+    assert!(rx_from_worker1.try_recv().is_err());
+
+    Ok(())
+}
+
 #[nativelink_test]
 async fn worker_should_not_queue_if_properties_dont_match_test() -> Result<(), Error> {
     let worker_id1: WorkerId = WorkerId(Uuid::new_v4());
@@ -718,6 +829,9 @@ async fn worker_should_not_queue_if_properties_dont_match_test() -> Result<(), E
                 queued_timestamp: Some(insert_timestamp.into()),
                 platform: Some((&worker2_properties).into()),
                 worker_id: worker_id2.to_string(),
+                trace_context: HashMap::new(),
+                request_id: String::new(),
+                timeout: None,
             })),
         };
         let msg_for_worker = rx_from_worker2.recv().await.unwrap();
@@ -815,6 +929,9 @@ async fn cacheable_items_join_same_action_queued_test() -> Result<(), Error> {
                 queued_timestamp: Some(insert_timestamp1.into()),
                 platform: Some(Platform::default()),
                 worker_id: worker_id.to_string(),
+                trace_context: HashMap::new(),
+                request_id: String::new(),
+                timeout: None,
             })),
         };
         let msg_for_worker = rx_from_worker.recv().await.unwrap();
@@ -1008,6 +1125,10 @@ impl AwaitedActionDb for RxMockAwaitedAction {
     ) -> Result<Self::Subscriber, Error> {
         unreachable!();
     }
+
+    async fn detach_client(&self, _client_operation_id: &OperationId) -> Result<bool, Error> {
+        unreachable!();
+    }
 }
 
 #[nativelink_test]
@@ -1153,6 +1274,9 @@ async fn worker_timesout_reschedules_running_job_test() -> Result<(), Error> {
         queued_timestamp: Some(insert_timestamp.into()),
         platform: Some(Platform::default()),
         worker_id: worker_id1.to_string(),
+        trace_context: HashMap::new(),
+        request_id: String::new(),
+        timeout: None,
     };
 
     {
@@ -1236,6 +1360,72 @@ async fn worker_timesout_reschedules_running_job_test() -> Result<(), Error> {
     Ok(())
 }
 
+/// This tests that an executing action is queued again if its worker stops
+/// updating it, even if no client is waiting for it. This happens when a
+/// scheduler recovers its actions from its backend after a restart.
+#[nativelink_test]
+async fn stale_executing_action_is_requeued_without_client_test() -> Result<(), Error> {
+    let worker_id: WorkerId = WorkerId(Uuid::new_v4());
+
+    let mut supported_props = HashMap::new();
+    supported_props.insert("prop1".to_string(), PropertyType::minimum);
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec {
+            supported_platform_properties: Some(supported_props),
+            worker_timeout_s: WORKER_TIMEOUT_S,
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+    let action_digest = DigestInfo::new([99u8; 32], 512);
+    MockClock::advance(Duration::from_secs(NOW_TIME));
+
+    // Use property to restrict the worker to a single action, so the action
+    // is not started on it again once it is queued.
+    let mut properties = HashMap::new();
+    properties.insert("prop1".to_string(), PlatformPropertyValue::Minimum(1));
+    let action_props: HashMap<String, String> = properties
+        .iter()
+        .map(|(k, v)| (k.clone(), v.as_str().into_owned()))
+        .collect();
+    // Note: This needs to stay in scope or a disconnect will trigger.
+    let mut rx_from_worker =
+        setup_new_worker(&scheduler, worker_id, PlatformProperties { properties }).await?;
+    let mut action_listener =
+        setup_action(&scheduler, action_digest, action_props, make_system_time(1)).await?;
+
+    match rx_from_worker.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(_)) => { /* Success */ }
+        v => panic!("Expected StartAction, got : {v:?}"),
+    }
+    assert_eq!(
+        action_listener.changed().await.unwrap().0.stage,
+        ActionStage::Executing
+    );
+
+    // The worker never updates the action, and the client is not waiting
+    // for changes while the time passes.
+    MockClock::advance(Duration::from_secs(WORKER_TIMEOUT_S + 1));
+    for _ in 0..10 {
+        tokio::task::yield_now().await;
+    }
+    assert_eq!(
+        action_listener.changed().await.unwrap().0.stage,
+        ActionStage::Queued
+    );
+
+    Ok(())
+}
+
 #[nativelink_test]
 async fn update_action_sends_completed_result_to_client_test() -> Result<(), Error> {
     let worker_id: WorkerId = WorkerId(Uuid::new_v4());
@@ -1308,6 +1498,8 @@ async fn update_action_sends_completed_result_to_client_test() -> Result<(), Err
             execution_completed_timestamp: make_system_time(11),
             output_upload_start_timestamp: make_system_time(12),
             output_upload_completed_timestamp: make_system_time(13),
+            retries: 0,
+            oom_kill_memory_limit_bytes: None,
         },
         server_logs: HashMap::default(),
         error: None,
@@ -1416,6 +1608,8 @@ async fn update_action_sends_completed_result_after_disconnect() -> Result<(), E
             execution_completed_timestamp: make_system_time(11),
             output_upload_start_timestamp: make_system_time(12),
             output_upload_completed_timestamp: make_system_time(13),
+            retries: 0,
+            oom_kill_memory_limit_bytes: None,
         },
         server_logs: HashMap::default(),
         error: None,
@@ -1516,6 +1710,8 @@ async fn update_action_with_wrong_worker_id_errors_test() -> Result<(), Error> {
             execution_completed_timestamp: make_system_time(11),
             output_upload_start_timestamp: make_system_time(12),
             output_upload_completed_timestamp: make_system_time(13),
+            retries: 0,
+            oom_kill_memory_limit_bytes: None,
         },
         server_logs: HashMap::default(),
         error: None,
@@ -1606,6 +1802,9 @@ async fn does_not_crash_if_operation_joined_then_relaunched() -> Result<(), Erro
                 queued_timestamp: Some(insert_timestamp.into()),
                 platform: Some(Platform::default()),
                 worker_id: worker_id.to_string(),
+                trace_context: HashMap::new(),
+                request_id: String::new(),
+                timeout: None,
             })),
         };
         let msg_for_worker = rx_from_worker.recv().await.unwrap();
@@ -1650,6 +1849,8 @@ async fn does_not_crash_if_operation_joined_then_relaunched() -> Result<(), Erro
             execution_completed_timestamp: SystemTime::UNIX_EPOCH,
             output_upload_start_timestamp: SystemTime::UNIX_EPOCH,
             output_upload_completed_timestamp: SystemTime::UNIX_EPOCH,
+            retries: 0,
+            oom_kill_memory_limit_bytes: None,
         },
         server_logs: HashMap::default(),
         error: None,
@@ -1784,6 +1985,8 @@ async fn run_two_jobs_on_same_worker_with_platform_properties_restrictions() ->
             execution_completed_timestamp: make_system_time(11),
             output_upload_start_timestamp: make_system_time(12),
             output_upload_completed_timestamp: make_system_time(13),
+            retries: 0,
+            oom_kill_memory_limit_bytes: None,
         },
         server_logs: HashMap::default(),
         error: None,
@@ -1933,14 +2136,23 @@ async fn run_jobs_in_the_order_they_were_queued() -> Result<(), Error> {
     Ok(())
 }
 
+/// This tests that queued actions with a higher priority run first, and
+/// that client priorities are mapped by the scheduler config.
 #[nativelink_test]
-async fn worker_retries_on_internal_error_and_fails_test() -> Result<(), Error> {
+async fn run_jobs_in_order_of_mapped_priority_test() -> Result<(), Error> {
     let worker_id: WorkerId = WorkerId(Uuid::new_v4());
 
+    let mut supported_props = HashMap::new();
+    supported_props.insert("prop1".to_string(), PropertyType::minimum);
     let task_change_notify = Arc::new(Notify::new());
     let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
         &SimpleSpec {
-            max_job_retries: 1,
+            supported_platform_properties: Some(supported_props),
+            priority_mapping: PriorityMappingSpec {
+                invert: true,
+                min_priority: None,
+                max_priority: Some(5),
+            },
             ..Default::default()
         },
         memory_awaited_action_db_factory(
@@ -1953,92 +2165,509 @@ async fn worker_retries_on_internal_error_and_fails_test() -> Result<(), Error>
         MockInstantWrapped::default,
         None,
     );
-    let action_digest = DigestInfo::new([99u8; 32], 512);
 
-    let mut rx_from_worker =
-        setup_new_worker(&scheduler, worker_id, PlatformProperties::default()).await?;
-    let insert_timestamp = make_system_time(1);
-    let mut action_listener =
-        setup_action(&scheduler, action_digest, HashMap::new(), insert_timestamp).await?;
+    // Use property to restrict the worker to a single action at a time.
+    let mut properties = HashMap::new();
+    properties.insert("prop1".to_string(), PlatformPropertyValue::Minimum(1));
+    let action_props: HashMap<String, String> = properties
+        .iter()
+        .map(|(k, v)| (k.clone(), v.as_str().into_owned()))
+        .collect();
+    let platform_properties = PlatformProperties { properties };
 
-    let operation_id = {
-        // Other tests check full data. We only care if we got StartAction.
-        let operation_id = match rx_from_worker.recv().await.unwrap().update {
-            Some(update_for_worker::Update::StartAction(exec)) => exec.operation_id,
-            v => panic!("Expected StartAction, got : {v:?}"),
-        };
-        // Other tests check full data. We only care if client thinks we are Executing.
+    let mut action_listeners = Vec::new();
+    // The first action is queued first, but has the lowest priority once
+    // inverted. The other two are both clamped to 5 once inverted, so they
+    // run in the order they were queued.
+    for (digest_byte, priority, add_time) in [(1u8, 10, 1), (2, -10, 3), (3, -20, 2)] {
+        let mut action_info = make_base_action_info(
+            make_system_time(add_time),
+            DigestInfo::new([digest_byte; 32], 512),
+        );
+        let action_info_mut = Arc::make_mut(&mut action_info);
+        action_info_mut.platform_properties = action_props.clone();
+        action_info_mut.priority = priority;
+        action_listeners.push(
+            scheduler
+                .add_action(OperationId::default(), action_info)
+                .await?,
+        );
+    }
+
+    let mut rx_from_worker = setup_new_worker(&scheduler, worker_id, platform_properties).await?;
+    match rx_from_worker.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(start_execute)) => {
+            assert_eq!(
+                start_execute.execute_request.unwrap().action_digest,
+                Some(DigestInfo::new([3u8; 32], 512).into())
+            );
+        }
+        v => panic!("Expected StartAction, got : {v:?}"),
+    }
+    for (action_listener, expected_stage) in action_listeners.iter_mut().zip([
+        ActionStage::Queued,
+        ActionStage::Queued,
+        ActionStage::Executing,
+    ]) {
         assert_eq!(
             action_listener.changed().await.unwrap().0.stage,
-            ActionStage::Executing
+            expected_stage
         );
-        OperationId::from(operation_id.as_str())
-    };
+    }
 
-    let _ = scheduler
-        .update_action(
-            &worker_id,
-            &operation_id,
-            UpdateOperationType::UpdateWithError(make_err!(Code::Internal, "Some error")),
-        )
-        .await;
+    Ok(())
+}
 
-    {
-        // Client should get notification saying it has been queued again.
-        let (action_state, _maybe_origin_metadata) = action_listener.changed().await.unwrap();
-        let expected_action_state = ActionState {
-            // Name is a random string, so we ignore it and just make it the same.
-            client_operation_id: action_state.client_operation_id.clone(),
-            stage: ActionStage::Queued,
-            action_digest: action_state.action_digest,
-        };
-        assert_eq!(action_state.as_ref(), &expected_action_state);
+#[nativelink_test]
+async fn run_jobs_in_order_of_aged_priority_test() -> Result<(), Error> {
+    const PRIORITY_AGING_INTERVAL_S: u64 = 60;
+    let worker_id: WorkerId = WorkerId(Uuid::new_v4());
+
+    let mut supported_props = HashMap::new();
+    supported_props.insert("prop1".to_string(), PropertyType::minimum);
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec {
+            supported_platform_properties: Some(supported_props),
+            priority_aging_interval_s: PRIORITY_AGING_INTERVAL_S,
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+
+    // Use property to restrict the worker to a single action at a time.
+    let mut properties = HashMap::new();
+    properties.insert("prop1".to_string(), PlatformPropertyValue::Minimum(1));
+    let action_props: HashMap<String, String> = properties
+        .iter()
+        .map(|(k, v)| (k.clone(), v.as_str().into_owned()))
+        .collect();
+    let platform_properties = PlatformProperties { properties };
+
+    let now = SystemTime::now();
+    let queued_for = Duration::from_secs(PRIORITY_AGING_INTERVAL_S * 20);
+    let mut action_listeners = Vec::new();
+    // The first action has the lower priority, but it has been queued long
+    // enough to be raised above the second one.
+    for (digest_byte, priority, insert_timestamp) in [(1u8, 0, now - queued_for), (2, 10, now)] {
+        let mut action_info =
+            make_base_action_info(insert_timestamp, DigestInfo::new([digest_byte; 32], 512));
+        let action_info_mut = Arc::make_mut(&mut action_info);
+        action_info_mut.platform_properties = action_props.clone();
+        action_info_mut.priority = priority;
+        action_listeners.push(
+            scheduler
+                .add_action(OperationId::default(), action_info)
+                .await?,
+        );
     }
+    scheduler.do_try_match_for_test().await?;
+    assert!(scheduler.max_queue_age() >= queued_for);
 
-    // Now connect a new worker and it should pickup the action.
-    let mut rx_from_worker =
-        setup_new_worker(&scheduler, worker_id, PlatformProperties::default()).await?;
-    {
-        // Other tests check full data. We only care if we got StartAction.
-        match rx_from_worker.recv().await.unwrap().update {
-            Some(update_for_worker::Update::StartAction(_)) => { /* Success */ }
-            v => panic!("Expected StartAction, got : {v:?}"),
+    let mut rx_from_worker = setup_new_worker(&scheduler, worker_id, platform_properties).await?;
+    match rx_from_worker.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(start_execute)) => {
+            assert_eq!(
+                start_execute.execute_request.unwrap().action_digest,
+                Some(DigestInfo::new([1u8; 32], 512).into())
+            );
         }
-        // Other tests check full data. We only care if client thinks we are Executing.
+        v => panic!("Expected StartAction, got : {v:?}"),
+    }
+    for (action_listener, expected_stage) in action_listeners
+        .iter_mut()
+        .zip([ActionStage::Executing, ActionStage::Queued])
+    {
         assert_eq!(
             action_listener.changed().await.unwrap().0.stage,
-            ActionStage::Executing
+            expected_stage
         );
     }
 
-    let err = make_err!(Code::Internal, "Some error");
-    // Send internal error from worker again.
-    let _ = scheduler
-        .update_action(
-            &worker_id,
-            &operation_id,
-            UpdateOperationType::UpdateWithError(err.clone()),
-        )
-        .await;
+    Ok(())
+}
 
-    {
-        // Client should get notification saying it has been queued again.
+/// This tests that requesting an already queued action with a higher
+/// priority moves it up the queue.
+#[nativelink_test]
+async fn joining_queued_action_upgrades_priority_test() -> Result<(), Error> {
+    let worker_id: WorkerId = WorkerId(Uuid::new_v4());
+
+    let mut supported_props = HashMap::new();
+    supported_props.insert("prop1".to_string(), PropertyType::minimum);
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec {
+            supported_platform_properties: Some(supported_props),
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+    let action_digest1 = DigestInfo::new([11u8; 32], 512);
+    let action_digest2 = DigestInfo::new([99u8; 32], 512);
+
+    // Use property to restrict the worker to a single action at a time.
+    let mut properties = HashMap::new();
+    properties.insert("prop1".to_string(), PlatformPropertyValue::Minimum(1));
+    let action_props: HashMap<String, String> = properties
+        .iter()
+        .map(|(k, v)| (k.clone(), v.as_str().into_owned()))
+        .collect();
+    let platform_properties = PlatformProperties { properties };
+
+    let mut client1_action_listener = setup_action(
+        &scheduler,
+        action_digest1,
+        action_props.clone(),
+        make_system_time(1),
+    )
+    .await?;
+    let mut client2_action_listener = setup_action(
+        &scheduler,
+        action_digest2,
+        action_props.clone(),
+        make_system_time(2),
+    )
+    .await?;
+    // A second client asks for the action queued last with a higher priority.
+    let mut action_info = make_base_action_info(make_system_time(3), action_digest2);
+    let action_info_mut = Arc::make_mut(&mut action_info);
+    action_info_mut.platform_properties = action_props;
+    action_info_mut.priority = 10;
+    let mut client3_action_listener = scheduler
+        .add_action(OperationId::default(), action_info)
+        .await?;
+
+    let mut rx_from_worker = setup_new_worker(&scheduler, worker_id, platform_properties).await?;
+    match rx_from_worker.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(start_execute)) => {
+            assert_eq!(
+                start_execute.execute_request.unwrap().action_digest,
+                Some(action_digest2.into())
+            );
+        }
+        v => panic!("Expected StartAction, got : {v:?}"),
+    }
+    assert_eq!(
+        client1_action_listener.changed().await.unwrap().0.stage,
+        ActionStage::Queued
+    );
+    assert_eq!(
+        client2_action_listener.changed().await.unwrap().0.stage,
+        ActionStage::Executing
+    );
+    assert_eq!(
+        client3_action_listener.changed().await.unwrap().0.stage,
+        ActionStage::Executing
+    );
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn rejects_actions_when_queue_is_full_test() -> Result<(), Error> {
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec {
+            max_queued_actions: 2,
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+    let action_digest1 = DigestInfo::new([11u8; 32], 512);
+    let action_digest2 = DigestInfo::new([22u8; 32], 512);
+    let action_digest3 = DigestInfo::new([33u8; 32], 512);
+
+    // No worker is connected, so all actions stay queued.
+    let _client1_action_listener = setup_action(
+        &scheduler,
+        action_digest1,
+        HashMap::new(),
+        make_system_time(1),
+    )
+    .await?;
+    let _client2_action_listener = setup_action(
+        &scheduler,
+        action_digest2,
+        HashMap::new(),
+        make_system_time(2),
+    )
+    .await?;
+    let result = setup_action(
+        &scheduler,
+        action_digest3,
+        HashMap::new(),
+        make_system_time(3),
+    )
+    .await;
+    assert_eq!(
+        result.err().map(|err| err.code),
+        Some(Code::ResourceExhausted)
+    );
+
+    // Joining an action that is already queued does not grow the queue.
+    let mut client3_action_listener = setup_action(
+        &scheduler,
+        action_digest1,
+        HashMap::new(),
+        make_system_time(4),
+    )
+    .await?;
+    assert_eq!(
+        client3_action_listener.changed().await.unwrap().0.stage,
+        ActionStage::Queued
+    );
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn rejects_actions_when_pool_is_full_test() -> Result<(), Error> {
+    let mut supported_props = HashMap::new();
+    supported_props.insert("prop1".to_string(), PropertyType::minimum);
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec {
+            supported_platform_properties: Some(supported_props),
+            max_queued_actions_per_pool: 1,
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+    let small_props = HashMap::from([("prop1".to_string(), "1".to_string())]);
+    let large_props = HashMap::from([("prop1".to_string(), "8".to_string())]);
+
+    let _client1_action_listener = setup_action(
+        &scheduler,
+        DigestInfo::new([11u8; 32], 512),
+        small_props.clone(),
+        make_system_time(1),
+    )
+    .await?;
+    // Actions with other platform properties are queued in another pool.
+    let _client2_action_listener = setup_action(
+        &scheduler,
+        DigestInfo::new([22u8; 32], 512),
+        large_props,
+        make_system_time(2),
+    )
+    .await?;
+    let result = setup_action(
+        &scheduler,
+        DigestInfo::new([33u8; 32], 512),
+        small_props,
+        make_system_time(3),
+    )
+    .await;
+    assert_eq!(
+        result.err().map(|err| err.code),
+        Some(Code::ResourceExhausted)
+    );
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn dispatched_actions_leave_room_in_queue_test() -> Result<(), Error> {
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec {
+            max_queued_actions: 1,
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+    let action_digest1 = DigestInfo::new([11u8; 32], 512);
+    let action_digest2 = DigestInfo::new([22u8; 32], 512);
+
+    let _client1_action_listener = setup_action(
+        &scheduler,
+        action_digest1,
+        HashMap::new(),
+        make_system_time(1),
+    )
+    .await?;
+    let result = setup_action(
+        &scheduler,
+        action_digest2,
+        HashMap::new(),
+        make_system_time(2),
+    )
+    .await;
+    assert_eq!(
+        result.err().map(|err| err.code),
+        Some(Code::ResourceExhausted)
+    );
+
+    // Once the worker runs the first action, the queue is empty again.
+    let mut rx_from_worker = setup_new_worker(
+        &scheduler,
+        WorkerId(Uuid::new_v4()),
+        PlatformProperties::default(),
+    )
+    .await?;
+    scheduler.do_try_match_for_test().await?;
+    assert!(matches!(
+        rx_from_worker.recv().await.unwrap().update,
+        Some(update_for_worker::Update::StartAction(_))
+    ));
+    let _client2_action_listener = setup_action(
+        &scheduler,
+        action_digest2,
+        HashMap::new(),
+        make_system_time(3),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn worker_retries_on_internal_error_and_fails_test() -> Result<(), Error> {
+    let worker_id: WorkerId = WorkerId(Uuid::new_v4());
+
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec {
+            max_job_retries: 1,
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+    let action_digest = DigestInfo::new([99u8; 32], 512);
+
+    let mut rx_from_worker =
+        setup_new_worker(&scheduler, worker_id, PlatformProperties::default()).await?;
+    let insert_timestamp = make_system_time(1);
+    let mut action_listener =
+        setup_action(&scheduler, action_digest, HashMap::new(), insert_timestamp).await?;
+
+    let operation_id = {
+        // Other tests check full data. We only care if we got StartAction.
+        let operation_id = match rx_from_worker.recv().await.unwrap().update {
+            Some(update_for_worker::Update::StartAction(exec)) => exec.operation_id,
+            v => panic!("Expected StartAction, got : {v:?}"),
+        };
+        // Other tests check full data. We only care if client thinks we are Executing.
+        assert_eq!(
+            action_listener.changed().await.unwrap().0.stage,
+            ActionStage::Executing
+        );
+        OperationId::from(operation_id.as_str())
+    };
+
+    let _ = scheduler
+        .update_action(
+            &worker_id,
+            &operation_id,
+            UpdateOperationType::UpdateWithError(make_err!(Code::Internal, "Some error")),
+        )
+        .await;
+
+    {
+        // Client should get notification saying it has been queued again.
         let (action_state, _maybe_origin_metadata) = action_listener.changed().await.unwrap();
         let expected_action_state = ActionState {
             // Name is a random string, so we ignore it and just make it the same.
             client_operation_id: action_state.client_operation_id.clone(),
-            stage: ActionStage::Completed(ActionResult {
-                output_files: Vec::default(),
-                output_folders: Vec::default(),
-                output_file_symlinks: Vec::default(),
-                output_directory_symlinks: Vec::default(),
-                exit_code: INTERNAL_ERROR_EXIT_CODE,
-                stdout_digest: DigestInfo::zero_digest(),
-                stderr_digest: DigestInfo::zero_digest(),
-                execution_metadata: ExecutionMetadata {
-                    worker: worker_id.to_string(),
-                    queued_timestamp: SystemTime::UNIX_EPOCH,
-                    worker_start_timestamp: SystemTime::UNIX_EPOCH,
+            stage: ActionStage::Queued,
+            action_digest: action_state.action_digest,
+        };
+        assert_eq!(action_state.as_ref(), &expected_action_state);
+    }
+
+    // Now connect a new worker and it should pickup the action.
+    let mut rx_from_worker =
+        setup_new_worker(&scheduler, worker_id, PlatformProperties::default()).await?;
+    {
+        // Other tests check full data. We only care if we got StartAction.
+        match rx_from_worker.recv().await.unwrap().update {
+            Some(update_for_worker::Update::StartAction(_)) => { /* Success */ }
+            v => panic!("Expected StartAction, got : {v:?}"),
+        }
+        // Other tests check full data. We only care if client thinks we are Executing.
+        assert_eq!(
+            action_listener.changed().await.unwrap().0.stage,
+            ActionStage::Executing
+        );
+    }
+
+    let err = make_err!(Code::Internal, "Some error");
+    // Send internal error from worker again.
+    let _ = scheduler
+        .update_action(
+            &worker_id,
+            &operation_id,
+            UpdateOperationType::UpdateWithError(err.clone()),
+        )
+        .await;
+
+    {
+        // Client should get notification saying it has been queued again.
+        let (action_state, _maybe_origin_metadata) = action_listener.changed().await.unwrap();
+        let expected_action_state = ActionState {
+            // Name is a random string, so we ignore it and just make it the same.
+            client_operation_id: action_state.client_operation_id.clone(),
+            stage: ActionStage::Completed(ActionResult {
+                output_files: Vec::default(),
+                output_folders: Vec::default(),
+                output_file_symlinks: Vec::default(),
+                output_directory_symlinks: Vec::default(),
+                exit_code: INTERNAL_ERROR_EXIT_CODE,
+                stdout_digest: DigestInfo::zero_digest(),
+                stderr_digest: DigestInfo::zero_digest(),
+                execution_metadata: ExecutionMetadata {
+                    worker: worker_id.to_string(),
+                    queued_timestamp: SystemTime::UNIX_EPOCH,
+                    worker_start_timestamp: SystemTime::UNIX_EPOCH,
                     worker_completed_timestamp: SystemTime::UNIX_EPOCH,
                     input_fetch_start_timestamp: SystemTime::UNIX_EPOCH,
                     input_fetch_completed_timestamp: SystemTime::UNIX_EPOCH,
@@ -2046,87 +2675,868 @@ async fn worker_retries_on_internal_error_and_fails_test() -> Result<(), Error>
                     execution_completed_timestamp: SystemTime::UNIX_EPOCH,
                     output_upload_start_timestamp: SystemTime::UNIX_EPOCH,
                     output_upload_completed_timestamp: SystemTime::UNIX_EPOCH,
+                    retries: 1,
+                },
+                server_logs: HashMap::default(),
+                error: Some(err.clone()),
+                message: String::new(),
+            }),
+            action_digest: action_state.action_digest,
+        };
+        let mut received_state = action_state.as_ref().clone();
+        if let ActionStage::Completed(stage) = &mut received_state.stage {
+            if let Some(real_err) = &mut stage.error {
+                assert!(
+                    real_err.to_string().contains("Job cancelled because it attempted to execute too many times"),
+                    "{real_err} did not contain 'Job cancelled because it attempted to execute too many times'",
+                );
+                *real_err = err;
+            }
+        } else {
+            panic!("Expected Completed, got : {:?}", action_state.stage);
+        };
+        assert_eq!(received_state, expected_action_state);
+    }
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn worker_does_not_retry_unlisted_failure_kind_test() -> Result<(), Error> {
+    let worker_id: WorkerId = WorkerId(Uuid::new_v4());
+
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec {
+            retry_on_failures: vec![
+                JobFailureKind::worker_lost,
+                JobFailureKind::infrastructure_error,
+            ],
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+    let action_digest = DigestInfo::new([99u8; 32], 512);
+
+    let mut rx_from_worker =
+        setup_new_worker(&scheduler, worker_id, PlatformProperties::default()).await?;
+    let mut action_listener = setup_action(
+        &scheduler,
+        action_digest,
+        HashMap::new(),
+        make_system_time(1),
+    )
+    .await?;
+
+    let operation_id = match rx_from_worker.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(exec)) => {
+            OperationId::from(exec.operation_id.as_str())
+        }
+        v => panic!("Expected StartAction, got : {v:?}"),
+    };
+    assert_eq!(
+        action_listener.changed().await.unwrap().0.stage,
+        ActionStage::Executing
+    );
+
+    // Missing inputs will not be found by a retry either.
+    scheduler
+        .update_action(
+            &worker_id,
+            &operation_id,
+            UpdateOperationType::UpdateWithError(make_err!(
+                Code::FailedPrecondition,
+                "Missing input"
+            )),
+        )
+        .await?;
+
+    let (action_state, _maybe_origin_metadata) = action_listener.changed().await.unwrap();
+    let ActionStage::Completed(action_result) = &action_state.stage else {
+        panic!("Expected Completed, got : {:?}", action_state.stage);
+    };
+    let err = action_result.error.as_ref().unwrap();
+    assert_eq!(err.code, Code::FailedPrecondition);
+    assert!(
+        err.to_string().contains("not retried"),
+        "{err} did not contain 'not retried'"
+    );
+    assert_eq!(action_result.execution_metadata.retries, 0);
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn completed_action_reports_retries_after_worker_lost_test() -> Result<(), Error> {
+    let worker_id1: WorkerId = WorkerId(Uuid::new_v4());
+    let worker_id2: WorkerId = WorkerId(Uuid::new_v4());
+
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec::default(),
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+    let action_digest = DigestInfo::new([99u8; 32], 512);
+
+    let mut rx_from_worker1 =
+        setup_new_worker(&scheduler, worker_id1, PlatformProperties::default()).await?;
+    let mut action_listener = setup_action(
+        &scheduler,
+        action_digest,
+        HashMap::new(),
+        make_system_time(1),
+    )
+    .await?;
+    match rx_from_worker1.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(_)) => { /* Success */ }
+        v => panic!("Expected StartAction, got : {v:?}"),
+    }
+
+    // The worker goes away while running the action.
+    scheduler.remove_worker(&worker_id1).await?;
+    let mut rx_from_worker2 =
+        setup_new_worker(&scheduler, worker_id2, PlatformProperties::default()).await?;
+    let operation_id = match rx_from_worker2.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(exec)) => {
+            OperationId::from(exec.operation_id.as_str())
+        }
+        v => panic!("Expected StartAction, got : {v:?}"),
+    };
+
+    scheduler
+        .update_action(
+            &worker_id2,
+            &operation_id,
+            UpdateOperationType::UpdateWithActionStage(ActionStage::Completed(
+                ActionResult::default(),
+            )),
+        )
+        .await?;
+    loop {
+        let (action_state, _maybe_origin_metadata) = action_listener.changed().await.unwrap();
+        if let ActionStage::Completed(action_result) = &action_state.stage {
+            assert_eq!(action_result.execution_metadata.retries, 1);
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn ensure_scheduler_drops_inner_spawn() -> Result<(), Error> {
+    struct DropChecker {
+        dropped: Arc<AtomicBool>,
+    }
+    impl Drop for DropChecker {
+        fn drop(&mut self) {
+            self.dropped.store(true, Ordering::Relaxed);
+        }
+    }
+
+    let dropped = Arc::new(AtomicBool::new(false));
+    let drop_checker = Arc::new(DropChecker {
+        dropped: dropped.clone(),
+    });
+
+    // Since the inner spawn owns this callback, we can use the callback to know if the
+    // inner spawn was dropped because our callback would be dropped, which dropps our
+    // DropChecker.
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec::default(),
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        move || {
+            // This will ensure dropping happens if this function is ever dropped.
+            let _drop_checker = drop_checker.clone();
+            async move {}
+        },
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+    assert_eq!(dropped.load(Ordering::Relaxed), false);
+
+    drop(scheduler);
+    tokio::task::yield_now().await; // The drop may happen in a different task.
+
+    // Ensure our callback was dropped.
+    assert_eq!(dropped.load(Ordering::Relaxed), true);
+
+    Ok(())
+}
+
+/// Regression test for: https://github.com/TraceMachina/nativelink/issues/257.
+#[nativelink_test]
+async fn ensure_task_or_worker_change_notification_received_test() -> Result<(), Error> {
+    let worker_id1: WorkerId = WorkerId(Uuid::new_v4());
+    let worker_id2: WorkerId = WorkerId(Uuid::new_v4());
+
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec::default(),
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+    let action_digest = DigestInfo::new([99u8; 32], 512);
+
+    let mut rx_from_worker1 =
+        setup_new_worker(&scheduler, worker_id1, PlatformProperties::default()).await?;
+    let mut action_listener = setup_action(
+        &scheduler,
+        action_digest,
+        HashMap::new(),
+        make_system_time(1),
+    )
+    .await?;
+
+    let mut rx_from_worker2 =
+        setup_new_worker(&scheduler, worker_id2, PlatformProperties::default()).await?;
+
+    let operation_id = {
+        // Other tests check full data. We only care if we got StartAction.
+        let operation_id = match rx_from_worker1.recv().await.unwrap().update {
+            Some(update_for_worker::Update::StartAction(exec)) => exec.operation_id,
+            v => panic!("Expected StartAction, got : {v:?}"),
+        };
+        // Other tests check full data. We only care if client thinks we are Executing.
+        assert_eq!(
+            action_listener.changed().await.unwrap().0.stage,
+            ActionStage::Executing
+        );
+        OperationId::from(operation_id.as_str())
+    };
+
+    let _ = scheduler
+        .update_action(
+            &worker_id1,
+            &operation_id,
+            UpdateOperationType::UpdateWithError(make_err!(Code::NotFound, "Some error")),
+        )
+        .await;
+
+    tokio::task::yield_now().await; // Allow task<->worker matcher to run.
+
+    // Now connect a new worker and it should pickup the action.
+    {
+        // Other tests check full data. We only care if we got StartAction.
+        rx_from_worker2
+            .recv()
+            .await
+            .err_tip(|| "worker went away")?;
+        // Other tests check full data. We only care if client thinks we are Executing.
+        assert_eq!(
+            action_listener.changed().await.unwrap().0.stage,
+            ActionStage::Executing
+        );
+    }
+
+    Ok(())
+}
+
+// Note: This is a regression test for:
+// https://github.com/TraceMachina/nativelink/issues/1197
+#[nativelink_test]
+async fn client_reconnect_keeps_action_alive() -> Result<(), Error> {
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec {
+            worker_timeout_s: WORKER_TIMEOUT_S,
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+    let action_digest = DigestInfo::new([99u8; 32], 512);
+
+    let insert_timestamp = make_system_time(1);
+    let action_listener = setup_action(&scheduler, action_digest, HashMap::new(), insert_timestamp)
+        .await
+        .unwrap();
+
+    let client_id = action_listener
+        .as_state()
+        .await
+        .unwrap()
+        .0
+        .client_operation_id
+        .clone();
+
+    // Simulate client disconnecting.
+    drop(action_listener);
+
+    let mut new_action_listener = scheduler
+        .filter_operations(OperationFilter {
+            client_operation_id: Some(client_id.clone()),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .next()
+        .await
+        .expect("Action not found");
+
+    // We should get one notification saying it's queued.
+    assert_eq!(
+        new_action_listener.changed().await.unwrap().0.stage,
+        ActionStage::Queued
+    );
+
+    let changed_fut = new_action_listener.changed();
+    tokio::pin!(changed_fut);
+
+    // Now increment time and ensure the action does not get evicted.
+    for _ in 0..500 {
+        MockClock::advance(Duration::from_secs(2));
+        // All others should be pending.
+        assert_eq!(poll!(&mut changed_fut), Poll::Pending);
+        tokio::task::yield_now().await;
+        // Eviction happens when someone touches the internal
+        // evicting map. So we constantly ask for some other client
+        // to trigger eviction logic.
+        assert!(scheduler
+            .filter_operations(OperationFilter {
+                client_operation_id: Some(OperationId::from("dummy_client_id")),
+                ..Default::default()
+            })
+            .await
+            .unwrap()
+            .next()
+            .await
+            .is_none());
+    }
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn client_timesout_job_then_same_action_requested() -> Result<(), Error> {
+    const CLIENT_ACTION_TIMEOUT_S: u64 = 60;
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec {
+            worker_timeout_s: WORKER_TIMEOUT_S,
+            client_action_timeout_s: CLIENT_ACTION_TIMEOUT_S,
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+    let action_digest = DigestInfo::new([99u8; 32], 512);
+
+    {
+        let insert_timestamp = make_system_time(1);
+        let mut action_listener =
+            setup_action(&scheduler, action_digest, HashMap::new(), insert_timestamp)
+                .await
+                .unwrap();
+
+        // We should get one notification saying it's queued.
+        assert_eq!(
+            action_listener.changed().await.unwrap().0.stage,
+            ActionStage::Queued
+        );
+
+        let changed_fut = action_listener.changed();
+        tokio::pin!(changed_fut);
+
+        MockClock::advance(Duration::from_secs(2));
+        scheduler.do_try_match_for_test().await.unwrap();
+        assert_eq!(poll!(&mut changed_fut), Poll::Pending);
+    }
+
+    MockClock::advance(Duration::from_secs(CLIENT_ACTION_TIMEOUT_S + 1));
+
+    {
+        let insert_timestamp = make_system_time(1);
+        let mut action_listener =
+            setup_action(&scheduler, action_digest, HashMap::new(), insert_timestamp)
+                .await
+                .unwrap();
+
+        // We should get one notification saying it's queued.
+        assert_eq!(
+            action_listener.changed().await.unwrap().0.stage,
+            ActionStage::Queued
+        );
+
+        let changed_fut = action_listener.changed();
+        tokio::pin!(changed_fut);
+
+        MockClock::advance(Duration::from_secs(2));
+        tokio::task::yield_now().await;
+        assert_eq!(poll!(&mut changed_fut), Poll::Pending);
+    }
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn cancel_executing_action_kills_it_on_worker_test() -> Result<(), Error> {
+    let worker_id: WorkerId = WorkerId(Uuid::new_v4());
+
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec::default(),
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+    let action_digest = DigestInfo::new([99u8; 32], 512);
+
+    let mut rx_from_worker =
+        setup_new_worker(&scheduler, worker_id, PlatformProperties::default()).await?;
+    let insert_timestamp = make_system_time(1);
+    let mut action_listener =
+        setup_action(&scheduler, action_digest, HashMap::new(), insert_timestamp).await?;
+
+    let operation_id = match rx_from_worker.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(start_execute)) => {
+            assert_eq!(
+                action_listener.changed().await.unwrap().0.stage,
+                ActionStage::Executing
+            );
+            start_execute.operation_id
+        }
+        v => panic!("Expected StartAction, got : {v:?}"),
+    };
+
+    let client_operation_id = action_listener
+        .as_state()
+        .await?
+        .0
+        .client_operation_id
+        .clone();
+    scheduler.cancel_operation(&client_operation_id).await?;
+
+    {
+        // Worker should have been asked to kill the operation.
+        let expected_msg_for_worker = UpdateForWorker {
+            update: Some(update_for_worker::Update::KillOperationRequest(
+                KillOperationRequest {
+                    operation_id: operation_id.clone(),
                 },
-                server_logs: HashMap::default(),
-                error: Some(err.clone()),
-                message: String::new(),
-            }),
-            action_digest: action_state.action_digest,
+            )),
         };
-        let mut received_state = action_state.as_ref().clone();
-        if let ActionStage::Completed(stage) = &mut received_state.stage {
-            if let Some(real_err) = &mut stage.error {
-                assert!(
-                    real_err.to_string().contains("Job cancelled because it attempted to execute too many times"),
-                    "{real_err} did not contain 'Job cancelled because it attempted to execute too many times'",
-                );
-                *real_err = err;
-            }
-        } else {
+        assert_eq!(
+            rx_from_worker.recv().await.unwrap(),
+            expected_msg_for_worker
+        );
+    }
+    {
+        // Client should see the operation completed as cancelled.
+        let (action_state, _maybe_origin_metadata) = action_listener.changed().await?;
+        let ActionStage::Completed(action_result) = &action_state.stage else {
             panic!("Expected Completed, got : {:?}", action_state.stage);
         };
-        assert_eq!(received_state, expected_action_state);
+        assert_eq!(action_result.error.as_ref().unwrap().code, Code::Cancelled);
     }
 
+    // The worker reporting the killed operation must not evict it.
+    scheduler
+        .update_action(
+            &worker_id,
+            &OperationId::from(operation_id),
+            UpdateOperationType::UpdateWithError(make_err!(Code::Aborted, "Killed")),
+        )
+        .await?;
+
     Ok(())
 }
 
 #[nativelink_test]
-async fn ensure_scheduler_drops_inner_spawn() -> Result<(), Error> {
-    struct DropChecker {
-        dropped: Arc<AtomicBool>,
+async fn cancel_queued_action_test() -> Result<(), Error> {
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec::default(),
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+    let action_digest = DigestInfo::new([99u8; 32], 512);
+
+    let insert_timestamp = make_system_time(1);
+    let mut action_listener =
+        setup_action(&scheduler, action_digest, HashMap::new(), insert_timestamp).await?;
+    assert_eq!(
+        action_listener.changed().await?.0.stage,
+        ActionStage::Queued
+    );
+
+    let client_operation_id = action_listener
+        .as_state()
+        .await?
+        .0
+        .client_operation_id
+        .clone();
+    scheduler.cancel_operation(&client_operation_id).await?;
+    {
+        let (action_state, _maybe_origin_metadata) = action_listener.changed().await?;
+        let ActionStage::Completed(action_result) = &action_state.stage else {
+            panic!("Expected Completed, got : {:?}", action_state.stage);
+        };
+        assert_eq!(action_result.error.as_ref().unwrap().code, Code::Cancelled);
     }
-    impl Drop for DropChecker {
-        fn drop(&mut self) {
-            self.dropped.store(true, Ordering::Relaxed);
+
+    // Cancelling a finished operation does nothing.
+    scheduler.cancel_operation(&client_operation_id).await?;
+
+    let err = scheduler
+        .cancel_operation(&OperationId::from("unknown_operation"))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code, Code::NotFound, "{err:?}");
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn cancel_joined_action_detaches_client_test() -> Result<(), Error> {
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec::default(),
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+    let action_digest = DigestInfo::new([99u8; 32], 512);
+
+    let mut client1_action_listener = setup_action(
+        &scheduler,
+        action_digest,
+        HashMap::new(),
+        make_system_time(1),
+    )
+    .await?;
+    let mut client2_action_listener = setup_action(
+        &scheduler,
+        action_digest,
+        HashMap::new(),
+        make_system_time(2),
+    )
+    .await?;
+    let (client1_action_state, _maybe_origin_metadata) = client1_action_listener.changed().await?;
+    let (client2_action_state, _maybe_origin_metadata) = client2_action_listener.changed().await?;
+    assert_eq!(client2_action_state.stage, ActionStage::Queued);
+
+    // The operation keeps running for the other client.
+    scheduler
+        .cancel_operation(&client1_action_state.client_operation_id)
+        .await?;
+    assert_eq!(
+        client2_action_listener.as_state().await?.0.stage,
+        ActionStage::Queued
+    );
+
+    // The last client cancels the operation.
+    scheduler
+        .cancel_operation(&client2_action_state.client_operation_id)
+        .await?;
+    {
+        let (action_state, _maybe_origin_metadata) = client2_action_listener.changed().await?;
+        let ActionStage::Completed(action_result) = &action_state.stage else {
+            panic!("Expected Completed, got : {:?}", action_state.stage);
+        };
+        assert_eq!(action_result.error.as_ref().unwrap().code, Code::Cancelled);
+    }
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn action_timeouts_are_defaulted_and_clamped_test() -> Result<(), Error> {
+    const DEFAULT_ACTION_TIMEOUT_S: u64 = 60;
+    const MAX_ACTION_TIMEOUT_S: u64 = 600;
+    let worker_id: WorkerId = WorkerId(Uuid::new_v4());
+
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec {
+            default_action_timeout_s: DEFAULT_ACTION_TIMEOUT_S,
+            max_action_timeout_s: MAX_ACTION_TIMEOUT_S,
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+
+    let mut rx_from_worker =
+        setup_new_worker(&scheduler, worker_id, PlatformProperties::default()).await?;
+    for (action_timeout, expected_timeout) in [
+        (Duration::ZERO, DEFAULT_ACTION_TIMEOUT_S),
+        (Duration::from_secs(120), 120),
+        (Duration::from_secs(3600), MAX_ACTION_TIMEOUT_S),
+    ] {
+        let mut action_info = make_base_action_info(
+            make_system_time(1),
+            DigestInfo::new([u8::try_from(expected_timeout % 256).unwrap(); 32], 512),
+        );
+        Arc::make_mut(&mut action_info).timeout = action_timeout;
+        let _action_listener = scheduler
+            .add_action(OperationId::default(), action_info)
+            .await?;
+        tokio::task::yield_now().await; // Allow task<->worker matcher to run.
+
+        match rx_from_worker.recv().await.unwrap().update {
+            Some(update_for_worker::Update::StartAction(start_execute)) => {
+                assert_eq!(
+                    start_execute.timeout,
+                    Some(Duration::from_secs(expected_timeout).try_into().unwrap())
+                );
+            }
+            v => panic!("Expected StartAction, got : {v:?}"),
         }
     }
 
-    let dropped = Arc::new(AtomicBool::new(false));
-    let drop_checker = Arc::new(DropChecker {
-        dropped: dropped.clone(),
-    });
+    Ok(())
+}
+
+#[nativelink_test]
+async fn action_exceeding_timeout_is_completed_test() -> Result<(), Error> {
+    const MAX_ACTION_TIMEOUT_S: u64 = 10;
+    let worker_id: WorkerId = WorkerId(Uuid::new_v4());
 
-    // Since the inner spawn owns this callback, we can use the callback to know if the
-    // inner spawn was dropped because our callback would be dropped, which dropps our
-    // DropChecker.
     let task_change_notify = Arc::new(Notify::new());
     let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
-        &SimpleSpec::default(),
+        &SimpleSpec {
+            worker_timeout_s: WORKER_TIMEOUT_S,
+            max_action_timeout_s: MAX_ACTION_TIMEOUT_S,
+            ..Default::default()
+        },
         memory_awaited_action_db_factory(
             0,
             &task_change_notify.clone(),
             MockInstantWrapped::default,
         ),
-        move || {
-            // This will ensure dropping happens if this function is ever dropped.
-            let _drop_checker = drop_checker.clone();
-            async move {}
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+    let action_digest = DigestInfo::new([99u8; 32], 512);
+    MockClock::advance(Duration::from_secs(NOW_TIME));
+
+    let mut rx_from_worker =
+        setup_new_worker(&scheduler, worker_id, PlatformProperties::default()).await?;
+    let mut action_listener = setup_action(
+        &scheduler,
+        action_digest,
+        HashMap::new(),
+        make_system_time(1),
+    )
+    .await?;
+
+    let operation_id = match rx_from_worker.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(start_execute)) => start_execute.operation_id,
+        v => panic!("Expected StartAction, got : {v:?}"),
+    };
+    assert_eq!(
+        action_listener.changed().await.unwrap().0.stage,
+        ActionStage::Executing
+    );
+
+    // The worker neither finishes nor updates the action once its timeout
+    // and the worker timeout have passed.
+    MockClock::advance(Duration::from_secs(
+        MAX_ACTION_TIMEOUT_S + WORKER_TIMEOUT_S + 1,
+    ));
+    for _ in 0..10 {
+        tokio::task::yield_now().await;
+    }
+    {
+        let (action_state, _maybe_origin_metadata) = action_listener.changed().await.unwrap();
+        let ActionStage::Completed(action_result) = &action_state.stage else {
+            panic!("Expected Completed, got : {:?}", action_state.stage);
+        };
+        assert_eq!(
+            action_result.error.as_ref().unwrap().code,
+            Code::DeadlineExceeded
+        );
+    }
+    // The worker is told to stop running the action.
+    assert_eq!(
+        rx_from_worker.recv().await.unwrap(),
+        UpdateForWorker {
+            update: Some(update_for_worker::Update::KillOperationRequest(
+                KillOperationRequest { operation_id },
+            )),
+        }
+    );
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn autoscaling_signals_are_reported_per_pool_test() -> Result<(), Error> {
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec {
+            supported_platform_properties: Some(HashMap::from([(
+                "pool".to_string(),
+                PropertyType::exact,
+            )])),
+            autoscaling: Some(AutoscalingSpec {
+                pool_properties: vec!["pool".to_string()],
+                ..Default::default()
+            }),
+            ..Default::default()
         },
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
         task_change_notify,
         MockInstantWrapped::default,
         None,
     );
-    assert_eq!(dropped.load(Ordering::Relaxed), false);
+    let worker_properties = |pool: &str| {
+        PlatformProperties::new(HashMap::from([(
+            "pool".to_string(),
+            PlatformPropertyValue::Exact(pool.to_string()),
+        )]))
+    };
+    let mut rx_from_cpu_worker = setup_new_worker(
+        &scheduler,
+        WorkerId(Uuid::new_v4()),
+        worker_properties("cpu"),
+    )
+    .await?;
+    let _rx_from_arm_worker = setup_new_worker(
+        &scheduler,
+        WorkerId(Uuid::new_v4()),
+        worker_properties("arm"),
+    )
+    .await?;
 
-    drop(scheduler);
-    tokio::task::yield_now().await; // The drop may happen in a different task.
+    let _cpu_action_listener = setup_action(
+        &scheduler,
+        DigestInfo::new([1u8; 32], 512),
+        HashMap::from([("pool".to_string(), "cpu".to_string())]),
+        make_system_time(1),
+    )
+    .await?;
+    match rx_from_cpu_worker.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(_)) => {}
+        v => panic!("Expected StartAction, got : {v:?}"),
+    }
+    // No worker can run actions of the gpu pool.
+    let _gpu_action_listener = setup_action(
+        &scheduler,
+        DigestInfo::new([2u8; 32], 512),
+        HashMap::from([("pool".to_string(), "gpu".to_string())]),
+        SystemTime::now(),
+    )
+    .await?;
 
-    // Ensure our callback was dropped.
-    assert_eq!(dropped.load(Ordering::Relaxed), true);
+    assert!(scheduler.autoscaling_signals().is_empty());
+    scheduler.update_autoscaling_signals_for_test().await?;
+    let signals = scheduler.autoscaling_signals();
+    assert_eq!(
+        signals.keys().collect::<Vec<_>>(),
+        vec!["pool=arm", "pool=cpu", "pool=gpu"]
+    );
+    assert_eq!(
+        signals["pool=arm"],
+        PoolSignals {
+            idle_workers: 1,
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        signals["pool=cpu"],
+        PoolSignals {
+            executing_actions: 1,
+            busy_workers: 1,
+            desired_workers: 1,
+            ..Default::default()
+        }
+    );
+    assert_eq!(signals["pool=gpu"].queued_actions, 1);
+    assert_eq!(signals["pool=gpu"].desired_workers, 1);
+    assert_eq!(signals["pool=gpu"].busy_workers, 0);
 
     Ok(())
 }
 
-/// Regression test for: https://github.com/TraceMachina/nativelink/issues/257.
 #[nativelink_test]
-async fn ensure_task_or_worker_change_notification_received_test() -> Result<(), Error> {
-    let worker_id1: WorkerId = WorkerId(Uuid::new_v4());
-    let worker_id2: WorkerId = WorkerId(Uuid::new_v4());
-
+async fn straggler_is_hedged_on_another_worker_test() -> Result<(), Error> {
     let task_change_notify = Arc::new(Notify::new());
     let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
-        &SimpleSpec::default(),
+        &SimpleSpec {
+            experimental_hedging: Some(HedgingSpec {
+                min_samples: 1,
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
         memory_awaited_action_db_factory(
             0,
             &task_change_notify.clone(),
@@ -2137,70 +3547,106 @@ async fn ensure_task_or_worker_change_notification_received_test() -> Result<(),
         MockInstantWrapped::default,
         None,
     );
-    let action_digest = DigestInfo::new([99u8; 32], 512);
-
+    let start_action = |update: Option<update_for_worker::Update>| match update {
+        Some(update_for_worker::Update::StartAction(start_execute)) => {
+            OperationId::from(start_execute.operation_id)
+        }
+        v => panic!("Expected StartAction, got : {v:?}"),
+    };
+    let worker_id1 = WorkerId(Uuid::new_v4());
     let mut rx_from_worker1 =
         setup_new_worker(&scheduler, worker_id1, PlatformProperties::default()).await?;
-    let mut action_listener = setup_action(
+
+    // A quick first run of the command tells how long it usually takes.
+    let _first_action_listener = setup_action(
         &scheduler,
-        action_digest,
+        DigestInfo::new([1u8; 32], 512),
         HashMap::new(),
         make_system_time(1),
     )
     .await?;
+    let operation_id = start_action(rx_from_worker1.recv().await.unwrap().update);
+    scheduler
+        .update_action(
+            &worker_id1,
+            &operation_id,
+            UpdateOperationType::UpdateWithActionStage(ActionStage::Completed(
+                ActionResult::default(),
+            )),
+        )
+        .await?;
 
+    let mut action_listener = setup_action(
+        &scheduler,
+        DigestInfo::new([2u8; 32], 512),
+        HashMap::new(),
+        make_system_time(2),
+    )
+    .await?;
+    let original_operation_id = start_action(rx_from_worker1.recv().await.unwrap().update);
+    let worker_id2 = WorkerId(Uuid::new_v4());
     let mut rx_from_worker2 =
         setup_new_worker(&scheduler, worker_id2, PlatformProperties::default()).await?;
 
-    let operation_id = {
-        // Other tests check full data. We only care if we got StartAction.
-        let operation_id = match rx_from_worker1.recv().await.unwrap().update {
-            Some(update_for_worker::Update::StartAction(exec)) => exec.operation_id,
-            v => panic!("Expected StartAction, got : {v:?}"),
-        };
-        // Other tests check full data. We only care if client thinks we are Executing.
-        assert_eq!(
-            action_listener.changed().await.unwrap().0.stage,
-            ActionStage::Executing
-        );
-        OperationId::from(operation_id.as_str())
-    };
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    scheduler.hedge_stragglers_for_test().await?;
+    scheduler.do_try_match_for_test().await?;
+    let hedge_operation_id = start_action(rx_from_worker2.recv().await.unwrap().update);
+    assert_ne!(hedge_operation_id, original_operation_id);
 
-    let _ = scheduler
+    // The copy finishes first, so the client gets its result and the
+    // original is killed.
+    let action_result = ActionResult {
+        exit_code: 0,
+        execution_metadata: ExecutionMetadata {
+            worker: worker_id2.to_string(),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    scheduler
         .update_action(
-            &worker_id1,
-            &operation_id,
-            UpdateOperationType::UpdateWithError(make_err!(Code::NotFound, "Some error")),
+            &worker_id2,
+            &hedge_operation_id,
+            UpdateOperationType::UpdateWithActionStage(ActionStage::Completed(
+                action_result.clone(),
+            )),
         )
-        .await;
-
-    tokio::task::yield_now().await; // Allow task<->worker matcher to run.
-
-    // Now connect a new worker and it should pickup the action.
-    {
-        // Other tests check full data. We only care if we got StartAction.
-        rx_from_worker2
-            .recv()
-            .await
-            .err_tip(|| "worker went away")?;
-        // Other tests check full data. We only care if client thinks we are Executing.
-        assert_eq!(
-            action_listener.changed().await.unwrap().0.stage,
-            ActionStage::Executing
-        );
+        .await?;
+    loop {
+        let (action_state, _maybe_origin_metadata) = action_listener.changed().await?;
+        if action_state.stage.is_finished() {
+            assert_eq!(action_state.stage, ActionStage::Completed(action_result));
+            break;
+        }
     }
+    assert_eq!(
+        rx_from_worker1.recv().await.unwrap(),
+        UpdateForWorker {
+            update: Some(update_for_worker::Update::KillOperationRequest(
+                KillOperationRequest {
+                    operation_id: original_operation_id.to_string(),
+                },
+            )),
+        }
+    );
 
     Ok(())
 }
 
-// Note: This is a regression test for:
-// https://github.com/TraceMachina/nativelink/issues/1197
 #[nativelink_test]
-async fn client_reconnect_keeps_action_alive() -> Result<(), Error> {
+async fn sub_queues_are_matched_independently_test() -> Result<(), Error> {
     let task_change_notify = Arc::new(Notify::new());
     let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
         &SimpleSpec {
-            worker_timeout_s: WORKER_TIMEOUT_S,
+            supported_platform_properties: Some(HashMap::from([(
+                "pool".to_string(),
+                PropertyType::exact,
+            )])),
+            sub_queues: Some(SubQueuesSpec {
+                properties: vec!["pool".to_string()],
+                ..Default::default()
+            }),
             ..Default::default()
         },
         memory_awaited_action_db_factory(
@@ -2213,76 +3659,70 @@ async fn client_reconnect_keeps_action_alive() -> Result<(), Error> {
         MockInstantWrapped::default,
         None,
     );
-    let action_digest = DigestInfo::new([99u8; 32], 512);
-
-    let insert_timestamp = make_system_time(1);
-    let action_listener = setup_action(&scheduler, action_digest, HashMap::new(), insert_timestamp)
-        .await
-        .unwrap();
-
-    let client_id = action_listener
-        .as_state()
-        .await
-        .unwrap()
-        .0
-        .client_operation_id
-        .clone();
-
-    // Simulate client disconnecting.
-    drop(action_listener);
-
-    let mut new_action_listener = scheduler
-        .filter_operations(OperationFilter {
-            client_operation_id: Some(client_id.clone()),
-            ..Default::default()
-        })
-        .await
-        .unwrap()
-        .next()
-        .await
-        .expect("Action not found");
-
-    // We should get one notification saying it's queued.
-    assert_eq!(
-        new_action_listener.changed().await.unwrap().0.stage,
-        ActionStage::Queued
-    );
-
-    let changed_fut = new_action_listener.changed();
-    tokio::pin!(changed_fut);
+    let mut rx_from_mac_worker = setup_new_worker(
+        &scheduler,
+        WorkerId(Uuid::new_v4()),
+        PlatformProperties::new(HashMap::from([(
+            "pool".to_string(),
+            PlatformPropertyValue::Exact("mac".to_string()),
+        )])),
+    )
+    .await?;
+    // No worker can run the linux actions, so they stay queued.
+    for i in 0..3 {
+        let _linux_action_listener = setup_action(
+            &scheduler,
+            DigestInfo::new([i; 32], 512),
+            HashMap::from([("pool".to_string(), "linux".to_string())]),
+            make_system_time(1),
+        )
+        .await?;
+    }
+    let _mac_action_listener = setup_action(
+        &scheduler,
+        DigestInfo::new([9u8; 32], 512),
+        HashMap::from([("pool".to_string(), "mac".to_string())]),
+        make_system_time(2),
+    )
+    .await?;
 
-    // Now increment time and ensure the action does not get evicted.
-    for _ in 0..500 {
-        MockClock::advance(Duration::from_secs(2));
-        // All others should be pending.
-        assert_eq!(poll!(&mut changed_fut), Poll::Pending);
-        tokio::task::yield_now().await;
-        // Eviction happens when someone touches the internal
-        // evicting map. So we constantly ask for some other client
-        // to trigger eviction logic.
-        assert!(scheduler
-            .filter_operations(OperationFilter {
-                client_operation_id: Some(OperationId::from("dummy_client_id")),
-                ..Default::default()
-            })
-            .await
-            .unwrap()
-            .next()
-            .await
-            .is_none());
+    scheduler.do_try_match_for_test().await?;
+    assert_eq!(scheduler.sub_queue_names(), vec!["pool=linux", "pool=mac"]);
+    // The matching loop of the mac sub-queue dispatches its action.
+    match rx_from_mac_worker.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(_)) => {}
+        v => panic!("Expected StartAction, got : {v:?}"),
     }
 
+    scheduler
+        .do_try_match_sub_queue_for_test("pool=linux")
+        .await?;
+    scheduler
+        .do_try_match_sub_queue_for_test("pool=mac")
+        .await?;
+    let linux_queue = scheduler.sub_queue("pool=linux").unwrap();
+    assert_eq!(linux_queue.queued_actions(), 3);
+    assert_eq!(linux_queue.dispatched_actions(), 0);
+    let mac_queue = scheduler.sub_queue("pool=mac").unwrap();
+    assert_eq!(mac_queue.queued_actions(), 0);
+    assert_eq!(mac_queue.dispatched_actions(), 1);
+
     Ok(())
 }
 
 #[nativelink_test]
-async fn client_timesout_job_then_same_action_requested() -> Result<(), Error> {
-    const CLIENT_ACTION_TIMEOUT_S: u64 = 60;
+async fn full_sub_queue_rejects_actions_test() -> Result<(), Error> {
     let task_change_notify = Arc::new(Notify::new());
     let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
         &SimpleSpec {
-            worker_timeout_s: WORKER_TIMEOUT_S,
-            client_action_timeout_s: CLIENT_ACTION_TIMEOUT_S,
+            supported_platform_properties: Some(HashMap::from([(
+                "pool".to_string(),
+                PropertyType::exact,
+            )])),
+            sub_queues: Some(SubQueuesSpec {
+                properties: vec!["pool".to_string()],
+                max_queued_actions: 1,
+            }),
             ..Default::default()
         },
         memory_awaited_action_db_factory(
@@ -2295,51 +3735,33 @@ async fn client_timesout_job_then_same_action_requested() -> Result<(), Error> {
         MockInstantWrapped::default,
         None,
     );
-    let action_digest = DigestInfo::new([99u8; 32], 512);
-
-    {
-        let insert_timestamp = make_system_time(1);
-        let mut action_listener =
-            setup_action(&scheduler, action_digest, HashMap::new(), insert_timestamp)
-                .await
-                .unwrap();
-
-        // We should get one notification saying it's queued.
-        assert_eq!(
-            action_listener.changed().await.unwrap().0.stage,
-            ActionStage::Queued
-        );
-
-        let changed_fut = action_listener.changed();
-        tokio::pin!(changed_fut);
-
-        MockClock::advance(Duration::from_secs(2));
-        scheduler.do_try_match_for_test().await.unwrap();
-        assert_eq!(poll!(&mut changed_fut), Poll::Pending);
-    }
-
-    MockClock::advance(Duration::from_secs(CLIENT_ACTION_TIMEOUT_S + 1));
-
-    {
-        let insert_timestamp = make_system_time(1);
-        let mut action_listener =
-            setup_action(&scheduler, action_digest, HashMap::new(), insert_timestamp)
-                .await
-                .unwrap();
-
-        // We should get one notification saying it's queued.
-        assert_eq!(
-            action_listener.changed().await.unwrap().0.stage,
-            ActionStage::Queued
-        );
-
-        let changed_fut = action_listener.changed();
-        tokio::pin!(changed_fut);
-
-        MockClock::advance(Duration::from_secs(2));
-        tokio::task::yield_now().await;
-        assert_eq!(poll!(&mut changed_fut), Poll::Pending);
-    }
+    let linux_props = HashMap::from([("pool".to_string(), "linux".to_string())]);
+    let _linux_action_listener = setup_action(
+        &scheduler,
+        DigestInfo::new([1u8; 32], 512),
+        linux_props.clone(),
+        make_system_time(1),
+    )
+    .await?;
+    let result = setup_action(
+        &scheduler,
+        DigestInfo::new([2u8; 32], 512),
+        linux_props,
+        make_system_time(2),
+    )
+    .await;
+    assert_eq!(
+        result.err().map(|err| err.code),
+        Some(Code::ResourceExhausted)
+    );
+    // Other sub-queues still have room.
+    let _mac_action_listener = setup_action(
+        &scheduler,
+        DigestInfo::new([3u8; 32], 512),
+        HashMap::from([("pool".to_string(), "mac".to_string())]),
+        make_system_time(3),
+    )
+    .await?;
 
     Ok(())
 }
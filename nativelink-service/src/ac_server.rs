@@ -28,8 +28,12 @@ use nativelink_proto::build::bazel::remote::execution::v2::{
 use nativelink_store::ac_utils::{get_and_decode_digest, ESTIMATED_DIGEST_SIZE};
 use nativelink_store::grpc_store::GrpcStore;
 use nativelink_store::store_manager::StoreManager;
+use nativelink_util::audit_log::{audit_log, audit_record, AuditOperation, AuditRecord};
 use nativelink_util::common::DigestInfo;
-use nativelink_util::digest_hasher::make_ctx_for_hash_func;
+use nativelink_util::digest_hasher::make_ctx_for_instance_hash_func;
+use nativelink_util::histogram_metrics::RpcTimer;
+use nativelink_util::instance_access::InstanceAccess;
+use nativelink_util::origin_context::make_ctx_for_instance_name;
 use nativelink_util::origin_event::OriginEventContext;
 use nativelink_util::store_trait::{Store, StoreLike};
 use prost::Message;
@@ -40,6 +44,7 @@ use tracing::{error_span, event, instrument, Level};
 pub struct AcStoreInfo {
     store: Store,
     read_only: bool,
+    access: InstanceAccess,
 }
 
 pub struct AcServer {
@@ -67,6 +72,7 @@ impl AcServer {
                 AcStoreInfo {
                     store,
                     read_only: ac_cfg.read_only,
+                    access: InstanceAccess::new(&ac_cfg.access),
                 },
             );
         }
@@ -88,6 +94,7 @@ impl AcServer {
             .stores
             .get(instance_name)
             .err_tip(|| format!("'instance_name' not configured for '{instance_name}'"))?;
+        store_info.access.check_read(instance_name)?;
 
         // TODO(blaise.bruer) We should write a test for these errors.
         let digest: DigestInfo = request
@@ -134,6 +141,7 @@ impl AcServer {
                 "The store '{instance_name}' is read only on this endpoint",
             ));
         }
+        store_info.access.check_write(instance_name)?;
 
         let digest: DigestInfo = request
             .action_digest
@@ -158,9 +166,12 @@ impl AcServer {
             .encode(&mut store_data)
             .err_tip(|| "Provided ActionResult could not be serialized")?;
 
-        store_info
-            .store
-            .update_oneshot(digest, store_data.freeze())
+        make_ctx_for_instance_name(instance_name)
+            .err_tip(|| "In AcServer::inner_update_action_result")?
+            .wrap_async(
+                error_span!("ac_server_store_update"),
+                store_info.store.update_oneshot(digest, store_data.freeze()),
+            )
             .await
             .err_tip(|| "Failed to update in action cache")?;
         Ok(Response::new(action_result))
@@ -182,8 +193,13 @@ impl ActionCache for AcServer {
     ) -> Result<Response<ActionResult>, Status> {
         let request = grpc_request.into_inner();
         let ctx = OriginEventContext::new(|| &request).await;
+        let timer = RpcTimer::new("ActionCache", "GetActionResult", &request.instance_name);
+        let audit = audit_record(|| {
+            AuditRecord::new(AuditOperation::AcRead, &request.instance_name)
+                .with_digests(request.action_digest.as_ref())
+        });
 
-        let resp = make_ctx_for_hash_func(request.digest_function)
+        let resp = make_ctx_for_instance_hash_func(&request.instance_name, request.digest_function)
             .err_tip(|| "In AcServer::get_action_result")?
             .wrap_async(
                 error_span!("ac_server_get_action_result"),
@@ -195,6 +211,8 @@ impl ActionCache for AcServer {
             event!(Level::ERROR, return = ?resp);
         }
         let resp = resp.map_err(Into::into);
+        timer.observe(&resp);
+        audit_log(audit, &resp).await;
         ctx.emit(|| &resp).await;
         resp
     }
@@ -213,7 +231,17 @@ impl ActionCache for AcServer {
     ) -> Result<Response<ActionResult>, Status> {
         let request = grpc_request.into_inner();
         let ctx = OriginEventContext::new(|| &request).await;
-        let resp = make_ctx_for_hash_func(request.digest_function)
+        let timer = RpcTimer::new("ActionCache", "UpdateActionResult", &request.instance_name);
+        let audit = audit_record(|| {
+            let mut record = AuditRecord::new(AuditOperation::AcWrite, &request.instance_name)
+                .with_digests(request.action_digest.as_ref());
+            record.upload_bytes = request
+                .action_result
+                .as_ref()
+                .map(|action_result| action_result.encoded_len() as u64);
+            record
+        });
+        let resp = make_ctx_for_instance_hash_func(&request.instance_name, request.digest_function)
             .err_tip(|| "In AcServer::update_action_result")?
             .wrap_async(
                 error_span!("ac_server_update_action_result"),
@@ -221,6 +249,8 @@ impl ActionCache for AcServer {
             )
             .await
             .map_err(Into::into);
+        timer.observe(&resp);
+        audit_log(audit, &resp).await;
         ctx.emit(|| &resp).await;
         resp
     }
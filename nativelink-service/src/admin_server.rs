@@ -0,0 +1,350 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use axum::body::Body;
+use axum::extract::Path;
+use axum::routing::{get, post};
+use axum::Router;
+use futures::StreamExt;
+use hyper::header::CONTENT_TYPE;
+use hyper::{Response, StatusCode};
+use nativelink_error::{make_err, make_input_err, Code, Error, ResultExt};
+use nativelink_scheduler::worker_scheduler::WorkerScheduler;
+use nativelink_store::fast_slow_store::FastSlowStore;
+use nativelink_store::shard_store::ShardStore;
+use nativelink_store::store_manager::StoreManager;
+use nativelink_util::action_messages::{ActionStage, WorkerId};
+use nativelink_util::operation_state_manager::{
+    ClientStateManager, OperationFilter, OperationStageFlags, OrderDirection,
+};
+use nativelink_util::set_log_filter;
+use nativelink_util::store_trait::{Store, StoreDriver, StoreKey};
+use serde_json::json;
+
+/// Serves the admin API, which triggers operational actions on the stores,
+/// schedulers and workers of the process while it is running.
+pub struct AdminServer {
+    store_manager: Arc<StoreManager>,
+    action_schedulers: HashMap<String, Arc<dyn ClientStateManager>>,
+    worker_schedulers: HashMap<String, Arc<dyn WorkerScheduler>>,
+}
+
+impl AdminServer {
+    pub fn new(
+        store_manager: Arc<StoreManager>,
+        action_schedulers: &HashMap<String, Arc<dyn ClientStateManager>>,
+        worker_schedulers: &HashMap<String, Arc<dyn WorkerScheduler>>,
+    ) -> Self {
+        Self {
+            store_manager,
+            action_schedulers: action_schedulers.clone(),
+            worker_schedulers: worker_schedulers.clone(),
+        }
+    }
+
+    pub fn into_router(self) -> Router {
+        let server = Arc::new(self);
+        let drain_server = server.clone();
+        let worker_status_server = server.clone();
+        let queue_server = server.clone();
+        let rebalance_server = server.clone();
+        let evict_server = server.clone();
+        let flush_server = server;
+        Router::new()
+            .route(
+                "/scheduler/:instance_name/set_drain_worker/:worker_id/:is_draining",
+                post(
+                    move |Path((scheduler_name, worker_id, is_draining)): Path<(
+                        String,
+                        String,
+                        String,
+                    )>| async move {
+                        to_response(
+                            drain_server
+                                .set_drain_worker(&scheduler_name, worker_id, &is_draining)
+                                .await,
+                        )
+                    },
+                ),
+            )
+            .route(
+                "/scheduler/:instance_name/worker_status/:worker_id",
+                get(
+                    move |Path((scheduler_name, worker_id)): Path<(String, String)>| async move {
+                        to_response(
+                            worker_status_server
+                                .worker_status(&scheduler_name, worker_id)
+                                .await,
+                        )
+                    },
+                ),
+            )
+            .route(
+                "/scheduler/:instance_name/queue",
+                get(move |Path(scheduler_name): Path<String>| async move {
+                    to_response(queue_server.dump_queue(&scheduler_name).await)
+                }),
+            )
+            .route(
+                "/stores/:store_name/rebalance_shards/:remove_moved",
+                post(
+                    move |Path((store_name, remove_moved)): Path<(String, String)>| async move {
+                        to_response(
+                            rebalance_server
+                                .rebalance_shards(&store_name, &remove_moved)
+                                .await,
+                        )
+                    },
+                ),
+            )
+            .route(
+                "/stores/:store_name/evict/:bytes",
+                post(
+                    move |Path((store_name, bytes)): Path<(String, String)>| async move {
+                        to_response(evict_server.evict(&store_name, &bytes).await)
+                    },
+                ),
+            )
+            .route(
+                "/stores/:store_name/flush_write_back",
+                post(move |Path(store_name): Path<String>| async move {
+                    to_response(flush_server.flush_write_back(&store_name).await)
+                }),
+            )
+            .route(
+                "/log_filter",
+                post(|filter: String| async move {
+                    to_response(
+                        set_log_filter(filter.trim())
+                            .map(|()| text_response(format!("Log filter set to '{filter}'"))),
+                    )
+                }),
+            )
+    }
+
+    fn get_store(&self, store_name: &str) -> Result<Store, Error> {
+        self.store_manager.get_store(store_name).ok_or_else(|| {
+            make_err!(
+                Code::NotFound,
+                "Can not get a store with the name of '{store_name}'"
+            )
+        })
+    }
+
+    async fn set_drain_worker(
+        &self,
+        scheduler_name: &str,
+        worker_id: String,
+        is_draining: &str,
+    ) -> Result<Response<Body>, Error> {
+        let is_draining = parse_flag(is_draining)?;
+        self.worker_schedulers
+            .get(scheduler_name)
+            .ok_or_else(|| {
+                make_err!(
+                    Code::NotFound,
+                    "Can not get an instance with the name of '{scheduler_name}'"
+                )
+            })?
+            .set_drain_worker(&WorkerId::try_from(worker_id.clone())?, is_draining)
+            .await?;
+        let action = if is_draining {
+            "Draining"
+        } else {
+            "Undraining"
+        };
+        Ok(text_response(format!("{action} worker {worker_id}")))
+    }
+
+    /// Reports if the worker is draining and how many actions it is still
+    /// running, so it can be shut down once it is drained.
+    async fn worker_status(
+        &self,
+        scheduler_name: &str,
+        worker_id: String,
+    ) -> Result<Response<Body>, Error> {
+        let worker_status = self
+            .worker_schedulers
+            .get(scheduler_name)
+            .ok_or_else(|| {
+                make_err!(
+                    Code::NotFound,
+                    "Can not get an instance with the name of '{scheduler_name}'"
+                )
+            })?
+            .get_worker_status(&WorkerId::try_from(worker_id)?)
+            .await?;
+        json_response(&json!({
+            "is_draining": worker_status.is_draining,
+            "running_actions": worker_status.running_actions,
+            "is_drained": worker_status.is_drained(),
+        }))
+    }
+
+    /// Lists the operations of the scheduler that are not finished, in the
+    /// order they are dispatched in.
+    async fn dump_queue(&self, scheduler_name: &str) -> Result<Response<Body>, Error> {
+        let scheduler = self.action_schedulers.get(scheduler_name).ok_or_else(|| {
+            make_err!(
+                Code::NotFound,
+                "Can not get a scheduler with the name of '{scheduler_name}'"
+            )
+        })?;
+        let mut action_state_results = scheduler
+            .filter_operations(OperationFilter {
+                stages: OperationStageFlags::CacheCheck
+                    | OperationStageFlags::Queued
+                    | OperationStageFlags::Executing,
+                order_by_priority_direction: Some(OrderDirection::Desc),
+                ..Default::default()
+            })
+            .await
+            .err_tip(|| "In AdminServer::dump_queue")?;
+        let mut operations = Vec::new();
+        while let Some(action_state_result) = action_state_results.next().await {
+            let (action_state, _maybe_origin_metadata) = action_state_result
+                .as_state()
+                .await
+                .err_tip(|| "In AdminServer::dump_queue")?;
+            let (action_info, _maybe_origin_metadata) = action_state_result
+                .as_action_info()
+                .await
+                .err_tip(|| "In AdminServer::dump_queue")?;
+            let maybe_worker_id = action_state_result
+                .as_worker_id()
+                .await
+                .err_tip(|| "In AdminServer::dump_queue")?;
+            operations.push(json!({
+                "client_operation_id": action_state.client_operation_id.to_string(),
+                "stage": stage_name(&action_state.stage),
+                "action_digest": action_state.action_digest.to_string(),
+                "instance_name": action_info.instance_name(),
+                "priority": action_info.priority,
+                "insert_timestamp": action_info
+                    .insert_timestamp
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs(),
+                "queued_for_s": action_info
+                    .insert_timestamp
+                    .elapsed()
+                    .unwrap_or_default()
+                    .as_secs(),
+                "platform_properties": action_info.platform_properties,
+                "worker_id": maybe_worker_id.map(|worker_id| worker_id.to_string()),
+            }));
+        }
+        json_response(&json!({ "operations": operations }))
+    }
+
+    async fn rebalance_shards(
+        &self,
+        store_name: &str,
+        remove_moved: &str,
+    ) -> Result<Response<Body>, Error> {
+        let remove_moved = parse_flag(remove_moved)?;
+        let store = self.get_store(store_name)?;
+        let report = store
+            .downcast_ref::<ShardStore>(None)
+            .ok_or_else(|| make_input_err!("Store '{store_name}' is not a shard store"))?
+            .rebalance(remove_moved)
+            .await?;
+        Ok(text_response(format!(
+            "Rebalanced store {store_name}: {report:?}"
+        )))
+    }
+
+    /// Evicts the expired entries of the store and `bytes` more. Stores
+    /// wrapping a single other store, like a verify store, evict from it.
+    async fn evict(&self, store_name: &str, bytes: &str) -> Result<Response<Body>, Error> {
+        let bytes = bytes
+            .parse::<u64>()
+            .map_err(|e| make_input_err!("Invalid number of bytes '{bytes}': {e:?}"))?;
+        let store = self.get_store(store_name)?;
+        let evicted_bytes = store
+            .inner_store(None::<StoreKey>)
+            .evict(bytes)
+            .await
+            .err_tip(|| format!("While evicting from store '{store_name}'"))?;
+        Ok(text_response(format!(
+            "Evicted {evicted_bytes} bytes from store {store_name}"
+        )))
+    }
+
+    async fn flush_write_back(&self, store_name: &str) -> Result<Response<Body>, Error> {
+        let store = self.get_store(store_name)?;
+        let flushed = store
+            .downcast_ref::<FastSlowStore>(None)
+            .ok_or_else(|| make_input_err!("Store '{store_name}' is not a fast slow store"))?
+            .flush_write_back()
+            .await?;
+        Ok(text_response(format!(
+            "Flushed {flushed} objects of store {store_name} to its slow store"
+        )))
+    }
+}
+
+fn parse_flag(value: &str) -> Result<bool, Error> {
+    match value {
+        "0" => Ok(false),
+        "1" => Ok(true),
+        _ => Err(make_input_err!("{value} is neither 0 nor 1")),
+    }
+}
+
+const fn stage_name(stage: &ActionStage) -> &'static str {
+    match stage {
+        ActionStage::Unknown => "Unknown",
+        ActionStage::CacheCheck => "CacheCheck",
+        ActionStage::Queued => "Queued",
+        ActionStage::Executing => "Executing",
+        ActionStage::Completed(_) => "Completed",
+        ActionStage::CompletedFromCache(_) => "CompletedFromCache",
+    }
+}
+
+fn json_response(value: &serde_json::Value) -> Result<Response<Body>, Error> {
+    let body = serde_json::to_string_pretty(value)
+        .map_err(|e| make_err!(Code::Internal, "Could not convert to json {e:?}"))?;
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .map_err(|e| make_err!(Code::Internal, "Could not build response: {e:?}"))
+}
+
+fn text_response(text: String) -> Response<Body> {
+    Response::new(Body::from(text))
+}
+
+fn to_response(result: Result<Response<Body>, Error>) -> Response<Body> {
+    match result {
+        Ok(response) => response,
+        Err(err) => {
+            let status = match err.code {
+                Code::NotFound => StatusCode::NOT_FOUND,
+                Code::InvalidArgument => StatusCode::BAD_REQUEST,
+                Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            let mut response = Response::new(Body::from(format!("Error: {err:?}")));
+            *response.status_mut() = status;
+            response
+        }
+    }
+}
@@ -19,25 +19,32 @@ use bytes::BytesMut;
 use futures::stream::unfold;
 use futures::Stream;
 use nativelink_error::{Error, ResultExt};
+use nativelink_proto::build_event_stream::{self, build_event, file, File};
 use nativelink_proto::com::github::trace_machina::nativelink::events::{bep_event, BepEvent};
+use nativelink_proto::google::devtools::build::v1::build_event::Event;
 use nativelink_proto::google::devtools::build::v1::publish_build_event_server::{
     PublishBuildEvent, PublishBuildEventServer,
 };
 use nativelink_proto::google::devtools::build::v1::{
     PublishBuildToolEventStreamRequest, PublishBuildToolEventStreamResponse,
-    PublishLifecycleEventRequest,
+    PublishLifecycleEventRequest, StreamId,
 };
 use nativelink_store::store_manager::StoreManager;
+use nativelink_util::common::DigestInfo;
+use nativelink_util::instance_access::InstanceAccess;
 use nativelink_util::origin_context::{ActiveOriginContext, ORIGIN_IDENTITY};
 use nativelink_util::store_trait::{Store, StoreDriver, StoreKey, StoreLike};
 use prost::Message;
 use tonic::{Request, Response, Result, Status, Streaming};
-use tracing::{instrument, Level};
+use tracing::{event, instrument, Level};
 
 /// Current version of the BEP event. This might be used in the future if
 /// there is a breaking change in the BEP event format.
 const BEP_EVENT_VERSION: u32 = 0;
 
+/// Type URL of the Bazel build events sent in `Event::BazelEvent`.
+const BAZEL_EVENT_TYPE_URL: &str = "type.googleapis.com/build_event_stream.BuildEvent";
+
 fn get_identity() -> Result<Option<String>, Status> {
     ActiveOriginContext::get()
         .map_or(Ok(None), |ctx| ctx.get_value(&ORIGIN_IDENTITY))
@@ -45,8 +52,101 @@ fn get_identity() -> Result<Option<String>, Status> {
         .map_or_else(|e| Err(e.into()), |v| Ok(v.map(|v| v.as_ref().clone())))
 }
 
+/// Returns the files referenced by a Bazel build event.
+fn referenced_files(bazel_event: &build_event_stream::BuildEvent) -> Vec<&File> {
+    match &bazel_event.payload {
+        Some(build_event::Payload::Action(action)) => {
+            [&action.stdout, &action.stderr, &action.primary_output]
+                .into_iter()
+                .flatten()
+                .chain(&action.action_metadata_logs)
+                .collect()
+        }
+        Some(build_event::Payload::NamedSetOfFiles(file_set)) => file_set.files.iter().collect(),
+        Some(build_event::Payload::TestResult(result)) => {
+            result.test_action_output.iter().collect()
+        }
+        Some(build_event::Payload::TestSummary(summary)) => {
+            summary.passed.iter().chain(&summary.failed).collect()
+        }
+        Some(build_event::Payload::BuildToolLogs(logs)) => logs.log.iter().collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Parses the digest of a file that was uploaded to a remote cache, which
+/// Bazel reports as `bytestream://<host>/[<instance_name>/]blobs/<hash>/<size>`.
+fn digest_from_uri(uri: &str) -> Option<DigestInfo> {
+    let mut parts = uri.strip_prefix("bytestream://")?.rsplit('/');
+    let size: u64 = parts.next()?.parse().ok()?;
+    let hash = parts.next()?;
+    if parts.next()? != "blobs" {
+        return None;
+    }
+    DigestInfo::try_new(hash, size).ok()
+}
+
+/// Records the files referenced by the Bazel build event in `request` that
+/// are in `cas_store` under `BepFile:<build_id>:<invocation_id>:<digest>`,
+/// so they can be looked up by invocation.
+async fn link_cas_files(
+    store: Pin<&dyn StoreDriver>,
+    cas_store: &Store,
+    stream_id: &StreamId,
+    request: &PublishBuildToolEventStreamRequest,
+) -> Result<(), Error> {
+    let Some(Event::BazelEvent(bazel_event)) = request
+        .ordered_build_event
+        .as_ref()
+        .and_then(|ordered_build_event| ordered_build_event.event.as_ref())
+        .and_then(|build_event| build_event.event.as_ref())
+    else {
+        return Ok(());
+    };
+    if bazel_event.type_url != BAZEL_EVENT_TYPE_URL {
+        return Ok(());
+    }
+    let bazel_event = build_event_stream::BuildEvent::decode(bazel_event.value.as_slice())
+        .err_tip(|| "Could not decode Bazel build event")?;
+    let files: Vec<(DigestInfo, &File)> = referenced_files(&bazel_event)
+        .into_iter()
+        .filter_map(|referenced_file| match &referenced_file.file {
+            Some(file::File::Uri(uri)) => {
+                digest_from_uri(uri).map(|digest| (digest, referenced_file))
+            }
+            _ => None,
+        })
+        .collect();
+    if files.is_empty() {
+        return Ok(());
+    }
+    let keys: Vec<StoreKey> = files.iter().map(|(digest, _)| (*digest).into()).collect();
+    let sizes = cas_store
+        .has_many(&keys)
+        .await
+        .err_tip(|| "Failed to check referenced files in CAS")?;
+    for ((digest, referenced_file), size) in files.into_iter().zip(sizes) {
+        if size.is_none() {
+            continue;
+        }
+        store
+            .update_oneshot(
+                StoreKey::Str(Cow::Owned(format!(
+                    "BepFile:{}:{}:{digest}",
+                    &stream_id.build_id, &stream_id.invocation_id,
+                ))),
+                referenced_file.encode_to_vec().into(),
+            )
+            .await
+            .err_tip(|| "Failed to store referenced file")?;
+    }
+    Ok(())
+}
+
 pub struct BepServer {
     store: Store,
+    cas_store: Option<Store>,
+    access: InstanceAccess,
 }
 
 impl BepServer {
@@ -57,8 +157,21 @@ impl BepServer {
         let store = store_manager
             .get_store(&config.store)
             .err_tip(|| format!("Expected store {} to exist in store manager", &config.store))?;
+        let cas_store = config
+            .cas_store
+            .as_ref()
+            .map(|cas_store| {
+                store_manager
+                    .get_store(cas_store)
+                    .err_tip(|| format!("Expected store {cas_store} to exist in store manager"))
+            })
+            .transpose()?;
 
-        Ok(Self { store })
+        Ok(Self {
+            store,
+            cas_store,
+            access: InstanceAccess::new(&config.access),
+        })
     }
 
     pub fn into_service(self) -> PublishBuildEventServer<BepServer> {
@@ -111,6 +224,7 @@ impl BepServer {
     ) -> Result<Response<PublishBuildToolEventStreamStream>, Error> {
         async fn process_request(
             store: Pin<&dyn StoreDriver>,
+            cas_store: Option<&Store>,
             request: PublishBuildToolEventStreamRequest,
             identity: String,
         ) -> Result<PublishBuildToolEventStreamResponse, Status> {
@@ -126,6 +240,17 @@ impl BepServer {
 
             let sequence_number = ordered_build_event.sequence_number;
 
+            // Linking files is best effort, the event is stored either way.
+            if let Some(cas_store) = cas_store {
+                if let Err(err) = link_cas_files(store, cas_store, &stream_id, &request).await {
+                    event!(
+                        Level::WARN,
+                        ?err,
+                        "Failed to link files of build event to the CAS"
+                    );
+                }
+            }
+
             let bep_event = BepEvent {
                 version: BEP_EVENT_VERSION,
                 identity,
@@ -156,6 +281,7 @@ impl BepServer {
 
         struct State {
             store: Store,
+            cas_store: Option<Store>,
             stream: Streaming<PublishBuildToolEventStreamRequest>,
             identity: String,
         }
@@ -164,6 +290,7 @@ impl BepServer {
             unfold(
                 Some(State {
                     store: self.store.clone(),
+                    cas_store: self.cas_store.clone(),
                     stream,
                     identity: identity.unwrap_or_default(),
                 }),
@@ -179,6 +306,7 @@ impl BepServer {
                         };
                     process_request(
                         state.store.as_store_driver_pin(),
+                        state.cas_store.as_ref(),
                         request,
                         state.identity.clone(),
                     )
@@ -214,6 +342,8 @@ impl PublishBuildEvent for BepServer {
         &self,
         grpc_request: Request<PublishLifecycleEventRequest>,
     ) -> Result<Response<()>, Status> {
+        // Build events have no instance name.
+        self.access.check_write("")?;
         self.inner_publish_lifecycle_event(grpc_request.into_inner(), get_identity()?)
             .await
             .map_err(Error::into)
@@ -230,6 +360,7 @@ impl PublishBuildEvent for BepServer {
         &self,
         grpc_request: Request<Streaming<PublishBuildToolEventStreamRequest>>,
     ) -> Result<Response<Self::PublishBuildToolEventStreamStream>, Status> {
+        self.access.check_write("")?;
         self.inner_publish_build_tool_event_stream(grpc_request.into_inner(), get_identity()?)
             .await
             .map_err(Error::into)
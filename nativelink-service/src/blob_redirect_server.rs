@@ -0,0 +1,156 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::extract::Path;
+use axum::routing::get;
+use axum::Router;
+use hyper::header::{CONTENT_LENGTH, LOCATION};
+use hyper::{Response, StatusCode};
+use nativelink_config::cas_server::BlobRedirectConfig;
+use nativelink_error::{make_err, make_input_err, Code, Error, ResultExt};
+use nativelink_store::s3_store::DefaultS3Store;
+use nativelink_store::store_manager::StoreManager;
+use nativelink_util::background_spawn;
+use nativelink_util::buf_channel::make_buf_channel_pair;
+use nativelink_util::common::DigestInfo;
+use nativelink_util::instance_access::InstanceAccess;
+use nativelink_util::store_trait::{Store, StoreLike};
+use tracing::{event, Level};
+
+/// Serves CAS blobs over plain HTTP. Large blobs in S3 stores are not
+/// proxied, instead the client is redirected to a presigned URL.
+pub struct BlobRedirectServer {
+    stores: HashMap<String, Store>,
+    access: HashMap<String, InstanceAccess>,
+}
+
+impl BlobRedirectServer {
+    pub fn new(config: &BlobRedirectConfig, store_manager: &StoreManager) -> Result<Self, Error> {
+        let mut stores = HashMap::with_capacity(config.cas_stores.len());
+        for (instance_name, store_name) in &config.cas_stores {
+            let store = store_manager
+                .get_store(store_name)
+                .ok_or_else(|| make_input_err!("'cas_store': '{}' does not exist", store_name))?;
+            stores.insert(instance_name.to_string(), store);
+        }
+        let access = config
+            .access
+            .iter()
+            .map(|(instance_name, access_cfg)| {
+                (instance_name.to_string(), InstanceAccess::new(access_cfg))
+            })
+            .collect();
+        Ok(BlobRedirectServer { stores, access })
+    }
+
+    pub fn into_router(self) -> Router {
+        let server = Arc::new(self);
+        let server_clone = server.clone();
+        Router::new()
+            .route(
+                "/:hash/:size",
+                get(move |Path(params): Path<(String, String)>| async move {
+                    let (hash, size) = params;
+                    server.get_blob("", &hash, &size).await
+                }),
+            )
+            .route(
+                "/:instance_name/:hash/:size",
+                get(
+                    move |Path(params): Path<(String, String, String)>| async move {
+                        let (instance_name, hash, size) = params;
+                        server_clone.get_blob(&instance_name, &hash, &size).await
+                    },
+                ),
+            )
+    }
+
+    async fn get_blob(&self, instance_name: &str, hash: &str, size: &str) -> Response<Body> {
+        match self.inner_get_blob(instance_name, hash, size).await {
+            Ok(response) => response,
+            Err(err) => {
+                let status = match err.code {
+                    Code::NotFound => StatusCode::NOT_FOUND,
+                    Code::InvalidArgument => StatusCode::BAD_REQUEST,
+                    Code::PermissionDenied => StatusCode::FORBIDDEN,
+                    _ => StatusCode::INTERNAL_SERVER_ERROR,
+                };
+                let mut response = Response::new(Body::from(format!("Error: {err:?}")));
+                *response.status_mut() = status;
+                response
+            }
+        }
+    }
+
+    async fn inner_get_blob(
+        &self,
+        instance_name: &str,
+        hash: &str,
+        size: &str,
+    ) -> Result<Response<Body>, Error> {
+        let store = self.stores.get(instance_name).ok_or_else(|| {
+            make_err!(
+                Code::NotFound,
+                "'instance_name' not configured for '{instance_name}'"
+            )
+        })?;
+        if let Some(access) = self.access.get(instance_name) {
+            access.check_read(instance_name)?;
+        }
+        let size = size
+            .parse::<u64>()
+            .map_err(|e| make_input_err!("Invalid size '{size}': {e:?}"))?;
+        let digest = DigestInfo::try_new(hash, size)?;
+        let Some(object_size) = store
+            .has(digest)
+            .await
+            .err_tip(|| "In BlobRedirectServer::inner_get_blob")?
+        else {
+            return Err(make_err!(Code::NotFound, "Blob {digest} not found"));
+        };
+
+        if let Some(s3_store) = store.downcast_ref::<DefaultS3Store>(Some(digest.into())) {
+            let threshold = s3_store.presigned_url_threshold();
+            if threshold != 0 && object_size >= threshold {
+                let url = s3_store
+                    .presigned_get_url(digest.into())
+                    .await
+                    .err_tip(|| "In BlobRedirectServer::inner_get_blob")?;
+                return Response::builder()
+                    .status(StatusCode::TEMPORARY_REDIRECT)
+                    .header(LOCATION, url)
+                    .body(Body::empty())
+                    .map_err(|e| make_err!(Code::Internal, "Could not build redirect: {e:?}"));
+            }
+        }
+
+        let (tx, rx) = make_buf_channel_pair();
+        let store = store.clone();
+        background_spawn!("blob_redirect_server_get", async move {
+            // The client sees a truncated body if this fails.
+            if let Err(err) = store.get(digest, tx).await {
+                event!(Level::WARN, ?digest, ?err, "Failed to stream blob");
+            }
+        });
+        Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_LENGTH, object_size)
+            .body(Body::from_stream(rx))
+            .map_err(|e| make_err!(Code::Internal, "Could not build response: {e:?}"))
+    }
+}
@@ -13,19 +13,23 @@
 // limitations under the License.
 
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::Into;
 use std::fmt::{Debug, Formatter};
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
+use bytes::{Bytes, BytesMut};
 use futures::future::{pending, BoxFuture};
 use futures::stream::unfold;
-use futures::{try_join, Future, Stream, TryFutureExt};
-use nativelink_config::cas_server::ByteStreamConfig;
+use futures::{try_join, Future, Stream, StreamExt, TryFutureExt};
+use nativelink_config::cas_server::{ByteStreamConfig, PersistentUploadsConfig};
 use nativelink_error::{make_err, make_input_err, Code, Error, ResultExt};
+use nativelink_proto::build::bazel::remote::execution::v2::Digest;
 use nativelink_proto::google::bytestream::byte_stream_server::{
     ByteStream, ByteStreamServer as Server,
 };
@@ -35,20 +39,28 @@ use nativelink_proto::google::bytestream::{
 };
 use nativelink_store::grpc_store::GrpcStore;
 use nativelink_store::store_manager::StoreManager;
+use nativelink_util::audit_log::{audit_log, audit_record, AuditOperation, AuditRecord};
+use nativelink_util::blob_compression::BlobCompressor;
 use nativelink_util::buf_channel::{
     make_buf_channel_pair, DropCloserReadHalf, DropCloserWriteHalf,
 };
 use nativelink_util::common::DigestInfo;
 use nativelink_util::digest_hasher::{
-    default_digest_hasher_func, make_ctx_for_hash_func, DigestHasherFunc,
+    make_ctx_for_hash_func, resolve_digest_hasher_func, DigestHasherFunc,
 };
+use nativelink_util::fs;
+use nativelink_util::histogram_metrics::RpcTimer;
+use nativelink_util::instance_access::{active_identity, InstanceAccess};
+use nativelink_util::origin_context::make_ctx_for_instance_name;
 use nativelink_util::origin_event::OriginEventContext;
 use nativelink_util::proto_stream_utils::WriteRequestStreamWrapper;
-use nativelink_util::resource_info::ResourceInfo;
+use nativelink_util::resource_info::{ActionOutputStreamName, ResourceInfo};
 use nativelink_util::spawn;
 use nativelink_util::store_trait::{Store, StoreLike, UploadSizeInfo};
 use nativelink_util::task::JoinHandleDropGuard;
 use parking_lot::Mutex;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::watch;
 use tokio::time::sleep;
 use tonic::{Request, Response, Status, Streaming};
 use tracing::{enabled, error_span, event, instrument, Instrument, Level};
@@ -56,12 +68,21 @@ use tracing::{enabled, error_span, event, instrument, Instrument, Level};
 /// If this value changes update the documentation in the config definition.
 const DEFAULT_PERSIST_STREAM_ON_DISCONNECT_TIMEOUT: Duration = Duration::from_secs(60);
 
+/// If this value changes update the documentation in the config definition.
+const DEFAULT_PERSISTENT_UPLOAD_IDLE_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// How often persistent uploads that timed out are removed.
+const PERSISTENT_UPLOAD_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
 /// If this value changes update the documentation in the config definition.
 const DEFAULT_MAX_BYTES_PER_STREAM: usize = 64 * 1024;
 
 /// If this value changes update the documentation in the config definition.
 const DEFAULT_MAX_DECODING_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
 
+/// If this value changes update the documentation in the config definition.
+const DEFAULT_MAX_ACTION_OUTPUT_STREAM_SIZE: usize = 1024 * 1024;
+
 type ReadStream = Pin<Box<dyn Stream<Item = Result<ReadResponse, Status>> + Send + 'static>>;
 type StoreUpdateFuture = Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'static>>;
 
@@ -155,15 +176,230 @@ impl IdleStream {
 }
 
 type BytesWrittenAndIdleStream = (Arc<AtomicU64>, Option<IdleStream>);
+
+/// The end of the data written so far to a live action output stream.
+#[derive(Default)]
+struct ActionOutputData {
+    data: BytesMut,
+    /// The offset of the first byte of `data` in the stream. Older bytes
+    /// were dropped to keep the stream under its maximum size.
+    start_offset: usize,
+    finished: bool,
+}
+
+/// A live stdout or stderr stream of a running action. It only lives while
+/// the worker writes it; once the action is done clients read the output
+/// from the CAS.
+struct ActionOutputStream {
+    output: Mutex<ActionOutputData>,
+    /// The maximum number of bytes kept of the end of the stream.
+    max_size: usize,
+    /// Readers wait on this to be told when data is written or the stream
+    /// is finished.
+    changed: watch::Sender<()>,
+}
+
+impl ActionOutputStream {
+    fn new(max_size: usize) -> Self {
+        Self {
+            output: Mutex::new(ActionOutputData::default()),
+            max_size,
+            changed: watch::channel(()).0,
+        }
+    }
+
+    /// Appends data written at `write_offset` and returns the size of the
+    /// stream. Only the last `max_size` bytes of the stream are kept.
+    fn append(&self, write_offset: i64, data: &[u8]) -> Result<i64, Error> {
+        let mut output = self.output.lock();
+        let size = (output.start_offset + output.data.len()) as i64;
+        if write_offset != size {
+            return Err(make_input_err!(
+                "Action output was written at offset {write_offset}, but {size} bytes were written so far"
+            ));
+        }
+        output.data.extend_from_slice(data);
+        if output.data.len() > self.max_size {
+            let dropped = output.data.len() - self.max_size;
+            let _ = output.data.split_to(dropped);
+            output.start_offset += dropped;
+        }
+        drop(output);
+        self.changed.send_replace(());
+        Ok(size + data.len() as i64)
+    }
+
+    fn finish(&self) {
+        self.output.lock().finished = true;
+        self.changed.send_replace(());
+    }
+}
+
+type ActionOutputStreams = Mutex<HashMap<ActionOutputStreamName, Arc<ActionOutputStream>>>;
+
+/// Finishes an action output stream and removes it from the streams that
+/// can be read when it is dropped.
+struct ActionOutputStreamGuard<'a> {
+    action_output_streams: &'a ActionOutputStreams,
+    stream_name: ActionOutputStreamName,
+    output_stream: Arc<ActionOutputStream>,
+}
+
+impl Drop for ActionOutputStreamGuard<'_> {
+    fn drop(&mut self) {
+        self.output_stream.finish();
+        let mut action_output_streams = self.action_output_streams.lock();
+        // Another execution of the action may have replaced the stream.
+        if action_output_streams
+            .get(&self.stream_name)
+            .is_some_and(|output_stream| Arc::ptr_eq(output_stream, &self.output_stream))
+        {
+            action_output_streams.remove(&self.stream_name);
+        }
+    }
+}
 type SleepFn = Arc<dyn Fn() -> BoxFuture<'static, ()> + Send + Sync>;
 
+/// Uploads that are written to files until they finish, so clients can
+/// resume them after a disconnect or a restart of the server.
+struct PersistentUploads {
+    path: PathBuf,
+    idle_timeout: Duration,
+    /// Names of the files of the uploads that a client is writing to.
+    in_use: Mutex<HashSet<String>>,
+}
+
+impl PersistentUploads {
+    fn new(config: &PersistentUploadsConfig) -> Result<Self, Error> {
+        std::fs::create_dir_all(&config.path)
+            .err_tip(|| format!("Could not create persistent uploads dir {}", config.path))?;
+        let idle_timeout = if config.idle_timeout_s == 0 {
+            DEFAULT_PERSISTENT_UPLOAD_IDLE_TIMEOUT
+        } else {
+            Duration::from_secs(config.idle_timeout_s)
+        };
+        Ok(Self {
+            path: PathBuf::from(&config.path),
+            idle_timeout,
+            in_use: Mutex::new(HashSet::new()),
+        })
+    }
+
+    /// Returns the name of the file of an upload. Uploads with the same
+    /// UUID but another digest or compressor are kept apart.
+    fn file_name(
+        uuid: &str,
+        digest: DigestInfo,
+        compressor: BlobCompressor,
+    ) -> Result<String, Error> {
+        if uuid.is_empty()
+            || !uuid
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(make_input_err!("Invalid UUID '{uuid}' in resource name"));
+        }
+        Ok(format!("{uuid}-{digest}-{compressor:?}"))
+    }
+
+    /// Returns the number of bytes of an upload that are on disk, or `None`
+    /// if the upload does not exist.
+    async fn committed_size(&self, file_name: &str) -> Result<Option<u64>, Error> {
+        match fs::metadata(self.path.join(file_name)).await {
+            Ok(metadata) => Ok(Some(metadata.len())),
+            Err(err) if err.code == Code::NotFound => Ok(None),
+            Err(err) => Err(err).err_tip(|| "Could not get size of persistent upload"),
+        }
+    }
+
+    /// Marks an upload as being written to until the guard is dropped.
+    fn claim<'a>(&'a self, file_name: &str) -> Result<PersistentUploadGuard<'a>, Error> {
+        if !self.in_use.lock().insert(file_name.to_string()) {
+            return Err(make_input_err!("Cannot upload same UUID simultaneously"));
+        }
+        Ok(PersistentUploadGuard {
+            uploads: self,
+            file_name: file_name.to_string(),
+        })
+    }
+
+    /// Removes the uploads that were not written to for `idle_timeout`.
+    async fn remove_expired(&self) -> Result<(), Error> {
+        let mut expired = Vec::new();
+        {
+            let (_permit, mut dir) = fs::read_dir(&self.path)
+                .await
+                .err_tip(|| "Could not read persistent uploads dir")?
+                .into_inner();
+            while let Some(entry) = dir
+                .next_entry()
+                .await
+                .err_tip(|| "Could not read persistent uploads dir entry")?
+            {
+                let metadata = entry
+                    .metadata()
+                    .await
+                    .err_tip(|| "Could not get metadata of persistent upload")?;
+                let idle_time = metadata
+                    .modified()
+                    .ok()
+                    .and_then(|modified| modified.elapsed().ok())
+                    .unwrap_or_default();
+                if idle_time > self.idle_timeout {
+                    expired.push(entry.file_name());
+                }
+            }
+        }
+        for file_name in expired {
+            if file_name
+                .to_str()
+                .is_some_and(|file_name| self.in_use.lock().contains(file_name))
+            {
+                continue;
+            }
+            event!(
+                Level::INFO,
+                ?file_name,
+                "Removing expired persistent upload"
+            );
+            fs::remove_file(self.path.join(&file_name))
+                .await
+                .err_tip(|| format!("Could not remove persistent upload {file_name:?}"))?;
+        }
+        Ok(())
+    }
+}
+
+struct PersistentUploadGuard<'a> {
+    uploads: &'a PersistentUploads,
+    file_name: String,
+}
+
+impl PersistentUploadGuard<'_> {
+    fn path(&self) -> PathBuf {
+        self.uploads.path.join(&self.file_name)
+    }
+}
+
+impl Drop for PersistentUploadGuard<'_> {
+    fn drop(&mut self) {
+        self.uploads.in_use.lock().remove(&self.file_name);
+    }
+}
+
 pub struct ByteStreamServer {
     stores: HashMap<String, Store>,
+    access: HashMap<String, InstanceAccess>,
     // Max number of bytes to send on each grpc stream chunk.
     max_bytes_per_stream: usize,
     max_decoding_message_size: usize,
     active_uploads: Arc<Mutex<HashMap<String, BytesWrittenAndIdleStream>>>,
     sleep_fn: SleepFn,
+    persistent_uploads: Option<Arc<PersistentUploads>>,
+    _persistent_uploads_sweeper: Option<JoinHandleDropGuard<()>>,
+    action_output_streams: ActionOutputStreams,
+    max_action_output_stream_size: usize,
+    action_output_writer_identities: HashSet<String>,
 }
 
 impl ByteStreamServer {
@@ -202,15 +438,82 @@ impl ByteStreamServer {
         } else {
             config.max_decoding_message_size
         };
+        let max_action_output_stream_size = if config.max_action_output_stream_size == 0 {
+            DEFAULT_MAX_ACTION_OUTPUT_STREAM_SIZE
+        } else {
+            config.max_action_output_stream_size
+        };
+        let access = config
+            .access
+            .iter()
+            .map(|(instance_name, access_cfg)| {
+                (instance_name.to_string(), InstanceAccess::new(access_cfg))
+            })
+            .collect();
+        let persistent_uploads = config
+            .experimental_persistent_uploads
+            .as_ref()
+            .map(|config| PersistentUploads::new(config).map(Arc::new))
+            .transpose()?;
+        let persistent_uploads_sweeper = persistent_uploads.as_ref().map(|persistent_uploads| {
+            let weak_persistent_uploads = Arc::downgrade(persistent_uploads);
+            spawn!("bytestream_persistent_uploads_sweeper", async move {
+                loop {
+                    let Some(persistent_uploads) = weak_persistent_uploads.upgrade() else {
+                        return;
+                    };
+                    if let Err(err) = persistent_uploads.remove_expired().await {
+                        event!(
+                            Level::ERROR,
+                            ?err,
+                            "Failed to remove expired persistent uploads"
+                        );
+                    }
+                    drop(persistent_uploads);
+                    sleep(PERSISTENT_UPLOAD_SWEEP_INTERVAL).await;
+                }
+            })
+        });
         Ok(ByteStreamServer {
             stores,
+            access,
             max_bytes_per_stream,
             max_decoding_message_size,
             active_uploads: Arc::new(Mutex::new(HashMap::new())),
             sleep_fn,
+            persistent_uploads,
+            _persistent_uploads_sweeper: persistent_uploads_sweeper,
+            action_output_streams: Mutex::new(HashMap::new()),
+            max_action_output_stream_size,
+            action_output_writer_identities: config
+                .action_output_writer_identities
+                .iter()
+                .cloned()
+                .collect(),
         })
     }
 
+    /// Checks that the client may read from `instance_name`, or write to it
+    /// if `write` is set.
+    fn check_access(&self, instance_name: &str, write: bool) -> Result<(), Error> {
+        match self.access.get(instance_name) {
+            Some(access) if write => access.check_write(instance_name),
+            Some(access) => access.check_read(instance_name),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the store of `instance_name` if the client may access it.
+    fn checked_store(&self, instance_name: &str, write: bool) -> Result<Store, Error> {
+        let store = self
+            .stores
+            .get(instance_name)
+            .err_tip(|| format!("'instance_name' not configured for '{instance_name}'"))?
+            .clone();
+        self.check_access(instance_name, write)?;
+        Ok(store)
+    }
+
     pub fn into_service(self) -> Server<Self> {
         let max_decoding_message_size = self.max_decoding_message_size;
         Server::new(self).max_decoding_message_size(max_decoding_message_size)
@@ -219,9 +522,15 @@ impl ByteStreamServer {
     fn create_or_join_upload_stream(
         &self,
         uuid: String,
+        instance_name: &str,
         store: Store,
         digest: DigestInfo,
+        compressor: BlobCompressor,
     ) -> Result<ActiveStreamGuard<'_>, Error> {
+        // The upload may be resumed by a later request, so the instance
+        // name goes with the update future rather than with the request.
+        let update_ctx = make_ctx_for_instance_name(instance_name)
+            .err_tip(|| "In ByteStreamServer::create_or_join_upload_stream")?;
         let (uuid, bytes_received) = match self.active_uploads.lock().entry(uuid) {
             Entry::Occupied(mut entry) => {
                 let maybe_idle_stream = entry.get_mut();
@@ -245,15 +554,25 @@ impl ByteStreamServer {
         // removing the entry from the map, otherwise that UUID becomes
         // unusable.
 
-        let (tx, rx) = make_buf_channel_pair();
-        let store_update_fut = Box::pin(async move {
-            // We need to wrap `Store::update()` in a another future because we need to capture
-            // `store` to ensure its lifetime follows the future and not the caller.
-            store
+        let (tx, mut rx) = make_buf_channel_pair();
+        let store_update_fut = Box::pin(update_ctx.wrap_async(
+            error_span!("bytestream_store_update"),
+            async move {
+                // We need to wrap `Store::update()` in a another future because we need to capture
+                // `store` to ensure its lifetime follows the future and not the caller.
                 // Bytestream always uses digest size as the actual byte size.
-                .update(digest, rx, UploadSizeInfo::ExactSize(digest.size_bytes()))
-                .await
-        });
+                let size_info = UploadSizeInfo::ExactSize(digest.size_bytes());
+                if compressor == BlobCompressor::Identity {
+                    return store.update(digest, rx, size_info).await;
+                }
+                let (mut store_tx, store_rx) = make_buf_channel_pair();
+                try_join!(
+                    compressor.decompress_stream(&mut rx, &mut store_tx),
+                    store.update(digest, store_rx, size_info),
+                )
+                .map(|_| ())
+            },
+        ));
         Ok(ActiveStreamGuard {
             stream_state: Some(StreamState {
                 uuid,
@@ -265,10 +584,137 @@ impl ByteStreamServer {
         })
     }
 
+    /// Reads a live action output stream from `read_offset` until the
+    /// worker finished writing it. Data is sent as soon as it is written.
+    /// Reading fails once the data at the read offset was dropped to keep
+    /// the stream under its maximum size.
+    fn read_action_output(
+        &self,
+        stream_name: &ActionOutputStreamName,
+        read_request: &ReadRequest,
+    ) -> Result<impl Stream<Item = Result<ReadResponse, Status>> + Send + 'static, Error> {
+        struct ReaderState {
+            output_stream: Arc<ActionOutputStream>,
+            changed: watch::Receiver<()>,
+            offset: usize,
+            maybe_end: Option<usize>,
+            max_bytes_per_stream: usize,
+        }
+
+        self.check_access(&stream_name.instance_name, false)?;
+        let offset = usize::try_from(read_request.read_offset)
+            .err_tip(|| "Could not convert read_offset to usize")?;
+        let read_limit = usize::try_from(read_request.read_limit)
+            .err_tip(|| "Could not convert read_limit to usize")?;
+        let output_stream = self
+            .action_output_streams
+            .lock()
+            .get(stream_name)
+            .cloned()
+            .ok_or_else(|| {
+                make_err!(
+                    Code::NotFound,
+                    "Action output stream {stream_name} is not being written"
+                )
+            })?;
+        let state = ReaderState {
+            changed: output_stream.changed.subscribe(),
+            output_stream,
+            offset,
+            maybe_end: (read_limit != 0).then(|| offset.saturating_add(read_limit)),
+            max_bytes_per_stream: self.max_bytes_per_stream,
+        };
+        Ok(unfold(Some(state), |maybe_state| async move {
+            let mut state = maybe_state?;
+            loop {
+                let (data, finished) = {
+                    let output = state.output_stream.output.lock();
+                    if state.offset < output.start_offset {
+                        let err = make_err!(
+                            Code::OutOfRange,
+                            "Action output before offset {} is no longer kept, read it from the CAS once the action is done",
+                            output.start_offset
+                        );
+                        return Some((Err(err.into()), None));
+                    }
+                    let end = state
+                        .maybe_end
+                        .unwrap_or(usize::MAX)
+                        .min(output.start_offset + output.data.len())
+                        .min(state.offset.saturating_add(state.max_bytes_per_stream));
+                    let data = if state.offset < end {
+                        Bytes::copy_from_slice(
+                            &output.data
+                                [state.offset - output.start_offset..end - output.start_offset],
+                        )
+                    } else {
+                        Bytes::new()
+                    };
+                    (data, output.finished)
+                };
+                if !data.is_empty() {
+                    state.offset += data.len();
+                    return Some((Ok(ReadResponse { data }), Some(state)));
+                }
+                if finished || state.maybe_end == Some(state.offset) {
+                    return None;
+                }
+                if state.changed.changed().await.is_err() {
+                    return None;
+                }
+            }
+        }))
+    }
+
+    /// Makes the data of the stream readable as a live action output stream
+    /// while it is written. A stream written by another execution of the
+    /// same action is replaced, so only the identities of the workers may
+    /// write action output streams.
+    async fn write_action_output(
+        &self,
+        stream_name: ActionOutputStreamName,
+        mut stream: impl Stream<Item = Result<WriteRequest, Status>> + Unpin,
+    ) -> Result<Response<WriteResponse>, Error> {
+        let identity = active_identity();
+        if !self.action_output_writer_identities.contains(&identity) {
+            return Err(make_err!(
+                Code::PermissionDenied,
+                "Identity '{identity}' may not write action output stream {stream_name}"
+            ));
+        }
+        self.check_access(&stream_name.instance_name, true)?;
+        let output_stream = Arc::new(ActionOutputStream::new(self.max_action_output_stream_size));
+        let maybe_replaced_output_stream = self
+            .action_output_streams
+            .lock()
+            .insert(stream_name.clone(), output_stream.clone());
+        if let Some(replaced_output_stream) = maybe_replaced_output_stream {
+            replaced_output_stream.finish();
+        }
+        let _guard = ActionOutputStreamGuard {
+            action_output_streams: &self.action_output_streams,
+            stream_name,
+            output_stream: output_stream.clone(),
+        };
+        loop {
+            let write_request = stream
+                .next()
+                .await
+                .err_tip(|| "Action output stream closed without finish_write")?
+                .err_tip(|| "Error receiving action output")?;
+            let committed_size =
+                output_stream.append(write_request.write_offset, &write_request.data)?;
+            if write_request.finish_write {
+                return Ok(Response::new(WriteResponse { committed_size }));
+            }
+        }
+    }
+
     async fn inner_read(
         &self,
         store: Store,
         digest: DigestInfo,
+        compressor: BlobCompressor,
         read_request: ReadRequest,
     ) -> Result<impl Stream<Item = Result<ReadResponse, Status>> + Send + 'static, Error> {
         struct ReaderState {
@@ -281,13 +727,17 @@ impl ByteStreamServer {
         let read_limit = u64::try_from(read_request.read_limit)
             .err_tip(|| "Could not convert read_limit to u64")?;
 
-        let (tx, rx) = make_buf_channel_pair();
+        let (mut tx, rx) = make_buf_channel_pair();
 
         let read_limit = if read_limit != 0 {
             Some(read_limit)
         } else {
             None
         };
+        // For compressed blobs the offset and limit apply to the uncompressed
+        // data, which is compressed as it is read from the store.
+        let read_offset = u64::try_from(read_request.read_offset)
+            .err_tip(|| "Could not convert read_offset to u64")?;
 
         // This allows us to call a destructor when the the object is dropped.
         let state = Some(ReaderState {
@@ -295,15 +745,15 @@ impl ByteStreamServer {
             max_bytes_per_stream: self.max_bytes_per_stream,
             maybe_get_part_result: None,
             get_part_fut: Box::pin(async move {
-                store
-                    .get_part(
-                        digest,
-                        tx,
-                        u64::try_from(read_request.read_offset)
-                            .err_tip(|| "Could not convert read_offset to u64")?,
-                        read_limit,
-                    )
-                    .await
+                if compressor == BlobCompressor::Identity {
+                    return store.get_part(digest, tx, read_offset, read_limit).await;
+                }
+                let (store_tx, mut store_rx) = make_buf_channel_pair();
+                try_join!(
+                    store.get_part(digest, store_tx, read_offset, read_limit),
+                    compressor.compress_stream(&mut store_rx, &mut tx),
+                )
+                .map(|_| ())
             }),
         });
 
@@ -406,7 +856,7 @@ impl ByteStreamServer {
             >,
             tx: &mut DropCloserWriteHalf,
             outer_bytes_received: &Arc<AtomicU64>,
-            expected_size: u64,
+            max_size: Option<u64>,
         ) -> Result<(), Error> {
             loop {
                 let write_request = match stream.next().await {
@@ -427,39 +877,8 @@ impl ByteStreamServer {
                     Some(Ok(write_request)) => write_request,
                 };
 
-                if write_request.write_offset < 0 {
-                    return Err(make_input_err!(
-                        "Invalid negative write offset in write request: {}",
-                        write_request.write_offset
-                    ));
-                }
-                let write_offset = write_request.write_offset as u64;
-
-                // If we get duplicate data because a client didn't know where
-                // it left off from, then we can simply skip it.
-                let data = if write_offset < tx.get_bytes_written() {
-                    if (write_offset + write_request.data.len() as u64) < tx.get_bytes_written() {
-                        if write_request.finish_write {
-                            return Err(make_input_err!(
-                                "Resumed stream finished at {} bytes when we already received {} bytes.",
-                                write_offset + write_request.data.len() as u64,
-                                tx.get_bytes_written()
-                            ));
-                        }
-                        continue;
-                    }
-                    write_request
-                        .data
-                        .slice((tx.get_bytes_written() - write_offset) as usize..)
-                } else {
-                    if write_offset != tx.get_bytes_written() {
-                        return Err(make_input_err!(
-                            "Received out of order data. Got {}, expected {}",
-                            write_offset,
-                            tx.get_bytes_written()
-                        ));
-                    }
-                    write_request.data
+                let Some(data) = unwritten_data(&write_request, tx.get_bytes_written())? else {
+                    continue;
                 };
 
                 // Do not process EOF or weird stuff will happen.
@@ -472,7 +891,7 @@ impl ByteStreamServer {
                     outer_bytes_received.store(tx.get_bytes_written(), Ordering::Release);
                 }
 
-                if expected_size < tx.get_bytes_written() {
+                if max_size.is_some_and(|max_size| max_size < tx.get_bytes_written()) {
                     return Err(make_input_err!("Received more bytes than expected"));
                 }
                 if write_request.finish_write {
@@ -492,8 +911,37 @@ impl ByteStreamServer {
             .as_ref()
             .ok_or_else(|| make_input_err!("UUID must be set if writing data"))?
             .to_string();
-        let mut active_stream_guard = self.create_or_join_upload_stream(uuid, store, digest)?;
+        let compressor =
+            BlobCompressor::from_resource_name(stream.resource_info.compressor.as_deref())?;
         let expected_size = stream.resource_info.expected_size as u64;
+        // The offsets of compressed uploads count compressed bytes, which
+        // may be more than the size of the blob. The store checks the size
+        // of the decompressed data instead.
+        let max_size = (compressor == BlobCompressor::Identity).then_some(expected_size);
+
+        if let Some(persistent_uploads) = &self.persistent_uploads {
+            let committed_size = Self::persistent_write(
+                persistent_uploads,
+                &uuid,
+                store,
+                digest,
+                compressor,
+                max_size,
+                stream,
+            )
+            .await?;
+            return Ok(Response::new(WriteResponse {
+                committed_size: committed_size as i64,
+            }));
+        }
+
+        let mut active_stream_guard = self.create_or_join_upload_stream(
+            uuid,
+            stream.resource_info.instance_name.as_ref(),
+            store,
+            digest,
+            compressor,
+        )?;
 
         let active_stream = active_stream_guard.stream_state.as_mut().unwrap();
         try_join!(
@@ -501,36 +949,112 @@ impl ByteStreamServer {
                 stream,
                 &mut active_stream.tx,
                 &active_stream_guard.bytes_received,
-                expected_size
+                max_size
             ),
             (&mut active_stream.store_update_fut)
                 .map_err(|err| { err.append("Error updating inner store") })
         )?;
 
+        let committed_size = if compressor == BlobCompressor::Identity {
+            expected_size
+        } else {
+            active_stream_guard.bytes_received.load(Ordering::Acquire)
+        };
         // Close our guard and consider the stream no longer active.
         active_stream_guard.graceful_finish();
 
         Ok(Response::new(WriteResponse {
-            committed_size: expected_size as i64,
+            committed_size: committed_size as i64,
         }))
     }
 
+    /// Writes the data of an upload to its file, which may already have
+    /// data of an earlier request, and uploads the file to the store once
+    /// the client finished the upload. Returns the size of the upload.
+    async fn persistent_write(
+        persistent_uploads: &PersistentUploads,
+        uuid: &str,
+        store: Store,
+        digest: DigestInfo,
+        compressor: BlobCompressor,
+        max_size: Option<u64>,
+        mut stream: WriteRequestStreamWrapper<
+            impl Stream<Item = Result<WriteRequest, Status>> + Unpin,
+        >,
+    ) -> Result<u64, Error> {
+        let upload_guard =
+            persistent_uploads.claim(&PersistentUploads::file_name(uuid, digest, compressor)?)?;
+        let path = upload_guard.path();
+        let mut file = fs::open_file_for_write(&path)
+            .await
+            .err_tip(|| "Could not open persistent upload")?;
+        let writer = file.as_writer().await?;
+        let mut committed_size = writer
+            .seek(SeekFrom::End(0))
+            .await
+            .err_tip(|| "Could not seek to end of persistent upload")?;
+        if committed_size > 0 {
+            event!(
+                Level::INFO,
+                ?path,
+                committed_size,
+                "Resuming persistent upload"
+            );
+        }
+        loop {
+            let write_request = match stream.next().await {
+                None => {
+                    return Err(make_input_err!(
+                        "Client closed stream before sending all data"
+                    ))
+                }
+                Some(Err(err)) => return Err(err),
+                Some(Ok(write_request)) => write_request,
+            };
+            let Some(data) = unwritten_data(&write_request, committed_size)? else {
+                continue;
+            };
+            if !data.is_empty() {
+                // Flushed right away, so `QueryWriteStatus` only counts
+                // the data that was written to the file.
+                writer
+                    .write_all(&data)
+                    .await
+                    .err_tip(|| "Could not write to persistent upload")?;
+                writer
+                    .flush()
+                    .await
+                    .err_tip(|| "Could not flush persistent upload")?;
+                committed_size += data.len() as u64;
+            }
+            if max_size.is_some_and(|max_size| max_size < committed_size) {
+                return Err(make_input_err!("Received more bytes than expected"));
+            }
+            if write_request.finish_write {
+                break;
+            }
+        }
+        drop(file);
+
+        // The file is removed even if the store failed, as the client starts
+        // the upload over after an error.
+        let update_result = upload_file_to_store(&store, digest, compressor, &path).await;
+        let remove_result = fs::remove_file(&path)
+            .await
+            .err_tip(|| "Could not remove finished persistent upload");
+        update_result
+            .err_tip(|| "Error updating inner store")
+            .merge(remove_result)?;
+        Ok(committed_size)
+    }
+
     async fn inner_query_write_status(
         &self,
         query_request: &QueryWriteStatusRequest,
     ) -> Result<Response<QueryWriteStatusResponse>, Error> {
         let mut resource_info = ResourceInfo::new(&query_request.resource_name, true)?;
 
-        let store_clone = self
-            .stores
-            .get(resource_info.instance_name.as_ref())
-            .err_tip(|| {
-                format!(
-                    "'instance_name' not configured for '{}'",
-                    &resource_info.instance_name
-                )
-            })?
-            .clone();
+        let store_clone = self.checked_store(resource_info.instance_name.as_ref(), false)?;
 
         let digest = DigestInfo::try_new(resource_info.hash.as_ref(), resource_info.expected_size)?;
 
@@ -558,6 +1082,18 @@ impl ByteStreamServer {
             }
         }
 
+        if let Some(persistent_uploads) = &self.persistent_uploads {
+            let compressor =
+                BlobCompressor::from_resource_name(resource_info.compressor.as_deref())?;
+            let file_name = PersistentUploads::file_name(&uuid, digest, compressor)?;
+            if let Some(committed_size) = persistent_uploads.committed_size(&file_name).await? {
+                return Ok(Response::new(QueryWriteStatusResponse {
+                    committed_size: committed_size as i64,
+                    complete: false,
+                }));
+            }
+        }
+
         let has_fut = store_clone.has(digest);
         let Some(item_size) = has_fut.await.err_tip(|| "Failed to call .has() on store")? else {
             // We lie here and say that the stream needs to start over, even though
@@ -576,6 +1112,90 @@ impl ByteStreamServer {
     }
 }
 
+/// Uploads the data of a finished persistent upload at `path` to `store`.
+async fn upload_file_to_store(
+    store: &Store,
+    digest: DigestInfo,
+    compressor: BlobCompressor,
+    path: &Path,
+) -> Result<(), Error> {
+    let mut file = fs::open_file(path, u64::MAX)
+        .await
+        .err_tip(|| "Could not open persistent upload")?;
+    let (tx, mut rx) = make_buf_channel_pair();
+    let read_fut = async move {
+        let (_, mut tx) = file
+            .read_buf_cb(
+                (BytesMut::with_capacity(fs::DEFAULT_READ_BUFF_SIZE), tx),
+                move |(chunk, mut tx)| async move {
+                    tx.send(chunk.freeze())
+                        .await
+                        .err_tip(|| "Failed to send persistent upload to store")?;
+                    Ok((BytesMut::with_capacity(fs::DEFAULT_READ_BUFF_SIZE), tx))
+                },
+            )
+            .await
+            .err_tip(|| "Could not read persistent upload")?;
+        tx.send_eof()
+            .err_tip(|| "Could not send EOF of persistent upload to store")
+    };
+    // Bytestream always uses digest size as the actual byte size.
+    let size_info = UploadSizeInfo::ExactSize(digest.size_bytes());
+    if compressor == BlobCompressor::Identity {
+        return try_join!(read_fut, store.update(digest, rx, size_info)).map(|_| ());
+    }
+    let (mut store_tx, store_rx) = make_buf_channel_pair();
+    try_join!(
+        read_fut,
+        compressor.decompress_stream(&mut rx, &mut store_tx),
+        store.update(digest, store_rx, size_info),
+    )
+    .map(|_| ())
+}
+
+/// Returns the data of `write_request` that was not received yet, after
+/// `bytes_written` bytes of the upload were, or `None` if all of it was.
+fn unwritten_data(
+    write_request: &WriteRequest,
+    bytes_written: u64,
+) -> Result<Option<Bytes>, Error> {
+    if write_request.write_offset < 0 {
+        return Err(make_input_err!(
+            "Invalid negative write offset in write request: {}",
+            write_request.write_offset
+        ));
+    }
+    let write_offset = write_request.write_offset as u64;
+
+    // If we get duplicate data because a client didn't know where
+    // it left off from, then we can simply skip it.
+    if write_offset < bytes_written {
+        if (write_offset + write_request.data.len() as u64) < bytes_written {
+            if write_request.finish_write {
+                return Err(make_input_err!(
+                    "Resumed stream finished at {} bytes when we already received {} bytes.",
+                    write_offset + write_request.data.len() as u64,
+                    bytes_written
+                ));
+            }
+            return Ok(None);
+        }
+        return Ok(Some(
+            write_request
+                .data
+                .slice((bytes_written - write_offset) as usize..),
+        ));
+    }
+    if write_offset != bytes_written {
+        return Err(make_input_err!(
+            "Received out of order data. Got {}, expected {}",
+            write_offset,
+            bytes_written
+        ));
+    }
+    Ok(Some(write_request.data.clone()))
+}
+
 #[tonic::async_trait]
 impl ByteStream for ByteStreamServer {
     type ReadStream = ReadStream;
@@ -594,13 +1214,35 @@ impl ByteStream for ByteStreamServer {
         let read_request = grpc_request.into_inner();
         let ctx = OriginEventContext::new(|| &read_request).await;
 
+        if let Some(stream_name) = ActionOutputStreamName::parse(&read_request.resource_name) {
+            let resp = self
+                .read_action_output(&stream_name, &read_request)
+                .err_tip(|| "In ByteStreamServer::read")
+                .map(|stream| -> Response<Self::ReadStream> {
+                    Response::new(Box::pin(ctx.wrap_stream(stream)))
+                })
+                .map_err(Into::into);
+            ctx.emit(|| &resp).await;
+            return resp;
+        }
+
         let resource_info = ResourceInfo::new(&read_request.resource_name, false)?;
         let instance_name = resource_info.instance_name.as_ref();
-        let store = self
-            .stores
-            .get(instance_name)
-            .err_tip(|| format!("'instance_name' not configured for '{instance_name}'"))?
-            .clone();
+        let timer = RpcTimer::new("ByteStream", "Read", instance_name);
+        let audit = audit_record(|| {
+            AuditRecord::new(AuditOperation::CasRead, instance_name).with_digests([&Digest {
+                hash: resource_info.hash.to_string(),
+                size_bytes: resource_info.expected_size as i64,
+            }])
+        });
+        let store = match self.checked_store(instance_name, false) {
+            Ok(store) => store,
+            Err(err) => {
+                let resp = Err(err.into());
+                audit_log(audit, &resp).await;
+                return resp;
+            }
+        };
 
         let digest = DigestInfo::try_new(resource_info.hash.as_ref(), resource_info.expected_size)?;
 
@@ -608,20 +1250,28 @@ impl ByteStream for ByteStreamServer {
         if let Some(grpc_store) = store.downcast_ref::<GrpcStore>(Some(digest.into())) {
             let stream = grpc_store.read(Request::new(read_request)).await?;
             let resp = Ok(Response::new(ctx.wrap_stream(stream)));
+            timer.observe(&resp);
+            audit_log(audit, &resp).await;
             ctx.emit(|| &resp).await;
             return resp;
         }
 
-        let digest_function = resource_info.digest_function.as_deref().map_or_else(
-            || Ok(default_digest_hasher_func()),
-            DigestHasherFunc::try_from,
+        let digest_function = resolve_digest_hasher_func(
+            instance_name,
+            resource_info
+                .digest_function
+                .as_deref()
+                .map(DigestHasherFunc::try_from)
+                .transpose()?,
         )?;
+        digest_function.check_digest(&digest)?;
+        let compressor = BlobCompressor::from_resource_name(resource_info.compressor.as_deref())?;
 
         let resp = make_ctx_for_hash_func(digest_function)
             .err_tip(|| "In BytestreamServer::read")?
             .wrap_async(
                 error_span!("bytestream_read"),
-                self.inner_read(store, digest, read_request),
+                self.inner_read(store, digest, compressor, read_request),
             )
             .await
             .err_tip(|| "In ByteStreamServer::read")
@@ -633,6 +1283,8 @@ impl ByteStream for ByteStreamServer {
         if resp.is_ok() {
             event!(Level::DEBUG, return = "Ok(<stream>)");
         }
+        timer.observe(&resp);
+        audit_log(audit, &resp).await;
         ctx.emit(|| &resp).await;
         resp
     }
@@ -650,17 +1302,43 @@ impl ByteStream for ByteStreamServer {
     ) -> Result<Response<WriteResponse>, Status> {
         let request = grpc_request.into_inner();
         let ctx = OriginEventContext::new(|| &request).await;
-        let stream = WriteRequestStreamWrapper::from(ctx.wrap_stream(request))
+        let mut request = ctx.wrap_stream(request).peekable();
+        let maybe_stream_name = match Pin::new(&mut request).peek().await {
+            Some(Ok(first_msg)) => ActionOutputStreamName::parse(&first_msg.resource_name),
+            _ => None,
+        };
+        if let Some(stream_name) = maybe_stream_name {
+            let resp = self
+                .write_action_output(stream_name, request)
+                .await
+                .err_tip(|| "In ByteStreamServer::write")
+                .map_err(Into::into);
+            ctx.emit(|| &resp).await;
+            return resp;
+        }
+        let stream = WriteRequestStreamWrapper::from(request)
             .await
             .err_tip(|| "Could not unwrap first stream message")
             .map_err(Into::<Status>::into)?;
 
         let instance_name = stream.resource_info.instance_name.as_ref();
-        let store = self
-            .stores
-            .get(instance_name)
-            .err_tip(|| format!("'instance_name' not configured for '{instance_name}'"))?
-            .clone();
+        let timer = RpcTimer::new("ByteStream", "Write", instance_name);
+        let audit = audit_record(|| {
+            AuditRecord::new(AuditOperation::CasWrite, instance_name)
+                .with_digests([&Digest {
+                    hash: stream.resource_info.hash.to_string(),
+                    size_bytes: stream.resource_info.expected_size as i64,
+                }])
+                .with_upload_bytes(stream.resource_info.expected_size as u64)
+        });
+        let store = match self.checked_store(instance_name, true) {
+            Ok(store) => store,
+            Err(err) => {
+                let resp = Err(err.into());
+                audit_log(audit, &resp).await;
+                return resp;
+            }
+        };
 
         let digest = DigestInfo::try_new(
             &stream.resource_info.hash,
@@ -671,18 +1349,22 @@ impl ByteStream for ByteStreamServer {
         // If we are a GrpcStore we shortcut here, as this is a special store.
         if let Some(grpc_store) = store.downcast_ref::<GrpcStore>(Some(digest.into())) {
             let resp = grpc_store.write(stream).await.map_err(Into::into);
+            timer.observe(&resp);
+            audit_log(audit, &resp).await;
             ctx.emit(|| &resp).await;
             return resp;
         }
 
-        let digest_function = stream
-            .resource_info
-            .digest_function
-            .as_deref()
-            .map_or_else(
-                || Ok(default_digest_hasher_func()),
-                DigestHasherFunc::try_from,
-            )?;
+        let digest_function = resolve_digest_hasher_func(
+            instance_name,
+            stream
+                .resource_info
+                .digest_function
+                .as_deref()
+                .map(DigestHasherFunc::try_from)
+                .transpose()?,
+        )?;
+        digest_function.check_digest(&digest)?;
 
         let resp = make_ctx_for_hash_func(digest_function)
             .err_tip(|| "In BytestreamServer::write")?
@@ -693,6 +1375,8 @@ impl ByteStream for ByteStreamServer {
             .await
             .err_tip(|| "In ByteStreamServer::write")
             .map_err(Into::into);
+        timer.observe(&resp);
+        audit_log(audit, &resp).await;
         ctx.emit(|| &resp).await;
         resp
     }
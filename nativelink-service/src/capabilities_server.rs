@@ -15,12 +15,11 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 
-use nativelink_config::cas_server::{CapabilitiesConfig, InstanceName};
-use nativelink_error::{Error, ResultExt};
+use nativelink_config::cas_server::{CapabilitiesConfig, InstanceName, ServicesConfig};
+use nativelink_error::{error_if, Error, ResultExt};
 use nativelink_proto::build::bazel::remote::execution::v2::capabilities_server::{
     Capabilities, CapabilitiesServer as Server,
 };
-use nativelink_proto::build::bazel::remote::execution::v2::digest_function::Value as DigestFunction;
 use nativelink_proto::build::bazel::remote::execution::v2::priority_capabilities::PriorityRange;
 use nativelink_proto::build::bazel::remote::execution::v2::symlink_absolute_path_strategy::Value as SymlinkAbsolutePathStrategy;
 use nativelink_proto::build::bazel::remote::execution::v2::{
@@ -28,28 +27,57 @@ use nativelink_proto::build::bazel::remote::execution::v2::{
     GetCapabilitiesRequest, PriorityCapabilities, ServerCapabilities,
 };
 use nativelink_proto::build::bazel::semver::SemVer;
-use nativelink_util::digest_hasher::default_digest_hasher_func;
+use nativelink_util::blob_compression::BlobCompressor;
+use nativelink_util::digest_hasher::{
+    default_digest_hasher_func, instance_digest_hasher_funcs, set_instance_digest_hasher_funcs,
+    DigestHasherFunc, InstanceDigestHasherFuncs,
+};
 use nativelink_util::operation_state_manager::ClientStateManager;
 use nativelink_util::origin_event::OriginEventContext;
 use tonic::{Request, Response, Status};
 use tracing::{event, instrument, Level};
 
-const MAX_BATCH_TOTAL_SIZE: i64 = 64 * 1024;
+use crate::cas_server::{max_batch_total_size, DEFAULT_MAX_BATCH_TOTAL_SIZE};
+
+/// Capabilities of an instance derived from the services configured for it.
+#[derive(Debug)]
+struct InstanceCapabilities {
+    /// Set if remote execution is configured in the capabilities of the
+    /// instance.
+    supported_node_properties: Option<Vec<String>>,
+    exec_enabled: bool,
+    update_enabled: bool,
+    max_batch_total_size: i64,
+    symlink_absolute_path_strategy: SymlinkAbsolutePathStrategy,
+    supported_compressors: Vec<i32>,
+    supported_batch_update_compressors: Vec<i32>,
+}
+
+fn compressors_to_proto(compressors: &[BlobCompressor]) -> Vec<i32> {
+    compressors
+        .iter()
+        .map(|compressor| compressor.to_proto().into())
+        .collect()
+}
 
 #[derive(Debug, Default)]
 pub struct CapabilitiesServer {
-    supported_node_properties_for_instance: HashMap<InstanceName, Vec<String>>,
+    instances: HashMap<InstanceName, InstanceCapabilities>,
 }
 
 impl CapabilitiesServer {
+    /// Also registers the digest functions of each instance name, so the
+    /// other services accept and default to the same digest functions.
     pub async fn new(
-        config: &HashMap<InstanceName, CapabilitiesConfig>,
+        services: &ServicesConfig,
         scheduler_map: &HashMap<String, Arc<dyn ClientStateManager>>,
     ) -> Result<Self, Error> {
-        let mut supported_node_properties_for_instance = HashMap::new();
-        for (instance_name, cfg) in config {
-            let mut properties = Vec::new();
+        let mut instances = HashMap::new();
+        for (instance_name, cfg) in services.capabilities.iter().flatten() {
+            set_instance_digest_hasher_funcs(instance_name, make_digest_hasher_funcs(cfg)?);
+            let mut supported_node_properties = None;
             if let Some(remote_execution_cfg) = &cfg.remote_execution {
+                let mut properties = Vec::new();
                 let scheduler =
                     scheduler_map
                         .get(&remote_execution_cfg.scheduler)
@@ -76,12 +104,49 @@ impl CapabilitiesServer {
                         remote_execution_cfg.scheduler
                     );
                 }
+                supported_node_properties = Some(properties);
             }
-            supported_node_properties_for_instance.insert(instance_name.clone(), properties);
+            let cas_cfg = services.cas.as_ref().and_then(|cas| cas.get(instance_name));
+            let has_bytestream = services
+                .bytestream
+                .as_ref()
+                .is_some_and(|bytestream| bytestream.cas_stores.contains_key(instance_name));
+            let symlink_absolute_path_strategy = if cfg.allow_absolute_symlinks {
+                SymlinkAbsolutePathStrategy::Allowed
+            } else {
+                SymlinkAbsolutePathStrategy::Disallowed
+            };
+            instances.insert(
+                instance_name.clone(),
+                InstanceCapabilities {
+                    supported_node_properties,
+                    exec_enabled: services
+                        .execution
+                        .as_ref()
+                        .is_some_and(|execution| execution.contains_key(instance_name)),
+                    update_enabled: services
+                        .ac
+                        .as_ref()
+                        .and_then(|ac| ac.get(instance_name))
+                        .is_some_and(|ac_cfg| !ac_cfg.read_only),
+                    max_batch_total_size: cas_cfg
+                        .map_or(DEFAULT_MAX_BATCH_TOTAL_SIZE, max_batch_total_size)
+                        as i64,
+                    symlink_absolute_path_strategy,
+                    supported_compressors: if has_bytestream {
+                        compressors_to_proto(BlobCompressor::STREAM_COMPRESSORS)
+                    } else {
+                        Vec::new()
+                    },
+                    supported_batch_update_compressors: if cas_cfg.is_some() {
+                        compressors_to_proto(BlobCompressor::BATCH_COMPRESSORS)
+                    } else {
+                        Vec::new()
+                    },
+                },
+            );
         }
-        Ok(CapabilitiesServer {
-            supported_node_properties_for_instance,
-        })
+        Ok(CapabilitiesServer { instances })
     }
 
     pub fn into_service(self) -> Server<CapabilitiesServer> {
@@ -89,6 +154,28 @@ impl CapabilitiesServer {
     }
 }
 
+fn make_digest_hasher_funcs(cfg: &CapabilitiesConfig) -> Result<InstanceDigestHasherFuncs, Error> {
+    let supported: Vec<DigestHasherFunc> = if cfg.digest_functions.is_empty() {
+        DigestHasherFunc::ALL.to_vec()
+    } else {
+        cfg.digest_functions
+            .iter()
+            .copied()
+            .map(DigestHasherFunc::from)
+            .collect()
+    };
+    let default = match cfg.default_digest_function {
+        Some(default) => DigestHasherFunc::from(default),
+        None if supported.contains(&default_digest_hasher_func()) => default_digest_hasher_func(),
+        None => supported[0],
+    };
+    error_if!(
+        !supported.contains(&default),
+        "default_digest_function {default} must be one of digest_functions {supported:?}"
+    );
+    Ok(InstanceDigestHasherFuncs { default, supported })
+}
+
 #[tonic::async_trait]
 impl Capabilities for CapabilitiesServer {
     #[allow(clippy::blocks_in_conditions)]
@@ -107,40 +194,46 @@ impl Capabilities for CapabilitiesServer {
         let ctx = OriginEventContext::new(|| &request).await;
 
         let instance_name = request.instance_name;
-        let maybe_supported_node_properties = self
-            .supported_node_properties_for_instance
-            .get(&instance_name);
+        let instance = self
+            .instances
+            .get(&instance_name)
+            .err_tip(|| format!("'instance_name' not configured for '{instance_name}'"))?;
+        let digest_hasher_funcs = instance_digest_hasher_funcs(&instance_name);
+        let digest_functions: Vec<i32> = digest_hasher_funcs
+            .supported
+            .iter()
+            .map(|func| func.proto_digest_func().into())
+            .collect();
         let execution_capabilities =
-            maybe_supported_node_properties.map(|props_for_instance| ExecutionCapabilities {
-                digest_function: default_digest_hasher_func().proto_digest_func().into(),
-                exec_enabled: true, // TODO(blaise.bruer) Make this configurable.
-                execution_priority_capabilities: Some(PriorityCapabilities {
-                    priorities: vec![PriorityRange {
-                        min_priority: 0,
-                        max_priority: i32::MAX,
-                    }],
-                }),
-                supported_node_properties: props_for_instance.clone(),
-                digest_functions: vec![
-                    DigestFunction::Sha256.into(),
-                    DigestFunction::Blake3.into(),
-                ],
-            });
+            instance
+                .supported_node_properties
+                .as_ref()
+                .map(|props_for_instance| ExecutionCapabilities {
+                    digest_function: digest_hasher_funcs.default.proto_digest_func().into(),
+                    exec_enabled: instance.exec_enabled,
+                    execution_priority_capabilities: Some(PriorityCapabilities {
+                        priorities: vec![PriorityRange {
+                            min_priority: 0,
+                            max_priority: i32::MAX,
+                        }],
+                    }),
+                    supported_node_properties: props_for_instance.clone(),
+                    digest_functions: digest_functions.clone(),
+                });
 
         let resp = ServerCapabilities {
             cache_capabilities: Some(CacheCapabilities {
-                digest_functions: vec![
-                    DigestFunction::Sha256.into(),
-                    DigestFunction::Blake3.into(),
-                ],
+                digest_functions,
                 action_cache_update_capabilities: Some(ActionCacheUpdateCapabilities {
-                    update_enabled: true,
+                    update_enabled: instance.update_enabled,
                 }),
                 cache_priority_capabilities: None,
-                max_batch_total_size_bytes: MAX_BATCH_TOTAL_SIZE,
-                symlink_absolute_path_strategy: SymlinkAbsolutePathStrategy::Disallowed.into(),
-                supported_compressors: vec![],
-                supported_batch_update_compressors: vec![],
+                max_batch_total_size_bytes: instance.max_batch_total_size,
+                symlink_absolute_path_strategy: instance.symlink_absolute_path_strategy.into(),
+                supported_compressors: instance.supported_compressors.clone(),
+                supported_batch_update_compressors: instance
+                    .supported_batch_update_compressors
+                    .clone(),
             }),
             execution_capabilities,
             deprecated_api_version: None,
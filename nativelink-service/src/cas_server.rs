@@ -25,7 +25,7 @@ use nativelink_proto::build::bazel::remote::execution::v2::content_addressable_s
     ContentAddressableStorage, ContentAddressableStorageServer as Server,
 };
 use nativelink_proto::build::bazel::remote::execution::v2::{
-    batch_read_blobs_response, batch_update_blobs_response, compressor, BatchReadBlobsRequest,
+    batch_read_blobs_response, batch_update_blobs_response, BatchReadBlobsRequest,
     BatchReadBlobsResponse, BatchUpdateBlobsRequest, BatchUpdateBlobsResponse, Directory,
     FindMissingBlobsRequest, FindMissingBlobsResponse, GetTreeRequest, GetTreeResponse,
 };
@@ -33,17 +33,31 @@ use nativelink_proto::google::rpc::Status as GrpcStatus;
 use nativelink_store::ac_utils::get_and_decode_digest;
 use nativelink_store::grpc_store::GrpcStore;
 use nativelink_store::store_manager::StoreManager;
+use nativelink_util::audit_log::{audit_log, audit_record, AuditOperation, AuditRecord};
+use nativelink_util::blob_compression::BlobCompressor;
 use nativelink_util::common::DigestInfo;
-use nativelink_util::digest_hasher::make_ctx_for_hash_func;
+use nativelink_util::digest_hasher::make_ctx_for_instance_hash_func;
+use nativelink_util::histogram_metrics::RpcTimer;
+use nativelink_util::instance_access::InstanceAccess;
+use nativelink_util::origin_context::make_ctx_for_instance_name;
 use nativelink_util::origin_event::OriginEventContext;
 use nativelink_util::store_trait::{Store, StoreLike};
 use tonic::{Request, Response, Status};
 use tracing::{error_span, event, instrument, Level};
 
+struct CasStoreInfo {
+    store: Store,
+    access: InstanceAccess,
+    max_batch_total_size: usize,
+}
+
 pub struct CasServer {
-    stores: HashMap<String, Store>,
+    stores: HashMap<String, CasStoreInfo>,
 }
 
+/// If this value changes update the documentation in the config definition.
+pub const DEFAULT_MAX_BATCH_TOTAL_SIZE: usize = 4 * 1024 * 1024;
+
 type GetTreeStream = Pin<Box<dyn Stream<Item = Result<GetTreeResponse, Status>> + Send + 'static>>;
 
 impl CasServer {
@@ -56,13 +70,29 @@ impl CasServer {
             let store = store_manager.get_store(&cas_cfg.cas_store).ok_or_else(|| {
                 make_input_err!("'cas_store': '{}' does not exist", cas_cfg.cas_store)
             })?;
-            stores.insert(instance_name.to_string(), store);
+            stores.insert(
+                instance_name.to_string(),
+                CasStoreInfo {
+                    store,
+                    access: InstanceAccess::new(&cas_cfg.access),
+                    max_batch_total_size: max_batch_total_size(cas_cfg),
+                },
+            );
         }
         Ok(CasServer { stores })
     }
 
     pub fn into_service(self) -> Server<CasServer> {
-        Server::new(self)
+        // Batch requests also hold the digests of the blobs, and compressed
+        // blobs may be a little larger than the blobs themselves.
+        let max_decoding_message_size = self
+            .stores
+            .values()
+            .map(|store_info| store_info.max_batch_total_size)
+            .max()
+            .unwrap_or(DEFAULT_MAX_BATCH_TOTAL_SIZE)
+            .saturating_mul(2);
+        Server::new(self).max_decoding_message_size(max_decoding_message_size)
     }
 
     async fn inner_find_missing_blobs(
@@ -70,11 +100,12 @@ impl CasServer {
         request: FindMissingBlobsRequest,
     ) -> Result<Response<FindMissingBlobsResponse>, Error> {
         let instance_name = &request.instance_name;
-        let store = self
+        let store_info = self
             .stores
             .get(instance_name)
-            .err_tip(|| format!("'instance_name' not configured for '{instance_name}'"))?
-            .clone();
+            .err_tip(|| format!("'instance_name' not configured for '{instance_name}'"))?;
+        store_info.access.check_read(instance_name)?;
+        let store = store_info.store.clone();
 
         let mut requested_blobs = Vec::with_capacity(request.blob_digests.len());
         for digest in &request.blob_digests {
@@ -101,11 +132,12 @@ impl CasServer {
     ) -> Result<Response<BatchUpdateBlobsResponse>, Error> {
         let instance_name = &request.instance_name;
 
-        let store = self
+        let store_info = self
             .stores
             .get(instance_name)
-            .err_tip(|| format!("'instance_name' not configured for '{instance_name}'"))?
-            .clone();
+            .err_tip(|| format!("'instance_name' not configured for '{instance_name}'"))?;
+        store_info.access.check_write(instance_name)?;
+        let store = store_info.store.clone();
 
         // If we are a GrpcStore we shortcut here, as this is a special store.
         // Note: We don't know the digests here, so we try perform a very shallow
@@ -113,39 +145,60 @@ impl CasServer {
         if let Some(grpc_store) = store.downcast_ref::<GrpcStore>(None) {
             return grpc_store.batch_update_blobs(Request::new(request)).await;
         }
+        check_batch_total_size(
+            request
+                .requests
+                .iter()
+                .map(|request| request.digest.as_ref().map_or(0, |d| d.size_bytes)),
+            store_info.max_batch_total_size,
+        )?;
 
-        let store_ref = &store;
-        let update_futures: FuturesUnordered<_> = request
-            .requests
+        let mut digests = Vec::with_capacity(request.requests.len());
+        let mut items = Vec::with_capacity(request.requests.len());
+        for request in request.requests {
+            let digest = request
+                .digest
+                .clone()
+                .err_tip(|| "Digest not found in request")?;
+            let digest_info = DigestInfo::try_from(digest.clone())?;
+            let size_bytes = usize::try_from(digest_info.size_bytes())
+                .err_tip(|| "Digest size_bytes was not convertible to usize")?;
+            let compressor = BlobCompressor::from_proto(request.compressor)?;
+            let request_data = if compressor == BlobCompressor::Identity {
+                request.data
+            } else {
+                compressor
+                    .decompress(request.data, size_bytes)
+                    .err_tip(|| format!("Decompressing blob {digest_info}"))?
+            };
+            error_if!(
+                size_bytes != request_data.len(),
+                "Digest for upload had mismatching sizes, digest said {} data  said {}",
+                size_bytes,
+                request_data.len()
+            );
+            digests.push(digest);
+            items.push((digest_info.into(), request_data));
+        }
+        let results = make_ctx_for_instance_name(instance_name)
+            .err_tip(|| "In CasServer::inner_batch_update_blobs")?
+            .wrap_async(
+                error_span!("cas_server_update_many"),
+                store.update_many(items),
+            )
+            .await;
+        let responses = digests
             .into_iter()
-            .map(|request| async move {
-                let digest = request
-                    .digest
-                    .clone()
-                    .err_tip(|| "Digest not found in request")?;
-                let request_data = request.data;
-                let digest_info = DigestInfo::try_from(digest.clone())?;
-                let size_bytes = usize::try_from(digest_info.size_bytes())
-                    .err_tip(|| "Digest size_bytes was not convertible to usize")?;
-                error_if!(
-                    size_bytes != request_data.len(),
-                    "Digest for upload had mismatching sizes, digest said {} data  said {}",
-                    size_bytes,
-                    request_data.len()
-                );
-                let result = store_ref
-                    .update_oneshot(digest_info, request_data)
-                    .await
-                    .err_tip(|| "Error writing to store");
-                Ok::<_, Error>(batch_update_blobs_response::Response {
-                    digest: Some(digest),
-                    status: Some(result.map_or_else(Into::into, |()| GrpcStatus::default())),
-                })
+            .zip(results)
+            .map(|(digest, result)| batch_update_blobs_response::Response {
+                digest: Some(digest),
+                status: Some(
+                    result
+                        .err_tip(|| "Error writing to store")
+                        .map_or_else(Into::into, |()| GrpcStatus::default()),
+                ),
             })
             .collect();
-        let responses = update_futures
-            .try_collect::<Vec<batch_update_blobs_response::Response>>()
-            .await?;
 
         Ok(Response::new(BatchUpdateBlobsResponse { responses }))
     }
@@ -156,11 +209,12 @@ impl CasServer {
     ) -> Result<Response<BatchReadBlobsResponse>, Error> {
         let instance_name = &request.instance_name;
 
-        let store = self
+        let store_info = self
             .stores
             .get(instance_name)
-            .err_tip(|| format!("'instance_name' not configured for '{instance_name}'"))?
-            .clone();
+            .err_tip(|| format!("'instance_name' not configured for '{instance_name}'"))?;
+        store_info.access.check_read(instance_name)?;
+        let store = store_info.store.clone();
 
         // If we are a GrpcStore we shortcut here, as this is a special store.
         // Note: We don't know the digests here, so we try perform a very shallow
@@ -168,7 +222,21 @@ impl CasServer {
         if let Some(grpc_store) = store.downcast_ref::<GrpcStore>(None) {
             return grpc_store.batch_read_blobs(Request::new(request)).await;
         }
+        check_batch_total_size(
+            request.digests.iter().map(|digest| digest.size_bytes),
+            store_info.max_batch_total_size,
+        )?;
 
+        // We use the most preferred compressor the client accepts.
+        let compressor = BlobCompressor::BATCH_COMPRESSORS
+            .iter()
+            .copied()
+            .find(|compressor| {
+                request
+                    .acceptable_compressors
+                    .contains(&compressor.to_proto().into())
+            })
+            .unwrap_or(BlobCompressor::Identity);
         let store_ref = &store;
         let read_futures: FuturesUnordered<_> = request
             .digests
@@ -179,7 +247,8 @@ impl CasServer {
                 let result = store_ref
                     .get_part_unchunked(digest_copy, 0, None)
                     .await
-                    .err_tip(|| "Error reading from store");
+                    .err_tip(|| "Error reading from store")
+                    .and_then(|data| compressor.compress(data));
                 let (status, data) = result.map_or_else(
                     |mut e| {
                         if e.code == Code::NotFound {
@@ -195,7 +264,7 @@ impl CasServer {
                 Ok::<_, Error>(batch_read_blobs_response::Response {
                     status: Some(status),
                     digest: Some(digest),
-                    compressor: compressor::Value::Identity.into(),
+                    compressor: compressor.to_proto().into(),
                     data,
                 })
             })
@@ -213,11 +282,12 @@ impl CasServer {
     ) -> Result<impl Stream<Item = Result<GetTreeResponse, Status>> + Send + 'static, Error> {
         let instance_name = &request.instance_name;
 
-        let store = self
+        let store_info = self
             .stores
             .get(instance_name)
-            .err_tip(|| format!("'instance_name' not configured for '{instance_name}'"))?
-            .clone();
+            .err_tip(|| format!("'instance_name' not configured for '{instance_name}'"))?;
+        store_info.access.check_read(instance_name)?;
+        let store = store_info.store.clone();
 
         // If we are a GrpcStore we shortcut here, as this is a special store.
         // Note: We don't know the digests here, so we try perform a very shallow
@@ -301,6 +371,30 @@ impl CasServer {
     }
 }
 
+/// Returns the maximum total size of the blobs of batch requests to an
+/// instance with `config`.
+pub fn max_batch_total_size(config: &CasStoreConfig) -> usize {
+    if config.max_batch_total_size_bytes == 0 {
+        DEFAULT_MAX_BATCH_TOTAL_SIZE
+    } else {
+        config.max_batch_total_size_bytes
+    }
+}
+
+fn check_batch_total_size(
+    sizes: impl Iterator<Item = i64>,
+    max_batch_total_size: usize,
+) -> Result<(), Error> {
+    let total_size = sizes.fold(0u64, |total, size| {
+        total.saturating_add(u64::try_from(size).unwrap_or(0))
+    });
+    error_if!(
+        total_size > max_batch_total_size as u64,
+        "Batch request has {total_size} bytes of blobs, the limit is {max_batch_total_size} bytes"
+    );
+    Ok(())
+}
+
 #[tonic::async_trait]
 impl ContentAddressableStorage for CasServer {
     type GetTreeStream = GetTreeStream;
@@ -319,7 +413,16 @@ impl ContentAddressableStorage for CasServer {
     ) -> Result<Response<FindMissingBlobsResponse>, Status> {
         let request = grpc_request.into_inner();
         let ctx = OriginEventContext::new(|| &request).await;
-        let resp = make_ctx_for_hash_func(request.digest_function)
+        let timer = RpcTimer::new(
+            "ContentAddressableStorage",
+            "FindMissingBlobs",
+            &request.instance_name,
+        );
+        let audit = audit_record(|| {
+            AuditRecord::new(AuditOperation::CasFindMissing, &request.instance_name)
+                .with_digests(&request.blob_digests)
+        });
+        let resp = make_ctx_for_instance_hash_func(&request.instance_name, request.digest_function)
             .err_tip(|| "In CasServer::find_missing_blobs")?
             .wrap_async(
                 error_span!("cas_server_find_missing_blobs"),
@@ -328,6 +431,8 @@ impl ContentAddressableStorage for CasServer {
             .await
             .err_tip(|| "Failed on find_missing_blobs() command")
             .map_err(Into::into);
+        timer.observe(&resp);
+        audit_log(audit, &resp).await;
         ctx.emit(|| &resp).await;
         resp
     }
@@ -346,7 +451,17 @@ impl ContentAddressableStorage for CasServer {
     ) -> Result<Response<BatchUpdateBlobsResponse>, Status> {
         let request = grpc_request.into_inner();
         let ctx = OriginEventContext::new(|| &request).await;
-        let resp = make_ctx_for_hash_func(request.digest_function)
+        let timer = RpcTimer::new(
+            "ContentAddressableStorage",
+            "BatchUpdateBlobs",
+            &request.instance_name,
+        );
+        let audit = audit_record(|| {
+            AuditRecord::new(AuditOperation::CasWrite, &request.instance_name)
+                .with_digests(request.requests.iter().filter_map(|r| r.digest.as_ref()))
+                .with_upload_bytes(request.requests.iter().map(|r| r.data.len() as u64).sum())
+        });
+        let resp = make_ctx_for_instance_hash_func(&request.instance_name, request.digest_function)
             .err_tip(|| "In CasServer::batch_update_blobs")?
             .wrap_async(
                 error_span!("cas_server_batch_update_blobs"),
@@ -355,6 +470,8 @@ impl ContentAddressableStorage for CasServer {
             .await
             .err_tip(|| "Failed on batch_update_blobs() command")
             .map_err(Into::into);
+        timer.observe(&resp);
+        audit_log(audit, &resp).await;
         ctx.emit(|| &resp).await;
         resp
     }
@@ -373,7 +490,16 @@ impl ContentAddressableStorage for CasServer {
     ) -> Result<Response<BatchReadBlobsResponse>, Status> {
         let request = grpc_request.into_inner();
         let ctx = OriginEventContext::new(|| &request).await;
-        let resp = make_ctx_for_hash_func(request.digest_function)
+        let timer = RpcTimer::new(
+            "ContentAddressableStorage",
+            "BatchReadBlobs",
+            &request.instance_name,
+        );
+        let audit = audit_record(|| {
+            AuditRecord::new(AuditOperation::CasRead, &request.instance_name)
+                .with_digests(&request.digests)
+        });
+        let resp = make_ctx_for_instance_hash_func(&request.instance_name, request.digest_function)
             .err_tip(|| "In CasServer::batch_read_blobs")?
             .wrap_async(
                 error_span!("cas_server_batch_read_blobs"),
@@ -382,6 +508,8 @@ impl ContentAddressableStorage for CasServer {
             .await
             .err_tip(|| "Failed on batch_read_blobs() command")
             .map_err(Into::into);
+        timer.observe(&resp);
+        audit_log(audit, &resp).await;
         ctx.emit(|| &resp).await;
         resp
     }
@@ -399,7 +527,16 @@ impl ContentAddressableStorage for CasServer {
     ) -> Result<Response<Self::GetTreeStream>, Status> {
         let request = grpc_request.into_inner();
         let ctx = OriginEventContext::new(|| &request).await;
-        let resp = make_ctx_for_hash_func(request.digest_function)
+        let timer = RpcTimer::new(
+            "ContentAddressableStorage",
+            "GetTree",
+            &request.instance_name,
+        );
+        let audit = audit_record(|| {
+            AuditRecord::new(AuditOperation::CasRead, &request.instance_name)
+                .with_digests(request.root_digest.as_ref())
+        });
+        let resp = make_ctx_for_instance_hash_func(&request.instance_name, request.digest_function)
             .err_tip(|| "In CasServer::get_tree")?
             .wrap_async(
                 error_span!("cas_server_get_tree"),
@@ -414,6 +551,8 @@ impl ContentAddressableStorage for CasServer {
         if resp.is_ok() {
             event!(Level::DEBUG, return = "Ok(<stream>)");
         }
+        timer.observe(&resp);
+        audit_log(audit, &resp).await;
         ctx.emit(|| &resp).await;
         resp
     }
@@ -19,10 +19,11 @@ use std::pin::Pin;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use bytes::Bytes;
 use futures::stream::unfold;
 use futures::{Stream, StreamExt};
 use nativelink_config::cas_server::{ExecutionConfig, InstanceName};
-use nativelink_error::{make_input_err, Error, ResultExt};
+use nativelink_error::{make_input_err, Code, Error, ResultExt};
 use nativelink_proto::build::bazel::remote::execution::v2::execution_server::{
     Execution, ExecutionServer as Server,
 };
@@ -30,37 +31,79 @@ use nativelink_proto::build::bazel::remote::execution::v2::{
     Action, Command, ExecuteRequest, WaitExecutionRequest,
 };
 use nativelink_proto::google::longrunning::Operation;
+use nativelink_proto::google::rpc::{RetryInfo, Status as RpcStatus};
 use nativelink_store::ac_utils::get_and_decode_digest;
 use nativelink_store::store_manager::StoreManager;
 use nativelink_util::action_messages::{
     ActionInfo, ActionUniqueKey, ActionUniqueQualifier, OperationId, DEFAULT_EXECUTION_PRIORITY,
 };
+use nativelink_util::audit_log::{audit_log, audit_record, AuditOperation, AuditRecord};
 use nativelink_util::common::DigestInfo;
-use nativelink_util::digest_hasher::{make_ctx_for_hash_func, DigestHasherFunc};
+use nativelink_util::digest_hasher::{
+    default_digest_hasher_func, make_ctx_for_instance_hash_func, DigestHasherFunc,
+    ACTIVE_HASHER_FUNC,
+};
+use nativelink_util::histogram_metrics::RpcTimer;
+use nativelink_util::instance_access::InstanceAccess;
 use nativelink_util::operation_state_manager::{
     ActionStateResult, ClientStateManager, OperationFilter,
 };
+use nativelink_util::origin_context::ActiveOriginContext;
 use nativelink_util::origin_event::OriginEventContext;
 use nativelink_util::store_trait::Store;
+use prost::Message;
 use tonic::{Request, Response, Status};
 use tracing::{error_span, event, instrument, Level};
 
+/// Default delay clients are asked to wait before retrying an execution
+/// rejected because the queue of the scheduler is full.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_QUEUE_FULL_RETRY_DELAY_S: u64 = 5;
+
+/// Converts `err` to a status. `ResourceExhausted` errors carry a
+/// `RetryInfo` with `retry_delay`, so clients back off before retrying.
+fn to_status_with_retry_info(err: Error, retry_delay: Duration) -> Status {
+    if err.code != Code::ResourceExhausted {
+        return err.into();
+    }
+    let status = Status::from(err);
+    let retry_info = RetryInfo {
+        retry_delay: Some(prost_types::Duration {
+            seconds: i64::try_from(retry_delay.as_secs()).unwrap_or(i64::MAX),
+            nanos: i32::try_from(retry_delay.subsec_nanos()).unwrap_or_default(),
+        }),
+    };
+    let rpc_status = RpcStatus {
+        code: status.code() as i32,
+        message: status.message().to_string(),
+        details: vec![prost_types::Any {
+            type_url: "type.googleapis.com/google.rpc.RetryInfo".to_string(),
+            value: retry_info.encode_to_vec(),
+        }],
+    };
+    Status::with_details(
+        status.code(),
+        status.message(),
+        Bytes::from(rpc_status.encode_to_vec()),
+    )
+}
+
 type InstanceInfoName = String;
 
-struct NativelinkOperationId {
-    instance_name: InstanceInfoName,
-    client_operation_id: OperationId,
+pub(crate) struct NativelinkOperationId {
+    pub(crate) instance_name: InstanceInfoName,
+    pub(crate) client_operation_id: OperationId,
 }
 
 impl NativelinkOperationId {
-    fn new(instance_name: InstanceInfoName, client_operation_id: OperationId) -> Self {
+    pub(crate) fn new(instance_name: InstanceInfoName, client_operation_id: OperationId) -> Self {
         Self {
             instance_name,
             client_operation_id,
         }
     }
 
-    fn from_name(name: &str) -> Result<Self, Error> {
+    pub(crate) fn from_name(name: &str) -> Result<Self, Error> {
         let (instance_name, name) = name
             .split_once('/')
             .err_tip(|| "Expected instance_name and name to be separated by '/'")?;
@@ -80,6 +123,9 @@ impl fmt::Display for NativelinkOperationId {
 struct InstanceInfo {
     scheduler: Arc<dyn ClientStateManager>,
     cas_store: Store,
+    access: InstanceAccess,
+    queue_full_retry_delay: Duration,
+    stream_action_output: bool,
 }
 
 impl InstanceInfo {
@@ -133,7 +179,9 @@ impl InstanceInfo {
             digest_function,
             digest: action_digest,
         };
-        let unique_qualifier = if skip_cache_lookup {
+        // Results of `do_not_cache` actions must not be reused either, even
+        // if the cache still has one from before the action was marked.
+        let unique_qualifier = if skip_cache_lookup || action.do_not_cache {
             ActionUniqueQualifier::Uncachable(action_key)
         } else {
             ActionUniqueQualifier::Cachable(action_key)
@@ -181,11 +229,19 @@ impl ExecutionServer {
                 })?
                 .clone();
 
+            let mut queue_full_retry_delay_s = exec_cfg.queue_full_retry_delay_s;
+            if queue_full_retry_delay_s == 0 {
+                queue_full_retry_delay_s = DEFAULT_QUEUE_FULL_RETRY_DELAY_S;
+            }
+
             instance_infos.insert(
                 instance_name.to_string(),
                 InstanceInfo {
                     scheduler,
                     cas_store,
+                    access: InstanceAccess::new(&exec_cfg.access),
+                    queue_full_retry_delay: Duration::from_secs(queue_full_retry_delay_s),
+                    stream_action_output: exec_cfg.stream_action_output,
                 },
             );
         }
@@ -196,13 +252,19 @@ impl ExecutionServer {
         Server::new(self)
     }
 
+    /// Returns the updates of the operation. If `stream_action_output` is
+    /// set, they name the live stdout and stderr streams of the action while
+    /// it is executing.
     fn to_execute_stream(
         nl_client_operation_id: &NativelinkOperationId,
         action_listener: Box<dyn ActionStateResult>,
+        stream_action_output: bool,
     ) -> impl Stream<Item = Result<Operation, Status>> + Send + 'static {
         let client_operation_id = OperationId::from(nl_client_operation_id.to_string());
+        let instance_name = nl_client_operation_id.instance_name.clone();
         unfold(Some(action_listener), move |maybe_action_listener| {
             let client_operation_id = client_operation_id.clone();
+            let instance_name = instance_name.clone();
             async move {
                 let mut action_listener = maybe_action_listener?;
                 match action_listener.changed().await {
@@ -214,10 +276,15 @@ impl ExecutionServer {
                         } else {
                             Some(action_listener)
                         };
-                        Some((
-                            Ok(action_update.as_operation(client_operation_id)),
-                            maybe_action_listener,
-                        ))
+                        let operation = if stream_action_output {
+                            action_update.as_operation_with_output_streams(
+                                client_operation_id,
+                                &instance_name,
+                            )
+                        } else {
+                            action_update.as_operation(client_operation_id)
+                        };
+                        Some((Ok(operation), maybe_action_listener))
                     }
                     Err(err) => {
                         event!(Level::ERROR, ?err, "Error in action_listener stream");
@@ -228,9 +295,12 @@ impl ExecutionServer {
         })
     }
 
+    /// Schedules the action of `request`. The arguments of its command are
+    /// added to `audit`, if the request is audited.
     async fn inner_execute(
         &self,
         request: ExecuteRequest,
+        audit: &mut Option<AuditRecord>,
     ) -> Result<impl Stream<Item = Result<Operation, Status>> + Send + 'static, Error> {
         let instance_name = request.instance_name;
 
@@ -238,6 +308,7 @@ impl ExecutionServer {
             .instance_infos
             .get(&instance_name)
             .err_tip(|| "Instance name '{}' not configured")?;
+        instance_info.access.check_write(&instance_name)?;
 
         let digest = DigestInfo::try_from(
             request
@@ -252,6 +323,15 @@ impl ExecutionServer {
 
         let action =
             get_and_decode_digest::<Action>(&instance_info.cas_store, digest.into()).await?;
+        if let (Some(record), Some(command_digest)) = (audit.as_mut(), &action.command_digest) {
+            let command_digest = DigestInfo::try_from(command_digest.clone())?;
+            if let Ok(command) =
+                get_and_decode_digest::<Command>(&instance_info.cas_store, command_digest.into())
+                    .await
+            {
+                record.command = command.arguments;
+            }
+        }
         let action_info = instance_info
             .build_action_info(
                 instance_name.clone(),
@@ -259,10 +339,10 @@ impl ExecutionServer {
                 action,
                 priority,
                 request.skip_cache_lookup,
-                request
-                    .digest_function
-                    .try_into()
-                    .err_tip(|| "Could not convert digest function in inner_execute()")?,
+                // Resolved for the instance name in `execute()`.
+                ActiveOriginContext::get_value(&ACTIVE_HASHER_FUNC)
+                    .err_tip(|| "Could not get digest function in inner_execute()")?
+                    .map_or_else(default_digest_hasher_func, |v| *v),
             )
             .await?;
 
@@ -284,6 +364,7 @@ impl ExecutionServer {
                     .clone(),
             ),
             action_listener,
+            instance_info.stream_action_output,
         )))
     }
 
@@ -299,6 +380,9 @@ impl ExecutionServer {
                 nl_operation_id.instance_name,
             )));
         };
+        instance_info
+            .access
+            .check_read(&nl_operation_id.instance_name)?;
         let Some(rx) = instance_info
             .scheduler
             .filter_operations(OperationFilter {
@@ -312,7 +396,11 @@ impl ExecutionServer {
         else {
             return Err(Status::not_found("Failed to find existing task"));
         };
-        Ok(Self::to_execute_stream(&nl_operation_id, rx))
+        Ok(Self::to_execute_stream(
+            &nl_operation_id,
+            rx,
+            instance_info.stream_action_output,
+        ))
     }
 }
 
@@ -334,17 +422,28 @@ impl Execution for ExecutionServer {
     ) -> Result<Response<ExecuteStream>, Status> {
         let request = grpc_request.into_inner();
         let ctx = OriginEventContext::new(|| &request).await;
-        let resp = make_ctx_for_hash_func(request.digest_function)
+        let timer = RpcTimer::new("Execution", "Execute", &request.instance_name);
+        let queue_full_retry_delay = self.instance_infos.get(&request.instance_name).map_or(
+            Duration::from_secs(DEFAULT_QUEUE_FULL_RETRY_DELAY_S),
+            |instance_info| instance_info.queue_full_retry_delay,
+        );
+        let mut audit = audit_record(|| {
+            AuditRecord::new(AuditOperation::Execute, &request.instance_name)
+                .with_digests(request.action_digest.as_ref())
+        });
+        let resp = make_ctx_for_instance_hash_func(&request.instance_name, request.digest_function)
             .err_tip(|| "In ExecutionServer::execute")?
             .wrap_async(
                 error_span!("execution_server_execute"),
-                self.inner_execute(request),
+                self.inner_execute(request, &mut audit),
             )
             .await
             .map(|stream| ctx.wrap_stream(stream))
             .map(Response::new)
             .err_tip(|| "Failed on execute() command")
-            .map_err(Into::into);
+            .map_err(|err| to_status_with_retry_info(err, queue_full_retry_delay));
+        timer.observe(&resp);
+        audit_log(audit, &resp).await;
         ctx.emit(|| &resp).await;
         resp
     }
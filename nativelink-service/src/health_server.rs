@@ -24,9 +24,7 @@ use futures::StreamExt;
 use http_body_util::Full;
 use hyper::header::{HeaderValue, CONTENT_TYPE};
 use hyper::{Request, Response, StatusCode};
-use nativelink_util::health_utils::{
-    HealthRegistry, HealthStatus, HealthStatusDescription, HealthStatusReporter,
-};
+use nativelink_util::health_utils::{HealthRegistry, HealthStatus, TimedHealthStatusDescription};
 use nativelink_util::origin_context::OriginContext;
 use tower::Service;
 use tracing::error_span;
@@ -34,17 +32,46 @@ use tracing::error_span;
 /// Content type header value for JSON.
 const JSON_CONTENT_TYPE: &str = "application/json; charset=utf-8";
 
+/// What a request to the health server checks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HealthProbe {
+    /// Reports the status of every component, and fails if any failed.
+    Status,
+    /// Succeeds as long as the process serves requests, without checking
+    /// any component.
+    Liveness,
+    /// Reports the status of every component, and fails if any failed or
+    /// is still initializing, like a store loading its index.
+    Readiness,
+}
+
 #[derive(Clone)]
 pub struct HealthServer {
     health_registry: HealthRegistry,
+    probe: HealthProbe,
 }
 
 impl HealthServer {
     pub fn new(health_registry: HealthRegistry) -> Self {
-        Self { health_registry }
+        Self::new_with_probe(health_registry, HealthProbe::Status)
+    }
+
+    pub fn new_with_probe(health_registry: HealthRegistry, probe: HealthProbe) -> Self {
+        Self {
+            health_registry,
+            probe,
+        }
     }
 }
 
+fn json_response(status_code: StatusCode, body: String) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status_code)
+        .header(CONTENT_TYPE, HeaderValue::from_static(JSON_CONTENT_TYPE))
+        .body(Full::new(Bytes::from(body)))
+        .unwrap()
+}
+
 impl Service<Request<Body>> for HealthServer {
     type Response = Response<Full<Bytes>>;
     type Error = Infallible;
@@ -56,36 +83,40 @@ impl Service<Request<Body>> for HealthServer {
 
     fn call(&mut self, _req: Request<Body>) -> Self::Future {
         let health_registry = self.health_registry.clone();
+        let probe = self.probe;
         Box::pin(Arc::new(OriginContext::new()).wrap_async(
             error_span!("health_server_call"),
             async move {
-                let health_status_descriptions: Vec<HealthStatusDescription> =
-                    health_registry.health_status_report().collect().await;
+                if probe == HealthProbe::Liveness {
+                    return Ok(json_response(
+                        StatusCode::OK,
+                        r#"{"status":"Ok"}"#.to_string(),
+                    ));
+                }
+                let health_status_descriptions: Vec<TimedHealthStatusDescription> =
+                    health_registry.timed_health_status_report().collect().await;
 
                 match serde_json5::to_string(&health_status_descriptions) {
                     Ok(body) => {
-                        let contains_failed_report =
-                            health_status_descriptions.iter().any(|description| {
-                                matches!(description.status, HealthStatus::Failed { .. })
-                            });
-                        let status_code = if contains_failed_report {
-                            StatusCode::SERVICE_UNAVAILABLE
-                        } else {
+                        let is_healthy = health_status_descriptions.iter().all(|timed| {
+                            let status = &timed.description.status;
+                            match probe {
+                                HealthProbe::Readiness => status.is_ready(),
+                                _ => !matches!(status, HealthStatus::Failed { .. }),
+                            }
+                        });
+                        let status_code = if is_healthy {
                             StatusCode::OK
+                        } else {
+                            StatusCode::SERVICE_UNAVAILABLE
                         };
-
-                        Ok(Response::builder()
-                            .status(status_code)
-                            .header(CONTENT_TYPE, HeaderValue::from_static(JSON_CONTENT_TYPE))
-                            .body(Full::new(Bytes::from(body)))
-                            .unwrap())
+                        Ok(json_response(status_code, body))
                     }
 
-                    Err(e) => Ok(Response::builder()
-                        .status(StatusCode::INTERNAL_SERVER_ERROR)
-                        .header(CONTENT_TYPE, HeaderValue::from_static(JSON_CONTENT_TYPE))
-                        .body(Full::new(Bytes::from(format!("Internal Failure: {e:?}"))))
-                        .unwrap()),
+                    Err(e) => Ok(json_response(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        format!("Internal Failure: {e:?}"),
+                    )),
                 }
             },
         ))
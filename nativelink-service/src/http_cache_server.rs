@@ -0,0 +1,268 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::extract::Path;
+use axum::routing::{get, MethodRouter};
+use axum::Router;
+use futures::{try_join, StreamExt};
+use hyper::header::CONTENT_LENGTH;
+use hyper::{HeaderMap, Response, StatusCode};
+use nativelink_config::cas_server::HttpCacheConfig;
+use nativelink_error::{error_if, make_err, make_input_err, Code, Error, ResultExt};
+use nativelink_store::cas_utils::is_zero_digest;
+use nativelink_store::store_manager::StoreManager;
+use nativelink_util::background_spawn;
+use nativelink_util::buf_channel::make_buf_channel_pair;
+use nativelink_util::common::DigestInfo;
+use nativelink_util::digest_hasher::{default_digest_hasher_func, DigestHasher};
+use nativelink_util::instance_access::InstanceAccess;
+use nativelink_util::store_trait::{
+    Store, StoreKey, StoreLike, StoreOptimizations, UploadSizeInfo,
+};
+use tracing::{event, Level};
+
+#[derive(Clone, Copy, Debug)]
+enum CacheKind {
+    Ac,
+    Cas,
+}
+
+/// Serves the AC and CAS over the HTTP cache protocol of bazel-remote, where
+/// objects are addressed by their hash only.
+pub struct HttpCacheServer {
+    ac_store: Store,
+    cas_store: Store,
+    access: InstanceAccess,
+}
+
+impl HttpCacheServer {
+    pub fn new(config: &HttpCacheConfig, store_manager: &StoreManager) -> Result<Self, Error> {
+        let ac_store = store_manager
+            .get_store(&config.ac_store)
+            .ok_or_else(|| make_input_err!("'ac_store': '{}' does not exist", config.ac_store))?;
+        let cas_store = store_manager
+            .get_store(&config.cas_store)
+            .ok_or_else(|| make_input_err!("'cas_store': '{}' does not exist", config.cas_store))?;
+        // Looking up an object by its hash lists the keys with the hash,
+        // which would walk every key in the other stores.
+        for (field, name, store) in [
+            ("ac_store", &config.ac_store, &ac_store),
+            ("cas_store", &config.cas_store, &cas_store),
+        ] {
+            error_if!(
+                !store.optimized_for(StoreOptimizations::OrderedList),
+                "'{field}': '{name}' can not look up objects by hash in HttpCacheServer, use a memory or filesystem store"
+            );
+        }
+        Ok(HttpCacheServer {
+            ac_store,
+            cas_store,
+            access: InstanceAccess::new(&config.access),
+        })
+    }
+
+    pub fn into_router(self) -> Router {
+        let server = Arc::new(self);
+        Router::new()
+            .route(
+                "/ac/:hash",
+                Self::method_router(server.clone(), CacheKind::Ac),
+            )
+            .route("/cas/:hash", Self::method_router(server, CacheKind::Cas))
+    }
+
+    fn method_router(server: Arc<Self>, kind: CacheKind) -> MethodRouter {
+        let head_server = server.clone();
+        let put_server = server.clone();
+        get(move |Path(hash): Path<String>| async move {
+            to_response(server.get_object(kind, &hash).await)
+        })
+        .head(move |Path(hash): Path<String>| async move {
+            to_response(head_server.head_object(kind, &hash).await)
+        })
+        .put(
+            move |Path(hash): Path<String>, headers: HeaderMap, body: Body| async move {
+                to_response(put_server.put_object(kind, &hash, &headers, body).await)
+            },
+        )
+    }
+
+    fn store(&self, kind: CacheKind) -> &Store {
+        match kind {
+            CacheKind::Ac => &self.ac_store,
+            CacheKind::Cas => &self.cas_store,
+        }
+    }
+
+    /// Finds the digest of the object with `hash`. The protocol does not
+    /// send the size, so the keys with the hash are listed to find it.
+    async fn find_digest(&self, kind: CacheKind, hash: &str) -> Result<DigestInfo, Error> {
+        let first = DigestInfo::try_new(hash, 0)?;
+        if is_zero_digest(first) {
+            return Ok(first);
+        }
+        let last = DigestInfo::try_new(hash, u64::MAX)?;
+        let mut found = None;
+        self.store(kind)
+            .list(StoreKey::from(first)..=StoreKey::from(last), |key| {
+                if let StoreKey::Digest(digest) = key {
+                    found = Some(*digest);
+                }
+                false
+            })
+            .await
+            .err_tip(|| "In HttpCacheServer::find_digest")?;
+        found.ok_or_else(|| make_err!(Code::NotFound, "Object {hash} not found"))
+    }
+
+    /// Finds the digest of the object with `hash` and the size of the
+    /// object. The size of the digests of ActionResults written over gRPC
+    /// is the size of their Action, not of the ActionResult.
+    async fn find_object(&self, kind: CacheKind, hash: &str) -> Result<(DigestInfo, u64), Error> {
+        let digest = self.find_digest(kind, hash).await?;
+        let size = self
+            .store(kind)
+            .has(digest)
+            .await
+            .err_tip(|| "In HttpCacheServer::find_object")?
+            .ok_or_else(|| make_err!(Code::NotFound, "Object {hash} not found"))?;
+        Ok((digest, size))
+    }
+
+    /// Returns the digest to write the ActionResult of the Action with
+    /// `hash` to. gRPC clients look ActionResults up by the digest of their
+    /// Action, so the digest of an existing entry or of the Action in the
+    /// CAS is used. Only if neither exists, which gRPC clients would not
+    /// find either, the size of the ActionResult is used.
+    async fn ac_digest(&self, hash: &str, size: u64) -> Result<DigestInfo, Error> {
+        for kind in [CacheKind::Ac, CacheKind::Cas] {
+            match self.find_digest(kind, hash).await {
+                Ok(digest) if !is_zero_digest(digest) => return Ok(digest),
+                Ok(_) => {}
+                Err(err) if err.code == Code::NotFound => {}
+                Err(err) => return Err(err).err_tip(|| "In HttpCacheServer::ac_digest"),
+            }
+        }
+        DigestInfo::try_new(hash, size)
+    }
+
+    async fn head_object(&self, kind: CacheKind, hash: &str) -> Result<Response<Body>, Error> {
+        // The protocol has no instance name.
+        self.access.check_read("")?;
+        let (_, size) = self.find_object(kind, hash).await?;
+        Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_LENGTH, size)
+            .body(Body::empty())
+            .map_err(|e| make_err!(Code::Internal, "Could not build response: {e:?}"))
+    }
+
+    async fn get_object(&self, kind: CacheKind, hash: &str) -> Result<Response<Body>, Error> {
+        self.access.check_read("")?;
+        let (digest, size) = self.find_object(kind, hash).await?;
+        let (tx, rx) = make_buf_channel_pair();
+        let store = self.store(kind).clone();
+        background_spawn!("http_cache_server_get", async move {
+            // The client sees a truncated body if this fails.
+            if let Err(err) = store.get(digest, tx).await {
+                event!(Level::WARN, ?digest, ?err, "Failed to stream object");
+            }
+        });
+        Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_LENGTH, size)
+            .body(Body::from_stream(rx))
+            .map_err(|e| make_err!(Code::Internal, "Could not build response: {e:?}"))
+    }
+
+    /// Streams the request body into the store. Objects uploaded to the
+    /// CAS must match their hash, otherwise the upload is aborted.
+    async fn put_object(
+        &self,
+        kind: CacheKind,
+        hash: &str,
+        headers: &HeaderMap,
+        body: Body,
+    ) -> Result<Response<Body>, Error> {
+        self.access.check_write("")?;
+        let size = headers
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok())
+            .ok_or_else(|| make_input_err!("A valid Content-Length header is required"))?;
+        let digest = match kind {
+            CacheKind::Ac => self.ac_digest(hash, size).await?,
+            CacheKind::Cas => DigestInfo::try_new(hash, size)?,
+        };
+        let (mut tx, rx) = make_buf_channel_pair();
+        let send_fut = async move {
+            let mut hasher = match kind {
+                CacheKind::Ac => None,
+                CacheKind::Cas => Some(default_digest_hasher_func().hasher()),
+            };
+            let mut body_stream = body.into_data_stream();
+            while let Some(chunk) = body_stream.next().await {
+                let chunk =
+                    chunk.map_err(|e| make_input_err!("Could not read request body: {e:?}"))?;
+                if let Some(hasher) = &mut hasher {
+                    hasher.update(&chunk);
+                }
+                tx.send(chunk)
+                    .await
+                    .err_tip(|| "In HttpCacheServer::put_object")?;
+            }
+            if let Some(mut hasher) = hasher {
+                let actual_digest = hasher.finalize_digest();
+                // Dropping `tx` without an EOF aborts the upload.
+                if actual_digest != digest {
+                    return Err(make_input_err!(
+                        "Uploaded object has digest {actual_digest}, expected {digest}"
+                    ));
+                }
+            }
+            tx.send_eof()
+        };
+        try_join!(
+            send_fut,
+            self.store(kind)
+                .update(digest, rx, UploadSizeInfo::ExactSize(size)),
+        )
+        .err_tip(|| "In HttpCacheServer::put_object")?;
+        Response::builder()
+            .status(StatusCode::OK)
+            .body(Body::empty())
+            .map_err(|e| make_err!(Code::Internal, "Could not build response: {e:?}"))
+    }
+}
+
+fn to_response(result: Result<Response<Body>, Error>) -> Response<Body> {
+    match result {
+        Ok(response) => response,
+        Err(err) => {
+            let status = match err.code {
+                Code::NotFound => StatusCode::NOT_FOUND,
+                Code::InvalidArgument => StatusCode::BAD_REQUEST,
+                Code::PermissionDenied => StatusCode::FORBIDDEN,
+                Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            let mut response = Response::new(Body::from(format!("Error: {err:?}")));
+            *response.status_mut() = status;
+            response
+        }
+    }
+}
@@ -13,10 +13,16 @@
 // limitations under the License.
 
 pub mod ac_server;
+pub mod admin_server;
 pub mod bep_server;
+pub mod blob_redirect_server;
 pub mod bytestream_server;
 pub mod capabilities_server;
 pub mod cas_server;
 pub mod execution_server;
 pub mod health_server;
+pub mod http_cache_server;
+pub mod operations_server;
+pub mod pprof_server;
+pub mod remote_asset_server;
 pub mod worker_api_server;
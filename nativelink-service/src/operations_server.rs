@@ -0,0 +1,312 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use futures::StreamExt;
+use nativelink_config::cas_server::{ExecutionConfig, InstanceName};
+use nativelink_error::{error_if, make_err, make_input_err, Code, Error, ResultExt};
+use nativelink_proto::build::bazel::remote::execution::v2::ExecutedActionMetadata;
+use nativelink_proto::google::longrunning::operations_server::{
+    Operations, OperationsServer as Server,
+};
+use nativelink_proto::google::longrunning::{
+    CancelOperationRequest, DeleteOperationRequest, GetOperationRequest, ListOperationsRequest,
+    ListOperationsResponse, Operation, WaitOperationRequest,
+};
+use nativelink_util::action_messages::OperationId;
+use nativelink_util::instance_access::InstanceAccess;
+use nativelink_util::operation_state_manager::{
+    ActionStateResult, ClientStateManager, OperationFilter,
+};
+use tonic::{Request, Response, Status};
+use tracing::{instrument, Level};
+
+use crate::execution_server::NativelinkOperationId;
+
+struct InstanceInfo {
+    scheduler: Arc<dyn ClientStateManager>,
+    access: InstanceAccess,
+}
+
+/// Implements `google.longrunning.Operations` for the operations of the
+/// execution service, so they can be listed, queried and cancelled.
+pub struct OperationsServer {
+    instance_infos: HashMap<InstanceName, InstanceInfo>,
+}
+
+impl OperationsServer {
+    pub fn new(
+        config: &HashMap<InstanceName, ExecutionConfig>,
+        scheduler_map: &HashMap<String, Arc<dyn ClientStateManager>>,
+    ) -> Result<Self, Error> {
+        let mut instance_infos = HashMap::with_capacity(config.len());
+        for (instance_name, exec_cfg) in config {
+            let scheduler = scheduler_map
+                .get(&exec_cfg.scheduler)
+                .err_tip(|| {
+                    format!(
+                        "Scheduler needs config for '{}' because it exists in execution",
+                        exec_cfg.scheduler
+                    )
+                })?
+                .clone();
+            instance_infos.insert(
+                instance_name.to_string(),
+                InstanceInfo {
+                    scheduler,
+                    access: InstanceAccess::new(&exec_cfg.access),
+                },
+            );
+        }
+        Ok(Self { instance_infos })
+    }
+
+    pub fn into_service(self) -> Server<OperationsServer> {
+        Server::new(self)
+    }
+
+    fn get_instance_info(&self, instance_name: &str) -> Result<&InstanceInfo, Error> {
+        self.instance_infos.get(instance_name).ok_or_else(|| {
+            make_err!(
+                Code::NotFound,
+                "No scheduler with the instance name {instance_name}"
+            )
+        })
+    }
+
+    /// Finds an operation by its client operation id or, as listing
+    /// operations reports them by it, by its operation id.
+    async fn find_operation(
+        instance_info: &InstanceInfo,
+        client_operation_id: &OperationId,
+    ) -> Result<Box<dyn ActionStateResult>, Error> {
+        let filters = [
+            OperationFilter {
+                client_operation_id: Some(client_operation_id.clone()),
+                ..Default::default()
+            },
+            OperationFilter {
+                operation_id: Some(client_operation_id.clone()),
+                ..Default::default()
+            },
+        ];
+        for filter in filters {
+            let maybe_action_state_result = instance_info
+                .scheduler
+                .filter_operations(filter)
+                .await
+                .err_tip(|| "In OperationsServer::find_operation")?
+                .next()
+                .await;
+            if let Some(action_state_result) = maybe_action_state_result {
+                return Ok(action_state_result);
+            }
+        }
+        Err(make_err!(
+            Code::NotFound,
+            "Operation {client_operation_id} does not exist"
+        ))
+    }
+
+    async fn inner_list_operations(
+        &self,
+        request: ListOperationsRequest,
+    ) -> Result<ListOperationsResponse, Error> {
+        // The name of the parent resource of operations is their instance name.
+        let instance_name = request.name;
+        let instance_info = self.get_instance_info(&instance_name)?;
+        instance_info.access.check_read(&instance_name)?;
+        error_if!(
+            !request.filter.is_empty(),
+            "Filtering operations is not supported, got '{}'",
+            request.filter
+        );
+        let page_size = usize::try_from(request.page_size)
+            .map_err(|e| make_input_err!("Invalid page_size {}: {e:?}", request.page_size))?;
+        let offset = if request.page_token.is_empty() {
+            0
+        } else {
+            request.page_token.parse::<usize>().map_err(|e| {
+                make_input_err!("Invalid page_token '{}': {e:?}", request.page_token)
+            })?
+        };
+
+        let mut action_state_results = instance_info
+            .scheduler
+            .filter_operations(OperationFilter::default())
+            .await
+            .err_tip(|| "In OperationsServer::list_operations")?;
+        let mut operations = Vec::new();
+        let mut next_page_token = String::new();
+        let mut index = 0;
+        while let Some(action_state_result) = action_state_results.next().await {
+            let (action_state, _maybe_origin_metadata) = action_state_result
+                .as_state()
+                .await
+                .err_tip(|| "In OperationsServer::list_operations")?;
+            // Only in-flight operations are listed.
+            if action_state.stage.is_finished() {
+                continue;
+            }
+            let (action_info, _maybe_origin_metadata) = action_state_result
+                .as_action_info()
+                .await
+                .err_tip(|| "In OperationsServer::list_operations")?;
+            // Schedulers may be shared by several instances.
+            if action_info.instance_name() != &instance_name {
+                continue;
+            }
+            if index >= offset {
+                if page_size != 0 && operations.len() == page_size {
+                    next_page_token = index.to_string();
+                    break;
+                }
+                let name = NativelinkOperationId::new(
+                    instance_name.clone(),
+                    action_state.client_operation_id.clone(),
+                );
+                let worker = action_state_result
+                    .as_worker_id()
+                    .await
+                    .err_tip(|| "In OperationsServer::list_operations")?
+                    .map(|worker_id| worker_id.to_string())
+                    .unwrap_or_default();
+                // Let the listing tell how long operations were queued for
+                // and which worker is running them.
+                operations.push(action_state.as_operation_with_partial_execution_metadata(
+                    OperationId::from(name.to_string()),
+                    Some(ExecutedActionMetadata {
+                        worker,
+                        queued_timestamp: Some(action_info.insert_timestamp.into()),
+                        ..Default::default()
+                    }),
+                ));
+            }
+            index += 1;
+        }
+        Ok(ListOperationsResponse {
+            operations,
+            next_page_token,
+        })
+    }
+
+    async fn inner_get_operation(&self, request: GetOperationRequest) -> Result<Operation, Error> {
+        let nl_operation_id = NativelinkOperationId::from_name(&request.name)
+            .err_tip(|| "Failed to parse operation name in OperationsServer::get_operation")?;
+        let instance_info = self.get_instance_info(&nl_operation_id.instance_name)?;
+        instance_info
+            .access
+            .check_read(&nl_operation_id.instance_name)?;
+        let action_state_result =
+            Self::find_operation(instance_info, &nl_operation_id.client_operation_id).await?;
+        let (action_state, _maybe_origin_metadata) = action_state_result
+            .as_state()
+            .await
+            .err_tip(|| "In OperationsServer::get_operation")?;
+        Ok(action_state.as_operation(OperationId::from(request.name)))
+    }
+
+    async fn inner_cancel_operation(&self, request: CancelOperationRequest) -> Result<(), Error> {
+        let nl_operation_id = NativelinkOperationId::from_name(&request.name)
+            .err_tip(|| "Failed to parse operation name in OperationsServer::cancel_operation")?;
+        let instance_info = self.get_instance_info(&nl_operation_id.instance_name)?;
+        instance_info
+            .access
+            .check_write(&nl_operation_id.instance_name)?;
+        instance_info
+            .scheduler
+            .cancel_operation(&nl_operation_id.client_operation_id)
+            .await
+            .err_tip(|| "In OperationsServer::cancel_operation")
+    }
+}
+
+#[tonic::async_trait]
+impl Operations for OperationsServer {
+    #[allow(clippy::blocks_in_conditions)]
+    #[instrument(
+        err,
+        ret(level = Level::DEBUG),
+        level = Level::ERROR,
+        skip_all,
+        fields(request = ?grpc_request.get_ref())
+    )]
+    async fn list_operations(
+        &self,
+        grpc_request: Request<ListOperationsRequest>,
+    ) -> Result<Response<ListOperationsResponse>, Status> {
+        self.inner_list_operations(grpc_request.into_inner())
+            .await
+            .err_tip(|| "Failed on list_operations() command")
+            .map(Response::new)
+            .map_err(Into::into)
+    }
+
+    #[allow(clippy::blocks_in_conditions)]
+    #[instrument(
+        err,
+        ret(level = Level::DEBUG),
+        level = Level::ERROR,
+        skip_all,
+        fields(request = ?grpc_request.get_ref())
+    )]
+    async fn get_operation(
+        &self,
+        grpc_request: Request<GetOperationRequest>,
+    ) -> Result<Response<Operation>, Status> {
+        self.inner_get_operation(grpc_request.into_inner())
+            .await
+            .err_tip(|| "Failed on get_operation() command")
+            .map(Response::new)
+            .map_err(Into::into)
+    }
+
+    async fn delete_operation(
+        &self,
+        _grpc_request: Request<DeleteOperationRequest>,
+    ) -> Result<Response<()>, Status> {
+        Err(Status::unimplemented(
+            "Operations are removed once no client is waiting for them",
+        ))
+    }
+
+    #[allow(clippy::blocks_in_conditions)]
+    #[instrument(
+        err,
+        level = Level::ERROR,
+        skip_all,
+        fields(request = ?grpc_request.get_ref())
+    )]
+    async fn cancel_operation(
+        &self,
+        grpc_request: Request<CancelOperationRequest>,
+    ) -> Result<Response<()>, Status> {
+        self.inner_cancel_operation(grpc_request.into_inner())
+            .await
+            .err_tip(|| "Failed on cancel_operation() command")
+            .map(Response::new)
+            .map_err(Into::into)
+    }
+
+    async fn wait_operation(
+        &self,
+        _grpc_request: Request<WaitOperationRequest>,
+    ) -> Result<Response<Operation>, Status> {
+        Err(Status::unimplemented(
+            "Use WaitExecution to wait for operations",
+        ))
+    }
+}
@@ -0,0 +1,216 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::extract::RawQuery;
+use axum::routing::get;
+use axum::Router;
+use hyper::header::CONTENT_TYPE;
+use hyper::{Response, StatusCode};
+use nativelink_config::cas_server::PprofConfig;
+use nativelink_error::{make_err, make_input_err, Code, Error, ResultExt};
+use nativelink_util::memory_budget::global_memory_budget;
+use nativelink_util::spawn_blocking;
+use pprof::protos::Message;
+use serde_json::json;
+
+/// Note: This must be kept in sync with the documentation in
+/// `PprofConfig::max_profile_duration_s`.
+const DEFAULT_MAX_PROFILE_DURATION_S: u64 = 300;
+
+/// Duration and frequency of CPU profiles that don't set them, the same as
+/// the ones of Go's `net/http/pprof`.
+const DEFAULT_PROFILE_DURATION_S: u64 = 30;
+const DEFAULT_PROFILE_FREQUENCY_HZ: i32 = 99;
+const MAX_PROFILE_FREQUENCY_HZ: i32 = 1000;
+
+/// Frames of these libraries are left out of CPU profiles, as unwinding
+/// through them from a signal handler may crash the process.
+const PROFILE_BLOCKLIST: [&str; 4] = ["libc", "libgcc", "pthread", "vdso"];
+
+/// Serves CPU profiles and memory summaries of the process on demand.
+pub struct PprofServer {
+    max_profile_duration: Duration,
+    /// Set while a CPU profile is taken, as the profiler can only take one
+    /// at a time.
+    profiling: AtomicBool,
+}
+
+impl PprofServer {
+    pub fn new(config: &PprofConfig) -> Self {
+        let max_profile_duration_s = if config.max_profile_duration_s == 0 {
+            DEFAULT_MAX_PROFILE_DURATION_S
+        } else {
+            config.max_profile_duration_s
+        };
+        Self {
+            max_profile_duration: Duration::from_secs(max_profile_duration_s),
+            profiling: AtomicBool::new(false),
+        }
+    }
+
+    pub fn into_router(self) -> Router {
+        let server = Arc::new(self);
+        Router::new()
+            .route(
+                "/profile",
+                get(move |RawQuery(query): RawQuery| async move {
+                    to_response(server.cpu_profile(query.as_deref()).await)
+                }),
+            )
+            .route("/heap", get(|| async { to_response(heap_summary()) }))
+    }
+
+    async fn cpu_profile(self: Arc<Self>, query: Option<&str>) -> Result<Response<Body>, Error> {
+        let mut duration_s = DEFAULT_PROFILE_DURATION_S;
+        let mut frequency = DEFAULT_PROFILE_FREQUENCY_HZ;
+        for (name, value) in query
+            .unwrap_or_default()
+            .split('&')
+            .filter_map(|param| param.split_once('='))
+        {
+            match name {
+                "seconds" => {
+                    duration_s = value
+                        .parse()
+                        .map_err(|e| make_input_err!("Invalid seconds '{value}': {e:?}"))?;
+                }
+                "frequency" => {
+                    frequency = value
+                        .parse()
+                        .map_err(|e| make_input_err!("Invalid frequency '{value}': {e:?}"))?;
+                }
+                _ => {}
+            }
+        }
+        let duration = Duration::from_secs(duration_s);
+        if duration.is_zero() || duration > self.max_profile_duration {
+            return Err(make_input_err!(
+                "Profile duration must be between 1 and {} seconds, got {duration_s}",
+                self.max_profile_duration.as_secs()
+            ));
+        }
+        if !(1..=MAX_PROFILE_FREQUENCY_HZ).contains(&frequency) {
+            return Err(make_input_err!(
+                "Profile frequency must be between 1 and {MAX_PROFILE_FREQUENCY_HZ} Hz, got {frequency}"
+            ));
+        }
+        if self.profiling.swap(true, Ordering::Acquire) {
+            return Err(make_err!(
+                Code::ResourceExhausted,
+                "A CPU profile is already being taken"
+            ));
+        }
+
+        let profiling_guard = ProfilingGuard(self.clone());
+        // The profiler samples every thread of the process, so this thread
+        // only has to wait.
+        let profile = spawn_blocking!("pprof_cpu_profile", move || {
+            let _profiling_guard = profiling_guard;
+            take_cpu_profile(duration, frequency)
+        })
+        .await
+        .map_err(|e| make_err!(Code::Internal, "CPU profile task failed: {e:?}"))??;
+        Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, "application/octet-stream")
+            .body(Body::from(profile))
+            .map_err(|e| make_err!(Code::Internal, "Could not build response: {e:?}"))
+    }
+}
+
+/// Allows the next CPU profile once dropped, even if the client went away
+/// while the profile was taken.
+struct ProfilingGuard(Arc<PprofServer>);
+
+impl Drop for ProfilingGuard {
+    fn drop(&mut self) {
+        self.0.profiling.store(false, Ordering::Release);
+    }
+}
+
+/// Samples the CPU for `duration` and encodes the profile in the pprof
+/// format.
+fn take_cpu_profile(duration: Duration, frequency: i32) -> Result<Vec<u8>, Error> {
+    let guard = pprof::ProfilerGuardBuilder::default()
+        .frequency(frequency)
+        .blocklist(&PROFILE_BLOCKLIST)
+        .build()
+        .map_err(|e| make_err!(Code::Internal, "Could not start CPU profiler: {e:?}"))?;
+    std::thread::sleep(duration);
+    let profile = guard
+        .report()
+        .build()
+        .and_then(|report| report.pprof())
+        .map_err(|e| make_err!(Code::Internal, "Could not build CPU profile: {e:?}"))?;
+    Ok(profile.encode_to_vec())
+}
+
+/// Returns the memory accounted by the memory budget and the memory sizes
+/// the kernel reports for the process.
+fn heap_summary() -> Result<Response<Body>, Error> {
+    let memory_budget = global_memory_budget();
+    let summary = json!({
+        "memory_budget": {
+            "used_bytes": memory_budget.used_bytes(),
+            "max_bytes": memory_budget.max_bytes(),
+        },
+        "process": process_memory()?,
+    });
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(summary.to_string()))
+        .map_err(|e| make_err!(Code::Internal, "Could not build response: {e:?}"))
+}
+
+/// Returns the memory sizes of `/proc/self/status`, like the resident size
+/// `VmRSS` and its peak `VmHWM`, in bytes. Empty on platforms without it.
+fn process_memory() -> Result<BTreeMap<String, u64>, Error> {
+    let status = match std::fs::read_to_string("/proc/self/status") {
+        Ok(status) => status,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(Error::from(e)).err_tip(|| "Could not read /proc/self/status"),
+    };
+    Ok(status
+        .lines()
+        .filter_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            let kib = value.trim().strip_suffix(" kB")?.parse::<u64>().ok()?;
+            name.starts_with("Vm")
+                .then(|| (name.to_string(), kib * 1024))
+        })
+        .collect())
+}
+
+fn to_response(result: Result<Response<Body>, Error>) -> Response<Body> {
+    match result {
+        Ok(response) => response,
+        Err(err) => {
+            let status = match err.code {
+                Code::InvalidArgument => StatusCode::BAD_REQUEST,
+                Code::ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            let mut response = Response::new(Body::from(format!("Error: {err:?}")));
+            *response.status_mut() = status;
+            response
+        }
+    }
+}
@@ -0,0 +1,693 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bytes::{Bytes, BytesMut};
+use nativelink_config::cas_server::{InstanceName, RemoteAssetConfig};
+use nativelink_error::{make_err, make_input_err, Code, Error, ResultExt};
+use nativelink_proto::build::bazel::remote::asset::v1::fetch_server::{Fetch, FetchServer};
+use nativelink_proto::build::bazel::remote::asset::v1::push_server::{Push, PushServer};
+use nativelink_proto::build::bazel::remote::asset::v1::{
+    FetchBlobRequest, FetchBlobResponse, FetchDirectoryRequest, FetchDirectoryResponse,
+    PushBlobRequest, PushBlobResponse, PushDirectoryRequest, PushDirectoryResponse, Qualifier,
+};
+use nativelink_proto::build::bazel::remote::execution::v2::Digest;
+use nativelink_store::asset_fetcher::{AssetFetcher, HttpAssetFetcher};
+use nativelink_store::store_manager::StoreManager;
+use nativelink_util::common::DigestInfo;
+use nativelink_util::digest_hasher::{
+    default_digest_hasher_func, make_ctx_for_instance_hash_func, DigestHasher, DigestHasherFunc,
+    ACTIVE_HASHER_FUNC,
+};
+use nativelink_util::instance_access::InstanceAccess;
+use nativelink_util::origin_context::ActiveOriginContext;
+use nativelink_util::store_trait::{Store, StoreKey, StoreLike};
+use prost::Message;
+use tonic::{Request, Response, Status};
+use tracing::{error_span, event, instrument, Level};
+
+/// Default for `cache_ttl_s` in the config.
+const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Default for `fetch_timeout_s` in the config.
+const DEFAULT_FETCH_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Default for `max_fetch_size` in the config.
+const DEFAULT_MAX_FETCH_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// Qualifier with the Subresource Integrity checksum of the content.
+const CHECKSUM_SRI_QUALIFIER: &str = "checksum.sri";
+
+/// Qualifier Bazel uses to tell apart downloads of the same URI.
+const CANONICAL_ID_QUALIFIER: &str = "bazel.canonical_id";
+
+/// Prefix of qualifiers with a header to send to every URI.
+const HTTP_HEADER_QUALIFIER_PREFIX: &str = "http_header:";
+
+/// Prefix of qualifiers with a header to send to one URI, named
+/// `http_header_url:<index of the uri>:<header name>`.
+const HTTP_HEADER_URL_QUALIFIER_PREFIX: &str = "http_header_url:";
+
+/// The qualifiers of a request that this server supports.
+#[derive(Default)]
+struct Qualifiers {
+    /// Hash functions and hashes the content must match.
+    checksums: Vec<(DigestHasherFunc, Vec<u8>)>,
+    /// Headers sent with the download of every URI.
+    headers: Vec<(String, String)>,
+    /// Headers sent with the download of the URI at the index.
+    uri_headers: HashMap<usize, Vec<(String, String)>>,
+    /// Qualifiers that identify the content, sorted by name. Headers are
+    /// left out, so credentials don't change which content a URI names.
+    identifying: Vec<Qualifier>,
+}
+
+impl Qualifiers {
+    fn parse(qualifiers: &[Qualifier]) -> Result<Self, Error> {
+        let mut names = HashSet::with_capacity(qualifiers.len());
+        let mut parsed = Self::default();
+        for qualifier in qualifiers {
+            let name = qualifier.name.as_str();
+            if !names.insert(name) {
+                return Err(make_input_err!("Qualifier {name} is set more than once"));
+            }
+            if name == CHECKSUM_SRI_QUALIFIER {
+                parsed.checksums = parse_sri(&qualifier.value)?;
+                parsed.identifying.push(qualifier.clone());
+            } else if name == CANONICAL_ID_QUALIFIER {
+                parsed.identifying.push(qualifier.clone());
+            } else if let Some(header) = name.strip_prefix(HTTP_HEADER_QUALIFIER_PREFIX) {
+                parsed
+                    .headers
+                    .push((header.to_string(), qualifier.value.clone()));
+            } else if let Some(rest) = name.strip_prefix(HTTP_HEADER_URL_QUALIFIER_PREFIX) {
+                let (index, header) = rest
+                    .split_once(':')
+                    .and_then(|(index, header)| Some((index.parse::<usize>().ok()?, header)))
+                    .ok_or_else(|| make_input_err!("Invalid qualifier {name}"))?;
+                parsed
+                    .uri_headers
+                    .entry(index)
+                    .or_default()
+                    .push((header.to_string(), qualifier.value.clone()));
+            } else {
+                return Err(make_input_err!("Qualifier {name} is not supported"));
+            }
+        }
+        parsed.identifying.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(parsed)
+    }
+
+    /// The headers to send with the download of the URI at `index`.
+    fn headers_for_uri(&self, index: usize) -> Vec<(String, String)> {
+        let mut headers = self.headers.clone();
+        if let Some(uri_headers) = self.uri_headers.get(&index) {
+            headers.extend(uri_headers.iter().cloned());
+        }
+        headers
+    }
+}
+
+/// Parses a Subresource Integrity value, ie: `sha256-<base64 hash>`.
+/// Multiple hashes may be given separated by spaces.
+fn parse_sri(value: &str) -> Result<Vec<(DigestHasherFunc, Vec<u8>)>, Error> {
+    let checksums = value
+        .split_whitespace()
+        .map(|checksum| {
+            let (algorithm, hash) = checksum
+                .split_once('-')
+                .ok_or_else(|| make_input_err!("Invalid checksum {checksum}"))?;
+            let hasher_func = match algorithm {
+                "sha256" => DigestHasherFunc::Sha256,
+                "sha384" => DigestHasherFunc::Sha384,
+                "sha512" => DigestHasherFunc::Sha512,
+                _ => {
+                    return Err(make_input_err!(
+                        "Checksum algorithm {algorithm} is not supported"
+                    ))
+                }
+            };
+            let hash = STANDARD
+                .decode(hash)
+                .map_err(|e| make_input_err!("Invalid checksum {checksum}: {e:?}"))?;
+            Ok((hasher_func, hash))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    if checksums.is_empty() {
+        return Err(make_input_err!(
+            "Qualifier {CHECKSUM_SRI_QUALIFIER} is empty"
+        ));
+    }
+    Ok(checksums)
+}
+
+/// Whether the URI identifies a blob or a directory tree.
+#[derive(Clone, Copy)]
+enum AssetKind {
+    Blob,
+    Directory,
+}
+
+/// The key in the `asset_store` that `uri` with `qualifiers` is cached in.
+fn asset_key(
+    kind: AssetKind,
+    uri: &str,
+    qualifiers: &Qualifiers,
+    hasher_func: DigestHasherFunc,
+) -> StoreKey<'static> {
+    let mut hasher = DigestHasherFunc::Sha256.hasher();
+    hasher.update(hasher_func.to_string().as_bytes());
+    hasher.update(b"\0");
+    hasher.update(uri.as_bytes());
+    for qualifier in &qualifiers.identifying {
+        hasher.update(b"\0");
+        hasher.update(qualifier.name.as_bytes());
+        hasher.update(b"=");
+        hasher.update(qualifier.value.as_bytes());
+    }
+    let prefix = match kind {
+        AssetKind::Blob => "remote-asset-blob",
+        AssetKind::Directory => "remote-asset-directory",
+    };
+    StoreKey::Str(Cow::Owned(format!(
+        "{prefix}-{}",
+        hasher.finalize_digest().packed_hash()
+    )))
+}
+
+fn active_hasher_func() -> Result<DigestHasherFunc, Error> {
+    Ok(ActiveOriginContext::get_value(&ACTIVE_HASHER_FUNC)
+        .err_tip(|| "Could not get digest function in RemoteAssetServer")?
+        .map_or_else(default_digest_hasher_func, |v| *v))
+}
+
+fn is_expired(expires_at: Option<&prost_types::Timestamp>) -> bool {
+    expires_at
+        .and_then(|expires_at| SystemTime::try_from(expires_at.clone()).ok())
+        .is_some_and(|expires_at| expires_at <= SystemTime::now())
+}
+
+struct InstanceInfo {
+    cas_store: Store,
+    asset_store: Store,
+    cache_ttl: Duration,
+    fetch_timeout: Duration,
+    max_fetch_size: u64,
+    allow_push: bool,
+    access: InstanceAccess,
+}
+
+impl InstanceInfo {
+    /// The expiry of content cached now, unless the client set one.
+    fn expires_at(&self, expire_at: Option<prost_types::Timestamp>) -> prost_types::Timestamp {
+        expire_at.unwrap_or_else(|| (SystemTime::now() + self.cache_ttl).into())
+    }
+
+    async fn store_entry(&self, key: StoreKey<'static>, entry: &impl Message) -> Result<(), Error> {
+        let mut data = BytesMut::with_capacity(entry.encoded_len());
+        entry
+            .encode(&mut data)
+            .err_tip(|| "Could not encode remote asset entry")?;
+        self.asset_store
+            .update_oneshot(key, data.freeze())
+            .await
+            .err_tip(|| "Failed to update asset store")
+    }
+
+    /// Returns the entry cached in `key`, if it exists, is not expired and
+    /// the CAS still has its content.
+    async fn load_entry<T: AssetEntry>(&self, key: StoreKey<'static>) -> Result<Option<T>, Error> {
+        let data = match self.asset_store.get_part_unchunked(key, 0, None).await {
+            Ok(data) => data,
+            Err(err) if err.code == Code::NotFound => return Ok(None),
+            Err(err) => return Err(err).err_tip(|| "Failed to read asset store"),
+        };
+        let entry = T::decode(data).err_tip(|| "Could not decode remote asset entry")?;
+        if is_expired(entry.expires_at()) {
+            return Ok(None);
+        }
+        let Some(digest) = entry.digest() else {
+            return Ok(None);
+        };
+        let digest = DigestInfo::try_from(digest.clone())?;
+        Ok(self.cas_store.has(digest).await?.map(|_| entry))
+    }
+}
+
+/// The responses that are cached in the `asset_store`.
+trait AssetEntry: Message + Default {
+    fn expires_at(&self) -> Option<&prost_types::Timestamp>;
+    fn digest(&self) -> Option<&Digest>;
+}
+
+impl AssetEntry for FetchBlobResponse {
+    fn expires_at(&self) -> Option<&prost_types::Timestamp> {
+        self.expires_at.as_ref()
+    }
+
+    fn digest(&self) -> Option<&Digest> {
+        self.blob_digest.as_ref()
+    }
+}
+
+impl AssetEntry for FetchDirectoryResponse {
+    fn expires_at(&self) -> Option<&prost_types::Timestamp> {
+        self.expires_at.as_ref()
+    }
+
+    fn digest(&self) -> Option<&Digest> {
+        self.root_directory_digest.as_ref()
+    }
+}
+
+pub struct RemoteAssetServer {
+    instance_infos: HashMap<InstanceName, InstanceInfo>,
+    fetcher: Arc<dyn AssetFetcher>,
+}
+
+impl Debug for RemoteAssetServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RemoteAssetServer").finish()
+    }
+}
+
+impl RemoteAssetServer {
+    pub fn new(
+        config: &HashMap<InstanceName, RemoteAssetConfig>,
+        store_manager: &StoreManager,
+    ) -> Result<Self, Error> {
+        Self::new_with_fetcher(config, store_manager, HttpAssetFetcher::new())
+    }
+
+    pub fn new_with_fetcher(
+        config: &HashMap<InstanceName, RemoteAssetConfig>,
+        store_manager: &StoreManager,
+        fetcher: Arc<dyn AssetFetcher>,
+    ) -> Result<Self, Error> {
+        let mut instance_infos = HashMap::with_capacity(config.len());
+        for (instance_name, asset_cfg) in config {
+            let cas_store = store_manager
+                .get_store(&asset_cfg.cas_store)
+                .ok_or_else(|| {
+                    make_input_err!("'cas_store': '{}' does not exist", asset_cfg.cas_store)
+                })?;
+            let asset_store = store_manager
+                .get_store(&asset_cfg.asset_store)
+                .ok_or_else(|| {
+                    make_input_err!("'asset_store': '{}' does not exist", asset_cfg.asset_store)
+                })?;
+            instance_infos.insert(
+                instance_name.to_string(),
+                InstanceInfo {
+                    cas_store,
+                    asset_store,
+                    cache_ttl: if asset_cfg.cache_ttl_s == 0 {
+                        DEFAULT_CACHE_TTL
+                    } else {
+                        Duration::from_secs(asset_cfg.cache_ttl_s)
+                    },
+                    fetch_timeout: if asset_cfg.fetch_timeout_s == 0 {
+                        DEFAULT_FETCH_TIMEOUT
+                    } else {
+                        Duration::from_secs(asset_cfg.fetch_timeout_s)
+                    },
+                    max_fetch_size: if asset_cfg.max_fetch_size == 0 {
+                        DEFAULT_MAX_FETCH_SIZE
+                    } else {
+                        asset_cfg.max_fetch_size
+                    },
+                    allow_push: asset_cfg.allow_push,
+                    access: InstanceAccess::new(&asset_cfg.access),
+                },
+            );
+        }
+        Ok(Self {
+            instance_infos,
+            fetcher,
+        })
+    }
+
+    pub fn into_services(self) -> (FetchServer<Self>, PushServer<Self>) {
+        let server = Arc::new(self);
+        (
+            FetchServer::from_arc(server.clone()),
+            PushServer::from_arc(server),
+        )
+    }
+
+    fn instance_info(&self, instance_name: &str) -> Result<&InstanceInfo, Error> {
+        self.instance_infos
+            .get(instance_name)
+            .err_tip(|| format!("'instance_name' not configured for '{instance_name}'"))
+    }
+
+    /// Downloads `uri` into the CAS and returns its digest.
+    async fn fetch_uri(
+        &self,
+        instance_info: &InstanceInfo,
+        uri: &str,
+        headers: &[(String, String)],
+        qualifiers: &Qualifiers,
+        timeout: Duration,
+    ) -> Result<DigestInfo, Error> {
+        let data: Bytes = tokio::time::timeout(
+            timeout,
+            self.fetcher
+                .fetch(uri, headers, instance_info.max_fetch_size),
+        )
+        .await
+        .map_err(|_| make_err!(Code::DeadlineExceeded, "Fetching {uri} timed out"))??;
+        for (hasher_func, expected_hash) in &qualifiers.checksums {
+            let mut hasher = hasher_func.hasher();
+            hasher.update(&data);
+            if hasher.finalize_digest().packed_hash()[..] != expected_hash[..] {
+                return Err(make_err!(
+                    Code::Aborted,
+                    "Content of {uri} does not match its {CHECKSUM_SRI_QUALIFIER} qualifier"
+                ));
+            }
+        }
+        let mut hasher = active_hasher_func()?.hasher();
+        hasher.update(&data);
+        let digest = hasher.finalize_digest();
+        instance_info
+            .cas_store
+            .update_oneshot(digest, data)
+            .await
+            .err_tip(|| format!("Failed to upload {uri} to the CAS"))?;
+        Ok(digest)
+    }
+
+    async fn inner_fetch_blob(
+        &self,
+        request: FetchBlobRequest,
+    ) -> Result<FetchBlobResponse, Error> {
+        let instance_info = self.instance_info(&request.instance_name)?;
+        instance_info.access.check_read(&request.instance_name)?;
+        if request.uris.is_empty() {
+            return Err(make_input_err!("At least one URI must be given"));
+        }
+        let qualifiers = Qualifiers::parse(&request.qualifiers)?;
+        let hasher_func = active_hasher_func()?;
+        let digest_function = hasher_func.proto_digest_func() as i32;
+
+        // We don't know when cached content was fetched, so it is only
+        // returned if the client accepts content of any age.
+        if request.oldest_content_accepted.is_none() {
+            for uri in &request.uris {
+                let key = asset_key(AssetKind::Blob, uri, &qualifiers, hasher_func);
+                if let Some(entry) = instance_info.load_entry::<FetchBlobResponse>(key).await? {
+                    return Ok(entry);
+                }
+            }
+        }
+
+        // Fetched content is written to the CAS and the asset store.
+        instance_info.access.check_write(&request.instance_name)?;
+        let timeout = request
+            .timeout
+            .and_then(|timeout| Duration::try_from(timeout).ok())
+            .filter(|timeout| !timeout.is_zero())
+            .unwrap_or(instance_info.fetch_timeout);
+        let mut last_err = None;
+        for (index, uri) in request.uris.iter().enumerate() {
+            let headers = qualifiers.headers_for_uri(index);
+            match self
+                .fetch_uri(instance_info, uri, &headers, &qualifiers, timeout)
+                .await
+            {
+                Ok(digest) => {
+                    let response = FetchBlobResponse {
+                        status: None,
+                        uri: uri.clone(),
+                        qualifiers: qualifiers.identifying.clone(),
+                        expires_at: Some(instance_info.expires_at(None)),
+                        blob_digest: Some(digest.into()),
+                        digest_function,
+                    };
+                    let key = asset_key(AssetKind::Blob, uri, &qualifiers, hasher_func);
+                    instance_info.store_entry(key, &response).await?;
+                    return Ok(response);
+                }
+                Err(err) if err.code == Code::InvalidArgument => return Err(err),
+                Err(err) => {
+                    event!(Level::INFO, ?uri, ?err, "Failed to fetch remote asset");
+                    last_err = Some((uri.clone(), err));
+                }
+            }
+        }
+        let (uri, err) = last_err.err_tip(|| "No URI was fetched in inner_fetch_blob")?;
+        Ok(FetchBlobResponse {
+            status: Some(err.into()),
+            uri,
+            digest_function,
+            ..Default::default()
+        })
+    }
+
+    async fn inner_fetch_directory(
+        &self,
+        request: FetchDirectoryRequest,
+    ) -> Result<FetchDirectoryResponse, Error> {
+        let instance_info = self.instance_info(&request.instance_name)?;
+        instance_info.access.check_read(&request.instance_name)?;
+        if request.uris.is_empty() {
+            return Err(make_input_err!("At least one URI must be given"));
+        }
+        let qualifiers = Qualifiers::parse(&request.qualifiers)?;
+        let hasher_func = active_hasher_func()?;
+        if request.oldest_content_accepted.is_none() {
+            for uri in &request.uris {
+                let key = asset_key(AssetKind::Directory, uri, &qualifiers, hasher_func);
+                if let Some(entry) = instance_info
+                    .load_entry::<FetchDirectoryResponse>(key)
+                    .await?
+                {
+                    return Ok(entry);
+                }
+            }
+        }
+        // Directories are not fetched from origin, only pushed ones are
+        // returned.
+        Ok(FetchDirectoryResponse {
+            status: Some(
+                make_err!(
+                    Code::NotFound,
+                    "No directory was pushed for {:?}",
+                    request.uris
+                )
+                .into(),
+            ),
+            uri: request.uris[0].clone(),
+            digest_function: hasher_func.proto_digest_func() as i32,
+            ..Default::default()
+        })
+    }
+
+    /// Checks that content may be pushed for `uris` and that the CAS has
+    /// `digest`.
+    async fn check_push(
+        &self,
+        instance_info: &InstanceInfo,
+        instance_name: &str,
+        uris: &[String],
+        digest: Option<&Digest>,
+    ) -> Result<DigestInfo, Error> {
+        if !instance_info.allow_push {
+            return Err(make_err!(
+                Code::PermissionDenied,
+                "Pushing remote assets is not allowed for '{instance_name}'"
+            ));
+        }
+        instance_info.access.check_write(instance_name)?;
+        if uris.is_empty() {
+            return Err(make_input_err!("At least one URI must be given"));
+        }
+        let digest =
+            DigestInfo::try_from(digest.err_tip(|| "Digest was not set in message")?.clone())?;
+        if instance_info.cas_store.has(digest).await?.is_none() {
+            return Err(make_err!(
+                Code::FailedPrecondition,
+                "{digest} must be in the CAS before it is pushed"
+            ));
+        }
+        Ok(digest)
+    }
+
+    async fn inner_push_blob(&self, request: PushBlobRequest) -> Result<PushBlobResponse, Error> {
+        let instance_info = self.instance_info(&request.instance_name)?;
+        let digest = self
+            .check_push(
+                instance_info,
+                &request.instance_name,
+                &request.uris,
+                request.blob_digest.as_ref(),
+            )
+            .await?;
+        let qualifiers = Qualifiers::parse(&request.qualifiers)?;
+        let hasher_func = active_hasher_func()?;
+        for uri in &request.uris {
+            let entry = FetchBlobResponse {
+                status: None,
+                uri: uri.clone(),
+                qualifiers: qualifiers.identifying.clone(),
+                expires_at: Some(instance_info.expires_at(request.expire_at)),
+                blob_digest: Some(digest.into()),
+                digest_function: hasher_func.proto_digest_func() as i32,
+            };
+            let key = asset_key(AssetKind::Blob, uri, &qualifiers, hasher_func);
+            instance_info.store_entry(key, &entry).await?;
+        }
+        Ok(PushBlobResponse {})
+    }
+
+    async fn inner_push_directory(
+        &self,
+        request: PushDirectoryRequest,
+    ) -> Result<PushDirectoryResponse, Error> {
+        let instance_info = self.instance_info(&request.instance_name)?;
+        let digest = self
+            .check_push(
+                instance_info,
+                &request.instance_name,
+                &request.uris,
+                request.root_directory_digest.as_ref(),
+            )
+            .await?;
+        let qualifiers = Qualifiers::parse(&request.qualifiers)?;
+        let hasher_func = active_hasher_func()?;
+        for uri in &request.uris {
+            let entry = FetchDirectoryResponse {
+                status: None,
+                uri: uri.clone(),
+                qualifiers: qualifiers.identifying.clone(),
+                expires_at: Some(instance_info.expires_at(request.expire_at)),
+                root_directory_digest: Some(digest.into()),
+                digest_function: hasher_func.proto_digest_func() as i32,
+            };
+            let key = asset_key(AssetKind::Directory, uri, &qualifiers, hasher_func);
+            instance_info.store_entry(key, &entry).await?;
+        }
+        Ok(PushDirectoryResponse {})
+    }
+}
+
+#[tonic::async_trait]
+impl Fetch for RemoteAssetServer {
+    #[allow(clippy::blocks_in_conditions)]
+    #[instrument(
+        err,
+        ret(level = Level::INFO),
+        level = Level::ERROR,
+        skip_all,
+        fields(request = ?grpc_request.get_ref())
+    )]
+    async fn fetch_blob(
+        &self,
+        grpc_request: Request<FetchBlobRequest>,
+    ) -> Result<Response<FetchBlobResponse>, Status> {
+        let request = grpc_request.into_inner();
+        make_ctx_for_instance_hash_func(&request.instance_name, request.digest_function)
+            .err_tip(|| "In RemoteAssetServer::fetch_blob")?
+            .wrap_async(
+                error_span!("remote_asset_server_fetch_blob"),
+                self.inner_fetch_blob(request),
+            )
+            .await
+            .map(Response::new)
+            .map_err(Into::into)
+    }
+
+    #[allow(clippy::blocks_in_conditions)]
+    #[instrument(
+        err,
+        ret(level = Level::INFO),
+        level = Level::ERROR,
+        skip_all,
+        fields(request = ?grpc_request.get_ref())
+    )]
+    async fn fetch_directory(
+        &self,
+        grpc_request: Request<FetchDirectoryRequest>,
+    ) -> Result<Response<FetchDirectoryResponse>, Status> {
+        let request = grpc_request.into_inner();
+        make_ctx_for_instance_hash_func(&request.instance_name, request.digest_function)
+            .err_tip(|| "In RemoteAssetServer::fetch_directory")?
+            .wrap_async(
+                error_span!("remote_asset_server_fetch_directory"),
+                self.inner_fetch_directory(request),
+            )
+            .await
+            .map(Response::new)
+            .map_err(Into::into)
+    }
+}
+
+#[tonic::async_trait]
+impl Push for RemoteAssetServer {
+    #[allow(clippy::blocks_in_conditions)]
+    #[instrument(
+        err,
+        ret(level = Level::INFO),
+        level = Level::ERROR,
+        skip_all,
+        fields(request = ?grpc_request.get_ref())
+    )]
+    async fn push_blob(
+        &self,
+        grpc_request: Request<PushBlobRequest>,
+    ) -> Result<Response<PushBlobResponse>, Status> {
+        let request = grpc_request.into_inner();
+        make_ctx_for_instance_hash_func(&request.instance_name, request.digest_function)
+            .err_tip(|| "In RemoteAssetServer::push_blob")?
+            .wrap_async(
+                error_span!("remote_asset_server_push_blob"),
+                self.inner_push_blob(request),
+            )
+            .await
+            .map(Response::new)
+            .map_err(Into::into)
+    }
+
+    #[allow(clippy::blocks_in_conditions)]
+    #[instrument(
+        err,
+        ret(level = Level::INFO),
+        level = Level::ERROR,
+        skip_all,
+        fields(request = ?grpc_request.get_ref())
+    )]
+    async fn push_directory(
+        &self,
+        grpc_request: Request<PushDirectoryRequest>,
+    ) -> Result<Response<PushDirectoryResponse>, Status> {
+        let request = grpc_request.into_inner();
+        make_ctx_for_instance_hash_func(&request.instance_name, request.digest_function)
+            .err_tip(|| "In RemoteAssetServer::push_directory")?
+            .wrap_async(
+                error_span!("remote_asset_server_push_directory"),
+                self.inner_push_directory(request),
+            )
+            .await
+            .map(Response::new)
+            .map_err(Into::into)
+    }
+}
@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::Into;
 use std::pin::Pin;
 use std::sync::Arc;
@@ -32,6 +32,7 @@ use nativelink_scheduler::worker::Worker;
 use nativelink_scheduler::worker_scheduler::WorkerScheduler;
 use nativelink_util::background_spawn;
 use nativelink_util::action_messages::{OperationId, WorkerId};
+use nativelink_util::common::DigestInfo;
 use nativelink_util::operation_state_manager::UpdateOperationType;
 use nativelink_util::platform_properties::PlatformProperties;
 use tokio::sync::mpsc;
@@ -177,10 +178,20 @@ impl WorkerApiServer {
         keep_alive_request: KeepAliveRequest,
     ) -> Result<Response<()>, Error> {
         let worker_id: WorkerId = keep_alive_request.worker_id.try_into()?;
+        let cached_input_roots = keep_alive_request
+            .cached_input_roots
+            .into_iter()
+            .map(DigestInfo::try_from)
+            .collect::<Result<HashSet<_>, _>>()
+            .err_tip(|| "Invalid cached_input_roots in inner_keep_alive()")?;
         self.scheduler
             .worker_keep_alive_received(&worker_id, (self.now_fn)()?.as_secs())
             .await
             .err_tip(|| "Could not process keep_alive from worker in inner_keep_alive()")?;
+        self.scheduler
+            .set_worker_cached_input_roots(&worker_id, cached_input_roots)
+            .await
+            .err_tip(|| "Could not set cached input roots of worker in inner_keep_alive()")?;
         Ok(Response::new(()))
     }
 
@@ -189,6 +200,13 @@ impl WorkerApiServer {
         going_away_request: GoingAwayRequest,
     ) -> Result<Response<()>, Error> {
         let worker_id: WorkerId = going_away_request.worker_id.try_into()?;
+        if going_away_request.drain {
+            self.scheduler
+                .set_drain_worker(&worker_id, true)
+                .await
+                .err_tip(|| "While calling WorkerApiServer::inner_going_away")?;
+            return Ok(Response::new(()));
+        }
         self.scheduler
             .remove_worker(&worker_id)
             .await
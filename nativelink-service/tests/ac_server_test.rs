@@ -17,6 +17,7 @@ use std::sync::Arc;
 
 use bytes::BytesMut;
 use maplit::hashmap;
+use nativelink_config::cas_server::{AcStoreConfig, InstanceAccessConfig};
 use nativelink_config::stores::{MemorySpec, StoreSpec};
 use nativelink_error::Error;
 use nativelink_macro::nativelink_test;
@@ -28,10 +29,12 @@ use nativelink_service::ac_server::AcServer;
 use nativelink_store::default_store_factory::store_factory;
 use nativelink_store::store_manager::StoreManager;
 use nativelink_util::common::DigestInfo;
+use nativelink_util::origin_context::{OriginContext, AUTHENTICATED_IDENTITY};
 use nativelink_util::store_trait::StoreLike;
 use pretty_assertions::assert_eq;
 use prost::Message;
 use tonic::{Code, Request, Response, Status};
+use tracing::trace_span;
 
 const INSTANCE_NAME: &str = "foo_instance_name";
 const HASH1: &str = "0123456789abcdef000000000000000000000000000000000123456789abcdef";
@@ -80,6 +83,7 @@ fn make_ac_server(store_manager: &StoreManager) -> Result<AcServer, Error> {
             "foo_instance_name".to_string() => nativelink_config::cas_server::AcStoreConfig{
                 ac_store: "main_ac".to_string(),
                 read_only: false,
+                access: Default::default(),
             }
         },
         store_manager,
@@ -219,3 +223,62 @@ async fn one_item_update_test() -> Result<(), Box<dyn std::error::Error>> {
     assert_eq!(decoded_action_result, action_result);
     Ok(())
 }
+
+#[nativelink_test]
+async fn update_requires_write_identity_test() -> Result<(), Box<dyn std::error::Error>> {
+    let store_manager = make_store_manager().await?;
+    let ac_server = AcServer::new(
+        &hashmap! {
+            INSTANCE_NAME.to_string() => AcStoreConfig {
+                ac_store: "main_ac".to_string(),
+                read_only: false,
+                access: InstanceAccessConfig {
+                    read_identities: None,
+                    write_identities: Some(vec!["ci".to_string()]),
+                },
+            }
+        },
+        &store_manager,
+    )?;
+    let ctx_for_identity = |identity: &str| {
+        let mut ctx = OriginContext::new();
+        ctx.set_value(&AUTHENTICATED_IDENTITY, Arc::new(identity.to_string()));
+        Arc::new(ctx)
+    };
+
+    let action_result = ActionResult {
+        exit_code: 45,
+        ..Default::default()
+    };
+    let size_bytes = get_encoded_proto_size(&action_result)? as i64;
+    let digest = Digest {
+        hash: HASH1.to_string(),
+        size_bytes,
+    };
+
+    let err = ctx_for_identity("dev")
+        .wrap_async(
+            trace_span!("update_as_dev"),
+            update_action_result(&ac_server, digest.clone(), action_result.clone()),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::PermissionDenied);
+
+    ctx_for_identity("ci")
+        .wrap_async(
+            trace_span!("update_as_ci"),
+            update_action_result(&ac_server, digest, action_result.clone()),
+        )
+        .await?;
+
+    // Anyone may read.
+    let response = ctx_for_identity("dev")
+        .wrap_async(
+            trace_span!("read_as_dev"),
+            get_action_result(&ac_server, HASH1, size_bytes),
+        )
+        .await?;
+    assert_eq!(response.into_inner(), action_result);
+    Ok(())
+}
@@ -0,0 +1,278 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use axum::body::Body;
+use axum::Router;
+use bytes::Bytes;
+use http_body_util::BodyExt;
+use hyper::{Method, Request, StatusCode};
+use maplit::hashmap;
+use nativelink_config::schedulers::SimpleSpec;
+use nativelink_config::stores::{EvictionPolicy, MemorySpec, NoopSpec, StoreSpec};
+use nativelink_error::{make_err, Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_scheduler::default_scheduler_factory::memory_awaited_action_db_factory;
+use nativelink_scheduler::platform_property_manager::PlatformProperties;
+use nativelink_scheduler::simple_scheduler::SimpleScheduler;
+use nativelink_scheduler::worker::Worker;
+use nativelink_scheduler::worker_scheduler::WorkerScheduler;
+use nativelink_service::admin_server::AdminServer;
+use nativelink_store::default_store_factory::store_factory;
+use nativelink_store::store_manager::StoreManager;
+use nativelink_util::action_messages::{
+    ActionInfo, ActionUniqueKey, ActionUniqueQualifier, OperationId, WorkerId,
+};
+use nativelink_util::common::DigestInfo;
+use nativelink_util::digest_hasher::DigestHasherFunc;
+use nativelink_util::operation_state_manager::ClientStateManager;
+use nativelink_util::store_trait::StoreLike;
+use pretty_assertions::assert_eq;
+use tokio::sync::{mpsc, Notify};
+use tower::Service;
+use uuid::Uuid;
+
+const MEMORY_STORE_NAME: &str = "memory_store";
+const NOOP_STORE_NAME: &str = "noop_store";
+const SCHEDULER_NAME: &str = "main_scheduler";
+const HASH1: &str = "0123456789abcdef000000000000000000010000000000000123456789abcdef";
+const HASH2: &str = "0123456789abcdef000000000000000000020000000000000123456789abcdef";
+const VALUE: &str = "123";
+
+async fn make_store_manager() -> Result<Arc<StoreManager>, Error> {
+    let store_manager = Arc::new(StoreManager::new());
+    store_manager.add_store(
+        MEMORY_STORE_NAME,
+        store_factory(
+            &StoreSpec::memory(MemorySpec {
+                eviction_policy: Some(EvictionPolicy {
+                    max_seconds: 1000,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            &store_manager,
+            None,
+        )
+        .await?,
+    );
+    store_manager.add_store(
+        NOOP_STORE_NAME,
+        store_factory(&StoreSpec::noop(NoopSpec::default()), &store_manager, None).await?,
+    );
+    Ok(store_manager)
+}
+
+fn make_router(store_manager: &Arc<StoreManager>) -> (Arc<SimpleScheduler>, Router) {
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, worker_scheduler) = SimpleScheduler::new(
+        &SimpleSpec::default(),
+        memory_awaited_action_db_factory(0, &task_change_notify, SystemTime::now),
+        task_change_notify,
+        None,
+    );
+    let action_schedulers: HashMap<String, Arc<dyn ClientStateManager>> = hashmap! {
+        SCHEDULER_NAME.to_string() => scheduler.clone() as Arc<dyn ClientStateManager>,
+    };
+    let worker_schedulers: HashMap<String, Arc<dyn WorkerScheduler>> = hashmap! {
+        SCHEDULER_NAME.to_string() => worker_scheduler as Arc<dyn WorkerScheduler>,
+    };
+    let router = AdminServer::new(
+        store_manager.clone(),
+        &action_schedulers,
+        &worker_schedulers,
+    )
+    .into_router();
+    (scheduler, router)
+}
+
+async fn send(
+    router: &mut Router,
+    method: Method,
+    uri: &str,
+) -> Result<(StatusCode, Bytes), Error> {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .body(Body::empty())
+        .map_err(|e| make_err!(Code::Internal, "{e:?}"))?;
+    let response = router
+        .call(request)
+        .await
+        .map_err(|e| make_err!(Code::Internal, "{e:?}"))?;
+    let status = response.status();
+    let body = response
+        .into_body()
+        .collect()
+        .await
+        .map_err(|e| make_err!(Code::Internal, "{e:?}"))?
+        .to_bytes();
+    Ok((status, body))
+}
+
+#[nativelink_test]
+async fn evict_store_test() -> Result<(), Error> {
+    let store_manager = make_store_manager().await?;
+    let (_scheduler, mut router) = make_router(&store_manager);
+    let store = store_manager.get_store(MEMORY_STORE_NAME).unwrap();
+    let digest1 = DigestInfo::try_new(HASH1, VALUE.len())?;
+    let digest2 = DigestInfo::try_new(HASH2, VALUE.len())?;
+    store.update_oneshot(digest1, VALUE.into()).await?;
+    store.update_oneshot(digest2, VALUE.into()).await?;
+
+    // Nothing is expired, so only the requested bytes are evicted, oldest
+    // first.
+    let (status, body) = send(
+        &mut router,
+        Method::POST,
+        &format!("/stores/{MEMORY_STORE_NAME}/evict/1"),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK, "{body:?}");
+    assert_eq!(store.has(digest1).await?, None);
+    assert_eq!(store.has(digest2).await?, Some(VALUE.len() as u64));
+
+    let (status, _) = send(
+        &mut router,
+        Method::POST,
+        &format!("/stores/{NOOP_STORE_NAME}/evict/1"),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::NOT_IMPLEMENTED);
+
+    let (status, _) = send(&mut router, Method::POST, "/stores/unknown_store/evict/1").await?;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = send(
+        &mut router,
+        Method::POST,
+        &format!("/stores/{MEMORY_STORE_NAME}/evict/many"),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn flush_write_back_requires_fast_slow_store_test() -> Result<(), Error> {
+    let store_manager = make_store_manager().await?;
+    let (_scheduler, mut router) = make_router(&store_manager);
+
+    let (status, body) = send(
+        &mut router,
+        Method::POST,
+        &format!("/stores/{MEMORY_STORE_NAME}/flush_write_back"),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{body:?}");
+    Ok(())
+}
+
+#[nativelink_test]
+async fn dump_queue_test() -> Result<(), Error> {
+    let store_manager = make_store_manager().await?;
+    let (scheduler, mut router) = make_router(&store_manager);
+    let action_digest = DigestInfo::new([1u8; 32], 512);
+    let _action_listener = scheduler
+        .add_action(
+            OperationId::default(),
+            Arc::new(ActionInfo {
+                command_digest: DigestInfo::new([0u8; 32], 0),
+                input_root_digest: DigestInfo::new([0u8; 32], 0),
+                timeout: Duration::MAX,
+                platform_properties: HashMap::new(),
+                priority: 5,
+                load_timestamp: UNIX_EPOCH,
+                insert_timestamp: SystemTime::now(),
+                unique_qualifier: ActionUniqueQualifier::Cachable(ActionUniqueKey {
+                    instance_name: "admin_instance".to_string(),
+                    digest_function: DigestHasherFunc::Sha256,
+                    digest: action_digest,
+                }),
+            }),
+        )
+        .await?;
+
+    let (status, body) = send(
+        &mut router,
+        Method::GET,
+        &format!("/scheduler/{SCHEDULER_NAME}/queue"),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK, "{body:?}");
+    let queue: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let operations = queue["operations"].as_array().unwrap();
+    assert_eq!(operations.len(), 1, "{queue}");
+    assert_eq!(operations[0]["stage"], "Queued");
+    assert_eq!(operations[0]["instance_name"], "admin_instance");
+    assert_eq!(operations[0]["priority"], 5);
+    assert_eq!(operations[0]["action_digest"], action_digest.to_string());
+    assert!(operations[0]["queued_for_s"].is_u64(), "{queue}");
+    // The action is not assigned to a worker yet.
+    assert!(operations[0]["worker_id"].is_null(), "{queue}");
+
+    let (status, _) = send(&mut router, Method::GET, "/scheduler/unknown/queue").await?;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn worker_status_reports_drained_worker_test() -> Result<(), Error> {
+    let store_manager = make_store_manager().await?;
+    let (scheduler, mut router) = make_router(&store_manager);
+    let worker_id = WorkerId(Uuid::new_v4());
+    // Note: This needs to stay in scope or a disconnect will trigger.
+    let (tx, _rx_from_worker) = mpsc::unbounded_channel();
+    scheduler
+        .add_worker(Worker::new(worker_id, PlatformProperties::default(), tx, 0))
+        .await?;
+
+    let status_uri = format!("/scheduler/{SCHEDULER_NAME}/worker_status/{worker_id}");
+    let (status, body) = send(&mut router, Method::GET, &status_uri).await?;
+    assert_eq!(status, StatusCode::OK, "{body:?}");
+    let worker_status: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(worker_status["is_draining"], false);
+    assert_eq!(worker_status["is_drained"], false);
+
+    let (status, body) = send(
+        &mut router,
+        Method::POST,
+        &format!("/scheduler/{SCHEDULER_NAME}/set_drain_worker/{worker_id}/true"),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK, "{body:?}");
+
+    // The worker is not running anything, so it can be shut down right away.
+    let (status, body) = send(&mut router, Method::GET, &status_uri).await?;
+    assert_eq!(status, StatusCode::OK, "{body:?}");
+    let worker_status: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(worker_status["is_draining"], true);
+    assert_eq!(worker_status["running_actions"], 0);
+    assert_eq!(worker_status["is_drained"], true);
+
+    let (status, _) = send(
+        &mut router,
+        Method::GET,
+        &format!(
+            "/scheduler/{SCHEDULER_NAME}/worker_status/{}",
+            WorkerId(Uuid::new_v4())
+        ),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    Ok(())
+}
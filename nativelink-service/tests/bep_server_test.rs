@@ -15,12 +15,14 @@
 use std::borrow::Cow;
 use std::sync::Arc;
 
+use bytes::Bytes;
 use futures::StreamExt;
 use hyper::body::Frame;
-use nativelink_config::cas_server::BepConfig;
+use nativelink_config::cas_server::{BepConfig, InstanceAccessConfig};
 use nativelink_config::stores::{MemorySpec, StoreSpec};
 use nativelink_error::{Error, ResultExt};
 use nativelink_macro::nativelink_test;
+use nativelink_proto::build_event_stream::{self, build_event, file, File, NamedSetOfFiles};
 use nativelink_proto::com::github::trace_machina::nativelink::events::{bep_event, BepEvent};
 use nativelink_proto::google::devtools::build::v1::build_event::console_output::Output;
 use nativelink_proto::google::devtools::build::v1::build_event::{
@@ -39,28 +41,31 @@ use nativelink_store::default_store_factory::store_factory;
 use nativelink_store::store_manager::StoreManager;
 use nativelink_util::buf_channel::make_buf_channel_pair;
 use nativelink_util::channel_body_for_tests::ChannelBody;
-use nativelink_util::common::encode_stream_proto;
+use nativelink_util::common::{encode_stream_proto, DigestInfo};
 use nativelink_util::store_trait::{Store, StoreKey, StoreLike};
 use pretty_assertions::assert_eq;
 use prost::Message;
-use prost_types::Timestamp;
+use prost_types::{Any, Timestamp};
 use tonic::codec::{Codec, ProstCodec};
 use tonic::{Request, Streaming};
 
 const BEP_STORE_NAME: &str = "main_bep";
+const CAS_STORE_NAME: &str = "main_cas";
 
 /// Utility function to construct a [`StoreManager`]
 async fn make_store_manager() -> Result<Arc<StoreManager>, Error> {
     let store_manager = Arc::new(StoreManager::new());
-    store_manager.add_store(
-        BEP_STORE_NAME,
-        store_factory(
-            &StoreSpec::memory(MemorySpec::default()),
-            &store_manager,
-            None,
-        )
-        .await?,
-    );
+    for store_name in [BEP_STORE_NAME, CAS_STORE_NAME] {
+        store_manager.add_store(
+            store_name,
+            store_factory(
+                &StoreSpec::memory(MemorySpec::default()),
+                &store_manager,
+                None,
+            )
+            .await?,
+        );
+    }
     Ok(store_manager)
 }
 
@@ -69,6 +74,8 @@ fn make_bep_server(store_manager: &StoreManager) -> Result<BepServer, Error> {
     BepServer::new(
         &BepConfig {
             store: BEP_STORE_NAME.to_string(),
+            cas_store: Some(CAS_STORE_NAME.to_string()),
+            access: InstanceAccessConfig::default(),
         },
         store_manager,
     )
@@ -354,3 +361,98 @@ async fn publish_build_tool_event_stream_test() -> Result<(), Box<dyn std::error
         Ok(())
     }
 }
+
+/// Asserts that files referenced by Bazel build events are linked if they are in the CAS.
+#[nativelink_test]
+async fn publish_build_tool_event_links_cas_files() -> Result<(), Box<dyn std::error::Error>> {
+    const HASH1: &str = "0123456789abcdef000000000000000000000000000000000000000000000000";
+    const HASH2: &str = "0123456789abcdef111111111111111111111111111111111111111111111111";
+    const CONTENT: &str = "test-output";
+
+    let store_manager = make_store_manager().await?;
+    let bep_server = make_bep_server(&store_manager)?;
+    let bep_store = get_bep_store(&store_manager)?;
+
+    let uploaded_digest = DigestInfo::try_new(HASH1, CONTENT.len())?;
+    let missing_digest = DigestInfo::try_new(HASH2, CONTENT.len())?;
+    store_manager
+        .get_store(CAS_STORE_NAME)
+        .err_tip(|| "While retrieving cas_store")?
+        .update_oneshot(uploaded_digest, Bytes::from_static(CONTENT.as_bytes()))
+        .await?;
+
+    let make_file = |name: &str, digest: DigestInfo| File {
+        name: name.to_string(),
+        digest: digest.packed_hash().to_string(),
+        length: CONTENT.len() as i64,
+        file: Some(file::File::Uri(format!(
+            "bytestream://localhost:50051/main/blobs/{}/{}",
+            digest.packed_hash(),
+            digest.size_bytes()
+        ))),
+        ..Default::default()
+    };
+    let uploaded_file = make_file("uploaded.txt", uploaded_digest);
+    let bazel_event = build_event_stream::BuildEvent {
+        payload: Some(build_event::Payload::NamedSetOfFiles(NamedSetOfFiles {
+            files: vec![
+                uploaded_file.clone(),
+                make_file("missing.txt", missing_digest),
+            ],
+            file_sets: Vec::new(),
+        })),
+        ..Default::default()
+    };
+    let stream_id = StreamId {
+        build_id: "some-build-id".to_string(),
+        invocation_id: "some-invocation-id".to_string(),
+        component: BuildComponent::Tool as i32,
+    };
+    let request = PublishBuildToolEventStreamRequest {
+        ordered_build_event: Some(OrderedBuildEvent {
+            stream_id: Some(stream_id.clone()),
+            sequence_number: 1,
+            event: Some(BuildEvent {
+                event_time: Some(Timestamp::date(1999, 1, 4)?),
+                event: Some(Event::BazelEvent(Any {
+                    type_url: "type.googleapis.com/build_event_stream.BuildEvent".to_string(),
+                    value: bazel_event.encode_to_vec(),
+                })),
+            }),
+        }),
+        ..Default::default()
+    };
+
+    let (request_tx, body) = ChannelBody::new();
+    let mut codec = ProstCodec::<PublishBuildToolEventStreamRequest, _>::default();
+    let stream = Streaming::new_request(codec.decoder(), body, None, None);
+    let mut response_stream = bep_server
+        .publish_build_tool_event_stream(Request::new(stream))
+        .await?
+        .into_inner();
+    request_tx
+        .send(Frame::data(encode_stream_proto(&request)?))
+        .await?;
+    let response = response_stream
+        .next()
+        .await
+        .err_tip(|| "Response stream closed unexpectedly")??;
+    assert_eq!(response.sequence_number, 1);
+
+    let file_key = |digest: DigestInfo| {
+        StoreKey::Str(Cow::Owned(format!(
+            "BepFile:{}:{}:{digest}",
+            stream_id.build_id, stream_id.invocation_id
+        )))
+    };
+    assert_eq!(
+        File::decode(
+            bep_store
+                .get_part_unchunked(file_key(uploaded_digest), 0, None)
+                .await?
+        )?,
+        uploaded_file
+    );
+    assert_eq!(bep_store.has(file_key(missing_digest)).await?, None);
+    Ok(())
+}
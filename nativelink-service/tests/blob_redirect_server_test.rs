@@ -0,0 +1,126 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::Router;
+use bytes::Bytes;
+use http_body_util::BodyExt;
+use hyper::{Request, StatusCode};
+use maplit::hashmap;
+use nativelink_config::cas_server::BlobRedirectConfig;
+use nativelink_config::stores::{MemorySpec, StoreSpec};
+use nativelink_error::{make_err, Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_service::blob_redirect_server::BlobRedirectServer;
+use nativelink_store::default_store_factory::store_factory;
+use nativelink_store::store_manager::StoreManager;
+use nativelink_util::common::DigestInfo;
+use nativelink_util::store_trait::StoreLike;
+use pretty_assertions::assert_eq;
+use tower::Service;
+
+const INSTANCE_NAME: &str = "foo_instance_name";
+const STORE_NAME: &str = "main_cas";
+const HASH1: &str = "0123456789abcdef000000000000000000000000000000000123456789abcdef";
+const VALUE1: &str = "123";
+
+async fn make_store_manager() -> Result<Arc<StoreManager>, Error> {
+    let store_manager = Arc::new(StoreManager::new());
+    store_manager.add_store(
+        STORE_NAME,
+        store_factory(
+            &StoreSpec::memory(MemorySpec::default()),
+            &store_manager,
+            None,
+        )
+        .await?,
+    );
+    Ok(store_manager)
+}
+
+fn make_router(store_manager: &StoreManager) -> Result<Router, Error> {
+    Ok(BlobRedirectServer::new(
+        &BlobRedirectConfig {
+            path: String::new(),
+            cas_stores: hashmap! {
+                String::new() => STORE_NAME.to_string(),
+                INSTANCE_NAME.to_string() => STORE_NAME.to_string(),
+            },
+            access: hashmap! {},
+        },
+        store_manager,
+    )?
+    .into_router())
+}
+
+async fn get(router: &mut Router, uri: &str) -> Result<(StatusCode, Bytes), Error> {
+    let request = Request::builder()
+        .uri(uri)
+        .body(Body::empty())
+        .map_err(|e| make_err!(Code::Internal, "{e:?}"))?;
+    let response = router
+        .call(request)
+        .await
+        .map_err(|e| make_err!(Code::Internal, "{e:?}"))?;
+    let status = response.status();
+    let body = response
+        .into_body()
+        .collect()
+        .await
+        .map_err(|e| make_err!(Code::Internal, "{e:?}"))?
+        .to_bytes();
+    Ok((status, body))
+}
+
+#[nativelink_test]
+async fn small_blobs_are_streamed() -> Result<(), Error> {
+    let store_manager = make_store_manager().await?;
+    let mut router = make_router(&store_manager)?;
+    let digest = DigestInfo::try_new(HASH1, VALUE1.len())?;
+    store_manager
+        .get_store(STORE_NAME)
+        .unwrap()
+        .update_oneshot(digest, VALUE1.into())
+        .await?;
+
+    assert_eq!(
+        get(&mut router, &format!("/{HASH1}/{}", VALUE1.len())).await?,
+        (StatusCode::OK, Bytes::from_static(VALUE1.as_bytes()))
+    );
+    assert_eq!(
+        get(
+            &mut router,
+            &format!("/{INSTANCE_NAME}/{HASH1}/{}", VALUE1.len())
+        )
+        .await?,
+        (StatusCode::OK, Bytes::from_static(VALUE1.as_bytes()))
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn bad_requests_are_rejected() -> Result<(), Error> {
+    let store_manager = make_store_manager().await?;
+    let mut router = make_router(&store_manager)?;
+
+    let (status, _) = get(&mut router, &format!("/{HASH1}/{}", VALUE1.len())).await?;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = get(&mut router, &format!("/bad_instance/{HASH1}/3")).await?;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = get(&mut router, &format!("/{HASH1}/not_a_size")).await?;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    Ok(())
+}
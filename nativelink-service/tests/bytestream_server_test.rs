@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use bytes::Bytes;
@@ -24,7 +25,7 @@ use hyper_util::rt::TokioIo;
 use hyper_util::server::conn::auto;
 use hyper_util::service::TowerToHyperService;
 use maplit::hashmap;
-use nativelink_config::cas_server::ByteStreamConfig;
+use nativelink_config::cas_server::{ByteStreamConfig, PersistentUploadsConfig};
 use nativelink_config::stores::{MemorySpec, StoreSpec};
 use nativelink_error::{make_err, Code, Error, ResultExt};
 use nativelink_macro::nativelink_test;
@@ -78,9 +79,14 @@ fn make_bytestream_server(
         cas_stores: hashmap! {
             "foo_instance_name".to_string() => "main_cas".to_string(),
         },
+        access: HashMap::new(),
         persist_stream_on_disconnect_timeout: 0,
         max_bytes_per_stream: 1024,
         max_decoding_message_size: 0,
+        experimental_persistent_uploads: None,
+        max_action_output_stream_size: 0,
+        // Clients of the tests have the empty identity.
+        action_output_writer_identities: vec![String::new()],
     });
     ByteStreamServer::new(&config, store_manager)
 }
@@ -1025,3 +1031,447 @@ pub async fn max_decoding_message_size_test() -> Result<(), Box<dyn std::error::
 
     Ok(())
 }
+
+#[nativelink_test]
+pub async fn write_zstd_compressed_blob() -> Result<(), Box<dyn std::error::Error>> {
+    let store_manager = make_store_manager().await?;
+    let bs_server = Arc::new(
+        make_bytestream_server(store_manager.as_ref(), None).expect("Failed to make server"),
+    );
+    let store = store_manager.get_store("main_cas").unwrap();
+
+    let raw_data = "12456789abcdefghijk".repeat(1000);
+    let compressed = zstd::bulk::compress(raw_data.as_bytes(), 3)?;
+    let (first, second) = compressed.split_at(compressed.len() / 2);
+    let resource_name = format!(
+        "{}/uploads/{}/compressed-blobs/zstd/{}/{}",
+        INSTANCE_NAME,
+        "4dcec57e-1389-4ab5-b188-4a59f22ceb4b",
+        HASH1,
+        raw_data.len(),
+    );
+
+    let (tx, join_handle) = make_stream_and_writer_spawn(bs_server, None);
+    {
+        tx.send(Frame::data(encode_stream_proto(&WriteRequest {
+            resource_name: resource_name.clone(),
+            write_offset: 0,
+            finish_write: false,
+            data: Bytes::copy_from_slice(first),
+        })?))
+        .await?;
+        // Offsets of compressed uploads count compressed bytes.
+        tx.send(Frame::data(encode_stream_proto(&WriteRequest {
+            resource_name,
+            write_offset: first.len() as i64,
+            finish_write: true,
+            data: Bytes::copy_from_slice(second),
+        })?))
+        .await?;
+        let response = join_handle
+            .await
+            .expect("Failed to join")
+            .expect("Failed write");
+        assert_eq!(
+            response.into_inner().committed_size,
+            compressed.len() as i64
+        );
+    }
+    {
+        let data = store
+            .get_part_unchunked(DigestInfo::try_new(HASH1, raw_data.len())?, 0, None)
+            .await?;
+        assert_eq!(
+            data,
+            raw_data.as_bytes(),
+            "Expected decompressed data in store"
+        );
+    }
+    Ok(())
+}
+
+#[nativelink_test]
+pub async fn write_invalid_zstd_data_fails() -> Result<(), Box<dyn std::error::Error>> {
+    let store_manager = make_store_manager().await?;
+    let bs_server = Arc::new(
+        make_bytestream_server(store_manager.as_ref(), None).expect("Failed to make server"),
+    );
+    let store = store_manager.get_store("main_cas").unwrap();
+
+    let resource_name = format!(
+        "{}/uploads/{}/compressed-blobs/zstd/{}/{}",
+        INSTANCE_NAME, "4dcec57e-1389-4ab5-b188-4a59f22ceb4b", HASH1, 100,
+    );
+    let (tx, join_handle) = make_stream_and_writer_spawn(bs_server, None);
+    tx.send(Frame::data(encode_stream_proto(&WriteRequest {
+        resource_name,
+        write_offset: 0,
+        finish_write: true,
+        data: "not zstd data".into(),
+    })?))
+    .await?;
+    assert!(join_handle.await.expect("Failed to join").is_err());
+    assert_eq!(store.has(DigestInfo::try_new(HASH1, 100)?).await?, None);
+    Ok(())
+}
+
+#[nativelink_test]
+pub async fn read_zstd_compressed_blob() -> Result<(), Box<dyn std::error::Error>> {
+    let store_manager = make_store_manager().await?;
+    let bs_server = Arc::new(
+        make_bytestream_server(store_manager.as_ref(), None).expect("Failed to make server"),
+    );
+    let store = store_manager.get_store("main_cas").unwrap();
+
+    let raw_data = "12456789abcdefghijk".repeat(1000);
+    let digest = DigestInfo::try_new(HASH1, raw_data.len())?;
+    store
+        .update_oneshot(digest, raw_data.clone().into())
+        .await?;
+
+    let read_request = ReadRequest {
+        resource_name: format!(
+            "{}/compressed-blobs/zstd/{}/{}",
+            INSTANCE_NAME,
+            HASH1,
+            raw_data.len()
+        ),
+        // Offset and limit apply to the uncompressed data.
+        read_offset: 10,
+        read_limit: 0,
+    };
+    let mut read_stream = bs_server
+        .read(Request::new(read_request))
+        .await?
+        .into_inner();
+    let mut compressed = Vec::new();
+    while let Some(result_read_response) = read_stream.next().await {
+        compressed.append(&mut result_read_response?.data.to_vec());
+    }
+    assert!(compressed.len() < raw_data.len());
+    assert_eq!(
+        zstd::bulk::decompress(&compressed, raw_data.len())?,
+        raw_data[10..].as_bytes(),
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+pub async fn read_action_output_stream_while_written() -> Result<(), Box<dyn std::error::Error>> {
+    let store_manager = make_store_manager().await?;
+    let bs_server = Arc::new(
+        make_bytestream_server(store_manager.as_ref(), None).expect("Failed to make server"),
+    );
+    let resource_name = format!("{INSTANCE_NAME}/action-outputs/{HASH1}/100/stdout");
+
+    let (tx, join_handle) = make_stream_and_writer_spawn(bs_server.clone(), None);
+    let mut write_request = WriteRequest {
+        resource_name: resource_name.clone(),
+        write_offset: 0,
+        finish_write: false,
+        data: "first line\n".into(),
+    };
+    tx.send(Frame::data(encode_stream_proto(&write_request)?))
+        .await?;
+    // Wait for the stream to be written before it is read.
+    let mut read_stream = loop {
+        match bs_server
+            .read(Request::new(ReadRequest {
+                resource_name: resource_name.clone(),
+                read_offset: 0,
+                read_limit: 0,
+            }))
+            .await
+        {
+            Ok(response) => break response.into_inner(),
+            Err(status) => {
+                assert_eq!(status.code(), tonic::Code::NotFound);
+                yield_now().await;
+            }
+        }
+    };
+    assert_eq!(read_stream.next().await.unwrap()?.data, "first line\n");
+
+    // Data written later is sent to the reader as it comes.
+    write_request.write_offset = 11;
+    write_request.data = "second line\n".into();
+    tx.send(Frame::data(encode_stream_proto(&write_request)?))
+        .await?;
+    assert_eq!(read_stream.next().await.unwrap()?.data, "second line\n");
+
+    write_request.write_offset = 23;
+    write_request.data = vec![].into();
+    write_request.finish_write = true;
+    tx.send(Frame::data(encode_stream_proto(&write_request)?))
+        .await?;
+    let server_result = join_handle
+        .await
+        .expect("Failed to join")
+        .expect("Failed write");
+    assert_eq!(server_result.into_inner().committed_size, 23);
+    assert!(read_stream.next().await.is_none());
+
+    // Finished streams can not be read anymore.
+    let status = bs_server
+        .read(Request::new(ReadRequest {
+            resource_name,
+            read_offset: 0,
+            read_limit: 0,
+        }))
+        .await
+        .err()
+        .expect("Expected finished stream to not be found");
+    assert_eq!(status.code(), tonic::Code::NotFound);
+    Ok(())
+}
+
+#[nativelink_test]
+pub async fn action_output_stream_rejects_gaps() -> Result<(), Box<dyn std::error::Error>> {
+    let store_manager = make_store_manager().await?;
+    let bs_server = Arc::new(
+        make_bytestream_server(store_manager.as_ref(), None).expect("Failed to make server"),
+    );
+    let (tx, join_handle) = make_stream_and_writer_spawn(bs_server, None);
+    tx.send(Frame::data(encode_stream_proto(&WriteRequest {
+        resource_name: format!("{INSTANCE_NAME}/action-outputs/{HASH1}/100/stderr"),
+        write_offset: 5,
+        finish_write: true,
+        data: "data".into(),
+    })?))
+    .await?;
+    assert!(join_handle.await.expect("Failed to join").is_err());
+    Ok(())
+}
+
+#[nativelink_test]
+pub async fn action_output_stream_keeps_only_its_end() -> Result<(), Box<dyn std::error::Error>> {
+    let store_manager = make_store_manager().await?;
+    let bs_server = Arc::new(make_bytestream_server(
+        store_manager.as_ref(),
+        Some(ByteStreamConfig {
+            cas_stores: hashmap! {
+                INSTANCE_NAME.to_string() => "main_cas".to_string(),
+            },
+            max_action_output_stream_size: 8,
+            action_output_writer_identities: vec![String::new()],
+            ..Default::default()
+        }),
+    )?);
+    let resource_name = format!("{INSTANCE_NAME}/action-outputs/{HASH1}/100/stdout");
+
+    let (tx, _join_handle) = make_stream_and_writer_spawn(bs_server.clone(), None);
+    tx.send(Frame::data(encode_stream_proto(&WriteRequest {
+        resource_name: resource_name.clone(),
+        write_offset: 0,
+        finish_write: false,
+        data: "0123456789abcdef".into(),
+    })?))
+    .await?;
+    // Wait for the stream to be written before it is read.
+    let mut read_stream = loop {
+        match bs_server
+            .read(Request::new(ReadRequest {
+                resource_name: resource_name.clone(),
+                read_offset: 8,
+                read_limit: 0,
+            }))
+            .await
+        {
+            Ok(response) => break response.into_inner(),
+            Err(status) => {
+                assert_eq!(status.code(), tonic::Code::NotFound);
+                yield_now().await;
+            }
+        }
+    };
+    assert_eq!(read_stream.next().await.unwrap()?.data, "89abcdef");
+
+    // The start of the stream was dropped.
+    let mut read_stream = bs_server
+        .read(Request::new(ReadRequest {
+            resource_name,
+            read_offset: 0,
+            read_limit: 0,
+        }))
+        .await?
+        .into_inner();
+    let status = read_stream.next().await.unwrap().unwrap_err();
+    assert_eq!(status.code(), tonic::Code::OutOfRange);
+    Ok(())
+}
+
+#[nativelink_test]
+pub async fn action_output_stream_rejects_other_writers() -> Result<(), Box<dyn std::error::Error>>
+{
+    let store_manager = make_store_manager().await?;
+    let bs_server = Arc::new(make_bytestream_server(
+        store_manager.as_ref(),
+        Some(ByteStreamConfig {
+            cas_stores: hashmap! {
+                INSTANCE_NAME.to_string() => "main_cas".to_string(),
+            },
+            action_output_writer_identities: vec!["worker".to_string()],
+            ..Default::default()
+        }),
+    )?);
+    let (tx, join_handle) = make_stream_and_writer_spawn(bs_server, None);
+    tx.send(Frame::data(encode_stream_proto(&WriteRequest {
+        resource_name: format!("{INSTANCE_NAME}/action-outputs/{HASH1}/100/stdout"),
+        write_offset: 0,
+        finish_write: true,
+        data: "data".into(),
+    })?))
+    .await?;
+    let status = join_handle
+        .await
+        .expect("Failed to join")
+        .expect_err("Expected write of client without worker identity to fail");
+    assert_eq!(status.code(), tonic::Code::PermissionDenied);
+    Ok(())
+}
+
+fn make_temp_path() -> String {
+    format!(
+        "{}/{}",
+        std::env::var("TEST_TMPDIR")
+            .unwrap_or_else(|_| std::env::temp_dir().to_str().unwrap().to_string()),
+        uuid::Uuid::new_v4(),
+    )
+}
+
+fn make_persistent_uploads_config(path: &str) -> ByteStreamConfig {
+    ByteStreamConfig {
+        cas_stores: hashmap! {
+            INSTANCE_NAME.to_string() => "main_cas".to_string(),
+        },
+        experimental_persistent_uploads: Some(PersistentUploadsConfig {
+            path: path.to_string(),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+#[nativelink_test]
+pub async fn persistent_upload_resumes_after_restart_test() -> Result<(), Box<dyn std::error::Error>>
+{
+    const WRITE_DATA: &str = "12456789abcdefghijk";
+    const BYTE_SPLIT_OFFSET: usize = 8;
+
+    let store_manager = make_store_manager().await?;
+    let store = store_manager.get_store("main_cas").unwrap();
+    let path = make_temp_path();
+    let resource_name = make_resource_name(WRITE_DATA.len());
+    let mut write_request = WriteRequest {
+        resource_name: resource_name.clone(),
+        write_offset: 0,
+        finish_write: false,
+        data: WRITE_DATA[..BYTE_SPLIT_OFFSET].into(),
+    };
+    {
+        // Write the first chunk, then disconnect and stop the server.
+        let bs_server = Arc::new(make_bytestream_server(
+            store_manager.as_ref(),
+            Some(make_persistent_uploads_config(&path)),
+        )?);
+        let (tx, join_handle) = make_stream_and_writer_spawn(bs_server, None);
+        tx.send(Frame::data(encode_stream_proto(&write_request)?))
+            .await?;
+        drop(tx);
+        let result = join_handle.await.expect("Failed to join");
+        assert!(result.is_err(), "Expected error to be returned");
+    }
+
+    let bs_server = Arc::new(make_bytestream_server(
+        store_manager.as_ref(),
+        Some(make_persistent_uploads_config(&path)),
+    )?);
+    let response = bs_server
+        .query_write_status(Request::new(QueryWriteStatusRequest {
+            resource_name: resource_name.clone(),
+        }))
+        .await?;
+    assert_eq!(
+        response.into_inner(),
+        QueryWriteStatusResponse {
+            committed_size: BYTE_SPLIT_OFFSET as i64,
+            complete: false,
+        }
+    );
+
+    // Resume from the committed size. Data that was already written is
+    // skipped.
+    let (tx, join_handle) = make_stream_and_writer_spawn(bs_server.clone(), None);
+    write_request.write_offset = BYTE_SPLIT_OFFSET as i64 - 2;
+    write_request.finish_write = true;
+    write_request.data = WRITE_DATA[BYTE_SPLIT_OFFSET - 2..].into();
+    tx.send(Frame::data(encode_stream_proto(&write_request)?))
+        .await?;
+    let response = join_handle
+        .await
+        .expect("Failed to join")
+        .expect("Failed write");
+    assert_eq!(
+        response.into_inner().committed_size,
+        WRITE_DATA.len() as i64
+    );
+
+    let digest = DigestInfo::try_new(HASH1, WRITE_DATA.len())?;
+    assert_eq!(store.get_part_unchunked(digest, 0, None).await?, WRITE_DATA);
+    let response = bs_server
+        .query_write_status(Request::new(QueryWriteStatusRequest { resource_name }))
+        .await?;
+    assert_eq!(
+        response.into_inner(),
+        QueryWriteStatusResponse {
+            committed_size: WRITE_DATA.len() as i64,
+            complete: true,
+        }
+    );
+    assert_eq!(std::fs::read_dir(&path)?.count(), 0);
+    Ok(())
+}
+
+#[nativelink_test]
+pub async fn persistent_upload_rejects_gap_test() -> Result<(), Box<dyn std::error::Error>> {
+    const WRITE_DATA: &str = "12456789abcdefghijk";
+    const BYTE_SPLIT_OFFSET: usize = 8;
+
+    let store_manager = make_store_manager().await?;
+    let bs_server = Arc::new(make_bytestream_server(
+        store_manager.as_ref(),
+        Some(make_persistent_uploads_config(&make_temp_path())),
+    )?);
+    let resource_name = make_resource_name(WRITE_DATA.len());
+    let mut write_request = WriteRequest {
+        resource_name: resource_name.clone(),
+        write_offset: 0,
+        finish_write: false,
+        data: WRITE_DATA[..BYTE_SPLIT_OFFSET].into(),
+    };
+    let (tx, join_handle) = make_stream_and_writer_spawn(bs_server.clone(), None);
+    tx.send(Frame::data(encode_stream_proto(&write_request)?))
+        .await?;
+    drop(tx);
+    assert!(join_handle.await.expect("Failed to join").is_err());
+
+    // Data after the committed size can not be written.
+    let (tx, join_handle) = make_stream_and_writer_spawn(bs_server.clone(), None);
+    write_request.write_offset = BYTE_SPLIT_OFFSET as i64 + 1;
+    write_request.finish_write = true;
+    write_request.data = WRITE_DATA[BYTE_SPLIT_OFFSET + 1..].into();
+    tx.send(Frame::data(encode_stream_proto(&write_request)?))
+        .await?;
+    let err = join_handle.await.expect("Failed to join").unwrap_err();
+    assert_eq!(err.code(), tonic::Code::InvalidArgument, "{err:?}");
+
+    // The data that was written is kept.
+    let response = bs_server
+        .query_write_status(Request::new(QueryWriteStatusRequest { resource_name }))
+        .await?;
+    assert_eq!(
+        response.into_inner().committed_size,
+        BYTE_SPLIT_OFFSET as i64
+    );
+    Ok(())
+}
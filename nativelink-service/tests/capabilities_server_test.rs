@@ -0,0 +1,138 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use maplit::hashmap;
+use nativelink_config::cas_server::{
+    AcStoreConfig, ByteStreamConfig, CapabilitiesConfig, CasStoreConfig, ServicesConfig,
+};
+use nativelink_macro::nativelink_test;
+use nativelink_proto::build::bazel::remote::execution::v2::capabilities_server::Capabilities;
+use nativelink_proto::build::bazel::remote::execution::v2::symlink_absolute_path_strategy::Value as SymlinkAbsolutePathStrategy;
+use nativelink_proto::build::bazel::remote::execution::v2::{
+    compressor, CacheCapabilities, GetCapabilitiesRequest,
+};
+use nativelink_service::capabilities_server::CapabilitiesServer;
+use pretty_assertions::assert_eq;
+use tonic::Request;
+
+async fn get_cache_capabilities(
+    server: &CapabilitiesServer,
+    instance_name: &str,
+) -> Result<CacheCapabilities, tonic::Status> {
+    Ok(server
+        .get_capabilities(Request::new(GetCapabilitiesRequest {
+            instance_name: instance_name.to_string(),
+        }))
+        .await?
+        .into_inner()
+        .cache_capabilities
+        .unwrap())
+}
+
+#[nativelink_test]
+async fn reports_configured_cache_capabilities() -> Result<(), Box<dyn std::error::Error>> {
+    const INSTANCE_NAME: &str = "configured_capabilities_instance";
+
+    let services = ServicesConfig {
+        cas: Some(hashmap! {
+            INSTANCE_NAME.to_string() => CasStoreConfig {
+                cas_store: "main_cas".to_string(),
+                access: Default::default(),
+                max_batch_total_size_bytes: 1024 * 1024,
+            },
+        }),
+        ac: Some(hashmap! {
+            INSTANCE_NAME.to_string() => AcStoreConfig {
+                ac_store: "main_ac".to_string(),
+                read_only: true,
+                access: Default::default(),
+            },
+        }),
+        capabilities: Some(hashmap! {
+            INSTANCE_NAME.to_string() => CapabilitiesConfig {
+                allow_absolute_symlinks: true,
+                ..Default::default()
+            },
+        }),
+        bytestream: Some(ByteStreamConfig {
+            cas_stores: hashmap! {
+                INSTANCE_NAME.to_string() => "main_cas".to_string(),
+            },
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let server = CapabilitiesServer::new(&services, &HashMap::new()).await?;
+
+    let capabilities = get_cache_capabilities(&server, INSTANCE_NAME).await?;
+    assert_eq!(capabilities.max_batch_total_size_bytes, 1024 * 1024);
+    assert!(
+        !capabilities
+            .action_cache_update_capabilities
+            .unwrap()
+            .update_enabled
+    );
+    assert_eq!(
+        capabilities.symlink_absolute_path_strategy,
+        i32::from(SymlinkAbsolutePathStrategy::Allowed)
+    );
+    assert_eq!(
+        capabilities.supported_compressors,
+        vec![i32::from(compressor::Value::Zstd)]
+    );
+    assert_eq!(
+        capabilities.supported_batch_update_compressors,
+        vec![
+            i32::from(compressor::Value::Zstd),
+            i32::from(compressor::Value::Deflate)
+        ]
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn instance_without_services_has_defaults() -> Result<(), Box<dyn std::error::Error>> {
+    const INSTANCE_NAME: &str = "capabilities_only_instance";
+
+    let services = ServicesConfig {
+        capabilities: Some(hashmap! {
+            INSTANCE_NAME.to_string() => CapabilitiesConfig::default(),
+        }),
+        ..Default::default()
+    };
+    let server = CapabilitiesServer::new(&services, &HashMap::new()).await?;
+
+    let capabilities = get_cache_capabilities(&server, INSTANCE_NAME).await?;
+    assert_eq!(capabilities.max_batch_total_size_bytes, 4 * 1024 * 1024);
+    assert_eq!(
+        capabilities.symlink_absolute_path_strategy,
+        i32::from(SymlinkAbsolutePathStrategy::Disallowed)
+    );
+    assert_eq!(capabilities.supported_compressors, Vec::<i32>::new());
+    assert_eq!(
+        capabilities.supported_batch_update_compressors,
+        Vec::<i32>::new()
+    );
+
+    let status = get_cache_capabilities(&server, "unknown_instance")
+        .await
+        .unwrap_err();
+    assert!(
+        status.message().contains("'instance_name' not configured"),
+        "{status:?}"
+    );
+    Ok(())
+}
@@ -15,9 +15,10 @@
 use std::pin::Pin;
 use std::sync::Arc;
 
+use bytes::Bytes;
 use futures::StreamExt;
 use maplit::hashmap;
-use nativelink_config::stores::{MemorySpec, StoreSpec};
+use nativelink_config::stores::{MemorySpec, StoreSpec, VerifySpec};
 use nativelink_error::Error;
 use nativelink_macro::nativelink_test;
 use nativelink_proto::build::bazel::remote::execution::v2::content_addressable_storage_server::ContentAddressableStorage;
@@ -32,8 +33,11 @@ use nativelink_service::cas_server::CasServer;
 use nativelink_store::ac_utils::serialize_and_upload_message;
 use nativelink_store::default_store_factory::store_factory;
 use nativelink_store::store_manager::StoreManager;
+use nativelink_util::blob_compression::BlobCompressor;
 use nativelink_util::common::DigestInfo;
-use nativelink_util::digest_hasher::DigestHasherFunc;
+use nativelink_util::digest_hasher::{
+    set_instance_digest_hasher_funcs, DigestHasher, DigestHasherFunc, InstanceDigestHasherFuncs,
+};
 use nativelink_util::store_trait::{StoreKey, StoreLike};
 use pretty_assertions::assert_eq;
 use prost_types::Timestamp;
@@ -64,6 +68,8 @@ fn make_cas_server(store_manager: &StoreManager) -> Result<CasServer, Error> {
         &hashmap! {
             "foo_instance_name".to_string() => nativelink_config::cas_server::CasStoreConfig{
                 cas_store: "main_cas".to_string(),
+                access: Default::default(),
+                max_batch_total_size_bytes: 0,
             }
         },
         store_manager,
@@ -151,8 +157,8 @@ async fn has_three_requests_one_bad_hash() -> Result<(), Box<dyn std::error::Err
         .await;
     let error = raw_response.unwrap_err();
     assert!(
-        error.to_string().contains("Invalid sha256 hash: BAD_HASH"),
-        "'Invalid sha256 hash: BAD_HASH' not found in: {error:?}"
+        error.to_string().contains("Invalid hash: BAD_HASH"),
+        "'Invalid hash: BAD_HASH' not found in: {error:?}"
     );
     Ok(())
 }
@@ -665,3 +671,196 @@ async fn batch_update_blobs_two_items_existence_with_third_missing(
     }
     Ok(())
 }
+
+#[nativelink_test]
+async fn digest_function_is_negotiated_per_instance_name() -> Result<(), Box<dyn std::error::Error>>
+{
+    const BLAKE3_INSTANCE_NAME: &str = "blake3_instance_name";
+    const VALUE: &[u8] = b"blake3 content";
+
+    let store_manager = Arc::new(StoreManager::new());
+    store_manager.add_store(
+        "verify_cas",
+        store_factory(
+            &StoreSpec::verify(Box::new(VerifySpec {
+                backend: StoreSpec::memory(MemorySpec::default()),
+                verify_size: true,
+                verify_hash: true,
+            })),
+            &store_manager,
+            None,
+        )
+        .await?,
+    );
+    let cas_server = CasServer::new(
+        &hashmap! {
+            BLAKE3_INSTANCE_NAME.to_string() => nativelink_config::cas_server::CasStoreConfig{
+                cas_store: "verify_cas".to_string(),
+                access: Default::default(),
+                max_batch_total_size_bytes: 0,
+            }
+        },
+        &store_manager,
+    )?;
+    set_instance_digest_hasher_funcs(
+        BLAKE3_INSTANCE_NAME,
+        InstanceDigestHasherFuncs {
+            default: DigestHasherFunc::Blake3,
+            supported: vec![DigestHasherFunc::Blake3],
+        },
+    );
+
+    let mut hasher = DigestHasherFunc::Blake3.hasher();
+    hasher.update(VALUE);
+    let digest: Digest = hasher.finalize_digest().into();
+    let update_request = |digest_function: i32| BatchUpdateBlobsRequest {
+        instance_name: BLAKE3_INSTANCE_NAME.to_string(),
+        requests: vec![batch_update_blobs_request::Request {
+            digest: Some(digest.clone()),
+            data: VALUE.into(),
+            compressor: compressor::Value::Identity.into(),
+        }],
+        digest_function,
+    };
+
+    // Digest functions the instance does not support are rejected.
+    let err = cas_server
+        .batch_update_blobs(Request::new(update_request(
+            digest_function::Value::Sha256.into(),
+        )))
+        .await
+        .expect_err("Expected sha256 to be rejected");
+    assert_eq!(err.code(), Code::InvalidArgument);
+
+    // Requests without a digest function are verified with the default of
+    // the instance.
+    let response = cas_server
+        .batch_update_blobs(Request::new(update_request(0)))
+        .await?
+        .into_inner();
+    assert_eq!(
+        response.responses[0].status,
+        Some(GrpcStatus {
+            code: 0,
+            message: String::new(),
+            details: vec![],
+        })
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn batch_update_blobs_decompresses_blobs() -> Result<(), Box<dyn std::error::Error>> {
+    const VALUE1: &str = "compressed with zstd compressed with zstd";
+    const VALUE2: &str = "compressed with deflate compressed with deflate";
+
+    let store_manager = make_store_manager().await?;
+    let cas_server = make_cas_server(&store_manager)?;
+    let store = store_manager.get_store("main_cas").unwrap();
+
+    let digest1 = Digest {
+        hash: HASH1.to_string(),
+        size_bytes: VALUE1.len() as i64,
+    };
+    let digest2 = Digest {
+        hash: HASH2.to_string(),
+        size_bytes: VALUE2.len() as i64,
+    };
+    let response = cas_server
+        .batch_update_blobs(Request::new(BatchUpdateBlobsRequest {
+            instance_name: INSTANCE_NAME.to_string(),
+            requests: vec![
+                batch_update_blobs_request::Request {
+                    digest: Some(digest1.clone()),
+                    data: BlobCompressor::Zstd.compress(Bytes::from(VALUE1))?,
+                    compressor: compressor::Value::Zstd.into(),
+                },
+                batch_update_blobs_request::Request {
+                    digest: Some(digest2.clone()),
+                    data: BlobCompressor::Deflate.compress(Bytes::from(VALUE2))?,
+                    compressor: compressor::Value::Deflate.into(),
+                },
+            ],
+            digest_function: digest_function::Value::Sha256.into(),
+        }))
+        .await?
+        .into_inner();
+    for response in response.responses {
+        assert_eq!(response.status.unwrap().code, 0);
+    }
+    assert_eq!(
+        store
+            .get_part_unchunked(DigestInfo::try_from(digest1)?, 0, None)
+            .await?,
+        VALUE1
+    );
+    assert_eq!(
+        store
+            .get_part_unchunked(DigestInfo::try_from(digest2)?, 0, None)
+            .await?,
+        VALUE2
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn batch_update_blobs_rejects_wrong_decompressed_size(
+) -> Result<(), Box<dyn std::error::Error>> {
+    const VALUE: &str = "123456789";
+
+    let store_manager = make_store_manager().await?;
+    let cas_server = make_cas_server(&store_manager)?;
+
+    let result = cas_server
+        .batch_update_blobs(Request::new(BatchUpdateBlobsRequest {
+            instance_name: INSTANCE_NAME.to_string(),
+            requests: vec![batch_update_blobs_request::Request {
+                digest: Some(Digest {
+                    hash: HASH1.to_string(),
+                    size_bytes: VALUE.len() as i64 - 1,
+                }),
+                data: BlobCompressor::Zstd.compress(Bytes::from(VALUE))?,
+                compressor: compressor::Value::Zstd.into(),
+            }],
+            digest_function: digest_function::Value::Sha256.into(),
+        }))
+        .await;
+    assert_eq!(result.unwrap_err().code(), Code::InvalidArgument);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn batch_read_blobs_compresses_with_acceptable_compressor(
+) -> Result<(), Box<dyn std::error::Error>> {
+    const VALUE: &str = "123456789123456789123456789";
+
+    let store_manager = make_store_manager().await?;
+    let cas_server = make_cas_server(&store_manager)?;
+    let store = store_manager.get_store("main_cas").unwrap();
+    store
+        .update_oneshot(DigestInfo::try_new(HASH1, VALUE.len())?, VALUE.into())
+        .await?;
+
+    let response = cas_server
+        .batch_read_blobs(Request::new(BatchReadBlobsRequest {
+            instance_name: INSTANCE_NAME.to_string(),
+            digests: vec![Digest {
+                hash: HASH1.to_string(),
+                size_bytes: VALUE.len() as i64,
+            }],
+            acceptable_compressors: vec![
+                compressor::Value::Brotli.into(),
+                compressor::Value::Deflate.into(),
+            ],
+            digest_function: digest_function::Value::Sha256.into(),
+        }))
+        .await?
+        .into_inner();
+    let response = &response.responses[0];
+    assert_eq!(response.compressor, i32::from(compressor::Value::Deflate));
+    assert_eq!(
+        BlobCompressor::Deflate.decompress(response.data.clone(), VALUE.len())?,
+        VALUE
+    );
+    Ok(())
+}
@@ -0,0 +1,77 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use axum::body::Body;
+use http_body_util::BodyExt;
+use hyper::{Request, StatusCode};
+use nativelink_error::{make_err, Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_service::health_server::{HealthProbe, HealthServer};
+use nativelink_util::health_utils::{
+    HealthRegistry, HealthRegistryBuilder, HealthStatus, HealthStatusIndicator,
+};
+use pretty_assertions::assert_eq;
+use tower::Service;
+
+struct InitializingComponent;
+
+#[async_trait::async_trait]
+impl HealthStatusIndicator for InitializingComponent {
+    fn get_name(&self) -> &'static str {
+        "InitializingComponent"
+    }
+
+    async fn check_health(&self, _namespace: Cow<'static, str>) -> HealthStatus {
+        HealthStatus::new_initializing(self, "Loading index".into())
+    }
+}
+
+fn make_health_registry() -> HealthRegistry {
+    let mut health_registry_builder = HealthRegistryBuilder::new("nativelink");
+    health_registry_builder.register_indicator(Arc::new(InitializingComponent));
+    health_registry_builder.build()
+}
+
+async fn check(probe: HealthProbe) -> Result<(StatusCode, String), Error> {
+    let response = HealthServer::new_with_probe(make_health_registry(), probe)
+        .call(Request::new(Body::empty()))
+        .await
+        .map_err(|e| make_err!(Code::Internal, "{e:?}"))?;
+    let status = response.status();
+    let body = response
+        .into_body()
+        .collect()
+        .await
+        .map_err(|e| make_err!(Code::Internal, "{e:?}"))?
+        .to_bytes();
+    Ok((status, String::from_utf8_lossy(&body).to_string()))
+}
+
+#[nativelink_test]
+async fn initializing_component_is_not_ready_test() -> Result<(), Error> {
+    let (status, body) = check(HealthProbe::Status).await?;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert!(body.contains("latency_ms"), "{body}");
+    assert!(body.contains("/nativelink/InitializingComponent"), "{body}");
+
+    let (status, body) = check(HealthProbe::Readiness).await?;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE, "{body}");
+
+    let (status, body) = check(HealthProbe::Liveness).await?;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    Ok(())
+}
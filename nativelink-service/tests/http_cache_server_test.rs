@@ -0,0 +1,265 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::Router;
+use bytes::Bytes;
+use http_body_util::BodyExt;
+use hyper::header::CONTENT_LENGTH;
+use hyper::{Method, Request, StatusCode};
+use nativelink_config::cas_server::{HttpCacheConfig, InstanceAccessConfig};
+use nativelink_config::stores::{MemorySpec, NoopSpec, StoreSpec};
+use nativelink_error::{make_err, Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_service::http_cache_server::HttpCacheServer;
+use nativelink_store::default_store_factory::store_factory;
+use nativelink_store::store_manager::StoreManager;
+use nativelink_util::common::DigestInfo;
+use nativelink_util::digest_hasher::{DigestHasher, DigestHasherFunc};
+use nativelink_util::store_trait::StoreLike;
+use pretty_assertions::assert_eq;
+use tower::Service;
+
+const AC_STORE_NAME: &str = "main_ac";
+const CAS_STORE_NAME: &str = "main_cas";
+const HASH1: &str = "0123456789abcdef000000000000000000000000000000000123456789abcdef";
+const VALUE1: &str = "123";
+
+async fn make_store_manager() -> Result<Arc<StoreManager>, Error> {
+    let store_manager = Arc::new(StoreManager::new());
+    for store_name in [AC_STORE_NAME, CAS_STORE_NAME] {
+        store_manager.add_store(
+            store_name,
+            store_factory(
+                &StoreSpec::memory(MemorySpec::default()),
+                &store_manager,
+                None,
+            )
+            .await?,
+        );
+    }
+    Ok(store_manager)
+}
+
+fn make_router(store_manager: &StoreManager) -> Result<Router, Error> {
+    make_router_with_access(store_manager, InstanceAccessConfig::default())
+}
+
+fn make_router_with_access(
+    store_manager: &StoreManager,
+    access: InstanceAccessConfig,
+) -> Result<Router, Error> {
+    Ok(HttpCacheServer::new(
+        &HttpCacheConfig {
+            path: String::new(),
+            cas_store: CAS_STORE_NAME.to_string(),
+            ac_store: AC_STORE_NAME.to_string(),
+            access,
+        },
+        store_manager,
+    )?
+    .into_router())
+}
+
+fn sha256_digest(data: &str) -> DigestInfo {
+    let mut hasher = DigestHasherFunc::Sha256.hasher();
+    hasher.update(data.as_bytes());
+    hasher.finalize_digest()
+}
+
+async fn send(
+    router: &mut Router,
+    method: Method,
+    uri: &str,
+    body: Option<&'static str>,
+) -> Result<(StatusCode, Bytes), Error> {
+    let mut request = Request::builder().method(method).uri(uri);
+    if let Some(body) = body {
+        request = request.header(CONTENT_LENGTH, body.len());
+    }
+    let request = request
+        .body(body.map_or_else(Body::empty, Body::from))
+        .map_err(|e| make_err!(Code::Internal, "{e:?}"))?;
+    let response = router
+        .call(request)
+        .await
+        .map_err(|e| make_err!(Code::Internal, "{e:?}"))?;
+    let status = response.status();
+    let body = response
+        .into_body()
+        .collect()
+        .await
+        .map_err(|e| make_err!(Code::Internal, "{e:?}"))?
+        .to_bytes();
+    Ok((status, body))
+}
+
+#[nativelink_test]
+async fn cas_objects_round_trip() -> Result<(), Error> {
+    let store_manager = make_store_manager().await?;
+    let mut router = make_router(&store_manager)?;
+    let digest = sha256_digest(VALUE1);
+    let uri = format!("/cas/{}", digest.packed_hash());
+
+    let (status, _) = send(&mut router, Method::HEAD, &uri, None).await?;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = send(&mut router, Method::PUT, &uri, Some(VALUE1)).await?;
+    assert_eq!(status, StatusCode::OK);
+    assert!(store_manager
+        .get_store(CAS_STORE_NAME)
+        .unwrap()
+        .has(digest)
+        .await?
+        .is_some());
+
+    let (status, _) = send(&mut router, Method::HEAD, &uri, None).await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        send(&mut router, Method::GET, &uri, None).await?,
+        (StatusCode::OK, Bytes::from_static(VALUE1.as_bytes()))
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn cas_uploads_must_match_hash() -> Result<(), Error> {
+    let store_manager = make_store_manager().await?;
+    let mut router = make_router(&store_manager)?;
+
+    let (status, _) = send(
+        &mut router,
+        Method::PUT,
+        &format!("/cas/{HASH1}"),
+        Some(VALUE1),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(&mut router, Method::GET, &format!("/cas/{HASH1}"), None).await?;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn ac_objects_are_stored_under_any_hash() -> Result<(), Error> {
+    let store_manager = make_store_manager().await?;
+    let mut router = make_router(&store_manager)?;
+    let uri = format!("/ac/{HASH1}");
+
+    let (status, _) = send(&mut router, Method::PUT, &uri, Some(VALUE1)).await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        send(&mut router, Method::GET, &uri, None).await?,
+        (StatusCode::OK, Bytes::from_static(VALUE1.as_bytes()))
+    );
+    // Objects in the AC are not visible in the CAS.
+    let (status, _) = send(&mut router, Method::GET, &format!("/cas/{HASH1}"), None).await?;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn ac_objects_are_shared_with_grpc_clients() -> Result<(), Error> {
+    const ACTION_SIZE: u64 = 100;
+    let store_manager = make_store_manager().await?;
+    let mut router = make_router(&store_manager)?;
+    let ac_store = store_manager.get_store(AC_STORE_NAME).unwrap();
+    let cas_store = store_manager.get_store(CAS_STORE_NAME).unwrap();
+
+    // Entries written over gRPC have the size of their Action, the stored
+    // object is sent with its own size.
+    ac_store
+        .update_oneshot(DigestInfo::try_new(HASH1, ACTION_SIZE)?, VALUE1.into())
+        .await?;
+    let request = Request::builder()
+        .method(Method::HEAD)
+        .uri(format!("/ac/{HASH1}"))
+        .body(Body::empty())
+        .map_err(|e| make_err!(Code::Internal, "{e:?}"))?;
+    let response = router
+        .call(request)
+        .await
+        .map_err(|e| make_err!(Code::Internal, "{e:?}"))?;
+    assert_eq!(response.headers()[CONTENT_LENGTH], VALUE1.len().to_string());
+    assert_eq!(
+        send(&mut router, Method::GET, &format!("/ac/{HASH1}"), None).await?,
+        (StatusCode::OK, Bytes::from_static(VALUE1.as_bytes()))
+    );
+
+    // Entries written over HTTP get the digest of their Action.
+    let action = "action";
+    let action_digest = sha256_digest(action);
+    cas_store
+        .update_oneshot(action_digest, action.into())
+        .await?;
+    let uri = format!("/ac/{}", action_digest.packed_hash());
+    let (status, _) = send(&mut router, Method::PUT, &uri, Some(VALUE1)).await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        ac_store.get_part_unchunked(action_digest, 0, None).await?,
+        Bytes::from_static(VALUE1.as_bytes())
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn identities_without_write_access_can_not_upload() -> Result<(), Error> {
+    let store_manager = make_store_manager().await?;
+    let mut router = make_router_with_access(
+        &store_manager,
+        InstanceAccessConfig {
+            read_identities: None,
+            write_identities: Some(vec!["ci".to_string()]),
+        },
+    )?;
+    let uri = format!("/ac/{HASH1}");
+
+    let (status, _) = send(&mut router, Method::PUT, &uri, Some(VALUE1)).await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = send(&mut router, Method::GET, &uri, None).await?;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn uploads_without_content_length_are_rejected() -> Result<(), Error> {
+    let store_manager = make_store_manager().await?;
+    let mut router = make_router(&store_manager)?;
+
+    let request = Request::builder()
+        .method(Method::PUT)
+        .uri(format!("/ac/{HASH1}"))
+        .body(Body::from(VALUE1))
+        .map_err(|e| make_err!(Code::Internal, "{e:?}"))?;
+    let response = router
+        .call(request)
+        .await
+        .map_err(|e| make_err!(Code::Internal, "{e:?}"))?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn stores_that_can_not_look_up_hashes_are_rejected() -> Result<(), Error> {
+    let store_manager = make_store_manager().await?;
+    store_manager.add_store(
+        CAS_STORE_NAME,
+        store_factory(&StoreSpec::noop(NoopSpec::default()), &store_manager, None).await?,
+    );
+    let err = make_router(&store_manager).unwrap_err();
+    assert_eq!(err.code, Code::InvalidArgument, "{err:?}");
+    Ok(())
+}
@@ -0,0 +1,249 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use maplit::hashmap;
+use nativelink_config::cas_server::ExecutionConfig;
+use nativelink_config::schedulers::SimpleSpec;
+use nativelink_error::Error;
+use nativelink_macro::nativelink_test;
+use nativelink_proto::build::bazel::remote::execution::v2::ExecuteOperationMetadata;
+use nativelink_proto::google::longrunning::operations_server::Operations;
+use nativelink_proto::google::longrunning::{
+    CancelOperationRequest, GetOperationRequest, ListOperationsRequest,
+};
+use nativelink_scheduler::default_scheduler_factory::memory_awaited_action_db_factory;
+use nativelink_scheduler::simple_scheduler::SimpleScheduler;
+use nativelink_scheduler::worker::Worker;
+use nativelink_scheduler::worker_scheduler::WorkerScheduler;
+use nativelink_service::operations_server::OperationsServer;
+use nativelink_util::action_messages::{
+    ActionInfo, ActionStage, ActionUniqueKey, ActionUniqueQualifier, OperationId, WorkerId,
+};
+use nativelink_util::common::DigestInfo;
+use nativelink_util::digest_hasher::DigestHasherFunc;
+use nativelink_util::operation_state_manager::{ActionStateResult, ClientStateManager};
+use nativelink_util::platform_properties::PlatformProperties;
+use pretty_assertions::assert_eq;
+use prost::Message;
+use tokio::sync::{mpsc, Notify};
+use tonic::{Code, Request};
+use uuid::Uuid;
+
+const INSTANCE_NAME: &str = "operations_instance";
+const OTHER_INSTANCE_NAME: &str = "other_operations_instance";
+
+fn make_operations_server() -> Result<(Arc<SimpleScheduler>, OperationsServer), Error> {
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new(
+        &SimpleSpec::default(),
+        memory_awaited_action_db_factory(0, &task_change_notify, SystemTime::now),
+        task_change_notify,
+        None,
+    );
+    let scheduler_map: HashMap<String, Arc<dyn ClientStateManager>> = hashmap! {
+        "main_scheduler".to_string() => scheduler.clone() as Arc<dyn ClientStateManager>,
+    };
+    let make_config = || ExecutionConfig {
+        cas_store: "main_cas".to_string(),
+        scheduler: "main_scheduler".to_string(),
+        access: Default::default(),
+        queue_full_retry_delay_s: 0,
+        stream_action_output: false,
+    };
+    let server = OperationsServer::new(
+        &hashmap! {
+            INSTANCE_NAME.to_string() => make_config(),
+            OTHER_INSTANCE_NAME.to_string() => make_config(),
+        },
+        &scheduler_map,
+    )?;
+    Ok((scheduler, server))
+}
+
+async fn add_action(
+    scheduler: &SimpleScheduler,
+    instance_name: &str,
+    digest_byte: u8,
+) -> Result<(Box<dyn ActionStateResult>, String), Error> {
+    let action_info = Arc::new(ActionInfo {
+        command_digest: DigestInfo::new([0u8; 32], 0),
+        input_root_digest: DigestInfo::new([0u8; 32], 0),
+        timeout: Duration::MAX,
+        platform_properties: HashMap::new(),
+        priority: 0,
+        load_timestamp: UNIX_EPOCH,
+        insert_timestamp: SystemTime::now(),
+        unique_qualifier: ActionUniqueQualifier::Cachable(ActionUniqueKey {
+            instance_name: instance_name.to_string(),
+            digest_function: DigestHasherFunc::Sha256,
+            digest: DigestInfo::new([digest_byte; 32], 512),
+        }),
+    });
+    let action_listener = scheduler
+        .add_action(OperationId::default(), action_info)
+        .await?;
+    let client_operation_id = action_listener
+        .as_state()
+        .await?
+        .0
+        .client_operation_id
+        .clone();
+    Ok((
+        action_listener,
+        format!("{instance_name}/{client_operation_id}"),
+    ))
+}
+
+fn list_request(page_size: i32, page_token: &str) -> Request<ListOperationsRequest> {
+    Request::new(ListOperationsRequest {
+        name: INSTANCE_NAME.to_string(),
+        filter: String::new(),
+        page_size,
+        page_token: page_token.to_string(),
+    })
+}
+
+#[nativelink_test]
+async fn list_and_get_operations_test() -> Result<(), Box<dyn std::error::Error>> {
+    let (scheduler, server) = make_operations_server()?;
+    let (_listener1, name1) = add_action(&scheduler, INSTANCE_NAME, 1).await?;
+    let (_listener2, _name2) = add_action(&scheduler, INSTANCE_NAME, 2).await?;
+    let (_listener3, _name3) = add_action(&scheduler, OTHER_INSTANCE_NAME, 3).await?;
+
+    let response = server
+        .list_operations(list_request(0, ""))
+        .await?
+        .into_inner();
+    assert_eq!(response.operations.len(), 2);
+    assert_eq!(response.next_page_token, "");
+    for operation in &response.operations {
+        assert!(!operation.done, "{operation:?}");
+        assert!(
+            operation.name.starts_with(&format!("{INSTANCE_NAME}/")),
+            "{operation:?}"
+        );
+    }
+
+    let first_page = server
+        .list_operations(list_request(1, ""))
+        .await?
+        .into_inner();
+    assert_eq!(first_page.operations.len(), 1);
+    assert_eq!(first_page.next_page_token, "1");
+    let second_page = server
+        .list_operations(list_request(1, &first_page.next_page_token))
+        .await?
+        .into_inner();
+    assert_eq!(second_page.operations.len(), 1);
+    assert_eq!(second_page.next_page_token, "");
+
+    // Operations can be queried by the name `Execute` returned and by the
+    // name they were listed with.
+    let operation = server
+        .get_operation(Request::new(GetOperationRequest {
+            name: name1.clone(),
+        }))
+        .await?
+        .into_inner();
+    assert_eq!(operation.name, name1);
+    let listed_name = response.operations[0].name.clone();
+    let operation = server
+        .get_operation(Request::new(GetOperationRequest {
+            name: listed_name.clone(),
+        }))
+        .await?
+        .into_inner();
+    assert_eq!(operation.name, listed_name);
+
+    let status = server
+        .get_operation(Request::new(GetOperationRequest {
+            name: format!("{INSTANCE_NAME}/unknown_operation"),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound, "{status:?}");
+    Ok(())
+}
+
+#[nativelink_test]
+async fn cancel_operation_test() -> Result<(), Box<dyn std::error::Error>> {
+    let (scheduler, server) = make_operations_server()?;
+    let (mut action_listener, name) = add_action(&scheduler, INSTANCE_NAME, 1).await?;
+
+    server
+        .cancel_operation(Request::new(CancelOperationRequest { name }))
+        .await?;
+
+    let action_result = loop {
+        let (action_state, _maybe_origin_metadata) = action_listener.changed().await?;
+        if let ActionStage::Completed(action_result) = &action_state.stage {
+            break action_result.clone();
+        }
+    };
+    assert_eq!(
+        action_result.error.unwrap().code,
+        nativelink_error::Code::Cancelled
+    );
+
+    // Cancelled operations are no longer in-flight.
+    let response = server
+        .list_operations(list_request(0, ""))
+        .await?
+        .into_inner();
+    assert_eq!(response.operations.len(), 0);
+
+    let status = server
+        .cancel_operation(Request::new(CancelOperationRequest {
+            name: "unknown_instance/operation".to_string(),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound, "{status:?}");
+    Ok(())
+}
+
+#[nativelink_test]
+async fn list_operations_reports_worker_test() -> Result<(), Box<dyn std::error::Error>> {
+    let (scheduler, server) = make_operations_server()?;
+    let worker_id = WorkerId(Uuid::new_v4());
+    // Note: This needs to stay in scope or a disconnect will trigger.
+    let (tx, _rx_from_worker) = mpsc::unbounded_channel();
+    scheduler
+        .add_worker(Worker::new(worker_id, PlatformProperties::default(), tx, 0))
+        .await?;
+    let (mut action_listener, _name) = add_action(&scheduler, INSTANCE_NAME, 1).await?;
+    while action_listener.changed().await?.0.stage != ActionStage::Executing {}
+
+    let response = server
+        .list_operations(list_request(0, ""))
+        .await?
+        .into_inner();
+    assert_eq!(response.operations.len(), 1);
+    let metadata = ExecuteOperationMetadata::decode(
+        response.operations[0]
+            .metadata
+            .as_ref()
+            .unwrap()
+            .value
+            .as_slice(),
+    )?;
+    let partial_execution_metadata = metadata.partial_execution_metadata.unwrap();
+    assert_eq!(partial_execution_metadata.worker, worker_id.to_string());
+    assert!(partial_execution_metadata.queued_timestamp.is_some());
+    Ok(())
+}
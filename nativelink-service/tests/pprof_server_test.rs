@@ -0,0 +1,91 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use axum::body::Body;
+use axum::Router;
+use bytes::Bytes;
+use http_body_util::BodyExt;
+use hyper::{Request, StatusCode};
+use nativelink_config::cas_server::PprofConfig;
+use nativelink_error::{make_err, Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_service::pprof_server::PprofServer;
+use pretty_assertions::assert_eq;
+use tower::Service;
+
+fn make_router() -> Router {
+    PprofServer::new(&PprofConfig {
+        max_profile_duration_s: 2,
+        ..Default::default()
+    })
+    .into_router()
+}
+
+async fn get(router: &mut Router, uri: &str) -> Result<(StatusCode, Bytes), Error> {
+    let request = Request::builder()
+        .uri(uri)
+        .body(Body::empty())
+        .map_err(|e| make_err!(Code::Internal, "{e:?}"))?;
+    let response = router
+        .call(request)
+        .await
+        .map_err(|e| make_err!(Code::Internal, "{e:?}"))?;
+    let status = response.status();
+    let body = response
+        .into_body()
+        .collect()
+        .await
+        .map_err(|e| make_err!(Code::Internal, "{e:?}"))?
+        .to_bytes();
+    Ok((status, body))
+}
+
+#[nativelink_test]
+async fn cpu_profile_test() -> Result<(), Error> {
+    let mut router = make_router();
+    let (status, body) = get(&mut router, "/profile?seconds=1&frequency=100").await?;
+    assert_eq!(status, StatusCode::OK, "{body:?}");
+    assert!(!body.is_empty());
+    Ok(())
+}
+
+#[nativelink_test]
+async fn rejects_invalid_profile_parameters_test() -> Result<(), Error> {
+    let mut router = make_router();
+    for uri in [
+        "/profile?seconds=0",
+        "/profile?seconds=3",
+        "/profile?seconds=abc",
+        "/profile?seconds=1&frequency=0",
+    ] {
+        let (status, _) = get(&mut router, uri).await?;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{uri}");
+    }
+    Ok(())
+}
+
+#[nativelink_test]
+async fn heap_summary_test() -> Result<(), Error> {
+    let mut router = make_router();
+    let (status, body) = get(&mut router, "/heap").await?;
+    assert_eq!(status, StatusCode::OK);
+    let summary: serde_json::Value = serde_json::from_slice(&body)
+        .map_err(|e| make_err!(Code::Internal, "Invalid JSON: {e:?}"))?;
+    assert!(summary["memory_budget"]["used_bytes"].is_u64());
+    assert!(summary["memory_budget"]["max_bytes"].is_u64());
+    if cfg!(target_os = "linux") {
+        assert!(summary["process"]["VmRSS"].as_u64().unwrap() > 0);
+    }
+    Ok(())
+}
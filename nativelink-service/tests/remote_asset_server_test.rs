@@ -0,0 +1,273 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bytes::Bytes;
+use maplit::hashmap;
+use nativelink_config::cas_server::{InstanceAccessConfig, RemoteAssetConfig};
+use nativelink_config::stores::{MemorySpec, StoreSpec};
+use nativelink_error::{make_err, Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_proto::build::bazel::remote::asset::v1::fetch_server::Fetch;
+use nativelink_proto::build::bazel::remote::asset::v1::push_server::Push;
+use nativelink_proto::build::bazel::remote::asset::v1::{
+    FetchBlobRequest, PushBlobRequest, Qualifier,
+};
+use nativelink_service::remote_asset_server::RemoteAssetServer;
+use nativelink_store::asset_fetcher::AssetFetcher;
+use nativelink_store::default_store_factory::store_factory;
+use nativelink_store::store_manager::StoreManager;
+use nativelink_util::common::DigestInfo;
+use nativelink_util::digest_hasher::{DigestHasher, DigestHasherFunc};
+use nativelink_util::store_trait::StoreLike;
+use pretty_assertions::assert_eq;
+use tonic::Request;
+
+const INSTANCE_NAME: &str = "foo_instance_name";
+const CAS_STORE_NAME: &str = "main_cas";
+const URI1: &str = "https://example.com/file1.tar.gz";
+const URI2: &str = "https://mirror.example.com/file1.tar.gz";
+const CONTENT1: &str = "content1";
+
+/// Serves fixed content by URI and counts the fetches.
+#[derive(Default)]
+struct MockAssetFetcher {
+    content: HashMap<&'static str, Bytes>,
+    fetches: AtomicUsize,
+}
+
+#[async_trait]
+impl AssetFetcher for MockAssetFetcher {
+    async fn fetch(
+        &self,
+        uri: &str,
+        _headers: &[(String, String)],
+        _max_size: u64,
+    ) -> Result<Bytes, Error> {
+        self.fetches.fetch_add(1, Ordering::Relaxed);
+        self.content
+            .get(uri)
+            .cloned()
+            .ok_or_else(|| make_err!(Code::NotFound, "{uri} not found"))
+    }
+}
+
+async fn make_store_manager() -> Result<Arc<StoreManager>, Error> {
+    let store_manager = Arc::new(StoreManager::new());
+    for store_name in [CAS_STORE_NAME, "main_asset"] {
+        store_manager.add_store(
+            store_name,
+            store_factory(
+                &StoreSpec::memory(MemorySpec::default()),
+                &store_manager,
+                None,
+            )
+            .await?,
+        );
+    }
+    Ok(store_manager)
+}
+
+fn make_server(
+    store_manager: &StoreManager,
+    fetcher: Arc<MockAssetFetcher>,
+    allow_push: bool,
+) -> Result<RemoteAssetServer, Error> {
+    RemoteAssetServer::new_with_fetcher(
+        &hashmap! {
+            INSTANCE_NAME.to_string() => RemoteAssetConfig {
+                cas_store: CAS_STORE_NAME.to_string(),
+                asset_store: "main_asset".to_string(),
+                cache_ttl_s: 0,
+                fetch_timeout_s: 0,
+                max_fetch_size: 0,
+                allow_push,
+                access: InstanceAccessConfig::default(),
+            }
+        },
+        store_manager,
+        fetcher,
+    )
+}
+
+fn make_fetcher() -> Arc<MockAssetFetcher> {
+    Arc::new(MockAssetFetcher {
+        content: HashMap::from([(URI2, Bytes::from_static(CONTENT1.as_bytes()))]),
+        ..Default::default()
+    })
+}
+
+fn sha256_digest(data: &str) -> DigestInfo {
+    let mut hasher = DigestHasherFunc::Sha256.hasher();
+    hasher.update(data.as_bytes());
+    hasher.finalize_digest()
+}
+
+fn fetch_request(uris: &[&str], qualifiers: Vec<Qualifier>) -> Request<FetchBlobRequest> {
+    Request::new(FetchBlobRequest {
+        instance_name: INSTANCE_NAME.to_string(),
+        uris: uris.iter().map(ToString::to_string).collect(),
+        qualifiers,
+        ..Default::default()
+    })
+}
+
+fn sri_qualifier(data: &str) -> Qualifier {
+    Qualifier {
+        name: "checksum.sri".to_string(),
+        value: format!(
+            "sha256-{}",
+            STANDARD.encode(&sha256_digest(data).packed_hash()[..])
+        ),
+    }
+}
+
+#[nativelink_test]
+async fn fetch_blob_downloads_into_cas_and_caches_uri() -> Result<(), Box<dyn std::error::Error>> {
+    let store_manager = make_store_manager().await?;
+    let fetcher = make_fetcher();
+    let server = make_server(&store_manager, fetcher.clone(), false)?;
+    let digest = sha256_digest(CONTENT1);
+
+    // The first URI fails, so the content is fetched from the mirror.
+    let response = server
+        .fetch_blob(fetch_request(&[URI1, URI2], Vec::new()))
+        .await?
+        .into_inner();
+    assert_eq!(response.status, None);
+    assert_eq!(response.uri, URI2);
+    assert_eq!(response.blob_digest, Some(digest.into()));
+    assert_eq!(
+        store_manager
+            .get_store(CAS_STORE_NAME)
+            .unwrap()
+            .get_part_unchunked(digest, 0, None)
+            .await?,
+        Bytes::from_static(CONTENT1.as_bytes())
+    );
+    assert_eq!(fetcher.fetches.load(Ordering::Relaxed), 2);
+
+    // The mapping of the mirror is cached.
+    let response = server
+        .fetch_blob(fetch_request(&[URI2], Vec::new()))
+        .await?
+        .into_inner();
+    assert_eq!(response.blob_digest, Some(digest.into()));
+    assert_eq!(fetcher.fetches.load(Ordering::Relaxed), 2);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn fetch_blob_verifies_checksum() -> Result<(), Box<dyn std::error::Error>> {
+    let store_manager = make_store_manager().await?;
+    let server = make_server(&store_manager, make_fetcher(), false)?;
+
+    let response = server
+        .fetch_blob(fetch_request(&[URI2], vec![sri_qualifier("other content")]))
+        .await?
+        .into_inner();
+    assert_eq!(
+        response.status.map(|status| status.code),
+        Some(Code::Aborted as i32)
+    );
+    assert_eq!(response.blob_digest, None);
+
+    let response = server
+        .fetch_blob(fetch_request(&[URI2], vec![sri_qualifier(CONTENT1)]))
+        .await?
+        .into_inner();
+    assert_eq!(response.status, None);
+    assert_eq!(response.blob_digest, Some(sha256_digest(CONTENT1).into()));
+    Ok(())
+}
+
+#[nativelink_test]
+async fn fetch_blob_reports_errors() -> Result<(), Box<dyn std::error::Error>> {
+    let store_manager = make_store_manager().await?;
+    let server = make_server(&store_manager, make_fetcher(), false)?;
+
+    let response = server
+        .fetch_blob(fetch_request(&[URI1], Vec::new()))
+        .await?
+        .into_inner();
+    assert_eq!(
+        response.status.map(|status| status.code),
+        Some(Code::NotFound as i32)
+    );
+    assert_eq!(response.uri, URI1);
+
+    let unsupported_qualifier = Qualifier {
+        name: "vcs.branch".to_string(),
+        value: "main".to_string(),
+    };
+    let status = server
+        .fetch_blob(fetch_request(&[URI2], vec![unsupported_qualifier]))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn pushed_blob_is_fetched_without_download() -> Result<(), Box<dyn std::error::Error>> {
+    let store_manager = make_store_manager().await?;
+    let fetcher = make_fetcher();
+    let server = make_server(&store_manager, fetcher.clone(), true)?;
+    let digest = sha256_digest(CONTENT1);
+    store_manager
+        .get_store(CAS_STORE_NAME)
+        .unwrap()
+        .update_oneshot(digest, Bytes::from_static(CONTENT1.as_bytes()))
+        .await?;
+
+    server
+        .push_blob(Request::new(PushBlobRequest {
+            instance_name: INSTANCE_NAME.to_string(),
+            uris: vec![URI1.to_string()],
+            blob_digest: Some(digest.into()),
+            ..Default::default()
+        }))
+        .await?;
+    let response = server
+        .fetch_blob(fetch_request(&[URI1], Vec::new()))
+        .await?
+        .into_inner();
+    assert_eq!(response.blob_digest, Some(digest.into()));
+    assert_eq!(fetcher.fetches.load(Ordering::Relaxed), 0);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn push_is_denied_unless_allowed() -> Result<(), Box<dyn std::error::Error>> {
+    let store_manager = make_store_manager().await?;
+    let server = make_server(&store_manager, make_fetcher(), false)?;
+
+    let status = server
+        .push_blob(Request::new(PushBlobRequest {
+            instance_name: INSTANCE_NAME.to_string(),
+            uris: vec![URI1.to_string()],
+            blob_digest: Some(sha256_digest(CONTENT1).into()),
+            ..Default::default()
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::PermissionDenied);
+    Ok(())
+}
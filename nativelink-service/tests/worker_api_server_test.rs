@@ -30,7 +30,8 @@ use nativelink_proto::build::bazel::remote::execution::v2::{
 };
 use nativelink_proto::com::github::trace_machina::nativelink::remote_execution::worker_api_server::WorkerApi;
 use nativelink_proto::com::github::trace_machina::nativelink::remote_execution::{
-    execute_result, update_for_worker, ExecuteResult, KeepAliveRequest, SupportedProperties,
+    execute_result, update_for_worker, ExecuteResult, GoingAwayRequest, KeepAliveRequest,
+    SupportedProperties,
 };
 use nativelink_proto::google::rpc::Status as ProtoStatus;
 use nativelink_scheduler::api_worker_scheduler::ApiWorkerScheduler;
@@ -151,6 +152,7 @@ async fn setup_api_server(worker_timeout: u64, now_fn: NowFn) -> Result<TestCont
         WorkerAllocationStrategy::default(),
         tasks_or_worker_change_notify,
         worker_timeout,
+        None,
     );
 
     let mut schedulers: HashMap<String, Arc<dyn WorkerScheduler>> = HashMap::new();
@@ -286,6 +288,7 @@ pub async fn server_does_not_timeout_if_keep_alive_test() -> Result<(), Box<dyn
             .worker_api_server
             .keep_alive(Request::new(KeepAliveRequest {
                 worker_id: test_context.worker_id.to_string(),
+                cached_input_roots: vec![],
             }))
             .await
             .err_tip(|| "Error sending keep alive")?;
@@ -368,6 +371,50 @@ pub async fn going_away_removes_worker_test() -> Result<(), Box<dyn std::error::
     Ok(())
 }
 
+#[nativelink_test]
+pub async fn going_away_with_drain_keeps_worker_test() -> Result<(), Box<dyn std::error::Error>> {
+    let test_context = setup_api_server(BASE_WORKER_TIMEOUT_S, Box::new(static_now_fn)).await?;
+
+    test_context
+        .worker_api_server
+        .going_away(Request::new(GoingAwayRequest {
+            worker_id: test_context.worker_id.to_string(),
+            drain: true,
+        }))
+        .await?;
+
+    // The worker stays connected to finish its actions, but gets no new ones.
+    let worker_exists = test_context
+        .scheduler
+        .contains_worker_for_test(&test_context.worker_id)
+        .await;
+    assert!(worker_exists, "Expected worker to exist in worker map");
+    let worker_status = test_context
+        .scheduler
+        .get_worker_status(&test_context.worker_id)
+        .await?;
+    assert!(worker_status.is_draining, "Expected worker to be draining");
+    assert!(worker_status.is_drained(), "Expected worker to be drained");
+
+    test_context
+        .worker_api_server
+        .going_away(Request::new(GoingAwayRequest {
+            worker_id: test_context.worker_id.to_string(),
+            drain: false,
+        }))
+        .await?;
+    let worker_exists = test_context
+        .scheduler
+        .contains_worker_for_test(&test_context.worker_id)
+        .await;
+    assert!(
+        !worker_exists,
+        "Expected worker to be removed from worker map"
+    );
+
+    Ok(())
+}
+
 fn make_system_time(time: u64) -> SystemTime {
     UNIX_EPOCH.checked_add(Duration::from_secs(time)).unwrap()
 }
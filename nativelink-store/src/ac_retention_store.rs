@@ -0,0 +1,413 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::mem;
+use std::ops::Bound;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::{self, StreamExt};
+use nativelink_config::stores::{AcRetentionSpec, StoreSpec};
+use nativelink_error::{make_err, make_input_err, Code, Error, ResultExt};
+use nativelink_metric::MetricsComponent;
+use nativelink_proto::build::bazel::remote::execution::v2::ActionResult as ProtoActionResult;
+use nativelink_util::background_spawn;
+use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
+use nativelink_util::health_utils::{HealthStatus, HealthStatusIndicator};
+use nativelink_util::instant_wrapper::InstantWrapper;
+use nativelink_util::store_trait::{
+    Store, StoreDriver, StoreKey, StoreLike, StoreOptimizations, UploadSizeInfo,
+};
+use parking_lot::Mutex;
+use prost::Message;
+use tokio::time::sleep;
+use tracing::{event, Level};
+
+/// ActionResults larger than this are not read into memory to check their
+/// age. Matches the limit of `ac_utils`.
+const MAX_ACTION_RESULT_SIZE: u64 = 10 << 20; // 10mb.
+
+/// Number of ActionResults that are read at the same time during a sweep.
+const SIMULTANEOUS_SWEEP_READS: usize = 16;
+
+/// The outcome of an `AcRetentionStore::sweep()`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AcRetentionSweepReport {
+    /// Number of keys that were listed in the backend.
+    pub scanned_keys: u64,
+    /// Number of ActionResults removed because they were too old.
+    pub expired_by_age: u64,
+    /// Number of ActionResults removed because they were not used for
+    /// `max_sweeps_without_hit` sweeps.
+    pub expired_without_hit: u64,
+}
+
+/// Why an ActionResult was removed by a sweep.
+enum Expiry {
+    Age,
+    WithoutHit,
+}
+
+#[derive(MetricsComponent)]
+pub struct AcRetentionStore<NowFn> {
+    #[metric(group = "backend")]
+    backend: Store,
+    now_fn: NowFn,
+    #[metric(help = "Maximum age in seconds of an ActionResult, zero if unlimited")]
+    max_age_s: u64,
+    #[metric(help = "Sweeps without a hit after which an ActionResult is removed")]
+    max_sweeps_without_hit: u32,
+    /// Number of sweeps since each key was last read or written. Keys that
+    /// are used are removed, so the next sweep counts them from zero.
+    sweeps_without_hit: Mutex<HashMap<StoreKey<'static>, u32>>,
+    sweep_lock: tokio::sync::Mutex<()>,
+    #[metric(help = "Number of ActionResults removed because they were too old")]
+    expired_by_age_count: AtomicU64,
+    #[metric(help = "Number of ActionResults removed because they were not used")]
+    expired_without_hit_count: AtomicU64,
+}
+
+impl<I, NowFn> AcRetentionStore<NowFn>
+where
+    I: InstantWrapper,
+    NowFn: Fn() -> I + Send + Sync + Unpin + 'static,
+{
+    pub fn new(spec: &AcRetentionSpec, backend: Store, now_fn: NowFn) -> Arc<Self> {
+        Arc::new(Self {
+            backend,
+            now_fn,
+            max_age_s: spec.max_age_s,
+            max_sweeps_without_hit: spec.max_sweeps_without_hit,
+            sweeps_without_hit: Mutex::new(HashMap::new()),
+            sweep_lock: tokio::sync::Mutex::new(()),
+            expired_by_age_count: AtomicU64::new(0),
+            expired_without_hit_count: AtomicU64::new(0),
+        })
+    }
+
+    /// Runs `sweep()` every `interval` until the store is dropped.
+    pub fn start_periodic_sweep(self: &Arc<Self>, interval: Duration) {
+        let weak_store = Arc::downgrade(self);
+        background_spawn!("ac_retention_store_sweep", async move {
+            loop {
+                sleep(interval).await;
+                let Some(store) = weak_store.upgrade() else {
+                    return;
+                };
+                match store.sweep().await {
+                    Ok(report) => event!(
+                        Level::INFO,
+                        ?report,
+                        "Finished sweep of AC in retention store"
+                    ),
+                    Err(err) => event!(Level::WARN, ?err, "Failed to sweep AC in retention store"),
+                }
+            }
+        });
+    }
+
+    /// Removes every ActionResult in the backend that is older than
+    /// `max_age_s` or that was not used during the last
+    /// `max_sweeps_without_hit` sweeps.
+    pub async fn sweep(&self) -> Result<AcRetentionSweepReport, Error> {
+        let Ok(_sweep_guard) = self.sweep_lock.try_lock() else {
+            return Err(make_err!(
+                Code::FailedPrecondition,
+                "A sweep is already running in AcRetentionStore"
+            ));
+        };
+        let mut report = AcRetentionSweepReport::default();
+
+        let mut keys = Vec::new();
+        self.backend
+            .list(.., |key| {
+                keys.push(key.borrow().into_owned());
+                true
+            })
+            .await
+            .err_tip(|| "Failed to list backend in AcRetentionStore::sweep")?;
+        report.scanned_keys = keys.len() as u64;
+
+        // Count this sweep for every key and forget the keys that are gone
+        // from the backend.
+        let keys: Vec<(StoreKey<'static>, bool)> = {
+            let mut sweeps_without_hit = self.sweeps_without_hit.lock();
+            let previous = mem::take(&mut *sweeps_without_hit);
+            keys.into_iter()
+                .map(|key| {
+                    let sweeps = previous.get(&key).map_or(0, |sweeps| sweeps + 1);
+                    sweeps_without_hit.insert(key.clone(), sweeps);
+                    let idle =
+                        self.max_sweeps_without_hit != 0 && sweeps >= self.max_sweeps_without_hit;
+                    (key, idle)
+                })
+                .collect()
+        };
+
+        let mut checks = stream::iter(keys)
+            .map(|(key, idle)| async move { self.sweep_key(key, idle).await })
+            .buffer_unordered(SIMULTANEOUS_SWEEP_READS);
+        while let Some(result) = checks.next().await {
+            match result {
+                Ok(Some(Expiry::Age)) => report.expired_by_age += 1,
+                Ok(Some(Expiry::WithoutHit)) => report.expired_without_hit += 1,
+                Ok(None) => {}
+                // The ActionResult was evicted after it was listed.
+                Err(err) if err.code == Code::NotFound => {}
+                Err(err) => {
+                    return Err(err).err_tip(|| "Failed to read backend in AcRetentionStore::sweep")
+                }
+            }
+        }
+        self.expired_by_age_count
+            .fetch_add(report.expired_by_age, Ordering::Relaxed);
+        self.expired_without_hit_count
+            .fetch_add(report.expired_without_hit, Ordering::Relaxed);
+        Ok(report)
+    }
+
+    async fn sweep_key(&self, key: StoreKey<'static>, idle: bool) -> Result<Option<Expiry>, Error> {
+        let expiry = if idle {
+            Expiry::WithoutHit
+        } else if self.max_age_s != 0 {
+            let data = self
+                .backend
+                .get_part_unchunked(key.borrow(), 0, Some(MAX_ACTION_RESULT_SIZE))
+                .await?;
+            if !self.is_expired(&data) {
+                return Ok(None);
+            }
+            Expiry::Age
+        } else {
+            return Ok(None);
+        };
+        let removed = self
+            .backend
+            .remove(key.borrow())
+            .await
+            .err_tip(|| "Failed to remove from backend in AcRetentionStore::sweep")?;
+        self.sweeps_without_hit.lock().remove(&key);
+        Ok(removed.then_some(expiry))
+    }
+
+    /// Whether `data` is an ActionResult that completed more than
+    /// `max_age_s` ago. Data that is not an ActionResult or has no
+    /// completion timestamp never expires.
+    fn is_expired(&self, data: &Bytes) -> bool {
+        if self.max_age_s == 0 {
+            return false;
+        }
+        let Ok(action_result) = ProtoActionResult::decode(data.clone()) else {
+            return false;
+        };
+        let Some(completed_s) = action_result
+            .execution_metadata
+            .and_then(|metadata| metadata.worker_completed_timestamp)
+            .and_then(|timestamp| u64::try_from(timestamp.seconds).ok())
+            .filter(|completed_s| *completed_s != 0)
+        else {
+            return false;
+        };
+        (self.now_fn)().unix_timestamp().saturating_sub(completed_s) > self.max_age_s
+    }
+
+    fn record_hit(&self, key: &StoreKey<'_>) {
+        if self.max_sweeps_without_hit != 0 {
+            self.sweeps_without_hit
+                .lock()
+                .remove(&key.borrow().into_owned());
+        }
+    }
+
+    /// Reads the ActionResult at `key`, removing it from the backend and
+    /// returning not found if it expired.
+    async fn get_unexpired(&self, key: StoreKey<'_>) -> Result<Bytes, Error> {
+        let data = self
+            .backend
+            .get_part_unchunked(key.borrow(), 0, Some(MAX_ACTION_RESULT_SIZE))
+            .await?;
+        if !self.is_expired(&data) {
+            self.record_hit(&key);
+            return Ok(data);
+        }
+        match self.backend.remove(key.borrow()).await {
+            Ok(true) => {
+                self.expired_by_age_count.fetch_add(1, Ordering::Relaxed);
+            }
+            Ok(false) => {}
+            Err(err) => event!(
+                Level::WARN,
+                ?key,
+                ?err,
+                "Failed to remove expired ActionResult in AcRetentionStore"
+            ),
+        }
+        self.sweeps_without_hit
+            .lock()
+            .remove(&key.borrow().into_owned());
+        Err(make_err!(
+            Code::NotFound,
+            "ActionResult expired in AcRetentionStore: {key:?}"
+        ))
+    }
+}
+
+#[async_trait]
+impl<I, NowFn> StoreDriver for AcRetentionStore<NowFn>
+where
+    I: InstantWrapper,
+    NowFn: Fn() -> I + Send + Sync + Unpin + 'static,
+{
+    async fn has_with_results(
+        self: Pin<&Self>,
+        keys: &[StoreKey<'_>],
+        results: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        self.backend
+            .has_with_results(keys, results)
+            .await
+            .err_tip(|| "In AcRetentionStore::has_with_results")?;
+        for (key, result) in keys.iter().zip(results.iter_mut()) {
+            if result.is_none() {
+                continue;
+            }
+            if self.max_age_s == 0 {
+                self.record_hit(key);
+                continue;
+            }
+            match self.get_unexpired(key.borrow()).await {
+                Ok(_) => {}
+                Err(err) if err.code == Code::NotFound => *result = None,
+                Err(err) => return Err(err).err_tip(|| "In AcRetentionStore::has_with_results"),
+            }
+        }
+        Ok(())
+    }
+
+    async fn list(
+        self: Pin<&Self>,
+        range: (Bound<StoreKey<'_>>, Bound<StoreKey<'_>>),
+        handler: &mut (dyn for<'a> FnMut(&'a StoreKey) -> bool + Send + Sync + '_),
+    ) -> Result<u64, Error> {
+        self.backend
+            .as_store_driver_pin()
+            .list(range, handler)
+            .await
+    }
+
+    fn optimized_for(&self, optimization: StoreOptimizations) -> bool {
+        // Other optimizations would bypass recording the hits.
+        optimization == StoreOptimizations::OrderedList && self.backend.optimized_for(optimization)
+    }
+
+    async fn remove(self: Pin<&Self>, key: StoreKey<'_>) -> Result<bool, Error> {
+        self.sweeps_without_hit
+            .lock()
+            .remove(&key.borrow().into_owned());
+        self.backend.remove(key).await
+    }
+
+    async fn update(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        reader: DropCloserReadHalf,
+        upload_size: UploadSizeInfo,
+    ) -> Result<(), Error> {
+        self.backend
+            .update(key.borrow(), reader, upload_size)
+            .await
+            .err_tip(|| "In AcRetentionStore::update")?;
+        self.record_hit(&key);
+        Ok(())
+    }
+
+    async fn get_part(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        writer: &mut DropCloserWriteHalf,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<(), Error> {
+        let data = self
+            .get_unexpired(key)
+            .await
+            .err_tip(|| "In AcRetentionStore::get_part")?;
+        let offset = usize::try_from(offset)
+            .err_tip(|| "Could not convert offset to usize")?
+            .min(data.len());
+        let end = match length {
+            Some(length) => offset
+                .saturating_add(
+                    usize::try_from(length).err_tip(|| "Could not convert length to usize")?,
+                )
+                .min(data.len()),
+            None => data.len(),
+        };
+        if end > offset {
+            writer
+                .send(data.slice(offset..end))
+                .await
+                .err_tip(|| "Failed to write data in AcRetentionStore::get_part")?;
+        }
+        writer
+            .send_eof()
+            .err_tip(|| "Failed to write EOF in AcRetentionStore::get_part")
+    }
+
+    fn inner_store(&self, _key: Option<StoreKey>) -> &dyn StoreDriver {
+        self
+    }
+
+    fn as_any<'a>(&'a self) -> &'a (dyn std::any::Any + Sync + Send + 'static) {
+        self
+    }
+
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn std::any::Any + Sync + Send + 'static> {
+        self
+    }
+
+    async fn reconfigure(&self, spec: &StoreSpec, check_only: bool) -> Result<(), Error> {
+        let StoreSpec::ac_retention(spec) = spec else {
+            return Err(make_input_err!(
+                "AcRetentionStore can only be reconfigured with an ac_retention spec"
+            ));
+        };
+        self.backend
+            .as_store_driver()
+            .reconfigure(&spec.backend, check_only)
+            .await
+            .err_tip(|| "In AcRetentionStore::reconfigure for backend")
+    }
+}
+
+#[async_trait]
+impl<I, NowFn> HealthStatusIndicator for AcRetentionStore<NowFn>
+where
+    I: InstantWrapper,
+    NowFn: Fn() -> I + Send + Sync + Unpin + 'static,
+{
+    fn get_name(&self) -> &'static str {
+        "AcRetentionStore"
+    }
+
+    async fn check_health(&self, namespace: Cow<'static, str>) -> HealthStatus {
+        StoreDriver::check_health(Pin::new(self), namespace).await
+    }
+}
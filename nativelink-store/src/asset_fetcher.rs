@@ -0,0 +1,164 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Downloads the content of URIs for the Remote Asset API.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use hyper::body::HttpBody;
+use hyper::header::{CONTENT_LENGTH, LOCATION};
+use hyper::{Body, Request, StatusCode, Uri};
+use nativelink_error::{make_err, make_input_err, Code, Error};
+
+use crate::http_store::HttpClient;
+
+/// Number of redirects that are followed before a fetch fails.
+const MAX_REDIRECTS: usize = 10;
+
+/// Downloads the content of a URI.
+#[async_trait]
+pub trait AssetFetcher: Send + Sync + 'static {
+    /// Downloads `uri` and returns its content. `headers` are sent with the
+    /// request. Fails with `ResourceExhausted` if the content is larger
+    /// than `max_size` bytes.
+    async fn fetch(
+        &self,
+        uri: &str,
+        headers: &[(String, String)],
+        max_size: u64,
+    ) -> Result<Bytes, Error>;
+}
+
+/// Fetches `http://` and `https://` URIs and follows redirects.
+pub struct HttpAssetFetcher {
+    http_client: Arc<dyn HttpClient>,
+}
+
+impl HttpAssetFetcher {
+    pub fn new() -> Arc<Self> {
+        let connector = hyper_rustls::HttpsConnectorBuilder::new()
+            .with_webpki_roots()
+            .https_or_http()
+            .enable_http1()
+            .enable_http2()
+            .build();
+        Self::new_with_client(Arc::new(
+            hyper::Client::builder().build::<_, Body>(connector),
+        ))
+    }
+
+    pub fn new_with_client(http_client: Arc<dyn HttpClient>) -> Arc<Self> {
+        Arc::new(Self { http_client })
+    }
+}
+
+/// Resolves the `Location` of a redirect from `uri`.
+fn resolve_redirect(uri: &Uri, location: &str) -> Result<Uri, Error> {
+    let location = location
+        .parse::<Uri>()
+        .map_err(|e| make_err!(Code::Unavailable, "Invalid redirect to {location}: {e:?}"))?;
+    if location.scheme().is_some() {
+        return Ok(location);
+    }
+    let mut parts = location.into_parts();
+    parts.scheme = uri.scheme().cloned();
+    parts.authority = uri.authority().cloned();
+    Uri::from_parts(parts).map_err(|e| make_err!(Code::Unavailable, "Invalid redirect: {e:?}"))
+}
+
+fn error_for_status(uri: &Uri, status: StatusCode) -> Error {
+    let code = match status {
+        StatusCode::NOT_FOUND | StatusCode::GONE => Code::NotFound,
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Code::PermissionDenied,
+        StatusCode::TOO_MANY_REQUESTS => Code::ResourceExhausted,
+        _ => Code::Unavailable,
+    };
+    make_err!(code, "Fetching {uri} failed with status {status}")
+}
+
+#[async_trait]
+impl AssetFetcher for HttpAssetFetcher {
+    async fn fetch(
+        &self,
+        uri: &str,
+        headers: &[(String, String)],
+        max_size: u64,
+    ) -> Result<Bytes, Error> {
+        let mut uri = uri
+            .parse::<Uri>()
+            .map_err(|e| make_input_err!("Invalid URI {uri}: {e:?}"))?;
+        if !matches!(uri.scheme_str(), Some("http" | "https")) {
+            return Err(make_input_err!(
+                "Only http:// and https:// URIs can be fetched, got {uri}"
+            ));
+        }
+        for _ in 0..=MAX_REDIRECTS {
+            let mut request = Request::get(&uri);
+            for (name, value) in headers {
+                request = request.header(name, value);
+            }
+            let request = request
+                .body(Body::empty())
+                .map_err(|e| make_input_err!("Could not build request for {uri}: {e:?}"))?;
+            let response = self.http_client.send(request).await?;
+            let status = response.status();
+            if status.is_redirection() {
+                let location = response
+                    .headers()
+                    .get(LOCATION)
+                    .and_then(|v| v.to_str().ok())
+                    .ok_or_else(|| {
+                        make_err!(Code::Unavailable, "Redirect from {uri} has no location")
+                    })?;
+                uri = resolve_redirect(&uri, location)?;
+                continue;
+            }
+            if !status.is_success() {
+                return Err(error_for_status(&uri, status));
+            }
+            let content_length = response
+                .headers()
+                .get(CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<u64>().ok());
+            if content_length.is_some_and(|len| len > max_size) {
+                return Err(make_err!(
+                    Code::ResourceExhausted,
+                    "Content of {uri} is larger than {max_size} bytes"
+                ));
+            }
+            let mut body = response.into_body();
+            let mut data = BytesMut::new();
+            while let Some(chunk) = body.data().await {
+                let chunk = chunk.map_err(|e| {
+                    make_err!(Code::Unavailable, "Failed to read body of {uri}: {e:?}")
+                })?;
+                if (data.len() + chunk.len()) as u64 > max_size {
+                    return Err(make_err!(
+                        Code::ResourceExhausted,
+                        "Content of {uri} is larger than {max_size} bytes"
+                    ));
+                }
+                data.extend_from_slice(&chunk);
+            }
+            return Ok(data.freeze());
+        }
+        Err(make_err!(
+            Code::Unavailable,
+            "Fetching {uri} exceeded {MAX_REDIRECTS} redirects"
+        ))
+    }
+}
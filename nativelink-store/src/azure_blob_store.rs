@@ -0,0 +1,737 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::borrow::Cow;
+use std::fmt::Write;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
+use base64::Engine;
+use bytes::Bytes;
+use futures::stream::{unfold, FuturesUnordered};
+use futures::TryStreamExt;
+use hyper::body::HttpBody;
+use hyper::client::connect::HttpConnector;
+use hyper::header::{AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE, LAST_MODIFIED};
+use hyper::http::request::Builder as RequestBuilder;
+use hyper::{Body, Request, Response, StatusCode};
+use hyper_rustls::HttpsConnector;
+use nativelink_config::stores::{AzureBlobSpec, StoreSpec};
+use nativelink_error::{make_err, make_input_err, Code, Error, ResultExt};
+use nativelink_metric::MetricsComponent;
+use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
+use nativelink_util::health_utils::{HealthStatus, HealthStatusIndicator};
+use nativelink_util::instant_wrapper::InstantWrapper;
+use nativelink_util::retry::{Retrier, RetryResult};
+use nativelink_util::store_trait::{StoreDriver, StoreKey, UploadSizeInfo};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use rand::rngs::OsRng;
+use rand::Rng;
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use tokio::time::sleep;
+
+use crate::cas_utils::is_zero_digest;
+
+// Version of the blob service REST API. Bearer tokens need 2017-11-09 or later.
+const API_VERSION: &str = "2021-08-06";
+
+// Default size of each staged block.
+// Note: If you change this, adjust the docs in the config.
+const DEFAULT_BLOCK_SIZE: usize = 8 * 1024 * 1024; // 8MiB.
+
+// Largest block the service accepts.
+const MAX_BLOCK_SIZE: usize = 4000 * 1024 * 1024; // 4000MiB.
+
+// Largest number of blocks in a committed block list.
+const MAX_BLOCKS: u64 = 50_000;
+
+// Token endpoint of the Azure instance metadata service (ie: managed identity).
+const IMDS_TOKEN_URL: &str = "http://169.254.169.254/metadata/identity/oauth2/token?api-version=2018-02-01&resource=https%3A%2F%2Fstorage.azure.com%2F";
+
+// Access tokens are refreshed this long before they expire.
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+// Blob names keep their `/` so a `key_prefix` shows up as virtual directories.
+const BLOB_NAME_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~')
+    .remove(b'/');
+
+// Block ids are sent as a query value.
+const QUERY_VALUE_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Sends the HTTP requests of an [`AzureBlobStore`].
+#[async_trait]
+pub trait AzureHttpClient: Send + Sync + 'static {
+    async fn send(&self, request: Request<Body>) -> Result<Response<Body>, Error>;
+}
+
+#[async_trait]
+impl AzureHttpClient for hyper::Client<HttpsConnector<HttpConnector>> {
+    async fn send(&self, request: Request<Body>) -> Result<Response<Body>, Error> {
+        self.request(request).await.map_err(|e| {
+            make_err!(
+                Code::Unavailable,
+                "Failed to send request to Azure Blob Storage: {e:?}"
+            )
+        })
+    }
+}
+
+/// Provides the OAuth2 access tokens sent with every request when no SAS
+/// token is configured.
+#[async_trait]
+pub trait AzureTokenProvider: Send + Sync + 'static {
+    async fn access_token(&self) -> Result<String, Error>;
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    // The metadata service sends this as a string.
+    expires_in: serde_json::Value,
+}
+
+/// An [`AzureTokenProvider`] asking the instance metadata service for tokens
+/// of the managed identity of the machine or pod. Tokens are cached until
+/// shortly before they expire.
+pub struct ManagedIdentityTokenProvider {
+    http_client: Arc<dyn AzureHttpClient>,
+    token_url: String,
+    cached_token: Mutex<Option<(String, SystemTime)>>,
+}
+
+impl ManagedIdentityTokenProvider {
+    pub fn new(spec: &AzureBlobSpec, http_client: Arc<dyn AzureHttpClient>) -> Self {
+        let token_url = match &spec.managed_identity_client_id {
+            Some(client_id) => format!(
+                "{IMDS_TOKEN_URL}&client_id={}",
+                utf8_percent_encode(client_id, QUERY_VALUE_ENCODE_SET)
+            ),
+            None => IMDS_TOKEN_URL.to_string(),
+        };
+        Self {
+            http_client,
+            token_url,
+            cached_token: Mutex::new(None),
+        }
+    }
+
+    async fn fetch_token(&self) -> Result<(String, Duration), Error> {
+        let request = Request::get(&self.token_url)
+            .header("Metadata", "true")
+            .body(Body::empty())
+            .map_err(|e| make_err!(Code::Internal, "Could not build Azure token request: {e:?}"))?;
+        let response = self
+            .http_client
+            .send(request)
+            .await
+            .err_tip(|| "While requesting Azure managed identity token")?;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body())
+            .await
+            .map_err(|e| {
+                make_err!(
+                    Code::Unavailable,
+                    "Could not read Azure token response: {e:?}"
+                )
+            })?;
+        if !status.is_success() {
+            return Err(make_err!(
+                Code::Unavailable,
+                "Failed to get Azure managed identity token ({status}): {}",
+                String::from_utf8_lossy(&body)
+            ));
+        }
+        let response: TokenResponse = serde_json::from_slice(&body).map_err(|e| {
+            make_err!(
+                Code::Unavailable,
+                "Could not parse Azure token response: {e}"
+            )
+        })?;
+        let expires_in = response
+            .expires_in
+            .as_u64()
+            .or_else(|| response.expires_in.as_str()?.parse().ok())
+            .err_tip(|| {
+                format!(
+                    "Invalid expires_in {:?} in Azure token response",
+                    response.expires_in
+                )
+            })?;
+        Ok((response.access_token, Duration::from_secs(expires_in)))
+    }
+}
+
+#[async_trait]
+impl AzureTokenProvider for ManagedIdentityTokenProvider {
+    async fn access_token(&self) -> Result<String, Error> {
+        let mut cached_token = self.cached_token.lock().await;
+        if let Some((token, expires_at)) = &*cached_token {
+            if SystemTime::now() + TOKEN_REFRESH_MARGIN < *expires_at {
+                return Ok(token.clone());
+            }
+        }
+        let (token, expires_in) = self.fetch_token().await?;
+        *cached_token = Some((token.clone(), SystemTime::now() + expires_in));
+        Ok(token)
+    }
+}
+
+enum AzureAuth {
+    /// Append a shared access signature to the query of every request.
+    SasToken(String),
+    /// Send an OAuth2 bearer token with every request.
+    BearerToken(Arc<dyn AzureTokenProvider>),
+}
+
+/// Server side errors and throttling are retried, everything else is final.
+fn is_retryable_status(status: StatusCode) -> bool {
+    status.is_server_error()
+        || status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::REQUEST_TIMEOUT
+}
+
+fn retry_for_status<T>(status: StatusCode, err: Error) -> RetryResult<T> {
+    if is_retryable_status(status) {
+        RetryResult::Retry(err)
+    } else {
+        RetryResult::Err(err)
+    }
+}
+
+/// Build an error for an unexpected response, including the error message
+/// the service sent in the body.
+async fn error_from_response(context: &str, response: Response<Body>) -> Error {
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body())
+        .await
+        .unwrap_or_default();
+    make_err!(
+        Code::Unavailable,
+        "{context} failed with status {status} in Azure Blob Storage: {}",
+        String::from_utf8_lossy(&body)
+    )
+}
+
+/// The id of block `index` of an upload. All ids of a blob must have the
+/// same length, and the random `upload_id` keeps concurrent uploads of the
+/// same blob from committing each other's blocks.
+fn block_id(upload_id: u32, index: u64) -> String {
+    BASE64_STANDARD.encode(format!("{upload_id:08x}{index:016x}"))
+}
+
+fn block_list_xml(block_ids: &[String]) -> String {
+    let mut xml = String::from(r#"<?xml version="1.0" encoding="utf-8"?><BlockList>"#);
+    for block_id in block_ids {
+        // Base64 never needs XML escaping.
+        let _ = write!(xml, "<Latest>{block_id}</Latest>");
+    }
+    xml.push_str("</BlockList>");
+    xml
+}
+
+#[derive(MetricsComponent)]
+pub struct AzureBlobStore<NowFn> {
+    http_client: Arc<dyn AzureHttpClient>,
+    auth: AzureAuth,
+    now_fn: NowFn,
+    #[metric(help = "The endpoint of the blob service")]
+    endpoint: String,
+    #[metric(help = "The container name for the Azure Blob store")]
+    container: String,
+    #[metric(help = "The key prefix for the Azure Blob store")]
+    key_prefix: String,
+    retrier: Retrier,
+    #[metric(help = "The number of seconds to consider a blob expired")]
+    consider_expired_after_s: i64,
+    #[metric(help = "The size of each staged block")]
+    block_size: usize,
+}
+
+impl<I, NowFn> AzureBlobStore<NowFn>
+where
+    I: InstantWrapper,
+    NowFn: Fn() -> I + Send + Sync + Unpin + 'static,
+{
+    pub fn new(spec: &AzureBlobSpec, now_fn: NowFn) -> Result<Arc<Self>, Error> {
+        let jitter_amt = spec.retry.jitter;
+        let jitter_fn = Arc::new(move |delay: Duration| {
+            if jitter_amt == 0. {
+                return delay;
+            }
+            let min = 1. - (jitter_amt / 2.);
+            let max = 1. + (jitter_amt / 2.);
+            delay.mul_f32(OsRng.gen_range(min..max))
+        });
+        let http_client = {
+            // The metadata service is only reachable over http, so the scheme
+            // of the endpoint is checked in `new_with_client_and_jitter` instead.
+            let connector_with_schemes = hyper_rustls::HttpsConnectorBuilder::new()
+                .with_webpki_roots()
+                .https_or_http();
+            let connector = if spec.disable_http2 {
+                connector_with_schemes.enable_http1().build()
+            } else {
+                connector_with_schemes.enable_http1().enable_http2().build()
+            };
+            Arc::new(hyper::Client::builder().build::<_, Body>(connector))
+        };
+        Self::new_with_client_and_jitter(spec, http_client, jitter_fn, now_fn)
+    }
+
+    pub fn new_with_client_and_jitter(
+        spec: &AzureBlobSpec,
+        http_client: Arc<dyn AzureHttpClient>,
+        jitter_fn: Arc<dyn Fn(Duration) -> Duration + Send + Sync>,
+        now_fn: NowFn,
+    ) -> Result<Arc<Self>, Error> {
+        if spec.container.is_empty() {
+            return Err(make_err!(
+                Code::InvalidArgument,
+                "container must be set in Azure Blob store"
+            ));
+        }
+        let endpoint = match &spec.endpoint {
+            Some(endpoint) => endpoint.trim_end_matches('/').to_string(),
+            None if spec.account_name.is_empty() => {
+                return Err(make_err!(
+                    Code::InvalidArgument,
+                    "account_name or endpoint must be set in Azure Blob store"
+                ));
+            }
+            None => format!("https://{}.blob.core.windows.net", spec.account_name),
+        };
+        if !endpoint.starts_with("https://") && !spec.insecure_allow_http {
+            return Err(make_err!(
+                Code::InvalidArgument,
+                "Azure Blob endpoint {endpoint} must use https unless insecure_allow_http is set"
+            ));
+        }
+        let block_size = if spec.block_size == 0 {
+            DEFAULT_BLOCK_SIZE
+        } else {
+            spec.block_size
+        };
+        if block_size > MAX_BLOCK_SIZE {
+            return Err(make_err!(
+                Code::InvalidArgument,
+                "block_size must be at most {MAX_BLOCK_SIZE} in Azure Blob store, got {block_size}"
+            ));
+        }
+        let auth = match &spec.sas_token {
+            Some(sas_token) => AzureAuth::SasToken(sas_token.trim_start_matches('?').to_string()),
+            None => AzureAuth::BearerToken(Arc::new(ManagedIdentityTokenProvider::new(
+                spec,
+                http_client.clone(),
+            ))),
+        };
+        Ok(Arc::new(Self {
+            http_client,
+            auth,
+            now_fn,
+            endpoint,
+            container: spec.container.to_string(),
+            key_prefix: spec.key_prefix.as_ref().unwrap_or(&String::new()).clone(),
+            retrier: Retrier::new(
+                Arc::new(|duration| Box::pin(sleep(duration))),
+                jitter_fn,
+                spec.retry.clone(),
+            ),
+            consider_expired_after_s: i64::from(spec.consider_expired_after_s),
+            block_size,
+        }))
+    }
+
+    fn make_blob_url(&self, key: &StoreKey<'_>) -> String {
+        let name = format!("{}{}", self.key_prefix, key.as_str());
+        format!(
+            "{}/{}/{}",
+            self.endpoint,
+            self.container,
+            utf8_percent_encode(&name, BLOB_NAME_ENCODE_SET)
+        )
+    }
+
+    /// Send an authorized request to the blob service.
+    async fn send(
+        &self,
+        method: &str,
+        url: &str,
+        configure: impl FnOnce(RequestBuilder) -> RequestBuilder,
+        body: Body,
+    ) -> Result<Response<Body>, Error> {
+        let request = match &self.auth {
+            AzureAuth::SasToken(sas_token) => {
+                let separator = if url.contains('?') { '&' } else { '?' };
+                Request::builder()
+                    .method(method)
+                    .uri(format!("{url}{separator}{sas_token}"))
+            }
+            AzureAuth::BearerToken(token_provider) => {
+                let token = token_provider
+                    .access_token()
+                    .await
+                    .err_tip(|| "While getting access token in Azure Blob store")?;
+                Request::builder()
+                    .method(method)
+                    .uri(url)
+                    .header(AUTHORIZATION, format!("Bearer {token}"))
+            }
+        };
+        let request = configure(request.header("x-ms-version", API_VERSION))
+            .body(body)
+            .map_err(|e| make_err!(Code::Internal, "Could not build Azure Blob request: {e:?}"))?;
+        self.http_client.send(request).await
+    }
+
+    async fn has(self: Pin<&Self>, digest: &StoreKey<'_>) -> Result<Option<u64>, Error> {
+        let url = &self.make_blob_url(digest);
+        self.retrier
+            .retry(unfold((), move |state| async move {
+                let response = match self.send("HEAD", url, |r| r, Body::empty()).await {
+                    Ok(response) => response,
+                    Err(err) => return Some((RetryResult::Retry(err), state)),
+                };
+                let status = response.status();
+                if status == StatusCode::NOT_FOUND {
+                    return Some((RetryResult::Ok(None), state));
+                }
+                if !status.is_success() {
+                    let err = error_from_response("Get blob properties", response).await;
+                    return Some((retry_for_status(status, err), state));
+                }
+                let headers = response.headers();
+                if self.consider_expired_after_s != 0 {
+                    let last_modified = headers
+                        .get(LAST_MODIFIED)
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(|v| httpdate::parse_http_date(v).ok())
+                        .and_then(|v| v.duration_since(UNIX_EPOCH).ok());
+                    if let Some(last_modified) = last_modified {
+                        let now_s = (self.now_fn)().unix_timestamp() as i64;
+                        if last_modified.as_secs() as i64 + self.consider_expired_after_s <= now_s {
+                            return Some((RetryResult::Ok(None), state));
+                        }
+                    }
+                }
+                let Some(length) = headers
+                    .get(CONTENT_LENGTH)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse::<u64>().ok())
+                else {
+                    return Some((
+                        RetryResult::Err(make_err!(
+                            Code::Unavailable,
+                            "Missing or invalid content length in Azure Blob Storage: {:?}",
+                            headers.get(CONTENT_LENGTH)
+                        )),
+                        state,
+                    ));
+                };
+                Some((RetryResult::Ok(Some(length)), state))
+            }))
+            .await
+    }
+
+    /// PUT `data` to `url`, retrying from memory.
+    async fn put(
+        self: Pin<&Self>,
+        context: &'static str,
+        url: &str,
+        content_type: &'static str,
+        is_blob: bool,
+        data: Bytes,
+    ) -> Result<(), Error> {
+        self.retrier
+            .retry(unfold(data, move |data| async move {
+                let configure = |request: RequestBuilder| {
+                    let request = request
+                        .header(CONTENT_TYPE, content_type)
+                        .header(CONTENT_LENGTH, data.len());
+                    if is_blob {
+                        request.header("x-ms-blob-type", "BlockBlob")
+                    } else {
+                        request
+                    }
+                };
+                let retry_result = match self
+                    .send("PUT", url, configure, Body::from(data.clone()))
+                    .await
+                {
+                    Ok(response) if response.status().is_success() => RetryResult::Ok(()),
+                    Ok(response) => {
+                        let status = response.status();
+                        let err = error_from_response(context, response).await;
+                        retry_for_status(status, err)
+                    }
+                    Err(err) => RetryResult::Retry(err),
+                };
+                Some((retry_result, data))
+            }))
+            .await
+    }
+}
+
+#[async_trait]
+impl<I, NowFn> StoreDriver for AzureBlobStore<NowFn>
+where
+    I: InstantWrapper,
+    NowFn: Fn() -> I + Send + Sync + Unpin + 'static,
+{
+    async fn has_with_results(
+        self: Pin<&Self>,
+        keys: &[StoreKey<'_>],
+        results: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        keys.iter()
+            .zip(results.iter_mut())
+            .map(|(key, result)| async move {
+                // We need to do a special pass to ensure our zero key exist.
+                if is_zero_digest(key.borrow()) {
+                    *result = Some(0);
+                    return Ok::<_, Error>(());
+                }
+                *result = self.has(key).await?;
+                Ok::<_, Error>(())
+            })
+            .collect::<FuturesUnordered<_>>()
+            .try_collect()
+            .await
+    }
+
+    async fn update(
+        self: Pin<&Self>,
+        digest: StoreKey<'_>,
+        mut reader: DropCloserReadHalf,
+        upload_size: UploadSizeInfo,
+    ) -> Result<(), Error> {
+        let blob_url = &self.make_blob_url(&digest);
+
+        // Blobs no larger than a single block are uploaded in one request.
+        if let UploadSizeInfo::ExactSize(sz) = upload_size {
+            if sz <= self.block_size as u64 {
+                let data = reader
+                    .consume(Some(
+                        usize::try_from(sz).err_tip(|| "Could not convert size to usize")?,
+                    ))
+                    .await
+                    .err_tip(|| "Failed to read data in AzureBlobStore::update")?;
+                return self
+                    .put("Put blob", blob_url, "application/octet-stream", true, data)
+                    .await;
+            }
+        }
+
+        // Stage the data as blocks, then commit them as the content of the
+        // blob. Blocks that are never committed (ie: because the upload
+        // failed) are garbage collected by the service after a week.
+        let upload_id = OsRng.gen::<u32>();
+        let mut block_ids = Vec::new();
+        loop {
+            let block = reader
+                .consume(Some(self.block_size))
+                .await
+                .err_tip(|| "Failed to read block in AzureBlobStore::update")?;
+            // Empty uploads commit an empty block list.
+            if block.is_empty() {
+                break;
+            }
+            let index = block_ids.len() as u64;
+            if index >= MAX_BLOCKS {
+                return Err(make_err!(
+                    Code::ResourceExhausted,
+                    "Upload exceeds {MAX_BLOCKS} blocks of {} bytes in Azure Blob store",
+                    self.block_size
+                ));
+            }
+            let block_len = block.len();
+            let block_id = block_id(upload_id, index);
+            let url = format!(
+                "{blob_url}?comp=block&blockid={}",
+                utf8_percent_encode(&block_id, QUERY_VALUE_ENCODE_SET)
+            );
+            self.put("Put block", &url, "application/octet-stream", false, block)
+                .await?;
+            block_ids.push(block_id);
+            if block_len < self.block_size {
+                break;
+            }
+        }
+        self.put(
+            "Put block list",
+            &format!("{blob_url}?comp=blocklist"),
+            "application/xml",
+            false,
+            Bytes::from(block_list_xml(&block_ids)),
+        )
+        .await
+    }
+
+    async fn get_part(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        writer: &mut DropCloserWriteHalf,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<(), Error> {
+        if is_zero_digest(key.borrow()) {
+            writer
+                .send_eof()
+                .err_tip(|| "Failed to send zero EOF in Azure Blob store get_part")?;
+            return Ok(());
+        }
+        if length == Some(0) {
+            if self.has(&key).await?.is_none() {
+                return Err(make_err!(
+                    Code::NotFound,
+                    "No such key in Azure Blob Storage: {key:?}"
+                ));
+            }
+            return writer
+                .send_eof()
+                .err_tip(|| "Failed to send EOF in Azure Blob store get_part");
+        }
+
+        let url = &self.make_blob_url(&key);
+        // Ranges are inclusive at both ends.
+        let end_read_byte = length
+            .map(|length| offset.checked_add(length - 1))
+            .map_or(Some(None), |v| v.map(Some))
+            .err_tip(|| "Integer overflow protection triggered")?;
+
+        self.retrier
+            .retry(unfold(writer, move |writer| async move {
+                let range = format!(
+                    "bytes={}-{}",
+                    offset + writer.get_bytes_written(),
+                    end_read_byte.map_or_else(String::new, |v| v.to_string())
+                );
+                let response = match self
+                    .send("GET", url, |r| r.header("x-ms-range", range), Body::empty())
+                    .await
+                {
+                    Ok(response) => response,
+                    Err(err) => return Some((RetryResult::Retry(err), writer)),
+                };
+                let status = response.status();
+                if status == StatusCode::NOT_FOUND {
+                    return Some((
+                        RetryResult::Err(make_err!(
+                            Code::NotFound,
+                            "No such key in Azure Blob Storage: {url}"
+                        )),
+                        writer,
+                    ));
+                }
+                if status != StatusCode::RANGE_NOT_SATISFIABLE {
+                    if !status.is_success() {
+                        let err = error_from_response("Get blob", response).await;
+                        return Some((retry_for_status(status, err), writer));
+                    }
+                    // Copy data from the response to the writer stream.
+                    let mut body = response.into_body();
+                    while let Some(maybe_bytes) = body.data().await {
+                        match maybe_bytes {
+                            Ok(bytes) => {
+                                if bytes.is_empty() {
+                                    continue;
+                                }
+                                if let Err(e) = writer.send(bytes).await {
+                                    return Some((
+                                        RetryResult::Err(make_err!(
+                                            Code::Aborted,
+                                            "Error sending bytes to consumer in Azure Blob Storage: {e}"
+                                        )),
+                                        writer,
+                                    ));
+                                }
+                            }
+                            Err(e) => {
+                                return Some((
+                                    RetryResult::Retry(make_err!(
+                                        Code::Aborted,
+                                        "Bad bytestream element in Azure Blob Storage: {e}"
+                                    )),
+                                    writer,
+                                ));
+                            }
+                        }
+                    }
+                }
+                // A range starting at the end of the blob is not satisfiable,
+                // but just means there is nothing (left) to read.
+                if let Err(e) = writer.send_eof() {
+                    return Some((
+                        RetryResult::Err(make_err!(
+                            Code::Aborted,
+                            "Failed to send EOF to consumer in Azure Blob Storage: {e}"
+                        )),
+                        writer,
+                    ));
+                }
+                Some((RetryResult::Ok(()), writer))
+            }))
+            .await
+    }
+
+    fn inner_store(&self, _digest: Option<StoreKey>) -> &'_ dyn StoreDriver {
+        self
+    }
+
+    fn as_any<'a>(&'a self) -> &'a (dyn std::any::Any + Sync + Send + 'static) {
+        self
+    }
+
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn std::any::Any + Sync + Send + 'static> {
+        self
+    }
+
+    async fn reconfigure(&self, spec: &StoreSpec, check_only: bool) -> Result<(), Error> {
+        let StoreSpec::experimental_azure_blob_store(spec) = spec else {
+            return Err(make_input_err!(
+                "AzureBlobStore can only be reconfigured with an experimental_azure_blob_store spec"
+            ));
+        };
+        if !check_only {
+            self.retrier.set_config(spec.retry.clone());
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<I, NowFn> HealthStatusIndicator for AzureBlobStore<NowFn>
+where
+    I: InstantWrapper,
+    NowFn: Fn() -> I + Send + Sync + Unpin + 'static,
+{
+    fn get_name(&self) -> &'static str {
+        "AzureBlobStore"
+    }
+
+    async fn check_health(&self, namespace: Cow<'static, str>) -> HealthStatus {
+        StoreDriver::check_health(Pin::new(self), namespace).await
+    }
+}
@@ -12,18 +12,23 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashSet;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 use std::{iter, mem};
 
 use async_trait::async_trait;
-use futures::stream::{FuturesUnordered, StreamExt};
+use futures::stream::{self, FuturesUnordered, StreamExt};
 use futures::{select, FutureExt, TryFutureExt};
-use nativelink_error::{make_err, Code, Error, ResultExt};
+use nativelink_config::stores::StoreSpec;
+use nativelink_error::{make_err, make_input_err, Code, Error, ResultExt};
 use nativelink_metric::MetricsComponent;
 use nativelink_proto::build::bazel::remote::execution::v2::{
     ActionResult as ProtoActionResult, OutputDirectory as ProtoOutputDirectory, Tree as ProtoTree,
 };
+use nativelink_util::background_spawn;
 use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
 use nativelink_util::common::DigestInfo;
 use nativelink_util::health_utils::{default_health_status_indicator, HealthStatusIndicator};
@@ -31,10 +36,34 @@ use nativelink_util::metrics_utils::CounterWithTime;
 use nativelink_util::store_trait::{Store, StoreDriver, StoreKey, StoreLike, UploadSizeInfo};
 use parking_lot::Mutex;
 use tokio::sync::Notify;
+use tokio::time::sleep;
 use tracing::{event, Level};
 
 use crate::ac_utils::{get_and_decode_digest, get_size_and_decode_digest};
 
+/// Number of ActionResults that are read at the same time during a garbage
+/// collection.
+const SIMULTANEOUS_GC_ACTION_RESULT_READS: usize = 16;
+
+/// Number of reachable digests that are marked in the CAS with a single
+/// `has_many()` during a garbage collection.
+const GC_MARK_BATCH_SIZE: usize = 1000;
+
+/// The outcome of a `CompletenessCheckingStore::gc()`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ReachabilityGcReport {
+    /// Number of ActionResults that were read.
+    pub action_results: u64,
+    /// Number of CAS digests referenced by the ActionResults.
+    pub reachable_digests: u64,
+    /// Number of referenced CAS digests that are not in the CAS.
+    pub missing_digests: u64,
+    /// Number of unreachable objects that were removed from the CAS.
+    pub removed_digests: u64,
+    /// Number of bytes of the removed objects.
+    pub removed_bytes: u64,
+}
+
 /// Given a proto action result, return all relevant digests and
 /// output directories that need to be checked.
 fn get_digests_and_output_dirs(
@@ -104,15 +133,36 @@ async fn check_output_directories<'a>(
     Ok(())
 }
 
+/// Clears the running flag of a garbage collection when it ends.
+struct GcRunGuard<'a>(&'a AtomicBool);
+
+impl Drop for GcRunGuard<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
 #[derive(MetricsComponent)]
 pub struct CompletenessCheckingStore {
     cas_store: Store,
     ac_store: Store,
 
+    gc_running: AtomicBool,
+    // Unreachable digests found by the last garbage collection, which are
+    // removed if the next one finds them unreachable again.
+    unreachable_candidates: Mutex<HashSet<DigestInfo>>,
+    // Keys of the ActionResults written while a garbage collection runs,
+    // whose references it has not seen yet.
+    written_action_results: Mutex<Vec<StoreKey<'static>>>,
+
     #[metric(help = "Incomplete entries hit in CompletenessCheckingStore")]
     incomplete_entries_counter: CounterWithTime,
     #[metric(help = "Complete entries hit in CompletenessCheckingStore")]
     complete_entries_counter: CounterWithTime,
+    #[metric(help = "Unreachable objects removed from the CAS by garbage collection")]
+    gc_removed_digests: AtomicU64,
+    #[metric(help = "Bytes of unreachable objects removed from the CAS by garbage collection")]
+    gc_removed_bytes: AtomicU64,
 }
 
 impl CompletenessCheckingStore {
@@ -120,11 +170,200 @@ impl CompletenessCheckingStore {
         Arc::new(CompletenessCheckingStore {
             cas_store,
             ac_store,
+            gc_running: AtomicBool::new(false),
+            unreachable_candidates: Mutex::new(HashSet::new()),
+            written_action_results: Mutex::new(Vec::new()),
             incomplete_entries_counter: CounterWithTime::default(),
             complete_entries_counter: CounterWithTime::default(),
+            gc_removed_digests: AtomicU64::new(0),
+            gc_removed_bytes: AtomicU64::new(0),
         })
     }
 
+    /// Runs `gc()` every `interval` until the store is dropped.
+    pub fn start_periodic_gc(self: &Arc<Self>, interval: Duration, remove_unreachable: bool) {
+        let weak_store = Arc::downgrade(self);
+        background_spawn!("completeness_checking_store_gc", async move {
+            loop {
+                sleep(interval).await;
+                let Some(store) = weak_store.upgrade() else {
+                    return;
+                };
+                match store.gc(remove_unreachable).await {
+                    Ok(report) => event!(
+                        Level::INFO,
+                        ?report,
+                        "Finished garbage collection of CAS in completeness checking store"
+                    ),
+                    Err(err) => event!(
+                        Level::WARN,
+                        ?err,
+                        "Failed to garbage collect CAS in completeness checking store"
+                    ),
+                }
+            }
+        });
+    }
+
+    /// Marks every CAS object referenced by an ActionResult in the AC store
+    /// by querying it in the CAS store, which makes it the most recently
+    /// used object in stores that evict the least recently used objects.
+    ///
+    /// With `remove_unreachable` the CAS objects that are not referenced by
+    /// any ActionResult are removed, if they were also unreferenced in the
+    /// previous garbage collection. The grace period gives objects that were
+    /// just uploaded time to be referenced by an ActionResult.
+    pub async fn gc(&self, remove_unreachable: bool) -> Result<ReachabilityGcReport, Error> {
+        if self.gc_running.swap(true, Ordering::AcqRel) {
+            return Err(make_err!(
+                Code::FailedPrecondition,
+                "A garbage collection is already running in CompletenessCheckingStore"
+            ));
+        }
+        let _gc_run_guard = GcRunGuard(&self.gc_running);
+        self.written_action_results.lock().clear();
+        let mut report = ReachabilityGcReport::default();
+
+        let mut action_result_keys = Vec::new();
+        self.ac_store
+            .list(.., |key| {
+                action_result_keys.push(key.borrow().into_owned());
+                true
+            })
+            .await
+            .err_tip(|| "Failed to list AC store in CompletenessCheckingStore::gc")?;
+        let mut reachable = HashSet::new();
+        let mut action_result_reads = stream::iter(action_result_keys)
+            .map(|key| async move { self.reachable_digests(key).await })
+            .buffer_unordered(SIMULTANEOUS_GC_ACTION_RESULT_READS);
+        while let Some(result) = action_result_reads.next().await {
+            match result {
+                Ok(digests) => {
+                    report.action_results += 1;
+                    reachable.extend(digests);
+                }
+                // The ActionResult was evicted after it was listed or is
+                // corrupt.
+                Err(err) if err.code == Code::NotFound => {}
+                Err(err) => {
+                    return Err(err)
+                        .err_tip(|| "Failed to read AC store in CompletenessCheckingStore::gc")
+                }
+            }
+        }
+        report.reachable_digests = reachable.len() as u64;
+
+        let reachable_keys: Vec<StoreKey> =
+            reachable.iter().map(|digest| (*digest).into()).collect();
+        for keys in reachable_keys.chunks(GC_MARK_BATCH_SIZE) {
+            let results = self
+                .cas_store
+                .has_many(keys)
+                .await
+                .err_tip(|| "Failed to mark CAS store in CompletenessCheckingStore::gc")?;
+            report.missing_digests +=
+                results.iter().filter(|result| result.is_none()).count() as u64;
+        }
+
+        if remove_unreachable {
+            let mut unreachable = Vec::new();
+            self.cas_store
+                .list(.., |key| {
+                    if let StoreKey::Digest(digest) = key {
+                        if !reachable.contains(digest) {
+                            unreachable.push(*digest);
+                        }
+                    }
+                    true
+                })
+                .await
+                .err_tip(|| "Failed to list CAS store in CompletenessCheckingStore::gc")?;
+            let previous_candidates = mem::take(&mut *self.unreachable_candidates.lock());
+            let mut candidates = HashSet::new();
+            for digest in unreachable {
+                if !previous_candidates.contains(&digest) {
+                    candidates.insert(digest);
+                    continue;
+                }
+                // An ActionResult written since the AC store was listed may
+                // reference the object by now.
+                self.mark_written_action_results(&mut reachable).await?;
+                if reachable.contains(&digest) {
+                    continue;
+                }
+                let removed = self.cas_store.remove(digest).await.err_tip(|| {
+                    "Failed to remove from CAS store in CompletenessCheckingStore::gc"
+                })?;
+                if removed {
+                    report.removed_digests += 1;
+                    report.removed_bytes += digest.size_bytes();
+                }
+            }
+            *self.unreachable_candidates.lock() = candidates;
+            self.gc_removed_digests
+                .fetch_add(report.removed_digests, Ordering::Relaxed);
+            self.gc_removed_bytes
+                .fetch_add(report.removed_bytes, Ordering::Relaxed);
+        }
+        Ok(report)
+    }
+
+    /// Adds the objects referenced by the ActionResults written since this
+    /// was last called during the running garbage collection to `reachable`.
+    async fn mark_written_action_results(
+        &self,
+        reachable: &mut HashSet<DigestInfo>,
+    ) -> Result<(), Error> {
+        let written_keys = mem::take(&mut *self.written_action_results.lock());
+        for key in written_keys {
+            match self.reachable_digests(key).await {
+                Ok(digests) => reachable.extend(digests),
+                Err(err) if err.code == Code::NotFound => {}
+                Err(err) => {
+                    return Err(err)
+                        .err_tip(|| "Failed to read AC store in CompletenessCheckingStore::gc")
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the digests of every CAS object the ActionResult at `key`
+    /// references, including the trees of its output directories and the
+    /// files in them.
+    async fn reachable_digests(&self, key: StoreKey<'_>) -> Result<Vec<DigestInfo>, Error> {
+        let action_result =
+            get_and_decode_digest::<ProtoActionResult>(&self.ac_store, key.borrow()).await?;
+        let (keys, output_directories) = get_digests_and_output_dirs(action_result)?;
+        let mut digests: Vec<DigestInfo> = keys.into_iter().map(StoreKey::into_digest).collect();
+        for output_directory in output_directories {
+            let Some(tree_digest) = output_directory.tree_digest else {
+                continue;
+            };
+            let tree_digest = DigestInfo::try_from(tree_digest)
+                .err_tip(|| "Could not decode tree digest in CompletenessCheckingStore::gc")?;
+            digests.push(tree_digest);
+            let tree = match get_and_decode_digest::<ProtoTree>(&self.cas_store, tree_digest.into())
+                .await
+            {
+                Ok(tree) => tree,
+                // Counted as missing when the digests are marked.
+                Err(err) if err.code == Code::NotFound => continue,
+                Err(err) => return Err(err),
+            };
+            for directory in tree.children.into_iter().chain(tree.root) {
+                for file in directory.files {
+                    if let Some(digest) = file.digest {
+                        digests.push(DigestInfo::try_from(digest).err_tip(|| {
+                            "Could not decode file digest in CompletenessCheckingStore::gc"
+                        })?);
+                    }
+                }
+            }
+        }
+        Ok(digests)
+    }
+
     /// Check that all files and directories in action results
     /// exist in the CAS. Does this by decoding digests and
     /// checking their existence in two separate sets of futures that
@@ -353,7 +592,12 @@ impl StoreDriver for CompletenessCheckingStore {
         reader: DropCloserReadHalf,
         size_info: UploadSizeInfo,
     ) -> Result<(), Error> {
-        self.ac_store.update(key, reader, size_info).await
+        let written_key = key.borrow().into_owned();
+        self.ac_store.update(key, reader, size_info).await?;
+        if self.gc_running.load(Ordering::Acquire) {
+            self.written_action_results.lock().push(written_key);
+        }
+        Ok(())
     }
 
     async fn get_part(
@@ -387,6 +631,24 @@ impl StoreDriver for CompletenessCheckingStore {
     fn as_any_arc(self: Arc<Self>) -> Arc<dyn std::any::Any + Sync + Send + 'static> {
         self
     }
+
+    async fn reconfigure(&self, spec: &StoreSpec, check_only: bool) -> Result<(), Error> {
+        let StoreSpec::completeness_checking(spec) = spec else {
+            return Err(make_input_err!(
+                "CompletenessCheckingStore can only be reconfigured with a completeness_checking spec"
+            ));
+        };
+        self.ac_store
+            .as_store_driver()
+            .reconfigure(&spec.backend, check_only)
+            .await
+            .err_tip(|| "In CompletenessCheckingStore::reconfigure for backend")?;
+        self.cas_store
+            .as_store_driver()
+            .reconfigure(&spec.cas_store, check_only)
+            .await
+            .err_tip(|| "In CompletenessCheckingStore::reconfigure for cas_store")
+    }
 }
 
 default_health_status_indicator!(CompletenessCheckingStore);
@@ -13,6 +13,8 @@
 // limitations under the License.
 
 use std::cmp;
+use std::collections::HashMap;
+use std::num::NonZeroU32;
 use std::pin::Pin;
 use std::sync::Arc;
 
@@ -20,11 +22,11 @@ use async_trait::async_trait;
 use bincode::config::{FixintEncoding, WithOtherIntEncoding};
 use bincode::{DefaultOptions, Options};
 use byteorder::{ByteOrder, LittleEndian};
-use bytes::{Buf, BufMut, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use futures::future::FutureExt;
 use lz4_flex::block::{compress_into, decompress_into, get_maximum_output_size};
-use nativelink_config::stores::CompressionSpec;
-use nativelink_error::{error_if, make_err, Code, Error, ResultExt};
+use nativelink_config::stores::{CompressionSpec, StoreSpec, ZstdConfig};
+use nativelink_error::{error_if, make_err, make_input_err, Code, Error, ResultExt};
 use nativelink_metric::MetricsComponent;
 use nativelink_util::buf_channel::{
     make_buf_channel_pair, DropCloserReadHalf, DropCloserWriteHalf,
@@ -33,6 +35,9 @@ use nativelink_util::health_utils::{default_health_status_indicator, HealthStatu
 use nativelink_util::spawn;
 use nativelink_util::store_trait::{Store, StoreDriver, StoreKey, StoreLike, UploadSizeInfo};
 use serde::{Deserialize, Serialize};
+use tracing::{event, Level};
+use zstd::bulk::{Compressor, Decompressor};
+use zstd::dict::{DecoderDictionary, EncoderDictionary};
 
 use crate::cas_utils::is_zero_digest;
 
@@ -40,9 +45,21 @@ use crate::cas_utils::is_zero_digest;
 // backwards compatibility issues.
 pub const CURRENT_STREAM_FORMAT_VERSION: u8 = 1;
 
+// Version of the stream format whose blocks are compressed with zstd instead of lz4.
+pub const ZSTD_STREAM_FORMAT_VERSION: u8 = 2;
+
+// Version of the stream format whose blocks are stored without compression.
+pub const STORED_STREAM_FORMAT_VERSION: u8 = 3;
+
 // Default block size that will be used to slice stream into.
 pub const DEFAULT_BLOCK_SIZE: u32 = 64 * 1024;
 
+// Default zstd compression level, same as the zstd command line tool.
+pub const DEFAULT_ZSTD_LEVEL: i32 = 3;
+
+// Default maximum size of a trained zstd dictionary, same as `zstd --train`.
+pub const DEFAULT_ZSTD_DICTIONARY_MAX_SIZE: u32 = 110 * 1024;
+
 const U32_SZ: u64 = std::mem::size_of::<u8>() as u64;
 
 type BincodeOptions = WithOtherIntEncoding<DefaultOptions, FixintEncoding>;
@@ -67,6 +84,7 @@ type BincodeOptions = WithOtherIntEncoding<DefaultOptions, FixintEncoding>;
 // The frame format is as follows:
 // |----------------------------------HEADER-----------------------------------------|
 // |  version(u8) |  block_size (u32) |  upload_size_type (u32) |  upload_size (u32) |
+// |  [dictionary_id (u32) - version 2 only]                                         |
 // |----------------------------------BLOCK------------------------------------------|
 // |  frame_type(u8) 0x00 |  compressed_data_size (u32) |        ...DATA...          |
 // |                                ...DATA...                                       |
@@ -79,7 +97,9 @@ type BincodeOptions = WithOtherIntEncoding<DefaultOptions, FixintEncoding>;
 // |---------------------------------------------------------------------------------|
 //
 // version              - A constant number used to define what version of this format is being
-//                        used. Version in header and footer must match.
+//                        used. Version in header and footer must match. Version 1 has lz4
+//                        compressed blocks, version 2 has zstd compressed blocks and version 3
+//                        has uncompressed blocks.
 // block_size           - Size of each block uncompressed except for last block. This means that
 //                        every block uncompressed will be a constant size except last block may
 //                        be variable size. Block size in header and footer must match.
@@ -89,6 +109,8 @@ type BincodeOptions = WithOtherIntEncoding<DefaultOptions, FixintEncoding>;
 //                        payload size. It is a debug field and a "best guess" on how large the data
 //                        is. The header does not contain the upload data size. This value is the
 //                        value counter part to what the `upload_size_type` field.
+// dictionary_id        - Id of the zstd dictionary the blocks were compressed with, or 0 if none
+//                        was used. Only present in version 2 (zstd) streams.
 // frame_type           - Type of each frame. 0 = BLOCK frame, 1 = FOOTER frame. Header frame will
 //                        always start with the first byte of the stream, so no magic number for it.
 // compressed_data_size - The size of this block. The bytes after this field should be read
@@ -158,17 +180,21 @@ struct UploadState {
 }
 
 impl UploadState {
-    pub fn new(store: &CompressionStore, upload_size: UploadSizeInfo) -> Result<Self, Error> {
+    pub fn new(
+        store: &CompressionStore,
+        encoding: &Encoding,
+        upload_size: UploadSizeInfo,
+    ) -> Result<Self, Error> {
         let input_max_size = match upload_size {
             UploadSizeInfo::MaxSize(sz) | UploadSizeInfo::ExactSize(sz) => sz,
         };
 
-        let max_index_count = (input_max_size / u64::from(store.config.block_size)) + 1;
+        let max_index_count = (input_max_size / u64::from(encoding.block_size)) + 1;
 
         let header = Header {
-            version: CURRENT_STREAM_FORMAT_VERSION,
+            version: encoding.codec.stream_format_version(),
             config: Lz4Config {
-                block_size: store.config.block_size,
+                block_size: encoding.block_size,
             },
             upload_size,
         };
@@ -183,14 +209,21 @@ impl UploadState {
             index_count: max_index_count as u32,
             uncompressed_data_size: 0, // Updated later.
             config: header.config,
-            version: CURRENT_STREAM_FORMAT_VERSION,
+            version: header.version,
         };
 
-        // This is more accurate of an estimate than what get_maximum_output_size calculates.
-        let max_block_size = lz4_compress_bound(u64::from(store.config.block_size)) + U32_SZ + 1;
+        let max_block_size = encoding
+            .codec
+            .compress_bound(u64::from(encoding.block_size))
+            + U32_SZ
+            + 1;
 
         let max_output_size = {
-            let header_size = store.bincode_options.serialized_size(&header).unwrap();
+            let header_size = store.bincode_options.serialized_size(&header).unwrap()
+                + encoding
+                    .codec
+                    .dictionary_id()
+                    .map_or(0, |_| std::mem::size_of::<u32>() as u64);
             let max_content_size = max_block_size * max_index_count;
             let max_footer_size =
                 U32_SZ + 1 + store.bincode_options.serialized_size(&footer).unwrap();
@@ -206,6 +239,237 @@ impl UploadState {
     }
 }
 
+/// The algorithm new blobs are compressed with.
+enum Codec {
+    /// Blocks are stored without compression.
+    Stored,
+    Lz4,
+    Zstd {
+        level: i32,
+        /// The dictionary and its id, if one is configured.
+        dictionary: Option<(u32, EncoderDictionary<'static>)>,
+    },
+}
+
+impl Codec {
+    fn stream_format_version(&self) -> u8 {
+        match self {
+            Self::Stored => STORED_STREAM_FORMAT_VERSION,
+            Self::Lz4 => CURRENT_STREAM_FORMAT_VERSION,
+            Self::Zstd { .. } => ZSTD_STREAM_FORMAT_VERSION,
+        }
+    }
+
+    /// The dictionary id written after the header, which only zstd streams
+    /// have.
+    fn dictionary_id(&self) -> Option<u32> {
+        match self {
+            Self::Stored | Self::Lz4 => None,
+            Self::Zstd { dictionary, .. } => Some(dictionary.as_ref().map_or(0, |(id, _)| *id)),
+        }
+    }
+
+    /// Worst case compressed size of a block of `input_size` bytes.
+    fn compress_bound(&self, input_size: u64) -> u64 {
+        match self {
+            Self::Stored => input_size,
+            // This is more accurate of an estimate than what get_maximum_output_size calculates.
+            Self::Lz4 => lz4_compress_bound(input_size),
+            Self::Zstd { .. } => {
+                zstd::zstd_safe::compress_bound(usize::try_from(input_size).unwrap_or(usize::MAX))
+                    as u64
+            }
+        }
+    }
+}
+
+/// How blobs in a size range are compressed.
+struct Encoding {
+    block_size: u32,
+    codec: Codec,
+}
+
+impl Encoding {
+    fn block_encoder(&self) -> Result<BlockEncoder<'_>, Error> {
+        let compressor = match &self.codec {
+            Codec::Stored => return Ok(BlockEncoder::Stored),
+            Codec::Lz4 => return Ok(BlockEncoder::Lz4),
+            Codec::Zstd {
+                dictionary: Some((_, dictionary)),
+                ..
+            } => Compressor::with_prepared_dictionary(dictionary),
+            Codec::Zstd { level, .. } => Compressor::new(*level),
+        };
+        compressor
+            .map(BlockEncoder::Zstd)
+            .map_err(|e| make_err!(Code::Internal, "Failed to create zstd compressor : {e:?}"))
+    }
+}
+
+/// Compresses the blocks of a single upload.
+enum BlockEncoder<'a> {
+    Stored,
+    Lz4,
+    Zstd(Compressor<'a>),
+}
+
+impl BlockEncoder<'_> {
+    /// Appends the compressed `chunk` to `output` and returns the size of
+    /// the compressed data.
+    fn compress(
+        &mut self,
+        chunk: &[u8],
+        block_size: u32,
+        output: &mut BytesMut,
+    ) -> Result<usize, Error> {
+        match self {
+            Self::Stored => {
+                output.extend_from_slice(chunk);
+                Ok(chunk.len())
+            }
+            Self::Lz4 => {
+                let max_output_size = get_maximum_output_size(block_size as usize);
+                output.reserve(max_output_size);
+
+                // For efficiency reasons we do some raw slice manipulation so we can write directly
+                // into our buffer instead of having to do another allocation.
+                let raw_compressed_data = unsafe {
+                    std::slice::from_raw_parts_mut(output.chunk_mut().as_mut_ptr(), max_output_size)
+                };
+
+                let compressed_data_sz = compress_into(chunk, raw_compressed_data)
+                    .map_err(|e| make_err!(Code::Internal, "Compression error {:?}", e))?;
+                unsafe {
+                    output.advance_mut(compressed_data_sz);
+                }
+                Ok(compressed_data_sz)
+            }
+            Self::Zstd(compressor) => {
+                let compressed_data = compressor
+                    .compress(chunk)
+                    .map_err(|e| make_err!(Code::Internal, "Compression error {:?}", e))?;
+                output.extend_from_slice(&compressed_data);
+                Ok(compressed_data.len())
+            }
+        }
+    }
+}
+
+/// Decompresses the blocks of a single blob.
+enum BlockDecoder<'a> {
+    Stored,
+    Lz4,
+    Zstd(Decompressor<'a>),
+}
+
+impl BlockDecoder<'_> {
+    /// Decompresses `chunk`, which may not be larger than `block_size`
+    /// uncompressed.
+    fn decompress(&mut self, chunk: Bytes, block_size: u32) -> Result<Bytes, Error> {
+        match self {
+            Self::Stored => {
+                error_if!(
+                    chunk.len() > block_size as usize,
+                    "Stored block is larger than the block size in compression store, {} > {}",
+                    chunk.len(),
+                    block_size
+                );
+                Ok(chunk)
+            }
+            Self::Lz4 => {
+                let max_output_size = get_maximum_output_size(block_size as usize);
+                let mut uncompressed_data = BytesMut::with_capacity(max_output_size);
+
+                // For efficiency reasons we do some raw slice manipulation so we can write directly
+                // into our buffer instead of having to do another allocation.
+                let raw_decompressed_data = unsafe {
+                    std::slice::from_raw_parts_mut(
+                        uncompressed_data.chunk_mut().as_mut_ptr(),
+                        max_output_size,
+                    )
+                };
+
+                let uncompressed_chunk_sz = decompress_into(&chunk, raw_decompressed_data)
+                    .map_err(|e| make_err!(Code::Internal, "Decompression error {:?}", e))?;
+                unsafe { uncompressed_data.advance_mut(uncompressed_chunk_sz) };
+                Ok(uncompressed_data.freeze())
+            }
+            Self::Zstd(decompressor) => decompressor
+                .decompress(&chunk, block_size as usize)
+                .map(Bytes::from)
+                .map_err(|e| make_err!(Code::Internal, "Decompression error {:?}", e)),
+        }
+    }
+}
+
+/// Returns the id of the zstd `dictionary` read from `path`.
+fn zstd_dictionary_id(dictionary: &[u8], path: &str) -> Result<u32, Error> {
+    zstd::zstd_safe::get_dict_id_from_dict(dictionary)
+        .map(NonZeroU32::get)
+        .ok_or_else(|| make_input_err!("{path} is not a zstd dictionary in compression store"))
+}
+
+/// Reads the dictionary at `dictionary_path`. If there is none yet and
+/// training samples are configured, a dictionary is trained from them and
+/// written to `dictionary_path`, so the same one is used after a restart.
+fn load_zstd_dictionary(config: &ZstdConfig) -> Result<Option<Vec<u8>>, Error> {
+    if config.dictionary_path.is_empty() {
+        error_if!(
+            !config.dictionary_training_samples_path.is_empty(),
+            "dictionary_training_samples_path requires dictionary_path to be set in compression store"
+        );
+        return Ok(None);
+    }
+    match std::fs::read(&config.dictionary_path) {
+        Ok(dictionary) => return Ok(Some(dictionary)),
+        Err(err)
+            if err.kind() == std::io::ErrorKind::NotFound
+                && !config.dictionary_training_samples_path.is_empty() => {}
+        Err(err) => {
+            return Err(err).err_tip(|| {
+                format!(
+                    "Failed to read zstd dictionary {} in compression store",
+                    config.dictionary_path
+                )
+            })
+        }
+    }
+
+    let samples_path = &config.dictionary_training_samples_path;
+    let mut samples = Vec::new();
+    for entry in std::fs::read_dir(samples_path)
+        .err_tip(|| format!("Failed to read training samples in {samples_path}"))?
+    {
+        let entry =
+            entry.err_tip(|| format!("Failed to read training samples in {samples_path}"))?;
+        if entry.file_type()?.is_file() {
+            samples.push(std::fs::read(entry.path())?);
+        }
+    }
+    let max_size = if config.dictionary_max_size == 0 {
+        DEFAULT_ZSTD_DICTIONARY_MAX_SIZE
+    } else {
+        config.dictionary_max_size
+    };
+    let dictionary = zstd::dict::from_samples(&samples, max_size as usize).map_err(|e| {
+        make_input_err!("Failed to train zstd dictionary from {samples_path} : {e:?}")
+    })?;
+    std::fs::write(&config.dictionary_path, &dictionary).err_tip(|| {
+        format!(
+            "Failed to write zstd dictionary {} in compression store",
+            config.dictionary_path
+        )
+    })?;
+    event!(
+        Level::INFO,
+        dictionary_path = config.dictionary_path,
+        sample_count = samples.len(),
+        dictionary_size = dictionary.len(),
+        "Trained zstd dictionary for compression store"
+    );
+    Ok(Some(dictionary))
+}
+
 /// This store will compress data before sending it on to the inner store.
 /// Note: Currently using `get_part()` and trying to read part of the data will
 /// result in the entire contents being read from the inner store but will
@@ -214,29 +478,157 @@ impl UploadState {
 pub struct CompressionStore {
     #[metric(group = "inner_store")]
     inner_store: Store,
-    config: nativelink_config::stores::Lz4Config,
+    #[metric(help = "Uploads smaller than this are stored without compression")]
+    min_compression_size: u64,
+    #[metric(help = "Uploads larger than this are stored without compression, 0 is no limit")]
+    max_compression_size: u64,
+    #[metric(help = "Maximum block size of data that will be decompressed")]
+    max_decode_block_size: u32,
+    /// The encoding of each size band by the largest size in the band,
+    /// sorted by size.
+    size_bands: Vec<(u64, Encoding)>,
+    /// The encoding of uploads larger than every size band.
+    default_encoding: Encoding,
+    /// The encoding of uploads that are not compressed.
+    stored_encoding: Encoding,
+    /// Dictionaries that zstd blobs can be decompressed with, by id.
+    zstd_decoder_dictionaries: HashMap<u32, DecoderDictionary<'static>>,
     bincode_options: BincodeOptions,
 }
 
+/// Returns the encoding of `algorithm` and the maximum block size it
+/// decompresses. The dictionaries of zstd are added to
+/// `zstd_decoder_dictionaries`.
+fn make_encoding(
+    algorithm: &nativelink_config::stores::CompressionAlgorithm,
+    zstd_decoder_dictionaries: &mut HashMap<u32, DecoderDictionary<'static>>,
+) -> Result<(Encoding, u32), Error> {
+    let (block_size, max_decode_block_size) = match algorithm {
+        nativelink_config::stores::CompressionAlgorithm::lz4(lz4_config) => {
+            (lz4_config.block_size, lz4_config.max_decode_block_size)
+        }
+        nativelink_config::stores::CompressionAlgorithm::zstd(zstd_config) => {
+            (zstd_config.block_size, zstd_config.max_decode_block_size)
+        }
+    };
+    let block_size = if block_size == 0 {
+        DEFAULT_BLOCK_SIZE
+    } else {
+        block_size
+    };
+    let max_decode_block_size = if max_decode_block_size == 0 {
+        block_size
+    } else {
+        max_decode_block_size
+    };
+
+    let codec = match algorithm {
+        nativelink_config::stores::CompressionAlgorithm::lz4(_) => Codec::Lz4,
+        nativelink_config::stores::CompressionAlgorithm::zstd(zstd_config) => {
+            let level = if zstd_config.level == 0 {
+                DEFAULT_ZSTD_LEVEL
+            } else {
+                zstd_config.level
+            };
+            for path in &zstd_config.decompression_dictionary_paths {
+                let dictionary = std::fs::read(path).err_tip(|| {
+                    format!("Failed to read zstd dictionary {path} in compression store")
+                })?;
+                zstd_decoder_dictionaries.insert(
+                    zstd_dictionary_id(&dictionary, path)?,
+                    DecoderDictionary::copy(&dictionary),
+                );
+            }
+            let dictionary = match load_zstd_dictionary(zstd_config)? {
+                Some(dictionary) => {
+                    let id = zstd_dictionary_id(&dictionary, &zstd_config.dictionary_path)?;
+                    zstd_decoder_dictionaries.insert(id, DecoderDictionary::copy(&dictionary));
+                    Some((id, EncoderDictionary::copy(&dictionary, level)))
+                }
+                None => None,
+            };
+            Codec::Zstd { level, dictionary }
+        }
+    };
+    Ok((Encoding { block_size, codec }, max_decode_block_size))
+}
+
 impl CompressionStore {
     pub fn new(spec: &CompressionSpec, inner_store: Store) -> Result<Arc<Self>, Error> {
-        let lz4_config = match spec.compression_algorithm {
-            nativelink_config::stores::CompressionAlgorithm::lz4(mut lz4_config) => {
-                if lz4_config.block_size == 0 {
-                    lz4_config.block_size = DEFAULT_BLOCK_SIZE;
-                }
-                if lz4_config.max_decode_block_size == 0 {
-                    lz4_config.max_decode_block_size = lz4_config.block_size;
-                }
-                lz4_config
-            }
+        error_if!(
+            spec.max_compression_size != 0
+                && spec.max_compression_size < spec.min_compression_size,
+            "max_compression_size must not be smaller than min_compression_size in compression store"
+        );
+        let mut zstd_decoder_dictionaries = HashMap::new();
+        let (default_encoding, mut max_decode_block_size) =
+            make_encoding(&spec.compression_algorithm, &mut zstd_decoder_dictionaries)?;
+        let mut size_bands = Vec::with_capacity(spec.size_bands.len());
+        for size_band in &spec.size_bands {
+            let (encoding, band_max_decode_block_size) = make_encoding(
+                &size_band.compression_algorithm,
+                &mut zstd_decoder_dictionaries,
+            )?;
+            // Blobs of every band must be readable.
+            max_decode_block_size = max_decode_block_size.max(band_max_decode_block_size);
+            size_bands.push((size_band.max_size, encoding));
+        }
+        size_bands.sort_by_key(|(max_size, _)| *max_size);
+        let stored_encoding = Encoding {
+            block_size: default_encoding.block_size,
+            codec: Codec::Stored,
         };
         Ok(Arc::new(CompressionStore {
             inner_store,
-            config: lz4_config,
+            min_compression_size: spec.min_compression_size,
+            max_compression_size: spec.max_compression_size,
+            max_decode_block_size,
+            size_bands,
+            default_encoding,
+            stored_encoding,
+            zstd_decoder_dictionaries,
             bincode_options: DefaultOptions::new().with_fixint_encoding(),
         }))
     }
+
+    /// Returns how an upload of `upload_size` is compressed. Uploads of an
+    /// unknown size are treated as if they were as large as they may be.
+    fn encoding_for(&self, upload_size: UploadSizeInfo) -> &Encoding {
+        let size = match upload_size {
+            UploadSizeInfo::ExactSize(size) | UploadSizeInfo::MaxSize(size) => size,
+        };
+        if size < self.min_compression_size
+            || (self.max_compression_size != 0 && size > self.max_compression_size)
+        {
+            return &self.stored_encoding;
+        }
+        self.size_bands
+            .iter()
+            .find(|(max_size, _)| size <= *max_size)
+            .map_or(&self.default_encoding, |(_, encoding)| encoding)
+    }
+
+    /// Returns the decoder of a zstd stream compressed with the dictionary
+    /// `dictionary_id`, where 0 means no dictionary.
+    fn zstd_block_decoder(&self, dictionary_id: u32) -> Result<BlockDecoder<'_>, Error> {
+        let decompressor = if dictionary_id == 0 {
+            Decompressor::new()
+        } else {
+            let dictionary = self
+                .zstd_decoder_dictionaries
+                .get(&dictionary_id)
+                .ok_or_else(|| {
+                    make_err!(
+                        Code::Internal,
+                        "Data was compressed with unknown zstd dictionary {dictionary_id} in compression store"
+                    )
+                })?;
+            Decompressor::with_prepared_dictionary(dictionary)
+        };
+        decompressor
+            .map(BlockDecoder::Zstd)
+            .map_err(|e| make_err!(Code::Internal, "Failed to create zstd decompressor : {e:?}"))
+    }
 }
 
 #[async_trait]
@@ -249,13 +641,18 @@ impl StoreDriver for CompressionStore {
         self.inner_store.has_with_results(digests, results).await
     }
 
+    async fn remove(self: Pin<&Self>, key: StoreKey<'_>) -> Result<bool, Error> {
+        self.inner_store.remove(key).await
+    }
+
     async fn update(
         self: Pin<&Self>,
         key: StoreKey<'_>,
         mut reader: DropCloserReadHalf,
         upload_size: UploadSizeInfo,
     ) -> Result<(), Error> {
-        let mut output_state = UploadState::new(&self, upload_size)?;
+        let encoding = self.get_ref().encoding_for(upload_size);
+        let mut output_state = UploadState::new(&self, encoding, upload_size)?;
 
         let (mut tx, rx) = make_buf_channel_pair();
 
@@ -281,14 +678,18 @@ impl StoreDriver for CompressionStore {
         );
 
         let write_fut = async move {
+            let mut encoder = encoding.block_encoder()?;
             {
                 // Write Header.
-                let serialized_header = self
+                let mut serialized_header = self
                     .bincode_options
                     .serialize(&output_state.header)
                     .map_err(|e| {
                         make_err!(Code::Internal, "Failed to serialize header : {:?}", e)
                     })?;
+                if let Some(dictionary_id) = encoding.codec.dictionary_id() {
+                    serialized_header.extend_from_slice(&dictionary_id.to_le_bytes());
+                }
                 tx.send(serialized_header.into())
                     .await
                     .err_tip(|| "Failed to write compression header on upload")?;
@@ -298,7 +699,7 @@ impl StoreDriver for CompressionStore {
             let mut index_count: u32 = 0;
             for index in &mut output_state.footer.indexes {
                 let chunk = reader
-                    .consume(Some(self.config.block_size as usize))
+                    .consume(Some(encoding.block_size as usize))
                     .await
                     .err_tip(|| "Failed to read take in update in compression store")?;
                 if chunk.is_empty() {
@@ -312,25 +713,12 @@ impl StoreDriver for CompressionStore {
                     "Got more data than stated in compression store upload request"
                 );
 
-                let max_output_size = get_maximum_output_size(self.config.block_size as usize);
-                let mut compressed_data_buf = BytesMut::with_capacity(max_output_size);
+                let mut compressed_data_buf = BytesMut::with_capacity(1 + 4);
                 compressed_data_buf.put_u8(CHUNK_FRAME_TYPE);
                 compressed_data_buf.put_u32_le(0); // Filled later.
 
-                // For efficiency reasons we do some raw slice manipulation so we can write directly
-                // into our buffer instead of having to do another allocation.
-                let raw_compressed_data = unsafe {
-                    std::slice::from_raw_parts_mut(
-                        compressed_data_buf.chunk_mut().as_mut_ptr(),
-                        max_output_size,
-                    )
-                };
-
-                let compressed_data_sz = compress_into(&chunk, raw_compressed_data)
-                    .map_err(|e| make_err!(Code::Internal, "Compression error {:?}", e))?;
-                unsafe {
-                    compressed_data_buf.advance_mut(compressed_data_sz);
-                }
+                let compressed_data_sz =
+                    encoder.compress(&chunk, encoding.block_size, &mut compressed_data_buf)?;
 
                 // Now fill the size in our slice.
                 LittleEndian::write_u32(&mut compressed_data_buf[1..5], compressed_data_sz as u32);
@@ -447,18 +835,41 @@ impl StoreDriver for CompressionStore {
             };
 
             error_if!(
-                header.version != CURRENT_STREAM_FORMAT_VERSION,
-                "Expected header version to match in get compression, got {}, want {}",
+                !matches!(
+                    header.version,
+                    CURRENT_STREAM_FORMAT_VERSION
+                        | ZSTD_STREAM_FORMAT_VERSION
+                        | STORED_STREAM_FORMAT_VERSION
+                ),
+                "Expected header version to match in get compression, got {}, want {}, {} or {}",
                 header.version,
-                CURRENT_STREAM_FORMAT_VERSION
+                CURRENT_STREAM_FORMAT_VERSION,
+                ZSTD_STREAM_FORMAT_VERSION,
+                STORED_STREAM_FORMAT_VERSION
             );
             error_if!(
-                header.config.block_size > self.config.max_decode_block_size,
+                header.config.block_size > self.max_decode_block_size,
                 "Block size is too large in compression, got {} > {}",
                 header.config.block_size,
-                self.config.max_decode_block_size
+                self.max_decode_block_size
             );
 
+            let mut decoder = match header.version {
+                ZSTD_STREAM_FORMAT_VERSION => {
+                    let mut chunk = rx
+                        .consume(Some(4))
+                        .await
+                        .err_tip(|| "Failed to read dictionary id in compression store")?;
+                    error_if!(
+                        chunk.len() < 4,
+                        "Received EOF too early while reading dictionary id in compression store"
+                    );
+                    self.zstd_block_decoder(chunk.get_u32_le())?
+                }
+                STORED_STREAM_FORMAT_VERSION => BlockDecoder::Stored,
+                _ => BlockDecoder::Lz4,
+            };
+
             let mut chunk = rx
                 .consume(Some(1 + 4))
                 .await
@@ -493,22 +904,8 @@ impl StoreDriver for CompressionStore {
                     ));
                 }
                 {
-                    let max_output_size =
-                        get_maximum_output_size(header.config.block_size as usize);
-                    let mut uncompressed_data = BytesMut::with_capacity(max_output_size);
-
-                    // For efficiency reasons we do some raw slice manipulation so we can write directly
-                    // into our buffer instead of having to do another allocation.
-                    let raw_decompressed_data = unsafe {
-                        std::slice::from_raw_parts_mut(
-                            uncompressed_data.chunk_mut().as_mut_ptr(),
-                            max_output_size,
-                        )
-                    };
-
-                    let uncompressed_chunk_sz = decompress_into(&chunk, raw_decompressed_data)
-                        .map_err(|e| make_err!(Code::Internal, "Decompression error {:?}", e))?;
-                    unsafe { uncompressed_data.advance_mut(uncompressed_chunk_sz) };
+                    let uncompressed_data = decoder.decompress(chunk, header.config.block_size)?;
+                    let uncompressed_chunk_sz = uncompressed_data.len();
                     let new_uncompressed_data_sz =
                         uncompressed_data_sz + uncompressed_chunk_sz as u64;
                     if new_uncompressed_data_sz >= offset && remaining_bytes_to_send > 0 {
@@ -520,7 +917,7 @@ impl StoreDriver for CompressionStore {
                         if end_pos != start_pos {
                             // Make sure we don't send an EOF by accident.
                             writer
-                                .send(uncompressed_data.freeze().slice(start_pos..end_pos))
+                                .send(uncompressed_data.slice(start_pos..end_pos))
                                 .await
                                 .err_tip(|| "Failed sending chunk in compression store")?;
                         }
@@ -619,6 +1016,19 @@ impl StoreDriver for CompressionStore {
     fn as_any_arc(self: Arc<Self>) -> Arc<dyn std::any::Any + Sync + Send + 'static> {
         self
     }
+
+    async fn reconfigure(&self, spec: &StoreSpec, check_only: bool) -> Result<(), Error> {
+        let StoreSpec::compression(spec) = spec else {
+            return Err(make_input_err!(
+                "CompressionStore can only be reconfigured with a compression spec"
+            ));
+        };
+        self.inner_store
+            .as_store_driver()
+            .reconfigure(&spec.backend, check_only)
+            .await
+            .err_tip(|| "In CompressionStore::reconfigure for backend")
+    }
 }
 
 default_health_status_indicator!(CompressionStore);
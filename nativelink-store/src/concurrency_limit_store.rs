@@ -0,0 +1,156 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use nativelink_config::stores::{ConcurrencyLimitSpec, StoreSpec};
+use nativelink_error::{error_if, make_err, make_input_err, Code, Error, ResultExt};
+use nativelink_metric::MetricsComponent;
+use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
+use nativelink_util::health_utils::{default_health_status_indicator, HealthStatusIndicator};
+use nativelink_util::store_trait::{Store, StoreDriver, StoreKey, StoreLike, UploadSizeInfo};
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio::time::timeout;
+
+#[derive(MetricsComponent)]
+pub struct ConcurrencyLimitStore {
+    #[metric(group = "inner_store")]
+    inner_store: Store,
+    #[metric(help = "Maximum number of requests in flight to the inner store")]
+    max_concurrent_requests: usize,
+    permits: Semaphore,
+    queue_timeout: Option<Duration>,
+    #[metric(help = "Number of requests that had to wait for a free slot")]
+    queued_count: AtomicU64,
+    #[metric(help = "Number of requests that timed out waiting for a free slot")]
+    queue_timeout_count: AtomicU64,
+}
+
+impl ConcurrencyLimitStore {
+    pub fn new(spec: &ConcurrencyLimitSpec, inner_store: Store) -> Result<Arc<Self>, Error> {
+        error_if!(
+            spec.max_concurrent_requests == 0,
+            "max_concurrent_requests must be set in ConcurrencyLimitStore"
+        );
+        Ok(Arc::new(Self {
+            inner_store,
+            max_concurrent_requests: spec.max_concurrent_requests,
+            permits: Semaphore::new(spec.max_concurrent_requests),
+            queue_timeout: (spec.queue_timeout_ms != 0)
+                .then(|| Duration::from_millis(spec.queue_timeout_ms)),
+            queued_count: AtomicU64::new(0),
+            queue_timeout_count: AtomicU64::new(0),
+        }))
+    }
+
+    /// Number of requests that can be started right away.
+    pub fn available_permits(&self) -> usize {
+        self.permits.available_permits()
+    }
+
+    async fn acquire(&self) -> Result<SemaphorePermit<'_>, Error> {
+        if let Ok(permit) = self.permits.try_acquire() {
+            return Ok(permit);
+        }
+        self.queued_count.fetch_add(1, Ordering::Relaxed);
+        let acquire_result = match self.queue_timeout {
+            Some(queue_timeout) => timeout(queue_timeout, self.permits.acquire())
+                .await
+                .map_err(|_| {
+                    self.queue_timeout_count.fetch_add(1, Ordering::Relaxed);
+                    make_err!(
+                        Code::ResourceExhausted,
+                        "Timed out after {queue_timeout:?} waiting for one of the {} request slots in ConcurrencyLimitStore",
+                        self.max_concurrent_requests
+                    )
+                })?,
+            None => self.permits.acquire().await,
+        };
+        acquire_result.map_err(|e| {
+            make_err!(
+                Code::Internal,
+                "ConcurrencyLimitStore semaphore closed: {e:?}"
+            )
+        })
+    }
+}
+
+#[async_trait]
+impl StoreDriver for ConcurrencyLimitStore {
+    async fn has_with_results(
+        self: Pin<&Self>,
+        keys: &[StoreKey<'_>],
+        results: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        self.inner_store.has_with_results(keys, results).await
+    }
+
+    async fn update(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        reader: DropCloserReadHalf,
+        upload_size: UploadSizeInfo,
+    ) -> Result<(), Error> {
+        let _permit = self
+            .acquire()
+            .await
+            .err_tip(|| "In ConcurrencyLimitStore::update")?;
+        self.inner_store.update(key, reader, upload_size).await
+    }
+
+    async fn get_part(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        writer: &mut DropCloserWriteHalf,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<(), Error> {
+        let _permit = self
+            .acquire()
+            .await
+            .err_tip(|| "In ConcurrencyLimitStore::get_part")?;
+        self.inner_store.get_part(key, writer, offset, length).await
+    }
+
+    fn inner_store(&self, _key: Option<StoreKey>) -> &'_ dyn StoreDriver {
+        self
+    }
+
+    fn as_any<'a>(&'a self) -> &'a (dyn std::any::Any + Sync + Send + 'static) {
+        self
+    }
+
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn std::any::Any + Sync + Send + 'static> {
+        self
+    }
+
+    async fn reconfigure(&self, spec: &StoreSpec, check_only: bool) -> Result<(), Error> {
+        let StoreSpec::concurrency_limit(spec) = spec else {
+            return Err(make_input_err!(
+                "ConcurrencyLimitStore can only be reconfigured with a concurrency_limit spec"
+            ));
+        };
+        self.inner_store
+            .as_store_driver()
+            .reconfigure(&spec.backend, check_only)
+            .await
+            .err_tip(|| "In ConcurrencyLimitStore::reconfigure for backend")
+    }
+}
+
+default_health_status_indicator!(ConcurrencyLimitStore);
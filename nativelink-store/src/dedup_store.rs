@@ -13,23 +13,30 @@
 // limitations under the License.
 
 use std::cmp;
+use std::collections::{HashMap, HashSet};
 use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
 use bincode::config::{FixintEncoding, WithOtherIntEncoding};
 use bincode::{DefaultOptions, Options};
+use bytes::{Bytes, BytesMut};
 use futures::stream::{self, FuturesOrdered, StreamExt, TryStreamExt};
-use nativelink_config::stores::DedupSpec;
-use nativelink_error::{make_err, Code, Error, ResultExt};
+use nativelink_config::stores::{DedupChunker, DedupSpec, StoreSpec};
+use nativelink_error::{error_if, make_err, make_input_err, Code, Error, ResultExt};
 use nativelink_metric::MetricsComponent;
+use nativelink_util::background_spawn;
 use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
 use nativelink_util::common::DigestInfo;
 use nativelink_util::fastcdc::FastCDC;
 use nativelink_util::health_utils::{default_health_status_indicator, HealthStatusIndicator};
 use nativelink_util::store_trait::{Store, StoreDriver, StoreKey, StoreLike, UploadSizeInfo};
+use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
-use tokio_util::codec::FramedRead;
+use tokio::time::sleep;
+use tokio_util::codec::{Decoder, FramedRead};
 use tokio_util::io::StreamReader;
 use tracing::{event, Level};
 
@@ -42,21 +49,165 @@ const DEFAULT_NORM_SIZE: u64 = 256 * 1024;
 const DEFAULT_MAX_SIZE: u64 = 512 * 1024;
 const DEFAULT_MAX_CONCURRENT_FETCH_PER_GET: usize = 10;
 
+/// Number of indexes that are read at the same time during a garbage
+/// collection.
+const SIMULTANEOUS_GC_INDEX_READS: usize = 16;
+
 #[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
 pub struct DedupIndex {
     pub entries: Vec<DigestInfo>,
 }
 
+/// Splits the content of an upload into chunks.
+#[derive(Clone)]
+enum Chunker {
+    FastCdc(FastCDC),
+    FixedSize(usize),
+}
+
+impl Decoder for Chunker {
+    type Item = Bytes;
+    type Error = std::io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        match self {
+            Self::FastCdc(fast_cdc) => fast_cdc.decode(buf),
+            Self::FixedSize(chunk_size) => {
+                if buf.len() < *chunk_size {
+                    return Ok(None);
+                }
+                Ok(Some(buf.split_to(*chunk_size).freeze()))
+            }
+        }
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if let Self::FastCdc(fast_cdc) = self {
+            return fast_cdc.decode_eof(buf);
+        }
+        if let Some(frame) = self.decode(buf)? {
+            return Ok(Some(frame));
+        }
+        if buf.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(buf.split().freeze()))
+    }
+}
+
+/// Result of a `DedupStore::gc` run.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DedupGcReport {
+    /// Number of indexes that were read.
+    pub indexes: u64,
+    /// Number of chunks referenced by the indexes.
+    pub referenced_chunks: u64,
+    /// Number of unreferenced chunks removed from the content store.
+    pub removed_chunks: u64,
+    /// Sum of the sizes of the removed chunks.
+    pub reclaimed_bytes: u64,
+}
+
+/// Keeps garbage collections from removing the chunks of uploads whose
+/// index is not written yet.
+#[derive(Default)]
+struct GcState {
+    /// Number of in-flight uploads that reference each chunk.
+    pending_chunks: HashMap<DigestInfo, usize>,
+    /// Chunks that uploads referenced since the running collection
+    /// started, or `None` if no collection is running.
+    protected_chunks: Option<HashSet<DigestInfo>>,
+}
+
+/// The chunks referenced by an in-flight upload. They stay protected from
+/// garbage collection until this is dropped.
+struct PendingChunks<'a> {
+    gc_state: &'a Mutex<GcState>,
+    chunks: Mutex<Vec<DigestInfo>>,
+}
+
+impl<'a> PendingChunks<'a> {
+    fn new(gc_state: &'a Mutex<GcState>) -> Self {
+        Self {
+            gc_state,
+            chunks: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn add(&self, chunk: DigestInfo) {
+        let mut gc_state = self.gc_state.lock();
+        *gc_state.pending_chunks.entry(chunk).or_default() += 1;
+        if let Some(protected_chunks) = &mut gc_state.protected_chunks {
+            protected_chunks.insert(chunk);
+        }
+        self.chunks.lock().push(chunk);
+    }
+}
+
+impl Drop for PendingChunks<'_> {
+    fn drop(&mut self) {
+        let mut gc_state = self.gc_state.lock();
+        for chunk in self.chunks.get_mut().drain(..) {
+            if let Some(count) = gc_state.pending_chunks.get_mut(&chunk) {
+                *count -= 1;
+                if *count == 0 {
+                    gc_state.pending_chunks.remove(&chunk);
+                }
+            }
+        }
+    }
+}
+
+/// Marks a garbage collection as running until this is dropped.
+struct GcRun<'a> {
+    gc_state: &'a Mutex<GcState>,
+}
+
+impl<'a> GcRun<'a> {
+    fn start(gc_state: &'a Mutex<GcState>) -> Result<Self, Error> {
+        let mut state = gc_state.lock();
+        if state.protected_chunks.is_some() {
+            return Err(make_err!(
+                Code::FailedPrecondition,
+                "A garbage collection is already running in dedup store"
+            ));
+        }
+        // Uploads that are in flight may write their index after the
+        // indexes have been read.
+        state.protected_chunks = Some(state.pending_chunks.keys().copied().collect());
+        Ok(Self { gc_state })
+    }
+
+    fn is_protected(&self, chunk: &DigestInfo) -> bool {
+        self.gc_state
+            .lock()
+            .protected_chunks
+            .as_ref()
+            .is_some_and(|protected_chunks| protected_chunks.contains(chunk))
+    }
+}
+
+impl Drop for GcRun<'_> {
+    fn drop(&mut self) {
+        self.gc_state.lock().protected_chunks = None;
+    }
+}
+
 #[derive(MetricsComponent)]
 pub struct DedupStore {
     #[metric(group = "index_store")]
     index_store: Store,
     #[metric(group = "content_store")]
     content_store: Store,
-    fast_cdc_decoder: FastCDC,
+    chunker: Chunker,
     #[metric(help = "Maximum number of concurrent fetches per get")]
     max_concurrent_fetch_per_get: usize,
     bincode_options: WithOtherIntEncoding<DefaultOptions, FixintEncoding>,
+    gc_state: Mutex<GcState>,
+    #[metric(help = "Number of unreferenced chunks removed by garbage collection")]
+    gc_removed_chunks: AtomicU64,
+    #[metric(help = "Number of bytes reclaimed by garbage collection")]
+    gc_reclaimed_bytes: AtomicU64,
 }
 
 impl DedupStore {
@@ -85,18 +236,137 @@ impl DedupStore {
         } else {
             spec.max_concurrent_fetch_per_get as usize
         };
-        Ok(Arc::new(Self {
+        let normal_size =
+            usize::try_from(normal_size).err_tip(|| "Could not convert normal_size to usize")?;
+        let chunker = match spec.chunker {
+            DedupChunker::fastcdc => {
+                error_if!(
+                    min_size >= normal_size as u64 || normal_size as u64 >= max_size,
+                    "Expected min_size < normal_size < max_size in dedup store, got {min_size}, {normal_size}, {max_size}"
+                );
+                Chunker::FastCdc(FastCDC::new(
+                    usize::try_from(min_size).err_tip(|| "Could not convert min_size to usize")?,
+                    normal_size,
+                    usize::try_from(max_size).err_tip(|| "Could not convert max_size to usize")?,
+                ))
+            }
+            DedupChunker::fixed_size => Chunker::FixedSize(normal_size),
+        };
+        let store = Arc::new(Self {
             index_store,
             content_store,
-            fast_cdc_decoder: FastCDC::new(
-                usize::try_from(min_size).err_tip(|| "Could not convert min_size to usize")?,
-                usize::try_from(normal_size)
-                    .err_tip(|| "Could not convert normal_size to usize")?,
-                usize::try_from(max_size).err_tip(|| "Could not convert max_size to usize")?,
-            ),
+            chunker,
             max_concurrent_fetch_per_get,
             bincode_options: DefaultOptions::new().with_fixint_encoding(),
-        }))
+            gc_state: Mutex::new(GcState::default()),
+            gc_removed_chunks: AtomicU64::new(0),
+            gc_reclaimed_bytes: AtomicU64::new(0),
+        });
+        if spec.gc_interval_s != 0 {
+            let interval = Duration::from_secs(spec.gc_interval_s);
+            let weak_store = Arc::downgrade(&store);
+            background_spawn!("dedup_store_gc", async move {
+                loop {
+                    sleep(interval).await;
+                    let Some(store) = weak_store.upgrade() else {
+                        return;
+                    };
+                    match store.gc().await {
+                        Ok(report) => event!(
+                            Level::INFO,
+                            ?report,
+                            "Finished garbage collection in dedup store"
+                        ),
+                        Err(err) => {
+                            event!(Level::WARN, ?err, "Failed to garbage collect dedup store")
+                        }
+                    }
+                }
+            });
+        }
+        Ok(store)
+    }
+
+    /// Removes the chunks in the content store that no index in the index
+    /// store references. Chunks of uploads through this store that run at
+    /// the same time are kept, even if their index is not written yet.
+    pub async fn gc(&self) -> Result<DedupGcReport, Error> {
+        let gc_run = GcRun::start(&self.gc_state)?;
+        let mut report = DedupGcReport::default();
+
+        let mut index_keys = Vec::new();
+        self.index_store
+            .list(.., |key| {
+                index_keys.push(key.borrow().into_owned());
+                true
+            })
+            .await
+            .err_tip(|| "Failed to list index store in DedupStore::gc")?;
+        let mut referenced_chunks = HashSet::new();
+        let mut index_reads = stream::iter(index_keys)
+            .map(|key| async move {
+                let result = self
+                    .index_store
+                    .get_part_unchunked(key.borrow(), 0, None)
+                    .await;
+                (key, result)
+            })
+            .buffer_unordered(SIMULTANEOUS_GC_INDEX_READS);
+        while let Some((key, result)) = index_reads.next().await {
+            let data = match result {
+                Ok(data) => data,
+                // The index was evicted after it was listed.
+                Err(err) if err.code == Code::NotFound => continue,
+                Err(err) => {
+                    return Err(err).err_tip(|| "Failed to read index store in DedupStore::gc")
+                }
+            };
+            match self.bincode_options.deserialize::<DedupIndex>(&data) {
+                Ok(index) => {
+                    report.indexes += 1;
+                    referenced_chunks.extend(index.entries);
+                }
+                Err(err) => event!(
+                    Level::WARN,
+                    ?key,
+                    ?err,
+                    "Failed to deserialize index in DedupStore::gc",
+                ),
+            }
+        }
+        drop(index_reads);
+        report.referenced_chunks = referenced_chunks.len() as u64;
+
+        let mut unreferenced_chunks = Vec::new();
+        self.content_store
+            .list(.., |key| {
+                if let StoreKey::Digest(digest) = key {
+                    if !referenced_chunks.contains(digest) {
+                        unreferenced_chunks.push(*digest);
+                    }
+                }
+                true
+            })
+            .await
+            .err_tip(|| "Failed to list content store in DedupStore::gc")?;
+        for chunk in unreferenced_chunks {
+            if gc_run.is_protected(&chunk) {
+                continue;
+            }
+            let removed = self
+                .content_store
+                .remove(chunk)
+                .await
+                .err_tip(|| "Failed to remove chunk in DedupStore::gc")?;
+            if removed {
+                report.removed_chunks += 1;
+                report.reclaimed_bytes += chunk.size_bytes();
+                self.gc_removed_chunks.fetch_add(1, Ordering::Relaxed);
+                self.gc_reclaimed_bytes
+                    .fetch_add(chunk.size_bytes(), Ordering::Relaxed);
+            }
+        }
+        Ok(report)
     }
 
     async fn has(self: Pin<&Self>, key: StoreKey<'_>) -> Result<Option<u64>, Error> {
@@ -187,12 +457,17 @@ impl StoreDriver for DedupStore {
         _size_info: UploadSizeInfo,
     ) -> Result<(), Error> {
         let mut bytes_reader = StreamReader::new(reader);
-        let frame_reader = FramedRead::new(&mut bytes_reader, self.fast_cdc_decoder.clone());
+        let frame_reader = FramedRead::new(&mut bytes_reader, self.chunker.clone());
+        let pending_chunks = PendingChunks::new(&self.gc_state);
+        let pending_chunks = &pending_chunks;
         let index_entries = frame_reader
             .map(|r| r.err_tip(|| "Failed to decode frame from fast_cdc"))
             .map_ok(|frame| async move {
                 let hash = blake3::hash(&frame[..]).into();
                 let index_entry = DigestInfo::new(hash, frame.len() as u64);
+                // Registered before the check, so a garbage collection can't
+                // remove the chunk between the check and the index write.
+                pending_chunks.add(index_entry);
                 if self
                     .content_store
                     .has(index_entry)
@@ -362,6 +637,24 @@ impl StoreDriver for DedupStore {
     fn as_any_arc(self: Arc<Self>) -> Arc<dyn std::any::Any + Sync + Send + 'static> {
         self
     }
+
+    async fn reconfigure(&self, spec: &StoreSpec, check_only: bool) -> Result<(), Error> {
+        let StoreSpec::dedup(spec) = spec else {
+            return Err(make_input_err!(
+                "DedupStore can only be reconfigured with a dedup spec"
+            ));
+        };
+        self.index_store
+            .as_store_driver()
+            .reconfigure(&spec.index_store, check_only)
+            .await
+            .err_tip(|| "In DedupStore::reconfigure for index_store")?;
+        self.content_store
+            .as_store_driver()
+            .reconfigure(&spec.content_store, check_only)
+            .await
+            .err_tip(|| "In DedupStore::reconfigure for content_store")
+    }
 }
 
 default_health_status_indicator!(DedupStore);
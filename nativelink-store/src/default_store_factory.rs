@@ -14,7 +14,7 @@
 
 use std::pin::Pin;
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 use futures::stream::FuturesOrdered;
 use futures::{Future, TryStreamExt};
@@ -23,18 +23,28 @@ use nativelink_error::Error;
 use nativelink_util::health_utils::HealthRegistryBuilder;
 use nativelink_util::store_trait::{Store, StoreDriver};
 
+use crate::ac_retention_store::AcRetentionStore;
+use crate::azure_blob_store::AzureBlobStore;
 use crate::completeness_checking_store::CompletenessCheckingStore;
 use crate::compression_store::CompressionStore;
+use crate::concurrency_limit_store::ConcurrencyLimitStore;
 use crate::dedup_store::DedupStore;
+use crate::encryption_store::EncryptionStore;
 use crate::existence_cache_store::ExistenceCacheStore;
+use crate::failover_store::FailoverStore;
 use crate::fast_slow_store::FastSlowStore;
 use crate::filesystem_store::FilesystemStore;
+use crate::gcs_store::GcsStore;
 use crate::grpc_store::GrpcStore;
+use crate::http_store::HttpStore;
 use crate::memory_store::MemoryStore;
+use crate::mirror_store::MirrorStore;
 use crate::noop_store::NoopStore;
+use crate::quota_store::QuotaStore;
+use crate::rate_limit_store::RateLimitStore;
 use crate::redis_store::RedisStore;
 use crate::ref_store::RefStore;
-use crate::s3_store::S3Store;
+use crate::s3_store::DefaultS3Store;
 use crate::shard_store::ShardStore;
 use crate::size_partitioning_store::SizePartitioningStore;
 use crate::store_manager::StoreManager;
@@ -49,8 +59,21 @@ pub fn store_factory<'a>(
 ) -> Pin<FutureMaybeStore<'a>> {
     Box::pin(async move {
         let store: Arc<dyn StoreDriver> = match backend {
-            StoreSpec::memory(spec) => MemoryStore::new(spec),
-            StoreSpec::experimental_s3_store(spec) => S3Store::new(spec, SystemTime::now).await?,
+            StoreSpec::memory(spec) => {
+                let spill_store = match &spec.spill {
+                    Some(spill_spec) => Some(Store::new(<FilesystemStore>::new(spill_spec).await?)),
+                    None => None,
+                };
+                MemoryStore::new_with_spill_store(spec, spill_store)
+            }
+            StoreSpec::experimental_s3_store(spec) => {
+                DefaultS3Store::new(spec, SystemTime::now).await?
+            }
+            StoreSpec::experimental_gcs_store(spec) => GcsStore::new(spec, SystemTime::now)?,
+            StoreSpec::experimental_azure_blob_store(spec) => {
+                AzureBlobStore::new(spec, SystemTime::now)?
+            }
+            StoreSpec::experimental_http_store(spec) => HttpStore::new(spec)?,
             StoreSpec::redis_store(spec) => RedisStore::new(spec.clone())?,
             StoreSpec::verify(spec) => VerifyStore::new(
                 spec,
@@ -60,6 +83,10 @@ pub fn store_factory<'a>(
                 &spec.clone(),
                 store_factory(&spec.backend, store_manager, None).await?,
             )?,
+            StoreSpec::encryption(spec) => EncryptionStore::new(
+                spec,
+                store_factory(&spec.backend, store_manager, None).await?,
+            )?,
             StoreSpec::dedup(spec) => DedupStore::new(
                 spec,
                 store_factory(&spec.index_store, store_manager, None).await?,
@@ -69,15 +96,53 @@ pub fn store_factory<'a>(
                 spec,
                 store_factory(&spec.backend, store_manager, None).await?,
             ),
-            StoreSpec::completeness_checking(spec) => CompletenessCheckingStore::new(
+            StoreSpec::rate_limit(spec) => RateLimitStore::new(
+                spec,
+                store_factory(&spec.backend, store_manager, None).await?,
+            ),
+            StoreSpec::concurrency_limit(spec) => ConcurrencyLimitStore::new(
+                spec,
+                store_factory(&spec.backend, store_manager, None).await?,
+            )?,
+            StoreSpec::quota(spec) => QuotaStore::new(
+                spec,
                 store_factory(&spec.backend, store_manager, None).await?,
-                store_factory(&spec.cas_store, store_manager, None).await?,
             ),
+            StoreSpec::completeness_checking(spec) => {
+                let store = CompletenessCheckingStore::new(
+                    store_factory(&spec.backend, store_manager, None).await?,
+                    store_factory(&spec.cas_store, store_manager, None).await?,
+                );
+                if spec.gc_interval_s != 0 {
+                    store.start_periodic_gc(
+                        Duration::from_secs(spec.gc_interval_s),
+                        spec.gc_remove_unreachable,
+                    );
+                }
+                store
+            }
+            StoreSpec::ac_retention(spec) => {
+                let store = AcRetentionStore::new(
+                    spec,
+                    store_factory(&spec.backend, store_manager, None).await?,
+                    SystemTime::now,
+                );
+                if spec.sweep_interval_s != 0 {
+                    store.start_periodic_sweep(Duration::from_secs(spec.sweep_interval_s));
+                }
+                store
+            }
             StoreSpec::fast_slow(spec) => FastSlowStore::new(
                 spec,
                 store_factory(&spec.fast, store_manager, None).await?,
                 store_factory(&spec.slow, store_manager, None).await?,
             ),
+            StoreSpec::failover(spec) => FailoverStore::new(
+                spec,
+                store_factory(&spec.primary, store_manager, None).await?,
+                store_factory(&spec.secondary, store_manager, None).await?,
+                SystemTime::now,
+            ),
             StoreSpec::filesystem(spec) => <FilesystemStore>::new(spec).await?,
             StoreSpec::ref_store(spec) => RefStore::new(spec, Arc::downgrade(store_manager)),
             StoreSpec::size_partitioning(spec) => SizePartitioningStore::new(
@@ -97,6 +162,16 @@ pub fn store_factory<'a>(
                     .await?;
                 ShardStore::new(spec, stores)?
             }
+            StoreSpec::mirror(spec) => {
+                let stores = spec
+                    .stores
+                    .iter()
+                    .map(|store_spec| store_factory(store_spec, store_manager, None))
+                    .collect::<FuturesOrdered<_>>()
+                    .try_collect::<Vec<_>>()
+                    .await?;
+                MirrorStore::new(spec, stores)?
+            }
         };
 
         if let Some(health_registry_builder) = maybe_health_registry_builder {
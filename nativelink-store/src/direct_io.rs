@@ -0,0 +1,224 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Reads and writes of files that bypass the page cache with `O_DIRECT`.
+
+use std::fs::File;
+use std::os::unix::fs::FileExt;
+use std::sync::{Arc, Once};
+
+use bytes::{Bytes, BytesMut};
+use nativelink_error::{make_err, Code, Error, ResultExt};
+use nativelink_util::fs;
+use nativelink_util::spawn_blocking;
+use tracing::{event, Level};
+
+/// Alignment of the offsets, lengths and buffers of every read and write.
+/// This is the logical block size of virtually every device.
+pub const DIRECT_IO_ALIGNMENT: usize = 4096;
+
+static DIRECT_IO_UNSUPPORTED: Once = Once::new();
+
+/// Sets `O_DIRECT` on `file`. Filesystems that don't support it, like
+/// tmpfs, keep using the page cache.
+#[cfg(target_os = "linux")]
+fn set_direct_io(file: &File) -> Result<(), Error> {
+    use std::os::fd::AsRawFd;
+
+    let fd = file.as_raw_fd();
+    // Safety: `fd` is a valid descriptor owned by `file`.
+    let result = unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        if flags < 0 {
+            flags
+        } else {
+            libc::fcntl(fd, libc::F_SETFL, flags | libc::O_DIRECT)
+        }
+    };
+    if result < 0 {
+        let err = std::io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::EINVAL) {
+            return Err(err).err_tip(|| "Failed to set O_DIRECT on file");
+        }
+        DIRECT_IO_UNSUPPORTED.call_once(|| {
+            event!(
+                Level::WARN,
+                "Filesystem does not support O_DIRECT, falling back to the page cache"
+            );
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_direct_io(_file: &File) -> Result<(), Error> {
+    DIRECT_IO_UNSUPPORTED.call_once(|| {
+        event!(
+            Level::WARN,
+            "O_DIRECT is only supported on Linux, falling back to the page cache"
+        );
+    });
+    Ok(())
+}
+
+/// Returns a zeroed buffer with room for `len` bytes at an aligned address
+/// and the offset of that address in the buffer.
+fn aligned_buffer(len: usize) -> (BytesMut, usize) {
+    let buf = BytesMut::zeroed(len + DIRECT_IO_ALIGNMENT);
+    let start = buf.as_ptr().align_offset(DIRECT_IO_ALIGNMENT);
+    (buf, start)
+}
+
+/// A file opened through a `ResumeableFileSlot` that is read or written
+/// with `O_DIRECT`, so its data does not end up in the page cache.
+///
+/// Every read and write is widened to `DIRECT_IO_ALIGNMENT`. Writes hold
+/// back the unaligned tail until `finish()` writes it padded and truncates
+/// the file to its real size.
+pub struct DirectFile {
+    slot: fs::ResumeableFileSlot,
+    file: Option<Arc<File>>,
+    position: u64,
+    remaining: u64,
+    pending: BytesMut,
+}
+
+impl DirectFile {
+    /// Starts at the current position of `slot` and stops at its limit.
+    pub async fn new(mut slot: fs::ResumeableFileSlot) -> Result<Self, Error> {
+        let position = slot
+            .stream_position()
+            .await
+            .err_tip(|| "In DirectFile::new")?;
+        let remaining = slot
+            .as_reader()
+            .await
+            .err_tip(|| "In DirectFile::new")?
+            .limit();
+        Ok(Self {
+            slot,
+            file: None,
+            position,
+            remaining,
+            pending: BytesMut::new(),
+        })
+    }
+
+    async fn file(&mut self) -> Result<Arc<File>, Error> {
+        if let Some(file) = &self.file {
+            return Ok(file.clone());
+        }
+        let file = self
+            .slot
+            .as_writer()
+            .await
+            .err_tip(|| "In DirectFile::file")?
+            .as_ref()
+            .try_clone()
+            .await
+            .err_tip(|| "Failed to duplicate file descriptor for O_DIRECT")?
+            .into_std()
+            .await;
+        set_direct_io(&file)?;
+        let file = Arc::new(file);
+        self.file = Some(file.clone());
+        Ok(file)
+    }
+
+    /// Reads up to `max_len` bytes. An empty buffer means EOF.
+    pub async fn read_chunk(&mut self, max_len: usize) -> Result<BytesMut, Error> {
+        let want = usize::try_from(self.remaining)
+            .unwrap_or(usize::MAX)
+            .min(max_len);
+        if want == 0 {
+            return Ok(BytesMut::new());
+        }
+        let file = self.file().await?;
+        let skip = (self.position % DIRECT_IO_ALIGNMENT as u64) as usize;
+        let aligned_offset = self.position - skip as u64;
+        let read_len = (skip + want).next_multiple_of(DIRECT_IO_ALIGNMENT);
+        let (mut buf, start) = aligned_buffer(read_len);
+        let (mut buf, read) = spawn_blocking!("filesystem_store_direct_read", move || {
+            let read = file.read_at(&mut buf[start..start + read_len], aligned_offset);
+            (buf, read)
+        })
+        .await
+        .map_err(|e| make_err!(Code::Internal, "Direct read task failed: {e:?}"))?;
+        let read = read.err_tip(|| "Failed to read file with O_DIRECT")?;
+        let _ = buf.split_to(start + skip);
+        buf.truncate(read.saturating_sub(skip).min(want));
+        self.position += buf.len() as u64;
+        self.remaining -= buf.len() as u64;
+        Ok(buf)
+    }
+
+    /// Writes `data` after the data written before. Only whole aligned
+    /// blocks are written, the rest is kept for the next call.
+    pub async fn write_all(&mut self, data: Bytes) -> Result<(), Error> {
+        self.pending.extend_from_slice(&data);
+        let aligned_len = self.pending.len() - self.pending.len() % DIRECT_IO_ALIGNMENT;
+        if aligned_len == 0 {
+            return Ok(());
+        }
+        let blocks = self.pending.split_to(aligned_len);
+        self.write_blocks(&blocks).await
+    }
+
+    /// Writes the data held back by `write_all()` and truncates the file to
+    /// the amount of data written.
+    pub async fn finish(&mut self) -> Result<(), Error> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let tail = self.pending.split();
+        let tail_len = tail.len() as u64;
+        let mut blocks = BytesMut::zeroed(tail.len().next_multiple_of(DIRECT_IO_ALIGNMENT));
+        blocks[..tail.len()].copy_from_slice(&tail);
+        self.write_blocks(&blocks).await?;
+        let file = self.file().await?;
+        let final_len = self.position - blocks.len() as u64 + tail_len;
+        self.position = final_len;
+        spawn_blocking!("filesystem_store_direct_truncate", move || {
+            file.set_len(final_len)
+        })
+        .await
+        .map_err(|e| make_err!(Code::Internal, "Direct truncate task failed: {e:?}"))?
+        .err_tip(|| "Failed to truncate file written with O_DIRECT")
+    }
+
+    async fn write_blocks(&mut self, blocks: &[u8]) -> Result<(), Error> {
+        let file = self.file().await?;
+        let (mut buf, start) = aligned_buffer(blocks.len());
+        buf[start..start + blocks.len()].copy_from_slice(blocks);
+        let len = blocks.len();
+        let offset = self.position;
+        spawn_blocking!("filesystem_store_direct_write", move || {
+            file.write_all_at(&buf[start..start + len], offset)
+        })
+        .await
+        .map_err(|e| make_err!(Code::Internal, "Direct write task failed: {e:?}"))?
+        .err_tip(|| "Failed to write file with O_DIRECT")?;
+        self.position += len as u64;
+        Ok(())
+    }
+
+    pub async fn close_file(&mut self) -> Result<(), Error> {
+        self.file = None;
+        self.slot.close_file().await
+    }
+
+    pub fn into_inner(self) -> fs::ResumeableFileSlot {
+        self.slot
+    }
+}
@@ -0,0 +1,579 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures::future::FutureExt;
+use nativelink_config::stores::{EncryptionSpec, StoreSpec};
+use nativelink_error::{error_if, make_err, make_input_err, Code, Error, ResultExt};
+use nativelink_metric::MetricsComponent;
+use nativelink_util::buf_channel::{
+    make_buf_channel_pair, DropCloserReadHalf, DropCloserWriteHalf,
+};
+use nativelink_util::health_utils::{default_health_status_indicator, HealthStatusIndicator};
+use nativelink_util::spawn;
+use nativelink_util::store_trait::{Store, StoreDriver, StoreKey, StoreLike, UploadSizeInfo};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::digest::{digest, SHA256};
+use ring::rand::{SecureRandom, SystemRandom};
+use tracing::{event, Level};
+
+use crate::cas_utils::is_zero_digest;
+
+// In the event the stream format changes this number should be incremented to prevent
+// backwards compatibility issues.
+pub const CURRENT_STREAM_FORMAT_VERSION: u8 = 1;
+
+// Default block size that will be used to slice stream into.
+// Note: If you change these, adjust the docs in the config.
+pub const DEFAULT_BLOCK_SIZE: u32 = 64 * 1024;
+const DEFAULT_MAX_DECODE_BLOCK_SIZE: u32 = 4 * 1024 * 1024;
+
+const KEY_LEN: usize = 32;
+const KEY_ID_LEN: usize = 4;
+const TAG_LEN: u64 = 16;
+
+/// Size of the header in front of the encrypted blocks.
+pub const HEADER_SIZE: u64 =
+    1 + 4 + KEY_ID_LEN as u64 + NONCE_LEN as u64 + KEY_LEN as u64 + TAG_LEN;
+
+// Every object is encrypted with its own random data key, so the block nonces can be a
+// simple counter without ever being reused for the same key. The data key is stored in
+// the header, encrypted (wrapped) by the configured key.
+//
+// Blocks are of a fixed size, so the position of any byte in the encrypted stream can be
+// calculated from the header alone, which allows reading a part of the data without
+// reading everything before it. The last block is always shorter than `block_size` (it
+// may be empty) and is marked as last in its associated data, so a truncated stream can
+// not be passed off as a complete one.
+//
+// The stream format is as follows:
+// |----------------------------------HEADER-----------------------------------------|
+// |  version(u8) |  block_size (u32) |  key_id [u8; 4] |  wrap_nonce [u8; 12]        |
+// |  wrapped_data_key [u8; 32 + 16]                                                 |
+// |----------------------------------BLOCK------------------------------------------|
+// |  ...ENCRYPTED DATA (block_size)... |  tag [u8; 16]                              |
+// | [Possibly repeat block]                                                         |
+// |----------------------------------LAST BLOCK-------------------------------------|
+// |  ...ENCRYPTED DATA (< block_size)... |  tag [u8; 16]                            |
+// |---------------------------------------------------------------------------------|
+//
+// version              - A constant number used to define what version of this format is
+//                        being used.
+// block_size           - Size of each block unencrypted, except for the last block.
+// key_id               - The first bytes of the SHA-256 of the key that wrapped the data key.
+// wrap_nonce           - Random nonce used to wrap the data key.
+// wrapped_data_key     - The data key encrypted with the key. The preceding header fields
+//                        and the store key of the object are its associated data, so they
+//                        can not be modified and the object can not be moved to another
+//                        store key either.
+// tag                  - Authentication tag of the block. The nonce of a block is its index
+//                        and the associated data is 1 for the last block and 0 otherwise.
+//
+// Note: All fields little-endian.
+
+/// Size of the data once encrypted with the given block size.
+pub fn encrypted_size(size: u64, block_size: u32) -> u64 {
+    let block_count = size / u64::from(block_size) + 1;
+    HEADER_SIZE + size + block_count * TAG_LEN
+}
+
+/// Size of the data before it was encrypted with the given block size, or
+/// None if no data encrypts to this size.
+pub fn decrypted_size(encrypted_size: u64, block_size: u32) -> Option<u64> {
+    let encrypted_block_size = u64::from(block_size) + TAG_LEN;
+    let blocks_size = encrypted_size.checked_sub(HEADER_SIZE)?;
+    let last_block_size = (blocks_size % encrypted_block_size).checked_sub(TAG_LEN)?;
+    Some(blocks_size / encrypted_block_size * u64::from(block_size) + last_block_size)
+}
+
+fn block_nonce(block_index: u64) -> Nonce {
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce[NONCE_LEN - 8..].copy_from_slice(&block_index.to_be_bytes());
+    Nonce::assume_unique_for_key(nonce)
+}
+
+fn block_aad(is_last: bool) -> Aad<[u8; 1]> {
+    Aad::from([u8::from(is_last)])
+}
+
+fn wrap_aad(header_fields: &[u8], key: &StoreKey<'_>) -> Vec<u8> {
+    let mut aad = header_fields.to_vec();
+    aad.extend_from_slice(key.as_str().as_bytes());
+    aad
+}
+
+fn make_key(key: &[u8]) -> Result<LessSafeKey, Error> {
+    let key = UnboundKey::new(&AES_256_GCM, key)
+        .map_err(|_| make_input_err!("Expected a {KEY_LEN} byte key in encryption store"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+struct EncryptionKey {
+    id: [u8; KEY_ID_LEN],
+    key: LessSafeKey,
+}
+
+impl EncryptionKey {
+    fn from_base64(encoded: &str) -> Result<Self, Error> {
+        let key = STANDARD
+            .decode(encoded.trim())
+            .map_err(|e| make_input_err!("Failed to decode base64 key in encryption store: {e}"))?;
+        error_if!(
+            key.len() != KEY_LEN,
+            "Expected a {KEY_LEN} byte key in encryption store, got {} bytes",
+            key.len()
+        );
+        let mut id = [0u8; KEY_ID_LEN];
+        id.copy_from_slice(&digest(&SHA256, &key).as_ref()[..KEY_ID_LEN]);
+        Ok(Self {
+            id,
+            key: make_key(&key)?,
+        })
+    }
+}
+
+/// This store will encrypt data before sending it on to the inner store and
+/// decrypt it when it is read back.
+#[derive(MetricsComponent)]
+pub struct EncryptionStore {
+    #[metric(group = "inner_store")]
+    inner_store: Store,
+    #[metric(help = "Size of the blocks that are encrypted individually")]
+    block_size: u32,
+    #[metric(help = "Maximum block size allowed when decrypting data")]
+    max_decode_block_size: u32,
+    /// The first key is used to encrypt, all of them to decrypt.
+    keys: Vec<EncryptionKey>,
+    rng: SystemRandom,
+}
+
+impl EncryptionStore {
+    pub fn new(spec: &EncryptionSpec, inner_store: Store) -> Result<Arc<Self>, Error> {
+        let key = match (&spec.key, &spec.key_file) {
+            (Some(key), None) => key.clone(),
+            (None, Some(key_file)) => std::fs::read_to_string(key_file)
+                .err_tip(|| format!("Failed to read key_file {key_file} in encryption store"))?,
+            _ => {
+                return Err(make_input_err!(
+                    "Exactly one of key and key_file must be set in encryption store"
+                ))
+            }
+        };
+        let keys = std::iter::once(key.as_str())
+            .chain(spec.decryption_keys.iter().map(String::as_str))
+            .map(EncryptionKey::from_base64)
+            .collect::<Result<Vec<_>, _>>()?;
+        for (i, key) in keys.iter().enumerate() {
+            error_if!(
+                keys[..i].iter().any(|other| other.id == key.id),
+                "Duplicate key in encryption store"
+            );
+        }
+        let block_size = if spec.block_size == 0 {
+            DEFAULT_BLOCK_SIZE
+        } else {
+            spec.block_size
+        };
+        let max_decode_block_size = if spec.max_decode_block_size == 0 {
+            cmp::max(DEFAULT_MAX_DECODE_BLOCK_SIZE, block_size)
+        } else {
+            spec.max_decode_block_size
+        };
+        Ok(Arc::new(EncryptionStore {
+            inner_store,
+            block_size,
+            max_decode_block_size,
+            keys,
+            rng: SystemRandom::new(),
+        }))
+    }
+
+    fn fill_random(&self, buf: &mut [u8]) -> Result<(), Error> {
+        self.rng.fill(buf).map_err(|_| {
+            make_err!(
+                Code::Internal,
+                "Failed to generate random bytes in encryption store"
+            )
+        })
+    }
+
+    /// Create a new data key and the header that stores it for `key`.
+    fn make_header(&self, key: &StoreKey<'_>) -> Result<(Bytes, LessSafeKey), Error> {
+        let mut data_key = [0u8; KEY_LEN];
+        self.fill_random(&mut data_key)?;
+        let mut wrap_nonce = [0u8; NONCE_LEN];
+        self.fill_random(&mut wrap_nonce)?;
+
+        let encryption_key = &self.keys[0];
+        let mut header = BytesMut::with_capacity(HEADER_SIZE as usize);
+        header.put_u8(CURRENT_STREAM_FORMAT_VERSION);
+        header.put_u32_le(self.block_size);
+        header.put_slice(&encryption_key.id);
+        header.put_slice(&wrap_nonce);
+        let mut wrapped_data_key = data_key.to_vec();
+        encryption_key
+            .key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(wrap_nonce),
+                Aad::from(wrap_aad(&header, key)),
+                &mut wrapped_data_key,
+            )
+            .map_err(|_| {
+                make_err!(
+                    Code::Internal,
+                    "Failed to wrap data key in encryption store"
+                )
+            })?;
+        header.put_slice(&wrapped_data_key);
+        Ok((header.freeze(), make_key(&data_key)?))
+    }
+
+    /// Parse the header of the object stored under `key` and unwrap its data
+    /// key. Returns the block size and the data key.
+    fn read_header(
+        &self,
+        mut header: Bytes,
+        key: &StoreKey<'_>,
+    ) -> Result<(u32, LessSafeKey), Error> {
+        error_if!(
+            header.len() as u64 != HEADER_SIZE,
+            "Expected inner store to return the proper amount of data in encryption store {} != {}",
+            header.len(),
+            HEADER_SIZE
+        );
+        let aad = header.split_to(header.len() - KEY_LEN - TAG_LEN as usize);
+        let mut fields = aad.clone();
+        let version = fields.get_u8();
+        error_if!(
+            version != CURRENT_STREAM_FORMAT_VERSION,
+            "Expected header version to match in encryption store, got {}, want {}",
+            version,
+            CURRENT_STREAM_FORMAT_VERSION
+        );
+        let block_size = fields.get_u32_le();
+        error_if!(
+            block_size == 0 || block_size > self.max_decode_block_size,
+            "Block size is invalid or too large in encryption store, got {} > {}",
+            block_size,
+            self.max_decode_block_size
+        );
+        let key_id = fields.split_to(KEY_ID_LEN);
+        let Some(encryption_key) = self.keys.iter().find(|key| key.id[..] == key_id[..]) else {
+            return Err(make_err!(
+                Code::FailedPrecondition,
+                "Data was encrypted with an unknown key {} in encryption store",
+                hex::encode(&key_id)
+            ));
+        };
+        let wrap_nonce: [u8; NONCE_LEN] = fields[..]
+            .try_into()
+            .map_err(|_| make_err!(Code::Internal, "Invalid nonce in encryption store"))?;
+        let mut wrapped_data_key = header.to_vec();
+        let data_key = encryption_key
+            .key
+            .open_in_place(
+                Nonce::assume_unique_for_key(wrap_nonce),
+                Aad::from(wrap_aad(&aad, key)),
+                &mut wrapped_data_key,
+            )
+            .map_err(|_| {
+                make_err!(
+                    Code::DataLoss,
+                    "Failed to unwrap data key in encryption store, the header was modified or the data was written under another key"
+                )
+            })?;
+        Ok((block_size, make_key(data_key)?))
+    }
+}
+
+#[async_trait]
+impl StoreDriver for EncryptionStore {
+    async fn has_with_results(
+        self: Pin<&Self>,
+        keys: &[StoreKey<'_>],
+        results: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        self.inner_store.has_with_results(keys, results).await?;
+        for (key, result) in keys.iter().zip(results.iter_mut()) {
+            if is_zero_digest(key.borrow()) {
+                continue;
+            }
+            let Some(size) = *result else {
+                continue;
+            };
+            *result = decrypted_size(size, self.block_size);
+            if result.is_none() {
+                event!(
+                    Level::WARN,
+                    ?key,
+                    size,
+                    "Unexpected size of encrypted data in encryption store, treating as missing"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    async fn update(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        mut reader: DropCloserReadHalf,
+        upload_size: UploadSizeInfo,
+    ) -> Result<(), Error> {
+        let (input_max_size, inner_upload_size) = match upload_size {
+            UploadSizeInfo::ExactSize(sz) => (
+                sz,
+                UploadSizeInfo::ExactSize(encrypted_size(sz, self.block_size)),
+            ),
+            UploadSizeInfo::MaxSize(sz) => (
+                sz,
+                UploadSizeInfo::MaxSize(encrypted_size(sz, self.block_size)),
+            ),
+        };
+        let (header, data_key) = self.make_header(&key)?;
+
+        let (mut tx, rx) = make_buf_channel_pair();
+
+        let inner_store = self.inner_store.clone();
+        let key = key.into_owned();
+        let update_fut = spawn!("encryption_store_update_spawn", async move {
+            inner_store
+                .update(key, rx, inner_upload_size)
+                .await
+                .err_tip(|| "Inner store update in encryption store failed")
+        })
+        .map(
+            |result| match result.err_tip(|| "Failed to run encryption update spawn") {
+                Ok(inner_result) => inner_result,
+                Err(e) => Err(e),
+            },
+        );
+
+        let write_fut = async move {
+            tx.send(header)
+                .await
+                .err_tip(|| "Failed to write encryption header on upload")?;
+
+            let mut received_amt: u64 = 0;
+            for block_index in 0.. {
+                let chunk = reader
+                    .consume(Some(self.block_size as usize))
+                    .await
+                    .err_tip(|| "Failed to read take in update in encryption store")?;
+                received_amt += chunk.len() as u64;
+                error_if!(
+                    received_amt > input_max_size,
+                    "Got more data than stated in encryption store upload request"
+                );
+
+                // A full block is never the last one, so data that is a multiple of
+                // the block size ends with an empty block.
+                let is_last = chunk.len() < self.block_size as usize;
+                let mut block = BytesMut::with_capacity(chunk.len() + TAG_LEN as usize);
+                block.extend_from_slice(&chunk);
+                data_key
+                    .seal_in_place_append_tag(
+                        block_nonce(block_index),
+                        block_aad(is_last),
+                        &mut block,
+                    )
+                    .map_err(|_| {
+                        make_err!(
+                            Code::Internal,
+                            "Failed to encrypt block in encryption store"
+                        )
+                    })?;
+                tx.send(block.freeze())
+                    .await
+                    .err_tip(|| "Failed to write block to inner store in encryption store")?;
+                if is_last {
+                    break;
+                }
+            }
+            tx.send_eof()
+                .err_tip(|| "Failed writing EOF in encryption store update")
+        };
+        let (write_result, update_result) = tokio::join!(write_fut, update_fut);
+        write_result.merge(update_result)
+    }
+
+    async fn get_part(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        writer: &mut DropCloserWriteHalf,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<(), Error> {
+        if is_zero_digest(key.borrow()) {
+            writer
+                .send_eof()
+                .err_tip(|| "Failed to send zero EOF in encryption store get_part")?;
+            return Ok(());
+        }
+        let end_pos = length
+            .map(|length| offset.checked_add(length))
+            .unwrap_or(Some(u64::MAX))
+            .err_tip(|| "Integer overflow protection triggered")?;
+
+        // Partial reads fetch the header on its own, so only the blocks that
+        // contain the requested range need to be read.
+        let partial_read_header = if offset == 0 && length.is_none() {
+            None
+        } else {
+            let header = self
+                .inner_store
+                .get_part_unchunked(key.borrow(), 0, Some(HEADER_SIZE))
+                .await
+                .err_tip(|| "Failed to read header in encryption store get_part")?;
+            Some(self.read_header(header, &key)?)
+        };
+        let (inner_offset, inner_length, first_block_index) = match &partial_read_header {
+            None => (0, None, 0),
+            Some((block_size, _)) => {
+                let block_size = u64::from(*block_size);
+                let first_block_index = offset / block_size;
+                let end_block_index = end_pos.div_ceil(block_size).max(first_block_index + 1);
+                let encrypted_block_size = block_size + TAG_LEN;
+                (
+                    HEADER_SIZE + first_block_index * encrypted_block_size,
+                    length.map(|_| (end_block_index - first_block_index) * encrypted_block_size),
+                    first_block_index,
+                )
+            }
+        };
+
+        let (tx, mut rx) = make_buf_channel_pair();
+
+        let inner_store = self.inner_store.clone();
+        let key = key.into_owned();
+        let header_key = key.clone();
+        let get_part_fut = spawn!("encryption_store_get_part_spawn", async move {
+            inner_store
+                .get_part(key, tx, inner_offset, inner_length)
+                .await
+                .err_tip(|| "Inner store get in encryption store failed")
+        })
+        .map(
+            |result| match result.err_tip(|| "Failed to run encryption get spawn") {
+                Ok(inner_result) => inner_result,
+                Err(e) => Err(e),
+            },
+        );
+        let read_fut = async move {
+            let (block_size, data_key) = match partial_read_header {
+                Some(header) => header,
+                None => {
+                    let header = rx
+                        .consume(Some(HEADER_SIZE as usize))
+                        .await
+                        .err_tip(|| "Failed to read header in get_part encryption store")?;
+                    self.read_header(header, &header_key)?
+                }
+            };
+            let encrypted_block_size = block_size as usize + TAG_LEN as usize;
+
+            let mut block_index = first_block_index;
+            let mut block_start_pos = first_block_index * u64::from(block_size);
+            while block_start_pos < end_pos {
+                let chunk = rx
+                    .consume(Some(encrypted_block_size))
+                    .await
+                    .err_tip(|| "Failed to read block in get_part encryption store")?;
+                if (chunk.len() as u64) < TAG_LEN {
+                    return Err(make_err!(
+                        Code::DataLoss,
+                        "Unexpected EOF when reading block {} in encryption store get_part, the data was truncated",
+                        block_index
+                    ));
+                }
+                let is_last = chunk.len() < encrypted_block_size;
+                let mut block = BytesMut::from(&chunk[..]);
+                let block_len = data_key
+                    .open_in_place(block_nonce(block_index), block_aad(is_last), &mut block)
+                    .map_err(|_| {
+                        make_err!(
+                            Code::DataLoss,
+                            "Failed to decrypt block {} in encryption store, the data was modified",
+                            block_index
+                        )
+                    })?
+                    .len();
+                block.truncate(block_len);
+                let start = cmp::min(offset.saturating_sub(block_start_pos), block_len as u64);
+                let end = cmp::min(end_pos - block_start_pos, block_len as u64);
+                if start < end {
+                    writer
+                        .send(block.freeze().slice(start as usize..end as usize))
+                        .await
+                        .err_tip(|| "Failed sending chunk in encryption store")?;
+                }
+                if is_last {
+                    break;
+                }
+                block_index += 1;
+                block_start_pos += u64::from(block_size);
+            }
+            rx.drain()
+                .await
+                .err_tip(|| "Failed to drain inner store in encryption store get_part")?;
+            writer
+                .send_eof()
+                .err_tip(|| "Failed to send eof in encryption store get_part")
+        };
+
+        let (read_result, get_part_fut_result) = tokio::join!(read_fut, get_part_fut);
+        if let Err(mut e) = read_result {
+            // We may need to propagate the error from reading the data through first.
+            if let Err(err) = get_part_fut_result {
+                e = err.merge(e);
+            }
+            return Err(e);
+        }
+        get_part_fut_result
+    }
+
+    fn inner_store(&self, _digest: Option<StoreKey>) -> &dyn StoreDriver {
+        self
+    }
+
+    fn as_any(&self) -> &(dyn std::any::Any + Sync + Send + 'static) {
+        self
+    }
+
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn std::any::Any + Sync + Send + 'static> {
+        self
+    }
+
+    async fn reconfigure(&self, spec: &StoreSpec, check_only: bool) -> Result<(), Error> {
+        let StoreSpec::encryption(spec) = spec else {
+            return Err(make_input_err!(
+                "EncryptionStore can only be reconfigured with an encryption spec"
+            ));
+        };
+        self.inner_store
+            .as_store_driver()
+            .reconfigure(&spec.backend, check_only)
+            .await
+            .err_tip(|| "In EncryptionStore::reconfigure for backend")
+    }
+}
+
+default_health_status_indicator!(EncryptionStore);
@@ -18,8 +18,8 @@ use std::sync::Arc;
 use std::time::SystemTime;
 
 use async_trait::async_trait;
-use nativelink_config::stores::{EvictionPolicy, ExistenceCacheSpec};
-use nativelink_error::{error_if, Error, ResultExt};
+use nativelink_config::stores::{EvictionPolicy, ExistenceCacheSpec, StoreSpec};
+use nativelink_error::{error_if, make_input_err, Error, ResultExt};
 use nativelink_metric::MetricsComponent;
 use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
 use nativelink_util::common::DigestInfo;
@@ -43,11 +43,28 @@ impl LenEntry for ExistanceItem {
     }
 }
 
+/// Marks a digest the inner store reported as missing.
+#[derive(Clone, Debug)]
+struct MissingItem;
+
+impl LenEntry for MissingItem {
+    #[inline]
+    fn len(&self) -> u64 {
+        1
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        false
+    }
+}
+
 #[derive(MetricsComponent)]
 pub struct ExistenceCacheStore<I: InstantWrapper> {
     #[metric(group = "inner_store")]
     inner_store: Store,
     existence_cache: EvictingMap<DigestInfo, ExistanceItem, I>,
+    negative_cache: Option<EvictingMap<DigestInfo, MissingItem, I>>,
 }
 
 impl ExistenceCacheStore<SystemTime> {
@@ -64,9 +81,14 @@ impl<I: InstantWrapper> ExistenceCacheStore<I> {
     ) -> Arc<Self> {
         let empty_policy = EvictionPolicy::default();
         let eviction_policy = spec.eviction_policy.as_ref().unwrap_or(&empty_policy);
+        let negative_cache = spec
+            .negative_cache_eviction_policy
+            .as_ref()
+            .map(|policy| EvictingMap::new(policy, I::from_secs(anchor_time.unix_timestamp())));
         Arc::new(Self {
             inner_store,
             existence_cache: EvictingMap::new(eviction_policy, anchor_time),
+            negative_cache,
         })
     }
 
@@ -82,6 +104,23 @@ impl<I: InstantWrapper> ExistenceCacheStore<I> {
         self.existence_cache.remove(digest).await;
     }
 
+    pub async fn missing_in_cache(&self, digest: &DigestInfo) -> bool {
+        let Some(negative_cache) = &self.negative_cache else {
+            return false;
+        };
+        let mut results = [None];
+        negative_cache
+            .sizes_for_keys([digest], &mut results[..], true /* peek */)
+            .await;
+        results[0].is_some()
+    }
+
+    async fn remove_from_negative_cache(&self, digest: &DigestInfo) {
+        if let Some(negative_cache) = &self.negative_cache {
+            negative_cache.remove(digest).await;
+        }
+    }
+
     async fn inner_has_with_results(
         self: Pin<&Self>,
         keys: &[DigestInfo],
@@ -91,10 +130,30 @@ impl<I: InstantWrapper> ExistenceCacheStore<I> {
             .sizes_for_keys(keys, results, true /* peek */)
             .await;
 
+        // Keys that the inner store recently reported as missing do not need
+        // to be queried again.
+        let mut known_missing = vec![false; keys.len()];
+        if let Some(negative_cache) = &self.negative_cache {
+            let mut negative_results = vec![None; keys.len()];
+            negative_cache
+                .sizes_for_keys(keys, &mut negative_results, true /* peek */)
+                .await;
+            for ((known_missing, negative_result), result) in known_missing
+                .iter_mut()
+                .zip(negative_results)
+                .zip(results.iter())
+            {
+                *known_missing = result.is_none() && negative_result.is_some();
+            }
+        }
+
         let not_cached_keys: Vec<_> = keys
             .iter()
             .zip(results.iter())
-            .filter_map(|(digest, result)| result.map_or_else(|| Some(digest.into()), |_| None))
+            .zip(known_missing.iter())
+            .filter_map(|((digest, result), known_missing)| {
+                (result.is_none() && !known_missing).then(|| digest.into())
+            })
             .collect();
 
         // Hot path optimization when all keys are cached.
@@ -123,13 +182,28 @@ impl<I: InstantWrapper> ExistenceCacheStore<I> {
             let _ = self.existence_cache.insert_many(inserts).await;
         }
 
+        // Remember the keys the inner store does not have.
+        if let Some(negative_cache) = &self.negative_cache {
+            let inserts = not_cached_keys
+                .iter()
+                .zip(inner_results.iter())
+                .filter_map(|(key, result)| {
+                    result
+                        .is_none()
+                        .then(|| (key.borrow().into_digest(), MissingItem))
+                })
+                .collect::<Vec<_>>();
+            let _ = negative_cache.insert_many(inserts).await;
+        }
+
         // Merge the results from the cache and the query.
         {
             let mut inner_results_iter = inner_results.into_iter();
-            // We know at this point that any None in results was queried and will have
-            // a result in inner_results_iter, so use this knowledge to fill in the results.
-            for result in results.iter_mut() {
-                if result.is_none() {
+            // We know at this point that any None in results that is not known to be
+            // missing was queried and will have a result in inner_results_iter, so use
+            // this knowledge to fill in the results.
+            for (result, known_missing) in results.iter_mut().zip(known_missing) {
+                if result.is_none() && !known_missing {
                     *result = inner_results_iter
                         .next()
                         .expect("has_with_results returned less results than expected");
@@ -186,6 +260,7 @@ impl<I: InstantWrapper> StoreDriver for ExistenceCacheStore<I> {
         }
         let result = self.inner_store.update(digest, reader, size_info).await;
         if result.is_ok() {
+            self.remove_from_negative_cache(&digest).await;
             if let UploadSizeInfo::ExactSize(size) = size_info {
                 let _ = self
                     .existence_cache
@@ -209,6 +284,7 @@ impl<I: InstantWrapper> StoreDriver for ExistenceCacheStore<I> {
             .get_part(digest, writer, offset, length)
             .await;
         if result.is_ok() {
+            self.remove_from_negative_cache(&digest).await;
             let _ = self
                 .existence_cache
                 .insert(digest, ExistanceItem(digest.size_bytes()))
@@ -228,6 +304,41 @@ impl<I: InstantWrapper> StoreDriver for ExistenceCacheStore<I> {
     fn as_any_arc(self: Arc<Self>) -> Arc<dyn std::any::Any + Sync + Send + 'static> {
         self
     }
+
+    async fn reconfigure(&self, spec: &StoreSpec, check_only: bool) -> Result<(), Error> {
+        let StoreSpec::existence_cache(spec) = spec else {
+            return Err(make_input_err!(
+                "ExistenceCacheStore can only be reconfigured with an existence_cache spec"
+            ));
+        };
+        let empty_policy = EvictionPolicy::default();
+        let eviction_policy = spec.eviction_policy.as_ref().unwrap_or(&empty_policy);
+        let result = if check_only {
+            self.existence_cache.check_policy(eviction_policy).await
+        } else {
+            self.existence_cache.set_policy(eviction_policy).await
+        };
+        result.err_tip(|| "In ExistenceCacheStore::reconfigure")?;
+        match (&self.negative_cache, &spec.negative_cache_eviction_policy) {
+            (Some(negative_cache), Some(policy)) => {
+                let result = if check_only {
+                    negative_cache.check_policy(policy).await
+                } else {
+                    negative_cache.set_policy(policy).await
+                };
+                result.err_tip(|| "In ExistenceCacheStore::reconfigure for negative cache")?;
+            }
+            (None, None) => {}
+            _ => return Err(make_input_err!(
+                "Negative cache of ExistenceCacheStore can't be enabled or disabled while running"
+            )),
+        }
+        self.inner_store
+            .as_store_driver()
+            .reconfigure(&spec.backend, check_only)
+            .await
+            .err_tip(|| "In ExistenceCacheStore::reconfigure for backend")
+    }
 }
 
 #[async_trait]
@@ -0,0 +1,385 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::borrow::{BorrowMut, Cow};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::join;
+use nativelink_config::stores::{FailoverSpec, StoreSpec};
+use nativelink_error::{make_input_err, Code, Error, ResultExt};
+use nativelink_metric::MetricsComponent;
+use nativelink_util::background_spawn;
+use nativelink_util::buf_channel::{
+    make_buf_channel_pair, DropCloserReadHalf, DropCloserWriteHalf,
+};
+use nativelink_util::health_utils::{HealthStatus, HealthStatusIndicator};
+use nativelink_util::instant_wrapper::InstantWrapper;
+use nativelink_util::store_trait::{Store, StoreDriver, StoreKey, StoreLike, UploadSizeInfo};
+use tokio::sync::Semaphore;
+use tracing::{event, Level};
+
+// Note: If you change these, adjust the docs in the config.
+const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+const DEFAULT_RECOVERY_INTERVAL_S: u32 = 30;
+const DEFAULT_MAX_CONCURRENT_BACKFILLS: usize = 16;
+
+#[derive(MetricsComponent)]
+pub struct FailoverStore<NowFn> {
+    #[metric(group = "primary_store")]
+    primary: Store,
+    #[metric(group = "secondary_store")]
+    secondary: Store,
+    now_fn: NowFn,
+    #[metric(help = "Consecutive failed requests before the primary is skipped")]
+    failure_threshold: u32,
+    #[metric(help = "Seconds the primary is skipped after reaching the failure threshold")]
+    recovery_interval_s: u64,
+    #[metric(help = "Number of consecutive failed requests to the primary")]
+    consecutive_failures: AtomicU64,
+    /// Unix timestamp until which the primary is skipped, zero if it is not.
+    #[metric(help = "Unix timestamp until which the primary is skipped")]
+    primary_skipped_until_s: AtomicU64,
+    backfill: bool,
+    backfill_permits: Arc<Semaphore>,
+    #[metric]
+    metrics: Arc<FailoverStoreMetrics>,
+}
+
+impl<I, NowFn> FailoverStore<NowFn>
+where
+    I: InstantWrapper,
+    NowFn: Fn() -> I + Send + Sync + Unpin + 'static,
+{
+    pub fn new(spec: &FailoverSpec, primary: Store, secondary: Store, now_fn: NowFn) -> Arc<Self> {
+        let failure_threshold = if spec.failure_threshold == 0 {
+            DEFAULT_FAILURE_THRESHOLD
+        } else {
+            spec.failure_threshold
+        };
+        let recovery_interval_s = if spec.recovery_interval_s == 0 {
+            DEFAULT_RECOVERY_INTERVAL_S
+        } else {
+            spec.recovery_interval_s
+        };
+        let max_concurrent_backfills = if spec.max_concurrent_backfills == 0 {
+            DEFAULT_MAX_CONCURRENT_BACKFILLS
+        } else {
+            spec.max_concurrent_backfills
+        };
+        Arc::new(Self {
+            primary,
+            secondary,
+            now_fn,
+            failure_threshold,
+            recovery_interval_s: u64::from(recovery_interval_s),
+            consecutive_failures: AtomicU64::new(0),
+            primary_skipped_until_s: AtomicU64::new(0),
+            backfill: !spec.disable_backfill,
+            backfill_permits: Arc::new(Semaphore::new(max_concurrent_backfills)),
+            metrics: Arc::new(FailoverStoreMetrics::default()),
+        })
+    }
+
+    pub fn primary(&self) -> &Store {
+        &self.primary
+    }
+
+    pub fn secondary(&self) -> &Store {
+        &self.secondary
+    }
+
+    /// Whether requests should currently be sent to the primary.
+    pub fn is_primary_available(&self) -> bool {
+        (self.now_fn)().unix_timestamp() >= self.primary_skipped_until_s.load(Ordering::Acquire)
+    }
+
+    /// Track the health of the primary. Not found errors are valid answers
+    /// and count as successful requests.
+    fn record_primary_result<T>(&self, result: &Result<T, Error>) {
+        match result {
+            Err(err) if err.code != Code::NotFound => {
+                self.metrics
+                    .primary_failure_count
+                    .fetch_add(1, Ordering::Relaxed);
+                let failures = self.consecutive_failures.fetch_add(1, Ordering::AcqRel) + 1;
+                if failures >= u64::from(self.failure_threshold) {
+                    let skipped_until_s =
+                        (self.now_fn)().unix_timestamp() + self.recovery_interval_s;
+                    let previous = self
+                        .primary_skipped_until_s
+                        .swap(skipped_until_s, Ordering::AcqRel);
+                    if previous == 0 {
+                        event!(
+                            Level::WARN,
+                            ?err,
+                            failures,
+                            recovery_interval_s = self.recovery_interval_s,
+                            "Primary store is failing, switching to secondary store in FailoverStore"
+                        );
+                    }
+                }
+            }
+            _ => {
+                self.consecutive_failures.store(0, Ordering::Release);
+                if self.primary_skipped_until_s.swap(0, Ordering::AcqRel) != 0 {
+                    event!(
+                        Level::INFO,
+                        "Primary store recovered, switching back in FailoverStore"
+                    );
+                }
+            }
+        }
+    }
+
+    /// Copy an object from the secondary into the primary in the background.
+    fn spawn_backfill(&self, key: StoreKey<'_>) {
+        if !self.backfill {
+            return;
+        }
+        let Ok(permit) = self.backfill_permits.clone().try_acquire_owned() else {
+            event!(
+                Level::DEBUG,
+                ?key,
+                "Too many backfills in progress, skipping backfill in FailoverStore"
+            );
+            return;
+        };
+        let key = key.into_owned();
+        let primary = self.primary.clone();
+        let secondary = self.secondary.clone();
+        let metrics = self.metrics.clone();
+        metrics
+            .backfill_started_count
+            .fetch_add(1, Ordering::Relaxed);
+        background_spawn!("failover_store_backfill", async move {
+            let _permit = permit;
+            let result = async {
+                let Some(size) = secondary
+                    .has(key.borrow())
+                    .await
+                    .err_tip(|| "In FailoverStore::spawn_backfill::has")?
+                else {
+                    return Ok(());
+                };
+                let (tx, rx) = make_buf_channel_pair();
+                let (get_res, update_res) = join!(
+                    secondary.get(key.borrow(), tx),
+                    primary.update(key.borrow(), rx, UploadSizeInfo::ExactSize(size))
+                );
+                get_res
+                    .err_tip(|| "In FailoverStore::spawn_backfill::get")
+                    .merge(update_res.err_tip(|| "In FailoverStore::spawn_backfill::update"))
+            }
+            .await;
+            if let Err(err) = result {
+                metrics
+                    .backfill_failed_count
+                    .fetch_add(1, Ordering::Relaxed);
+                event!(
+                    Level::WARN,
+                    ?key,
+                    ?err,
+                    "Failed to backfill primary store in FailoverStore"
+                );
+            }
+        });
+    }
+}
+
+#[async_trait]
+impl<I, NowFn> StoreDriver for FailoverStore<NowFn>
+where
+    I: InstantWrapper,
+    NowFn: Fn() -> I + Send + Sync + Unpin + 'static,
+{
+    async fn has_with_results(
+        self: Pin<&Self>,
+        keys: &[StoreKey<'_>],
+        results: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        if self.is_primary_available() {
+            let primary_res = self.primary.has_with_results(keys, results).await;
+            self.record_primary_result(&primary_res);
+            if primary_res.is_ok() {
+                // Only ask the secondary about the misses of the primary.
+                let missing: Vec<usize> = results
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, result)| result.is_none().then_some(i))
+                    .collect();
+                if missing.is_empty() {
+                    return Ok(());
+                }
+                let missing_keys: Vec<_> = missing.iter().map(|&i| keys[i].borrow()).collect();
+                let mut missing_results = vec![None; missing_keys.len()];
+                // The answer of the primary stands if the secondary fails.
+                if let Err(err) = self
+                    .secondary
+                    .has_with_results(&missing_keys, &mut missing_results)
+                    .await
+                {
+                    event!(
+                        Level::WARN,
+                        ?err,
+                        "Secondary store failed in FailoverStore::has_with_results"
+                    );
+                    return Ok(());
+                }
+                for (i, result) in missing.into_iter().zip(missing_results) {
+                    results[i] = result;
+                }
+                return Ok(());
+            }
+        }
+        self.metrics
+            .secondary_request_count
+            .fetch_add(1, Ordering::Relaxed);
+        self.secondary
+            .has_with_results(keys, results)
+            .await
+            .err_tip(|| "In FailoverStore::has_with_results::secondary")
+    }
+
+    async fn update(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        reader: DropCloserReadHalf,
+        upload_size: UploadSizeInfo,
+    ) -> Result<(), Error> {
+        // The reader is consumed by the upload, so a failed upload to the
+        // primary can not be sent to the secondary. It is left to the client
+        // to retry, by which time the primary may be skipped.
+        if self.is_primary_available() {
+            let result = self.primary.update(key, reader, upload_size).await;
+            self.record_primary_result(&result);
+            return result.err_tip(|| "In FailoverStore::update::primary");
+        }
+        self.metrics
+            .secondary_request_count
+            .fetch_add(1, Ordering::Relaxed);
+        self.secondary
+            .update(key, reader, upload_size)
+            .await
+            .err_tip(|| "In FailoverStore::update::secondary")
+    }
+
+    async fn get_part(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        writer: &mut DropCloserWriteHalf,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<(), Error> {
+        let mut primary_missed = false;
+        if self.is_primary_available() {
+            let result = self
+                .primary
+                .get_part(key.borrow(), writer.borrow_mut(), offset, length)
+                .await;
+            self.record_primary_result(&result);
+            match result {
+                Ok(()) => return Ok(()),
+                Err(err) if err.code == Code::NotFound => primary_missed = true,
+                Err(err) => {
+                    event!(
+                        Level::INFO,
+                        ?key,
+                        ?err,
+                        bytes_written = writer.get_bytes_written(),
+                        "Primary store failed in FailoverStore::get_part, trying secondary"
+                    );
+                }
+            }
+        }
+        self.metrics
+            .secondary_request_count
+            .fetch_add(1, Ordering::Relaxed);
+        // Continue where the primary left off.
+        let bytes_written = writer.get_bytes_written();
+        let secondary_res = self
+            .secondary
+            .get_part(
+                key.borrow(),
+                writer.borrow_mut(),
+                offset + bytes_written,
+                length.map(|length| length.saturating_sub(bytes_written)),
+            )
+            .await
+            .err_tip(|| "In FailoverStore::get_part::secondary");
+        if secondary_res.is_ok() && primary_missed {
+            self.spawn_backfill(key);
+        }
+        secondary_res
+    }
+
+    fn inner_store(&self, _key: Option<StoreKey>) -> &dyn StoreDriver {
+        self
+    }
+
+    fn as_any<'a>(&'a self) -> &'a (dyn std::any::Any + Sync + Send + 'static) {
+        self
+    }
+
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn std::any::Any + Sync + Send + 'static> {
+        self
+    }
+
+    async fn reconfigure(&self, spec: &StoreSpec, check_only: bool) -> Result<(), Error> {
+        let StoreSpec::failover(spec) = spec else {
+            return Err(make_input_err!(
+                "FailoverStore can only be reconfigured with a failover spec"
+            ));
+        };
+        self.primary
+            .as_store_driver()
+            .reconfigure(&spec.primary, check_only)
+            .await
+            .err_tip(|| "In FailoverStore::reconfigure for primary")?;
+        self.secondary
+            .as_store_driver()
+            .reconfigure(&spec.secondary, check_only)
+            .await
+            .err_tip(|| "In FailoverStore::reconfigure for secondary")
+    }
+}
+
+#[derive(Default, MetricsComponent)]
+struct FailoverStoreMetrics {
+    #[metric(help = "Number of failed requests to the primary store")]
+    primary_failure_count: AtomicU64,
+    #[metric(help = "Number of requests sent to the secondary store")]
+    secondary_request_count: AtomicU64,
+    #[metric(help = "Number of backfills of the primary store started")]
+    backfill_started_count: AtomicU64,
+    #[metric(help = "Number of backfills of the primary store that failed")]
+    backfill_failed_count: AtomicU64,
+}
+
+#[async_trait]
+impl<I, NowFn> HealthStatusIndicator for FailoverStore<NowFn>
+where
+    I: InstantWrapper,
+    NowFn: Fn() -> I + Send + Sync + Unpin + 'static,
+{
+    fn get_name(&self) -> &'static str {
+        "FailoverStore"
+    }
+
+    async fn check_health(&self, namespace: Cow<'static, str>) -> HealthStatus {
+        StoreDriver::check_health(Pin::new(self), namespace).await
+    }
+}
@@ -14,54 +14,255 @@
 
 use std::borrow::BorrowMut;
 use std::cmp::{max, min};
+use std::collections::HashMap;
 use std::ops::Range;
 use std::pin::Pin;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Weak};
+use std::time::Duration;
 
 use async_trait::async_trait;
-use futures::{join, FutureExt};
-use nativelink_config::stores::FastSlowSpec;
-use nativelink_error::{make_err, Code, Error, ResultExt};
+use bytes::Bytes;
+use futures::stream::{self, StreamExt};
+use futures::{join, try_join, FutureExt};
+use nativelink_config::stores::{
+    FastSlowSpec, FastSlowWriteBackConfig, PopulateOnReadPolicy, StoreSpec,
+};
+use nativelink_error::{make_err, make_input_err, Code, Error, ResultExt};
 use nativelink_metric::MetricsComponent;
+use nativelink_util::background_spawn;
 use nativelink_util::buf_channel::{
     make_buf_channel_pair, DropCloserReadHalf, DropCloserWriteHalf,
 };
 use nativelink_util::fs;
 use nativelink_util::health_utils::{default_health_status_indicator, HealthStatusIndicator};
+use nativelink_util::retry::{Retrier, RetryResult};
 use nativelink_util::store_trait::{
-    slow_update_store_with_file, Store, StoreDriver, StoreKey, StoreLike, StoreOptimizations,
-    UploadSizeInfo,
+    slow_update_many, slow_update_store_with_file, Store, StoreDriver, StoreKey, StoreLike,
+    StoreOptimizations, UploadSizeInfo,
 };
+use parking_lot::Mutex;
+use rand::rngs::OsRng;
+use rand::Rng;
+use tokio::sync::mpsc;
+use tokio::time::sleep;
+use tracing::{event, Level};
+
+// NOTE: If these change update the comments in `stores.rs` to reflect
+// the new defaults.
+const DEFAULT_WRITE_BACK_MAX_QUEUED_UPLOADS: usize = 1024;
+const DEFAULT_WRITE_BACK_MAX_CONCURRENT_UPLOADS: usize = 16;
 
 // TODO(blaise.bruer) This store needs to be evaluated for more efficient memory usage,
 // there are many copies happening internally.
-
-// TODO(blaise.bruer) We should consider copying the data in the background to allow the
-// client to hang up while the data is buffered. An alternative is to possibly make a
-// "BufferedStore" that could be placed on the "slow" store that would hang up early
-// if data is in the buffer.
 #[derive(MetricsComponent)]
 pub struct FastSlowStore {
     #[metric(group = "fast_store")]
     fast_store: Store,
     #[metric(group = "slow_store")]
     slow_store: Store,
+    populate_on_read: PopulateOnReadPolicy,
+    write_back: Option<WriteBack>,
     weak_self: Weak<Self>,
     #[metric]
     metrics: FastSlowStoreMetrics,
 }
 
+/// An object that was written to the fast store and still has to be
+/// copied to the slow store.
+struct PendingUpload {
+    size: u64,
+    /// The object was written again while it was being copied, so it has
+    /// to be copied again afterwards.
+    rewritten: bool,
+}
+
+/// State of the write back mode, where objects are copied from the fast
+/// store to the slow store in the background.
+struct WriteBack {
+    sender: mpsc::Sender<StoreKey<'static>>,
+    pending: Mutex<HashMap<StoreKey<'static>, PendingUpload>>,
+    max_concurrent_uploads: usize,
+    retrier: Retrier,
+}
+
 impl FastSlowStore {
-    pub fn new(_spec: &FastSlowSpec, fast_store: Store, slow_store: Store) -> Arc<Self> {
+    pub fn new(spec: &FastSlowSpec, fast_store: Store, slow_store: Store) -> Arc<Self> {
         Arc::new_cyclic(|weak_self| Self {
             fast_store,
             slow_store,
+            populate_on_read: spec.populate_on_read,
+            write_back: spec
+                .write_back
+                .as_ref()
+                .map(|config| Self::start_write_back(config, weak_self.clone())),
             weak_self: weak_self.clone(),
             metrics: FastSlowStoreMetrics::default(),
         })
     }
 
+    fn start_write_back(config: &FastSlowWriteBackConfig, weak_self: Weak<Self>) -> WriteBack {
+        let max_queued_uploads = if config.max_queued_uploads == 0 {
+            DEFAULT_WRITE_BACK_MAX_QUEUED_UPLOADS
+        } else {
+            config.max_queued_uploads
+        };
+        let max_concurrent_uploads = if config.max_concurrent_uploads == 0 {
+            DEFAULT_WRITE_BACK_MAX_CONCURRENT_UPLOADS
+        } else {
+            config.max_concurrent_uploads
+        };
+        let (sender, receiver) = mpsc::channel(max_queued_uploads);
+        // The task stops once the store is dropped, because that drops the
+        // only sender.
+        background_spawn!("fast_slow_store_write_back", async move {
+            stream::unfold(receiver, |mut receiver| async move {
+                receiver.recv().await.map(|key| (key, receiver))
+            })
+            .for_each_concurrent(max_concurrent_uploads, |key| {
+                let weak_self = weak_self.clone();
+                async move {
+                    if let Some(store) = weak_self.upgrade() {
+                        store.write_back_key(key).await;
+                    }
+                }
+            })
+            .await;
+        });
+        let jitter_amt = config.retry.jitter;
+        WriteBack {
+            sender,
+            pending: Mutex::new(HashMap::new()),
+            max_concurrent_uploads,
+            retrier: Retrier::new(
+                Arc::new(|duration| Box::pin(sleep(duration))),
+                Arc::new(move |delay: Duration| {
+                    if jitter_amt == 0. {
+                        return delay;
+                    }
+                    let min = 1. - (jitter_amt / 2.);
+                    let max = 1. + (jitter_amt / 2.);
+                    delay.mul_f32(OsRng.gen_range(min..max))
+                }),
+                config.retry.clone(),
+            ),
+        }
+    }
+
+    /// Queues `key`, which was just written to the fast store, to be copied
+    /// to the slow store. If the queue is full it is copied right away.
+    async fn enqueue_write_back(
+        &self,
+        write_back: &WriteBack,
+        key: StoreKey<'static>,
+        size: u64,
+    ) -> Result<(), Error> {
+        {
+            let mut pending = write_back.pending.lock();
+            if let Some(pending_upload) = pending.get_mut(&key) {
+                pending_upload.size = size;
+                pending_upload.rewritten = true;
+                return Ok(());
+            }
+            if write_back.sender.try_send(key.clone()).is_ok() {
+                pending.insert(
+                    key,
+                    PendingUpload {
+                        size,
+                        rewritten: false,
+                    },
+                );
+                return Ok(());
+            }
+        }
+        self.metrics
+            .write_back_queue_full_count
+            .fetch_add(1, Ordering::Acquire);
+        self.copy_to_slow_store(write_back, key.borrow(), size)
+            .await
+            .err_tip(|| "Failed to write to slow store after write back queue was full")
+    }
+
+    /// Copies a queued object to the slow store, until it was not written
+    /// again while it was copied.
+    async fn write_back_key(&self, key: StoreKey<'static>) {
+        let Some(write_back) = &self.write_back else {
+            return;
+        };
+        loop {
+            let Some(size) = write_back
+                .pending
+                .lock()
+                .get(&key)
+                .map(|pending| pending.size)
+            else {
+                return;
+            };
+            match self
+                .copy_to_slow_store(write_back, key.borrow(), size)
+                .await
+            {
+                Ok(()) => {
+                    self.metrics
+                        .write_back_uploads
+                        .fetch_add(1, Ordering::Acquire);
+                    self.metrics
+                        .write_back_uploaded_bytes
+                        .fetch_add(size, Ordering::Acquire);
+                }
+                Err(err) => {
+                    self.metrics
+                        .write_back_failures
+                        .fetch_add(1, Ordering::Acquire);
+                    event!(
+                        Level::ERROR,
+                        ?key,
+                        ?err,
+                        "Failed to write back object to slow store"
+                    );
+                }
+            }
+            let mut pending = write_back.pending.lock();
+            match pending.get_mut(&key) {
+                Some(pending_upload) if pending_upload.rewritten => {
+                    pending_upload.rewritten = false;
+                }
+                _ => {
+                    pending.remove(&key);
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn copy_to_slow_store(
+        &self,
+        write_back: &WriteBack,
+        key: StoreKey<'_>,
+        size: u64,
+    ) -> Result<(), Error> {
+        let key = &key;
+        write_back
+            .retrier
+            .retry(stream::unfold((), move |()| async move {
+                let (tx, rx) = make_buf_channel_pair();
+                let result = try_join!(
+                    self.fast_store.get(key.borrow(), tx),
+                    self.slow_store
+                        .update(key.borrow(), rx, UploadSizeInfo::ExactSize(size)),
+                );
+                let result = match result {
+                    Ok(_) => RetryResult::Ok(()),
+                    // The object was evicted from the fast store, so there
+                    // is nothing left to copy.
+                    Err(err) if err.code == Code::NotFound => RetryResult::Err(err),
+                    Err(err) => RetryResult::Retry(err),
+                };
+                Some((result, ()))
+            }))
+            .await
+    }
+
     pub fn fast_store(&self) -> &Store {
         &self.fast_store
     }
@@ -74,6 +275,68 @@ impl FastSlowStore {
         self.weak_self.upgrade()
     }
 
+    /// Copies the objects that are waiting to be written back to the slow
+    /// store right away, instead of when their turn in the queue comes.
+    /// Objects that fail to be copied are queued again. Returns the number
+    /// of objects copied.
+    pub async fn flush_write_back(&self) -> Result<usize, Error> {
+        let Some(write_back) = &self.write_back else {
+            return Err(make_input_err!("FastSlowStore is not in write back mode"));
+        };
+        // Queued keys without a pending upload are skipped by the
+        // background task, so the objects are only copied once.
+        let pending_uploads: Vec<_> = write_back
+            .pending
+            .lock()
+            .drain()
+            .map(|(key, pending_upload)| (key, pending_upload.size))
+            .collect();
+        let results: Vec<_> = stream::iter(pending_uploads)
+            .map(|(key, size)| async move {
+                let result = self
+                    .copy_to_slow_store(write_back, key.borrow(), size)
+                    .await;
+                (key, size, result)
+            })
+            .buffer_unordered(write_back.max_concurrent_uploads)
+            .collect()
+            .await;
+        let mut flushed = 0;
+        let mut errors = Vec::new();
+        for (key, size, result) in results {
+            match result {
+                Ok(()) => {
+                    flushed += 1;
+                    self.metrics
+                        .write_back_uploads
+                        .fetch_add(1, Ordering::Acquire);
+                    self.metrics
+                        .write_back_uploaded_bytes
+                        .fetch_add(size, Ordering::Acquire);
+                }
+                // The object was evicted from the fast store, so there is
+                // nothing left to copy.
+                Err(err) if err.code == Code::NotFound => {}
+                Err(err) => {
+                    self.metrics
+                        .write_back_failures
+                        .fetch_add(1, Ordering::Acquire);
+                    errors.push(err.append(format!("Failed to flush {key:?}")));
+                    if let Err(err) = self.enqueue_write_back(write_back, key, size).await {
+                        errors.push(err);
+                    }
+                }
+            }
+        }
+        match errors
+            .into_iter()
+            .reduce(|err, other_err| err.merge(other_err))
+        {
+            Some(err) => Err(err),
+            None => Ok(flushed),
+        }
+    }
+
     /// Ensure our fast store is populated. This should be kept as a low
     /// cost function. Since the data itself is shared and not copied it should be fairly
     /// low cost to just discard the data, but does cost a few mutex locks while
@@ -90,15 +353,44 @@ impl FastSlowStore {
         // TODO(blaise.bruer) This is extremely inefficient, since we are just trying
         // to send the stream to /dev/null. Maybe we could instead make a version of
         // the stream that can send to the drain more efficiently?
-        let (tx, mut rx) = make_buf_channel_pair();
+        let (mut tx, mut rx) = make_buf_channel_pair();
         let drain_fut = async move {
             while !rx.recv().await?.is_empty() {}
             Ok(())
         };
-        let (drain_res, get_res) = join!(drain_fut, StoreDriver::get(Pin::new(self), key, tx));
+        let get_fut =
+            self.get_part_with_policy(key, &mut tx, 0, None, PopulateOnReadPolicy::always);
+        let (drain_res, get_res) = join!(drain_fut, get_fut);
         get_res.err_tip(|| "Failed to populate()").merge(drain_res)
     }
 
+    /// Size of an object that was just written to the fast store.
+    async fn written_size(
+        &self,
+        key: StoreKey<'_>,
+        size_info: UploadSizeInfo,
+    ) -> Result<u64, Error> {
+        if let UploadSizeInfo::ExactSize(size) = size_info {
+            return Ok(size);
+        }
+        self.fast_store
+            .has(key)
+            .await
+            .err_tip(|| "While querying fast store in fast_slow store write back")?
+            .err_tip(|| "Object was evicted from fast store before it could be written back")
+    }
+
+    /// Whether an object of `size` bytes read from the slow store should be
+    /// written to the fast store.
+    fn should_populate(policy: PopulateOnReadPolicy, size: u64) -> bool {
+        match policy {
+            PopulateOnReadPolicy::always => true,
+            PopulateOnReadPolicy::never => false,
+            PopulateOnReadPolicy::only_if_smaller_than(max_size) => size < max_size,
+            PopulateOnReadPolicy::probabilistic(probability) => OsRng.gen::<f32>() < probability,
+        }
+    }
+
     /// Returns the range of bytes that should be sent given a slice bounds
     /// offset so the output range maps the `received_range.start` to 0.
     // TODO(allada) This should be put into utils, as this logic is used
@@ -144,7 +436,39 @@ impl StoreDriver for FastSlowStore {
         // down stream might be unable to get it.  This should not affect
         // workers as they only use get() and a CAS can use an
         // ExistenceCacheStore to avoid the bottleneck.
-        self.slow_store.has_with_results(key, results).await
+        self.slow_store.has_with_results(key, results).await?;
+        if let Some(write_back) = &self.write_back {
+            // Objects that are not copied to the slow store yet still exist.
+            let pending = write_back.pending.lock();
+            if !pending.is_empty() {
+                for (key, result) in key.iter().zip(results.iter_mut()) {
+                    if result.is_none() {
+                        *result = pending
+                            .get(&key.borrow().into_owned())
+                            .map(|pending_upload| pending_upload.size);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn remove(self: Pin<&Self>, key: StoreKey<'_>) -> Result<bool, Error> {
+        let removed_from_fast = self
+            .fast_store
+            .remove(key.borrow())
+            .await
+            .err_tip(|| "Failed to remove from fast store in FastSlowStore")?;
+        let slow_store = self.slow_store.inner_store::<StoreKey<'_>>(None);
+        if slow_store.optimized_for(StoreOptimizations::NoopDownloads) {
+            return Ok(removed_from_fast);
+        }
+        let removed_from_slow = self
+            .slow_store
+            .remove(key)
+            .await
+            .err_tip(|| "Failed to remove from slow store in FastSlowStore")?;
+        Ok(removed_from_fast || removed_from_slow)
     }
 
     async fn update(
@@ -164,6 +488,17 @@ impl StoreDriver for FastSlowStore {
             return self.slow_store.update(key, reader, size_info).await;
         }
 
+        if let Some(write_back) = &self.write_back {
+            self.fast_store
+                .update(key.borrow(), reader, size_info)
+                .await
+                .err_tip(|| "Failed to update fast store in fast_slow store write back")?;
+            let size = self.written_size(key.borrow(), size_info).await?;
+            return self
+                .enqueue_write_back(write_back, key.into_owned(), size)
+                .await;
+        }
+
         let (mut fast_tx, fast_rx) = make_buf_channel_pair();
         let (mut slow_tx, slow_rx) = make_buf_channel_pair();
 
@@ -213,6 +548,51 @@ impl StoreDriver for FastSlowStore {
         Ok(())
     }
 
+    /// Sends the objects to both stores with `update_many()`, so a slow
+    /// store that batches them, like `GrpcStore`, can. With write back each
+    /// object goes through `update()` instead.
+    async fn update_many(
+        self: Pin<&Self>,
+        items: Vec<(StoreKey<'_>, Bytes)>,
+    ) -> Vec<Result<(), Error>> {
+        if self.write_back.is_some() {
+            return slow_update_many(self, items).await;
+        }
+        if self
+            .slow_store
+            .optimized_for(StoreOptimizations::NoopUpdates)
+        {
+            return self.fast_store.update_many(items).await;
+        }
+        if self
+            .fast_store
+            .optimized_for(StoreOptimizations::NoopUpdates)
+        {
+            return self.slow_store.update_many(items).await;
+        }
+        let fast_items = items
+            .iter()
+            .map(|(key, data)| (key.borrow().into_owned(), data.clone()))
+            .collect();
+        let (fast_results, slow_results) = join!(
+            self.fast_store.update_many(fast_items),
+            self.slow_store.update_many(items)
+        );
+        fast_results
+            .into_iter()
+            .zip(slow_results)
+            .map(|(fast_result, slow_result)| {
+                fast_result
+                    .err_tip(|| "Failed to update fast store in FastSlowStore::update_many")
+                    .merge(
+                        slow_result.err_tip(|| {
+                            "Failed to update slow store in FastSlowStore::update_many"
+                        }),
+                    )
+            })
+            .collect()
+    }
+
     /// FastSlowStore has optimiations for dealing with files.
     fn optimized_for(&self, optimization: StoreOptimizations) -> bool {
         optimization == StoreOptimizations::FileUpdates
@@ -227,6 +607,33 @@ impl StoreDriver for FastSlowStore {
         mut file: fs::ResumeableFileSlot,
         upload_size: UploadSizeInfo,
     ) -> Result<Option<fs::ResumeableFileSlot>, Error> {
+        if let Some(write_back) = &self.write_back {
+            if !self
+                .slow_store
+                .optimized_for(StoreOptimizations::NoopUpdates)
+            {
+                if !self
+                    .fast_store
+                    .optimized_for(StoreOptimizations::FileUpdates)
+                {
+                    // Goes through `update()`, which writes back.
+                    slow_update_store_with_file(self, key, &mut file, upload_size)
+                        .await
+                        .err_tip(|| "In FastSlowStore::update_with_whole_file write back")?;
+                    return Ok(Some(file));
+                }
+                let maybe_file = self
+                    .fast_store
+                    .update_with_whole_file(key.borrow(), file, upload_size)
+                    .await
+                    .err_tip(|| "In FastSlowStore::update_with_whole_file write back")?;
+                let size = self.written_size(key.borrow(), upload_size).await?;
+                self.enqueue_write_back(write_back, key.into_owned(), size)
+                    .await?;
+                return Ok(maybe_file);
+            }
+        }
+
         if self
             .fast_store
             .optimized_for(StoreOptimizations::FileUpdates)
@@ -285,6 +692,63 @@ impl StoreDriver for FastSlowStore {
         writer: &mut DropCloserWriteHalf,
         offset: u64,
         length: Option<u64>,
+    ) -> Result<(), Error> {
+        self.get_part_with_policy(key, writer, offset, length, self.populate_on_read)
+            .await
+    }
+
+    fn inner_store(&self, _key: Option<StoreKey>) -> &dyn StoreDriver {
+        self
+    }
+
+    fn as_any<'a>(&'a self) -> &'a (dyn std::any::Any + Sync + Send + 'static) {
+        self
+    }
+
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn std::any::Any + Sync + Send + 'static> {
+        self
+    }
+
+    async fn reconfigure(&self, spec: &StoreSpec, check_only: bool) -> Result<(), Error> {
+        let StoreSpec::fast_slow(spec) = spec else {
+            return Err(make_input_err!(
+                "FastSlowStore can only be reconfigured with a fast_slow spec"
+            ));
+        };
+        match (&self.write_back, &spec.write_back) {
+            (Some(write_back), Some(config)) => {
+                if !check_only {
+                    write_back.retrier.set_config(config.retry.clone());
+                }
+            }
+            (None, None) => {}
+            _ => {
+                return Err(make_input_err!(
+                    "write_back of FastSlowStore can't be enabled or disabled while running"
+                ))
+            }
+        }
+        self.fast_store
+            .as_store_driver()
+            .reconfigure(&spec.fast, check_only)
+            .await
+            .err_tip(|| "In FastSlowStore::reconfigure for fast")?;
+        self.slow_store
+            .as_store_driver()
+            .reconfigure(&spec.slow, check_only)
+            .await
+            .err_tip(|| "In FastSlowStore::reconfigure for slow")
+    }
+}
+
+impl FastSlowStore {
+    async fn get_part_with_policy(
+        &self,
+        key: StoreKey<'_>,
+        writer: &mut DropCloserWriteHalf,
+        offset: u64,
+        length: Option<u64>,
+        populate_on_read: PopulateOnReadPolicy,
     ) -> Result<(), Error> {
         // TODO(blaise.bruer) Investigate if we should maybe ignore errors here instead of
         // forwarding the up.
@@ -317,6 +781,22 @@ impl StoreDriver for FastSlowStore {
             .slow_store_hit_count
             .fetch_add(1, Ordering::Acquire);
 
+        if !Self::should_populate(populate_on_read, sz) {
+            self.metrics
+                .skipped_populate_count
+                .fetch_add(1, Ordering::Acquire);
+            let bytes_written_before = writer.get_bytes_written();
+            self.slow_store
+                .get_part(key, writer.borrow_mut(), offset, length)
+                .await
+                .err_tip(|| "Failed to read from slow store in fast_slow store")?;
+            self.metrics.slow_store_downloaded_bytes.fetch_add(
+                writer.get_bytes_written() - bytes_written_before,
+                Ordering::Acquire,
+            );
+            return Ok(());
+        }
+
         let send_range = offset..length.map_or(u64::MAX, |length| length + offset);
         let mut bytes_received: u64 = 0;
 
@@ -378,18 +858,6 @@ impl StoreDriver for FastSlowStore {
             Err(err) => fast_res.merge(slow_res).merge(Err(err)),
         }
     }
-
-    fn inner_store(&self, _key: Option<StoreKey>) -> &dyn StoreDriver {
-        self
-    }
-
-    fn as_any<'a>(&'a self) -> &'a (dyn std::any::Any + Sync + Send + 'static) {
-        self
-    }
-
-    fn as_any_arc(self: Arc<Self>) -> Arc<dyn std::any::Any + Sync + Send + 'static> {
-        self
-    }
 }
 
 #[derive(Default, MetricsComponent)]
@@ -402,6 +870,18 @@ struct FastSlowStoreMetrics {
     slow_store_hit_count: AtomicU64,
     #[metric(help = "Downloaded bytes from the slow store")]
     slow_store_downloaded_bytes: AtomicU64,
+    #[metric(help = "Reads from the slow store that were not written to the fast store")]
+    skipped_populate_count: AtomicU64,
+    #[metric(help = "Objects copied to the slow store in the background")]
+    write_back_uploads: AtomicU64,
+    #[metric(help = "Bytes copied to the slow store in the background")]
+    write_back_uploaded_bytes: AtomicU64,
+    #[metric(help = "Objects that failed to be copied to the slow store in the background")]
+    write_back_failures: AtomicU64,
+    #[metric(
+        help = "Updates written to the slow store right away because the write back queue was full"
+    )]
+    write_back_queue_full_count: AtomicU64,
 }
 
 default_health_status_indicator!(FastSlowStore);
@@ -12,9 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::borrow::Cow;
+use std::borrow::{Borrow, Cow};
+use std::collections::HashSet;
 use std::ffi::{OsStr, OsString};
 use std::fmt::{Debug, Formatter};
+use std::ops::Bound;
 use std::pin::Pin;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Weak};
@@ -22,29 +24,34 @@ use std::time::{Duration, SystemTime};
 
 use async_lock::RwLock;
 use async_trait::async_trait;
-use bytes::BytesMut;
+use bytes::{Bytes, BytesMut};
 use filetime::{set_file_atime, FileTime};
 use futures::stream::{StreamExt, TryStreamExt};
 use futures::{Future, TryFutureExt};
-use nativelink_config::stores::FilesystemSpec;
-use nativelink_error::{make_err, make_input_err, Code, Error, ResultExt};
+use nativelink_config::stores::{EvictionPolicy, FilesystemSpec, FsckMode, StoreSpec};
+use nativelink_error::{error_if, make_err, make_input_err, Code, Error, ResultExt};
 use nativelink_metric::MetricsComponent;
 use nativelink_util::buf_channel::{
     make_buf_channel_pair, DropCloserReadHalf, DropCloserWriteHalf,
 };
 use nativelink_util::common::{fs, DigestInfo};
+use nativelink_util::digest_hasher::{default_digest_hasher_func, DigestHasher, DigestHasherFunc};
 use nativelink_util::evicting_map::{EvictingMap, LenEntry};
 use nativelink_util::health_utils::{HealthRegistryBuilder, HealthStatus, HealthStatusIndicator};
+use nativelink_util::instant_wrapper::InstantWrapper;
 use nativelink_util::store_trait::{
     StoreDriver, StoreKey, StoreKeyBorrow, StoreOptimizations, UploadSizeInfo,
 };
 use nativelink_util::{background_spawn, spawn_blocking};
+use serde::{Deserialize, Serialize};
 use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, SeekFrom};
 use tokio::time::{sleep, timeout, Sleep};
 use tokio_stream::wrappers::ReadDirStream;
 use tracing::{event, Level};
 
 use crate::cas_utils::is_zero_digest;
+use crate::direct_io::DirectFile;
+use crate::uring_io::{UringFile, UringIo, DEFAULT_QUEUE_DEPTH};
 
 // Default size to allocate memory of the buffer when reading files.
 const DEFAULT_BUFF_SIZE: usize = 32 * 1024;
@@ -172,6 +179,9 @@ pub trait FileEntry: LenEntry + Send + Sync + Debug + 'static {
     where
         Self: Sized;
 
+    /// Returns the size of the data in bytes.
+    fn data_size(&self) -> u64;
+
     /// Returns the underlying reference to the size of the data in bytes
     fn data_size_mut(&mut self) -> &mut u64;
 
@@ -264,6 +274,10 @@ impl FileEntry for FileEntryImpl {
         ))
     }
 
+    fn data_size(&self) -> u64 {
+        self.data_size
+    }
+
     fn data_size_mut(&mut self) -> &mut u64 {
         &mut self.data_size
     }
@@ -327,12 +341,12 @@ impl Debug for FileEntryImpl {
 fn make_temp_digest(mut digest: DigestInfo) -> DigestInfo {
     static DELETE_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
     let mut hash = *digest.packed_hash();
-    hash[24..].clone_from_slice(
+    hash[24..32].clone_from_slice(
         &DELETE_FILE_COUNTER
             .fetch_add(1, Ordering::Relaxed)
             .to_le_bytes(),
     );
-    digest.set_packed_hash(*hash);
+    digest.set_packed_hash(hash);
     digest
 }
 
@@ -438,6 +452,55 @@ pub fn key_from_file(file_name: &str, file_type: FileType) -> Result<StoreKey<'_
 /// `add_files_to_cache`.
 const SIMULTANEOUS_METADATA_READS: usize = 200;
 
+/// The number of files checked at the same time by `FilesystemStore::fsck`.
+const SIMULTANEOUS_FSCK_CHECKS: usize = 16;
+
+/// Result of a `FilesystemStore::fsck` run.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FsckReport {
+    /// Number of entries that were checked.
+    pub checked: u64,
+    /// Number of entries that were corrupt and removed from the store.
+    pub corrupt: u64,
+    /// Number of entries that could not be checked.
+    pub skipped: u64,
+}
+
+/// Returns whether the file at `path` has the size, and with
+/// `FsckMode::hash` the hash, of `digest`. The hash function is not part of
+/// the file name, so every supported one is tried.
+async fn file_matches_digest(mode: FsckMode, digest: DigestInfo, path: &OsStr) -> bool {
+    let Ok(metadata) = fs::metadata(path).await else {
+        return false;
+    };
+    if metadata.len() != digest.size_bytes() {
+        return false;
+    }
+    if mode != FsckMode::hash {
+        return true;
+    }
+    let default_hasher_func = default_digest_hasher_func();
+    let hash_size = digest.packed_hash().len();
+    let hasher_funcs = DigestHasherFunc::ALL.into_iter().filter(|hasher_func| {
+        *hasher_func != default_hasher_func && hasher_func.hash_size() == hash_size
+    });
+    for hasher_func in std::iter::once(default_hasher_func).chain(hasher_funcs) {
+        let Ok(file) = fs::open_file(path, u64::MAX).await else {
+            return false;
+        };
+        match hasher_func
+            .hasher()
+            .digest_for_file(file, Some(digest.size_bytes()))
+            .await
+        {
+            Ok((computed_digest, _file)) if computed_digest == digest => return true,
+            Ok(_) => {}
+            Err(_) => return false,
+        }
+    }
+    false
+}
+
 async fn add_files_to_cache<Fe: FileEntry>(
     evicting_map: &EvictingMap<StoreKeyBorrow, Arc<Fe>, SystemTime>,
     anchor_time: &SystemTime,
@@ -633,6 +696,241 @@ async fn add_files_to_cache<Fe: FileEntry>(
     Ok(())
 }
 
+/// Version of the format of index snapshots. Snapshots of other versions
+/// are ignored.
+const INDEX_SNAPSHOT_VERSION: u32 = 1;
+
+/// Interval at which the index snapshot is written if none is configured.
+const DEFAULT_INDEX_SNAPSHOT_INTERVAL: Duration = Duration::from_secs(300);
+
+/// The key of an entry in an index snapshot.
+#[derive(Serialize, Deserialize, Debug)]
+enum IndexSnapshotKey {
+    Digest([u8; 32], u64),
+    Str(String),
+    /// A digest with a hash longer than 32 bytes, ie: SHA-512.
+    LongDigest(Vec<u8>, u64),
+}
+
+/// An entry of the evicting map with the unix timestamp of its last access.
+#[derive(Serialize, Deserialize, Debug)]
+struct IndexSnapshotEntry {
+    key: IndexSnapshotKey,
+    data_size: u64,
+    last_access: u64,
+}
+
+/// The entries of the evicting map from the least to the most recently
+/// used one, which is written to `index_snapshot_path` so the store can
+/// start without reading the metadata of every file.
+#[derive(Serialize, Deserialize, Debug)]
+struct IndexSnapshot {
+    version: u32,
+    entries: Vec<IndexSnapshotEntry>,
+}
+
+/// Returns the names of the files in `folder` of the content path.
+async fn read_file_names(
+    shared_context: &SharedContext,
+    folder: &str,
+) -> Result<HashSet<String>, Error> {
+    let (_permit, dir_handle) = fs::read_dir(format!("{}/{folder}", shared_context.content_path))
+        .await
+        .err_tip(|| "Failed opening content directory for iterating in filesystem store")?
+        .into_inner();
+    let mut file_names = HashSet::new();
+    let mut read_dir_stream = ReadDirStream::new(dir_handle);
+    while let Some(dir_entry) = read_dir_stream.next().await {
+        let dir_entry = dir_entry.err_tip(|| "Failed to read content directory entry")?;
+        if let Ok(file_name) = dir_entry.file_name().into_string() {
+            file_names.insert(file_name);
+        }
+    }
+    Ok(file_names)
+}
+
+/// Fills `evicting_map` from the index snapshot at `snapshot_path` instead
+/// of reading the metadata of every file. Entries whose files are gone are
+/// dropped and files that are not in the snapshot are added as the most
+/// recently used entries. Returns `false` if there is no usable snapshot.
+async fn add_files_from_index_snapshot<Fe: FileEntry>(
+    evicting_map: &EvictingMap<StoreKeyBorrow, Arc<Fe>, SystemTime>,
+    anchor_time: &SystemTime,
+    shared_context: &Arc<SharedContext>,
+    block_size: u64,
+    snapshot_path: &str,
+) -> Result<bool, Error> {
+    async fn insert_entry<Fe: FileEntry>(
+        evicting_map: &EvictingMap<StoreKeyBorrow, Arc<Fe>, SystemTime>,
+        shared_context: &Arc<SharedContext>,
+        block_size: u64,
+        key: StoreKey<'static>,
+        data_size: u64,
+        seconds_since_anchor: i32,
+    ) {
+        let file_entry = Fe::create(
+            data_size,
+            block_size,
+            RwLock::new(EncodedFilePath {
+                shared_context: shared_context.clone(),
+                path_type: PathType::Content,
+                key: key.clone(),
+            }),
+        );
+        evicting_map
+            .insert_with_time(key.into(), Arc::new(file_entry), seconds_since_anchor)
+            .await;
+    }
+
+    let data = match fs::read(snapshot_path).await {
+        Ok(data) => data,
+        Err(err) if err.code == Code::NotFound => return Ok(false),
+        Err(err) => {
+            return Err(err).err_tip(|| format!("Failed to read index snapshot {snapshot_path}"))
+        }
+    };
+    let snapshot = match bincode::deserialize::<IndexSnapshot>(&data) {
+        Ok(snapshot) if snapshot.version == INDEX_SNAPSHOT_VERSION => snapshot,
+        Ok(snapshot) => {
+            event!(
+                Level::WARN,
+                snapshot_path,
+                version = snapshot.version,
+                "Ignoring index snapshot of unknown version, reading the metadata of all files"
+            );
+            return Ok(false);
+        }
+        Err(err) => {
+            event!(
+                Level::WARN,
+                snapshot_path,
+                ?err,
+                "Ignoring unreadable index snapshot, reading the metadata of all files"
+            );
+            return Ok(false);
+        }
+    };
+
+    let mut digest_files = read_file_names(shared_context, DIGEST_FOLDER).await?;
+    let mut str_files = read_file_names(shared_context, STR_FOLDER).await?;
+    let snapshot_entries: Vec<(StoreKey<'static>, u64, u64)> = snapshot
+        .entries
+        .into_iter()
+        .filter_map(|entry| {
+            let (key, exists) = match entry.key {
+                IndexSnapshotKey::Digest(hash, size) => {
+                    let digest = DigestInfo::new(hash, size);
+                    (
+                        StoreKey::Digest(digest),
+                        digest_files.remove(&digest.to_string()),
+                    )
+                }
+                IndexSnapshotKey::LongDigest(hash, size) => {
+                    let digest = DigestInfo::try_from_hash_bytes(&hash, size).ok()?;
+                    (
+                        StoreKey::Digest(digest),
+                        digest_files.remove(&digest.to_string()),
+                    )
+                }
+                IndexSnapshotKey::Str(name) => {
+                    let exists = str_files.remove(&name);
+                    (StoreKey::Str(Cow::Owned(name)), exists)
+                }
+            };
+            exists.then_some((key, entry.data_size, entry.last_access))
+        })
+        .collect();
+
+    let anchor_timestamp = anchor_time.unix_timestamp() as i64;
+    let mut entries = futures::stream::iter(snapshot_entries)
+        .map(|(key, data_size, last_access)| async move {
+            let data_size = match &key {
+                StoreKey::Digest(_) => data_size,
+                // String keys can be replaced with different content, so
+                // their size is read from the file.
+                StoreKey::Str(name) => {
+                    let path = format!("{}/{STR_FOLDER}/{name}", shared_context.content_path);
+                    fs::metadata(&path).await.ok()?.len()
+                }
+            };
+            Some((key, data_size, last_access))
+        })
+        // Keeps the order of the snapshot, which is the LRU order.
+        .buffered(SIMULTANEOUS_METADATA_READS);
+    let mut restored_count = 0;
+    while let Some(entry) = entries.next().await {
+        let Some((key, data_size, last_access)) = entry else {
+            continue;
+        };
+        let seconds_since_anchor =
+            (last_access as i64 - anchor_timestamp).clamp(i64::from(i32::MIN), 0) as i32;
+        insert_entry(
+            evicting_map,
+            shared_context,
+            block_size,
+            key,
+            data_size,
+            seconds_since_anchor,
+        )
+        .await;
+        restored_count += 1;
+    }
+    drop(entries);
+
+    // Files written after the snapshot was taken.
+    let new_files: Vec<(String, FileType)> = digest_files
+        .into_iter()
+        .map(|file_name| (file_name, FileType::Digest))
+        .chain(
+            str_files
+                .into_iter()
+                .map(|file_name| (file_name, FileType::String)),
+        )
+        .collect();
+    let new_file_count = new_files.len();
+    let mut new_entries = futures::stream::iter(new_files)
+        .map(|(file_name, file_type)| async move {
+            let folder = match file_type {
+                FileType::Digest => DIGEST_FOLDER,
+                FileType::String => STR_FOLDER,
+            };
+            let path = format!("{}/{folder}/{file_name}", shared_context.content_path);
+            let result = match key_from_file(&file_name, file_type) {
+                Ok(key) => fs::metadata(&path)
+                    .await
+                    .map(|metadata| (key.into_owned(), metadata.len())),
+                Err(err) => Err(err),
+            };
+            (path, result)
+        })
+        .buffer_unordered(SIMULTANEOUS_METADATA_READS);
+    while let Some((path, result)) = new_entries.next().await {
+        match result {
+            Ok((key, data_size)) => {
+                insert_entry(evicting_map, shared_context, block_size, key, data_size, 0).await;
+            }
+            Err(err) => {
+                event!(
+                    Level::WARN,
+                    ?path,
+                    ?err,
+                    "Failed to add file to eviction cache",
+                );
+                // Ignore result.
+                let _ = fs::remove_file(&path).await;
+            }
+        }
+    }
+    event!(
+        Level::INFO,
+        snapshot_path,
+        restored_count,
+        new_file_count,
+        "Restored filesystem store index from snapshot"
+    );
+    Ok(true)
+}
+
 async fn prune_temp_path(temp_path: &str) -> Result<(), Error> {
     async fn prune_temp_inner(temp_path: &str, subpath: &str) -> Result<(), Error> {
         let (_permit, dir_handle) = fs::read_dir(format!("{temp_path}/{subpath}"))
@@ -667,11 +965,99 @@ pub struct FilesystemStore<Fe: FileEntry = FileEntryImpl> {
     block_size: u64,
     #[metric(help = "Size of the configured read buffer size")]
     read_buffer_size: usize,
+    uring: Option<Arc<UringIo>>,
+    #[metric(help = "Whether content files are read and written with O_DIRECT")]
+    direct_io: bool,
+    fsck_quarantine_path: Option<String>,
+    #[metric(help = "Number of corrupt entries removed by fsck")]
+    fsck_corrupt_count: AtomicU64,
+    #[metric(help = "Path the eviction index is periodically written to")]
+    index_snapshot_path: Option<String>,
     weak_self: Weak<Self>,
     sleep_fn: fn(Duration) -> Sleep,
     rename_fn: fn(&OsStr, &OsStr) -> Result<(), std::io::Error>,
 }
 
+/// A content or temp file that is being read or written, either through
+/// io_uring, with `O_DIRECT` or through regular file I/O.
+enum ContentFile {
+    Slot(fs::ResumeableFileSlot),
+    Uring(UringFile),
+    Direct(DirectFile),
+}
+
+impl ContentFile {
+    async fn new(
+        uring: Option<&Arc<UringIo>>,
+        direct_io: bool,
+        slot: fs::ResumeableFileSlot,
+    ) -> Result<Self, Error> {
+        if let Some(uring) = uring {
+            return Ok(Self::Uring(UringFile::new(uring.clone(), slot).await?));
+        }
+        if direct_io {
+            return Ok(Self::Direct(DirectFile::new(slot).await?));
+        }
+        Ok(Self::Slot(slot))
+    }
+
+    /// Reads up to `max_len` bytes. An empty buffer means EOF.
+    async fn read_chunk(&mut self, max_len: usize) -> Result<BytesMut, Error> {
+        match self {
+            Self::Slot(slot) => {
+                let mut buf = BytesMut::with_capacity(max_len);
+                slot.as_reader()
+                    .await
+                    .err_tip(|| "In ContentFile::read_chunk")?
+                    .read_buf(&mut buf)
+                    .await
+                    .err_tip(|| "Failed to read data in filesystem store")?;
+                Ok(buf)
+            }
+            Self::Uring(file) => file.read_chunk(max_len).await,
+            Self::Direct(file) => file.read_chunk(max_len).await,
+        }
+    }
+
+    async fn write_all(&mut self, mut data: Bytes) -> Result<(), Error> {
+        match self {
+            Self::Slot(slot) => slot
+                .as_writer()
+                .await
+                .err_tip(|| "In ContentFile::write_all")?
+                .write_all_buf(&mut data)
+                .await
+                .err_tip(|| "Failed to write data into filesystem store"),
+            Self::Uring(file) => file.write_all(data).await,
+            Self::Direct(file) => file.write_all(data).await,
+        }
+    }
+
+    /// Writes out data that is still held back.
+    async fn finish(&mut self) -> Result<(), Error> {
+        match self {
+            Self::Slot(_) | Self::Uring(_) => Ok(()),
+            Self::Direct(file) => file.finish().await,
+        }
+    }
+
+    async fn close_file(&mut self) -> Result<(), Error> {
+        match self {
+            Self::Slot(slot) => slot.close_file().await,
+            Self::Uring(file) => file.close_file().await,
+            Self::Direct(file) => file.close_file().await,
+        }
+    }
+
+    fn into_slot(self) -> fs::ResumeableFileSlot {
+        match self {
+            Self::Slot(slot) => slot,
+            Self::Uring(file) => file.into_inner(),
+            Self::Direct(file) => file.into_inner(),
+        }
+    }
+}
+
 impl<Fe: FileEntry> FilesystemStore<Fe> {
     pub async fn new(spec: &FilesystemSpec) -> Result<Arc<Self>, Error> {
         Self::new_with_timeout_and_rename_fn(spec, sleep, |from, to| std::fs::rename(from, to))
@@ -694,7 +1080,7 @@ impl<Fe: FileEntry> FilesystemStore<Fe> {
 
         let now = SystemTime::now();
 
-        let empty_policy = nativelink_config::stores::EvictionPolicy::default();
+        let empty_policy = EvictionPolicy::default();
         let eviction_policy = spec.eviction_policy.as_ref().unwrap_or(&empty_policy);
         let evicting_map = Arc::new(EvictingMap::new(eviction_policy, now));
 
@@ -714,30 +1100,235 @@ impl<Fe: FileEntry> FilesystemStore<Fe> {
         } else {
             spec.block_size
         };
-        add_files_to_cache(
-            evicting_map.as_ref(),
-            &now,
-            &shared_context,
-            block_size,
-            rename_fn,
-        )
-        .await?;
+        let index_snapshot_path = if spec.index_snapshot_path.is_empty() {
+            None
+        } else {
+            Some(spec.index_snapshot_path.clone())
+        };
+        let restored_from_snapshot = match &index_snapshot_path {
+            Some(snapshot_path) => {
+                add_files_from_index_snapshot(
+                    evicting_map.as_ref(),
+                    &now,
+                    &shared_context,
+                    block_size,
+                    snapshot_path,
+                )
+                .await?
+            }
+            None => false,
+        };
+        if !restored_from_snapshot {
+            add_files_to_cache(
+                evicting_map.as_ref(),
+                &now,
+                &shared_context,
+                block_size,
+                rename_fn,
+            )
+            .await?;
+        }
         prune_temp_path(&shared_context.temp_path).await?;
+        EvictingMap::spawn_background_eviction(&evicting_map, |evicting_map| evicting_map);
 
         let read_buffer_size = if spec.read_buffer_size == 0 {
             DEFAULT_BUFF_SIZE
         } else {
             spec.read_buffer_size as usize
         };
-        Ok(Arc::new_cyclic(|weak_self| Self {
+        error_if!(
+            spec.use_io_uring && spec.use_direct_io,
+            "use_io_uring and use_direct_io can not be combined in FilesystemStore"
+        );
+        let uring = if spec.use_io_uring {
+            UringIo::try_new(DEFAULT_QUEUE_DEPTH)
+        } else {
+            None
+        };
+        let fsck_quarantine_path = if spec.fsck_quarantine_path.is_empty() {
+            None
+        } else {
+            fs::create_dir_all(&spec.fsck_quarantine_path)
+                .await
+                .err_tip(|| format!("Failed to create directory {}", spec.fsck_quarantine_path))?;
+            Some(spec.fsck_quarantine_path.clone())
+        };
+        let store = Arc::new_cyclic(|weak_self| Self {
             shared_context,
             evicting_map,
             block_size,
             read_buffer_size,
+            uring,
+            direct_io: spec.use_direct_io,
+            fsck_quarantine_path,
+            fsck_corrupt_count: AtomicU64::new(0),
+            index_snapshot_path,
             weak_self: weak_self.clone(),
             sleep_fn,
             rename_fn,
-        }))
+        });
+        if spec.fsck_on_startup != FsckMode::none {
+            let report = store
+                .fsck(spec.fsck_on_startup)
+                .await
+                .err_tip(|| "Failed to check filesystem store on startup")?;
+            event!(
+                Level::INFO,
+                ?report,
+                content_path = spec.content_path,
+                "Finished filesystem store integrity check"
+            );
+        }
+        if store.index_snapshot_path.is_some() {
+            let interval = if spec.index_snapshot_interval_s == 0 {
+                DEFAULT_INDEX_SNAPSHOT_INTERVAL
+            } else {
+                Duration::from_secs(spec.index_snapshot_interval_s)
+            };
+            let weak_store = Arc::downgrade(&store);
+            background_spawn!("filesystem_store_index_snapshot", async move {
+                loop {
+                    (sleep_fn)(interval).await;
+                    let Some(store) = weak_store.upgrade() else {
+                        return;
+                    };
+                    if let Err(err) = store.write_index_snapshot().await {
+                        event!(
+                            Level::WARN,
+                            ?err,
+                            "Failed to write filesystem store index snapshot"
+                        );
+                    }
+                }
+            });
+        }
+        Ok(store)
+    }
+
+    /// Writes the eviction index to `index_snapshot_path`, so the next start
+    /// can restore it without reading the metadata of every file. Does
+    /// nothing if no snapshot path is configured.
+    pub async fn write_index_snapshot(&self) -> Result<(), Error> {
+        let Some(snapshot_path) = self.index_snapshot_path.clone() else {
+            return Ok(());
+        };
+        let mut entries = Vec::new();
+        self.evicting_map
+            .for_each_with_last_access(|key, entry, last_access| {
+                let key: &StoreKey<'_> = key.borrow();
+                let key = match key {
+                    StoreKey::Digest(digest) => match <[u8; 32]>::try_from(&**digest.packed_hash())
+                    {
+                        Ok(hash) => IndexSnapshotKey::Digest(hash, digest.size_bytes()),
+                        Err(_) => IndexSnapshotKey::LongDigest(
+                            digest.packed_hash().to_vec(),
+                            digest.size_bytes(),
+                        ),
+                    },
+                    StoreKey::Str(name) => IndexSnapshotKey::Str(name.to_string()),
+                };
+                entries.push(IndexSnapshotEntry {
+                    key,
+                    data_size: entry.data_size(),
+                    last_access,
+                });
+            })
+            .await;
+        let data = bincode::serialize(&IndexSnapshot {
+            version: INDEX_SNAPSHOT_VERSION,
+            entries,
+        })
+        .map_err(|e| make_err!(Code::Internal, "Failed to serialize index snapshot: {e:?}"))?;
+        spawn_blocking!("filesystem_store_write_index_snapshot", move || {
+            // Written to a temporary file first, so a crash never leaves a
+            // partial snapshot behind.
+            let temp_path = format!("{snapshot_path}.tmp");
+            let mut file = std::fs::File::create(&temp_path)?;
+            std::io::Write::write_all(&mut file, &data)?;
+            file.sync_all()?;
+            std::fs::rename(&temp_path, &snapshot_path)
+        })
+        .await
+        .map_err(|e| make_err!(Code::Internal, "Index snapshot task failed: {e:?}"))?
+        .err_tip(|| "Failed to write filesystem store index snapshot")
+    }
+
+    /// Checks the entries in the store against the digests in their file
+    /// names and removes the corrupt ones. Entries with string keys are
+    /// skipped, because their names don't say what the content should be.
+    pub async fn fsck(&self, mode: FsckMode) -> Result<FsckReport, Error> {
+        let mut report = FsckReport::default();
+        if mode == FsckMode::none {
+            return Ok(report);
+        }
+        let mut entries = Vec::new();
+        self.evicting_map
+            .range::<_, StoreKey<'static>>(.., |key, entry| {
+                match StoreKey::from(key.clone()) {
+                    StoreKey::Digest(digest) => entries.push((digest, entry.clone())),
+                    StoreKey::Str(_) => report.skipped += 1,
+                }
+                true
+            })
+            .await;
+        let mut checks = futures::stream::iter(entries)
+            .map(|(digest, entry)| async move {
+                let corrupt = self.fsck_entry(mode, digest, &entry).await;
+                (digest, entry, corrupt)
+            })
+            .buffer_unordered(SIMULTANEOUS_FSCK_CHECKS);
+        while let Some((digest, entry, corrupt)) = checks.next().await {
+            report.checked += 1;
+            if !corrupt {
+                continue;
+            }
+            report.corrupt += 1;
+            self.fsck_corrupt_count.fetch_add(1, Ordering::Relaxed);
+            // Another entry may have replaced the corrupt one in the meantime.
+            self.evicting_map
+                .remove_if(&StoreKey::Digest(digest), |map_entry| {
+                    Arc::<Fe>::ptr_eq(map_entry, &entry)
+                })
+                .await;
+        }
+        Ok(report)
+    }
+
+    /// Returns whether the file of `entry` does not match `digest`. Corrupt
+    /// files are copied to the quarantine directory if there is one.
+    async fn fsck_entry(&self, mode: FsckMode, digest: DigestInfo, entry: &Fe) -> bool {
+        let quarantine_path = self.fsck_quarantine_path.as_deref();
+        let result = entry
+            .get_file_path_locked(move |full_content_path| async move {
+                let corrupt = !file_matches_digest(mode, digest, &full_content_path).await;
+                if let (true, Some(quarantine_path)) = (corrupt, quarantine_path) {
+                    let quarantine_file = format!("{quarantine_path}/{digest}");
+                    if let Err(err) = fs::hard_link(&full_content_path, &quarantine_file).await {
+                        event!(
+                            Level::WARN,
+                            ?full_content_path,
+                            ?quarantine_file,
+                            ?err,
+                            "Failed to quarantine corrupt file in filesystem store"
+                        );
+                    }
+                }
+                Result::<bool, Error>::Ok(corrupt)
+            })
+            .await;
+        match result {
+            Ok(corrupt) => corrupt,
+            Err(err) => {
+                // A file that can not be found is as good as corrupt.
+                event!(
+                    Level::WARN,
+                    ?digest,
+                    ?err,
+                    "Failed to check file in filesystem store"
+                );
+                true
+            }
+        }
     }
 
     pub fn get_arc(&self) -> Option<Arc<Self>> {
@@ -754,10 +1345,14 @@ impl<Fe: FileEntry> FilesystemStore<Fe> {
     async fn update_file<'a>(
         self: Pin<&'a Self>,
         mut entry: Fe,
-        mut resumeable_temp_file: fs::ResumeableFileSlot,
+        resumeable_temp_file: fs::ResumeableFileSlot,
         final_key: StoreKey<'static>,
         mut reader: DropCloserReadHalf,
     ) -> Result<(), Error> {
+        let mut temp_file =
+            ContentFile::new(self.uring.as_ref(), self.direct_io, resumeable_temp_file)
+                .await
+                .err_tip(|| "in filesystem_store::update_file")?;
         let mut data_size = 0;
         loop {
             let Ok(data_result) = timeout(fs::idle_file_descriptor_timeout(), reader.recv()).await
@@ -768,26 +1363,28 @@ impl<Fe: FileEntry> FilesystemStore<Fe> {
                 // descriptors may be open at any given time. If we are streaming from
                 // File -> File, it can cause a deadlock if the Write file is not sending
                 // data because it is waiting for a file descriotor to open before sending data.
-                resumeable_temp_file.close_file().await.err_tip(|| {
+                temp_file.close_file().await.err_tip(|| {
                     "Could not close file due to timeout in FileSystemStore::update_file"
                 })?;
                 continue;
             };
-            let mut data = data_result.err_tip(|| "Failed to receive data in filesystem store")?;
+            let data = data_result.err_tip(|| "Failed to receive data in filesystem store")?;
             let data_len = data.len();
             if data_len == 0 {
                 break; // EOF.
             }
-            resumeable_temp_file
-                .as_writer()
+            temp_file
+                .write_all(data)
                 .await
-                .err_tip(|| "in filesystem_store::update_file")?
-                .write_all_buf(&mut data)
-                .await
-                .err_tip(|| "Failed to write data into filesystem store")?;
+                .err_tip(|| "in filesystem_store::update_file")?;
             data_size += data_len as u64;
         }
 
+        temp_file
+            .finish()
+            .await
+            .err_tip(|| "in filesystem_store::update_file")?;
+        let mut resumeable_temp_file = temp_file.into_slot();
         resumeable_temp_file
             .as_writer()
             .await
@@ -913,6 +1510,27 @@ impl<Fe: FileEntry> StoreDriver for FilesystemStore<Fe> {
         Ok(())
     }
 
+    async fn list(
+        self: Pin<&Self>,
+        range: (Bound<StoreKey<'_>>, Bound<StoreKey<'_>>),
+        handler: &mut (dyn for<'a> FnMut(&'a StoreKey) -> bool + Send + Sync + '_),
+    ) -> Result<u64, Error> {
+        let range = (
+            range.0.map(StoreKey::into_owned),
+            range.1.map(StoreKey::into_owned),
+        );
+        let iterations = self
+            .evicting_map
+            .range(range, move |key, _value| handler(key.borrow()))
+            .await;
+        Ok(iterations)
+    }
+
+    async fn remove(self: Pin<&Self>, key: StoreKey<'_>) -> Result<bool, Error> {
+        // The file is deleted once the last reader of the entry is done.
+        Ok(self.evicting_map.remove(&key.into_owned()).await)
+    }
+
     async fn update(
         self: Pin<&Self>,
         key: StoreKey<'_>,
@@ -936,7 +1554,10 @@ impl<Fe: FileEntry> StoreDriver for FilesystemStore<Fe> {
     }
 
     fn optimized_for(&self, optimization: StoreOptimizations) -> bool {
-        optimization == StoreOptimizations::FileUpdates
+        matches!(
+            optimization,
+            StoreOptimizations::FileUpdates | StoreOptimizations::OrderedList
+        )
     }
 
     async fn update_with_whole_file(
@@ -1004,17 +1625,19 @@ impl<Fe: FileEntry> StoreDriver for FilesystemStore<Fe> {
             )
         })?;
         let read_limit = length.unwrap_or(u64::MAX);
-        let mut resumeable_temp_file = entry.read_file_part(offset, read_limit).await?;
+        let mut content_file = ContentFile::new(
+            self.uring.as_ref(),
+            self.direct_io,
+            entry.read_file_part(offset, read_limit).await?,
+        )
+        .await
+        .err_tip(|| "In FileSystemStore::get_part()")?;
 
         loop {
-            let mut buf = BytesMut::with_capacity(self.read_buffer_size);
-            resumeable_temp_file
-                .as_reader()
+            let buf = content_file
+                .read_chunk(self.read_buffer_size)
                 .await
-                .err_tip(|| "In FileSystemStore::get_part()")?
-                .read_buf(&mut buf)
-                .await
-                .err_tip(|| "Failed to read data in filesystem store")?;
+                .err_tip(|| "In FileSystemStore::get_part()")?;
             if buf.is_empty() {
                 break; // EOF.
             }
@@ -1030,7 +1653,7 @@ impl<Fe: FileEntry> StoreDriver for FilesystemStore<Fe> {
                 tokio::pin!(sleep_fn);
                 tokio::select! {
                     () = & mut (sleep_fn) => {
-                        resumeable_temp_file
+                        content_file
                             .close_file()
                             .await
                             .err_tip(|| "Could not close file due to timeout in FileSystemStore::get_part")?;
@@ -1068,6 +1691,26 @@ impl<Fe: FileEntry> StoreDriver for FilesystemStore<Fe> {
     fn register_health(self: Arc<Self>, registry: &mut HealthRegistryBuilder) {
         registry.register_indicator(self);
     }
+
+    async fn reconfigure(&self, spec: &StoreSpec, check_only: bool) -> Result<(), Error> {
+        let StoreSpec::filesystem(spec) = spec else {
+            return Err(make_input_err!(
+                "FilesystemStore can only be reconfigured with a filesystem spec"
+            ));
+        };
+        let empty_policy = EvictionPolicy::default();
+        let eviction_policy = spec.eviction_policy.as_ref().unwrap_or(&empty_policy);
+        let result = if check_only {
+            self.evicting_map.check_policy(eviction_policy).await
+        } else {
+            self.evicting_map.set_policy(eviction_policy).await
+        };
+        result.err_tip(|| "In FilesystemStore::reconfigure")
+    }
+
+    async fn evict(&self, bytes: u64) -> Result<u64, Error> {
+        Ok(self.evicting_map.run_eviction(bytes).await)
+    }
 }
 
 #[async_trait]
@@ -0,0 +1,887 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::borrow::Cow;
+use std::env;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use bytes::Bytes;
+use futures::stream::{unfold, FuturesUnordered};
+use futures::TryStreamExt;
+use hyper::body::HttpBody;
+use hyper::client::connect::HttpConnector;
+use hyper::header::{
+    AUTHORIZATION, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, LAST_MODIFIED, LOCATION, RANGE,
+};
+use hyper::http::request::Builder as RequestBuilder;
+use hyper::{Body, Request, Response, StatusCode};
+use hyper_rustls::HttpsConnector;
+use nativelink_config::stores::{GcsSpec, StoreSpec};
+use nativelink_error::{make_err, make_input_err, Code, Error, ResultExt};
+use nativelink_metric::MetricsComponent;
+use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
+use nativelink_util::health_utils::{HealthStatus, HealthStatusIndicator};
+use nativelink_util::instant_wrapper::InstantWrapper;
+use nativelink_util::retry::{Retrier, RetryResult};
+use nativelink_util::store_trait::{StoreDriver, StoreKey, UploadSizeInfo};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use rand::rngs::OsRng;
+use rand::Rng;
+use ring::rand::SystemRandom;
+use ring::signature::{RsaKeyPair, RSA_PKCS1_SHA256};
+use rustls::pki_types::PrivateKeyDer;
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use tokio::time::sleep;
+use tracing::{event, Level};
+
+use crate::cas_utils::is_zero_digest;
+
+// Default endpoint of the GCS API.
+// Note: If you change this, adjust the docs in the config.
+const DEFAULT_ENDPOINT: &str = "https://storage.googleapis.com";
+
+// All but the last part of a resumable upload must be a multiple of this. See:
+// https://cloud.google.com/storage/docs/performing-resumable-uploads#chunked-upload
+const RESUMABLE_CHUNK_ALIGNMENT: usize = 256 * 1024; // 256KiB.
+
+// Default size of each part of a resumable upload.
+// Note: If you change this, adjust the docs in the config.
+const DEFAULT_RESUMABLE_CHUNK_SIZE: usize = 8 * 1024 * 1024; // 8MiB.
+
+// OAuth2 scope requested for access tokens.
+const STORAGE_SCOPE: &str = "https://www.googleapis.com/auth/devstorage.read_write";
+
+// Default OAuth2 token endpoint for service account keys without a `token_uri`.
+const DEFAULT_TOKEN_URI: &str = "https://oauth2.googleapis.com/token";
+
+// Token endpoint of the GCE/GKE metadata server (ie: workload identity).
+const METADATA_TOKEN_URL: &str =
+    "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+
+// Access tokens are refreshed this long before they expire.
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+// Object names are sent as a single path segment or query value.
+const OBJECT_NAME_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Sends the HTTP requests of a [`GcsStore`].
+#[async_trait]
+pub trait GcsHttpClient: Send + Sync + 'static {
+    async fn send(&self, request: Request<Body>) -> Result<Response<Body>, Error>;
+}
+
+#[async_trait]
+impl GcsHttpClient for hyper::Client<HttpsConnector<HttpConnector>> {
+    async fn send(&self, request: Request<Body>) -> Result<Response<Body>, Error> {
+        self.request(request)
+            .await
+            .map_err(|e| make_err!(Code::Unavailable, "Failed to send request to GCS: {e:?}"))
+    }
+}
+
+/// Provides the OAuth2 access tokens sent with every request to GCS.
+#[async_trait]
+pub trait GcsTokenProvider: Send + Sync + 'static {
+    async fn access_token(&self) -> Result<String, Error>;
+}
+
+#[derive(Deserialize)]
+struct ServiceAccountKey {
+    client_email: String,
+    private_key: String,
+    token_uri: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+enum TokenSource {
+    /// Sign a JWT with the key of a service account and exchange it for an
+    /// access token.
+    ServiceAccount {
+        client_email: String,
+        token_uri: String,
+        key_pair: RsaKeyPair,
+    },
+    /// Ask the metadata server of the machine or pod.
+    MetadataServer,
+}
+
+/// A [`GcsTokenProvider`] for service account keys and the metadata server.
+/// Tokens are cached until shortly before they expire.
+pub struct OAuthTokenProvider {
+    http_client: Arc<dyn GcsHttpClient>,
+    source: TokenSource,
+    cached_token: Mutex<Option<(String, SystemTime)>>,
+}
+
+impl OAuthTokenProvider {
+    pub fn new(spec: &GcsSpec, http_client: Arc<dyn GcsHttpClient>) -> Result<Self, Error> {
+        let credentials_file = spec
+            .credentials_file
+            .clone()
+            .or_else(|| env::var("GOOGLE_APPLICATION_CREDENTIALS").ok());
+        let source = match credentials_file {
+            Some(credentials_file) => {
+                let data = std::fs::read(&credentials_file).err_tip(|| {
+                    format!("Could not read GCS credentials file {credentials_file}")
+                })?;
+                let key: ServiceAccountKey = serde_json::from_slice(&data).map_err(|e| {
+                    make_err!(
+                        Code::InvalidArgument,
+                        "Could not parse GCS service account key {credentials_file}: {e}"
+                    )
+                })?;
+                let Some(PrivateKeyDer::Pkcs8(der)) =
+                    rustls_pemfile::private_key(&mut key.private_key.as_bytes())
+                        .err_tip(|| format!("Could not parse private_key in {credentials_file}"))?
+                else {
+                    return Err(make_err!(
+                        Code::InvalidArgument,
+                        "Expected a PKCS#8 private_key in {credentials_file}"
+                    ));
+                };
+                let key_pair = RsaKeyPair::from_pkcs8(der.secret_pkcs8_der()).map_err(|e| {
+                    make_err!(
+                        Code::InvalidArgument,
+                        "Invalid private_key in {credentials_file}: {e}"
+                    )
+                })?;
+                TokenSource::ServiceAccount {
+                    client_email: key.client_email,
+                    token_uri: key
+                        .token_uri
+                        .unwrap_or_else(|| DEFAULT_TOKEN_URI.to_string()),
+                    key_pair,
+                }
+            }
+            None => TokenSource::MetadataServer,
+        };
+        Ok(Self {
+            http_client,
+            source,
+            cached_token: Mutex::new(None),
+        })
+    }
+
+    async fn fetch_token(&self) -> Result<TokenResponse, Error> {
+        let request = match &self.source {
+            TokenSource::ServiceAccount {
+                client_email,
+                token_uri,
+                key_pair,
+            } => {
+                let assertion = make_jwt(client_email, token_uri, key_pair)?;
+                Request::post(token_uri)
+                    .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+                    .body(Body::from(format!(
+                        "grant_type=urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Ajwt-bearer&assertion={assertion}"
+                    )))
+            }
+            TokenSource::MetadataServer => Request::get(METADATA_TOKEN_URL)
+                .header("Metadata-Flavor", "Google")
+                .body(Body::empty()),
+        }
+        .map_err(|e| make_err!(Code::Internal, "Could not build GCS token request: {e:?}"))?;
+        let response = self
+            .http_client
+            .send(request)
+            .await
+            .err_tip(|| "While requesting GCS access token")?;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body())
+            .await
+            .map_err(|e| {
+                make_err!(
+                    Code::Unavailable,
+                    "Could not read GCS token response: {e:?}"
+                )
+            })?;
+        if !status.is_success() {
+            return Err(make_err!(
+                Code::Unavailable,
+                "Failed to get GCS access token ({status}): {}",
+                String::from_utf8_lossy(&body)
+            ));
+        }
+        serde_json::from_slice(&body)
+            .map_err(|e| make_err!(Code::Unavailable, "Could not parse GCS token response: {e}"))
+    }
+}
+
+#[async_trait]
+impl GcsTokenProvider for OAuthTokenProvider {
+    async fn access_token(&self) -> Result<String, Error> {
+        let mut cached_token = self.cached_token.lock().await;
+        if let Some((token, expires_at)) = &*cached_token {
+            if SystemTime::now() + TOKEN_REFRESH_MARGIN < *expires_at {
+                return Ok(token.clone());
+            }
+        }
+        let response = self.fetch_token().await?;
+        let expires_at = SystemTime::now() + Duration::from_secs(response.expires_in);
+        *cached_token = Some((response.access_token.clone(), expires_at));
+        Ok(response.access_token)
+    }
+}
+
+/// Create the signed JWT a service account exchanges for an access token. See:
+/// https://developers.google.com/identity/protocols/oauth2/service-account#authorizingrequests
+fn make_jwt(client_email: &str, token_uri: &str, key_pair: &RsaKeyPair) -> Result<String, Error> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| make_err!(Code::Internal, "System time is before the epoch: {e:?}"))?
+        .as_secs();
+    let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"RS256","typ":"JWT"}"#);
+    let claims = URL_SAFE_NO_PAD.encode(
+        serde_json::json!({
+            "iss": client_email,
+            "scope": STORAGE_SCOPE,
+            "aud": token_uri,
+            "iat": now,
+            "exp": now + 3600,
+        })
+        .to_string(),
+    );
+    let message = format!("{header}.{claims}");
+    let mut signature = vec![0; key_pair.public().modulus_len()];
+    key_pair
+        .sign(
+            &RSA_PKCS1_SHA256,
+            &SystemRandom::new(),
+            message.as_bytes(),
+            &mut signature,
+        )
+        .map_err(|e| make_err!(Code::Internal, "Could not sign GCS token request: {e:?}"))?;
+    Ok(format!("{message}.{}", URL_SAFE_NO_PAD.encode(signature)))
+}
+
+/// Server side errors and throttling are retried, everything else is final.
+fn is_retryable_status(status: StatusCode) -> bool {
+    status.is_server_error()
+        || status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::REQUEST_TIMEOUT
+}
+
+fn retry_for_status<T>(status: StatusCode, err: Error) -> RetryResult<T> {
+    if is_retryable_status(status) {
+        RetryResult::Retry(err)
+    } else {
+        RetryResult::Err(err)
+    }
+}
+
+/// Build an error for an unexpected response, including the error message
+/// GCS sent in the body.
+async fn error_from_response(context: &str, response: Response<Body>) -> Error {
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body())
+        .await
+        .unwrap_or_default();
+    make_err!(
+        Code::Unavailable,
+        "{context} failed with status {status} in GCS: {}",
+        String::from_utf8_lossy(&body)
+    )
+}
+
+/// The number of bytes of a resumable upload that GCS has persisted, taken
+/// from the `Range: bytes=0-<last>` header of a `308 Resume Incomplete`.
+fn persisted_bytes(response: &Response<Body>) -> Result<u64, Error> {
+    let Some(range) = response.headers().get(RANGE) else {
+        return Ok(0);
+    };
+    range
+        .to_str()
+        .ok()
+        .and_then(|range| range.strip_prefix("bytes=0-"))
+        .and_then(|last| last.parse::<u64>().ok())
+        .map(|last| last + 1)
+        .err_tip(|| format!("Invalid range header {range:?} in GCS response"))
+}
+
+#[derive(MetricsComponent)]
+pub struct GcsStore<NowFn> {
+    http_client: Arc<dyn GcsHttpClient>,
+    token_provider: Arc<dyn GcsTokenProvider>,
+    now_fn: NowFn,
+    #[metric(help = "The endpoint of the GCS API")]
+    endpoint: String,
+    #[metric(help = "The bucket name for the GCS store")]
+    bucket: String,
+    #[metric(help = "The key prefix for the GCS store")]
+    key_prefix: String,
+    retrier: Retrier,
+    #[metric(help = "The number of seconds to consider an object expired")]
+    consider_expired_after_s: i64,
+    #[metric(help = "The size of each part of a resumable upload")]
+    resumable_chunk_size: usize,
+}
+
+impl<I, NowFn> GcsStore<NowFn>
+where
+    I: InstantWrapper,
+    NowFn: Fn() -> I + Send + Sync + Unpin + 'static,
+{
+    pub fn new(spec: &GcsSpec, now_fn: NowFn) -> Result<Arc<Self>, Error> {
+        let jitter_amt = spec.retry.jitter;
+        let jitter_fn = Arc::new(move |delay: Duration| {
+            if jitter_amt == 0. {
+                return delay;
+            }
+            let min = 1. - (jitter_amt / 2.);
+            let max = 1. + (jitter_amt / 2.);
+            delay.mul_f32(OsRng.gen_range(min..max))
+        });
+        let http_client: Arc<dyn GcsHttpClient> = {
+            // The metadata server is only reachable over http, so the scheme of
+            // the endpoint is checked in `new_with_client_and_jitter` instead.
+            let connector_with_schemes = hyper_rustls::HttpsConnectorBuilder::new()
+                .with_webpki_roots()
+                .https_or_http();
+            let connector = if spec.disable_http2 {
+                connector_with_schemes.enable_http1().build()
+            } else {
+                connector_with_schemes.enable_http1().enable_http2().build()
+            };
+            Arc::new(hyper::Client::builder().build::<_, Body>(connector))
+        };
+        let token_provider = Arc::new(
+            OAuthTokenProvider::new(spec, http_client.clone())
+                .err_tip(|| "While loading GCS credentials")?,
+        );
+        Self::new_with_client_and_jitter(spec, http_client, token_provider, jitter_fn, now_fn)
+    }
+
+    pub fn new_with_client_and_jitter(
+        spec: &GcsSpec,
+        http_client: Arc<dyn GcsHttpClient>,
+        token_provider: Arc<dyn GcsTokenProvider>,
+        jitter_fn: Arc<dyn Fn(Duration) -> Duration + Send + Sync>,
+        now_fn: NowFn,
+    ) -> Result<Arc<Self>, Error> {
+        let endpoint = spec
+            .endpoint
+            .as_deref()
+            .unwrap_or(DEFAULT_ENDPOINT)
+            .trim_end_matches('/')
+            .to_string();
+        if !endpoint.starts_with("https://") && !spec.insecure_allow_http {
+            return Err(make_err!(
+                Code::InvalidArgument,
+                "GCS endpoint {endpoint} must use https unless insecure_allow_http is set"
+            ));
+        }
+        let resumable_chunk_size = if spec.resumable_chunk_size == 0 {
+            DEFAULT_RESUMABLE_CHUNK_SIZE
+        } else {
+            spec.resumable_chunk_size
+        };
+        if resumable_chunk_size % RESUMABLE_CHUNK_ALIGNMENT != 0 {
+            return Err(make_err!(
+                Code::InvalidArgument,
+                "resumable_chunk_size must be a multiple of {RESUMABLE_CHUNK_ALIGNMENT} in GCS store, got {resumable_chunk_size}"
+            ));
+        }
+        Ok(Arc::new(Self {
+            http_client,
+            token_provider,
+            now_fn,
+            endpoint,
+            bucket: spec.bucket.to_string(),
+            key_prefix: spec.key_prefix.as_ref().unwrap_or(&String::new()).clone(),
+            retrier: Retrier::new(
+                Arc::new(|duration| Box::pin(sleep(duration))),
+                jitter_fn,
+                spec.retry.clone(),
+            ),
+            consider_expired_after_s: i64::from(spec.consider_expired_after_s),
+            resumable_chunk_size,
+        }))
+    }
+
+    fn make_object_name(&self, key: &StoreKey<'_>) -> String {
+        let name = format!("{}{}", self.key_prefix, key.as_str());
+        utf8_percent_encode(&name, OBJECT_NAME_ENCODE_SET).to_string()
+    }
+
+    /// URL of an object in the XML API, used for metadata and downloads.
+    fn object_url(&self, object_name: &str) -> String {
+        format!("{}/{}/{object_name}", self.endpoint, self.bucket)
+    }
+
+    /// URL to start an upload of an object with the JSON API.
+    fn upload_url(&self, object_name: &str, upload_type: &str) -> String {
+        format!(
+            "{}/upload/storage/v1/b/{}/o?uploadType={upload_type}&name={object_name}",
+            self.endpoint, self.bucket
+        )
+    }
+
+    /// Send an authenticated request to GCS.
+    async fn send(&self, request: RequestBuilder, body: Body) -> Result<Response<Body>, Error> {
+        let token = self
+            .token_provider
+            .access_token()
+            .await
+            .err_tip(|| "While getting access token in GCS store")?;
+        let request = request
+            .header(AUTHORIZATION, format!("Bearer {token}"))
+            .body(body)
+            .map_err(|e| make_err!(Code::Internal, "Could not build GCS request: {e:?}"))?;
+        self.http_client.send(request).await
+    }
+
+    async fn has(self: Pin<&Self>, digest: &StoreKey<'_>) -> Result<Option<u64>, Error> {
+        let url = &self.object_url(&self.make_object_name(digest));
+        self.retrier
+            .retry(unfold((), move |state| async move {
+                let response = match self.send(Request::head(url), Body::empty()).await {
+                    Ok(response) => response,
+                    Err(err) => return Some((RetryResult::Retry(err), state)),
+                };
+                let status = response.status();
+                if status == StatusCode::NOT_FOUND {
+                    return Some((RetryResult::Ok(None), state));
+                }
+                if !status.is_success() {
+                    let err = error_from_response("HEAD object", response).await;
+                    return Some((retry_for_status(status, err), state));
+                }
+                let headers = response.headers();
+                if self.consider_expired_after_s != 0 {
+                    let last_modified = headers
+                        .get(LAST_MODIFIED)
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(|v| httpdate::parse_http_date(v).ok())
+                        .and_then(|v| v.duration_since(UNIX_EPOCH).ok());
+                    if let Some(last_modified) = last_modified {
+                        let now_s = (self.now_fn)().unix_timestamp() as i64;
+                        if last_modified.as_secs() as i64 + self.consider_expired_after_s <= now_s {
+                            return Some((RetryResult::Ok(None), state));
+                        }
+                    }
+                }
+                let Some(length) = headers
+                    .get(CONTENT_LENGTH)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse::<u64>().ok())
+                else {
+                    return Some((
+                        RetryResult::Err(make_err!(
+                            Code::Unavailable,
+                            "Missing or invalid content length in GCS: {:?}",
+                            headers.get(CONTENT_LENGTH)
+                        )),
+                        state,
+                    ));
+                };
+                Some((RetryResult::Ok(Some(length)), state))
+            }))
+            .await
+    }
+
+    /// Upload an object in a single request. Used for small objects, which
+    /// are retried from memory.
+    async fn upload_simple(self: Pin<&Self>, object_name: &str, data: Bytes) -> Result<(), Error> {
+        let url = &self.upload_url(object_name, "media");
+        self.retrier
+            .retry(unfold(data, move |data| async move {
+                let request = Request::post(url)
+                    .header(CONTENT_TYPE, "application/octet-stream")
+                    .header(CONTENT_LENGTH, data.len());
+                let retry_result = match self.send(request, Body::from(data.clone())).await {
+                    Ok(response) if response.status().is_success() => RetryResult::Ok(()),
+                    Ok(response) => {
+                        let status = response.status();
+                        let err = error_from_response("Upload", response).await;
+                        retry_for_status(status, err)
+                    }
+                    Err(err) => RetryResult::Retry(err),
+                };
+                Some((retry_result, data))
+            }))
+            .await
+    }
+
+    /// Start a resumable upload session and return its URL.
+    async fn start_resumable_upload(self: Pin<&Self>, object_name: &str) -> Result<String, Error> {
+        let url = &self.upload_url(object_name, "resumable");
+        self.retrier
+            .retry(unfold((), move |state| async move {
+                let request = Request::post(url)
+                    .header("X-Upload-Content-Type", "application/octet-stream")
+                    .header(CONTENT_LENGTH, 0);
+                let response = match self.send(request, Body::empty()).await {
+                    Ok(response) => response,
+                    Err(err) => return Some((RetryResult::Retry(err), state)),
+                };
+                let status = response.status();
+                if !status.is_success() {
+                    let err = error_from_response("Starting resumable upload", response).await;
+                    return Some((retry_for_status(status, err), state));
+                }
+                let retry_result = response
+                    .headers()
+                    .get(LOCATION)
+                    .and_then(|v| v.to_str().ok())
+                    .map_or_else(
+                        || {
+                            RetryResult::Err(make_err!(
+                                Code::Internal,
+                                "Expected a session location in GCS resumable upload response"
+                            ))
+                        },
+                        |location| RetryResult::Ok(location.to_string()),
+                    );
+                Some((retry_result, state))
+            }))
+            .await
+    }
+
+    /// Ask GCS how many bytes of a resumable upload it has persisted.
+    async fn query_resumable_upload(&self, session_url: &str) -> Result<u64, Error> {
+        let request = Request::put(session_url)
+            .header(CONTENT_RANGE, "bytes */*")
+            .header(CONTENT_LENGTH, 0);
+        let response = self.send(request, Body::empty()).await?;
+        if response.status() != StatusCode::PERMANENT_REDIRECT {
+            return Err(error_from_response("Querying resumable upload", response).await);
+        }
+        persisted_bytes(&response)
+    }
+
+    /// Upload `part`, which starts at `part_start` of the object, to a
+    /// resumable upload session. After a failure the part is resumed from
+    /// the last byte GCS acknowledged.
+    async fn upload_resumable_part(
+        self: Pin<&Self>,
+        session_url: &str,
+        part_start: u64,
+        part: Bytes,
+        is_last: bool,
+    ) -> Result<(), Error> {
+        let part = &part;
+        let part_end = part_start + part.len() as u64;
+        // The number of bytes of `part` GCS already persisted.
+        self.retrier
+            .retry(unfold(0, move |acknowledged: usize| async move {
+                let data = part.slice(acknowledged..);
+                let start = part_start + acknowledged as u64;
+                let total = if is_last {
+                    part_end.to_string()
+                } else {
+                    "*".to_string()
+                };
+                let content_range = if data.is_empty() {
+                    format!("bytes */{total}")
+                } else {
+                    format!("bytes {start}-{}/{total}", part_end - 1)
+                };
+                let request = Request::put(session_url)
+                    .header(CONTENT_RANGE, content_range)
+                    .header(CONTENT_LENGTH, data.len());
+                let err = match self.send(request, Body::from(data)).await {
+                    Ok(response) if response.status().is_success() => {
+                        return Some((RetryResult::Ok(()), acknowledged));
+                    }
+                    Ok(response) if response.status() == StatusCode::PERMANENT_REDIRECT => {
+                        match persisted_bytes(&response) {
+                            Ok(persisted) if persisted == part_end && !is_last => {
+                                return Some((RetryResult::Ok(()), acknowledged));
+                            }
+                            Ok(persisted) => make_err!(
+                                Code::Aborted,
+                                "GCS persisted {persisted} bytes of resumable upload, expected {part_end}"
+                            ),
+                            Err(err) => err,
+                        }
+                    }
+                    Ok(response) => {
+                        let status = response.status();
+                        let err = error_from_response("Resumable upload", response).await;
+                        // This includes the session having expired or been cancelled.
+                        if !is_retryable_status(status) {
+                            return Some((RetryResult::Err(err), acknowledged));
+                        }
+                        err
+                    }
+                    Err(err) => err,
+                };
+                // Find out where to resume from.
+                let persisted = match self.query_resumable_upload(session_url).await {
+                    Ok(persisted) => persisted,
+                    Err(query_err) => {
+                        return Some((RetryResult::Retry(err.merge(query_err)), acknowledged));
+                    }
+                };
+                let Some(acknowledged) = persisted
+                    .checked_sub(part_start)
+                    .and_then(|v| usize::try_from(v).ok())
+                    .filter(|v| *v <= part.len())
+                else {
+                    return Some((
+                        RetryResult::Err(err.append(format!(
+                            "Can not resume GCS upload of part at {part_start} with {persisted} bytes persisted"
+                        ))),
+                        acknowledged,
+                    ));
+                };
+                Some((RetryResult::Retry(err), acknowledged))
+            }))
+            .await
+    }
+}
+
+#[async_trait]
+impl<I, NowFn> StoreDriver for GcsStore<NowFn>
+where
+    I: InstantWrapper,
+    NowFn: Fn() -> I + Send + Sync + Unpin + 'static,
+{
+    async fn has_with_results(
+        self: Pin<&Self>,
+        keys: &[StoreKey<'_>],
+        results: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        keys.iter()
+            .zip(results.iter_mut())
+            .map(|(key, result)| async move {
+                // We need to do a special pass to ensure our zero key exist.
+                if is_zero_digest(key.borrow()) {
+                    *result = Some(0);
+                    return Ok::<_, Error>(());
+                }
+                *result = self.has(key).await?;
+                Ok::<_, Error>(())
+            })
+            .collect::<FuturesUnordered<_>>()
+            .try_collect()
+            .await
+    }
+
+    async fn update(
+        self: Pin<&Self>,
+        digest: StoreKey<'_>,
+        mut reader: DropCloserReadHalf,
+        upload_size: UploadSizeInfo,
+    ) -> Result<(), Error> {
+        let object_name = &self.make_object_name(&digest);
+
+        // Objects no larger than a single part skip the resumable upload
+        // session, which costs an extra round trip.
+        if let UploadSizeInfo::ExactSize(sz) = upload_size {
+            if sz <= self.resumable_chunk_size as u64 {
+                let data = reader
+                    .consume(Some(
+                        usize::try_from(sz).err_tip(|| "Could not convert size to usize")?,
+                    ))
+                    .await
+                    .err_tip(|| "Failed to read data in GcsStore::update")?;
+                return self.upload_simple(object_name, data).await;
+            }
+        }
+
+        let session_url = &self.start_resumable_upload(object_name).await?;
+        let upload_result = async {
+            let mut part_start = 0;
+            loop {
+                let part = reader
+                    .consume(Some(self.resumable_chunk_size))
+                    .await
+                    .err_tip(|| "Failed to read part in GcsStore::update")?;
+                let is_last = part.len() < self.resumable_chunk_size
+                    || reader
+                        .peek()
+                        .await
+                        .err_tip(|| "Failed to peek in GcsStore::update")?
+                        .is_empty();
+                let part_len = part.len() as u64;
+                self.upload_resumable_part(session_url, part_start, part, is_last)
+                    .await?;
+                if is_last {
+                    return Ok::<_, Error>(());
+                }
+                part_start += part_len;
+            }
+        }
+        .await;
+        // If we fail attempt to cancel the upload session (cleanup).
+        if let Err(err) = upload_result {
+            // Note: We don't retry here because this is just a best attempt.
+            if let Err(cancel_err) = self.send(Request::delete(session_url), Body::empty()).await {
+                event!(
+                    Level::INFO,
+                    ?cancel_err,
+                    "Failed to cancel resumable upload in GCS store"
+                );
+            }
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    async fn get_part(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        writer: &mut DropCloserWriteHalf,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<(), Error> {
+        if is_zero_digest(key.borrow()) {
+            writer
+                .send_eof()
+                .err_tip(|| "Failed to send zero EOF in GCS store get_part")?;
+            return Ok(());
+        }
+        if length == Some(0) {
+            if self.has(&key).await?.is_none() {
+                return Err(make_err!(Code::NotFound, "No such key in GCS: {key:?}"));
+            }
+            return writer
+                .send_eof()
+                .err_tip(|| "Failed to send EOF in GCS store get_part");
+        }
+
+        let url = &self.object_url(&self.make_object_name(&key));
+        // Ranges are inclusive at both ends.
+        let end_read_byte = length
+            .map(|length| offset.checked_add(length - 1))
+            .map_or(Some(None), |v| v.map(Some))
+            .err_tip(|| "Integer overflow protection triggered")?;
+
+        self.retrier
+            .retry(unfold(writer, move |writer| async move {
+                let range = format!(
+                    "bytes={}-{}",
+                    offset + writer.get_bytes_written(),
+                    end_read_byte.map_or_else(String::new, |v| v.to_string())
+                );
+                let response = match self
+                    .send(Request::get(url).header(RANGE, range), Body::empty())
+                    .await
+                {
+                    Ok(response) => response,
+                    Err(err) => return Some((RetryResult::Retry(err), writer)),
+                };
+                let status = response.status();
+                if status == StatusCode::NOT_FOUND {
+                    return Some((
+                        RetryResult::Err(make_err!(Code::NotFound, "No such key in GCS: {url}")),
+                        writer,
+                    ));
+                }
+                if status != StatusCode::RANGE_NOT_SATISFIABLE {
+                    if !status.is_success() {
+                        let err = error_from_response("GET object", response).await;
+                        return Some((retry_for_status(status, err), writer));
+                    }
+                    // Copy data from the GCS response to the writer stream.
+                    let mut body = response.into_body();
+                    while let Some(maybe_bytes) = body.data().await {
+                        match maybe_bytes {
+                            Ok(bytes) => {
+                                if bytes.is_empty() {
+                                    continue;
+                                }
+                                if let Err(e) = writer.send(bytes).await {
+                                    return Some((
+                                        RetryResult::Err(make_err!(
+                                            Code::Aborted,
+                                            "Error sending bytes to consumer in GCS: {e}"
+                                        )),
+                                        writer,
+                                    ));
+                                }
+                            }
+                            Err(e) => {
+                                return Some((
+                                    RetryResult::Retry(make_err!(
+                                        Code::Aborted,
+                                        "Bad bytestream element in GCS: {e}"
+                                    )),
+                                    writer,
+                                ));
+                            }
+                        }
+                    }
+                }
+                // A range starting at the end of the object is not satisfiable,
+                // but just means there is nothing (left) to read.
+                if let Err(e) = writer.send_eof() {
+                    return Some((
+                        RetryResult::Err(make_err!(
+                            Code::Aborted,
+                            "Failed to send EOF to consumer in GCS: {e}"
+                        )),
+                        writer,
+                    ));
+                }
+                Some((RetryResult::Ok(()), writer))
+            }))
+            .await
+    }
+
+    fn inner_store(&self, _digest: Option<StoreKey>) -> &'_ dyn StoreDriver {
+        self
+    }
+
+    fn as_any<'a>(&'a self) -> &'a (dyn std::any::Any + Sync + Send + 'static) {
+        self
+    }
+
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn std::any::Any + Sync + Send + 'static> {
+        self
+    }
+
+    async fn reconfigure(&self, spec: &StoreSpec, check_only: bool) -> Result<(), Error> {
+        let StoreSpec::experimental_gcs_store(spec) = spec else {
+            return Err(make_input_err!(
+                "GcsStore can only be reconfigured with an experimental_gcs_store spec"
+            ));
+        };
+        if !check_only {
+            self.retrier.set_config(spec.retry.clone());
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<I, NowFn> HealthStatusIndicator for GcsStore<NowFn>
+where
+    I: InstantWrapper,
+    NowFn: Fn() -> I + Send + Sync + Unpin + 'static,
+{
+    fn get_name(&self) -> &'static str {
+        "GcsStore"
+    }
+
+    async fn check_health(&self, namespace: Cow<'static, str>) -> HealthStatus {
+        StoreDriver::check_health(Pin::new(self), namespace).await
+    }
+}
@@ -13,23 +13,26 @@
 // limitations under the License.
 
 use std::borrow::Cow;
+use std::collections::HashMap;
+use std::mem;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::time::Duration;
 
 use async_trait::async_trait;
-use bytes::BytesMut;
+use bytes::{Bytes, BytesMut};
 use futures::stream::{unfold, FuturesUnordered};
 use futures::{future, Future, Stream, StreamExt, TryFutureExt, TryStreamExt};
-use nativelink_config::stores::GrpcSpec;
-use nativelink_error::{error_if, make_input_err, Error, ResultExt};
+use nativelink_config::stores::{GrpcSpec, StoreSpec};
+use nativelink_error::{error_if, make_err, make_input_err, Code, Error, ResultExt};
 use nativelink_metric::MetricsComponent;
 use nativelink_proto::build::bazel::remote::execution::v2::action_cache_client::ActionCacheClient;
 use nativelink_proto::build::bazel::remote::execution::v2::content_addressable_storage_client::ContentAddressableStorageClient;
 use nativelink_proto::build::bazel::remote::execution::v2::{
-    ActionResult, BatchReadBlobsRequest, BatchReadBlobsResponse, BatchUpdateBlobsRequest,
-    BatchUpdateBlobsResponse, FindMissingBlobsRequest, FindMissingBlobsResponse,
-    GetActionResultRequest, GetTreeRequest, GetTreeResponse, UpdateActionResultRequest,
+    batch_update_blobs_request, compressor, ActionResult, BatchReadBlobsRequest,
+    BatchReadBlobsResponse, BatchUpdateBlobsRequest, BatchUpdateBlobsResponse,
+    FindMissingBlobsRequest, FindMissingBlobsResponse, GetActionResultRequest, GetTreeRequest,
+    GetTreeResponse, UpdateActionResultRequest,
 };
 use nativelink_proto::google::bytestream::byte_stream_client::ByteStreamClient;
 use nativelink_proto::google::bytestream::{
@@ -47,7 +50,7 @@ use nativelink_util::proto_stream_utils::{
 };
 use nativelink_util::resource_info::ResourceInfo;
 use nativelink_util::retry::{Retrier, RetryResult};
-use nativelink_util::store_trait::{StoreDriver, StoreKey, UploadSizeInfo};
+use nativelink_util::store_trait::{slow_update_many, StoreDriver, StoreKey, UploadSizeInfo};
 use nativelink_util::{default_health_status_indicator, tls_utils};
 use parking_lot::Mutex;
 use prost::Message;
@@ -58,6 +61,13 @@ use tonic::{IntoRequest, Request, Response, Status, Streaming};
 use tracing::{event, Level};
 use uuid::Uuid;
 
+// The default limit of gRPC messages is 4MiB, so `update_many()` sends
+// batches of at most this many bytes in one `BatchUpdateBlobs` request.
+const MAX_BATCH_UPDATE_SIZE: usize = 3 * 1024 * 1024; // 3MiB.
+
+// Rough size of the digest and framing of each blob in a batch request.
+const BATCH_UPDATE_ITEM_OVERHEAD: usize = 128;
+
 // This store is usually a pass-through store, but can also be used as a CAS store. Using it as an
 // AC store has one major side-effect... The has() function may not give the proper size of the
 // underlying data. This might cause issues if embedded in certain stores.
@@ -354,6 +364,43 @@ impl GrpcStore {
         Ok(result)
     }
 
+    /// Writes a stream that is not a blob, like the live output of a running
+    /// action, to `resource_name`. The data is sent as `stream` yields it and
+    /// the write is finished when it ends. Unlike blobs the stream can not be
+    /// resumed, so it is not retried.
+    pub async fn write_live_stream(
+        &self,
+        resource_name: String,
+        stream: impl Stream<Item = Bytes> + Send + 'static,
+    ) -> Result<WriteResponse, Error> {
+        let channel = self
+            .connection_manager
+            .connection()
+            .await
+            .err_tip(|| "in GrpcStore::write_live_stream")?;
+        let mut write_offset = 0;
+        let requests = stream
+            .map(Some)
+            .chain(futures::stream::once(future::ready(None)))
+            .map(move |maybe_data| {
+                let finish_write = maybe_data.is_none();
+                let data = maybe_data.unwrap_or_default();
+                let request_write_offset = write_offset;
+                write_offset += data.len() as i64;
+                WriteRequest {
+                    resource_name: resource_name.clone(),
+                    write_offset: request_write_offset,
+                    finish_write,
+                    data,
+                }
+            });
+        ByteStreamClient::new(channel)
+            .write(requests)
+            .await
+            .map(Response::into_inner)
+            .err_tip(|| "in GrpcStore::write_live_stream")
+    }
+
     pub async fn query_write_status(
         &self,
         grpc_request: Request<QueryWriteStatusRequest>,
@@ -653,6 +700,114 @@ impl StoreDriver for GrpcStore {
         Ok(())
     }
 
+    async fn update_many(
+        self: Pin<&Self>,
+        items: Vec<(StoreKey<'_>, Bytes)>,
+    ) -> Vec<Result<(), Error>> {
+        if matches!(self.store_type, nativelink_config::stores::StoreType::ac) {
+            return slow_update_many(self, items).await;
+        }
+        let digest_function = match ActiveOriginContext::get_value(&ACTIVE_HASHER_FUNC)
+            .err_tip(|| "In GrpcStore::update_many")
+        {
+            Ok(hasher) => hasher
+                .map_or_else(default_digest_hasher_func, |v| *v)
+                .proto_digest_func()
+                .into(),
+            Err(err) => return items.iter().map(|_| Err(err.clone())).collect(),
+        };
+
+        // Group the objects into requests that fit into a gRPC message, the
+        // objects that are too large on their own are streamed instead.
+        let mut results = vec![Ok(()); items.len()];
+        let mut batches = Vec::new();
+        let mut batch = Vec::new();
+        let mut batch_size = 0;
+        let mut large_items = Vec::new();
+        for (index, (key, data)) in items.into_iter().enumerate() {
+            let item_size = data.len() + BATCH_UPDATE_ITEM_OVERHEAD;
+            if item_size > MAX_BATCH_UPDATE_SIZE {
+                large_items.push((index, (key, data)));
+                continue;
+            }
+            if batch_size + item_size > MAX_BATCH_UPDATE_SIZE {
+                batches.push(mem::take(&mut batch));
+                batch_size = 0;
+            }
+            batch_size += item_size;
+            batch.push((index, key.into_digest(), data));
+        }
+        if !batch.is_empty() {
+            batches.push(batch);
+        }
+
+        let batch_futures = batches
+            .into_iter()
+            .map(|batch| async move {
+                let digests: Vec<_> = batch
+                    .iter()
+                    .map(|(index, digest, _)| (*index, *digest))
+                    .collect();
+                let request = BatchUpdateBlobsRequest {
+                    instance_name: self.instance_name.clone(),
+                    requests: batch
+                        .into_iter()
+                        .map(|(_, digest, data)| batch_update_blobs_request::Request {
+                            digest: Some(digest.into()),
+                            data,
+                            compressor: compressor::Value::Identity.into(),
+                        })
+                        .collect(),
+                    digest_function,
+                };
+                let response = self
+                    .batch_update_blobs(Request::new(request))
+                    .await
+                    .err_tip(|| "In GrpcStore::update_many");
+                (digests, response)
+            })
+            .collect::<FuturesUnordered<_>>()
+            .collect::<Vec<_>>();
+        let (large_indexes, large_items): (Vec<_>, Vec<_>) = large_items.into_iter().unzip();
+        let (batch_responses, large_results) =
+            future::join(batch_futures, slow_update_many(self, large_items)).await;
+
+        for (index, result) in large_indexes.into_iter().zip(large_results) {
+            results[index] = result;
+        }
+        for (digests, response) in batch_responses {
+            let statuses = match response {
+                Ok(response) => response
+                    .into_inner()
+                    .responses
+                    .into_iter()
+                    .filter_map(|response| {
+                        let digest = DigestInfo::try_from(response.digest?).ok()?;
+                        Some((digest, response.status.unwrap_or_default()))
+                    })
+                    .collect::<HashMap<_, _>>(),
+                Err(err) => {
+                    for (index, _) in digests {
+                        results[index] = Err(err.clone());
+                    }
+                    continue;
+                }
+            };
+            for (index, digest) in digests {
+                results[index] = match statuses.get(&digest) {
+                    Some(status) if status.code == 0 => Ok(()),
+                    Some(status) => Err(Error::from(status.clone()))
+                        .err_tip(|| format!("Failed to upload {digest} in GrpcStore::update_many")),
+                    None => Err(make_err!(
+                        Code::Internal,
+                        "No result for {digest} in BatchUpdateBlobs response in GrpcStore::update_many"
+                    )),
+                };
+            }
+        }
+        results
+    }
+
     async fn get_part(
         self: Pin<&Self>,
         key: StoreKey<'_>,
@@ -766,6 +921,18 @@ impl StoreDriver for GrpcStore {
     fn as_any_arc(self: Arc<Self>) -> Arc<dyn std::any::Any + Sync + Send + 'static> {
         self
     }
+
+    async fn reconfigure(&self, spec: &StoreSpec, check_only: bool) -> Result<(), Error> {
+        let StoreSpec::grpc(spec) = spec else {
+            return Err(make_input_err!(
+                "GrpcStore can only be reconfigured with a grpc spec"
+            ));
+        };
+        if !check_only {
+            self.retrier.set_config(spec.retry.clone());
+        }
+        Ok(())
+    }
 }
 
 default_health_status_indicator!(GrpcStore);
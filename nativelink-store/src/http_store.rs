@@ -0,0 +1,497 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::borrow::Cow;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::stream::{unfold, FuturesUnordered};
+use futures::TryStreamExt;
+use hyper::body::HttpBody;
+use hyper::client::connect::HttpConnector;
+use hyper::header::{HeaderName, HeaderValue, CONTENT_LENGTH, RANGE};
+use hyper::http::request::Builder as RequestBuilder;
+use hyper::{Body, Request, Response, StatusCode};
+use hyper_rustls::HttpsConnector;
+use nativelink_config::stores::{HttpSpec, StoreSpec};
+use nativelink_error::{make_err, make_input_err, Code, Error, ResultExt};
+use nativelink_metric::MetricsComponent;
+use nativelink_util::buf_channel::{
+    make_buf_channel_pair, DropCloserReadHalf, DropCloserWriteHalf,
+};
+use nativelink_util::health_utils::{HealthStatus, HealthStatusIndicator};
+use nativelink_util::retry::{Retrier, RetryResult};
+use nativelink_util::store_trait::{StoreDriver, StoreKey, UploadSizeInfo};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use rand::rngs::OsRng;
+use rand::Rng;
+use tokio::time::sleep;
+use tracing::{event, Level};
+
+use crate::cas_utils::is_zero_digest;
+
+// Default max buffer size for retrying upload requests.
+// Note: If you change this, adjust the docs in the config.
+const DEFAULT_MAX_RETRY_BUFFER_PER_REQUEST: usize = 5 * 1024 * 1024; // 5MB.
+
+// Keys keep their `/` so a `key_prefix` maps to directories.
+const KEY_PATH_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~')
+    .remove(b'/');
+
+/// Sends the HTTP requests of an [`HttpStore`].
+#[async_trait]
+pub trait HttpClient: Send + Sync + 'static {
+    async fn send(&self, request: Request<Body>) -> Result<Response<Body>, Error>;
+}
+
+#[async_trait]
+impl HttpClient for hyper::Client<HttpsConnector<HttpConnector>> {
+    async fn send(&self, request: Request<Body>) -> Result<Response<Body>, Error> {
+        self.request(request)
+            .await
+            .map_err(|e| make_err!(Code::Unavailable, "Failed to send HTTP request: {e:?}"))
+    }
+}
+
+/// Server side errors and throttling are retried, everything else is final.
+fn is_retryable_status(status: StatusCode) -> bool {
+    status.is_server_error()
+        || status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::REQUEST_TIMEOUT
+}
+
+fn retry_for_status<T>(status: StatusCode, err: Error) -> RetryResult<T> {
+    if is_retryable_status(status) {
+        RetryResult::Retry(err)
+    } else {
+        RetryResult::Err(err)
+    }
+}
+
+/// Build an error for an unexpected response, including the body the server
+/// sent with it.
+async fn error_from_response(context: &str, response: Response<Body>) -> Error {
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body())
+        .await
+        .unwrap_or_default();
+    make_err!(
+        Code::Unavailable,
+        "{context} failed with status {status} in HTTP store: {}",
+        String::from_utf8_lossy(&body)
+    )
+}
+
+#[derive(MetricsComponent)]
+pub struct HttpStore {
+    http_client: Arc<dyn HttpClient>,
+    #[metric(help = "The base URL of the HTTP store")]
+    endpoint: String,
+    #[metric(help = "The key prefix for the HTTP store")]
+    key_prefix: String,
+    hash_only_paths: bool,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    retrier: Retrier,
+    #[metric(help = "The number of bytes to buffer for retrying requests")]
+    max_retry_buffer_per_request: usize,
+}
+
+impl HttpStore {
+    pub fn new(spec: &HttpSpec) -> Result<Arc<Self>, Error> {
+        let jitter_amt = spec.retry.jitter;
+        let jitter_fn = Arc::new(move |delay: Duration| {
+            if jitter_amt == 0. {
+                return delay;
+            }
+            let min = 1. - (jitter_amt / 2.);
+            let max = 1. + (jitter_amt / 2.);
+            delay.mul_f32(OsRng.gen_range(min..max))
+        });
+        let http_client = {
+            let connector_with_schemes = hyper_rustls::HttpsConnectorBuilder::new()
+                .with_webpki_roots()
+                .https_or_http();
+            let connector = if spec.disable_http2 {
+                connector_with_schemes.enable_http1().build()
+            } else {
+                connector_with_schemes.enable_http1().enable_http2().build()
+            };
+            Arc::new(hyper::Client::builder().build::<_, Body>(connector))
+        };
+        Self::new_with_client_and_jitter(spec, http_client, jitter_fn)
+    }
+
+    pub fn new_with_client_and_jitter(
+        spec: &HttpSpec,
+        http_client: Arc<dyn HttpClient>,
+        jitter_fn: Arc<dyn Fn(Duration) -> Duration + Send + Sync>,
+    ) -> Result<Arc<Self>, Error> {
+        if !spec.endpoint.starts_with("http://") && !spec.endpoint.starts_with("https://") {
+            return Err(make_err!(
+                Code::InvalidArgument,
+                "HTTP store endpoint must start with http:// or https://, got {}",
+                spec.endpoint
+            ));
+        }
+        let mut headers = spec
+            .headers
+            .iter()
+            .map(|(name, value)| {
+                let name = HeaderName::try_from(name.as_str()).map_err(|e| {
+                    make_err!(Code::InvalidArgument, "Invalid header name {name}: {e}")
+                })?;
+                let mut value = HeaderValue::try_from(value.as_str()).map_err(|e| {
+                    make_err!(
+                        Code::InvalidArgument,
+                        "Invalid value for header {name}: {e}"
+                    )
+                })?;
+                // Headers usually carry credentials, keep them out of logs.
+                value.set_sensitive(true);
+                Ok((name, value))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        // Keep requests deterministic.
+        headers.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+        Ok(Arc::new(Self {
+            http_client,
+            endpoint: spec.endpoint.trim_end_matches('/').to_string(),
+            key_prefix: spec.key_prefix.as_ref().unwrap_or(&String::new()).clone(),
+            hash_only_paths: spec.hash_only_paths,
+            headers,
+            retrier: Retrier::new(
+                Arc::new(|duration| Box::pin(sleep(duration))),
+                jitter_fn,
+                spec.retry.clone(),
+            ),
+            max_retry_buffer_per_request: spec
+                .max_retry_buffer_per_request
+                .unwrap_or(DEFAULT_MAX_RETRY_BUFFER_PER_REQUEST),
+        }))
+    }
+
+    fn make_url(&self, key: &StoreKey<'_>) -> String {
+        let key = match key {
+            StoreKey::Digest(digest) if self.hash_only_paths => {
+                Cow::Owned(digest.packed_hash().to_string())
+            }
+            key => key.as_str(),
+        };
+        format!(
+            "{}/{}",
+            self.endpoint,
+            utf8_percent_encode(&format!("{}{key}", self.key_prefix), KEY_PATH_ENCODE_SET)
+        )
+    }
+
+    /// Send a request with the configured headers.
+    async fn send(&self, mut request: RequestBuilder, body: Body) -> Result<Response<Body>, Error> {
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        let request = request
+            .body(body)
+            .map_err(|e| make_err!(Code::Internal, "Could not build HTTP request: {e:?}"))?;
+        self.http_client.send(request).await
+    }
+
+    async fn has(self: Pin<&Self>, key: &StoreKey<'_>) -> Result<Option<u64>, Error> {
+        let url = &self.make_url(key);
+        self.retrier
+            .retry(unfold((), move |state| async move {
+                let response = match self.send(Request::head(url), Body::empty()).await {
+                    Ok(response) => response,
+                    Err(err) => return Some((RetryResult::Retry(err), state)),
+                };
+                let status = response.status();
+                if status == StatusCode::NOT_FOUND {
+                    return Some((RetryResult::Ok(None), state));
+                }
+                if !status.is_success() {
+                    let err = error_from_response("HEAD", response).await;
+                    return Some((retry_for_status(status, err), state));
+                }
+                let headers = response.headers();
+                let Some(length) = headers
+                    .get(CONTENT_LENGTH)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse::<u64>().ok())
+                else {
+                    return Some((
+                        RetryResult::Err(make_err!(
+                            Code::Unavailable,
+                            "Missing or invalid content length in HTTP store: {:?}",
+                            headers.get(CONTENT_LENGTH)
+                        )),
+                        state,
+                    ));
+                };
+                Some((RetryResult::Ok(Some(length)), state))
+            }))
+            .await
+    }
+}
+
+#[async_trait]
+impl StoreDriver for HttpStore {
+    async fn has_with_results(
+        self: Pin<&Self>,
+        keys: &[StoreKey<'_>],
+        results: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        keys.iter()
+            .zip(results.iter_mut())
+            .map(|(key, result)| async move {
+                // We need to do a special pass to ensure our zero key exist.
+                if is_zero_digest(key.borrow()) {
+                    *result = Some(0);
+                    return Ok::<_, Error>(());
+                }
+                *result = self.has(key).await?;
+                Ok::<_, Error>(())
+            })
+            .collect::<FuturesUnordered<_>>()
+            .try_collect()
+            .await
+    }
+
+    async fn update(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        mut reader: DropCloserReadHalf,
+        upload_size: UploadSizeInfo,
+    ) -> Result<(), Error> {
+        let url = &self.make_url(&key);
+        reader.set_max_recent_data_size(
+            u64::try_from(self.max_retry_buffer_per_request)
+                .err_tip(|| "Could not convert max_retry_buffer_per_request to u64")?,
+        );
+        self.retrier
+            .retry(unfold(reader, move |mut reader| async move {
+                // The body is consumed by the request, so we need a new pair
+                // for every attempt.
+                let (mut tx, rx) = make_buf_channel_pair();
+                let mut request = Request::put(url);
+                // Without a known size the body is sent with chunked encoding.
+                if let UploadSizeInfo::ExactSize(sz) = upload_size {
+                    request = request.header(CONTENT_LENGTH, sz);
+                }
+                let (upload_res, bind_res) = tokio::join!(
+                    self.send(request, Body::wrap_stream(rx)),
+                    tx.bind_buffered(&mut reader)
+                );
+                let upload_res = match upload_res {
+                    Ok(response) if response.status().is_success() => Ok(()),
+                    Ok(response) => {
+                        let status = response.status();
+                        let err = error_from_response("PUT", response).await;
+                        if !is_retryable_status(status) {
+                            return Some((RetryResult::Err(err), reader));
+                        }
+                        Err(err)
+                    }
+                    Err(err) => Err(err),
+                };
+                let Err(err) = upload_res.merge(bind_res) else {
+                    return Some((RetryResult::Ok(()), reader));
+                };
+                let bytes_received = reader.get_bytes_received();
+                if let Err(try_reset_err) = reader.try_reset_stream() {
+                    event!(
+                        Level::ERROR,
+                        ?bytes_received,
+                        err = ?try_reset_err,
+                        "Unable to reset stream after failed upload in HttpStore::update"
+                    );
+                    return Some((
+                        RetryResult::Err(err.merge(try_reset_err).append(format!(
+                            "Failed to retry upload with {bytes_received} bytes received in HttpStore::update"
+                        ))),
+                        reader,
+                    ));
+                }
+                event!(
+                    Level::INFO,
+                    ?err,
+                    ?bytes_received,
+                    "Retryable HTTP store error"
+                );
+                Some((RetryResult::Retry(err), reader))
+            }))
+            .await
+    }
+
+    async fn get_part(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        writer: &mut DropCloserWriteHalf,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<(), Error> {
+        if is_zero_digest(key.borrow()) {
+            writer
+                .send_eof()
+                .err_tip(|| "Failed to send zero EOF in HTTP store get_part")?;
+            return Ok(());
+        }
+        if length == Some(0) {
+            if self.has(&key).await?.is_none() {
+                return Err(make_err!(
+                    Code::NotFound,
+                    "No such key in HTTP store: {key:?}"
+                ));
+            }
+            return writer
+                .send_eof()
+                .err_tip(|| "Failed to send EOF in HTTP store get_part");
+        }
+
+        let url = &self.make_url(&key);
+        // Ranges are inclusive at both ends.
+        let end_read_byte = length
+            .map(|length| offset.checked_add(length - 1))
+            .map_or(Some(None), |v| v.map(Some))
+            .err_tip(|| "Integer overflow protection triggered")?;
+
+        self.retrier
+            .retry(unfold(writer, move |writer| async move {
+                let start = offset + writer.get_bytes_written();
+                let mut request = Request::get(url);
+                // Servers that ignore `Range` reply with the whole file, so
+                // only ask for one when needed.
+                let is_ranged = start != 0 || end_read_byte.is_some();
+                if is_ranged {
+                    request = request.header(
+                        RANGE,
+                        format!(
+                            "bytes={start}-{}",
+                            end_read_byte.map_or_else(String::new, |v| v.to_string())
+                        ),
+                    );
+                }
+                let response = match self.send(request, Body::empty()).await {
+                    Ok(response) => response,
+                    Err(err) => return Some((RetryResult::Retry(err), writer)),
+                };
+                let status = response.status();
+                if status == StatusCode::NOT_FOUND {
+                    return Some((
+                        RetryResult::Err(make_err!(
+                            Code::NotFound,
+                            "No such key in HTTP store: {url}"
+                        )),
+                        writer,
+                    ));
+                }
+                if status != StatusCode::RANGE_NOT_SATISFIABLE {
+                    if !status.is_success() {
+                        let err = error_from_response("GET", response).await;
+                        return Some((retry_for_status(status, err), writer));
+                    }
+                    if is_ranged && status != StatusCode::PARTIAL_CONTENT {
+                        return Some((
+                            RetryResult::Err(make_err!(
+                                Code::Unavailable,
+                                "HTTP store server replied with {status} to a range request for {url}, it must support range requests"
+                            )),
+                            writer,
+                        ));
+                    }
+                    // Copy data from the response to the writer stream.
+                    let mut body = response.into_body();
+                    while let Some(maybe_bytes) = body.data().await {
+                        match maybe_bytes {
+                            Ok(bytes) => {
+                                if bytes.is_empty() {
+                                    continue;
+                                }
+                                if let Err(e) = writer.send(bytes).await {
+                                    return Some((
+                                        RetryResult::Err(make_err!(
+                                            Code::Aborted,
+                                            "Error sending bytes to consumer in HTTP store: {e}"
+                                        )),
+                                        writer,
+                                    ));
+                                }
+                            }
+                            Err(e) => {
+                                return Some((
+                                    RetryResult::Retry(make_err!(
+                                        Code::Aborted,
+                                        "Bad bytestream element in HTTP store: {e}"
+                                    )),
+                                    writer,
+                                ));
+                            }
+                        }
+                    }
+                }
+                // A range starting at the end of the file is not satisfiable,
+                // but just means there is nothing (left) to read.
+                if let Err(e) = writer.send_eof() {
+                    return Some((
+                        RetryResult::Err(make_err!(
+                            Code::Aborted,
+                            "Failed to send EOF to consumer in HTTP store: {e}"
+                        )),
+                        writer,
+                    ));
+                }
+                Some((RetryResult::Ok(()), writer))
+            }))
+            .await
+    }
+
+    fn inner_store(&self, _digest: Option<StoreKey>) -> &'_ dyn StoreDriver {
+        self
+    }
+
+    fn as_any<'a>(&'a self) -> &'a (dyn std::any::Any + Sync + Send + 'static) {
+        self
+    }
+
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn std::any::Any + Sync + Send + 'static> {
+        self
+    }
+
+    async fn reconfigure(&self, spec: &StoreSpec, check_only: bool) -> Result<(), Error> {
+        let StoreSpec::experimental_http_store(spec) = spec else {
+            return Err(make_input_err!(
+                "HttpStore can only be reconfigured with an experimental_http_store spec"
+            ));
+        };
+        if !check_only {
+            self.retrier.set_config(spec.retry.clone());
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl HealthStatusIndicator for HttpStore {
+    fn get_name(&self) -> &'static str {
+        "HttpStore"
+    }
+
+    async fn check_health(&self, namespace: Cow<'static, str>) -> HealthStatus {
+        StoreDriver::check_health(Pin::new(self), namespace).await
+    }
+}
@@ -12,18 +12,31 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod ac_retention_store;
 pub mod ac_utils;
+pub mod asset_fetcher;
+pub mod azure_blob_store;
 pub mod cas_utils;
 pub mod completeness_checking_store;
 pub mod compression_store;
+pub mod concurrency_limit_store;
 pub mod dedup_store;
 pub mod default_store_factory;
+pub mod direct_io;
+pub mod encryption_store;
 pub mod existence_cache_store;
+pub mod failover_store;
 pub mod fast_slow_store;
 pub mod filesystem_store;
+pub mod gcs_store;
 pub mod grpc_store;
+pub mod http_store;
 pub mod memory_store;
+pub mod metrics_store;
+pub mod mirror_store;
 pub mod noop_store;
+pub mod quota_store;
+pub mod rate_limit_store;
 pub mod redis_store;
 mod redis_utils;
 pub mod ref_store;
@@ -31,4 +44,6 @@ pub mod s3_store;
 pub mod shard_store;
 pub mod size_partitioning_store;
 pub mod store_manager;
+pub mod store_migration;
+pub mod uring_io;
 pub mod verify_store;
@@ -13,26 +13,50 @@
 // limitations under the License.
 
 use std::borrow::Borrow;
+use std::collections::hash_map::DefaultHasher;
 use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
 use std::ops::Bound;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
 use std::time::SystemTime;
 
 use async_trait::async_trait;
 use bytes::{Bytes, BytesMut};
-use nativelink_config::stores::MemorySpec;
-use nativelink_error::{Code, Error, ResultExt};
+use nativelink_config::stores::{EvictionPolicy, MemorySpec, StoreSpec};
+use nativelink_error::{error_if, make_err, make_input_err, Code, Error, ResultExt};
 use nativelink_metric::MetricsComponent;
+use nativelink_util::background_spawn;
 use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
 use nativelink_util::evicting_map::{EvictingMap, LenEntry};
 use nativelink_util::health_utils::{default_health_status_indicator, HealthStatusIndicator};
-use nativelink_util::store_trait::{StoreDriver, StoreKey, StoreKeyBorrow, UploadSizeInfo};
+use nativelink_util::memory_budget::{
+    global_memory_budget, MemoryConsumer, MemoryReservation, ENTRY_OVERHEAD_BYTES,
+};
+use nativelink_util::store_trait::{
+    Store, StoreDriver, StoreKey, StoreKeyBorrow, StoreLike, StoreOptimizations, UploadSizeInfo,
+};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{event, Level};
 
 use crate::cas_utils::is_zero_digest;
 
 #[derive(Clone)]
-pub struct BytesWrapper(Bytes);
+pub struct BytesWrapper {
+    data: Bytes,
+    spill: Option<Arc<SpillHandle>>,
+}
+
+impl BytesWrapper {
+    /// Makes sure the entry is not written to the spill area when it is
+    /// removed from memory.
+    fn disable_spill(&self) {
+        if let Some(spill) = &self.spill {
+            spill.enabled.store(false, Ordering::Relaxed);
+        }
+    }
+}
 
 impl Debug for BytesWrapper {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -43,38 +67,330 @@ impl Debug for BytesWrapper {
 impl LenEntry for BytesWrapper {
     #[inline]
     fn len(&self) -> u64 {
-        Bytes::len(&self.0) as u64
+        Bytes::len(&self.data) as u64
     }
 
     #[inline]
     fn is_empty(&self) -> bool {
-        Bytes::is_empty(&self.0)
+        Bytes::is_empty(&self.data)
+    }
+
+    async fn unref(&self) {
+        let Some(spill) = &self.spill else {
+            return;
+        };
+        if !spill.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+        // This is called with the lock of the map held, so the write is
+        // queued and done by the spill task. If the spill store can't keep
+        // up the entry is dropped instead of piling up in memory.
+        let spilled_entry = SpilledEntry {
+            key: spill.key.clone(),
+            data: self.data.clone(),
+            _reservation: global_memory_budget().reserve(self.len()),
+        };
+        if spill
+            .sender
+            .try_send(SpillRequest::Spill(spilled_entry))
+            .is_err()
+        {
+            spill.dropped_count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Maximum number of evicted entries waiting to be written to the spill
+/// area. Entries evicted while it is full are dropped.
+const MAX_QUEUED_SPILLS: usize = 1024;
+
+/// Lets an entry in memory find its way into the spill area once it is
+/// evicted.
+struct SpillHandle {
+    key: StoreKey<'static>,
+    enabled: AtomicBool,
+    sender: mpsc::Sender<SpillRequest>,
+    dropped_count: Arc<AtomicU64>,
+}
+
+/// An entry evicted from memory that is waiting to be written to the
+/// spill area. Its data still counts against the memory budget until then.
+struct SpilledEntry {
+    key: StoreKey<'static>,
+    data: Bytes,
+    _reservation: MemoryReservation,
+}
+
+/// Work for the spill task. Removals go through the same queue as the
+/// writes, so a removed key can't be written back by a spill that was
+/// queued before it.
+enum SpillRequest {
+    Spill(SpilledEntry),
+    Remove {
+        key: StoreKey<'static>,
+        result_sender: Option<oneshot::Sender<Result<bool, Error>>>,
+    },
+}
+
+/// The on-disk area evicted entries are moved to.
+#[derive(MetricsComponent)]
+struct SpillArea {
+    #[metric(group = "spill_store")]
+    store: Store,
+    sender: mpsc::Sender<SpillRequest>,
+    #[metric(help = "Number of entries written to the spill store")]
+    spilled_count: AtomicU64,
+    #[metric(help = "Number of entries that failed to be written to the spill store")]
+    spill_failed_count: AtomicU64,
+    #[metric(
+        help = "Number of evicted entries dropped because too many were waiting to be spilled"
+    )]
+    spill_dropped_count: Arc<AtomicU64>,
+    #[metric(help = "Number of entries moved back into memory from the spill store")]
+    promoted_count: AtomicU64,
+}
+
+impl SpillArea {
+    fn spawn(spill_store: Store) -> Arc<Self> {
+        let (sender, mut receiver) = mpsc::channel::<SpillRequest>(MAX_QUEUED_SPILLS);
+        let spill_area = Arc::new(Self {
+            store: spill_store,
+            sender,
+            spilled_count: AtomicU64::new(0),
+            spill_failed_count: AtomicU64::new(0),
+            spill_dropped_count: Arc::new(AtomicU64::new(0)),
+            promoted_count: AtomicU64::new(0),
+        });
+        // The task ends once the store and all of its entries are dropped,
+        // because every sender is gone then.
+        let weak_spill_area = Arc::downgrade(&spill_area);
+        background_spawn!("memory_store_spill", async move {
+            while let Some(spill_request) = receiver.recv().await {
+                let Some(spill_area) = weak_spill_area.upgrade() else {
+                    return;
+                };
+                let spilled_entry = match spill_request {
+                    SpillRequest::Spill(spilled_entry) => spilled_entry,
+                    SpillRequest::Remove { key, result_sender } => {
+                        let result = spill_area.store.remove(key.borrow()).await;
+                        if let Some(result_sender) = result_sender {
+                            // The caller may have given up waiting.
+                            let _ = result_sender.send(result);
+                        }
+                        continue;
+                    }
+                };
+                let SpilledEntry {
+                    key,
+                    data,
+                    _reservation,
+                } = spilled_entry;
+                match spill_area.store.update_oneshot(key.borrow(), data).await {
+                    Ok(()) => {
+                        spill_area.spilled_count.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(err) => {
+                        spill_area
+                            .spill_failed_count
+                            .fetch_add(1, Ordering::Relaxed);
+                        event!(
+                            Level::WARN,
+                            ?key,
+                            ?err,
+                            "Failed to write evicted entry to spill store in MemoryStore"
+                        );
+                    }
+                }
+            }
+        });
+        spill_area
+    }
+}
+
+type Shard = EvictingMap<StoreKeyBorrow, BytesWrapper, SystemTime>;
+
+/// Splits the limits of `policy` evenly between `shard_count` shards.
+fn shard_eviction_policy(policy: &EvictionPolicy, shard_count: usize) -> EvictionPolicy {
+    // A limit that is set must not become zero, because zero means unlimited.
+    let split = |limit: usize| {
+        if limit == 0 {
+            0
+        } else {
+            limit.div_ceil(shard_count)
+        }
+    };
+    EvictionPolicy {
+        max_bytes: split(policy.max_bytes),
+        evict_bytes: split(policy.evict_bytes),
+        max_count: split(policy.max_count as usize) as u64,
+        high_watermark_bytes: split(policy.high_watermark_bytes),
+        low_watermark_bytes: split(policy.low_watermark_bytes),
+        eviction_bytes_per_second: split(policy.eviction_bytes_per_second),
+        ..policy.clone()
     }
 }
 
 #[derive(MetricsComponent)]
 pub struct MemoryStore {
-    #[metric(group = "evicting_map")]
-    evicting_map: EvictingMap<StoreKeyBorrow, BytesWrapper, SystemTime>,
+    #[metric(group = "shards")]
+    shards: Vec<Shard>,
+    #[metric(group = "spill")]
+    spill: Option<Arc<SpillArea>>,
 }
 
 impl MemoryStore {
     pub fn new(spec: &MemorySpec) -> Arc<Self> {
-        let empty_policy = nativelink_config::stores::EvictionPolicy::default();
+        Self::new_with_spill_store(spec, None)
+    }
+
+    /// Creates a store that writes entries evicted from memory to
+    /// `spill_store` and reads them back from there on a miss.
+    pub fn new_with_spill_store(spec: &MemorySpec, spill_store: Option<Store>) -> Arc<Self> {
+        let empty_policy = EvictionPolicy::default();
         let eviction_policy = spec.eviction_policy.as_ref().unwrap_or(&empty_policy);
-        Arc::new(Self {
-            evicting_map: EvictingMap::new(eviction_policy, SystemTime::now()),
-        })
+        let shard_count = spec.shard_count.max(1);
+        let shard_policy = shard_eviction_policy(eviction_policy, shard_count);
+        let now = SystemTime::now();
+        let store = Arc::new(Self {
+            shards: (0..shard_count)
+                .map(|_| EvictingMap::new(&shard_policy, now))
+                .collect(),
+            spill: spill_store.map(SpillArea::spawn),
+        });
+        for shard_index in 0..shard_count {
+            EvictingMap::spawn_background_eviction(&store, move |store| &store.shards[shard_index]);
+        }
+        let weak_store: Weak<dyn MemoryConsumer> = Arc::downgrade(&store);
+        global_memory_budget().register(weak_store);
+        store
     }
 
     /// Returns the number of key-value pairs that are currently in the the cache.
     /// Function is not for production code paths.
     pub async fn len_for_test(&self) -> usize {
-        self.evicting_map.len_for_test().await
+        let mut len = 0;
+        for shard in &self.shards {
+            len += shard.len_for_test().await;
+        }
+        len
     }
 
+    /// Removes the entry from memory and from the spill area. Failures to
+    /// remove a spilled copy are logged and reported as not removed.
     pub async fn remove_entry(&self, key: StoreKey<'_>) -> bool {
-        self.evicting_map.remove(&key).await
+        self.remove_with_spill(key.borrow())
+            .await
+            .unwrap_or_else(|err| {
+                event!(
+                    Level::WARN,
+                    ?key,
+                    ?err,
+                    "Failed to remove entry from spill store in MemoryStore"
+                );
+                false
+            })
+    }
+
+    /// Removes the entry from memory and from the spill area, so it can't
+    /// be promoted back later.
+    async fn remove_with_spill(&self, key: StoreKey<'_>) -> Result<bool, Error> {
+        // A removed entry is gone for good, so it must not be spilled.
+        let removed = self
+            .shard_for_key(&key)
+            .remove_if(&key, |entry| {
+                entry.disable_spill();
+                true
+            })
+            .await;
+        let Some(spill_area) = &self.spill else {
+            return Ok(removed);
+        };
+        let (result_sender, result_receiver) = oneshot::channel();
+        spill_area
+            .sender
+            .send(SpillRequest::Remove {
+                key: key.into_owned(),
+                result_sender: Some(result_sender),
+            })
+            .await
+            .map_err(|_| make_err!(Code::Internal, "Spill task of MemoryStore is gone"))?;
+        let spill_removed = result_receiver
+            .await
+            .map_err(|_| make_err!(Code::Internal, "Spill task of MemoryStore is gone"))?
+            .err_tip(|| "Failed to remove from spill store in MemoryStore")?;
+        Ok(removed || spill_removed)
+    }
+
+    fn make_entry(&self, key: &StoreKey<'_>, data: Bytes) -> BytesWrapper {
+        BytesWrapper {
+            data,
+            spill: self.spill.as_ref().map(|spill_area| {
+                Arc::new(SpillHandle {
+                    key: key.borrow().into_owned(),
+                    enabled: AtomicBool::new(true),
+                    sender: spill_area.sender.clone(),
+                    dropped_count: spill_area.spill_dropped_count.clone(),
+                })
+            }),
+        }
+    }
+
+    /// Moves an entry from the spill store back into memory. Returns `None`
+    /// if there is no spill store or the entry is not in it.
+    async fn promote(&self, key: &StoreKey<'_>) -> Result<Option<BytesWrapper>, Error> {
+        let Some(spill_area) = &self.spill else {
+            return Ok(None);
+        };
+        let data = match spill_area
+            .store
+            .get_part_unchunked(key.borrow(), 0, None)
+            .await
+        {
+            Ok(data) => data,
+            Err(err) if err.code == Code::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err).err_tip(|| "Failed to read from spill store in MemoryStore")
+            }
+        };
+        let entry = self.make_entry(key, data);
+        self.shard_for_key(key)
+            .insert(key.borrow().into_owned().into(), entry.clone())
+            .await;
+        // The copy on disk is no longer needed, the entry is spilled again
+        // once it is evicted. If the queue is full the copy is left behind,
+        // which only costs disk space.
+        let _ = spill_area.sender.try_send(SpillRequest::Remove {
+            key: key.borrow().into_owned(),
+            result_sender: None,
+        });
+        spill_area.promoted_count.fetch_add(1, Ordering::Relaxed);
+        global_memory_budget().enforce().await;
+        Ok(Some(entry))
+    }
+
+    fn shard_for_key(&self, key: &StoreKey<'_>) -> &Shard {
+        &self.shards[self.shard_index(key)]
+    }
+
+    fn shard_index(&self, key: &StoreKey<'_>) -> usize {
+        if self.shards.len() == 1 {
+            return 0;
+        }
+        let hash = match key {
+            // Digests are already uniformly distributed, so their prefix
+            // can be used directly.
+            StoreKey::Digest(digest) => {
+                let mut prefix = [0u8; 8];
+                prefix.copy_from_slice(&digest.packed_hash()[..8]);
+                u64::from_le_bytes(prefix)
+            }
+            StoreKey::Str(str_key) => {
+                let mut hasher = DefaultHasher::new();
+                str_key.hash(&mut hasher);
+                hasher.finish()
+            }
+        };
+        (hash % self.shards.len() as u64) as usize
     }
 }
 
@@ -85,13 +401,37 @@ impl StoreDriver for MemoryStore {
         keys: &[StoreKey<'_>],
         results: &mut [Option<u64>],
     ) -> Result<(), Error> {
-        self.evicting_map
-            .sizes_for_keys::<_, StoreKey<'_>, &StoreKey<'_>>(
-                keys.iter(),
-                results,
-                false, /* peek */
-            )
-            .await;
+        if let [shard] = &self.shards[..] {
+            shard
+                .sizes_for_keys::<_, StoreKey<'_>, &StoreKey<'_>>(
+                    keys.iter(),
+                    results,
+                    false, /* peek */
+                )
+                .await;
+        } else {
+            // Look up the keys of every shard in one batch.
+            let mut indexes_by_shard = vec![Vec::new(); self.shards.len()];
+            for (index, key) in keys.iter().enumerate() {
+                indexes_by_shard[self.shard_index(key)].push(index);
+            }
+            for (shard, indexes) in self.shards.iter().zip(indexes_by_shard) {
+                if indexes.is_empty() {
+                    continue;
+                }
+                let mut shard_results = vec![None; indexes.len()];
+                shard
+                    .sizes_for_keys::<_, StoreKey<'_>, &StoreKey<'_>>(
+                        indexes.iter().map(|&index| &keys[index]),
+                        &mut shard_results,
+                        false, /* peek */
+                    )
+                    .await;
+                for (index, result) in indexes.into_iter().zip(shard_results) {
+                    results[index] = result;
+                }
+            }
+        }
         // We need to do a special pass to ensure our zero digest exist.
         keys.iter()
             .zip(results.iter_mut())
@@ -100,9 +440,34 @@ impl StoreDriver for MemoryStore {
                     *result = Some(0);
                 }
             });
+        if let Some(spill_area) = &self.spill {
+            // Entries that are not in memory may have been spilled.
+            let missing_indexes: Vec<usize> = (0..keys.len())
+                .filter(|&index| results[index].is_none())
+                .collect();
+            if !missing_indexes.is_empty() {
+                let missing_keys: Vec<StoreKey<'_>> = missing_indexes
+                    .iter()
+                    .map(|&index| keys[index].borrow())
+                    .collect();
+                let mut spill_results = vec![None; missing_keys.len()];
+                spill_area
+                    .store
+                    .has_with_results(&missing_keys, &mut spill_results)
+                    .await
+                    .err_tip(|| "Failed to check spill store in MemoryStore")?;
+                for (index, result) in missing_indexes.into_iter().zip(spill_results) {
+                    results[index] = result;
+                }
+            }
+        }
         Ok(())
     }
 
+    async fn remove(self: Pin<&Self>, key: StoreKey<'_>) -> Result<bool, Error> {
+        self.remove_with_spill(key).await
+    }
+
     async fn list(
         self: Pin<&Self>,
         range: (Bound<StoreKey<'_>>, Bound<StoreKey<'_>>),
@@ -112,34 +477,84 @@ impl StoreDriver for MemoryStore {
             range.0.map(StoreKey::into_owned),
             range.1.map(StoreKey::into_owned),
         );
-        let iterations = self
-            .evicting_map
-            .range(range, move |key, _value| handler(key.borrow()))
-            .await;
+        if let [shard] = &self.shards[..] {
+            let iterations = shard
+                .range(range, move |key, _value| handler(key.borrow()))
+                .await;
+            return Ok(iterations);
+        }
+        // Every shard is sorted on its own, so the keys are merged before
+        // they are handed out in order.
+        let mut keys: Vec<StoreKey<'static>> = Vec::new();
+        for shard in &self.shards {
+            shard
+                .range(range.clone(), |key, _value| {
+                    keys.push(key.clone().into());
+                    true
+                })
+                .await;
+        }
+        keys.sort_unstable();
+        let mut iterations = 0;
+        for key in &keys {
+            if !handler(key) {
+                break;
+            }
+            iterations += 1;
+        }
         Ok(iterations)
     }
 
+    fn optimized_for(&self, optimization: StoreOptimizations) -> bool {
+        optimization == StoreOptimizations::OrderedList
+    }
+
     async fn update(
         self: Pin<&Self>,
         key: StoreKey<'_>,
         mut reader: DropCloserReadHalf,
-        _size_info: UploadSizeInfo,
+        size_info: UploadSizeInfo,
     ) -> Result<(), Error> {
+        // The buffer being filled counts against the memory budget too. The
+        // declared size comes from the client, so it is only used to reject
+        // uploads that can never fit and room is made as the data arrives.
+        let max_bytes = global_memory_budget().max_bytes();
+        if let UploadSizeInfo::ExactSize(size) = size_info {
+            error_if!(
+                max_bytes != 0 && size > max_bytes,
+                "Upload of {size} bytes is larger than the memory budget of {max_bytes} bytes in memory_store::update"
+            );
+        }
+        let mut reservation = global_memory_budget().reserve(0);
+
         // Internally Bytes might hold a reference to more data than just our data. To prevent
         // this potential case, we make a full copy of our data for long-term storage.
         let final_buffer = {
-            let buffer = reader
-                .consume(None)
-                .await
-                .err_tip(|| "Failed to collect all bytes from reader in memory_store::update")?;
-            let mut new_buffer = BytesMut::with_capacity(buffer.len());
-            new_buffer.extend_from_slice(&buffer[..]);
+            let mut new_buffer = BytesMut::new();
+            loop {
+                let chunk = reader.recv().await.err_tip(|| {
+                    "Failed to collect all bytes from reader in memory_store::update"
+                })?;
+                if chunk.is_empty() {
+                    break; // EOF.
+                }
+                reservation.grow(chunk.len() as u64);
+                error_if!(
+                    max_bytes != 0 && (new_buffer.len() + chunk.len()) as u64 > max_bytes,
+                    "Upload is larger than the memory budget of {max_bytes} bytes in memory_store::update"
+                );
+                global_memory_budget().enforce().await;
+                new_buffer.extend_from_slice(&chunk);
+            }
             new_buffer.freeze()
         };
 
-        self.evicting_map
-            .insert(key.into_owned().into(), BytesWrapper(final_buffer))
+        let entry = self.make_entry(&key, final_buffer);
+        self.shard_for_key(&key)
+            .insert(key.into_owned().into(), entry)
             .await;
+        drop(reservation);
+        global_memory_budget().enforce().await;
         Ok(())
     }
 
@@ -162,18 +577,18 @@ impl StoreDriver for MemoryStore {
             return Ok(());
         }
 
-        let value = self
-            .evicting_map
-            .get(&key)
-            .await
-            .err_tip_with_code(|_| (Code::NotFound, format!("Key {key:?} not found")))?;
+        let value = match self.shard_for_key(&key).get(&key).await {
+            Some(value) => Some(value),
+            None => self.promote(&key).await?,
+        }
+        .err_tip_with_code(|_| (Code::NotFound, format!("Key {key:?} not found")))?;
         let default_len = usize::try_from(value.len())
             .err_tip(|| "Could not convert value.len() to usize")?
             .saturating_sub(offset);
         let length = length.unwrap_or(default_len).min(default_len);
         if length > 0 {
             writer
-                .send(value.0.slice(offset..(offset + length)))
+                .send(value.data.slice(offset..(offset + length)))
                 .await
                 .err_tip(|| "Failed to write data in memory store")?;
         }
@@ -194,6 +609,80 @@ impl StoreDriver for MemoryStore {
     fn as_any_arc(self: Arc<Self>) -> Arc<dyn std::any::Any + Sync + Send + 'static> {
         self
     }
+
+    async fn reconfigure(&self, spec: &StoreSpec, check_only: bool) -> Result<(), Error> {
+        let StoreSpec::memory(spec) = spec else {
+            return Err(make_input_err!(
+                "MemoryStore can only be reconfigured with a memory spec"
+            ));
+        };
+        error_if!(
+            spec.shard_count.max(1) != self.shards.len(),
+            "shard_count of MemoryStore can't be changed while running"
+        );
+        error_if!(
+            spec.spill.is_some() != self.spill.is_some(),
+            "spill of MemoryStore can't be added or removed while running"
+        );
+        let empty_policy = EvictionPolicy::default();
+        let eviction_policy = spec.eviction_policy.as_ref().unwrap_or(&empty_policy);
+        let shard_policy = shard_eviction_policy(eviction_policy, self.shards.len());
+        for shard in &self.shards {
+            let result = if check_only {
+                shard.check_policy(&shard_policy).await
+            } else {
+                shard.set_policy(&shard_policy).await
+            };
+            result.err_tip(|| "In MemoryStore::reconfigure")?;
+        }
+        if let (Some(spill_area), Some(spill_spec)) = (&self.spill, &spec.spill) {
+            spill_area
+                .store
+                .as_store_driver()
+                .reconfigure(&StoreSpec::filesystem(spill_spec.clone()), check_only)
+                .await
+                .err_tip(|| "In MemoryStore::reconfigure for spill store")?;
+        }
+        Ok(())
+    }
+
+    async fn evict(&self, bytes: u64) -> Result<u64, Error> {
+        let shard_bytes = bytes.div_ceil(self.shards.len() as u64);
+        let mut evicted_bytes = 0;
+        for shard in &self.shards {
+            evicted_bytes += shard.run_eviction(shard_bytes).await;
+        }
+        Ok(evicted_bytes)
+    }
+}
+
+fn shard_accounted_bytes(shard: &Shard) -> u64 {
+    shard.approximate_size_bytes() + shard.approximate_len() * ENTRY_OVERHEAD_BYTES
+}
+
+#[async_trait]
+impl MemoryConsumer for MemoryStore {
+    fn accounted_bytes(&self) -> u64 {
+        self.shards.iter().map(shard_accounted_bytes).sum()
+    }
+
+    async fn evict(&self, bytes: u64) -> u64 {
+        // Every shard evicts a share proportional to its size, so the
+        // eviction is spread over the shards like the entries are.
+        let total_bytes = self.accounted_bytes().max(1);
+        let mut evicted_bytes = 0;
+        for shard in &self.shards {
+            let share = u64::try_from(
+                (u128::from(bytes) * u128::from(shard_accounted_bytes(shard)))
+                    .div_ceil(u128::from(total_bytes)),
+            )
+            .unwrap_or(u64::MAX);
+            if share != 0 {
+                evicted_bytes += shard.evict_bytes(share, ENTRY_OVERHEAD_BYTES).await;
+            }
+        }
+        evicted_bytes
+    }
 }
 
 default_health_status_indicator!(MemoryStore);
@@ -0,0 +1,271 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::borrow::Cow;
+use std::ops::Bound;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Instant;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use nativelink_config::stores::StoreSpec;
+use nativelink_error::Error;
+use nativelink_metric::{
+    MetricFieldData, MetricKind, MetricPublishKnownKindData, MetricsComponent,
+};
+use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
+use nativelink_util::fs;
+use nativelink_util::health_utils::{HealthRegistryBuilder, HealthStatus, HealthStatusIndicator};
+use nativelink_util::histogram_metrics::{
+    check_slow_store_operation, observe_store_bytes, observe_store_operation,
+};
+use nativelink_util::store_trait::{
+    Store, StoreDriver, StoreKey, StoreLike, StoreOptimizations, UploadSizeInfo,
+};
+
+/// Wraps a store that has a name in the config and records the latency of
+/// its operations and the size of the data they move in histograms labeled
+/// with that name, and logs the slow ones. Everything else is passed
+/// through, so the store looks the same as the one it wraps to the metrics
+/// tree and to downcasts.
+pub struct MetricsStore {
+    name: String,
+    inner: Store,
+}
+
+impl MetricsStore {
+    pub fn new(name: &str, inner: Store) -> Arc<Self> {
+        Arc::new(Self {
+            name: name.to_string(),
+            inner,
+        })
+    }
+
+    fn observe<T>(
+        &self,
+        operation: &str,
+        key: &StoreKey<'_>,
+        size: Option<u64>,
+        start: Instant,
+        result: &Result<T, Error>,
+    ) {
+        let elapsed = start.elapsed();
+        observe_store_operation(&self.name, operation, elapsed, result.is_ok());
+        check_slow_store_operation(
+            &self.name,
+            operation,
+            || key.as_str().into_owned(),
+            size,
+            elapsed,
+        );
+    }
+}
+
+impl MetricsComponent for MetricsStore {
+    fn publish(
+        &self,
+        kind: MetricKind,
+        field_metadata: MetricFieldData,
+    ) -> Result<MetricPublishKnownKindData, nativelink_metric::Error> {
+        self.inner.publish(kind, field_metadata)
+    }
+}
+
+#[async_trait]
+impl StoreDriver for MetricsStore {
+    async fn has_with_results(
+        self: Pin<&Self>,
+        digests: &[StoreKey<'_>],
+        results: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        let start = Instant::now();
+        let result = self
+            .inner
+            .as_store_driver_pin()
+            .has_with_results(digests, results)
+            .await;
+        let elapsed = start.elapsed();
+        observe_store_operation(&self.name, "has", elapsed, result.is_ok());
+        check_slow_store_operation(
+            &self.name,
+            "has",
+            || match digests {
+                [key] => key.as_str().into_owned(),
+                keys => format!("<{} keys>", keys.len()),
+            },
+            None,
+            elapsed,
+        );
+        result
+    }
+
+    async fn list(
+        self: Pin<&Self>,
+        range: (Bound<StoreKey<'_>>, Bound<StoreKey<'_>>),
+        handler: &mut (dyn for<'a> FnMut(&'a StoreKey) -> bool + Send + Sync + '_),
+    ) -> Result<u64, Error> {
+        self.inner.as_store_driver_pin().list(range, handler).await
+    }
+
+    async fn remove(self: Pin<&Self>, key: StoreKey<'_>) -> Result<bool, Error> {
+        self.inner.remove(key).await
+    }
+
+    async fn update(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        reader: DropCloserReadHalf,
+        upload_size: UploadSizeInfo,
+    ) -> Result<(), Error> {
+        let start = Instant::now();
+        let result = self.inner.update(key.borrow(), reader, upload_size).await;
+        // The reader is consumed by the inner store, so only uploads of a
+        // known size are counted.
+        let size = match upload_size {
+            UploadSizeInfo::ExactSize(size) => Some(size),
+            UploadSizeInfo::MaxSize(_) => None,
+        };
+        self.observe("update", &key, size, start, &result);
+        if let (Ok(()), Some(size)) = (&result, size) {
+            observe_store_bytes(&self.name, "update", size);
+        }
+        result
+    }
+
+    async fn update_many(
+        self: Pin<&Self>,
+        items: Vec<(StoreKey<'_>, Bytes)>,
+    ) -> Vec<Result<(), Error>> {
+        let sizes: Vec<u64> = items.iter().map(|(_, data)| data.len() as u64).collect();
+        let start = Instant::now();
+        let results = self.inner.update_many(items).await;
+        let elapsed = start.elapsed();
+        observe_store_operation(
+            &self.name,
+            "update_many",
+            elapsed,
+            results.iter().all(Result::is_ok),
+        );
+        check_slow_store_operation(
+            &self.name,
+            "update_many",
+            || format!("<{} keys>", sizes.len()),
+            Some(sizes.iter().sum()),
+            elapsed,
+        );
+        for (result, size) in results.iter().zip(sizes) {
+            if result.is_ok() {
+                observe_store_bytes(&self.name, "update", size);
+            }
+        }
+        results
+    }
+
+    fn optimized_for(&self, optimization: StoreOptimizations) -> bool {
+        self.inner.optimized_for(optimization)
+    }
+
+    async fn update_with_whole_file(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        file: fs::ResumeableFileSlot,
+        upload_size: UploadSizeInfo,
+    ) -> Result<Option<fs::ResumeableFileSlot>, Error> {
+        let start = Instant::now();
+        let result = self
+            .inner
+            .update_with_whole_file(key.borrow(), file, upload_size)
+            .await;
+        let size = match upload_size {
+            UploadSizeInfo::ExactSize(size) => Some(size),
+            UploadSizeInfo::MaxSize(_) => None,
+        };
+        self.observe("update", &key, size, start, &result);
+        if let (Ok(_), Some(size)) = (&result, size) {
+            observe_store_bytes(&self.name, "update", size);
+        }
+        result
+    }
+
+    async fn update_oneshot(self: Pin<&Self>, key: StoreKey<'_>, data: Bytes) -> Result<(), Error> {
+        let size = data.len() as u64;
+        let start = Instant::now();
+        let result = self.inner.update_oneshot(key.borrow(), data).await;
+        self.observe("update", &key, Some(size), start, &result);
+        if result.is_ok() {
+            observe_store_bytes(&self.name, "update", size);
+        }
+        result
+    }
+
+    async fn get_part(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        writer: &mut DropCloserWriteHalf,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<(), Error> {
+        let bytes_written_before = writer.get_bytes_written();
+        let start = Instant::now();
+        let result = self
+            .inner
+            .get_part(key.borrow(), &mut *writer, offset, length)
+            .await;
+        let size = writer.get_bytes_written() - bytes_written_before;
+        self.observe("get_part", &key, Some(size), start, &result);
+        observe_store_bytes(&self.name, "get_part", size);
+        result
+    }
+
+    fn inner_store(&self, key: Option<StoreKey>) -> &dyn StoreDriver {
+        self.inner.inner_store(key)
+    }
+
+    fn as_any<'a>(&'a self) -> &'a (dyn std::any::Any + Sync + Send + 'static) {
+        self
+    }
+
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn std::any::Any + Sync + Send + 'static> {
+        // Callers downcast named stores to the type they were configured
+        // with, like the redis store of a scheduler.
+        self.inner.clone().into_inner().as_any_arc()
+    }
+
+    fn register_health(self: Arc<Self>, registry: &mut HealthRegistryBuilder) {
+        self.inner.register_health(registry);
+    }
+
+    async fn reconfigure(&self, spec: &StoreSpec, check_only: bool) -> Result<(), Error> {
+        self.inner
+            .as_store_driver()
+            .reconfigure(spec, check_only)
+            .await
+    }
+
+    async fn evict(&self, bytes: u64) -> Result<u64, Error> {
+        self.inner.evict(bytes).await
+    }
+}
+
+#[async_trait]
+impl HealthStatusIndicator for MetricsStore {
+    fn get_name(&self) -> &'static str {
+        HealthStatusIndicator::get_name(self.inner.as_store_driver())
+    }
+
+    async fn check_health(&self, namespace: Cow<'static, str>) -> HealthStatus {
+        StoreDriver::check_health(self.inner.as_store_driver_pin(), namespace).await
+    }
+}
@@ -0,0 +1,364 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::borrow::BorrowMut;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::join;
+use futures::stream::{FuturesUnordered, StreamExt};
+use nativelink_config::stores::{MirrorSpec, StoreSpec};
+use nativelink_error::{error_if, make_err, make_input_err, Code, Error, ResultExt};
+use nativelink_metric::MetricsComponent;
+use nativelink_util::background_spawn;
+use nativelink_util::buf_channel::{
+    make_buf_channel_pair, DropCloserReadHalf, DropCloserWriteHalf,
+};
+use nativelink_util::health_utils::{default_health_status_indicator, HealthStatusIndicator};
+use nativelink_util::store_trait::{Store, StoreDriver, StoreKey, StoreLike, UploadSizeInfo};
+use tokio::sync::Semaphore;
+use tracing::{event, Level};
+
+// Note: If you change this, adjust the docs in the config.
+const DEFAULT_MAX_CONCURRENT_REPAIRS: usize = 16;
+
+#[derive(MetricsComponent)]
+pub struct MirrorStore {
+    #[metric(group = "stores")]
+    stores: Vec<Store>,
+    #[metric(help = "Number of stores that must accept a write for it to succeed")]
+    write_quorum: usize,
+    repair: bool,
+    repair_permits: Arc<Semaphore>,
+    #[metric]
+    metrics: Arc<MirrorStoreMetrics>,
+}
+
+impl MirrorStore {
+    pub fn new(spec: &MirrorSpec, stores: Vec<Store>) -> Result<Arc<Self>, Error> {
+        error_if!(
+            spec.stores.len() != stores.len(),
+            "Config stores do not match stores length"
+        );
+        error_if!(
+            stores.is_empty(),
+            "MirrorStore must have at least one store"
+        );
+        error_if!(
+            spec.write_quorum > stores.len(),
+            "write_quorum of {} is larger than the {} stores of MirrorStore",
+            spec.write_quorum,
+            stores.len()
+        );
+        let write_quorum = if spec.write_quorum == 0 {
+            stores.len()
+        } else {
+            spec.write_quorum
+        };
+        let max_concurrent_repairs = if spec.max_concurrent_repairs == 0 {
+            DEFAULT_MAX_CONCURRENT_REPAIRS
+        } else {
+            spec.max_concurrent_repairs
+        };
+        Ok(Arc::new(Self {
+            stores,
+            write_quorum,
+            repair: !spec.disable_repair,
+            repair_permits: Arc::new(Semaphore::new(max_concurrent_repairs)),
+            metrics: Arc::new(MirrorStoreMetrics::default()),
+        }))
+    }
+
+    pub fn stores(&self) -> &[Store] {
+        &self.stores
+    }
+
+    /// Copy an object from `source` to the `targets` that are missing it
+    /// in the background.
+    fn spawn_repair(&self, key: StoreKey<'_>, source: &Store, targets: Vec<Store>) {
+        if !self.repair || targets.is_empty() {
+            return;
+        }
+        let Ok(permit) = self.repair_permits.clone().try_acquire_owned() else {
+            event!(
+                Level::DEBUG,
+                ?key,
+                "Too many repairs in progress, skipping repair in MirrorStore"
+            );
+            return;
+        };
+        let key = key.into_owned();
+        let source = source.clone();
+        let metrics = self.metrics.clone();
+        metrics.repair_started_count.fetch_add(1, Ordering::Relaxed);
+        background_spawn!("mirror_store_repair", async move {
+            let _permit = permit;
+            let result = async {
+                let Some(size) = source
+                    .has(key.borrow())
+                    .await
+                    .err_tip(|| "In MirrorStore::spawn_repair::has")?
+                else {
+                    return Ok(());
+                };
+                for target in targets {
+                    let (tx, rx) = make_buf_channel_pair();
+                    let (get_res, update_res) = join!(
+                        source.get(key.borrow(), tx),
+                        target.update(key.borrow(), rx, UploadSizeInfo::ExactSize(size))
+                    );
+                    get_res
+                        .err_tip(|| "In MirrorStore::spawn_repair::get")
+                        .merge(update_res.err_tip(|| "In MirrorStore::spawn_repair::update"))?;
+                }
+                Result::<(), Error>::Ok(())
+            }
+            .await;
+            if let Err(err) = result {
+                metrics.repair_failed_count.fetch_add(1, Ordering::Relaxed);
+                event!(
+                    Level::WARN,
+                    ?key,
+                    ?err,
+                    "Failed to repair missing object in MirrorStore"
+                );
+            }
+        });
+    }
+}
+
+#[async_trait]
+impl StoreDriver for MirrorStore {
+    async fn has_with_results(
+        self: Pin<&Self>,
+        keys: &[StoreKey<'_>],
+        results: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        // Only ask the next store about the keys that were not found yet. A
+        // failing store is skipped, as long as another store answers.
+        let mut last_err = None;
+        let mut answered = false;
+        for store in &self.stores {
+            let missing: Vec<usize> = results
+                .iter()
+                .enumerate()
+                .filter_map(|(i, result)| result.is_none().then_some(i))
+                .collect();
+            if missing.is_empty() {
+                break;
+            }
+            let missing_keys: Vec<_> = missing.iter().map(|&i| keys[i].borrow()).collect();
+            let mut missing_results = vec![None; missing_keys.len()];
+            if let Err(err) = store
+                .has_with_results(&missing_keys, &mut missing_results)
+                .await
+            {
+                event!(
+                    Level::INFO,
+                    ?err,
+                    "Store failed in MirrorStore::has_with_results, trying next store"
+                );
+                last_err = Some(err);
+                continue;
+            }
+            answered = true;
+            for (i, result) in missing.into_iter().zip(missing_results) {
+                results[i] = result;
+            }
+        }
+        match last_err {
+            Some(err) if !answered => Err(err).err_tip(|| "In MirrorStore::has_with_results"),
+            _ => Ok(()),
+        }
+    }
+
+    async fn update(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        mut reader: DropCloserReadHalf,
+        upload_size: UploadSizeInfo,
+    ) -> Result<(), Error> {
+        let (mut txs, mut update_futs): (Vec<_>, FuturesUnordered<_>) = self
+            .stores
+            .iter()
+            .enumerate()
+            .map(|(i, store)| {
+                let (tx, rx) = make_buf_channel_pair();
+                let key = key.borrow();
+                (Some(tx), async move {
+                    (i, store.update(key, rx, upload_size).await)
+                })
+            })
+            .unzip();
+
+        // Every chunk is sent to all stores that have not failed yet, so the
+        // slowest store sets the pace of the upload.
+        let tee_fut = async move {
+            loop {
+                let chunk = reader
+                    .recv()
+                    .await
+                    .err_tip(|| "Failed to read data in MirrorStore::update")?;
+                let is_eof = chunk.is_empty();
+                for maybe_tx in &mut txs {
+                    let Some(tx) = maybe_tx else {
+                        continue;
+                    };
+                    let send_res = if is_eof {
+                        tx.send_eof()
+                    } else {
+                        tx.send(chunk.clone()).await
+                    };
+                    // The store reports why it failed in its update result.
+                    if send_res.is_err() {
+                        *maybe_tx = None;
+                    }
+                }
+                if is_eof {
+                    return Result::<(), Error>::Ok(());
+                }
+            }
+        };
+        let collect_fut = async {
+            let mut results = Vec::with_capacity(self.stores.len());
+            while let Some(result) = update_futs.next().await {
+                results.push(result);
+            }
+            results
+        };
+        let (tee_res, results) = join!(tee_fut, collect_fut);
+        tee_res?;
+
+        let mut success_count = 0;
+        let mut errors = Vec::new();
+        for (i, result) in results {
+            match result {
+                Ok(()) => success_count += 1,
+                Err(err) => {
+                    self.metrics
+                        .write_failure_count
+                        .fetch_add(1, Ordering::Relaxed);
+                    event!(
+                        Level::WARN,
+                        ?key,
+                        store_index = i,
+                        ?err,
+                        "Store failed to write object in MirrorStore"
+                    );
+                    errors.push(err);
+                }
+            }
+        }
+        if success_count >= self.write_quorum {
+            return Ok(());
+        }
+        let err = make_err!(
+            Code::Unavailable,
+            "Only {success_count} stores accepted the write in MirrorStore, but {} are required",
+            self.write_quorum
+        );
+        Err(errors.into_iter().fold(err, Error::merge))
+    }
+
+    async fn get_part(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        writer: &mut DropCloserWriteHalf,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<(), Error> {
+        let mut missing_stores = Vec::new();
+        let mut last_err = None;
+        for store in &self.stores {
+            // Continue where the previous store left off.
+            let bytes_written = writer.get_bytes_written();
+            let result = store
+                .get_part(
+                    key.borrow(),
+                    writer.borrow_mut(),
+                    offset + bytes_written,
+                    length.map(|length| length.saturating_sub(bytes_written)),
+                )
+                .await;
+            match result {
+                Ok(()) => {
+                    self.spawn_repair(key, store, missing_stores);
+                    return Ok(());
+                }
+                Err(err) => {
+                    if err.code == Code::NotFound {
+                        missing_stores.push(store.clone());
+                    } else {
+                        event!(
+                            Level::INFO,
+                            ?key,
+                            ?err,
+                            "Store failed in MirrorStore::get_part, trying next store"
+                        );
+                    }
+                    last_err = Some(err);
+                }
+            }
+        }
+        Err(last_err
+            .unwrap_or_else(|| make_err!(Code::Internal, "No stores in MirrorStore"))
+            .append("In MirrorStore::get_part"))
+    }
+
+    fn inner_store(&self, _key: Option<StoreKey>) -> &dyn StoreDriver {
+        self
+    }
+
+    fn as_any<'a>(&'a self) -> &'a (dyn std::any::Any + Sync + Send + 'static) {
+        self
+    }
+
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn std::any::Any + Sync + Send + 'static> {
+        self
+    }
+
+    async fn reconfigure(&self, spec: &StoreSpec, check_only: bool) -> Result<(), Error> {
+        let StoreSpec::mirror(spec) = spec else {
+            return Err(make_input_err!(
+                "MirrorStore can only be reconfigured with a mirror spec"
+            ));
+        };
+        error_if!(
+            spec.stores.len() != self.stores.len(),
+            "Stores of MirrorStore can't be added or removed while running"
+        );
+        for (store, store_spec) in self.stores.iter().zip(&spec.stores) {
+            store
+                .as_store_driver()
+                .reconfigure(store_spec, check_only)
+                .await
+                .err_tip(|| "In MirrorStore::reconfigure")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default, MetricsComponent)]
+struct MirrorStoreMetrics {
+    #[metric(help = "Number of writes to a single store that failed")]
+    write_failure_count: AtomicU64,
+    #[metric(help = "Number of repairs of missing objects started")]
+    repair_started_count: AtomicU64,
+    #[metric(help = "Number of repairs of missing objects that failed")]
+    repair_failed_count: AtomicU64,
+}
+
+default_health_status_indicator!(MirrorStore);
@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::ops::Bound;
 use std::pin::Pin;
 use std::sync::Arc;
 
@@ -54,6 +55,21 @@ impl StoreDriver for NoopStore {
         Ok(())
     }
 
+    async fn list(
+        self: Pin<&Self>,
+        _range: (Bound<StoreKey<'_>>, Bound<StoreKey<'_>>),
+        _handler: &mut (dyn for<'a> FnMut(&'a StoreKey) -> bool + Send + Sync + '_),
+    ) -> Result<u64, Error> {
+        // Nothing is ever stored, so there is nothing to list. This keeps
+        // the store usable as a placeholder where keys are listed, like
+        // rebalancing a shard store.
+        Ok(0)
+    }
+
+    async fn remove(self: Pin<&Self>, _key: StoreKey<'_>) -> Result<bool, Error> {
+        Ok(false)
+    }
+
     async fn update(
         self: Pin<&Self>,
         _key: StoreKey<'_>,
@@ -0,0 +1,293 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::join;
+use nativelink_config::stores::{QuotaLimitSpec, QuotaSpec, StoreSpec};
+use nativelink_error::{make_err, make_input_err, Code, Error, ResultExt};
+use nativelink_metric::MetricsComponent;
+use nativelink_util::buf_channel::{
+    make_buf_channel_pair, DropCloserReadHalf, DropCloserWriteHalf,
+};
+use nativelink_util::health_utils::{default_health_status_indicator, HealthStatusIndicator};
+use nativelink_util::origin_context::{ActiveOriginContext, ACTIVE_INSTANCE_NAME};
+use nativelink_util::store_trait::{Store, StoreDriver, StoreKey, StoreLike, UploadSizeInfo};
+use parking_lot::Mutex;
+use tokio::time::{sleep, Instant};
+
+use crate::rate_limit_store::TokenBucket;
+
+const DEFAULT_RESET_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The usage of a single instance name in the current quota window.
+#[derive(MetricsComponent)]
+pub struct InstanceUsage {
+    #[metric(help = "Number of bytes the instance name may write per window")]
+    max_bytes: u64,
+    #[metric(help = "Number of bytes written by the instance name in the current window")]
+    bytes_written: AtomicU64,
+    #[metric(help = "Number of writes rejected because the quota was used up")]
+    rejected_count: AtomicU64,
+    #[metric(help = "Number of chunks delayed because the quota was used up")]
+    throttled_count: AtomicU64,
+    #[metric(group = "throttle")]
+    throttle: Option<TokenBucket>,
+    window_start: Mutex<Instant>,
+}
+
+impl InstanceUsage {
+    fn new(spec: &QuotaLimitSpec) -> Self {
+        Self {
+            max_bytes: spec.max_bytes,
+            bytes_written: AtomicU64::new(0),
+            rejected_count: AtomicU64::new(0),
+            throttled_count: AtomicU64::new(0),
+            throttle: TokenBucket::new(spec.throttle_bytes_per_second, 0),
+            window_start: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// The usage of the current window with the limits of `spec`.
+    fn with_limits(&self, spec: &QuotaLimitSpec) -> Self {
+        Self {
+            bytes_written: AtomicU64::new(self.bytes_written.load(Ordering::Relaxed)),
+            rejected_count: AtomicU64::new(self.rejected_count.load(Ordering::Relaxed)),
+            throttled_count: AtomicU64::new(self.throttled_count.load(Ordering::Relaxed)),
+            window_start: Mutex::new(*self.window_start.lock()),
+            ..Self::new(spec)
+        }
+    }
+
+    /// Starts a new window if the current one is older than `reset_interval`.
+    fn maybe_reset(&self, reset_interval: Duration) {
+        let mut window_start = self.window_start.lock();
+        if window_start.elapsed() >= reset_interval {
+            *window_start = Instant::now();
+            self.bytes_written.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn quota_exceeded_err(&self, instance_name: &str) -> Error {
+        self.rejected_count.fetch_add(1, Ordering::Relaxed);
+        make_err!(
+            Code::ResourceExhausted,
+            "Instance name '{instance_name}' used up its quota of {} bytes in QuotaStore",
+            self.max_bytes
+        )
+    }
+}
+
+/// The quotas of a [`QuotaStore`], replaced when it is reconfigured.
+struct QuotaLimits {
+    instance_quotas: HashMap<String, QuotaLimitSpec>,
+    default_quota: Option<QuotaLimitSpec>,
+    reset_interval: Duration,
+}
+
+impl QuotaLimits {
+    fn new(spec: &QuotaSpec) -> Self {
+        Self {
+            instance_quotas: spec.instance_quotas.clone(),
+            default_quota: spec.default_quota.clone(),
+            reset_interval: if spec.reset_interval_s == 0 {
+                DEFAULT_RESET_INTERVAL
+            } else {
+                Duration::from_secs(spec.reset_interval_s)
+            },
+        }
+    }
+
+    fn quota_for(&self, instance_name: &str) -> Option<&QuotaLimitSpec> {
+        self.instance_quotas
+            .get(instance_name)
+            .or(self.default_quota.as_ref())
+    }
+}
+
+#[derive(MetricsComponent)]
+pub struct QuotaStore {
+    #[metric(group = "inner_store")]
+    inner_store: Store,
+    limits: Mutex<QuotaLimits>,
+    #[metric(group = "instances")]
+    instances: Mutex<HashMap<String, Arc<InstanceUsage>>>,
+}
+
+impl QuotaStore {
+    pub fn new(spec: &QuotaSpec, inner_store: Store) -> Arc<Self> {
+        Arc::new(Self {
+            inner_store,
+            limits: Mutex::new(QuotaLimits::new(spec)),
+            instances: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Number of bytes written by `instance_name` in the current window.
+    pub fn bytes_written(&self, instance_name: &str) -> u64 {
+        self.instances
+            .lock()
+            .get(instance_name)
+            .map_or(0, |usage| usage.bytes_written.load(Ordering::Relaxed))
+    }
+
+    fn usage_for(&self, instance_name: &str) -> Option<Arc<InstanceUsage>> {
+        let limits = self.limits.lock();
+        let spec = limits.quota_for(instance_name)?;
+        let usage = self
+            .instances
+            .lock()
+            .entry(instance_name.to_string())
+            .or_insert_with(|| Arc::new(InstanceUsage::new(spec)))
+            .clone();
+        usage.maybe_reset(limits.reset_interval);
+        Some(usage)
+    }
+
+    /// Replaces the quotas. The bytes written in the current window still
+    /// count against the new quotas. Writes in progress finish with the
+    /// quota they started with.
+    fn set_limits(&self, spec: &QuotaSpec) {
+        let mut limits = self.limits.lock();
+        *limits = QuotaLimits::new(spec);
+        let mut instances = self.instances.lock();
+        *instances = instances
+            .iter()
+            .filter_map(|(instance_name, usage)| {
+                let spec = limits.quota_for(instance_name)?;
+                Some((instance_name.clone(), Arc::new(usage.with_limits(spec))))
+            })
+            .collect();
+    }
+}
+
+#[async_trait]
+impl StoreDriver for QuotaStore {
+    async fn has_with_results(
+        self: Pin<&Self>,
+        keys: &[StoreKey<'_>],
+        results: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        self.inner_store.has_with_results(keys, results).await
+    }
+
+    async fn update(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        mut reader: DropCloserReadHalf,
+        upload_size: UploadSizeInfo,
+    ) -> Result<(), Error> {
+        // Requests that did not come through a service with an instance
+        // name are accounted to the empty instance name.
+        let instance_name = ActiveOriginContext::get_value(&ACTIVE_INSTANCE_NAME)
+            .ok()
+            .flatten()
+            .map_or_else(String::new, |name| name.as_ref().clone());
+        let Some(usage) = self.usage_for(&instance_name) else {
+            return self.inner_store.update(key, reader, upload_size).await;
+        };
+        if usage.throttle.is_none() {
+            let expected_size = match upload_size {
+                UploadSizeInfo::ExactSize(size) => size,
+                UploadSizeInfo::MaxSize(_) => 0,
+            };
+            let used = usage.bytes_written.load(Ordering::Relaxed);
+            if used.saturating_add(expected_size) > usage.max_bytes {
+                return Err(usage.quota_exceeded_err(&instance_name));
+            }
+        }
+
+        let (mut tx, rx) = make_buf_channel_pair();
+        let forward_fut = async move {
+            loop {
+                let chunk = reader
+                    .recv()
+                    .await
+                    .err_tip(|| "Failed to read data in QuotaStore::update")?;
+                if chunk.is_empty() {
+                    return tx
+                        .send_eof()
+                        .err_tip(|| "Failed to send EOF in QuotaStore::update");
+                }
+                let chunk_len = chunk.len() as u64;
+                let written = usage.bytes_written.fetch_add(chunk_len, Ordering::Relaxed);
+                if written.saturating_add(chunk_len) > usage.max_bytes {
+                    let Some(throttle) = &usage.throttle else {
+                        // Dropping the sender without EOF fails the upload.
+                        return Err(usage.quota_exceeded_err(&instance_name));
+                    };
+                    let delay = throttle.reserve(chunk_len);
+                    if !delay.is_zero() {
+                        usage.throttled_count.fetch_add(1, Ordering::Relaxed);
+                        sleep(delay).await;
+                    }
+                }
+                tx.send(chunk)
+                    .await
+                    .err_tip(|| "Failed to send data in QuotaStore::update")?;
+            }
+        };
+        let (forward_res, update_res) =
+            join!(forward_fut, self.inner_store.update(key, rx, upload_size));
+        // The forwarding error comes first so a used up quota is reported as
+        // such and not as the inner store failing on a missing EOF.
+        forward_res.merge(update_res)
+    }
+
+    async fn get_part(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        writer: &mut DropCloserWriteHalf,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<(), Error> {
+        self.inner_store.get_part(key, writer, offset, length).await
+    }
+
+    fn inner_store(&self, _key: Option<StoreKey>) -> &'_ dyn StoreDriver {
+        self
+    }
+
+    fn as_any<'a>(&'a self) -> &'a (dyn std::any::Any + Sync + Send + 'static) {
+        self
+    }
+
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn std::any::Any + Sync + Send + 'static> {
+        self
+    }
+
+    async fn reconfigure(&self, spec: &StoreSpec, check_only: bool) -> Result<(), Error> {
+        let StoreSpec::quota(spec) = spec else {
+            return Err(make_input_err!(
+                "QuotaStore can only be reconfigured with a quota spec"
+            ));
+        };
+        self.inner_store
+            .as_store_driver()
+            .reconfigure(&spec.backend, check_only)
+            .await
+            .err_tip(|| "In QuotaStore::reconfigure for backend")?;
+        if !check_only {
+            self.set_limits(spec);
+        }
+        Ok(())
+    }
+}
+
+default_health_status_indicator!(QuotaStore);
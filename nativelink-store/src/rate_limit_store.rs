@@ -0,0 +1,246 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::join;
+use nativelink_config::stores::{RateLimitSpec, StoreSpec};
+use nativelink_error::{make_input_err, Error, ResultExt};
+use nativelink_metric::MetricsComponent;
+use nativelink_util::buf_channel::{
+    make_buf_channel_pair, DropCloserReadHalf, DropCloserWriteHalf,
+};
+use nativelink_util::health_utils::{default_health_status_indicator, HealthStatusIndicator};
+use nativelink_util::store_trait::{Store, StoreDriver, StoreKey, StoreLike, UploadSizeInfo};
+use parking_lot::Mutex;
+use tokio::time::{sleep, Instant};
+
+/// A token bucket that is refilled with `rate` tokens per second and holds
+/// at most `burst` tokens.
+#[derive(MetricsComponent)]
+pub struct TokenBucket {
+    #[metric(help = "Number of tokens added to the bucket every second")]
+    rate: u64,
+    #[metric(help = "Maximum number of tokens the bucket can hold")]
+    burst: u64,
+    state: Mutex<TokenBucketState>,
+}
+
+struct TokenBucketState {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: u64, burst: u64) -> Option<Self> {
+        if rate == 0 {
+            return None;
+        }
+        let burst = if burst == 0 { rate } else { burst };
+        Some(Self {
+            rate,
+            burst,
+            state: Mutex::new(TokenBucketState {
+                tokens: burst as f64,
+                last_refill: Instant::now(),
+            }),
+        })
+    }
+
+    /// Take `amount` tokens out of the bucket and return how long the caller
+    /// has to wait before using them. The bucket may go into debt, so a
+    /// request larger than `burst` is allowed, but the requests after it are
+    /// delayed until the debt is paid off.
+    pub fn reserve(&self, amount: u64) -> Duration {
+        let mut state = self.state.lock();
+        let now = Instant::now();
+        let elapsed = now.saturating_duration_since(state.last_refill);
+        state.last_refill = now;
+        state.tokens =
+            (state.tokens + elapsed.as_secs_f64() * self.rate as f64).min(self.burst as f64);
+        state.tokens -= amount as f64;
+        if state.tokens >= 0. {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(-state.tokens / self.rate as f64)
+    }
+}
+
+#[derive(MetricsComponent)]
+pub struct RateLimitStore {
+    #[metric(group = "inner_store")]
+    inner_store: Store,
+    #[metric(group = "bytes_limit")]
+    bytes_limit: Option<TokenBucket>,
+    #[metric(group = "ops_limit")]
+    ops_limit: Option<TokenBucket>,
+    #[metric(help = "Number of times a request was delayed by the rate limit")]
+    throttled_count: AtomicU64,
+    #[metric(help = "Total time in milliseconds requests were delayed by the rate limit")]
+    throttled_ms: AtomicU64,
+}
+
+impl RateLimitStore {
+    pub fn new(spec: &RateLimitSpec, inner_store: Store) -> Arc<Self> {
+        Arc::new(Self {
+            inner_store,
+            bytes_limit: TokenBucket::new(spec.max_bytes_per_second, spec.max_bytes_burst),
+            ops_limit: TokenBucket::new(spec.max_ops_per_second, spec.max_ops_burst),
+            throttled_count: AtomicU64::new(0),
+            throttled_ms: AtomicU64::new(0),
+        })
+    }
+
+    async fn throttle(&self, limit: Option<&TokenBucket>, amount: u64) {
+        let Some(limit) = limit else {
+            return;
+        };
+        let delay = limit.reserve(amount);
+        if delay.is_zero() {
+            return;
+        }
+        self.throttled_count.fetch_add(1, Ordering::Relaxed);
+        self.throttled_ms.fetch_add(
+            u64::try_from(delay.as_millis()).unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+        sleep(delay).await;
+    }
+
+    async fn throttle_ops(&self, count: usize) {
+        self.throttle(self.ops_limit.as_ref(), count as u64).await;
+    }
+
+    async fn throttle_bytes(&self, count: usize) {
+        self.throttle(self.bytes_limit.as_ref(), count as u64).await;
+    }
+}
+
+#[async_trait]
+impl StoreDriver for RateLimitStore {
+    async fn has_with_results(
+        self: Pin<&Self>,
+        keys: &[StoreKey<'_>],
+        results: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        self.throttle_ops(keys.len()).await;
+        self.inner_store.has_with_results(keys, results).await
+    }
+
+    async fn update(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        mut reader: DropCloserReadHalf,
+        upload_size: UploadSizeInfo,
+    ) -> Result<(), Error> {
+        self.throttle_ops(1).await;
+        if self.bytes_limit.is_none() {
+            return self.inner_store.update(key, reader, upload_size).await;
+        }
+        let (mut tx, rx) = make_buf_channel_pair();
+        let forward_fut = async move {
+            loop {
+                let chunk = reader
+                    .recv()
+                    .await
+                    .err_tip(|| "Failed to read data in RateLimitStore::update")?;
+                if chunk.is_empty() {
+                    return tx
+                        .send_eof()
+                        .err_tip(|| "Failed to send EOF in RateLimitStore::update");
+                }
+                self.throttle_bytes(chunk.len()).await;
+                tx.send(chunk)
+                    .await
+                    .err_tip(|| "Failed to send data in RateLimitStore::update")?;
+            }
+        };
+        let (forward_res, update_res) =
+            join!(forward_fut, self.inner_store.update(key, rx, upload_size));
+        update_res.merge(forward_res)
+    }
+
+    async fn get_part(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        writer: &mut DropCloserWriteHalf,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<(), Error> {
+        self.throttle_ops(1).await;
+        if self.bytes_limit.is_none() {
+            return self.inner_store.get_part(key, writer, offset, length).await;
+        }
+        let (tx, mut rx) = make_buf_channel_pair();
+        let forward_fut = async move {
+            loop {
+                let chunk = rx
+                    .recv()
+                    .await
+                    .err_tip(|| "Failed to read data in RateLimitStore::get_part")?;
+                if chunk.is_empty() {
+                    return writer
+                        .send_eof()
+                        .err_tip(|| "Failed to send EOF in RateLimitStore::get_part");
+                }
+                self.throttle_bytes(chunk.len()).await;
+                writer
+                    .send(chunk)
+                    .await
+                    .err_tip(|| "Failed to send data in RateLimitStore::get_part")?;
+            }
+        };
+        // The sender is dropped once the inner store is done, so the
+        // forwarding stops if the inner store fails without sending EOF.
+        let get_fut = async move {
+            let mut tx = tx;
+            self.inner_store
+                .get_part(key, &mut tx, offset, length)
+                .await
+        };
+        let (forward_res, get_res) = join!(forward_fut, get_fut);
+        get_res.merge(forward_res)
+    }
+
+    fn inner_store(&self, _key: Option<StoreKey>) -> &'_ dyn StoreDriver {
+        self
+    }
+
+    fn as_any<'a>(&'a self) -> &'a (dyn std::any::Any + Sync + Send + 'static) {
+        self
+    }
+
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn std::any::Any + Sync + Send + 'static> {
+        self
+    }
+
+    async fn reconfigure(&self, spec: &StoreSpec, check_only: bool) -> Result<(), Error> {
+        let StoreSpec::rate_limit(spec) = spec else {
+            return Err(make_input_err!(
+                "RateLimitStore can only be reconfigured with a rate_limit spec"
+            ));
+        };
+        self.inner_store
+            .as_store_driver()
+            .reconfigure(&spec.backend, check_only)
+            .await
+            .err_tip(|| "In RateLimitStore::reconfigure for backend")
+    }
+}
+
+default_health_status_indicator!(RateLimitStore);
@@ -13,28 +13,36 @@
 // limitations under the License.
 
 use std::borrow::Cow;
-use std::cmp;
+use std::num::NonZeroUsize;
+use std::ops::{Bound, Deref, RangeBounds};
 use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Weak};
 use std::time::Duration;
+use std::{cmp, iter};
 
 use async_trait::async_trait;
 use bytes::Bytes;
 use const_format::formatcp;
-use fred::clients::{Pool as RedisPool, SubscriberClient};
-use fred::interfaces::{ClientLike, KeysInterface, PubsubInterface};
+use fred::clients::{Client as RedisClient, Pool as RedisPool, SubscriberClient};
+use fred::interfaces::{ClientLike, KeysInterface, PubsubInterface, TrackingInterface};
 use fred::prelude::{EventInterface, HashesInterface, RediSearchInterface};
 use fred::types::config::{
-    Config as RedisConfig, ConnectionConfig, PerformanceConfig, ReconnectPolicy, UnresponsiveConfig,
+    Config as RedisConfig, ConnectionConfig, PerformanceConfig, ReconnectError, ReconnectPolicy,
+    ServerConfig, TlsConnector, UnresponsiveConfig,
 };
 use fred::types::redisearch::{
     AggregateOperation, FtAggregateOptions, FtCreateOptions, IndexKind, Load, SearchField,
     SearchSchema, SearchSchemaKind, WithCursor,
 };
 use fred::types::scripts::Script;
-use fred::types::{Builder, Key as RedisKey, Map as RedisMap, SortOrder, Value as RedisValue};
+use fred::types::{
+    Builder, Expiration, Key as RedisKey, Map as RedisMap, RespVersion, SortOrder,
+    Value as RedisValue,
+};
 use futures::stream::FuturesUnordered;
-use futures::{future, FutureExt, Stream, StreamExt, TryFutureExt, TryStreamExt};
+use futures::{future, stream, FutureExt, Stream, StreamExt, TryFutureExt, TryStreamExt};
+use lru::LruCache;
 use nativelink_config::stores::{RedisMode, RedisSpec};
 use nativelink_error::{make_err, make_input_err, Code, Error, ResultExt};
 use nativelink_metric::MetricsComponent;
@@ -42,15 +50,19 @@ use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
 use nativelink_util::health_utils::{HealthRegistryBuilder, HealthStatus, HealthStatusIndicator};
 use nativelink_util::spawn;
 use nativelink_util::store_trait::{
-    BoolValue, SchedulerCurrentVersionProvider, SchedulerIndexProvider, SchedulerStore,
-    SchedulerStoreDataProvider, SchedulerStoreDecodeTo, SchedulerStoreKeyProvider,
+    slow_update_many, BoolValue, SchedulerCurrentVersionProvider, SchedulerIndexProvider,
+    SchedulerStore, SchedulerStoreDataProvider, SchedulerStoreDecodeTo, SchedulerStoreKeyProvider,
     SchedulerSubscription, SchedulerSubscriptionManager, StoreDriver, StoreKey, UploadSizeInfo,
 };
 use nativelink_util::task::JoinHandleDropGuard;
 use parking_lot::{Mutex, RwLock};
 use patricia_tree::StringPatriciaMap;
+use rustls::crypto::ring;
+use rustls::{ClientConfig as RustlsClientConfig, RootCertStore};
 use tokio::select;
-use tokio::time::sleep;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio::time::{sleep, timeout};
 use tracing::{event, Level};
 use uuid::Uuid;
 
@@ -61,6 +73,10 @@ use crate::redis_utils::ft_aggregate;
 /// Note: If this changes it should be updated in the config documentation.
 const DEFAULT_READ_CHUNK_SIZE: usize = 64 * 1024;
 
+/// The default maximum number of outstanding reads per get if not specified.
+/// Note: If this changes it should be updated in the config documentation.
+const DEFAULT_MAX_CHUNK_READS_PER_GET: usize = 4;
+
 /// The default size of the connection pool if not specified.
 /// Note: If this changes it should be updated in the config documentation.
 const DEFAULT_CONNECTION_POOL_SIZE: usize = 3;
@@ -88,6 +104,28 @@ const DEFAULT_COMMAND_TIMEOUT_MS: u64 = 10_000;
 /// Note: If this changes it should be updated in the config documentation.
 const DEFAULT_MAX_CHUNK_UPLOADS_PER_UPDATE: usize = 10;
 
+/// The default maximum size of a single write command during an update.
+/// Note: If this changes it should be updated in the config documentation.
+const DEFAULT_UPLOAD_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// The default largest value kept in the client-side cache if not specified.
+/// Note: If this changes it should be updated in the config documentation.
+const DEFAULT_CLIENT_SIDE_CACHE_MAX_VALUE_SIZE: usize = 64 * 1024;
+
+/// The default time in milliseconds to wait for a pooled connection with
+/// spare capacity if not specified.
+/// Note: If this changes it should be updated in the config documentation.
+const DEFAULT_CONNECTION_ACQUIRE_TIMEOUT_MS: u64 = 10_000;
+
+/// The largest value redis can store in a single string.
+const MAX_REDIS_STRING_SIZE: usize = 512 * 1024 * 1024;
+
+/// Marks the value of a key as [`ChunkedBlobMetadata`].
+const CHUNKED_METADATA_MAGIC: &str = "nativelink-chunked-v1";
+
+/// The number of keys redis is asked to return per SCAN call in `list()`.
+const LIST_SCAN_COUNT: u32 = 1000;
+
 #[allow(clippy::trivially_copy_pass_by_ref)]
 fn to_hex(value: &u32) -> String {
     format!("{value:08x}")
@@ -99,6 +137,20 @@ pub struct RedisStore {
     /// The client pool connecting to the backing Redis instance(s).
     client_pool: RedisPool,
 
+    /// One semaphore per client in `client_pool` limiting the number of
+    /// in-flight operations on that connection. Empty if unlimited.
+    in_flight_limits: Vec<Semaphore>,
+
+    /// The client index to start looking for spare capacity at.
+    next_client_index: AtomicUsize,
+
+    /// How long to wait for a connection with spare capacity.
+    connection_acquire_timeout: Duration,
+
+    /// The maximum number of in-flight operations per connection.
+    #[metric(help = "The maximum number of in-flight operations per redis connection")]
+    max_in_flight_per_connection: usize,
+
     /// A channel to publish updates to when a key is added, removed, or modified.
     #[metric(
         help = "The pubsub channel to publish updates to when a key is added, removed, or modified"
@@ -129,11 +181,35 @@ pub struct RedisStore {
     #[metric(help = "The amount of data to read from Redis at a time")]
     read_chunk_size: usize,
 
+    /// The maximum number of `read_chunk_size` reads outstanding per get.
+    #[metric(help = "The maximum number of chunk reads outstanding per get")]
+    max_chunk_reads_per_get: usize,
+
     /// The maximum number of chunk uploads per update.
     /// This is used to limit the number of chunk uploads per update to prevent
     #[metric(help = "The maximum number of chunk uploads per update")]
     max_chunk_uploads_per_update: usize,
 
+    /// The maximum amount of data written to Redis in a single command
+    /// during an update. Larger chunks are split before being sent.
+    #[metric(help = "The maximum amount of data written to Redis in a single command")]
+    upload_chunk_size: usize,
+
+    /// If non-zero, blobs are stored across keys of at most this size and
+    /// the key of the blob holds a [`ChunkedBlobMetadata`] record.
+    #[metric(help = "The maximum size of a single redis key holding blob data, 0 if unchunked")]
+    storage_chunk_size: usize,
+
+    /// Expiry in seconds set on keys when they are written, read or
+    /// checked for existence. Zero means keys never expire.
+    #[metric(help = "The number of seconds until an untouched key expires")]
+    key_ttl_s: i64,
+
+    /// Expiry in seconds of temporary keys used while an upload is in
+    /// progress. Zero means temporary keys never expire.
+    #[metric(help = "The number of seconds until an incomplete upload expires")]
+    temp_key_ttl_s: i64,
+
     /// Redis script used to update a value in redis if the version matches.
     /// This is done by incrementing the version number and then setting the new data
     /// only if the version number matches the existing version number.
@@ -141,6 +217,12 @@ pub struct RedisStore {
 
     /// A manager for subscriptions to keys in Redis.
     subscription_manager: Mutex<Option<Arc<RedisSubscriptionManager>>>,
+
+    /// In-process cache of small values, if client-side caching is enabled.
+    client_side_cache: Option<Arc<ClientSideCache>>,
+
+    /// Tasks keeping `CLIENT TRACKING` enabled on each pooled connection.
+    _client_tracking_spawns: Vec<JoinHandleDropGuard<()>>,
 }
 
 impl RedisStore {
@@ -152,20 +234,29 @@ impl RedisStore {
                 "No addresses were specified in redis store configuration."
             ));
         };
-        let [addr] = spec.addresses.as_slice() else {
-            return Err(make_err!(Code::Unimplemented, "Connecting directly to multiple redis nodes in a cluster is currently unsupported. Please specify a single URL to a single node, and nativelink will use cluster discover to find the other nodes."));
+        let mut redis_config = if spec.mode == RedisMode::Sentinel {
+            sentinel_config(&spec.addresses, spec.sentinel_master_name.as_deref())?
+        } else {
+            let [addr] = spec.addresses.as_slice() else {
+                return Err(make_err!(Code::Unimplemented, "Connecting directly to multiple redis nodes in a cluster is currently unsupported. Please specify a single URL to a single node, and nativelink will use cluster discover to find the other nodes."));
+            };
+            match spec.mode {
+                RedisMode::Cluster => RedisConfig::from_url_clustered(addr),
+                _ => RedisConfig::from_url_centralized(addr),
+            }
+            .err_tip_with_code(|e| {
+                (
+                    Code::InvalidArgument,
+                    format!("while parsing redis node address: {e}"),
+                )
+            })?
         };
-        let redis_config = match spec.mode {
-            RedisMode::Cluster => RedisConfig::from_url_clustered(addr),
-            RedisMode::Sentinel => RedisConfig::from_url_sentinel(addr),
-            RedisMode::Standard => RedisConfig::from_url_centralized(addr),
+        apply_auth_and_tls(&mut redis_config, &spec)?;
+        if spec.client_side_cache_max_entries != 0 {
+            // Invalidation messages for tracked keys are pushed on the same
+            // connection, which is only possible with RESP3.
+            redis_config.version = RespVersion::RESP3;
         }
-        .err_tip_with_code(|e| {
-            (
-                Code::InvalidArgument,
-                format!("while parsing redis node address: {e}"),
-            )
-        })?;
 
         let reconnect_policy = {
             if spec.retry.delay == 0.0 {
@@ -207,13 +298,52 @@ impl RedisStore {
             if spec.read_chunk_size == 0 {
                 spec.read_chunk_size = DEFAULT_READ_CHUNK_SIZE;
             }
+            if spec.max_chunk_reads_per_get == 0 {
+                spec.max_chunk_reads_per_get = DEFAULT_MAX_CHUNK_READS_PER_GET;
+            }
             if spec.max_chunk_uploads_per_update == 0 {
                 spec.max_chunk_uploads_per_update = DEFAULT_MAX_CHUNK_UPLOADS_PER_UPDATE;
             }
+            if spec.upload_chunk_size == 0 {
+                spec.upload_chunk_size = DEFAULT_UPLOAD_CHUNK_SIZE;
+            }
+            if spec.connection_acquire_timeout_ms == 0 {
+                spec.connection_acquire_timeout_ms = DEFAULT_CONNECTION_ACQUIRE_TIMEOUT_MS;
+            }
+            if spec.client_side_cache_max_value_size == 0 {
+                spec.client_side_cache_max_value_size = DEFAULT_CLIENT_SIDE_CACHE_MAX_VALUE_SIZE;
+            }
+        }
+        if spec.storage_chunk_size > MAX_REDIS_STRING_SIZE {
+            return Err(make_input_err!(
+                "storage_chunk_size must not be larger than {MAX_REDIS_STRING_SIZE} in redis store configuration, got {}",
+                spec.storage_chunk_size
+            ));
         }
         let connection_timeout = Duration::from_millis(spec.connection_timeout_ms);
         let command_timeout = Duration::from_millis(spec.command_timeout_ms);
 
+        let mut connection_config = ConnectionConfig {
+            connection_timeout,
+            internal_command_timeout: command_timeout,
+            unresponsive: UnresponsiveConfig {
+                max_timeout: Some(connection_timeout),
+                // This number needs to be less than the connection timeout.
+                // We use 4 as it is a good balance between not spamming the server
+                // and not waiting too long.
+                interval: connection_timeout / 4,
+            },
+            ..Default::default()
+        };
+        if spec.mode == RedisMode::Sentinel {
+            // During a failover the old master is demoted (READONLY) or reports
+            // MASTERDOWN. Reconnecting makes the client ask the sentinels for
+            // the newly promoted master.
+            connection_config
+                .reconnect_errors
+                .extend([ReconnectError::ReadOnly, ReconnectError::MasterDown]);
+        }
+
         let mut builder = Builder::from_config(redis_config);
         builder
             .set_performance_config(PerformanceConfig {
@@ -221,18 +351,7 @@ impl RedisStore {
                 broadcast_channel_capacity: spec.broadcast_channel_capacity,
                 ..Default::default()
             })
-            .set_connection_config(ConnectionConfig {
-                connection_timeout,
-                internal_command_timeout: command_timeout,
-                unresponsive: UnresponsiveConfig {
-                    max_timeout: Some(connection_timeout),
-                    // This number needs to be less than the connection timeout.
-                    // We use 4 as it is a good balance between not spamming the server
-                    // and not waiting too long.
-                    interval: connection_timeout / 4,
-                },
-                ..Default::default()
-            })
+            .set_connection_config(connection_config)
             .set_policy(reconnect_policy);
 
         let client_pool = builder
@@ -250,12 +369,26 @@ impl RedisStore {
             || Uuid::new_v4().to_string(),
             spec.key_prefix.clone(),
             spec.read_chunk_size,
+            spec.max_chunk_reads_per_get,
             spec.max_chunk_uploads_per_update,
+            spec.upload_chunk_size,
+            spec.storage_chunk_size,
+            spec.max_in_flight_per_connection,
+            Duration::from_millis(spec.connection_acquire_timeout_ms),
+            i64::from(spec.key_ttl_seconds),
+            i64::from(spec.temp_key_ttl_seconds),
         )
+        .map(|store| {
+            store.with_client_side_cache(
+                spec.client_side_cache_max_entries,
+                spec.client_side_cache_max_value_size,
+            )
+        })
         .map(Arc::new)
     }
 
     /// Used for testing when determinism is required.
+    #[expect(clippy::too_many_arguments)]
     pub fn new_from_builder_and_parts(
         client_pool: RedisPool,
         subscriber_client: SubscriberClient,
@@ -263,23 +396,115 @@ impl RedisStore {
         temp_name_generator_fn: fn() -> String,
         key_prefix: String,
         read_chunk_size: usize,
+        max_chunk_reads_per_get: usize,
         max_chunk_uploads_per_update: usize,
+        upload_chunk_size: usize,
+        storage_chunk_size: usize,
+        max_in_flight_per_connection: usize,
+        connection_acquire_timeout: Duration,
+        key_ttl_s: i64,
+        temp_key_ttl_s: i64,
     ) -> Result<Self, Error> {
         // Start connection pool (this will retry forever by default).
         client_pool.connect();
         subscriber_client.connect();
 
+        let in_flight_limits = if max_in_flight_per_connection == 0 {
+            Vec::new()
+        } else {
+            client_pool
+                .clients()
+                .iter()
+                .map(|_| Semaphore::new(max_in_flight_per_connection))
+                .collect()
+        };
+
         Ok(Self {
             client_pool,
+            in_flight_limits,
+            next_client_index: AtomicUsize::new(0),
+            connection_acquire_timeout,
+            max_in_flight_per_connection,
             pub_sub_channel,
             subscriber_client,
             fingerprint_create_index: fingerprint_create_index_template(),
             temp_name_generator_fn,
             key_prefix,
             read_chunk_size,
+            max_chunk_reads_per_get,
             max_chunk_uploads_per_update,
+            upload_chunk_size,
+            storage_chunk_size,
+            key_ttl_s,
+            temp_key_ttl_s,
             update_if_version_matches_script: Script::from_lua(LUA_VERSION_SET_SCRIPT),
             subscription_manager: Mutex::new(None),
+            client_side_cache: None,
+            _client_tracking_spawns: Vec::new(),
+        })
+    }
+
+    /// Enable the client-side cache holding up to `max_entries` values of at
+    /// most `max_value_size` bytes. Does nothing if `max_entries` is zero.
+    /// The clients must speak RESP3.
+    #[must_use]
+    pub fn with_client_side_cache(mut self, max_entries: usize, max_value_size: usize) -> Self {
+        let Some(max_entries) = NonZeroUsize::new(max_entries) else {
+            return self;
+        };
+        let clients = self.client_pool.clients();
+        let cache = Arc::new(ClientSideCache::new(
+            max_entries,
+            max_value_size,
+            clients.len(),
+        ));
+        self._client_tracking_spawns = clients
+            .iter()
+            .map(|client| spawn_client_tracking(client.clone(), Arc::downgrade(&cache)))
+            .collect();
+        self.client_side_cache = Some(cache);
+        self
+    }
+
+    /// Check out a client from the pool. If `max_in_flight_per_connection`
+    /// is set, a connection with spare capacity is preferred and the
+    /// returned [`PooledClient`] holds a slot on it until dropped.
+    async fn get_client(&self) -> Result<PooledClient<'_>, Error> {
+        if self.in_flight_limits.is_empty() {
+            return Ok(PooledClient {
+                client: self.client_pool.next(),
+                _permit: None,
+            });
+        }
+        let clients = self.client_pool.clients();
+        let start = self.next_client_index.fetch_add(1, Ordering::Relaxed);
+        for i in 0..clients.len() {
+            let index = (start + i) % clients.len();
+            if let Ok(permit) = self.in_flight_limits[index].try_acquire() {
+                return Ok(PooledClient {
+                    client: &clients[index],
+                    _permit: Some(permit),
+                });
+            }
+        }
+        // Every connection is busy, so wait on the one that is next in line.
+        let index = start % clients.len();
+        let permit = timeout(
+            self.connection_acquire_timeout,
+            self.in_flight_limits[index].acquire(),
+        )
+        .await
+        .map_err(|_| {
+            make_err!(
+                Code::ResourceExhausted,
+                "Timed out after {:?} waiting for a redis connection in RedisStore",
+                self.connection_acquire_timeout
+            )
+        })?
+        .map_err(|e| make_err!(Code::Internal, "Redis connection semaphore closed: {e:?}"))?;
+        Ok(PooledClient {
+            client: &clients[index],
+            _permit: Some(permit),
         })
     }
 
@@ -305,6 +530,661 @@ impl RedisStore {
             }
         }
     }
+
+    /// Refresh the expiry of a chunked blob's metadata and chunk keys.
+    async fn refresh_chunked_expiry(
+        &self,
+        client: &RedisClient,
+        final_key: &str,
+        metadata: &ChunkedBlobMetadata,
+    ) -> Result<(), Error> {
+        if self.key_ttl_s == 0 {
+            return Ok(());
+        }
+        for key in iter::once(final_key.to_string()).chain(metadata.chunk_keys(final_key)) {
+            client
+                .expire::<(), _>(&key, self.key_ttl_s, None)
+                .await
+                .err_tip(|| format!("While refreshing expiry of {key} in RedisStore"))?;
+        }
+        Ok(())
+    }
+
+    /// Look up the size of a blob stored in the chunked layout.
+    async fn has_chunked(
+        &self,
+        client: &RedisClient,
+        final_key: &str,
+    ) -> Result<Option<u64>, Error> {
+        let Some(data) = client
+            .get::<Option<Bytes>, _>(final_key)
+            .await
+            .err_tip(|| format!("In RedisStore::has_chunked for {final_key}"))?
+        else {
+            return Ok(None);
+        };
+        let metadata = ChunkedBlobMetadata::decode(&data)
+            .err_tip(|| format!("In RedisStore::has_chunked for {final_key}"))?;
+        self.refresh_chunked_expiry(client, final_key, &metadata)
+            .await?;
+        Ok(Some(metadata.total_len))
+    }
+
+    /// Upload a blob in the chunked layout. The chunks are written under a
+    /// fresh upload id, so the previous version of the blob stays readable
+    /// until the metadata is swapped to point to the new chunks.
+    async fn update_chunked(
+        &self,
+        client: &RedisClient,
+        key: &StoreKey<'_>,
+        final_key: &str,
+        reader: DropCloserReadHalf,
+    ) -> Result<(), Error> {
+        let upload_id = (self.temp_name_generator_fn)();
+        let chunk_size = self.storage_chunk_size as u64;
+        let bytes_received = AtomicU64::new(0);
+
+        let upload_result = async {
+            let upload_chunk_size = self.upload_chunk_size;
+            let bytes_received = &bytes_received;
+            let upload_id = upload_id.as_str();
+            let mut write_stream = reader
+                .flat_map(move |chunk_res| {
+                    stream::iter(match chunk_res {
+                        Ok(chunk) => {
+                            let offset =
+                                bytes_received.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+                            split_at_boundaries(offset, chunk, upload_chunk_size, chunk_size)
+                                .into_iter()
+                                .map(Ok)
+                                .collect()
+                        }
+                        Err(e) => vec![Err(e)],
+                    })
+                })
+                .map(|res| {
+                    let (offset, piece) =
+                        res.err_tip(|| "Failed to read chunk in update in redis store")?;
+                    let chunk_key =
+                        ChunkedBlobMetadata::chunk_key_for(final_key, upload_id, offset / chunk_size);
+                    let chunk_offset = u32::try_from(offset % chunk_size)
+                        .err_tip(|| "Could not convert chunk offset to u32 in RedisStore::update")?;
+                    Ok(async move {
+                        if chunk_offset != 0 || self.temp_key_ttl_s == 0 {
+                            return client
+                                .setrange::<(), _, _>(&chunk_key, chunk_offset, piece)
+                                .await
+                                .err_tip(|| {
+                                    format!("While writing {chunk_key} in RedisStore::update")
+                                });
+                        }
+                        // Pieces of a chunk are written concurrently, so the first one
+                        // must not replace the chunk like SET would if it reaches redis
+                        // after a later piece. The expiry is sent in the same pipeline
+                        // and cleans up incomplete uploads.
+                        let pipeline = client.pipeline();
+                        pipeline
+                            .setrange::<(), _, _>(&chunk_key, chunk_offset, piece)
+                            .await
+                            .err_tip(|| "While queueing setrange in RedisStore::update")?;
+                        pipeline
+                            .expire::<(), _>(&chunk_key, self.temp_key_ttl_s, None)
+                            .await
+                            .err_tip(|| "While queueing expire in RedisStore::update")?;
+                        pipeline
+                            .all::<()>()
+                            .await
+                            .err_tip(|| format!("While writing {chunk_key} in RedisStore::update"))
+                    })
+                })
+                .try_buffer_unordered(self.max_chunk_uploads_per_update);
+            while write_stream.try_next().await?.is_some() {}
+            drop(write_stream);
+
+            let metadata = ChunkedBlobMetadata {
+                upload_id: upload_id.to_string(),
+                total_len: bytes_received.load(Ordering::Relaxed),
+                chunk_size,
+            };
+            for (index, chunk_key) in metadata.chunk_keys(final_key).into_iter().enumerate() {
+                let expected_len =
+                    cmp::min(chunk_size, metadata.total_len - index as u64 * chunk_size);
+                let chunk_len = client
+                    .strlen::<u64, _>(&chunk_key)
+                    .await
+                    .err_tip(|| format!("In RedisStore::update strlen check for {chunk_key}"))?;
+                // See the length check of the unchunked layout.
+                if chunk_len != expected_len {
+                    return Err(make_input_err!(
+                        "Data length mismatch in RedisStore::update for {}({}) - expected {} bytes, got {} bytes",
+                        key.as_str(),
+                        chunk_key,
+                        expected_len,
+                        chunk_len,
+                    ));
+                }
+                if self.key_ttl_s != 0 {
+                    client
+                        .expire::<(), _>(&chunk_key, self.key_ttl_s, None)
+                        .await
+                        .err_tip(|| "While setting chunk expiry in RedisStore::update")?;
+                } else if self.temp_key_ttl_s != 0 {
+                    client
+                        .persist::<(), _>(&chunk_key)
+                        .await
+                        .err_tip(|| "While removing chunk expiry in RedisStore::update")?;
+                }
+            }
+
+            // Point the key at the new chunks. Readers see either the old or
+            // the new version of the blob.
+            let expiration = (self.key_ttl_s != 0).then_some(Expiration::EX(self.key_ttl_s));
+            client
+                .set::<Option<Bytes>, _, _>(final_key, metadata.encode(), expiration, None, true)
+                .await
+                .err_tip(|| "While writing chunked metadata in RedisStore::update")
+        }
+        .await;
+        let previous_metadata = match upload_result {
+            Ok(previous_metadata) => previous_metadata,
+            Err(err) => {
+                // Don't leave the partially written chunks behind.
+                let metadata = ChunkedBlobMetadata {
+                    upload_id,
+                    total_len: bytes_received.load(Ordering::Relaxed),
+                    chunk_size,
+                };
+                let chunk_keys = metadata.chunk_keys(final_key);
+                if !chunk_keys.is_empty() {
+                    if let Err(del_err) = client.del::<(), _>(chunk_keys).await {
+                        event!(
+                            Level::WARN,
+                            "Failed to delete chunks of {final_key} after failed upload in RedisStore::update - {del_err:?}"
+                        );
+                    }
+                }
+                return Err(err);
+            }
+        };
+
+        // The chunks of the previous version are no longer referenced.
+        if let Some(previous_metadata) = previous_metadata {
+            match ChunkedBlobMetadata::decode(&previous_metadata) {
+                Ok(previous) if previous.upload_id != upload_id => {
+                    let chunk_keys = previous.chunk_keys(final_key);
+                    if !chunk_keys.is_empty() {
+                        if let Err(del_err) = client.del::<(), _>(chunk_keys).await {
+                            event!(
+                                Level::WARN,
+                                "Failed to delete previous chunks of {final_key} in RedisStore::update - {del_err:?}"
+                            );
+                        }
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => event!(
+                    Level::WARN,
+                    "Replaced unreadable metadata of {final_key} in RedisStore::update - {err:?}"
+                ),
+            }
+        }
+
+        // If we have a publish channel configured, send a notice that the key has been set.
+        if let Some(pub_sub_channel) = &self.pub_sub_channel {
+            return Ok(client.publish(pub_sub_channel, final_key).await?);
+        };
+
+        Ok(())
+    }
+
+    /// Read a range of a blob stored in the chunked layout.
+    async fn get_part_chunked(
+        &self,
+        client: &RedisClient,
+        final_key: &str,
+        writer: &mut DropCloserWriteHalf,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<(), Error> {
+        let Some(data) = client
+            .get::<Option<Bytes>, _>(final_key)
+            .await
+            .err_tip(|| "In RedisStore::get_part_chunked::get")?
+        else {
+            return Err(make_err!(
+                Code::NotFound,
+                "Data not found in Redis store for key: {final_key}"
+            ));
+        };
+        let metadata = ChunkedBlobMetadata::decode(&data)
+            .err_tip(|| format!("In RedisStore::get_part_chunked for {final_key}"))?;
+        let chunk_size = metadata.chunk_size;
+
+        // Reads never cross a chunk boundary and are at most `read_chunk_size`
+        // bytes. The ranges are exclusive at the end.
+        let data_start = cmp::min(offset, metadata.total_len);
+        let data_end = length.map_or(metadata.total_len, |length| {
+            cmp::min(offset.saturating_add(length), metadata.total_len)
+        });
+        let read_chunk_size = self.read_chunk_size as u64;
+        let next_read = move |start: u64| {
+            let chunk_end = (start / chunk_size + 1) * chunk_size;
+            (
+                start,
+                cmp::min(
+                    cmp::min(start.saturating_add(read_chunk_size), chunk_end),
+                    data_end,
+                ),
+            )
+        };
+        let reads = iter::successors(
+            (data_start < data_end).then(|| next_read(data_start)),
+            move |&(_, prev_end)| (prev_end < data_end).then(|| next_read(prev_end)),
+        );
+
+        let metadata = &metadata;
+        let mut chunks = stream::iter(reads)
+            .map(|(start, end)| async move {
+                let chunk_key = metadata.chunk_key(final_key, start / chunk_size);
+                let range_start = usize::try_from(start % chunk_size)
+                    .err_tip(|| "Could not convert range start to usize")?;
+                let range_end = usize::try_from((end - 1) % chunk_size)
+                    .err_tip(|| "Could not convert range end to usize")?;
+                let chunk = client
+                    .getrange::<Bytes, _>(&chunk_key, range_start, range_end)
+                    .await
+                    .err_tip(|| "In RedisStore::get_part_chunked::getrange")?;
+                if chunk.len() as u64 != end - start {
+                    // The chunk expired or was evicted independently of the metadata.
+                    return Err(make_err!(
+                        Code::NotFound,
+                        "Chunk {chunk_key} of {final_key} is missing or truncated in Redis store"
+                    ));
+                }
+                Ok::<_, Error>(chunk)
+            })
+            .buffered(self.max_chunk_reads_per_get);
+        while let Some(chunk) = chunks.try_next().await? {
+            writer
+                .send(chunk)
+                .await
+                .err_tip(|| "Failed to write data in RedisStore::get_part_chunked")?;
+        }
+        drop(chunks);
+
+        self.refresh_chunked_expiry(client, final_key, metadata)
+            .await?;
+
+        writer
+            .send_eof()
+            .err_tip(|| "Failed to write EOF in redis store get_part_chunked")
+    }
+}
+
+/// Escape the characters that have a special meaning in SCAN's glob-style
+/// MATCH patterns.
+fn escape_glob_pattern(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Whether `key` is a temp key of an upload in progress or a chunk of a
+/// chunked blob, rather than a key of an object.
+fn is_internal_key(key: &str) -> bool {
+    key.starts_with("temp-") || (key.starts_with('{') && key.contains("}:"))
+}
+
+/// Split `chunk` into pieces of at most `max_len` bytes without copying.
+fn split_chunk(mut chunk: Bytes, max_len: usize) -> Vec<Bytes> {
+    if chunk.len() <= max_len {
+        return vec![chunk];
+    }
+    let mut pieces = Vec::with_capacity(chunk.len().div_ceil(max_len));
+    while !chunk.is_empty() {
+        pieces.push(chunk.split_to(cmp::min(max_len, chunk.len())));
+    }
+    pieces
+}
+
+/// Split `chunk`, which starts at `offset` within a blob, into pieces of at
+/// most `max_len` bytes that never cross a multiple of `boundary`. Each
+/// piece is returned with its offset within the blob.
+fn split_at_boundaries(
+    mut offset: u64,
+    mut chunk: Bytes,
+    max_len: usize,
+    boundary: u64,
+) -> Vec<(u64, Bytes)> {
+    let mut pieces = Vec::new();
+    while !chunk.is_empty() {
+        let until_boundary = usize::try_from(boundary - offset % boundary).unwrap_or(usize::MAX);
+        let piece = chunk.split_to(cmp::min(cmp::min(max_len, until_boundary), chunk.len()));
+        let piece_offset = offset;
+        offset += piece.len() as u64;
+        pieces.push((piece_offset, piece));
+    }
+    pieces
+}
+
+/// The value stored under the key of a blob in the chunked layout (see
+/// `storage_chunk_size`). The data itself lives in `chunk_size` sized keys
+/// named by [`ChunkedBlobMetadata::chunk_key`].
+#[derive(Debug, PartialEq, Eq)]
+struct ChunkedBlobMetadata {
+    upload_id: String,
+    total_len: u64,
+    chunk_size: u64,
+}
+
+impl ChunkedBlobMetadata {
+    /// The key chunk `index` of upload `upload_id` of `final_key` is stored
+    /// under. Like temp keys, the braces make sure every chunk lives on the
+    /// same cluster node as `final_key`.
+    fn chunk_key_for(final_key: &str, upload_id: &str, index: u64) -> String {
+        format!("{{{final_key}}}:{upload_id}:{index}")
+    }
+
+    fn chunk_key(&self, final_key: &str, index: u64) -> String {
+        Self::chunk_key_for(final_key, &self.upload_id, index)
+    }
+
+    fn chunk_keys(&self, final_key: &str) -> Vec<String> {
+        (0..self.total_len.div_ceil(self.chunk_size))
+            .map(|index| self.chunk_key(final_key, index))
+            .collect()
+    }
+
+    fn encode(&self) -> String {
+        format!(
+            "{CHUNKED_METADATA_MAGIC} {} {} {}",
+            self.upload_id, self.total_len, self.chunk_size
+        )
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, Error> {
+        let malformed = || make_err!(Code::DataLoss, "Malformed chunked blob metadata: {data:?}");
+        let data = std::str::from_utf8(data).map_err(|_| malformed())?;
+        let mut parts = data.split(' ');
+        let (
+            Some(CHUNKED_METADATA_MAGIC),
+            Some(upload_id),
+            Some(total_len),
+            Some(chunk_size),
+            None,
+        ) = (
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+        )
+        else {
+            return Err(malformed());
+        };
+        let total_len = total_len.parse::<u64>().map_err(|_| malformed())?;
+        let chunk_size = chunk_size.parse::<u64>().map_err(|_| malformed())?;
+        if chunk_size == 0 {
+            return Err(malformed());
+        }
+        Ok(Self {
+            upload_id: upload_id.to_string(),
+            total_len,
+            chunk_size,
+        })
+    }
+}
+
+/// A client checked out of the pool by [`RedisStore::get_client`].
+struct PooledClient<'a> {
+    client: &'a RedisClient,
+    _permit: Option<SemaphorePermit<'a>>,
+}
+
+impl Deref for PooledClient<'_> {
+    type Target = RedisClient;
+
+    fn deref(&self) -> &Self::Target {
+        self.client
+    }
+}
+
+/// An in-process cache of small values kept coherent with redis using
+/// RESP3 client-side caching (`CLIENT TRACKING`). Redis remembers which keys
+/// each tracking connection has read and pushes an invalidation message
+/// when one of them changes.
+struct ClientSideCache {
+    state: Mutex<ClientSideCacheState>,
+    max_value_size: usize,
+    /// The number of pooled connections, all of which must be tracking for
+    /// newly read values to be cached.
+    connection_count: usize,
+}
+
+struct ClientSideCacheState {
+    entries: LruCache<String, Bytes>,
+    /// Bumped on every invalidation, so a read that raced with an
+    /// invalidation doesn't insert a stale value.
+    epoch: u64,
+    /// The number of pooled connections that currently have tracking enabled.
+    tracking_connections: usize,
+}
+
+impl ClientSideCache {
+    fn new(max_entries: NonZeroUsize, max_value_size: usize, connection_count: usize) -> Self {
+        Self {
+            state: Mutex::new(ClientSideCacheState {
+                entries: LruCache::new(max_entries),
+                epoch: 0,
+                tracking_connections: 0,
+            }),
+            max_value_size,
+            connection_count,
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<Bytes> {
+        self.state.lock().entries.get(key).cloned()
+    }
+
+    /// The epoch to pass to [`ClientSideCache::insert`] for a value that
+    /// is about to be read.
+    fn epoch(&self) -> u64 {
+        self.state.lock().epoch
+    }
+
+    fn insert(&self, key: &str, value: Bytes, epoch: u64) {
+        if value.len() > self.max_value_size {
+            return;
+        }
+        let mut state = self.state.lock();
+        if state.epoch != epoch || state.tracking_connections != self.connection_count {
+            return;
+        }
+        state.entries.put(key.to_string(), value);
+    }
+
+    /// Drop `keys` from the cache. An empty list means every key was
+    /// invalidated (ie: after `FLUSHALL`).
+    fn invalidate(&self, keys: &[RedisKey]) {
+        let mut state = self.state.lock();
+        state.epoch += 1;
+        if keys.is_empty() {
+            state.entries.clear();
+            return;
+        }
+        for key in keys {
+            if let Some(key) = key.as_str() {
+                state.entries.pop(key);
+            }
+        }
+    }
+
+    fn clear(&self) {
+        self.invalidate(&[]);
+    }
+
+    fn tracking_started(&self) {
+        self.state.lock().tracking_connections += 1;
+    }
+
+    /// Invalidations may have been missed, so nothing cached can be trusted.
+    fn tracking_stopped(&self) {
+        let mut state = self.state.lock();
+        state.tracking_connections -= 1;
+        state.epoch += 1;
+        state.entries.clear();
+    }
+}
+
+/// Keep `CLIENT TRACKING` enabled on `client` and forward its invalidation
+/// messages to `weak_cache`, re-enabling tracking after every reconnect.
+fn spawn_client_tracking(
+    client: RedisClient,
+    weak_cache: Weak<ClientSideCache>,
+) -> JoinHandleDropGuard<()> {
+    spawn!("redis_client_tracking_spawn", async move {
+        let mut invalidation_rx = client.invalidation_rx();
+        let mut reconnect_rx = client.reconnect_rx();
+        loop {
+            if let Err(e) = client
+                .start_tracking(Vec::<String>::new(), false, false, false, false)
+                .await
+            {
+                event!(
+                    Level::ERROR,
+                    "Error enabling redis client tracking, retrying - {e}"
+                );
+                sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+            let Some(cache) = weak_cache.upgrade() else {
+                return;
+            };
+            cache.tracking_started();
+            drop(cache);
+            loop {
+                select! {
+                    invalidation = invalidation_rx.recv() => {
+                        let Some(cache) = weak_cache.upgrade() else {
+                            return;
+                        };
+                        match invalidation {
+                            Ok(invalidation) => cache.invalidate(&invalidation.keys),
+                            Err(RecvError::Lagged(_)) => {
+                                event!(Level::WARN, "Redis invalidation messages were dropped, clearing client-side cache");
+                                cache.clear();
+                            }
+                            Err(RecvError::Closed) => return,
+                        }
+                    },
+                    _ = reconnect_rx.recv() => {
+                        event!(Level::WARN, "Redis reconnected, clearing client-side cache and re-enabling tracking");
+                        break;
+                    },
+                }
+            }
+            let Some(cache) = weak_cache.upgrade() else {
+                return;
+            };
+            cache.tracking_stopped();
+        }
+    })
+}
+
+/// Build the client configuration for a store running in sentinel mode.
+///
+/// A single address without `master_name` is parsed as a sentinel URL
+/// (ie: `redis-sentinel://host:port?sentinelServiceName=name`), otherwise
+/// every address is a sentinel endpoint watching `master_name`.
+fn sentinel_config(addresses: &[String], master_name: Option<&str>) -> Result<RedisConfig, Error> {
+    let parse_err = |e: &Error| {
+        (
+            Code::InvalidArgument,
+            format!("while parsing redis sentinel address: {e}"),
+        )
+    };
+    let Some(master_name) = master_name else {
+        let [addr] = addresses else {
+            return Err(make_input_err!(
+                "sentinel_master_name must be set when multiple sentinel addresses are given in redis store configuration."
+            ));
+        };
+        return RedisConfig::from_url_sentinel(addr).err_tip_with_code(parse_err);
+    };
+    let mut hosts = Vec::with_capacity(addresses.len());
+    let mut maybe_config: Option<RedisConfig> = None;
+    for addr in addresses {
+        let config = RedisConfig::from_url_centralized(addr).err_tip_with_code(parse_err)?;
+        let ServerConfig::Centralized { server } = &config.server else {
+            return Err(make_input_err!(
+                "Expected a single host in redis sentinel address {addr}"
+            ));
+        };
+        hosts.push(server.clone());
+        // Credentials and database are taken from the first address.
+        maybe_config.get_or_insert(config);
+    }
+    let mut config = maybe_config.err_tip(|| "No sentinel addresses in sentinel_config")?;
+    config.server = ServerConfig::Sentinel {
+        hosts,
+        service_name: master_name.to_string(),
+        username: config.username.clone(),
+        password: config.password.clone(),
+    };
+    Ok(config)
+}
+
+/// Apply the ACL credentials and TLS settings from `spec` to `config`,
+/// overriding anything parsed from the address URL.
+fn apply_auth_and_tls(config: &mut RedisConfig, spec: &RedisSpec) -> Result<(), Error> {
+    if let Some(username) = &spec.username {
+        config.username = Some(username.clone());
+    }
+    match (&spec.password, &spec.password_file) {
+        (Some(_), Some(_)) => {
+            return Err(make_input_err!(
+                "Only one of password and password_file may be set in redis store configuration."
+            ));
+        }
+        (Some(password), None) => config.password = Some(password.clone()),
+        (None, Some(password_file)) => {
+            let password = std::fs::read_to_string(password_file)
+                .err_tip(|| format!("Could not read redis password_file {password_file}"))?;
+            config.password = Some(password.trim().to_string());
+        }
+        (None, None) => {}
+    }
+
+    if let Some(ca_cert) = &spec.ca_cert {
+        let pem =
+            std::fs::read(ca_cert).err_tip(|| format!("Could not read redis ca_cert {ca_cert}"))?;
+        let mut root_store = RootCertStore::empty();
+        for cert in rustls_pemfile::certs(&mut pem.as_slice()) {
+            let cert =
+                cert.err_tip(|| format!("Could not parse certificate in redis ca_cert {ca_cert}"))?;
+            root_store.add(cert).map_err(|e| {
+                make_input_err!("Could not add certificate from redis ca_cert {ca_cert}: {e:?}")
+            })?;
+        }
+        let tls_config =
+            RustlsClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .map_err(|e| make_err!(Code::Internal, "Could not configure redis TLS: {e:?}"))?
+                .with_root_certificates(root_store)
+                .with_no_client_auth();
+        config.tls = Some(TlsConnector::from(tls_config).into());
+    } else if spec.tls && config.tls.is_none() {
+        let connector = TlsConnector::default_rustls()
+            .err_tip(|| "While loading system root certificates for redis TLS")?;
+        config.tls = Some(connector.into());
+    }
+    Ok(())
 }
 
 #[async_trait]
@@ -318,7 +1198,11 @@ impl StoreDriver for RedisStore {
         // difficult and it doesn't work very well in cluster mode.
         // If we wanted to optimize this with pipeline be careful to
         // implement retry and to support cluster mode.
-        let client = self.client_pool.next();
+        let pooled_client = self
+            .get_client()
+            .await
+            .err_tip(|| "While acquiring client in RedisStore::has_with_results")?;
+        let client: &RedisClient = &pooled_client;
         keys.iter()
             .zip(results.iter_mut())
             .map(|(key, result)| async move {
@@ -328,6 +1212,18 @@ impl StoreDriver for RedisStore {
                     return Ok::<_, Error>(());
                 }
                 let encoded_key = self.encode_key(key);
+                if self.storage_chunk_size != 0 {
+                    *result = self.has_chunked(client, encoded_key.as_ref()).await?;
+                    return Ok(());
+                }
+                if let Some(value) = self
+                    .client_side_cache
+                    .as_ref()
+                    .and_then(|cache| cache.get(&encoded_key))
+                {
+                    *result = Some(value.len() as u64);
+                    return Ok(());
+                }
                 let pipeline = client.pipeline();
                 pipeline
                     .strlen::<(), _>(encoded_key.as_ref())
@@ -345,10 +1241,25 @@ impl StoreDriver for RedisStore {
                     .err_tip(|| {
                         format!("In RedisStore::has_with_results::exists for {encoded_key}")
                     })?;
-                let (blob_len, exists) = pipeline
-                    .all::<(u64, bool)>()
-                    .await
-                    .err_tip(|| "In RedisStore::has_with_results::query")?;
+                let (blob_len, exists) = if self.key_ttl_s == 0 {
+                    pipeline
+                        .all::<(u64, bool)>()
+                        .await
+                        .err_tip(|| "In RedisStore::has_with_results::query")?
+                } else {
+                    // Refresh the expiry, as the caller is likely about to use the key.
+                    pipeline
+                        .expire::<(), _>(encoded_key.as_ref(), self.key_ttl_s, None)
+                        .await
+                        .err_tip(|| {
+                            format!("In RedisStore::has_with_results::expire for {encoded_key}")
+                        })?;
+                    let (blob_len, exists, _) = pipeline
+                        .all::<(u64, bool, bool)>()
+                        .await
+                        .err_tip(|| "In RedisStore::has_with_results::query")?;
+                    (blob_len, exists)
+                };
 
                 *result = if exists { Some(blob_len) } else { None };
 
@@ -396,69 +1307,137 @@ impl StoreDriver for RedisStore {
             }
         }
 
-        let client = self.client_pool.next();
+        let pooled_client = self
+            .get_client()
+            .await
+            .err_tip(|| "While acquiring client in RedisStore::update")?;
+        let client: &RedisClient = &pooled_client;
 
-        let mut read_stream = reader
-            .scan(0u32, |bytes_read, chunk_res| {
-                future::ready(Some(
-                    chunk_res
-                        .err_tip(|| "Failed to read chunk in update in redis store")
-                        .and_then(|chunk| {
-                            let offset = *bytes_read;
-                            let chunk_len = u32::try_from(chunk.len()).err_tip(|| {
-                                "Could not convert chunk length to u32 in RedisStore::update"
+        if self.storage_chunk_size != 0 {
+            return self
+                .update_chunked(client, &key, final_key.as_ref(), reader)
+                .await;
+        }
+
+        let upload_result = async {
+            if self.temp_key_ttl_s != 0 {
+                // Create the temp key up front with an expiry, so it gets cleaned up
+                // if this upload never completes. SETRANGE keeps the expiry.
+                client
+                    .set::<(), _, _>(
+                        &temp_key,
+                        Bytes::new(),
+                        Some(Expiration::EX(self.temp_key_ttl_s)),
+                        None,
+                        false,
+                    )
+                    .await
+                    .err_tip(|| "While creating temp key in RedisStore::update")?;
+            }
+
+            let upload_chunk_size = self.upload_chunk_size;
+            let mut read_stream = reader
+                .flat_map(move |chunk_res| {
+                    // Split up large chunks so no single command holds more than
+                    // `upload_chunk_size` bytes.
+                    stream::iter(match chunk_res {
+                        Ok(chunk) => split_chunk(chunk, upload_chunk_size)
+                            .into_iter()
+                            .map(Ok)
+                            .collect(),
+                        Err(e) => vec![Err(e)],
+                    })
+                })
+                .scan(0u32, |bytes_read, chunk_res| {
+                    future::ready(Some(
+                        chunk_res
+                            .err_tip(|| "Failed to read chunk in update in redis store")
+                            .and_then(|chunk| {
+                                let offset = *bytes_read;
+                                let chunk_len = u32::try_from(chunk.len()).err_tip(|| {
+                                    "Could not convert chunk length to u32 in RedisStore::update"
+                                })?;
+                                let new_bytes_read = bytes_read
+                                    .checked_add(chunk_len)
+                                    .err_tip(|| "Overflow protection in RedisStore::update")?;
+                                *bytes_read = new_bytes_read;
+                                Ok::<_, Error>((offset, *bytes_read, chunk))
+                            }),
+                    ))
+                })
+                .map(|res| {
+                    let (offset, end_pos, chunk) = res?;
+                    let temp_key_ref = &temp_key;
+                    Ok(async move {
+                        client
+                            .setrange::<(), _, _>(temp_key_ref, offset, chunk)
+                            .await
+                            .err_tip(|| {
+                                "While appending to append to temp key in RedisStore::update"
                             })?;
-                            let new_bytes_read = bytes_read
-                                .checked_add(chunk_len)
-                                .err_tip(|| "Overflow protection in RedisStore::update")?;
-                            *bytes_read = new_bytes_read;
-                            Ok::<_, Error>((offset, *bytes_read, chunk))
-                        }),
-                ))
-            })
-            .map(|res| {
-                let (offset, end_pos, chunk) = res?;
-                let temp_key_ref = &temp_key;
-                Ok(async move {
-                    client
-                        .setrange::<(), _, _>(temp_key_ref, offset, chunk)
-                        .await
-                        .err_tip(|| {
-                            "While appending to append to temp key in RedisStore::update"
-                        })?;
-                    Ok::<u32, Error>(end_pos)
+                        Ok::<u32, Error>(end_pos)
+                    })
                 })
-            })
-            .try_buffer_unordered(self.max_chunk_uploads_per_update);
+                .try_buffer_unordered(self.max_chunk_uploads_per_update);
 
-        let mut total_len: u32 = 0;
-        while let Some(last_pos) = read_stream.try_next().await? {
-            if last_pos > total_len {
-                total_len = last_pos;
+            let mut total_len: u32 = 0;
+            while let Some(last_pos) = read_stream.try_next().await? {
+                if last_pos > total_len {
+                    total_len = last_pos;
+                }
             }
-        }
 
-        let blob_len = client
-            .strlen::<u64, _>(&temp_key)
-            .await
-            .err_tip(|| format!("In RedisStore::update strlen check for {temp_key}"))?;
-        // This is a safety check to ensure that in the event some kind of retry was to happen
-        // and the data was appended to the key twice, we reject the data.
-        if blob_len != u64::from(total_len) {
-            return Err(make_input_err!(
-                "Data length mismatch in RedisStore::update for {}({}) - expected {} bytes, got {} bytes",
-                key.borrow().as_str(),
-                temp_key,
-                total_len,
-                blob_len,
-            ));
+            let blob_len = client
+                .strlen::<u64, _>(&temp_key)
+                .await
+                .err_tip(|| format!("In RedisStore::update strlen check for {temp_key}"))?;
+            // This is a safety check to ensure that in the event some kind of retry was to happen
+            // and the data was appended to the key twice, we reject the data.
+            if blob_len != u64::from(total_len) {
+                return Err(make_input_err!(
+                    "Data length mismatch in RedisStore::update for {}({}) - expected {} bytes, got {} bytes",
+                    key.borrow().as_str(),
+                    temp_key,
+                    total_len,
+                    blob_len,
+                ));
+            }
+
+            // Rename the temp key so that the data appears under the real key. Any data already present in the real key is lost.
+            client
+                .rename::<(), _, _>(&temp_key, final_key.as_ref())
+                .await
+                .err_tip(|| "While queueing key rename in RedisStore::update()")?;
+            // Don't wait for redis to tell us our own cached value is stale.
+            if let Some(cache) = &self.client_side_cache {
+                cache.invalidate(&[final_key.as_ref().into()]);
+            }
+            Ok::<_, Error>(())
+        }
+        .await;
+        if let Err(err) = upload_result {
+            // Don't leave the partially written data behind.
+            if let Err(del_err) = client.del::<(), _>(&temp_key).await {
+                event!(
+                    Level::WARN,
+                    "Failed to delete {temp_key} after failed upload in RedisStore::update - {del_err:?}"
+                );
+            }
+            return Err(err);
         }
 
-        // Rename the temp key so that the data appears under the real key. Any data already present in the real key is lost.
-        client
-            .rename::<(), _, _>(&temp_key, final_key.as_ref())
-            .await
-            .err_tip(|| "While queueing key rename in RedisStore::update()")?;
+        // The renamed key inherits the expiry of the temp key, so replace it.
+        if self.key_ttl_s != 0 {
+            client
+                .expire::<(), _>(final_key.as_ref(), self.key_ttl_s, None)
+                .await
+                .err_tip(|| "While setting key expiry in RedisStore::update")?;
+        } else if self.temp_key_ttl_s != 0 {
+            client
+                .persist::<(), _>(final_key.as_ref())
+                .await
+                .err_tip(|| "While removing key expiry in RedisStore::update")?;
+        }
 
         // If we have a publish channel configured, send a notice that the key has been set.
         if let Some(pub_sub_channel) = &self.pub_sub_channel {
@@ -468,6 +1447,123 @@ impl StoreDriver for RedisStore {
         Ok(())
     }
 
+    async fn list(
+        self: Pin<&Self>,
+        range: (Bound<StoreKey<'_>>, Bound<StoreKey<'_>>),
+        handler: &mut (dyn for<'a> FnMut(&'a StoreKey) -> bool + Send + Sync + '_),
+    ) -> Result<u64, Error> {
+        // SCAN returns the keys in no particular order, so every key under
+        // the key prefix is checked against the range. In cluster mode every
+        // node is scanned.
+        let pooled_client = self
+            .get_client()
+            .await
+            .err_tip(|| "While acquiring client in RedisStore::list")?;
+        let client: &RedisClient = &pooled_client;
+        let pattern = format!("{}*", escape_glob_pattern(&self.key_prefix));
+        let mut pages = if client.is_clustered() {
+            client
+                .scan_cluster(pattern, Some(LIST_SCAN_COUNT), None)
+                .boxed()
+        } else {
+            client.scan(pattern, Some(LIST_SCAN_COUNT), None).boxed()
+        };
+        let mut iterations = 0;
+        while let Some(page) = pages.next().await {
+            let mut page = page.err_tip(|| "While scanning keys in RedisStore::list")?;
+            for redis_key in page.take_results().unwrap_or_default() {
+                let Some(encoded_key) = redis_key.as_str() else {
+                    continue;
+                };
+                if is_internal_key(encoded_key) {
+                    continue;
+                }
+                let key = StoreKey::from_encoded_str(
+                    encoded_key
+                        .strip_prefix(&self.key_prefix)
+                        .unwrap_or(encoded_key),
+                );
+                if !range.contains(&key) {
+                    continue;
+                }
+                if !handler(&key) {
+                    return Ok(iterations);
+                }
+                iterations += 1;
+            }
+        }
+        Ok(iterations)
+    }
+
+    async fn update_many(
+        self: Pin<&Self>,
+        items: Vec<(StoreKey<'_>, Bytes)>,
+    ) -> Vec<Result<(), Error>> {
+        // Objects that are small enough to be written with a single SET are
+        // sent in one pipeline. SET replaces the value atomically, so no temp
+        // key is needed. Everything else takes the regular upload path.
+        if self.storage_chunk_size != 0 {
+            return slow_update_many(self, items).await;
+        }
+        let (pipelined_items, other_items): (Vec<_>, Vec<_>) =
+            items.into_iter().enumerate().partition(|(_, (key, data))| {
+                data.len() <= self.upload_chunk_size && !is_zero_digest(key.borrow())
+            });
+        let mut results = vec![Ok(()); pipelined_items.len() + other_items.len()];
+
+        let pipeline_result = async {
+            if pipelined_items.is_empty() {
+                return Ok(());
+            }
+            let pooled_client = self
+                .get_client()
+                .await
+                .err_tip(|| "While acquiring client in RedisStore::update_many")?;
+            let client: &RedisClient = &pooled_client;
+            let final_keys: Vec<_> = pipelined_items
+                .iter()
+                .map(|(_, (key, _))| self.encode_key(key).into_owned())
+                .collect();
+            let pipeline = client.pipeline();
+            for (final_key, (_, (_, data))) in final_keys.iter().zip(&pipelined_items) {
+                let expiration = (self.key_ttl_s != 0).then_some(Expiration::EX(self.key_ttl_s));
+                pipeline
+                    .set::<(), _, _>(final_key.as_str(), data.clone(), expiration, None, false)
+                    .await
+                    .err_tip(|| "While queueing set in RedisStore::update_many")?;
+                if let Some(pub_sub_channel) = &self.pub_sub_channel {
+                    pipeline
+                        .publish::<(), _, _>(pub_sub_channel, final_key.as_str())
+                        .await
+                        .err_tip(|| "While queueing publish in RedisStore::update_many")?;
+                }
+            }
+            pipeline
+                .all::<()>()
+                .await
+                .err_tip(|| "In RedisStore::update_many")?;
+            if let Some(cache) = &self.client_side_cache {
+                let cache_keys: Vec<RedisKey> =
+                    final_keys.iter().map(|key| key.as_str().into()).collect();
+                cache.invalidate(&cache_keys);
+            }
+            Ok::<_, Error>(())
+        };
+        let (other_indexes, other_items): (Vec<_>, Vec<_>) = other_items.into_iter().unzip();
+        let (pipeline_result, other_results) =
+            future::join(pipeline_result, slow_update_many(self, other_items)).await;
+
+        if let Err(err) = pipeline_result {
+            for (index, _) in &pipelined_items {
+                results[*index] = Err(err.clone());
+            }
+        }
+        for (index, result) in other_indexes.into_iter().zip(other_results) {
+            results[index] = result;
+        }
+        results
+    }
+
     async fn get_part(
         self: Pin<&Self>,
         key: StoreKey<'_>,
@@ -488,10 +1584,51 @@ impl StoreDriver for RedisStore {
                 .err_tip(|| "Failed to send zero EOF in redis store get_part");
         }
 
-        let client = self.client_pool.next();
+        let pooled_client = self
+            .get_client()
+            .await
+            .err_tip(|| "While acquiring client in RedisStore::get_part")?;
+        let client: &RedisClient = &pooled_client;
         let encoded_key = self.encode_key(&key);
         let encoded_key = encoded_key.as_ref();
 
+        if self.storage_chunk_size != 0 {
+            return self
+                .get_part_chunked(
+                    client,
+                    encoded_key,
+                    writer,
+                    offset as u64,
+                    length.map(|v| v as u64),
+                )
+                .await;
+        }
+
+        let cache_epoch = match &self.client_side_cache {
+            Some(cache) => {
+                if let Some(value) = cache.get(encoded_key) {
+                    let start = cmp::min(offset, value.len());
+                    let end = length.map_or(value.len(), |length| {
+                        cmp::min(start.saturating_add(length), value.len())
+                    });
+                    if start < end {
+                        writer
+                            .send(value.slice(start..end))
+                            .await
+                            .err_tip(|| "Failed to write cached data in RedisStore::get_part")?;
+                    }
+                    return writer
+                        .send_eof()
+                        .err_tip(|| "Failed to write EOF in redis store get_part");
+                }
+                Some(cache.epoch())
+            }
+            None => None,
+        };
+        // Only a value read from the start can be cached.
+        let mut cached_chunks = (offset == 0 && cache_epoch.is_some()).then(Vec::new);
+        let mut cached_len = 0;
+
         // N.B. the `-1`'s you see here are because redis GETRANGE is inclusive at both the start and end, so when we
         // do math with indices we change them to be exclusive at the end.
 
@@ -502,45 +1639,55 @@ impl StoreDriver for RedisStore {
             .saturating_sub(1);
 
         // And we don't ever want to read more than `read_chunk_size` bytes at a time, so we'll need to iterate.
-        let mut chunk_start = data_start;
-        let mut chunk_end = cmp::min(
-            data_start.saturating_add(self.read_chunk_size) - 1,
-            data_end,
+        let read_chunk_size = self.read_chunk_size;
+        let chunk_ranges = iter::successors(
+            Some((
+                data_start,
+                cmp::min(data_start.saturating_add(read_chunk_size) - 1, data_end),
+            )),
+            move |&(_, prev_chunk_end)| {
+                (prev_chunk_end < data_end).then(|| {
+                    let chunk_start = prev_chunk_end + 1;
+                    let chunk_end =
+                        cmp::min(chunk_start.saturating_add(read_chunk_size) - 1, data_end);
+                    (chunk_start, chunk_end)
+                })
+            },
         );
 
-        loop {
-            let chunk: Bytes = client
-                .getrange(encoded_key, chunk_start, chunk_end)
-                .await
-                .err_tip(|| "In RedisStore::get_part::getrange")?;
-
-            let didnt_receive_full_chunk = chunk.len() < self.read_chunk_size;
-            let reached_end_of_data = chunk_end == data_end;
-
-            if didnt_receive_full_chunk || reached_end_of_data {
-                if !chunk.is_empty() {
-                    writer
-                        .send(chunk)
-                        .await
-                        .err_tip(|| "Failed to write data in RedisStore::get_part")?;
+        // Keep up to `max_chunk_reads_per_get` reads outstanding so the round trips
+        // overlap. The reads are still written out in order.
+        let mut chunks = stream::iter(chunk_ranges)
+            .map(|(chunk_start, chunk_end)| async move {
+                client
+                    .getrange::<Bytes, _>(encoded_key, chunk_start, chunk_end)
+                    .await
+                    .err_tip(|| "In RedisStore::get_part::getrange")
+            })
+            .buffered(self.max_chunk_reads_per_get);
+
+        while let Some(chunk) = chunks.try_next().await? {
+            let didnt_receive_full_chunk = chunk.len() < read_chunk_size;
+            if let (Some(cache), Some(pieces)) = (&self.client_side_cache, &mut cached_chunks) {
+                cached_len += chunk.len();
+                if cached_len > cache.max_value_size {
+                    cached_chunks = None;
+                } else if !chunk.is_empty() {
+                    pieces.push(chunk.clone());
                 }
-
+            }
+            if !chunk.is_empty() {
+                writer
+                    .send(chunk)
+                    .await
+                    .err_tip(|| "Failed to write data in RedisStore::get_part")?;
+            }
+            if didnt_receive_full_chunk {
                 break; // No more data to read.
             }
-
-            // We received a full chunk's worth of data, so write it...
-            writer
-                .send(chunk)
-                .await
-                .err_tip(|| "Failed to write data in RedisStore::get_part")?;
-
-            // ...and go grab the next chunk.
-            chunk_start = chunk_end + 1;
-            chunk_end = cmp::min(
-                chunk_start.saturating_add(self.read_chunk_size) - 1,
-                data_end,
-            );
         }
+        // Cancel any reads past the end of the data.
+        drop(chunks);
 
         // If we didn't write any data, check if the key exists, if not return a NotFound error.
         // This is required by spec.
@@ -559,6 +1706,28 @@ impl StoreDriver for RedisStore {
             }
         }
 
+        if self.key_ttl_s != 0 {
+            client
+                .expire::<(), _>(encoded_key, self.key_ttl_s, None)
+                .await
+                .err_tip(|| "In RedisStore::get_part::expire")?;
+        }
+
+        // Receiving less than was asked for means we have seen the whole value.
+        let requested_len = data_end - data_start + 1;
+        if let (Some(cache), Some(cache_epoch), Some(pieces)) =
+            (&self.client_side_cache, cache_epoch, cached_chunks)
+        {
+            if cached_len > 0 && cached_len < requested_len {
+                let value = if let [piece] = pieces.as_slice() {
+                    piece.clone()
+                } else {
+                    Bytes::from(pieces.concat())
+                };
+                cache.insert(encoded_key, value, cache_epoch);
+            }
+        }
+
         writer
             .send_eof()
             .err_tip(|| "Failed to write EOF in redis store get_part")
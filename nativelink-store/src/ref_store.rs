@@ -13,10 +13,12 @@
 // limitations under the License.
 
 use std::cell::UnsafeCell;
+use std::ops::Bound;
 use std::pin::Pin;
 use std::sync::{Arc, Mutex, Weak};
 
 use async_trait::async_trait;
+use bytes::Bytes;
 use nativelink_config::stores::RefSpec;
 use nativelink_error::{make_err, make_input_err, Code, Error, ResultExt};
 use nativelink_metric::MetricsComponent;
@@ -108,6 +110,21 @@ impl StoreDriver for RefStore {
         self.get_store()?.has_with_results(keys, results).await
     }
 
+    async fn list(
+        self: Pin<&Self>,
+        range: (Bound<StoreKey<'_>>, Bound<StoreKey<'_>>),
+        handler: &mut (dyn for<'a> FnMut(&'a StoreKey) -> bool + Send + Sync + '_),
+    ) -> Result<u64, Error> {
+        self.get_store()?
+            .as_store_driver_pin()
+            .list(range, handler)
+            .await
+    }
+
+    async fn remove(self: Pin<&Self>, key: StoreKey<'_>) -> Result<bool, Error> {
+        self.get_store()?.remove(key).await
+    }
+
     async fn update(
         self: Pin<&Self>,
         key: StoreKey<'_>,
@@ -117,6 +134,16 @@ impl StoreDriver for RefStore {
         self.get_store()?.update(key, reader, size_info).await
     }
 
+    async fn update_many(
+        self: Pin<&Self>,
+        items: Vec<(StoreKey<'_>, Bytes)>,
+    ) -> Vec<Result<(), Error>> {
+        match self.get_store() {
+            Ok(store) => store.update_many(items).await,
+            Err(err) => items.iter().map(|_| Err(err.clone())).collect(),
+        }
+    }
+
     async fn get_part(
         self: Pin<&Self>,
         key: StoreKey<'_>,
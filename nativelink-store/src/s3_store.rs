@@ -13,11 +13,13 @@
 // limitations under the License.
 
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::future::Future;
+use std::ops::{Bound, RangeBounds};
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 use std::{cmp, env};
 
 use async_trait::async_trait;
@@ -27,26 +29,30 @@ use aws_sdk_s3::config::Region;
 use aws_sdk_s3::operation::create_multipart_upload::CreateMultipartUploadOutput;
 use aws_sdk_s3::operation::get_object::GetObjectError;
 use aws_sdk_s3::operation::head_object::HeadObjectError;
+use aws_sdk_s3::presigning::PresigningConfig;
 use aws_sdk_s3::primitives::{ByteStream, SdkBody};
 use aws_sdk_s3::types::builders::{CompletedMultipartUploadBuilder, CompletedPartBuilder};
+use aws_sdk_s3::types::{CompletedPart, ServerSideEncryption, StorageClass};
 use aws_sdk_s3::Client;
 use aws_smithy_runtime::client::http::hyper_014::HyperClientBuilder;
 use bytes::Bytes;
-use futures::future::FusedFuture;
-use futures::stream::{unfold, FuturesUnordered};
+use futures::future::{ready, Either, FusedFuture};
+use futures::stream::{unfold, FuturesOrdered, FuturesUnordered};
 use futures::{FutureExt, Stream, StreamExt, TryFutureExt, TryStreamExt};
 use http_body::{Frame, SizeHint};
 use hyper::client::connect::{Connected, Connection, HttpConnector};
 use hyper::service::Service;
 use hyper::Uri;
 use hyper_rustls::{HttpsConnector, MaybeHttpsStream};
-use nativelink_config::stores::S3Spec;
+use md5::{Digest, Md5};
+use nativelink_config::stores::{S3ServerSideEncryptionAlgorithm, S3Spec, StoreSpec};
 // Note: S3 store should be very careful about the error codes it returns
 // when in a retryable wrapper. Always prefer Code::Aborted or another
 // retryable code over Code::InvalidArgument or make_input_err!().
 // ie: Don't import make_input_err!() to help prevent this.
-use nativelink_error::{make_err, Code, Error, ResultExt};
+use nativelink_error::{make_err, make_input_err, Code, Error, ResultExt};
 use nativelink_metric::MetricsComponent;
+use nativelink_util::background_spawn;
 use nativelink_util::buf_channel::{
     make_buf_channel_pair, DropCloserReadHalf, DropCloserWriteHalf,
 };
@@ -55,6 +61,7 @@ use nativelink_util::health_utils::{HealthStatus, HealthStatusIndicator};
 use nativelink_util::instant_wrapper::InstantWrapper;
 use nativelink_util::retry::{Retrier, RetryResult};
 use nativelink_util::store_trait::{StoreDriver, StoreKey, UploadSizeInfo};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 use rand::rngs::OsRng;
 use rand::Rng;
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
@@ -85,6 +92,17 @@ const DEFAULT_MAX_RETRY_BUFFER_PER_REQUEST: usize = 5 * 1024 * 1024; // 5MB.
 // Note: If you change this, adjust the docs in the config.
 const DEFAULT_MULTIPART_MAX_CONCURRENT_UPLOADS: usize = 10;
 
+// Default number of seconds a presigned URL stays valid.
+// Note: If you change this, adjust the docs in the config.
+const DEFAULT_PRESIGNED_URL_EXPIRATION_S: u64 = 15 * 60; // 15 minutes.
+
+/// Returns the entity tag S3 gives a part with this content, the quoted hex
+/// MD5 of it. Parts encrypted with SSE-KMS have other entity tags, so they
+/// never match and are uploaded again.
+fn part_e_tag(data: &[u8]) -> String {
+    format!("\"{}\"", hex::encode(Md5::digest(data)))
+}
+
 pub struct ConnectionWithPermit<T: Connection + AsyncRead + AsyncWrite + Unpin> {
     connection: T,
     _permit: SemaphorePermit<'static>,
@@ -236,6 +254,10 @@ impl http_body::Body for BodyWrapper {
     }
 }
 
+/// The S3 store created by the store factory. Use this type to find the
+/// S3 store with `downcast_ref()`.
+pub type DefaultS3Store = S3Store<fn() -> SystemTime>;
+
 #[derive(MetricsComponent)]
 pub struct S3Store<NowFn> {
     s3_client: Arc<Client>,
@@ -251,6 +273,20 @@ pub struct S3Store<NowFn> {
     max_retry_buffer_per_request: usize,
     #[metric(help = "The number of concurrent uploads allowed for multipart uploads")]
     multipart_max_concurrent_uploads: usize,
+    #[metric(help = "Whether incomplete multipart uploads are resumed")]
+    resume_multipart_uploads: bool,
+    #[metric(help = "The number of seconds after which incomplete multipart uploads are aborted")]
+    abort_incomplete_multipart_uploads_after_s: i64,
+    server_side_encryption: Option<ServerSideEncryption>,
+    ssekms_key_id: Option<String>,
+    bucket_key_enabled: Option<bool>,
+    storage_class: Option<StorageClass>,
+    #[metric(help = "The tags set on uploaded objects")]
+    tagging: Option<String>,
+    #[metric(help = "Objects of at least this size are served through presigned URLs")]
+    presigned_url_threshold: u64,
+    #[metric(help = "The number of seconds a presigned URL stays valid")]
+    presigned_url_expiration_s: u64,
 }
 
 impl<I, NowFn> S3Store<NowFn>
@@ -290,7 +326,25 @@ where
             }
             aws_sdk_s3::Client::new(&config_builder.load().await)
         };
-        Self::new_with_client_and_jitter(spec, s3_client, jitter_fn, now_fn)
+        let store = Self::new_with_client_and_jitter(spec, s3_client, jitter_fn, now_fn)?;
+        if store.abort_incomplete_multipart_uploads_after_s != 0 {
+            let store = store.clone();
+            background_spawn!("s3_store_abort_incomplete_multipart_uploads", async move {
+                match store.abort_incomplete_multipart_uploads().await {
+                    Ok(aborted) => event!(
+                        Level::INFO,
+                        aborted,
+                        "Aborted incomplete multipart uploads in S3 store"
+                    ),
+                    Err(err) => event!(
+                        Level::WARN,
+                        ?err,
+                        "Failed to abort incomplete multipart uploads in S3 store"
+                    ),
+                }
+            });
+        }
+        Ok(store)
     }
 
     pub fn new_with_client_and_jitter(
@@ -299,6 +353,58 @@ where
         jitter_fn: Arc<dyn Fn(Duration) -> Duration + Send + Sync>,
         now_fn: NowFn,
     ) -> Result<Arc<Self>, Error> {
+        let (server_side_encryption, ssekms_key_id, bucket_key_enabled) = match &spec.sse {
+            None => (None, None, None),
+            Some(sse) => {
+                if sse.algorithm != S3ServerSideEncryptionAlgorithm::aws_kms
+                    && (sse.kms_key_id.is_some() || sse.bucket_key_enabled.is_some())
+                {
+                    return Err(make_err!(
+                        Code::InvalidArgument,
+                        "kms_key_id and bucket_key_enabled require the aws:kms sse algorithm in S3 store"
+                    ));
+                }
+                let algorithm = match sse.algorithm {
+                    S3ServerSideEncryptionAlgorithm::aes256 => ServerSideEncryption::Aes256,
+                    S3ServerSideEncryptionAlgorithm::aws_kms => ServerSideEncryption::AwsKms,
+                };
+                (
+                    Some(algorithm),
+                    sse.kms_key_id.clone(),
+                    sse.bucket_key_enabled,
+                )
+            }
+        };
+        let storage_class = spec
+            .storage_class
+            .as_deref()
+            .map(|storage_class| {
+                if !StorageClass::values().contains(&storage_class) {
+                    return Err(make_err!(
+                        Code::InvalidArgument,
+                        "Unknown storage_class {storage_class} in S3 store, expected one of {:?}",
+                        StorageClass::values()
+                    ));
+                }
+                Ok(StorageClass::from(storage_class))
+            })
+            .transpose()?;
+        // Tags are sent url encoded in a single header. Sort them so requests
+        // are deterministic.
+        let mut tags: Vec<_> = spec.tags.iter().collect();
+        tags.sort();
+        let tagging = (!tags.is_empty()).then(|| {
+            tags.iter()
+                .map(|(key, value)| {
+                    format!(
+                        "{}={}",
+                        utf8_percent_encode(key, NON_ALPHANUMERIC),
+                        utf8_percent_encode(value, NON_ALPHANUMERIC)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("&")
+        });
         Ok(Arc::new(Self {
             s3_client: Arc::new(s3_client),
             now_fn,
@@ -316,13 +422,172 @@ where
             multipart_max_concurrent_uploads: spec
                 .multipart_max_concurrent_uploads
                 .map_or(DEFAULT_MULTIPART_MAX_CONCURRENT_UPLOADS, |v| v),
+            resume_multipart_uploads: spec.resume_multipart_uploads,
+            abort_incomplete_multipart_uploads_after_s: i64::from(
+                spec.abort_incomplete_multipart_uploads_after_s,
+            ),
+            server_side_encryption,
+            ssekms_key_id,
+            bucket_key_enabled,
+            storage_class,
+            tagging,
+            presigned_url_threshold: spec.presigned_url_threshold,
+            presigned_url_expiration_s: if spec.presigned_url_expiration_s == 0 {
+                DEFAULT_PRESIGNED_URL_EXPIRATION_S
+            } else {
+                u64::from(spec.presigned_url_expiration_s)
+            },
         }))
     }
 
+    /// Objects of at least this many bytes should be downloaded through a
+    /// presigned URL instead of being proxied. Zero means never.
+    pub const fn presigned_url_threshold(&self) -> u64 {
+        self.presigned_url_threshold
+    }
+
+    /// Create a presigned URL that allows anyone holding it to download
+    /// the object directly from S3 until it expires.
+    pub async fn presigned_get_url(&self, key: StoreKey<'_>) -> Result<String, Error> {
+        let presigning_config =
+            PresigningConfig::expires_in(Duration::from_secs(self.presigned_url_expiration_s))
+                .map_err(|e| make_err!(Code::Internal, "Invalid presigning config: {e:?}"))?;
+        let presigned_request = self
+            .s3_client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(self.make_s3_path(&key))
+            .presigned(presigning_config)
+            .await
+            .map_err(|e| make_err!(Code::Unavailable, "Failed to presign S3 object: {e:?}"))?;
+        Ok(presigned_request.uri().to_string())
+    }
+
     fn make_s3_path(&self, key: &StoreKey<'_>) -> String {
         format!("{}{}", self.key_prefix, key.as_str(),)
     }
 
+    /// Abort the incomplete multipart uploads under the key prefix that were
+    /// started more than `abort_incomplete_multipart_uploads_after_s` ago.
+    /// Returns the number of aborted uploads.
+    pub async fn abort_incomplete_multipart_uploads(&self) -> Result<usize, Error> {
+        let now_s = (self.now_fn)().unix_timestamp() as i64;
+        let mut aborted = 0;
+        let mut key_marker = None;
+        let mut upload_id_marker = None;
+        loop {
+            let output = self
+                .s3_client
+                .list_multipart_uploads()
+                .bucket(&self.bucket)
+                .set_prefix((!self.key_prefix.is_empty()).then(|| self.key_prefix.clone()))
+                .set_key_marker(key_marker.take())
+                .set_upload_id_marker(upload_id_marker.take())
+                .send()
+                .await
+                .map_err(|e| {
+                    make_err!(
+                        Code::Unavailable,
+                        "Failed to list multipart uploads in S3 store: {e:?}"
+                    )
+                })?;
+            for upload in output.uploads() {
+                let (Some(key), Some(upload_id), Some(initiated)) =
+                    (upload.key(), upload.upload_id(), upload.initiated())
+                else {
+                    continue;
+                };
+                if initiated.secs() + self.abort_incomplete_multipart_uploads_after_s > now_s {
+                    continue;
+                }
+                self.s3_client
+                    .abort_multipart_upload()
+                    .bucket(&self.bucket)
+                    .key(key)
+                    .upload_id(upload_id)
+                    .send()
+                    .await
+                    .map_err(|e| {
+                        make_err!(
+                            Code::Unavailable,
+                            "Failed to abort multipart upload {upload_id} of {key} in S3 store: {e:?}"
+                        )
+                    })?;
+                aborted += 1;
+            }
+            if output.is_truncated() != Some(true) {
+                return Ok(aborted);
+            }
+            key_marker = output.next_key_marker().map(str::to_string);
+            upload_id_marker = output.next_upload_id_marker().map(str::to_string);
+        }
+    }
+
+    /// Find the most recent incomplete multipart upload of `s3_path` along
+    /// with the size and entity tag of the parts that were already uploaded.
+    async fn find_incomplete_upload(
+        &self,
+        s3_path: &str,
+    ) -> Result<Option<(String, HashMap<i32, (i64, CompletedPart)>)>, Error> {
+        let output = self
+            .s3_client
+            .list_multipart_uploads()
+            .bucket(&self.bucket)
+            .prefix(s3_path)
+            .send()
+            .await
+            .map_err(|e| {
+                make_err!(
+                    Code::Unavailable,
+                    "Failed to list multipart uploads in S3 store: {e:?}"
+                )
+            })?;
+        // The prefix also matches longer keys.
+        let Some(upload_id) = output
+            .uploads()
+            .iter()
+            .filter(|upload| upload.key() == Some(s3_path))
+            .max_by_key(|upload| upload.initiated().map(|initiated| initiated.secs()))
+            .and_then(|upload| upload.upload_id())
+        else {
+            return Ok(None);
+        };
+
+        let mut uploaded_parts = HashMap::new();
+        let mut part_number_marker = None;
+        loop {
+            let output = self
+                .s3_client
+                .list_parts()
+                .bucket(&self.bucket)
+                .key(s3_path)
+                .upload_id(upload_id)
+                .set_part_number_marker(part_number_marker.take())
+                .send()
+                .await
+                .map_err(|e| {
+                    make_err!(
+                        Code::Unavailable,
+                        "Failed to list parts of multipart upload in S3 store: {e:?}"
+                    )
+                })?;
+            for part in output.parts() {
+                let (Some(part_number), Some(size)) = (part.part_number(), part.size()) else {
+                    continue;
+                };
+                let completed_part = CompletedPartBuilder::default()
+                    .set_e_tag(part.e_tag().map(str::to_string))
+                    .part_number(part_number)
+                    .build();
+                uploaded_parts.insert(part_number, (size, completed_part));
+            }
+            part_number_marker = output.next_part_number_marker().map(str::to_string);
+            if output.is_truncated() != Some(true) || part_number_marker.is_none() {
+                return Ok(Some((upload_id.to_string(), uploaded_parts)));
+            }
+        }
+    }
+
     async fn has(self: Pin<&Self>, digest: &StoreKey<'_>) -> Result<Option<u64>, Error> {
         self.retrier
             .retry(unfold((), move |state| async move {
@@ -401,6 +666,63 @@ where
             .await
     }
 
+    async fn list(
+        self: Pin<&Self>,
+        range: (Bound<StoreKey<'_>>, Bound<StoreKey<'_>>),
+        handler: &mut (dyn for<'a> FnMut(&'a StoreKey) -> bool + Send + Sync + '_),
+    ) -> Result<u64, Error> {
+        // S3 lists objects in the lexicographic order of their path, which is
+        // not the order of `StoreKey`, so every object under the key prefix
+        // is listed and checked against the range.
+        let now_s = (self.now_fn)().unix_timestamp() as i64;
+        let mut iterations = 0;
+        let mut continuation_token = None;
+        loop {
+            let output = self
+                .s3_client
+                .list_objects_v2()
+                .bucket(&self.bucket)
+                .set_prefix((!self.key_prefix.is_empty()).then(|| self.key_prefix.clone()))
+                .set_continuation_token(continuation_token.take())
+                .send()
+                .await
+                .map_err(|e| {
+                    make_err!(
+                        Code::Unavailable,
+                        "Failed to list objects in S3 store: {e:?}"
+                    )
+                })?;
+            for object in output.contents() {
+                let Some(path) = object.key() else {
+                    continue;
+                };
+                if self.consider_expired_after_s != 0 {
+                    if let Some(last_modified) = object.last_modified() {
+                        if last_modified.secs() + self.consider_expired_after_s <= now_s {
+                            continue;
+                        }
+                    }
+                }
+                let key =
+                    StoreKey::from_encoded_str(path.strip_prefix(&self.key_prefix).unwrap_or(path));
+                if !range.contains(&key) {
+                    continue;
+                }
+                if !handler(&key) {
+                    return Ok(iterations);
+                }
+                iterations += 1;
+            }
+            if output.is_truncated() != Some(true) {
+                return Ok(iterations);
+            }
+            continuation_token = output.next_continuation_token().map(str::to_string);
+            if continuation_token.is_none() {
+                return Ok(iterations);
+            }
+        }
+    }
+
     async fn update(
         self: Pin<&Self>,
         digest: StoreKey<'_>,
@@ -445,6 +767,11 @@ where
                                 .bucket(&self.bucket)
                                 .key(s3_path.clone())
                                 .content_length(sz as i64)
+                                .set_server_side_encryption(self.server_side_encryption.clone())
+                                .set_ssekms_key_id(self.ssekms_key_id.clone())
+                                .set_bucket_key_enabled(self.bucket_key_enabled)
+                                .set_storage_class(self.storage_class.clone())
+                                .set_tagging(self.tagging.clone())
                                 .body(ByteStream::from_body_1_x(BodyWrapper {
                                     reader: rx,
                                     size: sz,
@@ -489,38 +816,71 @@ where
                 .await;
         }
 
-        let upload_id = &self
-            .retrier
-            .retry(unfold((), move |()| async move {
-                let retry_result = self
-                    .s3_client
-                    .create_multipart_upload()
-                    .bucket(&self.bucket)
-                    .key(s3_path)
-                    .send()
-                    .await
-                    .map_or_else(
-                        |e| {
-                            RetryResult::Retry(make_err!(
-                                Code::Aborted,
-                                "Failed to create multipart upload to s3: {e:?}"
-                            ))
-                        },
-                        |CreateMultipartUploadOutput { upload_id, .. }| {
-                            upload_id.map_or_else(
-                                || {
-                                    RetryResult::Err(make_err!(
-                                        Code::Internal,
-                                        "Expected upload_id to be set by s3 response"
+        let resumable_upload = if self.resume_multipart_uploads {
+            self.find_incomplete_upload(s3_path)
+                .await
+                .unwrap_or_else(|err| {
+                    event!(
+                        Level::WARN,
+                        ?err,
+                        "Failed to find incomplete multipart upload in S3 store, starting a new one"
+                    );
+                    None
+                })
+        } else {
+            None
+        };
+        let (upload_id, uploaded_parts) =
+            if let Some((upload_id, uploaded_parts)) = resumable_upload {
+                event!(
+                    Level::INFO,
+                    ?upload_id,
+                    uploaded_parts = uploaded_parts.len(),
+                    "Resuming multipart upload in S3 store"
+                );
+                (upload_id, uploaded_parts)
+            } else {
+                let upload_id = self
+                    .retrier
+                    .retry(unfold((), move |()| async move {
+                        let retry_result = self
+                            .s3_client
+                            .create_multipart_upload()
+                            .bucket(&self.bucket)
+                            .key(s3_path)
+                            .set_server_side_encryption(self.server_side_encryption.clone())
+                            .set_ssekms_key_id(self.ssekms_key_id.clone())
+                            .set_bucket_key_enabled(self.bucket_key_enabled)
+                            .set_storage_class(self.storage_class.clone())
+                            .set_tagging(self.tagging.clone())
+                            .send()
+                            .await
+                            .map_or_else(
+                                |e| {
+                                    RetryResult::Retry(make_err!(
+                                        Code::Aborted,
+                                        "Failed to create multipart upload to s3: {e:?}"
                                     ))
                                 },
-                                RetryResult::Ok,
-                            )
-                        },
-                    );
-                Some((retry_result, ()))
-            }))
-            .await?;
+                                |CreateMultipartUploadOutput { upload_id, .. }| {
+                                    upload_id.map_or_else(
+                                        || {
+                                            RetryResult::Err(make_err!(
+                                                Code::Internal,
+                                                "Expected upload_id to be set by s3 response"
+                                            ))
+                                        },
+                                        RetryResult::Ok,
+                                    )
+                                },
+                            );
+                        Some((retry_result, ()))
+                    }))
+                    .await?;
+                (upload_id, HashMap::new())
+            };
+        let upload_id = &upload_id;
+        let uploaded_parts = &uploaded_parts;
 
         // S3 requires us to upload in parts if the size is greater than 5GB. The part size must be at least
         // 5mb (except last part) and can have up to 10,000 parts.
@@ -544,7 +904,20 @@ where
                         break; // Reached EOF.
                     }
 
-                    tx.send(retrier.retry(unfold(
+                    // Parts with the same number and content that were
+                    // uploaded by a previous attempt are not sent again.
+                    if let Some((_, completed_part)) = uploaded_parts
+                        .get(&part_number)
+                        .filter(|(size, completed_part)| {
+                            usize::try_from(*size) == Ok(write_buf.len())
+                                && completed_part.e_tag() == Some(part_e_tag(&write_buf).as_str())
+                        })
+                    {
+                        tx.send(Either::Left(ready(Ok::<_, Error>(completed_part.clone())))).await.map_err(|_| make_err!(Code::Internal, "Failed to send part to channel in s3_store"))?;
+                        continue;
+                    }
+
+                    tx.send(Either::Right(retrier.retry(unfold(
                         write_buf,
                         move |write_buf| {
                             async move {
@@ -580,7 +953,7 @@ where
                                 Some((retry_result, write_buf))
                             }
                         }
-                    ))).await.map_err(|_| make_err!(Code::Internal, "Failed to send part to channel in s3_store"))?;
+                    )))).await.map_err(|_| make_err!(Code::Internal, "Failed to send part to channel in s3_store"))?;
                 }
                 Result::<_, Error>::Ok(())
             }.fuse();
@@ -640,9 +1013,13 @@ where
                 .await
         };
         // Upload our parts and complete the multipart upload.
-        // If we fail attempt to abort the multipart upload (cleanup).
+        // If we fail attempt to abort the multipart upload (cleanup), unless
+        // it is kept so a retry can resume it.
         upload_parts()
             .or_else(move |e| async move {
+                if self.resume_multipart_uploads {
+                    return Err(e);
+                }
                 Result::<(), _>::Err(e).merge(
                     // Note: We don't retry here because this is just a best attempt.
                     self.s3_client
@@ -668,6 +1045,56 @@ where
             .await
     }
 
+    async fn update_many(
+        self: Pin<&Self>,
+        items: Vec<(StoreKey<'_>, Bytes)>,
+    ) -> Vec<Result<(), Error>> {
+        // The data is already in memory, so small objects are sent straight
+        // from it, instead of being copied through a channel and a retry
+        // buffer like in `update()`.
+        items
+            .into_iter()
+            .map(|(key, data)| async move {
+                if data.len() as u64 >= MIN_MULTIPART_SIZE {
+                    return self.update_oneshot(key, data).await;
+                }
+                let s3_path = &self.make_s3_path(&key);
+                let data = &data;
+                self.retrier
+                    .retry(unfold((), move |()| async move {
+                        let result = self
+                            .s3_client
+                            .put_object()
+                            .bucket(&self.bucket)
+                            .key(s3_path.clone())
+                            .content_length(data.len() as i64)
+                            .set_server_side_encryption(self.server_side_encryption.clone())
+                            .set_ssekms_key_id(self.ssekms_key_id.clone())
+                            .set_bucket_key_enabled(self.bucket_key_enabled)
+                            .set_storage_class(self.storage_class.clone())
+                            .set_tagging(self.tagging.clone())
+                            .body(ByteStream::from(data.clone()))
+                            .send()
+                            .await;
+                        // Ensure our code is Code::Aborted, so the client can retry if possible.
+                        let retry_result = result.map_or_else(
+                            |e| {
+                                RetryResult::Retry(make_err!(
+                                    Code::Aborted,
+                                    "Failed to upload object in S3Store::update_many - {e:?}"
+                                ))
+                            },
+                            |_| RetryResult::Ok(()),
+                        );
+                        Some((retry_result, ()))
+                    }))
+                    .await
+            })
+            .collect::<FuturesOrdered<_>>()
+            .collect()
+            .await
+    }
+
     async fn get_part(
         self: Pin<&Self>,
         key: StoreKey<'_>,
@@ -781,6 +1208,18 @@ where
     fn as_any_arc(self: Arc<Self>) -> Arc<dyn std::any::Any + Sync + Send + 'static> {
         self
     }
+
+    async fn reconfigure(&self, spec: &StoreSpec, check_only: bool) -> Result<(), Error> {
+        let StoreSpec::experimental_s3_store(spec) = spec else {
+            return Err(make_input_err!(
+                "S3Store can only be reconfigured with an experimental_s3_store spec"
+            ));
+        };
+        if !check_only {
+            self.retrier.set_config(spec.retry.clone());
+        }
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -18,13 +18,45 @@ use std::pin::Pin;
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use futures::stream::{FuturesUnordered, TryStreamExt};
-use nativelink_config::stores::ShardSpec;
-use nativelink_error::{error_if, Error, ResultExt};
+use futures::stream::{self, FuturesUnordered, StreamExt, TryStreamExt};
+use futures::try_join;
+use nativelink_config::stores::{ShardAlgorithm, ShardSpec, StoreSpec};
+use nativelink_error::{error_if, make_input_err, Error, ResultExt};
 use nativelink_metric::MetricsComponent;
-use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
+use nativelink_util::buf_channel::{
+    make_buf_channel_pair, DropCloserReadHalf, DropCloserWriteHalf,
+};
 use nativelink_util::health_utils::{default_health_status_indicator, HealthStatusIndicator};
 use nativelink_util::store_trait::{Store, StoreDriver, StoreKey, StoreLike, UploadSizeInfo};
+use tracing::{event, Level};
+
+/// Number of points each unit of weight places on the ring when the
+/// `consistent_hash` algorithm is used.
+const VIRTUAL_NODES_PER_WEIGHT: u64 = 128;
+
+/// Upper bound of the number of points on the ring, which keeps a huge
+/// weight from using a huge amount of memory.
+const MAX_VIRTUAL_NODES: u64 = 1 << 20;
+
+/// Number of keys that are copied at the same time by `rebalance()`.
+const SIMULTANEOUS_REBALANCE_COPIES: usize = 16;
+
+/// The outcome of a `ShardStore::rebalance()`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ShardRebalanceReport {
+    /// Number of keys that were found in the stores.
+    pub scanned_keys: u64,
+    /// Number of keys that were found in a store that no longer owns them.
+    pub moved_keys: u64,
+    /// Number of moved keys that had to be copied to the store that owns
+    /// them, because it did not have them yet.
+    pub copied_keys: u64,
+    /// Number of bytes of the copied keys.
+    pub copied_bytes: u64,
+    /// Number of moved keys that were removed from the store that no longer
+    /// owns them.
+    pub removed_keys: u64,
+}
 
 #[derive(MetricsComponent)]
 struct StoreAndWeight {
@@ -43,6 +75,11 @@ pub struct ShardStore {
         help = "The weights and stores that are used to determine which store to use"
     )]
     weights_and_stores: Vec<StoreAndWeight>,
+    #[metric(help = "The algorithm used to distribute keys over the stores")]
+    algorithm: String,
+    // Points on the hash ring in ascending order and the index of the store
+    // that owns the keys up to each point. Only used by `consistent_hash`.
+    ring: Vec<(u32, usize)>,
 }
 
 impl ShardStore {
@@ -74,17 +111,69 @@ impl ShardStore {
             .collect();
         // Our last item should always be the max.
         *weights.last_mut().unwrap() = u32::MAX;
+        let ring = match spec.algorithm {
+            ShardAlgorithm::weighted_range => Vec::new(),
+            ShardAlgorithm::consistent_hash => Self::make_ring(spec)?,
+        };
         Ok(Arc::new(Self {
             weights_and_stores: weights
                 .into_iter()
                 .zip(stores)
                 .map(|(weight, store)| StoreAndWeight { weight, store })
                 .collect(),
+            algorithm: format!("{:?}", spec.algorithm),
+            ring,
         }))
     }
 
+    fn make_ring(spec: &ShardSpec) -> Result<Vec<(u32, usize)>, Error> {
+        let total_virtual_nodes: u64 = spec
+            .stores
+            .iter()
+            .map(|shard_config| {
+                u64::from(shard_config.weight.unwrap_or(1)) * VIRTUAL_NODES_PER_WEIGHT
+            })
+            .sum();
+        error_if!(
+            total_virtual_nodes == 0,
+            "ShardStore must have at least one store with a non-zero weight"
+        );
+        error_if!(
+            total_virtual_nodes > MAX_VIRTUAL_NODES,
+            "ShardStore weights add up to {} points on the ring, the maximum is {MAX_VIRTUAL_NODES}. Use smaller weights.",
+            total_virtual_nodes
+        );
+        let mut ring = Vec::with_capacity(total_virtual_nodes as usize);
+        for (store_idx, shard_config) in spec.stores.iter().enumerate() {
+            let virtual_nodes =
+                u64::from(shard_config.weight.unwrap_or(1)) * VIRTUAL_NODES_PER_WEIGHT;
+            for replica in 0..virtual_nodes {
+                // The point only depends on the position of the store and the
+                // replica, so changing other stores does not move it.
+                let mut hasher = DefaultHasher::new();
+                hasher.write_u64(store_idx as u64);
+                hasher.write_u64(replica);
+                ring.push(((hasher.finish() >> 32) as u32, store_idx));
+            }
+        }
+        ring.sort_unstable();
+        Ok(ring)
+    }
+
     fn get_store_index(&self, store_key: &StoreKey) -> usize {
-        let key = match store_key {
+        let key = Self::key_hash(store_key);
+        if !self.ring.is_empty() {
+            let ring_idx = self.ring.partition_point(|(point, _)| *point < key);
+            // Keys after the last point wrap around to the first one.
+            return self.ring.get(ring_idx).unwrap_or(&self.ring[0]).1;
+        }
+        self.weights_and_stores
+            .binary_search_by_key(&key, |item| item.weight)
+            .unwrap_or_else(|index| index)
+    }
+
+    fn key_hash(store_key: &StoreKey) -> u32 {
+        match store_key {
             StoreKey::Digest(digest) => {
                 // Quote from std primitive array documentation:
                 //     Array’s try_from(slice) implementations (and the corresponding slice.try_into()
@@ -126,16 +215,85 @@ impl ShardStore {
                 let key_u64 = hasher.finish();
                 (key_u64 >> 32) as u32 // We only need the top 32 bits.
             }
-        };
-        self.weights_and_stores
-            .binary_search_by_key(&key, |item| item.weight)
-            .unwrap_or_else(|index| index)
+        }
     }
 
     fn get_store(&self, key: &StoreKey) -> &Store {
         let index = self.get_store_index(key);
         &self.weights_and_stores[index].store
     }
+
+    /// Moves every key that is in a store that does not own it anymore to
+    /// the store that does, which is needed after stores were added or
+    /// their weights changed. Keys the owning store already has are not
+    /// copied again. If `remove_moved` is set, the moved keys are removed
+    /// from the store they were found in.
+    ///
+    /// All stores must support listing their keys, and with `remove_moved`
+    /// also removing them.
+    pub async fn rebalance(&self, remove_moved: bool) -> Result<ShardRebalanceReport, Error> {
+        let mut report = ShardRebalanceReport::default();
+        for (store_idx, item) in self.weights_and_stores.iter().enumerate() {
+            let mut keys = Vec::new();
+            item.store
+                .list(.., |key| {
+                    keys.push(key.borrow().into_owned());
+                    true
+                })
+                .await
+                .err_tip(|| format!("Failed to list store {store_idx} in ShardStore::rebalance"))?;
+            report.scanned_keys += keys.len() as u64;
+            let mut moves = stream::iter(keys)
+                .filter(|key| std::future::ready(self.get_store_index(key) != store_idx))
+                .map(|key| self.move_key(key, store_idx, remove_moved))
+                .buffer_unordered(SIMULTANEOUS_REBALANCE_COPIES);
+            while let Some((copied_bytes, removed)) = moves.try_next().await? {
+                report.moved_keys += 1;
+                if let Some(copied_bytes) = copied_bytes {
+                    report.copied_keys += 1;
+                    report.copied_bytes += copied_bytes;
+                }
+                if removed {
+                    report.removed_keys += 1;
+                }
+            }
+        }
+        event!(Level::INFO, ?report, "Finished rebalancing ShardStore");
+        Ok(report)
+    }
+
+    /// Copies `key` from the store at `from_idx` to the store that owns it
+    /// and returns the number of bytes copied, if it had to be copied, and
+    /// whether it was removed from the store at `from_idx`.
+    async fn move_key(
+        &self,
+        key: StoreKey<'static>,
+        from_idx: usize,
+        remove_moved: bool,
+    ) -> Result<(Option<u64>, bool), Error> {
+        let source = &self.weights_and_stores[from_idx].store;
+        let destination = self.get_store(&key);
+        let mut copied_bytes = None;
+        if destination.has(key.borrow()).await?.is_none() {
+            // The key may have been evicted since it was listed.
+            let Some(size) = source.has(key.borrow()).await? else {
+                return Ok((None, false));
+            };
+            let (tx, rx) = make_buf_channel_pair();
+            try_join!(
+                source.get(key.borrow(), tx),
+                destination.update(key.borrow(), rx, UploadSizeInfo::ExactSize(size)),
+            )
+            .err_tip(|| format!("Failed to copy {key:?} in ShardStore::rebalance"))?;
+            copied_bytes = Some(size);
+        }
+        let removed = remove_moved
+            && source
+                .remove(key.borrow())
+                .await
+                .err_tip(|| format!("Failed to remove {key:?} in ShardStore::rebalance"))?;
+        Ok((copied_bytes, removed))
+    }
 }
 
 #[async_trait]
@@ -207,6 +365,14 @@ impl StoreDriver for ShardStore {
             .err_tip(|| "In ShardStore::update()")
     }
 
+    async fn remove(self: Pin<&Self>, key: StoreKey<'_>) -> Result<bool, Error> {
+        let store = self.get_store(&key);
+        store
+            .remove(key)
+            .await
+            .err_tip(|| "In ShardStore::remove()")
+    }
+
     async fn get_part(
         self: Pin<&Self>,
         key: StoreKey<'_>,
@@ -236,6 +402,27 @@ impl StoreDriver for ShardStore {
     fn as_any_arc(self: Arc<Self>) -> Arc<dyn std::any::Any + Sync + Send + 'static> {
         self
     }
+
+    async fn reconfigure(&self, spec: &StoreSpec, check_only: bool) -> Result<(), Error> {
+        let StoreSpec::shard(spec) = spec else {
+            return Err(make_input_err!(
+                "ShardStore can only be reconfigured with a shard spec"
+            ));
+        };
+        error_if!(
+            spec.stores.len() != self.weights_and_stores.len(),
+            "Stores of ShardStore can't be added or removed while running"
+        );
+        for (store_and_weight, shard_config) in self.weights_and_stores.iter().zip(&spec.stores) {
+            store_and_weight
+                .store
+                .as_store_driver()
+                .reconfigure(&shard_config.store, check_only)
+                .await
+                .err_tip(|| "In ShardStore::reconfigure")?;
+        }
+        Ok(())
+    }
 }
 
 default_health_status_indicator!(ShardStore);
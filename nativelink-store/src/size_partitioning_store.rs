@@ -16,7 +16,7 @@ use std::pin::Pin;
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use nativelink_config::stores::SizePartitioningSpec;
+use nativelink_config::stores::{SizePartitioningSpec, StoreSpec};
 use nativelink_error::{make_input_err, Error, ResultExt};
 use nativelink_metric::MetricsComponent;
 use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
@@ -159,6 +159,24 @@ impl StoreDriver for SizePartitioningStore {
     fn as_any_arc(self: Arc<Self>) -> Arc<dyn std::any::Any + Sync + Send + 'static> {
         self
     }
+
+    async fn reconfigure(&self, spec: &StoreSpec, check_only: bool) -> Result<(), Error> {
+        let StoreSpec::size_partitioning(spec) = spec else {
+            return Err(make_input_err!(
+                "SizePartitioningStore can only be reconfigured with a size_partitioning spec"
+            ));
+        };
+        self.lower_store
+            .as_store_driver()
+            .reconfigure(&spec.lower_store, check_only)
+            .await
+            .err_tip(|| "In SizePartitioningStore::reconfigure for lower_store")?;
+        self.upper_store
+            .as_store_driver()
+            .reconfigure(&spec.upper_store, check_only)
+            .await
+            .err_tip(|| "In SizePartitioningStore::reconfigure for upper_store")
+    }
 }
 
 default_health_status_indicator!(SizePartitioningStore);
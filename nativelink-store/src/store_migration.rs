@@ -0,0 +1,156 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Copies every object of one store into another store.
+
+use std::ops::Bound;
+
+use futures::stream::{self, StreamExt, TryStreamExt};
+use futures::try_join;
+use nativelink_error::{Code, Error, ResultExt};
+use nativelink_util::buf_channel::make_buf_channel_pair;
+use nativelink_util::store_trait::{Store, StoreKey, StoreLike, UploadSizeInfo};
+
+/// Number of objects copied at the same time if not configured.
+pub const DEFAULT_MIGRATION_PARALLELISM: usize = 16;
+
+/// Number of keys listed from the source store at a time if not configured.
+pub const DEFAULT_MIGRATION_BATCH_SIZE: usize = 1000;
+
+#[derive(Debug, Clone, Copy)]
+pub struct MigrationOptions {
+    /// Number of objects copied at the same time. Zero means
+    /// `DEFAULT_MIGRATION_PARALLELISM`.
+    pub parallelism: usize,
+    /// Number of keys listed from the source store at a time. Zero means
+    /// `DEFAULT_MIGRATION_BATCH_SIZE`.
+    pub batch_size: usize,
+}
+
+/// How far a migration got.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MigrationProgress {
+    /// Number of keys that were listed in the source store.
+    pub listed_keys: u64,
+    /// Number of objects that were copied to the destination store.
+    pub copied_keys: u64,
+    /// Number of bytes that were copied to the destination store.
+    pub copied_bytes: u64,
+    /// Number of objects that were not copied, because the destination
+    /// store already had them or they disappeared from the source store
+    /// after they were listed.
+    pub skipped_keys: u64,
+}
+
+/// Copies every object in `source` that `destination` does not have yet.
+///
+/// Keys are listed from `source` in order, a batch at a time, starting
+/// after `start_after` if given. After every batch is copied completely
+/// `on_batch` is called with the progress so far and the last key of the
+/// batch, which can be passed as `start_after` to resume the migration.
+/// Objects that are already in `destination` are skipped, so resuming from
+/// an older key only costs existence checks.
+///
+/// `source` must support listing its keys.
+pub async fn migrate_store(
+    source: &Store,
+    destination: &Store,
+    start_after: Option<StoreKey<'static>>,
+    options: MigrationOptions,
+    mut on_batch: impl FnMut(&MigrationProgress, &StoreKey<'static>) -> Result<(), Error>,
+) -> Result<MigrationProgress, Error> {
+    let parallelism = if options.parallelism == 0 {
+        DEFAULT_MIGRATION_PARALLELISM
+    } else {
+        options.parallelism
+    };
+    let batch_size = if options.batch_size == 0 {
+        DEFAULT_MIGRATION_BATCH_SIZE
+    } else {
+        options.batch_size
+    };
+    let mut progress = MigrationProgress::default();
+    let mut start = start_after;
+    loop {
+        let mut keys = Vec::with_capacity(batch_size);
+        let start_bound = match &start {
+            Some(key) => Bound::Excluded(key.borrow()),
+            None => Bound::Unbounded,
+        };
+        source
+            .list((start_bound, Bound::Unbounded), |key| {
+                keys.push(key.borrow().into_owned());
+                keys.len() < batch_size
+            })
+            .await
+            .err_tip(|| "Failed to list source store in migrate_store")?;
+        let Some(last_key) = keys.last().cloned() else {
+            return Ok(progress);
+        };
+        let batch_len = keys.len();
+        progress.listed_keys += batch_len as u64;
+
+        let existing = destination
+            .has_many(&keys)
+            .await
+            .err_tip(|| "Failed to check destination store in migrate_store")?;
+        let missing_keys: Vec<StoreKey<'static>> = keys
+            .into_iter()
+            .zip(existing)
+            .filter_map(|(key, existing)| existing.is_none().then_some(key))
+            .collect();
+        progress.skipped_keys += (batch_len - missing_keys.len()) as u64;
+
+        let mut copies = stream::iter(missing_keys)
+            .map(|key| copy_key(source, destination, key))
+            .buffer_unordered(parallelism);
+        while let Some(copied_bytes) = copies.try_next().await? {
+            match copied_bytes {
+                Some(copied_bytes) => {
+                    progress.copied_keys += 1;
+                    progress.copied_bytes += copied_bytes;
+                }
+                None => progress.skipped_keys += 1,
+            }
+        }
+        on_batch(&progress, &last_key)?;
+        start = Some(last_key);
+    }
+}
+
+/// Copies `key` from `source` to `destination` and returns the number of
+/// bytes copied, or `None` if `source` does not have it anymore.
+async fn copy_key(
+    source: &Store,
+    destination: &Store,
+    key: StoreKey<'static>,
+) -> Result<Option<u64>, Error> {
+    let Some(size) = source
+        .has(key.borrow())
+        .await
+        .err_tip(|| format!("Failed to check {key:?} in migrate_store"))?
+    else {
+        return Ok(None);
+    };
+    let (tx, rx) = make_buf_channel_pair();
+    match try_join!(
+        source.get(key.borrow(), tx),
+        destination.update(key.borrow(), rx, UploadSizeInfo::ExactSize(size)),
+    ) {
+        Ok(_) => Ok(Some(size)),
+        // The object was evicted from the source after it was checked.
+        Err(err) if err.code == Code::NotFound => Ok(None),
+        Err(err) => Err(err).err_tip(|| format!("Failed to copy {key:?} in migrate_store")),
+    }
+}
@@ -0,0 +1,380 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Positional reads and writes of files through io_uring.
+//!
+//! io_uring is only used when nativelink is built with the `io_uring`
+//! feature on Linux and the kernel allows it. Otherwise `UringIo::try_new`
+//! returns `None` and callers use regular tokio file I/O.
+
+use std::fs::File;
+use std::sync::Arc;
+
+use bytes::{Bytes, BytesMut};
+use nativelink_error::{make_err, Code, Error, ResultExt};
+use nativelink_util::fs;
+
+pub use self::imp::UringIo;
+
+/// Number of operations that can be in flight on a ring at once.
+pub const DEFAULT_QUEUE_DEPTH: u32 = 256;
+
+/// A file opened through a `ResumeableFileSlot` that is read or written at
+/// explicit offsets through io_uring.
+///
+/// The operations hold a duplicate of the descriptor of the slot, so the
+/// file stays open until they complete even if the caller is cancelled.
+/// The duplicate is dropped with `close_file()`, so the slot can still
+/// give up its descriptor while the caller is idle.
+pub struct UringFile {
+    uring: Arc<UringIo>,
+    slot: fs::ResumeableFileSlot,
+    file: Option<Arc<File>>,
+    position: u64,
+    remaining: u64,
+}
+
+impl UringFile {
+    /// Starts at the current position of `slot` and stops at its limit.
+    pub async fn new(uring: Arc<UringIo>, mut slot: fs::ResumeableFileSlot) -> Result<Self, Error> {
+        let position = slot
+            .stream_position()
+            .await
+            .err_tip(|| "In UringFile::new")?;
+        let remaining = slot
+            .as_reader()
+            .await
+            .err_tip(|| "In UringFile::new")?
+            .limit();
+        Ok(Self {
+            uring,
+            slot,
+            file: None,
+            position,
+            remaining,
+        })
+    }
+
+    async fn file(&mut self) -> Result<Arc<File>, Error> {
+        if let Some(file) = &self.file {
+            return Ok(file.clone());
+        }
+        let file = self
+            .slot
+            .as_writer()
+            .await
+            .err_tip(|| "In UringFile::file")?
+            .as_ref()
+            .try_clone()
+            .await
+            .err_tip(|| "Failed to duplicate file descriptor for io_uring")?
+            .into_std()
+            .await;
+        let file = Arc::new(file);
+        self.file = Some(file.clone());
+        Ok(file)
+    }
+
+    /// Reads up to `max_len` bytes. An empty buffer means EOF.
+    pub async fn read_chunk(&mut self, max_len: usize) -> Result<BytesMut, Error> {
+        let len = usize::try_from(self.remaining)
+            .unwrap_or(usize::MAX)
+            .min(max_len);
+        if len == 0 {
+            return Ok(BytesMut::new());
+        }
+        let file = self.file().await?;
+        let buf = self
+            .uring
+            .read_at(file, BytesMut::with_capacity(len), self.position)
+            .await
+            .err_tip(|| "Failed to read file with io_uring")?;
+        self.position += buf.len() as u64;
+        self.remaining -= buf.len() as u64;
+        Ok(buf)
+    }
+
+    /// Writes all of `data` after the data written before.
+    pub async fn write_all(&mut self, mut data: Bytes) -> Result<(), Error> {
+        while !data.is_empty() {
+            let file = self.file().await?;
+            let written = self
+                .uring
+                .write_at(file, data.clone(), self.position)
+                .await
+                .err_tip(|| "Failed to write file with io_uring")?;
+            if written == 0 {
+                return Err(make_err!(
+                    Code::Internal,
+                    "io_uring wrote zero bytes to {:?}",
+                    self.slot.get_path()
+                ));
+            }
+            self.position += written as u64;
+            let _ = data.split_to(written);
+        }
+        Ok(())
+    }
+
+    pub async fn close_file(&mut self) -> Result<(), Error> {
+        self.file = None;
+        self.slot.close_file().await
+    }
+
+    pub fn into_inner(self) -> fs::ResumeableFileSlot {
+        self.slot
+    }
+}
+
+#[cfg(all(feature = "io_uring", target_os = "linux"))]
+mod imp {
+    use std::fs::File;
+    use std::io;
+    use std::os::fd::AsRawFd;
+    use std::sync::{mpsc, Arc};
+
+    use bytes::{Bytes, BytesMut};
+    use io_uring::{opcode, types, IoUring};
+    use nativelink_error::{make_err, Code, Error};
+    use nativelink_util::spawn_blocking;
+    use nativelink_util::task::JoinHandleDropGuard;
+    use tokio::sync::oneshot;
+    use tracing::{event, Level};
+
+    /// An operation and everything it points to, which is kept alive until
+    /// the kernel is done with it.
+    enum Op {
+        Read {
+            file: Arc<File>,
+            buf: BytesMut,
+            offset: u64,
+        },
+        Write {
+            file: Arc<File>,
+            data: Bytes,
+            offset: u64,
+        },
+    }
+
+    struct Request {
+        op: Op,
+        done: oneshot::Sender<(io::Result<usize>, Op)>,
+    }
+
+    /// A ring that is driven by a dedicated blocking thread. Requests are
+    /// sent to the thread over a channel and completed through oneshots.
+    pub struct UringIo {
+        sender: mpsc::Sender<Request>,
+        _ring_thread: JoinHandleDropGuard<()>,
+    }
+
+    impl UringIo {
+        pub fn try_new(queue_depth: u32) -> Option<Arc<Self>> {
+            let ring = match IoUring::new(queue_depth) {
+                Ok(ring) => ring,
+                Err(err) => {
+                    event!(
+                        Level::WARN,
+                        ?err,
+                        "io_uring is not available, falling back to regular file I/O"
+                    );
+                    return None;
+                }
+            };
+            let (sender, receiver) = mpsc::channel();
+            let ring_thread = spawn_blocking!("filesystem_store_io_uring", move || {
+                run_ring(ring, &receiver);
+            });
+            Some(Arc::new(Self {
+                sender,
+                _ring_thread: ring_thread,
+            }))
+        }
+
+        async fn submit(&self, op: Op) -> Result<(io::Result<usize>, Op), Error> {
+            let (done, done_rx) = oneshot::channel();
+            self.sender
+                .send(Request { op, done })
+                .map_err(|_| make_err!(Code::Internal, "io_uring thread has stopped"))?;
+            done_rx
+                .await
+                .map_err(|_| make_err!(Code::Internal, "io_uring thread dropped a request"))
+        }
+
+        pub async fn read_at(
+            &self,
+            file: Arc<File>,
+            buf: BytesMut,
+            offset: u64,
+        ) -> Result<BytesMut, Error> {
+            match self.submit(Op::Read { file, buf, offset }).await? {
+                (Ok(_), Op::Read { buf, .. }) => Ok(buf),
+                (Err(err), _) => Err(err.into()),
+                (Ok(_), Op::Write { .. }) => unreachable!("io_uring returned a different op"),
+            }
+        }
+
+        pub async fn write_at(
+            &self,
+            file: Arc<File>,
+            data: Bytes,
+            offset: u64,
+        ) -> Result<usize, Error> {
+            let (result, _op) = self.submit(Op::Write { file, data, offset }).await?;
+            result.map_err(Into::into)
+        }
+    }
+
+    /// Submits requests and completes them until every sender is gone and
+    /// nothing is in flight anymore.
+    fn run_ring(mut ring: IoUring, receiver: &mpsc::Receiver<Request>) {
+        let capacity = ring.params().sq_entries() as usize;
+        let mut in_flight: Vec<Option<Request>> = (0..capacity).map(|_| None).collect();
+        let mut in_flight_count = 0;
+        let mut disconnected = false;
+        loop {
+            // Block for new requests only if there is nothing to wait for.
+            while in_flight_count < capacity && !disconnected {
+                let mut request = if in_flight_count == 0 {
+                    match receiver.recv() {
+                        Ok(request) => request,
+                        Err(_) => {
+                            disconnected = true;
+                            break;
+                        }
+                    }
+                } else {
+                    match receiver.try_recv() {
+                        Ok(request) => request,
+                        Err(mpsc::TryRecvError::Empty) => break,
+                        Err(mpsc::TryRecvError::Disconnected) => {
+                            disconnected = true;
+                            break;
+                        }
+                    }
+                };
+                let index = in_flight
+                    .iter()
+                    .position(Option::is_none)
+                    .expect("in_flight has a free slot below capacity");
+                let entry = request.op.entry().user_data(index as u64);
+                in_flight[index] = Some(request);
+                in_flight_count += 1;
+                // Safety: The file and buffer the entry points to are owned
+                // by the request in `in_flight` until the entry completes.
+                while unsafe { ring.submission().push(&entry) }.is_err() {
+                    if let Err(err) = ring.submit() {
+                        event!(Level::ERROR, ?err, "Failed to submit to io_uring");
+                    }
+                }
+            }
+            if in_flight_count == 0 {
+                return;
+            }
+            if let Err(err) = ring.submit_and_wait(1) {
+                if err.kind() != io::ErrorKind::Interrupted {
+                    event!(Level::ERROR, ?err, "Failed to wait on io_uring");
+                }
+                continue;
+            }
+            for cqe in ring.completion() {
+                let index = cqe.user_data() as usize;
+                let Some(Request { mut op, done }) = in_flight[index].take() else {
+                    continue;
+                };
+                in_flight_count -= 1;
+                let result = if cqe.result() < 0 {
+                    Err(io::Error::from_raw_os_error(-cqe.result()))
+                } else {
+                    let len = cqe.result() as usize;
+                    if let Op::Read { buf, .. } = &mut op {
+                        // Safety: The kernel initialized `len` bytes of the
+                        // spare capacity of the buffer.
+                        unsafe { buf.set_len(buf.len() + len) };
+                    }
+                    Ok(len)
+                };
+                // The caller may have given up on the request.
+                let _ = done.send((result, op));
+            }
+        }
+    }
+
+    impl Op {
+        fn entry(&mut self) -> io_uring::squeue::Entry {
+            match self {
+                Op::Read { file, buf, offset } => {
+                    // Moving the `BytesMut` does not move the memory it
+                    // points to, so the pointer stays valid.
+                    let spare_capacity = buf.spare_capacity_mut();
+                    let len = u32::try_from(spare_capacity.len()).unwrap_or(u32::MAX);
+                    let ptr = spare_capacity.as_mut_ptr().cast::<u8>();
+                    opcode::Read::new(types::Fd(file.as_raw_fd()), ptr, len)
+                        .offset(*offset)
+                        .build()
+                }
+                Op::Write { file, data, offset } => {
+                    let len = u32::try_from(data.len()).unwrap_or(u32::MAX);
+                    opcode::Write::new(types::Fd(file.as_raw_fd()), data.as_ptr(), len)
+                        .offset(*offset)
+                        .build()
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(all(feature = "io_uring", target_os = "linux")))]
+mod imp {
+    use std::fs::File;
+    use std::sync::Arc;
+
+    use bytes::{Bytes, BytesMut};
+    use nativelink_error::{make_err, Code, Error};
+    use tracing::{event, Level};
+
+    /// Stand-in for builds without io_uring support, which can never be
+    /// created.
+    pub struct UringIo {
+        _private: (),
+    }
+
+    impl UringIo {
+        pub fn try_new(_queue_depth: u32) -> Option<Arc<Self>> {
+            event!(
+                Level::WARN,
+                "nativelink was built without the io_uring feature, falling back to regular file I/O"
+            );
+            None
+        }
+
+        pub async fn read_at(
+            &self,
+            _file: Arc<File>,
+            _buf: BytesMut,
+            _offset: u64,
+        ) -> Result<BytesMut, Error> {
+            Err(make_err!(Code::Unimplemented, "io_uring is not supported"))
+        }
+
+        pub async fn write_at(
+            &self,
+            _file: Arc<File>,
+            _data: Bytes,
+            _offset: u64,
+        ) -> Result<usize, Error> {
+            Err(make_err!(Code::Unimplemented, "io_uring is not supported"))
+        }
+    }
+}
@@ -16,7 +16,7 @@ use std::pin::Pin;
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use nativelink_config::stores::VerifySpec;
+use nativelink_config::stores::{StoreSpec, VerifySpec};
 use nativelink_error::{make_input_err, Error, ResultExt};
 use nativelink_metric::MetricsComponent;
 use nativelink_util::buf_channel::{
@@ -178,12 +178,14 @@ impl StoreDriver for VerifyStore {
         }
 
         let mut hasher = if self.verify_hash {
-            Some(
-                ActiveOriginContext::get_value(&ACTIVE_HASHER_FUNC)
-                    .err_tip(|| "In verify_store::update")?
-                    .map_or_else(default_digest_hasher_func, |v| *v)
-                    .hasher(),
-            )
+            let hasher_func = ActiveOriginContext::get_value(&ACTIVE_HASHER_FUNC)
+                .err_tip(|| "In verify_store::update")?
+                .map_or_else(default_digest_hasher_func, |v| *v);
+            if let Err(err) = hasher_func.check_digest(&digest) {
+                self.hash_verification_failures.inc();
+                return Err(err).err_tip(|| "In verify_store::update");
+            }
+            Some(hasher_func.hasher())
         } else {
             None
         };
@@ -230,6 +232,19 @@ impl StoreDriver for VerifyStore {
     fn as_any_arc(self: Arc<Self>) -> Arc<dyn std::any::Any + Sync + Send + 'static> {
         self
     }
+
+    async fn reconfigure(&self, spec: &StoreSpec, check_only: bool) -> Result<(), Error> {
+        let StoreSpec::verify(spec) = spec else {
+            return Err(make_input_err!(
+                "VerifyStore can only be reconfigured with a verify spec"
+            ));
+        };
+        self.inner_store
+            .as_store_driver()
+            .reconfigure(&spec.backend, check_only)
+            .await
+            .err_tip(|| "In VerifyStore::reconfigure for backend")
+    }
 }
 
 default_health_status_indicator!(VerifyStore);
@@ -0,0 +1,150 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+use mock_instant::thread_local::MockClock;
+use nativelink_config::stores::{AcRetentionSpec, MemorySpec, StoreSpec};
+use nativelink_error::{Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_proto::build::bazel::remote::execution::v2::ActionResult as ProtoActionResult;
+use nativelink_store::ac_retention_store::{AcRetentionStore, AcRetentionSweepReport};
+use nativelink_store::ac_utils::serialize_and_upload_message;
+use nativelink_store::memory_store::MemoryStore;
+use nativelink_util::action_messages::{ActionResult, ExecutionMetadata};
+use nativelink_util::common::DigestInfo;
+use nativelink_util::digest_hasher::DigestHasherFunc;
+use nativelink_util::instant_wrapper::MockInstantWrapped;
+use nativelink_util::store_trait::{Store, StoreLike};
+use pretty_assertions::assert_eq;
+
+type TestStore = AcRetentionStore<fn() -> MockInstantWrapped>;
+
+fn make_store(max_age_s: u64, max_sweeps_without_hit: u32) -> (Arc<TestStore>, Store) {
+    let backend = Store::new(MemoryStore::new(&MemorySpec::default()));
+    let store = AcRetentionStore::new(
+        &AcRetentionSpec {
+            backend: StoreSpec::memory(MemorySpec::default()),
+            max_age_s,
+            sweep_interval_s: 0,
+            max_sweeps_without_hit,
+        },
+        backend.clone(),
+        MockInstantWrapped::default as fn() -> MockInstantWrapped,
+    );
+    (store, backend)
+}
+
+/// Uploads an ActionResult that completed at `completed_s` and returns its
+/// key. The exit code keeps the keys of ActionResults apart.
+async fn upload_action_result(
+    store: &Arc<TestStore>,
+    completed_s: u64,
+    exit_code: i32,
+) -> Result<DigestInfo, Error> {
+    let action_result: ProtoActionResult = ActionResult {
+        exit_code,
+        execution_metadata: ExecutionMetadata {
+            worker_completed_timestamp: UNIX_EPOCH + Duration::from_secs(completed_s),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+    .into();
+    serialize_and_upload_message(
+        &action_result,
+        store.as_pin(),
+        &mut DigestHasherFunc::Sha256.hasher(),
+    )
+    .await
+}
+
+#[nativelink_test]
+async fn expired_action_result_is_not_found_and_removed() -> Result<(), Error> {
+    let (store, backend) = make_store(100, 0);
+    MockClock::set_time(Duration::from_secs(1000));
+    let key = upload_action_result(&store, 1000, 0).await?;
+
+    MockClock::set_time(Duration::from_secs(1100));
+    assert!(store.has(key).await?.is_some());
+    assert!(store.get_part_unchunked(key, 0, None).await.is_ok());
+
+    MockClock::set_time(Duration::from_secs(1101));
+    assert_eq!(store.has(key).await?, None);
+    assert_eq!(
+        store
+            .get_part_unchunked(key, 0, None)
+            .await
+            .unwrap_err()
+            .code,
+        Code::NotFound
+    );
+    assert_eq!(backend.has(key).await?, None);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn sweep_removes_expired_action_results() -> Result<(), Error> {
+    let (store, backend) = make_store(100, 0);
+    let old_key = upload_action_result(&store, 1000, 0).await?;
+    let fresh_key = upload_action_result(&store, 1900, 1).await?;
+
+    MockClock::set_time(Duration::from_secs(2000));
+    let report = store.sweep().await?;
+    assert_eq!(
+        report,
+        AcRetentionSweepReport {
+            scanned_keys: 2,
+            expired_by_age: 1,
+            expired_without_hit: 0,
+        }
+    );
+    assert_eq!(backend.has(old_key).await?, None);
+    assert!(backend.has(fresh_key).await?.is_some());
+    Ok(())
+}
+
+#[nativelink_test]
+async fn sweep_removes_action_results_without_hits() -> Result<(), Error> {
+    let (store, backend) = make_store(0, 2);
+    let used_key = upload_action_result(&store, 1000, 0).await?;
+    let unused_key = upload_action_result(&store, 1000, 1).await?;
+
+    assert_eq!(store.sweep().await?.expired_without_hit, 0);
+    store.get_part_unchunked(used_key, 0, None).await?;
+    assert_eq!(store.sweep().await?.expired_without_hit, 0);
+    assert_eq!(store.sweep().await?.expired_without_hit, 1);
+
+    assert!(backend.has(used_key).await?.is_some());
+    assert_eq!(backend.has(unused_key).await?, None);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn action_results_without_timestamp_never_expire() -> Result<(), Error> {
+    let (store, _backend) = make_store(100, 0);
+    let action_result = ProtoActionResult::default();
+    let key = serialize_and_upload_message(
+        &action_result,
+        store.as_pin(),
+        &mut DigestHasherFunc::Sha256.hasher(),
+    )
+    .await?;
+
+    MockClock::set_time(Duration::from_secs(1_000_000));
+    assert_eq!(store.sweep().await?.expired_by_age, 0);
+    assert!(store.has(key).await?.is_some());
+    Ok(())
+}
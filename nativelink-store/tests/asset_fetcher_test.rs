@@ -0,0 +1,164 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use hyper::{Body, Request, Response, StatusCode};
+use nativelink_error::{Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_store::asset_fetcher::{AssetFetcher, HttpAssetFetcher};
+use nativelink_store::http_store::HttpClient;
+use pretty_assertions::assert_eq;
+
+/// Serves fixed responses by URI and records the headers of requests.
+#[derive(Default)]
+struct MockHttpClient {
+    responses: HashMap<&'static str, (StatusCode, Vec<(&'static str, &'static str)>, Bytes)>,
+    requests: Mutex<Vec<(String, Option<String>)>>,
+}
+
+#[async_trait]
+impl HttpClient for MockHttpClient {
+    async fn send(&self, request: Request<Body>) -> Result<Response<Body>, Error> {
+        let uri = request.uri().to_string();
+        self.requests.lock().unwrap().push((
+            uri.clone(),
+            request
+                .headers()
+                .get("authorization")
+                .map(|v| v.to_str().unwrap().to_string()),
+        ));
+        let (status, headers, body) = self.responses.get(uri.as_str()).cloned().unwrap_or((
+            StatusCode::NOT_FOUND,
+            Vec::new(),
+            Bytes::new(),
+        ));
+        let mut response = Response::builder().status(status);
+        for (name, value) in headers {
+            response = response.header(name, value);
+        }
+        Ok(response.body(Body::from(body)).unwrap())
+    }
+}
+
+#[nativelink_test]
+async fn follows_redirects_with_headers() -> Result<(), Error> {
+    let client = Arc::new(MockHttpClient {
+        responses: HashMap::from([
+            (
+                "https://example.com/file.tar.gz",
+                (
+                    StatusCode::FOUND,
+                    vec![("location", "/mirror/file.tar.gz")],
+                    Bytes::new(),
+                ),
+            ),
+            (
+                "https://example.com/mirror/file.tar.gz",
+                (StatusCode::OK, Vec::new(), Bytes::from_static(b"content")),
+            ),
+        ]),
+        ..Default::default()
+    });
+    let fetcher = HttpAssetFetcher::new_with_client(client.clone());
+
+    let data = fetcher
+        .fetch(
+            "https://example.com/file.tar.gz",
+            &[("authorization".to_string(), "Bearer token".to_string())],
+            100,
+        )
+        .await?;
+    assert_eq!(data, Bytes::from_static(b"content"));
+    assert_eq!(
+        *client.requests.lock().unwrap(),
+        vec![
+            (
+                "https://example.com/file.tar.gz".to_string(),
+                Some("Bearer token".to_string())
+            ),
+            (
+                "https://example.com/mirror/file.tar.gz".to_string(),
+                Some("Bearer token".to_string())
+            ),
+        ]
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn content_larger_than_max_size_is_rejected() -> Result<(), Error> {
+    let fetcher = HttpAssetFetcher::new_with_client(Arc::new(MockHttpClient {
+        responses: HashMap::from([(
+            "https://example.com/large",
+            (
+                StatusCode::OK,
+                Vec::new(),
+                Bytes::from_static(b"0123456789"),
+            ),
+        )]),
+        ..Default::default()
+    }));
+
+    let err = fetcher
+        .fetch("https://example.com/large", &[], 9)
+        .await
+        .unwrap_err();
+    assert_eq!(err.code, Code::ResourceExhausted);
+    assert!(fetcher
+        .fetch("https://example.com/large", &[], 10)
+        .await
+        .is_ok());
+    Ok(())
+}
+
+#[nativelink_test]
+async fn error_statuses_and_schemes_are_mapped() -> Result<(), Error> {
+    let fetcher = HttpAssetFetcher::new_with_client(Arc::new(MockHttpClient {
+        responses: HashMap::from([(
+            "https://example.com/private",
+            (StatusCode::FORBIDDEN, Vec::new(), Bytes::new()),
+        )]),
+        ..Default::default()
+    }));
+
+    assert_eq!(
+        fetcher
+            .fetch("https://example.com/missing", &[], 100)
+            .await
+            .unwrap_err()
+            .code,
+        Code::NotFound
+    );
+    assert_eq!(
+        fetcher
+            .fetch("https://example.com/private", &[], 100)
+            .await
+            .unwrap_err()
+            .code,
+        Code::PermissionDenied
+    );
+    assert_eq!(
+        fetcher
+            .fetch("ftp://example.com/file", &[], 100)
+            .await
+            .unwrap_err()
+            .code,
+        Code::InvalidArgument
+    );
+    Ok(())
+}
@@ -0,0 +1,307 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use hyper::{Body, Method, Request, Response, StatusCode};
+use nativelink_config::stores::{AzureBlobSpec, Retry};
+use nativelink_error::{Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_store::azure_blob_store::{AzureBlobStore, AzureHttpClient};
+use nativelink_util::common::DigestInfo;
+use nativelink_util::instant_wrapper::MockInstantWrapped;
+use nativelink_util::store_trait::StoreLike;
+use pretty_assertions::assert_eq;
+
+const ACCOUNT_NAME: &str = "dummyaccount";
+const CONTAINER_NAME: &str = "dummy-container";
+const VALID_HASH1: &str = "0123456789abcdef000000000000000000010000000000000123456789abcdef";
+const SAS_TOKEN: &str = "sv=2021-08-06&sig=dummy";
+const BLOCK_SIZE: usize = 16;
+
+struct ExpectedRequest {
+    method: Method,
+    uri: String,
+    headers: Vec<(&'static str, String)>,
+    body: Option<Bytes>,
+    response: (StatusCode, Vec<(&'static str, String)>, Bytes),
+}
+
+impl ExpectedRequest {
+    fn new(method: Method, uri: impl Into<String>) -> Self {
+        Self {
+            method,
+            uri: uri.into(),
+            headers: Vec::new(),
+            body: None,
+            response: (StatusCode::CREATED, Vec::new(), Bytes::new()),
+        }
+    }
+
+    fn header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    fn body(mut self, body: impl Into<Bytes>) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+
+    fn respond(mut self, status: StatusCode, headers: Vec<(&'static str, String)>) -> Self {
+        self.response = (status, headers, Bytes::new());
+        self
+    }
+
+    fn respond_with_body(mut self, status: StatusCode, body: impl Into<Bytes>) -> Self {
+        self.response = (status, Vec::new(), body.into());
+        self
+    }
+}
+
+/// Replays the expected requests in order. Block ids are random, so they are
+/// replaced with `*` before comparing URIs and recorded in `block_ids`.
+#[derive(Default)]
+struct MockHttpClient {
+    expected: Mutex<VecDeque<ExpectedRequest>>,
+    block_ids: Mutex<Vec<String>>,
+}
+
+impl MockHttpClient {
+    fn new(expected: Vec<ExpectedRequest>) -> Arc<Self> {
+        Arc::new(Self {
+            expected: Mutex::new(expected.into()),
+            block_ids: Mutex::default(),
+        })
+    }
+
+    fn assert_done(&self) {
+        assert!(
+            self.expected.lock().unwrap().is_empty(),
+            "Not all expected requests were sent"
+        );
+    }
+}
+
+#[async_trait]
+impl AzureHttpClient for MockHttpClient {
+    async fn send(&self, request: Request<Body>) -> Result<Response<Body>, Error> {
+        let expected = self
+            .expected
+            .lock()
+            .unwrap()
+            .pop_front()
+            .unwrap_or_else(|| panic!("Unexpected request {request:?}"));
+        let mut uri = request.uri().to_string();
+        if let Some(start) = uri.find("blockid=").map(|i| i + "blockid=".len()) {
+            let end = uri[start..].find('&').map_or(uri.len(), |i| start + i);
+            self.block_ids
+                .lock()
+                .unwrap()
+                .push(uri[start..end].to_string());
+            uri.replace_range(start..end, "*");
+        }
+        assert_eq!(request.method(), &expected.method);
+        assert_eq!(uri, expected.uri);
+        assert_eq!(request.headers()["x-ms-version"], "2021-08-06");
+        for (name, value) in &expected.headers {
+            assert_eq!(request.headers()[*name], value, "Mismatched {name} header");
+        }
+        let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+        if let Some(expected_body) = &expected.body {
+            assert_eq!(&body, expected_body);
+        }
+        let (status, headers, body) = expected.response;
+        let mut response = Response::builder().status(status);
+        for (name, value) in headers {
+            response = response.header(name, value);
+        }
+        Ok(response.body(Body::from(body)).unwrap())
+    }
+}
+
+fn make_store(
+    spec: AzureBlobSpec,
+    http_client: Arc<MockHttpClient>,
+) -> Result<Arc<AzureBlobStore<fn() -> MockInstantWrapped>>, Error> {
+    AzureBlobStore::new_with_client_and_jitter(
+        &AzureBlobSpec {
+            account_name: ACCOUNT_NAME.to_string(),
+            container: CONTAINER_NAME.to_string(),
+            retry: Retry {
+                max_retries: 3,
+                ..Default::default()
+            },
+            block_size: BLOCK_SIZE,
+            ..spec
+        },
+        http_client,
+        Arc::new(move |_delay| Duration::from_secs(0)),
+        MockInstantWrapped::default as fn() -> MockInstantWrapped,
+    )
+}
+
+fn sas_spec() -> AzureBlobSpec {
+    AzureBlobSpec {
+        sas_token: Some(format!("?{SAS_TOKEN}")),
+        ..Default::default()
+    }
+}
+
+fn blob_url(digest: DigestInfo) -> String {
+    format!("https://{ACCOUNT_NAME}.blob.core.windows.net/{CONTAINER_NAME}/{digest}")
+}
+
+#[nativelink_test]
+async fn has_blob_found_and_missing() -> Result<(), Error> {
+    let found = DigestInfo::try_new(VALID_HASH1, 100)?;
+    let missing = DigestInfo::try_new(VALID_HASH1, 101)?;
+    let http_client = MockHttpClient::new(vec![
+        ExpectedRequest::new(Method::HEAD, format!("{}?{SAS_TOKEN}", blob_url(found)))
+            .respond(StatusCode::OK, vec![("content-length", "512".to_string())]),
+        ExpectedRequest::new(Method::HEAD, format!("{}?{SAS_TOKEN}", blob_url(missing)))
+            .respond(StatusCode::NOT_FOUND, Vec::new()),
+    ]);
+    let store = make_store(sas_spec(), http_client.clone())?;
+
+    assert_eq!(store.has(found).await, Ok(Some(512)));
+    assert_eq!(store.has(missing).await, Ok(None));
+    http_client.assert_done();
+    Ok(())
+}
+
+#[nativelink_test]
+async fn managed_identity_token_is_fetched_once() -> Result<(), Error> {
+    let digest = DigestInfo::try_new(VALID_HASH1, 100)?;
+    let http_client = MockHttpClient::new(vec![
+        ExpectedRequest::new(
+            Method::GET,
+            "http://169.254.169.254/metadata/identity/oauth2/token?api-version=2018-02-01&resource=https%3A%2F%2Fstorage.azure.com%2F&client_id=dummy-client-id",
+        )
+        .header("metadata", "true")
+        .respond_with_body(
+            StatusCode::OK,
+            r#"{"access_token":"dummy-access-token","expires_in":"3599"}"#,
+        ),
+        ExpectedRequest::new(Method::HEAD, blob_url(digest))
+            .header("authorization", "Bearer dummy-access-token")
+            .respond(StatusCode::OK, vec![("content-length", "100".to_string())]),
+        ExpectedRequest::new(Method::HEAD, blob_url(digest))
+            .header("authorization", "Bearer dummy-access-token")
+            .respond(StatusCode::OK, vec![("content-length", "100".to_string())]),
+    ]);
+    let store = make_store(
+        AzureBlobSpec {
+            managed_identity_client_id: Some("dummy-client-id".to_string()),
+            ..Default::default()
+        },
+        http_client.clone(),
+    )?;
+
+    assert_eq!(store.has(digest).await, Ok(Some(100)));
+    assert_eq!(store.has(digest).await, Ok(Some(100)));
+    http_client.assert_done();
+    Ok(())
+}
+
+#[nativelink_test]
+async fn get_part_requests_range() -> Result<(), Error> {
+    let digest = DigestInfo::try_new(VALID_HASH1, 10)?;
+    let http_client = MockHttpClient::new(vec![ExpectedRequest::new(
+        Method::GET,
+        format!("{}?{SAS_TOKEN}", blob_url(digest)),
+    )
+    .header("x-ms-range", "bytes=2-5")
+    .respond_with_body(StatusCode::PARTIAL_CONTENT, "2345")]);
+    let store = make_store(sas_spec(), http_client.clone())?;
+
+    let data = store.get_part_unchunked(digest, 2, Some(4)).await?;
+    assert_eq!(data, Bytes::from_static(b"2345"));
+    http_client.assert_done();
+    Ok(())
+}
+
+#[nativelink_test]
+async fn get_part_missing_blob_is_not_found() -> Result<(), Error> {
+    let digest = DigestInfo::try_new(VALID_HASH1, 10)?;
+    let http_client = MockHttpClient::new(vec![ExpectedRequest::new(
+        Method::GET,
+        format!("{}?{SAS_TOKEN}", blob_url(digest)),
+    )
+    .header("x-ms-range", "bytes=0-")
+    .respond(StatusCode::NOT_FOUND, Vec::new())]);
+    let store = make_store(sas_spec(), http_client.clone())?;
+
+    let err = store.get_part_unchunked(digest, 0, None).await.unwrap_err();
+    assert_eq!(err.code, Code::NotFound, "{err:?}");
+    http_client.assert_done();
+    Ok(())
+}
+
+#[nativelink_test]
+async fn small_upload_is_sent_in_one_request() -> Result<(), Error> {
+    let data = Bytes::from_static(b"0123456789");
+    let digest = DigestInfo::try_new(VALID_HASH1, data.len())?;
+    let http_client = MockHttpClient::new(vec![
+        // The first attempt fails and is retried from memory.
+        ExpectedRequest::new(Method::PUT, format!("{}?{SAS_TOKEN}", blob_url(digest)))
+            .header("x-ms-blob-type", "BlockBlob")
+            .body(data.clone())
+            .respond(StatusCode::SERVICE_UNAVAILABLE, Vec::new()),
+        ExpectedRequest::new(Method::PUT, format!("{}?{SAS_TOKEN}", blob_url(digest)))
+            .header("x-ms-blob-type", "BlockBlob")
+            .body(data.clone()),
+    ]);
+    let store = make_store(sas_spec(), http_client.clone())?;
+
+    store.update_oneshot(digest, data).await?;
+    http_client.assert_done();
+    Ok(())
+}
+
+#[nativelink_test]
+async fn large_upload_stages_and_commits_blocks() -> Result<(), Error> {
+    const TOTAL_LEN: usize = BLOCK_SIZE + 4;
+    let data: Bytes = (0..TOTAL_LEN).map(|i| i as u8).collect();
+    let digest = DigestInfo::try_new(VALID_HASH1, TOTAL_LEN)?;
+    let block_url = format!("{}?comp=block&blockid=*&{SAS_TOKEN}", blob_url(digest));
+    let http_client = MockHttpClient::new(vec![
+        ExpectedRequest::new(Method::PUT, &block_url).body(data.slice(..BLOCK_SIZE)),
+        // A failed block is retried on its own.
+        ExpectedRequest::new(Method::PUT, &block_url)
+            .body(data.slice(BLOCK_SIZE..))
+            .respond(StatusCode::INTERNAL_SERVER_ERROR, Vec::new()),
+        ExpectedRequest::new(Method::PUT, &block_url).body(data.slice(BLOCK_SIZE..)),
+        ExpectedRequest::new(
+            Method::PUT,
+            format!("{}?comp=blocklist&{SAS_TOKEN}", blob_url(digest)),
+        ),
+    ]);
+    let store = make_store(sas_spec(), http_client.clone())?;
+
+    store.update_oneshot(digest, data).await?;
+    http_client.assert_done();
+    let block_ids = http_client.block_ids.lock().unwrap().clone();
+    assert_eq!(block_ids.len(), 3);
+    assert_eq!(
+        block_ids[1], block_ids[2],
+        "Retry should reuse the block id"
+    );
+    assert!(block_ids[0] != block_ids[1], "Blocks need distinct ids");
+    Ok(())
+}
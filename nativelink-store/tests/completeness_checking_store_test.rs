@@ -15,18 +15,21 @@
 use std::sync::Arc;
 
 use nativelink_config::stores::MemorySpec;
-use nativelink_error::Error;
+use nativelink_error::{Code, Error};
 use nativelink_macro::nativelink_test;
 use nativelink_proto::build::bazel::remote::execution::v2::{
     ActionResult as ProtoActionResult, Directory, DirectoryNode, FileNode, OutputDirectory,
     OutputFile, Tree,
 };
 use nativelink_store::ac_utils::serialize_and_upload_message;
-use nativelink_store::completeness_checking_store::CompletenessCheckingStore;
+use nativelink_store::completeness_checking_store::{
+    CompletenessCheckingStore, ReachabilityGcReport,
+};
 use nativelink_store::memory_store::MemoryStore;
 use nativelink_util::common::DigestInfo;
 use nativelink_util::digest_hasher::DigestHasherFunc;
 use nativelink_util::store_trait::{Store, StoreLike};
+use pretty_assertions::assert_eq;
 
 const ROOT_FILE: DigestInfo = DigestInfo::new([0u8; 32], 0);
 const ROOT_DIRECTORY: DigestInfo = DigestInfo::new([1u8; 32], 0);
@@ -34,6 +37,7 @@ const CHILD_FILE: DigestInfo = DigestInfo::new([2u8; 32], 0);
 const OUTPUT_FILE: DigestInfo = DigestInfo::new([4u8; 32], 0);
 const STDOUT: DigestInfo = DigestInfo::new([5u8; 32], 0);
 const STDERR: DigestInfo = DigestInfo::new([6u8; 32], 0);
+const UNREFERENCED: DigestInfo = DigestInfo::new([7u8; 32], 0);
 
 async fn setup() -> Result<(Arc<CompletenessCheckingStore>, Arc<MemoryStore>, DigestInfo), Error> {
     let backend_store = Store::new(MemoryStore::new(&MemorySpec::default()));
@@ -245,3 +249,63 @@ async fn verify_completeness_get() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[nativelink_test]
+async fn gc_marks_reachable_digests() -> Result<(), Error> {
+    let (ac_store, cas_store, _action_result_digest) = setup().await?;
+    cas_store.remove(STDERR).await?;
+
+    assert_eq!(
+        ac_store.gc(false).await?,
+        ReachabilityGcReport {
+            action_results: 1,
+            // Output file, stdout, stderr, the tree and the two files in it.
+            reachable_digests: 6,
+            missing_digests: 1,
+            removed_digests: 0,
+            removed_bytes: 0,
+        }
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn gc_removes_unreachable_digests_after_grace_period() -> Result<(), Error> {
+    let (ac_store, cas_store, _action_result_digest) = setup().await?;
+    cas_store.update_oneshot(UNREFERENCED, "".into()).await?;
+
+    // The first garbage collection only remembers unreachable digests.
+    assert_eq!(ac_store.gc(true).await?.removed_digests, 0);
+    assert!(cas_store.has(UNREFERENCED).await?.is_some());
+
+    // The serialized output directory is not referenced either.
+    assert_eq!(ac_store.gc(true).await?.removed_digests, 2);
+    assert_eq!(cas_store.has(UNREFERENCED).await?, None);
+    for digest in [ROOT_FILE, CHILD_FILE, OUTPUT_FILE, STDOUT, STDERR] {
+        assert!(
+            cas_store.has(digest).await?.is_some(),
+            "Expected reachable {digest} to be kept"
+        );
+    }
+    Ok(())
+}
+
+#[nativelink_test]
+async fn get_returns_not_found_with_missing_outputs() -> Result<(), Error> {
+    for missing_digest in [OUTPUT_FILE, CHILD_FILE, STDOUT, STDERR] {
+        let (ac_store, cas_store, action_result_digest) = setup().await?;
+
+        cas_store.remove_entry(missing_digest.into()).await;
+
+        let err = ac_store
+            .get_part_unchunked(action_result_digest, 0, None)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.code,
+            Code::NotFound,
+            "Expected NotFound with {missing_digest:?} missing, got {err:?}"
+        );
+    }
+    Ok(())
+}
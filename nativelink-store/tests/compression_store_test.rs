@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use std::cmp;
+use std::env;
 use std::io::Cursor;
 use std::pin::Pin;
 use std::str::from_utf8;
@@ -20,12 +21,15 @@ use std::sync::Arc;
 
 use bincode::{DefaultOptions, Options};
 use bytes::Bytes;
-use nativelink_config::stores::{CompressionSpec, MemorySpec, StoreSpec};
+use nativelink_config::stores::{
+    CompressionSizeBand, CompressionSpec, MemorySpec, StoreSpec, ZstdConfig,
+};
 use nativelink_error::{make_err, Code, Error, ResultExt};
 use nativelink_macro::nativelink_test;
 use nativelink_store::compression_store::{
     CompressionStore, Footer, Lz4Config, SliceIndex, CURRENT_STREAM_FORMAT_VERSION,
-    DEFAULT_BLOCK_SIZE, FOOTER_FRAME_TYPE,
+    DEFAULT_BLOCK_SIZE, FOOTER_FRAME_TYPE, STORED_STREAM_FORMAT_VERSION,
+    ZSTD_STREAM_FORMAT_VERSION,
 };
 use nativelink_store::memory_store::MemoryStore;
 use nativelink_util::buf_channel::make_buf_channel_pair;
@@ -65,6 +69,19 @@ fn extract_footer(data: &[u8]) -> Result<Footer, Error> {
         .map_err(|e| make_err!(Code::Internal, "Failed to deserialize header : {:?}", e))
 }
 
+fn make_temp_path(data: &str) -> String {
+    format!(
+        "{}/{}/{}",
+        env::var("TEST_TMPDIR").unwrap_or(env::temp_dir().to_str().unwrap().to_string()),
+        rand::thread_rng().gen::<u64>(),
+        data
+    )
+}
+
+/// Size of the header of a stream, which is followed by the dictionary id
+/// in zstd streams.
+const HEADER_SIZE: usize = 1 + 4 + 4 + 8;
+
 const VALID_HASH: &str = "0123456789abcdef000000000000000000010000000000000123456789abcdef";
 const DUMMY_DATA_SIZE: usize = 100; // Some dummy size to populate DigestInfo with.
 const MEGABYTE_SZ: usize = 1024 * 1024;
@@ -81,6 +98,9 @@ async fn simple_smoke_test() -> Result<(), Error> {
                     ..Default::default()
                 },
             ),
+            min_compression_size: 0,
+            max_compression_size: 0,
+            size_bands: vec![],
         },
         Store::new(MemoryStore::new(&MemorySpec::default())),
     )
@@ -119,6 +139,9 @@ async fn partial_reads_test() -> Result<(), Error> {
                     ..Default::default()
                 },
             ),
+            min_compression_size: 0,
+            max_compression_size: 0,
+            size_bands: vec![],
         },
         Store::new(MemoryStore::new(&MemorySpec::default())),
     )
@@ -167,6 +190,9 @@ async fn rand_5mb_smoke_test() -> Result<(), Error> {
                     ..Default::default()
                 },
             ),
+            min_compression_size: 0,
+            max_compression_size: 0,
+            size_bands: vec![],
         },
         Store::new(MemoryStore::new(&MemorySpec::default())),
     )
@@ -200,6 +226,9 @@ async fn sanity_check_zero_bytes_test() -> Result<(), Error> {
                     ..Default::default()
                 },
             ),
+            min_compression_size: 0,
+            max_compression_size: 0,
+            size_bands: vec![],
         },
         Store::new(inner_store.clone()),
     )
@@ -256,6 +285,9 @@ async fn check_header_test() -> Result<(), Error> {
                     ..Default::default()
                 },
             ),
+            min_compression_size: 0,
+            max_compression_size: 0,
+            size_bands: vec![],
         },
         Store::new(inner_store.clone()),
     )
@@ -342,6 +374,9 @@ async fn check_footer_test() -> Result<(), Error> {
                     ..Default::default()
                 },
             ),
+            min_compression_size: 0,
+            max_compression_size: 0,
+            size_bands: vec![],
         },
         Store::new(inner_store.clone()),
     )
@@ -488,6 +523,9 @@ async fn get_part_is_zero_digest() -> Result<(), Error> {
                     ..Default::default()
                 },
             ),
+            min_compression_size: 0,
+            max_compression_size: 0,
+            size_bands: vec![],
         },
         Store::new(inner_store.clone()),
     )
@@ -514,3 +552,228 @@ async fn get_part_is_zero_digest() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[nativelink_test]
+async fn zstd_round_trip_test() -> Result<(), Error> {
+    const BLOCK_SIZE: u32 = 1000;
+    let raw_data: Vec<u8> = (0..1000)
+        .flat_map(|i| format!("line {i}\n").into_bytes())
+        .collect();
+
+    let inner_store = MemoryStore::new(&MemorySpec::default());
+    let store = CompressionStore::new(
+        &CompressionSpec {
+            backend: StoreSpec::memory(MemorySpec::default()),
+            compression_algorithm: nativelink_config::stores::CompressionAlgorithm::zstd(
+                ZstdConfig {
+                    block_size: BLOCK_SIZE,
+                    ..Default::default()
+                },
+            ),
+            min_compression_size: 0,
+            max_compression_size: 0,
+            size_bands: vec![],
+        },
+        Store::new(inner_store.clone()),
+    )
+    .err_tip(|| "Failed to create compression store")?;
+
+    let digest = DigestInfo::try_new(VALID_HASH, raw_data.len()).unwrap();
+    store
+        .update_oneshot(digest, Bytes::from(raw_data.clone()))
+        .await?;
+
+    let compressed_data = inner_store.get_part_unchunked(digest, 0, None).await?;
+    assert_eq!(compressed_data[0], ZSTD_STREAM_FORMAT_VERSION);
+    assert_eq!(
+        u32::from_le_bytes(
+            compressed_data[HEADER_SIZE..HEADER_SIZE + 4]
+                .try_into()
+                .unwrap()
+        ),
+        0,
+        "Expected no dictionary to be used"
+    );
+    assert!(
+        compressed_data.len() < raw_data.len(),
+        "Expected data to be compressed"
+    );
+
+    assert_eq!(store.get_part_unchunked(digest, 0, None).await?, raw_data);
+    // A read across block boundaries.
+    assert_eq!(
+        store.get_part_unchunked(digest, 2500, Some(3000)).await?,
+        &raw_data[2500..5500]
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn zstd_dictionary_is_trained_and_recorded_in_header_test() -> Result<(), Error> {
+    const RAW_INPUT: &str = "message Request { string instance_name = 1; int32 id = 7; }";
+
+    let samples_path = make_temp_path("samples");
+    std::fs::create_dir_all(&samples_path)?;
+    let mut rng = SmallRng::seed_from_u64(1);
+    for i in 0..500 {
+        let sample = format!(
+            "message Request{i} {{ string instance_name = {}; int32 id = {}; bytes data = {}; }}",
+            rng.gen::<u8>(),
+            rng.gen::<u16>(),
+            rng.gen::<u32>(),
+        );
+        std::fs::write(format!("{samples_path}/{i}"), sample)?;
+    }
+    let dictionary_path = format!("{samples_path}.dict");
+
+    let inner_store = MemoryStore::new(&MemorySpec::default());
+    let make_store = |zstd_config: ZstdConfig| {
+        CompressionStore::new(
+            &CompressionSpec {
+                backend: StoreSpec::memory(MemorySpec::default()),
+                compression_algorithm: nativelink_config::stores::CompressionAlgorithm::zstd(
+                    zstd_config,
+                ),
+                min_compression_size: 0,
+                max_compression_size: 0,
+                size_bands: vec![],
+            },
+            Store::new(inner_store.clone()),
+        )
+    };
+    let store = make_store(ZstdConfig {
+        dictionary_path: dictionary_path.clone(),
+        dictionary_training_samples_path: samples_path,
+        dictionary_max_size: 4096,
+        ..Default::default()
+    })?;
+    let dictionary = std::fs::read(&dictionary_path)?;
+    let dictionary_id = zstd::zstd_safe::get_dict_id_from_dict(&dictionary)
+        .expect("Expected a trained zstd dictionary")
+        .get();
+
+    let digest = DigestInfo::try_new(VALID_HASH, RAW_INPUT.len()).unwrap();
+    store.update_oneshot(digest, RAW_INPUT.into()).await?;
+    let compressed_data = inner_store.get_part_unchunked(digest, 0, None).await?;
+    assert_eq!(
+        u32::from_le_bytes(
+            compressed_data[HEADER_SIZE..HEADER_SIZE + 4]
+                .try_into()
+                .unwrap()
+        ),
+        dictionary_id,
+        "Expected the dictionary id in the header"
+    );
+    assert_eq!(
+        store.get_part_unchunked(digest, 0, None).await?,
+        RAW_INPUT.as_bytes()
+    );
+
+    // A store that compresses without a dictionary can still read the blob
+    // with the old dictionary.
+    let store = make_store(ZstdConfig {
+        decompression_dictionary_paths: vec![dictionary_path],
+        ..Default::default()
+    })?;
+    assert_eq!(
+        store.get_part_unchunked(digest, 0, None).await?,
+        RAW_INPUT.as_bytes()
+    );
+
+    // Without the dictionary the blob can not be decompressed.
+    let store = make_store(ZstdConfig::default())?;
+    let err = store.get_part_unchunked(digest, 0, None).await.unwrap_err();
+    assert!(
+        err.to_string().contains("unknown zstd dictionary"),
+        "Unexpected error: {err:?}"
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn uploads_outside_compression_sizes_are_stored_test() -> Result<(), Error> {
+    let inner_store = MemoryStore::new(&MemorySpec::default());
+    let store = CompressionStore::new(
+        &CompressionSpec {
+            backend: StoreSpec::memory(MemorySpec::default()),
+            compression_algorithm: nativelink_config::stores::CompressionAlgorithm::lz4(
+                nativelink_config::stores::Lz4Config {
+                    block_size: 1000,
+                    ..Default::default()
+                },
+            ),
+            min_compression_size: 100,
+            max_compression_size: 10_000,
+            size_bands: vec![],
+        },
+        Store::new(inner_store.clone()),
+    )
+    .err_tip(|| "Failed to create compression store")?;
+
+    for (size, expected_version) in [
+        (10, STORED_STREAM_FORMAT_VERSION),
+        (5_000, CURRENT_STREAM_FORMAT_VERSION),
+        (20_000, STORED_STREAM_FORMAT_VERSION),
+    ] {
+        let raw_data = vec![b'a'; size];
+        let digest = DigestInfo::try_new(VALID_HASH, size).unwrap();
+        store
+            .update_oneshot(digest, Bytes::from(raw_data.clone()))
+            .await?;
+        let compressed_data = inner_store.get_part_unchunked(digest, 0, None).await?;
+        assert_eq!(
+            compressed_data[0], expected_version,
+            "Unexpected stream version for {size} bytes"
+        );
+        assert_eq!(store.get_part_unchunked(digest, 0, None).await?, raw_data);
+        assert_eq!(
+            store.get_part_unchunked(digest, 5, Some(3)).await?,
+            &raw_data[5..8]
+        );
+    }
+    Ok(())
+}
+
+#[nativelink_test]
+async fn size_bands_select_algorithm_test() -> Result<(), Error> {
+    let inner_store = MemoryStore::new(&MemorySpec::default());
+    let store = CompressionStore::new(
+        &CompressionSpec {
+            backend: StoreSpec::memory(MemorySpec::default()),
+            compression_algorithm: nativelink_config::stores::CompressionAlgorithm::lz4(
+                nativelink_config::stores::Lz4Config::default(),
+            ),
+            min_compression_size: 0,
+            max_compression_size: 0,
+            size_bands: vec![CompressionSizeBand {
+                max_size: 1000,
+                compression_algorithm: nativelink_config::stores::CompressionAlgorithm::zstd(
+                    ZstdConfig {
+                        level: 19,
+                        ..Default::default()
+                    },
+                ),
+            }],
+        },
+        Store::new(inner_store.clone()),
+    )
+    .err_tip(|| "Failed to create compression store")?;
+
+    for (size, expected_version) in [
+        (1000, ZSTD_STREAM_FORMAT_VERSION),
+        (1001, CURRENT_STREAM_FORMAT_VERSION),
+    ] {
+        let raw_data: Vec<u8> = (0..size).map(|i| (i % 7) as u8).collect();
+        let digest = DigestInfo::try_new(VALID_HASH, size).unwrap();
+        store
+            .update_oneshot(digest, Bytes::from(raw_data.clone()))
+            .await?;
+        let compressed_data = inner_store.get_part_unchunked(digest, 0, None).await?;
+        assert_eq!(
+            compressed_data[0], expected_version,
+            "Unexpected stream version for {size} bytes"
+        );
+        assert_eq!(store.get_part_unchunked(digest, 0, None).await?, raw_data);
+    }
+    Ok(())
+}
@@ -0,0 +1,101 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bytes::Bytes;
+use futures::task::Poll;
+use futures::{poll, FutureExt};
+use nativelink_config::stores::{ConcurrencyLimitSpec, MemorySpec, StoreSpec};
+use nativelink_error::{Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_store::concurrency_limit_store::ConcurrencyLimitStore;
+use nativelink_store::memory_store::MemoryStore;
+use nativelink_util::buf_channel::make_buf_channel_pair;
+use nativelink_util::common::DigestInfo;
+use nativelink_util::store_trait::{Store, StoreLike, UploadSizeInfo};
+use pretty_assertions::assert_eq;
+
+const VALID_HASH1: &str = "0123456789abcdef000000000000000000010000000000000123456789abcdef";
+const VALID_HASH2: &str = "0123456789abcdef000000000000000000020000000000000123456789abcdef";
+const VALUE: &str = "123";
+
+fn make_spec(max_concurrent_requests: usize, queue_timeout_ms: u64) -> ConcurrencyLimitSpec {
+    ConcurrencyLimitSpec {
+        backend: StoreSpec::memory(MemorySpec::default()),
+        max_concurrent_requests,
+        queue_timeout_ms,
+    }
+}
+
+#[nativelink_test]
+async fn requests_over_limit_wait_for_a_slot() -> Result<(), Error> {
+    let digest1 = DigestInfo::try_new(VALID_HASH1, VALUE.len())?;
+    let digest2 = DigestInfo::try_new(VALID_HASH2, VALUE.len())?;
+    let store = ConcurrencyLimitStore::new(
+        &make_spec(1, 0),
+        Store::new(MemoryStore::new(&MemorySpec::default())),
+    )?;
+    store.update_oneshot(digest2, VALUE.into()).await?;
+
+    // Keep an upload in flight by not sending EOF yet.
+    let (mut tx, rx) = make_buf_channel_pair();
+    let mut update_fut = store
+        .update(digest1, rx, UploadSizeInfo::ExactSize(VALUE.len() as u64))
+        .boxed();
+    assert_eq!(poll!(&mut update_fut), Poll::Pending);
+    assert_eq!(store.available_permits(), 0);
+
+    let mut get_fut = store.get_part_unchunked(digest2, 0, None).boxed();
+    assert_eq!(poll!(&mut get_fut), Poll::Pending);
+
+    tx.send(VALUE.into()).await?;
+    tx.send_eof()?;
+    update_fut.await?;
+    assert_eq!(get_fut.await?, Bytes::from_static(VALUE.as_bytes()));
+    assert_eq!(store.available_permits(), 1);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn queued_requests_time_out() -> Result<(), Error> {
+    let digest = DigestInfo::try_new(VALID_HASH1, VALUE.len())?;
+    let store = ConcurrencyLimitStore::new(
+        &make_spec(1, 10),
+        Store::new(MemoryStore::new(&MemorySpec::default())),
+    )?;
+
+    let (mut tx, rx) = make_buf_channel_pair();
+    let mut update_fut = store
+        .update(digest, rx, UploadSizeInfo::ExactSize(VALUE.len() as u64))
+        .boxed();
+    assert_eq!(poll!(&mut update_fut), Poll::Pending);
+
+    let err = store.get_part_unchunked(digest, 0, None).await.unwrap_err();
+    assert_eq!(err.code, Code::ResourceExhausted, "{err:?}");
+
+    tx.send(VALUE.into()).await?;
+    tx.send_eof()?;
+    update_fut.await?;
+    assert_eq!(
+        store.get_part_unchunked(digest, 0, None).await?,
+        Bytes::from_static(VALUE.as_bytes())
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn zero_limit_is_rejected() -> Result<(), Error> {
+    let inner_store = Store::new(MemoryStore::new(&MemorySpec::default()));
+    assert!(ConcurrencyLimitStore::new(&make_spec(0, 0), inner_store).is_err());
+    Ok(())
+}
@@ -12,14 +12,28 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use nativelink_config::stores::{DedupSpec, MemorySpec, StoreSpec};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bincode::{DefaultOptions, Options};
+use bytes::Bytes;
+use futures::join;
+use nativelink_config::stores::{DedupChunker, DedupSpec, MemorySpec, StoreSpec};
 use nativelink_error::{Code, Error, ResultExt};
 use nativelink_macro::nativelink_test;
+use nativelink_metric::MetricsComponent;
 use nativelink_store::cas_utils::ZERO_BYTE_DIGESTS;
-use nativelink_store::dedup_store::DedupStore;
+use nativelink_store::dedup_store::{DedupGcReport, DedupIndex, DedupStore};
 use nativelink_store::memory_store::MemoryStore;
+use nativelink_util::buf_channel::{
+    make_buf_channel_pair, DropCloserReadHalf, DropCloserWriteHalf,
+};
 use nativelink_util::common::DigestInfo;
-use nativelink_util::store_trait::{Store, StoreLike};
+use nativelink_util::health_utils::{default_health_status_indicator, HealthStatusIndicator};
+use nativelink_util::store_trait::{Store, StoreDriver, StoreKey, StoreLike, UploadSizeInfo};
 use pretty_assertions::assert_eq;
 use rand::rngs::SmallRng;
 use rand::{Rng, SeedableRng};
@@ -32,6 +46,8 @@ fn make_default_config() -> DedupSpec {
         normal_size: 32 * 1024,
         max_size: 128 * 1024,
         max_concurrent_fetch_per_get: 10,
+        chunker: DedupChunker::fastcdc,
+        gc_interval_s: 0,
     }
 }
 
@@ -44,6 +60,7 @@ fn make_random_data(sz: usize) -> Vec<u8> {
 
 const VALID_HASH1: &str = "0123456789abcdef000000000000000000010000000000000123456789abcdef";
 const VALID_HASH2: &str = "0123456789abcdef000000000000000000020000000000000123456789abcdef";
+const VALID_HASH3: &str = "0123456789abcdef000000000000000000030000000000000123456789abcdef";
 const MEGABYTE_SZ: usize = 1024 * 1024;
 
 #[nativelink_test]
@@ -167,6 +184,8 @@ async fn check_length_not_set_with_chunk_read_beyond_first_chunk_regression_test
             normal_size: 6,
             max_size: 7,
             max_concurrent_fetch_per_get: 10,
+            chunker: DedupChunker::fastcdc,
+            gc_interval_s: 0,
         },
         Store::new(MemoryStore::new(&MemorySpec::default())), // Index store.
         Store::new(MemoryStore::new(&MemorySpec::default())), // Content store.
@@ -212,6 +231,8 @@ async fn check_chunk_boundary_reads_test() -> Result<(), Error> {
             normal_size: 6,
             max_size: 7,
             max_concurrent_fetch_per_get: 10,
+            chunker: DedupChunker::fastcdc,
+            gc_interval_s: 0,
         },
         Store::new(MemoryStore::new(&MemorySpec::default())), // Index store.
         Store::new(MemoryStore::new(&MemorySpec::default())), // Content store.
@@ -284,6 +305,7 @@ async fn has_checks_content_store() -> Result<(), Error> {
             max_count: 10,
             ..Default::default()
         }),
+        ..Default::default()
     });
 
     let store = DedupStore::new(
@@ -350,6 +372,7 @@ async fn has_with_no_existing_index_returns_none_test() -> Result<(), Error> {
             max_count: 10,
             ..Default::default()
         }),
+        ..Default::default()
     });
 
     let store = DedupStore::new(
@@ -381,6 +404,7 @@ async fn has_with_zero_digest_returns_some_test() -> Result<(), Error> {
             max_count: 10,
             ..Default::default()
         }),
+        ..Default::default()
     });
 
     let store = DedupStore::new(
@@ -402,3 +426,249 @@ async fn has_with_zero_digest_returns_some_test() -> Result<(), Error> {
     }
     Ok(())
 }
+
+#[nativelink_test]
+async fn fixed_size_chunker_round_trip_test() -> Result<(), Error> {
+    const CHUNK_SIZE: u32 = 1000;
+    const DATA_SIZE: usize = 3500;
+
+    let index_store = MemoryStore::new(&MemorySpec::default());
+    let store = DedupStore::new(
+        &DedupSpec {
+            normal_size: CHUNK_SIZE,
+            chunker: DedupChunker::fixed_size,
+            ..make_default_config()
+        },
+        Store::new(index_store.clone()),
+        Store::new(MemoryStore::new(&MemorySpec::default())), // Content store.
+    )?;
+
+    let original_data = make_random_data(DATA_SIZE);
+    let digest = DigestInfo::try_new(VALID_HASH1, DATA_SIZE).unwrap();
+    store
+        .update_oneshot(digest, original_data.clone().into())
+        .await
+        .err_tip(|| "Failed to write data to dedup store")?;
+
+    let index_data = index_store.get_part_unchunked(digest, 0, None).await?;
+    let index = DefaultOptions::new()
+        .with_fixint_encoding()
+        .deserialize::<DedupIndex>(&index_data)
+        .expect("Expected index to deserialize");
+    let chunk_sizes: Vec<u64> = index.entries.iter().map(DigestInfo::size_bytes).collect();
+    assert_eq!(chunk_sizes, vec![1000, 1000, 1000, 500]);
+
+    let rt_data = store
+        .get_part_unchunked(digest, 1500, Some(1000))
+        .await
+        .err_tip(|| "Failed to get_part from dedup store")?;
+    assert_eq!(rt_data, original_data[1500..2500]);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn invalid_chunk_sizes_are_rejected_test() -> Result<(), Error> {
+    let result = DedupStore::new(
+        &DedupSpec {
+            min_size: 64 * 1024,
+            normal_size: 32 * 1024,
+            ..make_default_config()
+        },
+        Store::new(MemoryStore::new(&MemorySpec::default())), // Index store.
+        Store::new(MemoryStore::new(&MemorySpec::default())), // Content store.
+    );
+    assert_eq!(
+        result.err().map(|err| err.code),
+        Some(Code::InvalidArgument),
+        "Expected min_size > normal_size to be rejected"
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn chunks_are_fetched_concurrently_in_order_test() -> Result<(), Error> {
+    const MAX_CONCURRENT_FETCH_PER_GET: usize = 4;
+
+    /// Delays reads by a time that depends on the key, so chunks complete
+    /// out of order, and records the most reads that were in flight.
+    #[derive(MetricsComponent)]
+    struct SlowStore {
+        inner: Store,
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl StoreDriver for SlowStore {
+        async fn has_with_results(
+            self: Pin<&Self>,
+            digests: &[StoreKey<'_>],
+            results: &mut [Option<u64>],
+        ) -> Result<(), Error> {
+            self.inner.has_with_results(digests, results).await
+        }
+
+        async fn update(
+            self: Pin<&Self>,
+            key: StoreKey<'_>,
+            reader: DropCloserReadHalf,
+            size_info: UploadSizeInfo,
+        ) -> Result<(), Error> {
+            self.inner.update(key, reader, size_info).await
+        }
+
+        async fn get_part(
+            self: Pin<&Self>,
+            key: StoreKey<'_>,
+            writer: &mut DropCloserWriteHalf,
+            offset: u64,
+            length: Option<u64>,
+        ) -> Result<(), Error> {
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            let delay = u64::from(key.borrow().into_digest().packed_hash()[0] % 20);
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+            let result = self.inner.get_part(key, writer, offset, length).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            result
+        }
+
+        fn inner_store(&self, _digest: Option<StoreKey>) -> &'_ dyn StoreDriver {
+            self
+        }
+
+        fn as_any(&self) -> &(dyn std::any::Any + Sync + Send + 'static) {
+            self
+        }
+
+        fn as_any_arc(self: Arc<Self>) -> Arc<dyn std::any::Any + Sync + Send + 'static> {
+            self
+        }
+    }
+
+    default_health_status_indicator!(SlowStore);
+
+    let content_store = Arc::new(SlowStore {
+        inner: Store::new(MemoryStore::new(&MemorySpec::default())),
+        in_flight: AtomicUsize::new(0),
+        max_in_flight: AtomicUsize::new(0),
+    });
+    let store = DedupStore::new(
+        &DedupSpec {
+            max_concurrent_fetch_per_get: MAX_CONCURRENT_FETCH_PER_GET as u32,
+            ..make_default_config()
+        },
+        Store::new(MemoryStore::new(&MemorySpec::default())), // Index store.
+        Store::new(content_store.clone()),
+    )?;
+
+    let original_data = make_random_data(MEGABYTE_SZ);
+    let digest = DigestInfo::try_new(VALID_HASH1, MEGABYTE_SZ).unwrap();
+    store
+        .update_oneshot(digest, original_data.clone().into())
+        .await
+        .err_tip(|| "Failed to write data to dedup store")?;
+
+    let rt_data = store
+        .get_part_unchunked(digest, 0, None)
+        .await
+        .err_tip(|| "Failed to get_part from dedup store")?;
+    assert_eq!(rt_data, original_data, "Expected round trip data to match");
+
+    let max_in_flight = content_store.max_in_flight.load(Ordering::SeqCst);
+    assert!(
+        max_in_flight > 1 && max_in_flight <= MAX_CONCURRENT_FETCH_PER_GET,
+        "Expected between 2 and {MAX_CONCURRENT_FETCH_PER_GET} concurrent fetches, got {max_in_flight}"
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn gc_removes_unreferenced_chunks_test() -> Result<(), Error> {
+    let index_store = MemoryStore::new(&MemorySpec::default());
+    let content_store = MemoryStore::new(&MemorySpec::default());
+    let store = DedupStore::new(
+        &make_default_config(),
+        Store::new(index_store.clone()),
+        Store::new(content_store.clone()),
+    )?;
+
+    let shared_data = make_random_data(MEGABYTE_SZ);
+    let orphaned_data: Vec<u8> = shared_data.iter().map(|byte| !byte).collect();
+    let shared_digest1 = DigestInfo::try_new(VALID_HASH1, MEGABYTE_SZ).unwrap();
+    let shared_digest2 = DigestInfo::try_new(VALID_HASH2, MEGABYTE_SZ).unwrap();
+    let orphaned_digest = DigestInfo::try_new(VALID_HASH3, MEGABYTE_SZ).unwrap();
+    store
+        .update_oneshot(shared_digest1, shared_data.clone().into())
+        .await?;
+    store
+        .update_oneshot(shared_digest2, shared_data.clone().into())
+        .await?;
+    store
+        .update_oneshot(orphaned_digest, orphaned_data.into())
+        .await?;
+    let chunks_before_gc = content_store.len_for_test().await;
+
+    // Nothing is collected while every chunk is referenced.
+    let report = store.gc().await?;
+    assert_eq!(report.indexes, 3);
+    assert_eq!(report.removed_chunks, 0);
+
+    assert!(index_store.remove_entry(orphaned_digest.into()).await);
+    let report = store.gc().await?;
+    assert_eq!(report.indexes, 2);
+    assert_eq!(
+        report.reclaimed_bytes, MEGABYTE_SZ as u64,
+        "Expected every chunk of the evicted index to be reclaimed"
+    );
+    assert_eq!(
+        content_store.len_for_test().await,
+        chunks_before_gc - report.removed_chunks as usize
+    );
+
+    // Chunks are kept as long as one index references them.
+    assert!(index_store.remove_entry(shared_digest1.into()).await);
+    let report = store.gc().await?;
+    assert_eq!(report.removed_chunks, 0);
+    assert_eq!(
+        store.get_part_unchunked(shared_digest2, 0, None).await?,
+        Bytes::from(shared_data)
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn gc_keeps_chunks_of_in_flight_uploads_test() -> Result<(), Error> {
+    let content_store = MemoryStore::new(&MemorySpec::default());
+    let store = DedupStore::new(
+        &make_default_config(),
+        Store::new(MemoryStore::new(&MemorySpec::default())), // Index store.
+        Store::new(content_store.clone()),
+    )?;
+
+    let original_data = Bytes::from(make_random_data(MEGABYTE_SZ));
+    let digest = DigestInfo::try_new(VALID_HASH1, MEGABYTE_SZ).unwrap();
+    let (mut tx, rx) = make_buf_channel_pair();
+    let update_fut = store.update(digest, rx, UploadSizeInfo::ExactSize(MEGABYTE_SZ as u64));
+    let gc_fut = async {
+        tx.send(original_data.slice(..MEGABYTE_SZ / 2)).await?;
+        // Wait until the first chunks are written, but not the index.
+        while content_store.len_for_test().await == 0 {
+            tokio::task::yield_now().await;
+        }
+        let report = store.gc().await?;
+        tx.send(original_data.slice(MEGABYTE_SZ / 2..)).await?;
+        tx.send_eof()?;
+        Result::<DedupGcReport, Error>::Ok(report)
+    };
+    let (update_result, gc_result) = join!(update_fut, gc_fut);
+    update_result.err_tip(|| "Failed to write data to dedup store")?;
+    assert_eq!(gc_result?.removed_chunks, 0);
+
+    assert_eq!(
+        store.get_part_unchunked(digest, 0, None).await?,
+        original_data,
+        "Expected the chunks of the upload to survive garbage collection"
+    );
+    Ok(())
+}
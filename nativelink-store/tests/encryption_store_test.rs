@@ -0,0 +1,198 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use bytes::{Bytes, BytesMut};
+use nativelink_config::stores::{EncryptionSpec, MemorySpec, StoreSpec};
+use nativelink_error::{Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_store::encryption_store::{
+    decrypted_size, encrypted_size, EncryptionStore, DEFAULT_BLOCK_SIZE, HEADER_SIZE,
+};
+use nativelink_store::memory_store::MemoryStore;
+use nativelink_util::common::DigestInfo;
+use nativelink_util::store_trait::{Store, StoreLike};
+use pretty_assertions::assert_eq;
+
+const VALID_HASH: &str = "0123456789abcdef000000000000000000010000000000000123456789abcdef";
+// Base64 of 32 bytes of 0x01 and 0x02.
+const KEY1: &str = "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=";
+const KEY2: &str = "AgICAgICAgICAgICAgICAgICAgICAgICAgICAgICAgI=";
+
+fn make_store(
+    inner_store: &Store,
+    key: &str,
+    decryption_keys: &[&str],
+    block_size: u32,
+) -> Result<Arc<EncryptionStore>, Error> {
+    EncryptionStore::new(
+        &EncryptionSpec {
+            backend: StoreSpec::memory(MemorySpec::default()),
+            key: Some(key.to_string()),
+            key_file: None,
+            decryption_keys: decryption_keys.iter().map(ToString::to_string).collect(),
+            block_size,
+            max_decode_block_size: 0,
+        },
+        inner_store.clone(),
+    )
+}
+
+#[nativelink_test]
+async fn round_trip_does_not_store_plaintext() -> Result<(), Error> {
+    const RAW_INPUT: &str = "some very secret source code";
+    let inner_store = Store::new(MemoryStore::new(&MemorySpec::default()));
+    let store = make_store(&inner_store, KEY1, &[], 0)?;
+
+    let digest = DigestInfo::try_new(VALID_HASH, RAW_INPUT.len())?;
+    store.update_oneshot(digest, RAW_INPUT.into()).await?;
+
+    let inner_data = inner_store.get_part_unchunked(digest, 0, None).await?;
+    assert_eq!(
+        inner_data.len() as u64,
+        encrypted_size(RAW_INPUT.len() as u64, DEFAULT_BLOCK_SIZE)
+    );
+    assert!(
+        !inner_data
+            .windows(RAW_INPUT.len())
+            .any(|window| window == RAW_INPUT.as_bytes()),
+        "Expected data to be encrypted"
+    );
+
+    assert_eq!(store.has(digest).await, Ok(Some(RAW_INPUT.len() as u64)));
+    assert_eq!(
+        store.get_part_unchunked(digest, 0, None).await?,
+        Bytes::from_static(RAW_INPUT.as_bytes())
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn sizes_round_trip() -> Result<(), Error> {
+    for block_size in [1, 10, DEFAULT_BLOCK_SIZE] {
+        for size in [0, 1, 9, 10, 11, 100, 65536, 1_000_000] {
+            assert_eq!(
+                decrypted_size(encrypted_size(size, block_size), block_size),
+                Some(size),
+                "size {size} with block_size {block_size}"
+            );
+        }
+    }
+    assert_eq!(decrypted_size(HEADER_SIZE, 10), None);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn partial_reads_test() -> Result<(), Error> {
+    let raw_data: Bytes = (0..30u8).collect::<Vec<_>>().into();
+    let inner_store = Store::new(MemoryStore::new(&MemorySpec::default()));
+    let store = make_store(&inner_store, KEY1, &[], 10)?;
+
+    let digest = DigestInfo::try_new(VALID_HASH, raw_data.len())?;
+    store.update_oneshot(digest, raw_data.clone()).await?;
+
+    // Try many different combinations of offsets and lengths.
+    for offset in 0..=raw_data.len() {
+        for length in 0..=raw_data.len() - offset {
+            let data = store
+                .get_part_unchunked(digest, offset as u64, Some(length as u64))
+                .await?;
+            assert_eq!(
+                data,
+                raw_data.slice(offset..offset + length),
+                "offset {offset} length {length}"
+            );
+        }
+        let data = store
+            .get_part_unchunked(digest, offset as u64, None)
+            .await?;
+        assert_eq!(data, raw_data.slice(offset..), "offset {offset}");
+    }
+    Ok(())
+}
+
+#[nativelink_test]
+async fn old_keys_decrypt_after_rotation() -> Result<(), Error> {
+    const RAW_INPUT: &str = "123";
+    let inner_store = Store::new(MemoryStore::new(&MemorySpec::default()));
+    let digest = DigestInfo::try_new(VALID_HASH, RAW_INPUT.len())?;
+    make_store(&inner_store, KEY1, &[], 0)?
+        .update_oneshot(digest, RAW_INPUT.into())
+        .await?;
+
+    let rotated_store = make_store(&inner_store, KEY2, &[KEY1], 0)?;
+    assert_eq!(
+        rotated_store.get_part_unchunked(digest, 0, None).await?,
+        Bytes::from_static(RAW_INPUT.as_bytes())
+    );
+
+    let err = make_store(&inner_store, KEY2, &[], 0)?
+        .get_part_unchunked(digest, 0, None)
+        .await
+        .unwrap_err();
+    assert_eq!(err.code, Code::FailedPrecondition, "{err:?}");
+    Ok(())
+}
+
+#[nativelink_test]
+async fn modified_data_is_rejected() -> Result<(), Error> {
+    const RAW_INPUT: &str = "0123456789abcdef";
+    let inner_store = Store::new(MemoryStore::new(&MemorySpec::default()));
+    let store = make_store(&inner_store, KEY1, &[], 4)?;
+    let digest = DigestInfo::try_new(VALID_HASH, RAW_INPUT.len())?;
+    store.update_oneshot(digest, RAW_INPUT.into()).await?;
+    let encrypted = inner_store.get_part_unchunked(digest, 0, None).await?;
+
+    // Flip a bit in the second block.
+    let mut modified = BytesMut::from(&encrypted[..]);
+    modified[HEADER_SIZE as usize + 20 + 1] ^= 1;
+    inner_store
+        .update_oneshot(digest, modified.freeze())
+        .await?;
+    let err = store.get_part_unchunked(digest, 0, None).await.unwrap_err();
+    assert_eq!(err.code, Code::DataLoss, "{err:?}");
+
+    // Drop the last block, so the data ends on a full block.
+    inner_store
+        .update_oneshot(digest, encrypted.slice(..encrypted.len() - 16))
+        .await?;
+    let err = store.get_part_unchunked(digest, 0, None).await.unwrap_err();
+    assert_eq!(err.code, Code::DataLoss, "{err:?}");
+    Ok(())
+}
+
+#[nativelink_test]
+async fn data_moved_to_another_key_is_rejected() -> Result<(), Error> {
+    const RAW_INPUT: &str = "0123456789abcdef";
+    let inner_store = Store::new(MemoryStore::new(&MemorySpec::default()));
+    let store = make_store(&inner_store, KEY1, &[], 0)?;
+    let digest = DigestInfo::try_new(VALID_HASH, RAW_INPUT.len())?;
+    store.update_oneshot(digest, RAW_INPUT.into()).await?;
+    let encrypted = inner_store.get_part_unchunked(digest, 0, None).await?;
+
+    let other_digest = DigestInfo::try_new(VALID_HASH, RAW_INPUT.len() + 1)?;
+    inner_store.update_oneshot(other_digest, encrypted).await?;
+    let err = store
+        .get_part_unchunked(other_digest, 0, None)
+        .await
+        .unwrap_err();
+    assert_eq!(err.code, Code::DataLoss, "{err:?}");
+    let err = store
+        .get_part_unchunked(other_digest, 1, Some(2))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code, Code::DataLoss, "{err:?}");
+    Ok(())
+}
@@ -35,6 +35,7 @@ async fn simple_exist_cache_test() -> Result<(), Error> {
     let spec = ExistenceCacheSpec {
         backend: StoreSpec::noop(NoopSpec::default()), // Note: Not used.
         eviction_policy: Option::default(),
+        negative_cache_eviction_policy: None,
     };
     let inner_store = Store::new(MemoryStore::new(&MemorySpec::default()));
     let store = ExistenceCacheStore::new(&spec, inner_store.clone());
@@ -73,6 +74,7 @@ async fn update_flags_existance_cache_test() -> Result<(), Error> {
     let spec = ExistenceCacheSpec {
         backend: StoreSpec::noop(NoopSpec::default()),
         eviction_policy: Option::default(),
+        negative_cache_eviction_policy: None,
     };
     let inner_store = Store::new(MemoryStore::new(&MemorySpec::default()));
     let store = ExistenceCacheStore::new(&spec, inner_store.clone());
@@ -96,6 +98,7 @@ async fn get_part_caches_if_exact_size_set() -> Result<(), Error> {
     let spec = ExistenceCacheSpec {
         backend: StoreSpec::noop(NoopSpec::default()),
         eviction_policy: Option::default(),
+        negative_cache_eviction_policy: None,
     };
     let inner_store = Store::new(MemoryStore::new(&MemorySpec::default()));
     let digest = DigestInfo::try_new(VALID_HASH1, 3).unwrap();
@@ -134,6 +137,7 @@ async fn ensure_has_requests_eventually_do_let_evictions_happen() -> Result<(),
                 max_seconds: 10,
                 ..Default::default()
             }),
+            negative_cache_eviction_policy: None,
         },
         Store::new(inner_store.clone()),
         MockInstantWrapped::default(),
@@ -160,3 +164,64 @@ async fn ensure_has_requests_eventually_do_let_evictions_happen() -> Result<(),
 
     Ok(())
 }
+
+#[nativelink_test]
+async fn negative_cache_avoids_repeated_lookups_until_expired() -> Result<(), Error> {
+    const VALUE: &str = "123";
+    let inner_store = MemoryStore::new(&MemorySpec::default());
+    let digest = DigestInfo::try_new(VALID_HASH1, 3).unwrap();
+    let store = ExistenceCacheStore::new_with_time(
+        &ExistenceCacheSpec {
+            backend: StoreSpec::noop(NoopSpec::default()),
+            eviction_policy: Option::default(),
+            negative_cache_eviction_policy: Some(EvictionPolicy {
+                max_seconds: 10,
+                ..Default::default()
+            }),
+        },
+        Store::new(inner_store.clone()),
+        MockInstantWrapped::default(),
+    );
+
+    assert_eq!(store.has(digest).await, Ok(None));
+    assert!(
+        store.missing_in_cache(&digest).await,
+        "Expected digest to be cached as missing"
+    );
+
+    // Uploads that bypass the existence cache are not seen until the
+    // negative cache entry expires.
+    inner_store
+        .update_oneshot(digest, VALUE.into())
+        .await
+        .err_tip(|| "Failed to update store")?;
+    assert_eq!(store.has(digest).await, Ok(None));
+    MockClock::advance(Duration::from_secs(11));
+    assert_eq!(store.has(digest).await, Ok(Some(VALUE.len() as u64)));
+    Ok(())
+}
+
+#[nativelink_test]
+async fn update_clears_negative_cache_test() -> Result<(), Error> {
+    const VALUE: &str = "123";
+    let spec = ExistenceCacheSpec {
+        backend: StoreSpec::noop(NoopSpec::default()),
+        eviction_policy: Option::default(),
+        negative_cache_eviction_policy: Some(EvictionPolicy::default()),
+    };
+    let inner_store = Store::new(MemoryStore::new(&MemorySpec::default()));
+    let store = ExistenceCacheStore::new(&spec, inner_store.clone());
+
+    let digest = DigestInfo::try_new(VALID_HASH1, 3).unwrap();
+    assert_eq!(store.has(digest).await, Ok(None));
+    store
+        .update_oneshot(digest, VALUE.into())
+        .await
+        .err_tip(|| "Failed to update store")?;
+    assert!(
+        !store.missing_in_cache(&digest).await,
+        "Expected digest to be removed from negative cache"
+    );
+    assert_eq!(store.has(digest).await, Ok(Some(VALUE.len() as u64)));
+    Ok(())
+}
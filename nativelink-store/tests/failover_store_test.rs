@@ -0,0 +1,186 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use mock_instant::thread_local::MockClock;
+use nativelink_config::stores::{FailoverSpec, MemorySpec, StoreSpec};
+use nativelink_error::{make_err, Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_metric::MetricsComponent;
+use nativelink_store::failover_store::FailoverStore;
+use nativelink_store::memory_store::MemoryStore;
+use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
+use nativelink_util::common::DigestInfo;
+use nativelink_util::health_utils::{default_health_status_indicator, HealthStatusIndicator};
+use nativelink_util::instant_wrapper::MockInstantWrapped;
+use nativelink_util::store_trait::{Store, StoreDriver, StoreKey, StoreLike, UploadSizeInfo};
+use pretty_assertions::assert_eq;
+
+const VALID_HASH1: &str = "0123456789abcdef000000000000000000010000000000000123456789abcdef";
+const VALUE: &str = "123";
+
+/// A store that fails every request, like a region that is down.
+#[derive(MetricsComponent, Default)]
+struct UnavailableStore {
+    request_count: AtomicU64,
+}
+
+impl UnavailableStore {
+    fn fail(&self) -> Error {
+        self.request_count.fetch_add(1, Ordering::Relaxed);
+        make_err!(Code::Unavailable, "Region is down")
+    }
+}
+
+#[async_trait]
+impl StoreDriver for UnavailableStore {
+    async fn has_with_results(
+        self: Pin<&Self>,
+        _keys: &[StoreKey<'_>],
+        _results: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        Err(self.fail())
+    }
+
+    async fn update(
+        self: Pin<&Self>,
+        _key: StoreKey<'_>,
+        _reader: DropCloserReadHalf,
+        _size_info: UploadSizeInfo,
+    ) -> Result<(), Error> {
+        Err(self.fail())
+    }
+
+    async fn get_part(
+        self: Pin<&Self>,
+        _key: StoreKey<'_>,
+        _writer: &mut DropCloserWriteHalf,
+        _offset: u64,
+        _length: Option<u64>,
+    ) -> Result<(), Error> {
+        Err(self.fail())
+    }
+
+    fn inner_store(&self, _key: Option<StoreKey>) -> &'_ dyn StoreDriver {
+        self
+    }
+
+    fn as_any(&self) -> &(dyn std::any::Any + Sync + Send + 'static) {
+        self
+    }
+
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn std::any::Any + Sync + Send + 'static> {
+        self
+    }
+}
+
+default_health_status_indicator!(UnavailableStore);
+
+fn make_store(primary: Store, secondary: Store) -> Arc<FailoverStore<fn() -> MockInstantWrapped>> {
+    FailoverStore::new(
+        &FailoverSpec {
+            primary: StoreSpec::memory(MemorySpec::default()),
+            secondary: StoreSpec::memory(MemorySpec::default()),
+            failure_threshold: 2,
+            recovery_interval_s: 10,
+            disable_backfill: false,
+            max_concurrent_backfills: 0,
+        },
+        primary,
+        secondary,
+        MockInstantWrapped::default as fn() -> MockInstantWrapped,
+    )
+}
+
+#[nativelink_test]
+async fn read_miss_falls_back_and_backfills_primary() -> Result<(), Error> {
+    let digest = DigestInfo::try_new(VALID_HASH1, VALUE.len())?;
+    let primary = Store::new(MemoryStore::new(&MemorySpec::default()));
+    let secondary = Store::new(MemoryStore::new(&MemorySpec::default()));
+    secondary.update_oneshot(digest, VALUE.into()).await?;
+    let store = make_store(primary.clone(), secondary);
+
+    assert_eq!(store.has(digest).await, Ok(Some(VALUE.len() as u64)));
+    assert_eq!(
+        store.get_part_unchunked(digest, 0, None).await?,
+        Bytes::from_static(VALUE.as_bytes())
+    );
+
+    // The backfill runs in the background.
+    for _ in 0..100 {
+        if primary.has(digest).await?.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(
+        primary.get_part_unchunked(digest, 0, None).await?,
+        Bytes::from_static(VALUE.as_bytes())
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn failing_primary_is_skipped_until_recovery_interval() -> Result<(), Error> {
+    let digest = DigestInfo::try_new(VALID_HASH1, VALUE.len())?;
+    let primary = Arc::new(UnavailableStore::default());
+    let secondary = Store::new(MemoryStore::new(&MemorySpec::default()));
+    secondary.update_oneshot(digest, VALUE.into()).await?;
+    let store = make_store(Store::new(primary.clone()), secondary.clone());
+
+    // Failed requests are served by the secondary until the threshold is
+    // reached, then the primary is not asked anymore.
+    for _ in 0..4 {
+        assert_eq!(
+            store.get_part_unchunked(digest, 0, None).await?,
+            Bytes::from_static(VALUE.as_bytes())
+        );
+    }
+    assert_eq!(primary.request_count.load(Ordering::Relaxed), 2);
+    assert!(!store.is_primary_available());
+
+    // Writes go to the secondary while the primary is skipped.
+    let other_digest = DigestInfo::try_new(VALID_HASH1, 4)?;
+    store.update_oneshot(other_digest, "4567".into()).await?;
+    assert_eq!(secondary.has(other_digest).await?, Some(4));
+    assert_eq!(primary.request_count.load(Ordering::Relaxed), 2);
+
+    // After the recovery interval the primary is tried again.
+    MockClock::advance(Duration::from_secs(10));
+    assert!(store.is_primary_available());
+    assert_eq!(store.has(digest).await, Ok(Some(VALUE.len() as u64)));
+    assert_eq!(primary.request_count.load(Ordering::Relaxed), 3);
+    // It failed again, so it is skipped right away.
+    assert!(!store.is_primary_available());
+    Ok(())
+}
+
+#[nativelink_test]
+async fn update_goes_to_healthy_primary() -> Result<(), Error> {
+    let digest = DigestInfo::try_new(VALID_HASH1, VALUE.len())?;
+    let primary = Store::new(MemoryStore::new(&MemorySpec::default()));
+    let secondary = Store::new(MemoryStore::new(&MemorySpec::default()));
+    let store = make_store(primary.clone(), secondary.clone());
+
+    store.update_oneshot(digest, VALUE.into()).await?;
+    assert_eq!(primary.has(digest).await?, Some(VALUE.len() as u64));
+    assert_eq!(secondary.has(digest).await?, None);
+    Ok(())
+}
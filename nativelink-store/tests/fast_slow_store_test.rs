@@ -13,39 +13,56 @@
 // limitations under the License.
 
 use std::pin::Pin;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use async_trait::async_trait;
 use bytes::Bytes;
-use nativelink_config::stores::{FastSlowSpec, MemorySpec, NoopSpec, StoreSpec};
+use nativelink_config::stores::{
+    EvictionPolicy, FastSlowSpec, FastSlowWriteBackConfig, MemorySpec, NoopSpec,
+    PopulateOnReadPolicy, Retry, StoreSpec,
+};
 use nativelink_error::{make_err, Code, Error, ResultExt};
 use nativelink_macro::nativelink_test;
 use nativelink_metric::MetricsComponent;
 use nativelink_store::fast_slow_store::FastSlowStore;
 use nativelink_store::memory_store::MemoryStore;
 use nativelink_store::noop_store::NoopStore;
-use nativelink_util::buf_channel::make_buf_channel_pair;
+use nativelink_util::buf_channel::{
+    make_buf_channel_pair, DropCloserReadHalf, DropCloserWriteHalf,
+};
 use nativelink_util::common::DigestInfo;
 use nativelink_util::health_utils::{default_health_status_indicator, HealthStatusIndicator};
-use nativelink_util::store_trait::{Store, StoreDriver, StoreKey, StoreLike};
+use nativelink_util::store_trait::{Store, StoreDriver, StoreKey, StoreLike, UploadSizeInfo};
 use pretty_assertions::assert_eq;
 use rand::rngs::SmallRng;
 use rand::{Rng, SeedableRng};
+use tokio::sync::Semaphore;
 
 const MEGABYTE_SZ: usize = 1024 * 1024;
 
 fn make_stores() -> (Store, Store, Store) {
+    let (fast_slow_store, fast_store, slow_store) =
+        make_stores_with_policy(PopulateOnReadPolicy::always);
+    (Store::new(fast_slow_store), fast_store, slow_store)
+}
+
+fn make_stores_with_policy(
+    populate_on_read: PopulateOnReadPolicy,
+) -> (Arc<FastSlowStore>, Store, Store) {
     let fast_store = Store::new(MemoryStore::new(&MemorySpec::default()));
     let slow_store = Store::new(MemoryStore::new(&MemorySpec::default()));
-    let fast_slow_store = Store::new(FastSlowStore::new(
+    let fast_slow_store = FastSlowStore::new(
         &FastSlowSpec {
             fast: StoreSpec::memory(MemorySpec::default()),
             slow: StoreSpec::memory(MemorySpec::default()),
+            populate_on_read,
+            write_back: None,
         },
         fast_store.clone(),
         slow_store.clone(),
-    ));
+    );
     (fast_slow_store, fast_store, slow_store)
 }
 
@@ -94,6 +111,30 @@ async fn write_large_amount_to_both_stores_test() -> Result<(), Error> {
     Ok(())
 }
 
+#[nativelink_test]
+async fn update_many_writes_to_both_stores_test() -> Result<(), Error> {
+    let (store, fast_store, slow_store) = make_stores();
+
+    let first_data = make_random_data(100);
+    let second_data = make_random_data(200);
+    let first_digest = DigestInfo::try_new(VALID_HASH, 100).unwrap();
+    let second_digest = DigestInfo::try_new(VALID_HASH, 200).unwrap();
+    let results = store
+        .update_many(vec![
+            (first_digest.into(), first_data.clone().into()),
+            (second_digest.into(), second_data.clone().into()),
+        ])
+        .await;
+    assert_eq!(results, vec![Ok(()), Ok(())]);
+
+    check_data(&fast_store, first_digest, &first_data, "fast").await?;
+    check_data(&slow_store, first_digest, &first_data, "slow").await?;
+    check_data(&fast_store, second_digest, &second_data, "fast").await?;
+    check_data(&slow_store, second_digest, &second_data, "slow").await?;
+
+    Ok(())
+}
+
 #[nativelink_test]
 async fn fetch_slow_store_puts_in_fast_store_test() -> Result<(), Error> {
     let (fast_slow_store, fast_store, slow_store) = make_stores();
@@ -332,6 +373,8 @@ async fn drop_on_eof_completes_store_futures() -> Result<(), Error> {
         &FastSlowSpec {
             fast: StoreSpec::memory(MemorySpec::default()),
             slow: StoreSpec::memory(MemorySpec::default()),
+            populate_on_read: PopulateOnReadPolicy::always,
+            write_back: None,
         },
         fast_store,
         slow_store,
@@ -373,6 +416,8 @@ async fn ignore_value_in_fast_store() -> Result<(), Error> {
         &FastSlowSpec {
             fast: StoreSpec::memory(MemorySpec::default()),
             slow: StoreSpec::memory(MemorySpec::default()),
+            populate_on_read: PopulateOnReadPolicy::always,
+            write_back: None,
         },
         fast_store.clone(),
         slow_store,
@@ -396,6 +441,8 @@ async fn has_checks_fast_store_when_noop() -> Result<(), Error> {
     let fast_slow_store_config = FastSlowSpec {
         fast: StoreSpec::memory(MemorySpec::default()),
         slow: StoreSpec::noop(NoopSpec::default()),
+        populate_on_read: PopulateOnReadPolicy::always,
+        write_back: None,
     };
     let fast_slow_store = Arc::new(FastSlowStore::new(
         &fast_slow_store_config,
@@ -430,3 +477,291 @@ async fn has_checks_fast_store_when_noop() -> Result<(), Error> {
     );
     Ok(())
 }
+
+#[nativelink_test]
+async fn populate_on_read_never_skips_fast_store_test() -> Result<(), Error> {
+    let (fast_slow_store, fast_store, slow_store) =
+        make_stores_with_policy(PopulateOnReadPolicy::never);
+
+    let original_data = make_random_data(MEGABYTE_SZ);
+    let digest = DigestInfo::try_new(VALID_HASH, 100).unwrap();
+    slow_store
+        .update_oneshot(digest, original_data.clone().into())
+        .await?;
+
+    assert_eq!(
+        fast_slow_store
+            .get_part_unchunked(digest, 10, Some(50))
+            .await,
+        Ok(original_data[10..60].to_vec().into())
+    );
+    assert_eq!(fast_store.has(digest).await, Ok(None));
+
+    // Explicitly populating the fast store ignores the policy.
+    fast_slow_store.populate_fast_store(digest.into()).await?;
+    check_data(&fast_store, digest, &original_data, "fast_store").await?;
+    Ok(())
+}
+
+#[nativelink_test]
+async fn populate_on_read_only_if_smaller_than_test() -> Result<(), Error> {
+    let (fast_slow_store, fast_store, slow_store) = make_stores_with_policy(
+        PopulateOnReadPolicy::only_if_smaller_than(MEGABYTE_SZ as u64),
+    );
+
+    let small_data = make_random_data(MEGABYTE_SZ - 1);
+    let small_digest = DigestInfo::try_new(VALID_HASH, 100).unwrap();
+    let large_data = make_random_data(MEGABYTE_SZ);
+    let large_digest = DigestInfo::try_new(VALID_HASH, 200).unwrap();
+    slow_store
+        .update_oneshot(small_digest, small_data.clone().into())
+        .await?;
+    slow_store
+        .update_oneshot(large_digest, large_data.clone().into())
+        .await?;
+
+    assert_eq!(
+        fast_slow_store
+            .get_part_unchunked(small_digest, 0, None)
+            .await,
+        Ok(small_data.clone().into())
+    );
+    assert_eq!(
+        fast_slow_store
+            .get_part_unchunked(large_digest, 0, None)
+            .await,
+        Ok(large_data.into())
+    );
+
+    check_data(&fast_store, small_digest, &small_data, "fast_store").await?;
+    assert_eq!(fast_store.has(large_digest).await, Ok(None));
+    Ok(())
+}
+
+#[nativelink_test]
+async fn populate_on_read_probabilistic_bounds_test() -> Result<(), Error> {
+    let original_data = make_random_data(1024);
+    let digest = DigestInfo::try_new(VALID_HASH, 100).unwrap();
+    for (probability, expect_populated) in [(0.0, false), (1.0, true)] {
+        let (fast_slow_store, fast_store, slow_store) =
+            make_stores_with_policy(PopulateOnReadPolicy::probabilistic(probability));
+        slow_store
+            .update_oneshot(digest, original_data.clone().into())
+            .await?;
+        fast_slow_store.get_part_unchunked(digest, 0, None).await?;
+        assert_eq!(
+            fast_store.has(digest).await?.is_some(),
+            expect_populated,
+            "Unexpected fast store state for probability {probability}"
+        );
+    }
+    Ok(())
+}
+
+/// Forwards to a memory store, but updates wait for a permit and fail while
+/// `failures_left` is not zero.
+#[derive(MetricsComponent)]
+struct GatedStore {
+    inner: Store,
+    update_permits: Semaphore,
+    failures_left: AtomicU64,
+}
+
+impl GatedStore {
+    fn new(update_permits: usize, failures: u64) -> Arc<Self> {
+        Arc::new(Self {
+            inner: Store::new(MemoryStore::new(&MemorySpec::default())),
+            update_permits: Semaphore::new(update_permits),
+            failures_left: AtomicU64::new(failures),
+        })
+    }
+}
+
+#[async_trait]
+impl StoreDriver for GatedStore {
+    async fn has_with_results(
+        self: Pin<&Self>,
+        keys: &[StoreKey<'_>],
+        results: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        self.inner.has_with_results(keys, results).await
+    }
+
+    async fn update(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        mut reader: DropCloserReadHalf,
+        size_info: UploadSizeInfo,
+    ) -> Result<(), Error> {
+        self.update_permits
+            .acquire()
+            .await
+            .map_err(|e| make_err!(Code::Internal, "{e:?}"))?
+            .forget();
+        if self
+            .failures_left
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |v| v.checked_sub(1))
+            .is_ok()
+        {
+            reader.drain().await?;
+            return Err(make_err!(Code::Unavailable, "Injected failure"));
+        }
+        self.inner.update(key, reader, size_info).await
+    }
+
+    async fn get_part(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        writer: &mut DropCloserWriteHalf,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<(), Error> {
+        self.inner.get_part(key, writer, offset, length).await
+    }
+
+    fn inner_store(&self, _key: Option<StoreKey>) -> &'_ dyn StoreDriver {
+        self
+    }
+
+    fn as_any(&self) -> &(dyn std::any::Any + Sync + Send + 'static) {
+        self
+    }
+
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn std::any::Any + Sync + Send + 'static> {
+        self
+    }
+}
+
+default_health_status_indicator!(GatedStore);
+
+fn make_write_back_stores(slow_store: Arc<GatedStore>, retry: Retry) -> (Store, Store) {
+    let fast_store = Store::new(MemoryStore::new(&MemorySpec::default()));
+    let fast_slow_store = Store::new(FastSlowStore::new(
+        &FastSlowSpec {
+            fast: StoreSpec::memory(MemorySpec::default()),
+            slow: StoreSpec::memory(MemorySpec::default()),
+            populate_on_read: PopulateOnReadPolicy::always,
+            write_back: Some(FastSlowWriteBackConfig {
+                retry,
+                ..Default::default()
+            }),
+        },
+        fast_store.clone(),
+        Store::new(slow_store),
+    ));
+    (fast_slow_store, fast_store)
+}
+
+async fn wait_for_slow_store(slow_store: &GatedStore, digest: DigestInfo) -> Result<(), Error> {
+    for _ in 0..1000 {
+        if slow_store.inner.has(digest).await?.is_some() {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+    Err(make_err!(
+        Code::DeadlineExceeded,
+        "{digest} was never written back to the slow store"
+    ))
+}
+
+#[nativelink_test]
+async fn write_back_completes_before_slow_store_update_test() -> Result<(), Error> {
+    let slow_store = GatedStore::new(0, 0);
+    let (fast_slow_store, fast_store) =
+        make_write_back_stores(slow_store.clone(), Retry::default());
+
+    let original_data = make_random_data(MEGABYTE_SZ);
+    let digest = DigestInfo::try_new(VALID_HASH, 100).unwrap();
+    // The slow store has no permits yet, so this only completes if it does
+    // not wait for the slow store.
+    fast_slow_store
+        .update_oneshot(digest, original_data.clone().into())
+        .await?;
+
+    check_data(&fast_store, digest, &original_data, "fast_store").await?;
+    assert_eq!(slow_store.inner.has(digest).await, Ok(None));
+    // Objects that are still being written back exist.
+    assert_eq!(
+        fast_slow_store.has(digest).await,
+        Ok(Some(original_data.len() as u64))
+    );
+
+    slow_store.update_permits.add_permits(1);
+    wait_for_slow_store(&slow_store, digest).await?;
+    check_data(&slow_store.inner, digest, &original_data, "slow_store").await?;
+    Ok(())
+}
+
+#[nativelink_test]
+async fn write_back_retries_failed_uploads_test() -> Result<(), Error> {
+    let slow_store = GatedStore::new(10, 2);
+    let (fast_slow_store, _fast_store) = make_write_back_stores(
+        slow_store.clone(),
+        Retry {
+            max_retries: 3,
+            ..Default::default()
+        },
+    );
+
+    let original_data = make_random_data(MEGABYTE_SZ);
+    let digest = DigestInfo::try_new(VALID_HASH, 100).unwrap();
+    fast_slow_store
+        .update_oneshot(digest, original_data.clone().into())
+        .await?;
+
+    wait_for_slow_store(&slow_store, digest).await?;
+    check_data(&slow_store.inner, digest, &original_data, "slow_store").await?;
+    assert_eq!(slow_store.failures_left.load(Ordering::Acquire), 0);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn reconfigure_changes_nothing_if_any_store_rejects_the_spec() -> Result<(), Error> {
+    let (fast_slow_store, fast_store, _slow_store) = make_stores();
+    let first_digest = DigestInfo::try_new(VALID_HASH, 100).unwrap();
+    let second_digest = DigestInfo::try_new(VALID_HASH, 200).unwrap();
+    fast_store
+        .update_oneshot(first_digest, make_random_data(100).into())
+        .await?;
+    fast_store
+        .update_oneshot(second_digest, make_random_data(200).into())
+        .await?;
+    let fast_spec = StoreSpec::memory(MemorySpec {
+        eviction_policy: Some(EvictionPolicy {
+            max_count: 1,
+            ..Default::default()
+        }),
+        ..Default::default()
+    });
+
+    // The slow store can't change its shard count, so the new eviction
+    // policy of the fast store must not be applied either.
+    let err = fast_slow_store
+        .reconfigure(&StoreSpec::fast_slow(Box::new(FastSlowSpec {
+            fast: fast_spec.clone(),
+            slow: StoreSpec::memory(MemorySpec {
+                shard_count: 4,
+                ..Default::default()
+            }),
+            populate_on_read: PopulateOnReadPolicy::always,
+            write_back: None,
+        })))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code, Code::InvalidArgument, "{err:?}");
+    assert_eq!(fast_store.has(first_digest).await?, Some(100));
+    assert_eq!(fast_store.has(second_digest).await?, Some(200));
+
+    fast_slow_store
+        .reconfigure(&StoreSpec::fast_slow(Box::new(FastSlowSpec {
+            fast: fast_spec,
+            slow: StoreSpec::memory(MemorySpec::default()),
+            populate_on_read: PopulateOnReadPolicy::always,
+            write_back: None,
+        })))
+        .await?;
+    assert_eq!(fast_store.has(first_digest).await?, None);
+    assert_eq!(fast_store.has(second_digest).await?, Some(200));
+    Ok(())
+}
@@ -27,13 +27,15 @@ use filetime::{set_file_atime, FileTime};
 use futures::executor::block_on;
 use futures::task::Poll;
 use futures::{poll, Future, FutureExt};
-use nativelink_config::stores::{FastSlowSpec, FilesystemSpec, MemorySpec, StoreSpec};
+use nativelink_config::stores::{
+    FastSlowSpec, FilesystemSpec, FsckMode, MemorySpec, PopulateOnReadPolicy, StoreSpec,
+};
 use nativelink_error::{make_err, Code, Error, ResultExt};
 use nativelink_macro::nativelink_test;
 use nativelink_store::fast_slow_store::FastSlowStore;
 use nativelink_store::filesystem_store::{
     key_from_file, EncodedFilePath, FileEntry, FileEntryImpl, FileType, FilesystemStore,
-    DIGEST_FOLDER, STR_FOLDER,
+    FsckReport, DIGEST_FOLDER, STR_FOLDER,
 };
 use nativelink_util::buf_channel::make_buf_channel_pair;
 use nativelink_util::common::{fs, DigestInfo};
@@ -99,6 +101,10 @@ impl<Hooks: FileEntryHooks + 'static + Sync + Send> FileEntry for TestFileEntry<
         ))
     }
 
+    fn data_size(&self) -> u64 {
+        self.inner.as_ref().unwrap().data_size()
+    }
+
     fn data_size_mut(&mut self) -> &mut u64 {
         self.inner.as_mut().unwrap().data_size_mut()
     }
@@ -1334,6 +1340,8 @@ async fn update_with_whole_file_slow_path_when_low_file_descriptors() -> Result<
         &FastSlowSpec {
             fast: StoreSpec::memory(MemorySpec::default()),
             slow: StoreSpec::memory(MemorySpec::default()),
+            populate_on_read: PopulateOnReadPolicy::always,
+            write_back: None,
         },
         Store::new(
             FilesystemStore::<FileEntryImpl>::new(&FilesystemSpec {
@@ -1434,3 +1442,297 @@ async fn update_with_whole_file_uses_same_inode() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[serial]
+#[nativelink_test]
+async fn list_includes_keys_loaded_from_disk() -> Result<(), Error> {
+    let digest1 = DigestInfo::try_new(HASH1, VALUE1.len())?;
+    let digest2 = DigestInfo::try_new(HASH2, VALUE2.len())?;
+    let content_path = make_temp_path("content_path");
+    let temp_path = make_temp_path("temp_path");
+    let spec = FilesystemSpec {
+        content_path,
+        temp_path,
+        eviction_policy: None,
+        ..Default::default()
+    };
+    {
+        let store = FilesystemStore::<FileEntryImpl>::new(&spec).await?;
+        store.update_oneshot(digest1, VALUE1.into()).await?;
+        store.update_oneshot(digest2, VALUE2.into()).await?;
+        store
+            .update_oneshot(StoreKey::new_str(STRING_NAME), VALUE1.into())
+            .await?;
+    }
+
+    let store = FilesystemStore::<FileEntryImpl>::new(&spec).await?;
+    let mut keys = vec![];
+    let count = store
+        .list(.., |key| {
+            keys.push(key.borrow().into_owned());
+            true
+        })
+        .await?;
+    assert_eq!(count, 3);
+    assert_eq!(
+        keys,
+        vec![
+            StoreKey::new_str(STRING_NAME).into_owned(),
+            StoreKey::Digest(digest1),
+            StoreKey::Digest(digest2),
+        ]
+    );
+
+    // Listing stops as soon as the handler returns false.
+    let mut keys = vec![];
+    let count = store
+        .list(StoreKey::Digest(digest1).., |key| {
+            keys.push(key.borrow().into_owned());
+            false
+        })
+        .await?;
+    assert_eq!(count, 0);
+    assert_eq!(keys, vec![StoreKey::Digest(digest1)]);
+    Ok(())
+}
+
+// Without the `io_uring` feature or kernel support this covers the fallback
+// to regular file I/O.
+#[serial]
+#[nativelink_test]
+async fn reads_and_writes_with_io_uring_test() -> Result<(), Error> {
+    let digest = DigestInfo::try_new(HASH1, VALUE1.len())?;
+    let store = Store::new(
+        FilesystemStore::<FileEntryImpl>::new(&FilesystemSpec {
+            content_path: make_temp_path("content_path"),
+            temp_path: make_temp_path("temp_path"),
+            // A small buffer, so the value is read in multiple chunks.
+            read_buffer_size: 3,
+            use_io_uring: true,
+            ..Default::default()
+        })
+        .await?,
+    );
+
+    store.update_oneshot(digest, VALUE1.into()).await?;
+    assert_eq!(store.has(digest).await?, Some(VALUE1.len() as u64));
+    assert_eq!(
+        store.get_part_unchunked(digest, 0, None).await?,
+        VALUE1.as_bytes()
+    );
+    assert_eq!(
+        store.get_part_unchunked(digest, 2, Some(5)).await?,
+        VALUE1[2..7].as_bytes()
+    );
+    Ok(())
+}
+
+#[serial]
+#[nativelink_test]
+async fn reads_and_writes_with_direct_io_test() -> Result<(), Error> {
+    // Larger than one aligned block and not a multiple of it, so both the
+    // aligned part and the padded tail are written.
+    let value: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+    let digest = DigestInfo::try_new(HASH1, value.len())?;
+    let store = Store::new(
+        FilesystemStore::<FileEntryImpl>::new(&FilesystemSpec {
+            content_path: make_temp_path("content_path"),
+            temp_path: make_temp_path("temp_path"),
+            read_buffer_size: 3000,
+            use_direct_io: true,
+            ..Default::default()
+        })
+        .await?,
+    );
+
+    store
+        .update_oneshot(digest, Bytes::from(value.clone()))
+        .await?;
+    assert_eq!(store.has(digest).await?, Some(value.len() as u64));
+    assert_eq!(store.get_part_unchunked(digest, 0, None).await?, &value[..]);
+    // Reads that neither start nor end on an aligned offset.
+    assert_eq!(
+        store.get_part_unchunked(digest, 4000, Some(5000)).await?,
+        &value[4000..9000]
+    );
+    Ok(())
+}
+
+#[serial]
+#[nativelink_test]
+async fn fsck_on_startup_quarantines_truncated_files_test() -> Result<(), Error> {
+    let digest1 = DigestInfo::try_new(HASH1, VALUE1.len())?;
+    let digest2 = DigestInfo::try_new(HASH2, VALUE2.len())?;
+    let content_path = make_temp_path("content_path");
+    let temp_path = make_temp_path("temp_path");
+    let quarantine_path = make_temp_path("quarantine_path");
+    {
+        let store = FilesystemStore::<FileEntryImpl>::new(&FilesystemSpec {
+            content_path: content_path.clone(),
+            temp_path: temp_path.clone(),
+            ..Default::default()
+        })
+        .await?;
+        store.update_oneshot(digest1, VALUE1.into()).await?;
+        store.update_oneshot(digest2, VALUE2.into()).await?;
+    }
+    // Simulate a crash that left the first file truncated.
+    std::fs::write(
+        format!("{content_path}/{DIGEST_FOLDER}/{digest1}"),
+        &VALUE1[..4],
+    )?;
+
+    let store = FilesystemStore::<FileEntryImpl>::new(&FilesystemSpec {
+        content_path,
+        temp_path,
+        fsck_on_startup: FsckMode::size,
+        fsck_quarantine_path: quarantine_path.clone(),
+        ..Default::default()
+    })
+    .await?;
+    assert_eq!(store.has(digest1).await?, None);
+    assert_eq!(store.has(digest2).await?, Some(VALUE2.len() as u64));
+    assert_eq!(
+        std::fs::read(format!("{quarantine_path}/{digest1}"))?,
+        VALUE1[..4].as_bytes()
+    );
+    Ok(())
+}
+
+#[serial]
+#[nativelink_test]
+async fn fsck_with_hash_mode_finds_corrupt_content_test() -> Result<(), Error> {
+    const VALUE: &str = "some content";
+    let digest = DigestInfo::new(Sha256::digest(VALUE).into(), VALUE.len() as u64);
+    let content_path = make_temp_path("content_path");
+    let store = FilesystemStore::<FileEntryImpl>::new(&FilesystemSpec {
+        content_path: content_path.clone(),
+        temp_path: make_temp_path("temp_path"),
+        ..Default::default()
+    })
+    .await?;
+    store.update_oneshot(digest, VALUE.into()).await?;
+    store
+        .update_oneshot(StoreKey::new_str(STRING_NAME), VALUE.into())
+        .await?;
+
+    assert_eq!(
+        store.fsck(FsckMode::hash).await?,
+        FsckReport {
+            checked: 1,
+            corrupt: 0,
+            skipped: 1,
+        }
+    );
+
+    // Same size, different content, so only hashing notices.
+    std::fs::write(
+        format!("{content_path}/{DIGEST_FOLDER}/{digest}"),
+        "SOME CONTENT",
+    )?;
+    assert_eq!(
+        store.fsck(FsckMode::size).await?,
+        FsckReport {
+            checked: 1,
+            corrupt: 0,
+            skipped: 1,
+        }
+    );
+    assert_eq!(
+        store.fsck(FsckMode::hash).await?,
+        FsckReport {
+            checked: 1,
+            corrupt: 1,
+            skipped: 1,
+        }
+    );
+    assert_eq!(store.has(digest).await?, None);
+    Ok(())
+}
+
+#[serial]
+#[nativelink_test]
+async fn index_snapshot_keeps_lru_order_across_restarts_test() -> Result<(), Error> {
+    const HASH3: &str = "0123456789abcdef000000000000000000030000000000000123456789abcdef";
+    let digest1 = DigestInfo::try_new(HASH1, VALUE1.len())?;
+    let digest2 = DigestInfo::try_new(HASH2, VALUE2.len())?;
+    let digest3 = DigestInfo::try_new(HASH3, VALUE1.len())?;
+    let content_path = make_temp_path("content_path");
+    let temp_path = make_temp_path("temp_path");
+    let index_snapshot_dir = make_temp_path("index_snapshot");
+    fs::create_dir_all(&index_snapshot_dir).await?;
+    let index_snapshot_path = format!("{index_snapshot_dir}/index");
+    let spec = FilesystemSpec {
+        content_path,
+        temp_path,
+        index_snapshot_path,
+        eviction_policy: Some(nativelink_config::stores::EvictionPolicy {
+            max_count: 2,
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    {
+        let store = FilesystemStore::<FileEntryImpl>::new(&spec).await?;
+        store.update_oneshot(digest1, VALUE1.into()).await?;
+        store.update_oneshot(digest2, VALUE2.into()).await?;
+        // Makes digest1 the most recently used entry.
+        assert_eq!(
+            store.get_part_unchunked(digest1, 0, None).await?,
+            VALUE1.as_bytes()
+        );
+        store.write_index_snapshot().await?;
+    }
+    {
+        let store = FilesystemStore::<FileEntryImpl>::new(&spec).await?;
+        store.update_oneshot(digest3, VALUE1.into()).await?;
+        assert_eq!(
+            store.has(digest1).await?,
+            Some(VALUE1.len() as u64),
+            "Expected the most recently used entry to be kept"
+        );
+        assert_eq!(
+            store.has(digest2).await?,
+            None,
+            "Expected the least recently used entry to be evicted"
+        );
+        assert_eq!(store.has(digest3).await?, Some(VALUE1.len() as u64));
+    }
+    Ok(())
+}
+
+#[serial]
+#[nativelink_test]
+async fn index_snapshot_reconciles_with_files_on_disk_test() -> Result<(), Error> {
+    let digest1 = DigestInfo::try_new(HASH1, VALUE1.len())?;
+    let digest2 = DigestInfo::try_new(HASH2, VALUE2.len())?;
+    let content_path = make_temp_path("content_path");
+    let temp_path = make_temp_path("temp_path");
+    let index_snapshot_dir = make_temp_path("index_snapshot");
+    fs::create_dir_all(&index_snapshot_dir).await?;
+    let index_snapshot_path = format!("{index_snapshot_dir}/index");
+    let spec = FilesystemSpec {
+        content_path: content_path.clone(),
+        temp_path,
+        index_snapshot_path,
+        ..Default::default()
+    };
+    {
+        let store = FilesystemStore::<FileEntryImpl>::new(&spec).await?;
+        store.update_oneshot(digest1, VALUE1.into()).await?;
+        store.write_index_snapshot().await?;
+        // Written after the snapshot, so it is only on disk.
+        store.update_oneshot(digest2, VALUE2.into()).await?;
+    }
+    // Deleted after the snapshot, so it is only in the snapshot.
+    fs::remove_file(format!("{content_path}/{DIGEST_FOLDER}/{digest1}")).await?;
+    {
+        let store = FilesystemStore::<FileEntryImpl>::new(&spec).await?;
+        assert_eq!(store.has(digest1).await?, None);
+        assert_eq!(
+            store.get_part_unchunked(digest2, 0, None).await?,
+            VALUE2.as_bytes()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,286 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use hyper::{Body, Method, Request, Response, StatusCode};
+use nativelink_config::stores::{GcsSpec, Retry};
+use nativelink_error::{Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_store::gcs_store::{GcsHttpClient, GcsStore, GcsTokenProvider};
+use nativelink_util::common::DigestInfo;
+use nativelink_util::instant_wrapper::MockInstantWrapped;
+use nativelink_util::store_trait::StoreLike;
+use pretty_assertions::assert_eq;
+
+const BUCKET_NAME: &str = "dummy-bucket-name";
+const VALID_HASH1: &str = "0123456789abcdef000000000000000000010000000000000123456789abcdef";
+const ACCESS_TOKEN: &str = "dummy-access-token";
+const SESSION_URL: &str = "https://storage.googleapis.com/upload/session/1234";
+const RESUMABLE_CHUNK_SIZE: usize = 256 * 1024;
+
+struct ExpectedRequest {
+    method: Method,
+    uri: String,
+    headers: Vec<(&'static str, String)>,
+    body: Option<Bytes>,
+    response: Result<(StatusCode, Vec<(&'static str, String)>, Bytes), Error>,
+}
+
+impl ExpectedRequest {
+    fn new(method: Method, uri: impl Into<String>) -> Self {
+        Self {
+            method,
+            uri: uri.into(),
+            headers: Vec::new(),
+            body: None,
+            response: Ok((StatusCode::OK, Vec::new(), Bytes::new())),
+        }
+    }
+
+    fn header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    fn body(mut self, body: impl Into<Bytes>) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+
+    fn respond(mut self, status: StatusCode, headers: Vec<(&'static str, String)>) -> Self {
+        self.response = Ok((status, headers, Bytes::new()));
+        self
+    }
+
+    fn respond_with_body(mut self, status: StatusCode, body: impl Into<Bytes>) -> Self {
+        self.response = Ok((status, Vec::new(), body.into()));
+        self
+    }
+
+    fn fail(mut self, err: Error) -> Self {
+        self.response = Err(err);
+        self
+    }
+}
+
+#[derive(Default)]
+struct MockHttpClient {
+    expected: Mutex<VecDeque<ExpectedRequest>>,
+}
+
+impl MockHttpClient {
+    fn new(expected: Vec<ExpectedRequest>) -> Arc<Self> {
+        Arc::new(Self {
+            expected: Mutex::new(expected.into()),
+        })
+    }
+
+    fn assert_done(&self) {
+        assert!(
+            self.expected.lock().unwrap().is_empty(),
+            "Not all expected requests were sent"
+        );
+    }
+}
+
+#[async_trait]
+impl GcsHttpClient for MockHttpClient {
+    async fn send(&self, request: Request<Body>) -> Result<Response<Body>, Error> {
+        let expected = self
+            .expected
+            .lock()
+            .unwrap()
+            .pop_front()
+            .unwrap_or_else(|| panic!("Unexpected request {request:?}"));
+        assert_eq!(request.method(), &expected.method);
+        assert_eq!(request.uri().to_string(), expected.uri);
+        assert_eq!(
+            request.headers()["authorization"],
+            format!("Bearer {ACCESS_TOKEN}")
+        );
+        for (name, value) in &expected.headers {
+            assert_eq!(request.headers()[*name], value, "Mismatched {name} header");
+        }
+        let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+        if let Some(expected_body) = &expected.body {
+            assert_eq!(&body, expected_body);
+        }
+        let (status, headers, body) = expected.response?;
+        let mut response = Response::builder().status(status);
+        for (name, value) in headers {
+            response = response.header(name, value);
+        }
+        Ok(response.body(Body::from(body)).unwrap())
+    }
+}
+
+struct StaticTokenProvider;
+
+#[async_trait]
+impl GcsTokenProvider for StaticTokenProvider {
+    async fn access_token(&self) -> Result<String, Error> {
+        Ok(ACCESS_TOKEN.to_string())
+    }
+}
+
+fn make_store(
+    http_client: Arc<MockHttpClient>,
+) -> Result<Arc<GcsStore<fn() -> MockInstantWrapped>>, Error> {
+    GcsStore::new_with_client_and_jitter(
+        &GcsSpec {
+            bucket: BUCKET_NAME.to_string(),
+            retry: Retry {
+                max_retries: 3,
+                ..Default::default()
+            },
+            resumable_chunk_size: RESUMABLE_CHUNK_SIZE,
+            ..Default::default()
+        },
+        http_client,
+        Arc::new(StaticTokenProvider),
+        Arc::new(move |_delay| Duration::from_secs(0)),
+        MockInstantWrapped::default as fn() -> MockInstantWrapped,
+    )
+}
+
+fn object_url(digest: DigestInfo) -> String {
+    format!("https://storage.googleapis.com/{BUCKET_NAME}/{digest}")
+}
+
+fn upload_url(digest: DigestInfo, upload_type: &str) -> String {
+    format!(
+        "https://storage.googleapis.com/upload/storage/v1/b/{BUCKET_NAME}/o?uploadType={upload_type}&name={digest}"
+    )
+}
+
+#[nativelink_test]
+async fn has_object_found_and_missing() -> Result<(), Error> {
+    let found = DigestInfo::try_new(VALID_HASH1, 100)?;
+    let missing = DigestInfo::try_new(VALID_HASH1, 101)?;
+    let http_client = MockHttpClient::new(vec![
+        ExpectedRequest::new(Method::HEAD, object_url(found))
+            .respond(StatusCode::OK, vec![("content-length", "512".to_string())]),
+        ExpectedRequest::new(Method::HEAD, object_url(missing))
+            .respond(StatusCode::NOT_FOUND, Vec::new()),
+    ]);
+    let store = make_store(http_client.clone())?;
+
+    assert_eq!(store.has(found).await, Ok(Some(512)));
+    assert_eq!(store.has(missing).await, Ok(None));
+    http_client.assert_done();
+    Ok(())
+}
+
+#[nativelink_test]
+async fn get_part_requests_range() -> Result<(), Error> {
+    let digest = DigestInfo::try_new(VALID_HASH1, 10)?;
+    let http_client =
+        MockHttpClient::new(vec![ExpectedRequest::new(Method::GET, object_url(digest))
+            .header("range", "bytes=2-5")
+            .respond_with_body(StatusCode::PARTIAL_CONTENT, "2345")]);
+    let store = make_store(http_client.clone())?;
+
+    let data = store.get_part_unchunked(digest, 2, Some(4)).await?;
+    assert_eq!(data, Bytes::from_static(b"2345"));
+    http_client.assert_done();
+    Ok(())
+}
+
+#[nativelink_test]
+async fn get_part_missing_object_is_not_found() -> Result<(), Error> {
+    let digest = DigestInfo::try_new(VALID_HASH1, 10)?;
+    let http_client =
+        MockHttpClient::new(vec![ExpectedRequest::new(Method::GET, object_url(digest))
+            .header("range", "bytes=0-")
+            .respond(StatusCode::NOT_FOUND, Vec::new())]);
+    let store = make_store(http_client.clone())?;
+
+    let err = store.get_part_unchunked(digest, 0, None).await.unwrap_err();
+    assert_eq!(err.code, Code::NotFound, "{err:?}");
+    http_client.assert_done();
+    Ok(())
+}
+
+#[nativelink_test]
+async fn small_upload_is_sent_in_one_request() -> Result<(), Error> {
+    let data = Bytes::from_static(b"0123456789");
+    let digest = DigestInfo::try_new(VALID_HASH1, data.len())?;
+    let http_client = MockHttpClient::new(vec![
+        // The first attempt fails and is retried from memory.
+        ExpectedRequest::new(Method::POST, upload_url(digest, "media"))
+            .body(data.clone())
+            .respond(StatusCode::SERVICE_UNAVAILABLE, Vec::new()),
+        ExpectedRequest::new(Method::POST, upload_url(digest, "media")).body(data.clone()),
+    ]);
+    let store = make_store(http_client.clone())?;
+
+    store.update_oneshot(digest, data).await?;
+    http_client.assert_done();
+    Ok(())
+}
+
+#[nativelink_test]
+async fn resumable_upload_resumes_from_persisted_offset() -> Result<(), Error> {
+    const TOTAL_LEN: usize = RESUMABLE_CHUNK_SIZE + 10;
+    let data: Bytes = (0..TOTAL_LEN).map(|i| (i % 251) as u8).collect();
+    let digest = DigestInfo::try_new(VALID_HASH1, TOTAL_LEN)?;
+    let half = RESUMABLE_CHUNK_SIZE / 2;
+    let http_client = MockHttpClient::new(vec![
+        ExpectedRequest::new(Method::POST, upload_url(digest, "resumable"))
+            .respond(StatusCode::OK, vec![("location", SESSION_URL.to_string())]),
+        // The connection drops while sending the first part.
+        ExpectedRequest::new(Method::PUT, SESSION_URL)
+            .header(
+                "content-range",
+                format!("bytes 0-{}/*", RESUMABLE_CHUNK_SIZE - 1),
+            )
+            .fail(Error::new(
+                Code::Unavailable,
+                "connection reset".to_string(),
+            )),
+        // GCS persisted the first half of it.
+        ExpectedRequest::new(Method::PUT, SESSION_URL)
+            .header("content-range", "bytes */*")
+            .respond(
+                StatusCode::PERMANENT_REDIRECT,
+                vec![("range", format!("bytes=0-{}", half - 1))],
+            ),
+        ExpectedRequest::new(Method::PUT, SESSION_URL)
+            .header(
+                "content-range",
+                format!("bytes {half}-{}/*", RESUMABLE_CHUNK_SIZE - 1),
+            )
+            .body(data.slice(half..RESUMABLE_CHUNK_SIZE))
+            .respond(
+                StatusCode::PERMANENT_REDIRECT,
+                vec![("range", format!("bytes=0-{}", RESUMABLE_CHUNK_SIZE - 1))],
+            ),
+        ExpectedRequest::new(Method::PUT, SESSION_URL)
+            .header(
+                "content-range",
+                format!("bytes {RESUMABLE_CHUNK_SIZE}-{}/{TOTAL_LEN}", TOTAL_LEN - 1),
+            )
+            .body(data.slice(RESUMABLE_CHUNK_SIZE..)),
+    ]);
+    let store = make_store(http_client.clone())?;
+
+    store.update_oneshot(digest, data).await?;
+    http_client.assert_done();
+    Ok(())
+}
@@ -0,0 +1,259 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use hyper::{Body, Method, Request, Response, StatusCode};
+use nativelink_config::stores::{HttpSpec, Retry};
+use nativelink_error::{Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_store::http_store::{HttpClient, HttpStore};
+use nativelink_util::common::DigestInfo;
+use nativelink_util::store_trait::StoreLike;
+use pretty_assertions::assert_eq;
+
+const ENDPOINT: &str = "https://cache.example.com/cas";
+const VALID_HASH1: &str = "0123456789abcdef000000000000000000010000000000000123456789abcdef";
+const AUTH_HEADER: &str = "Bearer dummy-token";
+
+struct ExpectedRequest {
+    method: Method,
+    uri: String,
+    range: Option<String>,
+    body: Option<Bytes>,
+    response: (StatusCode, Vec<(&'static str, String)>, Bytes),
+}
+
+impl ExpectedRequest {
+    fn new(method: Method, uri: impl Into<String>) -> Self {
+        Self {
+            method,
+            uri: uri.into(),
+            range: None,
+            body: None,
+            response: (StatusCode::OK, Vec::new(), Bytes::new()),
+        }
+    }
+
+    fn range(mut self, range: &str) -> Self {
+        self.range = Some(range.to_string());
+        self
+    }
+
+    fn body(mut self, body: impl Into<Bytes>) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+
+    fn respond(mut self, status: StatusCode, headers: Vec<(&'static str, String)>) -> Self {
+        self.response = (status, headers, Bytes::new());
+        self
+    }
+
+    fn respond_with_body(mut self, status: StatusCode, body: impl Into<Bytes>) -> Self {
+        self.response = (status, Vec::new(), body.into());
+        self
+    }
+}
+
+#[derive(Default)]
+struct MockHttpClient {
+    expected: Mutex<VecDeque<ExpectedRequest>>,
+}
+
+impl MockHttpClient {
+    fn new(expected: Vec<ExpectedRequest>) -> Arc<Self> {
+        Arc::new(Self {
+            expected: Mutex::new(expected.into()),
+        })
+    }
+
+    fn assert_done(&self) {
+        assert!(
+            self.expected.lock().unwrap().is_empty(),
+            "Not all expected requests were sent"
+        );
+    }
+}
+
+#[async_trait]
+impl HttpClient for MockHttpClient {
+    async fn send(&self, request: Request<Body>) -> Result<Response<Body>, Error> {
+        let expected = self
+            .expected
+            .lock()
+            .unwrap()
+            .pop_front()
+            .unwrap_or_else(|| panic!("Unexpected request {request:?}"));
+        assert_eq!(request.method(), &expected.method);
+        assert_eq!(request.uri().to_string(), expected.uri);
+        assert_eq!(request.headers()["authorization"], AUTH_HEADER);
+        assert_eq!(
+            request
+                .headers()
+                .get("range")
+                .map(|v| v.to_str().unwrap().to_string()),
+            expected.range
+        );
+        let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+        if let Some(expected_body) = &expected.body {
+            assert_eq!(&body, expected_body);
+        }
+        let (status, headers, body) = expected.response;
+        let mut response = Response::builder().status(status);
+        for (name, value) in headers {
+            response = response.header(name, value);
+        }
+        Ok(response.body(Body::from(body)).unwrap())
+    }
+}
+
+fn make_store(spec: HttpSpec, http_client: Arc<MockHttpClient>) -> Result<Arc<HttpStore>, Error> {
+    HttpStore::new_with_client_and_jitter(
+        &HttpSpec {
+            endpoint: ENDPOINT.to_string(),
+            headers: HashMap::from([("Authorization".to_string(), AUTH_HEADER.to_string())]),
+            retry: Retry {
+                max_retries: 3,
+                ..Default::default()
+            },
+            ..spec
+        },
+        http_client,
+        Arc::new(move |_delay| Duration::from_secs(0)),
+    )
+}
+
+#[nativelink_test]
+async fn has_file_found_and_missing() -> Result<(), Error> {
+    let found = DigestInfo::try_new(VALID_HASH1, 100)?;
+    let missing = DigestInfo::try_new(VALID_HASH1, 101)?;
+    let http_client = MockHttpClient::new(vec![
+        ExpectedRequest::new(Method::HEAD, format!("{ENDPOINT}/{found}"))
+            .respond(StatusCode::OK, vec![("content-length", "100".to_string())]),
+        ExpectedRequest::new(Method::HEAD, format!("{ENDPOINT}/{missing}"))
+            .respond(StatusCode::NOT_FOUND, Vec::new()),
+    ]);
+    let store = make_store(HttpSpec::default(), http_client.clone())?;
+
+    assert_eq!(store.has(found).await, Ok(Some(100)));
+    assert_eq!(store.has(missing).await, Ok(None));
+    http_client.assert_done();
+    Ok(())
+}
+
+#[nativelink_test]
+async fn hash_only_paths_with_key_prefix() -> Result<(), Error> {
+    let digest = DigestInfo::try_new(VALID_HASH1, 100)?;
+    let http_client = MockHttpClient::new(vec![ExpectedRequest::new(
+        Method::HEAD,
+        format!("{ENDPOINT}/cas/{VALID_HASH1}"),
+    )
+    .respond(StatusCode::OK, vec![("content-length", "100".to_string())])]);
+    let store = make_store(
+        HttpSpec {
+            key_prefix: Some("cas/".to_string()),
+            hash_only_paths: true,
+            ..Default::default()
+        },
+        http_client.clone(),
+    )?;
+
+    assert_eq!(store.has(digest).await, Ok(Some(100)));
+    http_client.assert_done();
+    Ok(())
+}
+
+#[nativelink_test]
+async fn get_part_requests_range() -> Result<(), Error> {
+    let digest = DigestInfo::try_new(VALID_HASH1, 10)?;
+    let http_client = MockHttpClient::new(vec![
+        ExpectedRequest::new(Method::GET, format!("{ENDPOINT}/{digest}"))
+            .respond_with_body(StatusCode::OK, "0123456789"),
+        ExpectedRequest::new(Method::GET, format!("{ENDPOINT}/{digest}"))
+            .range("bytes=2-5")
+            .respond_with_body(StatusCode::PARTIAL_CONTENT, "2345"),
+    ]);
+    let store = make_store(HttpSpec::default(), http_client.clone())?;
+
+    assert_eq!(
+        store.get_part_unchunked(digest, 0, None).await?,
+        Bytes::from_static(b"0123456789")
+    );
+    assert_eq!(
+        store.get_part_unchunked(digest, 2, Some(4)).await?,
+        Bytes::from_static(b"2345")
+    );
+    http_client.assert_done();
+    Ok(())
+}
+
+#[nativelink_test]
+async fn get_part_rejects_ignored_range() -> Result<(), Error> {
+    let digest = DigestInfo::try_new(VALID_HASH1, 10)?;
+    let http_client = MockHttpClient::new(vec![ExpectedRequest::new(
+        Method::GET,
+        format!("{ENDPOINT}/{digest}"),
+    )
+    .range("bytes=2-5")
+    .respond_with_body(StatusCode::OK, "0123456789")]);
+    let store = make_store(HttpSpec::default(), http_client.clone())?;
+
+    let err = store
+        .get_part_unchunked(digest, 2, Some(4))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code, Code::Unavailable, "{err:?}");
+    http_client.assert_done();
+    Ok(())
+}
+
+#[nativelink_test]
+async fn upload_is_retried_from_buffer() -> Result<(), Error> {
+    let data = Bytes::from_static(b"0123456789");
+    let digest = DigestInfo::try_new(VALID_HASH1, data.len())?;
+    let http_client = MockHttpClient::new(vec![
+        ExpectedRequest::new(Method::PUT, format!("{ENDPOINT}/{digest}"))
+            .body(data.clone())
+            .respond(StatusCode::SERVICE_UNAVAILABLE, Vec::new()),
+        ExpectedRequest::new(Method::PUT, format!("{ENDPOINT}/{digest}"))
+            .body(data.clone())
+            .respond(StatusCode::CREATED, Vec::new()),
+    ]);
+    let store = make_store(HttpSpec::default(), http_client.clone())?;
+
+    store.update_oneshot(digest, data).await?;
+    http_client.assert_done();
+    Ok(())
+}
+
+#[nativelink_test]
+async fn upload_client_error_is_not_retried() -> Result<(), Error> {
+    let data = Bytes::from_static(b"0123456789");
+    let digest = DigestInfo::try_new(VALID_HASH1, data.len())?;
+    let http_client = MockHttpClient::new(vec![ExpectedRequest::new(
+        Method::PUT,
+        format!("{ENDPOINT}/{digest}"),
+    )
+    .respond(StatusCode::FORBIDDEN, Vec::new())]);
+    let store = make_store(HttpSpec::default(), http_client.clone())?;
+
+    assert!(store.update_oneshot(digest, data).await.is_err());
+    http_client.assert_done();
+    Ok(())
+}
@@ -12,20 +12,25 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::env;
 use std::ops::RangeBounds;
 use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
 
 use bytes::{BufMut, Bytes, BytesMut};
 use memory_stats::memory_stats;
-use nativelink_config::stores::MemorySpec;
-use nativelink_error::{Error, ResultExt};
+use nativelink_config::stores::{EvictionPolicy, FilesystemSpec, MemorySpec, StoreSpec};
+use nativelink_error::{Code, Error, ResultExt};
 use nativelink_macro::nativelink_test;
+use nativelink_store::filesystem_store::FilesystemStore;
 use nativelink_store::memory_store::MemoryStore;
 use nativelink_util::buf_channel::make_buf_channel_pair;
 use nativelink_util::common::DigestInfo;
 use nativelink_util::spawn;
-use nativelink_util::store_trait::{StoreKey, StoreLike};
+use nativelink_util::store_trait::{Store, StoreKey, StoreLike};
 use pretty_assertions::assert_eq;
+use rand::{thread_rng, Rng};
 use sha2::{Digest, Sha256};
 
 const VALID_HASH1: &str = "0123456789abcdef000000000000000000010000000000000123456789abcdef";
@@ -348,3 +353,227 @@ async fn list_test() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[nativelink_test]
+async fn update_many_stores_every_item() -> Result<(), Error> {
+    const VALUE1: &str = "13";
+    const VALUE2: &str = "456";
+    let store = MemoryStore::new(&MemorySpec::default());
+    let digest1 = DigestInfo::try_new(VALID_HASH1, VALUE1.len())?;
+    let digest2 = DigestInfo::try_new(VALID_HASH2, VALUE2.len())?;
+
+    let results = store
+        .update_many(vec![
+            (digest1.into(), Bytes::from_static(VALUE1.as_bytes())),
+            (digest2.into(), Bytes::from_static(VALUE2.as_bytes())),
+        ])
+        .await;
+    assert_eq!(results, vec![Ok(()), Ok(())]);
+    assert_eq!(
+        store.get_part_unchunked(digest1, 0, None).await?,
+        Bytes::from_static(VALUE1.as_bytes())
+    );
+    assert_eq!(
+        store.get_part_unchunked(digest2, 0, None).await?,
+        Bytes::from_static(VALUE2.as_bytes())
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn sharded_store_finds_and_lists_every_key() -> Result<(), Error> {
+    const VALUE: &str = "value";
+    // Digests with different prefixes, so they land in different shards.
+    let digests = [
+        "0123456789abcdef000000000000000000010000000000000123456789abcdef",
+        "123456789abcdef0000000000000000000020000000000000123456789abcdef",
+        "23456789abcdef01000000000000000000030000000000000123456789abcdef",
+        "3456789abcdef012000000000000000000040000000000000123456789abcdef",
+    ]
+    .map(|hash| DigestInfo::try_new(hash, VALUE.len()).unwrap());
+    let store = MemoryStore::new(&MemorySpec {
+        shard_count: 4,
+        ..Default::default()
+    });
+    for digest in digests {
+        store.update_oneshot(digest, VALUE.into()).await?;
+    }
+    store
+        .update_oneshot(StoreKey::new_str("key1"), VALUE.into())
+        .await?;
+    assert_eq!(store.len_for_test().await, 5);
+
+    let keys: Vec<StoreKey<'static>> = digests.iter().map(|digest| (*digest).into()).collect();
+    assert_eq!(
+        store.has_many(&keys).await?,
+        vec![Some(VALUE.len() as u64); digests.len()]
+    );
+    assert_eq!(
+        store.get_part_unchunked(digests[2], 0, None).await?,
+        Bytes::from_static(VALUE.as_bytes())
+    );
+
+    let mut found_keys = vec![];
+    store
+        .list(.., |key| {
+            found_keys.push(key.borrow().into_owned());
+            true
+        })
+        .await?;
+    let mut expected_keys = keys.clone();
+    expected_keys.push(StoreKey::new_str("key1"));
+    expected_keys.sort();
+    assert_eq!(found_keys, expected_keys);
+    Ok(())
+}
+
+/// Get temporary path from either `TEST_TMPDIR` or best effort temp directory if
+/// not set.
+fn make_temp_path(data: &str) -> String {
+    format!(
+        "{}/{}/{}",
+        env::var("TEST_TMPDIR").unwrap_or(env::temp_dir().to_str().unwrap().to_string()),
+        thread_rng().gen::<u64>(),
+        data
+    )
+}
+
+async fn make_spilling_store() -> Result<(Arc<MemoryStore>, Store), Error> {
+    let spill_store = Store::new(
+        <FilesystemStore>::new(&FilesystemSpec {
+            content_path: make_temp_path("content_path"),
+            temp_path: make_temp_path("temp_path"),
+            ..Default::default()
+        })
+        .await?,
+    );
+    let store = MemoryStore::new_with_spill_store(
+        &MemorySpec {
+            eviction_policy: Some(EvictionPolicy {
+                max_count: 1,
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        Some(spill_store.clone()),
+    );
+    Ok((store, spill_store))
+}
+
+async fn wait_until_spilled(spill_store: &Store, digest: DigestInfo) -> Result<(), Error> {
+    while spill_store.has(digest).await?.is_none() {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+    Ok(())
+}
+
+#[nativelink_test]
+async fn evicted_entries_are_spilled_and_promoted_on_read() -> Result<(), Error> {
+    const VALUE1: &str = "value1";
+    const VALUE2: &str = "value2";
+    let digest1 = DigestInfo::try_new(VALID_HASH1, VALUE1.len())?;
+    let digest2 = DigestInfo::try_new(VALID_HASH2, VALUE2.len())?;
+    let (store, spill_store) = make_spilling_store().await?;
+
+    store.update_oneshot(digest1, VALUE1.into()).await?;
+    // Only one entry fits in memory, so the first one is spilled.
+    store.update_oneshot(digest2, VALUE2.into()).await?;
+    wait_until_spilled(&spill_store, digest1).await?;
+    assert_eq!(store.len_for_test().await, 1);
+    assert_eq!(store.has(digest1).await?, Some(VALUE1.len() as u64));
+
+    // Reading the spilled entry moves it back into memory and spills the
+    // other one in turn.
+    assert_eq!(
+        store.get_part_unchunked(digest1, 0, None).await?,
+        Bytes::from_static(VALUE1.as_bytes())
+    );
+    wait_until_spilled(&spill_store, digest2).await?;
+    assert_eq!(
+        store.get_part_unchunked(digest2, 0, None).await?,
+        Bytes::from_static(VALUE2.as_bytes())
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn removed_entries_are_not_spilled() -> Result<(), Error> {
+    const VALUE1: &str = "value1";
+    const VALUE2: &str = "value2";
+    let digest1 = DigestInfo::try_new(VALID_HASH1, VALUE1.len())?;
+    let digest2 = DigestInfo::try_new(VALID_HASH2, VALUE2.len())?;
+    let (store, spill_store) = make_spilling_store().await?;
+
+    store.update_oneshot(digest1, VALUE1.into()).await?;
+    assert!(store.remove_entry(digest1.into()).await);
+    store.update_oneshot(digest2, VALUE2.into()).await?;
+    store.update_oneshot(digest1, VALUE1.into()).await?;
+    // Spills are written in order, so a spill of the removed entry would
+    // be done by the time the second entry is on disk.
+    wait_until_spilled(&spill_store, digest2).await?;
+    assert_eq!(store.has(digest1).await?, Some(VALUE1.len() as u64));
+    assert!(store.remove_entry(digest1.into()).await);
+    assert_eq!(store.has(digest1).await?, None);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn removed_entries_are_removed_from_spill_store() -> Result<(), Error> {
+    const VALUE1: &str = "value1";
+    const VALUE2: &str = "value2";
+    let digest1 = DigestInfo::try_new(VALID_HASH1, VALUE1.len())?;
+    let digest2 = DigestInfo::try_new(VALID_HASH2, VALUE2.len())?;
+    let (store, spill_store) = make_spilling_store().await?;
+
+    store.update_oneshot(digest1, VALUE1.into()).await?;
+    store.update_oneshot(digest2, VALUE2.into()).await?;
+    wait_until_spilled(&spill_store, digest1).await?;
+    // Only the spilled copy is left, it must not be promoted back later.
+    assert!(store.remove_entry(digest1.into()).await);
+    assert_eq!(spill_store.has(digest1).await?, None);
+    assert_eq!(store.has(digest1).await?, None);
+
+    // Promoting an entry removes its spilled copy.
+    store.update_oneshot(digest1, VALUE1.into()).await?;
+    wait_until_spilled(&spill_store, digest2).await?;
+    store.get_part_unchunked(digest2, 0, None).await?;
+    while spill_store.has(digest2).await?.is_some() {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+    assert!(store.remove_entry(digest2.into()).await);
+    assert_eq!(store.has(digest2).await?, None);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn reconfigure_applies_new_eviction_policy() -> Result<(), Error> {
+    const VALUE1: &str = "value1";
+    const VALUE2: &str = "value2";
+    let digest1 = DigestInfo::try_new(VALID_HASH1, VALUE1.len())?;
+    let digest2 = DigestInfo::try_new(VALID_HASH2, VALUE2.len())?;
+    let store = Store::new(MemoryStore::new(&MemorySpec::default()));
+    store.update_oneshot(digest1, VALUE1.into()).await?;
+    store.update_oneshot(digest2, VALUE2.into()).await?;
+
+    store
+        .reconfigure(&StoreSpec::memory(MemorySpec {
+            eviction_policy: Some(EvictionPolicy {
+                max_count: 1,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }))
+        .await?;
+    assert_eq!(store.has(digest1).await?, None);
+    assert_eq!(store.has(digest2).await?, Some(VALUE2.len() as u64));
+
+    let err = store
+        .reconfigure(&StoreSpec::memory(MemorySpec {
+            shard_count: 4,
+            ..Default::default()
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code, Code::InvalidArgument, "{err:?}");
+    Ok(())
+}
@@ -0,0 +1,107 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use nativelink_config::stores::MemorySpec;
+use nativelink_error::Error;
+use nativelink_macro::nativelink_test;
+use nativelink_store::memory_store::MemoryStore;
+use nativelink_store::metrics_store::MetricsStore;
+use nativelink_util::common::DigestInfo;
+use nativelink_util::store_trait::{Store, StoreLike};
+use pretty_assertions::assert_eq;
+
+const VALID_HASH1: &str = "0123456789abcdef000000000000000000010000000000000123456789abcdef";
+
+/// Returns the histogram of `family_name` labeled with `store_name` and
+/// `operation` as (sample count, sample sum).
+fn histogram_for(family_name: &str, store_name: &str, operation: &str) -> (u64, f64) {
+    let families = prometheus::gather();
+    let Some(family) = families
+        .iter()
+        .find(|family| family.get_name() == family_name)
+    else {
+        return (0, 0.0);
+    };
+    family
+        .get_metric()
+        .iter()
+        .filter(|metric| {
+            let labels = metric.get_label();
+            labels
+                .iter()
+                .any(|label| label.get_name() == "store" && label.get_value() == store_name)
+                && labels
+                    .iter()
+                    .any(|label| label.get_name() == "operation" && label.get_value() == operation)
+        })
+        .fold((0, 0.0), |(count, sum), metric| {
+            let histogram = metric.get_histogram();
+            (
+                count + histogram.get_sample_count(),
+                sum + histogram.get_sample_sum(),
+            )
+        })
+}
+
+#[nativelink_test]
+async fn records_operations_per_store_name() -> Result<(), Error> {
+    const STORE_NAME: &str = "records_operations_per_store_name";
+    const VALUE: &str = "1234";
+    let store = Store::new(MetricsStore::new(
+        STORE_NAME,
+        Store::new(MemoryStore::new(&MemorySpec::default())),
+    ));
+    let digest = DigestInfo::try_new(VALID_HASH1, VALUE.len())?;
+
+    store.update_oneshot(digest, VALUE.into()).await?;
+    assert_eq!(store.has(digest).await?, Some(VALUE.len() as u64));
+    assert_eq!(
+        store.get_part_unchunked(digest, 0, None).await?,
+        VALUE.as_bytes()
+    );
+
+    for operation in ["update", "has", "get_part"] {
+        let (count, _) = histogram_for(
+            "nativelink_store_operation_duration_seconds",
+            STORE_NAME,
+            operation,
+        );
+        assert_eq!(count, 1, "{operation}");
+    }
+    assert_eq!(
+        histogram_for("nativelink_store_operation_bytes", STORE_NAME, "update"),
+        (1, VALUE.len() as f64)
+    );
+    assert_eq!(
+        histogram_for("nativelink_store_operation_bytes", STORE_NAME, "get_part"),
+        (1, VALUE.len() as f64)
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn downcasts_to_wrapped_store() -> Result<(), Error> {
+    let store = Store::new(MetricsStore::new(
+        "downcasts_to_wrapped_store",
+        Store::new(MemoryStore::new(&MemorySpec::default())),
+    ));
+
+    assert!(store.downcast_ref::<MemoryStore>(None).is_some());
+    assert!(store
+        .into_inner()
+        .as_any_arc()
+        .downcast::<MemoryStore>()
+        .is_ok());
+    Ok(())
+}
@@ -0,0 +1,164 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use nativelink_config::stores::{MemorySpec, MirrorSpec, StoreSpec};
+use nativelink_error::{make_err, Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_metric::MetricsComponent;
+use nativelink_store::memory_store::MemoryStore;
+use nativelink_store::mirror_store::MirrorStore;
+use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
+use nativelink_util::common::DigestInfo;
+use nativelink_util::health_utils::{default_health_status_indicator, HealthStatusIndicator};
+use nativelink_util::store_trait::{Store, StoreDriver, StoreKey, StoreLike, UploadSizeInfo};
+use pretty_assertions::assert_eq;
+
+const VALID_HASH1: &str = "0123456789abcdef000000000000000000010000000000000123456789abcdef";
+const VALUE: &str = "123";
+
+/// A store that fails every request, like a broken disk.
+#[derive(MetricsComponent)]
+struct BrokenStore {}
+
+#[async_trait]
+impl StoreDriver for BrokenStore {
+    async fn has_with_results(
+        self: Pin<&Self>,
+        _keys: &[StoreKey<'_>],
+        _results: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        Err(make_err!(Code::Unavailable, "Disk is broken"))
+    }
+
+    async fn update(
+        self: Pin<&Self>,
+        _key: StoreKey<'_>,
+        _reader: DropCloserReadHalf,
+        _size_info: UploadSizeInfo,
+    ) -> Result<(), Error> {
+        Err(make_err!(Code::Unavailable, "Disk is broken"))
+    }
+
+    async fn get_part(
+        self: Pin<&Self>,
+        _key: StoreKey<'_>,
+        _writer: &mut DropCloserWriteHalf,
+        _offset: u64,
+        _length: Option<u64>,
+    ) -> Result<(), Error> {
+        Err(make_err!(Code::Unavailable, "Disk is broken"))
+    }
+
+    fn inner_store(&self, _key: Option<StoreKey>) -> &'_ dyn StoreDriver {
+        self
+    }
+
+    fn as_any(&self) -> &(dyn std::any::Any + Sync + Send + 'static) {
+        self
+    }
+
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn std::any::Any + Sync + Send + 'static> {
+        self
+    }
+}
+
+default_health_status_indicator!(BrokenStore);
+
+fn make_store(stores: Vec<Store>, write_quorum: usize) -> Result<Arc<MirrorStore>, Error> {
+    MirrorStore::new(
+        &MirrorSpec {
+            stores: stores
+                .iter()
+                .map(|_| StoreSpec::memory(MemorySpec::default()))
+                .collect(),
+            write_quorum,
+            disable_repair: false,
+            max_concurrent_repairs: 0,
+        },
+        stores,
+    )
+}
+
+#[nativelink_test]
+async fn writes_to_all_stores_and_repairs_missing_copies() -> Result<(), Error> {
+    let digest = DigestInfo::try_new(VALID_HASH1, VALUE.len())?;
+    let memory_store1 = MemoryStore::new(&MemorySpec::default());
+    let store1 = Store::new(memory_store1.clone());
+    let store2 = Store::new(MemoryStore::new(&MemorySpec::default()));
+    let store = make_store(vec![store1.clone(), store2.clone()], 0)?;
+
+    store.update_oneshot(digest, VALUE.into()).await?;
+    assert_eq!(store1.has(digest).await?, Some(VALUE.len() as u64));
+    assert_eq!(store2.has(digest).await?, Some(VALUE.len() as u64));
+
+    // Lose the copy in the first store, the second one serves reads.
+    assert!(memory_store1.remove_entry(digest.into()).await);
+    assert_eq!(store.has(digest).await, Ok(Some(VALUE.len() as u64)));
+    assert_eq!(
+        store.get_part_unchunked(digest, 0, None).await?,
+        Bytes::from_static(VALUE.as_bytes())
+    );
+
+    // The repair runs in the background.
+    for _ in 0..100 {
+        if store1.has(digest).await?.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(
+        store1.get_part_unchunked(digest, 0, None).await?,
+        Bytes::from_static(VALUE.as_bytes())
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn write_quorum_tolerates_failed_stores() -> Result<(), Error> {
+    let digest = DigestInfo::try_new(VALID_HASH1, VALUE.len())?;
+    let memory_store = Store::new(MemoryStore::new(&MemorySpec::default()));
+    let broken_store = Store::new(Arc::new(BrokenStore {}));
+
+    let store = make_store(vec![broken_store.clone(), memory_store.clone()], 1)?;
+    store.update_oneshot(digest, VALUE.into()).await?;
+    assert_eq!(memory_store.has(digest).await?, Some(VALUE.len() as u64));
+    // Reads skip the broken store.
+    assert_eq!(store.has(digest).await, Ok(Some(VALUE.len() as u64)));
+    assert_eq!(
+        store.get_part_unchunked(digest, 0, None).await?,
+        Bytes::from_static(VALUE.as_bytes())
+    );
+
+    // All stores need to accept the write by default.
+    let store = make_store(vec![broken_store, memory_store], 0)?;
+    let err = store
+        .update_oneshot(digest, VALUE.into())
+        .await
+        .unwrap_err();
+    assert_eq!(err.code, Code::Unavailable, "{err:?}");
+    Ok(())
+}
+
+#[nativelink_test]
+async fn write_quorum_larger_than_stores_is_rejected() -> Result<(), Error> {
+    let stores = vec![Store::new(MemoryStore::new(&MemorySpec::default()))];
+    assert!(make_store(stores, 2).is_err());
+    Ok(())
+}
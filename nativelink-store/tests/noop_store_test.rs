@@ -0,0 +1,62 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use nativelink_error::{Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_store::noop_store::NoopStore;
+use nativelink_util::common::DigestInfo;
+use nativelink_util::store_trait::{StoreKey, StoreLike};
+use pretty_assertions::assert_eq;
+
+const VALID_HASH1: &str = "0123456789abcdef000000000000000000010000000000000123456789abcdef";
+
+#[nativelink_test]
+async fn writes_are_discarded() -> Result<(), Error> {
+    const VALUE: &str = "123";
+    let store = NoopStore::new();
+    let digest = DigestInfo::try_new(VALID_HASH1, VALUE.len())?;
+
+    store.update_oneshot(digest, VALUE.into()).await?;
+
+    assert_eq!(store.has(digest).await?, None);
+    assert_eq!(
+        store
+            .get_part_unchunked(digest, 0, None)
+            .await
+            .unwrap_err()
+            .code,
+        Code::NotFound
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn list_and_remove_find_nothing() -> Result<(), Error> {
+    const VALUE: &str = "123";
+    let store = NoopStore::new();
+    let digest = DigestInfo::try_new(VALID_HASH1, VALUE.len())?;
+    store.update_oneshot(digest, VALUE.into()).await?;
+
+    let mut keys: Vec<StoreKey> = Vec::new();
+    let count = store
+        .list(.., |key| {
+            keys.push(key.borrow().into_owned());
+            true
+        })
+        .await?;
+    assert_eq!(count, 0);
+    assert!(keys.is_empty());
+    assert!(!store.remove(digest).await?);
+    Ok(())
+}
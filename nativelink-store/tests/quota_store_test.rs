@@ -0,0 +1,183 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use nativelink_config::stores::{MemorySpec, QuotaLimitSpec, QuotaSpec, StoreSpec};
+use nativelink_error::{Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_store::memory_store::MemoryStore;
+use nativelink_store::quota_store::QuotaStore;
+use nativelink_util::common::DigestInfo;
+use nativelink_util::origin_context::make_ctx_for_instance_name;
+use nativelink_util::store_trait::{Store, StoreLike};
+use pretty_assertions::assert_eq;
+use tracing::error_span;
+
+const VALID_HASH1: &str = "0123456789abcdef000000000000000000010000000000000123456789abcdef";
+const VALID_HASH2: &str = "0123456789abcdef000000000000000000020000000000000123456789abcdef";
+const VALID_HASH3: &str = "0123456789abcdef000000000000000000030000000000000123456789abcdef";
+const VALUE: &str = "0123456789";
+
+fn make_spec(instance_quotas: HashMap<String, QuotaLimitSpec>) -> QuotaSpec {
+    QuotaSpec {
+        backend: StoreSpec::memory(MemorySpec::default()),
+        instance_quotas,
+        default_quota: None,
+        reset_interval_s: 0,
+    }
+}
+
+fn limit(max_bytes: u64, throttle_bytes_per_second: u64) -> QuotaLimitSpec {
+    QuotaLimitSpec {
+        max_bytes,
+        throttle_bytes_per_second,
+    }
+}
+
+async fn update_as(
+    instance_name: &str,
+    store: &QuotaStore,
+    digest: DigestInfo,
+) -> Result<(), Error> {
+    make_ctx_for_instance_name(instance_name)?
+        .wrap_async(
+            error_span!("quota_store_test"),
+            store.update_oneshot(digest, VALUE.into()),
+        )
+        .await
+}
+
+#[nativelink_test]
+async fn writes_over_quota_are_rejected() -> Result<(), Error> {
+    let digest1 = DigestInfo::try_new(VALID_HASH1, VALUE.len())?;
+    let digest2 = DigestInfo::try_new(VALID_HASH2, VALUE.len())?;
+    let inner_store = Store::new(MemoryStore::new(&MemorySpec::default()));
+    let store = QuotaStore::new(
+        &make_spec(HashMap::from([(
+            "team_a".to_string(),
+            limit(VALUE.len() as u64 + 5, 0),
+        )])),
+        inner_store.clone(),
+    );
+
+    update_as("team_a", &store, digest1).await?;
+    assert_eq!(store.bytes_written("team_a"), VALUE.len() as u64);
+
+    let err = update_as("team_a", &store, digest2).await.unwrap_err();
+    assert_eq!(err.code, Code::ResourceExhausted, "{err:?}");
+    assert_eq!(inner_store.has(digest2).await?, None);
+    assert_eq!(store.bytes_written("team_a"), VALUE.len() as u64);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn usage_is_tracked_per_instance_name() -> Result<(), Error> {
+    let digest1 = DigestInfo::try_new(VALID_HASH1, VALUE.len())?;
+    let digest2 = DigestInfo::try_new(VALID_HASH2, VALUE.len())?;
+    let digest3 = DigestInfo::try_new(VALID_HASH3, VALUE.len())?;
+    let store = QuotaStore::new(
+        &QuotaSpec {
+            default_quota: Some(limit(VALUE.len() as u64, 0)),
+            ..make_spec(HashMap::from([(
+                "team_a".to_string(),
+                limit(VALUE.len() as u64, 0),
+            )]))
+        },
+        Store::new(MemoryStore::new(&MemorySpec::default())),
+    );
+
+    update_as("team_a", &store, digest1).await?;
+    // An instance name without its own quota gets a separate default one.
+    update_as("team_b", &store, digest2).await?;
+    assert!(update_as("team_b", &store, digest3).await.is_err());
+
+    assert_eq!(store.bytes_written("team_a"), VALUE.len() as u64);
+    assert_eq!(store.bytes_written("team_b"), VALUE.len() as u64);
+    assert_eq!(store.bytes_written("team_c"), 0);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn instance_names_without_quota_are_not_limited() -> Result<(), Error> {
+    let digest1 = DigestInfo::try_new(VALID_HASH1, VALUE.len())?;
+    let digest2 = DigestInfo::try_new(VALID_HASH2, VALUE.len())?;
+    let store = QuotaStore::new(
+        &make_spec(HashMap::from([("team_a".to_string(), limit(0, 0))])),
+        Store::new(MemoryStore::new(&MemorySpec::default())),
+    );
+
+    update_as("team_b", &store, digest1).await?;
+    // Writes without an instance name in the context are not limited either.
+    store.update_oneshot(digest2, VALUE.into()).await?;
+    assert_eq!(store.has(digest2).await?, Some(VALUE.len() as u64));
+    Ok(())
+}
+
+#[nativelink_test]
+async fn writes_over_quota_are_throttled_when_configured() -> Result<(), Error> {
+    let digest1 = DigestInfo::try_new(VALID_HASH1, VALUE.len())?;
+    let digest2 = DigestInfo::try_new(VALID_HASH2, VALUE.len())?;
+    let store = QuotaStore::new(
+        &make_spec(HashMap::from([(
+            "team_a".to_string(),
+            limit(VALUE.len() as u64, 100),
+        )])),
+        Store::new(MemoryStore::new(&MemorySpec::default())),
+    );
+
+    update_as("team_a", &store, digest1).await?;
+    // The throttle starts with a full second worth of bytes, so it takes
+    // a few writes before they are delayed.
+    let start = Instant::now();
+    for _ in 0..11 {
+        update_as("team_a", &store, digest2).await?;
+    }
+    assert!(start.elapsed() >= Duration::from_millis(90));
+    assert_eq!(store.bytes_written("team_a"), 12 * VALUE.len() as u64);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn reconfigure_replaces_quotas() -> Result<(), Error> {
+    let digest1 = DigestInfo::try_new(VALID_HASH1, VALUE.len())?;
+    let digest2 = DigestInfo::try_new(VALID_HASH2, VALUE.len())?;
+    let quota_store = QuotaStore::new(
+        &make_spec(HashMap::from([(
+            "team_a".to_string(),
+            limit(VALUE.len() as u64, 0),
+        )])),
+        Store::new(MemoryStore::new(&MemorySpec::default())),
+    );
+    update_as("team_a", &quota_store, digest1).await?;
+    assert!(update_as("team_a", &quota_store, digest2).await.is_err());
+
+    Store::new(quota_store.clone())
+        .reconfigure(&StoreSpec::quota(Box::new(make_spec(HashMap::from([(
+            "team_a".to_string(),
+            limit(2 * VALUE.len() as u64, 0),
+        )])))))
+        .await?;
+    // The bytes written before still count against the new quota.
+    update_as("team_a", &quota_store, digest2).await?;
+    assert_eq!(quota_store.bytes_written("team_a"), 2 * VALUE.len() as u64);
+    assert!(update_as("team_a", &quota_store, digest2).await.is_err());
+
+    Store::new(quota_store.clone())
+        .reconfigure(&StoreSpec::quota(Box::new(make_spec(HashMap::new()))))
+        .await?;
+    update_as("team_a", &quota_store, digest2).await?;
+    Ok(())
+}
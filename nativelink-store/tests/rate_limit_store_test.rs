@@ -0,0 +1,102 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use nativelink_config::stores::{MemorySpec, RateLimitSpec, StoreSpec};
+use nativelink_error::Error;
+use nativelink_macro::nativelink_test;
+use nativelink_store::memory_store::MemoryStore;
+use nativelink_store::rate_limit_store::RateLimitStore;
+use nativelink_util::common::DigestInfo;
+use nativelink_util::store_trait::{Store, StoreLike};
+use pretty_assertions::assert_eq;
+
+const VALID_HASH1: &str = "0123456789abcdef000000000000000000010000000000000123456789abcdef";
+const VALUE: &str = "0123456789";
+
+fn make_spec() -> RateLimitSpec {
+    RateLimitSpec {
+        backend: StoreSpec::memory(MemorySpec::default()),
+        max_bytes_per_second: 0,
+        max_bytes_burst: 0,
+        max_ops_per_second: 0,
+        max_ops_burst: 0,
+    }
+}
+
+#[nativelink_test]
+async fn unlimited_store_forwards_requests() -> Result<(), Error> {
+    let digest = DigestInfo::try_new(VALID_HASH1, VALUE.len())?;
+    let inner_store = Store::new(MemoryStore::new(&MemorySpec::default()));
+    let store = RateLimitStore::new(&make_spec(), inner_store.clone());
+
+    store.update_oneshot(digest, VALUE.into()).await?;
+    assert_eq!(inner_store.has(digest).await?, Some(VALUE.len() as u64));
+    assert_eq!(store.has(digest).await?, Some(VALUE.len() as u64));
+    assert_eq!(
+        store.get_part_unchunked(digest, 2, Some(3)).await?,
+        Bytes::from_static(&VALUE.as_bytes()[2..5])
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn requests_over_ops_budget_are_delayed() -> Result<(), Error> {
+    let digest = DigestInfo::try_new(VALID_HASH1, VALUE.len())?;
+    let store = RateLimitStore::new(
+        &RateLimitSpec {
+            max_ops_per_second: 20,
+            max_ops_burst: 1,
+            ..make_spec()
+        },
+        Store::new(MemoryStore::new(&MemorySpec::default())),
+    );
+
+    // The first request uses up the burst, the next two have to wait for
+    // a new token each.
+    let start = Instant::now();
+    store.update_oneshot(digest, VALUE.into()).await?;
+    assert_eq!(store.has(digest).await?, Some(VALUE.len() as u64));
+    assert_eq!(
+        store.get_part_unchunked(digest, 0, None).await?,
+        Bytes::from_static(VALUE.as_bytes())
+    );
+    assert!(start.elapsed() >= Duration::from_millis(90));
+    Ok(())
+}
+
+#[nativelink_test]
+async fn transfers_over_bytes_budget_are_delayed() -> Result<(), Error> {
+    let digest = DigestInfo::try_new(VALID_HASH1, VALUE.len())?;
+    let store = RateLimitStore::new(
+        &RateLimitSpec {
+            max_bytes_per_second: 100,
+            max_bytes_burst: VALUE.len() as u64,
+            ..make_spec()
+        },
+        Store::new(MemoryStore::new(&MemorySpec::default())),
+    );
+
+    // The upload uses up the burst, so the download waits for its bytes.
+    let start = Instant::now();
+    store.update_oneshot(digest, VALUE.into()).await?;
+    assert_eq!(
+        store.get_part_unchunked(digest, 0, None).await?,
+        Bytes::from_static(VALUE.as_bytes())
+    );
+    assert!(start.elapsed() >= Duration::from_millis(90));
+    Ok(())
+}
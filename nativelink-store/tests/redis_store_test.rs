@@ -16,6 +16,7 @@ use std::collections::VecDeque;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread::panicking;
+use std::time::Duration;
 
 use bytes::{Bytes, BytesMut};
 use fred::bytes_utils::string::Str;
@@ -25,6 +26,7 @@ use fred::mocks::{MockCommand, Mocks};
 use fred::prelude::{Builder, Pool as RedisPool};
 use fred::types::config::{Config as RedisConfig, PerformanceConfig};
 use fred::types::Value as RedisValue;
+use nativelink_config::stores::RedisSpec;
 use nativelink_error::{Code, Error};
 use nativelink_macro::nativelink_test;
 use nativelink_metric::{MetricFieldData, MetricKind, MetricsComponent, RootMetricsComponent};
@@ -46,6 +48,14 @@ const TEMP_UUID: &str = "550e8400-e29b-41d4-a716-446655440000";
 
 const DEFAULT_READ_CHUNK_SIZE: usize = 1024;
 const DEFAULT_MAX_CHUNK_UPLOADS_PER_UPDATE: usize = 10;
+// Only one read is outstanding at a time, so the order of commands is deterministic.
+const MAX_CHUNK_READS_PER_GET: usize = 1;
+const UPLOAD_CHUNK_SIZE: usize = 1024 * 1024;
+const STORAGE_CHUNK_SIZE: usize = 0;
+const MAX_IN_FLIGHT_PER_CONNECTION: usize = 0;
+const CONNECTION_ACQUIRE_TIMEOUT: Duration = Duration::from_secs(10);
+const KEY_TTL_S: i64 = 0;
+const TEMP_KEY_TTL_S: i64 = 0;
 
 fn mock_uuid_generator() -> String {
     uuid::Uuid::parse_str(TEMP_UUID).unwrap().to_string()
@@ -275,7 +285,14 @@ async fn upload_and_get_data() -> Result<(), Error> {
             mock_uuid_generator,
             String::new(),
             DEFAULT_READ_CHUNK_SIZE,
+            MAX_CHUNK_READS_PER_GET,
             DEFAULT_MAX_CHUNK_UPLOADS_PER_UPDATE,
+            UPLOAD_CHUNK_SIZE,
+            STORAGE_CHUNK_SIZE,
+            MAX_IN_FLIGHT_PER_CONNECTION,
+            CONNECTION_ACQUIRE_TIMEOUT,
+            KEY_TTL_S,
+            TEMP_KEY_TTL_S,
         )
         .unwrap()
     };
@@ -298,6 +315,166 @@ async fn upload_and_get_data() -> Result<(), Error> {
     Ok(())
 }
 
+#[nativelink_test]
+async fn upload_and_get_data_with_ttl() -> Result<(), Error> {
+    const KEY_TTL: i64 = 3600;
+    const TEMP_TTL: i64 = 60;
+
+    let data = Bytes::from_static(b"14");
+    let chunk_data = RedisValue::Bytes(data.clone());
+
+    let digest = DigestInfo::try_new(VALID_HASH1, 2)?;
+    let packed_hash_hex = format!("{digest}");
+
+    let temp_key = RedisValue::Bytes(make_temp_key(&packed_hash_hex).into());
+    let real_key = RedisValue::Bytes(packed_hash_hex.into());
+
+    let mocks = Arc::new(MockRedisBackend::new());
+
+    mocks
+        // The temp key is created with the temp ttl before any data is written.
+        .expect(
+            MockCommand {
+                cmd: Str::from_static("SET"),
+                subcommand: None,
+                args: vec![
+                    temp_key.clone(),
+                    RedisValue::Bytes(Bytes::new()),
+                    RedisValue::String(Str::from_static("EX")),
+                    TEMP_TTL.into(),
+                ],
+            },
+            Ok(RedisValue::Array(vec![RedisValue::Null])),
+        )
+        .expect(
+            MockCommand {
+                cmd: Str::from_static("SETRANGE"),
+                subcommand: None,
+                args: vec![temp_key.clone(), 0.into(), chunk_data],
+            },
+            Ok(RedisValue::Array(vec![RedisValue::Null])),
+        )
+        .expect(
+            MockCommand {
+                cmd: Str::from_static("STRLEN"),
+                subcommand: None,
+                args: vec![temp_key.clone()],
+            },
+            Ok(RedisValue::Array(vec![RedisValue::Integer(
+                data.len() as i64
+            )])),
+        )
+        .expect(
+            MockCommand {
+                cmd: Str::from_static("RENAME"),
+                subcommand: None,
+                args: vec![temp_key, real_key.clone()],
+            },
+            Ok(RedisValue::Array(vec![RedisValue::Null])),
+        )
+        // The final key gets the long lived ttl.
+        .expect(
+            MockCommand {
+                cmd: Str::from_static("EXPIRE"),
+                subcommand: None,
+                args: vec![real_key.clone(), KEY_TTL.into()],
+            },
+            Ok(RedisValue::Integer(1)),
+        );
+
+    // Checking for existence refreshes the ttl.
+    mocks
+        .expect(
+            MockCommand {
+                cmd: Str::from_static("STRLEN"),
+                subcommand: None,
+                args: vec![real_key.clone()],
+            },
+            Ok(RedisValue::Integer(2)),
+        )
+        .expect(
+            MockCommand {
+                cmd: Str::from_static("EXISTS"),
+                subcommand: None,
+                args: vec![real_key.clone()],
+            },
+            Ok(RedisValue::Integer(1)),
+        )
+        .expect(
+            MockCommand {
+                cmd: Str::from_static("EXPIRE"),
+                subcommand: None,
+                args: vec![real_key.clone(), KEY_TTL.into()],
+            },
+            Ok(RedisValue::Integer(1)),
+        );
+
+    // Reading refreshes the ttl.
+    mocks
+        .expect(
+            MockCommand {
+                cmd: Str::from_static("GETRANGE"),
+                subcommand: None,
+                args: vec![
+                    real_key.clone(),
+                    RedisValue::Integer(0),
+                    RedisValue::Integer(1),
+                ],
+            },
+            Ok(RedisValue::String(Str::from_static("14"))),
+        )
+        .expect(
+            MockCommand {
+                cmd: Str::from_static("EXPIRE"),
+                subcommand: None,
+                args: vec![real_key, KEY_TTL.into()],
+            },
+            Ok(RedisValue::Integer(1)),
+        );
+
+    let store = {
+        let mut builder = Builder::default_centralized();
+        builder.set_config(RedisConfig {
+            mocks: Some(Arc::clone(&mocks) as Arc<dyn Mocks>),
+            ..Default::default()
+        });
+        let (client_pool, subscriber_client) = make_clients(builder);
+        RedisStore::new_from_builder_and_parts(
+            client_pool,
+            subscriber_client,
+            None,
+            mock_uuid_generator,
+            String::new(),
+            DEFAULT_READ_CHUNK_SIZE,
+            MAX_CHUNK_READS_PER_GET,
+            DEFAULT_MAX_CHUNK_UPLOADS_PER_UPDATE,
+            UPLOAD_CHUNK_SIZE,
+            STORAGE_CHUNK_SIZE,
+            MAX_IN_FLIGHT_PER_CONNECTION,
+            CONNECTION_ACQUIRE_TIMEOUT,
+            KEY_TTL,
+            TEMP_TTL,
+        )
+        .unwrap()
+    };
+
+    store.update_oneshot(digest, data.clone()).await.unwrap();
+
+    let result = store.has(digest).await.unwrap();
+    assert!(
+        result.is_some(),
+        "Expected redis store to have hash: {VALID_HASH1}",
+    );
+
+    let result = store
+        .get_part_unchunked(digest, 0, Some(data.len() as u64))
+        .await
+        .unwrap();
+    assert_eq!(result, data, "Expected redis store to have updated value",);
+
+    Ok(())
+}
+
 #[nativelink_test]
 async fn upload_and_get_data_with_prefix() -> Result<(), Error> {
     let data = Bytes::from_static(b"14");
@@ -379,7 +556,14 @@ async fn upload_and_get_data_with_prefix() -> Result<(), Error> {
             mock_uuid_generator,
             prefix.to_string(),
             DEFAULT_READ_CHUNK_SIZE,
+            MAX_CHUNK_READS_PER_GET,
             DEFAULT_MAX_CHUNK_UPLOADS_PER_UPDATE,
+            UPLOAD_CHUNK_SIZE,
+            STORAGE_CHUNK_SIZE,
+            MAX_IN_FLIGHT_PER_CONNECTION,
+            CONNECTION_ACQUIRE_TIMEOUT,
+            KEY_TTL_S,
+            TEMP_KEY_TTL_S,
         )
         .unwrap()
     };
@@ -416,7 +600,14 @@ async fn upload_empty_data() -> Result<(), Error> {
         mock_uuid_generator,
         String::new(),
         DEFAULT_READ_CHUNK_SIZE,
+        MAX_CHUNK_READS_PER_GET,
         DEFAULT_MAX_CHUNK_UPLOADS_PER_UPDATE,
+        UPLOAD_CHUNK_SIZE,
+        STORAGE_CHUNK_SIZE,
+        MAX_IN_FLIGHT_PER_CONNECTION,
+        CONNECTION_ACQUIRE_TIMEOUT,
+        KEY_TTL_S,
+        TEMP_KEY_TTL_S,
     )
     .unwrap();
 
@@ -445,7 +636,14 @@ async fn upload_empty_data_with_prefix() -> Result<(), Error> {
         mock_uuid_generator,
         prefix.to_string(),
         DEFAULT_READ_CHUNK_SIZE,
+        MAX_CHUNK_READS_PER_GET,
         DEFAULT_MAX_CHUNK_UPLOADS_PER_UPDATE,
+        UPLOAD_CHUNK_SIZE,
+        STORAGE_CHUNK_SIZE,
+        MAX_IN_FLIGHT_PER_CONNECTION,
+        CONNECTION_ACQUIRE_TIMEOUT,
+        KEY_TTL_S,
+        TEMP_KEY_TTL_S,
     )
     .unwrap();
 
@@ -559,7 +757,14 @@ async fn test_large_downloads_are_chunked() -> Result<(), Error> {
             mock_uuid_generator,
             String::new(),
             READ_CHUNK_SIZE,
+            MAX_CHUNK_READS_PER_GET,
             DEFAULT_MAX_CHUNK_UPLOADS_PER_UPDATE,
+            UPLOAD_CHUNK_SIZE,
+            STORAGE_CHUNK_SIZE,
+            MAX_IN_FLIGHT_PER_CONNECTION,
+            CONNECTION_ACQUIRE_TIMEOUT,
+            KEY_TTL_S,
+            TEMP_KEY_TTL_S,
         )
         .unwrap()
     };
@@ -585,6 +790,64 @@ async fn test_large_downloads_are_chunked() -> Result<(), Error> {
     Ok(())
 }
 
+#[nativelink_test]
+async fn large_downloads_are_prefetched() -> Result<(), Error> {
+    const READ_CHUNK_SIZE: usize = 4;
+    const PREFETCH_WINDOW: usize = 3;
+    let data = Bytes::from_static(b"0123456789");
+
+    let digest = DigestInfo::try_new(VALID_HASH1, 1)?;
+    let real_key = RedisValue::Bytes(format!("{digest}").into());
+
+    let mocks = Arc::new(MockRedisBackend::new());
+    // All three reads are issued before the first one is consumed.
+    for (start, end, value) in [(0, 3, "0123"), (4, 7, "4567"), (8, 11, "89")] {
+        mocks.expect(
+            MockCommand {
+                cmd: Str::from_static("GETRANGE"),
+                subcommand: None,
+                args: vec![
+                    real_key.clone(),
+                    RedisValue::Integer(start),
+                    RedisValue::Integer(end),
+                ],
+            },
+            Ok(RedisValue::String(Str::from_static(value))),
+        );
+    }
+
+    let store = {
+        let mut builder = Builder::default_centralized();
+        builder.set_config(RedisConfig {
+            mocks: Some(Arc::clone(&mocks) as Arc<dyn Mocks>),
+            ..Default::default()
+        });
+        let (client_pool, subscriber_client) = make_clients(builder);
+        RedisStore::new_from_builder_and_parts(
+            client_pool,
+            subscriber_client,
+            None,
+            mock_uuid_generator,
+            String::new(),
+            READ_CHUNK_SIZE,
+            PREFETCH_WINDOW,
+            DEFAULT_MAX_CHUNK_UPLOADS_PER_UPDATE,
+            UPLOAD_CHUNK_SIZE,
+            STORAGE_CHUNK_SIZE,
+            MAX_IN_FLIGHT_PER_CONNECTION,
+            CONNECTION_ACQUIRE_TIMEOUT,
+            KEY_TTL_S,
+            TEMP_KEY_TTL_S,
+        )
+        .unwrap()
+    };
+
+    let result = store.get_part_unchunked(digest, 0, Some(12)).await.unwrap();
+    assert_eq!(result, data, "Expected redis store to return all chunks");
+
+    Ok(())
+}
+
 #[nativelink_test]
 async fn yield_between_sending_packets_in_update() -> Result<(), Error> {
     let data_p1 = Bytes::from(vec![b'A'; DEFAULT_READ_CHUNK_SIZE + 512]);
@@ -712,7 +975,14 @@ async fn yield_between_sending_packets_in_update() -> Result<(), Error> {
             mock_uuid_generator,
             String::new(),
             DEFAULT_READ_CHUNK_SIZE,
+            MAX_CHUNK_READS_PER_GET,
             DEFAULT_MAX_CHUNK_UPLOADS_PER_UPDATE,
+            UPLOAD_CHUNK_SIZE,
+            STORAGE_CHUNK_SIZE,
+            MAX_IN_FLIGHT_PER_CONNECTION,
+            CONNECTION_ACQUIRE_TIMEOUT,
+            KEY_TTL_S,
+            TEMP_KEY_TTL_S,
         )
         .unwrap()
     };
@@ -754,6 +1024,297 @@ async fn yield_between_sending_packets_in_update() -> Result<(), Error> {
     Ok(())
 }
 
+#[nativelink_test]
+async fn large_upload_chunks_are_split() -> Result<(), Error> {
+    const SMALL_UPLOAD_CHUNK_SIZE: usize = 4;
+    let data = Bytes::from_static(b"123456789");
+
+    let digest = DigestInfo::try_new(VALID_HASH1, 2)?;
+    let packed_hash_hex = format!("{digest}");
+
+    let temp_key = RedisValue::Bytes(make_temp_key(&packed_hash_hex).into());
+    let real_key = RedisValue::Bytes(packed_hash_hex.into());
+
+    let mocks = Arc::new(MockRedisBackend::new());
+    for (offset, piece) in [(0, "1234"), (4, "5678"), (8, "9")] {
+        mocks.expect(
+            MockCommand {
+                cmd: Str::from_static("SETRANGE"),
+                subcommand: None,
+                args: vec![
+                    temp_key.clone(),
+                    offset.into(),
+                    RedisValue::Bytes(Bytes::from_static(piece.as_bytes())),
+                ],
+            },
+            Ok(RedisValue::Array(vec![RedisValue::Null])),
+        );
+    }
+    mocks
+        .expect(
+            MockCommand {
+                cmd: Str::from_static("STRLEN"),
+                subcommand: None,
+                args: vec![temp_key.clone()],
+            },
+            Ok(RedisValue::Array(vec![RedisValue::Integer(
+                data.len() as i64
+            )])),
+        )
+        .expect(
+            MockCommand {
+                cmd: Str::from_static("RENAME"),
+                subcommand: None,
+                args: vec![temp_key, real_key],
+            },
+            Ok(RedisValue::Array(vec![RedisValue::Null])),
+        );
+
+    let store = {
+        let mut builder = Builder::default_centralized();
+        builder.set_config(RedisConfig {
+            mocks: Some(Arc::clone(&mocks) as Arc<dyn Mocks>),
+            ..Default::default()
+        });
+        let (client_pool, subscriber_client) = make_clients(builder);
+        RedisStore::new_from_builder_and_parts(
+            client_pool,
+            subscriber_client,
+            None,
+            mock_uuid_generator,
+            String::new(),
+            DEFAULT_READ_CHUNK_SIZE,
+            MAX_CHUNK_READS_PER_GET,
+            1, // Keep the order of the chunk uploads deterministic.
+            SMALL_UPLOAD_CHUNK_SIZE,
+            STORAGE_CHUNK_SIZE,
+            MAX_IN_FLIGHT_PER_CONNECTION,
+            CONNECTION_ACQUIRE_TIMEOUT,
+            KEY_TTL_S,
+            TEMP_KEY_TTL_S,
+        )
+        .unwrap()
+    };
+
+    store.update_oneshot(digest, data).await.unwrap();
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn upload_and_get_data_in_chunked_layout() -> Result<(), Error> {
+    const SMALL_STORAGE_CHUNK_SIZE: usize = 4;
+    let data = Bytes::from_static(b"123456789");
+
+    let digest = DigestInfo::try_new(VALID_HASH1, 2)?;
+    let packed_hash_hex = format!("{digest}");
+    let real_key = RedisValue::Bytes(packed_hash_hex.clone().into());
+    let chunk_key =
+        |index: u64| RedisValue::Bytes(format!("{{{packed_hash_hex}}}:{TEMP_UUID}:{index}").into());
+    let metadata = format!("nativelink-chunked-v1 {TEMP_UUID} 9 4");
+
+    let pieces = [(0, "1234"), (1, "5678"), (2, "9")];
+    let mocks = Arc::new(MockRedisBackend::new());
+    for (index, piece) in pieces {
+        mocks.expect(
+            MockCommand {
+                cmd: Str::from_static("SETRANGE"),
+                subcommand: None,
+                args: vec![
+                    chunk_key(index),
+                    0.into(),
+                    RedisValue::Bytes(Bytes::from_static(piece.as_bytes())),
+                ],
+            },
+            Ok(RedisValue::Array(vec![RedisValue::Null])),
+        );
+    }
+    for (index, piece) in pieces {
+        mocks.expect(
+            MockCommand {
+                cmd: Str::from_static("STRLEN"),
+                subcommand: None,
+                args: vec![chunk_key(index)],
+            },
+            Ok(RedisValue::Integer(piece.len() as i64)),
+        );
+    }
+    mocks
+        .expect(
+            MockCommand {
+                cmd: Str::from_static("SET"),
+                subcommand: None,
+                args: vec![
+                    real_key.clone(),
+                    metadata.clone().into(),
+                    RedisValue::String(Str::from_static("GET")),
+                ],
+            },
+            Ok(RedisValue::Null),
+        )
+        .expect(
+            MockCommand {
+                cmd: Str::from_static("GET"),
+                subcommand: None,
+                args: vec![real_key],
+            },
+            Ok(metadata.into()),
+        );
+    for (index, piece) in pieces {
+        mocks.expect(
+            MockCommand {
+                cmd: Str::from_static("GETRANGE"),
+                subcommand: None,
+                args: vec![
+                    chunk_key(index),
+                    RedisValue::Integer(0),
+                    RedisValue::Integer(piece.len() as i64 - 1),
+                ],
+            },
+            Ok(RedisValue::Bytes(Bytes::from_static(piece.as_bytes()))),
+        );
+    }
+
+    let store = {
+        let mut builder = Builder::default_centralized();
+        builder.set_config(RedisConfig {
+            mocks: Some(Arc::clone(&mocks) as Arc<dyn Mocks>),
+            ..Default::default()
+        });
+        let (client_pool, subscriber_client) = make_clients(builder);
+        RedisStore::new_from_builder_and_parts(
+            client_pool,
+            subscriber_client,
+            None,
+            mock_uuid_generator,
+            String::new(),
+            DEFAULT_READ_CHUNK_SIZE,
+            MAX_CHUNK_READS_PER_GET,
+            1, // Keep the order of the chunk uploads deterministic.
+            UPLOAD_CHUNK_SIZE,
+            SMALL_STORAGE_CHUNK_SIZE,
+            MAX_IN_FLIGHT_PER_CONNECTION,
+            CONNECTION_ACQUIRE_TIMEOUT,
+            KEY_TTL_S,
+            TEMP_KEY_TTL_S,
+        )
+        .unwrap()
+    };
+
+    store.update_oneshot(digest, data.clone()).await.unwrap();
+
+    let result = store
+        .get_part_unchunked(digest, 0, Some(data.len() as u64))
+        .await
+        .unwrap();
+    assert_eq!(result, data, "Expected chunks to be reassembled");
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn chunks_with_temp_ttl_are_never_replaced() -> Result<(), Error> {
+    const SMALL_STORAGE_CHUNK_SIZE: usize = 4;
+    const TEMP_KEY_TTL: i64 = 60;
+    let data = Bytes::from_static(b"12345");
+
+    let digest = DigestInfo::try_new(VALID_HASH1, 2)?;
+    let packed_hash_hex = format!("{digest}");
+    let real_key = RedisValue::Bytes(packed_hash_hex.clone().into());
+    let chunk_key =
+        |index: u64| RedisValue::Bytes(format!("{{{packed_hash_hex}}}:{TEMP_UUID}:{index}").into());
+    let metadata = format!("nativelink-chunked-v1 {TEMP_UUID} 5 4");
+
+    let pieces = [(0, "1234"), (1, "5")];
+    let mocks = Arc::new(MockRedisBackend::new());
+    // The first piece of a chunk is written with SETRANGE and an EXPIRE in
+    // the same pipeline, rather than with a SET that could replace later
+    // pieces.
+    for (index, piece) in pieces {
+        mocks
+            .expect(
+                MockCommand {
+                    cmd: Str::from_static("SETRANGE"),
+                    subcommand: None,
+                    args: vec![
+                        chunk_key(index),
+                        0.into(),
+                        RedisValue::Bytes(Bytes::from_static(piece.as_bytes())),
+                    ],
+                },
+                Ok(RedisValue::Integer(piece.len() as i64)),
+            )
+            .expect(
+                MockCommand {
+                    cmd: Str::from_static("EXPIRE"),
+                    subcommand: None,
+                    args: vec![chunk_key(index), TEMP_KEY_TTL.into()],
+                },
+                Ok(RedisValue::Integer(1)),
+            );
+    }
+    for (index, piece) in pieces {
+        mocks
+            .expect(
+                MockCommand {
+                    cmd: Str::from_static("STRLEN"),
+                    subcommand: None,
+                    args: vec![chunk_key(index)],
+                },
+                Ok(RedisValue::Integer(piece.len() as i64)),
+            )
+            .expect(
+                MockCommand {
+                    cmd: Str::from_static("PERSIST"),
+                    subcommand: None,
+                    args: vec![chunk_key(index)],
+                },
+                Ok(RedisValue::Integer(1)),
+            );
+    }
+    mocks.expect(
+        MockCommand {
+            cmd: Str::from_static("SET"),
+            subcommand: None,
+            args: vec![
+                real_key,
+                metadata.into(),
+                RedisValue::String(Str::from_static("GET")),
+            ],
+        },
+        Ok(RedisValue::Null),
+    );
+
+    let store = {
+        let mut builder = Builder::default_centralized();
+        builder.set_config(RedisConfig {
+            mocks: Some(Arc::clone(&mocks) as Arc<dyn Mocks>),
+            ..Default::default()
+        });
+        let (client_pool, subscriber_client) = make_clients(builder);
+        RedisStore::new_from_builder_and_parts(
+            client_pool,
+            subscriber_client,
+            None,
+            mock_uuid_generator,
+            String::new(),
+            DEFAULT_READ_CHUNK_SIZE,
+            MAX_CHUNK_READS_PER_GET,
+            1, // Keep the order of the chunk uploads deterministic.
+            UPLOAD_CHUNK_SIZE,
+            SMALL_STORAGE_CHUNK_SIZE,
+            MAX_IN_FLIGHT_PER_CONNECTION,
+            CONNECTION_ACQUIRE_TIMEOUT,
+            KEY_TTL_S,
+            TEMP_KEY_TTL,
+        )
+        .unwrap()
+    };
+
+    store.update_oneshot(digest, data).await.unwrap();
+    Ok(())
+}
+
 // Regression test for: https://github.com/TraceMachina/nativelink/issues/1286
 #[nativelink_test]
 async fn zero_len_items_exist_check() -> Result<(), Error> {
@@ -802,7 +1363,14 @@ async fn zero_len_items_exist_check() -> Result<(), Error> {
             mock_uuid_generator,
             String::new(),
             DEFAULT_READ_CHUNK_SIZE,
+            MAX_CHUNK_READS_PER_GET,
             DEFAULT_MAX_CHUNK_UPLOADS_PER_UPDATE,
+            UPLOAD_CHUNK_SIZE,
+            STORAGE_CHUNK_SIZE,
+            MAX_IN_FLIGHT_PER_CONNECTION,
+            CONNECTION_ACQUIRE_TIMEOUT,
+            KEY_TTL_S,
+            TEMP_KEY_TTL_S,
         )
         .unwrap()
     };
@@ -831,7 +1399,14 @@ async fn dont_loop_forever_on_empty() -> Result<(), Error> {
             mock_uuid_generator,
             String::new(),
             DEFAULT_READ_CHUNK_SIZE,
+            MAX_CHUNK_READS_PER_GET,
             DEFAULT_MAX_CHUNK_UPLOADS_PER_UPDATE,
+            UPLOAD_CHUNK_SIZE,
+            STORAGE_CHUNK_SIZE,
+            MAX_IN_FLIGHT_PER_CONNECTION,
+            CONNECTION_ACQUIRE_TIMEOUT,
+            KEY_TTL_S,
+            TEMP_KEY_TTL_S,
         )
         .unwrap()
     };
@@ -877,7 +1452,14 @@ async fn test_redis_fingerprint_metric() -> Result<(), Error> {
                     mock_uuid_generator,
                     String::new(),
                     DEFAULT_READ_CHUNK_SIZE,
+                    MAX_CHUNK_READS_PER_GET,
                     DEFAULT_MAX_CHUNK_UPLOADS_PER_UPDATE,
+                    UPLOAD_CHUNK_SIZE,
+                    STORAGE_CHUNK_SIZE,
+                    MAX_IN_FLIGHT_PER_CONNECTION,
+                    CONNECTION_ACQUIRE_TIMEOUT,
+                    KEY_TTL_S,
+                    TEMP_KEY_TTL_S,
                 )
                 .unwrap(),
             ))
@@ -923,3 +1505,111 @@ struct RootMetricsTest {
 }
 
 impl RootMetricsComponent for RootMetricsTest {}
+
+#[nativelink_test]
+async fn sentinel_requires_master_name_with_multiple_addresses() -> Result<(), Error> {
+    let spec: RedisSpec = from_str(
+        r#"{
+            "addresses": ["redis://127.0.0.1:26379", "redis://127.0.0.2:26379"],
+            "mode": "sentinel"
+        }"#,
+    )
+    .unwrap();
+
+    let Err(err) = RedisStore::new(spec) else {
+        panic!("Expected RedisStore::new to fail without sentinel_master_name");
+    };
+    assert_eq!(err.code, Code::InvalidArgument);
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn password_and_password_file_are_exclusive() -> Result<(), Error> {
+    let spec: RedisSpec = from_str(
+        r#"{
+            "addresses": ["redis://127.0.0.1:6379"],
+            "password": "hunter2",
+            "password_file": "/dev/null"
+        }"#,
+    )
+    .unwrap();
+
+    let Err(err) = RedisStore::new(spec) else {
+        panic!("Expected RedisStore::new to fail with both password and password_file");
+    };
+    assert_eq!(err.code, Code::InvalidArgument);
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn update_many_sets_small_items_in_one_pipeline() -> Result<(), Error> {
+    let data1 = Bytes::from_static(b"14");
+    let data2 = Bytes::from_static(b"123");
+    let digest1 = DigestInfo::try_new(VALID_HASH1, data1.len())?;
+    let digest2 = DigestInfo::try_new(VALID_HASH1, data2.len())?;
+
+    let mocks = Arc::new(MockRedisBackend::new());
+    // The zero digest is skipped, the other items are set directly without
+    // a temp key.
+    mocks
+        .expect(
+            MockCommand {
+                cmd: Str::from_static("SET"),
+                subcommand: None,
+                args: vec![
+                    RedisValue::Bytes(format!("{digest1}").into()),
+                    RedisValue::Bytes(data1.clone()),
+                ],
+            },
+            Ok(RedisValue::Null),
+        )
+        .expect(
+            MockCommand {
+                cmd: Str::from_static("SET"),
+                subcommand: None,
+                args: vec![
+                    RedisValue::Bytes(format!("{digest2}").into()),
+                    RedisValue::Bytes(data2.clone()),
+                ],
+            },
+            Ok(RedisValue::Null),
+        );
+
+    let store = {
+        let mut builder = Builder::default_centralized();
+        builder.set_config(RedisConfig {
+            mocks: Some(Arc::clone(&mocks) as Arc<dyn Mocks>),
+            ..Default::default()
+        });
+        let (client_pool, subscriber_client) = make_clients(builder);
+        RedisStore::new_from_builder_and_parts(
+            client_pool,
+            subscriber_client,
+            None,
+            mock_uuid_generator,
+            String::new(),
+            DEFAULT_READ_CHUNK_SIZE,
+            MAX_CHUNK_READS_PER_GET,
+            DEFAULT_MAX_CHUNK_UPLOADS_PER_UPDATE,
+            UPLOAD_CHUNK_SIZE,
+            STORAGE_CHUNK_SIZE,
+            MAX_IN_FLIGHT_PER_CONNECTION,
+            CONNECTION_ACQUIRE_TIMEOUT,
+            KEY_TTL_S,
+            TEMP_KEY_TTL_S,
+        )
+        .unwrap()
+    };
+
+    let results = store
+        .update_many(vec![
+            (digest1.into(), data1),
+            (ZERO_BYTE_DIGESTS[0].into(), Bytes::new()),
+            (digest2.into(), data2),
+        ])
+        .await;
+    assert_eq!(results, vec![Ok(()), Ok(()), Ok(())]);
+    Ok(())
+}
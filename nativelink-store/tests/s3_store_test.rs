@@ -12,10 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Duration;
 
-use aws_sdk_s3::config::{BehaviorVersion, Builder, Region};
+use aws_sdk_s3::config::{BehaviorVersion, Builder, Credentials, Region};
 use aws_sdk_s3::primitives::ByteStream;
 use aws_smithy_runtime::client::http::test_util::{ReplayEvent, StaticReplayClient};
 use aws_smithy_types::body::SdkBody;
@@ -25,8 +26,9 @@ use futures::task::Poll;
 use http::header;
 use http::status::StatusCode;
 use hyper::Body;
+use md5::Md5;
 use mock_instant::thread_local::MockClock;
-use nativelink_config::stores::S3Spec;
+use nativelink_config::stores::{S3ServerSideEncryption, S3ServerSideEncryptionAlgorithm, S3Spec};
 use nativelink_error::{make_input_err, Error, ResultExt};
 use nativelink_macro::nativelink_test;
 use nativelink_store::s3_store::S3Store;
@@ -34,7 +36,7 @@ use nativelink_util::buf_channel::make_buf_channel_pair;
 use nativelink_util::common::DigestInfo;
 use nativelink_util::instant_wrapper::MockInstantWrapped;
 use nativelink_util::spawn;
-use nativelink_util::store_trait::{StoreLike, UploadSizeInfo};
+use nativelink_util::store_trait::{StoreKey, StoreLike, UploadSizeInfo};
 use pretty_assertions::assert_eq;
 use sha2::{Digest, Sha256};
 
@@ -275,6 +277,215 @@ async fn simple_update_ac() -> Result<(), Error> {
     Ok(())
 }
 
+#[nativelink_test]
+async fn update_many_retries_small_objects() -> Result<(), Error> {
+    const VALUE: &str = "123";
+    let put_uri =
+        format!("https://{BUCKET_NAME}.s3.{REGION}.amazonaws.com/{VALID_HASH1}-3?x-id=PutObject");
+    let mock_client = StaticReplayClient::new(vec![
+        ReplayEvent::new(
+            http::Request::builder()
+                .method("PUT")
+                .uri(&put_uri)
+                .body(SdkBody::from(VALUE))
+                .unwrap(),
+            http::Response::builder()
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .body(SdkBody::empty())
+                .unwrap(),
+        ),
+        ReplayEvent::new(
+            http::Request::builder()
+                .method("PUT")
+                .uri(&put_uri)
+                .body(SdkBody::from(VALUE))
+                .unwrap(),
+            http::Response::builder()
+                .status(StatusCode::OK)
+                .body(SdkBody::empty())
+                .unwrap(),
+        ),
+    ]);
+    let test_config = Builder::new()
+        .behavior_version(BehaviorVersion::v2024_03_28())
+        .region(Region::from_static(REGION))
+        .http_client(mock_client.clone())
+        .build();
+    let s3_client = aws_sdk_s3::Client::from_conf(test_config);
+    let store = S3Store::new_with_client_and_jitter(
+        &S3Spec {
+            bucket: BUCKET_NAME.to_string(),
+            retry: nativelink_config::stores::Retry {
+                max_retries: 1,
+                delay: 0.,
+                jitter: 0.,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        s3_client,
+        Arc::new(move |_delay| Duration::from_secs(0)),
+        MockInstantWrapped::default,
+    )?;
+
+    let digest = DigestInfo::try_new(VALID_HASH1, VALUE.len())?;
+    let results = store
+        .update_many(vec![(digest.into(), Bytes::from_static(VALUE.as_bytes()))])
+        .await;
+    assert_eq!(results, vec![Ok(())]);
+    mock_client.assert_requests_match(&[]);
+    Ok(())
+}
+
+/// Upload a small object with `spec` and return the headers of the
+/// `PutObject` request.
+async fn put_object_headers(spec: S3Spec) -> Result<HashMap<String, String>, Error> {
+    const CONTENT_LENGTH: usize = 10;
+    let send_data = Bytes::from_static(b"0123456789");
+
+    let (mock_client, request_receiver) =
+        aws_smithy_runtime::client::http::test_util::capture_request(Some(
+            aws_smithy_runtime_api::http::Response::new(StatusCode::OK.into(), SdkBody::empty())
+                .try_into_http02x()
+                .unwrap(),
+        ));
+    let test_config = Builder::new()
+        .behavior_version(BehaviorVersion::v2024_03_28())
+        .region(Region::from_static(REGION))
+        .http_client(mock_client)
+        .build();
+    let s3_client = aws_sdk_s3::Client::from_conf(test_config);
+    let store = S3Store::new_with_client_and_jitter(
+        &S3Spec {
+            bucket: BUCKET_NAME.to_string(),
+            ..spec
+        },
+        s3_client,
+        Arc::new(move |_delay| Duration::from_secs(0)),
+        MockInstantWrapped::default,
+    )?;
+    let (mut tx, rx) = make_buf_channel_pair();
+    let mut update_fut = Box::pin(async move {
+        store
+            .update(
+                DigestInfo::try_new(VALID_HASH1, CONTENT_LENGTH)?,
+                rx,
+                UploadSizeInfo::ExactSize(CONTENT_LENGTH as u64),
+            )
+            .await
+    });
+
+    let (headers, body_stream) = {
+        assert_eq!(Poll::Pending, futures::poll!(&mut update_fut));
+        let sent_request = request_receiver.expect_request();
+        let headers = sent_request
+            .headers()
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        (headers, ByteStream::from_body_0_4(sent_request.into_body()))
+    };
+
+    let (update_res, send_res, data_sent_to_s3) = join!(
+        update_fut,
+        async move {
+            tx.send(send_data.clone()).await?;
+            tx.send_eof()
+        },
+        body_stream.collect()
+    );
+    update_res?;
+    send_res?;
+    assert_eq!(
+        data_sent_to_s3
+            .map_err(|e| make_input_err!("{e:?}"))?
+            .into_bytes(),
+        Bytes::from_static(b"0123456789")
+    );
+    Ok(headers)
+}
+
+#[nativelink_test]
+async fn update_sends_sse_kms_headers() -> Result<(), Error> {
+    const KMS_KEY_ID: &str = "arn:aws:kms:testregion:111122223333:key/dummy-key";
+    let headers = put_object_headers(S3Spec {
+        sse: Some(S3ServerSideEncryption {
+            algorithm: S3ServerSideEncryptionAlgorithm::aws_kms,
+            kms_key_id: Some(KMS_KEY_ID.to_string()),
+            bucket_key_enabled: Some(true),
+        }),
+        ..Default::default()
+    })
+    .await?;
+    assert_eq!(
+        headers
+            .get("x-amz-server-side-encryption")
+            .map(String::as_str),
+        Some("aws:kms")
+    );
+    assert_eq!(
+        headers
+            .get("x-amz-server-side-encryption-aws-kms-key-id")
+            .map(String::as_str),
+        Some(KMS_KEY_ID)
+    );
+    assert_eq!(
+        headers
+            .get("x-amz-server-side-encryption-bucket-key-enabled")
+            .map(String::as_str),
+        Some("true")
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn update_sends_storage_class_and_tags() -> Result<(), Error> {
+    let headers = put_object_headers(S3Spec {
+        storage_class: Some("STANDARD_IA".to_string()),
+        tags: HashMap::from([
+            ("team".to_string(), "build infra".to_string()),
+            ("kind".to_string(), "cas".to_string()),
+        ]),
+        ..Default::default()
+    })
+    .await?;
+    assert_eq!(
+        headers.get("x-amz-storage-class").map(String::as_str),
+        Some("STANDARD_IA")
+    );
+    // Tags are sorted and url encoded.
+    assert_eq!(
+        headers.get("x-amz-tagging").map(String::as_str),
+        Some("kind=cas&team=build%20infra")
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn sse_key_id_requires_kms() -> Result<(), Error> {
+    let test_config = Builder::new()
+        .behavior_version(BehaviorVersion::v2024_03_28())
+        .region(Region::from_static(REGION))
+        .http_client(StaticReplayClient::new(vec![]))
+        .build();
+    let result = S3Store::new_with_client_and_jitter(
+        &S3Spec {
+            bucket: BUCKET_NAME.to_string(),
+            sse: Some(S3ServerSideEncryption {
+                algorithm: S3ServerSideEncryptionAlgorithm::aes256,
+                kms_key_id: Some("dummy-key".to_string()),
+                bucket_key_enabled: None,
+            }),
+            ..Default::default()
+        },
+        aws_sdk_s3::Client::from_conf(test_config),
+        Arc::new(move |_delay| Duration::from_secs(0)),
+        MockInstantWrapped::default,
+    );
+    assert!(result.is_err());
+    Ok(())
+}
+
 #[nativelink_test]
 async fn simple_get_ac() -> Result<(), Error> {
     const VALUE: &str = "23";
@@ -547,6 +758,279 @@ async fn multipart_update_large_cas() -> Result<(), Error> {
     Ok(())
 }
 
+#[nativelink_test]
+async fn multipart_update_resumes_incomplete_upload() -> Result<(), Error> {
+    // Same as in s3_store.
+    const MIN_MULTIPART_SIZE: usize = 5 * 1024 * 1024; // 5mb.
+    const CAS_ENTRY_SIZE: usize = MIN_MULTIPART_SIZE * 2 + 50;
+
+    let send_data: Vec<u8> = (0..CAS_ENTRY_SIZE).map(|i| ((i * 3) % 256) as u8).collect();
+    let digest = DigestInfo::try_new(VALID_HASH1, send_data.len())?;
+    let object_url =
+        format!("https://{BUCKET_NAME}.s3.{REGION}.amazonaws.com/{VALID_HASH1}-{CAS_ENTRY_SIZE}");
+    // S3 uses the MD5 of a part as its entity tag.
+    let first_part_e_tag = format!(
+        "\"{}\"",
+        hex::encode(Md5::digest(&send_data[..MIN_MULTIPART_SIZE]))
+    );
+
+    let mock_client = StaticReplayClient::new(vec![
+        ReplayEvent::new(
+            http::Request::builder()
+                .uri(format!(
+                    "https://{BUCKET_NAME}.s3.{REGION}.amazonaws.com/?uploads&prefix={VALID_HASH1}-{CAS_ENTRY_SIZE}",
+                ))
+                .method("GET")
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(StatusCode::OK)
+                .body(SdkBody::from(format!(
+                    concat!(
+                        "<ListMultipartUploadsResult>",
+                        "<Upload><Key>{key}-5</Key><UploadId>Other-uploadid</UploadId>",
+                        "<Initiated>2024-01-01T00:00:00.000Z</Initiated></Upload>",
+                        "<Upload><Key>{key}</Key><UploadId>Dummy-uploadid</UploadId>",
+                        "<Initiated>2024-01-01T00:00:00.000Z</Initiated></Upload>",
+                        "</ListMultipartUploadsResult>",
+                    ),
+                    key = format!("{VALID_HASH1}-{CAS_ENTRY_SIZE}"),
+                )))
+                .unwrap(),
+        ),
+        ReplayEvent::new(
+            http::Request::builder()
+                .uri(format!("{object_url}?x-id=ListParts&uploadId=Dummy-uploadid"))
+                .method("GET")
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(StatusCode::OK)
+                .body(SdkBody::from(format!(
+                    concat!(
+                        "<ListPartsResult>",
+                        "<IsTruncated>false</IsTruncated>",
+                        // Matches the first part, so it is not uploaded again.
+                        "<Part><PartNumber>1</PartNumber><ETag>{first_part_e_tag}</ETag><Size>5242880</Size></Part>",
+                        // Was cut short, so it is uploaded again.
+                        "<Part><PartNumber>2</PartNumber><ETag>etag-2</ETag><Size>1024</Size></Part>",
+                        // Has other content, so it is uploaded again.
+                        "<Part><PartNumber>3</PartNumber><ETag>etag-3</ETag><Size>50</Size></Part>",
+                        "</ListPartsResult>",
+                    ),
+                    first_part_e_tag = first_part_e_tag,
+                )))
+                .unwrap(),
+        ),
+        ReplayEvent::new(
+            http::Request::builder()
+                .uri(format!(
+                    "{object_url}?x-id=UploadPart&partNumber=2&uploadId=Dummy-uploadid",
+                ))
+                .method("PUT")
+                .header("content-length", "5242880")
+                .body(SdkBody::from(&send_data[MIN_MULTIPART_SIZE..MIN_MULTIPART_SIZE * 2]))
+                .unwrap(),
+            http::Response::builder()
+                .status(StatusCode::OK)
+                .body(SdkBody::empty())
+                .unwrap(),
+        ),
+        ReplayEvent::new(
+            http::Request::builder()
+                .uri(format!(
+                    "{object_url}?x-id=UploadPart&partNumber=3&uploadId=Dummy-uploadid",
+                ))
+                .method("PUT")
+                .header("content-length", "50")
+                .body(SdkBody::from(&send_data[MIN_MULTIPART_SIZE * 2..]))
+                .unwrap(),
+            http::Response::builder()
+                .status(StatusCode::OK)
+                .body(SdkBody::empty())
+                .unwrap(),
+        ),
+        ReplayEvent::new(
+            http::Request::builder()
+                .uri(format!("{object_url}?uploadId=Dummy-uploadid"))
+                .method("POST")
+                .body(SdkBody::from(format!(
+                    concat!(
+                        r#"<CompleteMultipartUpload xmlns="http://s3.amazonaws.com/doc/2006-03-01/">"#,
+                        "<Part><ETag>{first_part_e_tag}</ETag><PartNumber>1</PartNumber></Part>",
+                        "<Part><PartNumber>2</PartNumber></Part>",
+                        "<Part><PartNumber>3</PartNumber></Part>",
+                        "</CompleteMultipartUpload>",
+                    ),
+                    first_part_e_tag = first_part_e_tag,
+                )))
+                .unwrap(),
+            http::Response::builder()
+                .status(StatusCode::OK)
+                .body(SdkBody::from(concat!(
+                    "<CompleteMultipartUploadResult>",
+                    "</CompleteMultipartUploadResult>",
+                )))
+                .unwrap(),
+        ),
+    ]);
+    let test_config = Builder::new()
+        .behavior_version(BehaviorVersion::v2024_03_28())
+        .region(Region::from_static(REGION))
+        .http_client(mock_client.clone())
+        .build();
+    let s3_client = aws_sdk_s3::Client::from_conf(test_config);
+    let store = S3Store::new_with_client_and_jitter(
+        &S3Spec {
+            bucket: BUCKET_NAME.to_string(),
+            resume_multipart_uploads: true,
+            ..Default::default()
+        },
+        s3_client,
+        Arc::new(move |_delay| Duration::from_secs(0)),
+        MockInstantWrapped::default,
+    )?;
+    store.update_oneshot(digest, send_data.into()).await?;
+    mock_client.assert_requests_match(&[]);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn abort_incomplete_multipart_uploads_only_aborts_old_uploads() -> Result<(), Error> {
+    let mock_client = StaticReplayClient::new(vec![
+        ReplayEvent::new(
+            http::Request::builder()
+                .uri(format!(
+                    "https://{BUCKET_NAME}.s3.{REGION}.amazonaws.com/?uploads&prefix=cas%2F",
+                ))
+                .method("GET")
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(StatusCode::OK)
+                .body(SdkBody::from(concat!(
+                    "<ListMultipartUploadsResult>",
+                    "<IsTruncated>false</IsTruncated>",
+                    "<Upload><Key>cas/old</Key><UploadId>Old-uploadid</UploadId>",
+                    "<Initiated>1970-01-01T00:00:00.000Z</Initiated></Upload>",
+                    "<Upload><Key>cas/new</Key><UploadId>New-uploadid</UploadId>",
+                    "<Initiated>1970-01-01T01:00:00.000Z</Initiated></Upload>",
+                    "</ListMultipartUploadsResult>",
+                )))
+                .unwrap(),
+        ),
+        ReplayEvent::new(
+            http::Request::builder()
+                .uri(format!(
+                    "https://{BUCKET_NAME}.s3.{REGION}.amazonaws.com/cas/old?x-id=AbortMultipartUpload&uploadId=Old-uploadid",
+                ))
+                .method("DELETE")
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(StatusCode::NO_CONTENT)
+                .body(SdkBody::empty())
+                .unwrap(),
+        ),
+    ]);
+    let test_config = Builder::new()
+        .behavior_version(BehaviorVersion::v2024_03_28())
+        .region(Region::from_static(REGION))
+        .http_client(mock_client.clone())
+        .build();
+    let s3_client = aws_sdk_s3::Client::from_conf(test_config);
+    let store = S3Store::new_with_client_and_jitter(
+        &S3Spec {
+            bucket: BUCKET_NAME.to_string(),
+            key_prefix: Some("cas/".to_string()),
+            abort_incomplete_multipart_uploads_after_s: 3600,
+            ..Default::default()
+        },
+        s3_client,
+        Arc::new(move |_delay| Duration::from_secs(0)),
+        MockInstantWrapped::default,
+    )?;
+    MockClock::advance(Duration::from_secs(5400));
+    assert_eq!(store.abort_incomplete_multipart_uploads().await?, 1);
+    mock_client.assert_requests_match(&[]);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn list_pages_through_objects_in_range() -> Result<(), Error> {
+    const HASH2: &str = "0123456789abcdef000000000000000000020000000000000123456789abcdef";
+    let list_url =
+        format!("https://{BUCKET_NAME}.s3.{REGION}.amazonaws.com/?list-type=2&prefix=cas%2F");
+    let mock_client = StaticReplayClient::new(vec![
+        ReplayEvent::new(
+            http::Request::builder()
+                .uri(&list_url)
+                .method("GET")
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(StatusCode::OK)
+                .body(SdkBody::from(format!(
+                    "<ListBucketResult><IsTruncated>true</IsTruncated>\
+                     <NextContinuationToken>page2</NextContinuationToken>\
+                     <Contents><Key>cas/{VALID_HASH1}-10</Key></Contents>\
+                     <Contents><Key>cas/some_string</Key></Contents>\
+                     </ListBucketResult>"
+                )))
+                .unwrap(),
+        ),
+        ReplayEvent::new(
+            http::Request::builder()
+                .uri(format!("{list_url}&continuation-token=page2"))
+                .method("GET")
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(StatusCode::OK)
+                .body(SdkBody::from(format!(
+                    "<ListBucketResult><IsTruncated>false</IsTruncated>\
+                     <Contents><Key>cas/{HASH2}-20</Key></Contents>\
+                     </ListBucketResult>"
+                )))
+                .unwrap(),
+        ),
+    ]);
+    let test_config = Builder::new()
+        .behavior_version(BehaviorVersion::v2024_03_28())
+        .region(Region::from_static(REGION))
+        .http_client(mock_client.clone())
+        .build();
+    let s3_client = aws_sdk_s3::Client::from_conf(test_config);
+    let store = S3Store::new_with_client_and_jitter(
+        &S3Spec {
+            bucket: BUCKET_NAME.to_string(),
+            key_prefix: Some("cas/".to_string()),
+            ..Default::default()
+        },
+        s3_client,
+        Arc::new(move |_delay| Duration::from_secs(0)),
+        MockInstantWrapped::default,
+    )?;
+
+    // Only digest keys are in the range, string keys sort before them.
+    let digest1 = DigestInfo::try_new(VALID_HASH1, 10)?;
+    let digest2 = DigestInfo::try_new(HASH2, 20)?;
+    let mut keys = vec![];
+    let count = store
+        .list(StoreKey::Digest(digest1).., |key| {
+            keys.push(key.borrow().into_owned());
+            true
+        })
+        .await?;
+    assert_eq!(count, 2);
+    assert_eq!(
+        keys,
+        vec![StoreKey::Digest(digest1), StoreKey::Digest(digest2)]
+    );
+    mock_client.assert_requests_match(&[]);
+    Ok(())
+}
+
 #[nativelink_test]
 async fn ensure_empty_string_in_stream_works_test() -> Result<(), Error> {
     const CAS_ENTRY_SIZE: usize = 10; // Length of "helloworld".
@@ -735,3 +1219,45 @@ async fn has_with_expired_result() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[nativelink_test]
+async fn presigned_get_url_points_to_object() -> Result<(), Error> {
+    let test_config = Builder::new()
+        .behavior_version(BehaviorVersion::v2024_03_28())
+        .region(Region::from_static(REGION))
+        .credentials_provider(Credentials::new(
+            "access_key",
+            "secret_key",
+            None,
+            None,
+            "test",
+        ))
+        .http_client(StaticReplayClient::new(vec![]))
+        .build();
+    let s3_client = aws_sdk_s3::Client::from_conf(test_config);
+    let store = S3Store::new_with_client_and_jitter(
+        &S3Spec {
+            bucket: BUCKET_NAME.to_string(),
+            key_prefix: Some("cas/".to_string()),
+            presigned_url_threshold: 1024,
+            presigned_url_expiration_s: 60,
+            ..Default::default()
+        },
+        s3_client,
+        Arc::new(move |_delay| Duration::from_secs(0)),
+        MockInstantWrapped::default,
+    )?;
+    assert_eq!(store.presigned_url_threshold(), 1024);
+
+    let digest = DigestInfo::try_new(VALID_HASH1, 2048).unwrap();
+    let url = store.presigned_get_url(digest.into()).await?;
+    assert!(
+        url.starts_with(&format!(
+            "https://{BUCKET_NAME}.s3.{REGION}.amazonaws.com/cas/{VALID_HASH1}-2048?"
+        )),
+        "Unexpected url: {url}"
+    );
+    assert!(url.contains("X-Amz-Expires=60"), "Unexpected url: {url}");
+    assert!(url.contains("X-Amz-Signature="), "Unexpected url: {url}");
+    Ok(())
+}
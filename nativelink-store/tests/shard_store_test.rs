@@ -14,11 +14,11 @@
 
 use std::sync::Arc;
 
-use nativelink_config::stores::{MemorySpec, ShardSpec, StoreSpec};
+use nativelink_config::stores::{MemorySpec, ShardAlgorithm, ShardSpec, StoreSpec};
 use nativelink_error::Error;
 use nativelink_macro::nativelink_test;
 use nativelink_store::memory_store::MemoryStore;
-use nativelink_store::shard_store::ShardStore;
+use nativelink_store::shard_store::{ShardRebalanceReport, ShardStore};
 use nativelink_util::common::DigestInfo;
 use nativelink_util::digest_hasher::{DigestHasher, DigestHasherFunc};
 use nativelink_util::store_trait::{Store, StoreLike};
@@ -29,14 +29,27 @@ use rand::{Rng, SeedableRng};
 const MEGABYTE_SZ: usize = 1024 * 1024;
 
 fn make_stores(weights: &[u32]) -> (Arc<ShardStore>, Vec<Arc<MemoryStore>>) {
-    let memory_store_config = MemorySpec::default();
-    let store_config = StoreSpec::memory(memory_store_config.clone());
+    make_stores_with_algorithm(weights, ShardAlgorithm::weighted_range)
+}
+
+fn make_stores_with_algorithm(
+    weights: &[u32],
+    algorithm: ShardAlgorithm,
+) -> (Arc<ShardStore>, Vec<Arc<MemoryStore>>) {
     let stores: Vec<_> = weights
         .iter()
-        .map(|_| MemoryStore::new(&memory_store_config))
+        .map(|_| MemoryStore::new(&MemorySpec::default()))
         .collect();
+    (make_shard_store(&stores, weights, algorithm), stores)
+}
 
-    let shard_store = ShardStore::new(
+fn make_shard_store(
+    stores: &[Arc<MemoryStore>],
+    weights: &[u32],
+    algorithm: ShardAlgorithm,
+) -> Arc<ShardStore> {
+    let store_config = StoreSpec::memory(MemorySpec::default());
+    ShardStore::new(
         &ShardSpec {
             stores: weights
                 .iter()
@@ -45,14 +58,29 @@ fn make_stores(weights: &[u32]) -> (Arc<ShardStore>, Vec<Arc<MemoryStore>>) {
                     weight: Some(*weight),
                 })
                 .collect(),
+            algorithm,
         },
         stores
             .iter()
             .map(|store| Store::new(store.clone()))
             .collect(),
     )
-    .unwrap();
-    (shard_store, stores)
+    .unwrap()
+}
+
+fn make_digest(counter: u64) -> DigestInfo {
+    let mut hasher = DigestHasherFunc::Blake3.hasher();
+    hasher.update(&counter.to_le_bytes());
+    hasher.finalize_digest()
+}
+
+async fn find_store_index(stores: &[Arc<MemoryStore>], digest: DigestInfo) -> Option<usize> {
+    for (index, store) in stores.iter().enumerate() {
+        if store.has(digest).await.unwrap().is_some() {
+            return Some(index);
+        }
+    }
+    None
 }
 
 fn make_random_data(sz: usize) -> Vec<u8> {
@@ -307,3 +335,110 @@ async fn verify_weights_left_bias() -> Result<(), Error> {
 async fn verify_weights_right_bias() -> Result<(), Error> {
     verify_weights(&[1, 1, 1, 1, 1, 100], &[5, 13, 12, 5, 11, 954], 1000, false).await
 }
+
+#[nativelink_test]
+async fn consistent_hash_only_moves_keys_to_added_store() -> Result<(), Error> {
+    const ROUNDS: u64 = 1000;
+    let (three_shard_store, three_stores) =
+        make_stores_with_algorithm(&[1, 1, 1], ShardAlgorithm::consistent_hash);
+    let (four_shard_store, four_stores) =
+        make_stores_with_algorithm(&[1, 1, 1, 1], ShardAlgorithm::consistent_hash);
+
+    let mut moved_keys = 0;
+    for counter in 0..ROUNDS {
+        let digest = make_digest(counter);
+        three_shard_store
+            .update_oneshot(digest, counter.to_le_bytes().to_vec().into())
+            .await?;
+        four_shard_store
+            .update_oneshot(digest, counter.to_le_bytes().to_vec().into())
+            .await?;
+        let before = find_store_index(&three_stores, digest).await.unwrap();
+        let after = find_store_index(&four_stores, digest).await.unwrap();
+        if before != after {
+            assert_eq!(after, 3, "Key moved between stores that already existed");
+            moved_keys += 1;
+        }
+    }
+    // The added store should take over roughly a quarter of the keys.
+    assert!(
+        (150..350).contains(&moved_keys),
+        "Expected about 250 moved keys, got {moved_keys}"
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn consistent_hash_honors_weights() -> Result<(), Error> {
+    const ROUNDS: u64 = 1000;
+    let (shard_store, stores) =
+        make_stores_with_algorithm(&[1, 3], ShardAlgorithm::consistent_hash);
+    for counter in 0..ROUNDS {
+        shard_store
+            .update_oneshot(make_digest(counter), counter.to_le_bytes().to_vec().into())
+            .await?;
+    }
+    let heavy_hits = stores[1].len_for_test().await;
+    assert_eq!(stores[0].len_for_test().await + heavy_hits, ROUNDS as usize);
+    assert!(
+        (650..850).contains(&heavy_hits),
+        "Expected about 750 keys in the heavier store, got {heavy_hits}"
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn rebalance_copies_moved_keys_to_added_store() -> Result<(), Error> {
+    const ROUNDS: u64 = 200;
+    let (three_shard_store, mut stores) =
+        make_stores_with_algorithm(&[1, 1, 1], ShardAlgorithm::consistent_hash);
+    for counter in 0..ROUNDS {
+        three_shard_store
+            .update_oneshot(make_digest(counter), counter.to_le_bytes().to_vec().into())
+            .await?;
+    }
+    stores.push(MemoryStore::new(&MemorySpec::default()));
+    let four_shard_store =
+        make_shard_store(&stores, &[1, 1, 1, 1], ShardAlgorithm::consistent_hash);
+
+    let report = four_shard_store.rebalance(true).await?;
+
+    let moved_keys = stores[3].len_for_test().await as u64;
+    assert!(
+        moved_keys > 0,
+        "Expected some keys to move to the added store"
+    );
+    assert_eq!(
+        report,
+        ShardRebalanceReport {
+            scanned_keys: ROUNDS,
+            moved_keys,
+            copied_keys: moved_keys,
+            copied_bytes: moved_keys * 8,
+            removed_keys: moved_keys,
+        }
+    );
+    let mut total_keys = 0;
+    for store in &stores {
+        total_keys += store.len_for_test().await;
+    }
+    assert_eq!(total_keys, ROUNDS as usize);
+    for counter in 0..ROUNDS {
+        assert_eq!(
+            four_shard_store
+                .get_part_unchunked(make_digest(counter), 0, None)
+                .await,
+            Ok(counter.to_le_bytes().to_vec().into()),
+        );
+    }
+
+    // Everything is where it belongs now, so nothing moves anymore.
+    assert_eq!(
+        four_shard_store.rebalance(true).await?,
+        ShardRebalanceReport {
+            scanned_keys: ROUNDS,
+            ..Default::default()
+        }
+    );
+    Ok(())
+}
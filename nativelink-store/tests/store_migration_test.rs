@@ -0,0 +1,114 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use nativelink_config::stores::MemorySpec;
+use nativelink_error::Error;
+use nativelink_macro::nativelink_test;
+use nativelink_store::memory_store::MemoryStore;
+use nativelink_store::store_migration::{migrate_store, MigrationOptions, MigrationProgress};
+use nativelink_util::common::DigestInfo;
+use nativelink_util::store_trait::{Store, StoreKey, StoreLike};
+use pretty_assertions::assert_eq;
+
+const VALUE: &str = "123";
+
+fn make_digest(i: u8) -> DigestInfo {
+    DigestInfo::new([i; 32], VALUE.len() as u64)
+}
+
+async fn make_source(num_keys: u8) -> Result<Store, Error> {
+    let store = Store::new(MemoryStore::new(&MemorySpec::default()));
+    for i in 0..num_keys {
+        store.update_oneshot(make_digest(i), VALUE.into()).await?;
+    }
+    Ok(store)
+}
+
+const OPTIONS: MigrationOptions = MigrationOptions {
+    parallelism: 2,
+    batch_size: 3,
+};
+
+#[nativelink_test]
+async fn migrate_copies_all_keys_in_batches() -> Result<(), Error> {
+    let source = make_source(7).await?;
+    let destination = Store::new(MemoryStore::new(&MemorySpec::default()));
+
+    let mut batch_last_keys = Vec::new();
+    let progress = migrate_store(&source, &destination, None, OPTIONS, |_, last_key| {
+        batch_last_keys.push(last_key.clone());
+        Ok(())
+    })
+    .await?;
+
+    assert_eq!(
+        progress,
+        MigrationProgress {
+            listed_keys: 7,
+            copied_keys: 7,
+            copied_bytes: 7 * VALUE.len() as u64,
+            skipped_keys: 0,
+        }
+    );
+    assert_eq!(
+        batch_last_keys,
+        vec![
+            StoreKey::from(make_digest(2)),
+            StoreKey::from(make_digest(5)),
+            StoreKey::from(make_digest(6)),
+        ]
+    );
+    for i in 0..7 {
+        assert_eq!(
+            destination
+                .get_part_unchunked(make_digest(i), 0, None)
+                .await?,
+            VALUE.as_bytes()
+        );
+    }
+    Ok(())
+}
+
+#[nativelink_test]
+async fn migrate_resumes_after_key_and_skips_existing() -> Result<(), Error> {
+    let source = make_source(5).await?;
+    let destination = Store::new(MemoryStore::new(&MemorySpec::default()));
+    destination
+        .update_oneshot(make_digest(3), VALUE.into())
+        .await?;
+
+    let progress = migrate_store(
+        &source,
+        &destination,
+        Some(make_digest(1).into()),
+        OPTIONS,
+        |_, _| Ok(()),
+    )
+    .await?;
+
+    assert_eq!(
+        progress,
+        MigrationProgress {
+            listed_keys: 3,
+            copied_keys: 2,
+            copied_bytes: 2 * VALUE.len() as u64,
+            skipped_keys: 1,
+        }
+    );
+    assert_eq!(destination.has(make_digest(0)).await?, None);
+    assert_eq!(destination.has(make_digest(1)).await?, None);
+    assert!(destination.has(make_digest(2)).await?.is_some());
+    assert!(destination.has(make_digest(4)).await?.is_some());
+    Ok(())
+}
@@ -27,6 +27,9 @@ use nativelink_proto::build::bazel::remote::execution::v2::{
     ExecuteRequest, ExecuteResponse, ExecutedActionMetadata, FileNode, LogFile, OutputDirectory,
     OutputFile, OutputSymlink, SymlinkNode,
 };
+use nativelink_proto::com::github::trace_machina::nativelink::remote_execution::{
+    OomKillMetadata, RetryMetadata,
+};
 use nativelink_proto::google::longrunning::operation::Result as LongRunningResult;
 use nativelink_proto::google::longrunning::Operation;
 use nativelink_proto::google::rpc::Status;
@@ -39,6 +42,7 @@ use uuid::Uuid;
 
 use crate::common::{DigestInfo, HashMapExt, VecExt};
 use crate::digest_hasher::DigestHasherFunc;
+use crate::resource_info::{ActionOutput, ActionOutputStreamName};
 
 /// Default priority remote execution jobs will get when not provided.
 pub const DEFAULT_EXECUTION_PRIORITY: i32 = 0;
@@ -338,7 +342,7 @@ impl ActionInfo {
                 .err_tip(|| "Expected action_digest to exist on ExecuteRequest")?
                 .try_into()?,
         };
-        let unique_qualifier = if execute_request.skip_cache_lookup {
+        let unique_qualifier = if execute_request.skip_cache_lookup || action.do_not_cache {
             ActionUniqueQualifier::Uncachable(unique_key)
         } else {
             ActionUniqueQualifier::Cachable(unique_key)
@@ -585,6 +589,14 @@ pub struct ExecutionMetadata {
     pub execution_completed_timestamp: SystemTime,
     pub output_upload_start_timestamp: SystemTime,
     pub output_upload_completed_timestamp: SystemTime,
+    /// Number of times the scheduler retried the action after it failed on
+    /// a worker.
+    #[serde(default)]
+    pub retries: u32,
+    /// The memory limit in bytes of the action if the worker killed it for
+    /// using more memory than that.
+    #[serde(default)]
+    pub oom_kill_memory_limit_bytes: Option<u64>,
 }
 
 impl Default for ExecutionMetadata {
@@ -600,6 +612,8 @@ impl Default for ExecutionMetadata {
             execution_completed_timestamp: SystemTime::UNIX_EPOCH,
             output_upload_start_timestamp: SystemTime::UNIX_EPOCH,
             output_upload_completed_timestamp: SystemTime::UNIX_EPOCH,
+            retries: 0,
+            oom_kill_memory_limit_bytes: None,
         }
     }
 }
@@ -622,7 +636,18 @@ impl From<ExecutionMetadata> for ExecutedActionMetadata {
                 .duration_since(val.execution_start_timestamp)
                 .ok()
                 .and_then(|duration| prost_types::Duration::try_from(duration).ok()),
-            auxiliary_metadata: Vec::default(),
+            auxiliary_metadata: {
+                let mut auxiliary_metadata = Vec::new();
+                if val.retries != 0 {
+                    auxiliary_metadata.push(to_any(&RetryMetadata {
+                        retries: val.retries,
+                    }));
+                }
+                if let Some(memory_limit_bytes) = val.oom_kill_memory_limit_bytes {
+                    auxiliary_metadata.push(to_any(&OomKillMetadata { memory_limit_bytes }));
+                }
+                auxiliary_metadata
+            },
         }
     }
 }
@@ -683,6 +708,22 @@ impl TryFrom<ExecutedActionMetadata> for ExecutionMetadata {
                     "Expected output_upload_completed_timestamp to exist in ExecutedActionMetadata"
                 })?
                 .try_into()?,
+            retries: eam
+                .auxiliary_metadata
+                .iter()
+                .find(|message| message.type_url == RetryMetadata::TYPE_URL)
+                .map(from_any::<RetryMetadata>)
+                .transpose()
+                .err_tip(|| "Could not decode RetryMetadata in ExecutedActionMetadata")?
+                .map_or(0, |retry_metadata| retry_metadata.retries),
+            oom_kill_memory_limit_bytes: eam
+                .auxiliary_metadata
+                .iter()
+                .find(|message| message.type_url == OomKillMetadata::TYPE_URL)
+                .map(from_any::<OomKillMetadata>)
+                .transpose()
+                .err_tip(|| "Could not decode OomKillMetadata in ExecutedActionMetadata")?
+                .map(|oom_kill_metadata| oom_kill_metadata.memory_limit_bytes),
         })
     }
 }
@@ -725,6 +766,8 @@ impl Default for ActionResult {
                 execution_completed_timestamp: SystemTime::UNIX_EPOCH,
                 output_upload_start_timestamp: SystemTime::UNIX_EPOCH,
                 output_upload_completed_timestamp: SystemTime::UNIX_EPOCH,
+                retries: 0,
+                oom_kill_memory_limit_bytes: None,
             },
             server_logs: HashMap::default(),
             error: None,
@@ -1052,6 +1095,16 @@ impl TypeUrl for ExecuteOperationMetadata {
         "type.googleapis.com/build.bazel.remote.execution.v2.ExecuteOperationMetadata";
 }
 
+impl TypeUrl for RetryMetadata {
+    const TYPE_URL: &'static str =
+        "type.googleapis.com/com.github.trace_machina.nativelink.remote_execution.RetryMetadata";
+}
+
+impl TypeUrl for OomKillMetadata {
+    const TYPE_URL: &'static str =
+        "type.googleapis.com/com.github.trace_machina.nativelink.remote_execution.OomKillMetadata";
+}
+
 fn from_any<T>(message: &Any) -> Result<T, Error>
 where
     T: TypeUrl + Default,
@@ -1144,6 +1197,37 @@ impl ActionState {
     }
 
     pub fn as_operation(&self, client_operation_id: OperationId) -> Operation {
+        self.as_operation_with_partial_execution_metadata(client_operation_id, None)
+    }
+
+    /// Same as `as_operation`, but also reports what is known about the
+    /// execution of the action while it is not finished, like the worker
+    /// running it.
+    pub fn as_operation_with_partial_execution_metadata(
+        &self,
+        client_operation_id: OperationId,
+        partial_execution_metadata: Option<ExecutedActionMetadata>,
+    ) -> Operation {
+        self.make_operation(client_operation_id, partial_execution_metadata, None)
+    }
+
+    /// Same as `as_operation`, but while the action is executing it also
+    /// reports the names of its live stdout and stderr streams, which
+    /// clients can read from the `ByteStream` service of `instance_name`.
+    pub fn as_operation_with_output_streams(
+        &self,
+        client_operation_id: OperationId,
+        instance_name: &str,
+    ) -> Operation {
+        self.make_operation(client_operation_id, None, Some(instance_name))
+    }
+
+    fn make_operation(
+        &self,
+        client_operation_id: OperationId,
+        partial_execution_metadata: Option<ExecutedActionMetadata>,
+        maybe_output_streams_instance_name: Option<&str>,
+    ) -> Operation {
         let stage = Into::<execution_stage::Value>::into(&self.stage) as i32;
         let name = client_operation_id.into_string();
 
@@ -1155,13 +1239,18 @@ impl ActionState {
         };
         let digest = Some(self.action_digest.into());
 
+        let stream_name = |output| match maybe_output_streams_instance_name {
+            Some(instance_name) if matches!(self.stage, ActionStage::Executing) => {
+                ActionOutputStreamName::new(instance_name, self.action_digest, output).to_string()
+            }
+            _ => String::default(),
+        };
         let metadata = ExecuteOperationMetadata {
             stage,
             action_digest: digest,
-            // TODO(blaise.bruer) We should support stderr/stdout streaming.
-            stdout_stream_name: String::default(),
-            stderr_stream_name: String::default(),
-            partial_execution_metadata: None,
+            stdout_stream_name: stream_name(ActionOutput::Stdout),
+            stderr_stream_name: stream_name(ActionOutput::Stderr),
+            partial_execution_metadata,
         };
 
         Operation {
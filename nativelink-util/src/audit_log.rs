@@ -0,0 +1,292 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Records of who read and wrote which entries of the caches and which
+//! actions they executed, written as JSON lines to a file or a store.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use futures::{future, FutureExt};
+use nativelink_error::{make_err, Code, Error, ResultExt};
+use nativelink_proto::build::bazel::remote::execution::v2::Digest;
+use serde::Serialize;
+use tokio::sync::{broadcast, mpsc};
+use tracing::{event, Level};
+use uuid::Uuid;
+
+use crate::fs;
+use crate::origin_context::{ActiveOriginContext, NLSymbol, ORIGIN_IDENTITY, REQUEST_ID};
+use crate::origin_event::get_node_id;
+use crate::shutdown_guard::{Priority, ShutdownGuard};
+use crate::store_trait::{Store, StoreLike};
+
+static AUDIT_LOG_TX: OnceLock<mpsc::Sender<AuditRecord>> = OnceLock::new();
+
+/// Enables the audit log. Records are sent to `tx`, which should be
+/// consumed by an [`AuditLogPublisher`].
+pub fn init_audit_log(tx: mpsc::Sender<AuditRecord>) -> Result<(), Error> {
+    AUDIT_LOG_TX
+        .set(tx)
+        .map_err(|_| make_err!(Code::Internal, "Audit log already initialized"))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOperation {
+    AcRead,
+    AcWrite,
+    CasFindMissing,
+    CasRead,
+    CasWrite,
+    Execute,
+}
+
+/// A request recorded in the audit log.
+#[derive(Clone, Debug, Serialize)]
+pub struct AuditRecord {
+    /// Milliseconds since the UNIX epoch when the request was received.
+    pub timestamp_ms: u64,
+    /// See [`REQUEST_ID`].
+    pub request_id: String,
+    /// See [`ORIGIN_IDENTITY`].
+    pub identity: String,
+    pub operation: AuditOperation,
+    pub instance_name: String,
+    /// The digests read or written as "hash-size", or the digest of the
+    /// action for executions.
+    pub digests: Vec<String>,
+    /// Number of bytes uploaded by writes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upload_bytes: Option<u64>,
+    /// Arguments of the command of executions.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub command: Vec<String>,
+    /// Whether the request succeeded.
+    pub succeeded: bool,
+}
+
+impl AuditRecord {
+    /// Creates the record of a request in the current origin context.
+    pub fn new(operation: AuditOperation, instance_name: &str) -> Self {
+        let context_value = |symbol: &'static NLSymbol<String>| {
+            ActiveOriginContext::get_value(symbol)
+                .ok()
+                .flatten()
+                .map(|value| value.as_ref().clone())
+                .unwrap_or_default()
+        };
+        Self {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            request_id: context_value(&REQUEST_ID),
+            identity: context_value(&ORIGIN_IDENTITY),
+            operation,
+            instance_name: instance_name.to_string(),
+            digests: Vec::new(),
+            upload_bytes: None,
+            command: Vec::new(),
+            succeeded: false,
+        }
+    }
+
+    #[must_use]
+    pub fn with_digests<'a>(mut self, digests: impl IntoIterator<Item = &'a Digest>) -> Self {
+        self.digests.extend(
+            digests
+                .into_iter()
+                .map(|digest| format!("{}-{}", digest.hash, digest.size_bytes)),
+        );
+        self
+    }
+
+    #[must_use]
+    pub const fn with_upload_bytes(mut self, upload_bytes: u64) -> Self {
+        self.upload_bytes = Some(upload_bytes);
+        self
+    }
+}
+
+/// Starts the record of a request with `make_record`, if the audit log is
+/// enabled. It is written with [`audit_log`] once the request finished.
+pub fn audit_record(make_record: impl FnOnce() -> AuditRecord) -> Option<AuditRecord> {
+    AUDIT_LOG_TX.get().map(|_| make_record())
+}
+
+/// Writes `record` to the audit log with the outcome of its request. Waits
+/// if the queue of records is full, so no record is lost.
+pub async fn audit_log<T, E>(record: Option<AuditRecord>, result: &Result<T, E>) {
+    let (Some(mut record), Some(tx)) = (record, AUDIT_LOG_TX.get()) else {
+        return;
+    };
+    record.succeeded = result.is_ok();
+    if tx.send(record).await.is_err() {
+        event!(Level::ERROR, "Audit log publisher is not running");
+    }
+}
+
+/// Where the records of the audit log are written.
+pub enum AuditLogWriter {
+    /// Appends to the file at `path`. Once it holds more than `max_bytes`
+    /// it is renamed to `path.1`, the previous `path.1` to `path.2` and so
+    /// on, dropping the file beyond `max_files`.
+    File {
+        path: PathBuf,
+        max_bytes: u64,
+        max_files: usize,
+    },
+    /// Uploads every batch of records to the store, under the key
+    /// `AuditLog:<uuid>`. The uuids are ordered by time.
+    Store(Store),
+}
+
+impl AuditLogWriter {
+    async fn write(&self, data: Vec<u8>) -> Result<(), Error> {
+        match self {
+            Self::File {
+                path,
+                max_bytes,
+                max_files,
+            } => {
+                let path = path.clone();
+                let (max_bytes, max_files) = (*max_bytes, *max_files);
+                fs::call_with_permit(move |_| {
+                    rotate_if_full(&path, max_bytes, max_files)?;
+                    std::fs::File::options()
+                        .create(true)
+                        .append(true)
+                        .open(&path)
+                        .and_then(|mut file| file.write_all(&data))
+                        .err_tip(|| format!("Could not write audit log to {path:?}"))
+                })
+                .await
+            }
+            Self::Store(store) => {
+                let uuid = Uuid::now_v6(&get_node_id(None));
+                store
+                    .update_oneshot(format!("AuditLog:{}", uuid.hyphenated()), data.into())
+                    .await
+                    .err_tip(|| "Could not upload audit log")
+            }
+        }
+    }
+}
+
+fn rotate_if_full(path: &Path, max_bytes: u64, max_files: usize) -> Result<(), Error> {
+    let size = match std::fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(Error::from(e).append(format!("Could not stat {path:?}"))),
+    };
+    if size < max_bytes {
+        return Ok(());
+    }
+    let rotated_path = |i: usize| {
+        let mut rotated_path = path.as_os_str().to_owned();
+        rotated_path.push(format!(".{i}"));
+        PathBuf::from(rotated_path)
+    };
+    for i in (1..max_files).rev() {
+        let from = rotated_path(i);
+        if from.exists() {
+            std::fs::rename(&from, rotated_path(i + 1))
+                .err_tip(|| format!("Could not rotate {from:?}"))?;
+        }
+    }
+    if max_files == 0 {
+        std::fs::remove_file(path).err_tip(|| format!("Could not remove {path:?}"))
+    } else {
+        std::fs::rename(path, rotated_path(1)).err_tip(|| format!("Could not rotate {path:?}"))
+    }
+}
+
+/// Writes the records of the audit log with an [`AuditLogWriter`].
+pub struct AuditLogPublisher {
+    writer: AuditLogWriter,
+    rx: mpsc::Receiver<AuditRecord>,
+    shutdown_tx: broadcast::Sender<ShutdownGuard>,
+}
+
+impl AuditLogPublisher {
+    pub const fn new(
+        writer: AuditLogWriter,
+        rx: mpsc::Receiver<AuditRecord>,
+        shutdown_tx: broadcast::Sender<ShutdownGuard>,
+    ) -> Self {
+        Self {
+            writer,
+            rx,
+            shutdown_tx,
+        }
+    }
+
+    /// Runs the audit log publisher.
+    pub async fn run(mut self) {
+        const MAX_RECORDS_PER_BATCH: usize = 1024;
+        let mut batch: Vec<AuditRecord> = Vec::with_capacity(MAX_RECORDS_PER_BATCH);
+        let mut shutdown_rx = self.shutdown_tx.subscribe();
+        let shutdown_fut = shutdown_rx.recv().fuse();
+        tokio::pin!(shutdown_fut);
+        let shutdown_guard = future::pending().left_future();
+        tokio::pin!(shutdown_guard);
+        loop {
+            tokio::select! {
+                biased;
+                received = self.rx.recv_many(&mut batch, MAX_RECORDS_PER_BATCH) => {
+                    if received == 0 {
+                        // All senders are dropped, no more records can come.
+                        return;
+                    }
+                    self.handle_batch(&mut batch).await;
+                }
+                shutdown_guard_res = &mut shutdown_fut => {
+                    let Ok(mut local_shutdown_guard) = shutdown_guard_res else {
+                        event!(Level::ERROR, "Received shutdown in audit log publisher but failed to get shutdown guard");
+                        return;
+                    };
+                    shutdown_guard.set(async move {
+                        local_shutdown_guard.wait_for(Priority::P0).await;
+                    }
+                    .right_future());
+                }
+                () = &mut shutdown_guard => {
+                    // All other services with less priority have completed.
+                    // We may still need to write any remaining records.
+                    while !self.rx.is_empty() {
+                        self.rx.recv_many(&mut batch, MAX_RECORDS_PER_BATCH).await;
+                        self.handle_batch(&mut batch).await;
+                    }
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn handle_batch(&self, batch: &mut Vec<AuditRecord>) {
+        let mut data = Vec::new();
+        for record in batch.drain(..) {
+            match serde_json::to_writer(&mut data, &record) {
+                Ok(()) => data.push(b'\n'),
+                Err(err) => event!(Level::ERROR, ?err, ?record, "Could not encode audit record"),
+            }
+        }
+        if let Err(err) = self.writer.write(data).await {
+            event!(Level::ERROR, ?err, "Failed to write audit log");
+        }
+    }
+}
@@ -0,0 +1,406 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use futures::task::{Context, Poll};
+use hyper::http::{self, HeaderMap, HeaderValue, StatusCode};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use nativelink_config::cas_server::{AuthConfig, JwtAuthConfig};
+use nativelink_error::{make_err, make_input_err, Code, Error, ResultExt};
+use sha2::{Digest, Sha256};
+use tower::layer::Layer;
+use tower::Service;
+use tracing::{trace_span, Span};
+
+use crate::origin_context::{ActiveOriginContext, AUTHENTICATED_IDENTITY, ORIGIN_IDENTITY};
+
+/// Header the token is read from if not configured.
+const DEFAULT_AUTH_HEADER: &str = "authorization";
+
+/// Claim the identity is read from if not configured.
+const DEFAULT_IDENTITY_CLAIM: &str = "sub";
+
+/// gRPC methods that only read data. Anonymous and read-only clients may
+/// only call these.
+const READ_ONLY_METHODS: &[&str] = &[
+    "/build.bazel.remote.execution.v2.ActionCache/GetActionResult",
+    "/build.bazel.remote.execution.v2.Capabilities/GetCapabilities",
+    "/build.bazel.remote.execution.v2.ContentAddressableStorage/BatchReadBlobs",
+    "/build.bazel.remote.execution.v2.ContentAddressableStorage/FindMissingBlobs",
+    "/build.bazel.remote.execution.v2.ContentAddressableStorage/GetTree",
+    "/google.bytestream.ByteStream/QueryWriteStatus",
+    "/google.bytestream.ByteStream/Read",
+    "/google.longrunning.Operations/GetOperation",
+    "/google.longrunning.Operations/ListOperations",
+    "/grpc.health.v1.Health/Check",
+];
+
+/// Every algorithm JWTs may be signed with. Keys are only used with
+/// algorithms of their own family.
+const JWT_ALGORITHMS: &[Algorithm] = &[
+    Algorithm::HS256,
+    Algorithm::HS384,
+    Algorithm::HS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::EdDSA,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    ReadOnly,
+    ReadWrite,
+}
+
+/// Which requests to a service only read data, so anonymous and read-only
+/// clients may send them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReadOnlyRequests {
+    /// Calls of the gRPC methods in `READ_ONLY_METHODS`.
+    GrpcMethods,
+    /// HTTP `GET` and `HEAD` requests.
+    HttpGetAndHead,
+    /// No request, every request needs read-write access.
+    Nothing,
+}
+
+impl ReadOnlyRequests {
+    fn is_read_only(self, method: &http::Method, path: &str) -> bool {
+        match self {
+            Self::GrpcMethods => READ_ONLY_METHODS.contains(&path),
+            Self::HttpGetAndHead => method == http::Method::GET || method == http::Method::HEAD,
+            Self::Nothing => false,
+        }
+    }
+}
+
+/// Verifies JWTs with the keys of a JSON Web Key Set.
+struct JwtVerifier {
+    keys: Vec<(Option<String>, DecodingKey)>,
+    validation: Validation,
+    identity_claim: String,
+}
+
+impl JwtVerifier {
+    fn new(config: &JwtAuthConfig) -> Result<Self, Error> {
+        let jwks_json = std::fs::read_to_string(&config.jwks_file)
+            .err_tip(|| format!("Could not read jwks_file {}", config.jwks_file))?;
+        let jwks: JwkSet = serde_json::from_str(&jwks_json)
+            .map_err(|e| make_input_err!("Invalid jwks_file {}: {e:?}", config.jwks_file))?;
+        let keys = jwks
+            .keys
+            .iter()
+            .map(|jwk| {
+                DecodingKey::from_jwk(jwk)
+                    .map(|key| (jwk.common.key_id.clone(), key))
+                    .map_err(|e| make_input_err!("Invalid key in {}: {e:?}", config.jwks_file))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        if keys.is_empty() {
+            return Err(make_input_err!("No keys in jwks_file {}", config.jwks_file));
+        }
+        let mut validation = Validation::new(Algorithm::RS256);
+        validation.algorithms = JWT_ALGORITHMS.to_vec();
+        validation.set_issuer(&[&config.issuer]);
+        if let Some(audience) = &config.audience {
+            validation.set_audience(&[audience]);
+        } else {
+            validation.validate_aud = false;
+        }
+        let identity_claim = if config.identity_claim.is_empty() {
+            DEFAULT_IDENTITY_CLAIM.to_string()
+        } else {
+            config.identity_claim.clone()
+        };
+        Ok(Self {
+            keys,
+            validation,
+            identity_claim,
+        })
+    }
+
+    /// Returns the identity in `token` if it is a valid JWT.
+    fn verify(&self, token: &str) -> Result<String, Error> {
+        let header = decode_header(token)
+            .map_err(|e| make_err!(Code::Unauthenticated, "Invalid JWT: {e:?}"))?;
+        let key = match (&header.kid, self.keys.as_slice()) {
+            (Some(kid), keys) => keys
+                .iter()
+                .find(|(key_id, _)| key_id.as_ref() == Some(kid))
+                .map(|(_, key)| key),
+            (None, [(_, key)]) => Some(key),
+            (None, _) => None,
+        }
+        .ok_or_else(|| make_err!(Code::Unauthenticated, "No key to verify JWT with"))?;
+        let claims = decode::<HashMap<String, serde_json::Value>>(token, key, &self.validation)
+            .map_err(|e| make_err!(Code::Unauthenticated, "Invalid JWT: {e:?}"))?
+            .claims;
+        claims
+            .get(&self.identity_claim)
+            .and_then(serde_json::Value::as_str)
+            .map(str::to_string)
+            .ok_or_else(|| {
+                make_err!(
+                    Code::Unauthenticated,
+                    "JWT has no '{}' claim",
+                    self.identity_claim
+                )
+            })
+    }
+}
+
+/// Finds the identity and access of clients from the token they send.
+pub struct Authenticator {
+    header_name: String,
+    /// Identity and access of the static tokens by the sha256 of the token,
+    /// so tokens are not compared byte by byte.
+    tokens: HashMap<[u8; 32], (String, Access)>,
+    jwt_verifier: Option<JwtVerifier>,
+    allow_anonymous_read: bool,
+}
+
+impl Authenticator {
+    pub fn new(config: &AuthConfig) -> Result<Self, Error> {
+        let header_name = if config.header_name.is_empty() {
+            DEFAULT_AUTH_HEADER.to_string()
+        } else {
+            config.header_name.to_lowercase()
+        };
+        let mut tokens = HashMap::with_capacity(config.tokens.len());
+        for token_config in &config.tokens {
+            if token_config.token.is_empty() {
+                return Err(make_input_err!(
+                    "Token for identity '{}' is empty",
+                    token_config.identity
+                ));
+            }
+            let access = if token_config.read_only {
+                Access::ReadOnly
+            } else {
+                Access::ReadWrite
+            };
+            tokens.insert(
+                Sha256::digest(token_config.token.as_bytes()).into(),
+                (token_config.identity.clone(), access),
+            );
+        }
+        let jwt_verifier = config.jwt.as_ref().map(JwtVerifier::new).transpose()?;
+        Ok(Self {
+            header_name,
+            tokens,
+            jwt_verifier,
+            allow_anonymous_read: config.allow_anonymous_read,
+        })
+    }
+
+    /// Returns the identity and access of the client that sent `headers`,
+    /// or `None` if it did not send a token.
+    pub fn authenticate(&self, headers: &HeaderMap) -> Result<Option<(String, Access)>, Error> {
+        let Some(value) = headers.get(&self.header_name) else {
+            return Ok(None);
+        };
+        let value = value
+            .to_str()
+            .map_err(|e| make_err!(Code::Unauthenticated, "Invalid auth header: {e:?}"))?;
+        let token = value.strip_prefix("Bearer ").unwrap_or(value).trim();
+        let token_hash: [u8; 32] = Sha256::digest(token.as_bytes()).into();
+        if let Some((identity, access)) = self.tokens.get(&token_hash) {
+            return Ok(Some((identity.clone(), *access)));
+        }
+        if let Some(jwt_verifier) = &self.jwt_verifier {
+            return jwt_verifier
+                .verify(token)
+                .map(|identity| Some((identity, Access::ReadWrite)));
+        }
+        Err(make_err!(Code::Unauthenticated, "Unknown token"))
+    }
+
+    /// Checks that a client may call the gRPC method at `path`. Returns the
+    /// identity of the client, if it sent a token.
+    pub fn authorize(&self, headers: &HeaderMap, path: &str) -> Result<Option<String>, Error> {
+        self.authorize_request(headers, !READ_ONLY_METHODS.contains(&path), path)
+    }
+
+    /// Checks that a client may send a request to `path`, which needs
+    /// read-write access if `writes` is set. Returns the identity of the
+    /// client, if it sent a token.
+    pub fn authorize_request(
+        &self,
+        headers: &HeaderMap,
+        writes: bool,
+        path: &str,
+    ) -> Result<Option<String>, Error> {
+        let (identity, access) = match self.authenticate(headers)? {
+            Some((identity, access)) => (Some(identity), access),
+            None if self.allow_anonymous_read => (None, Access::ReadOnly),
+            None => return Err(make_err!(Code::Unauthenticated, "A token is required")),
+        };
+        if access == Access::ReadOnly && writes {
+            return Err(make_err!(
+                Code::PermissionDenied,
+                "{} may not call {path}",
+                identity.as_deref().unwrap_or("Anonymous client")
+            ));
+        }
+        Ok(identity)
+    }
+}
+
+#[derive(Clone)]
+pub struct AuthMiddlewareLayer {
+    authenticator: Arc<Authenticator>,
+    read_only_requests: ReadOnlyRequests,
+}
+
+impl AuthMiddlewareLayer {
+    /// Returns the layer of gRPC services.
+    pub fn new(config: &AuthConfig) -> Result<Self, Error> {
+        Ok(Self {
+            authenticator: Arc::new(Authenticator::new(config)?),
+            read_only_requests: ReadOnlyRequests::GrpcMethods,
+        })
+    }
+
+    /// Returns the same layer for a service whose read-only requests are
+    /// `read_only_requests`.
+    #[must_use]
+    pub fn with_read_only_requests(&self, read_only_requests: ReadOnlyRequests) -> Self {
+        Self {
+            authenticator: self.authenticator.clone(),
+            read_only_requests,
+        }
+    }
+}
+
+impl<S> Layer<S> for AuthMiddlewareLayer {
+    type Service = AuthMiddleware<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        AuthMiddleware {
+            inner: service,
+            authenticator: self.authenticator.clone(),
+            read_only_requests: self.read_only_requests,
+        }
+    }
+}
+
+/// Rejects requests without valid credentials and sets the identity of the
+/// client in the origin context. It must be wrapped by the
+/// `OriginEventMiddleware`, otherwise the identity is overwritten.
+#[derive(Clone)]
+pub struct AuthMiddleware<S> {
+    inner: S,
+    authenticator: Arc<Authenticator>,
+    read_only_requests: ReadOnlyRequests,
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for AuthMiddleware<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    ReqBody: Send + 'static,
+    ResBody: From<String> + Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<ReqBody>) -> Self::Future {
+        // We must take the current `inner` and not the clone.
+        // See: https://docs.rs/tower/latest/tower/trait.Service.html#be-careful-when-cloning-inner-services
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        let path = req.uri().path();
+        let writes = !self.read_only_requests.is_read_only(req.method(), path);
+        let identity = match self
+            .authenticator
+            .authorize_request(req.headers(), writes, path)
+        {
+            Ok(identity) => identity,
+            Err(err) => {
+                let response = if is_grpc_request(req.headers()) {
+                    grpc_error_response(err)
+                } else {
+                    let status = if err.code == Code::PermissionDenied {
+                        StatusCode::FORBIDDEN
+                    } else {
+                        StatusCode::UNAUTHORIZED
+                    };
+                    http::Response::builder()
+                        .status(status)
+                        .body(err.message_string().into())
+                        .unwrap()
+                };
+                return Box::pin(async move { Ok(response) });
+            }
+        };
+        // Anonymous clients get the empty identity, so the identity they may
+        // have sent in the identity header is not kept.
+        let identity = identity.unwrap_or_default();
+        let mut context = ActiveOriginContext::fork().unwrap_or_default();
+        context.set_value(&ORIGIN_IDENTITY, Arc::new(identity.clone()));
+        context.set_value(&AUTHENTICATED_IDENTITY, Arc::new(identity.clone()));
+        Box::pin(async move {
+            // This runs in the request span of the `OriginEventMiddleware`,
+            // which only knows the identity from the identity header.
+            Span::current().record("identity", identity.as_str());
+            Arc::new(context)
+                .wrap_async(trace_span!("AuthMiddleware"), inner.call(req))
+                .await
+        })
+    }
+}
+
+fn is_grpc_request(headers: &HeaderMap) -> bool {
+    headers
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/grpc"))
+}
+
+/// A gRPC response without a message, which only has the status of `err`.
+/// gRPC clients treat other HTTP statuses as transport errors.
+fn grpc_error_response<ResBody: From<String>>(err: Error) -> http::Response<ResBody> {
+    let code = err.code;
+    let mut response = http::Response::new(String::new().into());
+    response.headers_mut().insert(
+        http::header::CONTENT_TYPE,
+        HeaderValue::from_static("application/grpc"),
+    );
+    // Only fails for messages that can not be percent-encoded, in which case
+    // the client still gets the code.
+    let status = tonic::Status::from(err);
+    if status.add_header(response.headers_mut()).is_err() {
+        response.headers_mut().insert(
+            "grpc-status",
+            HeaderValue::from(tonic::Code::from(code) as i32),
+        );
+    }
+    response
+}
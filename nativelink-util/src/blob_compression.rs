@@ -0,0 +1,230 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compression of blobs sent to and from clients, as described by the
+//! `compressed-blobs` resource names of the Remote Execution API.
+
+use std::io::{Read, Write};
+
+use bytes::Bytes;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use nativelink_error::{make_err, make_input_err, Code, Error, ResultExt};
+use nativelink_proto::build::bazel::remote::execution::v2::compressor;
+use zstd::stream::raw::{Decoder, Encoder, InBuffer, Operation, OutBuffer};
+
+use crate::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
+
+/// Compression level blobs are compressed with, same as the zstd command
+/// line tool.
+const ZSTD_COMPRESSION_LEVEL: i32 = 3;
+
+/// Size of the chunks the (de)compressed data is sent in.
+const OUTPUT_CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlobCompressor {
+    Identity,
+    Zstd,
+    Deflate,
+}
+
+impl BlobCompressor {
+    /// Compressors of `compressed-blobs` resource names, which are
+    /// (de)compressed as they are streamed.
+    pub const STREAM_COMPRESSORS: &'static [Self] = &[Self::Zstd];
+
+    /// Compressors of the blobs in `BatchReadBlobs` and `BatchUpdateBlobs`,
+    /// in the order they are preferred in.
+    pub const BATCH_COMPRESSORS: &'static [Self] = &[Self::Zstd, Self::Deflate];
+
+    /// Returns the compressor of a `Compressor.Value` of the Remote Execution
+    /// API.
+    pub fn from_proto(value: i32) -> Result<Self, Error> {
+        match compressor::Value::try_from(value) {
+            Ok(compressor::Value::Identity) => Ok(Self::Identity),
+            Ok(compressor::Value::Zstd) => Ok(Self::Zstd),
+            Ok(compressor::Value::Deflate) => Ok(Self::Deflate),
+            _ => Err(make_input_err!("Compressor {value} is not supported")),
+        }
+    }
+
+    pub fn to_proto(self) -> compressor::Value {
+        match self {
+            Self::Identity => compressor::Value::Identity,
+            Self::Zstd => compressor::Value::Zstd,
+            Self::Deflate => compressor::Value::Deflate,
+        }
+    }
+
+    /// Returns the compressor of a `compressed-blobs/{compressor}` resource
+    /// name, or `Identity` if the resource name has no compressor.
+    pub fn from_resource_name(compressor: Option<&str>) -> Result<Self, Error> {
+        match compressor {
+            None | Some("identity") => Ok(Self::Identity),
+            Some("zstd") => Ok(Self::Zstd),
+            Some(compressor) => Err(make_input_err!(
+                "Compressor '{compressor}' is not supported"
+            )),
+        }
+    }
+
+    /// Returns `data` compressed.
+    pub fn compress(self, data: Bytes) -> Result<Bytes, Error> {
+        match self {
+            Self::Identity => Ok(data),
+            Self::Zstd => zstd::bulk::compress(&data, ZSTD_COMPRESSION_LEVEL)
+                .map(Bytes::from)
+                .map_err(|e| make_err!(Code::Internal, "Failed to compress data: {e:?}")),
+            Self::Deflate => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                encoder
+                    .write_all(&data)
+                    .and_then(|()| encoder.finish())
+                    .map(Bytes::from)
+                    .map_err(|e| make_err!(Code::Internal, "Failed to compress data: {e:?}"))
+            }
+        }
+    }
+
+    /// Returns `data` decompressed. Fails if the decompressed data is not
+    /// exactly `size` bytes.
+    pub fn decompress(self, data: Bytes, size: usize) -> Result<Bytes, Error> {
+        let decompressed = match self {
+            Self::Identity => data,
+            Self::Zstd => zstd::bulk::decompress(&data, size)
+                .map(Bytes::from)
+                .map_err(|e| make_input_err!("Failed to decompress zstd data: {e:?}"))?,
+            Self::Deflate => {
+                let mut decompressed = Vec::with_capacity(size);
+                // Reading one byte more than expected detects larger data
+                // without decompressing all of it.
+                DeflateDecoder::new(data.as_ref())
+                    .take(size as u64 + 1)
+                    .read_to_end(&mut decompressed)
+                    .map_err(|e| make_input_err!("Failed to decompress deflate data: {e:?}"))?;
+                Bytes::from(decompressed)
+            }
+        };
+        if decompressed.len() != size {
+            return Err(make_input_err!(
+                "Decompressed data is {} bytes, expected {size} bytes",
+                decompressed.len()
+            ));
+        }
+        Ok(decompressed)
+    }
+
+    /// Reads uncompressed data from `reader`, compresses it and writes it to
+    /// `writer` followed by an EOF.
+    pub async fn compress_stream(
+        self,
+        reader: &mut DropCloserReadHalf,
+        writer: &mut DropCloserWriteHalf,
+    ) -> Result<(), Error> {
+        match self {
+            Self::Identity => return writer.bind_buffered(reader).await,
+            Self::Zstd => {}
+            Self::Deflate => return Err(make_input_err!("Deflate streams are not supported")),
+        }
+        let mut encoder = Encoder::new(ZSTD_COMPRESSION_LEVEL)
+            .map_err(|e| make_err!(Code::Internal, "Failed to create zstd encoder: {e:?}"))?;
+        loop {
+            let chunk = reader.recv().await.err_tip(|| "Reading data to compress")?;
+            if chunk.is_empty() {
+                break; // EOF.
+            }
+            let mut input = InBuffer::around(&chunk);
+            while input.pos() < chunk.len() {
+                let mut output = Vec::with_capacity(OUTPUT_CHUNK_SIZE);
+                encoder
+                    .run(&mut input, &mut OutBuffer::around(&mut output))
+                    .map_err(|e| make_err!(Code::Internal, "Failed to compress data: {e:?}"))?;
+                send_output(writer, output).await?;
+            }
+        }
+        loop {
+            let mut output = Vec::with_capacity(OUTPUT_CHUNK_SIZE);
+            let remaining = encoder
+                .finish(&mut OutBuffer::around(&mut output), true)
+                .map_err(|e| make_err!(Code::Internal, "Failed to compress data: {e:?}"))?;
+            send_output(writer, output).await?;
+            if remaining == 0 {
+                break;
+            }
+        }
+        writer
+            .send_eof()
+            .err_tip(|| "Sending EOF of compressed data")
+    }
+
+    /// Reads compressed data from `reader`, decompresses it and writes it to
+    /// `writer` followed by an EOF.
+    pub async fn decompress_stream(
+        self,
+        reader: &mut DropCloserReadHalf,
+        writer: &mut DropCloserWriteHalf,
+    ) -> Result<(), Error> {
+        match self {
+            Self::Identity => return writer.bind_buffered(reader).await,
+            Self::Zstd => {}
+            Self::Deflate => return Err(make_input_err!("Deflate streams are not supported")),
+        }
+        let mut decoder = Decoder::new()
+            .map_err(|e| make_err!(Code::Internal, "Failed to create zstd decoder: {e:?}"))?;
+        // The decoder returns 0 once it fully decoded and flushed a frame.
+        let mut frame_done = true;
+        loop {
+            let chunk = reader
+                .recv()
+                .await
+                .err_tip(|| "Reading data to decompress")?;
+            let mut input = InBuffer::around(&chunk);
+            // Output may still be buffered in the decoder after all input is
+            // consumed, so we keep going until it stops filling the output.
+            loop {
+                let mut output = Vec::with_capacity(OUTPUT_CHUNK_SIZE);
+                let hint = decoder
+                    .run(&mut input, &mut OutBuffer::around(&mut output))
+                    .map_err(|e| make_input_err!("Failed to decompress zstd data: {e:?}"))?;
+                frame_done = hint == 0;
+                let output_full = output.len() == output.capacity();
+                send_output(writer, output).await?;
+                if input.pos() == chunk.len() && !output_full {
+                    break;
+                }
+            }
+            if chunk.is_empty() {
+                break; // EOF.
+            }
+        }
+        if !frame_done {
+            return Err(make_input_err!("Compressed zstd data is truncated"));
+        }
+        writer
+            .send_eof()
+            .err_tip(|| "Sending EOF of decompressed data")
+    }
+}
+
+async fn send_output(writer: &mut DropCloserWriteHalf, output: Vec<u8>) -> Result<(), Error> {
+    if output.is_empty() {
+        return Ok(());
+    }
+    writer
+        .send(Bytes::from(output))
+        .await
+        .err_tip(|| "Sending (de)compressed data")
+}
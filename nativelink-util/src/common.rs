@@ -55,18 +55,32 @@ impl MetricsComponent for DigestInfo {
 
 impl DigestInfo {
     pub const fn new(packed_hash: [u8; 32], size_bytes: u64) -> Self {
+        Self::from_hash(packed_hash, size_bytes)
+    }
+
+    /// Like `new()`, but for hashes of any supported size, ie: the 64 byte
+    /// hashes of SHA-512.
+    pub const fn from_hash<const N: usize>(packed_hash: [u8; N], size_bytes: u64) -> Self {
         DigestInfo {
             size_bytes,
-            packed_hash: PackedHash(packed_hash),
+            packed_hash: PackedHash::from_array(packed_hash),
         }
     }
 
+    /// Like `from_hash()`, but for hashes whose size is only known at
+    /// runtime.
+    pub fn try_from_hash_bytes(packed_hash: &[u8], size_bytes: u64) -> Result<Self, Error> {
+        Ok(DigestInfo {
+            size_bytes,
+            packed_hash: PackedHash::from_bytes(packed_hash)?,
+        })
+    }
+
     pub fn try_new<T>(hash: &str, size_bytes: T) -> Result<Self, Error>
     where
         T: TryInto<u64> + std::fmt::Display + Copy,
     {
-        let packed_hash =
-            PackedHash::from_hex(hash).err_tip(|| format!("Invalid sha256 hash: {hash}"))?;
+        let packed_hash = PackedHash::from_hex(hash).err_tip(|| format!("Invalid hash: {hash}"))?;
         let size_bytes = size_bytes
             .try_into()
             .map_err(|_| make_input_err!("Could not convert {} into u64", size_bytes))?;
@@ -96,8 +110,8 @@ impl DigestInfo {
         &self.packed_hash
     }
 
-    pub fn set_packed_hash(&mut self, packed_hash: [u8; 32]) {
-        self.packed_hash = PackedHash(packed_hash);
+    pub fn set_packed_hash(&mut self, packed_hash: PackedHash) {
+        self.packed_hash = packed_hash;
     }
 
     pub const fn size_bytes(&self) -> u64 {
@@ -126,17 +140,17 @@ const fn count_digits(mut num: u64) -> usize {
 struct DigestStackStringifier<'a> {
     digest: &'a DigestInfo,
     /// Buffer that can hold the string representation of the `DigestInfo`.
-    /// - Hex is '2 * sizeof(PackedHash)'.
+    /// - Hex is at most '2 * MAX_PACKED_HASH_SIZE'.
     /// - Digits can be at most `count_digits(u64::MAX)`.
     /// - We also have a hyphen separator.
-    buf: [u8; std::mem::size_of::<PackedHash>() * 2 + count_digits(u64::MAX) + 1],
+    buf: [u8; MAX_PACKED_HASH_SIZE * 2 + count_digits(u64::MAX) + 1],
 }
 
 impl<'a> DigestStackStringifier<'a> {
     const fn new(digest: &'a DigestInfo) -> Self {
         DigestStackStringifier {
             digest,
-            buf: [b'-'; std::mem::size_of::<PackedHash>() * 2 + count_digits(u64::MAX) + 1],
+            buf: [b'-'; MAX_PACKED_HASH_SIZE * 2 + count_digits(u64::MAX) + 1],
         }
     }
 
@@ -145,14 +159,15 @@ impl<'a> DigestStackStringifier<'a> {
         // to the buffer.
         let len = {
             let mut cursor = Cursor::new(&mut self.buf[..]);
-            let hex = self.digest.packed_hash.to_hex().map_err(|e| {
+            let (hex, hex_len) = self.digest.packed_hash.to_hex().map_err(|e| {
                 make_input_err!(
                     "Could not convert PackedHash to hex - {e:?} - {:?}",
                     self.digest
                 )
             })?;
+            let hex = &hex[..hex_len];
             cursor
-                .write_all(&hex)
+                .write_all(hex)
                 .err_tip(|| format!("Could not write hex to buffer - {hex:?} - {hex:?}",))?;
             // Note: We already have a hyphen at this point because we
             // initialized the buffer with hyphens.
@@ -279,7 +294,7 @@ impl TryFrom<Digest> for DigestInfo {
 
     fn try_from(digest: Digest) -> Result<Self, Self::Error> {
         let packed_hash = PackedHash::from_hex(&digest.hash)
-            .err_tip(|| format!("Invalid sha256 hash: {}", digest.hash))?;
+            .err_tip(|| format!("Invalid hash: {}", digest.hash))?;
         let size_bytes = digest
             .size_bytes
             .try_into()
@@ -296,7 +311,7 @@ impl TryFrom<&Digest> for DigestInfo {
 
     fn try_from(digest: &Digest) -> Result<Self, Self::Error> {
         let packed_hash = PackedHash::from_hex(&digest.hash)
-            .err_tip(|| format!("Invalid sha256 hash: {}", digest.hash))?;
+            .err_tip(|| format!("Invalid hash: {}", digest.hash))?;
         let size_bytes = digest
             .size_bytes
             .try_into()
@@ -344,60 +359,130 @@ impl From<&DigestInfo> for Digest {
     }
 }
 
-#[derive(Serialize, Deserialize, Default, Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
-pub struct PackedHash([u8; 32]);
+/// Size in bytes of the largest supported hash, which is SHA-512.
+pub const MAX_PACKED_HASH_SIZE: usize = 64;
+
+/// Sizes in bytes of the hashes of the supported digest functions.
+const VALID_PACKED_HASH_SIZES: [usize; 3] = [32, 48, 64];
+
+/// A raw hash of 32 (SHA-256, BLAKE3), 48 (SHA-384) or 64 (SHA-512) bytes.
+/// The bytes after the hash are always zero.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct PackedHash {
+    bytes: [u8; MAX_PACKED_HASH_SIZE],
+    len: u8,
+}
 
 const SIZE_OF_PACKED_HASH: usize = 32;
 impl PackedHash {
     const fn new() -> Self {
-        PackedHash([0; SIZE_OF_PACKED_HASH])
+        Self::from_array([0; SIZE_OF_PACKED_HASH])
+    }
+
+    const fn from_array<const N: usize>(hash: [u8; N]) -> Self {
+        assert!(
+            N <= MAX_PACKED_HASH_SIZE,
+            "Hash is too large for PackedHash"
+        );
+        let mut bytes = [0u8; MAX_PACKED_HASH_SIZE];
+        let mut i = 0;
+        while i < N {
+            bytes[i] = hash[i];
+            i += 1;
+        }
+        PackedHash {
+            bytes,
+            len: N as u8,
+        }
+    }
+
+    fn from_bytes(hash: &[u8]) -> Result<Self, Error> {
+        if !VALID_PACKED_HASH_SIZES.contains(&hash.len()) {
+            return Err(make_input_err!(
+                "Hash is {} bytes, expected one of {VALID_PACKED_HASH_SIZES:?} bytes",
+                hash.len()
+            ));
+        }
+        let mut bytes = [0u8; MAX_PACKED_HASH_SIZE];
+        bytes[..hash.len()].copy_from_slice(hash);
+        Ok(PackedHash {
+            bytes,
+            len: hash.len() as u8,
+        })
     }
 
     fn from_hex(hash: &str) -> Result<Self, Error> {
-        let mut packed_hash = [0u8; 32];
-        hex::decode_to_slice(hash, &mut packed_hash)
-            .map_err(|e| make_input_err!("Invalid sha256 hash: {hash} - {e:?}"))?;
-        Ok(PackedHash(packed_hash))
+        let len = hash.len() / 2;
+        if hash.len() % 2 != 0 || !VALID_PACKED_HASH_SIZES.contains(&len) {
+            return Err(make_input_err!(
+                "Invalid hash: {hash} - expected {:?} hex characters, got {}",
+                VALID_PACKED_HASH_SIZES.map(|size| size * 2),
+                hash.len()
+            ));
+        }
+        let mut bytes = [0u8; MAX_PACKED_HASH_SIZE];
+        hex::decode_to_slice(hash, &mut bytes[..len])
+            .map_err(|e| make_input_err!("Invalid hash: {hash} - {e:?}"))?;
+        Ok(PackedHash {
+            bytes,
+            len: len as u8,
+        })
     }
 
-    /// Converts the packed hash into a hex string.
+    /// Converts the packed hash into a hex string. The first returned
+    /// value holds the hex characters up to the second returned value.
     #[inline]
-    fn to_hex(self) -> Result<[u8; SIZE_OF_PACKED_HASH * 2], fmt::Error> {
-        let mut hash = [0u8; SIZE_OF_PACKED_HASH * 2];
-        hex::encode_to_slice(self.0, &mut hash).map_err(|e| {
+    fn to_hex(self) -> Result<([u8; MAX_PACKED_HASH_SIZE * 2], usize), fmt::Error> {
+        let mut hash = [0u8; MAX_PACKED_HASH_SIZE * 2];
+        let hex_len = self.len() * 2;
+        hex::encode_to_slice(&*self, &mut hash[..hex_len]).map_err(|e| {
             event!(
                 Level::ERROR,
                 "Could not convert PackedHash to hex - {e:?} - {:?}",
-                self.0
+                &*self
             );
             fmt::Error
         })?;
-        Ok(hash)
+        Ok((hash, hex_len))
+    }
+}
+
+impl Default for PackedHash {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl fmt::Display for PackedHash {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let hash = self.to_hex()?;
-        match std::str::from_utf8(&hash) {
+        let (hash, hex_len) = self.to_hex()?;
+        match std::str::from_utf8(&hash[..hex_len]) {
             Ok(hash) => f.write_str(hash)?,
-            Err(_) => f.write_str(&format!("Could not convert hash to utf8 {:?}", self.0))?,
+            Err(_) => f.write_str(&format!("Could not convert hash to utf8 {:?}", &**self))?,
         }
         Ok(())
     }
 }
 
+impl fmt::Debug for PackedHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PackedHash")
+            .field(&format!("{self}"))
+            .finish()
+    }
+}
+
 impl Deref for PackedHash {
-    type Target = [u8; 32];
+    type Target = [u8];
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.bytes[..usize::from(self.len)]
     }
 }
 
 impl DerefMut for PackedHash {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        &mut self.bytes[..usize::from(self.len)]
     }
 }
 
@@ -28,6 +28,7 @@ use tracing::{event, Level};
 
 use crate::background_spawn;
 use crate::retry::{self, Retrier, RetryResult};
+use crate::telemetry::inject_trace_context;
 
 /// A helper utility that enables management of a suite of connections to an
 /// upstream gRPC endpoint using Tonic.
@@ -465,8 +466,10 @@ impl tonic::codegen::Service<tonic::codegen::http::Request<tonic::body::BoxBody>
 
     fn call(
         &mut self,
-        request: tonic::codegen::http::Request<tonic::body::BoxBody>,
+        mut request: tonic::codegen::http::Request<tonic::body::BoxBody>,
     ) -> Self::Future {
+        // Continue the trace of the caller in the process serving the request.
+        inject_trace_context(request.headers_mut());
         ResponseFuture {
             inner: self.channel.channel.call(request),
             connection_tx: self.connection_tx.clone(),
@@ -12,7 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::sync::{Arc, OnceLock};
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, OnceLock};
 
 use blake3::Hasher as Blake3Hasher;
 use bytes::BytesMut;
@@ -23,12 +24,13 @@ use nativelink_metric::{
     MetricFieldData, MetricKind, MetricPublishKnownKindData, MetricsComponent,
 };
 use nativelink_proto::build::bazel::remote::execution::v2::digest_function::Value as ProtoDigestFunction;
+use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
+use sha2::{Digest, Sha256, Sha384, Sha512};
 use tokio::io::{AsyncRead, AsyncReadExt};
 
 use crate::common::DigestInfo;
-use crate::origin_context::{ActiveOriginContext, OriginContext};
+use crate::origin_context::{ActiveOriginContext, OriginContext, ACTIVE_INSTANCE_NAME};
 use crate::{fs, make_symbol, spawn_blocking};
 
 // The symbol can be used to retrieve the active hasher function.
@@ -37,6 +39,9 @@ make_symbol!(ACTIVE_HASHER_FUNC, DigestHasherFunc);
 
 static DEFAULT_DIGEST_HASHER_FUNC: OnceLock<DigestHasherFunc> = OnceLock::new();
 
+static INSTANCE_DIGEST_HASHER_FUNCS: LazyLock<RwLock<HashMap<String, InstanceDigestHasherFuncs>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
 /// Utility function to make a context with a specific hasher function set.
 pub fn make_ctx_for_hash_func<H>(hasher: H) -> Result<Arc<OriginContext>, Error>
 where
@@ -52,6 +57,82 @@ where
     Ok(Arc::new(new_ctx))
 }
 
+/// Utility function to make a context with the hasher function requested
+/// for `instance_name` and the instance name itself set. `digest_function`
+/// is the proto value of the request, where zero means the default of the
+/// instance.
+pub fn make_ctx_for_instance_hash_func(
+    instance_name: &str,
+    digest_function: i32,
+) -> Result<Arc<OriginContext>, Error> {
+    let requested = if digest_function == 0 {
+        None
+    } else {
+        Some(DigestHasherFunc::try_from(digest_function)?)
+    };
+    let digest_hasher_func = resolve_digest_hasher_func(instance_name, requested)?;
+    let mut new_ctx =
+        ActiveOriginContext::fork().err_tip(|| "In make_ctx_for_instance_hash_func")?;
+    new_ctx.set_value(&ACTIVE_HASHER_FUNC, Arc::new(digest_hasher_func));
+    new_ctx.set_value(&ACTIVE_INSTANCE_NAME, Arc::new(instance_name.to_string()));
+    Ok(Arc::new(new_ctx))
+}
+
+/// The digest hash functions accepted by an instance name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InstanceDigestHasherFuncs {
+    /// Used for requests that don't name a digest function.
+    pub default: DigestHasherFunc,
+    /// Every digest function requests may name, including `default`.
+    pub supported: Vec<DigestHasherFunc>,
+}
+
+impl Default for InstanceDigestHasherFuncs {
+    fn default() -> Self {
+        Self {
+            default: default_digest_hasher_func(),
+            supported: DigestHasherFunc::ALL.to_vec(),
+        }
+    }
+}
+
+/// Sets the digest hash functions accepted by `instance_name`. Instance
+/// names that were never set accept every digest hash function and use
+/// `default_digest_hasher_func()` by default.
+pub fn set_instance_digest_hasher_funcs(instance_name: &str, funcs: InstanceDigestHasherFuncs) {
+    INSTANCE_DIGEST_HASHER_FUNCS
+        .write()
+        .insert(instance_name.to_string(), funcs);
+}
+
+/// Get the digest hash functions accepted by `instance_name`.
+pub fn instance_digest_hasher_funcs(instance_name: &str) -> InstanceDigestHasherFuncs {
+    INSTANCE_DIGEST_HASHER_FUNCS
+        .read()
+        .get(instance_name)
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Returns the hasher to use for a request to `instance_name` that asked
+/// for `requested`, or the default of the instance if it asked for none.
+pub fn resolve_digest_hasher_func(
+    instance_name: &str,
+    requested: Option<DigestHasherFunc>,
+) -> Result<DigestHasherFunc, Error> {
+    let funcs = instance_digest_hasher_funcs(instance_name);
+    let Some(requested) = requested else {
+        return Ok(funcs.default);
+    };
+    if !funcs.supported.contains(&requested) {
+        return Err(make_input_err!(
+            "Digest function {requested} is not supported by instance '{instance_name}', supported are {:?}",
+            funcs.supported
+        ));
+    }
+    Ok(requested)
+}
+
 /// Get the default hasher.
 pub fn default_digest_hasher_func() -> DigestHasherFunc {
     *DEFAULT_DIGEST_HASHER_FUNC.get_or_init(|| DigestHasherFunc::Sha256)
@@ -69,6 +150,8 @@ pub fn set_default_digest_hasher_func(hasher: DigestHasherFunc) -> Result<(), Er
 pub enum DigestHasherFunc {
     Sha256,
     Blake3,
+    Sha384,
+    Sha512,
 }
 
 impl MetricsComponent for DigestHasherFunc {
@@ -82,6 +165,9 @@ impl MetricsComponent for DigestHasherFunc {
 }
 
 impl DigestHasherFunc {
+    /// Every supported digest hash function.
+    pub const ALL: [Self; 4] = [Self::Sha256, Self::Blake3, Self::Sha384, Self::Sha512];
+
     pub fn hasher(&self) -> DigestHasherImpl {
         self.into()
     }
@@ -91,7 +177,32 @@ impl DigestHasherFunc {
         match self {
             Self::Sha256 => ProtoDigestFunction::Sha256,
             Self::Blake3 => ProtoDigestFunction::Blake3,
+            Self::Sha384 => ProtoDigestFunction::Sha384,
+            Self::Sha512 => ProtoDigestFunction::Sha512,
+        }
+    }
+
+    /// Size in bytes of the hashes of this function.
+    #[must_use]
+    pub const fn hash_size(&self) -> usize {
+        match self {
+            Self::Sha256 | Self::Blake3 => 32,
+            Self::Sha384 => 48,
+            Self::Sha512 => 64,
+        }
+    }
+
+    /// Returns an error if `digest` can not have been made by this
+    /// function, because its hash has a different size.
+    pub fn check_digest(&self, digest: &DigestInfo) -> Result<(), Error> {
+        let hash_size = digest.packed_hash().len();
+        if hash_size != self.hash_size() {
+            return Err(make_input_err!(
+                "Digest {digest} has a {hash_size} byte hash, but digest function {self} makes {} byte hashes",
+                self.hash_size()
+            ));
         }
+        Ok(())
     }
 }
 
@@ -100,6 +211,8 @@ impl From<ConfigDigestHashFunction> for DigestHasherFunc {
         match value {
             ConfigDigestHashFunction::sha256 => Self::Sha256,
             ConfigDigestHashFunction::blake3 => Self::Blake3,
+            ConfigDigestHashFunction::sha384 => Self::Sha384,
+            ConfigDigestHashFunction::sha512 => Self::Sha512,
         }
     }
 }
@@ -111,6 +224,8 @@ impl TryFrom<ProtoDigestFunction> for DigestHasherFunc {
         match value {
             ProtoDigestFunction::Sha256 => Ok(Self::Sha256),
             ProtoDigestFunction::Blake3 => Ok(Self::Blake3),
+            ProtoDigestFunction::Sha384 => Ok(Self::Sha384),
+            ProtoDigestFunction::Sha512 => Ok(Self::Sha512),
             v => Err(make_input_err!(
                 "Unknown or unsupported digest function for proto conversion {v:?}"
             )),
@@ -125,6 +240,8 @@ impl TryFrom<&str> for DigestHasherFunc {
         match value.to_uppercase().as_str() {
             "SHA256" => Ok(Self::Sha256),
             "BLAKE3" => Ok(Self::Blake3),
+            "SHA384" => Ok(Self::Sha384),
+            "SHA512" => Ok(Self::Sha512),
             v => Err(make_input_err!(
                 "Unknown or unsupported digest function for string conversion: {v:?}"
             )),
@@ -137,6 +254,8 @@ impl std::fmt::Display for DigestHasherFunc {
         match self {
             DigestHasherFunc::Sha256 => write!(f, "SHA256"),
             DigestHasherFunc::Blake3 => write!(f, "BLAKE3"),
+            DigestHasherFunc::Sha384 => write!(f, "SHA384"),
+            DigestHasherFunc::Sha512 => write!(f, "SHA512"),
         }
     }
 }
@@ -152,6 +271,8 @@ impl TryFrom<i32> for DigestHasherFunc {
         match ProtoDigestFunction::try_from(value) {
             Ok(ProtoDigestFunction::Sha256) => Ok(Self::Sha256),
             Ok(ProtoDigestFunction::Blake3) => Ok(Self::Blake3),
+            Ok(ProtoDigestFunction::Sha384) => Ok(Self::Sha384),
+            Ok(ProtoDigestFunction::Sha512) => Ok(Self::Sha512),
             value => Err(make_input_err!(
                 "Unknown or unsupported digest function for int conversion: {:?}",
                 value.map(|v| v.as_str_name())
@@ -165,6 +286,8 @@ impl From<&DigestHasherFunc> for DigestHasherImpl {
         let hash_func_impl = match value {
             DigestHasherFunc::Sha256 => DigestHasherFuncImpl::Sha256(Sha256::new()),
             DigestHasherFunc::Blake3 => DigestHasherFuncImpl::Blake3(Box::new(Blake3Hasher::new())),
+            DigestHasherFunc::Sha384 => DigestHasherFuncImpl::Sha384(Sha384::new()),
+            DigestHasherFunc::Sha512 => DigestHasherFuncImpl::Sha512(Sha512::new()),
         };
         Self {
             hashed_size: 0,
@@ -217,6 +340,8 @@ pub trait DigestHasher {
 pub enum DigestHasherFuncImpl {
     Sha256(Sha256),
     Blake3(Box<Blake3Hasher>), // Box because Blake3Hasher is 1.3kb in size.
+    Sha384(Sha384),
+    Sha512(Sha512),
 }
 
 /// The individual implementation of the hash function.
@@ -246,6 +371,8 @@ impl DigestHasher for DigestHasherImpl {
         self.hashed_size += input.len() as u64;
         match &mut self.hash_func_impl {
             DigestHasherFuncImpl::Sha256(h) => sha2::digest::Update::update(h, input),
+            DigestHasherFuncImpl::Sha384(h) => sha2::digest::Update::update(h, input),
+            DigestHasherFuncImpl::Sha512(h) => sha2::digest::Update::update(h, input),
             DigestHasherFuncImpl::Blake3(h) => {
                 Blake3Hasher::update(h, input);
             }
@@ -254,11 +381,24 @@ impl DigestHasher for DigestHasherImpl {
 
     #[inline]
     fn finalize_digest(&mut self) -> DigestInfo {
-        let hash = match &mut self.hash_func_impl {
-            DigestHasherFuncImpl::Sha256(h) => h.finalize_reset().into(),
-            DigestHasherFuncImpl::Blake3(h) => h.finalize().into(),
-        };
-        DigestInfo::new(hash, self.hashed_size)
+        match &mut self.hash_func_impl {
+            DigestHasherFuncImpl::Sha256(h) => {
+                DigestInfo::new(h.finalize_reset().into(), self.hashed_size)
+            }
+            DigestHasherFuncImpl::Blake3(h) => {
+                DigestInfo::new(h.finalize().into(), self.hashed_size)
+            }
+            DigestHasherFuncImpl::Sha384(h) => {
+                let mut hash = [0u8; 48];
+                hash.copy_from_slice(&h.finalize_reset());
+                DigestInfo::from_hash(hash, self.hashed_size)
+            }
+            DigestHasherFuncImpl::Sha512(h) => {
+                let mut hash = [0u8; 64];
+                hash.copy_from_slice(&h.finalize_reset());
+                DigestInfo::from_hash(hash, self.hashed_size)
+            }
+        }
     }
 
     async fn digest_for_file(
@@ -281,7 +421,9 @@ impl DigestHasher for DigestHasherImpl {
             }
         }
         match self.hash_func_impl {
-            DigestHasherFuncImpl::Sha256(_) => self.hash_file(file).await,
+            DigestHasherFuncImpl::Sha256(_)
+            | DigestHasherFuncImpl::Sha384(_)
+            | DigestHasherFuncImpl::Sha512(_) => self.hash_file(file).await,
             DigestHasherFuncImpl::Blake3(mut hasher) => {
                 spawn_blocking!("digest_for_file", move || {
                     hasher.update_mmap(file.get_path()).map_err(|e| {
@@ -14,32 +14,59 @@
 
 use std::borrow::Borrow;
 use std::cmp::Eq;
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::Debug;
 use std::future::Future;
 use std::hash::Hash;
 use std::ops::RangeBounds;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_lock::Mutex;
 use lru::LruCache;
-use nativelink_config::stores::EvictionPolicy;
+use nativelink_config::stores::{EvictionAlgorithm, EvictionPolicy};
+use nativelink_error::{make_input_err, Error};
 use nativelink_metric::MetricsComponent;
 use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
 use tracing::{event, Level};
 
+use crate::background_spawn;
 use crate::instant_wrapper::InstantWrapper;
 use crate::metrics_utils::{Counter, CounterWithTime};
 
+/// How often a paced background eviction task evicts a batch of entries.
+const BACKGROUND_EVICTION_TICK: Duration = Duration::from_millis(100);
+
+/// Default percentage of `max_bytes` reserved for the protected segment of
+/// `EvictionAlgorithm::segmented_lru`.
+const DEFAULT_PROTECTED_PERCENT: u32 = 80;
+
+/// Segment of the entries that were not accessed since they were inserted
+/// with `EvictionAlgorithm::segmented_lru`.
+const PROBATIONARY_SEGMENT: u64 = 0;
+/// Segment of the entries that were accessed since they were inserted with
+/// `EvictionAlgorithm::segmented_lru`.
+const PROTECTED_SEGMENT: u64 = 1;
+
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct SerializedLRU<K> {
     pub data: Vec<(K, i32)>,
     pub anchor_time: u64,
 }
 
+/// Position of an entry in the eviction order of the algorithms that don't
+/// use the LRU order directly. Entries with the lowest rank are evicted
+/// first. The first value is the access count with `lfu` and the segment
+/// with `segmented_lru`, the second value is unique and increases with
+/// every access.
+type EvictionRank = (u64, u64);
+
 #[derive(Debug)]
 struct EvictionItem<T: LenEntry + Debug> {
     seconds_since_anchor: i32,
+    rank: EvictionRank,
     data: T,
 }
 
@@ -97,10 +124,27 @@ impl<T: LenEntry + Send + Sync> LenEntry for Arc<T> {
     }
 }
 
+/// Copy of the size of the map that can be read without taking the lock.
+#[derive(Default)]
+struct SizeSnapshot {
+    size_bytes: AtomicU64,
+    len: AtomicU64,
+}
+
 #[derive(MetricsComponent)]
 struct State<K: Ord + Hash + Eq + Clone + Debug + Send, T: LenEntry + Debug + Send> {
     lru: LruCache<K, EvictionItem<T>>,
     btree: Option<BTreeSet<K>>,
+    algorithm: EvictionAlgorithm,
+    /// Eviction order for `lfu` and `segmented_lru`. Empty for the other
+    /// algorithms, which evict in the order of `lru`.
+    ranks: BTreeMap<EvictionRank, K>,
+    next_rank: u64,
+    #[metric(help = "Total size of the items in the protected segment")]
+    protected_bytes: u64,
+    #[metric(help = "Maximum size of the protected segment")]
+    max_protected_bytes: u64,
+    snapshot: Arc<SizeSnapshot>,
     #[metric(help = "Total size of all items in the store")]
     sum_store_size: u64,
 
@@ -114,6 +158,8 @@ struct State<K: Ord + Hash + Eq + Clone + Debug + Send, T: LenEntry + Debug + Se
     replaced_items: CounterWithTime,
     #[metric(help = "Number of bytes inserted into the store since it was created")]
     lifetime_inserted_bytes: Counter,
+    #[metric(help = "Number of bytes evicted from the store by the background task")]
+    background_evicted_bytes: Counter,
 }
 
 impl<K: Ord + Hash + Eq + Clone + Debug + Send + Sync, T: LenEntry + Debug + Sync + Send>
@@ -128,7 +174,16 @@ impl<K: Ord + Hash + Eq + Clone + Debug + Send + Sync, T: LenEntry + Debug + Syn
         if let Some(btree) = &mut self.btree {
             btree.remove(key.borrow());
         }
+        if self.uses_ranks() {
+            self.ranks.remove(&eviction_item.rank);
+            if self.algorithm == EvictionAlgorithm::segmented_lru
+                && eviction_item.rank.0 == PROTECTED_SEGMENT
+            {
+                self.protected_bytes -= eviction_item.data.len();
+            }
+        }
         self.sum_store_size -= eviction_item.data.len();
+        self.update_snapshot();
         if replaced {
             self.replaced_items.inc();
             self.replaced_bytes.add(eviction_item.data.len());
@@ -146,12 +201,144 @@ impl<K: Ord + Hash + Eq + Clone + Debug + Send + Sync, T: LenEntry + Debug + Syn
         if let Some(btree) = &mut self.btree {
             btree.insert(key.clone());
         }
+        if self.uses_ranks() {
+            self.ranks.insert(eviction_item.rank, key.clone());
+        }
         if let Some(old_item) = self.lru.put(key.clone(), eviction_item) {
             self.remove(&key, &old_item, true).await;
             return Some(old_item.data);
         }
         None
     }
+
+    fn update_snapshot(&self) {
+        self.snapshot
+            .size_bytes
+            .store(self.sum_store_size, Ordering::Relaxed);
+        self.snapshot
+            .len
+            .store(self.lru.len() as u64, Ordering::Relaxed);
+    }
+
+    fn uses_ranks(&self) -> bool {
+        matches!(
+            self.algorithm,
+            EvictionAlgorithm::lfu | EvictionAlgorithm::segmented_lru
+        )
+    }
+
+    fn take_next_rank(&mut self) -> u64 {
+        self.next_rank += 1;
+        self.next_rank
+    }
+
+    /// Rank of an item that is inserted into the cache. With `lfu`, new
+    /// items start with the access count of the least frequently used item,
+    /// so they are not the first to be evicted right after the insert.
+    fn insert_rank(&mut self) -> EvictionRank {
+        let first = match self.algorithm {
+            EvictionAlgorithm::lfu => self
+                .ranks
+                .first_key_value()
+                .map_or(1, |((access_count, _), _)| *access_count),
+            _ => PROBATIONARY_SEGMENT,
+        };
+        (first, self.take_next_rank())
+    }
+
+    /// Returns the item that will be evicted next.
+    fn peek_victim(&self) -> Option<&EvictionItem<T>> {
+        if !self.uses_ranks() {
+            return self.lru.peek_lru().map(|(_, item)| item);
+        }
+        let (_, key) = self.ranks.first_key_value()?;
+        self.lru.peek(key)
+    }
+
+    /// Removes the item that should be evicted next from the cache. The
+    /// caller is responsible for calling `remove()` on the returned item.
+    fn pop_victim(&mut self) -> Option<(K, EvictionItem<T>)> {
+        if !self.uses_ranks() {
+            return self.lru.pop_lru();
+        }
+        let (_, key) = self.ranks.first_key_value()?;
+        let key = key.clone();
+        self.lru.pop_entry(&key)
+    }
+
+    /// Moves an item that was just accessed in the eviction order. The LRU
+    /// order is already updated by `LruCache::get_mut()`.
+    fn record_access<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Ord + Hash + Eq + Debug + Sync,
+    {
+        if !self.uses_ranks() {
+            return;
+        }
+        let next_rank = self.take_next_rank();
+        let Some(item) = self.lru.peek_mut(key) else {
+            return;
+        };
+        let old_rank = item.rank;
+        let item_len = item.data.len();
+        item.rank = match self.algorithm {
+            EvictionAlgorithm::lfu => (old_rank.0 + 1, next_rank),
+            _ => (PROTECTED_SEGMENT, next_rank),
+        };
+        let new_rank = item.rank;
+        if let Some(key) = self.ranks.remove(&old_rank) {
+            self.ranks.insert(new_rank, key);
+        }
+        if self.algorithm == EvictionAlgorithm::segmented_lru && old_rank.0 == PROBATIONARY_SEGMENT
+        {
+            self.protected_bytes += item_len;
+            self.demote_protected_overflow();
+        }
+    }
+
+    /// Moves the least recently used items of the protected segment back to
+    /// the probationary segment until the protected segment fits.
+    fn demote_protected_overflow(&mut self) {
+        while self.protected_bytes > self.max_protected_bytes {
+            let Some((&old_rank, _)) = self.ranks.range((PROTECTED_SEGMENT, 0)..).next() else {
+                return;
+            };
+            let key = self.ranks.remove(&old_rank).expect("Rank was just found");
+            let new_rank = (PROBATIONARY_SEGMENT, self.take_next_rank());
+            let item = self
+                .lru
+                .peek_mut(&key)
+                .expect("Ranked item must be in the cache");
+            item.rank = new_rank;
+            self.protected_bytes -= item.data.len();
+            self.ranks.insert(new_rank, key);
+        }
+    }
+}
+
+/// Maximum size of the protected segment of `EvictionAlgorithm::segmented_lru`.
+fn max_protected_bytes(config: &EvictionPolicy) -> u64 {
+    if config.max_bytes == 0 {
+        return u64::MAX;
+    }
+    let protected_percent = if config.protected_percent == 0 {
+        DEFAULT_PROTECTED_PERCENT
+    } else {
+        config.protected_percent.min(100)
+    };
+    config.max_bytes as u64 * u64::from(protected_percent) / 100
+}
+
+/// Size at which background eviction stops.
+fn low_watermark_bytes(config: &EvictionPolicy) -> u64 {
+    if config.low_watermark_bytes == 0 {
+        config
+            .high_watermark_bytes
+            .saturating_sub(config.evict_bytes) as u64
+    } else {
+        config.low_watermark_bytes.min(config.high_watermark_bytes) as u64
+    }
 }
 
 #[derive(MetricsComponent)]
@@ -163,14 +350,24 @@ pub struct EvictingMap<
     #[metric]
     state: Mutex<State<K, T>>,
     anchor_time: I,
+    // The limits are atomics, so they can be changed by `set_policy()`
+    // while the map is in use.
     #[metric(help = "Maximum size of the store in bytes")]
-    max_bytes: u64,
+    max_bytes: AtomicU64,
     #[metric(help = "Number of bytes to evict when the store is full")]
-    evict_bytes: u64,
+    evict_bytes: AtomicU64,
     #[metric(help = "Maximum number of seconds to keep an item in the store")]
-    max_seconds: i32,
+    max_seconds: AtomicU64,
     #[metric(help = "Maximum number of items to keep in the store")]
-    max_count: u64,
+    max_count: AtomicU64,
+    #[metric(help = "Size of the store in bytes at which background eviction starts")]
+    high_watermark_bytes: AtomicU64,
+    #[metric(help = "Size of the store in bytes at which background eviction stops")]
+    low_watermark_bytes: AtomicU64,
+    #[metric(help = "Maximum number of bytes evicted per second by the background task")]
+    eviction_bytes_per_second: AtomicU64,
+    background_eviction_notify: Arc<Notify>,
+    snapshot: Arc<SizeSnapshot>,
 }
 
 impl<K, T, I> EvictingMap<K, T, I>
@@ -180,24 +377,211 @@ where
     I: InstantWrapper,
 {
     pub fn new(config: &EvictionPolicy, anchor_time: I) -> Self {
+        let snapshot = Arc::new(SizeSnapshot::default());
         EvictingMap {
             // We use unbounded because if we use the bounded version we can't call the delete
             // function on the LenEntry properly.
             state: Mutex::new(State {
                 lru: LruCache::unbounded(),
                 btree: None,
+                algorithm: config.algorithm,
+                ranks: BTreeMap::new(),
+                next_rank: 0,
+                protected_bytes: 0,
+                max_protected_bytes: max_protected_bytes(config),
+                snapshot: snapshot.clone(),
                 sum_store_size: 0,
                 evicted_bytes: Counter::default(),
                 evicted_items: CounterWithTime::default(),
                 replaced_bytes: Counter::default(),
                 replaced_items: CounterWithTime::default(),
                 lifetime_inserted_bytes: Counter::default(),
+                background_evicted_bytes: Counter::default(),
             }),
             anchor_time,
-            max_bytes: config.max_bytes as u64,
-            evict_bytes: config.evict_bytes as u64,
-            max_seconds: config.max_seconds as i32,
-            max_count: config.max_count,
+            max_bytes: AtomicU64::new(config.max_bytes as u64),
+            evict_bytes: AtomicU64::new(config.evict_bytes as u64),
+            max_seconds: AtomicU64::new(u64::from(config.max_seconds)),
+            max_count: AtomicU64::new(config.max_count),
+            high_watermark_bytes: AtomicU64::new(config.high_watermark_bytes as u64),
+            low_watermark_bytes: AtomicU64::new(low_watermark_bytes(config)),
+            eviction_bytes_per_second: AtomicU64::new(config.eviction_bytes_per_second as u64),
+            background_eviction_notify: Arc::new(Notify::new()),
+            snapshot,
+        }
+    }
+
+    /// Checks that `config` can be applied with [`EvictingMap::set_policy`]
+    /// without changing anything.
+    pub async fn check_policy(&self, config: &EvictionPolicy) -> Result<(), Error> {
+        let state = self.state.lock().await;
+        self.check_policy_locked(&state, config)
+    }
+
+    fn check_policy_locked(
+        &self,
+        state: &State<K, T>,
+        config: &EvictionPolicy,
+    ) -> Result<(), Error> {
+        if config.algorithm != state.algorithm {
+            return Err(make_input_err!(
+                "Eviction algorithm can't be changed from {:?} to {:?} while running",
+                state.algorithm,
+                config.algorithm
+            ));
+        }
+        if self.high_watermark_bytes.load(Ordering::Relaxed) == 0
+            && config.high_watermark_bytes != 0
+        {
+            return Err(make_input_err!(
+                "Background eviction can't be enabled while running"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Applies the limits of `config` to the map, evicting items right away
+    /// if the map no longer fits them. Changing the eviction algorithm or
+    /// enabling background eviction requires a new map.
+    pub async fn set_policy(&self, config: &EvictionPolicy) -> Result<(), Error> {
+        let mut state = self.state.lock().await;
+        self.check_policy_locked(&state, config)?;
+        self.max_bytes
+            .store(config.max_bytes as u64, Ordering::Relaxed);
+        self.evict_bytes
+            .store(config.evict_bytes as u64, Ordering::Relaxed);
+        self.max_seconds
+            .store(u64::from(config.max_seconds), Ordering::Relaxed);
+        self.max_count.store(config.max_count, Ordering::Relaxed);
+        self.high_watermark_bytes
+            .store(config.high_watermark_bytes as u64, Ordering::Relaxed);
+        self.low_watermark_bytes
+            .store(low_watermark_bytes(config), Ordering::Relaxed);
+        self.eviction_bytes_per_second
+            .store(config.eviction_bytes_per_second as u64, Ordering::Relaxed);
+        state.max_protected_bytes = max_protected_bytes(config);
+        state.demote_protected_overflow();
+        self.evict_items(&mut state).await;
+        self.notify_background_eviction_if_needed(&state);
+        Ok(())
+    }
+
+    /// Total size of the items in the map, read without taking the lock,
+    /// so it may lag behind concurrent inserts and removals.
+    pub fn approximate_size_bytes(&self) -> u64 {
+        self.snapshot.size_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Number of items in the map, read without taking the lock, so it may
+    /// lag behind concurrent inserts and removals.
+    pub fn approximate_len(&self) -> u64 {
+        self.snapshot.len.load(Ordering::Relaxed)
+    }
+
+    /// Evicts items in eviction order until at least `bytes` were evicted
+    /// or the map is empty. Every item counts as its length plus
+    /// `entry_overhead_bytes`. Returns the number of bytes that were evicted.
+    pub async fn evict_bytes(&self, bytes: u64, entry_overhead_bytes: u64) -> u64 {
+        let mut state = self.state.lock().await;
+        Self::pop_bytes(
+            &mut state,
+            bytes,
+            entry_overhead_bytes,
+            "Evicting to free memory",
+        )
+        .await
+    }
+
+    /// Evicts the items that are expired or don't fit the limits, like an
+    /// insert would, then at least `bytes` more in eviction order. Returns
+    /// the number of bytes that were evicted.
+    pub async fn run_eviction(&self, bytes: u64) -> u64 {
+        let mut state = self.state.lock().await;
+        let size_before = state.sum_store_size;
+        self.evict_items(&mut state).await;
+        let evicted_bytes = size_before.saturating_sub(state.sum_store_size);
+        evicted_bytes + Self::pop_bytes(&mut state, bytes, 0, "Evicting on request").await
+    }
+
+    async fn pop_bytes(
+        state: &mut State<K, T>,
+        bytes: u64,
+        entry_overhead_bytes: u64,
+        reason: &'static str,
+    ) -> u64 {
+        let mut evicted_bytes = 0;
+        while evicted_bytes < bytes {
+            let Some((key, eviction_item)) = state.pop_victim() else {
+                break;
+            };
+            event!(Level::INFO, ?key, "{reason}");
+            evicted_bytes += eviction_item.data.len() + entry_overhead_bytes;
+            state.remove(&key, &eviction_item, false).await;
+        }
+        evicted_bytes
+    }
+
+    /// Spawns the task that evicts entries once the map grows past
+    /// `high_watermark_bytes`. The map is reached through `owner`, and the
+    /// task exits once `owner` is dropped. Does nothing if background
+    /// eviction is not configured.
+    pub fn spawn_background_eviction<O, F>(owner: &Arc<O>, get_map: F)
+    where
+        O: Send + Sync + 'static,
+        F: Fn(&O) -> &Self + Send + Sync + 'static,
+        K: 'static,
+        I: 'static,
+    {
+        let map = get_map(owner);
+        if map.high_watermark_bytes.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let notify = map.background_eviction_notify.clone();
+        let weak_owner = Arc::downgrade(owner);
+        background_spawn!("evicting_map_background_eviction", async move {
+            loop {
+                notify.notified().await;
+                // The map notifies on drop, so the task wakes up to exit.
+                let Some(owner) = weak_owner.upgrade() else {
+                    return;
+                };
+                get_map(&owner).evict_to_low_watermark().await;
+            }
+        });
+    }
+
+    /// Evicts the oldest entries until the map is smaller than
+    /// `low_watermark_bytes`. If paced, the lock is released between
+    /// batches, so inserts and lookups are only delayed for a single batch.
+    async fn evict_to_low_watermark(&self) {
+        let eviction_bytes_per_second = self.eviction_bytes_per_second.load(Ordering::Relaxed);
+        let low_watermark_bytes = self.low_watermark_bytes.load(Ordering::Relaxed);
+        let batch_bytes = if eviction_bytes_per_second == 0 {
+            u64::MAX
+        } else {
+            (eviction_bytes_per_second as f64 * BACKGROUND_EVICTION_TICK.as_secs_f64()).max(1.)
+                as u64
+        };
+        loop {
+            {
+                let mut state = self.state.lock().await;
+                let mut evicted_bytes = 0;
+                while state.sum_store_size > low_watermark_bytes && evicted_bytes < batch_bytes {
+                    let Some((key, eviction_item)) = state.pop_victim() else {
+                        return;
+                    };
+                    event!(Level::INFO, ?key, "Evicting in background");
+                    evicted_bytes += eviction_item.data.len();
+                    state.background_evicted_bytes.add(eviction_item.data.len());
+                    state.remove(&key, &eviction_item, false).await;
+                }
+                if state.sum_store_size <= low_watermark_bytes {
+                    return;
+                }
+            }
+            // Only reached when paced, otherwise everything is evicted in
+            // a single batch.
+            tokio::time::sleep(BACKGROUND_EVICTION_TICK).await;
         }
     }
 
@@ -241,6 +625,22 @@ where
         continue_count
     }
 
+    /// Run the `handler` function on each key-value pair with the unix
+    /// timestamp of its last access, from the least to the most recently
+    /// used item.
+    pub async fn for_each_with_last_access<F>(&self, mut handler: F)
+    where
+        F: FnMut(&K, &T, u64) + Send,
+    {
+        let state = self.state.lock().await;
+        let anchor_timestamp = self.anchor_time.unix_timestamp() as i64;
+        for (key, eviction_item) in state.lru.iter().rev() {
+            let last_access =
+                (anchor_timestamp + i64::from(eviction_item.seconds_since_anchor)).max(0);
+            handler(key, &eviction_item.data, last_access as u64);
+        }
+    }
+
     /// Returns the number of key-value pairs that are currently in the the cache.
     /// Function is not for production code paths.
     pub async fn len_for_test(&self) -> usize {
@@ -256,47 +656,45 @@ where
     ) -> bool {
         let is_over_size = max_bytes != 0 && sum_store_size >= max_bytes;
 
-        let evict_older_than_seconds =
-            (self.anchor_time.elapsed().as_secs() as i32) - self.max_seconds;
+        let max_seconds = self.max_seconds.load(Ordering::Relaxed) as i32;
+        let evict_older_than_seconds = (self.anchor_time.elapsed().as_secs() as i32) - max_seconds;
         let old_item_exists =
-            self.max_seconds != 0 && peek_entry.seconds_since_anchor < evict_older_than_seconds;
+            max_seconds != 0 && peek_entry.seconds_since_anchor < evict_older_than_seconds;
 
-        let is_over_count = self.max_count != 0 && (lru_len as u64) > self.max_count;
+        let max_count = self.max_count.load(Ordering::Relaxed);
+        let is_over_count = max_count != 0 && (lru_len as u64) > max_count;
 
         is_over_size || old_item_exists || is_over_count
     }
 
     async fn evict_items(&self, state: &mut State<K, T>) {
-        let Some((_, mut peek_entry)) = state.lru.peek_lru() else {
+        let Some(mut peek_entry) = state.peek_victim() else {
             return;
         };
 
-        let max_bytes = if self.max_bytes != 0
-            && self.evict_bytes != 0
+        let configured_max_bytes = self.max_bytes.load(Ordering::Relaxed);
+        let evict_bytes = self.evict_bytes.load(Ordering::Relaxed);
+        let max_bytes = if configured_max_bytes != 0
+            && evict_bytes != 0
             && self.should_evict(
                 state.lru.len(),
                 peek_entry,
                 state.sum_store_size,
-                self.max_bytes,
+                configured_max_bytes,
             ) {
-            if self.max_bytes > self.evict_bytes {
-                self.max_bytes - self.evict_bytes
-            } else {
-                0
-            }
+            configured_max_bytes.saturating_sub(evict_bytes)
         } else {
-            self.max_bytes
+            configured_max_bytes
         };
 
         while self.should_evict(state.lru.len(), peek_entry, state.sum_store_size, max_bytes) {
             let (key, eviction_item) = state
-                .lru
-                .pop_lru()
+                .pop_victim()
                 .expect("Tried to peek() then pop() but failed");
             event!(Level::INFO, ?key, "Evicting",);
             state.remove(&key, &eviction_item, false).await;
 
-            peek_entry = if let Some((_, entry)) = state.lru.peek_lru() {
+            peek_entry = if let Some(entry) = state.peek_victim() {
                 entry
             } else {
                 return;
@@ -304,6 +702,13 @@ where
         }
     }
 
+    fn notify_background_eviction_if_needed(&self, state: &State<K, T>) {
+        let high_watermark_bytes = self.high_watermark_bytes.load(Ordering::Relaxed);
+        if high_watermark_bytes != 0 && state.sum_store_size > high_watermark_bytes {
+            self.background_eviction_notify.notify_one();
+        }
+    }
+
     /// Return the size of a `key`, if not found `None` is returned.
     pub async fn size_for_key<Q>(&self, key: &Q) -> Option<u64>
     where
@@ -339,8 +744,10 @@ where
         let mut state = self.state.lock().await;
 
         let lru_len = state.lru.len();
+        // With `ttl` an access must not extend the life of the entry.
+        let refresh = state.algorithm != EvictionAlgorithm::ttl;
         for (key, result) in keys.into_iter().zip(results.iter_mut()) {
-            let maybe_entry = if peek {
+            let maybe_entry = if peek || !refresh {
                 state.lru.peek_mut(key.borrow())
             } else {
                 state.lru.get_mut(key.borrow())
@@ -356,8 +763,12 @@ where
                     if !should_evict && peek {
                         *result = Some(entry.data.len());
                     } else if !should_evict && entry.data.touch().await {
-                        entry.seconds_since_anchor = self.anchor_time.elapsed().as_secs() as i32;
+                        if refresh {
+                            entry.seconds_since_anchor =
+                                self.anchor_time.elapsed().as_secs() as i32;
+                        }
                         *result = Some(entry.data.len());
+                        state.record_access::<Q>(key.borrow());
                     } else {
                         *result = None;
                         if let Some((key, eviction_item)) = state.lru.pop_entry(key.borrow()) {
@@ -383,11 +794,20 @@ where
         let mut state = self.state.lock().await;
         self.evict_items(&mut *state).await;
 
-        let entry = state.lru.get_mut(key.borrow())?;
+        let refresh = state.algorithm != EvictionAlgorithm::ttl;
+        let entry = if refresh {
+            state.lru.get_mut(key.borrow())
+        } else {
+            state.lru.peek_mut(key.borrow())
+        }?;
 
         if entry.data.touch().await {
-            entry.seconds_since_anchor = self.anchor_time.elapsed().as_secs() as i32;
-            return Some(entry.data.clone());
+            if refresh {
+                entry.seconds_since_anchor = self.anchor_time.elapsed().as_secs() as i32;
+            }
+            let data = entry.data.clone();
+            state.record_access(key);
+            return Some(data);
         }
 
         let (key, eviction_item) = state.lru.pop_entry(key.borrow())?;
@@ -447,6 +867,7 @@ where
             let new_item_size = data.len();
             let eviction_item = EvictionItem {
                 seconds_since_anchor,
+                rank: state.insert_rank(),
                 data,
             };
 
@@ -454,9 +875,11 @@ where
                 replaced_items.push(old_item);
             }
             state.sum_store_size += new_item_size;
+            state.update_snapshot();
             state.lifetime_inserted_bytes.add(new_item_size);
             self.evict_items(state).await;
         }
+        self.notify_background_eviction_if_needed(state);
         replaced_items
     }
 
@@ -500,3 +923,16 @@ where
         false
     }
 }
+
+impl<K, T, I> Drop for EvictingMap<K, T, I>
+where
+    K: Ord + Hash + Eq + Clone + Debug + Send,
+    T: LenEntry + Debug + Send,
+    I: InstantWrapper,
+{
+    fn drop(&mut self) {
+        // Wakes up the background eviction task, so it notices the owner is
+        // gone and exits.
+        self.background_eviction_notify.notify_one();
+    }
+}
@@ -389,12 +389,52 @@ pub async fn create_file(path: impl AsRef<Path>) -> Result<ResumeableFileSlot, E
     ))
 }
 
+/// Opens the file at `path` for writing without truncating it, creating it
+/// if it does not exist.
+pub async fn open_file_for_write(path: impl AsRef<Path>) -> Result<ResumeableFileSlot, Error> {
+    let path = path.as_ref().to_owned();
+    let (permit, os_file, path) = call_with_permit(move |permit| {
+        Ok((
+            permit,
+            std::fs::File::options()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&path)
+                .err_tip(|| format!("Could not open {path:?}"))?,
+            path,
+        ))
+    })
+    .await?;
+    Ok(ResumeableFileSlot::new(
+        FileSlot {
+            _permit: permit,
+            inner: tokio::fs::File::from_std(os_file),
+        },
+        path,
+        true, /* is_write */
+    ))
+}
+
 pub async fn hard_link(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> Result<(), Error> {
     let src = src.as_ref().to_owned();
     let dst = dst.as_ref().to_owned();
     call_with_permit(move |_| std::fs::hard_link(src, dst).map_err(Into::<Error>::into)).await
 }
 
+/// Copies a file. On Linux the copy shares its blocks with `src` on
+/// filesystems with reflinks.
+pub async fn copy(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> Result<(), Error> {
+    let src = src.as_ref().to_owned();
+    let dst = dst.as_ref().to_owned();
+    call_with_permit(move |_| {
+        std::fs::copy(src, dst)
+            .map(|_| ())
+            .map_err(Into::<Error>::into)
+    })
+    .await
+}
+
 pub async fn set_permissions(
     src: impl AsRef<Path>,
     perm: std::fs::Permissions,
@@ -17,6 +17,7 @@ use std::collections::HashMap;
 use std::fmt::Debug;
 use std::pin::Pin;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
 use futures::{Stream, StreamExt};
@@ -89,6 +90,12 @@ impl HealthStatus {
             message,
         }
     }
+
+    /// Returns true if the component can serve requests, a warning
+    /// does not prevent that.
+    pub const fn is_ready(&self) -> bool {
+        matches!(self, Self::Ok { .. } | Self::Warning { .. })
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize)]
@@ -97,6 +104,22 @@ pub struct HealthStatusDescription {
     pub status: HealthStatus,
 }
 
+/// Health status of a component with how long checking it took.
+#[derive(Clone, Debug, Serialize)]
+pub struct TimedHealthStatusDescription {
+    #[serde(flatten)]
+    pub description: HealthStatusDescription,
+    #[serde(rename = "latency_ms", serialize_with = "serialize_duration_as_millis")]
+    pub latency: Duration,
+}
+
+fn serialize_duration_as_millis<S: serde::Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64() * 1000.)
+}
+
 /// Health status indicator trait. This trait is used to define
 /// a health status indicator by implementing the `check_health` function.
 /// A default implementation is provided for the `check_health` function
@@ -162,6 +185,32 @@ pub struct HealthRegistry {
     indicators: Vec<(Cow<'static, str>, Arc<dyn HealthStatusIndicator>)>,
 }
 
+impl HealthRegistry {
+    /// Checks the health of all components at the same time, so a slow
+    /// component does not delay the others, and reports how long each
+    /// check took. The reports are in the same order as
+    /// `health_status_report`.
+    pub fn timed_health_status_report(
+        &self,
+    ) -> Pin<Box<dyn Stream<Item = TimedHealthStatusDescription> + Send + '_>> {
+        Box::pin(
+            futures::stream::iter(self.indicators.iter())
+                .map(|(namespace, indicator)| async move {
+                    let start = Instant::now();
+                    let status = indicator.check_health(namespace.clone()).await;
+                    TimedHealthStatusDescription {
+                        description: HealthStatusDescription {
+                            namespace: namespace.clone(),
+                            status,
+                        },
+                        latency: start.elapsed(),
+                    }
+                })
+                .buffered(self.indicators.len().max(1)),
+        )
+    }
+}
+
 pub trait HealthStatusReporter {
     fn health_status_report(
         &self,
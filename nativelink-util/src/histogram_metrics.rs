@@ -0,0 +1,204 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Latency and size histograms of stores and gRPC services, and the log of
+//! the slow ones.
+//!
+//! The `MetricsComponent` tree only publishes counters and values, so the
+//! distributions are kept in the default prometheus registry instead. The
+//! prometheus endpoint exports them next to the metrics of the tree.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+use prometheus::{
+    exponential_buckets, register_histogram_vec, register_int_counter_vec, HistogramVec,
+    IntCounterVec,
+};
+use tonic::Status;
+use tracing::{event, Level};
+
+/// From 0.5ms up to about 16s.
+fn latency_buckets() -> Vec<f64> {
+    exponential_buckets(0.0005, 2.0, 16).expect("Latency buckets are valid")
+}
+
+/// From 64 bytes up to 256MiB.
+fn size_buckets() -> Vec<f64> {
+    exponential_buckets(64.0, 4.0, 12).expect("Size buckets are valid")
+}
+
+static STORE_OPERATION_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "nativelink_store_operation_duration_seconds",
+        "Time it took stores to finish an operation",
+        &["store", "operation", "result"],
+        latency_buckets()
+    )
+    .expect("Could not register nativelink_store_operation_duration_seconds")
+});
+
+static STORE_OPERATION_BYTES: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "nativelink_store_operation_bytes",
+        "Number of bytes stores wrote or read in an operation",
+        &["store", "operation"],
+        size_buckets()
+    )
+    .expect("Could not register nativelink_store_operation_bytes")
+});
+
+static RPC_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "nativelink_rpc_duration_seconds",
+        "Time it took to answer a gRPC request",
+        &["service", "method", "instance_name", "code"],
+        latency_buckets()
+    )
+    .expect("Could not register nativelink_rpc_duration_seconds")
+});
+
+static SLOW_OPERATIONS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "nativelink_slow_operations_total",
+        "Number of store operations and gRPC requests slower than their threshold",
+        &["kind", "name", "operation"]
+    )
+    .expect("Could not register nativelink_slow_operations_total")
+});
+
+/// Thresholds of [`set_slow_operation_thresholds`] in microseconds, zero if
+/// slow operations are not logged.
+static SLOW_STORE_OPERATION_THRESHOLD_MICROS: AtomicU64 = AtomicU64::new(0);
+static SLOW_RPC_THRESHOLD_MICROS: AtomicU64 = AtomicU64::new(0);
+
+/// Logs the store operations and gRPC requests taking longer than
+/// `store_operation` and `rpc` respectively. A zero duration disables the
+/// log of its kind.
+pub fn set_slow_operation_thresholds(store_operation: Duration, rpc: Duration) {
+    let as_micros = |duration: Duration| u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+    SLOW_STORE_OPERATION_THRESHOLD_MICROS.store(as_micros(store_operation), Ordering::Relaxed);
+    SLOW_RPC_THRESHOLD_MICROS.store(as_micros(rpc), Ordering::Relaxed);
+}
+
+fn is_slow(threshold_micros: &AtomicU64, elapsed: Duration) -> bool {
+    let threshold_micros = threshold_micros.load(Ordering::Relaxed);
+    threshold_micros != 0 && elapsed.as_micros() > u128::from(threshold_micros)
+}
+
+/// Logs the operation `operation` of the store named `store_name` on the
+/// key described by `key` and counts it, if it was slow. `size` is the
+/// number of bytes it moved, if known.
+pub fn check_slow_store_operation(
+    store_name: &str,
+    operation: &str,
+    key: impl FnOnce() -> String,
+    size: Option<u64>,
+    elapsed: Duration,
+) {
+    if !is_slow(&SLOW_STORE_OPERATION_THRESHOLD_MICROS, elapsed) {
+        return;
+    }
+    SLOW_OPERATIONS
+        .with_label_values(&["store", store_name, operation])
+        .inc();
+    event!(
+        Level::WARN,
+        store = store_name,
+        operation,
+        key = key(),
+        size,
+        elapsed_ms = elapsed.as_millis() as u64,
+        "Slow store operation",
+    );
+}
+
+/// Records how long the operation `operation` of the store named
+/// `store_name` took.
+pub fn observe_store_operation(
+    store_name: &str,
+    operation: &str,
+    elapsed: Duration,
+    succeeded: bool,
+) {
+    let result = if succeeded { "ok" } else { "error" };
+    STORE_OPERATION_DURATION
+        .with_label_values(&[store_name, operation, result])
+        .observe(elapsed.as_secs_f64());
+}
+
+/// Records the number of bytes the operation `operation` of the store named
+/// `store_name` wrote or read.
+pub fn observe_store_bytes(store_name: &str, operation: &str, bytes: u64) {
+    STORE_OPERATION_BYTES
+        .with_label_values(&[store_name, operation])
+        .observe(bytes as f64);
+}
+
+/// Measures a gRPC request from its creation until [`RpcTimer::observe`]
+/// is called with the response.
+pub struct RpcTimer {
+    service: &'static str,
+    method: &'static str,
+    instance_name: String,
+    start: Instant,
+}
+
+impl RpcTimer {
+    pub fn new(service: &'static str, method: &'static str, instance_name: &str) -> Self {
+        Self {
+            service,
+            method,
+            instance_name: instance_name.to_string(),
+            start: Instant::now(),
+        }
+    }
+
+    /// Records the time since the timer was created, labeled with the gRPC
+    /// code of `result`. For streaming responses this is the time until the
+    /// stream was returned, not until it was consumed.
+    ///
+    /// Slow requests are logged in the span of the request, so the log has
+    /// its digests.
+    pub fn observe<T>(self, result: &Result<T, Status>) {
+        let code = match result {
+            Ok(_) => tonic::Code::Ok,
+            Err(status) => status.code(),
+        };
+        let elapsed = self.start.elapsed();
+        RPC_DURATION
+            .with_label_values(&[
+                self.service,
+                self.method,
+                &self.instance_name,
+                &format!("{code:?}"),
+            ])
+            .observe(elapsed.as_secs_f64());
+        if is_slow(&SLOW_RPC_THRESHOLD_MICROS, elapsed) {
+            SLOW_OPERATIONS
+                .with_label_values(&["rpc", self.service, self.method])
+                .inc();
+            event!(
+                Level::WARN,
+                service = self.service,
+                method = self.method,
+                instance_name = self.instance_name.as_str(),
+                ?code,
+                elapsed_ms = elapsed.as_millis() as u64,
+                "Slow gRPC request",
+            );
+        }
+    }
+}
@@ -0,0 +1,83 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashSet;
+
+use nativelink_config::cas_server::InstanceAccessConfig;
+use nativelink_error::{make_err, Code, Error};
+
+use crate::origin_context::{ActiveOriginContext, AUTHENTICATED_IDENTITY};
+
+/// Returns the identity the `AuthMiddleware` verified for the client of the
+/// active request, or the empty identity if it has none. The identity from
+/// the identity header is never used, as clients can send any identity in
+/// it.
+pub fn active_identity() -> String {
+    ActiveOriginContext::get_value(&AUTHENTICATED_IDENTITY)
+        .ok()
+        .flatten()
+        .map_or_else(String::new, |identity| identity.as_ref().clone())
+}
+
+/// Checks which identities may read from and write to an instance.
+#[derive(Debug, Default, Clone)]
+pub struct InstanceAccess {
+    read_identities: Option<HashSet<String>>,
+    write_identities: Option<HashSet<String>>,
+}
+
+impl InstanceAccess {
+    pub fn new(config: &InstanceAccessConfig) -> Self {
+        Self {
+            read_identities: config
+                .read_identities
+                .as_ref()
+                .map(|identities| identities.iter().cloned().collect()),
+            write_identities: config
+                .write_identities
+                .as_ref()
+                .map(|identities| identities.iter().cloned().collect()),
+        }
+    }
+
+    fn check(
+        identities: Option<&HashSet<String>>,
+        instance_name: &str,
+        action: &str,
+    ) -> Result<(), Error> {
+        let Some(identities) = identities else {
+            return Ok(());
+        };
+        let identity = active_identity();
+        if identities.contains(&identity) {
+            return Ok(());
+        }
+        Err(make_err!(
+            Code::PermissionDenied,
+            "Identity '{identity}' may not {action} instance '{instance_name}'"
+        ))
+    }
+
+    /// Checks that the client of the active request may read from
+    /// `instance_name`.
+    pub fn check_read(&self, instance_name: &str) -> Result<(), Error> {
+        Self::check(self.read_identities.as_ref(), instance_name, "read from")
+    }
+
+    /// Checks that the client of the active request may write to
+    /// `instance_name`.
+    pub fn check_write(&self, instance_name: &str) -> Result<(), Error> {
+        Self::check(self.write_identities.as_ref(), instance_name, "write to")
+    }
+}
@@ -13,6 +13,9 @@
 // limitations under the License.
 
 pub mod action_messages;
+pub mod audit_log;
+pub mod auth_middleware;
+pub mod blob_compression;
 pub mod buf_channel;
 pub mod channel_body_for_tests;
 pub mod chunked_stream;
@@ -23,8 +26,11 @@ pub mod evicting_map;
 pub mod fastcdc;
 pub mod fs;
 pub mod health_utils;
+pub mod histogram_metrics;
+pub mod instance_access;
 pub mod instant_wrapper;
 pub mod known_platform_property_provider;
+pub mod memory_budget;
 pub mod metrics_utils;
 pub mod operation_state_manager;
 pub mod origin_context;
@@ -33,17 +39,53 @@ pub mod origin_event_middleware;
 pub mod origin_event_publisher;
 pub mod platform_properties;
 pub mod proto_stream_utils;
+pub mod rate_limit_middleware;
 pub mod resource_info;
 pub mod retry;
 pub mod shutdown_guard;
 pub mod store_trait;
 pub mod task;
+pub mod telemetry;
 pub mod tls_utils;
 pub mod write_counter;
 
 // Re-export tracing mostly for use in macros.
 pub use tracing as __tracing;
 
+type LogFilterHandle =
+    tracing_subscriber::reload::Handle<tracing_subscriber::EnvFilter, tracing_subscriber::Registry>;
+
+static LOG_FILTER_HANDLE: std::sync::OnceLock<LogFilterHandle> = std::sync::OnceLock::new();
+
+fn make_log_filter_builder() -> tracing_subscriber::filter::Builder {
+    tracing_subscriber::EnvFilter::builder()
+        .with_default_directive(tracing::metadata::LevelFilter::WARN.into())
+}
+
+/// Replaces the filter of the logs emitted, using the syntax of `RUST_LOG`.
+/// An empty filter restores the filter `RUST_LOG` configured.
+pub fn set_log_filter(filter: &str) -> Result<(), nativelink_error::Error> {
+    let handle = LOG_FILTER_HANDLE.get().ok_or_else(|| {
+        nativelink_error::make_err!(
+            nativelink_error::Code::FailedPrecondition,
+            "Logging is not initialized"
+        )
+    })?;
+    let env_filter = if filter.is_empty() {
+        make_log_filter_builder().from_env_lossy()
+    } else {
+        make_log_filter_builder()
+            .parse(filter)
+            .map_err(|e| nativelink_error::make_input_err!("Invalid log filter '{filter}': {e}"))?
+    };
+    handle.reload(env_filter).map_err(|e| {
+        nativelink_error::make_err!(
+            nativelink_error::Code::Internal,
+            "Could not set log filter: {e}"
+        )
+    })
+}
+
 /// Initialize tracing.
 pub fn init_tracing() -> Result<(), nativelink_error::Error> {
     use tracing_subscriber::prelude::*;
@@ -57,9 +99,10 @@ pub fn init_tracing() -> Result<(), nativelink_error::Error> {
         ));
     }
     *logging_initized_guard = true;
-    let env_filter = tracing_subscriber::EnvFilter::builder()
-        .with_default_directive(tracing::metadata::LevelFilter::WARN.into())
-        .from_env_lossy();
+    // The filter is reloadable, so the log level can be changed at runtime.
+    let (env_filter, log_filter_handle) =
+        tracing_subscriber::reload::Layer::new(make_log_filter_builder().from_env_lossy());
+    let _ = LOG_FILTER_HANDLE.set(log_filter_handle);
 
     // Setup tracing logger for multiple format types, compact, json, and pretty as a single layer.
     // Configuration for log format comes from environment variable NL_LOG_FMT due to subscribers
@@ -93,6 +136,11 @@ pub fn init_tracing() -> Result<(), nativelink_error::Error> {
         ),
     };
 
+    // Export spans to an OpenTelemetry collector if one is configured.
+    if let Some(otel_layer) = telemetry::make_otel_layer()? {
+        layers.push(otel_layer);
+    }
+
     // Add a console subscriber if the feature is enabled, see tokio-console for a client console.
     // https://crates.io/crates/tokio-console
     if cfg!(feature = "enable_tokio_console") {
@@ -0,0 +1,180 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Weak};
+
+use async_lock::Mutex as AsyncMutex;
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use tracing::{event, Level};
+
+/// Estimated allocator and bookkeeping overhead of a single entry held in
+/// memory, on top of the bytes of its value.
+pub const ENTRY_OVERHEAD_BYTES: u64 = 128;
+
+static GLOBAL_MEMORY_BUDGET: LazyLock<MemoryBudget> = LazyLock::new(|| MemoryBudget::new(0));
+
+/// The memory budget shared by everything in the process that holds data
+/// in memory.
+pub fn global_memory_budget() -> &'static MemoryBudget {
+    &GLOBAL_MEMORY_BUDGET
+}
+
+/// Something that holds memory accounted by a `MemoryBudget` and can give
+/// some of it back when the budget is used up.
+#[async_trait]
+pub trait MemoryConsumer: Send + Sync {
+    /// Number of bytes currently held, including the overhead of entries.
+    fn accounted_bytes(&self) -> u64;
+
+    /// Evicts at least `bytes` if possible and returns the number of bytes
+    /// that were evicted.
+    async fn evict(&self, bytes: u64) -> u64;
+}
+
+/// Keeps the memory held by all registered consumers and reservations
+/// below `max_bytes` by asking the consumers to evict when it is exceeded.
+/// Each consumer evicts a share of the excess proportional to its size.
+pub struct MemoryBudget {
+    max_bytes: AtomicU64,
+    reserved_bytes: Arc<AtomicU64>,
+    consumers: Mutex<Vec<Weak<dyn MemoryConsumer>>>,
+    /// Serializes evictions, so consumers are not asked to evict the same
+    /// excess twice.
+    eviction_lock: AsyncMutex<()>,
+}
+
+impl MemoryBudget {
+    /// Creates a budget of `max_bytes`. Zero means unlimited.
+    pub fn new(max_bytes: u64) -> Self {
+        Self {
+            max_bytes: AtomicU64::new(max_bytes),
+            reserved_bytes: Arc::new(AtomicU64::new(0)),
+            consumers: Mutex::new(Vec::new()),
+            eviction_lock: AsyncMutex::new(()),
+        }
+    }
+
+    /// Sets the maximum number of bytes. Zero means unlimited.
+    pub fn set_max_bytes(&self, max_bytes: u64) {
+        self.max_bytes.store(max_bytes, Ordering::Relaxed);
+    }
+
+    pub fn max_bytes(&self) -> u64 {
+        self.max_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Adds a consumer to the budget. It is removed once it is dropped.
+    pub fn register(&self, consumer: Weak<dyn MemoryConsumer>) {
+        self.consumers.lock().push(consumer);
+    }
+
+    /// Accounts `bytes` of memory that are not held by a consumer, like a
+    /// buffer that is being filled, until the returned reservation is
+    /// dropped.
+    pub fn reserve(&self, bytes: u64) -> MemoryReservation {
+        self.reserved_bytes.fetch_add(bytes, Ordering::Relaxed);
+        MemoryReservation {
+            reserved_bytes: self.reserved_bytes.clone(),
+            bytes,
+        }
+    }
+
+    /// Number of bytes held by all consumers and reservations.
+    pub fn used_bytes(&self) -> u64 {
+        self.live_consumers()
+            .iter()
+            .map(|consumer| consumer.accounted_bytes())
+            .sum::<u64>()
+            + self.reserved_bytes.load(Ordering::Relaxed)
+    }
+
+    fn live_consumers(&self) -> Vec<Arc<dyn MemoryConsumer>> {
+        let mut consumers = self.consumers.lock();
+        let mut live_consumers = Vec::with_capacity(consumers.len());
+        consumers.retain(|consumer| {
+            let Some(consumer) = consumer.upgrade() else {
+                return false;
+            };
+            live_consumers.push(consumer);
+            true
+        });
+        live_consumers
+    }
+
+    /// Evicts from the consumers until the used bytes fit in the budget or
+    /// nothing more can be evicted.
+    pub async fn enforce(&self) {
+        let max_bytes = self.max_bytes();
+        if max_bytes == 0 || self.used_bytes() <= max_bytes {
+            return;
+        }
+        let _eviction_guard = self.eviction_lock.lock().await;
+        loop {
+            let consumers = self.live_consumers();
+            let consumer_bytes: Vec<u64> = consumers
+                .iter()
+                .map(|consumer| consumer.accounted_bytes())
+                .collect();
+            let total_consumer_bytes: u64 = consumer_bytes.iter().sum();
+            let used_bytes = total_consumer_bytes + self.reserved_bytes.load(Ordering::Relaxed);
+            if used_bytes <= max_bytes || total_consumer_bytes == 0 {
+                return;
+            }
+            let excess_bytes = used_bytes - max_bytes;
+            event!(
+                Level::WARN,
+                used_bytes,
+                max_bytes,
+                "Memory budget exceeded, evicting from memory stores"
+            );
+            let mut evicted_bytes = 0;
+            for (consumer, bytes) in consumers.iter().zip(consumer_bytes) {
+                // Rounded up, so a small excess is not split into nothing.
+                let share = u64::try_from(
+                    (u128::from(excess_bytes) * u128::from(bytes))
+                        .div_ceil(u128::from(total_consumer_bytes)),
+                )
+                .unwrap_or(u64::MAX);
+                if share != 0 {
+                    evicted_bytes += consumer.evict(share).await;
+                }
+            }
+            if evicted_bytes == 0 {
+                return;
+            }
+        }
+    }
+}
+
+/// Memory accounted by a `MemoryBudget` until this is dropped.
+pub struct MemoryReservation {
+    reserved_bytes: Arc<AtomicU64>,
+    bytes: u64,
+}
+
+impl MemoryReservation {
+    /// Accounts `bytes` more until the reservation is dropped.
+    pub fn grow(&mut self, bytes: u64) {
+        self.reserved_bytes.fetch_add(bytes, Ordering::Relaxed);
+        self.bytes += bytes;
+    }
+}
+
+impl Drop for MemoryReservation {
+    fn drop(&mut self) {
+        self.reserved_bytes.fetch_sub(self.bytes, Ordering::Relaxed);
+    }
+}
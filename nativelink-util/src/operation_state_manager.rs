@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::{BTreeMap, HashMap};
 use std::pin::Pin;
 use std::sync::Arc;
 use std::time::SystemTime;
@@ -19,7 +20,8 @@ use std::time::SystemTime;
 use async_trait::async_trait;
 use bitflags::bitflags;
 use futures::Stream;
-use nativelink_error::Error;
+use nativelink_config::schedulers::SchedulerSpec;
+use nativelink_error::{make_err, Code, Error};
 use nativelink_metric::MetricsComponent;
 
 use crate::action_messages::{
@@ -54,6 +56,11 @@ pub trait ActionStateResult: Send + Sync + 'static {
     async fn changed(&mut self) -> Result<(Arc<ActionState>, Option<OriginMetadata>), Error>;
     /// Provide result as action info. This behavior will not be supported by all implementations.
     async fn as_action_info(&self) -> Result<(Arc<ActionInfo>, Option<OriginMetadata>), Error>;
+    /// Provides the worker the action is assigned to. Implementations that
+    /// do not know the worker return None.
+    async fn as_worker_id(&self) -> Result<Option<WorkerId>, Error> {
+        Ok(None)
+    }
 }
 
 /// The direction in which the results are ordered.
@@ -98,6 +105,10 @@ pub struct OperationFilter {
 pub type ActionStateResultStream<'a> =
     Pin<Box<dyn Stream<Item = Box<dyn ActionStateResult>> + Send + 'a>>;
 
+/// The number of queued actions of each set of platform properties. The
+/// properties are sorted by name, so they can be used as a key.
+pub type QueuedActionCounts = HashMap<BTreeMap<String, String>, usize>;
+
 #[async_trait]
 pub trait ClientStateManager: Sync + Send + Unpin + MetricsComponent + 'static {
     /// Add a new action to the queue or joins an existing action.
@@ -113,6 +124,45 @@ pub trait ClientStateManager: Sync + Send + Unpin + MetricsComponent + 'static {
         filter: OperationFilter,
     ) -> Result<ActionStateResultStream, Error>;
 
+    /// Cancels the operation with the given client operation id (or
+    /// operation id), completing it with a `Cancelled` error and asking the
+    /// worker executing it, if any, to kill it.
+    async fn cancel_operation(&self, client_operation_id: &OperationId) -> Result<(), Error> {
+        Err(make_err!(
+            Code::Unimplemented,
+            "Cancelling operation {client_operation_id} is not supported by this scheduler"
+        ))
+    }
+
+    /// Returns the number of queued actions of each set of platform
+    /// properties.
+    async fn queued_action_counts(&self) -> Result<QueuedActionCounts, Error> {
+        Err(make_err!(
+            Code::Unimplemented,
+            "Counting queued actions is not supported by this scheduler"
+        ))
+    }
+
+    /// Returns whether an operation of the action with `unique_key` is
+    /// queued or running, so a new client of it joins the operation.
+    async fn has_unfinished_operation(&self, _unique_key: &ActionUniqueKey) -> Result<bool, Error> {
+        Err(make_err!(
+            Code::Unimplemented,
+            "Looking up operations by action is not supported by this scheduler"
+        ))
+    }
+
+    /// Applies the settings of `spec` that can be changed while the scheduler
+    /// is running, like property modifications. `spec` is the spec the
+    /// scheduler was created from, after the config was reloaded.
+    ///
+    /// If `check_only` is set, the spec is only checked and nothing is
+    /// changed, so the whole tree of schedulers can be checked before any
+    /// of them is changed.
+    async fn reconfigure(&self, _spec: &SchedulerSpec, _check_only: bool) -> Result<(), Error> {
+        Ok(())
+    }
+
     /// Returns the known platform property provider for the given instance
     /// if this implementation supports it.
     // TODO(https://github.com/rust-lang/rust/issues/65991) When this lands we can
@@ -133,6 +183,10 @@ pub enum UpdateOperationType {
 
     /// Notification that the operation has been completed.
     UpdateWithError(Error),
+
+    /// Notification that the worker running the operation went away or
+    /// stopped updating it.
+    UpdateWithWorkerLost(Error),
 }
 
 #[async_trait]
@@ -157,6 +211,14 @@ pub trait MatchingEngineStateManager: Sync + Send + MetricsComponent {
         filter: OperationFilter,
     ) -> Result<ActionStateResultStream<'a>, Error>;
 
+    /// Completes the operation with the given client operation id (or
+    /// operation id) with a `Cancelled` error. Returns the operation id and
+    /// the worker it was assigned to if it was executing.
+    async fn cancel_operation(
+        &self,
+        client_operation_id: &OperationId,
+    ) -> Result<Option<(OperationId, WorkerId)>, Error>;
+
     /// Assign an operation to a worker or unassign it.
     async fn assign_operation(
         &self,
@@ -25,7 +25,7 @@ use std::sync::Arc;
 use std::task::{Context, Poll};
 
 use futures::Future;
-use nativelink_error::{make_err, Code, Error};
+use nativelink_error::{make_err, Code, Error, ResultExt};
 use pin_project_lite::pin_project;
 use tracing::instrument::Instrumented;
 use tracing::{Instrument, Span};
@@ -57,6 +57,27 @@ macro_rules! make_symbol {
 // See: IdentityHeaderSpec for details.
 make_symbol!(ORIGIN_IDENTITY, String);
 
+// Symbol that represents the identity of the origin of a request that was
+// verified by the `AuthMiddleware`. Unlike `ORIGIN_IDENTITY`, clients can
+// not choose it, so access to instances is checked against it.
+make_symbol!(AUTHENTICATED_IDENTITY, String);
+
+// Symbol that represents the instance name of the request being processed,
+// so stores can account for usage per instance name.
+make_symbol!(ACTIVE_INSTANCE_NAME, String);
+
+// Symbol that represents the id of the request being processed, so logs of
+// the work done for it, even on workers, can be correlated with it.
+make_symbol!(REQUEST_ID, String);
+
+/// Utility function to make a context with the instance name of the
+/// request set.
+pub fn make_ctx_for_instance_name(instance_name: &str) -> Result<Arc<OriginContext>, Error> {
+    let mut new_ctx = ActiveOriginContext::fork().err_tip(|| "In make_ctx_for_instance_name")?;
+    new_ctx.set_value(&ACTIVE_INSTANCE_NAME, Arc::new(instance_name.to_string()));
+    Ok(Arc::new(new_ctx))
+}
+
 pub struct NLSymbol<T: Send + Sync + 'static> {
     pub name: &'static str,
     pub _phantom: std::marker::PhantomData<T>,
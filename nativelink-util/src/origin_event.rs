@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashMap;
 use std::marker::PhantomData;
 use std::pin::Pin;
 use std::sync::{Arc, OnceLock};
@@ -162,6 +163,14 @@ pub struct OriginMetadata {
         deserialize_with = "deserialize_request_metadata"
     )]
     pub bazel_metadata: Option<RequestMetadata>,
+    /// The id of the request in the logs, so the logs of the work done for
+    /// it later, like running its action on a worker, can be found too.
+    #[serde(default)]
+    pub request_id: String,
+    /// The W3C trace context of the request, so the work done for it later,
+    /// like running its action on a worker, joins the trace of the client.
+    #[serde(default)]
+    pub trace_context: HashMap<String, String>,
 }
 
 pub struct OriginEventCollector {
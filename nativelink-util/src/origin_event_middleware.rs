@@ -12,13 +12,14 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use base64::prelude::BASE64_STANDARD_NO_PAD;
 use base64::Engine;
 use futures::future::BoxFuture;
 use futures::task::{Context, Poll};
-use hyper::http::{self, StatusCode};
+use hyper::http::{self, HeaderValue, StatusCode};
 use nativelink_config::cas_server::IdentityHeaderSpec;
 use nativelink_proto::build::bazel::remote::execution::v2::RequestMetadata;
 use nativelink_proto::com::github::trace_machina::nativelink::events::OriginEvent;
@@ -26,9 +27,9 @@ use prost::Message;
 use tokio::sync::mpsc;
 use tower::layer::Layer;
 use tower::Service;
-use tracing::trace_span;
+use tracing::error_span;
 
-use crate::origin_context::{ActiveOriginContext, ORIGIN_IDENTITY};
+use crate::origin_context::{ActiveOriginContext, ORIGIN_IDENTITY, REQUEST_ID};
 use crate::origin_event::{OriginEventCollector, OriginMetadata, ORIGIN_EVENT_COLLECTOR};
 
 /// Default identity header name.
@@ -37,6 +38,14 @@ use crate::origin_event::{OriginEventCollector, OriginMetadata, ORIGIN_EVENT_COL
 // We should consolidate these.
 const DEFAULT_IDENTITY_HEADER: &str = "x-identity";
 
+/// Header with the id of the request in the logs. Clients may set it to
+/// choose the id, otherwise one is generated. It is always set on the
+/// response, so clients can report it.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest request id accepted from a client.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
 #[derive(Default, Clone)]
 pub struct OriginRequestMetadata {
     pub identity: String,
@@ -128,12 +137,30 @@ where
             context.set_value(&ORIGIN_IDENTITY, Arc::new(identity.clone()));
             identity
         };
+        let request_id = req
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|header| header.to_str().ok())
+            .filter(|request_id| !request_id.is_empty() && request_id.len() <= MAX_REQUEST_ID_LEN)
+            .map_or_else(|| uuid::Uuid::new_v4().to_string(), str::to_string);
+        context.set_value(&REQUEST_ID, Arc::new(request_id.clone()));
+        let bazel_metadata = req
+            .headers()
+            .get("build.bazel.remote.execution.v2.requestmetadata-bin")
+            .and_then(|header| BASE64_STANDARD_NO_PAD.decode(header.as_bytes()).ok())
+            .and_then(|data| RequestMetadata::decode(data.as_slice()).ok());
+        // All logs of the request carry these fields, so a failed Bazel
+        // invocation can be found in them. The identity is updated by the
+        // `AuthMiddleware` if the request has a token.
+        let span = error_span!(
+            "request",
+            %request_id,
+            %identity,
+            invocation_id = bazel_metadata
+                .as_ref()
+                .map_or("", |metadata| metadata.tool_invocation_id.as_str()),
+        );
         if let Some(origin_event_tx) = &self.maybe_origin_event_tx {
-            let bazel_metadata = req
-                .headers()
-                .get("build.bazel.remote.execution.v2.requestmetadata-bin")
-                .and_then(|header| BASE64_STANDARD_NO_PAD.decode(header.as_bytes()).ok())
-                .and_then(|data| RequestMetadata::decode(data.as_slice()).ok());
             context.set_value(
                 &ORIGIN_EVENT_COLLECTOR,
                 Arc::new(OriginEventCollector::new(
@@ -141,15 +168,20 @@ where
                     OriginMetadata {
                         identity,
                         bazel_metadata,
+                        request_id: request_id.clone(),
+                        // Set once the action of the request is queued.
+                        trace_context: HashMap::new(),
                     },
                 )),
             );
         }
 
         Box::pin(async move {
-            Arc::new(context)
-                .wrap_async(trace_span!("OriginEventMiddleware"), inner.call(req))
-                .await
+            let mut response = Arc::new(context).wrap_async(span, inner.call(req)).await?;
+            if let Ok(request_id) = HeaderValue::from_str(&request_id) {
+                response.headers_mut().insert(REQUEST_ID_HEADER, request_id);
+            }
+            Ok(response)
         })
     }
 }
@@ -13,8 +13,9 @@
 // limitations under the License.
 
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 
+use nativelink_error::{make_input_err, Error};
 use nativelink_metric::{
     publish, MetricFieldData, MetricKind, MetricPublishKnownKindData, MetricsComponent,
 };
@@ -97,18 +98,28 @@ impl From<&PlatformProperties> for ProtoPlatform {
 ///            TODO(allada) In the future this will be used by the scheduler and
 ///            worker to cause the scheduler to prefer certain workers over others,
 ///            but not restrict them based on these values.
+/// Range    - Means the number of the worker must satisfy the comparison of
+///            the action, without being subtracted from the worker.
+/// OneOf    - Means the worker must have one of the values of the action.
 #[derive(Eq, PartialEq, Hash, Clone, Ord, PartialOrd, Debug, Serialize, Deserialize)]
 pub enum PlatformPropertyValue {
     Exact(String),
     Minimum(u64),
     Priority(String),
     Unknown(String),
+    Range(PlatformPropertyRange),
+    OneOf(PlatformPropertyOneOf),
 }
 
 impl PlatformPropertyValue {
     /// Same as `PlatformProperties::is_satisfied_by`, but on an individual value.
     #[must_use]
     pub fn is_satisfied_by(&self, worker_value: &Self) -> bool {
+        // The expressions may be equal while the comparison does not hold,
+        // like `<8` on both sides.
+        if let (Self::Range(range), Self::Range(worker_range)) = (self, worker_value) {
+            return range.is_satisfied_by(worker_range.value);
+        }
         if self == worker_value {
             return true;
         }
@@ -123,8 +134,14 @@ impl PlatformPropertyValue {
             // workers can be selected, but might be used to prefer certain workers
             // over others.
             Self::Priority(_) => true,
+            Self::OneOf(one_of) => {
+                if let Self::OneOf(worker_one_of) = worker_value {
+                    return !one_of.values.is_disjoint(&worker_one_of.values);
+                }
+                false
+            }
             // Success exact case is handled above.
-            Self::Exact(_) | Self::Unknown(_) => false,
+            Self::Exact(_) | Self::Unknown(_) | Self::Range(_) => false,
         }
     }
 
@@ -134,6 +151,8 @@ impl PlatformPropertyValue {
                 Cow::Borrowed(value)
             }
             Self::Minimum(value) => Cow::Owned(value.to_string()),
+            Self::Range(range) => Cow::Borrowed(&range.expression),
+            Self::OneOf(one_of) => Cow::Borrowed(&one_of.expression),
         }
     }
 }
@@ -151,8 +170,101 @@ impl MetricsComponent for PlatformPropertyValue {
             Self::Minimum(v) => publish!(name, v, kind, help, "minimum"),
             Self::Priority(v) => publish!(name, v, kind, help, "priority"),
             Self::Unknown(v) => publish!(name, v, kind, help, "unknown"),
+            Self::Range(v) => publish!(name, &v.expression, kind, help, "range"),
+            Self::OneOf(v) => publish!(name, &v.expression, kind, help, "one_of"),
         }
 
         Ok(MetricPublishKnownKindData::Component)
     }
 }
+
+/// How the number of a worker is compared to the number of an action.
+#[derive(Eq, PartialEq, Hash, Clone, Copy, Ord, PartialOrd, Debug, Serialize, Deserialize)]
+pub enum PlatformPropertyComparison {
+    AtLeast,
+    GreaterThan,
+    AtMost,
+    LessThan,
+    Equal,
+}
+
+/// A numeric platform property. Actions compare the number of the worker to
+/// theirs with `>=`, `>`, `<=`, `<` or `=`. A number without comparison
+/// must be at least reached, so workers just set their number.
+#[derive(Eq, PartialEq, Hash, Clone, Ord, PartialOrd, Debug, Serialize, Deserialize)]
+pub struct PlatformPropertyRange {
+    /// The property value this was parsed from.
+    pub expression: String,
+    pub comparison: PlatformPropertyComparison,
+    pub value: u64,
+}
+
+impl PlatformPropertyRange {
+    pub fn parse(expression: &str) -> Result<Self, Error> {
+        let trimmed = expression.trim();
+        let (comparison, number) = [
+            (">=", PlatformPropertyComparison::AtLeast),
+            ("<=", PlatformPropertyComparison::AtMost),
+            (">", PlatformPropertyComparison::GreaterThan),
+            ("<", PlatformPropertyComparison::LessThan),
+            ("=", PlatformPropertyComparison::Equal),
+        ]
+        .into_iter()
+        .find_map(|(prefix, comparison)| {
+            trimmed
+                .strip_prefix(prefix)
+                .map(|number| (comparison, number))
+        })
+        .unwrap_or((PlatformPropertyComparison::AtLeast, trimmed));
+        let value = number.trim().parse::<u64>().map_err(|e| {
+            make_input_err!("Cannot convert platform property to a range: {expression} - {e}")
+        })?;
+        Ok(Self {
+            expression: expression.to_string(),
+            comparison,
+            value,
+        })
+    }
+
+    /// Returns if the number of a worker satisfies the comparison.
+    #[must_use]
+    pub fn is_satisfied_by(&self, worker_value: u64) -> bool {
+        match self.comparison {
+            PlatformPropertyComparison::AtLeast => worker_value >= self.value,
+            PlatformPropertyComparison::GreaterThan => worker_value > self.value,
+            PlatformPropertyComparison::AtMost => worker_value <= self.value,
+            PlatformPropertyComparison::LessThan => worker_value < self.value,
+            PlatformPropertyComparison::Equal => worker_value == self.value,
+        }
+    }
+}
+
+/// A platform property with a set of values, like `{a100,h100}`. Actions
+/// run on workers that have any of their values.
+#[derive(Eq, PartialEq, Hash, Clone, Ord, PartialOrd, Debug, Serialize, Deserialize)]
+pub struct PlatformPropertyOneOf {
+    /// The property value this was parsed from.
+    pub expression: String,
+    pub values: BTreeSet<String>,
+}
+
+impl PlatformPropertyOneOf {
+    /// Parses comma separated values, optionally in braces.
+    #[must_use]
+    pub fn parse(expression: &str) -> Self {
+        let trimmed = expression.trim();
+        let list = trimmed
+            .strip_prefix('{')
+            .and_then(|list| list.strip_suffix('}'))
+            .unwrap_or(trimmed);
+        Self {
+            expression: expression.to_string(),
+            values: list
+                .split(',')
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+}
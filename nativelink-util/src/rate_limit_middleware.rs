@@ -0,0 +1,411 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Instant;
+
+use bytes::Buf;
+use futures::future::BoxFuture;
+use futures::task::{Context, Poll};
+use http_body::{Body, Frame, SizeHint};
+use hyper::http::{self, HeaderValue};
+use nativelink_config::cas_server::RateLimitConfig;
+use nativelink_error::{make_err, Code, Error};
+use parking_lot::Mutex;
+use pin_project_lite::pin_project;
+use tower::layer::Layer;
+use tower::Service;
+
+use crate::instance_access::active_identity;
+
+const BYTESTREAM_WRITE_PATH: &str = "/google.bytestream.ByteStream/Write";
+const EXECUTE_PATH: &str = "/build.bazel.remote.execution.v2.Execution/Execute";
+
+/// Address a request was received from. Set on every request of a
+/// connection by `WithClientAddress`.
+#[derive(Clone, Copy, Debug)]
+pub struct ClientAddress(pub SocketAddr);
+
+/// Sets the `ClientAddress` of the requests of a connection, so clients
+/// without an identity can be limited by their IP address.
+#[derive(Clone)]
+pub struct WithClientAddress<S> {
+    inner: S,
+    address: SocketAddr,
+}
+
+impl<S> WithClientAddress<S> {
+    pub const fn new(inner: S, address: SocketAddr) -> Self {
+        Self { inner, address }
+    }
+}
+
+impl<S, ReqBody> Service<http::Request<ReqBody>> for WithClientAddress<S>
+where
+    S: Service<http::Request<ReqBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: http::Request<ReqBody>) -> Self::Future {
+        req.extensions_mut().insert(ClientAddress(self.address));
+        self.inner.call(req)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum LimitedMethod {
+    ByteStreamWrite,
+    Execute,
+    Other,
+}
+
+impl LimitedMethod {
+    fn from_path(path: &str) -> Self {
+        match path {
+            BYTESTREAM_WRITE_PATH => Self::ByteStreamWrite,
+            EXECUTE_PATH => Self::Execute,
+            _ => Self::Other,
+        }
+    }
+}
+
+struct ClientState {
+    requests: usize,
+    bytestream_writes: usize,
+    executes: usize,
+    /// Bytes the client may still upload. Refilled by `max_bytes_per_second`
+    /// every second, up to one second of it, and negative while the client
+    /// uploaded more than it may.
+    available_bytes: f64,
+    last_refill: Instant,
+}
+
+impl ClientState {
+    fn new(max_bytes_per_second: u64, now: Instant) -> Self {
+        Self {
+            requests: 0,
+            bytestream_writes: 0,
+            executes: 0,
+            available_bytes: max_bytes_per_second as f64,
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, max_bytes_per_second: u64, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.last_refill = now;
+        self.available_bytes = (self.available_bytes
+            + elapsed.as_secs_f64() * max_bytes_per_second as f64)
+            .min(max_bytes_per_second as f64);
+    }
+}
+
+/// Keeps track of the requests in flight and the uploaded bytes of every
+/// client, and admits new requests while the client is within its limits.
+pub struct RateLimiter {
+    max_concurrent_bytestream_writes: usize,
+    max_concurrent_executes: usize,
+    max_bytes_per_second: u64,
+    /// Clients with requests in flight or that uploaded more than they may.
+    clients: Mutex<HashMap<String, ClientState>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        Self {
+            max_concurrent_bytestream_writes: config.max_concurrent_bytestream_writes,
+            max_concurrent_executes: config.max_concurrent_executes,
+            max_bytes_per_second: config.max_bytes_per_second,
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Admits a request of `client` to the gRPC method at `path`. The
+    /// request counts against the limits of the client until the returned
+    /// slot is dropped.
+    pub fn admit(self: &Arc<Self>, client: &str, path: &str) -> Result<RequestSlot, Error> {
+        let method = LimitedMethod::from_path(path);
+        let now = Instant::now();
+        let mut clients = self.clients.lock();
+        let state = clients
+            .entry(client.to_string())
+            .or_insert_with(|| ClientState::new(self.max_bytes_per_second, now));
+        if self.max_bytes_per_second != 0 {
+            state.refill(self.max_bytes_per_second, now);
+            if state.available_bytes <= 0.0 {
+                return Err(make_err!(
+                    Code::ResourceExhausted,
+                    "Client '{client}' uploads more than {} bytes per second",
+                    self.max_bytes_per_second
+                ));
+            }
+        }
+        match method {
+            LimitedMethod::ByteStreamWrite => {
+                if self.max_concurrent_bytestream_writes != 0
+                    && state.bytestream_writes >= self.max_concurrent_bytestream_writes
+                {
+                    return Err(make_err!(
+                        Code::ResourceExhausted,
+                        "Client '{client}' has {} ByteStream writes in flight, the limit is {}",
+                        state.bytestream_writes,
+                        self.max_concurrent_bytestream_writes
+                    ));
+                }
+                state.bytestream_writes += 1;
+            }
+            LimitedMethod::Execute => {
+                if self.max_concurrent_executes != 0
+                    && state.executes >= self.max_concurrent_executes
+                {
+                    return Err(make_err!(
+                        Code::ResourceExhausted,
+                        "Client '{client}' has {} Execute requests in flight, the limit is {}",
+                        state.executes,
+                        self.max_concurrent_executes
+                    ));
+                }
+                state.executes += 1;
+            }
+            LimitedMethod::Other => {}
+        }
+        state.requests += 1;
+        Ok(RequestSlot {
+            limiter: self.clone(),
+            client: client.to_string(),
+            method,
+        })
+    }
+}
+
+/// A request admitted by the `RateLimiter`.
+pub struct RequestSlot {
+    limiter: Arc<RateLimiter>,
+    client: String,
+    method: LimitedMethod,
+}
+
+impl RequestSlot {
+    /// Counts `bytes` uploaded by the client against its byte limit.
+    pub fn record_bytes(&self, bytes: usize) {
+        if self.limiter.max_bytes_per_second == 0 {
+            return;
+        }
+        if let Some(state) = self.limiter.clients.lock().get_mut(&self.client) {
+            state.available_bytes -= bytes as f64;
+        }
+    }
+}
+
+impl Drop for RequestSlot {
+    fn drop(&mut self) {
+        let max_bytes_per_second = self.limiter.max_bytes_per_second;
+        let mut clients = self.limiter.clients.lock();
+        let Some(state) = clients.get_mut(&self.client) else {
+            return;
+        };
+        match self.method {
+            LimitedMethod::ByteStreamWrite => state.bytestream_writes -= 1,
+            LimitedMethod::Execute => state.executes -= 1,
+            LimitedMethod::Other => {}
+        }
+        state.requests -= 1;
+        if state.requests == 0 {
+            // Clients that are over their byte limit are kept, otherwise
+            // they could start over with their next request.
+            state.refill(max_bytes_per_second, Instant::now());
+            if state.available_bytes >= max_bytes_per_second as f64 {
+                clients.remove(&self.client);
+            }
+        }
+    }
+}
+
+pin_project! {
+    /// Body of a request admitted by the `RateLimiter` or of its response.
+    /// Both hold the slot of the request, as the response of an `Execute`
+    /// request streams until the action completed. The bytes of request
+    /// bodies count against the byte limit of the client.
+    pub struct RateLimitedBody<B> {
+        #[pin]
+        inner: B,
+        slot: Option<Arc<RequestSlot>>,
+        counts_bytes: bool,
+    }
+}
+
+impl<B> RateLimitedBody<B> {
+    const fn request(inner: B, slot: Arc<RequestSlot>) -> Self {
+        Self {
+            inner,
+            slot: Some(slot),
+            counts_bytes: true,
+        }
+    }
+
+    const fn response(inner: B, slot: Option<Arc<RequestSlot>>) -> Self {
+        Self {
+            inner,
+            slot,
+            counts_bytes: false,
+        }
+    }
+}
+
+impl<B: Body> Body for RateLimitedBody<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let poll = this.inner.poll_frame(cx);
+        if let (Poll::Ready(Some(Ok(frame))), Some(slot), true) =
+            (&poll, this.slot.as_ref(), *this.counts_bytes)
+        {
+            if let Some(data) = frame.data_ref() {
+                slot.record_bytes(data.remaining());
+            }
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimitMiddlewareLayer {
+    limiter: Arc<RateLimiter>,
+}
+
+impl RateLimitMiddlewareLayer {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        Self {
+            limiter: Arc::new(RateLimiter::new(config)),
+        }
+    }
+}
+
+impl<S> Layer<S> for RateLimitMiddlewareLayer {
+    type Service = RateLimitMiddleware<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        RateLimitMiddleware {
+            inner: service,
+            limiter: self.limiter.clone(),
+        }
+    }
+}
+
+/// Rejects requests of clients over their limits with
+/// `RESOURCE_EXHAUSTED`. Requests are only checked when they start, the
+/// bytes of a request body in flight are counted but never throttled. It must be wrapped by the `AuthMiddleware` and the
+/// `OriginEventMiddleware`, so the identity of the client is known.
+#[derive(Clone)]
+pub struct RateLimitMiddleware<S> {
+    inner: S,
+    limiter: Arc<RateLimiter>,
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for RateLimitMiddleware<S>
+where
+    S: Service<http::Request<RateLimitedBody<ReqBody>>, Response = http::Response<ResBody>>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+    ReqBody: Send + 'static,
+    ResBody: Default + Send + 'static,
+{
+    type Response = http::Response<RateLimitedBody<ResBody>>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<ReqBody>) -> Self::Future {
+        // We must take the current `inner` and not the clone.
+        // See: https://docs.rs/tower/latest/tower/trait.Service.html#be-careful-when-cloning-inner-services
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let limiter = self.limiter.clone();
+        let address = req
+            .extensions()
+            .get::<ClientAddress>()
+            .map(|address| address.0.ip());
+
+        Box::pin(async move {
+            // The identity is only known while the future of the outer
+            // middlewares is polled, not when they call this service.
+            let client = client_key(address);
+            let slot = match limiter.admit(&client, req.uri().path()) {
+                Ok(slot) => Arc::new(slot),
+                Err(err) => return Ok(resource_exhausted_response(err)),
+            };
+            let req = req.map(|body| RateLimitedBody::request(body, slot.clone()));
+            let response = inner.call(req).await?;
+            Ok(response.map(|body| RateLimitedBody::response(body, Some(slot))))
+        })
+    }
+}
+
+/// Clients are told apart by their identity, or their IP address if they
+/// have none.
+fn client_key(address: Option<IpAddr>) -> String {
+    let identity = active_identity();
+    if !identity.is_empty() {
+        return identity;
+    }
+    address.map_or_else(String::new, |address| address.to_string())
+}
+
+/// A gRPC response without a message, which only has the status of `err`.
+fn resource_exhausted_response<ResBody: Default>(
+    err: Error,
+) -> http::Response<RateLimitedBody<ResBody>> {
+    let mut response = http::Response::new(RateLimitedBody::response(ResBody::default(), None));
+    response.headers_mut().insert(
+        http::header::CONTENT_TYPE,
+        HeaderValue::from_static("application/grpc"),
+    );
+    // Only fails for messages that can not be percent-encoded, in which case
+    // the client still gets the code.
+    let status = tonic::Status::from(err);
+    if status.add_header(response.headers_mut()).is_err() {
+        response.headers_mut().insert(
+            "grpc-status",
+            HeaderValue::from(tonic::Code::ResourceExhausted as i32),
+        );
+    }
+    response
+}
@@ -17,6 +17,8 @@ use std::convert::AsRef;
 
 use nativelink_error::{error_if, make_input_err, Error, ResultExt};
 
+use crate::common::DigestInfo;
+
 const ERROR_MSG: &str = concat!(
     "Expected resource_name to be of pattern ",
     "'{?instance_name/}(?uploads/{uuid}/)blobs/{?/digest_function}{/hash}/{size}{?/optional_metadata}' or ",
@@ -196,6 +198,84 @@ impl<'a> ResourceInfo<'a> {
     }
 }
 
+/// The part of the resource names of live action output streams in front of
+/// the action digest.
+const ACTION_OUTPUTS: &str = "action-outputs";
+
+/// An output of a process that is streamed while it runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ActionOutput {
+    Stdout,
+    Stderr,
+}
+
+impl ActionOutput {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Stdout => "stdout",
+            Self::Stderr => "stderr",
+        }
+    }
+}
+
+/// The name of the live stdout or stderr stream of a running action, which
+/// the worker writes and clients read through the `ByteStream` service. It
+/// is of the pattern
+/// `'{?instance_name/}action-outputs/{hash}/{size}/{stdout|stderr}'` with
+/// the digest of the action.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ActionOutputStreamName {
+    pub instance_name: String,
+    pub action_digest: DigestInfo,
+    pub output: ActionOutput,
+}
+
+impl ActionOutputStreamName {
+    pub fn new(instance_name: &str, action_digest: DigestInfo, output: ActionOutput) -> Self {
+        Self {
+            instance_name: instance_name.to_string(),
+            action_digest,
+            output,
+        }
+    }
+
+    /// Returns the stream `resource_name` names, or `None` if it is not the
+    /// name of an action output stream.
+    pub fn parse(resource_name: &str) -> Option<Self> {
+        let mut rparts = resource_name.rsplitn(5, '/');
+        let output = match rparts.next()? {
+            "stdout" => ActionOutput::Stdout,
+            "stderr" => ActionOutput::Stderr,
+            _ => return None,
+        };
+        let size = rparts.next()?.parse::<u64>().ok()?;
+        let hash = rparts.next()?;
+        if rparts.next()? != ACTION_OUTPUTS {
+            return None;
+        }
+        Some(Self {
+            instance_name: rparts.next().unwrap_or_default().to_string(),
+            action_digest: DigestInfo::try_new(hash, size).ok()?,
+            output,
+        })
+    }
+}
+
+impl std::fmt::Display for ActionOutputStreamName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.instance_name.is_empty() {
+            write!(f, "{}/", self.instance_name)?;
+        }
+        write!(
+            f,
+            "{ACTION_OUTPUTS}/{}/{}/{}",
+            self.action_digest.packed_hash(),
+            self.action_digest.size_bytes(),
+            self.output.as_str()
+        )
+    }
+}
+
 #[derive(Debug, PartialEq)]
 enum State {
     Unknown,
@@ -20,6 +20,7 @@ use futures::future::Future;
 use futures::stream::StreamExt;
 use nativelink_config::stores::{ErrorCode, Retry};
 use nativelink_error::{make_err, Code, Error};
+use parking_lot::Mutex;
 use tracing::{event, Level};
 
 struct ExponentialBackoff {
@@ -52,11 +53,12 @@ pub enum RetryResult<T> {
 }
 
 /// Class used to retry a job with a sleep function in between each retry.
+/// Clones share their config, so `set_config()` applies to all of them.
 #[derive(Clone)]
 pub struct Retrier {
     sleep_fn: SleepFn,
     jitter_fn: JitterFn,
-    config: Retry,
+    config: Arc<Mutex<Retry>>,
 }
 
 fn to_error_code(code: Code) -> ErrorCode {
@@ -85,16 +87,23 @@ impl Retrier {
         Retrier {
             sleep_fn,
             jitter_fn,
-            config,
+            config: Arc::new(Mutex::new(config)),
         }
     }
 
+    /// Replaces the config jobs are retried with. Jobs that are already
+    /// being retried keep their delays. The jitter is fixed when the
+    /// retrier is created and is not changed.
+    pub fn set_config(&self, config: Retry) {
+        *self.config.lock() = config;
+    }
+
     /// This should only return true if the error code should be interpreted as
     /// temporary.
     fn should_retry(&self, code: Code) -> bool {
         if code == Code::Ok {
             false
-        } else if let Some(retry_codes) = &self.config.retry_on_errors {
+        } else if let Some(retry_codes) = &self.config.lock().retry_on_errors {
             retry_codes.contains(&to_error_code(code))
         } else {
             match code {
@@ -122,9 +131,13 @@ impl Retrier {
     }
 
     fn get_retry_config(&self) -> impl Iterator<Item = Duration> + '_ {
-        ExponentialBackoff::new(Duration::from_millis(self.config.delay as u64))
+        let (delay, max_retries) = {
+            let config = self.config.lock();
+            (config.delay, config.max_retries)
+        };
+        ExponentialBackoff::new(Duration::from_millis(delay as u64))
             .map(|d| (self.jitter_fn)(d))
-            .take(self.config.max_retries) // Remember this is number of retries, so will run max_retries + 1.
+            .take(max_retries) // Remember this is number of retries, so will run max_retries + 1.
     }
 
     // Clippy complains that this function can be `async fn`, but this is not true.
@@ -24,7 +24,9 @@ use std::sync::{Arc, OnceLock};
 use async_trait::async_trait;
 use bytes::{Bytes, BytesMut};
 use futures::future::{select, Either};
-use futures::{join, try_join, Future, FutureExt, Stream};
+use futures::stream::FuturesOrdered;
+use futures::{join, try_join, Future, FutureExt, Stream, StreamExt};
+use nativelink_config::stores::StoreSpec;
 use nativelink_error::{error_if, make_err, Code, Error, ResultExt};
 use nativelink_metric::MetricsComponent;
 use rand::rngs::StdRng;
@@ -73,6 +75,22 @@ pub enum UploadSizeInfo {
     MaxSize(u64),
 }
 
+/// Utility to send many objects to the store with one `update_oneshot()`
+/// call per object. The results are in the same order as `items`.
+// Note: This is not inlined because stores that override `update_many()`
+// may want to fall back to it for some of the objects.
+pub async fn slow_update_many<S: StoreDriver + ?Sized>(
+    store: Pin<&S>,
+    items: Vec<(StoreKey<'_>, Bytes)>,
+) -> Vec<Result<(), Error>> {
+    items
+        .into_iter()
+        .map(|(key, data)| store.update_oneshot(key, data))
+        .collect::<FuturesOrdered<_>>()
+        .collect()
+        .await
+}
+
 /// Utility to send all the data to the store from a file.
 // Note: This is not inlined because some code may want to bypass any underlying
 // optimizations that may be present in the inner store.
@@ -142,6 +160,10 @@ pub enum StoreOptimizations {
 
     /// If the store will never serve downloads.
     NoopDownloads,
+
+    /// The store lists a range of keys without visiting the keys outside
+    /// of it, so listing all the sizes of a hash is cheap.
+    OrderedList,
 }
 
 /// A wrapper struct for [`StoreKey`] to work around
@@ -235,6 +257,20 @@ impl<'a> StoreKey<'a> {
         }
     }
 
+    /// The reverse of [`StoreKey::as_str`], used by stores that keep the keys
+    /// as strings in the backend. Strings in the `{hash}-{size}` format of
+    /// digests are parsed into digest keys.
+    pub fn from_encoded_str(s: &str) -> StoreKey<'static> {
+        let digest = s.split_once('-').and_then(|(hash, size)| {
+            let size_bytes = size.parse::<u64>().ok()?;
+            DigestInfo::try_new(hash, size_bytes).ok()
+        });
+        match digest {
+            Some(digest) => StoreKey::Digest(digest),
+            None => StoreKey::Str(Cow::Owned(s.to_string())),
+        }
+    }
+
     /// Returns the key as a string. If the key is a digest, it will
     /// return a string representation of the digest. If the key is a string,
     /// it will return the string itself.
@@ -371,6 +407,24 @@ impl Store {
     pub fn register_health(&self, registry: &mut HealthRegistryBuilder) {
         self.inner.clone().register_health(registry);
     }
+
+    /// Checks `spec` against the whole tree of stores before applying it, so
+    /// a spec that can't be applied leaves every store unchanged.
+    /// See: [`StoreDriver::reconfigure`] for details.
+    #[inline]
+    pub async fn reconfigure(&self, spec: &StoreSpec) -> Result<(), Error> {
+        self.inner
+            .reconfigure(spec, true)
+            .await
+            .err_tip(|| "While checking the new spec")?;
+        self.inner.reconfigure(spec, false).await
+    }
+
+    /// See: [`StoreDriver::evict`] for details.
+    #[inline]
+    pub async fn evict(&self, bytes: u64) -> Result<u64, Error> {
+        self.inner.evict(bytes).await
+    }
 }
 
 impl StoreLike for Store {
@@ -476,6 +530,16 @@ pub trait StoreLike: Send + Sync + Sized + Unpin + 'static {
         }
     }
 
+    /// Removes the object from the store. Returns whether the object was
+    /// in the store. Stores that can't remove objects return an error.
+    #[inline]
+    fn remove<'a>(
+        &'a self,
+        key: impl Into<StoreKey<'a>>,
+    ) -> impl Future<Output = Result<bool, Error>> + Send + 'a {
+        self.as_store_driver_pin().remove(key.into())
+    }
+
     /// Sends the data to the store.
     #[inline]
     fn update<'a>(
@@ -488,6 +552,18 @@ pub trait StoreLike: Send + Sync + Sized + Unpin + 'static {
             .update(digest.into(), reader, upload_size)
     }
 
+    /// Sends many objects that are fully in memory to the store. This is
+    /// meant for lots of small objects (ie: `BatchUpdateBlobs`), where stores
+    /// can save the overhead of one request per object. The result for each
+    /// object is returned in the same order as `items`.
+    #[inline]
+    fn update_many<'a>(
+        &'a self,
+        items: Vec<(StoreKey<'a>, Bytes)>,
+    ) -> impl Future<Output = Vec<Result<(), Error>>> + Send + 'a {
+        self.as_store_driver_pin().update_many(items)
+    }
+
     /// Any optimizations the store might want to expose to the callers.
     /// By default, no optimizations are exposed.
     #[inline]
@@ -618,6 +694,14 @@ pub trait StoreDriver:
         ))
     }
 
+    /// See: [`StoreLike::remove`] for details.
+    async fn remove(self: Pin<&Self>, _key: StoreKey<'_>) -> Result<bool, Error> {
+        Err(make_err!(
+            Code::Unimplemented,
+            "Store::remove() not implemented for this store"
+        ))
+    }
+
     /// See: [`StoreLike::update`] for details.
     async fn update(
         self: Pin<&Self>,
@@ -626,6 +710,14 @@ pub trait StoreDriver:
         upload_size: UploadSizeInfo,
     ) -> Result<(), Error>;
 
+    /// See: [`StoreLike::update_many`] for details.
+    async fn update_many(
+        self: Pin<&Self>,
+        items: Vec<(StoreKey<'_>, Bytes)>,
+    ) -> Vec<Result<(), Error>> {
+        slow_update_many(self, items).await
+    }
+
     /// See: [`StoreLike::optimized_for`] for details.
     fn optimized_for(&self, _optimization: StoreOptimizations) -> bool {
         false
@@ -815,6 +907,29 @@ pub trait StoreDriver:
 
     // Register health checks used to monitor the store.
     fn register_health(self: Arc<Self>, _registry: &mut HealthRegistryBuilder) {}
+
+    /// Applies the settings of `spec` that can be changed while the store is
+    /// running, like eviction limits and retry policies. `spec` is the spec
+    /// the store was created from, after the config was reloaded. Stores
+    /// containing other stores pass the specs of those on to them.
+    ///
+    /// If `check_only` is set, the spec is only checked and nothing is
+    /// changed. Anything that makes the spec fail must be found then, as
+    /// the stores reconfigured before a failure keep their new settings.
+    async fn reconfigure(&self, _spec: &StoreSpec, _check_only: bool) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Evicts the entries that are expired or over the limits of the store
+    /// right away, then at least `bytes` more in eviction order. Returns the
+    /// number of bytes evicted. Only stores that evict on their own support
+    /// this.
+    async fn evict(&self, _bytes: u64) -> Result<u64, Error> {
+        Err(make_err!(
+            Code::Unimplemented,
+            "Store does not support eviction"
+        ))
+    }
 }
 
 /// The instructions on how to decode a value from a Bytes & version into
@@ -0,0 +1,189 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Export of spans to an OpenTelemetry collector and propagation of the W3C
+//! trace context between processes.
+//!
+//! Like the log format, the export is configured from the environment,
+//! because tracing is set up before the config is parsed. It is enabled by
+//! setting `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT` or
+//! `OTEL_EXPORTER_OTLP_ENDPOINT` to the OTLP/HTTP endpoint of the collector.
+//! `NL_OTEL_FILTER` selects the exported spans with the syntax of
+//! `RUST_LOG`, "info" if not set.
+
+use std::collections::HashMap;
+use std::task::{Context, Poll};
+
+use hyper::http::{self, HeaderMap, HeaderName, HeaderValue};
+use nativelink_error::{make_err, Code, Error};
+use opentelemetry::propagation::{Extractor, Injector, TextMapPropagator};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::{global, KeyValue};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::runtime::TokioCurrentThread;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::Resource;
+use tower::layer::Layer;
+use tower::Service;
+use tracing::instrument::Instrumented;
+use tracing::{info_span, Instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{EnvFilter, Layer as _, Registry};
+
+const OTLP_ENDPOINT_ENV_VARS: [&str; 2] = [
+    "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT",
+    "OTEL_EXPORTER_OTLP_ENDPOINT",
+];
+const OTEL_FILTER_ENV: &str = "NL_OTEL_FILTER";
+const DEFAULT_OTEL_FILTER: &str = "info";
+const DEFAULT_SERVICE_NAME: &str = "nativelink";
+
+/// Creates the layer exporting spans, if an OTLP endpoint is configured.
+pub(crate) fn make_otel_layer(
+) -> Result<Option<Box<dyn tracing_subscriber::Layer<Registry> + Send + Sync>>, Error> {
+    if !OTLP_ENDPOINT_ENV_VARS
+        .iter()
+        .any(|var| std::env::var_os(var).is_some())
+    {
+        return Ok(None);
+    }
+    let filter = EnvFilter::try_new(
+        std::env::var(OTEL_FILTER_ENV).unwrap_or_else(|_| DEFAULT_OTEL_FILTER.to_string()),
+    )
+    .map_err(|e| make_err!(Code::InvalidArgument, "Invalid {OTEL_FILTER_ENV}: {e:?}"))?;
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .build()
+        .map_err(|e| make_err!(Code::Internal, "Could not create OTLP exporter: {e:?}"))?;
+    let resource = if std::env::var_os("OTEL_SERVICE_NAME").is_some() {
+        Resource::default()
+    } else {
+        Resource::default().merge(&Resource::new([KeyValue::new(
+            "service.name",
+            DEFAULT_SERVICE_NAME,
+        )]))
+    };
+    // Batches are sent from a thread of their own, so spans can be exported
+    // before and outside of the runtime of the process.
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, TokioCurrentThread)
+        .with_resource(resource)
+        .build();
+    let tracer = provider.tracer(DEFAULT_SERVICE_NAME);
+    global::set_tracer_provider(provider);
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    Ok(Some(
+        tracing_opentelemetry::layer()
+            .with_tracer(tracer)
+            .with_filter(filter)
+            .boxed(),
+    ))
+}
+
+/// Returns the W3C trace context of the current span, to continue its trace
+/// in another process. Empty if spans are not exported.
+pub fn current_trace_context() -> HashMap<String, String> {
+    let mut trace_context = HashMap::new();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&Span::current().context(), &mut trace_context);
+    });
+    trace_context
+}
+
+/// Makes `span` a child of the span `trace_context` was taken from with
+/// [`current_trace_context`].
+pub fn set_trace_parent(span: &Span, trace_context: &HashMap<String, String>) {
+    if trace_context.is_empty() {
+        return;
+    }
+    span.set_parent(global::get_text_map_propagator(|propagator| {
+        propagator.extract(trace_context)
+    }));
+}
+
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(HeaderName::as_str).collect()
+    }
+}
+
+/// Adds the trace context of the current span to the headers of an outgoing
+/// request.
+pub fn inject_trace_context(headers: &mut HeaderMap) {
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&Span::current().context(), &mut HeaderInjector(headers));
+    });
+}
+
+/// Runs every request in a span that continues the trace of the client, if
+/// the request carries a trace context.
+#[derive(Clone, Default)]
+pub struct TraceContextMiddlewareLayer;
+
+impl<S> Layer<S> for TraceContextMiddlewareLayer {
+    type Service = TraceContextMiddleware<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        TraceContextMiddleware { inner: service }
+    }
+}
+
+#[derive(Clone)]
+pub struct TraceContextMiddleware<S> {
+    inner: S,
+}
+
+impl<S, ReqBody> Service<http::Request<ReqBody>> for TraceContextMiddleware<S>
+where
+    S: Service<http::Request<ReqBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Instrumented<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<ReqBody>) -> Self::Future {
+        let span = info_span!(
+            "grpc_request",
+            otel.name = req.uri().path(),
+            otel.kind = "server"
+        );
+        span.set_parent(global::get_text_map_propagator(|propagator| {
+            propagator.extract(&HeaderExtractor(req.headers()))
+        }));
+        self.inner.call(req).instrument(span)
+    }
+}
@@ -12,9 +12,21 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::fmt;
+use std::sync::Arc;
+
+use nativelink_config::cas_server::TlsConfig;
 use nativelink_config::stores::{ClientTlsConfig, GrpcEndpoint};
-use nativelink_error::{make_err, make_input_err, Code, Error};
+use nativelink_error::{make_err, make_input_err, Code, Error, ResultExt};
+use parking_lot::Mutex;
+use rustls_pemfile::{certs as extract_certs, crls as extract_crls};
+use tokio_rustls::rustls::crypto::ring::sign::any_supported_type;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier};
+use tokio_rustls::rustls::sign::CertifiedKey;
+use tokio_rustls::rustls::{RootCertStore, ServerConfig as TlsServerConfig};
 use tonic::transport::Uri;
+use x509_parser::parse_x509_certificate;
 
 pub fn load_client_config(
     config: &Option<ClientTlsConfig>,
@@ -110,3 +122,195 @@ pub fn endpoint(endpoint_config: &GrpcEndpoint) -> Result<tonic::transport::Endp
         Ok(endpoint)
     }
 }
+
+fn read_certs(cert_file: &str) -> Result<Vec<CertificateDer<'static>>, Error> {
+    let mut cert_reader = std::io::BufReader::new(
+        std::fs::File::open(cert_file)
+            .err_tip(|| format!("Could not open cert file {cert_file}"))?,
+    );
+    extract_certs(&mut cert_reader)
+        .collect::<Result<Vec<CertificateDer<'_>>, _>>()
+        .err_tip(|| format!("Could not extract certs from file {cert_file}"))
+}
+
+fn read_key(key_file: &str) -> Result<PrivateKeyDer<'static>, Error> {
+    let mut key_reader = std::io::BufReader::new(
+        std::fs::File::open(key_file).err_tip(|| format!("Could not open key file {key_file}"))?,
+    );
+    let key = match rustls_pemfile::read_one(&mut key_reader)
+        .err_tip(|| format!("Could not extract key(s) from file {key_file}"))?
+    {
+        Some(rustls_pemfile::Item::Pkcs8Key(key)) => key.into(),
+        Some(rustls_pemfile::Item::Sec1Key(key)) => key.into(),
+        Some(rustls_pemfile::Item::Pkcs1Key(key)) => key.into(),
+        _ => {
+            return Err(make_err!(
+                Code::Internal,
+                "No keys found in file {key_file}"
+            ))
+        }
+    };
+    if let Ok(Some(_)) = rustls_pemfile::read_one(&mut key_reader) {
+        return Err(make_err!(
+            Code::InvalidArgument,
+            "Expected 1 key in file {key_file}"
+        ));
+    }
+    Ok(key)
+}
+
+/// Serves the certificate in `cert_file` and `key_file` and can reload it
+/// when the files change, so certificates can be rotated without a restart.
+pub struct ReloadingCertResolver {
+    cert_file: String,
+    key_file: String,
+    /// The content of the files and the key that was loaded from them.
+    current: Mutex<(Vec<u8>, Arc<CertifiedKey>)>,
+}
+
+impl ReloadingCertResolver {
+    pub fn new(cert_file: &str, key_file: &str) -> Result<Self, Error> {
+        let (files_content, certified_key) = Self::load(cert_file, key_file)?;
+        Ok(Self {
+            cert_file: cert_file.to_string(),
+            key_file: key_file.to_string(),
+            current: Mutex::new((files_content, certified_key)),
+        })
+    }
+
+    fn load(cert_file: &str, key_file: &str) -> Result<(Vec<u8>, Arc<CertifiedKey>), Error> {
+        let mut files_content =
+            std::fs::read(cert_file).err_tip(|| format!("Could not read cert file {cert_file}"))?;
+        files_content.extend(
+            std::fs::read(key_file).err_tip(|| format!("Could not read key file {key_file}"))?,
+        );
+        let certs = read_certs(cert_file)?;
+        let key = any_supported_type(&read_key(key_file)?).map_err(|e| {
+            make_err!(
+                Code::InvalidArgument,
+                "Unsupported key in {key_file}: {e:?}"
+            )
+        })?;
+        Ok((files_content, Arc::new(CertifiedKey::new(certs, key))))
+    }
+
+    /// Loads the certificate again if `cert_file` or `key_file` changed and
+    /// returns if it did. The previous certificate is kept if the new one
+    /// can not be loaded.
+    pub fn reload_if_changed(&self) -> Result<bool, Error> {
+        let (files_content, certified_key) = Self::load(&self.cert_file, &self.key_file)
+            .err_tip(|| "In ReloadingCertResolver::reload_if_changed")?;
+        let mut current = self.current.lock();
+        if current.0 == files_content {
+            return Ok(false);
+        }
+        *current = (files_content, certified_key);
+        Ok(true)
+    }
+}
+
+impl fmt::Debug for ReloadingCertResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReloadingCertResolver")
+            .field("cert_file", &self.cert_file)
+            .field("key_file", &self.key_file)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ResolvesServerCert for ReloadingCertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.lock().1.clone())
+    }
+}
+
+/// Creates the TLS configuration of a server. The certificate is served by
+/// the returned resolver, which can be used to reload it.
+pub fn load_server_config(
+    config: &TlsConfig,
+) -> Result<(TlsServerConfig, Arc<ReloadingCertResolver>), Error> {
+    let verifier = if let Some(client_ca_file) = &config.client_ca_file {
+        let mut client_auth_roots = RootCertStore::empty();
+        for cert in read_certs(client_ca_file)? {
+            client_auth_roots
+                .add(cert)
+                .map_err(|e| make_err!(Code::Internal, "Could not read client CA: {e:?}"))?;
+        }
+        let crls = if let Some(client_crl_file) = &config.client_crl_file {
+            let mut crl_reader = std::io::BufReader::new(
+                std::fs::File::open(client_crl_file)
+                    .err_tip(|| format!("Could not open CRL file {client_crl_file}"))?,
+            );
+            extract_crls(&mut crl_reader)
+                .collect::<Result<_, _>>()
+                .err_tip(|| format!("Could not extract CRLs from file {client_crl_file}"))?
+        } else {
+            Vec::new()
+        };
+        WebPkiClientVerifier::builder(Arc::new(client_auth_roots))
+            .with_crls(crls)
+            .build()
+            .map_err(|e| {
+                make_err!(
+                    Code::Internal,
+                    "Could not create WebPkiClientVerifier: {e:?}"
+                )
+            })?
+    } else {
+        if !config.allowed_client_common_names.is_empty() {
+            return Err(make_input_err!(
+                "allowed_client_common_names requires client_ca_file to be set"
+            ));
+        }
+        WebPkiClientVerifier::no_client_auth()
+    };
+    let resolver = Arc::new(ReloadingCertResolver::new(
+        &config.cert_file,
+        &config.key_file,
+    )?);
+    let mut server_config = TlsServerConfig::builder()
+        .with_client_cert_verifier(verifier)
+        .with_cert_resolver(resolver.clone());
+    server_config.alpn_protocols.push("h2".into());
+    Ok((server_config, resolver))
+}
+
+/// Returns the common names in the subject of a DER encoded certificate.
+pub fn cert_common_names(cert: &CertificateDer<'_>) -> Result<Vec<String>, Error> {
+    let (_, cert) = parse_x509_certificate(cert)
+        .map_err(|e| make_input_err!("Could not parse certificate: {e:?}"))?;
+    cert.subject()
+        .iter_common_name()
+        .map(|common_name| {
+            common_name
+                .as_str()
+                .map(ToString::to_string)
+                .map_err(|e| make_input_err!("Invalid common name in certificate: {e:?}"))
+        })
+        .collect()
+}
+
+/// Checks that the certificate a client connected with has one of the
+/// `allowed_common_names`. Any certificate is allowed if the list is empty.
+pub fn check_client_common_name(
+    peer_certificates: Option<&[CertificateDer<'_>]>,
+    allowed_common_names: &[String],
+) -> Result<(), Error> {
+    if allowed_common_names.is_empty() {
+        return Ok(());
+    }
+    let cert = peer_certificates
+        .and_then(<[CertificateDer]>::first)
+        .ok_or_else(|| make_err!(Code::PermissionDenied, "No client certificate was sent"))?;
+    let common_names = cert_common_names(cert)?;
+    if common_names
+        .iter()
+        .any(|common_name| allowed_common_names.contains(common_name))
+    {
+        return Ok(());
+    }
+    Err(make_err!(
+        Code::PermissionDenied,
+        "Client certificate with common names {common_names:?} is not allowed"
+    ))
+}
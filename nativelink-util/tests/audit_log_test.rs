@@ -0,0 +1,128 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use nativelink_error::Error;
+use nativelink_macro::nativelink_test;
+use nativelink_proto::build::bazel::remote::execution::v2::Digest;
+use nativelink_util::audit_log::{
+    audit_record, AuditLogPublisher, AuditLogWriter, AuditOperation, AuditRecord,
+};
+use nativelink_util::fs;
+use pretty_assertions::assert_eq;
+use rand::{thread_rng, Rng};
+use tokio::sync::{broadcast, mpsc};
+
+/// Get temporary path from either `TEST_TMPDIR` or best effort temp directory if
+/// not set.
+async fn make_temp_path(data: &str) -> PathBuf {
+    let dir = format!(
+        "{}/{}",
+        env::var("TEST_TMPDIR").unwrap_or(env::temp_dir().to_str().unwrap().to_string()),
+        thread_rng().gen::<u64>(),
+    );
+    fs::create_dir_all(&dir).await.unwrap();
+    PathBuf::from(format!("{dir}/{data}"))
+}
+
+/// Writes `records` as one batch to the file at `path`.
+async fn publish(path: &Path, max_bytes: u64, records: Vec<AuditRecord>) {
+    let (tx, rx) = mpsc::channel(records.len());
+    for record in records {
+        tx.send(record).await.unwrap();
+    }
+    drop(tx);
+    let writer = AuditLogWriter::File {
+        path: path.to_path_buf(),
+        max_bytes,
+        max_files: 2,
+    };
+    let (shutdown_tx, _) = broadcast::channel(1);
+    AuditLogPublisher::new(writer, rx, shutdown_tx).run().await;
+}
+
+fn cas_write_record(hash: &str) -> AuditRecord {
+    let digest = Digest {
+        hash: hash.to_string(),
+        size_bytes: 5,
+    };
+    let mut record = AuditRecord::new(AuditOperation::CasWrite, "main")
+        .with_digests([&digest])
+        .with_upload_bytes(5);
+    record.succeeded = true;
+    record
+}
+
+#[nativelink_test]
+async fn writes_json_lines_test() -> Result<(), Error> {
+    let path = make_temp_path("audit.log").await;
+    let mut execute_record = AuditRecord::new(AuditOperation::Execute, "main");
+    execute_record.command = vec!["gcc".to_string(), "-c".to_string(), "a.c".to_string()];
+    publish(
+        &path,
+        u64::MAX,
+        vec![cas_write_record("aa"), execute_record],
+    )
+    .await;
+
+    let contents = std::fs::read_to_string(&path)?;
+    let lines: Vec<serde_json::Value> = contents
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["operation"], "cas_write");
+    assert_eq!(lines[0]["instance_name"], "main");
+    assert_eq!(lines[0]["digests"], serde_json::json!(["aa-5"]));
+    assert_eq!(lines[0]["upload_bytes"], 5);
+    assert_eq!(lines[0]["succeeded"], true);
+    assert!(lines[0].get("command").is_none());
+    assert_eq!(lines[1]["operation"], "execute");
+    assert_eq!(lines[1]["command"], serde_json::json!(["gcc", "-c", "a.c"]));
+    assert!(lines[1].get("upload_bytes").is_none());
+    Ok(())
+}
+
+#[nativelink_test]
+async fn rotates_full_file_test() -> Result<(), Error> {
+    let path = make_temp_path("audit.log").await;
+    for hash in ["aa", "bb", "cc", "dd"] {
+        publish(&path, 1, vec![cas_write_record(hash)]).await;
+    }
+
+    let digests_of = |suffix: &str| {
+        let mut file_path = path.as_os_str().to_owned();
+        file_path.push(suffix);
+        let contents = std::fs::read_to_string(file_path).unwrap();
+        let record: serde_json::Value = serde_json::from_str(contents.trim()).unwrap();
+        record["digests"][0].as_str().unwrap().to_string()
+    };
+    assert_eq!(digests_of(""), "dd-5");
+    assert_eq!(digests_of(".1"), "cc-5");
+    assert_eq!(digests_of(".2"), "bb-5");
+    // Only `max_files` rotated files are kept.
+    let mut dropped_path = path.as_os_str().to_owned();
+    dropped_path.push(".3");
+    assert!(!PathBuf::from(dropped_path).exists());
+    Ok(())
+}
+
+#[nativelink_test]
+async fn disabled_audit_log_records_nothing_test() -> Result<(), Error> {
+    let record = audit_record(|| panic!("Audit log is not enabled"));
+    assert!(record.is_none());
+    Ok(())
+}
@@ -0,0 +1,355 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::env;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hyper::http::{HeaderMap, HeaderValue, Request, Response, StatusCode};
+use jsonwebtoken::{encode, EncodingKey, Header};
+use nativelink_config::cas_server::{AuthConfig, AuthTokenConfig, JwtAuthConfig};
+use nativelink_error::{Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_util::auth_middleware::{AuthMiddlewareLayer, Authenticator, ReadOnlyRequests};
+use nativelink_util::origin_context::{
+    ActiveOriginContext, OriginContext, AUTHENTICATED_IDENTITY, ORIGIN_IDENTITY,
+};
+use pretty_assertions::assert_eq;
+use tower::{service_fn, Layer, ServiceExt};
+
+const GET_ACTION_RESULT: &str = "/build.bazel.remote.execution.v2.ActionCache/GetActionResult";
+const UPDATE_ACTION_RESULT: &str =
+    "/build.bazel.remote.execution.v2.ActionCache/UpdateActionResult";
+const JWT_SECRET: &[u8] = b"secret-key-for-tests";
+const JWT_ISSUER: &str = "https://issuer.example.com";
+
+fn make_config() -> AuthConfig {
+    AuthConfig {
+        tokens: vec![
+            AuthTokenConfig {
+                token: "ci-token".to_string(),
+                identity: "ci".to_string(),
+                read_only: false,
+            },
+            AuthTokenConfig {
+                token: "dev-token".to_string(),
+                identity: "dev".to_string(),
+                read_only: true,
+            },
+        ],
+        ..Default::default()
+    }
+}
+
+fn headers_with_token(token: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "authorization",
+        HeaderValue::from_str(&format!("Bearer {token}")).unwrap(),
+    );
+    headers
+}
+
+#[nativelink_test]
+async fn static_tokens_test() -> Result<(), Error> {
+    let authenticator = Authenticator::new(&make_config())?;
+
+    assert_eq!(
+        authenticator.authorize(&headers_with_token("ci-token"), UPDATE_ACTION_RESULT)?,
+        Some("ci".to_string())
+    );
+    assert_eq!(
+        authenticator.authorize(&headers_with_token("dev-token"), GET_ACTION_RESULT)?,
+        Some("dev".to_string())
+    );
+    assert_eq!(
+        authenticator
+            .authorize(&headers_with_token("dev-token"), UPDATE_ACTION_RESULT)
+            .unwrap_err()
+            .code,
+        Code::PermissionDenied
+    );
+    assert_eq!(
+        authenticator
+            .authorize(&headers_with_token("wrong-token"), GET_ACTION_RESULT)
+            .unwrap_err()
+            .code,
+        Code::Unauthenticated
+    );
+    assert_eq!(
+        authenticator
+            .authorize(&HeaderMap::new(), GET_ACTION_RESULT)
+            .unwrap_err()
+            .code,
+        Code::Unauthenticated
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn anonymous_read_test() -> Result<(), Error> {
+    let authenticator = Authenticator::new(&AuthConfig {
+        allow_anonymous_read: true,
+        ..make_config()
+    })?;
+
+    assert_eq!(
+        authenticator.authorize(&HeaderMap::new(), GET_ACTION_RESULT)?,
+        None
+    );
+    assert_eq!(
+        authenticator
+            .authorize(&HeaderMap::new(), UPDATE_ACTION_RESULT)
+            .unwrap_err()
+            .code,
+        Code::PermissionDenied
+    );
+    // A wrong token is not treated as anonymous.
+    assert_eq!(
+        authenticator
+            .authorize(&headers_with_token("wrong-token"), GET_ACTION_RESULT)
+            .unwrap_err()
+            .code,
+        Code::Unauthenticated
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn jwt_test() -> Result<(), Error> {
+    let jwks_file = env::temp_dir()
+        .join(format!("auth_middleware_test_{}.json", std::process::id()))
+        .to_str()
+        .unwrap()
+        .to_string();
+    std::fs::write(
+        &jwks_file,
+        r#"{"keys": [{"kty": "oct", "kid": "test-key", "k": "c2VjcmV0LWtleS1mb3ItdGVzdHM"}]}"#,
+    )?;
+    let authenticator = Authenticator::new(&AuthConfig {
+        jwt: Some(JwtAuthConfig {
+            issuer: JWT_ISSUER.to_string(),
+            audience: None,
+            jwks_file,
+            identity_claim: String::new(),
+        }),
+        ..Default::default()
+    })?;
+
+    let make_jwt = |issuer: &str| {
+        let expires_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 3600;
+        let mut header = Header::default();
+        header.kid = Some("test-key".to_string());
+        encode(
+            &header,
+            &serde_json::json!({"sub": "ci", "iss": issuer, "exp": expires_at}),
+            &EncodingKey::from_secret(JWT_SECRET),
+        )
+        .unwrap()
+    };
+    assert_eq!(
+        authenticator.authorize(
+            &headers_with_token(&make_jwt(JWT_ISSUER)),
+            UPDATE_ACTION_RESULT
+        )?,
+        Some("ci".to_string())
+    );
+    assert_eq!(
+        authenticator
+            .authorize(
+                &headers_with_token(&make_jwt("https://other.example.com")),
+                UPDATE_ACTION_RESULT
+            )
+            .unwrap_err()
+            .code,
+        Code::Unauthenticated
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn middleware_sets_identity_test() -> Result<(), Box<dyn std::error::Error>> {
+    let layer = AuthMiddlewareLayer::new(&make_config())?;
+    let service = layer.layer(service_fn(|_request: Request<()>| async move {
+        let identity = ActiveOriginContext::get_value(&ORIGIN_IDENTITY)?
+            .map(|identity| identity.as_ref().clone())
+            .unwrap_or_default();
+        Ok::<_, Error>(Response::new(identity))
+    }));
+
+    let mut request = Request::builder()
+        .uri(UPDATE_ACTION_RESULT)
+        .body(())
+        .unwrap();
+    *request.headers_mut() = headers_with_token("ci-token");
+    let response = service.clone().oneshot(request).await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.into_body(), "ci");
+
+    let mut request = Request::builder()
+        .uri(UPDATE_ACTION_RESULT)
+        .body(())
+        .unwrap();
+    *request.headers_mut() = headers_with_token("dev-token");
+    let response = service.clone().oneshot(request).await?;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let request = Request::builder().uri(GET_ACTION_RESULT).body(()).unwrap();
+    let response = service.oneshot(request).await?;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn grpc_requests_get_grpc_status_test() -> Result<(), Box<dyn std::error::Error>> {
+    let layer = AuthMiddlewareLayer::new(&make_config())?;
+    let service = layer.layer(service_fn(|_request: Request<()>| async move {
+        Ok::<_, Error>(Response::new(String::new()))
+    }));
+    let grpc_status_of = |path: &str, token: Option<&str>| {
+        let mut request = Request::builder()
+            .uri(path)
+            .header("content-type", "application/grpc")
+            .body(())
+            .unwrap();
+        if let Some(token) = token {
+            request.headers_mut().extend(headers_with_token(token));
+        }
+        let service = service.clone();
+        async move {
+            let response = service.oneshot(request).await?;
+            assert_eq!(response.status(), StatusCode::OK);
+            Ok::<_, Error>(
+                response
+                    .headers()
+                    .get("grpc-status")
+                    .map(|status| status.to_str().unwrap().to_string()),
+            )
+        }
+    };
+
+    assert_eq!(
+        grpc_status_of(GET_ACTION_RESULT, None).await?,
+        Some("16".to_string())
+    );
+    assert_eq!(
+        grpc_status_of(UPDATE_ACTION_RESULT, Some("dev-token")).await?,
+        Some("7".to_string())
+    );
+    assert_eq!(
+        grpc_status_of(
+            "/google.longrunning.Operations/GetOperation",
+            Some("dev-token")
+        )
+        .await?,
+        None
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn anonymous_clients_have_empty_identity_test() -> Result<(), Box<dyn std::error::Error>> {
+    let layer = AuthMiddlewareLayer::new(&AuthConfig {
+        allow_anonymous_read: true,
+        ..make_config()
+    })?;
+    let service = layer.layer(service_fn(|_request: Request<()>| async move {
+        let identities = [&ORIGIN_IDENTITY, &AUTHENTICATED_IDENTITY].map(|symbol| {
+            ActiveOriginContext::get_value(symbol)
+                .ok()
+                .flatten()
+                .map(|identity| identity.as_ref().clone())
+        });
+        Ok::<_, Error>(Response::new(identities))
+    }));
+
+    // The identity the client sent in the identity header is replaced.
+    let mut ctx = OriginContext::new();
+    ctx.set_value(&ORIGIN_IDENTITY, Arc::new("ci".to_string()));
+    let request = Request::builder().uri(GET_ACTION_RESULT).body(()).unwrap();
+    let response = Arc::new(ctx)
+        .wrap_async(
+            tracing::trace_span!("anonymous_clients_have_empty_identity_test"),
+            service.oneshot(request),
+        )
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.into_body(),
+        [Some(String::new()), Some(String::new())]
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn http_requests_are_classified_by_method_test() -> Result<(), Box<dyn std::error::Error>> {
+    let layer = AuthMiddlewareLayer::new(&AuthConfig {
+        allow_anonymous_read: true,
+        ..make_config()
+    })?;
+    let status_of = |read_only_requests, method, token: Option<&str>| {
+        let service = layer
+            .with_read_only_requests(read_only_requests)
+            .layer(service_fn(|_request: Request<()>| async move {
+                Ok::<_, Error>(Response::new(String::new()))
+            }));
+        let mut request = Request::builder()
+            .method(method)
+            .uri("/ac/0123")
+            .body(())
+            .unwrap();
+        if let Some(token) = token {
+            *request.headers_mut() = headers_with_token(token);
+        }
+        async move {
+            service
+                .oneshot(request)
+                .await
+                .map(|response| response.status())
+        }
+    };
+
+    let http = ReadOnlyRequests::HttpGetAndHead;
+    assert_eq!(status_of(http, "GET", None).await?, StatusCode::OK);
+    assert_eq!(
+        status_of(http, "HEAD", Some("dev-token")).await?,
+        StatusCode::OK
+    );
+    assert_eq!(status_of(http, "PUT", None).await?, StatusCode::FORBIDDEN);
+    assert_eq!(
+        status_of(http, "PUT", Some("dev-token")).await?,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        status_of(http, "PUT", Some("ci-token")).await?,
+        StatusCode::OK
+    );
+
+    // Admin requests always need read-write access.
+    let admin = ReadOnlyRequests::Nothing;
+    assert_eq!(status_of(admin, "GET", None).await?, StatusCode::FORBIDDEN);
+    assert_eq!(
+        status_of(admin, "GET", Some("dev-token")).await?,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        status_of(admin, "POST", Some("ci-token")).await?,
+        StatusCode::OK
+    );
+    Ok(())
+}
@@ -0,0 +1,113 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bytes::Bytes;
+use nativelink_error::{Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_util::blob_compression::BlobCompressor;
+use nativelink_util::buf_channel::make_buf_channel_pair;
+use pretty_assertions::assert_eq;
+use tokio::try_join;
+
+/// Sends `data` in chunks of `chunk_size` through `compressor` and returns
+/// everything it wrote.
+async fn run_stream(data: &[u8], chunk_size: usize, compress: bool) -> Result<Bytes, Error> {
+    let (mut input_tx, mut input_rx) = make_buf_channel_pair();
+    let (mut output_tx, mut output_rx) = make_buf_channel_pair();
+    let send_fut = async move {
+        for chunk in data.chunks(chunk_size) {
+            input_tx.send(Bytes::copy_from_slice(chunk)).await?;
+        }
+        input_tx.send_eof()
+    };
+    let run_fut = async move {
+        if compress {
+            BlobCompressor::Zstd
+                .compress_stream(&mut input_rx, &mut output_tx)
+                .await
+        } else {
+            BlobCompressor::Zstd
+                .decompress_stream(&mut input_rx, &mut output_tx)
+                .await
+        }
+    };
+    let (_, _, output) = try_join!(send_fut, run_fut, output_rx.consume(None))?;
+    Ok(output)
+}
+
+#[nativelink_test]
+async fn zstd_stream_round_trip_test() -> Result<(), Error> {
+    // Large enough to span several output chunks.
+    let data: Vec<u8> = (0..1024 * 1024u32).map(|i| (i % 251) as u8).collect();
+
+    let compressed = run_stream(&data, 10_000, true).await?;
+    assert!(compressed.len() < data.len());
+    assert_eq!(
+        zstd::bulk::decompress(&compressed, data.len()).unwrap(),
+        data
+    );
+
+    let decompressed = run_stream(&compressed, 1000, false).await?;
+    assert_eq!(decompressed.len(), data.len());
+    assert_eq!(decompressed.as_ref(), data.as_slice());
+    Ok(())
+}
+
+#[nativelink_test]
+async fn zstd_decompress_truncated_data_fails_test() -> Result<(), Error> {
+    let data = vec![7u8; 100_000];
+    let compressed = zstd::bulk::compress(&data, 3).unwrap();
+    let err = run_stream(&compressed[..compressed.len() - 4], 100, false)
+        .await
+        .unwrap_err();
+    assert_eq!(err.code, Code::InvalidArgument, "{err:?}");
+    Ok(())
+}
+
+#[nativelink_test]
+async fn compressor_from_resource_name_test() -> Result<(), Error> {
+    assert_eq!(
+        BlobCompressor::from_resource_name(None)?,
+        BlobCompressor::Identity
+    );
+    assert_eq!(
+        BlobCompressor::from_resource_name(Some("identity"))?,
+        BlobCompressor::Identity
+    );
+    assert_eq!(
+        BlobCompressor::from_resource_name(Some("zstd"))?,
+        BlobCompressor::Zstd
+    );
+    let err = BlobCompressor::from_resource_name(Some("brotli")).unwrap_err();
+    assert_eq!(err.code, Code::InvalidArgument);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn bulk_round_trip_test() -> Result<(), Error> {
+    let data = Bytes::from("foobar".repeat(1000));
+    for compressor in [
+        BlobCompressor::Identity,
+        BlobCompressor::Zstd,
+        BlobCompressor::Deflate,
+    ] {
+        let compressed = compressor.compress(data.clone())?;
+        assert_eq!(compressor.decompress(compressed.clone(), data.len())?, data);
+        let err = compressor
+            .decompress(compressed, data.len() - 1)
+            .unwrap_err();
+        assert_eq!(err.code, Code::InvalidArgument, "{compressor:?}");
+    }
+    Ok(())
+}
@@ -12,9 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use nativelink_error::{make_input_err, Error};
+use nativelink_error::{make_input_err, Code, Error};
 use nativelink_macro::nativelink_test;
+use nativelink_proto::build::bazel::remote::execution::v2::Digest;
 use nativelink_util::common::DigestInfo;
+use nativelink_util::digest_hasher::{DigestHasher, DigestHasherFunc};
 use pretty_assertions::assert_eq;
 
 const MIN_DIGEST: &str = "0000000000000000000000000000000000000000000000000000000000000000-0";
@@ -130,3 +132,56 @@ async fn digest_info_deserialize_test() -> Result<(), Error> {
     }
     Ok(())
 }
+
+#[nativelink_test]
+async fn digest_info_sha512_round_trip_test() -> Result<(), Error> {
+    let mut hasher = DigestHasherFunc::Sha512.hasher();
+    hasher.update(b"hello");
+    let digest = hasher.finalize_digest();
+    assert_eq!(digest.packed_hash().len(), 64);
+    assert_eq!(
+        format!("{digest}"),
+        "9b71d224bd62f3785d96d46ad3ea3d73319bfbc2890caadae2dff72519673ca72323c3d99ba5c11d7c7acc6e14b8c5da0c4663475c2e5c3adef46f73bcdec043-5"
+    );
+
+    let proto_digest: Digest = digest.into();
+    assert_eq!(DigestInfo::try_from(&proto_digest)?, digest);
+    assert_eq!(
+        serde_json::from_str::<DigestInfo>(&serde_json::to_string(&digest).unwrap()).unwrap(),
+        digest
+    );
+
+    let mut hasher = DigestHasherFunc::Sha384.hasher();
+    hasher.update(b"hello");
+    assert_eq!(hasher.finalize_digest().packed_hash().len(), 48);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn digest_info_rejects_mismatched_digest_function_test() -> Result<(), Error> {
+    const SHA256_DIGEST: DigestInfo = DigestInfo::new([1u8; 32], 1);
+    const SHA512_DIGEST: DigestInfo = DigestInfo::from_hash([1u8; 64], 1);
+
+    assert_eq!(
+        DigestHasherFunc::Sha256.check_digest(&SHA256_DIGEST),
+        Ok(())
+    );
+    assert_eq!(
+        DigestHasherFunc::Sha512.check_digest(&SHA512_DIGEST),
+        Ok(())
+    );
+    let err = DigestHasherFunc::Sha256
+        .check_digest(&SHA512_DIGEST)
+        .expect_err("Expected a SHA-512 digest to be rejected for SHA256");
+    assert_eq!(err.code, Code::InvalidArgument);
+    assert!(
+        err.to_string()
+            .contains("digest function SHA256 makes 32 byte hashes"),
+        "Unexpected error: {err:?}"
+    );
+
+    // Hashes of no supported size are rejected while parsing.
+    let err = DigestInfo::try_new("0123456789abcdef", 1).expect_err("Expected short hash to fail");
+    assert_eq!(err.code, Code::InvalidArgument);
+    Ok(())
+}
@@ -18,7 +18,7 @@ use std::time::Duration;
 
 use bytes::Bytes;
 use mock_instant::thread_local::MockClock;
-use nativelink_config::stores::EvictionPolicy;
+use nativelink_config::stores::{EvictionAlgorithm, EvictionPolicy};
 use nativelink_error::Error;
 use nativelink_macro::nativelink_test;
 use nativelink_util::common::DigestInfo;
@@ -61,6 +61,7 @@ async fn insert_purges_at_max_count() -> Result<(), Error> {
             max_seconds: 0,
             max_bytes: 0,
             evict_bytes: 0,
+            ..Default::default()
         },
         MockInstantWrapped::default(),
     );
@@ -118,6 +119,7 @@ async fn insert_purges_at_max_bytes() -> Result<(), Error> {
             max_seconds: 0,
             max_bytes: 17,
             evict_bytes: 0,
+            ..Default::default()
         },
         MockInstantWrapped::default(),
     );
@@ -175,6 +177,7 @@ async fn insert_purges_to_low_watermark_at_max_bytes() -> Result<(), Error> {
             max_seconds: 0,
             max_bytes: 17,
             evict_bytes: 9,
+            ..Default::default()
         },
         MockInstantWrapped::default(),
     );
@@ -233,6 +236,7 @@ async fn insert_purges_at_max_seconds() -> Result<(), Error> {
             max_seconds: 5,
             max_bytes: 0,
             evict_bytes: 0,
+            ..Default::default()
         },
         MockInstantWrapped::default(),
     );
@@ -295,6 +299,7 @@ async fn get_refreshes_time() -> Result<(), Error> {
             max_seconds: 3,
             max_bytes: 0,
             evict_bytes: 0,
+            ..Default::default()
         },
         MockInstantWrapped::default(),
     );
@@ -375,6 +380,7 @@ async fn unref_called_on_replace() -> Result<(), Error> {
             max_seconds: 0,
             max_bytes: 0,
             evict_bytes: 0,
+            ..Default::default()
         },
         MockInstantWrapped::default(),
     );
@@ -420,6 +426,7 @@ async fn contains_key_refreshes_time() -> Result<(), Error> {
             max_seconds: 3,
             max_bytes: 0,
             evict_bytes: 0,
+            ..Default::default()
         },
         MockInstantWrapped::default(),
     );
@@ -473,6 +480,7 @@ async fn hashes_equal_sizes_different_doesnt_override() -> Result<(), Error> {
             max_seconds: 0,
             max_bytes: 0,
             evict_bytes: 0,
+            ..Default::default()
         },
         MockInstantWrapped::default(),
     );
@@ -528,6 +536,7 @@ async fn get_evicts_on_time() -> Result<(), Error> {
             max_seconds: 5,
             max_bytes: 0,
             evict_bytes: 0,
+            ..Default::default()
         },
         MockInstantWrapped::default(),
     );
@@ -561,6 +570,7 @@ async fn remove_evicts_on_time() -> Result<(), Error> {
             max_seconds: 5,
             max_bytes: 0,
             evict_bytes: 0,
+            ..Default::default()
         },
         MockInstantWrapped::default(),
     );
@@ -617,6 +627,7 @@ async fn range_multiple_items_test() -> Result<(), Error> {
             max_seconds: 0,
             max_bytes: 0,
             evict_bytes: 0,
+            ..Default::default()
         },
         MockInstantWrapped::default(),
     );
@@ -671,3 +682,218 @@ async fn range_multiple_items_test() -> Result<(), Error> {
 
     Ok(())
 }
+
+async fn wait_for_len(
+    evicting_map: &EvictingMap<DigestInfo, BytesWrapper, MockInstantWrapped>,
+    expected_len: usize,
+) {
+    for _ in 0..100 {
+        if evicting_map.len_for_test().await == expected_len {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("Expected map to shrink to {expected_len} items");
+}
+
+#[nativelink_test]
+async fn background_eviction_evicts_to_low_watermark() -> Result<(), Error> {
+    const DATA: &str = "12345678";
+    let evicting_map = Arc::new(
+        EvictingMap::<DigestInfo, BytesWrapper, MockInstantWrapped>::new(
+            &EvictionPolicy {
+                max_bytes: 64,
+                high_watermark_bytes: 24,
+                low_watermark_bytes: 8,
+                ..Default::default()
+            },
+            MockInstantWrapped::default(),
+        ),
+    );
+    EvictingMap::spawn_background_eviction(&evicting_map, |evicting_map| evicting_map);
+
+    let keys = [HASH1, HASH2, HASH3, HASH4].map(|hash| DigestInfo::try_new(hash, 0).unwrap());
+    for key in keys {
+        evicting_map.insert(key, Bytes::from(DATA).into()).await;
+    }
+    // The inserts stay below `max_bytes`, so nothing is evicted inline and
+    // the background task evicts the oldest items.
+    wait_for_len(&evicting_map, 1).await;
+    assert_eq!(evicting_map.size_for_key(&keys[0]).await, None);
+    assert_eq!(evicting_map.size_for_key(&keys[3]).await, Some(8));
+    Ok(())
+}
+
+#[nativelink_test]
+async fn background_eviction_is_paced() -> Result<(), Error> {
+    const DATA: &str = "12345678";
+    let evicting_map = Arc::new(
+        EvictingMap::<DigestInfo, BytesWrapper, MockInstantWrapped>::new(
+            &EvictionPolicy {
+                high_watermark_bytes: 24,
+                low_watermark_bytes: 8,
+                // One item per 100ms batch.
+                eviction_bytes_per_second: 80,
+                ..Default::default()
+            },
+            MockInstantWrapped::default(),
+        ),
+    );
+    EvictingMap::spawn_background_eviction(&evicting_map, |evicting_map| evicting_map);
+
+    let start = std::time::Instant::now();
+    for hash in [HASH1, HASH2, HASH3, HASH4] {
+        evicting_map
+            .insert(DigestInfo::try_new(hash, 0)?, Bytes::from(DATA).into())
+            .await;
+    }
+    wait_for_len(&evicting_map, 1).await;
+    assert!(start.elapsed() >= Duration::from_millis(190));
+    Ok(())
+}
+
+#[nativelink_test]
+async fn lfu_evicts_least_frequently_used() -> Result<(), Error> {
+    const DATA: &str = "12345678";
+    let evicting_map = EvictingMap::<DigestInfo, BytesWrapper, MockInstantWrapped>::new(
+        &EvictionPolicy {
+            max_bytes: 17,
+            algorithm: EvictionAlgorithm::lfu,
+            ..Default::default()
+        },
+        MockInstantWrapped::default(),
+    );
+    let key1 = DigestInfo::try_new(HASH1, 0)?;
+    let key2 = DigestInfo::try_new(HASH2, 0)?;
+    let key3 = DigestInfo::try_new(HASH3, 0)?;
+    evicting_map.insert(key1, Bytes::from(DATA).into()).await;
+    evicting_map.insert(key2, Bytes::from(DATA).into()).await;
+    evicting_map.get(&key1).await;
+    evicting_map.get(&key1).await;
+    // Item 2 is now the most recently used, but it was used less often.
+    evicting_map.get(&key2).await;
+    evicting_map.insert(key3, Bytes::from(DATA).into()).await;
+
+    assert_eq!(
+        evicting_map.size_for_key(&key1).await,
+        Some(DATA.len() as u64),
+        "Expected map to have item 1"
+    );
+    assert_eq!(
+        evicting_map.size_for_key(&key2).await,
+        None,
+        "Expected map to not have item 2"
+    );
+    assert_eq!(
+        evicting_map.size_for_key(&key3).await,
+        Some(DATA.len() as u64),
+        "Expected map to have item 3"
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn ttl_access_does_not_extend_life() -> Result<(), Error> {
+    const DATA: &str = "12345678";
+    let evicting_map = EvictingMap::<DigestInfo, BytesWrapper, MockInstantWrapped>::new(
+        &EvictionPolicy {
+            max_seconds: 3,
+            algorithm: EvictionAlgorithm::ttl,
+            ..Default::default()
+        },
+        MockInstantWrapped::default(),
+    );
+    let key = DigestInfo::try_new(HASH1, 0)?;
+    evicting_map.insert(key, Bytes::from(DATA).into()).await;
+    MockClock::advance(Duration::from_secs(2));
+    assert!(evicting_map.get(&key).await.is_some());
+    MockClock::advance(Duration::from_secs(2));
+    assert_eq!(
+        evicting_map.size_for_key(&key).await,
+        None,
+        "Expected item to expire 3 seconds after the insert"
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn segmented_lru_protects_accessed_entries() -> Result<(), Error> {
+    const DATA: &str = "12345678";
+    let evicting_map = EvictingMap::<DigestInfo, BytesWrapper, MockInstantWrapped>::new(
+        &EvictionPolicy {
+            max_bytes: 17,
+            algorithm: EvictionAlgorithm::segmented_lru,
+            ..Default::default()
+        },
+        MockInstantWrapped::default(),
+    );
+    let key1 = DigestInfo::try_new(HASH1, 0)?;
+    let key2 = DigestInfo::try_new(HASH2, 0)?;
+    let key3 = DigestInfo::try_new(HASH3, 0)?;
+    evicting_map.insert(key1, Bytes::from(DATA).into()).await;
+    // Accessing item 1 moves it to the protected segment.
+    evicting_map.get(&key1).await;
+    evicting_map.insert(key2, Bytes::from(DATA).into()).await;
+    evicting_map.insert(key3, Bytes::from(DATA).into()).await;
+
+    assert_eq!(
+        evicting_map.size_for_key(&key2).await,
+        None,
+        "Expected map to not have item 2"
+    );
+    assert_eq!(
+        evicting_map.size_for_key(&key1).await,
+        Some(DATA.len() as u64),
+        "Expected map to have item 1"
+    );
+    assert_eq!(
+        evicting_map.size_for_key(&key3).await,
+        Some(DATA.len() as u64),
+        "Expected map to have item 3"
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn set_policy_applies_new_limits() -> Result<(), Error> {
+    let evicting_map = EvictingMap::<DigestInfo, BytesWrapper, MockInstantWrapped>::new(
+        &EvictionPolicy {
+            max_count: 3,
+            ..Default::default()
+        },
+        MockInstantWrapped::default(),
+    );
+    for hash in [HASH1, HASH2, HASH3] {
+        evicting_map
+            .insert(DigestInfo::try_new(hash, 0)?, Bytes::new().into())
+            .await;
+    }
+    assert_eq!(evicting_map.len_for_test().await, 3);
+
+    // Lowering the limit evicts right away.
+    evicting_map
+        .set_policy(&EvictionPolicy {
+            max_count: 1,
+            ..Default::default()
+        })
+        .await?;
+    assert_eq!(evicting_map.len_for_test().await, 1);
+    assert_eq!(
+        evicting_map
+            .size_for_key(&DigestInfo::try_new(HASH3, 0)?)
+            .await,
+        Some(0),
+        "Expected map to have the most recent item"
+    );
+
+    let err = evicting_map
+        .set_policy(&EvictionPolicy {
+            max_count: 1,
+            algorithm: EvictionAlgorithm::lfu,
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code, nativelink_error::Code::InvalidArgument, "{err:?}");
+    Ok(())
+}
@@ -21,7 +21,7 @@ use nativelink_error::Error;
 use nativelink_macro::nativelink_test;
 use nativelink_util::health_utils::{
     HealthRegistryBuilder, HealthStatus, HealthStatusDescription, HealthStatusIndicator,
-    HealthStatusReporter,
+    HealthStatusReporter, TimedHealthStatusDescription,
 };
 use pretty_assertions::assert_eq;
 
@@ -221,3 +221,44 @@ macro_rules! generate_health_status_indicator {
 fn vec_to_set(vec: Vec<HealthStatusDescription>) -> HashSet<HealthStatusDescription> {
     HashSet::from_iter(vec)
 }
+
+#[nativelink_test]
+async fn timed_report_matches_report() -> Result<(), Error> {
+    generate_health_status_indicator!(MockComponentImpl1, Ok, "ok");
+    generate_health_status_indicator!(MockComponentImpl2, Initializing, "loading");
+    generate_health_status_indicator!(MockComponentImpl3, Warning, "warning");
+
+    let mut health_registry_builder = HealthRegistryBuilder::new("nativelink");
+
+    health_registry_builder.register_indicator(Arc::new(MockComponentImpl1 {}));
+    health_registry_builder.register_indicator(Arc::new(MockComponentImpl2 {}));
+    health_registry_builder.register_indicator(Arc::new(MockComponentImpl3 {}));
+
+    let health_registry = health_registry_builder.build();
+    let health_status: Vec<HealthStatusDescription> =
+        health_registry.health_status_report().collect().await;
+    let timed_health_status: Vec<TimedHealthStatusDescription> =
+        health_registry.timed_health_status_report().collect().await;
+
+    assert_eq!(
+        timed_health_status
+            .iter()
+            .map(|timed| timed.description.clone())
+            .collect::<Vec<_>>(),
+        health_status
+    );
+    let ready: HashSet<_> = timed_health_status
+        .iter()
+        .filter(|timed| timed.description.status.is_ready())
+        .map(|timed| timed.description.namespace.clone())
+        .collect();
+    assert_eq!(
+        ready,
+        HashSet::from([
+            Cow::from("/nativelink/MockComponentImpl1"),
+            Cow::from("/nativelink/MockComponentImpl3"),
+        ])
+    );
+
+    Ok(())
+}
@@ -0,0 +1,126 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use nativelink_error::Error;
+use nativelink_macro::nativelink_test;
+use nativelink_util::histogram_metrics::{
+    check_slow_store_operation, set_slow_operation_thresholds, RpcTimer,
+};
+use pretty_assertions::assert_eq;
+use tonic::Status;
+
+/// Returns the sample count of every `nativelink_rpc_duration_seconds`
+/// histogram of `instance_name`, by gRPC code.
+fn rpc_counts_by_code(instance_name: &str) -> HashMap<String, u64> {
+    let mut counts = HashMap::new();
+    for family in prometheus::gather() {
+        if family.get_name() != "nativelink_rpc_duration_seconds" {
+            continue;
+        }
+        for metric in family.get_metric() {
+            let labels: HashMap<&str, &str> = metric
+                .get_label()
+                .iter()
+                .map(|label| (label.get_name(), label.get_value()))
+                .collect();
+            if labels.get("instance_name") != Some(&instance_name) {
+                continue;
+            }
+            assert_eq!(labels.get("service"), Some(&"TestService"));
+            assert_eq!(labels.get("method"), Some(&"TestMethod"));
+            *counts.entry(labels["code"].to_string()).or_default() +=
+                metric.get_histogram().get_sample_count();
+        }
+    }
+    counts
+}
+
+// Tests run concurrently, so they all set the same thresholds.
+const SLOW_STORE_OPERATION: Duration = Duration::from_secs(1);
+const SLOW_RPC: Duration = Duration::from_millis(1);
+
+/// Returns the value of `nativelink_slow_operations_total` for `kind` and
+/// `name`.
+fn slow_operation_count(kind: &str, name: &str) -> u64 {
+    prometheus::gather()
+        .iter()
+        .filter(|family| family.get_name() == "nativelink_slow_operations_total")
+        .flat_map(|family| family.get_metric())
+        .filter(|metric| {
+            let labels: HashMap<&str, &str> = metric
+                .get_label()
+                .iter()
+                .map(|label| (label.get_name(), label.get_value()))
+                .collect();
+            labels.get("kind") == Some(&kind) && labels.get("name") == Some(&name)
+        })
+        .map(|metric| metric.get_counter().get_value() as u64)
+        .sum()
+}
+
+#[nativelink_test]
+async fn rpc_timer_labels_by_code() -> Result<(), Error> {
+    const INSTANCE_NAME: &str = "rpc_timer_labels_by_code";
+
+    RpcTimer::new("TestService", "TestMethod", INSTANCE_NAME).observe(&Ok::<(), Status>(()));
+    RpcTimer::new("TestService", "TestMethod", INSTANCE_NAME).observe(&Ok::<(), Status>(()));
+    RpcTimer::new("TestService", "TestMethod", INSTANCE_NAME)
+        .observe(&Err::<(), _>(Status::not_found("missing")));
+
+    assert_eq!(
+        rpc_counts_by_code(INSTANCE_NAME),
+        HashMap::from([("Ok".to_string(), 2), ("NotFound".to_string(), 1)])
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn counts_slow_store_operations() -> Result<(), Error> {
+    const STORE_NAME: &str = "counts_slow_store_operations";
+    set_slow_operation_thresholds(SLOW_STORE_OPERATION, SLOW_RPC);
+
+    check_slow_store_operation(
+        STORE_NAME,
+        "get_part",
+        || panic!("Fast operations are not logged"),
+        Some(5),
+        Duration::from_millis(10),
+    );
+    assert_eq!(slow_operation_count("store", STORE_NAME), 0);
+
+    check_slow_store_operation(
+        STORE_NAME,
+        "get_part",
+        || "key".to_string(),
+        Some(5),
+        Duration::from_secs(2),
+    );
+    assert_eq!(slow_operation_count("store", STORE_NAME), 1);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn counts_slow_rpcs() -> Result<(), Error> {
+    set_slow_operation_thresholds(SLOW_STORE_OPERATION, SLOW_RPC);
+
+    let timer = RpcTimer::new("SlowService", "SlowMethod", "counts_slow_rpcs");
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    timer.observe(&Ok::<(), Status>(()));
+
+    assert_eq!(slow_operation_count("rpc", "SlowService"), 1);
+    Ok(())
+}
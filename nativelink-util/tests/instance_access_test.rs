@@ -0,0 +1,95 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use nativelink_config::cas_server::InstanceAccessConfig;
+use nativelink_error::{Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_util::instance_access::InstanceAccess;
+use nativelink_util::origin_context::{OriginContext, AUTHENTICATED_IDENTITY, ORIGIN_IDENTITY};
+use pretty_assertions::assert_eq;
+use tracing::trace_span;
+
+const INSTANCE_NAME: &str = "main";
+
+async fn check_as(
+    identity: Option<&str>,
+    access: &InstanceAccess,
+) -> (Result<(), Error>, Result<(), Error>) {
+    let mut ctx = OriginContext::new();
+    if let Some(identity) = identity {
+        ctx.set_value(&AUTHENTICATED_IDENTITY, Arc::new(identity.to_string()));
+    }
+    Arc::new(ctx)
+        .wrap_async(trace_span!("check_as"), async {
+            (
+                access.check_read(INSTANCE_NAME),
+                access.check_write(INSTANCE_NAME),
+            )
+        })
+        .await
+}
+
+#[nativelink_test]
+async fn unrestricted_by_default_test() -> Result<(), Error> {
+    let access = InstanceAccess::new(&InstanceAccessConfig::default());
+    let (read, write) = check_as(None, &access).await;
+    read?;
+    write?;
+    Ok(())
+}
+
+#[nativelink_test]
+async fn identities_are_checked_test() -> Result<(), Error> {
+    let access = InstanceAccess::new(&InstanceAccessConfig {
+        read_identities: Some(vec!["ci".to_string(), "dev".to_string(), String::new()]),
+        write_identities: Some(vec!["ci".to_string()]),
+    });
+
+    let (read, write) = check_as(Some("ci"), &access).await;
+    read?;
+    write?;
+
+    let (read, write) = check_as(Some("dev"), &access).await;
+    read?;
+    assert_eq!(write.unwrap_err().code, Code::PermissionDenied);
+
+    // Clients without an identity have the empty identity.
+    let (read, write) = check_as(None, &access).await;
+    read?;
+    assert_eq!(write.unwrap_err().code, Code::PermissionDenied);
+
+    let (read, _) = check_as(Some("other"), &access).await;
+    assert_eq!(read.unwrap_err().code, Code::PermissionDenied);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn identity_header_is_not_trusted_test() -> Result<(), Error> {
+    let access = InstanceAccess::new(&InstanceAccessConfig {
+        read_identities: None,
+        write_identities: Some(vec!["ci".to_string()]),
+    });
+    // The identity header sets only the origin identity.
+    let mut ctx = OriginContext::new();
+    ctx.set_value(&ORIGIN_IDENTITY, Arc::new("ci".to_string()));
+    let write = Arc::new(ctx)
+        .wrap_async(trace_span!("identity_header_is_not_trusted_test"), async {
+            access.check_write(INSTANCE_NAME)
+        })
+        .await;
+    assert_eq!(write.unwrap_err().code, Code::PermissionDenied);
+    Ok(())
+}
@@ -0,0 +1,109 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+
+use async_trait::async_trait;
+use nativelink_error::Error;
+use nativelink_macro::nativelink_test;
+use nativelink_util::memory_budget::{MemoryBudget, MemoryConsumer};
+use pretty_assertions::assert_eq;
+
+struct MockConsumer {
+    bytes: AtomicU64,
+}
+
+impl MockConsumer {
+    fn new(budget: &MemoryBudget, bytes: u64) -> Arc<Self> {
+        let consumer = Arc::new(Self {
+            bytes: AtomicU64::new(bytes),
+        });
+        let weak_consumer: Weak<dyn MemoryConsumer> = Arc::downgrade(&consumer);
+        budget.register(weak_consumer);
+        consumer
+    }
+
+    fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+}
+
+#[async_trait]
+impl MemoryConsumer for MockConsumer {
+    fn accounted_bytes(&self) -> u64 {
+        self.bytes()
+    }
+
+    async fn evict(&self, bytes: u64) -> u64 {
+        let evicted = bytes.min(self.bytes());
+        self.bytes.fetch_sub(evicted, Ordering::Relaxed);
+        evicted
+    }
+}
+
+#[nativelink_test]
+async fn consumers_evict_proportional_to_their_size() -> Result<(), Error> {
+    let budget = MemoryBudget::new(100);
+    let large_consumer = MockConsumer::new(&budget, 150);
+    let small_consumer = MockConsumer::new(&budget, 50);
+
+    budget.enforce().await;
+    assert_eq!(large_consumer.bytes(), 75);
+    assert_eq!(small_consumer.bytes(), 25);
+    assert_eq!(budget.used_bytes(), 100);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn reservations_count_against_budget() -> Result<(), Error> {
+    let budget = MemoryBudget::new(100);
+    let consumer = MockConsumer::new(&budget, 80);
+
+    let reservation = budget.reserve(40);
+    assert_eq!(budget.used_bytes(), 120);
+    budget.enforce().await;
+    assert_eq!(consumer.bytes(), 60);
+
+    drop(reservation);
+    assert_eq!(budget.used_bytes(), 60);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn grown_reservations_are_released_when_dropped() -> Result<(), Error> {
+    let budget = MemoryBudget::new(100);
+    let mut reservation = budget.reserve(0);
+    reservation.grow(30);
+    reservation.grow(20);
+    assert_eq!(budget.used_bytes(), 50);
+
+    drop(reservation);
+    assert_eq!(budget.used_bytes(), 0);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn unlimited_budget_and_dropped_consumers_are_ignored() -> Result<(), Error> {
+    let budget = MemoryBudget::new(0);
+    let consumer = MockConsumer::new(&budget, 200);
+    budget.enforce().await;
+    assert_eq!(consumer.bytes(), 200);
+
+    drop(consumer);
+    budget.set_max_bytes(100);
+    assert_eq!(budget.used_bytes(), 0);
+    budget.enforce().await;
+    Ok(())
+}
@@ -0,0 +1,78 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use hyper::http::{Request, Response};
+use nativelink_config::cas_server::IdentityHeaderSpec;
+use nativelink_error::Error;
+use nativelink_macro::nativelink_test;
+use nativelink_util::origin_context::{ActiveOriginContext, REQUEST_ID};
+use nativelink_util::origin_event_middleware::{OriginEventMiddlewareLayer, REQUEST_ID_HEADER};
+use pretty_assertions::assert_eq;
+use tower::{service_fn, Layer, ServiceExt};
+
+/// Sends a request with the `x-request-id` header set to `request_id` and
+/// returns the request id the service saw and the one of the response.
+async fn send_request(request_id: Option<&str>) -> Result<(String, String), Error> {
+    let layer = OriginEventMiddlewareLayer::new(None, IdentityHeaderSpec::default());
+    let service = layer.layer(service_fn(|_request: Request<()>| async move {
+        let request_id = ActiveOriginContext::get_value(&REQUEST_ID)?
+            .map(|request_id| request_id.as_ref().clone())
+            .unwrap_or_default();
+        Ok::<_, Error>(Response::new(request_id))
+    }));
+
+    let mut request = Request::builder().uri("/test").body(()).unwrap();
+    if let Some(request_id) = request_id {
+        request
+            .headers_mut()
+            .insert(REQUEST_ID_HEADER, request_id.parse().unwrap());
+    }
+    let response = service.oneshot(request).await?;
+    let response_request_id = response
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .expect("Expected request id header on response")
+        .to_str()
+        .unwrap()
+        .to_string();
+    Ok((response.into_body(), response_request_id))
+}
+
+#[nativelink_test]
+async fn generates_request_id_test() -> Result<(), Error> {
+    let (request_id, response_request_id) = send_request(None).await?;
+    assert!(!request_id.is_empty());
+    assert_eq!(request_id, response_request_id);
+
+    let (other_request_id, _) = send_request(None).await?;
+    assert_ne!(request_id, other_request_id);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn keeps_request_id_of_client_test() -> Result<(), Error> {
+    let (request_id, response_request_id) = send_request(Some("ci-build-1234")).await?;
+    assert_eq!(request_id, "ci-build-1234");
+    assert_eq!(response_request_id, "ci-build-1234");
+    Ok(())
+}
+
+#[nativelink_test]
+async fn replaces_too_long_request_id_test() -> Result<(), Error> {
+    let too_long = "a".repeat(129);
+    let (request_id, response_request_id) = send_request(Some(&too_long)).await?;
+    assert_ne!(request_id, too_long);
+    assert_eq!(request_id, response_request_id);
+    Ok(())
+}
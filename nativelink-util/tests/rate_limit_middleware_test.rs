@@ -0,0 +1,137 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use http_body_util::{BodyExt, Empty, Full};
+use hyper::http::{Request, Response};
+use nativelink_config::cas_server::RateLimitConfig;
+use nativelink_error::{Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_util::rate_limit_middleware::{
+    ClientAddress, RateLimitMiddlewareLayer, RateLimitedBody, RateLimiter,
+};
+use pretty_assertions::assert_eq;
+use tower::{service_fn, Layer, ServiceExt};
+
+const BYTESTREAM_WRITE: &str = "/google.bytestream.ByteStream/Write";
+const EXECUTE: &str = "/build.bazel.remote.execution.v2.Execution/Execute";
+const FIND_MISSING_BLOBS: &str =
+    "/build.bazel.remote.execution.v2.ContentAddressableStorage/FindMissingBlobs";
+
+fn make_request(path: &str, address: &str, body: &'static [u8]) -> Request<Full<Bytes>> {
+    let mut request = Request::builder()
+        .uri(path)
+        .body(Full::new(Bytes::from_static(body)))
+        .unwrap();
+    request
+        .extensions_mut()
+        .insert(ClientAddress(address.parse::<SocketAddr>().unwrap()));
+    request
+}
+
+#[nativelink_test]
+async fn limits_requests_in_flight_per_client_test() -> Result<(), Error> {
+    let limiter = Arc::new(RateLimiter::new(&RateLimitConfig {
+        max_concurrent_bytestream_writes: 1,
+        max_concurrent_executes: 2,
+        ..Default::default()
+    }));
+
+    let write = limiter.admit("ci", BYTESTREAM_WRITE)?;
+    assert_eq!(
+        limiter.admit("ci", BYTESTREAM_WRITE).unwrap_err().code,
+        Code::ResourceExhausted
+    );
+    // Other clients and other methods have their own limits.
+    let _other_write = limiter.admit("dev", BYTESTREAM_WRITE)?;
+    let _executes = [limiter.admit("ci", EXECUTE)?, limiter.admit("ci", EXECUTE)?];
+    assert_eq!(
+        limiter.admit("ci", EXECUTE).unwrap_err().code,
+        Code::ResourceExhausted
+    );
+    let _find_missing = limiter.admit("ci", FIND_MISSING_BLOBS)?;
+
+    drop(write);
+    let _write = limiter.admit("ci", BYTESTREAM_WRITE)?;
+    Ok(())
+}
+
+#[nativelink_test]
+async fn limits_uploaded_bytes_test() -> Result<(), Error> {
+    let limiter = Arc::new(RateLimiter::new(&RateLimitConfig {
+        max_bytes_per_second: 1000,
+        ..Default::default()
+    }));
+
+    let slot = limiter.admit("ci", BYTESTREAM_WRITE)?;
+    slot.record_bytes(10_000);
+    drop(slot);
+    // The client is remembered until it is within its limit again.
+    assert_eq!(
+        limiter.admit("ci", FIND_MISSING_BLOBS).unwrap_err().code,
+        Code::ResourceExhausted
+    );
+    let _other_client = limiter.admit("dev", BYTESTREAM_WRITE)?;
+    Ok(())
+}
+
+#[nativelink_test]
+async fn middleware_rejects_with_grpc_status_test() -> Result<(), Error> {
+    let layer = RateLimitMiddlewareLayer::new(&RateLimitConfig {
+        max_concurrent_bytestream_writes: 1,
+        max_bytes_per_second: 1000,
+        ..Default::default()
+    });
+    let service = layer.layer(service_fn(
+        |request: Request<RateLimitedBody<Full<Bytes>>>| async move {
+            let _ = request.into_body().collect().await;
+            Ok::<_, Error>(Response::new(Empty::<Bytes>::new()))
+        },
+    ));
+
+    // The slot of the write is held until its response is dropped.
+    let response = service
+        .clone()
+        .oneshot(make_request(BYTESTREAM_WRITE, "10.0.0.1:1000", b"data"))
+        .await?;
+    assert!(response.headers().get("grpc-status").is_none());
+    let rejected = service
+        .clone()
+        .oneshot(make_request(BYTESTREAM_WRITE, "10.0.0.1:1001", b"data"))
+        .await?;
+    assert_eq!(rejected.headers()["grpc-status"], "8");
+    let other_client = service
+        .clone()
+        .oneshot(make_request(BYTESTREAM_WRITE, "10.0.0.2:1000", b"data"))
+        .await?;
+    assert!(other_client.headers().get("grpc-status").is_none());
+    drop(response);
+
+    // Uploads over the byte limit are finished, but new requests are
+    // rejected.
+    let large_upload = service
+        .clone()
+        .oneshot(make_request(BYTESTREAM_WRITE, "10.0.0.1:1002", &[0; 2000]))
+        .await?;
+    assert!(large_upload.headers().get("grpc-status").is_none());
+    drop(large_upload);
+    let rejected = service
+        .oneshot(make_request(FIND_MISSING_BLOBS, "10.0.0.1:1003", b""))
+        .await?;
+    assert_eq!(rejected.headers()["grpc-status"], "8");
+    Ok(())
+}
@@ -15,7 +15,8 @@
 use std::borrow::Cow;
 
 use nativelink_macro::nativelink_test;
-use nativelink_util::resource_info::ResourceInfo;
+use nativelink_util::common::DigestInfo;
+use nativelink_util::resource_info::{ActionOutput, ActionOutputStreamName, ResourceInfo};
 use pretty_assertions::assert_eq;
 
 #[nativelink_test]
@@ -707,3 +708,40 @@ async fn write_invalid_size_test() -> Result<(), Box<dyn std::error::Error>> {
     assert!(ResourceInfo::new(RESOURCE_NAME, true).is_err());
     Ok(())
 }
+
+#[nativelink_test]
+async fn action_output_stream_name_round_trip_test() -> Result<(), Box<dyn std::error::Error>> {
+    const RESOURCE_NAME: &str = "instance/name/action-outputs/0123456789abcdef000000000000000000000000000000000123456789abcdef/12345/stderr";
+    let stream_name = ActionOutputStreamName::parse(RESOURCE_NAME).unwrap();
+    assert_eq!(
+        stream_name,
+        ActionOutputStreamName::new(
+            "instance/name",
+            DigestInfo::try_new(
+                "0123456789abcdef000000000000000000000000000000000123456789abcdef",
+                12345
+            )?,
+            ActionOutput::Stderr
+        )
+    );
+    assert_eq!(stream_name.to_string(), RESOURCE_NAME);
+
+    // The instance name is optional.
+    let stream_name = ActionOutputStreamName::parse(&RESOURCE_NAME[14..]).unwrap();
+    assert_eq!(stream_name.instance_name, "");
+    assert_eq!(stream_name.to_string(), &RESOURCE_NAME[14..]);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn blobs_are_not_action_output_streams_test() -> Result<(), Box<dyn std::error::Error>> {
+    for resource_name in [
+        "instance_name/blobs/0123456789abcdef000000000000000000000000000000000123456789abcdef/12345",
+        "instance_name/uploads/uuid/blobs/0123456789abcdef000000000000000000000000000000000123456789abcdef/12345",
+        "instance_name/action-outputs/0123456789abcdef000000000000000000000000000000000123456789abcdef/12345/stdin",
+        "instance_name/action-outputs/hash/12345/stdout",
+    ] {
+        assert_eq!(ActionOutputStreamName::parse(resource_name), None);
+    }
+    Ok(())
+}
@@ -0,0 +1,95 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use hyper::http::HeaderMap;
+use nativelink_error::Error;
+use nativelink_macro::nativelink_test;
+use nativelink_util::telemetry::{current_trace_context, inject_trace_context, set_trace_parent};
+use opentelemetry::global;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::TracerProvider;
+use tracing::info_span;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::Registry;
+
+const TRACE_ID: &str = "0af7651916cd43dd8448eb211c80319c";
+const TRACE_PARENT: &str = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+
+/// Runs `f` with spans recorded by OpenTelemetry, like when they are
+/// exported, but without an exporter.
+fn with_otel_spans<T>(f: impl FnOnce() -> T) -> T {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    let tracer = TracerProvider::builder().build().tracer("telemetry_test");
+    let subscriber = Registry::default().with(tracing_opentelemetry::layer().with_tracer(tracer));
+    tracing::subscriber::with_default(subscriber, f)
+}
+
+fn remote_trace_context() -> HashMap<String, String> {
+    HashMap::from([("traceparent".to_string(), TRACE_PARENT.to_string())])
+}
+
+#[nativelink_test]
+async fn span_continues_remote_trace() -> Result<(), Error> {
+    let trace_context = with_otel_spans(|| {
+        let span = info_span!("remote_child");
+        set_trace_parent(&span, &remote_trace_context());
+        span.in_scope(current_trace_context)
+    });
+
+    let trace_parent = &trace_context["traceparent"];
+    assert!(
+        trace_parent.starts_with(&format!("00-{TRACE_ID}-")),
+        "{trace_parent}"
+    );
+    // The span is a child, not the remote span itself.
+    assert_ne!(trace_parent, TRACE_PARENT);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn empty_trace_context_starts_new_trace() -> Result<(), Error> {
+    let trace_context = with_otel_spans(|| {
+        let span = info_span!("root");
+        set_trace_parent(&span, &HashMap::new());
+        span.in_scope(current_trace_context)
+    });
+
+    let trace_parent = &trace_context["traceparent"];
+    assert!(!trace_parent.contains(TRACE_ID), "{trace_parent}");
+    Ok(())
+}
+
+#[nativelink_test]
+async fn outgoing_headers_carry_trace_context() -> Result<(), Error> {
+    let mut headers = HeaderMap::new();
+    with_otel_spans(|| {
+        let span = info_span!("outgoing_request");
+        set_trace_parent(&span, &remote_trace_context());
+        span.in_scope(|| inject_trace_context(&mut headers));
+    });
+
+    let trace_parent = headers
+        .get("traceparent")
+        .expect("Expected traceparent header")
+        .to_str()
+        .expect("Expected traceparent to be ASCII");
+    assert!(
+        trace_parent.starts_with(&format!("00-{TRACE_ID}-")),
+        "{trace_parent}"
+    );
+    Ok(())
+}
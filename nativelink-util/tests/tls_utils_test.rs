@@ -0,0 +1,121 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::env;
+use std::path::PathBuf;
+
+use nativelink_config::cas_server::TlsConfig;
+use nativelink_error::{Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_util::tls_utils::{
+    cert_common_names, check_client_common_name, load_server_config, ReloadingCertResolver,
+};
+use pretty_assertions::assert_eq;
+use rustls_pemfile::certs as extract_certs;
+use tokio_rustls::rustls::pki_types::CertificateDer;
+
+const CA_PEM: &[u8] = include_bytes!("data/tls/ca.pem");
+const CLIENT_PEM: &[u8] = include_bytes!("data/tls/client.pem");
+const SERVER_KEY: &[u8] = include_bytes!("data/tls/server.key");
+const SERVER_PEM: &[u8] = include_bytes!("data/tls/server.pem");
+const SERVER2_KEY: &[u8] = include_bytes!("data/tls/server2.key");
+const SERVER2_PEM: &[u8] = include_bytes!("data/tls/server2.pem");
+
+/// Writes `data` to a file in `TEST_TMPDIR` or the best effort temp directory.
+fn write_temp_file(name: &str, data: &[u8]) -> Result<String, Error> {
+    let dir = PathBuf::from(
+        env::var("TEST_TMPDIR").unwrap_or(env::temp_dir().to_str().unwrap().to_string()),
+    )
+    .join(format!("tls_utils_test_{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(name);
+    std::fs::write(&path, data)?;
+    Ok(path.to_str().unwrap().to_string())
+}
+
+fn parse_cert(pem: &[u8]) -> CertificateDer<'static> {
+    extract_certs(&mut &pem[..]).next().unwrap().unwrap()
+}
+
+fn make_tls_config(client_ca_file: Option<String>, allowed: &[&str]) -> Result<TlsConfig, Error> {
+    Ok(TlsConfig {
+        cert_file: write_temp_file("config_server.pem", SERVER_PEM)?,
+        key_file: write_temp_file("config_server.key", SERVER_KEY)?,
+        client_ca_file,
+        client_crl_file: None,
+        allowed_client_common_names: allowed.iter().map(ToString::to_string).collect(),
+        cert_reload_interval_s: 0,
+    })
+}
+
+#[nativelink_test]
+async fn load_server_config_test() -> Result<(), Error> {
+    let ca_file = write_temp_file("ca.pem", CA_PEM)?;
+    let (server_config, _resolver) =
+        load_server_config(&make_tls_config(Some(ca_file), &["builder"])?)?;
+    assert_eq!(server_config.alpn_protocols, vec![b"h2".to_vec()]);
+
+    // Common names can only be checked if clients send a certificate.
+    let err = load_server_config(&make_tls_config(None, &["builder"])?).unwrap_err();
+    assert_eq!(err.code, Code::InvalidArgument);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn reload_if_changed_test() -> Result<(), Error> {
+    let cert_file = write_temp_file("reload_server.pem", SERVER_PEM)?;
+    let key_file = write_temp_file("reload_server.key", SERVER_KEY)?;
+    let resolver = ReloadingCertResolver::new(&cert_file, &key_file)?;
+    assert!(!resolver.reload_if_changed()?);
+
+    std::fs::write(&cert_file, SERVER2_PEM)?;
+    std::fs::write(&key_file, SERVER2_KEY)?;
+    assert!(resolver.reload_if_changed()?);
+    assert!(!resolver.reload_if_changed()?);
+
+    // A broken certificate is not loaded.
+    std::fs::write(&key_file, b"not a key")?;
+    assert!(resolver.reload_if_changed().is_err());
+    Ok(())
+}
+
+#[nativelink_test]
+async fn check_client_common_name_test() -> Result<(), Error> {
+    let client_cert = parse_cert(CLIENT_PEM);
+    assert_eq!(
+        cert_common_names(&client_cert)?,
+        vec!["builder".to_string()]
+    );
+
+    let peer_certificates = Some(std::slice::from_ref(&client_cert));
+    check_client_common_name(peer_certificates, &[])?;
+    check_client_common_name(None, &[])?;
+    check_client_common_name(
+        peer_certificates,
+        &["other".to_string(), "builder".to_string()],
+    )?;
+    assert_eq!(
+        check_client_common_name(peer_certificates, &["other".to_string()])
+            .unwrap_err()
+            .code,
+        Code::PermissionDenied
+    );
+    assert_eq!(
+        check_client_common_name(None, &["builder".to_string()])
+            .unwrap_err()
+            .code,
+        Code::PermissionDenied
+    );
+    Ok(())
+}
@@ -0,0 +1,80 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ffi::OsString;
+use std::path::Path;
+
+use nativelink_config::cas_server::BubblewrapRunnerConfig;
+use nativelink_error::{make_input_err, Error};
+
+/// Default bubblewrap executable.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_PROGRAM: &str = "bwrap";
+
+/// Returns the command line that runs `arguments` in a bubblewrap sandbox
+/// from `working_directory`. The file system is read only in the sandbox
+/// except for `/tmp`, which is a new tmpfs, and `action_directory`. The
+/// other directories of `root_action_directory` are hidden.
+pub fn bubblewrap_command_line<'a>(
+    config: &BubblewrapRunnerConfig,
+    root_action_directory: &str,
+    action_directory: &str,
+    working_directory: &str,
+    arguments: impl IntoIterator<Item = &'a String>,
+) -> Result<Vec<OsString>, Error> {
+    if !Path::new(action_directory).is_absolute() {
+        return Err(make_input_err!(
+            "Action directory {action_directory} must be an absolute path to be mounted in a sandbox"
+        ));
+    }
+    let program = if config.program.is_empty() {
+        DEFAULT_PROGRAM
+    } else {
+        &config.program
+    };
+    let mut command_line: Vec<OsString> = vec![
+        program.into(),
+        "--die-with-parent".into(),
+        "--unshare-all".into(),
+    ];
+    if config.allow_network {
+        command_line.push("--share-net".into());
+    }
+    // Later mounts are made on top of the earlier ones, so the action
+    // directory is bound last.
+    for arg in [
+        "--ro-bind",
+        "/",
+        "/",
+        "--dev",
+        "/dev",
+        "--proc",
+        "/proc",
+        "--tmpfs",
+        "/tmp",
+        "--tmpfs",
+        root_action_directory,
+        "--bind",
+        action_directory,
+        action_directory,
+        "--chdir",
+        working_directory,
+    ] {
+        command_line.push(arg.into());
+    }
+    command_line.extend(config.additional_args.iter().map(Into::into));
+    command_line.push("--".into());
+    command_line.extend(arguments.into_iter().map(Into::into));
+    Ok(command_line)
+}
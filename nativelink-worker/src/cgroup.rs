@@ -0,0 +1,233 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::time::Duration;
+
+use nativelink_config::cas_server::ResourceLimitsConfig;
+use nativelink_error::{make_err, make_input_err, Code, Error, ResultExt};
+use nativelink_util::action_messages::OperationId;
+use nativelink_util::background_spawn;
+use tokio::process;
+use tracing::{event, Level};
+
+/// Default platform property with the CPU shares of an action.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_CPU_SHARES_PROPERTY: &str = "cpu-shares";
+
+/// Default platform property with the memory limit of an action.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_MEMORY_LIMIT_PROPERTY: &str = "memory-limit-bytes";
+
+/// How often removing a cgroup is tried while its processes are killed.
+const REMOVE_ATTEMPTS: usize = 50;
+
+/// How long to wait between attempts to remove a cgroup.
+const REMOVE_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// The limits of an action.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ResourceLimits {
+    /// The `cpu.weight` of the cgroup of the action.
+    pub cpu_weight: Option<u64>,
+    /// The `memory.max` of the cgroup of the action.
+    pub memory_limit_bytes: Option<u64>,
+}
+
+impl ResourceLimits {
+    /// Returns the limits of an action with these platform properties.
+    pub fn new(
+        config: &ResourceLimitsConfig,
+        platform_properties: &HashMap<String, String>,
+    ) -> Result<Self, Error> {
+        let property_or_default = |property: &str, default_property: &str, default: u64| {
+            let property = if property.is_empty() {
+                default_property
+            } else {
+                property
+            };
+            let value = match platform_properties.get(property) {
+                Some(value) => value.parse::<u64>().map_err(|e| {
+                    make_input_err!("Could not parse platform property {property}={value} : {e:?}")
+                })?,
+                None => default,
+            };
+            Ok::<_, Error>(if value == 0 { None } else { Some(value) })
+        };
+        Ok(Self {
+            cpu_weight: property_or_default(
+                &config.cpu_shares_property,
+                DEFAULT_CPU_SHARES_PROPERTY,
+                config.default_cpu_shares,
+            )?
+            .map(cpu_shares_to_weight),
+            memory_limit_bytes: property_or_default(
+                &config.memory_limit_property,
+                DEFAULT_MEMORY_LIMIT_PROPERTY,
+                config.default_memory_limit_bytes,
+            )?,
+        })
+    }
+}
+
+/// Converts CPU shares of cgroup v1, where 1024 is the default, to the CPU
+/// weight of cgroup v2, where 100 is, the same way the container runtimes
+/// do.
+pub fn cpu_shares_to_weight(cpu_shares: u64) -> u64 {
+    let cpu_shares = cpu_shares.clamp(2, 262_144);
+    1 + ((cpu_shares - 2) * 9999) / 262_142
+}
+
+/// The cgroup of an action. The processes left in it are killed and it is
+/// removed when it is dropped.
+#[derive(Debug)]
+pub struct ActionCgroup {
+    path: String,
+    memory_limit_bytes: Option<u64>,
+}
+
+impl ActionCgroup {
+    /// Creates the cgroup of an action, or returns `None` if the action has
+    /// no limits.
+    pub async fn new(
+        config: &ResourceLimitsConfig,
+        operation_id: &OperationId,
+        platform_properties: &HashMap<String, String>,
+    ) -> Result<Option<Self>, Error> {
+        let limits = ResourceLimits::new(config, platform_properties)?;
+        if limits == ResourceLimits::default() {
+            return Ok(None);
+        }
+        let path = format!(
+            "{}/{}",
+            config.cgroup_directory,
+            operation_id.to_string().replace('/', "_")
+        );
+        tokio::fs::create_dir(&path)
+            .await
+            .err_tip(|| format!("Could not create cgroup {path}"))?;
+        let cgroup = Self {
+            path,
+            memory_limit_bytes: limits.memory_limit_bytes,
+        };
+        if let Some(cpu_weight) = limits.cpu_weight {
+            cgroup.write("cpu.weight", &cpu_weight.to_string()).await?;
+        }
+        if let Some(memory_limit_bytes) = limits.memory_limit_bytes {
+            cgroup
+                .write("memory.max", &memory_limit_bytes.to_string())
+                .await?;
+            // Without swap the action is killed at the limit instead of
+            // slowing down the worker. Not all kernels account swap.
+            if let Err(e) = tokio::fs::write(format!("{}/memory.swap.max", cgroup.path), "0").await
+            {
+                if e.kind() != ErrorKind::NotFound {
+                    return Err(e)
+                        .err_tip(|| format!("Could not disable swap of cgroup {}", cgroup.path));
+                }
+            }
+        }
+        Ok(Some(cgroup))
+    }
+
+    async fn write(&self, file: &str, value: &str) -> Result<(), Error> {
+        tokio::fs::write(format!("{}/{file}", self.path), value)
+            .await
+            .err_tip(|| format!("Could not write {value} to {file} of cgroup {}", self.path))
+    }
+
+    /// Makes `command` start its process in the cgroup, so the children of
+    /// the process are in it too.
+    pub fn spawn_in(&self, command: &mut process::Command) -> Result<(), Error> {
+        #[cfg(target_family = "unix")]
+        {
+            use std::io::Write;
+
+            let procs_file = std::fs::OpenOptions::new()
+                .write(true)
+                .open(format!("{}/cgroup.procs", self.path))
+                .err_tip(|| format!("Could not open cgroup.procs of cgroup {}", self.path))?;
+            // SAFETY: The closure runs between fork and exec, where it only
+            // writes to a file that was opened before, which does not
+            // allocate or take locks.
+            unsafe {
+                command.pre_exec(move || (&procs_file).write_all(b"0"));
+            }
+            Ok(())
+        }
+        #[cfg(not(target_family = "unix"))]
+        {
+            let _ = command;
+            Err(make_err!(
+                Code::Unimplemented,
+                "Resource limits of actions are only supported with cgroups on Linux"
+            ))
+        }
+    }
+
+    /// Returns an error for the action if the kernel killed one of its
+    /// processes because the cgroup used more memory than its limit.
+    pub async fn oom_kill_error(&self) -> Result<Option<Error>, Error> {
+        let Some(memory_limit_bytes) = self.memory_limit_bytes else {
+            return Ok(None);
+        };
+        let events = tokio::fs::read_to_string(format!("{}/memory.events", self.path))
+            .await
+            .err_tip(|| format!("Could not read memory.events of cgroup {}", self.path))?;
+        let oom_kills = events
+            .lines()
+            .find_map(|line| line.strip_prefix("oom_kill "))
+            .and_then(|oom_kills| oom_kills.trim().parse::<u64>().ok())
+            .unwrap_or(0);
+        if oom_kills == 0 {
+            return Ok(None);
+        }
+        Ok(Some(make_err!(
+            Code::ResourceExhausted,
+            "Action was killed because it used more than its memory limit of {memory_limit_bytes} bytes"
+        )))
+    }
+
+    /// The memory limit of the action in bytes, if it has one.
+    pub const fn memory_limit_bytes(&self) -> Option<u64> {
+        self.memory_limit_bytes
+    }
+}
+
+impl Drop for ActionCgroup {
+    fn drop(&mut self) {
+        let path = std::mem::take(&mut self.path);
+        background_spawn!("action_cgroup_remove", async move {
+            // Processes the action left behind are killed, which the
+            // cgroup must be empty of before it can be removed.
+            if let Err(err) = tokio::fs::write(format!("{path}/cgroup.kill"), "1").await {
+                event!(
+                    Level::WARN,
+                    ?err,
+                    ?path,
+                    "Could not kill processes of cgroup"
+                );
+            }
+            for _ in 0..REMOVE_ATTEMPTS {
+                match tokio::fs::remove_dir(&path).await {
+                    Ok(()) => return,
+                    Err(e) if e.kind() == ErrorKind::NotFound => return,
+                    Err(_) => tokio::time::sleep(REMOVE_RETRY_DELAY).await,
+                }
+            }
+            event!(Level::ERROR, ?path, "Could not remove cgroup of action");
+        });
+    }
+}
@@ -0,0 +1,157 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::Path;
+use std::process::Stdio;
+
+use nativelink_config::cas_server::ContainerRunnerConfig;
+use nativelink_error::{make_input_err, Error};
+use nativelink_util::action_messages::OperationId;
+use tokio::process;
+
+/// Default docker compatible tool used to run the containers.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_PROGRAM: &str = "docker";
+
+/// Default platform property that holds the image of an action.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_IMAGE_PROPERTY: &str = "container-image";
+
+/// Prefix of the images in the platform properties of the remote execution
+/// API, which the container tools do not understand.
+const IMAGE_PREFIX: &str = "docker://";
+
+fn program(config: &ContainerRunnerConfig) -> &str {
+    if config.program.is_empty() {
+        DEFAULT_PROGRAM
+    } else {
+        &config.program
+    }
+}
+
+/// Returns the name of the container the operation runs in. Characters the
+/// container tools do not allow in names are replaced.
+pub fn container_name(operation_id: &OperationId) -> String {
+    let operation_id: String = operation_id
+        .to_string()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("nativelink-{operation_id}")
+}
+
+/// Returns the image an action with these platform properties runs in.
+fn image<'a>(
+    config: &'a ContainerRunnerConfig,
+    platform_properties: &'a HashMap<String, String>,
+) -> Result<&'a str, Error> {
+    let image_property = if config.image_property.is_empty() {
+        DEFAULT_IMAGE_PROPERTY
+    } else {
+        &config.image_property
+    };
+    let image = platform_properties
+        .get(image_property)
+        .map_or(config.default_image.as_str(), |image| {
+            image.strip_prefix(IMAGE_PREFIX).unwrap_or(image)
+        });
+    if image.is_empty() {
+        return Err(make_input_err!(
+            "Action has no '{image_property}' platform property with the container image to run in"
+        ));
+    }
+    // The image is passed on the command line of the container tool, which
+    // would take it as an option.
+    if image.starts_with('-') {
+        return Err(make_input_err!(
+            "Container image '{image}' must not start with '-'"
+        ));
+    }
+    if !config.allowed_image_prefixes.is_empty()
+        && !config
+            .allowed_image_prefixes
+            .iter()
+            .any(|prefix| image.starts_with(prefix.as_str()))
+    {
+        return Err(make_input_err!(
+            "Container image '{image}' does not start with one of the allowed image prefixes {:?}",
+            config.allowed_image_prefixes
+        ));
+    }
+    Ok(image)
+}
+
+/// Returns the command line that runs `arguments` in a container named
+/// `container_name` from `working_directory`. The action directory is
+/// mounted at the same path, so paths in it stay the same. The values of
+/// the `environment_names` are taken from the environment of the command
+/// line.
+pub fn container_command_line<'a>(
+    config: &ContainerRunnerConfig,
+    container_name: &str,
+    platform_properties: &HashMap<String, String>,
+    action_directory: &str,
+    working_directory: &str,
+    environment_names: impl IntoIterator<Item = &'a str>,
+    arguments: impl IntoIterator<Item = &'a String>,
+) -> Result<Vec<OsString>, Error> {
+    if !Path::new(action_directory).is_absolute() {
+        return Err(make_input_err!(
+            "Action directory {action_directory} must be an absolute path to be mounted in a container"
+        ));
+    }
+    let image = image(config, platform_properties)?;
+    let mut command_line: Vec<OsString> = vec![
+        program(config).into(),
+        "run".into(),
+        "--rm".into(),
+        "--name".into(),
+        container_name.into(),
+        "--volume".into(),
+        format!("{action_directory}:{action_directory}").into(),
+        "--workdir".into(),
+        working_directory.into(),
+    ];
+    for name in environment_names {
+        command_line.push("--env".into());
+        command_line.push(name.into());
+    }
+    command_line.extend(config.additional_args.iter().map(Into::into));
+    command_line.push(image.into());
+    command_line.extend(arguments.into_iter().map(Into::into));
+    Ok(command_line)
+}
+
+/// Returns the command that removes the container. Killing the command line
+/// of a container does not always stop the container itself.
+pub fn remove_container_command(
+    config: &ContainerRunnerConfig,
+    container_name: &str,
+) -> process::Command {
+    let mut command = process::Command::new(program(config));
+    command
+        .args(["rm", "--force", container_name])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    command
+}
@@ -12,7 +12,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod bubblewrap_runner;
+pub mod cgroup;
+pub mod container_runner;
 pub mod local_worker;
+pub mod overlay_input_root;
+pub mod persistent_worker;
 pub mod running_actions_manager;
 pub mod worker_api_client_wrapper;
 pub mod worker_utils;
@@ -38,13 +38,14 @@ use nativelink_util::metrics_utils::{AsyncCounterWrapper, CounterWithTime};
 use nativelink_util::origin_context::ActiveOriginContext;
 use nativelink_util::shutdown_guard::ShutdownGuard;
 use nativelink_util::store_trait::Store;
+use nativelink_util::telemetry::set_trace_parent;
 use nativelink_util::{spawn, tls_utils};
 use tokio::process;
 use tokio::sync::{broadcast, mpsc};
 use tokio::time::sleep;
 use tokio_stream::wrappers::UnboundedReceiverStream;
 use tonic::Streaming;
-use tracing::{event, info_span, instrument, Level};
+use tracing::{error_span, event, instrument, Level};
 
 use crate::running_actions_manager::{
     ExecutionConfiguration, Metrics as RunningActionManagerMetrics, RunningAction,
@@ -154,6 +155,12 @@ impl<'a, T: WorkerApiClientTrait, U: RunningActionsManager> LocalWorkerImpl<'a,
             if let Err(e) = grpc_client
                 .keep_alive(KeepAliveRequest {
                     worker_id: self.worker_id.clone(),
+                    cached_input_roots: self
+                        .running_actions_manager
+                        .recent_input_roots()
+                        .into_iter()
+                        .map(Into::into)
+                        .collect(),
                 })
                 .await
             {
@@ -230,6 +237,14 @@ impl<'a, T: WorkerApiClientTrait, U: RunningActionsManager> LocalWorkerImpl<'a,
                             let operation_id = start_execute.operation_id.clone();
                             let maybe_instance_name = execute_request.map(|v| v.instance_name.clone());
                             let action_digest = execute_request.and_then(|v| v.action_digest.clone());
+                            // Continue the trace of the scheduler that dispatched the action.
+                            // The request id is on the span, so every log of the action carries it.
+                            let start_action_span = error_span!(
+                                "worker_start_action_ctx",
+                                %operation_id,
+                                request_id = %start_execute.request_id,
+                            );
+                            set_trace_parent(&start_action_span, &start_execute.trace_context);
                             let digest_hasher = execute_request
                                 .ok_or(make_input_err!("Expected execute_request to be set"))
                                 .and_then(|v| DigestHasherFunc::try_from(v.digest_function))
@@ -324,7 +339,7 @@ impl<'a, T: WorkerApiClientTrait, U: RunningActionsManager> LocalWorkerImpl<'a,
                             let add_future_channel = add_future_channel.clone();
                             let mut ctx = ActiveOriginContext::fork().err_tip(|| "Expected ActiveOriginContext to be set in local_worker::run")?;
                             ctx.set_value(&ACTIVE_HASHER_FUNC, Arc::new(digest_hasher));
-                            ctx.run(info_span!("worker_start_action_ctx"), move || {
+                            ctx.run(start_action_span, move || {
                                 futures_ref.push(
                                     spawn!("worker_start_action", start_action_fut).map(move |res| {
                                         let res = res.err_tip(|| "Failed to launch spawn")?;
@@ -356,9 +371,24 @@ impl<'a, T: WorkerApiClientTrait, U: RunningActionsManager> LocalWorkerImpl<'a,
                     let mut grpc_client = self.grpc_client.clone();
                     let worker_id = self.worker_id.clone();
                     let running_actions_manager = self.running_actions_manager.clone();
+                    let drain_on_shutdown = self.config.drain_on_shutdown;
                     let complete_msg_clone = complete_msg.map_err(|e| make_err!(Code::Internal, "Failed to receive shutdown message: {e:?}"))?.clone();
                     let shutdown_future = async move {
-                        if let Err(e) = grpc_client.going_away(GoingAwayRequest { worker_id }).await {
+                        if drain_on_shutdown {
+                            // Keep the connection while the running actions finish, so
+                            // their results still reach the scheduler.
+                            if let Err(e) = grpc_client.going_away(GoingAwayRequest { worker_id: worker_id.clone(), drain: true }).await {
+                                event!(Level::ERROR, "Failed to send draining GoingAwayRequest: {e}",);
+                                return Err(e.into());
+                            }
+                            running_actions_manager.complete_actions(complete_msg_clone).await;
+                            if let Err(e) = grpc_client.going_away(GoingAwayRequest { worker_id, drain: false }).await {
+                                event!(Level::ERROR, "Failed to send GoingAwayRequest: {e}",);
+                                return Err(e.into());
+                            }
+                            return Ok(());
+                        }
+                        if let Err(e) = grpc_client.going_away(GoingAwayRequest { worker_id, drain: false }).await {
                             event!(Level::ERROR, "Failed to send GoingAwayRequest: {e}",);
                             return Err(e.into());
                         }
@@ -428,6 +458,12 @@ pub async fn new_local_worker(
             execution_configuration: ExecutionConfiguration {
                 entrypoint,
                 additional_environment: config.additional_environment.clone(),
+                runner: config.runner.clone(),
+                resource_limits: config.resource_limits.clone(),
+                persistent_workers: config.persistent_workers.clone(),
+                input_root_strategy: config.input_root_strategy.clone(),
+                output_upload: config.output_upload.clone(),
+                stream_action_output: config.stream_action_output,
             },
             cas_store: fast_slow_store,
             ac_store,
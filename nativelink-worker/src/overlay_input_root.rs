@@ -0,0 +1,267 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::future::Future;
+use std::process::Stdio;
+use std::sync::Arc;
+
+use nativelink_config::cas_server::OverlayfsInputRootConfig;
+use nativelink_error::{make_err, Code, Error, ResultExt};
+use nativelink_util::background_spawn;
+use nativelink_util::common::{fs, DigestInfo};
+use parking_lot::Mutex;
+use tokio::process;
+use tracing::{event, Level};
+use uuid::Uuid;
+
+/// Default number of input roots kept in the shared content directory.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_MAX_INPUT_ROOTS: usize = 32;
+
+struct SharedInputRootEntry {
+    digest: DigestInfo,
+    directory: String,
+    /// The number of `SharedInputRoot`s of the entry.
+    users: usize,
+}
+
+/// The input roots the overlays of actions are mounted on. Each is made
+/// once in a directory of its own and shared by all actions with the same
+/// input root.
+pub struct SharedInputRoots {
+    directory: String,
+    max_input_roots: usize,
+    /// The input roots in the directory, least recently used first.
+    entries: Mutex<Vec<SharedInputRootEntry>>,
+}
+
+impl SharedInputRoots {
+    pub fn new(config: &OverlayfsInputRootConfig, directory: String) -> Self {
+        let mut max_input_roots = config.max_input_roots;
+        if max_input_roots == 0 {
+            max_input_roots = DEFAULT_MAX_INPUT_ROOTS;
+        }
+        Self {
+            directory,
+            max_input_roots,
+            entries: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns the input root with the digest, which `make_input_root` is
+    /// called to lay out in an empty directory if it is not there yet. The
+    /// directory is not removed while the returned `SharedInputRoot` lives.
+    pub async fn get_or_make<F, Fut>(
+        self: &Arc<Self>,
+        digest: DigestInfo,
+        make_input_root: F,
+    ) -> Result<SharedInputRoot, Error>
+    where
+        F: FnOnce(String) -> Fut,
+        Fut: Future<Output = Result<(), Error>>,
+    {
+        if let Some(input_root) = self.use_entry(digest) {
+            return Ok(input_root);
+        }
+        // Names are unique, so a directory that is being removed is never
+        // made again.
+        let directory = format!("{}/{digest}-{}", self.directory, Uuid::new_v4().simple());
+        fs::create_dir_all(&directory)
+            .await
+            .err_tip(|| format!("Could not create input root directory {directory}"))?;
+        if let Err(err) = make_input_root(directory.clone()).await {
+            Self::remove_directory(directory).await;
+            return Err(err);
+        }
+        let maybe_unused_directory = {
+            let mut entries = self.entries.lock();
+            // Another action may have made the same input root meanwhile.
+            if entries.iter().any(|entry| entry.digest == digest) {
+                Some(directory)
+            } else {
+                entries.push(SharedInputRootEntry {
+                    digest,
+                    directory,
+                    users: 0,
+                });
+                None
+            }
+        };
+        if let Some(unused_directory) = maybe_unused_directory {
+            Self::remove_directory(unused_directory).await;
+        }
+        self.use_entry(digest)
+            .err_tip(|| format!("Input root {digest} was removed right after it was made"))
+    }
+
+    /// Returns the entry with the digest as used, if there is one, and
+    /// removes the least recently used entries no action uses when there
+    /// are too many.
+    fn use_entry(self: &Arc<Self>, digest: DigestInfo) -> Option<SharedInputRoot> {
+        let (input_root, evicted_directories) = {
+            let mut entries = self.entries.lock();
+            let index = entries.iter().position(|entry| entry.digest == digest)?;
+            let mut entry = entries.remove(index);
+            entry.users += 1;
+            let input_root = SharedInputRoot {
+                shared_input_roots: self.clone(),
+                digest,
+                directory: entry.directory.clone(),
+            };
+            entries.push(entry);
+            let mut evicted_directories = Vec::new();
+            while entries.len() > self.max_input_roots {
+                let Some(index) = entries.iter().position(|entry| entry.users == 0) else {
+                    break;
+                };
+                evicted_directories.push(entries.remove(index).directory);
+            }
+            (input_root, evicted_directories)
+        };
+        for directory in evicted_directories {
+            background_spawn!(
+                "shared_input_roots_remove_directory",
+                Self::remove_directory(directory)
+            );
+        }
+        Some(input_root)
+    }
+
+    async fn remove_directory(directory: String) {
+        if let Err(err) = fs::remove_dir_all(&directory).await {
+            event!(
+                Level::ERROR,
+                ?err,
+                ?directory,
+                "Could not remove shared input root directory",
+            );
+        }
+    }
+}
+
+/// A shared input root used by an action.
+pub struct SharedInputRoot {
+    shared_input_roots: Arc<SharedInputRoots>,
+    digest: DigestInfo,
+    directory: String,
+}
+
+impl SharedInputRoot {
+    /// The directory with the input root, which must not be changed.
+    pub fn directory(&self) -> &str {
+        &self.directory
+    }
+}
+
+impl Drop for SharedInputRoot {
+    fn drop(&mut self) {
+        let mut entries = self.shared_input_roots.entries.lock();
+        if let Some(entry) = entries.iter_mut().find(|entry| entry.digest == self.digest) {
+            entry.users -= 1;
+        }
+    }
+}
+
+/// Returns the arguments of `mount` that mount an overlay of `upper_directory`
+/// on `lower_directory` at `mount_point`. `work_directory` must be an empty
+/// directory on the filesystem of `upper_directory`.
+pub fn overlay_mount_arguments(
+    lower_directory: &str,
+    upper_directory: &str,
+    work_directory: &str,
+    mount_point: &str,
+) -> Vec<String> {
+    vec![
+        "-t".to_string(),
+        "overlay".to_string(),
+        "overlay".to_string(),
+        "-o".to_string(),
+        format!("lowerdir={lower_directory},upperdir={upper_directory},workdir={work_directory}"),
+        mount_point.to_string(),
+    ]
+}
+
+/// Runs `mount` or `umount` and returns an error with its stderr if it
+/// fails.
+async fn run_mount_command(program: &str, arguments: &[String]) -> Result<(), Error> {
+    let output = process::Command::new(program)
+        .args(arguments)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .await
+        .err_tip(|| format!("Could not run {program} {arguments:?}"))?;
+    if !output.status.success() {
+        return Err(make_err!(
+            Code::Internal,
+            "{program} {arguments:?} failed with {} : {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// An overlay mounted on the work directory of an action on top of its
+/// shared input root. It must be unmounted before the action directory is
+/// removed.
+pub struct OverlayInputRoot {
+    mount_point: String,
+    _input_root: SharedInputRoot,
+}
+
+impl OverlayInputRoot {
+    /// Mounts the overlay at `mount_point`, with its writable layer in the
+    /// action directory.
+    pub async fn mount(
+        input_root: SharedInputRoot,
+        action_directory: &str,
+        mount_point: &str,
+    ) -> Result<Self, Error> {
+        let upper_directory = format!("{action_directory}/overlay_upper");
+        let work_directory = format!("{action_directory}/overlay_work");
+        for directory in [&upper_directory, &work_directory] {
+            fs::create_dir(directory)
+                .await
+                .err_tip(|| format!("Could not create overlay directory {directory}"))?;
+        }
+        run_mount_command(
+            "mount",
+            &overlay_mount_arguments(
+                input_root.directory(),
+                &upper_directory,
+                &work_directory,
+                mount_point,
+            ),
+        )
+        .await
+        .err_tip(|| format!("Could not mount overlay input root at {mount_point}"))?;
+        Ok(Self {
+            mount_point: mount_point.to_string(),
+            _input_root: input_root,
+        })
+    }
+
+    pub async fn unmount(self) -> Result<(), Error> {
+        run_mount_command("umount", &[self.mount_point.clone()])
+            .await
+            .err_tip(|| {
+                format!(
+                    "Could not unmount overlay input root at {}",
+                    self.mount_point
+                )
+            })
+    }
+}
@@ -0,0 +1,349 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::process::Stdio;
+
+use nativelink_config::cas_server::PersistentWorkersConfig;
+use nativelink_error::{make_err, Code, Error, ResultExt};
+use nativelink_proto::blaze::worker::{WorkRequest, WorkResponse};
+use nativelink_proto::build::bazel::remote::execution::v2::Command as ProtoCommand;
+use nativelink_util::background_spawn;
+use parking_lot::Mutex;
+use prost::Message;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::process;
+use tracing::{event, Level};
+use uuid::Uuid;
+
+/// Default platform property with the key of the persistent worker of an
+/// action.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_KEY_PROPERTY: &str = "persistentWorkerKey";
+
+/// Default number of idle processes kept for each key.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_MAX_IDLE_WORKERS_PER_KEY: usize = 4;
+
+/// Default maximum size of a `WorkResponse`.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_MAX_WORK_RESPONSE_SIZE: usize = 16 * 1024 * 1024;
+
+/// The argument persistent workers are started with.
+const PERSISTENT_WORKER_ARGUMENT: &str = "--persistent_worker";
+
+/// The maximum length of the varint in front of each message.
+const MAX_LENGTH_DELIMITER_SIZE: usize = 10;
+
+/// What the processes of persistent workers are kept by. Actions with the
+/// same key can run in the same process one after the other.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PersistentWorkerKey {
+    /// The key Bazel derived from the tool inputs of the action.
+    pub worker_key: String,
+    /// The arguments the process is started with.
+    pub startup_arguments: Vec<String>,
+    /// The directory in the input root the process runs in.
+    pub working_directory: String,
+    /// The environment variables of the process, sorted by name.
+    pub environment: Vec<(String, String)>,
+}
+
+impl PersistentWorkerKey {
+    /// Returns the key of the persistent worker an action runs in and the
+    /// path of the file with its request arguments, relative to its working
+    /// directory, or `None` if the action does not run in a persistent
+    /// worker. Bazel passes the request arguments of these actions in a
+    /// flag file that is the last argument of their command.
+    pub fn for_action(
+        config: &PersistentWorkersConfig,
+        command: &ProtoCommand,
+        platform_properties: &HashMap<String, String>,
+    ) -> Option<(Self, String)> {
+        let key_property = if config.key_property.is_empty() {
+            DEFAULT_KEY_PROPERTY
+        } else {
+            &config.key_property
+        };
+        let worker_key = platform_properties.get(key_property)?;
+        let (flagfile_argument, arguments) = command.arguments.split_last()?;
+        if arguments.is_empty() {
+            return None;
+        }
+        let flagfile = flagfile_argument
+            .strip_prefix('@')
+            .or_else(|| flagfile_argument.strip_prefix("--flagfile="))?;
+        let mut startup_arguments = arguments.to_vec();
+        startup_arguments.push(PERSISTENT_WORKER_ARGUMENT.to_string());
+        let mut environment: Vec<(String, String)> = command
+            .environment_variables
+            .iter()
+            .map(|variable| (variable.name.clone(), variable.value.clone()))
+            .collect();
+        environment.sort_unstable();
+        Some((
+            Self {
+                worker_key: worker_key.clone(),
+                startup_arguments,
+                working_directory: command.working_directory.clone(),
+                environment,
+            },
+            flagfile.to_string(),
+        ))
+    }
+}
+
+/// Writes a length delimited `WorkRequest`, as persistent workers read it.
+pub async fn write_work_request(
+    writer: &mut (impl AsyncWrite + Unpin),
+    request: &WorkRequest,
+) -> Result<(), Error> {
+    writer
+        .write_all(&request.encode_length_delimited_to_vec())
+        .await
+        .err_tip(|| "Could not write WorkRequest to persistent worker")?;
+    writer
+        .flush()
+        .await
+        .err_tip(|| "Could not flush WorkRequest to persistent worker")
+}
+
+/// Reads a length delimited `WorkResponse`, as persistent workers write it.
+/// Responses longer than `max_size` bytes are rejected before they are read.
+pub async fn read_work_response(
+    reader: &mut (impl AsyncRead + Unpin),
+    max_size: usize,
+) -> Result<WorkResponse, Error> {
+    let mut delimiter = Vec::with_capacity(MAX_LENGTH_DELIMITER_SIZE);
+    loop {
+        let byte = reader
+            .read_u8()
+            .await
+            .err_tip(|| "Could not read WorkResponse length from persistent worker")?;
+        delimiter.push(byte);
+        // The highest bit is set on all bytes of the varint but the last.
+        if byte & 0x80 == 0 {
+            break;
+        }
+        if delimiter.len() >= MAX_LENGTH_DELIMITER_SIZE {
+            return Err(make_err!(
+                Code::Internal,
+                "Persistent worker sent an invalid WorkResponse length"
+            ));
+        }
+    }
+    let length = prost::decode_length_delimiter(delimiter.as_slice())
+        .err_tip(|| "Could not decode WorkResponse length from persistent worker")?;
+    if length > max_size {
+        return Err(make_err!(
+            Code::Internal,
+            "Persistent worker sent a WorkResponse of {length} bytes, more than the maximum of {max_size} bytes"
+        ));
+    }
+    let mut message = vec![0; length];
+    reader
+        .read_exact(&mut message)
+        .await
+        .err_tip(|| "Could not read WorkResponse from persistent worker")?;
+    WorkResponse::decode(message.as_slice())
+        .err_tip(|| "Could not decode WorkResponse from persistent worker")
+}
+
+/// Moves the entries of one directory to another.
+async fn move_entries(from: &str, to: &str) -> Result<(), Error> {
+    let mut entries = tokio::fs::read_dir(from)
+        .await
+        .err_tip(|| format!("Could not read directory {from}"))?;
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .err_tip(|| format!("Could not read entry of directory {from}"))?
+    {
+        let destination = format!("{to}/{}", entry.file_name().to_string_lossy());
+        tokio::fs::rename(entry.path(), &destination)
+            .await
+            .err_tip(|| format!("Could not move {:?} to {destination}", entry.path()))?;
+    }
+    Ok(())
+}
+
+/// A running persistent worker. The process is killed and its directory is
+/// removed when it is dropped.
+struct PersistentWorkerProcess {
+    child: process::Child,
+    stdin: process::ChildStdin,
+    stdout: process::ChildStdout,
+    directory: String,
+}
+
+impl PersistentWorkerProcess {
+    fn spawn(key: &PersistentWorkerKey, directory: String) -> Result<Self, Error> {
+        let mut child = process::Command::new(&key.startup_arguments[0])
+            .args(&key.startup_arguments[1..])
+            .kill_on_drop(true)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .current_dir(format!("{directory}/{}", key.working_directory))
+            .env_clear()
+            .envs(key.environment.iter().cloned())
+            .spawn()
+            .err_tip(|| {
+                format!(
+                    "Could not start persistent worker {:?}",
+                    key.startup_arguments
+                )
+            })?;
+        let stdin = child
+            .stdin
+            .take()
+            .err_tip(|| "Expected stdin to exist on persistent worker")?;
+        let stdout = child
+            .stdout
+            .take()
+            .err_tip(|| "Expected stdout to exist on persistent worker")?;
+        Ok(Self {
+            child,
+            stdin,
+            stdout,
+            directory,
+        })
+    }
+
+    async fn work(
+        &mut self,
+        request: &WorkRequest,
+        max_response_size: usize,
+    ) -> Result<WorkResponse, Error> {
+        write_work_request(&mut self.stdin, request).await?;
+        read_work_response(&mut self.stdout, max_response_size).await
+    }
+
+    /// Returns whether the process is still running.
+    fn is_running(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+}
+
+impl Drop for PersistentWorkerProcess {
+    fn drop(&mut self) {
+        let directory = std::mem::take(&mut self.directory);
+        background_spawn!("persistent_worker_remove_directory", async move {
+            if let Err(err) = tokio::fs::remove_dir_all(&directory).await {
+                event!(
+                    Level::ERROR,
+                    ?err,
+                    ?directory,
+                    "Could not remove directory of persistent worker",
+                );
+            }
+        });
+    }
+}
+
+/// The processes of the persistent workers waiting for actions.
+pub struct PersistentWorkers {
+    config: PersistentWorkersConfig,
+    /// The directory with the directories of the processes.
+    directory: String,
+    max_idle_workers_per_key: usize,
+    max_work_response_size: usize,
+    idle_workers: Mutex<HashMap<PersistentWorkerKey, Vec<PersistentWorkerProcess>>>,
+}
+
+impl PersistentWorkers {
+    pub fn new(config: &PersistentWorkersConfig, directory: String) -> Self {
+        let mut max_idle_workers_per_key = config.max_idle_workers_per_key;
+        if max_idle_workers_per_key == 0 {
+            max_idle_workers_per_key = DEFAULT_MAX_IDLE_WORKERS_PER_KEY;
+        }
+        let mut max_work_response_size = config.max_work_response_size;
+        if max_work_response_size == 0 {
+            max_work_response_size = DEFAULT_MAX_WORK_RESPONSE_SIZE;
+        }
+        Self {
+            config: config.clone(),
+            directory,
+            max_idle_workers_per_key,
+            max_work_response_size,
+            idle_workers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// See `PersistentWorkerKey::for_action`.
+    pub fn key_of(
+        &self,
+        command: &ProtoCommand,
+        platform_properties: &HashMap<String, String>,
+    ) -> Option<(PersistentWorkerKey, String)> {
+        PersistentWorkerKey::for_action(&self.config, command, platform_properties)
+    }
+
+    /// Runs a request in an idle process with the key, or a new one if
+    /// there is none. The inputs in `work_directory` are moved to the
+    /// directory of the process for the request, and moved back with the
+    /// outputs when it is done. If the future is dropped, the process is
+    /// stopped.
+    pub async fn work(
+        &self,
+        key: PersistentWorkerKey,
+        work_directory: &str,
+        request: &WorkRequest,
+    ) -> Result<WorkResponse, Error> {
+        let maybe_idle_worker = {
+            let mut idle_workers = self.idle_workers.lock();
+            let maybe_idle_worker = idle_workers.get_mut(&key).and_then(Vec::pop);
+            if idle_workers.get(&key).is_some_and(Vec::is_empty) {
+                idle_workers.remove(&key);
+            }
+            maybe_idle_worker
+        };
+        let mut worker = match maybe_idle_worker {
+            Some(mut worker) if worker.is_running() => {
+                move_entries(work_directory, &worker.directory).await?;
+                worker
+            }
+            _ => {
+                let directory = format!("{}/{}", self.directory, Uuid::new_v4().simple());
+                tokio::fs::create_dir_all(&directory)
+                    .await
+                    .err_tip(|| format!("Could not create directory {directory}"))?;
+                // The working directory of the process is in the inputs, so
+                // they are moved before it starts.
+                move_entries(work_directory, &directory).await?;
+                match PersistentWorkerProcess::spawn(&key, directory.clone()) {
+                    Ok(worker) => worker,
+                    Err(err) => {
+                        let _ = tokio::fs::remove_dir_all(&directory).await;
+                        return Err(err);
+                    }
+                }
+            }
+        };
+        // On errors the process is dropped, which kills it, as its stdout
+        // may be left in the middle of a response.
+        let response = worker
+            .work(request, self.max_work_response_size)
+            .await
+            .err_tip(|| format!("In persistent worker {:?}", key.startup_arguments))?;
+        move_entries(&worker.directory, work_directory).await?;
+
+        let mut idle_workers = self.idle_workers.lock();
+        let key_idle_workers = idle_workers.entry(key).or_default();
+        if key_idle_workers.len() < self.max_idle_workers_per_key {
+            key_idle_workers.push(worker);
+        }
+        Ok(response)
+    }
+}
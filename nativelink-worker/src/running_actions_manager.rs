@@ -38,10 +38,13 @@ use futures::future::{
 };
 use futures::stream::{FuturesUnordered, StreamExt, TryStreamExt};
 use nativelink_config::cas_server::{
-    EnvironmentSource, UploadActionResultConfig, UploadCacheResultsStrategy,
+    ActionRunner, EnvironmentSource, InputRootStrategy, OutputUploadConfig,
+    PersistentWorkersConfig, ResourceLimitsConfig, UploadActionResultConfig,
+    UploadCacheResultsStrategy,
 };
 use nativelink_error::{make_err, make_input_err, Code, Error, ResultExt};
 use nativelink_metric::MetricsComponent;
+use nativelink_proto::blaze::worker::WorkRequest;
 use nativelink_proto::build::bazel::remote::execution::v2::{
     Action, ActionResult as ProtoActionResult, Command as ProtoCommand,
     Directory as ProtoDirectory, Directory, DirectoryNode, ExecuteResponse, FileNode, SymlinkNode,
@@ -63,6 +66,7 @@ use nativelink_util::action_messages::{
 use nativelink_util::common::{fs, DigestInfo};
 use nativelink_util::digest_hasher::{DigestHasher, DigestHasherFunc};
 use nativelink_util::metrics_utils::{AsyncCounterWrapper, CounterWithTime};
+use nativelink_util::resource_info::{ActionOutput, ActionOutputStreamName};
 use nativelink_util::shutdown_guard::ShutdownGuard;
 use nativelink_util::store_trait::{Store, StoreLike, UploadSizeInfo};
 use nativelink_util::{background_spawn, spawn, spawn_blocking};
@@ -73,16 +77,38 @@ use scopeguard::{guard, ScopeGuard};
 use serde::Deserialize;
 use tokio::io::{AsyncReadExt, AsyncSeekExt};
 use tokio::process;
-use tokio::sync::{oneshot, watch};
-use tokio_stream::wrappers::ReadDirStream;
+use tokio::sync::{mpsc, oneshot, watch, Semaphore};
+use tokio_stream::wrappers::{ReadDirStream, UnboundedReceiverStream};
 use tonic::Request;
-use tracing::{enabled, event, Level};
+use tracing::{enabled, event, info_span, Instrument, Level};
 use uuid::Uuid;
 
+use crate::bubblewrap_runner::bubblewrap_command_line;
+use crate::cgroup::ActionCgroup;
+use crate::container_runner::{container_command_line, container_name, remove_container_command};
+use crate::overlay_input_root::{OverlayInputRoot, SharedInputRoots};
+use crate::persistent_worker::{PersistentWorkerKey, PersistentWorkers};
+
 /// For simplicity we use a fixed exit code for cases when our program is terminated
 /// due to a signal.
 const EXIT_CODE_FOR_SIGNAL: i32 = 9;
 
+/// Number of input roots of the last actions the worker reports to the
+/// scheduler, which prefers the worker for actions with the same inputs.
+const MAX_RECENT_INPUT_ROOTS: usize = 32;
+
+/// Default number of uploads of the outputs of an action at once.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_MAX_CONCURRENT_OUTPUT_UPLOADS: usize = 64;
+
+/// Default size up to which output files are uploaded in batches.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_MAX_BATCH_OUTPUT_FILE_SIZE_BYTES: u64 = 64 * 1024;
+
+/// Default size of the files of a batch at which it is uploaded.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_MAX_OUTPUT_BATCH_SIZE_BYTES: u64 = 3 * 1024 * 1024;
+
 /// Default strategy for uploading historical results.
 /// Note: If this value changes the config documentation
 /// should reflect it.
@@ -115,14 +141,54 @@ struct SideChannelInfo {
 /// efficiency reasons. We will request the `FastSlowStore` to populate the entry then we will
 /// assume the `FilesystemStore` has the file available immediately after and hardlink the file
 /// to a new location.
+pub fn download_to_directory<'a>(
+    cas_store: &'a FastSlowStore,
+    filesystem_store: Pin<&'a FilesystemStore>,
+    digest: &'a DigestInfo,
+    current_directory: &'a str,
+) -> BoxFuture<'a, Result<(), Error>> {
+    lay_out_directory(
+        cas_store,
+        filesystem_store,
+        digest,
+        current_directory,
+        FileLayout::Hardlink,
+    )
+}
+
+/// Same as `download_to_directory`, but copies the files instead of hardlinking them, which
+/// shares their blocks with the `FilesystemStore` on filesystems with reflinks.
+pub fn copy_to_directory<'a>(
+    cas_store: &'a FastSlowStore,
+    filesystem_store: Pin<&'a FilesystemStore>,
+    digest: &'a DigestInfo,
+    current_directory: &'a str,
+) -> BoxFuture<'a, Result<(), Error>> {
+    lay_out_directory(
+        cas_store,
+        filesystem_store,
+        digest,
+        current_directory,
+        FileLayout::Copy,
+    )
+}
+
+/// How `lay_out_directory` puts the files of the `FilesystemStore` in the directory.
+#[derive(Debug, Clone, Copy)]
+enum FileLayout {
+    Hardlink,
+    Copy,
+}
+
 // Sadly we cannot use `async fn` here because the rust compiler cannot determine the auto traits
 // of the future. So we need to force this function to return a dynamic future instead.
 // see: https://github.com/rust-lang/rust/issues/78649
-pub fn download_to_directory<'a>(
+fn lay_out_directory<'a>(
     cas_store: &'a FastSlowStore,
     filesystem_store: Pin<&'a FilesystemStore>,
     digest: &'a DigestInfo,
     current_directory: &'a str,
+    file_layout: FileLayout,
 ) -> BoxFuture<'a, Result<(), Error>> {
     async move {
         let directory = get_and_decode_digest::<ProtoDirectory>(cas_store, digest.into())
@@ -154,10 +220,24 @@ pub fn download_to_directory<'a>(
                             .await
                             .err_tip(|| "During hard link")?;
                         file_entry
-                            .get_file_path_locked(|src| fs::hard_link(src, &dest))
+                            .get_file_path_locked(|src| {
+                                let dest = &dest;
+                                async move {
+                                    match file_layout {
+                                        FileLayout::Hardlink => fs::hard_link(src, dest).await,
+                                        FileLayout::Copy => fs::copy(src, dest).await,
+                                    }
+                                }
+                            })
                             .await
-                            .map_err(|e| {
-                                make_err!(Code::Internal, "Could not make hardlink, {e:?} : {dest}")
+                            .map_err(|e| match file_layout {
+                                FileLayout::Hardlink => make_err!(
+                                    Code::Internal,
+                                    "Could not make hardlink, {e:?} : {dest}"
+                                ),
+                                FileLayout::Copy => {
+                                    make_err!(Code::Internal, "Could not copy file, {e:?} : {dest}")
+                                }
                             })?;
                         #[cfg(target_family = "unix")]
                         if let Some(unix_mode) = unix_mode {
@@ -203,11 +283,12 @@ pub fn download_to_directory<'a>(
                     fs::create_dir(&new_directory_path)
                         .await
                         .err_tip(|| format!("Could not create directory {new_directory_path}"))?;
-                    download_to_directory(
+                    lay_out_directory(
                         cas_store,
                         filesystem_store,
                         &digest,
                         &new_directory_path,
+                        file_layout,
                     )
                     .await
                     .err_tip(|| format!("in download_to_directory : {new_directory_path}"))?;
@@ -294,7 +375,15 @@ async fn upload_file(
         .await
         .err_tip(|| format!("for {full_path:?}"))?;
 
-    let name = full_path
+    Ok(FileInfo {
+        name_or_path: NameOrPath::Name(file_name(&full_path)?),
+        digest,
+        is_executable,
+    })
+}
+
+fn file_name(full_path: &(impl AsRef<Path> + Debug)) -> Result<String, Error> {
+    Ok(full_path
         .as_ref()
         .file_name()
         .err_tip(|| format!("Expected file_name to exist on {full_path:?}"))?
@@ -306,13 +395,117 @@ async fn upload_file(
                 full_path
             )
         })?
-        .to_string();
+        .to_string())
+}
 
-    Ok(FileInfo {
-        name_or_path: NameOrPath::Name(name),
-        digest,
-        is_executable,
-    })
+/// Small output files waiting to be uploaded together.
+#[derive(Default)]
+struct OutputBatch {
+    items: Vec<(DigestInfo, Bytes)>,
+    size: u64,
+}
+
+/// Uploads the output files of an action, with at most `max_concurrent_uploads` uploads at
+/// once. Large files are uploaded as soon as they are hashed, small files are collected into
+/// batches that are sent with `update_many()`, which needs `flush()` to be called once all
+/// outputs were given to it.
+struct OutputUploader<'a, S> {
+    cas_store: Pin<&'a S>,
+    hasher: DigestHasherFunc,
+    upload_permits: Semaphore,
+    max_batch_file_size_bytes: u64,
+    max_batch_size_bytes: u64,
+    batch: Mutex<OutputBatch>,
+}
+
+impl<'a, S: StoreLike> OutputUploader<'a, S> {
+    fn new(cas_store: Pin<&'a S>, hasher: DigestHasherFunc, config: &OutputUploadConfig) -> Self {
+        let mut max_concurrent_uploads = config.max_concurrent_uploads;
+        if max_concurrent_uploads == 0 {
+            max_concurrent_uploads = DEFAULT_MAX_CONCURRENT_OUTPUT_UPLOADS;
+        }
+        let mut max_batch_file_size_bytes = config.max_batch_file_size_bytes;
+        if max_batch_file_size_bytes == 0 {
+            max_batch_file_size_bytes = DEFAULT_MAX_BATCH_OUTPUT_FILE_SIZE_BYTES;
+        }
+        let mut max_batch_size_bytes = config.max_batch_size_bytes;
+        if max_batch_size_bytes == 0 {
+            max_batch_size_bytes = DEFAULT_MAX_OUTPUT_BATCH_SIZE_BYTES;
+        }
+        Self {
+            cas_store,
+            hasher,
+            upload_permits: Semaphore::new(max_concurrent_uploads),
+            max_batch_file_size_bytes,
+            max_batch_size_bytes,
+            batch: Mutex::new(OutputBatch::default()),
+        }
+    }
+
+    async fn upload_file(
+        &self,
+        full_path: impl AsRef<Path> + Debug,
+        metadata: std::fs::Metadata,
+    ) -> Result<FileInfo, Error> {
+        if metadata.len() > self.max_batch_file_size_bytes {
+            let _permit =
+                self.upload_permits.acquire().await.map_err(|e| {
+                    make_err!(Code::Internal, "Could not get upload permit : {e:?}")
+                })?;
+            return upload_file(self.cas_store, full_path, self.hasher, metadata).await;
+        }
+        let data = Bytes::from(
+            fs::read(&full_path)
+                .await
+                .err_tip(|| format!("Could not read file {full_path:?}"))?,
+        );
+        let digest = compute_buf_digest(&data, &mut self.hasher.hasher());
+        let maybe_full_batch = {
+            let mut batch = self.batch.lock();
+            batch.size += data.len() as u64;
+            batch.items.push((digest, data));
+            if batch.size >= self.max_batch_size_bytes {
+                Some(std::mem::take(&mut *batch))
+            } else {
+                None
+            }
+        };
+        if let Some(full_batch) = maybe_full_batch {
+            self.upload_batch(full_batch).await?;
+        }
+        Ok(FileInfo {
+            name_or_path: NameOrPath::Name(file_name(&full_path)?),
+            digest,
+            is_executable: is_executable(&metadata, &full_path),
+        })
+    }
+
+    async fn upload_batch(&self, batch: OutputBatch) -> Result<(), Error> {
+        if batch.items.is_empty() {
+            return Ok(());
+        }
+        let _permit = self
+            .upload_permits
+            .acquire()
+            .await
+            .map_err(|e| make_err!(Code::Internal, "Could not get upload permit : {e:?}"))?;
+        let (digests, items): (Vec<_>, Vec<_>) = batch
+            .items
+            .into_iter()
+            .map(|(digest, data)| (digest, (digest.into(), data)))
+            .unzip();
+        let results = self.cas_store.update_many(items).await;
+        for (digest, result) in digests.into_iter().zip(results) {
+            result.err_tip(|| format!("Uploading output file with digest {digest}"))?;
+        }
+        Ok(())
+    }
+
+    /// Uploads the small files that are not uploaded yet.
+    async fn flush(&self) -> Result<(), Error> {
+        let batch = std::mem::take(&mut *self.batch.lock());
+        self.upload_batch(batch).await
+    }
 }
 
 async fn upload_symlink(
@@ -366,11 +559,10 @@ async fn upload_symlink(
     })
 }
 
-fn upload_directory<'a, P: AsRef<Path> + Debug + Send + Sync + Clone + 'a>(
-    cas_store: Pin<&'a impl StoreLike>,
+fn upload_directory<'a, S: StoreLike, P: AsRef<Path> + Debug + Send + Sync + Clone + 'a>(
+    uploader: &'a OutputUploader<'a, S>,
     full_dir_path: P,
     full_work_directory: &'a str,
-    hasher: DigestHasherFunc,
 ) -> BoxFuture<'a, Result<(Directory, VecDeque<ProtoDirectory>), Error>> {
     Box::pin(async move {
         let file_futures = FuturesUnordered::new();
@@ -396,7 +588,7 @@ fn upload_directory<'a, P: AsRef<Path> + Debug + Send + Sync + Clone + 'a>(
                 if file_type.is_dir() {
                     let full_dir_path = full_dir_path.clone();
                     dir_futures.push(
-                        upload_directory(cas_store, full_path.clone(), full_work_directory, hasher)
+                        upload_directory(uploader, full_path.clone(), full_work_directory)
                             .and_then(|(dir, all_dirs)| async move {
                                 let directory_name = full_path
                                     .file_name()
@@ -415,8 +607,8 @@ fn upload_directory<'a, P: AsRef<Path> + Debug + Send + Sync + Clone + 'a>(
 
                                 let digest = serialize_and_upload_message(
                                     &dir,
-                                    cas_store,
-                                    &mut hasher.hasher(),
+                                    uploader.cas_store,
+                                    &mut uploader.hasher.hasher(),
                                 )
                                 .await
                                 .err_tip(|| format!("for {full_path:?}"))?;
@@ -436,7 +628,8 @@ fn upload_directory<'a, P: AsRef<Path> + Debug + Send + Sync + Clone + 'a>(
                         let metadata = fs::metadata(&full_path)
                             .await
                             .err_tip(|| format!("Could not open file {full_path:?}"))?;
-                        upload_file(cas_store, &full_path, hasher, metadata)
+                        uploader
+                            .upload_file(&full_path, metadata)
                             .map_ok(Into::into)
                             .await
                     });
@@ -532,12 +725,18 @@ async fn do_cleanup(
     running_actions_manager: &RunningActionsManagerImpl,
     operation_id: &OperationId,
     action_directory: &str,
+    maybe_overlay_input_root: Option<OverlayInputRoot>,
 ) -> Result<(), Error> {
     event!(Level::INFO, "Worker cleaning up");
     // Note: We need to be careful to keep trying to cleanup even if one of the steps fails.
+    let unmount_result = match maybe_overlay_input_root {
+        Some(overlay_input_root) => overlay_input_root.unmount().await,
+        None => Ok(()),
+    };
     let remove_dir_result = fs::remove_dir_all(action_directory)
         .await
-        .err_tip(|| format!("Could not remove working directory {action_directory}"));
+        .err_tip(|| format!("Could not remove working directory {action_directory}"))
+        .merge(unmount_result);
     if let Err(err) = running_actions_manager.cleanup_action(operation_id) {
         event!(
             Level::ERROR,
@@ -607,6 +806,8 @@ struct RunningActionImplState {
     // that prevented the action from running, upload failures, timeouts, exc...
     // but we have (or could have) the action results (like stderr/stdout).
     error: Option<Error>,
+    // Set if the input root is an overlay, which is unmounted on cleanup.
+    overlay_input_root: Option<OverlayInputRoot>,
 }
 
 pub struct RunningActionImpl {
@@ -646,6 +847,7 @@ impl RunningActionImpl {
                 action_result: None,
                 execution_metadata,
                 error: None,
+                overlay_input_root: None,
             }),
             did_cleanup: AtomicBool::new(false),
         }
@@ -687,15 +889,12 @@ impl RunningActionImpl {
                 // Download the input files/folder and place them into the temp directory.
                 self.metrics()
                     .download_to_directory
-                    .wrap(download_to_directory(
-                        &self.running_actions_manager.cas_store,
-                        filesystem_store_pin,
-                        &self.action_info.input_root_digest,
-                        &self.work_directory,
-                    ))
+                    .wrap(self.make_input_root(filesystem_store_pin))
                     .await
             })
             .await?;
+            self.running_actions_manager
+                .record_input_root(self.action_info.input_root_digest);
             command
         };
         {
@@ -745,6 +944,217 @@ impl RunningActionImpl {
         Ok(self)
     }
 
+    /// Lays out the input root in the work directory the way the
+    /// `InputRootStrategy` of the worker does.
+    async fn make_input_root(&self, filesystem_store: Pin<&FilesystemStore>) -> Result<(), Error> {
+        let cas_store = &self.running_actions_manager.cas_store;
+        let input_root_digest = &self.action_info.input_root_digest;
+        match &self
+            .running_actions_manager
+            .execution_configuration
+            .input_root_strategy
+        {
+            InputRootStrategy::hardlink => {
+                download_to_directory(
+                    cas_store,
+                    filesystem_store,
+                    input_root_digest,
+                    &self.work_directory,
+                )
+                .await
+            }
+            InputRootStrategy::reflink => {
+                copy_to_directory(
+                    cas_store,
+                    filesystem_store,
+                    input_root_digest,
+                    &self.work_directory,
+                )
+                .await
+            }
+            InputRootStrategy::overlayfs(_) => {
+                let shared_input_roots =
+                    self.running_actions_manager
+                        .shared_input_roots
+                        .as_ref()
+                        .err_tip(|| "Expected shared input roots in make_input_root()")?;
+                let input_root = shared_input_roots
+                    .get_or_make(*input_root_digest, |directory| async move {
+                        download_to_directory(
+                            cas_store,
+                            filesystem_store,
+                            input_root_digest,
+                            &directory,
+                        )
+                        .await
+                    })
+                    .await?;
+                let overlay_input_root = OverlayInputRoot::mount(
+                    input_root,
+                    &self.action_directory,
+                    &self.work_directory,
+                )
+                .await?;
+                self.state.lock().overlay_input_root = Some(overlay_input_root);
+                Ok(())
+            }
+        }
+    }
+
+    /// Removes the container of the action in the background if it runs in
+    /// one, as killing its command line may leave it running.
+    fn remove_container(&self) {
+        let ActionRunner::container(config) =
+            &self.running_actions_manager.execution_configuration.runner
+        else {
+            return;
+        };
+        let mut command = remove_container_command(config, &container_name(&self.operation_id));
+        let operation_id = self.operation_id.clone();
+        background_spawn!("running_actions_manager_remove_container", async move {
+            if let Err(err) = command.status().await {
+                event!(
+                    Level::ERROR,
+                    ?operation_id,
+                    ?err,
+                    "Could not remove container of action",
+                );
+            }
+        });
+    }
+
+    /// Starts writing the live stdout or stderr stream of the action to the
+    /// slow CAS store in the background, and returns the sender the data of
+    /// the output is written to the stream with. The stream is finished
+    /// when the sender is dropped.
+    fn start_output_stream(&self, output: ActionOutput) -> mpsc::UnboundedSender<Bytes> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let stream_name = ActionOutputStreamName::new(
+            self.action_info.unique_qualifier.instance_name(),
+            self.action_info.unique_qualifier.digest(),
+            output,
+        );
+        let slow_store = self.running_actions_manager.cas_store.slow_store().clone();
+        let operation_id = self.operation_id.clone();
+        background_spawn!("running_actions_manager_output_stream", async move {
+            let Some(grpc_store) = slow_store.downcast_ref::<GrpcStore>(None) else {
+                return;
+            };
+            if let Err(err) = grpc_store
+                .write_live_stream(stream_name.to_string(), UnboundedReceiverStream::new(rx))
+                .await
+            {
+                event!(
+                    Level::WARN,
+                    ?operation_id,
+                    ?err,
+                    ?stream_name,
+                    "Could not stream output of action",
+                );
+            }
+        });
+        tx
+    }
+
+    /// Runs the action in a persistent worker, which is sent the arguments
+    /// in the flag file of the action as a `WorkRequest`. The output of the
+    /// request is the stderr of the action.
+    async fn execute_in_persistent_worker(
+        self: Arc<Self>,
+        key: PersistentWorkerKey,
+        flagfile: String,
+        command_proto: ProtoCommand,
+        kill_channel_rx: impl Future,
+    ) -> Result<Arc<Self>, Error> {
+        let persistent_workers = self
+            .running_actions_manager
+            .persistent_workers
+            .as_ref()
+            .err_tip(|| "Expected persistent workers in execute_in_persistent_worker()")?;
+        let flagfile_path = format!(
+            "{}/{}/{flagfile}",
+            self.work_directory, command_proto.working_directory
+        );
+        let flagfile_contents = fs::read(&flagfile_path)
+            .await
+            .err_tip(|| format!("Could not read flag file {flagfile_path} of action"))?;
+        let request = WorkRequest {
+            arguments: String::from_utf8_lossy(&flagfile_contents)
+                .lines()
+                .map(str::to_string)
+                .collect(),
+            ..Default::default()
+        };
+        event!(
+            Level::INFO,
+            startup_arguments = ?key.startup_arguments,
+            arguments = ?request.arguments,
+            "Executing command in persistent worker",
+        );
+        let command_line = key.startup_arguments.join(" ");
+
+        let timer = self.metrics().child_process.begin_timer();
+        let maybe_response = tokio::select! {
+            () = (self.running_actions_manager.callbacks.sleep_fn)(self.timeout) => {
+                self.running_actions_manager.metrics.task_timeouts.inc();
+                Err(Error::new(
+                    Code::DeadlineExceeded,
+                    format!(
+                        "Command '{command_line}' timed out after {} seconds",
+                        self.action_info.timeout.as_secs_f32()
+                    ),
+                ))
+            },
+            _ = kill_channel_rx => {
+                Err(Error::new(
+                    Code::Aborted,
+                    format!("Command '{command_line}' was killed by scheduler"),
+                ))
+            },
+            maybe_response = persistent_workers.work(key, &self.work_directory, &request) => {
+                Ok(maybe_response?)
+            },
+        };
+        let (execution_result, maybe_error) = match maybe_response {
+            Ok(response) => {
+                timer.measure();
+                if response.exit_code == 0 {
+                    self.metrics().child_process_success_error_code.inc();
+                } else {
+                    self.metrics().child_process_failure_error_code.inc();
+                }
+                (
+                    RunningActionImplExecutionResult {
+                        stdout: Bytes::new(),
+                        stderr: Bytes::from(response.output),
+                        exit_code: response.exit_code,
+                    },
+                    None,
+                )
+            }
+            Err(err) => {
+                drop(timer);
+                (
+                    RunningActionImplExecutionResult {
+                        stdout: Bytes::new(),
+                        stderr: Bytes::new(),
+                        exit_code: EXIT_CODE_FOR_SIGNAL,
+                    },
+                    Some(err),
+                )
+            }
+        };
+        {
+            let mut state = self.state.lock();
+            state.error = Error::merge_option(state.error.take(), maybe_error);
+            state.command_proto = Some(command_proto);
+            state.execution_result = Some(execution_result);
+            state.execution_metadata.execution_completed_timestamp =
+                (self.running_actions_manager.callbacks.now_fn)();
+        }
+        Ok(self)
+    }
+
     async fn inner_execute(self: Arc<Self>) -> Result<Arc<Self>, Error> {
         let (command_proto, mut kill_channel_rx) = {
             let mut state = self.state.lock();
@@ -766,37 +1176,25 @@ impl RunningActionImpl {
         if command_proto.arguments.is_empty() {
             return Err(make_input_err!("No arguments provided in Command proto"));
         }
-        let args: Vec<&OsStr> = if let Some(entrypoint) = &self
+        let maybe_persistent_worker = self
             .running_actions_manager
-            .execution_configuration
-            .entrypoint
-        {
-            std::iter::once(entrypoint.as_ref())
-                .chain(command_proto.arguments.iter().map(AsRef::as_ref))
-                .collect()
-        } else {
-            command_proto.arguments.iter().map(AsRef::as_ref).collect()
-        };
-        event!(Level::INFO, ?args, "Executing command",);
-        let mut command_builder = process::Command::new(args[0]);
-        command_builder
-            .args(&args[1..])
-            .kill_on_drop(true)
-            .stdin(Stdio::null())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .current_dir(format!(
-                "{}/{}",
-                self.work_directory, command_proto.working_directory
-            ))
-            .env_clear();
-
+            .persistent_workers
+            .as_ref()
+            .and_then(|persistent_workers| {
+                persistent_workers.key_of(&command_proto, &self.action_info.platform_properties)
+            });
+        if let Some((key, flagfile)) = maybe_persistent_worker {
+            return self
+                .execute_in_persistent_worker(key, flagfile, command_proto, kill_channel_rx)
+                .await;
+        }
         let requested_timeout = if self.action_info.timeout.is_zero() {
             self.running_actions_manager.max_action_timeout
         } else {
             self.action_info.timeout
         };
 
+        let mut environment: Vec<(&str, Cow<'_, str>)> = Vec::new();
         let mut maybe_side_channel_file: Option<Cow<'_, OsStr>> = None;
         if let Some(additional_environment) = &self
             .running_actions_manager
@@ -824,7 +1222,7 @@ impl RunningActionImpl {
                         Cow::Borrowed(self.action_directory.as_str())
                     }
                 };
-                command_builder.env(name, value.as_ref());
+                environment.push((name.as_str(), value));
             }
         }
 
@@ -853,8 +1251,93 @@ impl RunningActionImpl {
             }
             envs
         };
-        for environment_variable in envs {
-            command_builder.env(&environment_variable.name, &environment_variable.value);
+        for environment_variable in envs.iter() {
+            environment.push((
+                environment_variable.name.as_str(),
+                Cow::Borrowed(environment_variable.value.as_str()),
+            ));
+        }
+
+        let working_directory = format!(
+            "{}/{}",
+            self.work_directory, command_proto.working_directory
+        );
+        let maybe_runner_command_line =
+            match &self.running_actions_manager.execution_configuration.runner {
+                ActionRunner::local => None,
+                ActionRunner::container(config) => Some(container_command_line(
+                    config,
+                    &container_name(&self.operation_id),
+                    &self.action_info.platform_properties,
+                    &self.action_directory,
+                    &working_directory,
+                    environment.iter().map(|(name, _)| *name),
+                    &command_proto.arguments,
+                )?),
+                ActionRunner::bubblewrap(config) => Some(bubblewrap_command_line(
+                    config,
+                    &self.running_actions_manager.root_action_directory,
+                    &self.action_directory,
+                    &working_directory,
+                    &command_proto.arguments,
+                )?),
+            };
+        let command_args: Vec<&OsStr> = match &maybe_runner_command_line {
+            Some(runner_command_line) => runner_command_line.iter().map(AsRef::as_ref).collect(),
+            None => command_proto.arguments.iter().map(AsRef::as_ref).collect(),
+        };
+        let args: Vec<&OsStr> = if let Some(entrypoint) = &self
+            .running_actions_manager
+            .execution_configuration
+            .entrypoint
+        {
+            std::iter::once(entrypoint.as_ref())
+                .chain(command_args)
+                .collect()
+        } else {
+            command_args
+        };
+        event!(Level::INFO, ?args, "Executing command",);
+        let mut command_builder = process::Command::new(args[0]);
+        command_builder
+            .args(&args[1..])
+            .kill_on_drop(true)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .current_dir(&working_directory);
+        // The container tools may need the environment of the worker, for
+        // example to find their configuration, and only pass the variables
+        // they are told to the container.
+        if !matches!(
+            self.running_actions_manager.execution_configuration.runner,
+            ActionRunner::container(_)
+        ) {
+            command_builder.env_clear();
+        }
+        for (name, value) in &environment {
+            command_builder.env(name, value.as_ref());
+        }
+
+        // Dropping the cgroup kills what is left of the action and removes
+        // the cgroup, so it is kept until the action finished.
+        let maybe_cgroup = match &self
+            .running_actions_manager
+            .execution_configuration
+            .resource_limits
+        {
+            Some(config) => {
+                ActionCgroup::new(
+                    config,
+                    &self.operation_id,
+                    &self.action_info.platform_properties,
+                )
+                .await?
+            }
+            None => None,
+        };
+        if let Some(cgroup) = &maybe_cgroup {
+            cgroup.spawn_in(&mut command_builder)?;
         }
 
         let mut child_process = command_builder
@@ -879,9 +1362,22 @@ impl RunningActionImpl {
             });
         });
 
+        let (maybe_stdout_stream, maybe_stderr_stream) = if self
+            .running_actions_manager
+            .execution_configuration
+            .stream_action_output
+        {
+            (
+                Some(self.start_output_stream(ActionOutput::Stdout)),
+                Some(self.start_output_stream(ActionOutput::Stderr)),
+            )
+        } else {
+            (None, None)
+        };
         let all_stdout_fut = spawn!("stdout_reader", async move {
             let mut all_stdout = BytesMut::new();
             loop {
+                let start = all_stdout.len();
                 let sz = stdout_reader
                     .read_buf(&mut all_stdout)
                     .await
@@ -889,12 +1385,17 @@ impl RunningActionImpl {
                 if sz == 0 {
                     break; // EOF.
                 }
+                if let Some(stdout_stream) = &maybe_stdout_stream {
+                    // The output is still uploaded if the stream was lost.
+                    let _ = stdout_stream.send(Bytes::copy_from_slice(&all_stdout[start..]));
+                }
             }
             Result::<Bytes, Error>::Ok(all_stdout.freeze())
         });
         let all_stderr_fut = spawn!("stderr_reader", async move {
             let mut all_stderr = BytesMut::new();
             loop {
+                let start = all_stderr.len();
                 let sz = stderr_reader
                     .read_buf(&mut all_stderr)
                     .await
@@ -902,6 +1403,10 @@ impl RunningActionImpl {
                 if sz == 0 {
                     break; // EOF.
                 }
+                if let Some(stderr_stream) = &maybe_stderr_stream {
+                    // The output is still uploaded if the stream was lost.
+                    let _ = stderr_stream.send(Bytes::copy_from_slice(&all_stderr[start..]));
+                }
             }
             Result::<Bytes, Error>::Ok(all_stderr.freeze())
         });
@@ -921,6 +1426,7 @@ impl RunningActionImpl {
                             "Could not kill process in RunningActionsManager for action timeout",
                         );
                     }
+                    self.remove_container();
                     {
                         let mut state = self.state.lock();
                         state.error = Error::merge_option(state.error.take(), Some(Error::new(
@@ -937,7 +1443,6 @@ impl RunningActionImpl {
                     // Defuse our guard so it does not try to cleanup and make nessless logs.
                     drop(ScopeGuard::<_, _>::into_inner(child_process_guard));
                     let exit_status = maybe_exit_status.err_tip(|| "Failed to collect exit code of process")?;
-                    // TODO(allada) We should implement stderr/stdout streaming to client here.
                     // If we get killed before the stream is started, then these will lock up.
                     // TODO(allada) There is a significant bug here. If we kill the action and the action creates
                     // child processes, it can create zombies. See: https://github.com/tracemachina/nativelink/issues/225
@@ -963,6 +1468,14 @@ impl RunningActionImpl {
                         EXIT_CODE_FOR_SIGNAL
                     };
 
+                    let maybe_oom_kill = match &maybe_cgroup {
+                        Some(cgroup) if exit_code != 0 => cgroup
+                            .oom_kill_error()
+                            .await
+                            .err_tip(|| "Could not check if action was killed for its memory use")?
+                            .map(|err| (err, cgroup.memory_limit_bytes())),
+                        _ => None,
+                    };
                     let maybe_error_override = if let Some(side_channel_file) = maybe_side_channel_file {
                         process_side_channel_file(side_channel_file.clone(), &args, requested_timeout).await
                         .err_tip(|| format!("Error processing side channel file: {side_channel_file:?}"))?
@@ -972,6 +1485,10 @@ impl RunningActionImpl {
                     {
                         let mut state = self.state.lock();
                         state.error = Error::merge_option(state.error.take(), maybe_error_override);
+                        if let Some((oom_kill_error, memory_limit_bytes)) = maybe_oom_kill {
+                            state.error = Error::merge_option(state.error.take(), Some(oom_kill_error));
+                            state.execution_metadata.oom_kill_memory_limit_bytes = memory_limit_bytes;
+                        }
 
                         state.command_proto = Some(command_proto);
                         state.execution_result = Some(RunningActionImplExecutionResult{
@@ -999,6 +1516,7 @@ impl RunningActionImpl {
                             "Could not get child process id, maybe already dead?",
                         );
                     }
+                    self.remove_container();
                     {
                         let mut state = self.state.lock();
                         state.error = Error::merge_option(state.error.take(), Some(Error::new(
@@ -1043,6 +1561,14 @@ impl RunningActionImpl {
         };
         let cas_store = self.running_actions_manager.cas_store.as_ref();
         let hasher = self.action_info.unique_qualifier.digest_function();
+        let uploader = OutputUploader::new(
+            cas_store.as_pin(),
+            hasher,
+            &self
+                .running_actions_manager
+                .execution_configuration
+                .output_upload,
+        );
 
         let mut output_path_futures = FuturesUnordered::new();
         let mut output_paths = command_proto.output_paths;
@@ -1062,6 +1588,7 @@ impl RunningActionImpl {
                 )
             });
             let work_directory = &self.work_directory;
+            let uploader = &uploader;
             output_path_futures.push(async move {
                 let metadata = {
                     let metadata = match fs::symlink_metadata(&full_path).await {
@@ -1078,7 +1605,8 @@ impl RunningActionImpl {
 
                     if metadata.is_file() {
                         return Ok(OutputType::File(
-                            upload_file(cas_store.as_pin(), &full_path, hasher, metadata)
+                            uploader
+                                .upload_file(&full_path, metadata)
                                 .await
                                 .map(|mut file_info| {
                                     file_info.name_or_path = NameOrPath::Path(entry);
@@ -1091,7 +1619,7 @@ impl RunningActionImpl {
                 };
                 if metadata.is_dir() {
                     Ok(OutputType::Directory(
-                        upload_directory(cas_store.as_pin(), &full_path, work_directory, hasher)
+                        upload_directory(uploader, &full_path, work_directory)
                             .and_then(|(root_dir, children)| async move {
                                 let tree = ProtoTree {
                                     root: Some(root_dir),
@@ -1202,7 +1730,7 @@ impl RunningActionImpl {
                     OutputType::None => { /* Safe to ignore */ }
                 }
             }
-            Ok(())
+            uploader.flush().await
         });
         drop(output_path_futures);
         let (stdout_digest, stderr_digest) = match upload_result {
@@ -1259,9 +1787,15 @@ impl Drop for RunningActionImpl {
         );
         let running_actions_manager = self.running_actions_manager.clone();
         let action_directory = self.action_directory.clone();
+        let maybe_overlay_input_root = self.state.get_mut().overlay_input_root.take();
         background_spawn!("running_action_impl_drop", async move {
-            let Err(err) =
-                do_cleanup(&running_actions_manager, &operation_id, &action_directory).await
+            let Err(err) = do_cleanup(
+                &running_actions_manager,
+                &operation_id,
+                &action_directory,
+                maybe_overlay_input_root,
+            )
+            .await
             else {
                 return;
             };
@@ -1285,7 +1819,7 @@ impl RunningAction for RunningActionImpl {
         self.metrics()
             .clone()
             .prepare_action
-            .wrap(Self::inner_prepare_action(self))
+            .wrap(Self::inner_prepare_action(self).instrument(info_span!("prepare_action")))
             .await
     }
 
@@ -1293,7 +1827,7 @@ impl RunningAction for RunningActionImpl {
         self.metrics()
             .clone()
             .execute
-            .wrap(Self::inner_execute(self))
+            .wrap(Self::inner_execute(self).instrument(info_span!("execute")))
             .await
     }
 
@@ -1301,7 +1835,7 @@ impl RunningAction for RunningActionImpl {
         self.metrics()
             .clone()
             .upload_results
-            .wrap(Self::inner_upload_results(self))
+            .wrap(Self::inner_upload_results(self).instrument(info_span!("upload_results")))
             .await
     }
 
@@ -1310,10 +1844,12 @@ impl RunningAction for RunningActionImpl {
             .clone()
             .cleanup
             .wrap(async move {
+                let maybe_overlay_input_root = self.state.lock().overlay_input_root.take();
                 let result = do_cleanup(
                     &self.running_actions_manager,
                     &self.operation_id,
                     &self.action_directory,
+                    maybe_overlay_input_root,
                 )
                 .await;
                 self.did_cleanup.store(true, Ordering::Release);
@@ -1360,6 +1896,10 @@ pub trait RunningActionsManager: Sync + Send + Sized + Unpin + 'static {
         operation_id: &OperationId,
     ) -> impl Future<Output = Result<(), Error>> + Send;
 
+    /// Returns the input roots of the last actions whose inputs were
+    /// downloaded, most recent first.
+    fn recent_input_roots(&self) -> Vec<DigestInfo>;
+
     fn metrics(&self) -> &Arc<Metrics>;
 }
 
@@ -1392,6 +1932,21 @@ pub struct ExecutionConfiguration {
     /// executes other than those in the `ActionInfo`.  On Windows, `SystemRoot`
     /// and PATH are also assigned (see `inner_execute`).
     pub additional_environment: Option<HashMap<String, EnvironmentSource>>,
+    /// Whether the command runs directly on the worker, in a container or in
+    /// a sandbox.
+    pub runner: ActionRunner,
+    /// If set, the CPU and memory of each action is limited with a cgroup.
+    pub resource_limits: Option<ResourceLimitsConfig>,
+    /// If set, the actions of tools that support it run in persistent
+    /// workers.
+    pub persistent_workers: Option<PersistentWorkersConfig>,
+    /// How the input roots of actions are laid out in their directories.
+    pub input_root_strategy: InputRootStrategy,
+    /// How the output files of actions are uploaded.
+    pub output_upload: OutputUploadConfig,
+    /// Whether the stdout and stderr of actions are streamed to the
+    /// `ByteStream` service of the slow CAS store while they run.
+    pub stream_action_output: bool,
 }
 
 struct UploadActionResults {
@@ -1664,6 +2219,11 @@ pub struct RunningActionsManagerImpl {
     action_done_tx: watch::Sender<()>,
     callbacks: Callbacks,
     metrics: Arc<Metrics>,
+    /// Input roots of the last actions whose inputs were downloaded, most
+    /// recent first.
+    recent_input_roots: Mutex<VecDeque<DigestInfo>>,
+    persistent_workers: Option<PersistentWorkers>,
+    shared_input_roots: Option<Arc<SharedInputRoots>>,
 }
 
 impl RunningActionsManagerImpl {
@@ -1681,7 +2241,66 @@ impl RunningActionsManagerImpl {
             })?
             .get_arc()
             .err_tip(|| "FilesystemStore's internal Arc was lost")?;
+        if args.execution_configuration.stream_action_output
+            && args
+                .cas_store
+                .slow_store()
+                .downcast_ref::<GrpcStore>(None)
+                .is_none()
+        {
+            return Err(make_input_err!(
+                "Expected GrpcStore store for .slow_store() in RunningActionsManagerImpl to stream the output of actions"
+            ));
+        }
+        if args.execution_configuration.resource_limits.is_some()
+            && matches!(
+                args.execution_configuration.runner,
+                ActionRunner::container(_)
+            )
+        {
+            return Err(make_input_err!(
+                "Resource limits can not be used with the container runner"
+            ));
+        }
+        if args.execution_configuration.persistent_workers.is_some() {
+            if !matches!(args.execution_configuration.runner, ActionRunner::local) {
+                return Err(make_input_err!(
+                    "Persistent workers can only be used with the local runner"
+                ));
+            }
+            if args.execution_configuration.resource_limits.is_some() {
+                return Err(make_input_err!(
+                    "Persistent workers can not be used with resource limits"
+                ));
+            }
+        }
         let (action_done_tx, _) = watch::channel(());
+        let shared_input_roots = match &args.execution_configuration.input_root_strategy {
+            InputRootStrategy::hardlink | InputRootStrategy::reflink => None,
+            InputRootStrategy::overlayfs(config) => {
+                if args.execution_configuration.persistent_workers.is_some() {
+                    return Err(make_input_err!(
+                        "The overlayfs input root strategy can not be used with persistent workers"
+                    ));
+                }
+                Some(Arc::new(SharedInputRoots::new(
+                    config,
+                    format!("{}/input_roots", args.root_action_directory),
+                )))
+            }
+        };
+        // Operation ids are UUIDs, so these do not clash with the
+        // directories of the actions.
+        let persistent_workers = args
+            .execution_configuration
+            .persistent_workers
+            .as_ref()
+            .map(|config| {
+                PersistentWorkers::new(
+                    config,
+                    format!("{}/persistent_workers", args.root_action_directory),
+                )
+            });
         Ok(Self {
             root_action_directory: args.root_action_directory,
             execution_configuration: args.execution_configuration,
@@ -1699,6 +2318,9 @@ impl RunningActionsManagerImpl {
             action_done_tx,
             callbacks,
             metrics: Arc::new(Metrics::default()),
+            recent_input_roots: Mutex::new(VecDeque::with_capacity(MAX_RECENT_INPUT_ROOTS)),
+            persistent_workers,
+            shared_input_roots,
         })
     }
 
@@ -1712,6 +2334,15 @@ impl RunningActionsManagerImpl {
         )
     }
 
+    /// Remembers that the files of `input_root_digest` were downloaded, so
+    /// they are likely in the local cache.
+    fn record_input_root(&self, input_root_digest: DigestInfo) {
+        let mut recent_input_roots = self.recent_input_roots.lock();
+        recent_input_roots.retain(|digest| *digest != input_root_digest);
+        recent_input_roots.push_front(input_root_digest);
+        recent_input_roots.truncate(MAX_RECENT_INPUT_ROOTS);
+    }
+
     fn make_action_directory<'a>(
         &'a self,
         operation_id: &'a OperationId,
@@ -1744,13 +2375,18 @@ impl RunningActionsManagerImpl {
                 get_and_decode_digest::<Action>(self.cas_store.as_ref(), action_digest.into())
                     .await
                     .err_tip(|| "During start_action")?;
-            let action_info = ActionInfo::try_from_action_and_execute_request(
+            let mut action_info = ActionInfo::try_from_action_and_execute_request(
                 execute_request,
                 action,
                 load_start_timestamp,
                 queued_timestamp,
             )
             .err_tip(|| "Could not create ActionInfo in create_and_add_action()")?;
+            if let Some(timeout) = start_execute.timeout {
+                action_info.timeout = timeout.try_into().map_err(|_| {
+                    make_input_err!("Failed convert proto duration to system duration")
+                })?;
+            }
             Ok(action_info)
         })
     }
@@ -1824,6 +2460,8 @@ impl RunningActionsManager for RunningActionsManagerImpl {
                     execution_completed_timestamp: SystemTime::UNIX_EPOCH,
                     output_upload_start_timestamp: SystemTime::UNIX_EPOCH,
                     output_upload_completed_timestamp: SystemTime::UNIX_EPOCH,
+                    retries: 0,
+                    oom_kill_memory_limit_bytes: None,
                 };
                 let timeout = if action_info.timeout.is_zero() || self.timeout_handled_externally {
                     self.max_action_timeout
@@ -1921,6 +2559,10 @@ impl RunningActionsManager for RunningActionsManagerImpl {
             .await;
     }
 
+    fn recent_input_roots(&self) -> Vec<DigestInfo> {
+        self.recent_input_roots.lock().iter().copied().collect()
+    }
+
     #[inline]
     fn metrics(&self) -> &Arc<Metrics> {
         &self.metrics
@@ -0,0 +1,81 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use nativelink_config::cas_server::ResourceLimitsConfig;
+use nativelink_error::{Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_worker::cgroup::{cpu_shares_to_weight, ResourceLimits};
+use pretty_assertions::assert_eq;
+
+#[nativelink_test]
+async fn properties_override_default_limits_test() -> Result<(), Error> {
+    let config = ResourceLimitsConfig {
+        cgroup_directory: "/sys/fs/cgroup/nativelink".to_string(),
+        default_cpu_shares: 1024,
+        default_memory_limit_bytes: 1 << 30,
+        ..Default::default()
+    };
+    assert_eq!(
+        ResourceLimits::new(&config, &HashMap::new())?,
+        ResourceLimits {
+            cpu_weight: Some(39),
+            memory_limit_bytes: Some(1 << 30),
+        }
+    );
+    assert_eq!(
+        ResourceLimits::new(
+            &config,
+            &HashMap::from([
+                ("cpu-shares".to_string(), "262144".to_string()),
+                ("memory-limit-bytes".to_string(), "4096".to_string()),
+            ])
+        )?,
+        ResourceLimits {
+            cpu_weight: Some(10000),
+            memory_limit_bytes: Some(4096),
+        }
+    );
+    // Actions without properties have no limits if there are no defaults.
+    assert_eq!(
+        ResourceLimits::new(&ResourceLimitsConfig::default(), &HashMap::new())?,
+        ResourceLimits::default()
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn invalid_limit_is_rejected_test() -> Result<(), Error> {
+    let config = ResourceLimitsConfig {
+        memory_limit_property: "mem".to_string(),
+        ..Default::default()
+    };
+    let err = ResourceLimits::new(
+        &config,
+        &HashMap::from([("mem".to_string(), "4G".to_string())]),
+    )
+    .unwrap_err();
+    assert_eq!(err.code, Code::InvalidArgument, "{err:?}");
+    Ok(())
+}
+
+#[nativelink_test]
+async fn cpu_shares_map_to_weight_range_test() -> Result<(), Error> {
+    assert_eq!(cpu_shares_to_weight(0), 1);
+    assert_eq!(cpu_shares_to_weight(2), 1);
+    assert_eq!(cpu_shares_to_weight(1024), 39);
+    assert_eq!(cpu_shares_to_weight(u64::MAX), 10000);
+    Ok(())
+}
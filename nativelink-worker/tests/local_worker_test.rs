@@ -31,7 +31,9 @@ mod utils {
 
 use hyper::body::Frame;
 use nativelink_config::cas_server::{LocalWorkerConfig, WorkerProperty};
-use nativelink_config::stores::{FastSlowSpec, FilesystemSpec, MemorySpec, StoreSpec};
+use nativelink_config::stores::{
+    FastSlowSpec, FilesystemSpec, MemorySpec, PopulateOnReadPolicy, StoreSpec,
+};
 use nativelink_error::{make_err, make_input_err, Code, Error};
 use nativelink_macro::nativelink_test;
 use nativelink_proto::build::bazel::remote::execution::v2::platform::Property;
@@ -251,6 +253,9 @@ async fn blake3_digest_function_registerd_properly() -> Result<(), Box<dyn std::
                     queued_timestamp: None,
                     platform: Some(Platform::default()),
                     worker_id: expected_worker_id.clone(),
+                    trace_context: HashMap::new(),
+                    request_id: String::new(),
+                    timeout: None,
                 })),
             })?))
             .await
@@ -335,6 +340,9 @@ async fn simple_worker_start_action_test() -> Result<(), Box<dyn std::error::Err
                     queued_timestamp: None,
                     platform: Some(Platform::default()),
                     worker_id: expected_worker_id.clone(),
+                    trace_context: HashMap::new(),
+                    request_id: String::new(),
+                    timeout: None,
                 })),
             })?))
             .await
@@ -359,6 +367,8 @@ async fn simple_worker_start_action_test() -> Result<(), Box<dyn std::error::Err
             execution_completed_timestamp: SystemTime::UNIX_EPOCH,
             output_upload_start_timestamp: SystemTime::UNIX_EPOCH,
             output_upload_completed_timestamp: SystemTime::UNIX_EPOCH,
+            retries: 0,
+            oom_kill_memory_limit_bytes: None,
         },
         server_logs: HashMap::new(),
         error: None,
@@ -416,6 +426,8 @@ async fn new_local_worker_creates_work_directory_test() -> Result<(), Box<dyn st
             // Note: These are not needed for this test, so we put dummy memory stores here.
             fast: StoreSpec::memory(MemorySpec::default()),
             slow: StoreSpec::memory(MemorySpec::default()),
+            populate_on_read: PopulateOnReadPolicy::always,
+            write_back: None,
         },
         Store::new(
             <FilesystemStore>::new(&FilesystemSpec {
@@ -456,6 +468,8 @@ async fn new_local_worker_removes_work_directory_before_start_test(
             // Note: These are not needed for this test, so we put dummy memory stores here.
             fast: StoreSpec::memory(MemorySpec::default()),
             slow: StoreSpec::memory(MemorySpec::default()),
+            populate_on_read: PopulateOnReadPolicy::always,
+            write_back: None,
         },
         Store::new(
             <FilesystemStore>::new(&FilesystemSpec {
@@ -588,6 +602,9 @@ async fn experimental_precondition_script_fails() -> Result<(), Box<dyn std::err
                     queued_timestamp: None,
                     platform: Some(Platform::default()),
                     worker_id: expected_worker_id.clone(),
+                    trace_context: HashMap::new(),
+                    request_id: String::new(),
+                    timeout: None,
                 })),
             })?))
             .await
@@ -673,6 +690,9 @@ async fn kill_action_request_kills_action() -> Result<(), Box<dyn std::error::Er
                     queued_timestamp: None,
                     platform: Some(Platform::default()),
                     worker_id: expected_worker_id.clone(),
+                    trace_context: HashMap::new(),
+                    request_id: String::new(),
+                    timeout: None,
                 })),
             })?))
             .await
@@ -0,0 +1,115 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::env;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use nativelink_config::cas_server::OverlayfsInputRootConfig;
+use nativelink_error::Error;
+use nativelink_macro::nativelink_test;
+use nativelink_util::common::{fs, DigestInfo};
+use nativelink_worker::overlay_input_root::{
+    overlay_mount_arguments, SharedInputRoot, SharedInputRoots,
+};
+use pretty_assertions::assert_eq;
+use rand::{thread_rng, Rng};
+
+fn make_temp_path(data: &str) -> String {
+    format!(
+        "{}/{}/{}",
+        env::var("TEST_TMPDIR").unwrap_or(env::temp_dir().to_str().unwrap().to_string()),
+        thread_rng().gen::<u64>(),
+        data
+    )
+}
+
+/// Gets the input root, writing a file with its digest to new directories
+/// and counting how often that is done.
+async fn get_input_root(
+    shared_input_roots: &Arc<SharedInputRoots>,
+    digest: DigestInfo,
+    made_count: &AtomicUsize,
+) -> Result<SharedInputRoot, Error> {
+    shared_input_roots
+        .get_or_make(digest, |directory| async move {
+            made_count.fetch_add(1, Ordering::Relaxed);
+            Ok(tokio::fs::write(format!("{directory}/digest"), digest.to_string()).await?)
+        })
+        .await
+}
+
+#[nativelink_test]
+async fn input_root_is_made_once_test() -> Result<(), Error> {
+    let shared_input_roots = Arc::new(SharedInputRoots::new(
+        &OverlayfsInputRootConfig::default(),
+        make_temp_path("input_roots"),
+    ));
+    let digest = DigestInfo::new([1u8; 32], 32);
+    let made_count = AtomicUsize::new(0);
+
+    let first_input_root = get_input_root(&shared_input_roots, digest, &made_count).await?;
+    let second_input_root = get_input_root(&shared_input_roots, digest, &made_count).await?;
+    assert_eq!(made_count.load(Ordering::Relaxed), 1);
+    assert_eq!(first_input_root.directory(), second_input_root.directory());
+    let content = fs::read(format!("{}/digest", first_input_root.directory())).await?;
+    assert_eq!(String::from_utf8_lossy(&content), digest.to_string());
+    Ok(())
+}
+
+#[nativelink_test]
+async fn only_unused_input_roots_are_removed_test() -> Result<(), Error> {
+    let shared_input_roots = Arc::new(SharedInputRoots::new(
+        &OverlayfsInputRootConfig { max_input_roots: 1 },
+        make_temp_path("input_roots"),
+    ));
+    let first_digest = DigestInfo::new([1u8; 32], 32);
+    let second_digest = DigestInfo::new([2u8; 32], 32);
+    let third_digest = DigestInfo::new([3u8; 32], 32);
+    let made_count = AtomicUsize::new(0);
+
+    // The first input root is in use, so it is kept.
+    let first_input_root = get_input_root(&shared_input_roots, first_digest, &made_count).await?;
+    drop(get_input_root(&shared_input_roots, second_digest, &made_count).await?);
+    drop(get_input_root(&shared_input_roots, first_digest, &made_count).await?);
+    assert_eq!(made_count.load(Ordering::Relaxed), 2);
+
+    // Once it is not used, it is removed for a newer one.
+    drop(first_input_root);
+    drop(get_input_root(&shared_input_roots, third_digest, &made_count).await?);
+    drop(get_input_root(&shared_input_roots, first_digest, &made_count).await?);
+    assert_eq!(made_count.load(Ordering::Relaxed), 4);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn overlay_mount_arguments_test() -> Result<(), Error> {
+    assert_eq!(
+        overlay_mount_arguments(
+            "/roots/abc",
+            "/actions/1/upper",
+            "/actions/1/work",
+            "/actions/1/in"
+        ),
+        vec![
+            "-t",
+            "overlay",
+            "overlay",
+            "-o",
+            "lowerdir=/roots/abc,upperdir=/actions/1/upper,workdir=/actions/1/work",
+            "/actions/1/in",
+        ]
+    );
+    Ok(())
+}
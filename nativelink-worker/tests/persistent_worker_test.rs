@@ -0,0 +1,173 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use nativelink_config::cas_server::PersistentWorkersConfig;
+use nativelink_error::Error;
+use nativelink_macro::nativelink_test;
+use nativelink_proto::blaze::worker::{WorkRequest, WorkResponse};
+use nativelink_proto::build::bazel::remote::execution::v2::command::EnvironmentVariable;
+use nativelink_proto::build::bazel::remote::execution::v2::Command as ProtoCommand;
+use nativelink_worker::persistent_worker::{
+    read_work_response, write_work_request, PersistentWorkerKey,
+};
+use pretty_assertions::assert_eq;
+use prost::Message;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+fn make_command(arguments: &[&str]) -> ProtoCommand {
+    ProtoCommand {
+        arguments: arguments.iter().map(ToString::to_string).collect(),
+        environment_variables: vec![
+            EnvironmentVariable {
+                name: "PATH".to_string(),
+                value: "/bin".to_string(),
+            },
+            EnvironmentVariable {
+                name: "LANG".to_string(),
+                value: "C".to_string(),
+            },
+        ],
+        working_directory: "src".to_string(),
+        ..Default::default()
+    }
+}
+
+#[nativelink_test]
+async fn key_of_action_with_flagfile_test() -> Result<(), Error> {
+    let config = PersistentWorkersConfig::default();
+    let platform_properties =
+        HashMap::from([("persistentWorkerKey".to_string(), "abc123".to_string())]);
+    let expected_key = PersistentWorkerKey {
+        worker_key: "abc123".to_string(),
+        startup_arguments: vec![
+            "bin/javac".to_string(),
+            "--flag".to_string(),
+            "--persistent_worker".to_string(),
+        ],
+        working_directory: "src".to_string(),
+        environment: vec![
+            ("LANG".to_string(), "C".to_string()),
+            ("PATH".to_string(), "/bin".to_string()),
+        ],
+    };
+    assert_eq!(
+        PersistentWorkerKey::for_action(
+            &config,
+            &make_command(&["bin/javac", "--flag", "@out/args.params"]),
+            &platform_properties,
+        ),
+        Some((expected_key.clone(), "out/args.params".to_string()))
+    );
+    assert_eq!(
+        PersistentWorkerKey::for_action(
+            &config,
+            &make_command(&["bin/javac", "--flag", "--flagfile=out/args.params"]),
+            &platform_properties,
+        ),
+        Some((expected_key, "out/args.params".to_string()))
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn no_key_without_property_or_flagfile_test() -> Result<(), Error> {
+    let config = PersistentWorkersConfig {
+        key_property: "workerKey".to_string(),
+        ..Default::default()
+    };
+    let command = make_command(&["bin/javac", "@out/args.params"]);
+    // The default property is not used when another one is configured.
+    assert_eq!(
+        PersistentWorkerKey::for_action(
+            &config,
+            &command,
+            &HashMap::from([("persistentWorkerKey".to_string(), "abc123".to_string())]),
+        ),
+        None
+    );
+    let platform_properties = HashMap::from([("workerKey".to_string(), "abc123".to_string())]);
+    assert_eq!(
+        PersistentWorkerKey::for_action(
+            &config,
+            &make_command(&["bin/javac", "out/args.params"]),
+            &platform_properties,
+        ),
+        None
+    );
+    assert_eq!(
+        PersistentWorkerKey::for_action(
+            &config,
+            &make_command(&["@out/args.params"]),
+            &platform_properties,
+        ),
+        None
+    );
+    assert!(PersistentWorkerKey::for_action(&config, &command, &platform_properties).is_some());
+    Ok(())
+}
+
+#[nativelink_test]
+async fn work_request_and_response_round_trip_test() -> Result<(), Error> {
+    let (mut worker_side, mut nativelink_side) = tokio::io::duplex(1024);
+    let request = WorkRequest {
+        arguments: vec!["-d".to_string(), "out".to_string()],
+        request_id: 1,
+        ..Default::default()
+    };
+    write_work_request(&mut nativelink_side, &request).await?;
+    let mut sent = vec![0; request.encoded_len() + 1];
+    worker_side.read_exact(&mut sent).await?;
+    assert_eq!(
+        WorkRequest::decode_length_delimited(sent.as_slice())?,
+        request
+    );
+
+    // Larger than one varint byte, so the length takes two.
+    let response = WorkResponse {
+        exit_code: 1,
+        output: "error: ".repeat(50),
+        request_id: 1,
+        ..Default::default()
+    };
+    worker_side
+        .write_all(&response.encode_length_delimited_to_vec())
+        .await?;
+    assert_eq!(
+        read_work_response(&mut nativelink_side, 1024).await?,
+        response
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn work_response_larger_than_max_size_test() -> Result<(), Error> {
+    let (mut worker_side, mut nativelink_side) = tokio::io::duplex(1024);
+    // Only the length is sent, the response must be rejected before it is
+    // read.
+    let mut delimiter = Vec::new();
+    prost::encode_length_delimiter(1 << 30, &mut delimiter)?;
+    worker_side.write_all(&delimiter).await?;
+
+    let err = read_work_response(&mut nativelink_side, 1024)
+        .await
+        .unwrap_err();
+    assert!(
+        err.message_string()
+            .contains("more than the maximum of 1024 bytes"),
+        "{err:?}"
+    );
+    Ok(())
+}
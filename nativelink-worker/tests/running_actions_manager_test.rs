@@ -26,8 +26,13 @@ use std::sync::{Arc, LazyLock, Mutex};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use futures::{FutureExt, StreamExt, TryFutureExt, TryStreamExt};
-use nativelink_config::cas_server::EnvironmentSource;
-use nativelink_config::stores::{FastSlowSpec, FilesystemSpec, MemorySpec, StoreSpec};
+use nativelink_config::cas_server::{
+    ActionRunner, BubblewrapRunnerConfig, ContainerRunnerConfig, EnvironmentSource,
+    InputRootStrategy, OutputUploadConfig, PersistentWorkersConfig, ResourceLimitsConfig,
+};
+use nativelink_config::stores::{
+    FastSlowSpec, FilesystemSpec, MemorySpec, PopulateOnReadPolicy, StoreSpec,
+};
 use nativelink_error::{make_input_err, Code, Error, ResultExt};
 use nativelink_macro::nativelink_test;
 use nativelink_proto::build::bazel::remote::execution::v2::command::EnvironmentVariable;
@@ -54,8 +59,8 @@ use nativelink_util::common::{fs, DigestInfo};
 use nativelink_util::digest_hasher::{DigestHasher, DigestHasherFunc};
 use nativelink_util::store_trait::{Store, StoreLike};
 use nativelink_worker::running_actions_manager::{
-    download_to_directory, Callbacks, ExecutionConfiguration, RunningAction, RunningActionImpl,
-    RunningActionsManager, RunningActionsManagerArgs, RunningActionsManagerImpl,
+    copy_to_directory, download_to_directory, Callbacks, ExecutionConfiguration, RunningAction,
+    RunningActionImpl, RunningActionsManager, RunningActionsManagerArgs, RunningActionsManagerImpl,
 };
 use pretty_assertions::assert_eq;
 use prost::Message;
@@ -104,6 +109,8 @@ async fn setup_stores() -> Result<
         &FastSlowSpec {
             fast: StoreSpec::filesystem(fast_config),
             slow: StoreSpec::memory(slow_config),
+            populate_on_read: PopulateOnReadPolicy::always,
+            write_back: None,
         },
         Store::new(fast_store.clone()),
         Store::new(slow_store.clone()),
@@ -340,6 +347,57 @@ async fn download_to_directory_folder_download_test() -> Result<(), Box<dyn std:
 
 // Windows does not support symlinks.
 #[cfg(not(target_family = "windows"))]
+#[nativelink_test]
+async fn copy_to_directory_copies_files_test() -> Result<(), Box<dyn std::error::Error>> {
+    const FILE_NAME: &str = "file.txt";
+    const FILE_CONTENT: &str = "HELLOFILE";
+
+    let (fast_store, slow_store, cas_store, _ac_store) = setup_stores().await?;
+
+    let root_directory_digest = {
+        let file_content_digest = DigestInfo::new([2u8; 32], 32);
+        slow_store
+            .as_ref()
+            .update_oneshot(file_content_digest, FILE_CONTENT.into())
+            .await?;
+        let root_directory_digest = DigestInfo::new([1u8; 32], 32);
+        let root_directory = Directory {
+            files: vec![FileNode {
+                name: FILE_NAME.to_string(),
+                digest: Some(file_content_digest.into()),
+                is_executable: false,
+                node_properties: None,
+            }],
+            ..Default::default()
+        };
+        slow_store
+            .as_ref()
+            .update_oneshot(root_directory_digest, root_directory.encode_to_vec().into())
+            .await?;
+        root_directory_digest
+    };
+
+    let copy_dir = make_temp_path("copy_dir");
+    fs::create_dir_all(&copy_dir)
+        .await
+        .err_tip(|| format!("Could not make copy_dir : {copy_dir}"))?;
+    copy_to_directory(
+        cas_store.as_ref(),
+        fast_store.as_pin(),
+        &root_directory_digest,
+        &copy_dir,
+    )
+    .await?;
+
+    let file_path = format!("{copy_dir}/{FILE_NAME}");
+    let file_content = fs::read(&file_path).await?;
+    assert_eq!(std::str::from_utf8(&file_content)?, FILE_CONTENT);
+    // A copy is not another link to the file in the store.
+    #[cfg(target_family = "unix")]
+    assert_eq!(fs::metadata(&file_path).await?.nlink(), 1);
+    Ok(())
+}
+
 #[nativelink_test]
 async fn download_to_directory_symlink_download_test() -> Result<(), Box<dyn std::error::Error>> {
     const FILE_NAME: &str = "file.txt";
@@ -504,6 +562,9 @@ async fn ensure_output_files_full_directories_are_created_no_working_directory_t
                     queued_timestamp: None,
                     platform: action.platform.clone(),
                     worker_id: WORKER_ID.to_string(),
+                    trace_context: HashMap::new(),
+                    request_id: String::new(),
+                    timeout: None,
                 },
             )
             .await?;
@@ -623,6 +684,9 @@ async fn ensure_output_files_full_directories_are_created_test(
                     queued_timestamp: None,
                     platform: action.platform.clone(),
                     worker_id: WORKER_ID.to_string(),
+                    trace_context: HashMap::new(),
+                    request_id: String::new(),
+                    timeout: None,
                 },
             )
             .await?;
@@ -758,6 +822,9 @@ async fn blake3_upload_files() -> Result<(), Box<dyn std::error::Error>> {
                     queued_timestamp: None,
                     platform: action.platform.clone(),
                     worker_id: WORKER_ID.to_string(),
+                    trace_context: HashMap::new(),
+                    request_id: String::new(),
+                    timeout: None,
                 },
             )
             .await?;
@@ -814,6 +881,8 @@ async fn blake3_upload_files() -> Result<(), Box<dyn std::error::Error>> {
                 execution_completed_timestamp: increment_clock(&mut clock_time),
                 output_upload_start_timestamp: increment_clock(&mut clock_time),
                 output_upload_completed_timestamp: increment_clock(&mut clock_time),
+                retries: 0,
+                oom_kill_memory_limit_bytes: None,
                 worker_completed_timestamp: increment_clock(&mut clock_time),
             },
             error: None,
@@ -932,6 +1001,9 @@ async fn upload_files_from_above_cwd_test() -> Result<(), Box<dyn std::error::Er
                     queued_timestamp: None,
                     platform: action.platform.clone(),
                     worker_id: WORKER_ID.to_string(),
+                    trace_context: HashMap::new(),
+                    request_id: String::new(),
+                    timeout: None,
                 },
             )
             .await?;
@@ -988,6 +1060,8 @@ async fn upload_files_from_above_cwd_test() -> Result<(), Box<dyn std::error::Er
                 execution_completed_timestamp: increment_clock(&mut clock_time),
                 output_upload_start_timestamp: increment_clock(&mut clock_time),
                 output_upload_completed_timestamp: increment_clock(&mut clock_time),
+                retries: 0,
+                oom_kill_memory_limit_bytes: None,
                 worker_completed_timestamp: increment_clock(&mut clock_time),
             },
             error: None,
@@ -997,6 +1071,139 @@ async fn upload_files_from_above_cwd_test() -> Result<(), Box<dyn std::error::Er
     Ok(())
 }
 
+#[cfg(target_family = "unix")]
+#[nativelink_test]
+async fn upload_small_and_large_output_files_test() -> Result<(), Box<dyn std::error::Error>> {
+    const WORKER_ID: &str = "foo_worker_id";
+
+    fn test_monotonic_clock() -> SystemTime {
+        static CLOCK: AtomicU64 = AtomicU64::new(0);
+        monotonic_clock(&CLOCK)
+    }
+
+    let (_, slow_store, cas_store, ac_store) = setup_stores().await?;
+    let root_action_directory = make_temp_path("root_action_directory");
+    fs::create_dir_all(&root_action_directory).await?;
+
+    let running_actions_manager = Arc::new(RunningActionsManagerImpl::new_with_callbacks(
+        RunningActionsManagerArgs {
+            root_action_directory,
+            execution_configuration: ExecutionConfiguration {
+                // Each of the small files fills a batch of its own.
+                output_upload: OutputUploadConfig {
+                    max_concurrent_uploads: 1,
+                    max_batch_file_size_bytes: 4,
+                    max_batch_size_bytes: 1,
+                },
+                ..Default::default()
+            },
+            cas_store: cas_store.clone(),
+            ac_store: Some(Store::new(ac_store.clone())),
+            historical_store: Store::new(cas_store.clone()),
+            upload_action_result_config: &nativelink_config::cas_server::UploadActionResultConfig {
+                upload_ac_results_strategy:
+                    nativelink_config::cas_server::UploadCacheResultsStrategy::never,
+                ..Default::default()
+            },
+            max_action_timeout: Duration::MAX,
+            timeout_handled_externally: false,
+        },
+        Callbacks {
+            now_fn: test_monotonic_clock,
+            sleep_fn: |_duration| Box::pin(futures::future::pending()),
+        },
+    )?);
+    let action_result = {
+        let command = Command {
+            arguments: vec![
+                "sh".to_string(),
+                "-c".to_string(),
+                "printf 'abc' > small1.txt; printf 'def' > small2.txt; printf 'large file' > large.txt"
+                    .to_string(),
+            ],
+            output_paths: vec![
+                "large.txt".to_string(),
+                "small1.txt".to_string(),
+                "small2.txt".to_string(),
+            ],
+            ..Default::default()
+        };
+        let command_digest = serialize_and_upload_message(
+            &command,
+            cas_store.as_pin(),
+            &mut DigestHasherFunc::Sha256.hasher(),
+        )
+        .await?;
+        let input_root_digest = serialize_and_upload_message(
+            &Directory::default(),
+            cas_store.as_pin(),
+            &mut DigestHasherFunc::Sha256.hasher(),
+        )
+        .await?;
+        let action = Action {
+            command_digest: Some(command_digest.into()),
+            input_root_digest: Some(input_root_digest.into()),
+            ..Default::default()
+        };
+        let action_digest = serialize_and_upload_message(
+            &action,
+            cas_store.as_pin(),
+            &mut DigestHasherFunc::Sha256.hasher(),
+        )
+        .await?;
+
+        let running_action_impl = running_actions_manager
+            .create_and_add_action(
+                WORKER_ID.to_string(),
+                StartExecute {
+                    execute_request: Some(ExecuteRequest {
+                        action_digest: Some(action_digest.into()),
+                        ..Default::default()
+                    }),
+                    operation_id: OperationId::default().to_string(),
+                    queued_timestamp: None,
+                    platform: action.platform.clone(),
+                    worker_id: WORKER_ID.to_string(),
+                    trace_context: HashMap::new(),
+                    request_id: String::new(),
+                    timeout: None,
+                },
+            )
+            .await?;
+
+        run_action(running_action_impl.clone()).await?
+    };
+    let mut output_contents = Vec::new();
+    for output_file in &action_result.output_files {
+        let content = slow_store
+            .as_ref()
+            .get_part_unchunked(output_file.digest, 0, None)
+            .await?;
+        output_contents.push((
+            output_file.name_or_path.clone(),
+            from_utf8(&content)?.to_string(),
+        ));
+    }
+    assert_eq!(
+        output_contents,
+        vec![
+            (
+                NameOrPath::Path("large.txt".to_string()),
+                "large file".to_string()
+            ),
+            (
+                NameOrPath::Path("small1.txt".to_string()),
+                "abc".to_string()
+            ),
+            (
+                NameOrPath::Path("small2.txt".to_string()),
+                "def".to_string()
+            ),
+        ]
+    );
+    Ok(())
+}
+
 // Windows does not support symlinks.
 #[cfg(not(target_family = "windows"))]
 #[cfg_attr(feature = "nix", ignore)]
@@ -1095,6 +1302,9 @@ async fn upload_dir_and_symlink_test() -> Result<(), Box<dyn std::error::Error>>
                     queued_timestamp: Some(queued_timestamp.into()),
                     platform: action.platform.clone(),
                     worker_id: WORKER_ID.to_string(),
+                    trace_context: HashMap::new(),
+                    request_id: String::new(),
+                    timeout: None,
                 },
             )
             .await?;
@@ -1197,6 +1407,8 @@ async fn upload_dir_and_symlink_test() -> Result<(), Box<dyn std::error::Error>>
                 execution_completed_timestamp: increment_clock(&mut clock_time),
                 output_upload_start_timestamp: increment_clock(&mut clock_time),
                 output_upload_completed_timestamp: increment_clock(&mut clock_time),
+                retries: 0,
+                oom_kill_memory_limit_bytes: None,
                 worker_completed_timestamp: increment_clock(&mut clock_time),
             },
             error: None,
@@ -1292,6 +1504,9 @@ async fn cleanup_happens_on_job_failure() -> Result<(), Box<dyn std::error::Erro
                     queued_timestamp: Some(queued_timestamp.into()),
                     platform: action.platform.clone(),
                     worker_id: WORKER_ID.to_string(),
+                    trace_context: HashMap::new(),
+                    request_id: String::new(),
+                    timeout: None,
                 },
             )
             .await?;
@@ -1326,6 +1541,8 @@ async fn cleanup_happens_on_job_failure() -> Result<(), Box<dyn std::error::Erro
                 execution_completed_timestamp: increment_clock(&mut clock_time),
                 output_upload_start_timestamp: increment_clock(&mut clock_time),
                 output_upload_completed_timestamp: increment_clock(&mut clock_time),
+                retries: 0,
+                oom_kill_memory_limit_bytes: None,
                 worker_completed_timestamp: increment_clock(&mut clock_time),
             },
             error: None,
@@ -1426,6 +1643,9 @@ async fn kill_ends_action() -> Result<(), Box<dyn std::error::Error>> {
                 queued_timestamp: Some(make_system_time(1000).into()),
                 platform: action.platform.clone(),
                 worker_id: WORKER_ID.to_string(),
+                trace_context: HashMap::new(),
+                request_id: String::new(),
+                timeout: None,
             },
         )
         .await?;
@@ -1514,6 +1734,12 @@ exit 0
             execution_configuration: ExecutionConfiguration {
                 entrypoint: Some(test_wrapper_script.into_string().unwrap()),
                 additional_environment: None,
+                runner: ActionRunner::local,
+                resource_limits: None,
+                persistent_workers: None,
+                input_root_strategy: InputRootStrategy::hardlink,
+                output_upload: OutputUploadConfig::default(),
+                stream_action_output: false,
             },
             cas_store: cas_store.clone(),
             ac_store: Some(Store::new(ac_store.clone())),
@@ -1579,6 +1805,9 @@ exit 0
                 queued_timestamp: Some(make_system_time(1000).into()),
                 platform: action.platform.clone(),
                 worker_id: WORKER_ID.to_string(),
+                trace_context: HashMap::new(),
+                request_id: String::new(),
+                timeout: None,
             },
         )
         .await?;
@@ -1601,87 +1830,50 @@ exit 0
     Ok(())
 }
 
-#[cfg_attr(feature = "nix", ignore)]
-#[nativelink_test]
-async fn entrypoint_injects_properties() -> Result<(), Box<dyn std::error::Error>> {
-    #[cfg(target_family = "unix")]
-    const TEST_WRAPPER_SCRIPT_CONTENT: &str = "\
+/// Writes a script that prints its arguments, which replaces the tool of a
+/// runner, and returns its path.
+#[cfg(target_family = "unix")]
+async fn make_fake_runner_tool(name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    const FAKE_RUNNER_TOOL_CONTENT: &str = "\
 #!/usr/bin/env bash
-# Print some static text to stderr. This is what the test uses to
-# make sure the script did run.
->&2 printf \"Wrapper script did run with property $PROPERTY $VALUE $INNER_TIMEOUT\"
-
-# Now run the real command.
-exec \"$@\"
-";
-    #[cfg(target_family = "windows")]
-    const TEST_WRAPPER_SCRIPT_CONTENT: &str = "\
-@echo off
-:: Print some static text to stderr. This is what the test uses to
-:: make sure the script did run.
-echo | set /p=\"Wrapper script did run with property %PROPERTY% %VALUE% %INNER_TIMEOUT%\" 1>&2
-
-:: Run command, but morph the echo to ensure it doesn't
-:: add a new line to the end of the output.
-%1 | set /p=%2
-exit 0
+printf '%s ' \"$@\"
 ";
-    const WORKER_ID: &str = "foo_worker_id";
-    const EXPECTED_STDOUT: &str = "Action did run";
-    const TASK_TIMEOUT: Duration = Duration::from_secs(122);
-
-    let (_, _, cas_store, ac_store) = setup_stores().await?;
-    let root_action_directory = make_temp_path("root_action_directory");
-    fs::create_dir_all(&root_action_directory).await?;
-
-    let test_wrapper_script = {
-        let test_wrapper_dir = make_temp_path("wrapper_dir");
-        fs::create_dir_all(&test_wrapper_dir).await?;
-        #[cfg(target_family = "unix")]
-        let test_wrapper_script = OsString::from(test_wrapper_dir + "/test_wrapper_script.sh");
-        #[cfg(target_family = "windows")]
-        let test_wrapper_script = OsString::from(test_wrapper_dir + "\\test_wrapper_script.bat");
 
-        // We use std::fs::File here because we sometimes get strange bugs here
-        // that result in: "Text file busy (os error 26)" if it is an executeable.
-        // It is likley because somewhere the file descriotor does not get closed
-        // in tokio's async context.
-        let mut test_wrapper_script_handle = std::fs::File::create(&test_wrapper_script)?;
-        test_wrapper_script_handle.write_all(TEST_WRAPPER_SCRIPT_CONTENT.as_bytes())?;
-        #[cfg(target_family = "unix")]
-        test_wrapper_script_handle.set_permissions(Permissions::from_mode(0o777))?;
-        test_wrapper_script_handle.sync_all()?;
-        drop(test_wrapper_script_handle);
+    let fake_runner_tool_dir = make_temp_path("fake_runner_tool_dir");
+    fs::create_dir_all(&fake_runner_tool_dir).await?;
+    let fake_runner_tool = format!("{fake_runner_tool_dir}/{name}");
 
-        test_wrapper_script
-    };
+    // We use std::fs::File here because we sometimes get strange bugs here
+    // that result in: "Text file busy (os error 26)" if it is an executeable.
+    let mut fake_runner_tool_handle = std::fs::File::create(&fake_runner_tool)?;
+    fake_runner_tool_handle.write_all(FAKE_RUNNER_TOOL_CONTENT.as_bytes())?;
+    fake_runner_tool_handle.set_permissions(Permissions::from_mode(0o777))?;
+    fake_runner_tool_handle.sync_all()?;
+    drop(fake_runner_tool_handle);
 
     // TODO(#527) Sleep to reduce flakey chances.
     tokio::time::sleep(Duration::from_millis(250)).await;
+    Ok(fake_runner_tool)
+}
+
+/// Runs `printf hello` with `runner`, in the action directory `operation_id`
+/// of `root_action_directory`.
+#[cfg(target_family = "unix")]
+async fn run_action_with_runner(
+    root_action_directory: &str,
+    operation_id: &str,
+    runner: ActionRunner,
+    platform: Option<Platform>,
+) -> Result<ActionResult, Error> {
+    const WORKER_ID: &str = "foo_worker_id";
 
+    let (_, _, cas_store, ac_store) = setup_stores().await?;
     let running_actions_manager =
         Arc::new(RunningActionsManagerImpl::new(RunningActionsManagerArgs {
-            root_action_directory: root_action_directory.clone(),
+            root_action_directory: root_action_directory.to_string(),
             execution_configuration: ExecutionConfiguration {
-                entrypoint: Some(test_wrapper_script.into_string().unwrap()),
-                additional_environment: Some(HashMap::from([
-                    (
-                        "PROPERTY".to_string(),
-                        EnvironmentSource::property("property_name".to_string()),
-                    ),
-                    (
-                        "VALUE".to_string(),
-                        EnvironmentSource::value("raw_value".to_string()),
-                    ),
-                    (
-                        "INNER_TIMEOUT".to_string(),
-                        EnvironmentSource::timeout_millis,
-                    ),
-                    (
-                        "PATH".to_string(),
-                        EnvironmentSource::value(std::env::var("PATH").unwrap()),
-                    ),
-                ])),
+                runner,
+                ..Default::default()
             },
             cas_store: cas_store.clone(),
             ac_store: Some(Store::new(ac_store.clone())),
@@ -1694,13 +1886,13 @@ exit 0
             max_action_timeout: Duration::MAX,
             timeout_handled_externally: false,
         })?);
-    #[cfg(target_family = "unix")]
-    let arguments = vec!["printf".to_string(), EXPECTED_STDOUT.to_string()];
-    #[cfg(target_family = "windows")]
-    let arguments = vec!["echo".to_string(), EXPECTED_STDOUT.to_string()];
     let command = Command {
-        arguments,
+        arguments: vec!["printf".to_string(), "hello".to_string()],
         working_directory: ".".to_string(),
+        environment_variables: vec![EnvironmentVariable {
+            name: "PATH".to_string(),
+            value: std::env::var("PATH").unwrap(),
+        }],
         ..Default::default()
     };
     let command_digest = serialize_and_upload_message(
@@ -1718,16 +1910,7 @@ exit 0
     let action = Action {
         command_digest: Some(command_digest.into()),
         input_root_digest: Some(input_root_digest.into()),
-        platform: Some(Platform {
-            properties: vec![Property {
-                name: "property_name".into(),
-                value: "property_value".into(),
-            }],
-        }),
-        timeout: Some(prost_types::Duration {
-            seconds: TASK_TIMEOUT.as_secs() as i64,
-            nanos: 0,
-        }),
+        platform,
         ..Default::default()
     };
     let action_digest = serialize_and_upload_message(
@@ -1737,27 +1920,449 @@ exit 0
     )
     .await?;
 
-    let execute_request = ExecuteRequest {
-        action_digest: Some(action_digest.into()),
-        ..Default::default()
-    };
-    let operation_id = OperationId::default().to_string();
-
     let running_action_impl = running_actions_manager
         .clone()
         .create_and_add_action(
             WORKER_ID.to_string(),
             StartExecute {
-                execute_request: Some(execute_request),
-                operation_id,
+                execute_request: Some(ExecuteRequest {
+                    action_digest: Some(action_digest.into()),
+                    ..Default::default()
+                }),
+                operation_id: operation_id.to_string(),
                 queued_timestamp: Some(make_system_time(1000).into()),
                 platform: action.platform.clone(),
                 worker_id: WORKER_ID.to_string(),
+                trace_context: HashMap::new(),
+                request_id: String::new(),
+                timeout: None,
             },
         )
         .await?;
+    run_action(running_action_impl).await
+}
 
-    let result = run_action(running_action_impl).await?;
+// The container tool is replaced by a script that prints its arguments, so
+// the test checks the command line that would create the container.
+#[cfg(target_family = "unix")]
+#[cfg_attr(feature = "nix", ignore)]
+#[nativelink_test]
+async fn container_runner_runs_command_in_image() -> Result<(), Box<dyn std::error::Error>> {
+    let root_action_directory = make_temp_path("root_action_directory");
+    fs::create_dir_all(&root_action_directory).await?;
+    let fake_container_tool = make_fake_runner_tool("docker").await?;
+
+    let operation_id = OperationId::default().to_string();
+    let result = run_action_with_runner(
+        &root_action_directory,
+        &operation_id,
+        ActionRunner::container(ContainerRunnerConfig {
+            program: fake_container_tool,
+            additional_args: vec!["--network=none".to_string()],
+            ..Default::default()
+        }),
+        Some(Platform {
+            properties: vec![Property {
+                name: "container-image".into(),
+                value: "docker://ubuntu:24.04".into(),
+            }],
+        }),
+    )
+    .await?;
+    assert_eq!(result.exit_code, 0, "Exit code should be 0");
+
+    let action_directory = format!("{root_action_directory}/{operation_id}");
+    let expected_stdout = DigestHasherFunc::Sha256
+        .hasher()
+        .compute_from_reader(Cursor::new(format!(
+            "run --rm --name nativelink-{operation_id} \
+            --volume {action_directory}:{action_directory} \
+            --workdir {action_directory}/work/. --env PATH --network=none \
+            ubuntu:24.04 printf hello "
+        )))
+        .await?;
+    assert_eq!(expected_stdout, result.stdout_digest);
+
+    Ok(())
+}
+
+#[cfg(target_family = "unix")]
+#[nativelink_test]
+async fn container_runner_fails_without_image() -> Result<(), Box<dyn std::error::Error>> {
+    let root_action_directory = make_temp_path("root_action_directory");
+    fs::create_dir_all(&root_action_directory).await?;
+
+    let err = run_action_with_runner(
+        &root_action_directory,
+        &OperationId::default().to_string(),
+        ActionRunner::container(ContainerRunnerConfig::default()),
+        None,
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(err.code, Code::InvalidArgument, "{err:?}");
+    assert!(
+        err.message_string()
+            .contains("'container-image' platform property"),
+        "{err:?}"
+    );
+
+    Ok(())
+}
+
+#[cfg(target_family = "unix")]
+#[nativelink_test]
+async fn container_runner_rejects_image_options() -> Result<(), Box<dyn std::error::Error>> {
+    let root_action_directory = make_temp_path("root_action_directory");
+    fs::create_dir_all(&root_action_directory).await?;
+
+    let err = run_action_with_runner(
+        &root_action_directory,
+        &OperationId::default().to_string(),
+        ActionRunner::container(ContainerRunnerConfig::default()),
+        Some(Platform {
+            properties: vec![Property {
+                name: "container-image".into(),
+                value: "--privileged".into(),
+            }],
+        }),
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(err.code, Code::InvalidArgument, "{err:?}");
+    assert!(
+        err.message_string().contains("must not start with '-'"),
+        "{err:?}"
+    );
+
+    Ok(())
+}
+
+#[cfg(target_family = "unix")]
+#[nativelink_test]
+async fn container_runner_rejects_images_not_allowed() -> Result<(), Box<dyn std::error::Error>> {
+    let root_action_directory = make_temp_path("root_action_directory");
+    fs::create_dir_all(&root_action_directory).await?;
+
+    let err = run_action_with_runner(
+        &root_action_directory,
+        &OperationId::default().to_string(),
+        ActionRunner::container(ContainerRunnerConfig {
+            allowed_image_prefixes: vec!["ghcr.io/my-org/".to_string()],
+            ..Default::default()
+        }),
+        Some(Platform {
+            properties: vec![Property {
+                name: "container-image".into(),
+                value: "docker://ubuntu:24.04".into(),
+            }],
+        }),
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(err.code, Code::InvalidArgument, "{err:?}");
+    assert!(
+        err.message_string().contains("allowed image prefixes"),
+        "{err:?}"
+    );
+
+    Ok(())
+}
+
+// Persistent workers run outside of the runner and the cgroups, so they must
+// not be combined with them.
+#[nativelink_test]
+async fn persistent_workers_reject_runners_and_resource_limits(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let root_action_directory = make_temp_path("root_action_directory");
+    fs::create_dir_all(&root_action_directory).await?;
+    let (_, _, cas_store, ac_store) = setup_stores().await?;
+
+    let configurations = [
+        (
+            ExecutionConfiguration {
+                runner: ActionRunner::container(ContainerRunnerConfig::default()),
+                persistent_workers: Some(PersistentWorkersConfig::default()),
+                ..Default::default()
+            },
+            "only be used with the local runner",
+        ),
+        (
+            ExecutionConfiguration {
+                resource_limits: Some(ResourceLimitsConfig::default()),
+                persistent_workers: Some(PersistentWorkersConfig::default()),
+                ..Default::default()
+            },
+            "can not be used with resource limits",
+        ),
+    ];
+    for (execution_configuration, expected_message) in configurations {
+        let result = RunningActionsManagerImpl::new(RunningActionsManagerArgs {
+            root_action_directory: root_action_directory.clone(),
+            execution_configuration,
+            cas_store: cas_store.clone(),
+            ac_store: Some(Store::new(ac_store.clone())),
+            historical_store: Store::new(cas_store.clone()),
+            upload_action_result_config: &nativelink_config::cas_server::UploadActionResultConfig {
+                upload_ac_results_strategy:
+                    nativelink_config::cas_server::UploadCacheResultsStrategy::never,
+                ..Default::default()
+            },
+            max_action_timeout: Duration::MAX,
+            timeout_handled_externally: false,
+        });
+        let Err(err) = result else {
+            panic!("Expected {expected_message:?} error");
+        };
+        assert_eq!(err.code, Code::InvalidArgument, "{err:?}");
+        assert!(err.message_string().contains(expected_message), "{err:?}");
+    }
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn container_runner_rejects_resource_limits() -> Result<(), Box<dyn std::error::Error>> {
+    let root_action_directory = make_temp_path("root_action_directory");
+    fs::create_dir_all(&root_action_directory).await?;
+    let (_, _, cas_store, ac_store) = setup_stores().await?;
+
+    let result = RunningActionsManagerImpl::new(RunningActionsManagerArgs {
+        root_action_directory,
+        execution_configuration: ExecutionConfiguration {
+            runner: ActionRunner::container(ContainerRunnerConfig::default()),
+            resource_limits: Some(ResourceLimitsConfig::default()),
+            ..Default::default()
+        },
+        cas_store: cas_store.clone(),
+        ac_store: Some(Store::new(ac_store.clone())),
+        historical_store: Store::new(cas_store.clone()),
+        upload_action_result_config: &nativelink_config::cas_server::UploadActionResultConfig {
+            upload_ac_results_strategy:
+                nativelink_config::cas_server::UploadCacheResultsStrategy::never,
+            ..Default::default()
+        },
+        max_action_timeout: Duration::MAX,
+        timeout_handled_externally: false,
+    });
+    let Err(err) = result else {
+        panic!("Expected resource limits to be rejected with the container runner");
+    };
+    assert_eq!(err.code, Code::InvalidArgument, "{err:?}");
+    assert!(
+        err.message_string()
+            .contains("can not be used with the container runner"),
+        "{err:?}"
+    );
+
+    Ok(())
+}
+
+// Bubblewrap is replaced by a script that prints its arguments, so the test
+// checks the command line that would create the sandbox.
+#[cfg(target_family = "unix")]
+#[cfg_attr(feature = "nix", ignore)]
+#[nativelink_test]
+async fn bubblewrap_runner_hides_other_actions() -> Result<(), Box<dyn std::error::Error>> {
+    let root_action_directory = make_temp_path("root_action_directory");
+    fs::create_dir_all(&root_action_directory).await?;
+    let fake_bubblewrap = make_fake_runner_tool("bwrap").await?;
+
+    let operation_id = OperationId::default().to_string();
+    let result = run_action_with_runner(
+        &root_action_directory,
+        &operation_id,
+        ActionRunner::bubblewrap(BubblewrapRunnerConfig {
+            program: fake_bubblewrap,
+            allow_network: true,
+            ..Default::default()
+        }),
+        None,
+    )
+    .await?;
+    assert_eq!(result.exit_code, 0, "Exit code should be 0");
+
+    let action_directory = format!("{root_action_directory}/{operation_id}");
+    let expected_stdout = DigestHasherFunc::Sha256
+        .hasher()
+        .compute_from_reader(Cursor::new(format!(
+            "--die-with-parent --unshare-all --share-net --ro-bind / / \
+            --dev /dev --proc /proc --tmpfs /tmp --tmpfs {root_action_directory} \
+            --bind {action_directory} {action_directory} \
+            --chdir {action_directory}/work/. -- printf hello "
+        )))
+        .await?;
+    assert_eq!(expected_stdout, result.stdout_digest);
+
+    Ok(())
+}
+
+#[cfg_attr(feature = "nix", ignore)]
+#[nativelink_test]
+async fn entrypoint_injects_properties() -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(target_family = "unix")]
+    const TEST_WRAPPER_SCRIPT_CONTENT: &str = "\
+#!/usr/bin/env bash
+# Print some static text to stderr. This is what the test uses to
+# make sure the script did run.
+>&2 printf \"Wrapper script did run with property $PROPERTY $VALUE $INNER_TIMEOUT\"
+
+# Now run the real command.
+exec \"$@\"
+";
+    #[cfg(target_family = "windows")]
+    const TEST_WRAPPER_SCRIPT_CONTENT: &str = "\
+@echo off
+:: Print some static text to stderr. This is what the test uses to
+:: make sure the script did run.
+echo | set /p=\"Wrapper script did run with property %PROPERTY% %VALUE% %INNER_TIMEOUT%\" 1>&2
+
+:: Run command, but morph the echo to ensure it doesn't
+:: add a new line to the end of the output.
+%1 | set /p=%2
+exit 0
+";
+    const WORKER_ID: &str = "foo_worker_id";
+    const EXPECTED_STDOUT: &str = "Action did run";
+    const TASK_TIMEOUT: Duration = Duration::from_secs(122);
+
+    let (_, _, cas_store, ac_store) = setup_stores().await?;
+    let root_action_directory = make_temp_path("root_action_directory");
+    fs::create_dir_all(&root_action_directory).await?;
+
+    let test_wrapper_script = {
+        let test_wrapper_dir = make_temp_path("wrapper_dir");
+        fs::create_dir_all(&test_wrapper_dir).await?;
+        #[cfg(target_family = "unix")]
+        let test_wrapper_script = OsString::from(test_wrapper_dir + "/test_wrapper_script.sh");
+        #[cfg(target_family = "windows")]
+        let test_wrapper_script = OsString::from(test_wrapper_dir + "\\test_wrapper_script.bat");
+
+        // We use std::fs::File here because we sometimes get strange bugs here
+        // that result in: "Text file busy (os error 26)" if it is an executeable.
+        // It is likley because somewhere the file descriotor does not get closed
+        // in tokio's async context.
+        let mut test_wrapper_script_handle = std::fs::File::create(&test_wrapper_script)?;
+        test_wrapper_script_handle.write_all(TEST_WRAPPER_SCRIPT_CONTENT.as_bytes())?;
+        #[cfg(target_family = "unix")]
+        test_wrapper_script_handle.set_permissions(Permissions::from_mode(0o777))?;
+        test_wrapper_script_handle.sync_all()?;
+        drop(test_wrapper_script_handle);
+
+        test_wrapper_script
+    };
+
+    // TODO(#527) Sleep to reduce flakey chances.
+    tokio::time::sleep(Duration::from_millis(250)).await;
+
+    let running_actions_manager =
+        Arc::new(RunningActionsManagerImpl::new(RunningActionsManagerArgs {
+            root_action_directory: root_action_directory.clone(),
+            execution_configuration: ExecutionConfiguration {
+                entrypoint: Some(test_wrapper_script.into_string().unwrap()),
+                additional_environment: Some(HashMap::from([
+                    (
+                        "PROPERTY".to_string(),
+                        EnvironmentSource::property("property_name".to_string()),
+                    ),
+                    (
+                        "VALUE".to_string(),
+                        EnvironmentSource::value("raw_value".to_string()),
+                    ),
+                    (
+                        "INNER_TIMEOUT".to_string(),
+                        EnvironmentSource::timeout_millis,
+                    ),
+                    (
+                        "PATH".to_string(),
+                        EnvironmentSource::value(std::env::var("PATH").unwrap()),
+                    ),
+                ])),
+                runner: ActionRunner::local,
+                resource_limits: None,
+                persistent_workers: None,
+                input_root_strategy: InputRootStrategy::hardlink,
+                output_upload: OutputUploadConfig::default(),
+                stream_action_output: false,
+            },
+            cas_store: cas_store.clone(),
+            ac_store: Some(Store::new(ac_store.clone())),
+            historical_store: Store::new(cas_store.clone()),
+            upload_action_result_config: &nativelink_config::cas_server::UploadActionResultConfig {
+                upload_ac_results_strategy:
+                    nativelink_config::cas_server::UploadCacheResultsStrategy::never,
+                ..Default::default()
+            },
+            max_action_timeout: Duration::MAX,
+            timeout_handled_externally: false,
+        })?);
+    #[cfg(target_family = "unix")]
+    let arguments = vec!["printf".to_string(), EXPECTED_STDOUT.to_string()];
+    #[cfg(target_family = "windows")]
+    let arguments = vec!["echo".to_string(), EXPECTED_STDOUT.to_string()];
+    let command = Command {
+        arguments,
+        working_directory: ".".to_string(),
+        ..Default::default()
+    };
+    let command_digest = serialize_and_upload_message(
+        &command,
+        cas_store.as_pin(),
+        &mut DigestHasherFunc::Sha256.hasher(),
+    )
+    .await?;
+    let input_root_digest = serialize_and_upload_message(
+        &Directory::default(),
+        cas_store.as_pin(),
+        &mut DigestHasherFunc::Sha256.hasher(),
+    )
+    .await?;
+    let action = Action {
+        command_digest: Some(command_digest.into()),
+        input_root_digest: Some(input_root_digest.into()),
+        platform: Some(Platform {
+            properties: vec![Property {
+                name: "property_name".into(),
+                value: "property_value".into(),
+            }],
+        }),
+        timeout: Some(prost_types::Duration {
+            seconds: TASK_TIMEOUT.as_secs() as i64,
+            nanos: 0,
+        }),
+        ..Default::default()
+    };
+    let action_digest = serialize_and_upload_message(
+        &action,
+        cas_store.as_pin(),
+        &mut DigestHasherFunc::Sha256.hasher(),
+    )
+    .await?;
+
+    let execute_request = ExecuteRequest {
+        action_digest: Some(action_digest.into()),
+        ..Default::default()
+    };
+    let operation_id = OperationId::default().to_string();
+
+    let running_action_impl = running_actions_manager
+        .clone()
+        .create_and_add_action(
+            WORKER_ID.to_string(),
+            StartExecute {
+                execute_request: Some(execute_request),
+                operation_id,
+                queued_timestamp: Some(make_system_time(1000).into()),
+                platform: action.platform.clone(),
+                worker_id: WORKER_ID.to_string(),
+                trace_context: HashMap::new(),
+                request_id: String::new(),
+                timeout: None,
+            },
+        )
+        .await?;
+
+    let result = run_action(running_action_impl).await?;
     assert_eq!(result.exit_code, 0, "Exit code should be 0");
 
     let expected_stdout = DigestHasherFunc::Sha256
@@ -1838,6 +2443,12 @@ exit 1
                     "SIDE_CHANNEL_FILE".to_string(),
                     EnvironmentSource::side_channel_file,
                 )])),
+                runner: ActionRunner::local,
+                resource_limits: None,
+                persistent_workers: None,
+                input_root_strategy: InputRootStrategy::hardlink,
+                output_upload: OutputUploadConfig::default(),
+                stream_action_output: false,
             },
             cas_store: cas_store.clone(),
             ac_store: Some(Store::new(ac_store.clone())),
@@ -1896,6 +2507,9 @@ exit 1
                 queued_timestamp: Some(make_system_time(1000).into()),
                 platform: action.platform.clone(),
                 worker_id: WORKER_ID.to_string(),
+                trace_context: HashMap::new(),
+                request_id: String::new(),
+                timeout: None,
             },
         )
         .await?;
@@ -1962,6 +2576,8 @@ async fn caches_results_in_action_cache_store() -> Result<(), Box<dyn std::error
             execution_completed_timestamp: make_system_time(4),
             output_upload_start_timestamp: make_system_time(5),
             output_upload_completed_timestamp: make_system_time(6),
+            retries: 0,
+            oom_kill_memory_limit_bytes: None,
             worker_completed_timestamp: make_system_time(7),
         },
         error: None,
@@ -2033,6 +2649,8 @@ async fn failed_action_does_not_cache_in_action_cache() -> Result<(), Box<dyn st
             execution_completed_timestamp: make_system_time(4),
             output_upload_start_timestamp: make_system_time(5),
             output_upload_completed_timestamp: make_system_time(6),
+            retries: 0,
+            oom_kill_memory_limit_bytes: None,
             worker_completed_timestamp: make_system_time(7),
         },
         error: None,
@@ -2107,6 +2725,8 @@ async fn success_does_cache_in_historical_results() -> Result<(), Box<dyn std::e
             execution_completed_timestamp: make_system_time(4),
             output_upload_start_timestamp: make_system_time(5),
             output_upload_completed_timestamp: make_system_time(6),
+            retries: 0,
+            oom_kill_memory_limit_bytes: None,
             worker_completed_timestamp: make_system_time(7),
         },
         error: None,
@@ -2413,6 +3033,9 @@ async fn ensure_worker_timeout_chooses_correct_values() -> Result<(), Box<dyn st
                     queued_timestamp: Some(make_system_time(1000).into()),
                     platform: action.platform.clone(),
                     worker_id: WORKER_ID.to_string(),
+                    trace_context: HashMap::new(),
+                    request_id: String::new(),
+                    timeout: None,
                 },
             )
             .and_then(|action| {
@@ -2495,6 +3118,9 @@ async fn ensure_worker_timeout_chooses_correct_values() -> Result<(), Box<dyn st
                     queued_timestamp: Some(make_system_time(1000).into()),
                     platform: action.platform.clone(),
                     worker_id: WORKER_ID.to_string(),
+                    trace_context: HashMap::new(),
+                    request_id: String::new(),
+                    timeout: None,
                 },
             )
             .and_then(|action| {
@@ -2577,6 +3203,9 @@ async fn ensure_worker_timeout_chooses_correct_values() -> Result<(), Box<dyn st
                     queued_timestamp: Some(make_system_time(1000).into()),
                     platform: action.platform.clone(),
                     worker_id: WORKER_ID.to_string(),
+                    trace_context: HashMap::new(),
+                    request_id: String::new(),
+                    timeout: None,
                 },
             )
             .and_then(|action| {
@@ -2595,6 +3224,96 @@ async fn ensure_worker_timeout_chooses_correct_values() -> Result<(), Box<dyn st
         assert_eq!(SENT_TIMEOUT.load(Ordering::Relaxed), -1);
         assert_eq!(result.err().unwrap().code, Code::InvalidArgument);
     }
+    {
+        // Ensure the timeout chosen by the scheduler is used over the one
+        // of the action.
+        static SENT_TIMEOUT: AtomicI64 = AtomicI64::new(-1);
+        const MAX_TIMEOUT_DURATION: Duration = Duration::from_secs(100);
+        const TASK_TIMEOUT: Duration = Duration::from_secs(200);
+        const SCHEDULER_TIMEOUT: Duration = Duration::from_secs(50);
+
+        let action = Action {
+            command_digest: Some(command_digest.into()),
+            input_root_digest: Some(input_root_digest.into()),
+            timeout: Some(prost_types::Duration {
+                seconds: TASK_TIMEOUT.as_secs() as i64,
+                nanos: 0,
+            }),
+            ..Default::default()
+        };
+        let action_digest = serialize_and_upload_message(
+            &action,
+            cas_store.as_pin(),
+            &mut DigestHasherFunc::Sha256.hasher(),
+        )
+        .await?;
+
+        let running_actions_manager = Arc::new(RunningActionsManagerImpl::new_with_callbacks(
+            RunningActionsManagerArgs {
+                root_action_directory: root_action_directory.clone(),
+                execution_configuration: ExecutionConfiguration::default(),
+                cas_store: cas_store.clone(),
+                ac_store: Some(Store::new(ac_store.clone())),
+                historical_store: Store::new(cas_store.clone()),
+                upload_action_result_config:
+                    &nativelink_config::cas_server::UploadActionResultConfig {
+                        upload_ac_results_strategy:
+                            nativelink_config::cas_server::UploadCacheResultsStrategy::never,
+                        ..Default::default()
+                    },
+                max_action_timeout: MAX_TIMEOUT_DURATION,
+                timeout_handled_externally: false,
+            },
+            Callbacks {
+                now_fn: test_monotonic_clock,
+                sleep_fn: |duration| {
+                    SENT_TIMEOUT.store(duration.as_millis() as i64, Ordering::Relaxed);
+                    Box::pin(futures::future::pending())
+                },
+            },
+        )?);
+
+        let execute_request = ExecuteRequest {
+            action_digest: Some(action_digest.into()),
+            ..Default::default()
+        };
+        let operation_id = OperationId::default().to_string();
+
+        running_actions_manager
+            .create_and_add_action(
+                WORKER_ID.to_string(),
+                StartExecute {
+                    execute_request: Some(execute_request),
+                    operation_id,
+                    queued_timestamp: Some(make_system_time(1000).into()),
+                    platform: action.platform.clone(),
+                    worker_id: WORKER_ID.to_string(),
+                    trace_context: HashMap::new(),
+                    request_id: String::new(),
+                    timeout: Some(prost_types::Duration {
+                        seconds: SCHEDULER_TIMEOUT.as_secs() as i64,
+                        nanos: 0,
+                    }),
+                },
+            )
+            .and_then(|action| {
+                action
+                    .clone()
+                    .prepare_action()
+                    .and_then(RunningAction::execute)
+                    .then(|result| async move {
+                        if let Err(e) = action.cleanup().await {
+                            return Result::<ActionResult, Error>::Err(e).merge(result);
+                        }
+                        result
+                    })
+            })
+            .await?;
+        assert_eq!(
+            SENT_TIMEOUT.load(Ordering::Relaxed),
+            SCHEDULER_TIMEOUT.as_millis() as i64
+        );
+    }
     Ok(())
 }
 
@@ -2702,6 +3421,9 @@ async fn worker_times_out() -> Result<(), Box<dyn std::error::Error>> {
                 queued_timestamp: Some(make_system_time(1000).into()),
                 platform: action.platform.clone(),
                 worker_id: WORKER_ID.to_string(),
+                trace_context: HashMap::new(),
+                request_id: String::new(),
+                timeout: None,
             },
         )
         .and_then(|action| {
@@ -2830,6 +3552,9 @@ async fn kill_all_waits_for_all_tasks_to_finish() -> Result<(), Box<dyn std::err
                 queued_timestamp: Some(make_system_time(1000).into()),
                 platform: action.platform.clone(),
                 worker_id: WORKER_ID.to_string(),
+                trace_context: HashMap::new(),
+                request_id: String::new(),
+                timeout: None,
             },
         )
         .await?;
@@ -3080,6 +3805,9 @@ async fn action_directory_contents_are_cleaned() -> Result<(), Box<dyn std::erro
                 queued_timestamp: Some(queued_timestamp.into()),
                 platform: action.platform.clone(),
                 worker_id: WORKER_ID.to_string(),
+                trace_context: HashMap::new(),
+                request_id: String::new(),
+                timeout: None,
             },
         )
         .await?;
@@ -3094,6 +3822,104 @@ async fn action_directory_contents_are_cleaned() -> Result<(), Box<dyn std::erro
     Ok(())
 }
 
+#[nativelink_test]
+async fn reports_recent_input_roots_test() -> Result<(), Box<dyn std::error::Error>> {
+    const WORKER_ID: &str = "foo_worker_id";
+
+    let (_, _, cas_store, ac_store) = setup_stores().await?;
+    let root_action_directory = make_temp_path("root_action_directory");
+    fs::create_dir_all(&root_action_directory).await?;
+
+    let running_actions_manager =
+        Arc::new(RunningActionsManagerImpl::new(RunningActionsManagerArgs {
+            root_action_directory: root_action_directory.clone(),
+            execution_configuration: ExecutionConfiguration::default(),
+            cas_store: cas_store.clone(),
+            ac_store: Some(Store::new(ac_store.clone())),
+            historical_store: Store::new(cas_store.clone()),
+            upload_action_result_config: &nativelink_config::cas_server::UploadActionResultConfig {
+                upload_ac_results_strategy:
+                    nativelink_config::cas_server::UploadCacheResultsStrategy::never,
+                ..Default::default()
+            },
+            max_action_timeout: Duration::MAX,
+            timeout_handled_externally: false,
+        })?);
+    let queued_timestamp = make_system_time(1000);
+
+    #[cfg(target_family = "unix")]
+    let arguments = vec!["sh".to_string(), "-c".to_string(), "exit 0".to_string()];
+    #[cfg(target_family = "windows")]
+    let arguments = vec!["cmd".to_string(), "/C".to_string(), "exit 0".to_string()];
+
+    let command = Command {
+        arguments,
+        output_paths: vec![],
+        working_directory: ".".to_string(),
+        environment_variables: vec![EnvironmentVariable {
+            name: "PATH".to_string(),
+            value: std::env::var("PATH").unwrap(),
+        }],
+        ..Default::default()
+    };
+    let command_digest = serialize_and_upload_message(
+        &command,
+        cas_store.as_pin(),
+        &mut DigestHasherFunc::Sha256.hasher(),
+    )
+    .await?;
+    let input_root_digest = serialize_and_upload_message(
+        &Directory::default(),
+        cas_store.as_pin(),
+        &mut DigestHasherFunc::Sha256.hasher(),
+    )
+    .await?;
+    let action = Action {
+        command_digest: Some(command_digest.into()),
+        input_root_digest: Some(input_root_digest.into()),
+        ..Default::default()
+    };
+    let action_digest = serialize_and_upload_message(
+        &action,
+        cas_store.as_pin(),
+        &mut DigestHasherFunc::Sha256.hasher(),
+    )
+    .await?;
+
+    let execute_request = ExecuteRequest {
+        action_digest: Some(action_digest.into()),
+        ..Default::default()
+    };
+    let operation_id = OperationId::default().to_string();
+
+    let running_action_impl = running_actions_manager
+        .create_and_add_action(
+            WORKER_ID.to_string(),
+            StartExecute {
+                execute_request: Some(execute_request),
+                operation_id,
+                queued_timestamp: Some(queued_timestamp.into()),
+                platform: action.platform.clone(),
+                worker_id: WORKER_ID.to_string(),
+                trace_context: HashMap::new(),
+                request_id: String::new(),
+                timeout: None,
+            },
+        )
+        .await?;
+
+    assert_eq!(running_actions_manager.recent_input_roots(), vec![]);
+    run_action(running_action_impl.clone()).await?;
+
+    // The files of the input root are still in the local cache after the
+    // action is cleaned up.
+    assert_eq!(
+        running_actions_manager.recent_input_roots(),
+        vec![input_root_digest]
+    );
+    Ok(())
+}
+
 // We've experienced deadlocks when uploading, so make only a single permit available and
 // check it's able to handle uploading some directories with some files in.
 // Be default this test is ignored because it *must* be run single threaded... to run this
@@ -3216,6 +4042,9 @@ async fn upload_with_single_permit() -> Result<(), Box<dyn std::error::Error>> {
                     queued_timestamp: None,
                     platform: action.platform.clone(),
                     worker_id: WORKER_ID.to_string(),
+                    trace_context: HashMap::new(),
+                    request_id: String::new(),
+                    timeout: None,
                 },
             )
             .await?;
@@ -3278,6 +4107,8 @@ async fn upload_with_single_permit() -> Result<(), Box<dyn std::error::Error>> {
                 execution_completed_timestamp: increment_clock(&mut clock_time),
                 output_upload_start_timestamp: increment_clock(&mut clock_time),
                 output_upload_completed_timestamp: increment_clock(&mut clock_time),
+                retries: 0,
+                oom_kill_memory_limit_bytes: None,
                 worker_completed_timestamp: increment_clock(&mut clock_time),
             },
             error: None,
@@ -3397,6 +4228,9 @@ async fn running_actions_manager_respects_action_timeout() -> Result<(), Box<dyn
                 queued_timestamp: Some(make_system_time(1000).into()),
                 platform: action.platform.clone(),
                 worker_id: WORKER_ID.to_string(),
+                trace_context: HashMap::new(),
+                request_id: String::new(),
+                timeout: None,
             },
         )
         .await?;
@@ -185,6 +185,10 @@ impl RunningActionsManager for MockRunningActionsManager {
             .expect("Could not send request to mpsc");
     }
 
+    fn recent_input_roots(&self) -> Vec<DigestInfo> {
+        Vec::new()
+    }
+
     fn metrics(&self) -> &Arc<Metrics> {
         &self.metrics
     }
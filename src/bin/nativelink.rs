@@ -13,14 +13,15 @@
 // limitations under the License.
 
 use std::collections::{HashMap, HashSet};
+use std::io::Write;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use async_lock::Mutex as AsyncMutex;
 use axum::Router;
-use clap::Parser;
-use futures::future::{try_join_all, BoxFuture, Either, OptionFuture, TryFutureExt};
+use clap::{Parser, Subcommand};
+use futures::future::{try_join_all, BoxFuture, OptionFuture, TryFutureExt};
 use futures::FutureExt;
 use hyper::{Response, StatusCode};
 use hyper_util::rt::tokio::TokioIo;
@@ -28,59 +29,86 @@ use hyper_util::server::conn::auto;
 use hyper_util::service::TowerToHyperService;
 use mimalloc::MiMalloc;
 use nativelink_config::cas_server::{
-    CasConfig, GlobalConfig, HttpCompressionAlgorithm, ListenerConfig, ServerConfig, WorkerConfig,
+    AuditLogWriterSpec, CasConfig, GlobalConfig, HttpCompressionAlgorithm, ListenerConfig,
+    MetricsPushConfig, MetricsPushExporter, ServerConfig, WorkerConfig,
 };
-use nativelink_config::stores::ConfigDigestHashFunction;
+use nativelink_config::stores::{ConfigDigestHashFunction, StoreSpec};
 use nativelink_config::{SchedulerConfig, StoreConfig};
 use nativelink_error::{make_err, make_input_err, Code, Error, ResultExt};
 use nativelink_metric::{
     MetricFieldData, MetricKind, MetricPublishKnownKindData, MetricsComponent, RootMetricsComponent,
 };
-use nativelink_metric_collector::{otel_export, MetricsCollectorLayer};
+use nativelink_metric_collector::{
+    otel_export, statsd_export, MetricsCollectorLayer, RootMetricCollectedMetrics,
+};
+use nativelink_proto::build::bazel::remote::execution::v2::{
+    Action, ActionResult, Command as ProtoCommand, Directory, Tree,
+};
 use nativelink_scheduler::default_scheduler_factory::scheduler_factory;
+use nativelink_scheduler::worker_scheduler::WorkerScheduler;
 use nativelink_service::ac_server::AcServer;
+use nativelink_service::admin_server::AdminServer;
 use nativelink_service::bep_server::BepServer;
+use nativelink_service::blob_redirect_server::BlobRedirectServer;
 use nativelink_service::bytestream_server::ByteStreamServer;
 use nativelink_service::capabilities_server::CapabilitiesServer;
 use nativelink_service::cas_server::CasServer;
 use nativelink_service::execution_server::ExecutionServer;
-use nativelink_service::health_server::HealthServer;
+use nativelink_service::health_server::{HealthProbe, HealthServer};
+use nativelink_service::http_cache_server::HttpCacheServer;
+use nativelink_service::operations_server::OperationsServer;
+use nativelink_service::pprof_server::PprofServer;
+use nativelink_service::remote_asset_server::RemoteAssetServer;
 use nativelink_service::worker_api_server::WorkerApiServer;
 use nativelink_store::default_store_factory::store_factory;
+use nativelink_store::metrics_store::MetricsStore;
 use nativelink_store::store_manager::StoreManager;
-use nativelink_util::action_messages::WorkerId;
+use nativelink_store::store_migration::{
+    migrate_store, MigrationOptions, DEFAULT_MIGRATION_BATCH_SIZE, DEFAULT_MIGRATION_PARALLELISM,
+};
+use nativelink_util::audit_log::{init_audit_log, AuditLogPublisher, AuditLogWriter};
+use nativelink_util::auth_middleware::{AuthMiddlewareLayer, ReadOnlyRequests};
 use nativelink_util::common::fs::{set_idle_file_descriptor_timeout, set_open_file_limit};
-use nativelink_util::digest_hasher::{set_default_digest_hasher_func, DigestHasherFunc};
+use nativelink_util::digest_hasher::{
+    default_digest_hasher_func, set_default_digest_hasher_func, DigestHasher, DigestHasherFunc,
+};
 use nativelink_util::health_utils::HealthRegistryBuilder;
+use nativelink_util::histogram_metrics::set_slow_operation_thresholds;
+use nativelink_util::memory_budget::global_memory_budget;
 use nativelink_util::metrics_utils::{set_metrics_enabled_for_this_thread, Counter};
 use nativelink_util::operation_state_manager::ClientStateManager;
 use nativelink_util::origin_context::{ActiveOriginContext, OriginContext};
 use nativelink_util::origin_event_middleware::OriginEventMiddlewareLayer;
 use nativelink_util::origin_event_publisher::OriginEventPublisher;
+use nativelink_util::rate_limit_middleware::{RateLimitMiddlewareLayer, WithClientAddress};
 use nativelink_util::shutdown_guard::{Priority, ShutdownGuard};
 use nativelink_util::store_trait::{
-    set_default_digest_size_health_check, DEFAULT_DIGEST_SIZE_HEALTH_CHECK_CFG,
+    set_default_digest_size_health_check, Store, StoreKey, DEFAULT_DIGEST_SIZE_HEALTH_CHECK_CFG,
 };
 use nativelink_util::task::TaskExecutor;
-use nativelink_util::{background_spawn, init_tracing, spawn, spawn_blocking};
+use nativelink_util::telemetry::TraceContextMiddlewareLayer;
+use nativelink_util::tls_utils::{check_client_common_name, load_server_config};
+use nativelink_util::{background_spawn, init_tracing, set_log_filter, spawn, spawn_blocking};
 use nativelink_worker::local_worker::new_local_worker;
 use opentelemetry::metrics::MeterProvider;
-use opentelemetry_sdk::metrics::SdkMeterProvider;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider};
+use opentelemetry_sdk::runtime;
 use parking_lot::{Mutex, RwLock};
 use prometheus::{Encoder, TextEncoder};
-use rustls_pemfile::{certs as extract_certs, crls as extract_crls};
+use prost::Message;
 use scopeguard::guard;
-use tokio::net::TcpListener;
+use tokio::net::{lookup_host, TcpListener, UdpSocket};
 use tokio::select;
 #[cfg(target_family = "unix")]
 use tokio::signal::unix::{signal, SignalKind};
 use tokio::sync::{broadcast, mpsc};
-use tokio_rustls::rustls::pki_types::CertificateDer;
-use tokio_rustls::rustls::server::WebPkiClientVerifier;
-use tokio_rustls::rustls::{RootCertStore, ServerConfig as TlsServerConfig};
+use tokio::time::MissedTickBehavior;
 use tokio_rustls::TlsAcceptor;
+use tokio_util::either::Either as IoEither;
 use tonic::codec::CompressionEncoding;
 use tonic::transport::Server as TonicServer;
+use tonic_reflection::server::Builder as ReflectionBuilder;
 use tracing::{error_span, event, trace_span, Level};
 use tracing_subscriber::layer::SubscriberExt;
 
@@ -96,6 +124,21 @@ const DEFAULT_ADMIN_API_PATH: &str = "/admin";
 // Note: This must be kept in sync with the documentation in `HealthConfig::path`.
 const DEFAULT_HEALTH_STATUS_CHECK_PATH: &str = "/status";
 
+/// Note: This must be kept in sync with the documentation in `HealthConfig::liveness_path`.
+const DEFAULT_HEALTH_LIVENESS_PATH: &str = "/livez";
+
+/// Note: This must be kept in sync with the documentation in `HealthConfig::readiness_path`.
+const DEFAULT_HEALTH_READINESS_PATH: &str = "/readyz";
+
+/// Note: This must be kept in sync with the documentation in `BlobRedirectConfig::path`.
+const DEFAULT_BLOB_REDIRECT_PATH: &str = "/blobs";
+
+/// Note: This must be kept in sync with the documentation in `HttpCacheConfig::path`.
+const DEFAULT_HTTP_CACHE_PATH: &str = "/cache";
+
+/// Note: This must be kept in sync with the documentation in `PprofConfig::path`.
+const DEFAULT_PPROF_PATH: &str = "/debug/pprof";
+
 /// Name of environment variable to disable metrics.
 const METRICS_DISABLE_ENV: &str = "NATIVELINK_DISABLE_METRICS";
 
@@ -103,6 +146,18 @@ const METRICS_DISABLE_ENV: &str = "NATIVELINK_DISABLE_METRICS";
 // `OriginEventsConfig::max_event_queue_size`.
 const DEFAULT_MAX_QUEUE_EVENTS: usize = 65536;
 
+// Note: This must be kept in sync with the documentation in
+// `AuditLogSpec::max_queue_size`.
+const DEFAULT_MAX_QUEUED_AUDIT_RECORDS: usize = 65536;
+
+// Note: This must be kept in sync with the documentation in
+// `AuditLogFileSpec::max_bytes`.
+const DEFAULT_AUDIT_LOG_MAX_BYTES: u64 = 100 * 1024 * 1024;
+
+// Note: This must be kept in sync with the documentation in
+// `AuditLogFileSpec::max_files`.
+const DEFAULT_AUDIT_LOG_MAX_FILES: usize = 10;
+
 /// Broadcast Channel Capacity
 /// Note: The actual capacity may be greater than the provided capacity.
 const BROADCAST_CAPACITY: usize = 1;
@@ -113,12 +168,115 @@ const BROADCAST_CAPACITY: usize = 1;
     author = "Trace Machina, Inc. <nativelink@tracemachina.com>",
     version,
     about,
-    long_about = None
+    long_about = None,
+    args_conflicts_with_subcommands = true
 )]
 struct Args {
     /// Config file to use.
     #[clap(value_parser)]
+    config_file: Option<String>,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Copy every object of a store into another store, ie: to move a CAS
+    /// from a filesystem store to S3 while the server keeps running.
+    Migrate(MigrateArgs),
+
+    /// Inspect and modify the objects of a store in a server config, ie: to
+    /// debug cache inconsistencies. To inspect a running server, use a
+    /// config with a `grpc` store that points at it.
+    Store(StoreArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct StoreArgs {
+    /// Server config file with the store.
+    #[clap(value_parser)]
     config_file: String,
+
+    /// Name of the store in the config file.
+    #[clap(value_parser)]
+    store_name: String,
+
+    #[clap(subcommand)]
+    action: StoreAction,
+}
+
+#[derive(Subcommand, Debug)]
+enum StoreAction {
+    /// Print the size of an object, if the store has it.
+    Stat {
+        /// Key of the object, ie: `<hash>-<size>` for digests.
+        key: String,
+    },
+
+    /// Print the contents of an object.
+    Cat {
+        /// Key of the object, ie: `<hash>-<size>` for digests.
+        key: String,
+
+        /// Decode the object as this message and pretty-print it.
+        #[clap(long, value_enum)]
+        decode: Option<ProtoMessageType>,
+    },
+
+    /// Upload the contents of a file and print its key.
+    Upload {
+        /// File to upload.
+        file: String,
+
+        /// Key to upload the object to. Defaults to the digest of the
+        /// contents with the default digest function, like in a CAS.
+        #[clap(long)]
+        key: Option<String>,
+    },
+
+    /// Remove an object from the store.
+    Delete {
+        /// Key of the object, ie: `<hash>-<size>` for digests.
+        key: String,
+    },
+}
+
+/// Messages that `nativelink store cat --decode` can pretty-print.
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum ProtoMessageType {
+    Action,
+    ActionResult,
+    Command,
+    Directory,
+    Tree,
+}
+
+#[derive(clap::Args, Debug)]
+struct MigrateArgs {
+    /// Config file of the store to copy from. It holds a single store
+    /// config, like the `filesystem` or `experimental_s3_store` object of
+    /// an entry of `stores`. The store must support listing its keys.
+    #[clap(value_parser)]
+    source_config: String,
+
+    /// Config file of the store to copy to, in the same format.
+    #[clap(value_parser)]
+    destination_config: String,
+
+    /// Number of objects copied at the same time.
+    #[clap(long, default_value_t = DEFAULT_MIGRATION_PARALLELISM)]
+    parallelism: usize,
+
+    /// Number of keys listed from the source store at a time. Progress is
+    /// reported and saved after every batch.
+    #[clap(long, default_value_t = DEFAULT_MIGRATION_BATCH_SIZE)]
+    batch_size: usize,
+
+    /// File the last copied key is saved to after every batch. If the file
+    /// exists, the migration resumes after the key in it.
+    #[clap(long)]
+    resume_file: Option<String>,
 }
 
 /// The root metrics collector struct. All metrics will be
@@ -174,6 +332,7 @@ impl RootMetricsComponent for ConnectedClientsMetrics {}
 
 async fn inner_main(
     cfg: CasConfig,
+    config_file: String,
     server_start_timestamp: u64,
     shutdown_tx: broadcast::Sender<ShutdownGuard>,
 ) -> Result<(), Error> {
@@ -198,7 +357,7 @@ async fn inner_main(
             let store = store_factory(&spec, &store_manager, Some(&mut health_register_store))
                 .await
                 .err_tip(|| format!("Failed to create store '{name}'"))?;
-            store_manager.add_store(&name, store);
+            store_manager.add_store(&name, Store::new(MetricsStore::new(&name, store)));
         }
     }
 
@@ -228,19 +387,96 @@ async fn inner_main(
         })
         .transpose()?;
 
+    if let Some(audit_log_cfg) = &cfg.experimental_audit_log {
+        let mut max_queued_records = audit_log_cfg.max_queue_size;
+        if max_queued_records == 0 {
+            max_queued_records = DEFAULT_MAX_QUEUED_AUDIT_RECORDS;
+        }
+        let writer = match &audit_log_cfg.writer {
+            AuditLogWriterSpec::file(file_cfg) => AuditLogWriter::File {
+                path: file_cfg.path.clone().into(),
+                max_bytes: if file_cfg.max_bytes == 0 {
+                    DEFAULT_AUDIT_LOG_MAX_BYTES
+                } else {
+                    file_cfg.max_bytes
+                },
+                max_files: if file_cfg.max_files == 0 {
+                    DEFAULT_AUDIT_LOG_MAX_FILES
+                } else {
+                    file_cfg.max_files
+                },
+            },
+            AuditLogWriterSpec::store(store_cfg) => {
+                let store_name = store_cfg.store.as_str();
+                AuditLogWriter::Store(
+                    store_manager
+                        .get_store(store_name)
+                        .err_tip(|| format!("Could not get store {store_name} for audit log"))?,
+                )
+            }
+        };
+        let (tx, rx) = mpsc::channel(max_queued_records);
+        init_audit_log(tx)?;
+        root_futures.push(Box::pin(
+            AuditLogPublisher::new(writer, rx, shutdown_tx.clone())
+                .run()
+                .map(Ok),
+        ));
+    }
+
     let mut action_schedulers = HashMap::new();
     let mut worker_schedulers = HashMap::new();
-    for SchedulerConfig { name, spec } in cfg.schedulers.iter().flatten() {
-        let (maybe_action_scheduler, maybe_worker_scheduler) =
-            scheduler_factory(spec, &store_manager, maybe_origin_event_tx.as_ref())
-                .err_tip(|| format!("Failed to create scheduler '{name}'"))?;
-        if let Some(action_scheduler) = maybe_action_scheduler {
-            action_schedulers.insert(name.clone(), action_scheduler.clone());
-        }
-        if let Some(worker_scheduler) = maybe_worker_scheduler {
-            worker_schedulers.insert(name.clone(), worker_scheduler.clone());
+    {
+        let mut health_registry_lock = health_registry_builder.lock().await;
+
+        for SchedulerConfig { name, spec } in cfg.schedulers.iter().flatten() {
+            let (maybe_action_scheduler, maybe_worker_scheduler) =
+                scheduler_factory(spec, &store_manager, maybe_origin_event_tx.as_ref())
+                    .err_tip(|| format!("Failed to create scheduler '{name}'"))?;
+            if let Some(action_scheduler) = maybe_action_scheduler {
+                action_schedulers.insert(name.clone(), action_scheduler.clone());
+            }
+            if let Some(worker_scheduler) = maybe_worker_scheduler {
+                let health_component_name = format!("schedulers/{name}");
+                worker_scheduler
+                    .clone()
+                    .register_health(&mut health_registry_lock.sub_builder(&health_component_name));
+                worker_schedulers.insert(name.clone(), worker_scheduler.clone());
+            }
         }
     }
+    if !worker_schedulers.is_empty() {
+        // Actions are not dispatched after a shutdown signal, so workers only
+        // finish the actions they already run before the process exits.
+        let worker_schedulers = worker_schedulers.clone();
+        let mut shutdown_rx = shutdown_tx.subscribe();
+        background_spawn!("pause_dispatch_on_shutdown", async move {
+            if shutdown_rx.recv().await.is_err() {
+                return;
+            }
+            for (name, worker_scheduler) in &worker_schedulers {
+                if let Err(err) = worker_scheduler.set_dispatch_paused(true).await {
+                    event!(Level::ERROR, ?err, ?name, "Failed to pause dispatch");
+                }
+            }
+        });
+    }
+
+    #[cfg(target_family = "unix")]
+    {
+        let store_manager = store_manager.clone();
+        let action_schedulers = action_schedulers.clone();
+        let mut sighup = signal(SignalKind::hangup()).err_tip(|| "Failed to listen to SIGHUP")?;
+        background_spawn!("reload_config_on_sighup", async move {
+            while sighup.recv().await.is_some() {
+                event!(Level::WARN, ?config_file, "Reloading config on SIGHUP");
+                match reload_config(&config_file, &store_manager, &action_schedulers).await {
+                    Ok(()) => event!(Level::WARN, "Successfully reloaded config"),
+                    Err(err) => event!(Level::ERROR, ?err, "Failed to reload config"),
+                }
+            }
+        });
+    }
 
     let mut server_metrics: HashMap<String, Arc<dyn RootMetricsComponent>> = HashMap::new();
     // Registers all the ConnectedClientsMetrics to the registries
@@ -282,6 +518,45 @@ async fn inner_main(
         // Currently we only support http as our socket type.
         let ListenerConfig::http(http_config) = server_cfg.listener;
 
+        // The capabilities of an instance depend on the other services, so
+        // this must be created before their configs are consumed.
+        let capabilities_server = OptionFuture::from(
+            services
+                .capabilities
+                .as_ref()
+                .map(|_| CapabilitiesServer::new(&services, &action_schedulers)),
+        )
+        .await
+        .map_or(Ok::<Option<CapabilitiesServer>, Error>(None), |server| {
+            Ok(Some(server?))
+        })
+        .err_tip(|| "Could not create Capabilities service")?;
+
+        let (remote_asset_fetch, remote_asset_push) = services
+            .experimental_remote_asset
+            .map_or(Ok(None), |cfg| {
+                RemoteAssetServer::new(&cfg, &store_manager).map(|v| Some(v.into_services()))
+            })
+            .err_tip(|| "Could not create Remote Asset services")?
+            .unzip();
+
+        let (reflection_v1, reflection_v1alpha) = if http_config.enable_grpc_reflection {
+            let make_builder = || {
+                ReflectionBuilder::configure()
+                    .register_encoded_file_descriptor_set(nativelink_proto::FILE_DESCRIPTOR_SET)
+            };
+            (
+                Some(make_builder().build_v1().map_err(|e| {
+                    make_err!(Code::Internal, "Could not create reflection service: {e:?}")
+                })?),
+                Some(make_builder().build_v1alpha().map_err(|e| {
+                    make_err!(Code::Internal, "Could not create reflection service: {e:?}")
+                })?),
+            )
+        } else {
+            (None, None)
+        };
+
         let tonic_services = TonicServer::builder()
             .add_optional_service(
                 services
@@ -335,6 +610,33 @@ async fn inner_main(
                     })
                     .err_tip(|| "Could not create CAS service")?,
             )
+            .add_optional_service(
+                services
+                    .execution
+                    .as_ref()
+                    .map_or(Ok(None), |cfg| {
+                        OperationsServer::new(cfg, &action_schedulers).map(|v| {
+                            let mut service = v.into_service();
+                            let send_algo = &http_config.compression.send_compression_algorithm;
+                            if let Some(encoding) =
+                                into_encoding(send_algo.unwrap_or(HttpCompressionAlgorithm::none))
+                            {
+                                service = service.send_compressed(encoding);
+                            }
+                            for encoding in http_config
+                                .compression
+                                .accepted_compression_algorithms
+                                .iter()
+                                // Filter None values.
+                                .filter_map(|from: &HttpCompressionAlgorithm| into_encoding(*from))
+                            {
+                                service = service.accept_compressed(encoding);
+                            }
+                            Some(service)
+                        })
+                    })
+                    .err_tip(|| "Could not create Operations service")?,
+            )
             .add_optional_service(
                 services
                     .execution
@@ -387,44 +689,25 @@ async fn inner_main(
                     })
                     .err_tip(|| "Could not create ByteStream service")?,
             )
-            .add_optional_service(
-                OptionFuture::from(
-                    services
-                        .capabilities
-                        .as_ref()
-                        // Borrow checker fighting here...
-                        .map(|_| {
-                            CapabilitiesServer::new(
-                                services.capabilities.as_ref().unwrap(),
-                                &action_schedulers,
-                            )
-                        }),
-                )
-                .await
-                .map_or(Ok::<Option<CapabilitiesServer>, Error>(None), |server| {
-                    Ok(Some(server?))
-                })
-                .err_tip(|| "Could not create Capabilities service")?
-                .map(|v| {
-                    let mut service = v.into_service();
-                    let send_algo = &http_config.compression.send_compression_algorithm;
-                    if let Some(encoding) =
-                        into_encoding(send_algo.unwrap_or(HttpCompressionAlgorithm::none))
-                    {
-                        service = service.send_compressed(encoding);
-                    }
-                    for encoding in http_config
-                        .compression
-                        .accepted_compression_algorithms
-                        .iter()
-                        // Filter None values.
-                        .filter_map(|from: &HttpCompressionAlgorithm| into_encoding(*from))
-                    {
-                        service = service.accept_compressed(encoding);
-                    }
-                    service
-                }),
-            )
+            .add_optional_service(capabilities_server.map(|v| {
+                let mut service = v.into_service();
+                let send_algo = &http_config.compression.send_compression_algorithm;
+                if let Some(encoding) =
+                    into_encoding(send_algo.unwrap_or(HttpCompressionAlgorithm::none))
+                {
+                    service = service.send_compressed(encoding);
+                }
+                for encoding in http_config
+                    .compression
+                    .accepted_compression_algorithms
+                    .iter()
+                    // Filter None values.
+                    .filter_map(|from: &HttpCompressionAlgorithm| into_encoding(*from))
+                {
+                    service = service.accept_compressed(encoding);
+                }
+                service
+            }))
             .add_optional_service(
                 services
                     .worker_api
@@ -476,16 +759,84 @@ async fn inner_main(
                         })
                     })
                     .err_tip(|| "Could not create BEP service")?,
-            );
+            )
+            .add_optional_service(remote_asset_fetch.map(|mut service| {
+                let send_algo = &http_config.compression.send_compression_algorithm;
+                if let Some(encoding) =
+                    into_encoding(send_algo.unwrap_or(HttpCompressionAlgorithm::none))
+                {
+                    service = service.send_compressed(encoding);
+                }
+                for encoding in http_config
+                    .compression
+                    .accepted_compression_algorithms
+                    .iter()
+                    // Filter None values.
+                    .filter_map(|from: &HttpCompressionAlgorithm| into_encoding(*from))
+                {
+                    service = service.accept_compressed(encoding);
+                }
+                service
+            }))
+            .add_optional_service(remote_asset_push.map(|mut service| {
+                let send_algo = &http_config.compression.send_compression_algorithm;
+                if let Some(encoding) =
+                    into_encoding(send_algo.unwrap_or(HttpCompressionAlgorithm::none))
+                {
+                    service = service.send_compressed(encoding);
+                }
+                for encoding in http_config
+                    .compression
+                    .accepted_compression_algorithms
+                    .iter()
+                    // Filter None values.
+                    .filter_map(|from: &HttpCompressionAlgorithm| into_encoding(*from))
+                {
+                    service = service.accept_compressed(encoding);
+                }
+                service
+            }))
+            .add_optional_service(reflection_v1)
+            .add_optional_service(reflection_v1alpha);
 
         let health_registry = health_registry_builder.lock().await.build();
 
-        let mut svc = Router::new().merge(tonic_services.into_service().into_axum_router().layer(
-            OriginEventMiddlewareLayer::new(
-                maybe_origin_event_tx.clone(),
-                server_cfg.experimental_identity_header.clone(),
-            ),
-        ));
+        let mut tonic_router = tonic_services.into_service().into_axum_router();
+        if let Some(rate_limit_cfg) = &server_cfg.experimental_rate_limits {
+            // Innermost, so clients are limited by the identity that the
+            // outer middlewares found.
+            tonic_router = tonic_router.layer(RateLimitMiddlewareLayer::new(rate_limit_cfg));
+        }
+        let maybe_auth_layer = server_cfg
+            .experimental_auth
+            .as_ref()
+            .map(|auth_cfg| {
+                AuthMiddlewareLayer::new(auth_cfg).err_tip(|| "Could not create auth middleware")
+            })
+            .transpose()?;
+        // The HTTP services of the listener need the same credentials as
+        // the gRPC services.
+        let with_auth = |router: Router, read_only_requests| match &maybe_auth_layer {
+            Some(auth_layer) => {
+                router.layer(auth_layer.with_read_only_requests(read_only_requests))
+            }
+            None => router,
+        };
+        if let Some(auth_layer) = &maybe_auth_layer {
+            // Must be inside the `OriginEventMiddlewareLayer`, so the identity
+            // from the token is not overwritten.
+            tonic_router = tonic_router.layer(auth_layer.clone());
+        }
+        let mut svc = Router::new().merge(
+            tonic_router
+                .layer(OriginEventMiddlewareLayer::new(
+                    maybe_origin_event_tx.clone(),
+                    server_cfg.experimental_identity_header.clone(),
+                ))
+                // Outermost, so the spans of the request continue the trace
+                // of the client.
+                .layer(TraceContextMiddlewareLayer::default()),
+        );
 
         if let Some(health_cfg) = services.health {
             let path = if health_cfg.path.is_empty() {
@@ -493,7 +844,26 @@ async fn inner_main(
             } else {
                 &health_cfg.path
             };
-            svc = svc.route_service(path, HealthServer::new(health_registry));
+            let liveness_path = if health_cfg.liveness_path.is_empty() {
+                DEFAULT_HEALTH_LIVENESS_PATH
+            } else {
+                &health_cfg.liveness_path
+            };
+            let readiness_path = if health_cfg.readiness_path.is_empty() {
+                DEFAULT_HEALTH_READINESS_PATH
+            } else {
+                &health_cfg.readiness_path
+            };
+            svc = svc
+                .route_service(path, HealthServer::new(health_registry.clone()))
+                .route_service(
+                    liveness_path,
+                    HealthServer::new_with_probe(health_registry.clone(), HealthProbe::Liveness),
+                )
+                .route_service(
+                    readiness_path,
+                    HealthServer::new_with_probe(health_registry, HealthProbe::Readiness),
+                );
         }
 
         if let Some(prometheus_cfg) = services.experimental_prometheus {
@@ -520,23 +890,8 @@ async fn inner_main(
                             // collection. This allows it to call functions like `tokio::block_in_place`
                             // if it needs to wait on a future.
                             spawn_blocking!("prometheus_metrics", move || {
-                                let (layer, output_metrics) = MetricsCollectorLayer::new();
-
-                                // Traverse all the MetricsComponent's. The `MetricsCollectorLayer` will
-                                // collect all the metrics and store them in `output_metrics`.
-                                tracing::subscriber::with_default(
-                                    tracing_subscriber::registry().with(layer),
-                                    || {
-                                        let metrics_component = root_metrics_clone.read();
-                                        MetricsComponent::publish(
-                                            &*metrics_component,
-                                            MetricKind::Component,
-                                            MetricFieldData::default(),
-                                        )
-                                    },
-                                )
-                                .map_err(|e| make_err!(Code::Internal, "{e}"))
-                                .err_tip(|| "While processing prometheus metrics")?;
+                                let output_metrics = collect_metrics(&root_metrics_clone)
+                                    .err_tip(|| "While processing prometheus metrics")?;
 
                                 // Convert the collected metrics into OpenTelemetry metrics then
                                 // encode them into Prometheus format and populate them into a
@@ -601,9 +956,13 @@ async fn inner_main(
 
                                     // Translate the OpenTelemetry metrics to Prometheus format and encode
                                     // them into a hyper::Response.
+                                    // The latency and size histograms are kept in the
+                                    // default registry.
+                                    let mut metric_families = registry.gather();
+                                    metric_families.extend(prometheus::gather());
                                     let mut result = vec![];
                                     TextEncoder::new()
-                                        .encode(&registry.gather(), &mut result)
+                                        .encode(&metric_families, &mut result)
                                         .unwrap();
                                     let mut response =
                                         Response::new(axum::body::Body::from(result));
@@ -634,51 +993,67 @@ async fn inner_main(
             } else {
                 &admin_config.path
             };
-            let worker_schedulers = Arc::new(worker_schedulers.clone());
             svc = svc.nest_service(
                 path,
-                Router::new().route(
-                    "/scheduler/:instance_name/set_drain_worker/:worker_id/:is_draining",
-                    axum::routing::post(
-                        move |params: axum::extract::Path<(String, String, String)>| async move {
-                            let (instance_name, worker_id, is_draining) = params.0;
-                            (async move {
-                                let is_draining = match is_draining.as_str() {
-                                    "0" => false,
-                                    "1" => true,
-                                    _ => {
-                                        return Err(make_err!(
-                                            Code::Internal,
-                                            "{} is neither 0 nor 1",
-                                            is_draining
-                                        ))
-                                    }
-                                };
-                                worker_schedulers
-                                    .get(&instance_name)
-                                    .err_tip(|| {
-                                        format!(
-                                            "Can not get an instance with the name of '{}'",
-                                            &instance_name
-                                        )
-                                    })?
-                                    .clone()
-                                    .set_drain_worker(
-                                        &WorkerId::try_from(worker_id.clone())?,
-                                        is_draining,
-                                    )
-                                    .await?;
-                                Ok::<_, Error>(format!("Draining worker {worker_id}"))
-                            })
-                            .await
-                            .map_err(|e| {
-                                Err::<String, _>((
-                                    axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-                                    format!("Error: {e:?}"),
-                                ))
-                            })
-                        },
-                    ),
+                with_auth(
+                    AdminServer::new(
+                        store_manager.clone(),
+                        &action_schedulers,
+                        &worker_schedulers,
+                    )
+                    .into_router(),
+                    ReadOnlyRequests::Nothing,
+                ),
+            );
+        }
+
+        if let Some(blob_redirect_cfg) = services.experimental_blob_redirect {
+            let path = if blob_redirect_cfg.path.is_empty() {
+                DEFAULT_BLOB_REDIRECT_PATH
+            } else {
+                &blob_redirect_cfg.path
+            };
+            svc = svc.nest_service(
+                path,
+                with_auth(
+                    BlobRedirectServer::new(&blob_redirect_cfg, &store_manager)
+                        .err_tip(|| "Could not create blob redirect service")?
+                        .into_router(),
+                    ReadOnlyRequests::HttpGetAndHead,
+                ),
+            );
+        }
+
+        if let Some(http_cache_cfg) = services.experimental_http_cache {
+            let path = if http_cache_cfg.path.is_empty() {
+                DEFAULT_HTTP_CACHE_PATH
+            } else {
+                &http_cache_cfg.path
+            };
+            svc = svc.nest_service(
+                path,
+                with_auth(
+                    HttpCacheServer::new(&http_cache_cfg, &store_manager)
+                        .err_tip(|| "Could not create HTTP cache service")?
+                        .into_router(),
+                    ReadOnlyRequests::HttpGetAndHead,
+                ),
+            );
+        }
+
+        if let Some(pprof_cfg) = services.experimental_pprof {
+            let path = if pprof_cfg.path.is_empty() {
+                DEFAULT_PPROF_PATH
+            } else {
+                &pprof_cfg.path
+            };
+            // Profiles show the internals of the server, so only clients
+            // with read-write access may take them.
+            svc = svc.nest_service(
+                path,
+                with_auth(
+                    PprofServer::new(&pprof_cfg).into_router(),
+                    ReadOnlyRequests::Nothing,
                 ),
             );
         }
@@ -688,82 +1063,38 @@ async fn inner_main(
             .fallback((StatusCode::NOT_FOUND, "Not Found"));
 
         // Configure our TLS acceptor if we have TLS configured.
+        let allowed_client_common_names: Arc<[String]> = http_config
+            .tls
+            .as_ref()
+            .map_or_else(Vec::new, |tls_config| {
+                tls_config.allowed_client_common_names.clone()
+            })
+            .into();
         let maybe_tls_acceptor = http_config.tls.map_or(Ok(None), |tls_config| {
-            fn read_cert(cert_file: &str) -> Result<Vec<CertificateDer<'static>>, Error> {
-                let mut cert_reader = std::io::BufReader::new(
-                    std::fs::File::open(cert_file)
-                        .err_tip(|| format!("Could not open cert file {cert_file}"))?,
-                );
-                let certs = extract_certs(&mut cert_reader)
-                    .collect::<Result<Vec<CertificateDer<'_>>, _>>()
-                    .err_tip(|| format!("Could not extract certs from file {cert_file}"))?;
-                Ok(certs)
-            }
-            let certs = read_cert(&tls_config.cert_file)?;
-            let mut key_reader = std::io::BufReader::new(
-                std::fs::File::open(&tls_config.key_file)
-                    .err_tip(|| format!("Could not open key file {}", tls_config.key_file))?,
-            );
-            let key = match rustls_pemfile::read_one(&mut key_reader)
-                .err_tip(|| format!("Could not extract key(s) from file {}", tls_config.key_file))?
-            {
-                Some(rustls_pemfile::Item::Pkcs8Key(key)) => key.into(),
-                Some(rustls_pemfile::Item::Sec1Key(key)) => key.into(),
-                Some(rustls_pemfile::Item::Pkcs1Key(key)) => key.into(),
-                _ => {
-                    return Err(make_err!(
-                        Code::Internal,
-                        "No keys found in file {}",
-                        tls_config.key_file
-                    ))
-                }
-            };
-            if let Ok(Some(_)) = rustls_pemfile::read_one(&mut key_reader) {
-                return Err(make_err!(
-                    Code::InvalidArgument,
-                    "Expected 1 key in file {}",
-                    tls_config.key_file
-                ));
+            let (config, cert_resolver) = load_server_config(&tls_config)?;
+            if tls_config.cert_reload_interval_s > 0 {
+                let reload_interval = Duration::from_secs(tls_config.cert_reload_interval_s);
+                background_spawn!("tls_cert_reload", async move {
+                    let mut interval = tokio::time::interval(reload_interval);
+                    interval.tick().await;
+                    loop {
+                        interval.tick().await;
+                        match cert_resolver.reload_if_changed() {
+                            Ok(true) => {
+                                event!(Level::INFO, ?cert_resolver, "Reloaded TLS certificate")
+                            }
+                            Ok(false) => {}
+                            Err(err) => event!(
+                                Level::ERROR,
+                                ?err,
+                                ?cert_resolver,
+                                "Failed to reload TLS certificate"
+                            ),
+                        }
+                    }
+                });
             }
-            let verifier = if let Some(client_ca_file) = &tls_config.client_ca_file {
-                let mut client_auth_roots = RootCertStore::empty();
-                for cert in read_cert(client_ca_file)? {
-                    client_auth_roots.add(cert).map_err(|e| {
-                        make_err!(Code::Internal, "Could not read client CA: {e:?}")
-                    })?;
-                }
-                let crls = if let Some(client_crl_file) = &tls_config.client_crl_file {
-                    let mut crl_reader = std::io::BufReader::new(
-                        std::fs::File::open(client_crl_file)
-                            .err_tip(|| format!("Could not open CRL file {client_crl_file}"))?,
-                    );
-                    extract_crls(&mut crl_reader)
-                        .collect::<Result<_, _>>()
-                        .err_tip(|| format!("Could not extract CRLs from file {client_crl_file}"))?
-                } else {
-                    Vec::new()
-                };
-                WebPkiClientVerifier::builder(Arc::new(client_auth_roots))
-                    .with_crls(crls)
-                    .build()
-                    .map_err(|e| {
-                        make_err!(
-                            Code::Internal,
-                            "Could not create WebPkiClientVerifier: {e:?}"
-                        )
-                    })?
-            } else {
-                WebPkiClientVerifier::no_client_auth()
-            };
-            let mut config = TlsServerConfig::builder()
-                .with_client_cert_verifier(verifier)
-                .with_single_cert(certs, key)
-                .map_err(|e| {
-                    make_err!(Code::Internal, "Could not create TlsServerConfig : {e:?}")
-                })?;
-
-            config.alpn_protocols.push("h2".into());
-            Ok(Some(TlsAcceptor::from(Arc::new(config))))
+            Ok::<_, Error>(Some(TlsAcceptor::from(Arc::new(config))))
         })?;
 
         let socket_addr = http_config
@@ -818,9 +1149,15 @@ async fn inner_main(
             http.http2().max_header_list_size(value);
         }
         event!(Level::WARN, "Ready, listening on {socket_addr}",);
+        let shutdown_tx = shutdown_tx.clone();
+        let mut shutdown_rx = shutdown_tx.subscribe();
         root_futures.push(Box::pin(async move {
             loop {
                 select! {
+                    _ = shutdown_rx.recv() => {
+                        event!(Level::WARN, ?socket_addr, "Shutting down, no longer accepting connections");
+                        break;
+                    },
                     accept_result = tcp_listener.accept() => {
                         match accept_result {
                             Ok((tcp_stream, remote_addr)) => {
@@ -858,8 +1195,13 @@ async fn inner_main(
                                     },
                                 );
 
-                                let (http, svc, maybe_tls_acceptor) =
-                                    (http.clone(), svc.clone(), maybe_tls_acceptor.clone());
+                                let (http, svc, maybe_tls_acceptor, allowed_client_common_names) = (
+                                    http.clone(),
+                                    svc.clone(),
+                                    maybe_tls_acceptor.clone(),
+                                    allowed_client_common_names.clone(),
+                                );
+                                let mut connection_shutdown_rx = shutdown_tx.subscribe();
                                 Arc::new(OriginContext::new()).background_spawn(
                                     error_span!(
                                         target: "nativelink::services",
@@ -874,25 +1216,47 @@ async fn inner_main(
                                     fut: async move {
                                         // Move it into our spawn, so if our spawn dies the cleanup happens.
                                         let _guard = scope_guard;
-                                        let serve_connection = if let Some(tls_acceptor) = maybe_tls_acceptor {
+                                        let stream = if let Some(tls_acceptor) = maybe_tls_acceptor {
                                             match tls_acceptor.accept(tcp_stream).await {
-                                                Ok(tls_stream) => Either::Left(http.serve_connection(
-                                                    TokioIo::new(tls_stream),
-                                                    TowerToHyperService::new(svc),
-                                                )),
+                                                Ok(tls_stream) => {
+                                                    if let Err(err) = check_client_common_name(
+                                                        tls_stream.get_ref().1.peer_certificates(),
+                                                        &allowed_client_common_names,
+                                                    ) {
+                                                        event!(Level::WARN, ?err, "Rejected tls client");
+                                                        return;
+                                                    }
+                                                    IoEither::Left(tls_stream)
+                                                }
                                                 Err(err) => {
                                                     event!(Level::ERROR, ?err, "Failed to accept tls stream");
                                                     return;
                                                 }
                                             }
                                         } else {
-                                            Either::Right(http.serve_connection(
-                                                TokioIo::new(tcp_stream),
-                                                TowerToHyperService::new(svc),
-                                            ))
+                                            IoEither::Right(tcp_stream)
                                         };
-
-                                        if let Err(err) = serve_connection.await {
+                                        let serve_connection = http.serve_connection(
+                                            TokioIo::new(stream),
+                                            TowerToHyperService::new(WithClientAddress::new(
+                                                svc,
+                                                remote_addr,
+                                            )),
+                                        );
+                                        tokio::pin!(serve_connection);
+
+                                        let result = select! {
+                                            result = serve_connection.as_mut() => result,
+                                            shutdown_guard = connection_shutdown_rx.recv() => {
+                                                // Requests in flight are finished, but the client
+                                                // is told to send new ones elsewhere. The guard
+                                                // holds the shutdown until the connection closed.
+                                                let _shutdown_guard = shutdown_guard;
+                                                serve_connection.as_mut().graceful_shutdown();
+                                                serve_connection.await
+                                            },
+                                        };
+                                        if let Err(err) = result {
                                             event!(
                                                 target: "nativelink::services",
                                                 Level::ERROR,
@@ -913,7 +1277,10 @@ async fn inner_main(
                     },
                 }
             }
-            // Unreachable
+            // Drop the listener so new connections are refused, the process
+            // exits once the connections in flight are done.
+            drop(tcp_listener);
+            std::future::pending().await
         }));
     }
 
@@ -992,6 +1359,17 @@ async fn inner_main(
         root_metrics.write().workers = worker_metrics;
     }
 
+    if let Some(metrics_push_cfg) = cfg
+        .global
+        .as_ref()
+        .and_then(|global_cfg| global_cfg.metrics_push.clone())
+    {
+        background_spawn!(
+            "metrics_push",
+            push_metrics(root_metrics.clone(), metrics_push_cfg)
+        );
+    }
+
     if let Err(e) = try_join_all(root_futures).await {
         panic!("{e:?}");
     };
@@ -999,27 +1377,406 @@ async fn inner_main(
     Ok(())
 }
 
-async fn get_config() -> Result<CasConfig, Box<dyn std::error::Error>> {
-    let args = Args::parse();
+/// Collects the current values of the metrics of the `MetricsComponent`
+/// tree. Components may block while they are collected, so this should be
+/// called from a blocking thread.
+fn collect_metrics(
+    root_metrics: &RwLock<RootMetrics>,
+) -> Result<Arc<Mutex<RootMetricCollectedMetrics>>, Error> {
+    let (layer, output_metrics) = MetricsCollectorLayer::new();
+
+    // Traverse all the MetricsComponent's. The `MetricsCollectorLayer` will
+    // collect all the metrics and store them in `output_metrics`.
+    tracing::subscriber::with_default(tracing_subscriber::registry().with(layer), || {
+        let metrics_component = root_metrics.read();
+        MetricsComponent::publish(
+            &*metrics_component,
+            MetricKind::Component,
+            MetricFieldData::default(),
+        )
+    })
+    .map_err(|e| make_err!(Code::Internal, "{e}"))
+    .err_tip(|| "While collecting metrics")?;
+    Ok(output_metrics)
+}
+
+/// Pushes the metrics to the collector of `metrics_push_cfg` every interval
+/// until the process exits.
+async fn push_metrics(root_metrics: Arc<RwLock<RootMetrics>>, metrics_push_cfg: MetricsPushConfig) {
+    // Note: If the default changes make sure you update the documentation in
+    // `config/cas_server.rs`.
+    const DEFAULT_METRICS_PUSH_INTERVAL_S: u64 = 60;
+    let interval_s = if metrics_push_cfg.interval_s == 0 {
+        DEFAULT_METRICS_PUSH_INTERVAL_S
+    } else {
+        metrics_push_cfg.interval_s
+    };
+    let mut interval = tokio::time::interval(Duration::from_secs(interval_s));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        if let Err(err) = push_metrics_once(&root_metrics, &metrics_push_cfg.exporter).await {
+            event!(Level::WARN, ?err, "Failed to push metrics");
+        }
+    }
+}
+
+async fn push_metrics_once(
+    root_metrics: &Arc<RwLock<RootMetrics>>,
+    exporter: &MetricsPushExporter,
+) -> Result<(), Error> {
+    let root_metrics = root_metrics.clone();
+    match exporter.clone() {
+        MetricsPushExporter::otlp(otlp_cfg) => spawn_blocking!("metrics_push_otlp", move || {
+            let output_metrics = collect_metrics(&root_metrics)?;
+            push_otlp_metrics(&otlp_cfg.endpoint, &output_metrics.lock())
+        })
+        .await
+        .err_tip(|| "Could not join OTLP metrics push")?,
+        MetricsPushExporter::statsd(statsd_cfg) => {
+            let tags = statsd_cfg.tags.into_iter().collect();
+            let lines = spawn_blocking!("metrics_push_statsd", move || {
+                let output_metrics = collect_metrics(&root_metrics)?;
+                Result::<_, Error>::Ok(statsd_export(
+                    "nativelink".to_string(),
+                    &tags,
+                    &output_metrics.lock(),
+                ))
+            })
+            .await
+            .err_tip(|| "Could not join statsd metrics push")??;
+            send_statsd_lines(&statsd_cfg.address, &lines).await
+        }
+    }
+}
+
+/// Sends the collected metrics to the OTLP/HTTP metrics endpoint
+/// `endpoint`. Blocks until they are sent.
+fn push_otlp_metrics(
+    endpoint: &str,
+    output_metrics: &RootMetricCollectedMetrics,
+) -> Result<(), Error> {
+    let exporter = opentelemetry_otlp::MetricExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()
+        .map_err(|e| make_err!(Code::Internal, "{e}"))
+        .err_tip(|| "While creating OpenTelemetry OTLP exporter")?;
+    // Like for the prometheus service, a new provider is used every time,
+    // because `otel_export` adds the current values to new counters.
+    let provider = SdkMeterProvider::builder()
+        .with_reader(PeriodicReader::builder(exporter, runtime::Tokio).build())
+        .build();
+    otel_export(
+        "nativelink".to_string(),
+        &provider.meter("nativelink"),
+        output_metrics,
+    );
+    // The reader exports the metrics one last time when it is shut down,
+    // before its first period elapsed.
+    provider
+        .shutdown()
+        .map_err(|e| make_err!(Code::Unavailable, "{e}"))
+        .err_tip(|| format!("While pushing metrics to {endpoint}"))
+}
+
+/// Sends the statsd `lines` to `address`, in datagrams small enough to not
+/// be fragmented on most networks.
+async fn send_statsd_lines(address: &str, lines: &[String]) -> Result<(), Error> {
+    const MAX_DATAGRAM_BYTES: usize = 1432;
+    let addr = lookup_host(address)
+        .await
+        .err_tip(|| format!("Could not resolve statsd address {address}"))?
+        .next()
+        .err_tip(|| format!("No address found for statsd address {address}"))?;
+    let bind_addr: SocketAddr = if addr.is_ipv4() {
+        ([0, 0, 0, 0], 0).into()
+    } else {
+        ([0u16; 8], 0).into()
+    };
+    let socket = UdpSocket::bind(bind_addr)
+        .await
+        .err_tip(|| "Could not bind statsd socket")?;
+    let mut datagram = String::new();
+    for line in lines {
+        if !datagram.is_empty() && datagram.len() + 1 + line.len() > MAX_DATAGRAM_BYTES {
+            socket
+                .send_to(datagram.as_bytes(), addr)
+                .await
+                .err_tip(|| format!("Could not send metrics to {address}"))?;
+            datagram.clear();
+        }
+        if !datagram.is_empty() {
+            datagram.push('\n');
+        }
+        datagram.push_str(line);
+    }
+    if !datagram.is_empty() {
+        socket
+            .send_to(datagram.as_bytes(), addr)
+            .await
+            .err_tip(|| format!("Could not send metrics to {address}"))?;
+    }
+    Ok(())
+}
+
+async fn get_config(config_file: &str) -> Result<CasConfig, Box<dyn std::error::Error>> {
     let json_contents = String::from_utf8(
-        std::fs::read(&args.config_file)
-            .err_tip(|| format!("Could not open config file {}", args.config_file))?,
+        std::fs::read(config_file)
+            .err_tip(|| format!("Could not open config file {config_file}"))?,
     )?;
     Ok(serde_json5::from_str(&json_contents)?)
 }
 
+/// Reads the config file again and applies the changes that don't need a
+/// restart: the eviction and retry policies of stores, the modifications of
+/// property modifier schedulers and the log filter. Stores and schedulers
+/// that were added, and any other setting, need a restart.
+async fn reload_config(
+    config_file: &str,
+    store_manager: &StoreManager,
+    action_schedulers: &HashMap<String, Arc<dyn ClientStateManager>>,
+) -> Result<(), Error> {
+    let cfg = get_config(config_file)
+        .await
+        .map_err(|e| make_input_err!("{e}"))?;
+    let mut errors = Vec::new();
+    for StoreConfig { name, spec } in &cfg.stores {
+        let Some(store) = store_manager.get_store(name) else {
+            event!(Level::WARN, ?name, "New store is only created on restart");
+            continue;
+        };
+        // Checks the whole spec before changing anything.
+        if let Err(err) = store.reconfigure(spec).await {
+            errors.push(err.append(format!("Failed to reconfigure store '{name}'")));
+        }
+    }
+    for SchedulerConfig { name, spec } in cfg.schedulers.iter().flatten() {
+        let Some(action_scheduler) = action_schedulers.get(name) else {
+            event!(
+                Level::WARN,
+                ?name,
+                "New scheduler is only created on restart"
+            );
+            continue;
+        };
+        // Checks the whole spec before changing anything.
+        let result = match action_scheduler.reconfigure(spec, true).await {
+            Ok(()) => action_scheduler.reconfigure(spec, false).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            errors.push(err.append(format!("Failed to reconfigure scheduler '{name}'")));
+        }
+    }
+    let log_filter = cfg
+        .global
+        .as_ref()
+        .and_then(|global_cfg| global_cfg.log_filter.as_deref())
+        .unwrap_or_default();
+    if let Err(err) = set_log_filter(log_filter) {
+        errors.push(err);
+    }
+    errors
+        .into_iter()
+        .reduce(|err, other_err| err.merge(other_err))
+        .map_or(Ok(()), Err)
+}
+
+fn get_store_config(config_file: &str) -> Result<StoreSpec, Error> {
+    let json_contents = std::fs::read_to_string(config_file)
+        .err_tip(|| format!("Could not open store config file {config_file}"))?;
+    serde_json5::from_str(&json_contents)
+        .map_err(|e| make_input_err!("Could not parse store config file {config_file}: {e}"))
+}
+
+async fn migrate(args: MigrateArgs) -> Result<(), Error> {
+    let store_manager = Arc::new(StoreManager::new());
+    let source = store_factory(
+        &get_store_config(&args.source_config)?,
+        &store_manager,
+        None,
+    )
+    .await
+    .err_tip(|| "Failed to create source store")?;
+    let destination = store_factory(
+        &get_store_config(&args.destination_config)?,
+        &store_manager,
+        None,
+    )
+    .await
+    .err_tip(|| "Failed to create destination store")?;
+
+    let start_after = match &args.resume_file {
+        Some(resume_file) => match std::fs::read_to_string(resume_file) {
+            Ok(key) if !key.trim().is_empty() => Some(StoreKey::from_encoded_str(key.trim())),
+            Ok(_) => None,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+            Err(err) => {
+                return Err(err).err_tip(|| format!("Could not read resume file {resume_file}"))
+            }
+        },
+        None => None,
+    };
+    if let Some(key) = &start_after {
+        event!(Level::INFO, ?key, "Resuming migration");
+    }
+
+    let progress = migrate_store(
+        &source,
+        &destination,
+        start_after,
+        MigrationOptions {
+            parallelism: args.parallelism,
+            batch_size: args.batch_size,
+        },
+        |progress, last_key| {
+            event!(Level::INFO, ?progress, ?last_key, "Migrated batch of keys");
+            if let Some(resume_file) = &args.resume_file {
+                std::fs::write(resume_file, last_key.as_str().as_bytes())
+                    .err_tip(|| format!("Could not write resume file {resume_file}"))?;
+            }
+            Ok(())
+        },
+    )
+    .await?;
+    event!(Level::INFO, ?progress, "Finished migration");
+    Ok(())
+}
+
+fn decode_message(data: &[u8], message_type: ProtoMessageType) -> Result<String, Error> {
+    fn decode<T: Message + Default>(data: &[u8]) -> Result<String, Error> {
+        let message = T::decode(data).map_err(|e| make_input_err!("Could not decode: {e}"))?;
+        Ok(format!("{message:#?}"))
+    }
+    match message_type {
+        ProtoMessageType::Action => decode::<Action>(data),
+        ProtoMessageType::ActionResult => decode::<ActionResult>(data),
+        ProtoMessageType::Command => decode::<ProtoCommand>(data),
+        ProtoMessageType::Directory => decode::<Directory>(data),
+        ProtoMessageType::Tree => decode::<Tree>(data),
+    }
+}
+
+async fn inspect_store(args: StoreArgs) -> Result<(), Error> {
+    let cfg = get_config(&args.config_file)
+        .await
+        .map_err(|e| make_input_err!("{e}"))?;
+    if let Some(hash_function) = cfg
+        .global
+        .and_then(|global_cfg| global_cfg.default_digest_hash_function)
+    {
+        set_default_digest_hasher_func(DigestHasherFunc::from(hash_function))?;
+    }
+    let store_manager = Arc::new(StoreManager::new());
+    for StoreConfig { name, spec } in cfg.stores {
+        let store = store_factory(&spec, &store_manager, None)
+            .await
+            .err_tip(|| format!("Failed to create store '{name}'"))?;
+        store_manager.add_store(&name, store);
+    }
+    let store = store_manager.get_store(&args.store_name).err_tip(|| {
+        format!(
+            "Store '{}' is not in config file {}",
+            args.store_name, args.config_file
+        )
+    })?;
+
+    let output = match args.action {
+        StoreAction::Stat { key } => {
+            let key = StoreKey::from_encoded_str(&key);
+            let line = match store.has(key.borrow()).await? {
+                Some(size) => format!("{}: {size} bytes\n", key.as_str()),
+                None => format!("{}: not found\n", key.as_str()),
+            };
+            line.into_bytes()
+        }
+        StoreAction::Cat { key, decode } => {
+            let data = store
+                .get_part_unchunked(StoreKey::from_encoded_str(&key), 0, None)
+                .await?;
+            match decode {
+                Some(message_type) => {
+                    format!("{}\n", decode_message(&data, message_type)?).into_bytes()
+                }
+                None => data.to_vec(),
+            }
+        }
+        StoreAction::Upload { file, key } => {
+            let data = std::fs::read(&file).err_tip(|| format!("Could not read file {file}"))?;
+            let key = match key {
+                Some(key) => StoreKey::from_encoded_str(&key),
+                None => {
+                    let mut hasher = default_digest_hasher_func().hasher();
+                    hasher.update(&data);
+                    StoreKey::Digest(hasher.finalize_digest())
+                }
+            };
+            store.update_oneshot(key.borrow(), data.into()).await?;
+            format!("{}\n", key.as_str()).into_bytes()
+        }
+        StoreAction::Delete { key } => {
+            let key = StoreKey::from_encoded_str(&key);
+            let result = if store.remove(key.borrow()).await? {
+                "removed"
+            } else {
+                "not found"
+            };
+            format!("{}: {result}\n", key.as_str()).into_bytes()
+        }
+    };
+    std::io::stdout()
+        .write_all(&output)
+        .err_tip(|| "Could not write to stdout")?;
+    Ok(())
+}
+
+async fn run_command(command: Command) -> Result<(), Error> {
+    match command {
+        Command::Migrate(args) => migrate(args).await,
+        Command::Store(args) => inspect_store(args).await,
+    }
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     init_tracing()?;
 
-    let mut cfg = futures::executor::block_on(get_config())?;
+    let args = Args::parse();
+    if let Some(command) = args.command {
+        #[allow(clippy::disallowed_methods)]
+        {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+            runtime.block_on(
+                Arc::new(OriginContext::new())
+                    .wrap_async(trace_span!("command"), run_command(command)),
+            )?;
+        }
+        return Ok(());
+    }
+    let Some(config_file) = args.config_file else {
+        return Err(make_input_err!("A config file is required").into());
+    };
+
+    let mut cfg = futures::executor::block_on(get_config(&config_file))?;
 
-    let (mut metrics_enabled, max_blocking_threads) = {
+    let (mut metrics_enabled, max_blocking_threads, shutdown_grace_period) = {
         // Note: If the default changes make sure you update the documentation in
         // `config/cas_server.rs`.
         const DEFAULT_MAX_OPEN_FILES: usize = 512;
         // Note: If the default changes make sure you update the documentation in
         // `config/cas_server.rs`.
         const DEFAULT_IDLE_FILE_DESCRIPTOR_TIMEOUT_MILLIS: u64 = 1000;
+        // Note: If the default changes make sure you update the documentation in
+        // `config/cas_server.rs`.
+        const DEFAULT_SHUTDOWN_GRACE_PERIOD_S: u64 = 30;
+        // Note: If the default changes make sure you update the documentation in
+        // `config/cas_server.rs`.
+        const DEFAULT_SLOW_STORE_OPERATION_THRESHOLD_MS: u64 = 1000;
+        // Note: If the default changes make sure you update the documentation in
+        // `config/cas_server.rs`.
+        const DEFAULT_SLOW_RPC_THRESHOLD_MS: u64 = 5000;
         let global_cfg = if let Some(global_cfg) = &mut cfg.global {
             if global_cfg.max_open_files == 0 {
                 global_cfg.max_open_files = DEFAULT_MAX_OPEN_FILES;
@@ -1031,8 +1788,20 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             if global_cfg.default_digest_size_health_check == 0 {
                 global_cfg.default_digest_size_health_check = DEFAULT_DIGEST_SIZE_HEALTH_CHECK_CFG;
             }
+            if global_cfg.shutdown_grace_period_s == 0 {
+                global_cfg.shutdown_grace_period_s = DEFAULT_SHUTDOWN_GRACE_PERIOD_S;
+            }
+            if let Some(slow_operation_log_cfg) = &mut global_cfg.slow_operation_log {
+                if slow_operation_log_cfg.store_operation_threshold_ms == 0 {
+                    slow_operation_log_cfg.store_operation_threshold_ms =
+                        DEFAULT_SLOW_STORE_OPERATION_THRESHOLD_MS;
+                }
+                if slow_operation_log_cfg.rpc_threshold_ms == 0 {
+                    slow_operation_log_cfg.rpc_threshold_ms = DEFAULT_SLOW_RPC_THRESHOLD_MS;
+                }
+            }
 
-            *global_cfg
+            global_cfg.clone()
         } else {
             GlobalConfig {
                 max_open_files: DEFAULT_MAX_OPEN_FILES,
@@ -1045,6 +1814,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }),
                 default_digest_hash_function: None,
                 default_digest_size_health_check: DEFAULT_DIGEST_SIZE_HEALTH_CHECK_CFG,
+                max_process_bytes: 0,
+                shutdown_grace_period_s: DEFAULT_SHUTDOWN_GRACE_PERIOD_S,
+                log_filter: None,
+                metrics_push: None,
+                slow_operation_log: None,
             }
         };
         set_open_file_limit(global_cfg.max_open_files);
@@ -1057,8 +1831,22 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .unwrap_or(ConfigDigestHashFunction::sha256),
         ))?;
         set_default_digest_size_health_check(global_cfg.default_digest_size_health_check)?;
+        global_memory_budget().set_max_bytes(global_cfg.max_process_bytes);
+        if let Some(slow_operation_log_cfg) = &global_cfg.slow_operation_log {
+            set_slow_operation_thresholds(
+                Duration::from_millis(slow_operation_log_cfg.store_operation_threshold_ms),
+                Duration::from_millis(slow_operation_log_cfg.rpc_threshold_ms),
+            );
+        }
+        if let Some(log_filter) = &global_cfg.log_filter {
+            set_log_filter(log_filter)?;
+        }
         // TODO (#513): prevent deadlocks by assigning max blocking threads number of open files * ten
-        (!global_cfg.disable_metrics, global_cfg.max_open_files * 10)
+        (
+            !global_cfg.disable_metrics,
+            global_cfg.max_open_files * 10,
+            Duration::from_secs(global_cfg.shutdown_grace_period_s),
+        )
     };
     // Override metrics enabled if the environment variable is set.
     if std::env::var(METRICS_DISABLE_ENV).is_ok() {
@@ -1100,8 +1888,19 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     .await;
                 event!(Level::WARN, "Process terminated via SIGTERM",);
                 let _ = shutdown_tx_clone.send(shutdown_guard.clone());
-                let () = shutdown_guard.wait_for(Priority::P0).await;
-                event!(Level::WARN, "Successfully shut down nativelink.",);
+                let shutdown_fut = shutdown_guard.wait_for(Priority::P0);
+                if tokio::time::timeout(shutdown_grace_period, shutdown_fut)
+                    .await
+                    .is_err()
+                {
+                    event!(
+                        Level::WARN,
+                        ?shutdown_grace_period,
+                        "Shutdown grace period elapsed, exiting without waiting any longer.",
+                    );
+                } else {
+                    event!(Level::WARN, "Successfully shut down nativelink.",);
+                }
                 std::process::exit(143);
             });
         }
@@ -1109,7 +1908,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         runtime
             .block_on(Arc::new(OriginContext::new()).wrap_async(
                 trace_span!("main"),
-                inner_main(cfg, server_start_time, shutdown_tx),
+                inner_main(cfg, config_file, server_start_time, shutdown_tx),
             ))
             .err_tip(|| "main() function failed")?;
     }